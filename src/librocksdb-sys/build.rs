@@ -32,6 +32,12 @@ fn rocksdb_include_dir() -> String {
 fn bindgen_rocksdb() {
     let bindings = bindgen::Builder::default()
         .header(rocksdb_include_dir() + "/rocksdb/c.h")
+        .header("polodb_storage_backend.h")
+        .header("polodb_compact.h")
+        .header("polodb_options.h")
+        .header("polodb_transaction.h")
+        .header("polodb_lock.h")
+        .clang_arg(format!("-I{}", rocksdb_include_dir()))
         .derive_debug(false)
         .blocklist_type("max_align_t") // https://github.com/rust-lang-nursery/rust-bindgen/issues/550
         .ctypes_prefix("libc")
@@ -263,6 +269,11 @@ fn build_rocksdb() {
     }
 
     config.file("build_version.cc");
+    config.file("polodb_storage_backend.cc");
+    config.file("polodb_compact.cc");
+    config.file("polodb_options.cc");
+    config.file("polodb_transaction.cc");
+    config.file("polodb_lock.cc");
 
     config.cpp(true);
     config.flag_if_supported("-std=c++17");
@@ -376,6 +387,16 @@ fn main() {
         }
 
         println!("cargo:rerun-if-changed=rocksdb/");
+        println!("cargo:rerun-if-changed=polodb_storage_backend.cc");
+        println!("cargo:rerun-if-changed=polodb_storage_backend.h");
+        println!("cargo:rerun-if-changed=polodb_compact.cc");
+        println!("cargo:rerun-if-changed=polodb_compact.h");
+        println!("cargo:rerun-if-changed=polodb_options.cc");
+        println!("cargo:rerun-if-changed=polodb_options.h");
+        println!("cargo:rerun-if-changed=polodb_transaction.cc");
+        println!("cargo:rerun-if-changed=polodb_transaction.h");
+        println!("cargo:rerun-if-changed=polodb_lock.cc");
+        println!("cargo:rerun-if-changed=polodb_lock.h");
         fail_on_empty_directory("rocksdb");
         build_rocksdb();
     } else {
@@ -0,0 +1,36 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#include "polodb_compact.h"
+
+#include "rocksdb/options.h"
+#include "rocksdb/slice.h"
+
+using ROCKSDB_NAMESPACE::CompactRangeOptions;
+using ROCKSDB_NAMESPACE::Slice;
+
+// The same definition as db/c.cc, which is not exported by a header.
+struct rocksdb_compactoptions_t {
+  CompactRangeOptions rep;
+  Slice full_history_ts_low;
+};
+
+extern "C" {
+
+void polodb_compactoptions_set_target_path_id(rocksdb_compactoptions_t* opt,
+                                              uint32_t target_path_id) {
+  opt->rep.target_path_id = target_path_id;
+}
+
+}  // extern "C"
@@ -0,0 +1,34 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/* The compaction options which are missing in the C API of RocksDB. */
+
+#pragma once
+
+#include <stdint.h>
+
+#include "rocksdb/c.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The index in the db paths where the output files of
+ * the manual compaction are written. */
+extern ROCKSDB_LIBRARY_API void polodb_compactoptions_set_target_path_id(
+    rocksdb_compactoptions_t* opt, uint32_t target_path_id);
+
+#ifdef __cplusplus
+}
+#endif
@@ -0,0 +1,51 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#include "polodb_lock.h"
+
+#include <stdlib.h>
+#include <string.h>
+
+#include <string>
+
+#include "rocksdb/env.h"
+
+using ROCKSDB_NAMESPACE::Env;
+using ROCKSDB_NAMESPACE::FileLock;
+using ROCKSDB_NAMESPACE::Status;
+
+struct polodb_dblock_t {
+  FileLock* rep;
+};
+
+extern "C" {
+
+polodb_dblock_t* polodb_lock_db(const char* db_path, char** errptr) {
+  FileLock* lock = nullptr;
+  // the same file as LockFileName() of RocksDB
+  Status s = Env::Default()->LockFile(std::string(db_path) + "/LOCK", &lock);
+  if (!s.ok()) {
+    if (*errptr) free(*errptr);
+    *errptr = strdup(s.ToString().c_str());
+    return nullptr;
+  }
+  return new polodb_dblock_t{lock};
+}
+
+void polodb_unlock_db(polodb_dblock_t* lock) {
+  Env::Default()->UnlockFile(lock->rep);
+  delete lock;
+}
+
+}  // extern "C"
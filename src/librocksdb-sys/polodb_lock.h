@@ -0,0 +1,37 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+/* The LOCK file of a database, which is missing in the C API of RocksDB. */
+
+#pragma once
+
+#include "rocksdb/c.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct polodb_dblock_t polodb_dblock_t;
+
+/* Lock the database in `db_path` like opening it does. It fails if the
+ * database is opened by this process or by another one. */
+extern ROCKSDB_LIBRARY_API polodb_dblock_t* polodb_lock_db(const char* db_path,
+                                                           char** errptr);
+
+extern ROCKSDB_LIBRARY_API void polodb_unlock_db(polodb_dblock_t* lock);
+
+#ifdef __cplusplus
+}
+#endif
@@ -0,0 +1,52 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#include "polodb_options.h"
+
+#include <stdlib.h>
+#include <string.h>
+
+#include <string>
+#include <unordered_map>
+
+#include "rocksdb/db.h"
+#include "rocksdb/status.h"
+
+using ROCKSDB_NAMESPACE::DB;
+using ROCKSDB_NAMESPACE::Status;
+
+// The same definition as db/c.cc, which is not exported by a header.
+struct rocksdb_t {
+  DB* rep;
+};
+
+extern "C" {
+
+void polodb_set_db_options(rocksdb_t* db, int count, const char* const keys[],
+                           const char* const values[], char** errptr) {
+  std::unordered_map<std::string, std::string> options;
+  for (int i = 0; i < count; i++) {
+    options[keys[i]] = values[i];
+  }
+  Status s = db->rep->SetDBOptions(options);
+  if (s.ok()) {
+    return;
+  }
+  if (*errptr != nullptr) {
+    free(*errptr);
+  }
+  *errptr = strdup(s.ToString().c_str());
+}
+
+}  // extern "C"
@@ -0,0 +1,36 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/* The dynamic option setters which are missing in the C API of RocksDB. */
+
+#pragma once
+
+#include <stddef.h>
+
+#include "rocksdb/c.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Changes the mutable database-wide options of an opened database,
+ * e.g. "max_background_jobs". The values are parsed the same way as
+ * an options file. */
+extern ROCKSDB_LIBRARY_API void polodb_set_db_options(
+    rocksdb_t* db, int count, const char* const keys[],
+    const char* const values[], char** errptr);
+
+#ifdef __cplusplus
+}
+#endif
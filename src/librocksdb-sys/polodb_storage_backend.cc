@@ -0,0 +1,499 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#include "polodb_storage_backend.h"
+
+#include <cstdlib>
+#include <memory>
+#include <mutex>
+#include <set>
+#include <string>
+#include <vector>
+
+#include "rocksdb/env.h"
+#include "rocksdb/file_system.h"
+
+using ROCKSDB_NAMESPACE::Env;
+using ROCKSDB_NAMESPACE::FileLock;
+using ROCKSDB_NAMESPACE::FileOptions;
+using ROCKSDB_NAMESPACE::FileSystem;
+using ROCKSDB_NAMESPACE::FSDirectory;
+using ROCKSDB_NAMESPACE::FSRandomAccessFile;
+using ROCKSDB_NAMESPACE::FSSequentialFile;
+using ROCKSDB_NAMESPACE::FSWritableFile;
+using ROCKSDB_NAMESPACE::IODebugContext;
+using ROCKSDB_NAMESPACE::IOOptions;
+using ROCKSDB_NAMESPACE::IOStatus;
+using ROCKSDB_NAMESPACE::Slice;
+
+// Same layout as the definition in db/c.cc
+struct rocksdb_env_t {
+  Env* rep;
+  bool is_default;
+};
+
+namespace {
+
+class Backend {
+ public:
+  explicit Backend(const polodb_storage_backend_t& callbacks)
+      : callbacks_(callbacks) {}
+
+  ~Backend() {
+    if (callbacks_.destructor != nullptr) {
+      callbacks_.destructor(callbacks_.state);
+    }
+  }
+
+  const polodb_storage_backend_t& callbacks() const { return callbacks_; }
+
+  void* state() const { return callbacks_.state; }
+
+ private:
+  polodb_storage_backend_t callbacks_;
+};
+
+IOStatus ToStatus(int code, char* err, const std::string& context) {
+  std::string msg;
+  if (err != nullptr) {
+    msg = err;
+    free(err);
+  }
+  switch (code) {
+    case POLODB_STORAGE_OK:
+      return IOStatus::OK();
+    case POLODB_STORAGE_NOT_FOUND:
+      return IOStatus::PathNotFound(context, msg);
+    default:
+      return IOStatus::IOError(context, msg);
+  }
+}
+
+class BackendFile {
+ public:
+  BackendFile(std::shared_ptr<Backend> backend, void* file, std::string name)
+      : backend_(std::move(backend)), file_(file), name_(std::move(name)) {}
+
+  ~BackendFile() { backend_->callbacks().close(backend_->state(), file_); }
+
+  IOStatus ReadAt(uint64_t offset, size_t n, Slice* result,
+                  char* scratch) const {
+    size_t read = 0;
+    char* err = nullptr;
+    int code = backend_->callbacks().read_at(backend_->state(), file_, offset,
+                                             scratch, n, &read, &err);
+    IOStatus s = ToStatus(code, err, name_);
+    *result = s.ok() ? Slice(scratch, read) : Slice();
+    return s;
+  }
+
+  IOStatus WriteAt(uint64_t offset, const Slice& data) {
+    char* err = nullptr;
+    int code = backend_->callbacks().write_at(
+        backend_->state(), file_, offset, data.data(), data.size(), &err);
+    return ToStatus(code, err, name_);
+  }
+
+  IOStatus Sync() {
+    char* err = nullptr;
+    int code = backend_->callbacks().sync(backend_->state(), file_, &err);
+    return ToStatus(code, err, name_);
+  }
+
+  IOStatus Size(uint64_t* size) {
+    char* err = nullptr;
+    int code =
+        backend_->callbacks().size(backend_->state(), file_, size, &err);
+    return ToStatus(code, err, name_);
+  }
+
+  IOStatus Truncate(uint64_t size) {
+    char* err = nullptr;
+    int code =
+        backend_->callbacks().truncate(backend_->state(), file_, size, &err);
+    return ToStatus(code, err, name_);
+  }
+
+ private:
+  std::shared_ptr<Backend> backend_;
+  void* file_;
+  std::string name_;
+};
+
+class BackendSequentialFile : public FSSequentialFile {
+ public:
+  explicit BackendSequentialFile(std::unique_ptr<BackendFile> file)
+      : file_(std::move(file)) {}
+
+  IOStatus Read(size_t n, const IOOptions& /*options*/, Slice* result,
+                char* scratch, IODebugContext* /*dbg*/) override {
+    IOStatus s = file_->ReadAt(offset_, n, result, scratch);
+    if (s.ok()) {
+      offset_ += result->size();
+    }
+    return s;
+  }
+
+  IOStatus Skip(uint64_t n) override {
+    offset_ += n;
+    return IOStatus::OK();
+  }
+
+ private:
+  std::unique_ptr<BackendFile> file_;
+  uint64_t offset_ = 0;
+};
+
+class BackendRandomAccessFile : public FSRandomAccessFile {
+ public:
+  explicit BackendRandomAccessFile(std::unique_ptr<BackendFile> file)
+      : file_(std::move(file)) {}
+
+  IOStatus Read(uint64_t offset, size_t n, const IOOptions& /*options*/,
+                Slice* result, char* scratch,
+                IODebugContext* /*dbg*/) const override {
+    return file_->ReadAt(offset, n, result, scratch);
+  }
+
+ private:
+  std::unique_ptr<BackendFile> file_;
+};
+
+class BackendWritableFile : public FSWritableFile {
+ public:
+  BackendWritableFile(std::unique_ptr<BackendFile> file, uint64_t offset)
+      : file_(std::move(file)), offset_(offset) {}
+
+  using FSWritableFile::Append;
+
+  IOStatus Append(const Slice& data, const IOOptions& /*options*/,
+                  IODebugContext* /*dbg*/) override {
+    IOStatus s = file_->WriteAt(offset_, data);
+    if (s.ok()) {
+      offset_ += data.size();
+    }
+    return s;
+  }
+
+  IOStatus Truncate(uint64_t size, const IOOptions& /*options*/,
+                    IODebugContext* /*dbg*/) override {
+    IOStatus s = file_->Truncate(size);
+    if (s.ok()) {
+      offset_ = size;
+    }
+    return s;
+  }
+
+  IOStatus Close(const IOOptions& /*options*/,
+                 IODebugContext* /*dbg*/) override {
+    return IOStatus::OK();
+  }
+
+  IOStatus Flush(const IOOptions& /*options*/,
+                 IODebugContext* /*dbg*/) override {
+    return IOStatus::OK();
+  }
+
+  IOStatus Sync(const IOOptions& /*options*/,
+                IODebugContext* /*dbg*/) override {
+    return file_->Sync();
+  }
+
+  uint64_t GetFileSize(const IOOptions& /*options*/,
+                       IODebugContext* /*dbg*/) override {
+    return offset_;
+  }
+
+ private:
+  std::unique_ptr<BackendFile> file_;
+  uint64_t offset_;
+};
+
+class BackendDirectory : public FSDirectory {
+ public:
+  IOStatus Fsync(const IOOptions& /*options*/,
+                 IODebugContext* /*dbg*/) override {
+    return IOStatus::OK();
+  }
+};
+
+class BackendFileLock : public FileLock {
+ public:
+  explicit BackendFileLock(std::string name) : name_(std::move(name)) {}
+
+  const std::string& name() const { return name_; }
+
+ private:
+  std::string name_;
+};
+
+class BackendFileSystem : public FileSystem {
+ public:
+  explicit BackendFileSystem(std::shared_ptr<Backend> backend)
+      : backend_(std::move(backend)) {}
+
+  static const char* kClassName() { return "PoloDBStorageBackend"; }
+  const char* Name() const override { return kClassName(); }
+
+  IOStatus NewSequentialFile(const std::string& fname,
+                             const FileOptions& /*options*/,
+                             std::unique_ptr<FSSequentialFile>* result,
+                             IODebugContext* /*dbg*/) override {
+    std::unique_ptr<BackendFile> file;
+    IOStatus s = Open(fname, POLODB_STORAGE_OPEN_READ, &file);
+    if (s.ok()) {
+      result->reset(new BackendSequentialFile(std::move(file)));
+    }
+    return s;
+  }
+
+  IOStatus NewRandomAccessFile(const std::string& fname,
+                               const FileOptions& /*options*/,
+                               std::unique_ptr<FSRandomAccessFile>* result,
+                               IODebugContext* /*dbg*/) override {
+    std::unique_ptr<BackendFile> file;
+    IOStatus s = Open(fname, POLODB_STORAGE_OPEN_READ, &file);
+    if (s.ok()) {
+      result->reset(new BackendRandomAccessFile(std::move(file)));
+    }
+    return s;
+  }
+
+  IOStatus NewWritableFile(const std::string& fname,
+                           const FileOptions& /*options*/,
+                           std::unique_ptr<FSWritableFile>* result,
+                           IODebugContext* /*dbg*/) override {
+    std::unique_ptr<BackendFile> file;
+    IOStatus s = Open(fname, POLODB_STORAGE_OPEN_TRUNCATE, &file);
+    if (s.ok()) {
+      result->reset(new BackendWritableFile(std::move(file), 0));
+    }
+    return s;
+  }
+
+  IOStatus ReopenWritableFile(const std::string& fname,
+                              const FileOptions& /*options*/,
+                              std::unique_ptr<FSWritableFile>* result,
+                              IODebugContext* /*dbg*/) override {
+    std::unique_ptr<BackendFile> file;
+    IOStatus s = Open(fname, POLODB_STORAGE_OPEN_APPEND, &file);
+    uint64_t size = 0;
+    if (s.ok()) {
+      s = file->Size(&size);
+    }
+    if (s.ok()) {
+      result->reset(new BackendWritableFile(std::move(file), size));
+    }
+    return s;
+  }
+
+  IOStatus NewDirectory(const std::string& /*name*/,
+                        const IOOptions& /*options*/,
+                        std::unique_ptr<FSDirectory>* result,
+                        IODebugContext* /*dbg*/) override {
+    result->reset(new BackendDirectory());
+    return IOStatus::OK();
+  }
+
+  IOStatus FileExists(const std::string& fname, const IOOptions& /*options*/,
+                      IODebugContext* /*dbg*/) override {
+    char* err = nullptr;
+    int code = callbacks().exists(backend_->state(), fname.c_str(), &err);
+    // The engine expects NotFound instead of PathNotFound here
+    if (code == POLODB_STORAGE_NOT_FOUND) {
+      free(err);
+      return IOStatus::NotFound();
+    }
+    return ToStatus(code, err, fname);
+  }
+
+  IOStatus GetChildren(const std::string& dir, const IOOptions& /*options*/,
+                       std::vector<std::string>* result,
+                       IODebugContext* /*dbg*/) override {
+    char* names = nullptr;
+    size_t names_len = 0;
+    char* err = nullptr;
+    int code = callbacks().list(backend_->state(), dir.c_str(), &names,
+                                &names_len, &err);
+    result->clear();
+    if (names != nullptr) {
+      size_t begin = 0;
+      for (size_t i = 0; i < names_len; i++) {
+        if (names[i] == '\0') {
+          result->emplace_back(names + begin, i - begin);
+          begin = i + 1;
+        }
+      }
+      if (begin < names_len) {
+        result->emplace_back(names + begin, names_len - begin);
+      }
+      free(names);
+    }
+    return ToStatus(code, err, dir);
+  }
+
+  IOStatus DeleteFile(const std::string& fname, const IOOptions& /*options*/,
+                      IODebugContext* /*dbg*/) override {
+    return DeletePath(fname);
+  }
+
+  IOStatus Truncate(const std::string& fname, size_t size,
+                    const IOOptions& /*options*/,
+                    IODebugContext* /*dbg*/) override {
+    std::unique_ptr<BackendFile> file;
+    IOStatus s = Open(fname, POLODB_STORAGE_OPEN_APPEND, &file);
+    if (s.ok()) {
+      s = file->Truncate(size);
+    }
+    return s;
+  }
+
+  IOStatus CreateDir(const std::string& dirname, const IOOptions& options,
+                     IODebugContext* dbg) override {
+    return CreateDirIfMissing(dirname, options, dbg);
+  }
+
+  IOStatus CreateDirIfMissing(const std::string& dirname,
+                              const IOOptions& /*options*/,
+                              IODebugContext* /*dbg*/) override {
+    char* err = nullptr;
+    int code = callbacks().create_dir(backend_->state(), dirname.c_str(), &err);
+    return ToStatus(code, err, dirname);
+  }
+
+  IOStatus DeleteDir(const std::string& dirname, const IOOptions& /*options*/,
+                     IODebugContext* /*dbg*/) override {
+    return DeletePath(dirname);
+  }
+
+  IOStatus GetFileSize(const std::string& fname, const IOOptions& /*options*/,
+                       uint64_t* file_size, IODebugContext* /*dbg*/) override {
+    std::unique_ptr<BackendFile> file;
+    IOStatus s = Open(fname, POLODB_STORAGE_OPEN_READ, &file);
+    if (s.ok()) {
+      s = file->Size(file_size);
+    }
+    return s;
+  }
+
+  IOStatus GetFileModificationTime(const std::string& /*fname*/,
+                                   const IOOptions& /*options*/,
+                                   uint64_t* file_mtime,
+                                   IODebugContext* /*dbg*/) override {
+    *file_mtime = 0;
+    return IOStatus::OK();
+  }
+
+  IOStatus RenameFile(const std::string& src, const std::string& target,
+                      const IOOptions& /*options*/,
+                      IODebugContext* /*dbg*/) override {
+    char* err = nullptr;
+    int code = callbacks().rename(backend_->state(), src.c_str(),
+                                  target.c_str(), &err);
+    return ToStatus(code, err, src);
+  }
+
+  // The lock only protects the database from being opened twice in the same
+  // process, the backend is responsible for the locking between processes.
+  IOStatus LockFile(const std::string& fname, const IOOptions& /*options*/,
+                    FileLock** lock, IODebugContext* /*dbg*/) override {
+    std::lock_guard<std::mutex> guard(locks_mutex_);
+    if (!locks_.insert(fname).second) {
+      return IOStatus::IOError(fname, "lock is already held");
+    }
+    *lock = new BackendFileLock(fname);
+    return IOStatus::OK();
+  }
+
+  IOStatus UnlockFile(FileLock* lock, const IOOptions& /*options*/,
+                      IODebugContext* /*dbg*/) override {
+    auto backend_lock = static_cast<BackendFileLock*>(lock);
+    {
+      std::lock_guard<std::mutex> guard(locks_mutex_);
+      locks_.erase(backend_lock->name());
+    }
+    delete backend_lock;
+    return IOStatus::OK();
+  }
+
+  IOStatus GetTestDirectory(const IOOptions& /*options*/, std::string* path,
+                            IODebugContext* /*dbg*/) override {
+    *path = "/test";
+    return IOStatus::OK();
+  }
+
+  IOStatus GetAbsolutePath(const std::string& db_path,
+                           const IOOptions& /*options*/,
+                           std::string* output_path,
+                           IODebugContext* /*dbg*/) override {
+    *output_path = db_path;
+    return IOStatus::OK();
+  }
+
+  IOStatus IsDirectory(const std::string& path, const IOOptions& /*options*/,
+                       bool* is_dir, IODebugContext* /*dbg*/) override {
+    int result = 0;
+    char* err = nullptr;
+    int code =
+        callbacks().is_dir(backend_->state(), path.c_str(), &result, &err);
+    if (code == POLODB_STORAGE_OK && is_dir != nullptr) {
+      *is_dir = result != 0;
+    }
+    return ToStatus(code, err, path);
+  }
+
+ private:
+  const polodb_storage_backend_t& callbacks() const {
+    return backend_->callbacks();
+  }
+
+  IOStatus Open(const std::string& fname, int mode,
+                std::unique_ptr<BackendFile>* result) {
+    void* file = nullptr;
+    char* err = nullptr;
+    int code =
+        callbacks().open(backend_->state(), fname.c_str(), mode, &file, &err);
+    IOStatus s = ToStatus(code, err, fname);
+    if (s.ok()) {
+      result->reset(new BackendFile(backend_, file, fname));
+    }
+    return s;
+  }
+
+  IOStatus DeletePath(const std::string& path) {
+    char* err = nullptr;
+    int code = callbacks().delete_path(backend_->state(), path.c_str(), &err);
+    return ToStatus(code, err, path);
+  }
+
+  std::shared_ptr<Backend> backend_;
+  std::mutex locks_mutex_;
+  std::set<std::string> locks_;
+};
+
+}  // namespace
+
+extern "C" {
+
+rocksdb_env_t* polodb_create_storage_backend_env(
+    const polodb_storage_backend_t* backend) {
+  auto fs = std::make_shared<BackendFileSystem>(
+      std::make_shared<Backend>(*backend));
+  rocksdb_env_t* result = new rocksdb_env_t;
+  result->rep = ROCKSDB_NAMESPACE::NewCompositeEnv(fs).release();
+  result->is_default = false;
+  return result;
+}
+
+}  // end extern "C"
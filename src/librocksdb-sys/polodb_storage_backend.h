@@ -0,0 +1,68 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/* A RocksDB env which forwards the file operations to callbacks,
+ * so the storage backend can be implemented outside of C++. */
+
+#pragma once
+
+#include "rocksdb/c.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The status returned by the callbacks. The error message of
+ * POLODB_STORAGE_IO_ERROR is allocated by malloc() and freed by the env. */
+#define POLODB_STORAGE_OK 0
+#define POLODB_STORAGE_NOT_FOUND 1
+#define POLODB_STORAGE_IO_ERROR 2
+
+/* The modes to open a file. */
+#define POLODB_STORAGE_OPEN_READ 0
+#define POLODB_STORAGE_OPEN_TRUNCATE 1
+#define POLODB_STORAGE_OPEN_APPEND 2
+
+typedef struct polodb_storage_backend_t {
+  void* state;
+  void (*destructor)(void* state);
+  int (*open)(void* state, const char* path, int mode, void** file,
+              char** errptr);
+  void (*close)(void* state, void* file);
+  int (*read_at)(void* state, void* file, uint64_t offset, char* buf,
+                 size_t len, size_t* read, char** errptr);
+  int (*write_at)(void* state, void* file, uint64_t offset, const char* data,
+                  size_t len, char** errptr);
+  int (*sync)(void* state, void* file, char** errptr);
+  int (*size)(void* state, void* file, uint64_t* size, char** errptr);
+  int (*truncate)(void* state, void* file, uint64_t size, char** errptr);
+  int (*delete_path)(void* state, const char* path, char** errptr);
+  int (*rename)(void* state, const char* from, const char* to,
+                char** errptr);
+  int (*exists)(void* state, const char* path, char** errptr);
+  /* The names are separated by '\0' and allocated by malloc(). */
+  int (*list)(void* state, const char* dir, char** names, size_t* names_len,
+              char** errptr);
+  int (*create_dir)(void* state, const char* dir, char** errptr);
+  int (*is_dir)(void* state, const char* path, int* is_dir, char** errptr);
+} polodb_storage_backend_t;
+
+/* The env takes the ownership of the state and calls the destructor
+ * when the env is destroyed by rocksdb_env_destroy(). */
+extern ROCKSDB_LIBRARY_API rocksdb_env_t* polodb_create_storage_backend_env(
+    const polodb_storage_backend_t* backend);
+
+#ifdef __cplusplus
+} /* end extern "C" */
+#endif
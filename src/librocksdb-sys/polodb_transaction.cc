@@ -0,0 +1,73 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#include "polodb_transaction.h"
+
+#include <stdlib.h>
+#include <string.h>
+
+#include "rocksdb/slice.h"
+#include "rocksdb/status.h"
+#include "rocksdb/utilities/transaction.h"
+#include "rocksdb/utilities/transaction_db.h"
+#include "rocksdb/write_batch.h"
+
+using ROCKSDB_NAMESPACE::Slice;
+using ROCKSDB_NAMESPACE::Status;
+using ROCKSDB_NAMESPACE::Transaction;
+using ROCKSDB_NAMESPACE::TransactionDB;
+using ROCKSDB_NAMESPACE::TransactionDBWriteOptimizations;
+using ROCKSDB_NAMESPACE::WriteBatch;
+using ROCKSDB_NAMESPACE::WriteOptions;
+
+// The same definitions as db/c.cc, which are not exported by a header.
+struct rocksdb_transaction_t {
+  Transaction* rep;
+};
+struct rocksdb_transactiondb_t {
+  TransactionDB* rep;
+};
+struct rocksdb_writeoptions_t {
+  WriteOptions rep;
+};
+struct rocksdb_writebatch_t {
+  WriteBatch rep;
+};
+
+extern "C" {
+
+void polodb_transaction_put_log_data(rocksdb_transaction_t* txn,
+                                     const char* blob, size_t len) {
+  txn->rep->PutLogData(Slice(blob, len));
+}
+
+void polodb_transactiondb_write_unlocked(rocksdb_transactiondb_t* txn_db,
+                                         const rocksdb_writeoptions_t* options,
+                                         rocksdb_writebatch_t* batch,
+                                         char** errptr) {
+  // the transactional DeleteRange is not supported, but a batch
+  // written without the concurrency control can have range deletions
+  TransactionDBWriteOptimizations optimizations;
+  optimizations.skip_concurrency_control = true;
+  Status s = txn_db->rep->Write(options->rep, optimizations, &batch->rep);
+  if (s.ok()) {
+    return;
+  }
+  if (*errptr != nullptr) {
+    free(*errptr);
+  }
+  *errptr = strdup(s.ToString().c_str());
+}
+
+}  // extern "C"
@@ -0,0 +1,40 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/* The transaction functions which are missing in the C API of RocksDB. */
+
+#pragma once
+
+#include <stddef.h>
+
+#include "rocksdb/c.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Append a blob to the write-ahead log record of the transaction,
+ * which is not written to the database. */
+extern ROCKSDB_LIBRARY_API void polodb_transaction_put_log_data(
+    rocksdb_transaction_t* txn, const char* blob, size_t len);
+
+/* Write a batch without locking its keys, which is required by the
+ * range deletions. The batch must not conflict with the transactions. */
+extern ROCKSDB_LIBRARY_API void polodb_transactiondb_write_unlocked(
+    rocksdb_transactiondb_t* txn_db, const rocksdb_writeoptions_t* options,
+    rocksdb_writebatch_t* batch, char** errptr);
+
+#ifdef __cplusplus
+}
+#endif
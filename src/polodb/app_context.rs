@@ -29,17 +29,29 @@ pub(crate) struct AppContext {
 
 impl AppContext {
 
-    pub(crate) fn new(db: Database) -> Self {
+    pub(crate) fn new(db: Database, require_auth: bool) -> Self {
         AppContext {
-            inner: Arc::new(AppContextInner::new(db)),
+            inner: Arc::new(AppContextInner::new(db, require_auth)),
         }
     }
 
+    /// Whether the clients must authenticate before running the commands.
+    #[inline]
+    pub(crate) fn require_auth(&self) -> bool {
+        self.inner.require_auth
+    }
+
     #[inline]
     pub(crate) fn db(&self) -> Arc<Database> {
         self.inner.db.clone()
     }
 
+    /// The named database of the `$db` of a command.
+    #[inline]
+    pub(crate) fn database(&self, name: &str) -> Database {
+        self.inner.db.database(name)
+    }
+
     pub(crate) fn register_handlers(&self, handlers: Vec<Arc<dyn Handler>>) {
         let mut handlers_guard = self.inner.handlers.lock().unwrap();
         for handler in handlers {
@@ -62,17 +74,41 @@ impl AppContext {
         self.inner.conn_id.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
     }
 
-    pub (crate) fn save_cursor(&self, cursor: Arc<Mutex<ClientCursor<Document>>>) -> i64 {
+    /// Save the cursor of the user on the namespace, returns a random id,
+    /// so the clients can't guess the cursors of the others.
+    pub (crate) fn save_cursor(&self, cursor: Arc<Mutex<ClientCursor<Document>>>, owner: Option<&str>, db_name: &str, col_name: &str) -> i64 {
         let mut cursors = self.inner.cursors.lock().unwrap();
-        let mut cursor_id = cursors.len() as i64;
-        cursor_id += 1; // cursor_id starts from 1
-        cursors.insert(cursor_id, cursor);
+        let cursor_id = loop {
+            let mut bytes = [0u8; 8];
+            getrandom::getrandom(&mut bytes).expect("the system random number generator is unavailable");
+            // 0 means the cursor is exhausted
+            let cursor_id = i64::from_le_bytes(bytes) & i64::MAX;
+            if cursor_id != 0 && !cursors.contains_key(&cursor_id) {
+                break cursor_id;
+            }
+        };
+        cursors.insert(cursor_id, SavedCursor {
+            cursor,
+            owner: owner.map(str::to_string),
+            namespace: format!("{}.{}", db_name, col_name),
+        });
         cursor_id
     }
 
-    pub(crate) fn get_cursor(&self, cursor_id: i64) -> Option<Arc<Mutex<ClientCursor<Document>>>> {
+    pub(crate) fn get_cursor(&self, cursor_id: i64) -> Option<SavedCursor> {
         let cursors = self.inner.cursors.lock().unwrap();
-        cursors.get(&cursor_id).map(|c| c.clone())
+        cursors.get(&cursor_id).cloned()
+    }
+
+    /// Remove the cursors of the user on the namespace, ignores the others.
+    pub(crate) fn remove_owned_cursors(&self, cursor_ids: &[i64], owner: Option<&str>, db_name: &str, col_name: &str) {
+        let mut cursors = self.inner.cursors.lock().unwrap();
+        for cursor_id in cursor_ids {
+            let owned = cursors.get(cursor_id).is_some_and(|saved| saved.is_owned_by(owner, db_name, col_name));
+            if owned {
+                cursors.remove(cursor_id);
+            }
+        }
     }
 
     pub(crate) fn remove_cursor(&self, cursor_ids: &[i64]) {
@@ -82,8 +118,8 @@ impl AppContext {
         }
     }
 
-    pub(crate) fn create_session(&self, uuid: Uuid, txn: Transaction) -> SessionContext {
-        let result = SessionContext::new(txn);
+    pub(crate) fn create_session(&self, uuid: Uuid, db_name: &str, txn: Transaction) -> SessionContext {
+        let result = SessionContext::new(db_name, txn);
         let mut session_ctx = self.inner.session_ctx.lock().unwrap();
         session_ctx.insert(uuid, result.clone());
         result
@@ -95,19 +131,39 @@ impl AppContext {
     }
 }
 
+/// A cursor kept for the `getMore` commands.
+#[derive(Clone)]
+pub(crate) struct SavedCursor {
+    pub(crate) cursor: Arc<Mutex<ClientCursor<Document>>>,
+    /// The name of the user who opened the cursor, `None` if not authenticated.
+    pub(crate) owner: Option<String>,
+    /// `<db>.<collection>` of the cursor.
+    pub(crate) namespace: String,
+}
+
+impl SavedCursor {
+
+    pub(crate) fn is_owned_by(&self, owner: Option<&str>, db_name: &str, col_name: &str) -> bool {
+        self.owner.as_deref() == owner && self.namespace == format!("{}.{}", db_name, col_name)
+    }
+
+}
+
 struct AppContextInner {
     db: Arc<Database>,
+    require_auth: bool,
     handlers: Mutex<Vec<Arc<dyn Handler>>>,
-    cursors: Mutex<HashMap<i64, Arc<Mutex<ClientCursor<Document>>>>>,
+    cursors: Mutex<HashMap<i64, SavedCursor>>,
     conn_id: AtomicU64,
     session_ctx: Mutex<HashMap<Uuid, SessionContext>>,
 }
 
 impl AppContextInner {
 
-    fn new(db: Database) -> Self {
+    fn new(db: Database, require_auth: bool) -> Self {
         AppContextInner {
             db: Arc::new(db),
+            require_auth,
             handlers: Mutex::new(Vec::with_capacity(32)),
             cursors: Mutex::new(HashMap::new()),
             conn_id: AtomicU64::new(0),
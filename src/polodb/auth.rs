@@ -0,0 +1,189 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The authentication of the wire protocol by the SASL commands, and
+//! the authorization of the other commands by the roles of the user.
+//!
+//! Only `SCRAM-SHA-256` is supported, the drivers negotiate it by the
+//! `saslSupportedMechs` of the `hello` command.
+
+use anyhow::{anyhow, Result};
+use bson::{rawdoc, Binary, Document, RawBsonRef, RawDocumentBuf};
+use bson::spec::BinarySubtype;
+use polodb_core::auth::{lookup_collections, Action, ScramConversation, User, SCRAM_SHA_256};
+use polodb_core::Database;
+
+/// The error codes of MongoDB.
+pub(crate) const UNAUTHORIZED: i32 = 13;
+const AUTHENTICATION_FAILED: i32 = 18;
+
+/// The commands which don't need an authenticated user.
+const PUBLIC_COMMANDS: &[&str] = &[
+    "hello", "isMaster", "ismaster", "ping", "buildInfo", "buildinfo",
+    "saslStart", "saslContinue", "logout", "endSessions",
+];
+
+/// The authentication state of a connection.
+#[derive(Default)]
+pub(crate) struct ConnectionAuth {
+    user: Option<User>,
+    conversation: Option<ScramConversation>,
+}
+
+fn command_name(doc: &RawDocumentBuf) -> Result<&str> {
+    let (name, _) = doc.iter().next().ok_or(anyhow!("the command is empty"))??;
+    Ok(name)
+}
+
+pub(crate) fn error_doc(code: i32, message: &str) -> RawDocumentBuf {
+    let code_name = if code == UNAUTHORIZED { "Unauthorized" } else { "AuthenticationFailed" };
+    rawdoc! {
+        "ok": 0,
+        "errmsg": message,
+        "code": code,
+        "codeName": code_name,
+    }
+}
+
+fn payload_of(doc: &RawDocumentBuf) -> Result<&[u8]> {
+    match doc.get("payload")? {
+        Some(RawBsonRef::Binary(binary)) => Ok(binary.bytes),
+        Some(RawBsonRef::String(payload)) => Ok(payload.as_bytes()),
+        _ => Err(anyhow!("payload is missing")),
+    }
+}
+
+fn sasl_reply(done: bool, payload: Vec<u8>) -> RawDocumentBuf {
+    rawdoc! {
+        "conversationId": 1,
+        "done": done,
+        "payload": Binary { subtype: BinarySubtype::Generic, bytes: payload },
+        "ok": 1,
+    }
+}
+
+impl ConnectionAuth {
+
+    /// Handle `saslStart`, `saslContinue` and `logout`,
+    /// returns `None` for the other commands.
+    pub(crate) fn handle_command(&mut self, db: &Database, doc: &RawDocumentBuf) -> Result<Option<RawDocumentBuf>> {
+        let reply = match command_name(doc)? {
+            "saslStart" => {
+                self.conversation = None;
+                let mechanism = doc.get_str("mechanism")?;
+                if mechanism != SCRAM_SHA_256 {
+                    let message = format!("the mechanism {} is not supported", mechanism);
+                    return Ok(Some(error_doc(AUTHENTICATION_FAILED, &message)));
+                }
+                match db.users().scram_start(payload_of(doc)?) {
+                    Ok((conversation, server_first)) => {
+                        self.conversation = Some(conversation);
+                        sasl_reply(false, server_first)
+                    }
+                    Err(_) => error_doc(AUTHENTICATION_FAILED, "Authentication failed."),
+                }
+            }
+            "saslContinue" => match self.conversation.take() {
+                Some(conversation) => match conversation.finish(payload_of(doc)?) {
+                    Ok((server_final, user)) => {
+                        self.user = Some(user);
+                        sasl_reply(true, server_final)
+                    }
+                    Err(_) => error_doc(AUTHENTICATION_FAILED, "Authentication failed."),
+                },
+                // the empty step after the final message of the server
+                None if self.user.is_some() => sasl_reply(true, Vec::new()),
+                None => error_doc(AUTHENTICATION_FAILED, "no SASL conversation is started"),
+            },
+            "logout" => {
+                self.user = None;
+                self.conversation = None;
+                rawdoc! { "ok": 1 }
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(reply))
+    }
+
+    pub(crate) fn user_name(&self) -> Option<&str> {
+        self.user.as_ref().map(|user| user.name.as_str())
+    }
+
+    /// Check the roles of the user for the command,
+    /// returns the error reply if it's not allowed.
+    pub(crate) fn authorize(&self, doc: &RawDocumentBuf) -> Result<Option<RawDocumentBuf>> {
+        let name = command_name(doc)?;
+        if PUBLIC_COMMANDS.contains(&name) {
+            return Ok(None);
+        }
+        let user = match &self.user {
+            Some(user) => user,
+            None => {
+                let message = format!("command {} requires authentication", name);
+                return Ok(Some(error_doc(UNAUTHORIZED, &message)));
+            }
+        };
+        let database = match doc.get("$db")? {
+            Some(RawBsonRef::String(database)) => database,
+            _ => return Err(anyhow!("$db is missing")),
+        };
+        let collection = match name {
+            "getMore" => doc.get_str("collection").ok(),
+            _ => doc.get_str(name).ok(),
+        };
+
+        let mut checks = vec![(required_action(name), collection.map(str::to_string))];
+        if name == "aggregate" {
+            let pipeline = doc
+                .get_array("pipeline")?
+                .into_iter()
+                .filter_map(|stage| stage.ok()?.as_document().and_then(|stage| bson::from_slice(stage.as_bytes()).ok()))
+                .collect::<Vec<Document>>();
+            for foreign in lookup_collections(&pipeline) {
+                checks.push((Some(Action::Read), Some(foreign)));
+            }
+        }
+
+        for (action, collection) in checks {
+            let action = match action {
+                Some(action) => action,
+                None => continue,
+            };
+            if !user.is_allowed(action, Some(database), collection.as_deref()) {
+                let message = format!(
+                    "not authorized on {} to execute command {} on {}",
+                    database,
+                    name,
+                    collection.as_deref().unwrap_or("the database"),
+                );
+                return Ok(Some(error_doc(UNAUTHORIZED, &message)));
+            }
+        }
+        Ok(None)
+    }
+
+}
+
+/// `None` for the commands allowed to every authenticated user.
+fn required_action(command: &str) -> Option<Action> {
+    match command {
+        "find" | "aggregate" | "count" | "distinct" | "getMore" | "killCursors"
+        | "listCollections" | "listIndexes" => Some(Action::Read),
+        "insert" | "update" | "delete" | "findAndModify" | "create" | "drop"
+        | "createIndexes" | "dropIndexes" | "dropDatabase" => Some(Action::Write),
+        "commitTransaction" | "abortTransaction" | "connectionStatus" => None,
+        _ => Some(Action::Admin),
+    }
+}
@@ -0,0 +1,545 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The workloads of `polodb bench`.
+//!
+//! The collection is dropped and preloaded with `keys` documents, then the operations
+//! of the mix are run by the threads. The reads and the updates pick the keys of the
+//! preloaded documents by the distribution, the inserts add new keys.
+//! The workload is reproducible with the same seed, except the interleaving of the threads.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use bson::{doc, Document};
+use polodb_core::{CollectionT, Database};
+use serde::Serialize;
+
+const LOAD_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum Op {
+    Insert,
+    Read,
+    Update,
+}
+
+impl Op {
+
+    fn name(self) -> &'static str {
+        match self {
+            Op::Insert => "insert",
+            Op::Read => "read",
+            Op::Update => "update",
+        }
+    }
+
+}
+
+const OPS: [Op; 3] = [Op::Insert, Op::Read, Op::Update];
+
+/// The weights of the operations, e.g. `read=80,update=20`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) struct Mix {
+    pub insert: u32,
+    pub read: u32,
+    pub update: u32,
+}
+
+impl Mix {
+
+    pub(crate) fn parse(value: &str) -> Result<Mix> {
+        let mut mix = Mix { insert: 0, read: 0, update: 0 };
+        for item in value.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let (name, weight) = item.split_once('=')
+                .ok_or_else(|| anyhow!("invalid mix '{}', expected e.g. read=80,update=20", item))?;
+            let weight: u32 = weight.trim().parse()
+                .map_err(|_| anyhow!("invalid weight of '{}'", name))?;
+            match name.trim() {
+                "insert" => mix.insert = weight,
+                "read" => mix.read = weight,
+                "update" => mix.update = weight,
+                name => return Err(anyhow!("unknown operation '{}', expected insert, read or update", name)),
+            }
+        }
+        if mix.total() == 0 {
+            return Err(anyhow!("the weights of the mix are all 0"));
+        }
+        Ok(mix)
+    }
+
+    fn total(&self) -> u32 {
+        self.insert + self.read + self.update
+    }
+
+    fn pick(&self, rng: &mut Rng) -> Op {
+        let value = rng.below(self.total() as u64) as u32;
+        if value < self.insert {
+            Op::Insert
+        } else if value < self.insert + self.read {
+            Op::Read
+        } else {
+            Op::Update
+        }
+    }
+
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum Distribution {
+    Uniform,
+    /// A few keys are hot, like the YCSB workloads, with the constant 0.99.
+    Zipfian,
+    /// The keys in order, wrapping around the key space.
+    Sequential,
+}
+
+impl Distribution {
+
+    pub(crate) fn parse(value: &str) -> Result<Distribution> {
+        match value {
+            "uniform" => Ok(Distribution::Uniform),
+            "zipfian" => Ok(Distribution::Zipfian),
+            "sequential" => Ok(Distribution::Sequential),
+            _ => Err(anyhow!("unknown distribution '{}', expected uniform, zipfian or sequential", value)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Distribution::Uniform => "uniform",
+            Distribution::Zipfian => "zipfian",
+            Distribution::Sequential => "sequential",
+        }
+    }
+
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BenchOptions {
+    pub collection: String,
+    /// The number of the operations after the preload.
+    pub ops: u64,
+    pub threads: u32,
+    pub mix: Mix,
+    /// The size in bytes of the payload of the documents.
+    pub doc_size: usize,
+    /// The number of the preloaded documents.
+    pub keys: u64,
+    pub distribution: Distribution,
+    pub seed: u64,
+}
+
+impl Default for BenchOptions {
+
+    fn default() -> Self {
+        BenchOptions {
+            collection: "bench".to_string(),
+            ops: 100_000,
+            threads: 1,
+            mix: Mix { insert: 10, read: 80, update: 10 },
+            doc_size: 256,
+            keys: 10_000,
+            distribution: Distribution::Uniform,
+            seed: 42,
+        }
+    }
+
+}
+
+/// The latencies in microseconds.
+#[derive(Debug, Clone, Default, Serialize)]
+pub(crate) struct Latency {
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub p999: f64,
+    pub max: f64,
+}
+
+impl Latency {
+
+    fn from_samples(samples: &mut [Duration]) -> Latency {
+        if samples.is_empty() {
+            return Latency::default();
+        }
+        samples.sort_unstable();
+        let micros = |duration: Duration| duration.as_secs_f64() * 1_000_000.0;
+        let percentile = |p: f64| {
+            let index = ((samples.len() as f64 * p).ceil() as usize).clamp(1, samples.len()) - 1;
+            micros(samples[index])
+        };
+        let total: Duration = samples.iter().sum();
+        Latency {
+            mean: micros(total) / samples.len() as f64,
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            p99: percentile(0.99),
+            p999: percentile(0.999),
+            max: micros(samples[samples.len() - 1]),
+        }
+    }
+
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct OpReport {
+    pub op: Op,
+    pub count: u64,
+    pub errors: u64,
+    /// The operations per second.
+    pub throughput: f64,
+    pub latency: Latency,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BenchReport {
+    pub options: BenchOptions,
+    /// The time in seconds to preload the documents.
+    pub load_seconds: f64,
+    /// The time in seconds to run the operations.
+    pub seconds: f64,
+    /// The operations per second of all the threads.
+    pub throughput: f64,
+    pub operations: Vec<OpReport>,
+}
+
+impl BenchReport {
+
+    pub(crate) fn to_table(&self) -> String {
+        let options = &self.options;
+        let mut result = String::new();
+        let _ = writeln!(
+            result,
+            "{} ops, {} threads, mix insert={} read={} update={}, {} bytes per document, {} keys {}, seed {}",
+            options.ops, options.threads, options.mix.insert, options.mix.read, options.mix.update,
+            options.doc_size, options.keys, options.distribution.name(), options.seed,
+        );
+        let _ = writeln!(
+            result,
+            "load: {} documents in {:.3}s ({:.0} docs/s)",
+            options.keys, self.load_seconds, options.keys as f64 / self.load_seconds.max(f64::EPSILON),
+        );
+        let _ = writeln!(result, "run: {:.3}s ({:.0} ops/s)", self.seconds, self.throughput);
+        let _ = writeln!(
+            result,
+            "{:<8}{:>10}{:>8}{:>12}{:>10}{:>10}{:>10}{:>10}{:>10}{:>10}",
+            "op", "count", "errors", "ops/s", "mean", "p50", "p90", "p99", "p99.9", "max",
+        );
+        for op in &self.operations {
+            let latency = &op.latency;
+            let _ = writeln!(
+                result,
+                "{:<8}{:>10}{:>8}{:>12.0}{:>10.1}{:>10.1}{:>10.1}{:>10.1}{:>10.1}{:>10.1}",
+                op.op.name(), op.count, op.errors, op.throughput,
+                latency.mean, latency.p50, latency.p90, latency.p99, latency.p999, latency.max,
+            );
+        }
+        result.push_str("the latencies are in microseconds");
+        result
+    }
+
+}
+
+/// A small deterministic generator (splitmix64), the benchmark doesn't need a better one.
+struct Rng(u64);
+
+impl Rng {
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n.max(1)
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn payload(&mut self, size: usize) -> String {
+        const CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+        (0..size).map(|_| CHARS[self.below(CHARS.len() as u64) as usize] as char).collect()
+    }
+
+}
+
+/// The zipfian generator of "Quickly Generating Billion-Record Synthetic Databases",
+/// as used by YCSB.
+struct Zipfian {
+    items: u64,
+    theta: f64,
+    alpha: f64,
+    zeta: f64,
+    eta: f64,
+}
+
+impl Zipfian {
+
+    const THETA: f64 = 0.99;
+
+    fn new(items: u64) -> Zipfian {
+        let theta = Zipfian::THETA;
+        let zeta = (1..=items).map(|i| 1.0 / (i as f64).powf(theta)).sum::<f64>();
+        let zeta2 = 1.0 + 1.0 / 2f64.powf(theta);
+        Zipfian {
+            items,
+            theta,
+            alpha: 1.0 / (1.0 - theta),
+            zeta,
+            eta: (1.0 - (2.0 / items as f64).powf(1.0 - theta)) / (1.0 - zeta2 / zeta),
+        }
+    }
+
+    fn next(&self, rng: &mut Rng) -> u64 {
+        let u = rng.next_f64();
+        let uz = u * self.zeta;
+        if uz < 1.0 {
+            return 0;
+        }
+        if uz < 1.0 + 0.5f64.powf(self.theta) {
+            return 1.min(self.items - 1);
+        }
+        let value = (self.items as f64 * (self.eta * u - self.eta + 1.0).powf(self.alpha)) as u64;
+        value.min(self.items - 1)
+    }
+
+}
+
+enum KeyChooser {
+    Uniform,
+    Zipfian(Zipfian),
+    Sequential,
+}
+
+impl KeyChooser {
+
+    fn new(distribution: Distribution, keys: u64) -> KeyChooser {
+        match distribution {
+            Distribution::Uniform => KeyChooser::Uniform,
+            Distribution::Zipfian => KeyChooser::Zipfian(Zipfian::new(keys)),
+            Distribution::Sequential => KeyChooser::Sequential,
+        }
+    }
+
+    fn next(&self, rng: &mut Rng, keys: u64, counter: &mut u64) -> u64 {
+        match self {
+            KeyChooser::Uniform => rng.below(keys),
+            KeyChooser::Zipfian(zipfian) => {
+                // scatter the hot keys over the key space
+                let rank = zipfian.next(rng);
+                rank.wrapping_mul(0x9E3779B97F4A7C15) % keys
+            }
+            KeyChooser::Sequential => {
+                let key = *counter % keys;
+                *counter += 1;
+                key
+            }
+        }
+    }
+
+}
+
+#[derive(Default)]
+struct ThreadResult {
+    samples: [Vec<Duration>; 3],
+    errors: [u64; 3],
+}
+
+fn make_document(id: i64, rng: &mut Rng, size: usize) -> Document {
+    doc! {
+        "_id": id,
+        "payload": rng.payload(size),
+        "counter": 0_i64,
+    }
+}
+
+pub(crate) fn run_bench(db: &Database, options: &BenchOptions) -> Result<BenchReport> {
+    if options.keys == 0 && options.mix.read + options.mix.update > 0 {
+        return Err(anyhow!("the reads and the updates need at least 1 key"));
+    }
+    let threads = options.threads.max(1) as u64;
+    let collection = db.collection::<Document>(&options.collection);
+    collection.drop()?;
+
+    let mut rng = Rng(options.seed);
+    let load_start = Instant::now();
+    let mut next_key = 0;
+    while next_key < options.keys {
+        let end = (next_key + LOAD_BATCH_SIZE as u64).min(options.keys);
+        let docs: Vec<Document> = (next_key..end)
+            .map(|id| make_document(id as i64, &mut rng, options.doc_size))
+            .collect();
+        collection.insert_many(docs)?;
+        next_key = end;
+    }
+    let load_seconds = load_start.elapsed().as_secs_f64();
+
+    let chooser = KeyChooser::new(options.distribution, options.keys.max(1));
+    let next_insert_key = AtomicI64::new(options.keys as i64);
+    let run_start = Instant::now();
+    let results: Vec<ThreadResult> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|thread| {
+                let count = options.ops / threads + u64::from(thread < options.ops % threads);
+                let collection = db.collection::<Document>(&options.collection);
+                let chooser = &chooser;
+                let next_insert_key = &next_insert_key;
+                scope.spawn(move || {
+                    let mut rng = Rng(options.seed.wrapping_add(thread + 1));
+                    let mut sequence = thread * options.keys / threads;
+                    let mut result = ThreadResult::default();
+                    for _ in 0..count {
+                        let op = options.mix.pick(&mut rng);
+                        let start = Instant::now();
+                        let outcome = match op {
+                            Op::Insert => {
+                                let id = next_insert_key.fetch_add(1, Ordering::Relaxed);
+                                let doc = make_document(id, &mut rng, options.doc_size);
+                                collection.insert_one(doc).map(|_| ())
+                            }
+                            Op::Read => {
+                                let key = chooser.next(&mut rng, options.keys, &mut sequence) as i64;
+                                collection.find_one(doc! { "_id": key }).map(|_| ())
+                            }
+                            Op::Update => {
+                                let key = chooser.next(&mut rng, options.keys, &mut sequence) as i64;
+                                let payload = rng.payload(options.doc_size);
+                                collection.update_one(
+                                    doc! { "_id": key },
+                                    doc! { "$set": { "payload": payload }, "$inc": { "counter": 1 } },
+                                ).map(|_| ())
+                            }
+                        };
+                        let elapsed = start.elapsed();
+                        let index = op as usize;
+                        match outcome {
+                            Ok(()) => result.samples[index].push(elapsed),
+                            Err(_) => result.errors[index] += 1,
+                        }
+                    }
+                    result
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+    let seconds = run_start.elapsed().as_secs_f64().max(f64::EPSILON);
+
+    let mut operations = Vec::new();
+    for op in OPS {
+        let index = op as usize;
+        let mut samples: Vec<Duration> = results.iter()
+            .flat_map(|result| result.samples[index].iter().copied())
+            .collect();
+        let errors: u64 = results.iter().map(|result| result.errors[index]).sum();
+        if samples.is_empty() && errors == 0 {
+            continue;
+        }
+        operations.push(OpReport {
+            op,
+            count: samples.len() as u64,
+            errors,
+            throughput: samples.len() as f64 / seconds,
+            latency: Latency::from_samples(&mut samples),
+        });
+    }
+
+    let total: u64 = operations.iter().map(|op| op.count).sum();
+    Ok(BenchReport {
+        options: options.clone(),
+        load_seconds,
+        seconds,
+        throughput: total as f64 / seconds,
+        operations,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use bson::Document;
+    use polodb_core::{CollectionT, Database};
+    use super::{run_bench, BenchOptions, Distribution, Latency, Mix, Op, Rng, Zipfian};
+
+    #[test]
+    fn test_parse_mix() {
+        assert_eq!(Mix::parse("read=80, update=20").unwrap(), Mix { insert: 0, read: 80, update: 20 });
+        assert!(Mix::parse("read=0").is_err());
+        assert!(Mix::parse("scan=10").is_err());
+        assert!(Mix::parse("read").is_err());
+        assert_eq!(Distribution::parse("zipfian").unwrap(), Distribution::Zipfian);
+        assert!(Distribution::parse("latest").is_err());
+    }
+
+    #[test]
+    fn test_zipfian() {
+        let zipfian = Zipfian::new(1000);
+        let mut rng = Rng(1);
+        let mut counts = vec![0u32; 1000];
+        for _ in 0..10_000 {
+            counts[zipfian.next(&mut rng) as usize] += 1;
+        }
+        // the first ranks are the hottest
+        assert!(counts[0] > counts[10]);
+        assert!(counts[0] > 500);
+        assert!(counts.iter().filter(|count| **count > 0).count() > 100);
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let mut samples: Vec<Duration> = (1..=1000).rev().map(Duration::from_micros).collect();
+        let latency = Latency::from_samples(&mut samples);
+        assert_eq!(latency.p50, 500.0);
+        assert_eq!(latency.p99, 990.0);
+        assert_eq!(latency.max, 1000.0);
+        assert!((latency.mean - 500.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_run_bench() {
+        let db = Database::open_memory().unwrap();
+        let options = BenchOptions {
+            ops: 1000,
+            threads: 2,
+            keys: 100,
+            doc_size: 32,
+            mix: Mix { insert: 20, read: 60, update: 20 },
+            distribution: Distribution::Zipfian,
+            ..Default::default()
+        };
+        let report = run_bench(&db, &options).unwrap();
+
+        let count: u64 = report.operations.iter().map(|op| op.count).sum();
+        assert_eq!(count, 1000);
+        assert!(report.operations.iter().all(|op| op.errors == 0));
+        let inserts = report.operations.iter().find(|op| op.op == Op::Insert).unwrap().count;
+        let collection = db.collection::<Document>("bench");
+        assert_eq!(collection.count_documents().unwrap(), 100 + inserts);
+        assert!(report.to_table().contains("update"));
+    }
+
+}
@@ -32,15 +32,7 @@ impl Handler for AggregateHandle {
 
         let batch_size = -1;
 
-        let doc = &ctx.message.document_payload;
-        let db_name = match doc.get("$db")? {
-            Some(val) => {
-                val.as_str().ok_or(anyhow!("$db is not a string"))?
-            },
-            None => {
-                return Err(anyhow!("$db is missing"));
-            },
-        };
+        let db_name = ctx.db_name()?;
 
         let pipeline = ctx.message.document_payload.get_array("pipeline")?;
         let mut pipeline_arr: Vec<Document> = vec![];
@@ -57,17 +49,17 @@ impl Handler for AggregateHandle {
 
         let session_opt = ctx.session.clone();
         let cursor = if let Some(session) = session_opt {
-            let txn = session.get_transaction().ok_or(anyhow!("transaction not started"))?;
+            let txn = session.transaction_on(db_name)?;
             let collection = txn.collection::<Document>(&col_name);
             collection.aggregate(pipeline_arr).run()?
         } else {
-            let db = ctx.app_context.db();
+            let db = ctx.database()?;
             let collection = db.collection::<Document>(&col_name);
             collection.aggregate(pipeline_arr).run()?
         };
 
         let cursor = Arc::new(Mutex::new(cursor));
-        let cursor_id = ctx.app_context.save_cursor(cursor.clone());
+        let cursor_id = ctx.app_context.save_cursor(cursor.clone(), ctx.user.as_deref(), db_name, col_name);
         let cursor_doc = {
             let mut cursor_guard = cursor.lock().unwrap();
             FindHandler::mk_cursor_doc(cursor_id, db_name, col_name, &mut cursor_guard, batch_size as isize)?
@@ -18,8 +18,7 @@ use async_trait::async_trait;
 use bson::{rawdoc, Document, RawDocumentBuf};
 use anyhow::{anyhow, Result};
 use polodb_core::results::DeleteResult;
-use polodb_core::CollectionT;
-use crate::app_context::AppContext;
+use polodb_core::{CollectionT, Database};
 use crate::reply::Reply;
 use crate::session_context::SessionContext;
 
@@ -31,12 +30,11 @@ impl DeleteHandler {
         Arc::new(DeleteHandler {})
     }
 
-    fn handle_delete(ctx: AppContext, session_opt: &Option<SessionContext>, col_name: &str, delete_doc: Document, result: &mut DeleteResult) -> Result<()> {
-        let db = ctx.db();
+    fn handle_delete(db: &Database, db_name: &str, session_opt: &Option<SessionContext>, col_name: &str, delete_doc: Document, result: &mut DeleteResult) -> Result<()> {
         let filter = delete_doc.get_document("q")?;
 
         let tmp_result = if let Some(session) = session_opt {
-            let txn = session.get_transaction().ok_or(anyhow!("transaction not found"))?;
+            let txn = session.transaction_on(db_name)?;
             let collection = txn.collection::<Document>(col_name);
             collection.delete_many(filter.clone())?
         } else {
@@ -68,11 +66,13 @@ impl Handler for DeleteHandler {
         let deletes_arr = doc.get("deletes")?.unwrap().as_array().ok_or(anyhow!("deletes field is not an array"))?;
         let mut delete_result = DeleteResult::default();
 
+        let db_name = ctx.db_name()?;
+        let db = ctx.database()?;
         let session_opt = ctx.session.clone();
         for delete_doc in deletes_arr.into_iter() {
             let doc_ref = delete_doc?.as_document().ok_or(anyhow!("delete document is not a document"))?;
             let doc = bson::from_slice(doc_ref.as_bytes())?;
-            DeleteHandler::handle_delete(ctx.app_context.clone(), &session_opt, collection_name, doc, &mut delete_result)?;
+            DeleteHandler::handle_delete(&db, db_name, &session_opt, collection_name, doc, &mut delete_result)?;
         }
 
         let body = rawdoc! {
@@ -86,16 +86,8 @@ impl Handler for FindHandler {
     async fn handle(&self, ctx: &HandleContext) -> Result<Reply> {
         let doc = &ctx.message.document_payload;
         let collection_name = doc.get("find")?.unwrap().as_str().ok_or(anyhow!("find field is not a string"))?;
-        let db = ctx.app_context.db();
-
-        let db_name = match doc.get("$db")? {
-            Some(val) => {
-                val.as_str().ok_or(anyhow!("$db is not a string"))?
-            },
-            None => {
-                return Err(anyhow!("$db is missing"));
-            },
-        };
+        let db_name = ctx.db_name()?;
+        let db = ctx.database()?;
 
         let single_batch = match doc.get("singleBatch")? {
             Some(val) => {
@@ -140,7 +132,7 @@ impl Handler for FindHandler {
         let session_opt = ctx.session.clone();
         debug!("find collection: {}, auto commit: {}", collection_name, ctx.auto_commit);
         let mut cursor = if let Some(session) = session_opt {
-            let txn = session.get_transaction().ok_or(anyhow!("transaction not started"))?;
+            let txn = session.transaction_on(db_name)?;
             let collection = txn.collection::<Document>(collection_name);
             let mut find = collection.find(filter);
             if let Some(limit) = limit {
@@ -166,7 +158,7 @@ impl Handler for FindHandler {
         }
         let cursor = Arc::new(Mutex::new(cursor));
 
-        let cursor_id = ctx.app_context.save_cursor(cursor.clone());
+        let cursor_id = ctx.app_context.save_cursor(cursor.clone(), ctx.user.as_deref(), db_name, collection_name);
         let cursor_doc = {
             let mut cursor_guard = cursor.lock().unwrap();
             FindHandler::mk_cursor_doc(cursor_id, db_name, &collection_name, &mut cursor_guard, batch_size as isize)?
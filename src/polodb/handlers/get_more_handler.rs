@@ -5,6 +5,7 @@ use async_trait::async_trait;
 use bson::{rawdoc, Document, RawDocumentBuf};
 use log::debug;
 use polodb_core::ClientCursor;
+use crate::auth::{error_doc, UNAUTHORIZED};
 use crate::reply::Reply;
 
 pub(crate) struct GetMoreHandler {}
@@ -84,9 +85,16 @@ impl Handler for GetMoreHandler {
             _ => return Err(anyhow::anyhow!("getMore field is not an Int64")),
         };
 
+        let saved = ctx.app_context.get_cursor(cursor_id).ok_or(anyhow::anyhow!("cursor not found"))?;
+        // the roles are checked against the collection of the command,
+        // so it must be the one of the cursor
+        if !saved.is_owned_by(ctx.user.as_deref(), db_name, collection) {
+            let message = format!("cursor id {} was not created by the authenticated user on {}.{}", cursor_id, db_name, collection);
+            return Ok(Reply::new(ctx.message.request_id.unwrap(), error_doc(UNAUTHORIZED, &message)));
+        }
+
         let (cursor_doc, has_more) = {
-            let cursor = ctx.app_context.get_cursor(cursor_id).ok_or(anyhow::anyhow!("cursor not found"))?;
-            let mut cursor_guard = cursor.lock().unwrap();
+            let mut cursor_guard = saved.cursor.lock().unwrap();
             GetMoreHandler::mk_cursor_doc(db_name, collection, batch_size as isize, cursor_id, &mut cursor_guard)?
         };
 
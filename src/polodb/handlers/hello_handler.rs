@@ -13,7 +13,8 @@
 // limitations under the License.
 
 use std::sync::Arc;
-use bson::{rawdoc, RawDocumentBuf};
+use bson::{rawdoc, RawArrayBuf, RawDocumentBuf};
+use polodb_core::auth::SCRAM_SHA_256;
 use anyhow::Result;
 use crate::handlers::{HandleContext, Handler};
 use crate::reply::Reply;
@@ -41,7 +42,7 @@ impl Handler for HelloHandler {
     async fn handle(&self, ctx: &HandleContext) -> Result<Reply> {
         let req_id = ctx.message.request_id.unwrap();
         debug!("HelloHandler::handle {}", req_id);
-        let body = rawdoc! {
+        let mut body = rawdoc! {
             "ok": 1,
             "connectionId": ctx.conn_id as i64,
             "minWireVersion": 6,
@@ -50,6 +51,12 @@ impl Handler for HelloHandler {
             "maxMessageSizeBytes": 48000000,
             "logicalSessionTimeoutMinutes": 30,
         };
+        // the drivers negotiate the mechanism of the user by it
+        if ctx.message.document_payload.get("saslSupportedMechs")?.is_some() {
+            let mut mechanisms = RawArrayBuf::new();
+            mechanisms.push(SCRAM_SHA_256);
+            body.append("saslSupportedMechs", mechanisms);
+        }
         let reply = Reply::new(req_id, body);
         Ok(reply)
     }
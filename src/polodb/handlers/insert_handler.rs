@@ -49,7 +49,8 @@ impl Handler for InsertHandler {
 
         let auto_commit = utils::truly_value_for_bson_ref(doc.get("autocommit")?, true);
 
-        let db = ctx.app_context.db();
+        let db_name = ctx.db_name()?.to_string();
+        let db = ctx.database()?;
 
         let mut batch_insert = Vec::<bson::Document>::new();
         for doc_seq in ctx.message.document_sequences.as_slice() {
@@ -64,7 +65,7 @@ impl Handler for InsertHandler {
         let session_opt = ctx.session.clone();
         let insert_result = task::spawn_blocking(move || -> anyhow::Result<polodb_core::results::InsertManyResult> {
             if let Some(session) = session_opt {
-                let txn = session.get_transaction().ok_or(anyhow!("transaction not started"))?;
+                let txn = session.transaction_on(&db_name)?;
                 let collection = txn.collection::<bson::Document>(&collection_name);
                 let insert_result = collection.insert_many(batch_insert.as_slice())?;
                 return Ok(insert_result)
@@ -38,7 +38,8 @@ impl Handler for KillCursorsHandler {
 
     async fn handle(&self, ctx: &HandleContext) -> Result<Reply> {
         let doc = &ctx.message.document_payload;
-        let _collection_name = doc.get("killCursors")?.unwrap().as_str().ok_or(anyhow!("insert field is not a string"))?;
+        let collection_name = doc.get("killCursors")?.unwrap().as_str().ok_or(anyhow!("insert field is not a string"))?;
+        let db_name = doc.get_str("$db").map_err(|_| anyhow!("$db is missing"))?;
         let cursors_array = match doc.get("cursors")? {
             Some(val) => {
                 val.as_array().ok_or(anyhow!("cursorsArray is not an array"))?
@@ -53,7 +54,7 @@ impl Handler for KillCursorsHandler {
             let id = cursor_id?.as_i64().ok_or(anyhow!("cursor id is not an i64"))?;
             cursor_ids.push(id);
         }
-        ctx.app_context.remove_cursor(cursor_ids.as_slice());
+        ctx.app_context.remove_owned_cursors(cursor_ids.as_slice(), ctx.user.as_deref(), db_name, collection_name);
 
         let body = rawdoc! {
             "ok": 1,
@@ -0,0 +1,156 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use std::sync::Arc;
+use anyhow::{anyhow, Result};
+use bson::{doc, rawdoc, Bson, Document, RawArrayBuf, RawBson, RawDocumentBuf};
+use crate::handlers::{HandleContext, Handler};
+use crate::reply::Reply;
+use async_trait::async_trait;
+use polodb_core::options::ListCollectionsOptions;
+use polodb_core::results::CollectionInfo;
+
+pub(crate) struct ListCollectionsHandler {}
+
+impl ListCollectionsHandler {
+
+    #[allow(clippy::new_ret_no_self)]
+    pub(crate) fn new() -> Arc<dyn Handler> {
+        Arc::new(ListCollectionsHandler {})
+    }
+
+    fn collection_type(info: &CollectionInfo) -> &'static str {
+        if info.options.view_on.is_some() {
+            "view"
+        } else if info.options.timeseries.is_some() {
+            "timeseries"
+        } else {
+            "collection"
+        }
+    }
+
+    fn mk_options_doc(info: &CollectionInfo) -> Document {
+        let mut options = Document::new();
+        if let Some(view_on) = &info.options.view_on {
+            options.insert("viewOn", view_on.clone());
+            options.insert("pipeline", info.options.pipeline.clone().unwrap_or_default());
+        }
+        if info.options.capped.unwrap_or(false) {
+            options.insert("capped", true);
+            if let Some(size) = info.options.size {
+                options.insert("size", size as i64);
+            }
+            if let Some(max) = info.options.max {
+                options.insert("max", max as i64);
+            }
+        }
+        if let Some(validator) = &info.options.validator {
+            options.insert("validator", validator.clone());
+        }
+        options
+    }
+
+    // only the equality on `name` and `type` of the filter is supported
+    fn matches_filter(filter: &Document, name: &str, ty: &str) -> Result<bool> {
+        for (key, value) in filter.iter() {
+            let expected = match key.as_str() {
+                "name" => name,
+                "type" => ty,
+                _ => return Err(anyhow!("unsupported listCollections filter field: {}", key)),
+            };
+            match value {
+                Bson::String(value) if value == expected => (),
+                Bson::String(_) => return Ok(false),
+                _ => return Err(anyhow!("unsupported listCollections filter on {}", key)),
+            }
+        }
+        Ok(true)
+    }
+
+}
+
+#[async_trait]
+impl Handler for ListCollectionsHandler {
+
+    fn test(&self, doc: &RawDocumentBuf) -> Result<bool> {
+        let val = doc.get("listCollections")?;
+        Ok(val.is_some())
+    }
+
+    async fn handle(&self, ctx: &HandleContext) -> Result<Reply> {
+        let doc = &ctx.message.document_payload;
+        let db_name = ctx.db_name()?;
+
+        let filter = match doc.get("filter")? {
+            Some(val) => {
+                let doc = val.as_document().ok_or(anyhow!("filter is not a document"))?;
+                bson::from_slice::<Document>(doc.as_bytes())?
+            },
+            None => Document::new(),
+        };
+
+        let name_only = match doc.get("nameOnly")? {
+            Some(val) => val.as_bool().unwrap_or(false),
+            None => false,
+        };
+
+        let db = ctx.database()?;
+        let collections = db.list_collections(ListCollectionsOptions::default())?;
+
+        let mut first_batch = RawArrayBuf::new();
+        for info in &collections {
+            let ty = ListCollectionsHandler::collection_type(info);
+            if !ListCollectionsHandler::matches_filter(&filter, &info.name, ty)? {
+                continue;
+            }
+            let item = if name_only {
+                doc! {
+                    "name": info.name.clone(),
+                    "type": ty,
+                }
+            } else {
+                doc! {
+                    "name": info.name.clone(),
+                    "type": ty,
+                    "options": ListCollectionsHandler::mk_options_doc(info),
+                    "info": {
+                        "readOnly": ty == "view",
+                    },
+                    "idIndex": {
+                        "v": 2,
+                        "key": { "_id": 1 },
+                        "name": "_id_",
+                    },
+                }
+            };
+            let item_bytes = bson::to_vec(&item)?;
+            first_batch.push(RawBson::Document(RawDocumentBuf::from_bytes(item_bytes)?));
+        }
+
+        let mut cursor_doc = rawdoc! {
+            "id": 0_i64,
+            "ns": format!("{}.$cmd.listCollections", db_name),
+        };
+        cursor_doc.append("firstBatch", RawBson::Array(first_batch));
+
+        let body = rawdoc! {
+            "ok": 1,
+            "cursor": cursor_doc,
+        };
+        let reply = Reply::new(ctx.message.request_id.unwrap(), body);
+        Ok(reply)
+    }
+
+}
@@ -8,10 +8,12 @@ mod delete_handler;
 mod commit_transaction;
 mod abort_transaction;
 mod aggregate_handler;
+mod list_collections_handler;
 
 use std::sync::Arc;
 use bson::RawDocumentBuf;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use polodb_core::Database;
 use crate::reply::Reply;
 use crate::wire;
 use async_trait::async_trait;
@@ -26,6 +28,7 @@ pub(crate) use delete_handler::DeleteHandler;
 pub(crate) use commit_transaction::CommitTransactionHandler;
 pub(crate) use abort_transaction::AbortTransactionHandler;
 pub(crate) use aggregate_handler::AggregateHandle;
+pub(crate) use list_collections_handler::ListCollectionsHandler;
 use crate::app_context::AppContext;
 use crate::session_context::SessionContext;
 
@@ -34,11 +37,31 @@ pub(crate) const DEFAULT_BATCH_SIZE: i32 = 101;
 pub(crate) struct HandleContext<'a> {
     pub(crate) app_context: AppContext,
     pub(crate) conn_id: u64,
+    /// The name of the authenticated user.
+    pub(crate) user: Option<String>,
     pub(crate) message: &'a wire::Message,
     pub(crate) session: Option<SessionContext>,
     pub(crate) auto_commit: bool,
 }
 
+impl HandleContext<'_> {
+
+    /// The `$db` of the command.
+    pub(crate) fn db_name(&self) -> Result<&str> {
+        match self.message.document_payload.get("$db")? {
+            Some(val) => val.as_str().ok_or(anyhow!("$db is not a string")),
+            None => Err(anyhow!("$db is missing")),
+        }
+    }
+
+    /// The database of the command, the roles of the user are checked on the `$db`,
+    /// so the command must not access the others.
+    pub(crate) fn database(&self) -> Result<Database> {
+        Ok(self.app_context.database(self.db_name()?))
+    }
+
+}
+
 #[async_trait]
 pub(crate) trait Handler: Send + Sync {
 
@@ -54,6 +77,7 @@ pub(crate) fn make_handlers() -> Vec<Arc<dyn Handler>> {
         GetMoreHandler::new(),
         KillCursorsHandler::new(),
         AggregateHandle::new(),
+        ListCollectionsHandler::new(),
         InsertHandler::new(),
         UpdateHandler::new(),
         DeleteHandler::new(),
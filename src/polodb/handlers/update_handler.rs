@@ -19,8 +19,7 @@ use async_trait::async_trait;
 use bson::{rawdoc, Document, RawDocumentBuf};
 use log::debug;
 use polodb_core::results::UpdateResult;
-use polodb_core::CollectionT;
-use crate::app_context::AppContext;
+use polodb_core::{CollectionT, Database};
 use crate::reply::Reply;
 
 pub(crate) struct UpdateHandler {}
@@ -31,8 +30,7 @@ impl UpdateHandler {
         Arc::new(UpdateHandler {})
     }
 
-    fn handle_update(ctx: AppContext, col_name: &str, update: Document, result: &mut UpdateResult) -> Result<()> {
-        let db = ctx.db();
+    fn handle_update(db: &Database, col_name: &str, update: Document, result: &mut UpdateResult) -> Result<()> {
         let collection = db.collection::<Document>(col_name);
 
         let filter = update.get("q").ok_or(anyhow!("update document missing q field"))?;
@@ -63,13 +61,14 @@ impl Handler for UpdateHandler {
         let doc = &ctx.message.document_payload;
         let collection_name = doc.get("update")?.unwrap().as_str().ok_or(anyhow!("insert field is not a string"))?;
 
+        let db = ctx.database()?;
         let mut update_result = UpdateResult::default();
 
         let updates = doc.get_array("updates")?;
         for update in updates.into_iter() {
             let update = update?.as_document().ok_or(anyhow!("update is not a document"))?;
             let d = bson::from_slice::<Document>(update.as_bytes())?;
-            UpdateHandler::handle_update(ctx.app_context.clone(), collection_name, d, &mut update_result)?;
+            UpdateHandler::handle_update(&db, collection_name, d, &mut update_result)?;
         }
         debug!("update result: {:?}", update_result);
 
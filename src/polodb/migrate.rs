@@ -0,0 +1,385 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Migrate the databases of a running MongoDB into PoloDB.
+//!
+//! The documents and the indexes of the selected collections are copied
+//! into the named databases of PoloDB, e.g. the collection `shop.orders` of MongoDB
+//! is copied into `db.database("shop").collection("orders")`.
+//! The indexes which are not supported by PoloDB and the views are skipped,
+//! they're listed in the report.
+//!
+//! With `follow`, a change stream of the deployment is opened before the copy,
+//! so the changes made during the copy are not lost. After the copy, the changes are applied
+//! until the migration is cancelled. A message is printed every time the changes are caught up,
+//! stop the writes of the application and cancel the migration to cut over.
+//! The change streams need a replica set or a sharded cluster.
+
+use anyhow::{anyhow, Result};
+use bson::{doc, Document};
+use futures::TryStreamExt;
+use log::warn;
+use mongodb::change_stream::event::{ChangeStreamEvent, OperationType};
+use mongodb::change_stream::ChangeStream;
+use mongodb::options::FullDocumentType;
+use mongodb::results::CollectionType;
+use mongodb::Client;
+use polodb_core::{CollectionT, Database, IndexModel};
+use serde::Serialize;
+use tokio::select;
+use tokio_util::sync::CancellationToken;
+
+const INSERT_BATCH_SIZE: usize = 1000;
+const ID_INDEX_NAME: &str = "_id_";
+const SYSTEM_DATABASES: [&str; 3] = ["admin", "config", "local"];
+
+/// The selected namespaces, `db` for all the collections of a database, or `db.collection`.
+/// All the databases except `admin`, `config` and `local` if it's empty.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Namespaces(Vec<(String, Option<String>)>);
+
+impl Namespaces {
+
+    /// Parse the namespaces separated by commas.
+    pub(crate) fn parse(value: &str) -> Namespaces {
+        let namespaces = value
+            .split(',')
+            .map(str::trim)
+            .filter(|ns| !ns.is_empty())
+            .map(|ns| match ns.split_once('.') {
+                Some((db, coll)) => (db.to_string(), Some(coll.to_string())),
+                None => (ns.to_string(), None),
+            })
+            .collect();
+        Namespaces(namespaces)
+    }
+
+    /// The selected databases, `None` for all of them.
+    fn databases(&self) -> Option<Vec<String>> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let mut databases: Vec<String> = Vec::new();
+        for (db, _) in &self.0 {
+            if !databases.contains(db) {
+                databases.push(db.clone());
+            }
+        }
+        Some(databases)
+    }
+
+    fn contains_database(&self, db: &str) -> bool {
+        if self.0.is_empty() {
+            return !SYSTEM_DATABASES.contains(&db);
+        }
+        self.0.iter().any(|(name, _)| name == db)
+    }
+
+    fn contains(&self, db: &str, coll: &str) -> bool {
+        if coll.starts_with("system.") {
+            return false;
+        }
+        if self.0.is_empty() {
+            return !SYSTEM_DATABASES.contains(&db);
+        }
+        self.0.iter().any(|(name, collection)| {
+            name == db && (collection.is_none() || collection.as_deref() == Some(coll))
+        })
+    }
+
+}
+
+pub(crate) struct MigrateOptions {
+    pub uri: String,
+    pub namespaces: Namespaces,
+    /// Drop the collections of PoloDB before the copy,
+    /// otherwise the migration fails if a collection is not empty.
+    pub drop: bool,
+    /// Apply the changes after the copy until the migration is cancelled.
+    pub follow: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MigrateReport {
+    pub collections: u64,
+    pub documents: u64,
+    pub indexes: u64,
+    pub skipped_indexes: Vec<String>,
+    pub skipped_views: Vec<String>,
+    /// The number of the applied changes.
+    pub changes: u64,
+}
+
+pub(crate) async fn migrate(db: &Database, options: MigrateOptions, token: CancellationToken) -> Result<MigrateReport> {
+    let client = Client::with_uri_str(&options.uri).await?;
+
+    // the stream is opened before the copy, so the changes made during the copy are applied after it
+    let stream = if options.follow {
+        let stream = client
+            .watch()
+            .full_document(FullDocumentType::UpdateLookup)
+            .await
+            .map_err(|err| anyhow!("failed to open the change stream, it needs a replica set: {}", err))?;
+        Some(stream)
+    } else {
+        None
+    };
+
+    let databases = match options.namespaces.databases() {
+        Some(databases) => databases,
+        None => client
+            .list_database_names()
+            .await?
+            .into_iter()
+            .filter(|name| !SYSTEM_DATABASES.contains(&name.as_str()))
+            .collect(),
+    };
+
+    let mut report = MigrateReport::default();
+    for name in &databases {
+        copy_database(&client.database(name), &db.database(name), &options, &mut report).await?;
+    }
+
+    if let Some(mut stream) = stream {
+        follow(db, &mut stream, &options.namespaces, &mut report, token).await?;
+    }
+
+    Ok(report)
+}
+
+async fn copy_database(
+    source: &mongodb::Database,
+    target: &Database,
+    options: &MigrateOptions,
+    report: &mut MigrateReport,
+) -> Result<()> {
+    let mut specs = source.list_collections().await?;
+    while let Some(spec) = specs.try_next().await? {
+        if !options.namespaces.contains(source.name(), &spec.name) {
+            continue;
+        }
+        let ns = format!("{}.{}", source.name(), spec.name);
+        if spec.collection_type == CollectionType::View {
+            report.skipped_views.push(ns);
+            continue;
+        }
+
+        let col = target.collection::<Document>(&spec.name);
+        if options.drop {
+            col.drop()?;
+        } else if col.count_documents()? > 0 {
+            return Err(anyhow!("the collection '{}' is not empty, pass --drop to replace it", ns));
+        }
+
+        let mut cursor = source.collection::<Document>(&spec.name).find(doc! {}).await?;
+        let mut batch = Vec::with_capacity(INSERT_BATCH_SIZE);
+        while let Some(doc) = cursor.try_next().await? {
+            batch.push(doc);
+            if batch.len() == INSERT_BATCH_SIZE {
+                report.documents += batch.len() as u64;
+                col.insert_many(std::mem::take(&mut batch))?;
+            }
+        }
+        if !batch.is_empty() {
+            report.documents += batch.len() as u64;
+            col.insert_many(batch)?;
+        }
+
+        let mut indexes = source.run_cursor_command(doc! { "listIndexes": &spec.name }).await?;
+        while let Some(index) = indexes.try_next().await? {
+            let index_name = index.get_str("name").unwrap_or_default();
+            if index_name == ID_INDEX_NAME {
+                continue;
+            }
+            match IndexModel::from_spec(&index) {
+                Some(model) => {
+                    col.create_index(model)?;
+                    report.indexes += 1;
+                }
+                None => report.skipped_indexes.push(format!("{}.{}", ns, index_name)),
+            }
+        }
+
+        report.collections += 1;
+    }
+    Ok(())
+}
+
+async fn follow(
+    db: &Database,
+    stream: &mut ChangeStream<ChangeStreamEvent<Document>>,
+    namespaces: &Namespaces,
+    report: &mut MigrateReport,
+    token: CancellationToken,
+) -> Result<()> {
+    let mut caught_up = false;
+    while stream.is_alive() {
+        let event = select! {
+            _ = token.cancelled() => return Ok(()),
+            event = stream.next_if_any() => event?,
+        };
+        match event {
+            Some(event) => {
+                if apply_event(db, namespaces, event)? {
+                    report.changes += 1;
+                    caught_up = false;
+                }
+            }
+            None => {
+                if !caught_up {
+                    eprintln!("caught up, {} changes applied", report.changes);
+                    caught_up = true;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Apply a change to the database if it's in the selected namespaces.
+/// Return `false` if the change is ignored.
+pub(crate) fn apply_event(db: &Database, namespaces: &Namespaces, event: ChangeStreamEvent<Document>) -> Result<bool> {
+    let ns = match &event.ns {
+        Some(ns) => ns,
+        None => return Ok(false),
+    };
+    let target = db.database(&ns.db);
+
+    if event.operation_type == OperationType::DropDatabase {
+        if !namespaces.contains_database(&ns.db) {
+            return Ok(false);
+        }
+        target.drop()?;
+        return Ok(true);
+    }
+
+    let coll = match &ns.coll {
+        Some(coll) if namespaces.contains(&ns.db, coll) => coll,
+        _ => return Ok(false),
+    };
+    let id = event.document_key.as_ref().and_then(|key| key.get("_id"));
+
+    match event.operation_type {
+        OperationType::Insert | OperationType::Update | OperationType::Replace => {
+            let id = id.ok_or_else(|| anyhow!("the change of '{}.{}' has no document key", ns.db, coll))?;
+            // the document is replaced by the current one, it's deleted if it's gone since the change
+            let txn = target.start_transaction()?;
+            let col = txn.collection::<Document>(coll);
+            col.delete_one(doc! { "_id": id.clone() })?;
+            if let Some(doc) = event.full_document {
+                col.insert_one(doc)?;
+            }
+            txn.commit()?;
+        }
+        OperationType::Delete => {
+            let id = id.ok_or_else(|| anyhow!("the change of '{}.{}' has no document key", ns.db, coll))?;
+            target.collection::<Document>(coll).delete_one(doc! { "_id": id.clone() })?;
+        }
+        OperationType::Drop => {
+            target.collection::<Document>(coll).drop()?;
+        }
+        OperationType::Rename => match &event.to {
+            Some(to) if to.db == ns.db && to.coll.is_some() => {
+                target.rename_collection(coll, to.coll.as_ref().unwrap())?;
+            }
+            _ => {
+                warn!("the rename of '{}.{}' to another database is not applied", ns.db, coll);
+                return Ok(false);
+            }
+        },
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{doc, Document};
+    use mongodb::change_stream::event::ChangeStreamEvent;
+    use polodb_core::{CollectionT, Database, IndexModel, IndexOptions};
+    use super::{apply_event, Namespaces};
+
+    fn event(doc: Document) -> ChangeStreamEvent<Document> {
+        bson::from_document(doc).unwrap()
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let all = Namespaces::default();
+        assert!(all.databases().is_none());
+        assert!(all.contains("shop", "orders"));
+        assert!(!all.contains("admin", "users"));
+        assert!(!all.contains("shop", "system.views"));
+
+        let namespaces = Namespaces::parse("shop.orders, blog");
+        assert_eq!(namespaces.databases().unwrap(), vec!["shop".to_string(), "blog".to_string()]);
+        assert!(namespaces.contains("shop", "orders"));
+        assert!(!namespaces.contains("shop", "users"));
+        assert!(namespaces.contains("blog", "posts"));
+        assert!(!namespaces.contains("other", "posts"));
+    }
+
+    #[test]
+    fn test_apply_event() {
+        let db = Database::open_memory().unwrap();
+        let orders = db.database("shop").collection::<Document>("orders");
+        orders.create_index(IndexModel {
+            keys: doc! { "code": 1 },
+            options: Some(IndexOptions {
+                unique: Some(true),
+                ..Default::default()
+            }),
+        }).unwrap();
+        let namespaces = Namespaces::parse("shop.orders");
+
+        let applied = apply_event(&db, &namespaces, event(doc! {
+            "_id": { "_data": "1" },
+            "operationType": "insert",
+            "ns": { "db": "shop", "coll": "orders" },
+            "documentKey": { "_id": 1 },
+            "fullDocument": { "_id": 1, "code": "A", "qty": 1 },
+        })).unwrap();
+        assert!(applied);
+
+        apply_event(&db, &namespaces, event(doc! {
+            "_id": { "_data": "2" },
+            "operationType": "update",
+            "ns": { "db": "shop", "coll": "orders" },
+            "documentKey": { "_id": 1 },
+            "fullDocument": { "_id": 1, "code": "A", "qty": 2 },
+        })).unwrap();
+        let order = orders.find_one(doc! { "code": "A" }).unwrap().unwrap();
+        assert_eq!(order.get_i32("qty").unwrap(), 2);
+
+        // the other collections are ignored
+        let applied = apply_event(&db, &namespaces, event(doc! {
+            "_id": { "_data": "3" },
+            "operationType": "insert",
+            "ns": { "db": "shop", "coll": "users" },
+            "documentKey": { "_id": 1 },
+            "fullDocument": { "_id": 1 },
+        })).unwrap();
+        assert!(!applied);
+
+        apply_event(&db, &namespaces, event(doc! {
+            "_id": { "_data": "4" },
+            "operationType": "delete",
+            "ns": { "db": "shop", "coll": "orders" },
+            "documentKey": { "_id": 1 },
+        })).unwrap();
+        assert_eq!(orders.count_documents().unwrap(), 0);
+    }
+}
@@ -1,4 +1,5 @@
 use std::sync::{Arc, Mutex};
+use anyhow::{anyhow, Result};
 use polodb_core::Transaction;
 
 #[derive(Clone)]
@@ -8,9 +9,10 @@ pub(crate) struct SessionContext {
 
 impl SessionContext {
 
-    pub(crate) fn new(txn: Transaction) -> SessionContext {
+    pub(crate) fn new(db_name: &str, txn: Transaction) -> SessionContext {
         SessionContext {
             inner: Arc::new(SessionContextInner {
+                db_name: db_name.to_string(),
                 txn: Mutex::new(Some(txn)),
             }),
         }
@@ -21,6 +23,15 @@ impl SessionContext {
         txn.clone()
     }
 
+    /// The transaction for a command on `db_name`, the transaction
+    /// only reads and writes the database it's started on.
+    pub(crate) fn transaction_on(&self, db_name: &str) -> Result<Transaction> {
+        if self.inner.db_name != db_name {
+            return Err(anyhow!("the transaction is started on {}, it can't run a command on {}", self.inner.db_name, db_name));
+        }
+        self.get_transaction().ok_or(anyhow!("transaction not started"))
+    }
+
     pub(crate) fn clear_transaction(&self) {
         let mut txn = self.inner.txn.lock().unwrap();
         *txn = None;
@@ -33,6 +44,7 @@ impl Default for SessionContext {
     fn default() -> Self {
         SessionContext {
             inner: Arc::new(SessionContextInner {
+                db_name: String::new(),
                 txn: Mutex::new(None),
             }),
         }
@@ -41,5 +53,7 @@ impl Default for SessionContext {
 }
 
 struct SessionContextInner {
+    /// The `$db` of the command which started the transaction.
+    db_name: String,
     txn: Mutex<Option<Transaction>>,
 }
@@ -0,0 +1,872 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The interactive shell started by `polodb shell --path /path/to/db`.
+//!
+//! The commands are written like in the `mongo` shell, e.g.
+//! `db.users.find({ age: { $gt: 18 } }).sort({ name: 1 }).limit(10)`.
+//! The arguments are relaxed JSON: the keys may be unquoted, the strings single-quoted,
+//! and `ObjectId("...")`, `ISODate("...")`, `NumberLong(...)`, `NumberInt(...)`,
+//! `NumberDecimal("...")` and `UUID("...")` are written as in the `mongo` shell.
+//! The documents are printed as relaxed extended JSON.
+//!
+//! `begin` starts a transaction, the following commands run in it until `commit` or `abort`.
+
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+use anyhow::{anyhow, Result};
+use bson::{Bson, Document};
+use polodb_core::{CollectionT, Database, IndexModel, Transaction};
+use polodb_core::options::UpdateOptions;
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use serde::Serialize;
+use serde_json::{Map, Number, Value};
+
+const HELP: &str = "\
+show collections                 list the collections
+show dbs                         list the named databases
+use <name>                       switch to the named database, `use` alone for the default one
+begin, commit, abort             start, commit or abort a transaction
+db.<collection>.<method>(...)    run a method of the collection:
+    find(filter).sort(sort).skip(n).limit(n), findOne(filter), countDocuments(filter),
+    insertOne(doc), insertMany([docs]), updateOne(filter, update, { upsert }),
+    updateMany(filter, update, { upsert }), deleteOne(filter), deleteMany(filter),
+    aggregate([stages]), createIndex(keys, { name, unique }), dropIndex(name), drop(), stats()
+db.getCollection(\"name\")         the collection of a name which is not an identifier
+db.getCollectionNames(), db.createCollection(\"name\")
+help, exit";
+
+const COMMANDS: &[&str] = &["show collections", "show dbs", "use", "begin", "commit", "abort", "help", "exit", "db."];
+
+const DATABASE_METHODS: &[&str] = &["getCollection(", "getCollectionNames()", "createCollection("];
+
+const COLLECTION_METHODS: &[&str] = &[
+    "find(", "findOne(", "countDocuments(", "insertOne(", "insertMany(", "updateOne(", "updateMany(",
+    "deleteOne(", "deleteMany(", "aggregate(", "createIndex(", "dropIndex(", "drop()", "stats()",
+];
+
+/// A method call with its arguments.
+#[derive(Debug, PartialEq)]
+struct Call {
+    name: String,
+    args: Vec<Bson>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Statement {
+    Help,
+    Exit,
+    ShowCollections,
+    ShowDatabases,
+    Use(Option<String>),
+    Begin,
+    Commit,
+    Abort,
+    Database(Call),
+    Collection {
+        name: String,
+        calls: Vec<Call>,
+    },
+}
+
+pub(crate) enum Outcome {
+    Print(String),
+    Nothing,
+    Exit,
+}
+
+pub(crate) struct Shell {
+    root: Database,
+    named: Option<Database>,
+    txn: Option<Transaction>,
+}
+
+impl Shell {
+
+    pub(crate) fn new(db: Database) -> Shell {
+        Shell {
+            root: db,
+            named: None,
+            txn: None,
+        }
+    }
+
+    fn db(&self) -> &Database {
+        self.named.as_ref().unwrap_or(&self.root)
+    }
+
+    pub(crate) fn prompt(&self) -> String {
+        let name = self.db().name().unwrap_or("polodb");
+        if self.txn.is_some() {
+            format!("{} (txn)> ", name)
+        } else {
+            format!("{}> ", name)
+        }
+    }
+
+    pub(crate) fn collection_names(&self) -> Vec<String> {
+        self.db().list_collection_names().unwrap_or_default()
+    }
+
+    pub(crate) fn execute(&mut self, input: &str) -> Result<Outcome> {
+        let statement = match parse_statement(input)? {
+            Some(statement) => statement,
+            None => return Ok(Outcome::Nothing),
+        };
+        let output = match statement {
+            Statement::Help => HELP.to_string(),
+            Statement::Exit => return Ok(Outcome::Exit),
+            Statement::ShowCollections => self.collection_names().join("\n"),
+            Statement::ShowDatabases => self.root.list_database_names()?.join("\n"),
+            Statement::Use(name) => {
+                if self.txn.is_some() {
+                    return Err(anyhow!("commit or abort the transaction first"));
+                }
+                self.named = name.map(|name| self.root.database(&name));
+                return Ok(Outcome::Nothing);
+            }
+            Statement::Begin => {
+                if self.txn.is_some() {
+                    return Err(anyhow!("a transaction is already started"));
+                }
+                self.txn = Some(self.db().start_transaction()?);
+                return Ok(Outcome::Nothing);
+            }
+            Statement::Commit => {
+                let txn = self.txn.take().ok_or_else(|| anyhow!("no transaction is started"))?;
+                txn.commit()?;
+                return Ok(Outcome::Nothing);
+            }
+            Statement::Abort => {
+                let txn = self.txn.take().ok_or_else(|| anyhow!("no transaction is started"))?;
+                txn.rollback()?;
+                return Ok(Outcome::Nothing);
+            }
+            Statement::Database(call) => self.run_database_method(call)?,
+            Statement::Collection { name, calls } => match &self.txn {
+                Some(txn) => run_collection_methods(&txn.collection::<Document>(&name), calls)?,
+                None => run_collection_methods(&self.db().collection::<Document>(&name), calls)?,
+            },
+        };
+        Ok(Outcome::Print(output))
+    }
+
+    fn run_database_method(&self, call: Call) -> Result<String> {
+        match call.name.as_str() {
+            "getCollectionNames" => format_result(self.collection_names()),
+            "createCollection" => {
+                self.db().create_collection(&string_arg(&call, 0)?)?;
+                format_result(bson::doc! { "ok": 1 })
+            }
+            name => Err(anyhow!("unknown method of the database: {}", name)),
+        }
+    }
+
+}
+
+fn run_collection_methods<C: CollectionT<Document>>(collection: &C, calls: Vec<Call>) -> Result<String> {
+    let mut calls = calls.into_iter();
+    let call = calls.next().ok_or_else(|| anyhow!("call a method of the collection"))?;
+    if call.name != "find" {
+        if let Some(chained) = calls.next() {
+            return Err(anyhow!("{}() can't be chained after {}()", chained.name, call.name));
+        }
+    }
+
+    match call.name.as_str() {
+        "find" => {
+            let mut find = collection.find(optional_document_arg(&call, 0)?.unwrap_or_default());
+            for chained in calls {
+                find = match chained.name.as_str() {
+                    "sort" => find.sort(document_arg(&chained, 0)?),
+                    "skip" => find.skip(integer_arg(&chained, 0)?),
+                    "limit" => find.limit(integer_arg(&chained, 0)?),
+                    name => return Err(anyhow!("{}() can't be chained after find()", name)),
+                };
+            }
+            let documents = find.run()?.collect::<polodb_core::Result<Vec<Document>>>()?;
+            Ok(documents.into_iter().map(|doc| format_bson(Bson::Document(doc))).collect::<Vec<String>>().join("\n"))
+        }
+        "findOne" => {
+            match collection.find_one(optional_document_arg(&call, 0)?.unwrap_or_default())? {
+                Some(doc) => Ok(format_bson(Bson::Document(doc))),
+                None => Ok("null".to_string()),
+            }
+        }
+        "countDocuments" => {
+            let count = match optional_document_arg(&call, 0)? {
+                Some(filter) if !filter.is_empty() => collection.find(filter).run()?.count() as u64,
+                _ => collection.count_documents()?,
+            };
+            Ok(count.to_string())
+        }
+        "insertOne" => format_result(collection.insert_one(document_arg(&call, 0)?)?),
+        "insertMany" => {
+            let docs = array_arg(&call, 0)?
+                .into_iter()
+                .map(|value| match value {
+                    Bson::Document(doc) => Ok(doc),
+                    _ => Err(anyhow!("insertMany() takes an array of documents")),
+                })
+                .collect::<Result<Vec<Document>>>()?;
+            format_result(collection.insert_many(docs)?)
+        }
+        "updateOne" | "updateMany" => {
+            let filter = document_arg(&call, 0)?;
+            let update = document_arg(&call, 1)?;
+            let upsert = optional_document_arg(&call, 2)?
+                .and_then(|options| options.get_bool("upsert").ok())
+                .unwrap_or(false);
+            let options = UpdateOptions::builder().upsert(upsert).build();
+            let result = if call.name == "updateOne" {
+                collection.update_one_with_options(filter, update, options)?
+            } else {
+                collection.update_many_with_options(filter, update, options)?
+            };
+            format_result(result)
+        }
+        "deleteOne" => format_result(collection.delete_one(document_arg(&call, 0)?)?),
+        "deleteMany" => format_result(collection.delete_many(document_arg(&call, 0)?)?),
+        "aggregate" => {
+            let pipeline = array_arg(&call, 0)?
+                .into_iter()
+                .map(|value| match value {
+                    Bson::Document(doc) => Ok(doc),
+                    _ => Err(anyhow!("aggregate() takes an array of stages")),
+                })
+                .collect::<Result<Vec<Document>>>()?;
+            let documents = collection.aggregate(pipeline).run()?.collect::<polodb_core::Result<Vec<Document>>>()?;
+            Ok(documents.into_iter().map(|doc| format_bson(Bson::Document(doc))).collect::<Vec<String>>().join("\n"))
+        }
+        "createIndex" => {
+            let mut spec = optional_document_arg(&call, 1)?.unwrap_or_default();
+            spec.insert("key", document_arg(&call, 0)?);
+            let index = IndexModel::from_spec(&spec)
+                .ok_or_else(|| anyhow!("only the ascending index of one key is supported, with the options name and unique"))?;
+            collection.create_index(index)?;
+            format_result(bson::doc! { "ok": 1 })
+        }
+        "dropIndex" => {
+            collection.drop_index(string_arg(&call, 0)?)?;
+            format_result(bson::doc! { "ok": 1 })
+        }
+        "drop" => format_result(collection.drop()?),
+        "stats" => format_result(collection.stats()?),
+        name => Err(anyhow!("unknown method of the collection: {}", name)),
+    }
+}
+
+fn format_bson(value: Bson) -> String {
+    serde_json::to_string_pretty(&value.into_relaxed_extjson()).unwrap_or_default()
+}
+
+fn format_result<T: Serialize>(result: T) -> Result<String> {
+    Ok(format_bson(bson::to_bson(&result)?))
+}
+
+fn optional_document_arg(call: &Call, index: usize) -> Result<Option<Document>> {
+    match call.args.get(index) {
+        Some(Bson::Document(doc)) => Ok(Some(doc.clone())),
+        Some(_) => Err(anyhow!("the argument {} of {}() is not a document", index + 1, call.name)),
+        None => Ok(None),
+    }
+}
+
+fn document_arg(call: &Call, index: usize) -> Result<Document> {
+    optional_document_arg(call, index)?
+        .ok_or_else(|| anyhow!("{}() takes a document as the argument {}", call.name, index + 1))
+}
+
+fn array_arg(call: &Call, index: usize) -> Result<Vec<Bson>> {
+    match call.args.get(index) {
+        Some(Bson::Array(array)) => Ok(array.clone()),
+        _ => Err(anyhow!("{}() takes an array as the argument {}", call.name, index + 1)),
+    }
+}
+
+fn string_arg(call: &Call, index: usize) -> Result<String> {
+    match call.args.get(index) {
+        Some(Bson::String(value)) => Ok(value.clone()),
+        _ => Err(anyhow!("{}() takes a string as the argument {}", call.name, index + 1)),
+    }
+}
+
+fn integer_arg(call: &Call, index: usize) -> Result<u64> {
+    let value = match call.args.get(index) {
+        Some(Bson::Int32(value)) => *value as i64,
+        Some(Bson::Int64(value)) => *value,
+        _ => -1,
+    };
+    u64::try_from(value).map_err(|_| anyhow!("{}() takes a positive integer", call.name))
+}
+
+fn parse_statement(input: &str) -> Result<Option<Statement>> {
+    let input = input.trim().trim_end_matches(';').trim();
+    let words: Vec<&str> = input.split_whitespace().collect();
+    let statement = match words.as_slice() {
+        [] => return Ok(None),
+        ["help"] => Statement::Help,
+        ["exit"] | ["quit"] => Statement::Exit,
+        ["show", "collections"] | ["show", "tables"] => Statement::ShowCollections,
+        ["show", "dbs"] | ["show", "databases"] => Statement::ShowDatabases,
+        ["use"] => Statement::Use(None),
+        ["use", name] => Statement::Use(Some(name.to_string())),
+        ["begin"] => Statement::Begin,
+        ["commit"] => Statement::Commit,
+        ["abort"] | ["rollback"] => Statement::Abort,
+        _ => Parser::new(input).statement()?,
+    };
+    Ok(Some(statement))
+}
+
+/// Whether the brackets and the strings of the input are closed,
+/// otherwise the shell reads the next line.
+fn is_complete(input: &str) -> bool {
+    let mut depth = 0i32;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for c in input.chars() {
+        match quote {
+            Some(q) => {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == q {
+                    quote = None;
+                }
+            }
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                _ => (),
+            },
+        }
+    }
+    quote.is_none() && depth <= 0
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '$'
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+
+    fn new(input: &str) -> Parser {
+        Parser {
+            chars: input.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn statement(&mut self) -> Result<Statement> {
+        if self.ident()? != "db" {
+            return Err(anyhow!("unknown command, type help for the commands"));
+        }
+        self.expect('.')?;
+        let name = self.ident()?;
+        self.skip_whitespace();
+        let name = if self.peek() == Some('(') {
+            let args = self.args()?;
+            let call = Call { name, args };
+            if call.name != "getCollection" {
+                self.end()?;
+                return Ok(Statement::Database(call));
+            }
+            string_arg(&call, 0)?
+        } else {
+            name
+        };
+
+        let mut calls = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek().is_none() {
+                break;
+            }
+            self.expect('.')?;
+            let name = self.ident()?;
+            let args = self.args()?;
+            calls.push(Call { name, args });
+        }
+        if calls.is_empty() {
+            return Err(anyhow!("call a method of the collection, e.g. db.{}.find()", name));
+        }
+        Ok(Statement::Collection { name, calls })
+    }
+
+    fn end(&mut self) -> Result<()> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Ok(()),
+            Some(c) => Err(anyhow!("unexpected '{}'", c)),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(c) => Err(anyhow!("expected '{}' but found '{}'", expected, c)),
+            None => Err(anyhow!("expected '{}'", expected)),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        self.skip_whitespace();
+        let start = self.pos;
+        while self.peek().is_some_and(is_ident_char) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(match self.peek() {
+                Some(c) => anyhow!("unexpected '{}'", c),
+                None => anyhow!("unexpected end of the command"),
+            });
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn args(&mut self) -> Result<Vec<Bson>> {
+        self.expect('(')?;
+        let mut args = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(')') {
+                self.pos += 1;
+                break;
+            }
+            let value = self.value()?;
+            args.push(Bson::try_from(value)?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(')') => (),
+                _ => return Err(anyhow!("expected ',' or ')' in the arguments")),
+            }
+        }
+        Ok(args)
+    }
+
+    /// A value of relaxed JSON, the helpers are converted to extended JSON.
+    fn value(&mut self) -> Result<Value> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') | Some('\'') => Ok(Value::String(self.string()?)),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) if is_ident_char(c) => self.keyword(),
+            Some(c) => Err(anyhow!("unexpected '{}'", c)),
+            None => Err(anyhow!("unexpected end of the command")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value> {
+        self.expect('{')?;
+        let mut map = Map::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some('}') {
+                self.pos += 1;
+                break;
+            }
+            let key = match self.peek() {
+                Some('"') | Some('\'') => self.string()?,
+                _ => self.ident()?,
+            };
+            self.expect(':')?;
+            map.insert(key, self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => (),
+                _ => return Err(anyhow!("expected ',' or '}}' in the object")),
+            }
+        }
+        Ok(Value::Object(map))
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                break;
+            }
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => (),
+                _ => return Err(anyhow!("expected ',' or ']' in the array")),
+            }
+        }
+        Ok(Value::Array(values))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let quote = self.peek().ok_or_else(|| anyhow!("expected a string"))?;
+        self.pos += 1;
+        let mut result = String::new();
+        loop {
+            let c = self.peek().ok_or_else(|| anyhow!("the string is not closed"))?;
+            self.pos += 1;
+            if c == quote {
+                break;
+            }
+            if c != '\\' {
+                result.push(c);
+                continue;
+            }
+            let escaped = self.peek().ok_or_else(|| anyhow!("the string is not closed"))?;
+            self.pos += 1;
+            match escaped {
+                'n' => result.push('\n'),
+                't' => result.push('\t'),
+                'r' => result.push('\r'),
+                'u' => {
+                    let hex: String = self.chars.get(self.pos..self.pos + 4)
+                        .ok_or_else(|| anyhow!("invalid escape in the string"))?
+                        .iter()
+                        .collect();
+                    self.pos += 4;
+                    let code = u32::from_str_radix(&hex, 16)?;
+                    result.push(char::from_u32(code).ok_or_else(|| anyhow!("invalid escape in the string"))?);
+                }
+                c => result.push(c),
+            }
+        }
+        Ok(result)
+    }
+
+    fn number(&mut self) -> Result<Value> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(c)) {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        if let Ok(value) = text.parse::<i64>() {
+            return Ok(Value::Number(value.into()));
+        }
+        let value: f64 = text.parse().map_err(|_| anyhow!("invalid number: {}", text))?;
+        Number::from_f64(value)
+            .map(Value::Number)
+            .ok_or_else(|| anyhow!("invalid number: {}", text))
+    }
+
+    fn keyword(&mut self) -> Result<Value> {
+        let mut name = self.ident()?;
+        if name == "new" {
+            name = self.ident()?;
+        }
+        match name.as_str() {
+            "true" => return Ok(Value::Bool(true)),
+            "false" => return Ok(Value::Bool(false)),
+            "null" => return Ok(Value::Null),
+            _ => (),
+        }
+
+        self.expect('(')?;
+        self.skip_whitespace();
+        let arg = if self.peek() == Some(')') {
+            None
+        } else {
+            Some(self.value()?)
+        };
+        self.expect(')')?;
+        let text = match &arg {
+            Some(Value::String(text)) => Some(text.clone()),
+            Some(Value::Number(number)) => Some(number.to_string()),
+            Some(_) => return Err(anyhow!("invalid argument of {}()", name)),
+            None => None,
+        };
+
+        let (key, text) = match name.as_str() {
+            "ObjectId" => match text {
+                Some(text) => ("$oid", text),
+                None => ("$oid", bson::oid::ObjectId::new().to_hex()),
+            },
+            "ISODate" | "Date" => match text {
+                Some(text) => ("$date", text),
+                None => ("$date", bson::DateTime::now().try_to_rfc3339_string()?),
+            },
+            "NumberLong" => ("$numberLong", text.unwrap_or_else(|| "0".to_string())),
+            "NumberInt" => ("$numberInt", text.unwrap_or_else(|| "0".to_string())),
+            "NumberDecimal" => ("$numberDecimal", text.unwrap_or_else(|| "0".to_string())),
+            "UUID" => ("$uuid", text.ok_or_else(|| anyhow!("UUID() takes a string"))?),
+            _ => return Err(anyhow!("unknown function: {}", name)),
+        };
+        let mut map = Map::new();
+        map.insert(key.to_string(), Value::String(text));
+        Ok(Value::Object(map))
+    }
+
+}
+
+/// The start of the word to complete and the candidates.
+fn complete(line: &str, collections: &[String]) -> (usize, Vec<String>) {
+    let start = line.char_indices()
+        .rev()
+        .take_while(|(_, c)| is_ident_char(*c))
+        .last()
+        .map_or(line.len(), |(index, _)| index);
+    let word = &line[start..];
+    let before = &line[..start];
+
+    let candidates: Vec<String> = if before.trim().is_empty() {
+        COMMANDS.iter().map(|command| command.to_string()).collect()
+    } else if before.ends_with("db.") && !before[..before.len() - 3].ends_with(is_ident_char) {
+        collections.iter().cloned()
+            .chain(DATABASE_METHODS.iter().map(|method| method.to_string()))
+            .collect()
+    } else if is_collection_prefix(before) {
+        COLLECTION_METHODS.iter().map(|method| method.to_string()).collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut candidates: Vec<String> = candidates.into_iter()
+        .filter(|candidate| candidate.starts_with(word))
+        .collect();
+    candidates.sort();
+    (start, candidates)
+}
+
+/// The line ends with `db.<collection>.`
+fn is_collection_prefix(before: &str) -> bool {
+    let rest = match before.strip_suffix('.') {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let name_start = rest.rfind(|c: char| !is_ident_char(c)).map_or(0, |index| index + 1);
+    rest[..name_start].ends_with("db.") && name_start < rest.len()
+}
+
+struct ShellHelper {
+    collections: Arc<Mutex<Vec<String>>>,
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let collections = self.collections.lock().unwrap();
+        let (start, candidates) = complete(&line[..pos], &collections);
+        let pairs = candidates.into_iter()
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate,
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+/// Read and run the commands until `exit` or the end of the input.
+pub(crate) fn run_shell(db: Database) -> Result<()> {
+    let mut shell = Shell::new(db);
+    let collections = Arc::new(Mutex::new(shell.collection_names()));
+    let mut editor: Editor<ShellHelper, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(ShellHelper {
+        collections: collections.clone(),
+    }));
+    println!("PoloDB {}, type help for the commands", Database::get_version());
+
+    let mut input = String::new();
+    loop {
+        let prompt = if input.is_empty() { shell.prompt() } else { "... ".to_string() };
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                input.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+        if !input.is_empty() {
+            input.push('\n');
+        }
+        input.push_str(&line);
+        if !is_complete(&input) {
+            continue;
+        }
+        let command = std::mem::take(&mut input);
+        let _ = editor.add_history_entry(command.as_str());
+
+        match shell.execute(&command) {
+            Ok(Outcome::Print(output)) => {
+                if !output.is_empty() {
+                    println!("{}", output);
+                }
+            }
+            Ok(Outcome::Nothing) => (),
+            Ok(Outcome::Exit) => break,
+            Err(err) => eprintln!("error: {}", err),
+        }
+        *collections.lock().unwrap() = shell.collection_names();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{doc, Bson};
+    use polodb_core::Database;
+    use super::{complete, is_complete, parse_statement, Call, Outcome, Shell, Statement};
+
+    fn run(shell: &mut Shell, input: &str) -> String {
+        match shell.execute(input).unwrap() {
+            Outcome::Print(output) => output,
+            Outcome::Nothing => String::new(),
+            Outcome::Exit => panic!("unexpected exit"),
+        }
+    }
+
+    #[test]
+    fn test_parse_relaxed_json() {
+        let statement = parse_statement(
+            "db.users.find({ name: 'Alice', age: { $gt: 18 }, _id: ObjectId(\"5f1d7f5b9d1e8a2b3c4d5e6f\"), n: NumberLong(3), }).limit(2);"
+        ).unwrap().unwrap();
+        let filter = doc! {
+            "name": "Alice",
+            "age": { "$gt": 18 },
+            "_id": bson::oid::ObjectId::parse_str("5f1d7f5b9d1e8a2b3c4d5e6f").unwrap(),
+            "n": 3_i64,
+        };
+        assert_eq!(statement, Statement::Collection {
+            name: "users".to_string(),
+            calls: vec![
+                Call { name: "find".to_string(), args: vec![Bson::Document(filter)] },
+                Call { name: "limit".to_string(), args: vec![Bson::Int32(2)] },
+            ],
+        });
+
+        let statement = parse_statement("db.getCollection(\"my-logs\").drop()").unwrap().unwrap();
+        assert_eq!(statement, Statement::Collection {
+            name: "my-logs".to_string(),
+            calls: vec![Call { name: "drop".to_string(), args: vec![] }],
+        });
+
+        assert!(parse_statement("db.users.find({ name: })").is_err());
+        assert!(parse_statement("users.find()").is_err());
+        assert_eq!(parse_statement("  ").unwrap(), None);
+    }
+
+    #[test]
+    fn test_is_complete() {
+        assert!(is_complete("db.users.find({})"));
+        assert!(!is_complete("db.users.insertOne({"));
+        assert!(!is_complete("db.users.insertOne({ name: 'a)"));
+        assert!(is_complete("db.users.insertOne({ name: '{' })"));
+    }
+
+    #[test]
+    fn test_execute() {
+        let db = Database::open_memory().unwrap();
+        let mut shell = Shell::new(db);
+
+        run(&mut shell, "db.users.insertMany([{ _id: 1, name: 'Alice' }, { _id: 2, name: 'Bob' }, { _id: 3, name: 'Carol' }])");
+        assert_eq!(run(&mut shell, "show collections"), "users");
+        assert_eq!(run(&mut shell, "db.users.countDocuments()"), "3");
+        assert_eq!(run(&mut shell, "db.users.countDocuments({ _id: { $gt: 1 } })"), "2");
+
+        let output = run(&mut shell, "db.users.find().sort({ _id: -1 }).skip(1).limit(1)");
+        assert!(output.contains("\"Bob\""));
+        assert!(!output.contains("Carol"));
+
+        run(&mut shell, "db.users.updateOne({ _id: 1 }, { $set: { name: 'Alicia' } })");
+        assert!(run(&mut shell, "db.users.findOne({ _id: 1 })").contains("Alicia"));
+
+        run(&mut shell, "db.users.deleteMany({ _id: { $lt: 3 } })");
+        assert_eq!(run(&mut shell, "db.users.countDocuments()"), "1");
+        assert_eq!(run(&mut shell, "db.users.findOne({ _id: 1 })"), "null");
+
+        assert!(shell.execute("db.users.unknown()").is_err());
+        assert!(matches!(shell.execute("exit").unwrap(), Outcome::Exit));
+    }
+
+    #[test]
+    fn test_transaction() {
+        let db = Database::open_memory().unwrap();
+        let mut shell = Shell::new(db);
+
+        run(&mut shell, "begin");
+        assert_eq!(shell.prompt(), "polodb (txn)> ");
+        run(&mut shell, "db.items.insertOne({ name: 'a' })");
+        run(&mut shell, "abort");
+        assert_eq!(run(&mut shell, "db.items.countDocuments()"), "0");
+
+        run(&mut shell, "begin");
+        run(&mut shell, "db.items.insertOne({ name: 'b' })");
+        assert!(shell.execute("begin").is_err());
+        run(&mut shell, "commit");
+        assert_eq!(run(&mut shell, "db.items.countDocuments()"), "1");
+        assert!(shell.execute("commit").is_err());
+    }
+
+    #[test]
+    fn test_complete() {
+        let names = vec!["users".to_string(), "orders".to_string()];
+
+        assert_eq!(complete("db.us", &names), (3, vec!["users".to_string()]));
+        assert_eq!(complete("db.users.fi", &names), (9, vec!["find(".to_string(), "findOne(".to_string()]));
+        assert_eq!(complete("sh", &names), (0, vec!["show collections".to_string(), "show dbs".to_string()]));
+
+        let (start, candidates) = complete("db.", &names);
+        assert_eq!(start, 3);
+        assert!(candidates.contains(&"orders".to_string()));
+        assert!(candidates.contains(&"getCollectionNames()".to_string()));
+    }
+
+}
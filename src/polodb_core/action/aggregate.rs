@@ -12,18 +12,25 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::path::PathBuf;
 use std::sync::Weak;
+use std::time::Duration;
 use bson::Document;
 use serde::de::DeserializeOwned;
-use crate::{ClientCursor, Error, Result};
+use crate::{CancellationToken, ClientCursor, Error, Result};
 use crate::db::db_inner::DatabaseInner;
 use crate::transaction::TransactionInner;
+use crate::utils::spill::SpillOptions;
 
 pub struct Aggregate<'a, 'b, T: DeserializeOwned + Send + Sync = Document> {
     db: Weak<DatabaseInner>,
     name: &'a str,
     pipeline: Vec<Document>,
     txn: Option<&'b TransactionInner>,
+    cancellation_token: Option<CancellationToken>,
+    max_time: Option<Duration>,
+    spill_options: SpillOptions,
+    max_parallelism: usize,
     _phantom: std::marker::PhantomData<T>,
 }
 
@@ -34,20 +41,72 @@ impl <'a, 'b , T: DeserializeOwned + Send + Sync> Aggregate<'a, 'b, T> {
             name,
             pipeline,
             txn,
+            cancellation_token: None,
+            max_time: None,
+            spill_options: SpillOptions::default(),
+            max_parallelism: 1,
             _phantom: Default::default(),
         }
     }
 
+    /// Stop the scan with [`Error::OperationCancelled`] when the token is cancelled.
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation_token = Some(token);
+        self
+    }
+
+    /// Stop the scan with [`Error::MaxTimeExceeded`] when the cursor has spent
+    /// more than `max_time` reading the documents.
+    pub fn max_time(mut self, max_time: Duration) -> Self {
+        self.max_time = Some(max_time);
+        self
+    }
+
+    /// The bytes of the documents a `$sort` or the groups a `$group` keep in memory
+    /// before writing them to a temporary file, 100MB by default.
+    pub fn memory_limit(mut self, memory_limit: usize) -> Self {
+        self.spill_options.memory_limit = memory_limit;
+        self
+    }
+
+    /// The directory of the temporary files, [`std::env::temp_dir`] by default.
+    pub fn temp_dir<P: Into<PathBuf>>(mut self, temp_dir: P) -> Self {
+        self.spill_options.temp_dir = Some(temp_dir.into());
+        self
+    }
+
+    /// Scan the collection by up to `max_parallelism` threads, which read the ranges
+    /// of the primary keys of the same snapshot. Each thread runs the leading `$match`,
+    /// `$addFields` and `$unset` stages on its range, and a following `$group` whose accumulators
+    /// are all `$sum`s, whose groups are then merged. The other stages get the documents
+    /// of the threads in the same order as the single-threaded aggregation.
+    ///
+    /// The pipeline is aggregated by one thread in a transaction, and when it uses the indexes,
+    /// e.g. the `$match` is on an indexed field or the pipeline starts with `$vectorSearch`.
+    pub fn max_parallelism(mut self, max_parallelism: usize) -> Self {
+        self.max_parallelism = max_parallelism;
+        self
+    }
+
     pub fn run(self) -> Result<ClientCursor<T>> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
-        let txn = match self.txn {
-            Some(txn) => txn.clone(),
+        let mut cursor = match self.txn {
+            Some(txn) => db.aggregate_with_owned_session(&self.name, self.pipeline, txn.clone(), &self.spill_options)?,
+            None if self.max_parallelism > 1 => {
+                db.aggregate_parallel_with_owned_session(&self.name, self.pipeline, self.max_parallelism, &self.spill_options)?
+            }
             None => {
-                let txn = db.start_transaction()?;
-                txn
+                let txn = db.start_snapshot_transaction()?;
+                db.aggregate_with_owned_session(&self.name, self.pipeline, txn, &self.spill_options)?
             }
         };
-        db.aggregate_with_owned_session(&self.name, self.pipeline, txn.clone())
+        if let Some(token) = self.cancellation_token {
+            cursor.set_cancellation_token(token);
+        }
+        if let Some(max_time) = self.max_time {
+            cursor.set_max_time(max_time);
+        }
+        Ok(cursor)
     }
 
     pub fn with_type<U>(self) -> Aggregate<'a, 'b, U>
@@ -57,6 +116,10 @@ impl <'a, 'b , T: DeserializeOwned + Send + Sync> Aggregate<'a, 'b, T> {
             name: self.name,
             pipeline: self.pipeline,
             txn: self.txn,
+            cancellation_token: self.cancellation_token,
+            max_time: self.max_time,
+            spill_options: self.spill_options,
+            max_parallelism: self.max_parallelism,
             _phantom: Default::default(),
         }
     }
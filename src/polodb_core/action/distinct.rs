@@ -0,0 +1,69 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::PathBuf;
+use bson::{doc, Bson, Document};
+use crate::{ClientCursor, Result};
+use crate::action::Aggregate;
+
+/// The distinct values of a field, grouped by a `$group` stage,
+/// so they are written to temporary files like the groups.
+pub struct Distinct<'a, 'b> {
+    aggregate: Aggregate<'a, 'b>,
+}
+
+impl <'a, 'b> Distinct<'a, 'b> {
+    pub(crate) fn new(aggregate: Aggregate<'a, 'b>) -> Distinct<'a, 'b> {
+        Distinct { aggregate }
+    }
+
+    pub(crate) fn pipeline(field: &str, filter: Document) -> Vec<Document> {
+        vec![
+            doc! { "$match": filter },
+            doc! { "$group": { "_id": format!("${}", field) } },
+        ]
+    }
+
+    /// The bytes of the distinct values kept in memory before
+    /// writing them to a temporary file, 100MB by default.
+    pub fn memory_limit(mut self, memory_limit: usize) -> Self {
+        self.aggregate = self.aggregate.memory_limit(memory_limit);
+        self
+    }
+
+    /// The directory of the temporary files, [`std::env::temp_dir`] by default.
+    pub fn temp_dir<P: Into<PathBuf>>(mut self, temp_dir: P) -> Self {
+        self.aggregate = self.aggregate.temp_dir(temp_dir);
+        self
+    }
+
+    pub fn run(self) -> Result<DistinctCursor> {
+        let cursor = self.aggregate.run()?;
+        Ok(DistinctCursor { cursor })
+    }
+}
+
+/// The distinct values in the order of the index keys.
+pub struct DistinctCursor {
+    cursor: ClientCursor<Document>,
+}
+
+impl Iterator for DistinctCursor {
+    type Item = Result<Bson>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.cursor.next()?;
+        Some(item.map(|mut doc| doc.remove("_id").unwrap_or(Bson::Null)))
+    }
+}
@@ -12,12 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::path::PathBuf;
 use std::sync::Weak;
+use std::time::Duration;
 use bson::{Document, doc};
 use serde::de::DeserializeOwned;
 use crate::db::db_inner::DatabaseInner;
-use crate::{ClientCursor, Error, Result};
+use crate::{CancellationToken, ClientCursor, Error, Result};
 use crate::transaction::TransactionInner;
+use crate::coll::db_ref::RefResolver;
+use crate::utils::spill::SpillOptions;
 
 pub struct Find<'a, 'b, T: DeserializeOwned + Send + Sync> {
     db: Weak<DatabaseInner>,
@@ -27,6 +31,11 @@ pub struct Find<'a, 'b, T: DeserializeOwned + Send + Sync> {
     skip: Option<u64>,
     limit: Option<u64>,
     sort: Option<Document>,
+    cancellation_token: Option<CancellationToken>,
+    max_time: Option<Duration>,
+    resolve_depth: u32,
+    max_parallelism: usize,
+    spill_options: SpillOptions,
     _phantom: std::marker::PhantomData<T>,
 }
 
@@ -40,6 +49,11 @@ impl <'a, 'b , T: DeserializeOwned + Send + Sync> Find<'a, 'b, T> {
             skip: None,
             limit: None,
             sort: None,
+            cancellation_token: None,
+            max_time: None,
+            resolve_depth: 0,
+            max_parallelism: 1,
+            spill_options: SpillOptions::default(),
             _phantom: Default::default(),
         }
     }
@@ -54,21 +68,85 @@ impl <'a, 'b , T: DeserializeOwned + Send + Sync> Find<'a, 'b, T> {
         self
     }
 
+    /// Sort the documents by the fields, `1` for ascending and `-1` for descending.
+    ///
+    /// `{ "$natural": 1 }` or `{ "$natural": -1 }` scans a capped collection forward or backward
+    /// in insertion order without the indexes. The other collections don't keep the insertion order,
+    /// so they fail with [`Error::ValidationError`], except the forward scan of a time-series collection.
     pub fn sort(mut self, sort: Document) -> Self {
         self.sort = Some(sort);
         self
     }
 
+    /// Stop the scan with [`Error::OperationCancelled`] when the token is cancelled.
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation_token = Some(token);
+        self
+    }
+
+    /// Stop the scan with [`Error::MaxTimeExceeded`] when the cursor has spent
+    /// more than `max_time` reading the documents.
+    pub fn max_time(mut self, max_time: Duration) -> Self {
+        self.max_time = Some(max_time);
+        self
+    }
+
+    /// Replace the [`DbRef`](crate::DbRef)s of the returned documents with the referenced documents,
+    /// following the references of the referenced documents up to `depth` levels.
+    /// The dangling references are kept. The default depth 0 doesn't resolve anything.
+    pub fn resolve_refs(mut self, depth: u32) -> Self {
+        self.resolve_depth = depth;
+        self
+    }
+
+    /// Scan the collection by up to `max_parallelism` threads, which read the ranges
+    /// of the primary keys of the same snapshot. The documents are returned in the same order.
+    ///
+    /// The scan is single-threaded in a transaction, with `skip`, `limit` or `sort`,
+    /// and when the query uses the index.
+    pub fn max_parallelism(mut self, max_parallelism: usize) -> Self {
+        self.max_parallelism = max_parallelism;
+        self
+    }
+
+    /// The bytes of the documents the `sort` keeps in memory before
+    /// writing them to a temporary file, 100MB by default.
+    pub fn memory_limit(mut self, memory_limit: usize) -> Self {
+        self.spill_options.memory_limit = memory_limit;
+        self
+    }
+
+    /// The directory of the temporary files, [`std::env::temp_dir`] by default.
+    pub fn temp_dir<P: Into<PathBuf>>(mut self, temp_dir: P) -> Self {
+        self.spill_options.temp_dir = Some(temp_dir.into());
+        self
+    }
+
     pub fn run(self) -> Result<ClientCursor<T>> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let parallel = self.max_parallelism > 1
+            && self.txn.is_none()
+            && self.skip.is_none()
+            && self.limit.is_none()
+            && self.sort.is_none();
+        if parallel {
+            let mut cursor = db.find_parallel_with_owned_session(self.name, self.filter, self.max_parallelism)?;
+            if let Some(token) = self.cancellation_token {
+                cursor.set_cancellation_token(token);
+            }
+            if let Some(max_time) = self.max_time {
+                cursor.set_max_time(max_time);
+            }
+            if self.resolve_depth > 0 {
+                cursor.set_ref_resolver(RefResolver::new(self.db.clone(), self.name, self.resolve_depth, None));
+            }
+            return Ok(cursor);
+        }
         let txn = match self.txn {
             Some(txn) => txn.clone(),
-            None => {
-                let txn = db.start_transaction()?;
-                txn
-            }
+            None => db.start_snapshot_transaction()?,
         };
-        match (self.skip.as_ref(), self.limit.as_ref(), self.sort.as_ref()) {
+        let mut cursor = match (self.skip.as_ref(), self.limit.as_ref(), self.sort.as_ref()) {
             (None, None, None) => {
                 db.find_with_owned_session(&self.name, self.filter, txn)
             }
@@ -97,8 +175,23 @@ impl <'a, 'b , T: DeserializeOwned + Send + Sync> Find<'a, 'b, T> {
                     });
                 }
 
-                db.aggregate_with_owned_session(&self.name, pipeline, txn)
+                db.aggregate_with_owned_session(&self.name, pipeline, txn, &self.spill_options)
             }
+        }?;
+        if let Some(token) = self.cancellation_token {
+            cursor.set_cancellation_token(token);
+        }
+        if let Some(max_time) = self.max_time {
+            cursor.set_max_time(max_time);
+        }
+        if self.resolve_depth > 0 {
+            cursor.set_ref_resolver(RefResolver::new(
+                self.db.clone(),
+                self.name,
+                self.resolve_depth,
+                self.txn.cloned(),
+            ));
         }
+        Ok(cursor)
     }
 }
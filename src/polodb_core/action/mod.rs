@@ -14,6 +14,8 @@
 
 mod find;
 mod aggregate;
+mod distinct;
 
 pub use find::Find;
 pub use aggregate::Aggregate;
+pub use distinct::{Distinct, DistinctCursor};
@@ -0,0 +1,443 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Export the collections to Apache Arrow and Parquet.
+//! It's enabled by the `arrow` feature.
+//!
+//! [`crate::Database::export_arrow`] reads the documents of a collection, or the results
+//! of an aggregation, as Arrow record batches, to query them with DataFusion or Polars
+//! without going through JSON. [`crate::Database::export_parquet`] writes them
+//! to a Parquet file. The crates `arrow` and `parquet` are re-exported by PoloDB.
+//!
+//! The schema is given by [`ArrowExportOptions::schema`], or inferred from the first documents.
+//! The fields of the documents which are not in the schema are skipped. The fields
+//! of the inferred schema are nullable, and the types are inferred as:
+//!
+//! | BSON                  | Arrow                                  |
+//! |-----------------------|----------------------------------------|
+//! | boolean               | `Boolean`                              |
+//! | int32                 | `Int32`                                |
+//! | int64                 | `Int64`, or if it's mixed with int32   |
+//! | double                | `Float64`, or if it's mixed with integers |
+//! | date                  | `Timestamp(Millisecond, "UTC")`        |
+//! | binary                | `Binary`                               |
+//! | document              | `Struct`                               |
+//! | array                 | `List`                                 |
+//! | null                  | `Null`                                 |
+//! | the other types, and the mixed types | `Utf8`, the ObjectIds in hex, the dates in RFC 3339, and the others in Extended JSON |
+//!
+//! ```rust
+//! use polodb_core::{CollectionT, Database};
+//! use polodb_core::arrow::array::Int32Array;
+//! use polodb_core::arrow_export::ArrowExportOptions;
+//! use polodb_core::bson::doc;
+//!
+//! let db = Database::open_memory().unwrap();
+//! db.collection("orders").insert_many(vec![
+//!     doc! { "item": "apple", "qty": 5 },
+//!     doc! { "item": "pear", "qty": 3 },
+//! ]).unwrap();
+//!
+//! let batches = db.export_arrow("orders", ArrowExportOptions::default()).unwrap();
+//! for batch in batches {
+//!     let batch = batch.unwrap();
+//!     let qty = batch.column_by_name("qty").unwrap().as_any().downcast_ref::<Int32Array>().unwrap();
+//!     assert_eq!(qty.values(), &[5, 3]);
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::Arc;
+use arrow::array::{
+    ArrayRef, BinaryArray, BooleanArray, Float64Array, Int32Array, Int64Array, ListArray,
+    NullArray, StringArray, StructArray, TimestampMillisecondArray,
+};
+use arrow::buffer::{NullBuffer, OffsetBuffer};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::error::ArrowError;
+use arrow::record_batch::{RecordBatch, RecordBatchOptions, RecordBatchReader};
+use bson::{doc, Bson, Document};
+use indexmap::IndexMap;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use crate::utils::bson::format_value;
+use crate::{ClientCursor, CollectionT, Database, Error, Result};
+
+const DEFAULT_BATCH_SIZE: usize = 8192;
+const DEFAULT_SAMPLE_SIZE: usize = 1000;
+
+/// Options of [`crate::Database::export_arrow`] and [`crate::Database::export_parquet`].
+#[derive(Debug, Clone, Default)]
+pub struct ArrowExportOptions {
+    /// Only export the documents matching the filter.
+    pub filter: Option<Document>,
+
+    /// Export the results of the aggregation instead of the documents,
+    /// the filter is applied before the pipeline.
+    pub pipeline: Option<Vec<Document>>,
+
+    /// The schema of the record batches, inferred from the documents by default.
+    pub schema: Option<SchemaRef>,
+
+    /// The number of the rows of a record batch, 8192 by default.
+    pub batch_size: Option<usize>,
+
+    /// The number of the documents to infer the schema, 1000 by default.
+    pub sample_size: Option<usize>,
+}
+
+impl ArrowExportOptions {
+    pub fn builder() -> ArrowExportOptionsBuilder {
+        ArrowExportOptionsBuilder::default()
+    }
+}
+
+#[derive(Default)]
+pub struct ArrowExportOptionsBuilder {
+    options: ArrowExportOptions,
+}
+
+impl ArrowExportOptionsBuilder {
+    pub fn filter(mut self, filter: Document) -> Self {
+        self.options.filter = Some(filter);
+        self
+    }
+
+    pub fn pipeline(mut self, pipeline: impl IntoIterator<Item = Document>) -> Self {
+        self.options.pipeline = Some(pipeline.into_iter().collect());
+        self
+    }
+
+    pub fn schema(mut self, schema: SchemaRef) -> Self {
+        self.options.schema = Some(schema);
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.options.batch_size = Some(batch_size);
+        self
+    }
+
+    pub fn sample_size(mut self, sample_size: usize) -> Self {
+        self.options.sample_size = Some(sample_size);
+        self
+    }
+
+    pub fn build(self) -> ArrowExportOptions {
+        self.options
+    }
+}
+
+fn arrow_error(err: impl std::fmt::Display) -> Error {
+    Error::ArrowError(err.to_string())
+}
+
+/// The record batches of the documents, returned by [`crate::Database::export_arrow`].
+pub struct ArrowBatchReader {
+    schema: SchemaRef,
+    // the documents read to infer the schema
+    sample: VecDeque<Document>,
+    cursor: ClientCursor<Document>,
+    batch_size: usize,
+}
+
+impl ArrowBatchReader {
+
+    fn next_batch(&mut self) -> Result<Option<RecordBatch>> {
+        let mut docs = Vec::with_capacity(self.batch_size);
+        while docs.len() < self.batch_size {
+            match self.sample.pop_front() {
+                Some(doc) => docs.push(doc),
+                None => match self.cursor.next() {
+                    Some(doc) => docs.push(doc?),
+                    None => break,
+                },
+            }
+        }
+        if docs.is_empty() {
+            return Ok(None);
+        }
+        documents_to_record_batch(&docs, &self.schema).map(Some)
+    }
+
+}
+
+impl Iterator for ArrowBatchReader {
+    type Item = std::result::Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_batch()
+            .map_err(|err| ArrowError::ExternalError(Box::new(err)))
+            .transpose()
+    }
+}
+
+impl RecordBatchReader for ArrowBatchReader {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+pub(crate) fn export_arrow(db: &Database, col_name: &str, options: ArrowExportOptions) -> Result<ArrowBatchReader> {
+    let collection = db.collection::<Document>(col_name);
+    let filter = options.filter.unwrap_or_default();
+    let mut cursor = match options.pipeline {
+        Some(pipeline) => {
+            let mut stages = Vec::with_capacity(pipeline.len() + 1);
+            if !filter.is_empty() {
+                stages.push(doc! { "$match": filter });
+            }
+            stages.extend(pipeline);
+            collection.aggregate(stages).run()?
+        }
+        None => collection.find(filter).run()?,
+    };
+
+    let mut sample = VecDeque::new();
+    let schema = match options.schema {
+        Some(schema) => schema,
+        None => {
+            let sample_size = options.sample_size.unwrap_or(DEFAULT_SAMPLE_SIZE);
+            while sample.len() < sample_size {
+                match cursor.next() {
+                    Some(doc) => sample.push_back(doc?),
+                    None => break,
+                }
+            }
+            Arc::new(infer_schema(sample.iter()))
+        }
+    };
+
+    Ok(ArrowBatchReader {
+        schema,
+        sample,
+        cursor,
+        batch_size: options.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1),
+    })
+}
+
+pub(crate) fn export_parquet<W: Write + Send>(db: &Database, col_name: &str, writer: W, options: ArrowExportOptions) -> Result<u64> {
+    let reader = export_arrow(db, col_name, options)?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = ArrowWriter::try_new(writer, reader.schema(), Some(properties)).map_err(arrow_error)?;
+    let mut count: u64 = 0;
+    let mut reader = reader;
+    while let Some(batch) = reader.next_batch()? {
+        count += batch.num_rows() as u64;
+        writer.write(&batch).map_err(arrow_error)?;
+    }
+    writer.close().map_err(arrow_error)?;
+    Ok(count)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum InferredType {
+    Null,
+    Boolean,
+    Int32,
+    Int64,
+    Double,
+    DateTime,
+    Binary,
+    String,
+    Struct(IndexMap<String, InferredType>),
+    List(Box<InferredType>),
+}
+
+impl InferredType {
+
+    fn of(value: &Bson) -> InferredType {
+        match value {
+            Bson::Null | Bson::Undefined => InferredType::Null,
+            Bson::Boolean(_) => InferredType::Boolean,
+            Bson::Int32(_) => InferredType::Int32,
+            Bson::Int64(_) => InferredType::Int64,
+            Bson::Double(_) => InferredType::Double,
+            Bson::DateTime(_) => InferredType::DateTime,
+            Bson::Binary(_) => InferredType::Binary,
+            Bson::Document(doc) => InferredType::Struct(infer_fields(std::iter::once(doc))),
+            Bson::Array(values) => {
+                let item = values.iter().fold(InferredType::Null, |ty, value| ty.merge(InferredType::of(value)));
+                InferredType::List(Box::new(item))
+            }
+            _ => InferredType::String,
+        }
+    }
+
+    fn merge(self, other: InferredType) -> InferredType {
+        match (self, other) {
+            (InferredType::Null, ty) | (ty, InferredType::Null) => ty,
+            (InferredType::Int32, InferredType::Int64) | (InferredType::Int64, InferredType::Int32) => InferredType::Int64,
+            (InferredType::Int32, InferredType::Double) | (InferredType::Double, InferredType::Int32) |
+            (InferredType::Int64, InferredType::Double) | (InferredType::Double, InferredType::Int64) => InferredType::Double,
+            (InferredType::Struct(mut fields), InferredType::Struct(other)) => {
+                merge_fields(&mut fields, other);
+                InferredType::Struct(fields)
+            }
+            (InferredType::List(item), InferredType::List(other)) => InferredType::List(Box::new(item.merge(*other))),
+            (ty, other) if ty == other => ty,
+            _ => InferredType::String,
+        }
+    }
+
+    fn data_type(&self) -> DataType {
+        match self {
+            InferredType::Null => DataType::Null,
+            InferredType::Boolean => DataType::Boolean,
+            InferredType::Int32 => DataType::Int32,
+            InferredType::Int64 => DataType::Int64,
+            InferredType::Double => DataType::Float64,
+            InferredType::DateTime => DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+            InferredType::Binary => DataType::Binary,
+            InferredType::String => DataType::Utf8,
+            // the structs without fields can't be written to Parquet
+            InferredType::Struct(fields) if fields.is_empty() => DataType::Utf8,
+            InferredType::Struct(fields) => DataType::Struct(
+                fields.iter().map(|(name, ty)| Field::new(name, ty.data_type(), true)).collect(),
+            ),
+            InferredType::List(item) => DataType::List(Arc::new(Field::new("item", item.data_type(), true))),
+        }
+    }
+
+}
+
+fn merge_fields(fields: &mut IndexMap<String, InferredType>, other: IndexMap<String, InferredType>) {
+    for (name, ty) in other {
+        let merged = match fields.get_mut(&name) {
+            Some(existing) => std::mem::replace(existing, InferredType::Null).merge(ty),
+            None => ty,
+        };
+        fields.insert(name, merged);
+    }
+}
+
+fn infer_fields<'a>(docs: impl Iterator<Item = &'a Document>) -> IndexMap<String, InferredType> {
+    let mut fields = IndexMap::new();
+    for doc in docs {
+        let types = doc.iter().map(|(name, value)| (name.clone(), InferredType::of(value))).collect();
+        merge_fields(&mut fields, types);
+    }
+    fields
+}
+
+/// Infer the schema of the documents, the fields are in the order they first appear.
+pub fn infer_schema<'a>(docs: impl IntoIterator<Item = &'a Document>) -> Schema {
+    let fields = infer_fields(docs.into_iter());
+    Schema::new(
+        fields.iter()
+            .map(|(name, ty)| Field::new(name, ty.data_type(), true))
+            .collect::<Vec<Field>>(),
+    )
+}
+
+/// Convert the documents to a record batch of the schema.
+pub fn documents_to_record_batch(docs: &[Document], schema: &SchemaRef) -> Result<RecordBatch> {
+    let columns = schema.fields().iter()
+        .map(|field| {
+            let values: Vec<Option<&Bson>> = docs.iter().map(|doc| not_null(doc.get(field.name()))).collect();
+            to_array(&values, field.data_type(), field.name())
+        })
+        .collect::<Result<Vec<ArrayRef>>>()?;
+    let options = RecordBatchOptions::new().with_row_count(Some(docs.len()));
+    RecordBatch::try_new_with_options(schema.clone(), columns, &options).map_err(arrow_error)
+}
+
+fn not_null(value: Option<&Bson>) -> Option<&Bson> {
+    value.filter(|value| !matches!(value, Bson::Null | Bson::Undefined))
+}
+
+fn mismatch(path: &str, data_type: &DataType, value: &Bson) -> Error {
+    Error::ArrowError(format!("the value of '{}' is not {}: {}", path, data_type, value))
+}
+
+fn convert<'a, T>(
+    values: &[Option<&'a Bson>],
+    path: &str,
+    data_type: &DataType,
+    f: impl Fn(&'a Bson) -> Option<T>,
+) -> Result<Vec<Option<T>>> {
+    values.iter()
+        .map(|value| match value {
+            None => Ok(None),
+            Some(value) => f(value).map(Some).ok_or_else(|| mismatch(path, data_type, value)),
+        })
+        .collect()
+}
+
+/// Convert the values of a column, `None` for the missing and the null values.
+fn to_array(values: &[Option<&Bson>], data_type: &DataType, path: &str) -> Result<ArrayRef> {
+    let array: ArrayRef = match data_type {
+        DataType::Null => {
+            if let Some(value) = values.iter().flatten().next() {
+                return Err(mismatch(path, data_type, value));
+            }
+            Arc::new(NullArray::new(values.len()))
+        }
+        DataType::Boolean => Arc::new(BooleanArray::from(convert(values, path, data_type, Bson::as_bool)?)),
+        DataType::Int32 => Arc::new(Int32Array::from(convert(values, path, data_type, Bson::as_i32)?)),
+        DataType::Int64 => Arc::new(Int64Array::from(convert(values, path, data_type, |value| match value {
+            Bson::Int32(value) => Some(*value as i64),
+            Bson::Int64(value) => Some(*value),
+            _ => None,
+        })?)),
+        DataType::Float64 => Arc::new(Float64Array::from(convert(values, path, data_type, |value| match value {
+            Bson::Int32(value) => Some(*value as f64),
+            Bson::Int64(value) => Some(*value as f64),
+            Bson::Double(value) => Some(*value),
+            _ => None,
+        })?)),
+        DataType::Utf8 => Arc::new(StringArray::from(convert(values, path, data_type, |value| Some(format_value(value)))?)),
+        DataType::Binary => Arc::new(BinaryArray::from_opt_vec(convert(values, path, data_type, |value| match value {
+            Bson::Binary(binary) => Some(binary.bytes.as_slice()),
+            _ => None,
+        })?)),
+        DataType::Timestamp(TimeUnit::Millisecond, timezone) => {
+            let millis = convert(values, path, data_type, |value| value.as_datetime().map(|date| date.timestamp_millis()))?;
+            Arc::new(TimestampMillisecondArray::from(millis).with_timezone_opt(timezone.clone()))
+        }
+        DataType::Struct(fields) => {
+            let docs = convert(values, path, data_type, Bson::as_document)?;
+            let nulls = NullBuffer::from(docs.iter().map(Option::is_some).collect::<Vec<bool>>());
+            let children = fields.iter()
+                .map(|field| {
+                    let child_values: Vec<Option<&Bson>> = docs.iter()
+                        .map(|doc| doc.and_then(|doc| not_null(doc.get(field.name()))))
+                        .collect();
+                    to_array(&child_values, field.data_type(), &format!("{}.{}", path, field.name()))
+                })
+                .collect::<Result<Vec<ArrayRef>>>()?;
+            Arc::new(StructArray::try_new(fields.clone(), children, Some(nulls)).map_err(arrow_error)?)
+        }
+        DataType::List(field) => {
+            let arrays = convert(values, path, data_type, Bson::as_array)?;
+            let nulls = NullBuffer::from(arrays.iter().map(Option::is_some).collect::<Vec<bool>>());
+            let lengths = arrays.iter().map(|array| array.map_or(0, |array| array.len()));
+            let items: Vec<Option<&Bson>> = arrays.iter()
+                .flatten()
+                .flat_map(|array| array.iter().map(|value| not_null(Some(value))))
+                .collect();
+            let child = to_array(&items, field.data_type(), &format!("{}[]", path))?;
+            Arc::new(ListArray::try_new(field.clone(), OffsetBuffer::from_lengths(lengths), child, Some(nulls)).map_err(arrow_error)?)
+        }
+        data_type => {
+            return Err(Error::ArrowError(format!("the type {} of '{}' is not supported", data_type, path)));
+        }
+    };
+    Ok(array)
+}
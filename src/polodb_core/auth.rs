@@ -0,0 +1,700 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The user accounts of the wire-protocol and HTTP servers.
+//!
+//! The users are stored in the `__users` collection of the default database,
+//! with the SCRAM-SHA-256 credentials of their passwords, like MongoDB.
+//! The passwords themselves are never stored.
+//!
+//! A [`User`] has [`Role`]s, each one grants a [`Permission`] on a database,
+//! a collection of a database, or every database. The servers check the
+//! [`Action`] of every request against them with [`User::is_allowed`].
+//!
+//! ```rust
+//! use polodb_core::Database;
+//! use polodb_core::auth::{Action, Role};
+//!
+//! let db = Database::open_memory().unwrap();
+//! let users = db.users();
+//! users.create("alice", "secret", vec![
+//!     Role::read_write("shop"),
+//!     Role::read("analytics").collection("reports"),
+//! ]).unwrap();
+//!
+//! let alice = users.authenticate("alice", "secret").unwrap();
+//! assert!(alice.is_allowed(Action::Write, Some("shop"), Some("orders")));
+//! assert!(alice.is_allowed(Action::Read, Some("analytics"), Some("reports")));
+//! assert!(!alice.is_allowed(Action::Write, Some("analytics"), Some("reports")));
+//! assert!(users.authenticate("alice", "wrong").is_err());
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Weak;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use bson::{doc, Document};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::db::db_inner::DatabaseInner;
+use crate::{Collection, CollectionT, Error, Result};
+
+/// The collection of the users in the default database,
+/// only the users with [`Permission::Admin`] can access it through the servers.
+pub const USERS_COLLECTION: &str = "__users";
+
+/// The name of the only supported SASL mechanism.
+pub const SCRAM_SHA_256: &str = "SCRAM-SHA-256";
+
+/// The default iteration count of MongoDB for SCRAM-SHA-256.
+const SCRAM_ITERATIONS: u32 = 15000;
+const SALT_SIZE: usize = 28;
+const NONCE_SIZE: usize = 24;
+const TOKEN_SIZE: usize = 32;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// What a [`Role`] allows, each one includes the previous ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Permission {
+    Read,
+    ReadWrite,
+    /// Managing the users, and the other commands of the servers.
+    Admin,
+}
+
+/// The kind of a request checked by [`User::is_allowed`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Read,
+    Write,
+    Admin,
+}
+
+impl fmt::Display for Action {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Read => write!(f, "read"),
+            Action::Write => write!(f, "write"),
+            Action::Admin => write!(f, "administrate"),
+        }
+    }
+
+}
+
+impl Action {
+
+    fn required_permission(self) -> Permission {
+        match self {
+            Action::Read => Permission::Read,
+            Action::Write => Permission::ReadWrite,
+            Action::Admin => Permission::Admin,
+        }
+    }
+
+}
+
+/// A [`Permission`] on a scope.
+///
+/// Without a database, the role applies to every database, including the default one.
+/// Without a collection, it applies to every collection of the database.
+///
+/// A role is written as `<permission>[@<database>[.<collection>]]`,
+/// e.g. `readWrite@shop`, `read@shop.orders` or `admin`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Role {
+    pub permission: Permission,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collection: Option<String>,
+}
+
+impl Role {
+
+    fn new(permission: Permission, database: Option<String>) -> Role {
+        Role {
+            permission,
+            database,
+            collection: None,
+        }
+    }
+
+    pub fn read<T: Into<String>>(database: T) -> Role {
+        Role::new(Permission::Read, Some(database.into()))
+    }
+
+    pub fn read_write<T: Into<String>>(database: T) -> Role {
+        Role::new(Permission::ReadWrite, Some(database.into()))
+    }
+
+    pub fn read_any_database() -> Role {
+        Role::new(Permission::Read, None)
+    }
+
+    pub fn read_write_any_database() -> Role {
+        Role::new(Permission::ReadWrite, None)
+    }
+
+    /// Every permission on every database.
+    pub fn admin() -> Role {
+        Role::new(Permission::Admin, None)
+    }
+
+    /// Restrict the role to the collection `name` of its database.
+    pub fn collection<T: Into<String>>(mut self, name: T) -> Role {
+        self.collection = Some(name.into());
+        self
+    }
+
+    fn grants(&self, action: Action, database: Option<&str>, collection: Option<&str>) -> bool {
+        if self.permission < action.required_permission() {
+            return false;
+        }
+        if let Some(expected) = &self.database {
+            if database != Some(expected.as_str()) {
+                return false;
+            }
+        }
+        match (&self.collection, collection) {
+            (None, _) => true,
+            (Some(expected), Some(collection)) => expected == collection,
+            // a role on a collection doesn't grant the commands on the whole database
+            (Some(_), None) => false,
+        }
+    }
+
+}
+
+impl fmt::Display for Role {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let permission = match self.permission {
+            Permission::Read => "read",
+            Permission::ReadWrite => "readWrite",
+            Permission::Admin => "admin",
+        };
+        write!(f, "{}", permission)?;
+        if let Some(database) = &self.database {
+            write!(f, "@{}", database)?;
+            if let Some(collection) = &self.collection {
+                write!(f, ".{}", collection)?;
+            }
+        }
+        Ok(())
+    }
+
+}
+
+impl FromStr for Role {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Role> {
+        let (permission, scope) = match s.split_once('@') {
+            Some((permission, scope)) => (permission, Some(scope)),
+            None => (s, None),
+        };
+        let permission = match permission {
+            "read" => Permission::Read,
+            "readWrite" => Permission::ReadWrite,
+            "admin" => Permission::Admin,
+            _ => return Err(Error::ParseError(format!("unknown permission of role: '{}'", s))),
+        };
+        let (database, collection) = match scope.map(|scope| scope.split_once('.').unwrap_or((scope, ""))) {
+            Some(("", _)) => return Err(Error::ParseError(format!("the database of role '{}' is empty", s))),
+            Some((database, "")) => (Some(database.to_string()), None),
+            Some((database, collection)) => (Some(database.to_string()), Some(collection.to_string())),
+            None => (None, None),
+        };
+        Ok(Role {
+            permission,
+            database,
+            collection,
+        })
+    }
+
+}
+
+/// An authenticated user.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct User {
+    pub name: String,
+    pub roles: Vec<Role>,
+}
+
+impl User {
+
+    /// Whether one of the roles allows `action` on the collection of the database.
+    ///
+    /// `database` is `None` for the default database, and `collection` is `None`
+    /// for the commands on the whole database, e.g. listing the collections.
+    /// The collections named like the users collection need [`Permission::Admin`] in every database,
+    /// since the wire-protocol server maps all the databases to the default one.
+    pub fn is_allowed(&self, action: Action, database: Option<&str>, collection: Option<&str>) -> bool {
+        let action = if collection == Some(USERS_COLLECTION) {
+            Action::Admin
+        } else {
+            action
+        };
+        self.roles.iter().any(|role| role.grants(action, database, collection))
+    }
+
+}
+
+/// The stored SCRAM-SHA-256 credentials, in the format of MongoDB.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScramCredentials {
+    iteration_count: u32,
+    salt: String,
+    stored_key: String,
+    server_key: String,
+}
+
+impl ScramCredentials {
+
+    fn new(password: &str) -> Result<ScramCredentials> {
+        let salt = random_bytes(SALT_SIZE);
+        let salted_password = salt_password(password, &salt, SCRAM_ITERATIONS)?;
+        let client_key = hmac(&salted_password, b"Client Key");
+        Ok(ScramCredentials {
+            iteration_count: SCRAM_ITERATIONS,
+            salt: BASE64.encode(&salt),
+            stored_key: BASE64.encode(Sha256::digest(client_key)),
+            server_key: BASE64.encode(hmac(&salted_password, b"Server Key")),
+        })
+    }
+
+    fn verify_password(&self, password: &str) -> Result<bool> {
+        let salt = decode_base64(&self.salt)?;
+        let salted_password = salt_password(password, &salt, self.iteration_count)?;
+        let client_key = hmac(&salted_password, b"Client Key");
+        let stored_key = decode_base64(&self.stored_key)?;
+        Ok(constant_time_eq(&Sha256::digest(client_key), &stored_key))
+    }
+
+}
+
+#[derive(Serialize, Deserialize)]
+struct UserDocument {
+    #[serde(rename = "_id")]
+    name: String,
+    roles: Vec<Role>,
+    #[serde(rename = "SCRAM-SHA-256")]
+    scram: ScramCredentials,
+    /// The SHA-256 of the tokens in hex.
+    #[serde(default)]
+    tokens: Vec<String>,
+}
+
+impl UserDocument {
+
+    fn to_user(&self) -> User {
+        User {
+            name: self.name.clone(),
+            roles: self.roles.clone(),
+        }
+    }
+
+}
+
+fn random_bytes(size: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; size];
+    getrandom::getrandom(&mut bytes).expect("the system random number generator is unavailable");
+    bytes
+}
+
+fn hmac(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn salt_password(password: &str, salt: &[u8], iterations: u32) -> Result<Vec<u8>> {
+    let password = stringprep::saslprep(password)
+        .map_err(|err| Error::ValidationError(format!("invalid password: {}", err)))?;
+    let mut result = vec![0u8; 32];
+    pbkdf2::pbkdf2::<HmacSha256>(password.as_bytes(), salt, iterations, &mut result);
+    Ok(result)
+}
+
+fn decode_base64(value: &str) -> Result<Vec<u8>> {
+    BASE64.decode(value).map_err(|err| Error::ParseError(format!("invalid base64: {}", err)))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The users of the database, returned by [`crate::Database::users`].
+///
+/// The users are shared by the named databases stored in the same files.
+pub struct Users {
+    db: Weak<DatabaseInner>,
+}
+
+impl Users {
+
+    pub(crate) fn new(db: Weak<DatabaseInner>) -> Users {
+        Users { db }
+    }
+
+    fn collection(&self) -> Collection<UserDocument> {
+        Collection::new(self.db.clone(), USERS_COLLECTION)
+    }
+
+    fn find_document(&self, name: &str) -> Result<UserDocument> {
+        self.collection()
+            .find_one(doc! { "_id": name })?
+            .ok_or_else(|| Error::UserNotFound(name.to_string()))
+    }
+
+    fn update_document(&self, name: &str, update: Document) -> Result<()> {
+        let result = self.collection().update_one(doc! { "_id": name }, update)?;
+        if result.matched_count == 0 {
+            return Err(Error::UserNotFound(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Creates the user `name` with the credentials of `password`.
+    pub fn create(&self, name: &str, password: &str, roles: Vec<Role>) -> Result<User> {
+        if name.is_empty() {
+            return Err(Error::ValidationError("the name of the user is empty".to_string()));
+        }
+        let collection = self.collection();
+        if collection.find_one(doc! { "_id": name })?.is_some() {
+            return Err(Error::UserAlreadyExists(name.to_string()));
+        }
+        let document = UserDocument {
+            name: name.to_string(),
+            roles,
+            scram: ScramCredentials::new(password)?,
+            tokens: Vec::new(),
+        };
+        collection.insert_one(&document)?;
+        Ok(document.to_user())
+    }
+
+    /// Removes the user `name`, returns `false` if it doesn't exist.
+    pub fn drop(&self, name: &str) -> Result<bool> {
+        let result = self.collection().delete_one(doc! { "_id": name })?;
+        Ok(result.deleted_count > 0)
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<User>> {
+        match self.find_document(name) {
+            Ok(document) => Ok(Some(document.to_user())),
+            Err(Error::UserNotFound(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn list(&self) -> Result<Vec<User>> {
+        self.collection()
+            .find(doc! {})
+            .sort(doc! { "_id": 1 })
+            .run()?
+            .map(|document| document.map(|document| document.to_user()))
+            .collect()
+    }
+
+    /// Replaces the roles of the user `name`.
+    pub fn set_roles(&self, name: &str, roles: Vec<Role>) -> Result<()> {
+        let roles = bson::to_bson(&roles)?;
+        self.update_document(name, doc! { "$set": { "roles": roles } })
+    }
+
+    /// Replaces the password of the user `name`, the tokens are kept.
+    pub fn set_password(&self, name: &str, password: &str) -> Result<()> {
+        let scram = bson::to_bson(&ScramCredentials::new(password)?)?;
+        self.update_document(name, doc! { "$set": { SCRAM_SHA_256: scram } })
+    }
+
+    /// Checks the password of the user `name`.
+    /// Returns [`Error::AuthenticationFailed`] if the user doesn't exist or the password is wrong.
+    pub fn authenticate(&self, name: &str, password: &str) -> Result<User> {
+        let document = match self.find_document(name) {
+            Ok(document) => document,
+            Err(Error::UserNotFound(_)) => return Err(Error::AuthenticationFailed),
+            Err(err) => return Err(err),
+        };
+        if !document.scram.verify_password(password)? {
+            return Err(Error::AuthenticationFailed);
+        }
+        Ok(document.to_user())
+    }
+
+    /// Creates a random token to authenticate as the user `name` without the password,
+    /// e.g. `Authorization: Bearer <token>` of the HTTP server.
+    ///
+    /// Only the hash of the token is stored, the token can't be retrieved later.
+    pub fn create_token(&self, name: &str) -> Result<String> {
+        let mut document = self.find_document(name)?;
+        let token = random_bytes(TOKEN_SIZE).iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        document.tokens.push(hash_token(&token));
+        self.update_document(name, doc! { "$set": { "tokens": document.tokens } })?;
+        Ok(token)
+    }
+
+    /// Removes all the tokens of the user `name`, returns the number of the removed tokens.
+    pub fn revoke_tokens(&self, name: &str) -> Result<usize> {
+        let document = self.find_document(name)?;
+        self.update_document(name, doc! { "$set": { "tokens": [] } })?;
+        Ok(document.tokens.len())
+    }
+
+    /// Returns the user of a token created by [`Users::create_token`].
+    pub fn authenticate_token(&self, token: &str) -> Result<User> {
+        let hash = hash_token(token);
+        for document in self.collection().find(doc! {}).run()? {
+            let document = document?;
+            if document.tokens.iter().any(|t| constant_time_eq(t.as_bytes(), hash.as_bytes())) {
+                return Ok(document.to_user());
+            }
+        }
+        Err(Error::AuthenticationFailed)
+    }
+
+    /// Starts a SCRAM-SHA-256 conversation with the client-first message of the client.
+    /// Returns the conversation and the server-first message.
+    pub fn scram_start(&self, client_first: &[u8]) -> Result<(ScramConversation, Vec<u8>)> {
+        let client_first = std::str::from_utf8(client_first)?;
+        let (gs2_header, client_first_bare) = split_gs2_header(client_first)?;
+        let mut name = None;
+        let mut client_nonce = None;
+        for (key, value) in parse_attributes(client_first_bare)? {
+            match key {
+                'n' => name = Some(unescape_name(value)?),
+                'r' => client_nonce = Some(value),
+                'm' => return Err(Error::AuthenticationFailed),
+                _ => (),
+            }
+        }
+        let name = name.ok_or(Error::AuthenticationFailed)?;
+        let client_nonce = client_nonce.ok_or(Error::AuthenticationFailed)?;
+
+        let document = match self.find_document(&name) {
+            Ok(document) => document,
+            Err(Error::UserNotFound(_)) => return Err(Error::AuthenticationFailed),
+            Err(err) => return Err(err),
+        };
+        let nonce = format!("{}{}", client_nonce, BASE64.encode(random_bytes(NONCE_SIZE)));
+        let server_first = format!(
+            "r={},s={},i={}",
+            nonce,
+            document.scram.salt,
+            document.scram.iteration_count,
+        );
+        let conversation = ScramConversation {
+            user: document.to_user(),
+            credentials: document.scram,
+            gs2_header: gs2_header.to_string(),
+            nonce,
+            auth_message: format!("{},{}", client_first_bare, server_first),
+        };
+        Ok((conversation, server_first.into_bytes()))
+    }
+
+}
+
+/// A SCRAM-SHA-256 conversation started by [`Users::scram_start`].
+pub struct ScramConversation {
+    user: User,
+    credentials: ScramCredentials,
+    gs2_header: String,
+    nonce: String,
+    /// The client-first-bare and the server-first messages.
+    auth_message: String,
+}
+
+impl ScramConversation {
+
+    /// Checks the proof of the client-final message.
+    /// Returns the server-final message and the authenticated user.
+    pub fn finish(self, client_final: &[u8]) -> Result<(Vec<u8>, User)> {
+        let client_final = std::str::from_utf8(client_final)?;
+        let (without_proof, proof) = client_final
+            .rsplit_once(",p=")
+            .ok_or(Error::AuthenticationFailed)?;
+        let mut channel_binding = None;
+        let mut nonce = None;
+        for (key, value) in parse_attributes(without_proof)? {
+            match key {
+                'c' => channel_binding = Some(value),
+                'r' => nonce = Some(value),
+                _ => (),
+            }
+        }
+        if channel_binding != Some(BASE64.encode(&self.gs2_header).as_str()) || nonce != Some(self.nonce.as_str()) {
+            return Err(Error::AuthenticationFailed);
+        }
+
+        let auth_message = format!("{},{}", self.auth_message, without_proof);
+        let stored_key = decode_base64(&self.credentials.stored_key)?;
+        let client_signature = hmac(&stored_key, auth_message.as_bytes());
+        let proof = decode_base64(proof).map_err(|_| Error::AuthenticationFailed)?;
+        if proof.len() != client_signature.len() {
+            return Err(Error::AuthenticationFailed);
+        }
+        let client_key: Vec<u8> = proof.iter().zip(&client_signature).map(|(a, b)| a ^ b).collect();
+        if !constant_time_eq(&Sha256::digest(&client_key), &stored_key) {
+            return Err(Error::AuthenticationFailed);
+        }
+
+        let server_key = decode_base64(&self.credentials.server_key)?;
+        let server_signature = hmac(&server_key, auth_message.as_bytes());
+        let server_final = format!("v={}", BASE64.encode(server_signature));
+        Ok((server_final.into_bytes(), self.user))
+    }
+
+}
+
+/// Split the client-first message into the GS2 header and the bare message.
+/// The channel binding is not supported.
+fn split_gs2_header(message: &str) -> Result<(&str, &str)> {
+    if !(message.starts_with("n,") || message.starts_with("y,")) {
+        return Err(Error::AuthenticationFailed);
+    }
+    let end = message[2..].find(',').ok_or(Error::AuthenticationFailed)? + 3;
+    Ok((&message[..end], &message[end..]))
+}
+
+fn parse_attributes(message: &str) -> Result<Vec<(char, &str)>> {
+    message
+        .split(',')
+        .map(|attribute| {
+            let mut chars = attribute.chars();
+            match (chars.next(), chars.next()) {
+                (Some(key), Some('=')) => Ok((key, &attribute[2..])),
+                _ => Err(Error::AuthenticationFailed),
+            }
+        })
+        .collect()
+}
+
+fn unescape_name(name: &str) -> Result<String> {
+    let mut result = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(index) = rest.find('=') {
+        result.push_str(&rest[..index]);
+        match rest.get(index..index + 3) {
+            Some("=2C") => result.push(','),
+            Some("=3D") => result.push('='),
+            _ => return Err(Error::AuthenticationFailed),
+        }
+        rest = &rest[index + 3..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// The collections read by the `$lookup` stages of a pipeline,
+/// which need [`Action::Read`] besides the collection of the aggregation.
+pub fn lookup_collections(pipeline: &[Document]) -> Vec<String> {
+    let mut result = Vec::new();
+    for stage in pipeline {
+        if let Ok(lookup) = stage.get_document("$lookup") {
+            if let Ok(from) = lookup.get_str("from") {
+                result.push(from.to_string());
+            }
+            if let Ok(inner) = lookup.get_array("pipeline") {
+                let inner: Vec<Document> = inner.iter().filter_map(|s| s.as_document().cloned()).collect();
+                result.extend(lookup_collections(&inner));
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_role() {
+        let role: Role = "read@shop.orders".parse().unwrap();
+        assert_eq!(role, Role::read("shop").collection("orders"));
+        assert_eq!(role.to_string(), "read@shop.orders");
+        assert_eq!("readWrite@shop".parse::<Role>().unwrap(), Role::read_write("shop"));
+        assert_eq!("readWrite".parse::<Role>().unwrap(), Role::read_write_any_database());
+        assert_eq!("admin".parse::<Role>().unwrap(), Role::admin());
+        assert!("write@shop".parse::<Role>().is_err());
+        assert!("read@".parse::<Role>().is_err());
+    }
+
+    #[test]
+    fn test_is_allowed() {
+        let user = User {
+            name: "bob".to_string(),
+            roles: vec![Role::read("shop").collection("orders"), Role::read_write("blog")],
+        };
+        assert!(user.is_allowed(Action::Read, Some("shop"), Some("orders")));
+        assert!(!user.is_allowed(Action::Read, Some("shop"), Some("users")));
+        assert!(!user.is_allowed(Action::Read, Some("shop"), None));
+        assert!(!user.is_allowed(Action::Write, Some("shop"), Some("orders")));
+        assert!(user.is_allowed(Action::Write, Some("blog"), Some("posts")));
+        assert!(user.is_allowed(Action::Read, Some("blog"), None));
+        assert!(!user.is_allowed(Action::Admin, Some("blog"), None));
+
+        let user = User {
+            name: "carol".to_string(),
+            roles: vec![Role::read_write_any_database()],
+        };
+        assert!(user.is_allowed(Action::Write, None, Some("posts")));
+        assert!(!user.is_allowed(Action::Read, None, Some(USERS_COLLECTION)));
+        assert!(!user.is_allowed(Action::Read, Some("blog"), Some(USERS_COLLECTION)));
+    }
+
+    /// The example conversation of RFC 7677.
+    #[test]
+    fn test_scram_rfc_7677() {
+        let salt = BASE64.decode("W22ZaJ0SNY7soEsUEjb6gQ==").unwrap();
+        let salted_password = salt_password("pencil", &salt, 4096).unwrap();
+        let client_key = hmac(&salted_password, b"Client Key");
+        let credentials = ScramCredentials {
+            iteration_count: 4096,
+            salt: BASE64.encode(&salt),
+            stored_key: BASE64.encode(Sha256::digest(client_key)),
+            server_key: BASE64.encode(hmac(&salted_password, b"Server Key")),
+        };
+        let conversation = ScramConversation {
+            user: User { name: "user".to_string(), roles: vec![] },
+            credentials,
+            gs2_header: "n,,".to_string(),
+            nonce: "rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0".to_string(),
+            auth_message: "n=user,r=rOprNGfwEbeRWgbNEkqO,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096".to_string(),
+        };
+        let client_final = b"c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=";
+        let (server_final, user) = conversation.finish(client_final).unwrap();
+        assert_eq!(server_final, b"v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=".to_vec());
+        assert_eq!(user.name, "user");
+    }
+
+    #[test]
+    fn test_unescape_name() {
+        assert_eq!(unescape_name("a=2Cb=3Dc").unwrap(), "a,b=c");
+        assert!(unescape_name("a=b").is_err());
+    }
+
+}
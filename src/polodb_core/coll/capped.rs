@@ -0,0 +1,182 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The bookkeeping of the capped collections.
+//!
+//! The insertion order of a capped collection is kept in a keyspace
+//! of `[CAPPED_PREFIX, col_name, seq, pkey]`, the value is the size of the document.
+//! The keyspace is scanned like an index to return the documents in insertion order.
+//! The running count and size of the collection are kept in the stats key.
+
+use bson::Bson;
+use byteorder::{BigEndian, ByteOrder};
+use crate::coll::collection_info::CappedInfo;
+use crate::cursor::Cursor;
+use crate::options::CreateCollectionOptions;
+use crate::transaction::TransactionInner;
+use crate::{Error, Result};
+
+pub(crate) const CAPPED_PREFIX: &str = "$CAPPED";
+const CAPPED_STATS_PREFIX: &str = "$CAPPED_STATS";
+
+#[derive(Default)]
+struct CappedStats {
+    next_seq: u64,
+    count: u64,
+    size: u64,
+}
+
+impl CappedStats {
+
+    fn from_bytes(bytes: &[u8]) -> CappedStats {
+        CappedStats {
+            next_seq: BigEndian::read_u64(&bytes[0..8]),
+            count: BigEndian::read_u64(&bytes[8..16]),
+            size: BigEndian::read_u64(&bytes[16..24]),
+        }
+    }
+
+    fn to_bytes(&self) -> [u8; 24] {
+        let mut bytes = [0u8; 24];
+        BigEndian::write_u64(&mut bytes[0..8], self.next_seq);
+        BigEndian::write_u64(&mut bytes[8..16], self.count);
+        BigEndian::write_u64(&mut bytes[16..24], self.size);
+        bytes
+    }
+
+    fn exceeds(&self, info: &CappedInfo) -> bool {
+        info.max.map(|max| self.count > max).unwrap_or(false)
+            || info.size.map(|size| self.size > size).unwrap_or(false)
+    }
+
+}
+
+/// Validate the options and return the limits of the collection,
+/// `None` if the collection is not capped.
+pub(crate) fn capped_info_from_options(options: &CreateCollectionOptions) -> Result<Option<CappedInfo>> {
+    if !options.is_capped() {
+        if options.size.is_some() || options.max.is_some() {
+            return Err(Error::ValidationError("size and max are only supported by the capped collections".to_string()));
+        }
+        return Ok(None);
+    }
+    if options.size.is_none() && options.max.is_none() {
+        return Err(Error::ValidationError("a capped collection requires size or max".to_string()));
+    }
+    if options.size == Some(0) || options.max == Some(0) {
+        return Err(Error::ValidationError("size and max of a capped collection must be positive".to_string()));
+    }
+    Ok(Some(CappedInfo {
+        size: options.size,
+        max: options.max,
+    }))
+}
+
+/// The prefix of the insertion order of the collection.
+pub(crate) fn order_prefix(col_name: &str) -> Result<Vec<u8>> {
+    crate::utils::bson::stacked_key(&[
+        Bson::String(CAPPED_PREFIX.to_string()),
+        Bson::String(col_name.to_string()),
+    ])
+}
+
+fn stats_key(col_name: &str) -> Result<Vec<u8>> {
+    crate::utils::bson::stacked_key(&[
+        Bson::String(CAPPED_STATS_PREFIX.to_string()),
+        Bson::String(col_name.to_string()),
+    ])
+}
+
+/// Record a document of `size` bytes inserted into the collection.
+/// Return the primary keys of the documents to evict, the oldest first.
+pub(crate) fn record_insert(
+    txn: &TransactionInner,
+    col_name: &str,
+    info: &CappedInfo,
+    pkey: &Bson,
+    size: u64,
+) -> Result<Vec<Bson>> {
+    if let Some(limit) = info.size {
+        if size > limit {
+            return Err(Error::DocumentTooLarge { size, limit });
+        }
+    }
+
+    let stats_key = stats_key(col_name)?;
+    let mut stats = txn.rocksdb_txn
+        .get_for_update(&stats_key)?
+        .map(|bytes| CappedStats::from_bytes(&bytes))
+        .unwrap_or_default();
+
+    let order_key = {
+        let mut key = order_prefix(col_name)?;
+        crate::utils::bson::stacked_key_bytes(&mut key, &Bson::Int64(stats.next_seq as i64))?;
+        crate::utils::bson::stacked_key_bytes(&mut key, pkey)?;
+        key
+    };
+    txn.put(&order_key, &size.to_be_bytes())?;
+
+    stats.next_seq += 1;
+    stats.count += 1;
+    stats.size += size;
+
+    let mut evicted = Vec::new();
+    if stats.exceeds(info) {
+        let mut evicted_keys = Vec::new();
+        let mut cursor = Cursor::new(order_prefix(col_name)?, txn.rocksdb_txn.new_iterator());
+        cursor.reset()?;
+
+        // the new document fits the collection, so it's never evicted
+        while stats.exceeds(info) && cursor.has_next() {
+            let key = cursor.peek_key().expect("key must exist");
+            let value = cursor.copy_data()?;
+
+            let slices = crate::utils::bson::split_stacked_keys(key.as_ref())?;
+            evicted.push(slices.last().expect("pkey must exist").clone());
+
+            stats.count -= 1;
+            stats.size -= BigEndian::read_u64(&value);
+
+            evicted_keys.push(key);
+            cursor.next()?;
+        }
+
+        for key in evicted_keys {
+            txn.delete(key.as_ref())?;
+        }
+    }
+
+    txn.put(&stats_key, &stats.to_bytes())?;
+
+    Ok(evicted)
+}
+
+/// Remove the insertion order and the stats of a dropped collection.
+pub(crate) fn remove_all(txn: &TransactionInner, col_name: &str) -> Result<()> {
+    let mut keys = Vec::new();
+    let mut cursor = Cursor::new(order_prefix(col_name)?, txn.rocksdb_txn.new_iterator());
+    cursor.reset()?;
+    while cursor.has_next() {
+        keys.push(cursor.peek_key().expect("key must exist"));
+        cursor.next()?;
+    }
+
+    for key in keys {
+        txn.delete(key.as_ref())?;
+    }
+    txn.delete(&stats_key(col_name)?)?;
+
+    Ok(())
+}
@@ -13,15 +13,24 @@
 // limitations under the License.
 
 use serde::Serialize;
-use bson::Document;
+use bson::{doc, Bson, Document};
 use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::ops::RangeBounds;
 use std::sync::Weak;
+use std::time::{Duration, Instant};
 use serde::de::DeserializeOwned;
-use crate::options::UpdateOptions;
-use crate::{Error, IndexModel, Result};
+use uuid::Uuid;
+use crate::options::{MapReduceOptions, TransactionOptions, UpdateOptions};
+use crate::{ClientCursor, DbRef, Error, IndexModel, Result, WalSyncPolicy};
 use crate::db::db_inner::DatabaseInner;
-use crate::action::{Aggregate, Find};
-use crate::results::{DeleteResult, InsertManyResult, InsertOneResult, UpdateResult};
+use crate::transaction::TransactionInner;
+use crate::action::{Aggregate, Distinct, Find};
+use crate::coll::map_reduce::{self, MapReduceCursor, MapReduceEmitter};
+use crate::results::{CollectionStats, DeleteResult, IndexStats, DropResult, InsertManyResult, InsertOneResult, UpdateResult};
+
+// the interval of retrying the writes stalled by the flushes and the compactions
+const STALL_RETRY_INTERVAL: Duration = Duration::from_millis(10);
 
 macro_rules! try_multiple {
     ($err: expr, $action: expr) => {
@@ -55,6 +64,19 @@ pub trait CollectionT<T> {
     /// Return the size of all data in the collection.
     fn count_documents(&self) -> Result<u64>;
 
+    /// Return the storage statistics of the collection.
+    /// All the documents and index entries are scanned.
+    fn stats(&self) -> Result<CollectionStats>;
+
+    /// Refresh the statistics of the values of each index, see [`IndexStats`].
+    ///
+    /// The planner uses them to choose the index matching the fewest documents
+    /// when several indexes match a query. The entries of the indexes are scanned,
+    /// and the statistics are also refreshed by [`crate::Database::vacuum`].
+    /// The centroids of the vector indexes are retrained, and their vectors are moved to
+    /// the lists of the new centroids.
+    fn analyze(&self) -> Result<HashMap<String, IndexStats>>;
+
     /// Updates up to one document matching `query` in the collection.
     /// [documentation](https://www.polodb.org/docs/curd/update) for more information on specifying updates.
     fn update_one(&self, query: Document, update: Document) -> Result<UpdateResult>;
@@ -78,7 +100,23 @@ pub trait CollectionT<T> {
 
     /// Drops the index specified by `name` from this collection.
     fn drop_index(&self, name: impl AsRef<str>) -> Result<()>;
-    fn drop(&self) -> Result<()>;
+
+    /// Drops the collection with its documents, index entries and metadata in one transaction.
+    fn drop(&self) -> Result<DropResult>;
+
+    /// Drops the documents of the `partition` of a partitioned collection,
+    /// the other partitions are not scanned.
+    /// See [`crate::options::CreateCollectionOptions::partition_key`].
+    ///
+    /// The documents are removed by a range deletion without reading them, so the cost
+    /// doesn't depend on the size of the partition. The `deleted_count` of the result
+    /// is 0 and the `reclaimable_bytes` is estimated from the table files. The drop
+    /// is written at once, so it fails in a [`crate::Transaction`], and it has no
+    /// change events.
+    fn drop_partition(&self, partition: impl Into<Bson>) -> Result<DropResult>;
+
+    /// The partitions of a partitioned collection which have documents, in the order of the keys.
+    fn list_partitions(&self) -> Result<Vec<Bson>>;
 
     /// Inserts `doc` into the collection.
     fn insert_one(&self, doc: impl Borrow<T>) -> Result<InsertOneResult>
@@ -97,8 +135,112 @@ pub trait CollectionT<T> {
     fn find_one(&self, filter: Document) -> Result<Option<T>>
     where T: DeserializeOwned + Send + Sync;
 
+    /// Finds the documents whose `_id` is in `range` in the order of the `_id`s,
+    /// with a bounded scan of the primary keys instead of a filter.
+    ///
+    /// The `_id`s are compared by their stored keys: the bounds must have the same type
+    /// and can't be negative numbers. An unbounded side takes in the `_id`s of the other types.
+    ///
+    /// ```rust
+    /// use polodb_core::{CollectionT, Database};
+    /// use polodb_core::bson::{doc, Bson, Document};
+    ///
+    /// let db = Database::open_memory().unwrap();
+    /// let collection = db.collection::<Document>("events");
+    /// collection.insert_many((0..10).map(|i| doc! { "_id": i })).unwrap();
+    ///
+    /// let events = collection.find_range(Bson::Int32(3)..Bson::Int32(6)).unwrap()
+    ///     .collect::<polodb_core::Result<Vec<Document>>>()
+    ///     .unwrap();
+    /// assert_eq!(events.len(), 3);
+    /// ```
+    fn find_range(&self, range: impl RangeBounds<Bson>) -> Result<ClientCursor<T>>
+    where T: DeserializeOwned + Send + Sync;
+
+    /// Whether a document matches `filter`, without reading the other matching documents.
+    ///
+    /// A filter of a single `_id` equality only looks up the key, the document is not read.
+    fn exists(&self, filter: Document) -> Result<bool>;
+
+    /// Finds the documents of `ids` with one batched lookup of the primary keys,
+    /// the result has the order of `ids` and `None` for the missing documents.
+    fn find_many_by_ids(&self, ids: &[Bson]) -> Result<Vec<Option<T>>>
+    where T: DeserializeOwned + Send + Sync;
+
+    /// Inserts `doc` with the binary UUID (subtype 4) `id` as its `_id`,
+    /// replacing the `_id` of `doc` if any.
+    ///
+    /// To keep the field binary in a struct, serialize it with
+    /// [`bson::serde_helpers::uuid_1_as_binary`].
+    fn insert_one_with_uuid(&self, id: Uuid, doc: impl Borrow<T>) -> Result<InsertOneResult>
+    where T: Serialize;
+
+    /// Finds the document whose `_id` is the binary UUID `id`, with a primary key lookup.
+    fn find_by_uuid(&self, id: Uuid) -> Result<Option<T>>
+    where T: DeserializeOwned + Send + Sync;
+
+    /// Deletes the document whose `_id` is the binary UUID `id`.
+    fn delete_by_uuid(&self, id: Uuid) -> Result<DeleteResult>;
+
+    /// Finds the document referenced by `db_ref`.
+    ///
+    /// Without `$db`, the referenced collection is looked up in the database of this collection.
+    fn resolve_ref(&self, db_ref: &DbRef) -> Result<Option<Document>>;
+
     /// Runs an aggregation operation.
     fn aggregate(&self, pipeline: impl IntoIterator<Item = Document>) -> Aggregate<'_, '_>;
+
+    /// Returns the distinct values of `field` in the documents matching `filter`.
+    /// The documents without the field count as `null`.
+    fn distinct(&self, field: &str, filter: Document) -> Distinct<'_, '_> {
+        Distinct::new(self.aggregate(Distinct::pipeline(field, filter)))
+    }
+
+    /// Calls `map_fn` on each document matching the filter of `options`,
+    /// groups the emitted values by key, and reduces the values of each key with `reduce_fn`.
+    ///
+    /// The groups are reduced and written to temporary files when the emitted values
+    /// exceed the memory limit, so `reduce_fn` must accept its own results among the values.
+    /// It's not called for the keys with a single value.
+    ///
+    /// ```rust
+    /// use polodb_core::{Database, CollectionT};
+    /// use polodb_core::bson::{doc, Bson, Document};
+    /// use polodb_core::options::MapReduceOptions;
+    /// # let db_path = polodb_core::test_utils::mk_db_path("doc-test-map-reduce");
+    /// let db = Database::open_path(&db_path).unwrap();
+    /// let col = db.collection::<Document>("orders");
+    /// col.insert_many(vec![
+    ///     doc! { "customer": "alice", "amount": 10 },
+    ///     doc! { "customer": "bob", "amount": 5 },
+    ///     doc! { "customer": "alice", "amount": 7 },
+    /// ]).unwrap();
+    ///
+    /// let totals = col.map_reduce(
+    ///     |doc: Document, emitter| {
+    ///         emitter.emit(doc.get_str("customer").unwrap(), doc.get_i32("amount").unwrap());
+    ///     },
+    ///     |_key, values| {
+    ///         Bson::Int32(values.iter().map(|v| v.as_i32().unwrap()).sum())
+    ///     },
+    ///     MapReduceOptions::default(),
+    /// ).unwrap().collect::<polodb_core::Result<Vec<Document>>>().unwrap();
+    ///
+    /// assert_eq!(totals, vec![
+    ///     doc! { "_id": "alice", "value": 17 },
+    ///     doc! { "_id": "bob", "value": 5 },
+    /// ]);
+    /// ```
+    fn map_reduce<'a, M, R>(&self, map_fn: M, reduce_fn: R, options: MapReduceOptions) -> Result<MapReduceCursor<'a>>
+    where
+        T: DeserializeOwned + Send + Sync,
+        M: FnMut(T, &mut MapReduceEmitter),
+        R: FnMut(&Bson, Vec<Bson>) -> Bson + 'a,
+    {
+        let filter = options.filter.clone().unwrap_or_default();
+        let cursor = self.find(filter).run()?;
+        map_reduce::map_reduce(cursor, map_fn, reduce_fn, options)
+    }
 }
 
 
@@ -110,6 +252,8 @@ pub trait CollectionT<T> {
 pub struct Collection<T> {
     db: Weak<DatabaseInner>,
     name: String,
+    /// Overrides the [`WalSyncPolicy`] of the database for the writes of this handle.
+    sync_policy: Option<WalSyncPolicy>,
     _phantom: std::marker::PhantomData<T>,
 }
 
@@ -119,9 +263,103 @@ impl<T>  Collection<T>
         Collection {
             db,
             name: name.into(),
+            sync_policy: None,
             _phantom: std::default::Default::default(),
         }
     }
+
+    /// Write with `policy` instead of the [`WalSyncPolicy`] of the database,
+    /// e.g. sync the changes of the accounts on every commit while the telemetry
+    /// of the same database is only buffered.
+    ///
+    /// Only the writes of the returned handle are affected, the writes in a
+    /// [`crate::Transaction`] follow [`TransactionOptions::sync_policy`].
+    /// As for a transaction, [`WalSyncPolicy::Interval`] is only accepted if it's the policy
+    /// of the database, the writes with the other intervals fail with [`Error::ValidationError`].
+    ///
+    /// ```rust
+    /// use polodb_core::{Database, CollectionT, WalSyncPolicy};
+    /// use polodb_core::bson::{doc, Document};
+    /// # let db_path = polodb_core::test_utils::mk_db_path("doc-test-sync-policy");
+    /// let db = Database::open_path(&db_path).unwrap();
+    /// let telemetry = db.collection::<Document>("telemetry")
+    ///     .with_sync_policy(WalSyncPolicy::OsBuffered);
+    /// telemetry.insert_one(doc! { "cpu": 0.5 }).unwrap();
+    /// ```
+    pub fn with_sync_policy(mut self, policy: WalSyncPolicy) -> Collection<T> {
+        self.sync_policy = Some(policy);
+        self
+    }
+
+    /// Inserts `doc` without waiting for the stalled writes.
+    ///
+    /// Fails with [`Error::WouldBlock`] when the flushes or the compactions are
+    /// behind the writes, the caller can retry later instead of piling up the writes.
+    pub fn try_insert_one(&self, doc: impl Borrow<T>) -> Result<InsertOneResult>
+    where T: Serialize {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.check_quota()?;
+        let txn = db.start_transaction_with_options(&self.write_options(true))?;
+        let result = try_db_op!(txn, db.insert_one(
+            &self.name,
+            bson::to_document(doc.borrow())?,
+            &txn,
+        ));
+        Ok(result)
+    }
+
+    /// Inserts `docs` without waiting for the stalled writes, see [`Collection::try_insert_one`].
+    pub fn try_insert_many(&self, docs: impl IntoIterator<Item = impl Borrow<T>>) -> Result<InsertManyResult>
+    where T: Serialize {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.check_quota()?;
+        let txn = db.start_transaction_with_options(&self.write_options(true))?;
+        let result = try_db_op!(txn, db.insert_many(&self.name, docs, &txn));
+        Ok(result)
+    }
+
+    fn write_options(&self, no_slowdown: bool) -> TransactionOptions {
+        TransactionOptions {
+            sync_policy: self.sync_policy,
+            no_slowdown: Some(no_slowdown),
+        }
+    }
+
+    fn start_write_transaction(&self, db: &DatabaseInner) -> Result<TransactionInner> {
+        db.start_transaction_with_options(&self.write_options(false))
+    }
+
+    /// Run the update in the transactions which don't wait for the stalled writes,
+    /// and retry it until the writes are resumed or `max_time` is exceeded.
+    fn update_with_max_time<F>(&self, max_time: Duration, options: UpdateOptions, update: F) -> Result<UpdateResult>
+    where F: Fn(&TransactionInner, UpdateOptions) -> Result<UpdateResult> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let deadline = Instant::now() + max_time;
+        loop {
+            let options = UpdateOptions {
+                max_time: Some(deadline.saturating_duration_since(Instant::now())),
+                ..options.clone()
+            };
+            let txn = db.start_transaction_with_options(&self.write_options(true))?;
+            let result = match update(&txn, options) {
+                Ok(result) => txn.commit().map(|_| result),
+                Err(err) => {
+                    try_multiple!(err, txn.rollback());
+                    Err(err)
+                }
+            };
+            match result {
+                Err(Error::WouldBlock) if Instant::now() < deadline => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    std::thread::sleep(remaining.min(STALL_RETRY_INTERVAL));
+                }
+                Err(Error::WouldBlock) | Err(Error::MaxTimeExceeded(_)) => {
+                    return Err(Error::MaxTimeExceeded(max_time));
+                }
+                result => return result,
+            }
+        }
+    }
 }
 
 impl<T> CollectionT<T> for Collection<T> {
@@ -137,9 +375,23 @@ impl<T> CollectionT<T> for Collection<T> {
         Ok(count)
     }
 
-    fn update_one(&self, query: Document, update: Document) -> Result<UpdateResult> {
+    fn stats(&self) -> Result<CollectionStats> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let txn = db.start_transaction()?;
+        db.collection_stats(&self.name, &txn)
+    }
+
+    fn analyze(&self) -> Result<HashMap<String, IndexStats>> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
         let txn = db.start_transaction()?;
+        let result = try_db_op!(txn, db.analyze_collection(&self.name, &txn));
+        Ok(result)
+    }
+
+    fn update_one(&self, query: Document, update: Document) -> Result<UpdateResult> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.check_quota()?;
+        let txn = self.start_write_transaction(&db)?;
         let result = try_db_op!(txn, db.update_one(
             &self.name,
             query,
@@ -152,7 +404,13 @@ impl<T> CollectionT<T> for Collection<T> {
 
     fn update_one_with_options(&self, query: Document, update: Document, options: UpdateOptions) -> Result<UpdateResult> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
-        let txn = db.start_transaction()?;
+        db.check_quota()?;
+        if let Some(max_time) = options.max_time {
+            return self.update_with_max_time(max_time, options, |txn, options| {
+                db.update_one(&self.name, query.clone(), update.clone(), options, txn)
+            });
+        }
+        let txn = self.start_write_transaction(&db)?;
         let result = try_db_op!(txn, db.update_one(
             &self.name,
             query,
@@ -165,7 +423,8 @@ impl<T> CollectionT<T> for Collection<T> {
 
     fn update_many(&self, query: Document, update: Document) -> Result<UpdateResult> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
-        let txn = db.start_transaction()?;
+        db.check_quota()?;
+        let txn = self.start_write_transaction(&db)?;
         let result = try_db_op!(txn, db.update_many(
             &self.name,
             query,
@@ -178,7 +437,13 @@ impl<T> CollectionT<T> for Collection<T> {
 
     fn update_many_with_options(&self, query: Document, update: Document, options: UpdateOptions) -> Result<UpdateResult> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
-        let txn = db.start_transaction()?;
+        db.check_quota()?;
+        if let Some(max_time) = options.max_time {
+            return self.update_with_max_time(max_time, options, |txn, options| {
+                db.update_many(&self.name, query.clone(), update.clone(), options, txn)
+            });
+        }
+        let txn = self.start_write_transaction(&db)?;
         let result = try_db_op!(txn, db.update_many(
             &self.name,
             query,
@@ -191,43 +456,58 @@ impl<T> CollectionT<T> for Collection<T> {
 
     fn delete_one(&self, query: Document) -> Result<DeleteResult> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
-        let txn = db.start_transaction()?;
+        let txn = self.start_write_transaction(&db)?;
         let result = try_db_op!(txn, db.delete_one(&self.name, query, &txn));
         Ok(result)
     }
 
     fn delete_many(&self, query: Document) -> Result<DeleteResult> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
-        let txn = db.start_transaction()?;
+        let txn = self.start_write_transaction(&db)?;
         let result = try_db_op!(txn, db.delete_many(&self.name, query, &txn));
         Ok(result)
     }
 
     fn create_index(&self, index: IndexModel) -> Result<()> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
-        let txn = db.start_transaction()?;
+        db.check_quota()?;
+        let txn = self.start_write_transaction(&db)?;
         try_db_op!(txn, db.create_index(&self.name, index, &txn));
         Ok(())
     }
 
     fn drop_index(&self, name: impl AsRef<str>) -> Result<()> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
-        let txn = db.start_transaction()?;
+        let txn = self.start_write_transaction(&db)?;
         try_db_op!(txn, db.drop_index(&self.name, name.as_ref(), &txn));
         Ok(())
     }
 
-    fn drop(&self) -> Result<()> {
+    fn drop(&self) -> Result<DropResult> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let txn = self.start_write_transaction(&db)?;
+        let result = try_db_op!(txn, db.drop_collection(&self.name, &txn));
+        Ok(result)
+    }
+
+    fn drop_partition(&self, partition: impl Into<Bson>) -> Result<DropResult> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let txn = self.start_write_transaction(&db)?;
+        let result = try_db_op!(txn, db.drop_partition(&self.name, &partition.into(), &txn));
+        Ok(result)
+    }
+
+    fn list_partitions(&self) -> Result<Vec<Bson>> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
         let txn = db.start_transaction()?;
-        try_db_op!(txn, db.drop_collection(&self.name, &txn));
-        Ok(())
+        db.list_partitions(&self.name, &txn)
     }
 
     fn insert_one(&self, doc: impl Borrow<T>) -> Result<InsertOneResult>
     where T: Serialize {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
-        let txn = db.start_transaction()?;
+        db.check_quota()?;
+        let txn = self.start_write_transaction(&db)?;
         let result = try_db_op!(txn, db.insert_one(
             &self.name,
             bson::to_document(doc.borrow())?,
@@ -239,7 +519,8 @@ impl<T> CollectionT<T> for Collection<T> {
     fn insert_many(&self, docs: impl IntoIterator<Item = impl Borrow<T>>) -> Result<InsertManyResult>
     where T: Serialize {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
-        let txn = db.start_transaction()?;
+        db.check_quota()?;
+        let txn = self.start_write_transaction(&db)?;
         let result = try_db_op!(txn, db.insert_many(&self.name, docs, &txn));
         Ok(result)
     }
@@ -259,6 +540,55 @@ impl<T> CollectionT<T> for Collection<T> {
         Ok(Some(cursor.deserialize_current()?))
     }
 
+    fn find_range(&self, range: impl RangeBounds<Bson>) -> Result<ClientCursor<T>>
+    where T: DeserializeOwned + Send + Sync {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let txn = db.start_transaction()?;
+        db.find_range_with_owned_session(&self.name, range.start_bound(), range.end_bound(), txn)
+    }
+
+    fn exists(&self, filter: Document) -> Result<bool> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let txn = db.start_transaction()?;
+        db.exists(&self.name, filter, &txn)
+    }
+
+    fn find_many_by_ids(&self, ids: &[Bson]) -> Result<Vec<Option<T>>>
+    where T: DeserializeOwned + Send + Sync {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let txn = db.start_transaction()?;
+        let docs = db.find_many_by_ids(&self.name, ids, &txn)?;
+        docs.into_iter()
+            .map(|doc| Ok(doc.map(bson::from_document).transpose()?))
+            .collect()
+    }
+
+    fn insert_one_with_uuid(&self, id: Uuid, doc: impl Borrow<T>) -> Result<InsertOneResult>
+    where T: Serialize {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.check_quota()?;
+        let mut doc = bson::to_document(doc.borrow())?;
+        doc.insert("_id", bson::Uuid::from(id));
+        let txn = self.start_write_transaction(&db)?;
+        let result = try_db_op!(txn, db.insert_one(&self.name, doc, &txn));
+        Ok(result)
+    }
+
+    fn find_by_uuid(&self, id: Uuid) -> Result<Option<T>>
+    where T: DeserializeOwned + Send + Sync {
+        self.find_one(doc! { "_id": bson::Uuid::from(id) })
+    }
+
+    fn delete_by_uuid(&self, id: Uuid) -> Result<DeleteResult> {
+        self.delete_one(doc! { "_id": bson::Uuid::from(id) })
+    }
+
+    fn resolve_ref(&self, db_ref: &DbRef) -> Result<Option<Document>> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let txn = db.start_transaction()?;
+        db.resolve_ref(&self.name, db_ref, &txn)
+    }
+
     fn aggregate(&self, pipeline: impl IntoIterator<Item = Document>) -> Aggregate<'_, '_> {
         Aggregate::new(
             self.db.clone(),
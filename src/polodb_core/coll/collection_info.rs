@@ -12,12 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bson::{Binary, DateTime};
+use bson::{Binary, DateTime, Document};
 use bson::spec::BinarySubtype;
 use serde::{Deserialize, Serialize};
 use indexmap::IndexMap;
 use uuid::Uuid;
 use crate::IndexOptions;
+use crate::results::IndexStats;
+use crate::options::{CreateCollectionOptions, IdStrategy, TimeseriesOptions, ValidationAction};
 use crate::utils::bson::bson_datetime_now;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -28,6 +30,64 @@ pub struct IndexInfo {
     pub keys: IndexMap<String, i8>,
 
     pub options: Option<IndexOptions>,
+
+    /// The statistics of the values, `None` until the collection is analyzed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<IndexStats>,
+
+    #[serde(default, skip_serializing_if = "IndexKind::is_ascending")]
+    pub kind: IndexKind,
+
+    /// The centroids of the lists of a vector index, empty until they are trained.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub centroids: Vec<Vec<f32>>,
+}
+
+/// How the values of the indexed field are turned into the entries of an index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IndexKind {
+    /// An entry of the value, ordered ascending.
+    #[default]
+    Ascending,
+    /// An entry of each term of the string, split by the analyzer of the options.
+    Text,
+    /// An entry of each trigram of the lower-cased string, for `$contains` and `$fuzzy`.
+    Trigram,
+    /// An entry of the vector of numbers, in the list of its nearest centroid, for `$vectorSearch`.
+    Vector,
+    /// An entry of the GeoJSON point, in the cell of its geohash, for `$geoNear`.
+    Geo2dSphere,
+}
+
+impl IndexKind {
+
+    #[inline]
+    pub fn is_ascending(&self) -> bool {
+        *self == IndexKind::Ascending
+    }
+
+    /// The key of the index field in the specification, e.g. `{ "body": "text" }`.
+    pub fn spec_name(&self) -> Option<&'static str> {
+        match self {
+            IndexKind::Ascending => None,
+            IndexKind::Text => Some("text"),
+            IndexKind::Trigram => Some("trigram"),
+            IndexKind::Vector => Some("vector"),
+            IndexKind::Geo2dSphere => Some("2dsphere"),
+        }
+    }
+
+    pub fn from_spec_name(name: &str) -> Option<IndexKind> {
+        match name {
+            "text" => Some(IndexKind::Text),
+            "trigram" => Some(IndexKind::Trigram),
+            "vector" => Some(IndexKind::Vector),
+            "2dsphere" => Some(IndexKind::Geo2dSphere),
+            _ => None,
+        }
+    }
+
 }
 
 impl IndexInfo {
@@ -38,9 +98,23 @@ impl IndexInfo {
         IndexInfo {
             keys,
             options,
+            stats: None,
+            kind: IndexKind::Ascending,
+            centroids: Vec::new(),
         }
     }
 
+    pub fn with_kind(name: String, kind: IndexKind, options: Option<IndexOptions>) -> IndexInfo {
+        let mut index = IndexInfo::single_index(name, 1, options);
+        index.kind = kind;
+        index
+    }
+
+    #[inline]
+    pub fn is_text(&self) -> bool {
+        self.kind == IndexKind::Text
+    }
+
     #[inline]
     pub fn is_unique(&self) -> bool {
         self.options
@@ -64,7 +138,7 @@ pub struct CollectionSpecificationInfo {
 
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CollectionSpecification {
     /// The name of the collection.
@@ -82,6 +156,75 @@ pub struct CollectionSpecification {
     /// The name is converted to the underline format.
     /// For examples, `author.age` is converted to `author_age`
     pub indexes: IndexMap<String, IndexInfo>,
+
+    /// The limits of a capped collection, `None` for the normal collections.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capped: Option<CappedInfo>,
+
+    /// The options of a time-series collection.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeseries: Option<TimeseriesOptions>,
+
+    /// The definition of a view.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub view: Option<ViewInfo>,
+
+    /// The name used in the keys of the documents and the indexes.
+    /// It's kept when the collection is renamed, `None` if it's the name of the collection.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_name: Option<String>,
+
+    /// The validator of the inserted and updated documents.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validator: Option<ValidatorInfo>,
+
+    /// The default and computed fields of the inserted documents.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub defaults: Option<Document>,
+
+    /// How the `_id` of the inserted documents is generated, `None` for [`IdStrategy::ObjectId`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_strategy: Option<IdStrategy>,
+
+    /// The field partitioning the documents, `None` if the collection isn't partitioned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partition_key: Option<String>,
+
+    /// The name of the ordering of the `_id`s, `None` for the order of the values.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_ordering: Option<String>,
+}
+
+/// The validator of a collection.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidatorInfo {
+    pub validator: Document,
+
+    pub validation_action: ValidationAction,
+}
+
+/// A view is a read-only collection which returns
+/// the result of the pipeline on the source collection.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewInfo {
+    /// The name of the source collection or view.
+    pub view_on: String,
+
+    pub pipeline: Vec<Document>,
+}
+
+/// The limits of a capped collection.
+/// The oldest documents are removed when any of the limits is exceeded.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CappedInfo {
+    /// The max size in bytes of the documents.
+    pub size: Option<u64>,
+
+    /// The max count of the documents.
+    pub max: Option<u64>,
 }
 
 impl CollectionSpecification {
@@ -91,6 +234,11 @@ impl CollectionSpecification {
         self._id.as_str()
     }
 
+    #[inline]
+    pub fn storage_name(&self) -> &str {
+        self.storage_name.as_deref().unwrap_or(self.name())
+    }
+
     #[inline]
     pub(crate) fn new(id: String, uuid: Uuid) -> CollectionSpecification {
         CollectionSpecification {
@@ -106,6 +254,53 @@ impl CollectionSpecification {
             },
 
             indexes: IndexMap::new(),
+            capped: None,
+            timeseries: None,
+            view: None,
+            storage_name: None,
+            validator: None,
+            defaults: None,
+            id_strategy: None,
+            partition_key: None,
+            key_ordering: None,
+        }
+    }
+
+    #[inline]
+    pub fn is_capped(&self) -> bool {
+        self.capped.is_some()
+    }
+
+    #[inline]
+    pub fn is_timeseries(&self) -> bool {
+        self.timeseries.is_some()
+    }
+
+    #[inline]
+    pub fn is_view(&self) -> bool {
+        self.view.is_some()
+    }
+
+    #[inline]
+    pub fn is_partitioned(&self) -> bool {
+        self.partition_key.is_some()
+    }
+
+    /// The options to create the same collection.
+    pub(crate) fn options(&self) -> CreateCollectionOptions {
+        CreateCollectionOptions {
+            capped: self.capped.map(|_| true),
+            size: self.capped.and_then(|capped| capped.size),
+            max: self.capped.and_then(|capped| capped.max),
+            timeseries: self.timeseries.clone(),
+            view_on: self.view.as_ref().map(|view| view.view_on.clone()),
+            pipeline: self.view.as_ref().map(|view| view.pipeline.clone()),
+            validator: self.validator.as_ref().map(|info| info.validator.clone()),
+            validation_action: self.validator.as_ref().map(|info| info.validation_action),
+            defaults: self.defaults.clone(),
+            id_strategy: self.id_strategy.clone(),
+            partition_key: self.partition_key.clone(),
+            key_ordering: self.key_ordering.clone(),
         }
     }
 
@@ -121,6 +316,9 @@ pub enum CollectionType {
 
     /// Indicates that the data store is a collection.
     Collection,
+
+    /// Indicates that the data store is a time-series collection.
+    Timeseries,
 }
 
 #[cfg(test)]
@@ -0,0 +1,192 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use std::sync::Weak;
+use bson::{Bson, Document};
+use serde::{Deserialize, Serialize};
+use crate::db::db_inner::DatabaseInner;
+use crate::transaction::TransactionInner;
+use crate::{Error, Result};
+
+/// A reference to a document of another collection, stored as
+/// `{ "$ref": <collection>, "$id": <_id>, "$db": <database> }`
+/// like the DBRefs of MongoDB.
+///
+/// `$db` is the name of a named database, see [`crate::Database::database`].
+/// When it's absent, the reference points into the database of the referencing collection.
+///
+/// ```rust
+/// use polodb_core::{Database, CollectionT, DbRef};
+/// use polodb_core::bson::{Document, doc};
+///
+/// let db = Database::open_memory().unwrap();
+/// db.collection::<Document>("authors").insert_one(doc! { "_id": 1, "name": "Liu Cixin" }).unwrap();
+///
+/// let books = db.collection::<Document>("books");
+/// books.insert_one(doc! {
+///     "title": "The Three-Body Problem",
+///     "author": DbRef::new("authors", 1),
+/// }).unwrap();
+///
+/// let book = books.find_one(doc! {}).unwrap().unwrap();
+/// let author_ref = DbRef::from_document(book.get_document("author").unwrap()).unwrap();
+/// let author = books.resolve_ref(&author_ref).unwrap().unwrap();
+/// assert_eq!(author.get_str("name").unwrap(), "Liu Cixin");
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DbRef {
+    #[serde(rename = "$ref")]
+    pub collection: String,
+    #[serde(rename = "$id")]
+    pub id: Bson,
+    #[serde(rename = "$db", default, skip_serializing_if = "Option::is_none")]
+    pub database: Option<String>,
+}
+
+impl DbRef {
+
+    pub fn new<C: Into<String>, I: Into<Bson>>(collection: C, id: I) -> DbRef {
+        DbRef {
+            collection: collection.into(),
+            id: id.into(),
+            database: None,
+        }
+    }
+
+    pub fn with_database<D: Into<String>>(mut self, database: D) -> Self {
+        self.database = Some(database.into());
+        self
+    }
+
+    /// Return the reference stored in `doc`, `None` if `doc` is not a DBRef.
+    /// The extra fields of the document are ignored.
+    pub fn from_document(doc: &Document) -> Option<DbRef> {
+        let collection = match doc.get("$ref") {
+            Some(Bson::String(collection)) => collection.clone(),
+            _ => return None,
+        };
+        let id = doc.get("$id")?.clone();
+        let database = match doc.get("$db") {
+            Some(Bson::String(database)) => Some(database.clone()),
+            None => None,
+            Some(_) => return None,
+        };
+        Some(DbRef {
+            collection,
+            id,
+            database,
+        })
+    }
+
+    pub fn to_document(&self) -> Document {
+        let mut doc = Document::new();
+        doc.insert("$ref", self.collection.clone());
+        doc.insert("$id", self.id.clone());
+        if let Some(database) = &self.database {
+            doc.insert("$db", database.clone());
+        }
+        doc
+    }
+
+}
+
+impl From<DbRef> for Bson {
+
+    fn from(db_ref: DbRef) -> Self {
+        Bson::Document(db_ref.to_document())
+    }
+
+}
+
+/// Replace the DBRefs of the documents returned by a cursor
+/// with the referenced documents, see [`crate::action::Find::resolve_refs`].
+pub(crate) struct RefResolver {
+    db: Weak<DatabaseInner>,
+    col_name: String,
+    depth: u32,
+    // the transaction of the query, a new one is started for every document
+    // when the query is not in a transaction
+    txn: Option<TransactionInner>,
+}
+
+impl RefResolver {
+
+    pub(crate) fn new(db: Weak<DatabaseInner>, col_name: &str, depth: u32, txn: Option<TransactionInner>) -> RefResolver {
+        RefResolver {
+            db,
+            col_name: col_name.to_string(),
+            depth,
+            txn,
+        }
+    }
+
+    pub(crate) fn resolve(&self, doc: &mut Document) -> Result<()> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        match &self.txn {
+            Some(txn) => resolve_document(&db, &self.col_name, doc, self.depth, txn),
+            None => {
+                let txn = db.start_transaction()?;
+                resolve_document(&db, &self.col_name, doc, self.depth, &txn)
+            }
+        }
+    }
+
+}
+
+fn resolve_document(
+    db: &DatabaseInner,
+    col_name: &str,
+    doc: &mut Document,
+    depth: u32,
+    txn: &TransactionInner,
+) -> Result<()> {
+    if depth == 0 {
+        return Ok(());
+    }
+    for (_, value) in doc.iter_mut() {
+        resolve_value(db, col_name, value, depth, txn)?;
+    }
+    Ok(())
+}
+
+fn resolve_value(
+    db: &DatabaseInner,
+    col_name: &str,
+    value: &mut Bson,
+    depth: u32,
+    txn: &TransactionInner,
+) -> Result<()> {
+    match value {
+        Bson::Document(doc) => {
+            if let Some(db_ref) = DbRef::from_document(doc) {
+                // a dangling reference is kept as it is
+                if let Some(mut target) = db.resolve_ref(col_name, &db_ref, txn)? {
+                    let target_name = db.ref_col_name(col_name, &db_ref);
+                    resolve_document(db, &target_name, &mut target, depth - 1, txn)?;
+                    *value = Bson::Document(target);
+                }
+                return Ok(());
+            }
+            resolve_document(db, col_name, doc, depth, txn)
+        }
+        Bson::Array(arr) => {
+            for item in arr.iter_mut() {
+                resolve_value(db, col_name, item, depth, txn)?;
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,75 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The default and computed fields of the collections.
+//!
+//! The rules are a document of the fields to add to the inserted documents without them,
+//! applied in order, so a rule can use the fields added by the previous rules:
+//!
+//! - `"$$NOW"` is the time of the insert.
+//! - `"$field"` copies the field of the document, `null` if it's missing.
+//! - `{ "$op": ... }` computes the field by an aggregation operator, e.g. `$concat` or `$add`.
+//! - `{ "$literal": value }` is the value as is.
+//! - Any other value is the default value.
+//!
+//! The rules are applied before the `_id` is generated and before the validation.
+
+use bson::{Bson, Document};
+use crate::vm::operators::{OpRegistry, OperatorExpr};
+use crate::{Error, Result};
+
+fn compile_rule(paths: &mut Vec<String>, value: &Bson) -> Result<OperatorExpr> {
+    match value {
+        Bson::Document(doc) if doc.len() == 1 && doc.keys().next().unwrap().starts_with('$') => {
+            match doc.get("$literal") {
+                Some(literal) => Ok(OperatorExpr::Constant(literal.clone())),
+                None => OperatorExpr::compile_operand(paths, &OpRegistry, value),
+            }
+        }
+        Bson::Document(_) => Ok(OperatorExpr::Constant(value.clone())),
+        _ => OperatorExpr::compile_operand(paths, &OpRegistry, value),
+    }
+}
+
+/// Return an error if the rules can't be compiled.
+pub(crate) fn validate_defaults(defaults: &Document) -> Result<()> {
+    let mut paths = Vec::new();
+    for (field, value) in defaults {
+        if field.is_empty() || field.starts_with('$') || field.contains('.') {
+            return Err(Error::ValidationError(format!("illegal name of the default field: '{}'", field)));
+        }
+        crate::path_hint_2!(paths, field.clone(), {
+            compile_rule(&mut paths, value)?;
+        });
+    }
+    Ok(())
+}
+
+/// Add the missing fields to the inserted document.
+pub(crate) fn apply_defaults(defaults: &Document, doc: &mut Document) -> Result<()> {
+    let mut paths = Vec::new();
+    for (field, value) in defaults {
+        if doc.contains_key(field) {
+            continue;
+        }
+        let rule = compile_rule(&mut paths, value)?;
+        let value = match &rule {
+            OperatorExpr::Constant(value) => value.clone(),
+            _ => rule.next(&Bson::Document(doc.clone())),
+        };
+        doc.insert(field.clone(), value);
+    }
+    Ok(())
+}
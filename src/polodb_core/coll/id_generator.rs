@@ -0,0 +1,106 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use std::sync::Mutex;
+use bson::{Binary, Bson, Document};
+use bson::oid::ObjectId;
+use bson::spec::BinarySubtype;
+use crate::options::IdStrategy;
+use crate::{Config, Error, Result};
+
+/// 2024-01-01T00:00:00Z, the epoch of the snowflake ids.
+const SNOWFLAKE_EPOCH_MILLIS: i64 = 1_704_067_200_000;
+const SNOWFLAKE_NODE_BITS: u32 = 10;
+const SNOWFLAKE_SEQUENCE_BITS: u32 = 12;
+
+#[derive(Default)]
+struct SnowflakeState {
+    last_millis: i64,
+    sequence: i64,
+}
+
+/// The state of the generators of the `_id`.
+pub(crate) struct IdGenerators {
+    node: i64,
+    snowflake: Mutex<SnowflakeState>,
+}
+
+impl IdGenerators {
+
+    pub(crate) fn new(node_id: &[u8; 6]) -> IdGenerators {
+        let node = (((node_id[0] as i64) << 8) | node_id[1] as i64) & ((1 << SNOWFLAKE_NODE_BITS) - 1);
+        IdGenerators {
+            node,
+            snowflake: Mutex::new(SnowflakeState::default()),
+        }
+    }
+
+    pub(crate) fn generate(&self, strategy: Option<&IdStrategy>, doc: &Document, config: &Config) -> Result<Bson> {
+        let id = match strategy.unwrap_or(&IdStrategy::ObjectId) {
+            IdStrategy::ObjectId => Bson::ObjectId(ObjectId::new()),
+            IdStrategy::UuidV7 => Bson::Binary(Binary {
+                subtype: BinarySubtype::Uuid,
+                bytes: uuid::Uuid::now_v7().as_bytes().to_vec(),
+            }),
+            IdStrategy::Snowflake => Bson::Int64(self.next_snowflake()?),
+            IdStrategy::Custom(name) => {
+                let generator = config.id_generators
+                    .get(name)
+                    .ok_or_else(|| Error::IdGeneratorNotFound(name.clone()))?;
+                generator(doc)?
+            }
+        };
+        Ok(id)
+    }
+
+    /// The sequence is taken from the next millisecond when it's used up,
+    /// and the time never goes back, so the ids are increasing.
+    fn next_snowflake(&self) -> Result<i64> {
+        let now = bson::DateTime::now().timestamp_millis() - SNOWFLAKE_EPOCH_MILLIS;
+        let mut state = self.snowflake.lock()?;
+        if now > state.last_millis {
+            state.last_millis = now;
+            state.sequence = 0;
+        } else {
+            state.sequence += 1;
+            if state.sequence >= 1 << SNOWFLAKE_SEQUENCE_BITS {
+                state.last_millis += 1;
+                state.sequence = 0;
+            }
+        }
+        Ok((state.last_millis << (SNOWFLAKE_NODE_BITS + SNOWFLAKE_SEQUENCE_BITS))
+            | (self.node << SNOWFLAKE_SEQUENCE_BITS)
+            | state.sequence)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IdGenerators;
+
+    #[test]
+    fn test_snowflake_increasing() {
+        let generators = IdGenerators::new(&[0xff, 0xff, 0, 0, 0, 0]);
+        let mut prev = 0;
+        for _ in 0..10000 {
+            let id = generators.next_snowflake().unwrap();
+            assert!(id > prev);
+            assert_eq!((id >> 12) & 0x3ff, 0x3ff);
+            prev = id;
+        }
+    }
+
+}
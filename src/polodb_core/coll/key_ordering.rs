@@ -0,0 +1,299 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The custom orderings of the primary keys.
+//!
+//! The documents of a collection with a [`KeyOrdering`] are kept in the keyspace
+//! `[col_name, ordered_key]` instead of `[col_name, pkey]`. The ordered key is a binary of the
+//! subtype [`ORDERED_KEY_SUBTYPE`] of the sort key of the `_id` followed by the `_id` itself,
+//! so the documents are scanned in the order of the sort keys, and the `_id`s with the same
+//! sort key, e.g. `"ABC"` and `"abc"` of [`CaseInsensitiveOrdering`], are different documents
+//! in the order of their stored keys. The entries of the indexes end with the ordered keys too.
+
+use std::ops::Bound;
+use std::sync::Arc;
+use bson::{Binary, Bson};
+use bson::spec::BinarySubtype;
+use crate::options::CreateCollectionOptions;
+use crate::transaction::TransactionInner;
+use crate::utils::bson::{read_escaped_bytes, stacked_key_bytes, write_escaped_bytes, ORDERED_KEY_SUBTYPE};
+use crate::{Error, Result};
+
+/// The order of the primary keys of a collection, see
+/// [`crate::options::CreateCollectionOptions::key_ordering`].
+///
+/// The keys are ordered by their sort keys, which are compared byte by byte,
+/// like the collation keys of ICU. So a comparator is written as the bytes
+/// which compare the same way, e.g. the lower-cased string of a case-insensitive order.
+/// Register an ordering with [`crate::ConfigBuilder::set_key_ordering`].
+///
+/// An ordering must return the same sort key for a key every time,
+/// or the documents are not found by their `_id` any more.
+pub trait KeyOrdering: Send + Sync {
+    /// The sort key of `key`, an error rejects the document with the key.
+    fn sort_key(&self, key: &Bson) -> Result<Vec<u8>>;
+}
+
+/// Orders the strings ignoring their case, named `caseInsensitive`.
+/// The other keys are in the order of their types, like without an ordering.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CaseInsensitiveOrdering;
+
+impl KeyOrdering for CaseInsensitiveOrdering {
+    fn sort_key(&self, key: &Bson) -> Result<Vec<u8>> {
+        let mut result = Vec::new();
+        match key {
+            Bson::String(key) => stacked_key_bytes(&mut result, &Bson::String(key.to_lowercase()))?,
+            _ => stacked_key_bytes(&mut result, key)?,
+        }
+        Ok(result)
+    }
+}
+
+/// Orders the strings of [semantic versions](https://semver.org), named `semver`,
+/// e.g. `"1.2.0"` before `"1.10.0"`, and `"1.0.0-rc.1"` before `"1.0.0"`.
+/// The build metadata is ignored, and the other keys are rejected.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SemverOrdering;
+
+impl SemverOrdering {
+
+    fn parse_number(part: &str, version: &str) -> Result<u64> {
+        let valid = !part.is_empty()
+            && part.bytes().all(|b| b.is_ascii_digit())
+            && (part == "0" || !part.starts_with('0'));
+        match part.parse::<u64>() {
+            Ok(number) if valid => Ok(number),
+            _ => Err(Error::ValidationError(format!("'{}' is not a semantic version", version))),
+        }
+    }
+
+}
+
+impl KeyOrdering for SemverOrdering {
+    fn sort_key(&self, key: &Bson) -> Result<Vec<u8>> {
+        let version = match key {
+            Bson::String(version) => version.as_str(),
+            _ => return Err(Error::ValidationError(format!("{} is not a semantic version", key))),
+        };
+        let invalid = || Error::ValidationError(format!("'{}' is not a semantic version", version));
+        let without_build = version.split('+').next().unwrap_or_default();
+        let (core, pre_release) = match without_build.split_once('-') {
+            Some((core, pre_release)) => (core, Some(pre_release)),
+            None => (without_build, None),
+        };
+
+        let mut result = Vec::new();
+        let numbers: Vec<&str> = core.split('.').collect();
+        if numbers.len() != 3 {
+            return Err(invalid());
+        }
+        for number in numbers {
+            result.extend_from_slice(&SemverOrdering::parse_number(number, version)?.to_be_bytes());
+        }
+
+        // a pre-release is before its release, and the identifiers are compared one by one:
+        // the numbers by their values before the others in the ASCII order, then the fewer first
+        match pre_release {
+            Some(pre_release) => {
+                result.push(1);
+                for identifier in pre_release.split('.') {
+                    if identifier.is_empty() || !identifier.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+                        return Err(invalid());
+                    }
+                    if identifier.bytes().all(|b| b.is_ascii_digit()) {
+                        result.push(1);
+                        result.extend_from_slice(&SemverOrdering::parse_number(identifier, version)?.to_be_bytes());
+                    } else {
+                        result.push(2);
+                        result.extend_from_slice(identifier.as_bytes());
+                        result.push(0);
+                    }
+                }
+                result.push(0);
+            }
+            None => result.push(2),
+        }
+        Ok(result)
+    }
+}
+
+/// The built-in ordering named `name`.
+fn builtin_ordering(name: &str) -> Option<Arc<dyn KeyOrdering>> {
+    match name {
+        "caseInsensitive" => Some(Arc::new(CaseInsensitiveOrdering)),
+        "semver" => Some(Arc::new(SemverOrdering)),
+        _ => None,
+    }
+}
+
+/// The ordering named `name`, built-in or registered on the database.
+pub(crate) fn resolve(txn: &TransactionInner, name: &str) -> Result<Arc<dyn KeyOrdering>> {
+    builtin_ordering(name)
+        .or_else(|| txn.rocksdb_txn.key_ordering(name))
+        .ok_or_else(|| Error::KeyOrderingNotFound(name.to_string()))
+}
+
+/// Validate the key ordering of a new collection.
+pub(crate) fn validate_options(options: &CreateCollectionOptions, txn: &TransactionInner) -> Result<()> {
+    let name = match &options.key_ordering {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+    if options.is_capped() || options.timeseries.is_some() || options.view_on.is_some() || options.partition_key.is_some() {
+        return Err(Error::ValidationError("only a normal collection can have a key ordering".to_string()));
+    }
+    resolve(txn, name)?;
+    Ok(())
+}
+
+fn ordered_binary(bytes: Vec<u8>) -> Bson {
+    Bson::Binary(Binary {
+        subtype: BinarySubtype::UserDefined(ORDERED_KEY_SUBTYPE),
+        bytes,
+    })
+}
+
+/// The stored primary key of `id`, the sort key followed by the `_id`.
+pub(crate) fn ordered_key(ordering: &dyn KeyOrdering, id: &Bson) -> Result<Bson> {
+    let mut bytes = Vec::new();
+    write_escaped_bytes(&mut bytes, &ordering.sort_key(id)?)?;
+    stacked_key_bytes(&mut bytes, id)?;
+    Ok(ordered_binary(bytes))
+}
+
+/// The stored primary key of `id`, with the ordering of the collection if it has one.
+pub(crate) fn stored_key(ordering: Option<&dyn KeyOrdering>, id: &Bson) -> Result<Bson> {
+    match ordering {
+        Some(ordering) => ordered_key(ordering, id),
+        None => Ok(id.clone()),
+    }
+}
+
+/// The stored key bounding the keys whose sort key is `sort_key`, before them or after them.
+fn sort_key_bound(sort_key: &[u8], after: bool) -> Result<Bson> {
+    // the escaped sort key is followed by `[0, 0]` and the `_id` in the ordered keys,
+    // `[0, 1]` is after all of them and before the next sort key
+    let mut bytes = Vec::new();
+    write_escaped_bytes(&mut bytes, sort_key)?;
+    if after {
+        *bytes.last_mut().unwrap() = 1;
+    }
+    Ok(ordered_binary(bytes))
+}
+
+/// The bound of the stored keys of a range of `_id`s, the `_id`s with the same
+/// sort key as the bound value are in the range of an inclusive bound.
+pub(crate) fn ordered_bound(ordering: &dyn KeyOrdering, bound: Bound<&Bson>, is_lower: bool) -> Result<Bound<Bson>> {
+    let (value, inclusive) = match bound {
+        Bound::Included(value) => (value, true),
+        Bound::Excluded(value) => (value, false),
+        Bound::Unbounded => return Ok(Bound::Unbounded),
+    };
+    if is_ordered_key(value) {
+        return Ok(match inclusive {
+            true => Bound::Included(value.clone()),
+            false => Bound::Excluded(value.clone()),
+        });
+    }
+    let sort_key = ordering.sort_key(value)?;
+    // the lower bound includes the sort key unless it's exclusive, the upper bound too
+    let after = is_lower != inclusive;
+    let bound = sort_key_bound(&sort_key, after)?;
+    Ok(match is_lower {
+        true => Bound::Included(bound),
+        false => Bound::Excluded(bound),
+    })
+}
+
+pub(crate) fn is_ordered_key(key: &Bson) -> bool {
+    matches!(key, Bson::Binary(bin) if bin.subtype == BinarySubtype::UserDefined(ORDERED_KEY_SUBTYPE))
+}
+
+/// The `_id` of a stored primary key, which is itself without an ordering.
+pub(crate) fn id_of_stored_key(key: Bson) -> Result<Bson> {
+    let bytes = match &key {
+        Bson::Binary(bin) if bin.subtype == BinarySubtype::UserDefined(ORDERED_KEY_SUBTYPE) => bin.bytes.as_slice(),
+        _ => return Ok(key),
+    };
+    let mut reader = bytes;
+    read_escaped_bytes(&mut reader)?;
+    let mut ids = crate::utils::bson::split_stacked_keys(reader)?;
+    match ids.len() {
+        1 => Ok(ids.pop().unwrap()),
+        _ => Err(Error::ValidationError(format!("invalid ordered key: {}", key))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+    use bson::Bson;
+    use crate::utils::bson::stacked_key;
+    use super::{id_of_stored_key, ordered_bound, ordered_key, CaseInsensitiveOrdering, KeyOrdering, SemverOrdering};
+
+    fn stored(ordering: &dyn KeyOrdering, id: &str) -> Vec<u8> {
+        stacked_key([&ordered_key(ordering, &Bson::String(id.to_string())).unwrap()]).unwrap()
+    }
+
+    #[test]
+    fn test_semver_ordering() {
+        let versions = [
+            "0.9.0", "1.0.0-alpha", "1.0.0-alpha.1", "1.0.0-alpha.beta", "1.0.0-beta",
+            "1.0.0-beta.2", "1.0.0-beta.11", "1.0.0-rc.1", "1.0.0", "1.2.0", "1.10.0", "10.0.0",
+        ];
+        let keys: Vec<Vec<u8>> = versions.iter().map(|version| stored(&SemverOrdering, version)).collect();
+        let mut sorted = keys.clone();
+        sorted.sort();
+        assert_eq!(keys, sorted);
+
+        for invalid in ["1.0", "1.0.0.0", "01.0.0", "1.0.0-", "1.0.0-a..b", "v1.0.0"] {
+            assert!(SemverOrdering.sort_key(&Bson::String(invalid.to_string())).is_err(), "{}", invalid);
+        }
+        assert!(SemverOrdering.sort_key(&Bson::Int32(1)).is_err());
+        assert_eq!(
+            SemverOrdering.sort_key(&Bson::String("1.0.0+build.5".to_string())).unwrap(),
+            SemverOrdering.sort_key(&Bson::String("1.0.0".to_string())).unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_ordered_keys() {
+        let ids = ["apple", "Banana", "banana", "cherry"];
+        let keys: Vec<Vec<u8>> = ids.iter().map(|id| stored(&CaseInsensitiveOrdering, id)).collect();
+        let mut sorted = keys.clone();
+        sorted.sort();
+        assert_eq!(keys, sorted);
+
+        let id = Bson::String("Banana".to_string());
+        let key = ordered_key(&CaseInsensitiveOrdering, &id).unwrap();
+        assert_eq!(id_of_stored_key(key).unwrap(), id);
+        assert_eq!(id_of_stored_key(Bson::Int32(1)).unwrap(), Bson::Int32(1));
+
+        let bound_key = |bound: Bound<&Bson>, is_lower: bool| -> Vec<u8> {
+            match ordered_bound(&CaseInsensitiveOrdering, bound, is_lower).unwrap() {
+                Bound::Included(key) | Bound::Excluded(key) => stacked_key([&key]).unwrap(),
+                Bound::Unbounded => unreachable!(),
+            }
+        };
+        let banana = Bson::String("BANANA".to_string());
+        // both of the bananas are in the inclusive bounds, and neither in the exclusive ones
+        assert!(bound_key(Bound::Included(&banana), true) < keys[1]);
+        assert!(bound_key(Bound::Included(&banana), false) > keys[2]);
+        assert!(bound_key(Bound::Included(&banana), false) < keys[3]);
+        assert!(bound_key(Bound::Excluded(&banana), true) > keys[2]);
+        assert!(bound_key(Bound::Excluded(&banana), false) < keys[1]);
+        assert!(bound_key(Bound::Excluded(&banana), false) > keys[0]);
+    }
+}
@@ -0,0 +1,300 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use std::cmp::Ordering;
+use std::collections::{btree_map, BTreeMap, BinaryHeap};
+use std::path::PathBuf;
+use bson::{doc, Bson, Document};
+use serde::de::DeserializeOwned;
+use crate::options::MapReduceOptions;
+use crate::utils::bson::{key_cmp, value_size};
+use crate::utils::spill::{SpillFile, SpillReader, SpillWriter, DEFAULT_MEMORY_LIMIT};
+use crate::{ClientCursor, Result};
+
+
+/// The values of a key are reduced as soon as there are so many of them,
+/// so a hot key doesn't hold all its values until the end.
+const MAX_PENDING_VALUES: usize = 1000;
+
+type ReduceFn<'a> = Box<dyn FnMut(&Bson, Vec<Bson>) -> Bson + 'a>;
+
+/// Collects the key-value pairs emitted by the map function
+/// of [`crate::CollectionT::map_reduce`].
+#[derive(Default)]
+pub struct MapReduceEmitter {
+    emitted: Vec<(Bson, Bson)>,
+}
+
+impl MapReduceEmitter {
+
+    pub fn emit<K: Into<Bson>, V: Into<Bson>>(&mut self, key: K, value: V) {
+        self.emitted.push((key.into(), value.into()));
+    }
+
+}
+
+/// A key of the groups, ordered like the index keys.
+pub(crate) struct GroupKey(pub(crate) Bson);
+
+impl PartialEq for GroupKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for GroupKey {}
+
+impl PartialOrd for GroupKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for GroupKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        key_cmp(&self.0, &other.0)
+    }
+}
+
+/// The head of a spilled run in the merge heap, the smallest key first.
+pub(crate) struct MergeEntry {
+    pub(crate) key: GroupKey,
+    pub(crate) run: usize,
+    pub(crate) value: Bson,
+}
+
+impl PartialEq for MergeEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for MergeEntry {}
+
+impl PartialOrd for MergeEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MergeEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.key.cmp(&self.key).then_with(|| other.run.cmp(&self.run))
+    }
+}
+
+fn reduce_values(reduce_fn: &mut ReduceFn, key: &Bson, mut values: Vec<Bson>) -> Bson {
+    if values.len() == 1 {
+        return values.pop().unwrap();
+    }
+    reduce_fn(key, values)
+}
+
+struct Grouper<'a> {
+    groups: BTreeMap<GroupKey, Vec<Bson>>,
+    memory: usize,
+    memory_limit: usize,
+    temp_dir: Option<PathBuf>,
+    runs: Vec<SpillFile>,
+    reduce_fn: ReduceFn<'a>,
+}
+
+impl<'a> Grouper<'a> {
+
+    fn add(&mut self, key: Bson, value: Bson) -> Result<()> {
+        self.memory += value_size(&value);
+        match self.groups.entry(GroupKey(key)) {
+            btree_map::Entry::Vacant(vacant) => {
+                self.memory += value_size(&vacant.key().0);
+                vacant.insert(vec![value]);
+            }
+            btree_map::Entry::Occupied(mut occupied) => {
+                let values = occupied.get_mut();
+                values.push(value);
+                if values.len() >= MAX_PENDING_VALUES {
+                    let values = std::mem::take(values);
+                    let freed: usize = values.iter().map(value_size).sum();
+                    let reduced = (self.reduce_fn)(&occupied.key().0, values);
+                    self.memory = self.memory - freed + value_size(&reduced);
+                    occupied.get_mut().push(reduced);
+                }
+            }
+        }
+        if self.memory > self.memory_limit {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Reduce the groups in memory and write them to a sorted run.
+    fn spill(&mut self) -> Result<()> {
+        let mut writer = SpillWriter::create(self.temp_dir.as_deref())?;
+        for (key, values) in std::mem::take(&mut self.groups) {
+            let value = reduce_values(&mut self.reduce_fn, &key.0, values);
+            writer.write(&doc! { "_id": key.0, "value": value })?;
+        }
+        self.runs.push(writer.finish()?);
+        self.memory = 0;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<MapReduceCursor<'a>> {
+        if self.runs.is_empty() {
+            return Ok(MapReduceCursor {
+                source: MapReduceSource::Memory(self.groups.into_iter()),
+                reduce_fn: self.reduce_fn,
+                spilled_runs: 0,
+            });
+        }
+        if !self.groups.is_empty() {
+            self.spill()?;
+        }
+
+        let mut readers = Vec::with_capacity(self.runs.len());
+        let mut heap = BinaryHeap::with_capacity(self.runs.len());
+        for (run, file) in self.runs.iter().enumerate() {
+            let mut reader = file.reader()?;
+            if let Some(entry) = read_entry(&mut reader, run)? {
+                heap.push(entry);
+            }
+            readers.push(reader);
+        }
+
+        Ok(MapReduceCursor {
+            spilled_runs: self.runs.len(),
+            source: MapReduceSource::Merge {
+                readers,
+                heap,
+                _runs: self.runs,
+            },
+            reduce_fn: self.reduce_fn,
+        })
+    }
+
+}
+
+/// Read the next `{ "_id": key, "value": value }` of a sorted run.
+pub(crate) fn read_entry(reader: &mut SpillReader, run: usize) -> Result<Option<MergeEntry>> {
+    let mut doc = match reader.next_doc()? {
+        Some(doc) => doc,
+        None => return Ok(None),
+    };
+    let key = doc.remove("_id").unwrap_or(Bson::Null);
+    let value = doc.remove("value").unwrap_or(Bson::Null);
+    Ok(Some(MergeEntry {
+        key: GroupKey(key),
+        run,
+        value,
+    }))
+}
+
+enum MapReduceSource {
+    Memory(btree_map::IntoIter<GroupKey, Vec<Bson>>),
+    Merge {
+        readers: Vec<SpillReader>,
+        heap: BinaryHeap<MergeEntry>,
+        // dropped after the readers
+        _runs: Vec<SpillFile>,
+    },
+}
+
+/// The result of [`crate::CollectionT::map_reduce`], one document
+/// `{ "_id": key, "value": value }` per key in the order of the keys.
+///
+/// The groups which have been spilled to temporary files are merged
+/// and reduced again while iterating.
+pub struct MapReduceCursor<'a> {
+    source: MapReduceSource,
+    reduce_fn: ReduceFn<'a>,
+    spilled_runs: usize,
+}
+
+impl MapReduceCursor<'_> {
+
+    /// The number of sorted runs written to temporary files,
+    /// 0 if all the groups fit in memory.
+    pub fn spilled_runs(&self) -> usize {
+        self.spilled_runs
+    }
+
+    fn next_merged(&mut self) -> Result<Option<Document>> {
+        let (readers, heap) = match &mut self.source {
+            MapReduceSource::Merge { readers, heap, .. } => (readers, heap),
+            MapReduceSource::Memory(_) => unreachable!(),
+        };
+        let first = match heap.pop() {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let key = first.key;
+        let mut values = vec![first.value];
+        let mut consumed = vec![first.run];
+        while heap.peek().is_some_and(|entry| entry.key == key) {
+            let entry = heap.pop().unwrap();
+            values.push(entry.value);
+            consumed.push(entry.run);
+        }
+        for run in consumed {
+            if let Some(entry) = read_entry(&mut readers[run], run)? {
+                heap.push(entry);
+            }
+        }
+        let value = reduce_values(&mut self.reduce_fn, &key.0, values);
+        Ok(Some(doc! { "_id": key.0, "value": value }))
+    }
+
+}
+
+impl Iterator for MapReduceCursor<'_> {
+    type Item = Result<Document>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let MapReduceSource::Memory(groups) = &mut self.source {
+            let (key, values) = groups.next()?;
+            let value = reduce_values(&mut self.reduce_fn, &key.0, values);
+            return Some(Ok(doc! { "_id": key.0, "value": value }));
+        }
+        self.next_merged().transpose()
+    }
+}
+
+pub(crate) fn map_reduce<'a, T, M, R>(
+    mut cursor: ClientCursor<T>,
+    mut map_fn: M,
+    reduce_fn: R,
+    options: MapReduceOptions,
+) -> Result<MapReduceCursor<'a>>
+where
+    T: DeserializeOwned + Send + Sync,
+    M: FnMut(T, &mut MapReduceEmitter),
+    R: FnMut(&Bson, Vec<Bson>) -> Bson + 'a,
+{
+    let mut grouper = Grouper {
+        groups: BTreeMap::new(),
+        memory: 0,
+        memory_limit: options.memory_limit.unwrap_or(DEFAULT_MEMORY_LIMIT),
+        temp_dir: options.temp_dir,
+        runs: Vec::new(),
+        reduce_fn: Box::new(reduce_fn),
+    };
+    let mut emitter = MapReduceEmitter::default();
+    while cursor.advance()? {
+        map_fn(cursor.deserialize_current()?, &mut emitter);
+        for (key, value) in emitter.emitted.drain(..) {
+            grouper.add(key, value)?;
+        }
+    }
+    grouper.finish()
+}
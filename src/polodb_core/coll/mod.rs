@@ -12,9 +12,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub(crate) mod capped;
 mod collection;
 pub(crate) mod collection_info;
+pub(crate) mod db_ref;
+pub(crate) mod defaults;
+pub(crate) mod id_generator;
+pub(crate) mod key_ordering;
+pub(crate) mod map_reduce;
+pub(crate) mod partition;
+pub(crate) mod timeseries;
 mod txn_collection;
+pub(crate) mod validator;
+pub(crate) mod view;
 
 pub use collection::{Collection, CollectionT};
+pub use db_ref::DbRef;
+pub use key_ordering::{CaseInsensitiveOrdering, KeyOrdering, SemverOrdering};
+pub use map_reduce::{MapReduceCursor, MapReduceEmitter};
 pub use txn_collection::TransactionalCollection;
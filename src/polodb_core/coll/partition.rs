@@ -0,0 +1,163 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The storage of the partitioned collections.
+//!
+//! The documents of a partitioned collection are kept in the keyspace
+//! `[col_name, partition, pkey]`, where the partition is the value of the partition key
+//! of the document, `null` if it's missing. So the documents of a partition
+//! are a contiguous range of keys, which is scanned alone when the query
+//! has an equality on the partition key, and removed by a range deletion when the partition is dropped.
+
+use bson::{Bson, Document};
+use crate::cursor::Cursor;
+use crate::options::CreateCollectionOptions;
+use crate::transaction::TransactionInner;
+use crate::{Error, Result};
+
+/// Validate the partition key of a new collection.
+pub(crate) fn validate_options(options: &CreateCollectionOptions) -> Result<()> {
+    let partition_key = match &options.partition_key {
+        Some(partition_key) => partition_key,
+        None => return Ok(()),
+    };
+    if options.is_capped() || options.timeseries.is_some() || options.view_on.is_some() {
+        return Err(Error::ValidationError("only a normal collection can be partitioned".to_string()));
+    }
+    if partition_key.is_empty() || partition_key == "_id" || partition_key.starts_with("_id.")
+        || partition_key.starts_with('$') || partition_key.split('.').any(str::is_empty) {
+        return Err(Error::ValidationError(format!("invalid partition key: '{}'", partition_key)));
+    }
+    Ok(())
+}
+
+/// The partition of a document.
+pub(crate) fn partition_of(partition_key: &str, doc: &Document) -> Result<Bson> {
+    let value = crate::utils::bson::try_get_document_value(doc, partition_key).unwrap_or(Bson::Null);
+    if !is_partition_value(&value) {
+        return Err(Error::ValidationError(format!(
+            "the partition key '{}' can't be {}", partition_key, value,
+        )));
+    }
+    Ok(value)
+}
+
+/// The arrays and the documents are not partitions,
+/// because they are matched by their elements and fields.
+fn is_partition_value(value: &Bson) -> bool {
+    !matches!(value, Bson::Array(_) | Bson::Document(_) | Bson::RegularExpression(_))
+}
+
+/// The partition scanned by the query, `None` if all the partitions are scanned.
+pub(crate) fn partition_of_query(partition_key: &str, query: &Document) -> Option<Bson> {
+    query.get(partition_key)
+        .filter(|value| is_partition_value(value))
+        .cloned()
+}
+
+pub(crate) fn partition_prefix(col_name: &str, partition: &Bson) -> Result<Vec<u8>> {
+    crate::utils::bson::stacked_key(&[
+        Bson::String(col_name.to_string()),
+        partition.clone(),
+    ])
+}
+
+/// The range of the keys of a partition, the first byte of a primary key
+/// is its type, which is less than 0xFF.
+pub(crate) fn partition_range(col_name: &str, partition: &Bson) -> Result<(Vec<u8>, Vec<u8>)> {
+    let start = partition_prefix(col_name, partition)?;
+    let mut end = start.clone();
+    end.push(0xFF);
+    Ok((start, end))
+}
+
+pub(crate) fn document_key(col_name: &str, partition: &Bson, pkey: &Bson) -> Result<Vec<u8>> {
+    crate::utils::bson::stacked_key(&[
+        Bson::String(col_name.to_string()),
+        partition.clone(),
+        pkey.clone(),
+    ])
+}
+
+/// The partition key can't be changed by an update,
+/// because the document would be kept in the old partition.
+pub(crate) fn validate_update(partition_key: &str, update: &Document) -> Result<()> {
+    let conflicts = |path: &str| {
+        path == partition_key
+            || path.strip_prefix(partition_key).is_some_and(|rest| rest.starts_with('.'))
+            || partition_key.strip_prefix(path).is_some_and(|rest| rest.starts_with('.'))
+    };
+    for (operator, fields) in update {
+        let fields = match fields {
+            Bson::Document(fields) => fields,
+            _ => continue,
+        };
+        for (path, value) in fields {
+            let renamed_to = match (operator.as_str(), value) {
+                ("$rename", Bson::String(new_path)) => Some(new_path.as_str()),
+                _ => None,
+            };
+            if conflicts(path) || renamed_to.is_some_and(conflicts) {
+                return Err(Error::ValidationError(format!(
+                    "the partition key '{}' can't be updated", partition_key,
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The partitions of a collection in the order of the keys.
+/// Each partition is skipped after its first document is found.
+pub(crate) fn list_partitions(txn: &TransactionInner, col_name: &str) -> Result<Vec<Bson>> {
+    let mut result = Vec::new();
+    let mut cursor = Cursor::new_with_str_prefix(col_name, txn.rocksdb_txn.new_iterator())?;
+    cursor.reset()?;
+    while cursor.has_next() {
+        let key = cursor.peek_key().expect("key must exist");
+        let mut slices = crate::utils::bson::split_stacked_keys(key.as_ref())?;
+        if slices.len() != 3 {
+            return Err(Error::ValidationError(format!("invalid key of the partitioned collection '{}'", col_name)));
+        }
+        slices.pop();
+        let partition = slices.pop().unwrap();
+
+        // the first byte of a primary key is its type, which is less than 0xFF
+        let mut partition_end = Vec::new();
+        crate::utils::bson::stacked_key_bytes(&mut partition_end, &partition)?;
+        partition_end.push(0xFF);
+        cursor.reset_by_pkey_buf(&partition_end)?;
+
+        result.push(partition);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::doc;
+    use super::validate_update;
+
+    #[test]
+    fn test_validate_update() {
+        assert!(validate_update("day", &doc! { "$set": { "value": 1 } }).is_ok());
+        assert!(validate_update("day", &doc! { "$set": { "dayOfWeek": 1 } }).is_ok());
+        assert!(validate_update("day", &doc! { "$set": { "day": 1 } }).is_err());
+        assert!(validate_update("day", &doc! { "$unset": { "day.hour": "" } }).is_err());
+        assert!(validate_update("meta.day", &doc! { "$set": { "meta": {} } }).is_err());
+        assert!(validate_update("day", &doc! { "$rename": { "date": "day" } }).is_err());
+    }
+
+}
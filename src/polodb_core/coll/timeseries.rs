@@ -0,0 +1,281 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The storage of the time-series collections.
+//!
+//! The measurements are grouped by the metadata and the time window into bucket documents:
+//!
+//! ```text
+//! {
+//!     _id: ObjectId,
+//!     control: { min: <time>, max: <time>, count: <n> },
+//!     meta: <metadata>,
+//!     data: { <field>: { "0": <value>, "1": <value>, ... }, ... },
+//! }
+//! ```
+//!
+//! The open bucket of a metadata and time window is found by the key
+//! `[BUCKET_PREFIX, col_name, window_start] + bson({ meta })`, the value is the id of the bucket.
+
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use bson::{doc, Bson, DateTime, Document};
+use bson::oid::ObjectId;
+use crate::options::{CreateCollectionOptions, TimeseriesGranularity, TimeseriesOptions};
+use crate::cursor::Cursor;
+use crate::transaction::TransactionInner;
+use crate::{Error, Result};
+
+const BUCKET_PREFIX: &str = "$TS_BUCKET";
+/// A new bucket is started when the open bucket has so many measurements.
+const BUCKET_MAX_COUNT: i64 = 1000;
+/// A new bucket is started when the open bucket is larger than it.
+const BUCKET_MAX_SIZE: usize = 125 * 1024;
+
+/// Validate the time-series options of a new collection.
+pub(crate) fn validate_options(options: &CreateCollectionOptions) -> Result<()> {
+    let timeseries = match &options.timeseries {
+        Some(timeseries) => timeseries,
+        None => return Ok(()),
+    };
+    if options.is_capped() {
+        return Err(Error::ValidationError("a time-series collection can't be capped".to_string()));
+    }
+    if timeseries.time_field.is_empty() || timeseries.time_field == "_id" {
+        return Err(Error::ValidationError(format!("invalid time field: '{}'", timeseries.time_field)));
+    }
+    if let Some(meta_field) = &timeseries.meta_field {
+        if meta_field.is_empty() || meta_field == "_id" || meta_field == &timeseries.time_field {
+            return Err(Error::ValidationError(format!("invalid meta field: '{}'", meta_field)));
+        }
+    }
+    Ok(())
+}
+
+fn bucket_span_millis(granularity: Option<TimeseriesGranularity>) -> i64 {
+    const HOUR: i64 = 60 * 60 * 1000;
+    match granularity.unwrap_or(TimeseriesGranularity::Seconds) {
+        TimeseriesGranularity::Seconds => HOUR,
+        TimeseriesGranularity::Minutes => 24 * HOUR,
+        TimeseriesGranularity::Hours => 30 * 24 * HOUR,
+    }
+}
+
+fn bucket_prefix(col_name: &str) -> Result<Vec<u8>> {
+    crate::utils::bson::stacked_key(&[
+        Bson::String(BUCKET_PREFIX.to_string()),
+        Bson::String(col_name.to_string()),
+    ])
+}
+
+fn bucket_key(col_name: &str, bucket_id: ObjectId) -> Result<Vec<u8>> {
+    crate::utils::bson::stacked_key(&[
+        Bson::String(col_name.to_string()),
+        Bson::ObjectId(bucket_id),
+    ])
+}
+
+/// Insert a measurement into the open bucket of its metadata and time window.
+/// A new bucket is started if there is no open bucket or the open bucket is full.
+pub(crate) fn insert_measurement(
+    txn: &TransactionInner,
+    col_name: &str,
+    options: &TimeseriesOptions,
+    doc: &Document,
+) -> Result<()> {
+    let time = match doc.get(&options.time_field) {
+        Some(Bson::DateTime(time)) => *time,
+        _ => {
+            return Err(Error::ValidationError(
+                format!("the measurement requires a date in the field '{}'", options.time_field)
+            ));
+        }
+    };
+    let meta = options.meta_field
+        .as_ref()
+        .and_then(|meta_field| doc.get(meta_field))
+        .cloned();
+
+    let span = bucket_span_millis(options.granularity);
+    let window_start = time.timestamp_millis().div_euclid(span) * span;
+
+    let lookup_key = {
+        let mut key = bucket_prefix(col_name)?;
+        crate::utils::bson::stacked_key_bytes(&mut key, &Bson::Int64(window_start))?;
+        let meta_doc = doc! { "meta": meta.clone().unwrap_or(Bson::Null) };
+        key.extend_from_slice(&bson::to_vec(&meta_doc)?);
+        key
+    };
+
+    let open_bucket = match txn.rocksdb_txn.get_for_update(&lookup_key)? {
+        Some(id_bytes) => {
+            let bucket_id = ObjectId::from_bytes(id_bytes.as_slice().try_into().expect("invalid bucket id"));
+            read_open_bucket(txn, col_name, bucket_id)?
+        }
+        None => None,
+    };
+
+    let mut bucket = match open_bucket {
+        Some(bucket) => bucket,
+        None => {
+            let bucket_id = ObjectId::new();
+            txn.put(&lookup_key, &bucket_id.bytes())?;
+            new_bucket(bucket_id, time, meta)
+        }
+    };
+
+    append_measurement(&mut bucket, doc, time, options.meta_field.as_deref());
+
+    let bucket_id = bucket.get_object_id("_id").expect("invalid bucket");
+    txn.put(&bucket_key(col_name, bucket_id)?, &bson::to_vec(&bucket)?)?;
+
+    Ok(())
+}
+
+/// Return the bucket if it can take one more measurement.
+fn read_open_bucket(txn: &TransactionInner, col_name: &str, bucket_id: ObjectId) -> Result<Option<Document>> {
+    let bytes = match txn.rocksdb_txn.get(&bucket_key(col_name, bucket_id)?)? {
+        Some(bytes) => bytes,
+        None => return Ok(None),
+    };
+    if bytes.len() >= BUCKET_MAX_SIZE {
+        return Ok(None);
+    }
+    let bucket: Document = bson::from_slice(&bytes)?;
+    if bucket_count(&bucket) >= BUCKET_MAX_COUNT {
+        return Ok(None);
+    }
+    Ok(Some(bucket))
+}
+
+fn new_bucket(bucket_id: ObjectId, time: DateTime, meta: Option<Bson>) -> Document {
+    let mut bucket = doc! {
+        "_id": bucket_id,
+        "control": {
+            "min": time,
+            "max": time,
+            "count": 0_i64,
+        },
+    };
+    if let Some(meta) = meta {
+        bucket.insert("meta", meta);
+    }
+    bucket.insert("data", Document::new());
+    bucket
+}
+
+fn bucket_count(bucket: &Document) -> i64 {
+    bucket.get_document("control")
+        .and_then(|control| control.get_i64("count"))
+        .unwrap_or(0)
+}
+
+fn append_measurement(bucket: &mut Document, doc: &Document, time: DateTime, meta_field: Option<&str>) {
+    let count = bucket_count(bucket);
+
+    let control = bucket.get_document_mut("control").expect("invalid bucket");
+    if control.get_datetime("min").map(|min| time < *min).unwrap_or(true) {
+        control.insert("min", time);
+    }
+    if control.get_datetime("max").map(|max| time > *max).unwrap_or(true) {
+        control.insert("max", time);
+    }
+    control.insert("count", count + 1);
+
+    let index = count.to_string();
+    let data = bucket.get_document_mut("data").expect("invalid bucket");
+    for (key, value) in doc {
+        if Some(key.as_str()) == meta_field {
+            continue;
+        }
+        if !data.contains_key(key) {
+            data.insert(key.clone(), Document::new());
+        }
+        let column = data.get_document_mut(key).expect("invalid bucket");
+        column.insert(index.clone(), value.clone());
+    }
+}
+
+fn invalid_bucket() -> Error {
+    Error::ValidationError("invalid bucket document".to_string())
+}
+
+/// Unpack the buckets read by a scan into the measurements.
+pub(crate) struct BucketUnpacker {
+    meta_field: Option<String>,
+    pending: VecDeque<Document>,
+}
+
+impl BucketUnpacker {
+
+    pub(crate) fn new(meta_field: Option<String>) -> BucketUnpacker {
+        BucketUnpacker {
+            meta_field,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Document> {
+        self.pending.pop_front()
+    }
+
+    pub(crate) fn unpack(&mut self, bucket: &Document) -> Result<()> {
+        let count = bucket_count(bucket) as usize;
+        let mut measurements = vec![Document::new(); count];
+
+        let data = bucket.get_document("data").map_err(|_| invalid_bucket())?;
+        for (field, column) in data {
+            let column = column.as_document().ok_or_else(invalid_bucket)?;
+            for (index, value) in column {
+                let measurement = index.parse::<usize>()
+                    .ok()
+                    .and_then(|index| measurements.get_mut(index))
+                    .ok_or_else(invalid_bucket)?;
+                measurement.insert(field.clone(), value.clone());
+            }
+        }
+
+        if let (Some(meta_field), Some(meta)) = (&self.meta_field, bucket.get("meta")) {
+            for measurement in &mut measurements {
+                measurement.insert(meta_field.clone(), meta.clone());
+            }
+        }
+
+        self.pending.extend(measurements);
+        Ok(())
+    }
+
+}
+
+/// Remove the bucket lookup of a dropped collection.
+pub(crate) fn remove_all(txn: &TransactionInner, col_name: &str) -> Result<()> {
+    let mut keys = Vec::new();
+    let mut cursor = Cursor::new(bucket_prefix(col_name)?, txn.rocksdb_txn.new_iterator());
+    cursor.reset()?;
+    while cursor.has_next() {
+        keys.push(cursor.peek_key().expect("key must exist"));
+        cursor.next()?;
+    }
+
+    for key in keys {
+        txn.delete(key.as_ref())?;
+    }
+
+    Ok(())
+}
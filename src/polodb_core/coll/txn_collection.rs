@@ -13,15 +13,18 @@
 // limitations under the License.
 
 use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::ops::RangeBounds;
 use std::sync::Weak;
-use bson::Document;
+use bson::{doc, Bson, Document};
 use serde::Serialize;
 use crate::db::db_inner::DatabaseInner;
 use crate::options::UpdateOptions;
 use serde::de::DeserializeOwned;
-use crate::{CollectionT, Error, IndexModel, Result};
+use uuid::Uuid;
+use crate::{ClientCursor, CollectionT, DbRef, Error, IndexModel, Result};
 use crate::action::{Aggregate, Find};
-use crate::results::{DeleteResult, InsertManyResult, InsertOneResult, UpdateResult};
+use crate::results::{CollectionStats, DeleteResult, DropResult, IndexStats, InsertManyResult, InsertOneResult, UpdateResult};
 use crate::transaction::TransactionInner;
 
 pub struct TransactionalCollection<T> {
@@ -55,8 +58,19 @@ impl<T> CollectionT<T> for TransactionalCollection<T> {
         db.count_documents(&self.name, &self.txn)
     }
 
+    fn stats(&self) -> crate::Result<CollectionStats> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.collection_stats(&self.name, &self.txn)
+    }
+
+    fn analyze(&self) -> crate::Result<HashMap<String, IndexStats>> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.analyze_collection(&self.name, &self.txn)
+    }
+
     fn update_one(&self, query: Document, update: Document) -> crate::Result<UpdateResult> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.check_quota()?;
         let result = db.update_one(
             &self.name,
             query,
@@ -69,6 +83,7 @@ impl<T> CollectionT<T> for TransactionalCollection<T> {
 
     fn update_one_with_options(&self, query: Document, update: Document, options: UpdateOptions) -> crate::Result<UpdateResult> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.check_quota()?;
         let result = db.update_one(
             &self.name,
             query,
@@ -81,6 +96,7 @@ impl<T> CollectionT<T> for TransactionalCollection<T> {
 
     fn update_many(&self, query: Document, update: Document) -> crate::Result<UpdateResult> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.check_quota()?;
         let result = db.update_many(
             &self.name,
             query,
@@ -93,6 +109,7 @@ impl<T> CollectionT<T> for TransactionalCollection<T> {
 
     fn update_many_with_options(&self, query: Document, update: Document, options: UpdateOptions) -> Result<UpdateResult> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.check_quota()?;
         let result = db.update_many(
             &self.name,
             query,
@@ -117,6 +134,7 @@ impl<T> CollectionT<T> for TransactionalCollection<T> {
 
     fn create_index(&self, index: IndexModel) -> crate::Result<()> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.check_quota()?;
         db.create_index(&self.name, index, &self.txn)?;
         Ok(())
     }
@@ -127,15 +145,27 @@ impl<T> CollectionT<T> for TransactionalCollection<T> {
         Ok(())
     }
 
-    fn drop(&self) -> crate::Result<()> {
+    fn drop(&self) -> crate::Result<DropResult> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
-        db.drop_collection(&self.name, &self.txn)?;
-        Ok(())
+        let result = db.drop_collection(&self.name, &self.txn)?;
+        Ok(result)
+    }
+
+    fn drop_partition(&self, partition: impl Into<Bson>) -> crate::Result<DropResult> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let result = db.drop_partition(&self.name, &partition.into(), &self.txn)?;
+        Ok(result)
+    }
+
+    fn list_partitions(&self) -> crate::Result<Vec<Bson>> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.list_partitions(&self.name, &self.txn)
     }
 
     fn insert_one(&self, doc: impl Borrow<T>) -> crate::Result<InsertOneResult>
     where T: Serialize {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.check_quota()?;
         let result = db.insert_one(
             &self.name,
             bson::to_document(doc.borrow())?,
@@ -147,6 +177,7 @@ impl<T> CollectionT<T> for TransactionalCollection<T> {
     fn insert_many(&self, docs: impl IntoIterator<Item = impl Borrow<T>>) -> crate::Result<InsertManyResult>
     where T: Serialize {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.check_quota()?;
         let result = db.insert_many(&self.name, docs, &self.txn)?;
         Ok(result)
     }
@@ -166,6 +197,50 @@ impl<T> CollectionT<T> for TransactionalCollection<T> {
         Ok(Some(cursor.deserialize_current()?))
     }
 
+    fn find_range(&self, range: impl RangeBounds<Bson>) -> Result<ClientCursor<T>>
+    where T: DeserializeOwned + Send + Sync {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.find_range_with_owned_session(&self.name, range.start_bound(), range.end_bound(), self.txn.clone())
+    }
+
+    fn exists(&self, filter: Document) -> Result<bool> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.exists(&self.name, filter, &self.txn)
+    }
+
+    fn find_many_by_ids(&self, ids: &[Bson]) -> Result<Vec<Option<T>>>
+    where T: DeserializeOwned + Send + Sync {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let docs = db.find_many_by_ids(&self.name, ids, &self.txn)?;
+        docs.into_iter()
+            .map(|doc| Ok(doc.map(bson::from_document).transpose()?))
+            .collect()
+    }
+
+    fn insert_one_with_uuid(&self, id: Uuid, doc: impl Borrow<T>) -> Result<InsertOneResult>
+    where T: Serialize {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.check_quota()?;
+        let mut doc = bson::to_document(doc.borrow())?;
+        doc.insert("_id", bson::Uuid::from(id));
+        let result = db.insert_one(&self.name, doc, &self.txn)?;
+        Ok(result)
+    }
+
+    fn find_by_uuid(&self, id: Uuid) -> Result<Option<T>>
+    where T: DeserializeOwned + Send + Sync {
+        self.find_one(doc! { "_id": bson::Uuid::from(id) })
+    }
+
+    fn delete_by_uuid(&self, id: Uuid) -> Result<DeleteResult> {
+        self.delete_one(doc! { "_id": bson::Uuid::from(id) })
+    }
+
+    fn resolve_ref(&self, db_ref: &DbRef) -> Result<Option<Document>> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.resolve_ref(&self.name, db_ref, &self.txn)
+    }
+
     fn aggregate(&self, pipeline: impl IntoIterator<Item = Document>) -> Aggregate<'_, '_> {
         Aggregate::new(
             self.db.clone(),
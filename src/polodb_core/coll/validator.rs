@@ -0,0 +1,136 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The validators of the collections, only `$jsonSchema` is supported.
+//!
+//! The documents are validated in the write transactions, by the inserts
+//! and by the VM when the updated documents are written back.
+//! The documents which exist when the validator is attached are not checked.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use bson::Document;
+use crate::coll::collection_info::{CollectionSpecification, ValidatorInfo};
+use crate::errors::DocumentValidationError;
+use crate::options::{CreateCollectionOptions, ValidationAction};
+use crate::utils::json_schema::JsonSchema;
+use crate::{Error, Result};
+
+pub(crate) struct CollectionValidator {
+    ns: String,
+    /// The validator the schema is compiled from
+    info: ValidatorInfo,
+    schema: JsonSchema,
+}
+
+/// The compiled validators of the collections by their names.
+///
+/// The validator of the specification read by each write is compared
+/// with the cached one, so a changed validator is compiled again,
+/// even if it's changed by a transaction which isn't committed yet.
+#[derive(Default)]
+pub(crate) struct ValidatorCache {
+    validators: RwLock<HashMap<String, Arc<CollectionValidator>>>,
+}
+
+impl ValidatorCache {
+
+    pub(crate) fn get(&self, spec: &CollectionSpecification) -> Result<Option<Arc<CollectionValidator>>> {
+        let info = match &spec.validator {
+            Some(info) => info,
+            None => return Ok(None),
+        };
+        if let Some(validator) = self.validators.read().unwrap().get(spec.name()) {
+            if &validator.info == info {
+                return Ok(Some(validator.clone()));
+            }
+        }
+        let validator = Arc::new(CollectionValidator::compile(spec.name(), info)?);
+        self.validators.write().unwrap().insert(spec.name().to_string(), validator.clone());
+        Ok(Some(validator))
+    }
+
+    /// Remove the validator of a collection whose options are changed or which is dropped.
+    pub(crate) fn invalidate(&self, name: &str) {
+        self.validators.write().unwrap().remove(name);
+    }
+
+}
+
+fn parse_validator(validator: &Document) -> Result<JsonSchema> {
+    let schema = match validator.get("$jsonSchema") {
+        Some(schema) if validator.len() == 1 => schema,
+        _ => return Err(Error::ValidationError("only the $jsonSchema validators are supported".to_string())),
+    };
+    let schema = schema.as_document()
+        .ok_or_else(|| Error::ValidationError("$jsonSchema must be a document".to_string()))?;
+    JsonSchema::parse(schema)
+}
+
+/// Validate the options and return the validator of the collection,
+/// `None` if the collection is not validated.
+pub(crate) fn validator_info_from_options(options: &CreateCollectionOptions) -> Result<Option<ValidatorInfo>> {
+    let validator = match &options.validator {
+        Some(validator) => validator,
+        None => {
+            if options.validation_action.is_some() {
+                return Err(Error::ValidationError("validationAction requires a validator".to_string()));
+            }
+            return Ok(None);
+        }
+    };
+    if options.view_on.is_some() {
+        return Err(Error::ValidationError("a view can't have a validator".to_string()));
+    }
+    validator_info(validator.clone(), options.validation_action.unwrap_or_default()).map(Some)
+}
+
+pub(crate) fn validator_info(validator: Document, validation_action: ValidationAction) -> Result<ValidatorInfo> {
+    parse_validator(&validator)?;
+    Ok(ValidatorInfo {
+        validator,
+        validation_action,
+    })
+}
+
+impl CollectionValidator {
+
+    fn compile(ns: &str, info: &ValidatorInfo) -> Result<CollectionValidator> {
+        Ok(CollectionValidator {
+            ns: ns.to_string(),
+            info: info.clone(),
+            schema: parse_validator(&info.validator)?,
+        })
+    }
+
+    pub(crate) fn validate(&self, doc: &Document) -> Result<()> {
+        let reason = match self.schema.validate_document(doc) {
+            Ok(()) => return Ok(()),
+            Err(reason) => reason,
+        };
+        match self.info.validation_action {
+            ValidationAction::Error => Err(DocumentValidationError {
+                ns: self.ns.clone(),
+                reason,
+            }.into()),
+            ValidationAction::Warn => {
+                crate::polo_log!("document failed validation of collection '{}': {}", self.ns, reason);
+                crate::trace_event!(collection = self.ns.as_str(), reason = reason.as_str(), "document failed validation");
+                Ok(())
+            }
+        }
+    }
+
+}
@@ -0,0 +1,42 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use crate::coll::collection_info::ViewInfo;
+use crate::options::CreateCollectionOptions;
+use crate::{Error, Result};
+
+/// The max count of the views between a view and its source collection.
+pub(crate) const MAX_VIEW_DEPTH: usize = 20;
+
+/// Validate the options and return the definition of the view,
+/// `None` if the options don't define a view.
+pub(crate) fn view_info_from_options(options: &CreateCollectionOptions) -> Result<Option<ViewInfo>> {
+    let view_on = match &options.view_on {
+        Some(view_on) => view_on,
+        None => {
+            if options.pipeline.is_some() {
+                return Err(Error::ValidationError("pipeline is only supported by the views".to_string()));
+            }
+            return Ok(None);
+        }
+    };
+    if options.is_capped() || options.timeseries.is_some() {
+        return Err(Error::ValidationError("a view can't be capped or time-series".to_string()));
+    }
+    Ok(Some(ViewInfo {
+        view_on: view_on.clone(),
+        pipeline: options.pipeline.clone().unwrap_or_default(),
+    }))
+}
@@ -12,6 +12,104 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use bson::{Bson, Document};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::storage_backend::StorageBackend;
+use crate::{BlockCache, Database, KeyOrdering, Tokenizer};
+#[cfg(feature = "fault-injection")]
+use crate::fault_injection::FaultInjector;
+#[cfg(feature = "metrics")]
+use crate::MetricsRecorder;
+
+/// Controls when the write-ahead log is flushed to the disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WalSyncPolicy {
+    /// Sync the WAL before every commit returns. This is the most durable mode.
+    #[default]
+    EveryCommit,
+    /// Commits are written to the OS buffer, and a background thread syncs
+    /// the WAL at the given interval. At most one interval of commits
+    /// could be lost on power failure.
+    Interval(Duration),
+    /// Commits are written to the OS buffer and never synced explicitly.
+    /// The data survives a process crash but not a power failure.
+    OsBuffered,
+}
+
+impl WalSyncPolicy {
+
+    #[inline]
+    pub(crate) fn sync_on_commit(&self) -> bool {
+        matches!(self, WalSyncPolicy::EveryCommit)
+    }
+
+}
+
+/// A tunable which can be changed by [`Database::set_option`]
+/// while the database is open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeOption {
+    /// The capacity of the block cache in bytes. The cache is shared by
+    /// the databases opened with the same [`BlockCache`].
+    BlockCacheSize(usize),
+    /// The maximum number of the concurrent flushes and compactions.
+    MaxBackgroundJobs(u32),
+    /// The threshold of [`ConfigBuilder::set_slow_query_threshold`],
+    /// `None` disables the profiler.
+    SlowQueryThreshold(Option<Duration>),
+    /// The default [`WalSyncPolicy`] of the transactions begun afterward.
+    WalSyncPolicy(WalSyncPolicy),
+}
+
+/// The checksum algorithm used to verify the data blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChecksumType {
+    Crc32c,
+    XxHash64,
+    #[default]
+    Xxh3,
+}
+
+impl ChecksumType {
+
+    /// The value of `rocksdb::ChecksumType`
+    pub(crate) fn to_rocksdb(self) -> libc::c_char {
+        match self {
+            ChecksumType::Crc32c => 1,
+            ChecksumType::XxHash64 => 3,
+            ChecksumType::Xxh3 => 4,
+        }
+    }
+
+}
+
+/// Called with the database, its estimated size and the limit when the size exceeds
+/// [`ConfigBuilder::set_max_db_size`]. Delete the data to evict and call
+/// [`Database::vacuum`] to reclaim the space, the deleted data still take
+/// the space until they're compacted.
+pub type QuotaEvictor = Arc<dyn Fn(&Database, u64, u64) -> crate::Result<()> + Send + Sync>;
+
+/// What to do with the writes when the database exceeds its max size.
+#[derive(Clone, Default)]
+pub enum QuotaPolicy {
+    /// Reject the inserts, the updates and the index builds with
+    /// [`crate::Error::QuotaExceeded`]. The deletes are always allowed.
+    #[default]
+    Reject,
+    /// Call the evictor before the write, and reject the write
+    /// if the database still exceeds the max size after that.
+    ///
+    /// The evictor runs its own transactions, so it may conflict with the writes
+    /// of the transaction which triggers it.
+    Evict(QuotaEvictor),
+}
+
+/// Generates the `_id` of a document inserted into the collections of [`crate::options::IdStrategy::Custom`].
+pub type IdGenerator = Arc<dyn Fn(&Document) -> crate::Result<Bson> + Send + Sync>;
+
 ///
 /// Config builder for the database
 ///
@@ -80,6 +178,296 @@ impl ConfigBuilder {
         self
     }
 
+    pub fn get_memtable_size(&self) -> u64 {
+        self.inner.memtable_size
+    }
+
+    /// Set the capacity of a single memtable in bytes.
+    /// A memtable is flushed to disk when it is full.
+    pub fn set_memtable_size(&mut self, v: u64) -> &mut Self {
+        self.inner.memtable_size = v;
+        self
+    }
+
+    pub fn get_max_immutable_memtables(&self) -> u32 {
+        self.inner.max_immutable_memtables
+    }
+
+    /// Set how many full memtables waiting to be flushed
+    /// can be kept in memory at the same time.
+    pub fn set_max_immutable_memtables(&mut self, v: u32) -> &mut Self {
+        self.inner.max_immutable_memtables = v;
+        self
+    }
+
+    pub fn get_level0_slowdown_writes_trigger(&self) -> u32 {
+        self.inner.level0_slowdown_writes_trigger
+    }
+
+    /// Writes are slowed down when the number of level-0 files
+    /// reaches this value.
+    pub fn set_level0_slowdown_writes_trigger(&mut self, v: u32) -> &mut Self {
+        self.inner.level0_slowdown_writes_trigger = v;
+        self
+    }
+
+    pub fn get_level0_stop_writes_trigger(&self) -> u32 {
+        self.inner.level0_stop_writes_trigger
+    }
+
+    /// Writes are stopped when the number of level-0 files
+    /// reaches this value.
+    pub fn set_level0_stop_writes_trigger(&mut self, v: u32) -> &mut Self {
+        self.inner.level0_stop_writes_trigger = v;
+        self
+    }
+
+    pub fn get_soft_pending_compaction_bytes_limit(&self) -> u64 {
+        self.inner.soft_pending_compaction_bytes_limit
+    }
+
+    /// Writes are slowed down when the estimated bytes waiting for
+    /// the compaction exceed this value, 0 disables the slowdown.
+    pub fn set_soft_pending_compaction_bytes_limit(&mut self, v: u64) -> &mut Self {
+        self.inner.soft_pending_compaction_bytes_limit = v;
+        self
+    }
+
+    pub fn get_hard_pending_compaction_bytes_limit(&self) -> u64 {
+        self.inner.hard_pending_compaction_bytes_limit
+    }
+
+    /// Writes are stopped when the estimated bytes waiting for
+    /// the compaction exceed this value, 0 disables the stop.
+    pub fn set_hard_pending_compaction_bytes_limit(&mut self, v: u64) -> &mut Self {
+        self.inner.hard_pending_compaction_bytes_limit = v;
+        self
+    }
+
+    pub fn get_wal_sync_policy(&self) -> WalSyncPolicy {
+        self.inner.wal_sync_policy
+    }
+
+    /// Set the default [`WalSyncPolicy`] of the transactions.
+    /// It can be overridden by [`crate::options::TransactionOptions`].
+    pub fn set_wal_sync_policy(&mut self, v: WalSyncPolicy) -> &mut Self {
+        self.inner.wal_sync_policy = v;
+        self
+    }
+
+    pub fn get_wal_ttl_seconds(&self) -> u64 {
+        self.inner.wal_ttl_seconds
+    }
+
+    /// Keep the obsolete WAL files in the archive for the given seconds
+    /// instead of deleting them, so they can be shipped by [`crate::Database::ship_wal`].
+    /// 0 means the WAL files are deleted as soon as they are obsolete.
+    pub fn set_wal_ttl_seconds(&mut self, v: u64) -> &mut Self {
+        self.inner.wal_ttl_seconds = v;
+        self
+    }
+
+    pub fn get_wal_size_limit_mb(&self) -> u64 {
+        self.inner.wal_size_limit_mb
+    }
+
+    /// Limit the total size of the archived WAL files in MB.
+    /// 0 means no limit.
+    pub fn set_wal_size_limit_mb(&mut self, v: u64) -> &mut Self {
+        self.inner.wal_size_limit_mb = v;
+        self
+    }
+
+    pub fn get_checksum_type(&self) -> ChecksumType {
+        self.inner.checksum_type
+    }
+
+    /// Set the checksum algorithm of the newly written blocks.
+    /// The checksums are verified on every read.
+    pub fn set_checksum_type(&mut self, v: ChecksumType) -> &mut Self {
+        self.inner.checksum_type = v;
+        self
+    }
+
+    pub fn get_paranoid_checks(&self) -> bool {
+        self.inner.paranoid_checks
+    }
+
+    /// Check the files more aggressively on opening and
+    /// stop the writes when a corruption is detected.
+    pub fn set_paranoid_checks(&mut self, v: bool) -> &mut Self {
+        self.inner.paranoid_checks = v;
+        self
+    }
+
+    pub fn get_max_document_size(&self) -> u32 {
+        self.inner.max_document_size
+    }
+
+    /// The max size of the encoded documents in bytes, 16MB by default.
+    /// The inserts and updates of the larger documents fail
+    /// with [`crate::Error::DocumentTooLarge`] before they are serialized.
+    pub fn set_max_document_size(&mut self, v: u32) -> &mut Self {
+        self.inner.max_document_size = v;
+        self
+    }
+
+    pub fn get_slow_query_threshold(&self) -> Option<Duration> {
+        self.inner.slow_query_threshold
+    }
+
+    /// Record the queries running longer than `v` with their plans into the collection
+    /// [`crate::PROFILE_COLLECTION`] of each database, read them by [`crate::Database::profile_entries`].
+    /// Set `Duration::ZERO` to record all the queries. Disabled by default.
+    pub fn set_slow_query_threshold(&mut self, v: Duration) -> &mut Self {
+        self.inner.slow_query_threshold = Some(v);
+        self
+    }
+
+    pub fn get_max_db_size(&self) -> Option<u64> {
+        self.inner.max_db_size
+    }
+
+    /// Limit the estimated size of the database to `v` bytes, counted from the table files
+    /// and the memtables. The writes over the limit are handled by the [`QuotaPolicy`].
+    pub fn set_max_db_size(&mut self, v: u64) -> &mut Self {
+        self.inner.max_db_size = Some(v);
+        self
+    }
+
+    pub fn set_quota_policy(&mut self, v: QuotaPolicy) -> &mut Self {
+        self.inner.quota_policy = v;
+        self
+    }
+
+    pub fn get_mmap_reads(&self) -> bool {
+        self.inner.mmap_reads
+    }
+
+    /// Read the table files by mmap instead of pread.
+    /// It avoids the copies and the syscalls on read-mostly workloads,
+    /// but the address space of the process grows with the files.
+    /// The WAL and the files being written are not affected.
+    pub fn set_mmap_reads(&mut self, v: bool) -> &mut Self {
+        self.inner.mmap_reads = v;
+        self
+    }
+
+    pub fn get_direct_io_writes(&self) -> bool {
+        self.inner.direct_io_writes
+    }
+
+    /// Write the table files of the flushes and the compactions with O_DIRECT,
+    /// so the bulk loads don't evict the page cache of the other processes.
+    /// The file system must support O_DIRECT. The WAL is still buffered.
+    pub fn set_direct_io_writes(&mut self, v: bool) -> &mut Self {
+        self.inner.direct_io_writes = v;
+        self
+    }
+
+    /// Store the lower levels of the LSM tree in `path`, e.g. on a large slow storage.
+    /// The upper levels are kept in the directory of the database
+    /// as long as they fit in `hot_size_limit` bytes.
+    /// [`crate::Database::vacuum`] rewrites all the data into the cold path.
+    ///
+    /// The snapshots and the backups fail with [`crate::Error::ColdPathNotSupported`],
+    /// because they would miss the files in the cold path.
+    pub fn set_cold_path<P: AsRef<Path>>(&mut self, path: P, hot_size_limit: u64) -> &mut Self {
+        self.inner.cold_path = Some(path.as_ref().to_path_buf());
+        self.inner.hot_size_limit = hot_size_limit;
+        self
+    }
+
+    /// Store the files by the backend instead of the file system of the OS.
+    pub fn set_storage_backend(&mut self, v: Arc<dyn StorageBackend>) -> &mut Self {
+        self.inner.storage_backend = Some(v);
+        self
+    }
+
+    /// Cache the blocks read from the table files in `v`.
+    /// Clone the same cache into the configs of multiple databases
+    /// to bound them by one budget. A 32MB LRU cache is created
+    /// for each database if not set.
+    pub fn set_block_cache(&mut self, v: BlockCache) -> &mut Self {
+        self.inner.block_cache = Some(v);
+        self
+    }
+
+    /// Attach a [`FaultInjector`] to apply faults to the commits.
+    #[cfg(feature = "fault-injection")]
+    pub fn set_fault_injector(&mut self, v: FaultInjector) -> &mut Self {
+        self.inner.fault_injector = Some(v);
+        self
+    }
+
+    /// Report the operations and the storage to `v`.
+    #[cfg(feature = "metrics")]
+    pub fn set_metrics_recorder(&mut self, v: Arc<dyn MetricsRecorder>) -> &mut Self {
+        self.inner.metrics_recorder = Some(v);
+        self
+    }
+
+    /// Register the generator of [`crate::options::IdStrategy::Custom`] named `name`.
+    pub fn set_id_generator(&mut self, name: &str, v: IdGenerator) -> &mut Self {
+        self.inner.id_generators.insert(name.to_string(), v);
+        self
+    }
+
+    /// Register the tokenizer named `name` for the text indexes, see [`crate::IndexOptions::analyzer`].
+    /// The name must not be one of the built-in analyzers.
+    ///
+    /// The tokenizers are not saved in the database, so the same ones must be registered
+    /// every time the database is opened.
+    pub fn set_tokenizer(&mut self, name: &str, v: Arc<dyn Tokenizer>) -> &mut Self {
+        self.inner.tokenizers.insert(name.to_string(), v);
+        self
+    }
+
+    /// Register the ordering named `name` for the primary keys,
+    /// see [`crate::options::CreateCollectionOptions::key_ordering`].
+    /// The name must not be one of the built-in orderings.
+    ///
+    /// The orderings are not saved in the database, so the same ones must be registered
+    /// every time the database is opened.
+    pub fn set_key_ordering(&mut self, name: &str, v: Arc<dyn KeyOrdering>) -> &mut Self {
+        self.inner.key_orderings.insert(name.to_string(), v);
+        self
+    }
+
+    pub fn get_sync_tracking(&self) -> bool {
+        self.inner.sync_tracking
+    }
+
+    /// Track the changes of the documents for [`crate::Database::sync_with`].
+    /// Only the changes made while the tracking is enabled are synced.
+    pub fn set_sync_tracking(&mut self, v: bool) -> &mut Self {
+        self.inner.sync_tracking = v;
+        self
+    }
+
+    pub fn get_change_pre_images(&self) -> bool {
+        self.inner.change_pre_images
+    }
+
+    /// Log the documents before the writes in the WAL, so the
+    /// [`crate::results::ChangeEvent::before`] of [`crate::Database::ship_changes`] is set.
+    /// The documents are read and locked in the write path, which slows down the writes.
+    pub fn set_change_pre_images(&mut self, v: bool) -> &mut Self {
+        self.inner.change_pre_images = v;
+        self
+    }
+
+    pub fn get_auto_upgrade(&self) -> bool {
+        self.inner.auto_upgrade
+    }
+
+    /// Upgrade the format of an older database when it's opened, enabled by default.
+    /// Without it, the database is opened in its format until [`crate::Database::upgrade`].
+    pub fn set_auto_upgrade(&mut self, v: bool) -> &mut Self {
+        self.inner.auto_upgrade = v;
+        self
+    }
+
     pub fn take(self) -> Config {
         self.inner
     }
@@ -93,9 +481,42 @@ pub struct Config {
     pub lsm_page_size:     u32,
     pub lsm_block_size:    u32,
     pub sync_log_count:    u64,
+    pub memtable_size:     u64,
+    pub max_immutable_memtables: u32,
+    pub level0_slowdown_writes_trigger: u32,
+    pub level0_stop_writes_trigger: u32,
+    pub soft_pending_compaction_bytes_limit: u64,
+    pub hard_pending_compaction_bytes_limit: u64,
+    pub wal_sync_policy:   WalSyncPolicy,
+    pub wal_ttl_seconds:   u64,
+    pub wal_size_limit_mb: u64,
+    pub checksum_type:     ChecksumType,
+    pub paranoid_checks:   bool,
+    pub max_document_size: u32,
+    pub mmap_reads:        bool,
+    pub direct_io_writes:  bool,
+    pub cold_path:         Option<PathBuf>,
+    pub hot_size_limit:    u64,
+    pub storage_backend:   Option<Arc<dyn StorageBackend>>,
+    pub block_cache:       Option<BlockCache>,
+    pub slow_query_threshold: Option<Duration>,
+    pub max_db_size:       Option<u64>,
+    pub quota_policy:      QuotaPolicy,
+    pub id_generators:     HashMap<String, IdGenerator>,
+    pub tokenizers:        HashMap<String, Arc<dyn Tokenizer>>,
+    pub key_orderings:     HashMap<String, Arc<dyn KeyOrdering>>,
+    pub sync_tracking:     bool,
+    pub change_pre_images: bool,
+    pub auto_upgrade:      bool,
+    #[cfg(feature = "fault-injection")]
+    pub fault_injector:    Option<FaultInjector>,
+    #[cfg(feature = "metrics")]
+    pub metrics_recorder:  Option<Arc<dyn MetricsRecorder>>,
 }
 
 const SYNC_LOG_COUNT: u64 = 1000;
+const MEMTABLE_SIZE: u64 = 64 * 1024 * 1024;
+const MAX_DOCUMENT_SIZE: u32 = 16 * 1024 * 1024;
 
 impl Default for Config {
 
@@ -106,6 +527,37 @@ impl Default for Config {
             lsm_page_size: 4096,
             lsm_block_size: 4 * 1024 * 1024,
             sync_log_count: SYNC_LOG_COUNT,
+            memtable_size: MEMTABLE_SIZE,
+            max_immutable_memtables: 1,
+            level0_slowdown_writes_trigger: 20,
+            level0_stop_writes_trigger: 36,
+            soft_pending_compaction_bytes_limit: 64 * 1024 * 1024 * 1024,
+            hard_pending_compaction_bytes_limit: 256 * 1024 * 1024 * 1024,
+            wal_sync_policy: WalSyncPolicy::default(),
+            wal_ttl_seconds: 0,
+            wal_size_limit_mb: 0,
+            checksum_type: ChecksumType::default(),
+            paranoid_checks: true,
+            max_document_size: MAX_DOCUMENT_SIZE,
+            mmap_reads: false,
+            direct_io_writes: false,
+            cold_path: None,
+            hot_size_limit: 0,
+            storage_backend: None,
+            block_cache: None,
+            slow_query_threshold: None,
+            max_db_size: None,
+            quota_policy: QuotaPolicy::default(),
+            id_generators: HashMap::new(),
+            tokenizers: HashMap::new(),
+            key_orderings: HashMap::new(),
+            sync_tracking: false,
+            change_pre_images: false,
+            auto_upgrade: true,
+            #[cfg(feature = "fault-injection")]
+            fault_injector: None,
+            #[cfg(feature = "metrics")]
+            metrics_recorder: None,
         }
     }
 
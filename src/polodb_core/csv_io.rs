@@ -0,0 +1,227 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Export and import the documents as CSV.
+//!
+//! The columns are the dot paths of the fields, e.g. `address.city`,
+//! the embedded documents are flattened into the columns on export
+//! and rebuilt from the paths on import.
+
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use bson::{doc, oid::ObjectId, Bson, DateTime, Document};
+use crate::options::{CsvExportOptions, CsvImportOptions, CsvType};
+use crate::utils::bson::format_value;
+use crate::{CollectionT, Database, Error, Result};
+
+const INSERT_BATCH_SIZE: usize = 1000;
+
+fn csv_error(err: csv::Error) -> Error {
+    if !err.is_io_error() {
+        // the message has the position of the record
+        return Error::ParseError(err.to_string());
+    }
+    match err.into_kind() {
+        csv::ErrorKind::Io(err) => Error::from(err),
+        _ => unreachable!(),
+    }
+}
+
+pub(crate) fn export_csv<W: Write>(db: &Database, col_name: &str, writer: W, options: CsvExportOptions) -> Result<u64> {
+    let mut cursor = db.collection::<Document>(col_name)
+        .find(options.filter.unwrap_or_default())
+        .run()?
+        .peekable();
+
+    let fields = match options.fields {
+        Some(fields) => fields,
+        None => match cursor.peek() {
+            Some(Ok(first)) => {
+                let mut fields = Vec::new();
+                flatten_paths(first, "", &mut fields);
+                fields
+            }
+            _ => Vec::new(),
+        },
+    };
+
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(options.delimiter.unwrap_or(b','))
+        .from_writer(writer);
+    if options.header.unwrap_or(true) && !fields.is_empty() {
+        writer.write_record(&fields).map_err(csv_error)?;
+    }
+
+    let mut count: u64 = 0;
+    for doc in cursor {
+        let doc = doc?;
+        let record = fields.iter().map(|field| {
+            match get_path(&doc, field) {
+                Some(value) => format_value(value),
+                None => String::new(),
+            }
+        });
+        writer.write_record(record).map_err(csv_error)?;
+        count += 1;
+    }
+    writer.flush()?;
+
+    Ok(count)
+}
+
+fn flatten_paths(doc: &Document, prefix: &str, result: &mut Vec<String>) {
+    for (key, value) in doc {
+        let path = format!("{}{}", prefix, key);
+        match value {
+            Bson::Document(doc) if !doc.is_empty() => flatten_paths(doc, &format!("{}.", path), result),
+            _ => result.push(path),
+        }
+    }
+}
+
+/// The value of the dot path, the parts of the path into an array are the indexes.
+fn get_path<'a>(doc: &'a Document, path: &str) -> Option<&'a Bson> {
+    let mut parts = path.split('.');
+    let mut value = doc.get(parts.next()?)?;
+    for part in parts {
+        value = match value {
+            Bson::Document(doc) => doc.get(part)?,
+            Bson::Array(items) => items.get(part.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+/// Insert the document at the dot path, creating the embedded documents.
+fn set_path(doc: &mut Document, path: &str, value: Bson) -> std::result::Result<(), String> {
+    match path.split_once('.') {
+        None => {
+            doc.insert(path, value);
+            Ok(())
+        }
+        Some((first, rest)) => {
+            let child = doc.entry(first.to_string()).or_insert_with(|| Bson::Document(Document::new()));
+            match child {
+                Bson::Document(child) => set_path(child, rest, value),
+                _ => Err(format!("the column '{}' conflicts with the column '{}'", path, first)),
+            }
+        }
+    }
+}
+
+fn infer_value(cell: &str) -> Bson {
+    if cell.eq_ignore_ascii_case("true") {
+        return Bson::Boolean(true);
+    }
+    if cell.eq_ignore_ascii_case("false") {
+        return Bson::Boolean(false);
+    }
+    let digits = cell.strip_prefix('-').unwrap_or(cell);
+    let leading_zero = digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.");
+    if !leading_zero && digits.starts_with(|c: char| c.is_ascii_digit()) {
+        if let Ok(value) = cell.parse::<i32>() {
+            return Bson::Int32(value);
+        }
+        if let Ok(value) = cell.parse::<i64>() {
+            return Bson::Int64(value);
+        }
+        if let Ok(value) = cell.parse::<f64>() {
+            return Bson::Double(value);
+        }
+    }
+    Bson::String(cell.to_string())
+}
+
+fn parse_value(cell: &str, ty: CsvType) -> std::result::Result<Option<Bson>, String> {
+    if cell.is_empty() && ty != CsvType::String {
+        return Ok(None);
+    }
+    let value = match ty {
+        CsvType::Auto => infer_value(cell),
+        CsvType::String => Bson::String(cell.to_string()),
+        CsvType::Int32 => Bson::Int32(cell.trim().parse().map_err(|_| format!("'{}' is not an int32", cell))?),
+        CsvType::Int64 => Bson::Int64(cell.trim().parse().map_err(|_| format!("'{}' is not an int64", cell))?),
+        CsvType::Double => Bson::Double(cell.trim().parse().map_err(|_| format!("'{}' is not a double", cell))?),
+        CsvType::Boolean => match cell.trim().to_ascii_lowercase().as_str() {
+            "true" => Bson::Boolean(true),
+            "false" => Bson::Boolean(false),
+            _ => return Err(format!("'{}' is not a boolean", cell)),
+        },
+        CsvType::Date => {
+            let cell = cell.trim();
+            let date = if cell.len() == 10 {
+                DateTime::parse_rfc3339_str(format!("{}T00:00:00Z", cell))
+            } else {
+                DateTime::parse_rfc3339_str(cell)
+            };
+            Bson::DateTime(date.map_err(|_| format!("'{}' is not a date", cell))?)
+        }
+        CsvType::ObjectId => Bson::ObjectId(ObjectId::parse_str(cell.trim()).map_err(|_| format!("'{}' is not an ObjectId", cell))?),
+        CsvType::Json => {
+            let json: serde_json::Value = serde_json::from_str(cell).map_err(|err| err.to_string())?;
+            Bson::try_from(json).map_err(|err| err.to_string())?
+        }
+    };
+    Ok(Some(value))
+}
+
+pub(crate) fn import_csv<R: Read>(db: &Database, col_name: &str, reader: R, options: CsvImportOptions) -> Result<u64> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(options.delimiter.unwrap_or(b','))
+        .from_reader(reader);
+    let headers: Vec<String> = reader.headers().map_err(csv_error)?.iter().map(String::from).collect();
+    let column_types = options.column_types.unwrap_or_default();
+    let types: Vec<CsvType> = headers.iter()
+        .map(|header| column_types.get(header).copied().unwrap_or_default())
+        .collect();
+
+    // all the rows are imported, or none of them
+    let txn = db.start_transaction()?;
+    let col = txn.collection::<Document>(col_name);
+    if options.drop.unwrap_or(false) {
+        col.delete_many(doc! {})?;
+    }
+
+    let mut count: u64 = 0;
+    let mut batch = Vec::with_capacity(INSERT_BATCH_SIZE);
+    for record in reader.records() {
+        let record = record.map_err(csv_error)?;
+        let line = record.position().map(|position| position.line()).unwrap_or_default();
+        let mut doc = Document::new();
+        for ((header, ty), cell) in headers.iter().zip(&types).zip(record.iter()) {
+            let result = parse_value(cell, *ty)
+                .and_then(|value| match value {
+                    Some(value) => set_path(&mut doc, header, value),
+                    None => Ok(()),
+                });
+            if let Err(message) = result {
+                return Err(Error::ParseError(format!("line {}, column '{}': {}", line, header, message)));
+            }
+        }
+        batch.push(doc);
+        if batch.len() == INSERT_BATCH_SIZE {
+            count += batch.len() as u64;
+            col.insert_many(std::mem::take(&mut batch))?;
+        }
+    }
+    if !batch.is_empty() {
+        count += batch.len() as u64;
+        col.insert_many(batch)?;
+    }
+    txn.commit()?;
+
+    Ok(count)
+}
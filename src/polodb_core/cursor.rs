@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use std::cmp::Ordering;
+use std::ops::Bound;
 use std::sync::Arc;
 use bson::Bson;
 use crate::db::RocksDBIterator;
@@ -25,6 +26,11 @@ pub(crate) struct Cursor {
     pub(crate)  prefix_bytes: Vec<u8>,
     kv_cursor:    RocksDBIterator,
     current_key:  Option<Arc<[u8]>>,
+    /// The range of the keys after the prefix, see [`Cursor::set_range`]
+    lower_bound:  Bound<Vec<u8>>,
+    upper_bound:  Bound<Vec<u8>>,
+    /// Iterate from the last key, see [`Cursor::set_reverse`]
+    reverse:      bool,
 }
 
 impl Cursor {
@@ -41,9 +47,26 @@ impl Cursor {
             prefix_bytes,
             kv_cursor,
             current_key: None,
+            lower_bound: Bound::Unbounded,
+            upper_bound: Bound::Unbounded,
+            reverse: false,
         }
     }
 
+    /// Limit the keys after the prefix to a range,
+    /// [`Cursor::reset`] seeks to the lower bound and
+    /// [`Cursor::has_next`] stops after the upper bound.
+    pub fn set_range(&mut self, lower_bound: Bound<Vec<u8>>, upper_bound: Bound<Vec<u8>>) {
+        self.lower_bound = lower_bound;
+        self.upper_bound = upper_bound;
+    }
+
+    /// Iterate the keys from the last one, [`Cursor::reset`] seeks to
+    /// the upper bound and [`Cursor::has_next`] stops before the lower bound.
+    pub fn set_reverse(&mut self, reverse: bool) {
+        self.reverse = reverse;
+    }
+
     #[inline]
     pub fn copy_data(&self) -> Result<Vec<u8>> {
         self.kv_cursor.copy_data()
@@ -59,30 +82,64 @@ impl Cursor {
 
 
     pub fn reset(&mut self) -> Result<()> {
-        self.kv_cursor.seek(self.prefix_bytes.as_slice());
+        if self.reverse {
+            return self.reset_reverse();
+        }
+        let (lower, excluded) = match &self.lower_bound {
+            Bound::Unbounded => {
+                self.kv_cursor.seek(self.prefix_bytes.as_slice());
+                self.kv_cursor.error()?;
 
-        if self.kv_cursor.valid() {
-            self.current_key = Some(self.kv_cursor.copy_key_arc()?);
+                if self.kv_cursor.valid() {
+                    self.current_key = Some(self.kv_cursor.copy_key_arc()?);
+                }
+
+                return Ok(());
+            }
+            Bound::Included(lower) => (lower.clone(), false),
+            Bound::Excluded(lower) => (lower.clone(), true),
+        };
+        let found = self.reset_by_pkey_buf(&lower)?;
+        if found && excluded {
+            self.next()?;
         }
+        Ok(())
+    }
+
+    fn reset_reverse(&mut self) -> Result<()> {
+        let (upper, excluded) = match &self.upper_bound {
+            Bound::Unbounded => match prefix_successor(&self.prefix_bytes) {
+                Some(successor) => (successor, true),
+                None => (Vec::new(), false),
+            },
+            Bound::Included(upper) => ([self.prefix_bytes.as_slice(), upper].concat(), false),
+            Bound::Excluded(upper) => ([self.prefix_bytes.as_slice(), upper].concat(), true),
+        };
+        if upper.is_empty() {
+            self.kv_cursor.seek_to_last();
+        } else {
+            self.kv_cursor.seek_for_prev(&upper);
+        }
+        self.kv_cursor.error()?;
 
+        if !self.kv_cursor.valid() {
+            self.current_key = None;
+            return Ok(());
+        }
+        self.current_key = Some(self.kv_cursor.copy_key_arc()?);
+        if excluded && self.current_key.as_deref() == Some(upper.as_slice()) {
+            self.next()?;
+        }
         Ok(())
     }
 
     pub fn reset_by_pkey(&mut self, pkey: &Bson) -> Result<bool> {
         let mut key_buffer = self.prefix_bytes.clone();
-
-        {
-            let primary_key_buffer = crate::utils::bson::stacked_key([
-                pkey,
-            ])?;
-
-            key_buffer.extend_from_slice(&primary_key_buffer);
-        }
+        crate::utils::bson::stacked_key_bytes(&mut key_buffer, pkey)?;
 
         self.reset_by_custom_key(key_buffer.as_slice())
     }
 
-    #[allow(dead_code)]
     pub fn reset_by_pkey_buf(&mut self, pkey_buffer: &[u8]) -> Result<bool> {
         let mut key_buffer = self.prefix_bytes.clone();
 
@@ -93,6 +150,7 @@ impl Cursor {
 
     fn reset_by_custom_key(&mut self, key_buffer: &[u8]) -> Result<bool> {
         self.kv_cursor.seek(key_buffer);
+        self.kv_cursor.error()?;
 
         if self.kv_cursor.valid() {
             self.current_key = Some(self.kv_cursor.copy_key_arc()?);
@@ -104,6 +162,9 @@ impl Cursor {
         Ok(false)
     }
 
+    /// Seek to the first index entry of `index_value`.
+    /// The prefix of the cursor is narrowed to the value,
+    /// so the entries of the other values are not iterated.
     pub fn reset_by_index_value(&mut self, index_value: &Bson) -> Result<bool> {
         let key_buffer = {
             let mut key_buffer = self.prefix_bytes.clone();
@@ -117,16 +178,17 @@ impl Cursor {
         };
 
         self.kv_cursor.seek(key_buffer.as_slice());
+        self.kv_cursor.error()?;
 
+        let mut found = false;
         if self.kv_cursor.valid() {
-            self.current_key = Some(self.kv_cursor.copy_key_arc()?);
-            if let Some(found) = &self.current_key {
-                let starts_with = found.as_ref().starts_with(key_buffer.as_slice());
-                return Ok(starts_with);
-            }
+            let current_key = self.kv_cursor.copy_key_arc()?;
+            found = current_key.as_ref().starts_with(key_buffer.as_slice());
+            self.current_key = Some(current_key);
         }
+        self.prefix_bytes = key_buffer;
 
-        Ok(false)
+        Ok(found)
     }
 
     pub fn peek_key(&self) -> Option<Arc<[u8]>> {
@@ -142,14 +204,32 @@ impl Cursor {
             if !current_key.starts_with(self.prefix_bytes.as_slice()) {
                 return false;
             }
-            true
+            let key = &current_key[self.prefix_bytes.len()..];
+            if self.reverse {
+                return match &self.lower_bound {
+                    Bound::Included(lower) => key >= lower.as_slice(),
+                    Bound::Excluded(lower) => key > lower.as_slice(),
+                    Bound::Unbounded => true,
+                };
+            }
+            match &self.upper_bound {
+                Bound::Included(upper) => key <= upper.as_slice(),
+                Bound::Excluded(upper) => key < upper.as_slice(),
+                Bound::Unbounded => true,
+            }
         } else {
             false
         }
     }
 
     pub fn next(&mut self) -> Result<()> {
-        self.kv_cursor.next();
+        if self.reverse {
+            self.kv_cursor.prev();
+        } else {
+            self.kv_cursor.next();
+        }
+        // an invalid iterator may be caused by corruption instead of the end
+        self.kv_cursor.error()?;
         if !self.kv_cursor.valid() {
             self.current_key = None;
             return Ok(());
@@ -159,3 +239,16 @@ impl Cursor {
     }
 
 }
+
+/// The smallest key greater than all the keys starting with `prefix`,
+/// `None` if every byte of the prefix is `0xFF`.
+fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut successor = prefix.to_vec();
+    while let Some(last) = successor.pop() {
+        if last < 0xFF {
+            successor.push(last + 1);
+            return Some(successor);
+        }
+    }
+    None
+}
@@ -0,0 +1,482 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The helpers of the queries over the `DateTime` fields.
+//!
+//! A [`DateBucket`] truncates the dates to a unit such as a day or a month
+//! in a time zone, and gives the [`DateRange`] of the bucket of a date.
+//! A range is a plain `{ "$gte": start, "$lt": end }` filter,
+//! so the dates are compared as they are stored.
+//!
+//! The buckets are also available in the filters with `$dateBucket`,
+//! and in the aggregation pipelines with `$dateTrunc` and `$dateAdd`.
+//!
+//! ```rust
+//! use polodb_core::{CollectionT, Database};
+//! use polodb_core::bson::{doc, DateTime, Document};
+//! use polodb_core::date::{DateBucket, DateUnit, TimeZone};
+//!
+//! let db = Database::open_memory().unwrap();
+//! let events = db.collection::<Document>("events");
+//! // 2023-11-14T22:13:20Z, 2023-11-15 in UTC+02:00
+//! let at = DateTime::from_millis(1_700_000_000_000);
+//! events.insert_one(doc! { "at": at }).unwrap();
+//!
+//! let bucket = DateBucket::new(DateUnit::Day).timezone(TimeZone::parse("+02:00").unwrap());
+//! let range = bucket.range(at);
+//! assert_eq!(range.start, DateTime::parse_rfc3339_str("2023-11-14T22:00:00Z").unwrap());
+//! assert_eq!(events.find(doc! { "at": range.to_filter() }).run().unwrap().count(), 1);
+//!
+//! let filter = doc! {
+//!     "at": { "$dateBucket": { "date": at, "unit": "day", "timezone": "+02:00" } },
+//! };
+//! assert_eq!(events.find(filter).run().unwrap().count(), 1);
+//! ```
+//!
+//! The time zones are UTC offsets such as `"+05:30"`,
+//! the named zones of the tz database are not supported.
+//! With the `chrono` or the `time` feature, the `chrono::DateTime` and
+//! the `time::OffsetDateTime` values convert into [`bson::DateTime`].
+
+use std::str::FromStr;
+use bson::{doc, Bson, DateTime, Document};
+use crate::{Error, Result};
+
+const MILLIS_PER_SECOND: i64 = 1000;
+const MILLIS_PER_MINUTE: i64 = 60 * MILLIS_PER_SECOND;
+const MILLIS_PER_HOUR: i64 = 60 * MILLIS_PER_MINUTE;
+const MILLIS_PER_DAY: i64 = 24 * MILLIS_PER_HOUR;
+
+/// The buckets of several units are aligned to 2000-01-01 like MongoDB.
+const REFERENCE_YEAR: i64 = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateUnit {
+    Millisecond,
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl DateUnit {
+
+    /// The length of the unit, `None` for the calendar months.
+    fn millis(self) -> Option<i64> {
+        match self {
+            DateUnit::Millisecond => Some(1),
+            DateUnit::Second => Some(MILLIS_PER_SECOND),
+            DateUnit::Minute => Some(MILLIS_PER_MINUTE),
+            DateUnit::Hour => Some(MILLIS_PER_HOUR),
+            DateUnit::Day => Some(MILLIS_PER_DAY),
+            DateUnit::Week => Some(7 * MILLIS_PER_DAY),
+            DateUnit::Month | DateUnit::Quarter | DateUnit::Year => None,
+        }
+    }
+
+    fn months(self) -> i64 {
+        match self {
+            DateUnit::Quarter => 3,
+            DateUnit::Year => 12,
+            _ => 1,
+        }
+    }
+
+}
+
+impl FromStr for DateUnit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<DateUnit> {
+        let unit = match s {
+            "millisecond" => DateUnit::Millisecond,
+            "second" => DateUnit::Second,
+            "minute" => DateUnit::Minute,
+            "hour" => DateUnit::Hour,
+            "day" => DateUnit::Day,
+            "week" => DateUnit::Week,
+            "month" => DateUnit::Month,
+            "quarter" => DateUnit::Quarter,
+            "year" => DateUnit::Year,
+            _ => return Err(Error::ValidationError(format!("unknown date unit '{}'", s))),
+        };
+        Ok(unit)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+
+    fn days_from_monday(self) -> i64 {
+        self as i64
+    }
+
+}
+
+impl FromStr for Weekday {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Weekday> {
+        let weekday = match s.to_ascii_lowercase().as_str() {
+            "monday" | "mon" => Weekday::Monday,
+            "tuesday" | "tue" => Weekday::Tuesday,
+            "wednesday" | "wed" => Weekday::Wednesday,
+            "thursday" | "thu" => Weekday::Thursday,
+            "friday" | "fri" => Weekday::Friday,
+            "saturday" | "sat" => Weekday::Saturday,
+            "sunday" | "sun" => Weekday::Sunday,
+            _ => return Err(Error::ValidationError(format!("unknown day of week '{}'", s))),
+        };
+        Ok(weekday)
+    }
+}
+
+/// A time zone with a fixed offset from UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TimeZone {
+    offset_minutes: i32,
+}
+
+impl TimeZone {
+
+    pub const UTC: TimeZone = TimeZone { offset_minutes: 0 };
+
+    /// The offset must be less than 24 hours.
+    pub fn from_offset_minutes(offset_minutes: i32) -> Result<TimeZone> {
+        if offset_minutes.abs() >= 24 * 60 {
+            return Err(Error::ValidationError(format!("the offset {} minutes of the time zone is out of range", offset_minutes)));
+        }
+        Ok(TimeZone { offset_minutes })
+    }
+
+    /// Parse `"UTC"`, `"Z"`, or an offset like `"+05:30"`, `"-0800"` and `"+02"`.
+    pub fn parse(s: &str) -> Result<TimeZone> {
+        let invalid = || Error::ValidationError(format!("unknown time zone '{}', only the UTC offsets are supported", s));
+        if s == "UTC" || s == "GMT" || s == "Z" {
+            return Ok(TimeZone::UTC);
+        }
+
+        let (sign, rest) = match s.chars().next() {
+            Some('+') => (1, &s[1..]),
+            Some('-') => (-1, &s[1..]),
+            _ => return Err(invalid()),
+        };
+        let digits: String = rest.chars().filter(|ch| *ch != ':').collect();
+        if !digits.chars().all(|ch| ch.is_ascii_digit()) || rest.matches(':').count() > 1 {
+            return Err(invalid());
+        }
+        let (hours, minutes) = match digits.len() {
+            2 => (&digits[..2], "0"),
+            4 => (&digits[..2], &digits[2..]),
+            _ => return Err(invalid()),
+        };
+        let hours: i32 = hours.parse().map_err(|_| invalid())?;
+        let minutes: i32 = minutes.parse().map_err(|_| invalid())?;
+        if minutes >= 60 {
+            return Err(invalid());
+        }
+        TimeZone::from_offset_minutes(sign * (hours * 60 + minutes))
+    }
+
+    pub fn offset_minutes(&self) -> i32 {
+        self.offset_minutes
+    }
+
+    fn to_local(self, millis: i64) -> i64 {
+        millis + self.offset_minutes as i64 * MILLIS_PER_MINUTE
+    }
+
+    fn to_utc(self, local_millis: i64) -> i64 {
+        local_millis - self.offset_minutes as i64 * MILLIS_PER_MINUTE
+    }
+
+}
+
+impl FromStr for TimeZone {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<TimeZone> {
+        TimeZone::parse(s)
+    }
+}
+
+/// The dates from `start` included to `end` excluded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateRange {
+    pub start: DateTime,
+    pub end: DateTime,
+}
+
+impl DateRange {
+
+    pub fn new(start: impl Into<DateTime>, end: impl Into<DateTime>) -> DateRange {
+        DateRange {
+            start: start.into(),
+            end: end.into(),
+        }
+    }
+
+    pub fn contains(&self, date: DateTime) -> bool {
+        self.start <= date && date < self.end
+    }
+
+    /// The filter of the range: `{ "$gte": start, "$lt": end }`.
+    pub fn to_filter(&self) -> Document {
+        doc! {
+            "$gte": self.start,
+            "$lt": self.end,
+        }
+    }
+
+}
+
+/// The buckets of `bin_size` units in a time zone, like `$dateTrunc` of MongoDB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateBucket {
+    unit: DateUnit,
+    bin_size: u32,
+    timezone: TimeZone,
+    start_of_week: Weekday,
+}
+
+impl DateBucket {
+
+    pub fn new(unit: DateUnit) -> DateBucket {
+        DateBucket {
+            unit,
+            bin_size: 1,
+            timezone: TimeZone::UTC,
+            start_of_week: Weekday::Sunday,
+        }
+    }
+
+    /// The number of units of a bucket, 0 is treated as 1.
+    pub fn bin_size(mut self, bin_size: u32) -> Self {
+        self.bin_size = bin_size.max(1);
+        self
+    }
+
+    pub fn timezone(mut self, timezone: TimeZone) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// The first day of the weeks, Sunday by default.
+    pub fn start_of_week(mut self, start_of_week: Weekday) -> Self {
+        self.start_of_week = start_of_week;
+        self
+    }
+
+    /// The start of the bucket of `date`.
+    pub fn trunc(&self, date: impl Into<DateTime>) -> DateTime {
+        let local = self.timezone.to_local(date.into().timestamp_millis());
+        let bin_size = self.bin_size as i64;
+        let reference = days_from_civil(REFERENCE_YEAR, 1, 1) * MILLIS_PER_DAY;
+
+        let truncated = match self.unit.millis() {
+            Some(unit_millis) => {
+                let reference = if self.unit == DateUnit::Week {
+                    let days = reference / MILLIS_PER_DAY;
+                    let back = (weekday(days) - self.start_of_week.days_from_monday()).rem_euclid(7);
+                    (days - back) * MILLIS_PER_DAY
+                } else {
+                    reference
+                };
+                let size = unit_millis * bin_size;
+                reference + (local - reference).div_euclid(size) * size
+            }
+            None => {
+                let (year, month, _) = civil_from_days(local.div_euclid(MILLIS_PER_DAY));
+                let months = (year - REFERENCE_YEAR) * 12 + month - 1;
+                let size = self.unit.months() * bin_size;
+                let start = months.div_euclid(size) * size;
+                days_from_civil(REFERENCE_YEAR + start.div_euclid(12), start.rem_euclid(12) + 1, 1) * MILLIS_PER_DAY
+            }
+        };
+
+        DateTime::from_millis(self.timezone.to_utc(truncated))
+    }
+
+    /// The range of the bucket of `date`.
+    pub fn range(&self, date: impl Into<DateTime>) -> DateRange {
+        let start = self.trunc(date);
+        let end = date_add(start, self.unit, self.bin_size as i64, self.timezone);
+        DateRange { start, end }
+    }
+
+    /// Read `unit`, `binSize`, `timezone` and `startOfWeek` of an operator.
+    pub(crate) fn from_doc(doc: &Document) -> Result<DateBucket> {
+        let unit = match doc.get("unit") {
+            Some(Bson::String(unit)) => unit.parse::<DateUnit>()?,
+            _ => return Err(Error::ValidationError("the date unit is required".to_string())),
+        };
+        let mut bucket = DateBucket::new(unit);
+
+        match doc.get("binSize") {
+            None => (),
+            Some(Bson::Int32(n)) if *n > 0 => bucket.bin_size = *n as u32,
+            Some(Bson::Int64(n)) if *n > 0 && *n <= u32::MAX as i64 => bucket.bin_size = *n as u32,
+            Some(other) => return Err(Error::ValidationError(format!("binSize must be a positive integer, found {}", other))),
+        }
+        if let Some(timezone) = doc.get("timezone") {
+            bucket.timezone = parse_timezone(timezone)?;
+        }
+        match doc.get("startOfWeek") {
+            None => (),
+            Some(Bson::String(s)) => bucket.start_of_week = s.parse()?,
+            Some(other) => return Err(Error::ValidationError(format!("startOfWeek must be a string, found {}", other))),
+        }
+
+        Ok(bucket)
+    }
+
+}
+
+/// Add `amount` units to `date`. The months are added in the time zone,
+/// and the day is clamped to the end of the month: 01-31 plus one month is 02-28.
+pub fn date_add(date: impl Into<DateTime>, unit: DateUnit, amount: i64, timezone: TimeZone) -> DateTime {
+    let millis = date.into().timestamp_millis();
+    let result = match unit.millis() {
+        Some(unit_millis) => millis.saturating_add(unit_millis.saturating_mul(amount)),
+        None => {
+            let local = timezone.to_local(millis);
+            let (year, month, day) = civil_from_days(local.div_euclid(MILLIS_PER_DAY));
+            let time = local.rem_euclid(MILLIS_PER_DAY);
+
+            let months = year * 12 + month - 1 + unit.months() * amount;
+            let (year, month) = (months.div_euclid(12), months.rem_euclid(12) + 1);
+            let day = day.min(days_in_month(year, month));
+            timezone.to_utc(days_from_civil(year, month, day) * MILLIS_PER_DAY + time)
+        }
+    };
+    DateTime::from_millis(result)
+}
+
+pub(crate) fn parse_timezone(value: &Bson) -> Result<TimeZone> {
+    match value {
+        Bson::String(s) => TimeZone::parse(s),
+        other => Err(Error::ValidationError(format!("the time zone must be a string, found {}", other))),
+    }
+}
+
+/// The day of week of the days since 1970-01-01, counted from Monday.
+fn weekday(days: i64) -> i64 {
+    (days + 3).rem_euclid(7)
+}
+
+// The civil calendar algorithms of Howard Hinnant.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    days_from_civil(next_year, next_month, 1) - days_from_civil(year, month, 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::DateTime;
+    use super::{date_add, DateBucket, DateUnit, TimeZone, Weekday};
+
+    fn date(s: &str) -> DateTime {
+        DateTime::parse_rfc3339_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_trunc() {
+        let at = date("2024-05-15T13:45:30.250Z");
+        assert_eq!(DateBucket::new(DateUnit::Hour).trunc(at), date("2024-05-15T13:00:00Z"));
+        assert_eq!(DateBucket::new(DateUnit::Minute).bin_size(15).trunc(at), date("2024-05-15T13:45:00Z"));
+        assert_eq!(DateBucket::new(DateUnit::Month).trunc(at), date("2024-05-01T00:00:00Z"));
+        assert_eq!(DateBucket::new(DateUnit::Quarter).trunc(at), date("2024-04-01T00:00:00Z"));
+        assert_eq!(DateBucket::new(DateUnit::Year).trunc(at), date("2024-01-01T00:00:00Z"));
+        // 2024-05-15 is a Wednesday
+        assert_eq!(DateBucket::new(DateUnit::Week).trunc(at), date("2024-05-12T00:00:00Z"));
+        assert_eq!(
+            DateBucket::new(DateUnit::Week).start_of_week(Weekday::Monday).trunc(at),
+            date("2024-05-13T00:00:00Z"),
+        );
+
+        let tz = TimeZone::parse("-08:00").unwrap();
+        assert_eq!(DateBucket::new(DateUnit::Day).timezone(tz).trunc(at), date("2024-05-15T08:00:00Z"));
+        let tz = TimeZone::parse("+05:30").unwrap();
+        assert_eq!(
+            DateBucket::new(DateUnit::Day).timezone(tz).trunc(date("2024-05-15T20:00:00Z")),
+            date("2024-05-15T18:30:00Z"),
+        );
+        assert_eq!(DateBucket::new(DateUnit::Day).trunc(date("1969-12-31T23:00:00Z")), date("1969-12-31T00:00:00Z"));
+    }
+
+    #[test]
+    fn test_date_add() {
+        let utc = TimeZone::UTC;
+        assert_eq!(date_add(date("2024-01-31T10:00:00Z"), DateUnit::Month, 1, utc), date("2024-02-29T10:00:00Z"));
+        assert_eq!(date_add(date("2024-03-31T10:00:00Z"), DateUnit::Month, -1, utc), date("2024-02-29T10:00:00Z"));
+        assert_eq!(date_add(date("2024-02-29T00:00:00Z"), DateUnit::Year, 1, utc), date("2025-02-28T00:00:00Z"));
+        assert_eq!(date_add(date("2024-05-15T00:00:00Z"), DateUnit::Week, 2, utc), date("2024-05-29T00:00:00Z"));
+
+        let range = DateBucket::new(DateUnit::Month).range(date("2024-12-20T00:00:00Z"));
+        assert_eq!(range.start, date("2024-12-01T00:00:00Z"));
+        assert_eq!(range.end, date("2025-01-01T00:00:00Z"));
+        assert!(range.contains(date("2024-12-31T23:59:59.999Z")));
+        assert!(!range.contains(range.end));
+    }
+
+    #[test]
+    fn test_parse_timezone() {
+        assert_eq!(TimeZone::parse("UTC").unwrap(), TimeZone::UTC);
+        assert_eq!(TimeZone::parse("+05:30").unwrap().offset_minutes(), 330);
+        assert_eq!(TimeZone::parse("-0800").unwrap().offset_minutes(), -480);
+        assert_eq!(TimeZone::parse("+02").unwrap().offset_minutes(), 120);
+        assert!(TimeZone::parse("Europe/Paris").is_err());
+        assert!(TimeZone::parse("+25:00").is_err());
+        assert!(TimeZone::parse("+05:75").is_err());
+    }
+
+}
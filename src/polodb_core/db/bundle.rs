@@ -0,0 +1,349 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A bundle packs the files of a database into a single buffer.
+//!
+//! The layout is:
+//!
+//! ```text
+//! magic: "PLDBPACK", version: u32, file count: u32,
+//! for each file: name length: u32, name, data length: u64, data
+//! ```
+//!
+//! All the integers are little-endian.
+
+use std::collections::BTreeMap;
+use std::convert::{TryFrom, TryInto};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use crate::storage_backend::{OpenMode, StorageBackend, StorageFile};
+use crate::{Error, Result};
+
+/// The directory of the database opened by the backends of the bundle.
+pub(crate) const BUNDLE_PATH: &str = "/polodb-bundle";
+
+const MAGIC: &[u8; 8] = b"PLDBPACK";
+const VERSION: u32 = 1;
+
+type FileData = Arc<Mutex<Vec<u8>>>;
+
+/// Collects the files written by the engine in memory.
+#[derive(Default)]
+pub(crate) struct BundleWriter {
+    files: Mutex<BTreeMap<PathBuf, FileData>>,
+}
+
+impl BundleWriter {
+
+    /// Pack the files except the logs and the lock.
+    pub(crate) fn pack(&self) -> Vec<u8> {
+        let files = self.files.lock().unwrap();
+        let files = files
+            .iter()
+            .filter_map(|(path, data)| {
+                let name = path.file_name()?.to_str()?;
+                if name == "LOCK" || name.starts_with("LOG") {
+                    return None;
+                }
+                Some((name, data.lock().unwrap()))
+            })
+            .collect::<Vec<_>>();
+
+        let mut result = Vec::new();
+        result.extend_from_slice(MAGIC);
+        result.extend_from_slice(&VERSION.to_le_bytes());
+        result.extend_from_slice(&(files.len() as u32).to_le_bytes());
+        for (name, data) in &files {
+            result.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            result.extend_from_slice(name.as_bytes());
+            result.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            result.extend_from_slice(data.as_slice());
+        }
+        result
+    }
+
+}
+
+struct WriterFile {
+    data: FileData,
+}
+
+impl StorageFile for WriterFile {
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.data.lock().unwrap();
+        Ok(read_slice(data.as_slice(), offset, buf))
+    }
+
+    fn write_at(&self, offset: u64, bytes: &[u8]) -> io::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        let end = offset as usize + bytes.len();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[offset as usize..end].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.data.lock().unwrap().len() as u64)
+    }
+
+    fn truncate(&self, size: u64) -> io::Result<()> {
+        self.data.lock().unwrap().resize(size as usize, 0);
+        Ok(())
+    }
+
+}
+
+impl StorageBackend for BundleWriter {
+
+    fn open(&self, path: &Path, mode: OpenMode) -> io::Result<Box<dyn StorageFile>> {
+        let mut files = self.files.lock().unwrap();
+        let data = match (files.get(path), mode) {
+            (Some(data), OpenMode::Truncate) => {
+                data.lock().unwrap().clear();
+                data.clone()
+            }
+            (Some(data), _) => data.clone(),
+            (None, OpenMode::Read) => return Err(io::ErrorKind::NotFound.into()),
+            (None, _) => {
+                let data = FileData::default();
+                files.insert(path.to_path_buf(), data.clone());
+                data
+            }
+        };
+        Ok(Box::new(WriterFile { data }))
+    }
+
+    fn delete(&self, path: &Path) -> io::Result<()> {
+        let mut files = self.files.lock().unwrap();
+        // the directory always exists
+        if files.remove(path).is_none() && path != Path::new(BUNDLE_PATH) {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut files = self.files.lock().unwrap();
+        let data = files.remove(from).ok_or(io::Error::from(io::ErrorKind::NotFound))?;
+        files.insert(to.to_path_buf(), data);
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        Ok(path == Path::new(BUNDLE_PATH) || self.files.lock().unwrap().contains_key(path))
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<String>> {
+        let files = self.files.lock().unwrap();
+        Ok(list_files(files.keys().map(|path| path.as_path()), dir))
+    }
+
+    fn create_dir(&self, _dir: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+}
+
+/// Serves the files of a bundle without copying them.
+pub(crate) struct BundleReader<B> {
+    buffer: B,
+    // the name and the range of the files in the buffer
+    files: BTreeMap<PathBuf, (usize, usize)>,
+}
+
+impl<B: AsRef<[u8]>> BundleReader<B> {
+
+    pub(crate) fn new(buffer: B) -> Result<BundleReader<B>> {
+        let files = BundleReader::<B>::parse(buffer.as_ref()).ok_or(Error::NotAValidDatabase)?;
+        Ok(BundleReader {
+            buffer,
+            files,
+        })
+    }
+
+    fn parse(mut input: &[u8]) -> Option<BTreeMap<PathBuf, (usize, usize)>> {
+        let total_len = input.len();
+        if take(&mut input, MAGIC.len())? != MAGIC {
+            return None;
+        }
+        if read_u32(&mut input)? != VERSION {
+            return None;
+        }
+        let count = read_u32(&mut input)?;
+        let mut files = BTreeMap::new();
+        for _ in 0..count {
+            let name_len = read_u32(&mut input)? as usize;
+            let name = std::str::from_utf8(take(&mut input, name_len)?).ok()?;
+            let data_len = usize::try_from(read_u64(&mut input)?).ok()?;
+            let begin = total_len - input.len();
+            take(&mut input, data_len)?;
+            files.insert(Path::new(BUNDLE_PATH).join(name), (begin, begin + data_len));
+        }
+        Some(files)
+    }
+
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if input.len() < len {
+        return None;
+    }
+    let (result, rest) = input.split_at(len);
+    *input = rest;
+    Some(result)
+}
+
+fn read_u32(input: &mut &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(take(input, 4)?.try_into().ok()?))
+}
+
+fn read_u64(input: &mut &[u8]) -> Option<u64> {
+    Some(u64::from_le_bytes(take(input, 8)?.try_into().ok()?))
+}
+
+fn read_slice(data: &[u8], offset: u64, buf: &mut [u8]) -> usize {
+    let offset = (offset as usize).min(data.len());
+    let n = buf.len().min(data.len() - offset);
+    buf[..n].copy_from_slice(&data[offset..offset + n]);
+    n
+}
+
+fn list_files<'a>(paths: impl Iterator<Item = &'a Path>, dir: &Path) -> Vec<String> {
+    paths
+        .filter(|path| path.parent() == Some(dir))
+        .filter_map(|path| Some(path.file_name()?.to_str()?.to_string()))
+        .collect()
+}
+
+fn read_only_error() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "the bundle is read-only")
+}
+
+struct ReaderFile<B> {
+    reader: Arc<BundleReader<B>>,
+    range: (usize, usize),
+}
+
+impl<B: AsRef<[u8]> + Send + Sync> StorageFile for ReaderFile<B> {
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let data = &self.reader.buffer.as_ref()[self.range.0..self.range.1];
+        Ok(read_slice(data, offset, buf))
+    }
+
+    fn write_at(&self, _offset: u64, _data: &[u8]) -> io::Result<()> {
+        Err(read_only_error())
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok((self.range.1 - self.range.0) as u64)
+    }
+
+    fn truncate(&self, _size: u64) -> io::Result<()> {
+        Err(read_only_error())
+    }
+
+}
+
+/// A read-only [`StorageBackend`] over a bundle.
+/// The opened files share the buffer.
+pub(crate) struct BundleBackend<B> {
+    reader: Arc<BundleReader<B>>,
+}
+
+impl<B> BundleBackend<B> {
+
+    pub(crate) fn new(reader: BundleReader<B>) -> BundleBackend<B> {
+        BundleBackend {
+            reader: Arc::new(reader),
+        }
+    }
+
+}
+
+impl<B: AsRef<[u8]> + Send + Sync + 'static> StorageBackend for BundleBackend<B> {
+
+    fn open(&self, path: &Path, mode: OpenMode) -> io::Result<Box<dyn StorageFile>> {
+        if mode != OpenMode::Read {
+            return Err(read_only_error());
+        }
+        let range = *self.reader.files.get(path).ok_or(io::Error::from(io::ErrorKind::NotFound))?;
+        Ok(Box::new(ReaderFile {
+            reader: self.reader.clone(),
+            range,
+        }))
+    }
+
+    fn delete(&self, _path: &Path) -> io::Result<()> {
+        Err(read_only_error())
+    }
+
+    fn rename(&self, _from: &Path, _to: &Path) -> io::Result<()> {
+        Err(read_only_error())
+    }
+
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        Ok(path == Path::new(BUNDLE_PATH) || self.reader.files.contains_key(path))
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<String>> {
+        Ok(list_files(self.reader.files.keys().map(|path| path.as_path()), dir))
+    }
+
+    fn create_dir(&self, _dir: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_and_parse() {
+        let writer = BundleWriter::default();
+        let dir = Path::new(BUNDLE_PATH);
+        writer.open(&dir.join("CURRENT"), OpenMode::Truncate).unwrap()
+            .write_at(0, b"MANIFEST-000001\n").unwrap();
+        writer.open(&dir.join("LOG"), OpenMode::Truncate).unwrap()
+            .write_at(0, b"log").unwrap();
+
+        let buffer = writer.pack();
+        let backend = BundleBackend::new(BundleReader::new(buffer).unwrap());
+        assert_eq!(backend.list(dir).unwrap(), vec!["CURRENT".to_string()]);
+
+        let file = backend.open(&dir.join("CURRENT"), OpenMode::Read).unwrap();
+        let mut buf = [0u8; 64];
+        let n = file.read_at(9, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"000001\n");
+        assert!(file.write_at(0, b"x").is_err());
+        assert!(backend.open(&dir.join("LOG"), OpenMode::Read).is_err());
+
+        assert!(BundleReader::new(&b"PLDBPACK"[..]).is_err());
+    }
+
+}
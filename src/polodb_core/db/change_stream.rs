@@ -0,0 +1,121 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use std::collections::HashMap;
+use crate::coll::key_ordering;
+use crate::db::rocksdb_wal::WalPreImage;
+use crate::results::ChangeEvent;
+use crate::sync::{self, split_document_key};
+use crate::{Result, WalOperation, WalRecord};
+
+/// The changes of the documents in a commit record,
+/// in the order of the first write of each document.
+///
+/// The writes of the same document are merged into one change,
+/// the `after` is the value of the last write.
+pub(crate) fn change_events(record: &WalRecord, pre_images: Vec<WalPreImage>) -> Result<Vec<ChangeEvent>> {
+    let mut events = Vec::<ChangeEvent>::new();
+    let mut positions = HashMap::<&[u8], usize>::new();
+
+    for op in &record.operations {
+        let (key, after) = match op {
+            WalOperation::Put { key, value } => (key, Some(bson::from_slice(value)?)),
+            WalOperation::Delete { key } => (key, None),
+            // the documents of a dropped partition are not known
+            WalOperation::DeleteRange { .. } => continue,
+        };
+        if !sync::is_document_key(key) {
+            continue;
+        }
+        if let Some(position) = positions.get(key.as_slice()) {
+            events[*position].after = after;
+            continue;
+        }
+        let (collection, id) = match split_document_key(key)? {
+            Some((collection, id)) => (collection, key_ordering::id_of_stored_key(id)?),
+            None => continue,
+        };
+        positions.insert(key.as_slice(), events.len());
+        events.push(ChangeEvent {
+            sequence: record.sequence,
+            collection,
+            id,
+            before: None,
+            after,
+        });
+    }
+
+    for pre_image in pre_images {
+        if let (Some(position), Some(value)) = (positions.get(pre_image.key.as_slice()), pre_image.value) {
+            events[*position].before = Some(bson::from_slice(&value)?);
+        }
+    }
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{doc, Bson};
+    use crate::db::rocksdb_wal::WalPreImage;
+    use crate::utils::bson::stacked_key;
+    use crate::{WalOperation, WalRecord};
+    use super::change_events;
+
+    fn document_key(id: i32) -> Vec<u8> {
+        stacked_key(&[Bson::String("test".to_string()), Bson::Int32(id)]).unwrap()
+    }
+
+    #[test]
+    fn test_change_events_merge_writes() {
+        let record = WalRecord {
+            sequence: 10,
+            operations: vec![
+                WalOperation::Put {
+                    key: document_key(1),
+                    value: bson::to_vec(&doc! { "_id": 1, "x": 1 }).unwrap(),
+                },
+                WalOperation::Delete {
+                    key: document_key(2),
+                },
+                WalOperation::Put {
+                    key: document_key(1),
+                    value: bson::to_vec(&doc! { "_id": 1, "x": 2 }).unwrap(),
+                },
+            ],
+        };
+        let pre_images = vec![
+            WalPreImage {
+                key: document_key(1),
+                value: None,
+            },
+            WalPreImage {
+                key: document_key(2),
+                value: Some(bson::to_vec(&doc! { "_id": 2 }).unwrap()),
+            },
+        ];
+
+        let events = change_events(&record, pre_images).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].id, Bson::Int32(1));
+        assert_eq!(events[0].before, None);
+        assert_eq!(events[0].after, Some(doc! { "_id": 1, "x": 2 }));
+        assert_eq!(events[1].collection, "test");
+        assert_eq!(events[1].before, Some(doc! { "_id": 2 }));
+        assert_eq!(events[1].after, None);
+        assert!(events.iter().all(|event| event.sequence == 10));
+    }
+
+}
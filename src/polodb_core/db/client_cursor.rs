@@ -14,9 +14,15 @@
 
 use std::fmt;
 use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
 use bson::Bson;
 use serde::de::DeserializeOwned;
-use crate::{Result};
+use crate::{CancellationToken, Result};
+use crate::coll::db_ref::RefResolver;
+use crate::db::parallel_scan::ParallelScan;
+use crate::metrics::OperationTimer;
+use crate::migration::CollectionTransforms;
 use crate::vm::{VM, VmState};
 
 /// A `ClientCursor` is used get the result of a query.
@@ -25,48 +31,149 @@ use crate::vm::{VM, VmState};
 /// Additionally, you can use deserialize_current() method to
 /// deserialize the documents returned by advance()
 pub struct ClientCursor<T: DeserializeOwned + Send + Sync> {
-    vm: VM,
+    source: CursorSource,
+    transforms: Option<Arc<CollectionTransforms>>,
+    ref_resolver: Option<RefResolver>,
+    timer: Option<OperationTimer>,
     _phantom: PhantomData<T>,
 }
 
+enum CursorSource {
+    Vm(Box<VM>),
+    Parallel(Box<ParallelScan>),
+}
+
 impl<T: DeserializeOwned + Send + Sync> ClientCursor<T> {
 
     pub(crate) fn new(vm: VM) -> ClientCursor<T> {
+        ClientCursor::new_with_source(CursorSource::Vm(Box::new(vm)))
+    }
+
+    /// Return the documents of the partitions scanned by other threads.
+    pub(crate) fn new_parallel(scan: ParallelScan) -> ClientCursor<T> {
+        ClientCursor::new_with_source(CursorSource::Parallel(Box::new(scan)))
+    }
+
+    fn new_with_source(source: CursorSource) -> ClientCursor<T> {
         ClientCursor{
-            vm,
+            source,
+            transforms: None,
+            ref_resolver: None,
+            timer: None,
             _phantom: Default::default(),
         }
     }
 
-    #[inline]
-    fn has_row(&self) -> bool {
-        self.vm.state == VmState::HasRow
+    /// Apply the lazy transforms of the collection to the returned documents.
+    pub(crate) fn set_transforms(&mut self, transforms: Option<Arc<CollectionTransforms>>) {
+        self.transforms = transforms;
+    }
+
+    /// Replace the DBRefs of the returned documents with the referenced documents.
+    pub(crate) fn set_ref_resolver(&mut self, ref_resolver: RefResolver) {
+        self.ref_resolver = Some(ref_resolver);
+    }
+
+    /// Report the latency of the query when it returns the last document or fails.
+    pub(crate) fn set_timer(&mut self, timer: OperationTimer) {
+        self.timer = Some(timer);
+    }
+
+    pub(crate) fn set_cancellation_token(&mut self, token: CancellationToken) {
+        match &mut self.source {
+            CursorSource::Vm(vm) => vm.set_cancellation_token(token),
+            CursorSource::Parallel(scan) => scan.set_cancellation_token(token),
+        }
+    }
+
+    pub(crate) fn cancellation_token(&self) -> Option<&CancellationToken> {
+        match &self.source {
+            CursorSource::Vm(vm) => vm.cancellation_token(),
+            CursorSource::Parallel(scan) => scan.cancellation_token(),
+        }
+    }
+
+    pub(crate) fn set_max_time(&mut self, max_time: Duration) {
+        match &mut self.source {
+            CursorSource::Vm(vm) => vm.set_max_time(max_time),
+            CursorSource::Parallel(scan) => scan.set_max_time(max_time),
+        }
     }
 
     #[inline]
     pub(crate) fn get(&self) -> &Bson {
-        self.vm.stack_top()
+        match &self.source {
+            CursorSource::Vm(vm) => vm.stack_top(),
+            CursorSource::Parallel(scan) => scan.current(),
+        }
     }
 
     pub fn advance(&mut self) -> Result<bool> {
-        if self.vm.state == VmState::Halt {
+        let result = self.advance_source();
+        if !matches!(result, Ok(true)) {
+            if let Some(timer) = self.timer.take() {
+                timer.finish(result.is_ok());
+            }
+        }
+        result
+    }
+
+    fn advance_source(&mut self) -> Result<bool> {
+        let vm = match &mut self.source {
+            CursorSource::Vm(vm) => vm,
+            CursorSource::Parallel(scan) => return scan.advance(),
+        };
+        if vm.state == VmState::Halt {
             return Ok(false);
         }
-        self.vm.execute()?;
-        Ok(self.has_row())
+        vm.execute()?;
+        Ok(vm.state == VmState::HasRow)
     }
 
     pub fn deserialize_current(&self) -> Result<T> {
-        let result: T = bson::from_bson(self.get().clone())?;
+        let current = self.get();
+        if let Some(doc) = current.as_document() {
+            let transformed = match &self.transforms {
+                Some(transforms) => transforms.apply(doc)?,
+                None => None,
+            };
+            if let Some(ref_resolver) = &self.ref_resolver {
+                let mut doc = transformed.unwrap_or_else(|| doc.clone());
+                ref_resolver.resolve(&mut doc)?;
+                return Ok(bson::from_document(doc)?);
+            }
+            if let Some(doc) = transformed {
+                return Ok(bson::from_document(doc)?);
+            }
+        }
+        let result: T = bson::from_bson(current.clone())?;
         Ok(result)
     }
 
 }
 
+#[cfg(feature = "async")]
+impl<T> ClientCursor<T>
+where
+    T: DeserializeOwned + Unpin + Send + Sync + 'static,
+{
+
+    /// Read the documents as a [`futures_core::Stream`].
+    /// A thread advances the cursor up to `prefetch` documents ahead of the stream, at least 1,
+    /// see [`crate::stream::DEFAULT_PREFETCH`].
+    pub fn into_stream(self, prefetch: usize) -> Result<crate::stream::CursorStream<T>> {
+        crate::stream::CursorStream::new(self, prefetch)
+    }
+
+}
+
 impl<T: DeserializeOwned + Send + Sync> fmt::Display for ClientCursor<T> {
 
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Program: \n\n{}", self.vm.program)
+        match &self.source {
+            CursorSource::Vm(vm) => write!(f, "Program: \n\n{}", vm.program),
+            CursorSource::Parallel(scan) => write!(f, "{}", scan),
+        }
     }
 
 }
@@ -82,7 +189,7 @@ impl<T> Iterator for ClientCursor<T>
         match test {
             Ok(false) => None,
             Ok(true) => {
-                Some(Ok(bson::from_bson(self.get().clone()).unwrap()))
+                Some(self.deserialize_current())
             }
             Err(err) =>{
                 Some(Err(err))
@@ -0,0 +1,166 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The operations in flight.
+//!
+//! The queries register themselves in the [`OperationRegistry`] of the database
+//! while their programs are running, and unregister when they're dropped.
+//! Each operation has a [`CancellationToken`] checked by the scans.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Instant;
+use bson::Document;
+use crate::results::CurrentOp;
+
+/// A flag to stop an operation cooperatively.
+///
+/// Pass a clone to [`crate::action::Find::cancellation_token`] or
+/// [`crate::action::Aggregate::cancellation_token`] and call [`CancellationToken::cancel`]
+/// from another thread. The scan of the operation stops with
+/// [`crate::Error::OperationCancelled`] before it reads the next document.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    /// The token of the caller, which cancels this token too
+    parent: Option<Box<CancellationToken>>,
+}
+
+impl CancellationToken {
+
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self.parent.as_ref().is_some_and(|parent| parent.is_cancelled())
+    }
+
+    /// A token cancelled with this token, which can be cancelled alone.
+    pub(crate) fn child(&self) -> CancellationToken {
+        CancellationToken {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            parent: Some(Box::new(self.clone())),
+        }
+    }
+
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct OperationRegistry {
+    inner: Arc<OperationRegistryInner>,
+}
+
+#[derive(Default)]
+struct OperationRegistryInner {
+    next_id: AtomicU64,
+    operations: Mutex<BTreeMap<u64, OperationState>>,
+}
+
+struct OperationState {
+    op: String,
+    collection: String,
+    filter: Document,
+    start: Instant,
+    token: CancellationToken,
+}
+
+impl OperationRegistry {
+
+    /// Register an operation until the returned guard is dropped.
+    pub(crate) fn register(&self, op: &str, col_name: &str, filter: Document) -> OperationGuard {
+        let id = self.inner.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let token = CancellationToken::new();
+        let state = OperationState {
+            op: op.to_string(),
+            collection: col_name.to_string(),
+            filter,
+            start: Instant::now(),
+            token: token.clone(),
+        };
+        self.inner.operations.lock().unwrap().insert(id, state);
+        OperationGuard {
+            registry: self.inner.clone(),
+            id,
+            token,
+        }
+    }
+
+    /// The operations in flight, the oldest first.
+    pub(crate) fn current_ops(&self) -> Vec<CurrentOp> {
+        let operations = self.inner.operations.lock().unwrap();
+        operations
+            .iter()
+            .map(|(id, state)| CurrentOp {
+                id: *id,
+                op: state.op.clone(),
+                collection: state.collection.clone(),
+                filter: state.filter.clone(),
+                elapsed_micros: state.start.elapsed().as_micros() as u64,
+                cancelled: state.token.is_cancelled(),
+            })
+            .collect()
+    }
+
+    /// Cancel the operation `id` if it's in flight and `predicate` accepts its collection.
+    /// Return `false` if it's not found.
+    pub(crate) fn kill_op<F>(&self, id: u64, predicate: F) -> bool
+    where
+        F: FnOnce(&str) -> bool
+    {
+        let operations = self.inner.operations.lock().unwrap();
+        match operations.get(&id) {
+            Some(state) if predicate(&state.collection) => {
+                state.token.cancel();
+                true
+            }
+            _ => false,
+        }
+    }
+
+}
+
+/// Unregister the operation when it's dropped.
+pub(crate) struct OperationGuard {
+    registry: Arc<OperationRegistryInner>,
+    id: u64,
+    token: CancellationToken,
+}
+
+impl OperationGuard {
+
+    #[inline]
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+}
+
+impl Drop for OperationGuard {
+
+    fn drop(&mut self) {
+        if let Ok(mut operations) = self.registry.operations.lock() {
+            operations.remove(&self.id);
+        }
+    }
+
+}
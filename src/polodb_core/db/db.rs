@@ -12,15 +12,38 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
 use std::path::Path;
+use bson::{doc, Document};
 use serde::Serialize;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use crate::errors::Error;
-use crate::{Config, Transaction};
-use super::db_inner::DatabaseInner;
-use crate::coll::Collection;
+use crate::{Config, RuntimeOption, Transaction};
+use super::db_inner::{DatabaseInner, NAMESPACE_SEPARATOR};
+use crate::coll::{Collection, CollectionT};
+use crate::gridfs::{GridFsBucket, DEFAULT_BUCKET_NAME};
+use crate::kv::KvStore;
+#[cfg(feature = "auth")]
+use crate::auth::Users;
 use crate::metrics::Metrics;
+use crate::options::{CloneCollectionOptions, CreateCollectionOptions, ExportOptions, ImportOptions, ListCollectionsOptions, OpenOptions, TransactionOptions, ValidationAction};
+use crate::db::{format, WalOperation, WalRecord, PROFILE_COLLECTION};
+use crate::sync::{ApplyChangesResult, ChangeSet, SyncOptions, SyncResult, SyncTracker};
+use crate::migration::{self, Migrations};
+use crate::results::{BackupInfo, BlockCacheStats, ChangeEvent, CollectionInfo, CurrentOp, DropResult, DumpResult, FragmentationReport, ImportMetadataResult, InspectReport, MigrateResult, ProfileEntry, RecoverySummary, RepairReport, RestoreResult, StorageStats, UpgradeResult, VacuumResult};
+use crate::coll::collection_info::IndexInfo;
+use crate::{dump, extjson};
+#[cfg(feature = "csv")]
+use crate::csv_io;
+#[cfg(feature = "csv")]
+use crate::options::{CsvExportOptions, CsvImportOptions};
+use indexmap::IndexMap;
+#[cfg(feature = "arrow")]
+use crate::arrow_export::{ArrowBatchReader, ArrowExportOptions};
+#[cfg(feature = "sqlite")]
+use crate::sqlite::{SqliteImportOptions, SqliteImportResult};
 
 pub(crate) static SHOULD_LOG: AtomicBool = AtomicBool::new(false);
 
@@ -39,13 +62,27 @@ pub(crate) static SHOULD_LOG: AtomicBool = AtomicBool::new(false);
 /// You can use [`Database::create_collection`] to create a data collection.
 /// To obtain an exist collection, use [`Database::collection`],
 ///
+/// # Named databases
+/// The collections can be grouped into named databases stored in the same files,
+/// see [`Database::database`].
+///
 pub struct Database {
     inner: Arc<DatabaseInner>,
+    /// The name of the named database, `None` for the default one.
+    namespace: Option<String>,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 impl Database {
+    /// The default database of `inner`.
+    pub(crate) fn from_inner(inner: Arc<DatabaseInner>) -> Database {
+        Database {
+            inner,
+            namespace: None,
+        }
+    }
+
     pub fn set_log(v: bool) {
         SHOULD_LOG.store(v, Ordering::SeqCst);
     }
@@ -67,6 +104,15 @@ impl Database {
         Database::open_path_with_config(path, config)
     }
 
+    /// Return the options to open a database, e.g.
+    /// `Database::options().cache_size(64 << 20).read_only(true).open(path)`.
+    /// See [`OpenOptions`].
+    pub fn options() -> OpenOptions {
+        OpenOptions::new()
+    }
+
+    /// Open the database in `path` with the default options,
+    /// it's created if it doesn't exist.
     pub fn open_path<P: AsRef<Path>>(path: P) -> Result<Database>  {
         Database::open_path_with_config(path, Config::default())
     }
@@ -75,10 +121,144 @@ impl Database {
         let inner = DatabaseInner::open_file(path.as_ref(), config)?;
 
         Ok(Database {
-            inner: Arc::new(inner),
+            inner: inner.into_shared(),
+            namespace: None,
+        })
+    }
+
+    /// Open an existing database without modifying its files.
+    ///
+    /// All the writes fail with [`Error::ReadOnlyDatabase`].
+    /// The write-ahead log is replayed in memory and not written back,
+    /// so it's safe to open a copy made by [`Database::backup_to`],
+    /// or the files of a database used by another process.
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Database> {
+        Database::open_read_only_with_config(path, Config::default())
+    }
+
+    pub fn open_read_only_with_config<P: AsRef<Path>>(path: P, config: Config) -> Result<Database> {
+        let inner = DatabaseInner::open_read_only(path.as_ref(), config)?;
+
+        Ok(Database {
+            inner: inner.into_shared(),
+            namespace: None,
+        })
+    }
+
+    /// Open a read-only database from a buffer created by [`Database::to_bytes`],
+    /// e.g. an asset bundled by `include_bytes!`. The buffer is not copied.
+    pub fn open_bytes<B>(buffer: B) -> Result<Database>
+    where
+        B: AsRef<[u8]> + Send + Sync + 'static
+    {
+        Database::open_bytes_with_config(buffer, Config::default())
+    }
+
+    pub fn open_bytes_with_config<B>(buffer: B, config: Config) -> Result<Database>
+    where
+        B: AsRef<[u8]> + Send + Sync + 'static
+    {
+        let inner = DatabaseInner::open_bytes(buffer, config)?;
+
+        Ok(Database {
+            inner: inner.into_shared(),
+            namespace: None,
         })
     }
 
+    /// Pack all the data into a buffer, which can be opened by [`Database::open_bytes`].
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        self.inner.to_bytes()
+    }
+
+    /// Open a database which keeps all the data in memory.
+    /// Nothing is written to the disk unless [`Database::persist_to`] is called.
+    pub fn open_memory() -> Result<Database> {
+        Database::open_memory_with_config(Config::default())
+    }
+
+    pub fn open_memory_with_config(config: Config) -> Result<Database> {
+        let inner = DatabaseInner::open_memory(config)?;
+
+        Ok(Database {
+            inner: inner.into_shared(),
+            namespace: None,
+        })
+    }
+
+    /// Dump all the data into a new database in `path`,
+    /// which can be opened by [`Database::open_path`].
+    pub fn persist_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.inner.persist_to(path.as_ref())
+    }
+
+    /// Recover a damaged database in `path` into a new database in `target`, and open it.
+    ///
+    /// The files in `path` aren't changed: a copy of them is repaired, and the readable
+    /// data is copied into `target` one document at a time. The unreadable table and
+    /// log files are moved to the `lost` directory inside `target`, and the documents
+    /// which can not be decoded are skipped. What is recovered and what is skipped
+    /// is described in the returned [`RepairReport`].
+    pub fn open_repair<P: AsRef<Path>, Q: AsRef<Path>>(path: P, target: Q) -> Result<(Database, RepairReport)> {
+        Database::open_repair_with_config(path, target, Config::default())
+    }
+
+    pub fn open_repair_with_config<P: AsRef<Path>, Q: AsRef<Path>>(
+        path: P,
+        target: Q,
+        config: Config,
+    ) -> Result<(Database, RepairReport)> {
+        let (inner, report) = DatabaseInner::open_repair(path.as_ref(), target.as_ref(), config)?;
+
+        Ok((Database {
+            inner: inner.into_shared(),
+            namespace: None,
+        }, report))
+    }
+
+    /// Return the named database `name` stored in the same files as this one.
+    ///
+    /// The collections of a named database are separated from the collections
+    /// of the other databases, e.g. `db.database("tenant_a").collection("users")`
+    /// and `db.database("tenant_b").collection("users")` are different collections.
+    /// A named database exists as long as it has collections.
+    pub fn database(&self, name: &str) -> Database {
+        Database {
+            inner: self.inner.clone(),
+            namespace: Some(name.to_string()),
+        }
+    }
+
+    /// The name of the named database, `None` for the default one.
+    pub fn name(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    /// Gets the names of the named databases.
+    pub fn list_database_names(&self) -> Result<Vec<String>> {
+        let txn = self.inner.start_transaction()?;
+        self.inner.list_database_names_with_session(&txn)
+    }
+
+    /// Drops all the collections of the named database `name` in one transaction.
+    pub fn drop_database(&self, name: &str) -> Result<DropResult> {
+        self.database(name).drop()
+    }
+
+    /// Drops all the collections of this database in one transaction.
+    ///
+    /// The collections of the named databases are not dropped with the default database.
+    pub fn drop(&self) -> Result<DropResult> {
+        let txn = self.inner.start_transaction()?;
+        let result = self.inner.drop_database(self.namespace.as_deref(), &txn)?;
+        txn.commit()?;
+        Ok(result)
+    }
+
+    pub(crate) fn qualified_name(&self, name: &str) -> String {
+        qualify_col_name(self.namespace.as_deref(), name)
+    }
+
     /// Return the metrics object of the database
     pub fn metrics(&self) -> Metrics {
         self.inner.metrics()
@@ -86,10 +266,162 @@ impl Database {
 
     /// Creates a new collection in the database with the given `name`.
     pub fn create_collection(&self, name: &str) -> Result<()> {
-        let _ = self.inner.create_collection(name)?;
+        let _ = self.inner.create_collection(&self.qualified_name(name))?;
         Ok(())
     }
 
+    /// Creates a new collection with the given `options`.
+    ///
+    /// A capped collection keeps the documents in insertion order,
+    /// and removes the oldest documents on insert when it exceeds the `size` or `max`.
+    /// The documents of a capped collection can't be updated or deleted.
+    pub fn create_collection_with_options(&self, name: &str, mut options: CreateCollectionOptions) -> Result<()> {
+        options.view_on = options.view_on.map(|view_on| self.qualified_name(&view_on));
+        let _ = self.inner.create_collection_with_options(&self.qualified_name(name), &options)?;
+        Ok(())
+    }
+
+    /// Creates a read-only view which returns the result of the `pipeline` on `view_on`.
+    /// The source of a view can be a collection or another view.
+    ///
+    /// A view is queried like a collection, the filter of [`crate::CollectionT::find`]
+    /// is applied to the output of the pipeline.
+    pub fn create_view(&self, name: &str, view_on: &str, pipeline: impl IntoIterator<Item = Document>) -> Result<()> {
+        let options = CreateCollectionOptions::builder()
+            .view_on(view_on)
+            .pipeline(pipeline)
+            .build();
+        self.create_collection_with_options(name, options)
+    }
+
+    /// Attaches the validator to the collection `name`, replacing the existing one.
+    /// Only `{ "$jsonSchema": { ... } }` validators are supported.
+    ///
+    /// The inserted and updated documents are validated from now on,
+    /// the documents in the collection are not checked.
+    pub fn set_validator(&self, name: &str, validator: Document, action: ValidationAction) -> Result<()> {
+        self.inner.set_validator(&self.qualified_name(name), Some((validator, action)))
+    }
+
+    /// Removes the validator of the collection `name`.
+    pub fn remove_validator(&self, name: &str) -> Result<()> {
+        self.inner.set_validator(&self.qualified_name(name), None)
+    }
+
+    /// Applies the migrations newer than the schema version of the database in a transaction,
+    /// and installs the lazy transforms. See [`crate::migration`].
+    ///
+    /// The steps must write through the transaction they are given,
+    /// the concurrent migrations of the same database conflict with each other.
+    pub fn migrate(&self, migrations: &Migrations) -> Result<MigrateResult> {
+        let txn = self.start_transaction()?;
+        let (result, transforms) = migration::migrate(migrations, &txn)?;
+        txn.commit()?;
+        self.inner.install_transforms(self.namespace.as_deref(), transforms);
+        Ok(result)
+    }
+
+    /// Upgrade the database in `path` to [`crate::FORMAT_VERSION`], which is done
+    /// when it's opened unless [`crate::ConfigBuilder::set_auto_upgrade`] is disabled.
+    ///
+    /// Each upgrade is committed with its version, so an interrupted upgrade
+    /// resumes where it stopped. A database written by a newer release fails
+    /// with [`Error::FormatVersionTooNew`].
+    pub fn upgrade<P: AsRef<Path>>(path: P) -> Result<UpgradeResult> {
+        let config = Config {
+            auto_upgrade: false,
+            ..Config::default()
+        };
+        let inner = DatabaseInner::open_file(path.as_ref(), config)?;
+        format::upgrade(&inner)
+    }
+
+    /// The format version of the database, see [`Database::upgrade`].
+    pub fn format_version(&self) -> Result<u32> {
+        let (version, _) = format::current_version(&self.inner)?;
+        Ok(version)
+    }
+
+    /// The version of the latest migration applied to the database, 0 if it's never migrated.
+    pub fn schema_version(&self) -> Result<u32> {
+        self.inner.schema_version(self.namespace.as_deref())
+    }
+
+    /// Increments the sequence `name` and returns the new number, starting from 1.
+    ///
+    /// The increment is atomic: the concurrent callers never get the same number.
+    /// Use [`Transaction::next_sequence`] to roll it back with the other writes.
+    pub fn next_sequence(&self, name: &str) -> Result<i64> {
+        let txn = self.inner.start_transaction()?;
+        self.inner.next_sequence(self.namespace.as_deref(), name, &txn)
+    }
+
+    /// The last number returned by the sequence `name`, `None` if it's never used.
+    pub fn current_sequence(&self, name: &str) -> Result<Option<i64>> {
+        self.inner.current_sequence(self.namespace.as_deref(), name)
+    }
+
+    /// Sets the last number of the sequence `name`, the next one returned is `value + 1`.
+    pub fn reset_sequence(&self, name: &str, value: i64) -> Result<()> {
+        let txn = self.inner.start_transaction()?;
+        self.inner.reset_sequence(self.namespace.as_deref(), name, value, &txn)
+    }
+
+    /// Sets the default and computed fields of the collection `name`, replacing the existing ones.
+    ///
+    /// The fields are added to the inserted documents without them, in order:
+    ///
+    /// - `"$$NOW"` is the time of the insert.
+    /// - `"$field"` copies the field of the document, `null` if it's missing.
+    /// - `{ "$op": ... }` computes the field by an aggregation operator, e.g. `$concat` or `$add`.
+    /// - `{ "$literal": value }` is the value as is, any other value is the default value.
+    ///
+    /// The fields are added before the `_id` is generated and the document is validated.
+    /// The updated documents and the documents in the collection are not changed.
+    pub fn set_defaults(&self, name: &str, defaults: Document) -> Result<()> {
+        self.inner.set_defaults(&self.qualified_name(name), Some(defaults))
+    }
+
+    /// Removes the default fields of the collection `name`.
+    pub fn remove_defaults(&self, name: &str) -> Result<()> {
+        self.inner.set_defaults(&self.qualified_name(name), None)
+    }
+
+    /// Renames the collection `old_name` to `new_name`.
+    ///
+    /// Only the metadata of the collection is changed, the documents and the indexes
+    /// are not copied. The cursors opened before the rename keep reading the documents.
+    pub fn rename_collection(&self, old_name: &str, new_name: &str) -> Result<()> {
+        self.inner.rename_collection(&self.qualified_name(old_name), &self.qualified_name(new_name))
+    }
+
+    /// Copies the documents and the indexes of the collection `src` into the new collection `dst`.
+    /// Return the number of the copied documents.
+    pub fn clone_collection(&self, src: &str, dst: &str) -> Result<u64> {
+        self.clone_collection_to(src, self, dst, CloneCollectionOptions::default())
+    }
+
+    /// Copies the documents of the collection `src` into the new collection `dst` of `target`,
+    /// which can be this database or another open one. `dst` is created with the options
+    /// of `src`, see [`CloneCollectionOptions`].
+    ///
+    /// The documents are streamed from a cursor and written in one transaction,
+    /// so `dst` is either complete or not created.
+    pub fn clone_collection_to(
+        &self,
+        src: &str,
+        target: &Database,
+        dst: &str,
+        options: CloneCollectionOptions,
+    ) -> Result<u64> {
+        self.inner.clone_collection_to(
+            &self.qualified_name(src),
+            &target.inner,
+            &target.qualified_name(dst),
+            &options,
+        )
+    }
+
     ///
     /// [error]: ../enum.DbErr.html
     ///
@@ -97,19 +429,533 @@ impl Database {
     /// a new collection will be created.
     ///
     pub fn collection<T: Serialize>(&self, col_name: &str) -> Collection<T> {
-        Collection::new(Arc::downgrade(&self.inner), col_name)
+        Collection::new(Arc::downgrade(&self.inner), &self.qualified_name(col_name))
+    }
+
+    /// Return the default bucket to store the large binaries.
+    /// See [`crate::gridfs`].
+    pub fn fs(&self) -> GridFsBucket {
+        self.fs_bucket(DEFAULT_BUCKET_NAME)
+    }
+
+    /// Return the bucket named `name`, whose files are stored
+    /// in the `<name>_files` and `<name>_chunks` collections.
+    pub fn fs_bucket(&self, name: &str) -> GridFsBucket {
+        GridFsBucket::new(Arc::downgrade(&self.inner), &self.qualified_name(name))
+    }
+
+    /// Return the raw key-value store of the database, see [`crate::kv`].
+    pub fn kv(&self) -> KvStore {
+        KvStore::new(Arc::downgrade(&self.inner), self.namespace.clone(), None)
+    }
+
+    /// Return the user accounts of the servers, see [`crate::auth`].
+    #[cfg(feature = "auth")]
+    pub fn users(&self) -> Users {
+        Users::new(Arc::downgrade(&self.inner))
     }
 
     pub fn start_transaction(&self) -> Result<Transaction> {
         let mut inner = self.inner.start_transaction()?;
         inner.set_auto_commit(false);
-        Ok(Transaction::new(Arc::downgrade(&self.inner), inner))
+        Ok(Transaction::new(Arc::downgrade(&self.inner), self.namespace.clone(), inner))
+    }
+
+    /// Start a transaction with the given [`TransactionOptions`],
+    /// such as overriding the [`crate::WalSyncPolicy`] of the database.
+    pub fn start_transaction_with_options(&self, options: TransactionOptions) -> Result<Transaction> {
+        let mut inner = self.inner.start_transaction_with_options(&options)?;
+        inner.set_auto_commit(false);
+        Ok(Transaction::new(Arc::downgrade(&self.inner), self.namespace.clone(), inner))
+    }
+
+    /// Take a consistent snapshot of the database into `path` while
+    /// the reads and writes continue. The directory must not exist.
+    ///
+    /// The backup is a complete database which can be opened by [`Database::open_path`].
+    pub fn backup_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.inner.backup_to(path.as_ref())
+    }
+
+    /// Create an incremental backup in `backup_dir` while the reads and writes continue.
+    ///
+    /// The backups in the same directory share the table files, so only
+    /// the files created since the previous backup are copied.
+    pub fn backup_incremental<P: AsRef<Path>>(&self, backup_dir: P) -> Result<BackupInfo> {
+        self.inner.backup_incremental(backup_dir.as_ref())
+    }
+
+    /// List the backups in `backup_dir`, from the oldest to the newest.
+    pub fn list_backups<P: AsRef<Path>>(backup_dir: P) -> Result<Vec<BackupInfo>> {
+        DatabaseInner::list_backups(backup_dir.as_ref())
+    }
+
+    /// Restore a backup in `backup_dir` to `db_path`.
+    /// The latest backup is restored if `backup_id` is `None`.
+    pub fn restore_backup<P: AsRef<Path>, Q: AsRef<Path>>(backup_dir: P, backup_id: Option<u32>, db_path: Q) -> Result<()> {
+        DatabaseInner::restore_backup(backup_dir.as_ref(), backup_id, db_path.as_ref())
+    }
+
+    /// Dump the collections and the views of this database into the directory `path`,
+    /// in the format of `mongodump`, so it can be restored by [`Database::restore`]
+    /// or by `mongorestore --db <name> <path>`. Each collection has a `<name>.bson` file
+    /// of the documents and a `<name>.metadata.json` file of the options and the indexes.
+    ///
+    /// The documents are read in one transaction while the writes continue.
+    pub fn dump<P: AsRef<Path>>(&self, path: P) -> Result<DumpResult> {
+        dump::dump(self, path.as_ref())
+    }
+
+    /// Restore the dump in the directory `path` written by [`Database::dump`] or `mongodump`.
+    ///
+    /// The collections in the dump must not exist in this database. The documents
+    /// of each collection are inserted by the transactions of up to 1000 documents,
+    /// so a large dump isn't held in memory, and a collection which fails to be restored
+    /// is dropped. The validator and the default fields are attached after the documents. The indexes which are not supported,
+    /// e.g. the compound indexes, are skipped and returned in [`RestoreResult::skipped_indexes`].
+    pub fn restore<P: AsRef<Path>>(&self, path: P) -> Result<RestoreResult> {
+        dump::restore(self, path.as_ref())
+    }
+
+    /// Write the options, the validators, the default fields and the indexes of all the collections
+    /// and the views to `writer` as one JSON document, without the documents. See [`Database::import_metadata`].
+    ///
+    /// The collections and the indexes are sorted by their names, so the same metadata
+    /// is exported from the databases with the same collections.
+    pub fn export_metadata<W: Write>(&self, writer: W) -> Result<()> {
+        dump::export_metadata(self, writer)
+    }
+
+    /// Make the collections of this database the same as the metadata written by
+    /// [`Database::export_metadata`], e.g. from a file checked in with the code.
+    ///
+    /// The missing collections, views and indexes are created. The validators and the default
+    /// fields are replaced, the indexes with another definition are recreated and the indexes
+    /// not in the metadata are dropped. The collections not in the metadata are kept.
+    /// A collection created with other options, e.g. a capped collection in the metadata,
+    /// fails with [`Error::MetadataConflict`] before anything is changed.
+    ///
+    /// The changes are not done in one transaction, importing again after a failure continues them.
+    pub fn import_metadata<R: Read>(&self, reader: R) -> Result<ImportMetadataResult> {
+        dump::import_metadata(self, reader)
+    }
+
+    /// Write the documents of the collection `name` to `writer` as MongoDB Extended JSON,
+    /// one document per line by default, like `mongoexport`. Return the number of the documents.
+    ///
+    /// Unlike the plain JSON of `serde_json`, the types of the values are kept,
+    /// e.g. an ObjectId is written as `{ "$oid": "..." }`, so they're restored by [`Database::import_json`].
+    pub fn export_json<W: Write>(&self, name: &str, writer: W, options: ExportOptions) -> Result<u64> {
+        extjson::export_json(self, name, writer, options)
+    }
+
+    /// Insert the documents in Extended JSON, canonical or relaxed, read from `reader`
+    /// into the collection `name`. Return the number of the inserted documents.
+    ///
+    /// The input is one document per line, or a JSON array which is detected from the first character.
+    /// The documents are inserted in one transaction, so nothing is inserted if any of them is invalid.
+    pub fn import_json<R: Read>(&self, name: &str, reader: R, options: ImportOptions) -> Result<u64> {
+        extjson::import_json(self, name, reader, options)
+    }
+
+    /// Write the documents of the collection `name` to `writer` as CSV. Return the number of the documents.
+    ///
+    /// The columns are the dot paths of [`CsvExportOptions::fields`], e.g. `address.city`.
+    /// The dates are written in RFC 3339, the ObjectIds in hex, and the arrays
+    /// and the other types in Extended JSON. The missing fields are empty.
+    #[cfg(feature = "csv")]
+    pub fn export_csv<W: Write>(&self, name: &str, writer: W, options: CsvExportOptions) -> Result<u64> {
+        csv_io::export_csv(self, name, writer, options)
+    }
+
+    /// Insert the rows of the CSV read from `reader` into the collection `name`.
+    /// Return the number of the inserted documents.
+    ///
+    /// The first row is the header, the dot paths in it, e.g. `address.city`, are inserted as
+    /// embedded documents. The types of the values are inferred, or given by
+    /// [`CsvImportOptions::column_types`]. The empty cells are skipped unless the column is a string.
+    /// The rows are inserted in one transaction, so nothing is inserted if any of them is invalid.
+    #[cfg(feature = "csv")]
+    pub fn import_csv<R: Read>(&self, name: &str, reader: R, options: CsvImportOptions) -> Result<u64> {
+        csv_io::import_csv(self, name, reader, options)
+    }
+
+    /// Read the documents of the collection `name`, or the results of [`ArrowExportOptions::pipeline`],
+    /// as Arrow record batches. See [`crate::arrow_export`] for how the schema is inferred.
+    #[cfg(feature = "arrow")]
+    pub fn export_arrow(&self, name: &str, options: ArrowExportOptions) -> Result<ArrowBatchReader> {
+        crate::arrow_export::export_arrow(self, name, options)
+    }
+
+    /// Write the documents of the collection `name` to `writer` as a Parquet file
+    /// compressed by Snappy. Return the number of the rows.
+    #[cfg(feature = "arrow")]
+    pub fn export_parquet<W: Write + Send>(&self, name: &str, writer: W, options: ArrowExportOptions) -> Result<u64> {
+        crate::arrow_export::export_parquet(self, name, writer, options)
+    }
+
+    /// Import the tables of the SQLite database at `path` into the collections named after them.
+    /// See [`crate::sqlite`] for how the rows are converted.
+    #[cfg(feature = "sqlite")]
+    pub fn import_sqlite<P: AsRef<Path>>(&self, path: P, options: SqliteImportOptions) -> Result<SqliteImportResult> {
+        crate::sqlite::import_sqlite(self, path.as_ref(), options)
+    }
+
+    /// The id of this replica in the [`crate::sync`] of the documents.
+    pub fn sync_replica_id(&self) -> Result<String> {
+        crate::sync::sync_replica_id(self)
+    }
+
+    /// The checkpoint of the changes of the replica `replica_id` applied to this database,
+    /// 0 if none of them is applied.
+    pub fn sync_checkpoint(&self, replica_id: &str) -> Result<u64> {
+        crate::sync::sync_checkpoint(self, replica_id)
+    }
+
+    /// The latest changes of the documents since `checkpoint`, to apply to another replica.
+    pub fn sync_changes(&self, checkpoint: u64, options: &SyncOptions) -> Result<ChangeSet> {
+        crate::sync::sync_changes(self, checkpoint, options)
+    }
+
+    /// Apply the changes of another replica, and remember their checkpoint
+    /// as [`Database::sync_checkpoint`] of the replica.
+    pub fn apply_changes(&self, changes: &ChangeSet, options: &SyncOptions) -> Result<ApplyChangesResult> {
+        crate::sync::apply_changes(self, changes, options)
+    }
+
+    /// Exchange the changes of the documents with `other` since their last sync.
+    /// Both databases must be opened with [`crate::ConfigBuilder::set_sync_tracking`].
+    pub fn sync_with(&self, other: &Database, options: &SyncOptions) -> Result<SyncResult> {
+        let pulled = crate::sync::pull(self, other, options)?;
+        let pushed = crate::sync::pull(other, self, options)?;
+        Ok(SyncResult { pulled, pushed })
+    }
+
+    pub(crate) fn sync_tracker(&self) -> Result<Option<Arc<SyncTracker>>> {
+        self.inner.sync_tracker()
+    }
+
+    /// The names of the collections of this database mapped to the names of their keys.
+    pub(crate) fn collection_storage_names(&self, txn: &Transaction) -> Result<HashMap<String, String>> {
+        let mut result = HashMap::new();
+        for spec in self.inner.list_collection_specs(txn.inner())? {
+            if let Some(name) = self.unqualified_name(spec.name()) {
+                result.insert(name, spec.storage_name().to_string());
+            }
+        }
+        Ok(result)
+    }
+
+    /// The indexes of the collection `name` read by the transaction.
+    pub(crate) fn index_infos(&self, txn: &Transaction, name: &str) -> Result<IndexMap<String, IndexInfo>> {
+        let spec = self.inner
+            .get_collection_meta_by_name_advanced_auto(&self.qualified_name(name), false, txn.inner())?
+            .ok_or_else(|| Error::CollectionNotFound(name.to_string()))?;
+        Ok(spec.indexes)
+    }
+
+    /// Create a named snapshot of the database.
+    ///
+    /// The snapshot is stored in the `snapshots` directory of the database
+    /// and the table files are hard-linked, so it's cheap to create and
+    /// the files it references are kept after compaction.
+    pub fn create_snapshot(&self, name: &str) -> Result<()> {
+        self.inner.create_snapshot(name)
+    }
+
+    /// Return the names of the snapshots of the database.
+    pub fn list_snapshots(&self) -> Result<Vec<String>> {
+        self.inner.list_snapshots()
+    }
+
+    pub fn drop_snapshot(&self, name: &str) -> Result<()> {
+        self.inner.drop_snapshot(name)
+    }
+
+    /// Open the snapshot as a read-only database.
+    pub fn open_snapshot(&self, name: &str) -> Result<Database> {
+        let inner = self.inner.open_snapshot(name)?;
+        Ok(Database {
+            inner: inner.into_shared(),
+            namespace: self.namespace.clone(),
+        })
+    }
+
+    /// Roll back the database in `path` to the snapshot.
+    /// The database must be closed, it fails with [`Error::DatabaseOccupied`]
+    /// if it's opened by this process or by another one.
+    pub fn restore_snapshot<P: AsRef<Path>>(path: P, name: &str) -> Result<()> {
+        DatabaseInner::restore_snapshot(path.as_ref(), name)
+    }
+
+    /// Rewrite the live data into new files and release the space
+    /// of the deleted documents back to the OS.
+    pub fn vacuum(&self) -> Result<VacuumResult> {
+        self.inner.vacuum()
+    }
+
+    /// Return the usage of the block cache and the hits and misses
+    /// of the reads of this database.
+    /// See [`crate::ConfigBuilder::set_block_cache`].
+    pub fn block_cache_stats(&self) -> Result<BlockCacheStats> {
+        self.inner.block_cache_stats()
+    }
+
+    /// Return the statistics of the storage: the table files of each level,
+    /// the write-ahead log, the memtables, the compaction debt and the total size on disk.
+    ///
+    /// The files are shared by the named databases, so the statistics are of all of them.
+    pub fn stats(&self) -> Result<StorageStats> {
+        self.inner.storage_stats()
+    }
+
+    /// Estimate the dead bytes of each level of the storage: the tombstones and
+    /// the old versions of the keys, and how much space a compaction would release.
+    ///
+    /// The memtables are flushed first, and all the keys are read to count
+    /// the live ones, which costs as much as a full scan of the database.
+    /// The files are shared by the named databases, so the report is of all of them.
+    pub fn fragmentation(&self) -> Result<FragmentationReport> {
+        self.inner.fragmentation()
+    }
+
+    /// Describe what is physically stored in the files of the database:
+    /// the edits of the manifest, the live table files of each level with their
+    /// key ranges and blocks, and the records of the write-ahead log files.
+    ///
+    /// The manifest and the WAL files are decoded without RocksDB, so the records
+    /// which can't be replayed are reported too. Open the database with
+    /// [`Database::open_read_only`] to inspect it without changing the files.
+    /// The manifest and the WAL files are empty for the in-memory databases.
+    pub fn inspect(&self) -> Result<InspectReport> {
+        self.inner.inspect()
+    }
+
+    /// Describe what was discarded when the database was opened after a crash,
+    /// e.g. a power loss in the middle of a commit.
+    ///
+    /// The database is rolled back to the last commit which is completely written:
+    /// the write-ahead log is replayed until the first torn record, and the table files
+    /// of an interrupted flush or compaction are deleted. The summary is clean if the
+    /// database was closed cleanly, and always clean for the in-memory databases.
+    pub fn recovery_summary(&self) -> RecoverySummary {
+        self.inner.recovery_summary()
+    }
+
+    /// Report the block cache hits, the WAL syncs, the compactions and the sizes
+    /// of the storage to the recorder set by [`crate::ConfigBuilder::set_metrics_recorder`].
+    /// The operations are reported when they finish, but the storage is only
+    /// reported when this is called, usually before the metrics are scraped.
+    #[cfg(feature = "metrics")]
+    pub fn report_metrics(&self) -> Result<()> {
+        self.inner.report_metrics()
+    }
+
+    /// Return the queries of this database in flight, the oldest first.
+    /// The reads are in flight until their cursors reach the end or are dropped.
+    pub fn current_ops(&self) -> Vec<CurrentOp> {
+        self.inner.current_ops()
+            .into_iter()
+            .filter_map(|mut op| {
+                op.collection = self.unqualified_name(&op.collection)?;
+                Some(op)
+            })
+            .collect()
+    }
+
+    /// Cancel the operation `id` of [`Database::current_ops`].
+    /// It stops with [`Error::OperationCancelled`] before it reads the next document.
+    /// Return `false` if it's not in flight.
+    pub fn kill_op(&self, id: u64) -> bool {
+        self.inner.kill_op(id, |collection| self.unqualified_name(collection).is_some())
+    }
+
+    /// Change a tunable without reopening the database. The storage is shared
+    /// by the named databases, so the option applies to all of them.
+    pub fn set_option(&self, option: RuntimeOption) -> Result<()> {
+        self.inner.set_option(option)
+    }
+
+    /// Return the slow queries recorded by the profiler of this database, the oldest first.
+    /// See [`crate::ConfigBuilder::set_slow_query_threshold`].
+    ///
+    /// The entries are stored in the capped collection [`crate::PROFILE_COLLECTION`],
+    /// which can also be queried by [`crate::CollectionT::find`]. At most the latest 1000 entries are kept.
+    pub fn profile_entries(&self) -> Result<Vec<ProfileEntry>> {
+        self.collection::<ProfileEntry>(PROFILE_COLLECTION)
+            .find(doc! {})
+            .run()?
+            .collect()
+    }
+
+    /// Remove the entries of the profiler of this database,
+    /// return the count of the removed entries.
+    pub fn clear_profile(&self) -> Result<u64> {
+        let result = self.collection::<ProfileEntry>(PROFILE_COLLECTION).drop()?;
+        Ok(result.deleted_count)
+    }
+
+    /// Return the sequence number of the latest write of the database.
+    pub fn latest_sequence_number(&self) -> Result<u64> {
+        self.inner.latest_sequence_number()
+    }
+
+    /// Stream the commit records in the write-ahead log, starting
+    /// from the record containing sequence `since`, to the callback.
+    ///
+    /// The callback returns `false` to stop shipping. The sequence
+    /// to resume from is returned.
+    ///
+    /// Set [`crate::ConfigBuilder::set_wal_ttl_seconds`] to keep the
+    /// obsolete WAL files after flushing, otherwise the records
+    /// before the latest flush may be unavailable.
+    pub fn ship_wal<F>(&self, since: u64, f: F) -> Result<u64>
+    where
+        F: FnMut(&WalRecord) -> bool
+    {
+        self.inner.ship_wal(since, f)
+    }
+
+    /// Stream the changes of the documents in the collections of this database,
+    /// starting from the commit record containing sequence `since`, to the callback.
+    ///
+    /// The changes are decoded from the write-ahead log, so a [`ChangeEvent`] carries
+    /// the document after the change of the same commit. The document before the change
+    /// is only recorded if [`crate::ConfigBuilder::set_change_pre_images`] is enabled.
+    ///
+    /// The changes are reported with the current names of the collections,
+    /// so the changes before a [`Database::rename_collection`] have the new name.
+    ///
+    /// The callback returns `false` to stop shipping. The sequence to resume from is returned.
+    /// If the callback stops before the last change of a commit, the shipping resumes
+    /// from the same commit, so its earlier changes are shipped again.
+    pub fn ship_changes<F>(&self, since: u64, mut f: F) -> Result<u64>
+    where
+        F: FnMut(&ChangeEvent) -> bool
+    {
+        let mut names = self.collection_names_by_storage()?;
+        // the storage names not found again after reloading the names
+        let mut unknown = HashSet::<String>::new();
+        let mut error = None;
+        let next = self.inner.ship_changes(since, |event| {
+            // the collection is created or renamed after the names are loaded
+            if !names.contains_key(&event.collection) && !unknown.contains(&event.collection) {
+                match self.collection_names_by_storage() {
+                    Ok(reloaded) => names = reloaded,
+                    Err(err) => {
+                        error = Some(err);
+                        return false;
+                    }
+                }
+                if !names.contains_key(&event.collection) {
+                    unknown.insert(event.collection.clone());
+                }
+            }
+            let collection = match names.get(&event.collection) {
+                Some(collection) => collection.clone(),
+                // the collection is dropped
+                None => match self.unqualified_name(name_of_dropped_storage(&event.collection)) {
+                    Some(collection) => collection,
+                    None => return true,
+                },
+            };
+            let mut event = event.clone();
+            event.collection = collection;
+            f(&event)
+        })?;
+        match error {
+            Some(err) => Err(err),
+            None => Ok(next),
+        }
+    }
+
+    /// The storage names of the collections of this database mapped to their names.
+    fn collection_names_by_storage(&self) -> Result<HashMap<String, String>> {
+        let txn = self.start_transaction()?;
+        let names = self.collection_storage_names(&txn)?
+            .into_iter()
+            .map(|(name, storage_name)| (storage_name, name))
+            .collect();
+        Ok(names)
+    }
+
+    /// Reject the writes of the transactions, the followers of the replication
+    /// only apply the writes of the primary.
+    pub(crate) fn set_replica(&self, replica: bool) -> Result<()> {
+        self.inner.set_replica(replica)
+    }
+
+    pub(crate) fn write_replicated(&self, operations: &[WalOperation]) -> Result<()> {
+        self.inner.write_replicated(operations)
     }
 
     /// Gets the names of the collections in the database.
     pub fn list_collection_names(&self) -> Result<Vec<String>> {
         let txn = self.inner.start_transaction()?;
-        self.inner.list_collection_names_with_session(&txn)
+        let names = self.inner.list_collection_names_with_session(&txn)?;
+        let result = names
+            .iter()
+            .filter_map(|name| self.unqualified_name(name))
+            .collect();
+        Ok(result)
+    }
+
+    /// Lists the collections in the database with their options,
+    /// and the estimated count and size of the documents.
+    pub fn list_collections(&self, options: ListCollectionsOptions) -> Result<Vec<CollectionInfo>> {
+        let txn = self.inner.start_transaction()?;
+        let mut result = Vec::new();
+        for spec in self.inner.list_collection_specs(&txn)? {
+            let name = match self.unqualified_name(spec.name()) {
+                Some(name) => name,
+                None => continue,
+            };
+            if let Some(name_prefix) = &options.name_prefix {
+                if !name.starts_with(name_prefix.as_str()) {
+                    continue;
+                }
+            }
+            let mut info = self.inner.collection_info(&txn, &spec)?;
+            info.name = name;
+            info.options.view_on = info.options.view_on
+                .map(|view_on| self.unqualified_name(&view_on).unwrap_or(view_on));
+            result.push(info);
+        }
+        Ok(result)
+    }
+
+    /// The name of the collection in this database,
+    /// `None` if the collection belongs to another database.
+    fn unqualified_name(&self, name: &str) -> Option<String> {
+        match &self.namespace {
+            Some(namespace) => {
+                let (db_name, col_name) = name.split_once(NAMESPACE_SEPARATOR)?;
+                if db_name == namespace {
+                    Some(col_name.to_string())
+                } else {
+                    None
+                }
+            }
+            None if name.contains(NAMESPACE_SEPARATOR) => None,
+            None => Some(name.to_string()),
+        }
     }
 
 }
+
+/// The name of the collection `name` of the named database `namespace`.
+pub(crate) fn qualify_col_name(namespace: Option<&str>, name: &str) -> String {
+    match namespace {
+        Some(namespace) => format!("{}{}{}", namespace, NAMESPACE_SEPARATOR, name),
+        None => name.to_string(),
+    }
+}
+
+/// The name of a dropped collection from its storage name,
+/// without the unique suffix of a collection reusing the name of a renamed one.
+fn name_of_dropped_storage(storage_name: &str) -> &str {
+    match storage_name.rsplit_once('$') {
+        Some((name, suffix)) if suffix.len() == 32 && suffix.bytes().all(|b| b.is_ascii_hexdigit()) => name,
+        _ => storage_name,
+    }
+}
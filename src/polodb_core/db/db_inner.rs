@@ -13,84 +13,931 @@
 // limitations under the License.
 
 use std::borrow::Borrow;
-use std::collections::HashMap;
-use bson::{Bson, Document};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use bson::{doc, Bson, Document};
+use bson::spec::ElementType;
 use serde::Serialize;
 use super::db::Result;
-use crate::errors::Error;
-use crate::options::UpdateOptions;
-use crate::Config;
+use crate::errors::{DuplicateKeyError, Error};
+use crate::utils::spill::SpillOptions;
+use crate::options::{CloneCollectionOptions, CreateCollectionOptions, IdStrategy, TransactionOptions, UpdateOptions, ValidationAction};
+use crate::{Config, ConfigBuilder, Database, QuotaPolicy, RuntimeOption, WalSyncPolicy};
 use crate::vm::SubProgram;
 use crate::meta_doc_helper::meta_doc_key;
-use crate::index::{IndexBuilder, IndexModel, IndexOptions};
+use crate::index::{analyze_index, vector, Analyzer, IndexBuilder, IndexModel, IndexOptions};
 use crate::db::client_cursor::ClientCursor;
-use crate::results::{DeleteResult, InsertManyResult, InsertOneResult, UpdateResult};
-use std::path::Path;
-use bson::oid::ObjectId;
+use crate::db::parallel_scan::{ParallelScan, PartitionProgram, ScanPartition};
+use crate::db::fragmentation::LiveData;
+use crate::db::change_stream::change_events;
+use crate::db::rocksdb_wal::WalPreImage;
+use crate::results::{BackupInfo, BlockCacheStats, ChangeEvent, CollectionInfo, CollectionStats, CurrentOp, DeleteResult, DropResult, FragmentationReport, IndexStats, InsertManyResult, InsertOneResult, InspectReport, LevelInspection, RecoverySummary, RepairReport, SkippedDocument, StorageStats, TableFileInspection, UpdateResult, VacuumResult};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
 use serde::de::DeserializeOwned;
 use crate::coll::collection_info::{
     CollectionSpecification,
     IndexInfo,
+    IndexKind,
 };
+use crate::coll::capped;
+use crate::coll::defaults;
+use crate::coll::db_ref::DbRef;
+use crate::coll::id_generator::IdGenerators;
+use crate::coll::collection_info::CollectionType;
+use crate::coll::{key_ordering, partition, timeseries};
+use crate::coll::validator::{self, ValidatorCache};
+use crate::coll::view;
+use crate::migration::{self, CollectionTransforms};
 use crate::cursor::Cursor;
-use crate::index::{IndexHelper, IndexHelperOperation};
+use crate::index::{IndexHelper, IndexHelperOperation, INDEX_PREFIX};
 use crate::metrics::Metrics;
-use crate::db::rocksdb_wrapper::RocksDBWrapper;
+use crate::db::rocksdb_wrapper::{RocksDBLock, RocksDBWrapper};
+use crate::db::rocksdb_backup::RocksDBBackupEngine;
+use crate::db::bundle::{BundleBackend, BundleReader, BundleWriter, BUNDLE_PATH};
+use crate::db::{format, fragmentation, inspect, profiler, recovery, qualify_col_name, sequence, OperationRegistry, Profiler, RocksDBIterator, RocksDBPerfContext, RocksDBTransaction, WalOperation, WalRecord};
+use crate::sync::SyncTracker;
 use crate::transaction::TransactionInner;
 use crate::vm::VM;
 
 const TABLE_META_PREFIX: &'static str = "$TABLE_META";
+pub(crate) const NAMESPACE_SEPARATOR: char = '.';
+const COUNT_SAMPLE_SIZE: u64 = 1000;
+const SNAPSHOTS_DIR: &str = "snapshots";
+const LOCK_FILE: &str = "LOCK";
+/// The entries recovered by a transaction of the repair
+const REPAIR_BATCH_SIZE: usize = 1000;
 
 /**
  * API for all platforms
  */
 pub(crate) struct DatabaseInner {
+    /// `None` for the in-memory databases
+    path:         Option<PathBuf>,
     rocksdb:      RocksDBWrapper,
     node_id:      [u8; 6],
     metrics:      Metrics,
-    #[allow(dead_code)]
+    profiler:     Profiler,
+    operations:   OperationRegistry,
+    id_generators: IdGenerators,
+    /// The lazy transforms of the collections installed by the migrations
+    transforms:   RwLock<HashMap<String, Arc<CollectionTransforms>>>,
+    /// The compiled validators of the collections
+    validators:   ValidatorCache,
+    /// What was discarded when the database was opened
+    recovery:     RecoverySummary,
+    /// The default of the transactions, changed by [`RuntimeOption::WalSyncPolicy`]
+    wal_sync_policy: RwLock<WalSyncPolicy>,
     config:       Config,
 }
 
+/// The collections found by [`DatabaseInner::open_repair`].
+#[derive(Default)]
+struct RecoveredCollections {
+    /// The prefixes of the documents of the collections, and their names
+    data_prefixes: BTreeMap<Vec<u8>, String>,
+    /// The keys of the metadata which can not be decoded
+    invalid_metas: HashSet<Vec<u8>>,
+}
+
+impl RecoveredCollections {
+
+    fn collection_of(&self, key: &[u8]) -> Option<&str> {
+        self.data_prefixes.range::<[u8], _>((Bound::Unbounded, Bound::Included(key)))
+            .next_back()
+            .filter(|(prefix, _)| key.starts_with(prefix))
+            .map(|(_, name)| name.as_str())
+    }
+
+}
+
 impl DatabaseInner {
 
     pub fn open_file(path: &Path, config: Config) -> Result<DatabaseInner> {
         let metrics = Metrics::new();
+        // the files of a custom storage backend are not on the disk
+        let mut recovery = match &config.storage_backend {
+            Some(_) => RecoverySummary::default(),
+            None => recovery::scan(path, config.cold_path.as_deref())?,
+        };
+        let rocksdb = RocksDBWrapper::open(path, &config)?;
+        recovery.last_sequence = rocksdb.latest_sequence_number()?;
 
-        DatabaseInner::open_with_backend(
-            path,
+        let mut db = DatabaseInner::open_with_backend(
+            Some(path.to_path_buf()),
+            rocksdb,
             config,
             metrics,
-        )
+        )?;
+        db.recovery = recovery;
+
+        if db.config.auto_upgrade {
+            format::upgrade(&db)?;
+        } else {
+            format::check_version(&db)?;
+        }
+        Ok(db)
+    }
+
+    pub fn open_read_only(path: &Path, config: Config) -> Result<DatabaseInner> {
+        let metrics = Metrics::new();
+        let rocksdb = RocksDBWrapper::open_read_only(path, &config)?;
+
+        let db = DatabaseInner::open_with_backend(
+            Some(path.to_path_buf()),
+            rocksdb,
+            config,
+            metrics,
+        )?;
+        format::check_version(&db)?;
+        Ok(db)
+    }
+
+    pub fn open_bytes<B>(buffer: B, mut config: Config) -> Result<DatabaseInner>
+    where
+        B: AsRef<[u8]> + Send + Sync + 'static
+    {
+        let metrics = Metrics::new();
+        let reader = BundleReader::new(buffer)?;
+        config.storage_backend = Some(Arc::new(BundleBackend::new(reader)));
+        let rocksdb = RocksDBWrapper::open_read_only(Path::new(BUNDLE_PATH), &config)?;
+
+        let db = DatabaseInner::open_with_backend(
+            None,
+            rocksdb,
+            config,
+            metrics,
+        )?;
+        format::check_version(&db)?;
+        Ok(db)
+    }
+
+    pub fn open_memory(config: Config) -> Result<DatabaseInner> {
+        let metrics = Metrics::new();
+        let rocksdb = RocksDBWrapper::open_memory(&config)?;
+
+        let db = DatabaseInner::open_with_backend(
+            None,
+            rocksdb,
+            config,
+            metrics,
+        )?;
+        format::upgrade(&db)?;
+        Ok(db)
     }
 
     fn open_with_backend(
-        path: &Path,
+        path: Option<PathBuf>,
+        rocksdb: RocksDBWrapper,
         config: Config,
         metrics: Metrics,
     ) -> Result<DatabaseInner> {
         let mut node_id: [u8; 6] = [0; 6];
         getrandom::getrandom(&mut node_id).unwrap();
 
-        let rocksdb = RocksDBWrapper::open(path)?;
+        #[cfg(feature = "metrics")]
+        let metrics = metrics.with_recorder(config.metrics_recorder.clone());
+
+        let profiler = Profiler::new(config.slow_query_threshold);
 
         let ctx = DatabaseInner {
+            path,
             rocksdb,
             // first_page,
             node_id,
             metrics,
+            profiler,
+            operations: OperationRegistry::default(),
+            id_generators: IdGenerators::new(&node_id),
+            transforms: RwLock::new(HashMap::new()),
+            validators: ValidatorCache::default(),
+            recovery: RecoverySummary::default(),
+            wal_sync_policy: RwLock::new(config.wal_sync_policy),
             config,
         };
 
         Ok(ctx)
     }
 
+    /// Share the database, the profiler records the slow queries through it.
+    pub(crate) fn into_shared(self) -> Arc<DatabaseInner> {
+        let db = Arc::new(self);
+        db.profiler.set_database(Arc::downgrade(&db));
+        db
+    }
+
+    /// Repair a copy of the damaged database in `path` and recover the readable
+    /// data into a new database in `target`. The files in `path` aren't changed.
+    pub fn open_repair(path: &Path, target: &Path, config: Config) -> Result<(DatabaseInner, RepairReport)> {
+        if target.exists() {
+            return Err(Error::ValidationError(format!("the repair target '{}' already exists", target.display())));
+        }
+        let scratch = DatabaseInner::repair_scratch_path(target);
+        let _ = std::fs::remove_dir_all(scratch.as_path());
+
+        let mut report = RepairReport::default();
+        let result = DatabaseInner::repair_into(path, scratch.as_path(), target, &config, &mut report);
+        let _ = std::fs::remove_dir_all(scratch.as_path());
+        if let Err(err) = result {
+            let _ = std::fs::remove_dir_all(target);
+            return Err(err);
+        }
+
+        let db = DatabaseInner::open_file(target, config)?;
+        Ok((db, report))
+    }
+
+    /// The directory where the copy of the damaged database is repaired.
+    fn repair_scratch_path(target: &Path) -> PathBuf {
+        let mut name = target.file_name().unwrap_or_default().to_os_string();
+        name.push(".repairing");
+        target.with_file_name(name)
+    }
+
+    fn repair_into(path: &Path, scratch: &Path, target: &Path, config: &Config, report: &mut RepairReport) -> Result<()> {
+        // RocksDB repairs the files in place, so a copy of them is repaired
+        {
+            let _lock = RocksDBLock::acquire(path)?;
+            std::fs::create_dir_all(scratch)?;
+            for entry in std::fs::read_dir(path)? {
+                let entry = entry?;
+                if entry.file_type()?.is_file() && entry.file_name() != LOCK_FILE {
+                    std::fs::copy(entry.path(), scratch.join(entry.file_name()))?;
+                }
+            }
+        }
+        RocksDBWrapper::repair(scratch, config)?;
+        report.lost_files = DatabaseInner::list_lost_files(scratch.join("lost").as_path());
+
+        {
+            let source = RocksDBWrapper::open(scratch, config)?;
+            let target = RocksDBWrapper::open(target, config)?;
+            DatabaseInner::recover_data(&source, &target, report)?;
+        }
+
+        // the unreadable files are kept for the user
+        if !report.lost_files.is_empty() {
+            std::fs::rename(scratch.join("lost"), target.join("lost"))?;
+        }
+        Ok(())
+    }
+
+    fn list_lost_files(lost_dir: &Path) -> Vec<String> {
+        let mut result = Vec::new();
+        if let Ok(entries) = std::fs::read_dir(lost_dir) {
+            for entry in entries.flatten() {
+                result.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+        result
+    }
+
+    /// Copy the entries of `source` into `target` one by one, except the documents
+    /// and the collection metadata which can not be decoded.
+    fn recover_data(source: &RocksDBWrapper, target: &RocksDBWrapper, report: &mut RepairReport) -> Result<()> {
+        let source_txn = source.begin_transaction(false)?;
+        let collections = DatabaseInner::recover_collections(&source_txn, report);
+
+        let mut target_txn = target.begin_transaction(true)?;
+        let mut pending = 0;
+        let mut iter = source_txn.new_iterator();
+        iter.seek_to_first();
+        let mut resume_after: Option<Vec<u8>> = None;
+        loop {
+            while iter.valid() {
+                match DatabaseInner::recover_entry(&iter, &collections, &target_txn, report) {
+                    Ok(key) => resume_after = Some(key),
+                    Err(err) => report.errors.push(format!("failed to recover an entry: {}", err)),
+                }
+                pending += 1;
+                if pending == REPAIR_BATCH_SIZE {
+                    target_txn.commit()?;
+                    target_txn = target.begin_transaction(true)?;
+                    pending = 0;
+                }
+                iter.next();
+            }
+
+            let err = match iter.error() {
+                Ok(()) => break,
+                Err(err) => err,
+            };
+            // the rest of the collection is unreadable, continue from the next one
+            let after = resume_after.clone().unwrap_or_default();
+            report.errors.push(format!("failed to read after the key {}: {}", inspect::format_key(&after), err));
+            let next = collections.data_prefixes.keys()
+                .find(|prefix| prefix.as_slice() > after.as_slice() && !after.starts_with(prefix));
+            let next = match next {
+                Some(next) => next.clone(),
+                None => break,
+            };
+            iter = source_txn.new_iterator();
+            iter.seek(next.as_slice());
+            resume_after = Some(next);
+        }
+
+        target_txn.commit()?;
+        Ok(())
+    }
+
+    /// Find the collections by their metadata, the invalid metadata are reported.
+    fn recover_collections(txn: &RocksDBTransaction, report: &mut RepairReport) -> RecoveredCollections {
+        let mut collections = RecoveredCollections::default();
+        if let Err(err) = DatabaseInner::scan_collection_metas(txn, &mut collections, report) {
+            report.errors.push(format!("failed to scan collection metadata: {}", err));
+        }
+        collections
+    }
+
+    fn scan_collection_metas(
+        txn: &RocksDBTransaction,
+        collections: &mut RecoveredCollections,
+        report: &mut RepairReport,
+    ) -> Result<()> {
+        let mut cursor = Cursor::new_with_str_prefix(TABLE_META_PREFIX, txn.new_iterator())?;
+        cursor.reset()?;
+        while cursor.has_next() {
+            let key = cursor.peek_key().unwrap().to_vec();
+            match bson::from_slice::<CollectionSpecification>(cursor.copy_data()?.as_slice()) {
+                Ok(spec) => {
+                    let mut prefix = Vec::new();
+                    crate::utils::bson::stacked_key_bytes(&mut prefix, &Bson::String(spec.storage_name().to_string()))?;
+                    collections.data_prefixes.insert(prefix, spec.name().to_string());
+                }
+                Err(err) => {
+                    report.errors.push(format!("invalid collection metadata: {}", err));
+                    collections.invalid_metas.insert(key);
+                }
+            }
+            cursor.next()?;
+        }
+        Ok(())
+    }
+
+    /// Copy the current entry of `iter`, and return its key.
+    fn recover_entry(
+        iter: &RocksDBIterator,
+        collections: &RecoveredCollections,
+        target_txn: &RocksDBTransaction,
+        report: &mut RepairReport,
+    ) -> Result<Vec<u8>> {
+        let key = iter.copy_key()?;
+        if collections.invalid_metas.contains(&key) {
+            return Ok(key);
+        }
+        let data = iter.copy_data()?;
+        if let Some(collection) = collections.collection_of(&key) {
+            if let Err(err) = bson::from_slice::<Document>(data.as_slice()) {
+                report.skipped_documents.push(SkippedDocument {
+                    collection: collection.to_string(),
+                    key: inspect::format_key(&key),
+                    error: err.to_string(),
+                });
+                return Ok(key);
+            }
+            report.recovered_count += 1;
+        }
+        target_txn.set(&key, &data)?;
+        Ok(key)
+    }
+
     pub fn metrics(&self) -> Metrics {
         self.metrics.clone()
     }
 
     pub fn start_transaction(&self) -> Result<TransactionInner> {
-        Ok(TransactionInner::new(self.rocksdb.begin_transaction()?))
+        let sync = self.wal_sync_policy()?.sync_on_commit();
+        crate::trace_event!(sync, "begin transaction");
+        Ok(TransactionInner::new(self.rocksdb.begin_transaction(sync)?))
+    }
+
+    /// Begin a transaction which reads the database as of now,
+    /// so a long-lived cursor doesn't see the writes during its iteration,
+    /// e.g. the documents read by the keys of an index.
+    pub fn start_snapshot_transaction(&self) -> Result<TransactionInner> {
+        let snapshot = self.rocksdb.create_read_snapshot()?;
+        Ok(TransactionInner::new(self.rocksdb.begin_transaction_at(&snapshot)?))
+    }
+
+    pub fn start_transaction_with_options(&self, options: &TransactionOptions) -> Result<TransactionInner> {
+        let db_policy = self.wal_sync_policy()?;
+        let sync_policy = match options.sync_policy {
+            // the WAL is only synced periodically by the worker of the database policy
+            Some(WalSyncPolicy::Interval(_)) if options.sync_policy != Some(db_policy) => {
+                return Err(Error::ValidationError(
+                    "WalSyncPolicy::Interval can't override the sync policy of the database, use EveryCommit or OsBuffered".to_string()
+                ));
+            }
+            Some(policy) => policy,
+            None => db_policy,
+        };
+        crate::trace_event!(sync = sync_policy.sync_on_commit(), "begin transaction");
+        let txn = self.rocksdb.begin_transaction_with_options(
+            sync_policy.sync_on_commit(),
+            options.no_slowdown.unwrap_or(false),
+        )?;
+        Ok(TransactionInner::new(txn))
+    }
+
+    pub fn backup_to(&self, path: &Path) -> Result<()> {
+        self.copyable_disk_path()?;
+        self.rocksdb.create_checkpoint(path)
+    }
+
+    pub fn backup_incremental(&self, backup_dir: &Path) -> Result<BackupInfo> {
+        self.copyable_disk_path()?;
+        self.rocksdb.create_incremental_backup(backup_dir)
+    }
+
+    /// Copy all the data into a new database in `path`.
+    pub fn persist_to(&self, path: &Path) -> Result<()> {
+        let target = RocksDBWrapper::open(path, &Config::default())?;
+        self.copy_into(&target)
+    }
+
+    /// Pack all the data into a bundle, which can be opened by [`DatabaseInner::open_bytes`].
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let writer = Arc::new(BundleWriter::default());
+        {
+            let mut config = ConfigBuilder::new();
+            config.set_storage_backend(writer.clone());
+            let target = RocksDBWrapper::open(Path::new(BUNDLE_PATH), &config.take())?;
+            self.copy_into(&target)?;
+        }
+        Ok(writer.pack())
+    }
+
+    fn copy_into(&self, target: &RocksDBWrapper) -> Result<()> {
+        let source_txn = self.rocksdb.begin_transaction(false)?;
+        let target_txn = target.begin_transaction(true)?;
+
+        {
+            let iter = source_txn.new_iterator();
+            iter.seek_to_first();
+            while iter.valid() {
+                target_txn.set(iter.copy_key()?.as_slice(), iter.copy_data()?.as_slice())?;
+                iter.next();
+            }
+            iter.error()?;
+        }
+
+        target_txn.commit()?;
+        Ok(())
+    }
+
+    /// The directory of the database on the disk.
+    fn disk_path(&self) -> Result<&Path> {
+        self.path.as_deref().ok_or(Error::InMemoryNotSupported)
+    }
+
+    /// The directory of the database copied by the snapshots and the backups,
+    /// which would miss the files moved to the cold path.
+    fn copyable_disk_path(&self) -> Result<&Path> {
+        let path = self.disk_path()?;
+        if self.config.cold_path.is_some() {
+            return Err(Error::ColdPathNotSupported);
+        }
+        Ok(path)
+    }
+
+    pub fn list_backups(backup_dir: &Path) -> Result<Vec<BackupInfo>> {
+        let engine = RocksDBBackupEngine::open(backup_dir)?;
+        Ok(engine.backup_infos())
+    }
+
+    pub fn restore_backup(backup_dir: &Path, backup_id: Option<u32>, db_path: &Path) -> Result<()> {
+        let engine = RocksDBBackupEngine::open(backup_dir)?;
+        engine.restore(backup_id, db_path)
+    }
+
+    pub fn create_snapshot(&self, name: &str) -> Result<()> {
+        self.copyable_disk_path()?;
+        let snapshot_path = self.snapshot_path(name)?;
+        if snapshot_path.exists() {
+            return Err(Error::SnapshotAlreadyExists(name.to_string()));
+        }
+        std::fs::create_dir_all(self.disk_path()?.join(SNAPSHOTS_DIR))?;
+        self.rocksdb.create_checkpoint(snapshot_path.as_path())
+    }
+
+    pub fn list_snapshots(&self) -> Result<Vec<String>> {
+        let mut result = Vec::new();
+        let path = match self.path.as_deref() {
+            Some(path) => path,
+            None => return Ok(result),
+        };
+        if let Ok(entries) = std::fs::read_dir(path.join(SNAPSHOTS_DIR)) {
+            for entry in entries {
+                result.push(entry?.file_name().to_string_lossy().to_string());
+            }
+        }
+        result.sort();
+        Ok(result)
+    }
+
+    pub fn drop_snapshot(&self, name: &str) -> Result<()> {
+        let snapshot_path = self.existing_snapshot_path(name)?;
+        std::fs::remove_dir_all(snapshot_path)?;
+        Ok(())
+    }
+
+    pub fn open_snapshot(&self, name: &str) -> Result<DatabaseInner> {
+        let snapshot_path = self.existing_snapshot_path(name)?;
+        DatabaseInner::open_read_only(snapshot_path.as_path(), Config::default())
+    }
+
+    /// Replace the files of the closed database in `path` with the snapshot.
+    pub fn restore_snapshot(path: &Path, name: &str) -> Result<()> {
+        DatabaseInner::validate_snapshot_name(name)?;
+        let snapshot_path = path.join(SNAPSHOTS_DIR).join(name);
+        if !snapshot_path.is_dir() {
+            return Err(Error::SnapshotNotFound(name.to_string()));
+        }
+
+        // held until the files are replaced, so the database can't be opened meanwhile
+        let _lock = RocksDBLock::acquire(path)?;
+
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            if entry.file_name() == SNAPSHOTS_DIR || entry.file_name() == LOCK_FILE {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                std::fs::remove_dir_all(entry.path())?;
+            } else {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+
+        for entry in std::fs::read_dir(snapshot_path)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let target = path.join(&file_name);
+            // the table files are immutable, so they can be shared with the snapshot,
+            // but the MANIFEST, the OPTIONS and the WAL are written by the database
+            let file_name = file_name.to_string_lossy();
+            let immutable = file_name.ends_with(".sst") || file_name.ends_with(".blob");
+            if !immutable || std::fs::hard_link(entry.path(), target.as_path()).is_err() {
+                std::fs::copy(entry.path(), target.as_path())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn existing_snapshot_path(&self, name: &str) -> Result<PathBuf> {
+        let snapshot_path = self.snapshot_path(name)?;
+        if !snapshot_path.is_dir() {
+            return Err(Error::SnapshotNotFound(name.to_string()));
+        }
+        Ok(snapshot_path)
+    }
+
+    fn snapshot_path(&self, name: &str) -> Result<PathBuf> {
+        DatabaseInner::validate_snapshot_name(name)?;
+        Ok(self.disk_path()?.join(SNAPSHOTS_DIR).join(name))
+    }
+
+    fn validate_snapshot_name(name: &str) -> Result<()> {
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(Error::IllegalSnapshotName(name.to_string()));
+        }
+        for ch in name.chars() {
+            if ch == '/' || ch == '\\' || ch == '\n' || ch == '\t' || ch == '\r' {
+                return Err(Error::IllegalSnapshotName(name.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn vacuum(&self) -> Result<VacuumResult> {
+        crate::trace_span!("polodb.vacuum");
+        let size_before = DatabaseInner::files_size(self.path.as_deref())?;
+        self.rocksdb.compact_all()?;
+        // the statistics of a replica are replicated from the primary
+        if !self.rocksdb.is_replica()? {
+            self.analyze_all()?;
+        }
+        let size_after = DatabaseInner::files_size(self.path.as_deref())?;
+        Ok(VacuumResult {
+            size_before,
+            size_after,
+        })
+    }
+
+    /// The total size of the files in the directory of the database,
+    /// excluding the snapshots. It's 0 for the in-memory databases.
+    fn files_size(path: Option<&Path>) -> Result<u64> {
+        let mut size = 0;
+        let path = match path {
+            Some(path) => path,
+            None => return Ok(size),
+        };
+        for entry in std::fs::read_dir(path)? {
+            let metadata = entry?.metadata()?;
+            if metadata.is_file() {
+                size += metadata.len();
+            }
+        }
+        Ok(size)
+    }
+
+    pub fn storage_stats(&self) -> Result<StorageStats> {
+        let mut stats = self.rocksdb.storage_stats()?;
+        stats.wal_size = DatabaseInner::wal_size(self.path.as_deref())?;
+        stats.disk_size = DatabaseInner::files_size(self.path.as_deref())?;
+        if let (Some(_), Some(cold_path)) = (&self.path, &self.config.cold_path) {
+            if cold_path.exists() {
+                stats.disk_size += DatabaseInner::files_size(Some(cold_path))?;
+            }
+        }
+        Ok(stats)
+    }
+
+    pub fn recovery_summary(&self) -> RecoverySummary {
+        self.recovery.clone()
+    }
+
+    pub fn inspect(&self) -> Result<InspectReport> {
+        let levels = self.rocksdb.live_files()?
+            .into_iter()
+            .enumerate()
+            .map(|(level, (files, blocks))| LevelInspection {
+                level: level as u32,
+                files: files.into_iter()
+                    .map(|file| TableFileInspection {
+                        name: file.name,
+                        size: file.size,
+                        entries: file.entries,
+                        deletions: file.deletions,
+                        smallest_key: inspect::format_key(&file.smallest_key),
+                        largest_key: inspect::format_key(&file.largest_key),
+                    })
+                    .collect(),
+                blocks,
+            })
+            .collect();
+
+        let mut report = InspectReport {
+            latest_sequence: self.rocksdb.latest_sequence_number()?,
+            levels,
+            ..Default::default()
+        };
+        if let Some(path) = &self.path {
+            report.manifest = inspect::inspect_manifest(path)?;
+            report.wal_files = inspect::inspect_wal_files(path)?;
+        }
+        Ok(report)
+    }
+
+    pub fn fragmentation(&self) -> Result<FragmentationReport> {
+        crate::trace_span!("polodb.fragmentation");
+        // the dead bytes are counted in the table files only
+        self.rocksdb.flush()?;
+        let levels = self.rocksdb.live_files()?;
+
+        let txn = self.start_snapshot_transaction()?;
+        let iter = txn.rocksdb_txn.new_iterator();
+        let mut live = LiveData::default();
+        iter.seek_to_first();
+        while iter.valid() {
+            live.add(iter.copy_key()?.len(), iter.copy_data()?.len());
+            iter.next();
+        }
+        iter.error()?;
+
+        Ok(fragmentation::estimate(&levels, &live))
+    }
+
+    /// The total size of the live write-ahead log files. The archived ones are not counted.
+    fn wal_size(path: Option<&Path>) -> Result<u64> {
+        let mut size = 0;
+        let path = match path {
+            Some(path) => path,
+            None => return Ok(size),
+        };
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            let is_wal = entry.path().extension().is_some_and(|ext| ext == "log");
+            let metadata = entry.metadata()?;
+            if is_wal && metadata.is_file() {
+                size += metadata.len();
+            }
+        }
+        Ok(size)
+    }
+
+    /// Check the size of the database before a write,
+    /// see [`crate::ConfigBuilder::set_max_db_size`].
+    pub fn check_quota(self: &Arc<Self>) -> Result<()> {
+        let limit = match self.config.max_db_size {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let size = self.rocksdb.estimated_size()?;
+        if size <= limit {
+            return Ok(());
+        }
+        let size = match &self.config.quota_policy {
+            QuotaPolicy::Reject => size,
+            QuotaPolicy::Evict(evictor) => {
+                evictor(&Database::from_inner(self.clone()), size, limit)?;
+                self.rocksdb.estimated_size()?
+            }
+        };
+        if size > limit {
+            return Err(Error::QuotaExceeded { size, limit });
+        }
+        Ok(())
+    }
+
+    pub fn latest_sequence_number(&self) -> Result<u64> {
+        self.rocksdb.latest_sequence_number()
+    }
+
+    pub fn block_cache_stats(&self) -> Result<BlockCacheStats> {
+        self.rocksdb.block_cache_stats()
+    }
+
+    fn wal_sync_policy(&self) -> Result<WalSyncPolicy> {
+        Ok(*self.wal_sync_policy.read()?)
+    }
+
+    pub fn set_option(&self, option: RuntimeOption) -> Result<()> {
+        crate::trace_event!(option = ?option, "set option");
+        match option {
+            RuntimeOption::BlockCacheSize(capacity) => {
+                self.rocksdb.set_block_cache_capacity(capacity)
+            }
+            RuntimeOption::MaxBackgroundJobs(jobs) => {
+                if jobs == 0 {
+                    return Err(Error::ValidationError("max background jobs must be positive".to_string()));
+                }
+                self.rocksdb.set_db_options(&[("max_background_jobs", jobs.to_string())])
+            }
+            RuntimeOption::SlowQueryThreshold(threshold) => {
+                self.profiler.set_threshold(threshold);
+                Ok(())
+            }
+            RuntimeOption::WalSyncPolicy(policy) => {
+                let mut current = self.wal_sync_policy.write()?;
+                self.rocksdb.set_wal_sync_policy(policy)?;
+                *current = policy;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn current_ops(&self) -> Vec<CurrentOp> {
+        self.operations.current_ops()
+    }
+
+    pub fn kill_op<F>(&self, id: u64, predicate: F) -> bool
+    where
+        F: FnOnce(&str) -> bool
+    {
+        self.operations.kill_op(id, predicate)
+    }
+
+    /// Replace the lazy transforms of the collections of the named database `db_name`.
+    pub fn install_transforms(&self, db_name: Option<&str>, transforms: HashMap<String, Arc<CollectionTransforms>>) {
+        let mut installed = self.transforms.write().unwrap();
+        installed.retain(|name, _| !DatabaseInner::in_database(name, db_name));
+        installed.extend(transforms);
+    }
+
+    fn collection_transforms(&self, col_name: &str) -> Option<Arc<CollectionTransforms>> {
+        self.transforms.read().unwrap().get(col_name).cloned()
+    }
+
+    pub fn schema_version(&self, db_name: Option<&str>) -> Result<u32> {
+        let txn = self.start_transaction()?;
+        migration::read_version(&txn, db_name)
+    }
+
+    pub fn next_sequence(&self, db_name: Option<&str>, name: &str, txn: &TransactionInner) -> Result<i64> {
+        let value = sequence::next_value(txn, db_name, name)?;
+        txn.auto_commit()?;
+        Ok(value)
+    }
+
+    pub fn current_sequence(&self, db_name: Option<&str>, name: &str) -> Result<Option<i64>> {
+        let txn = self.start_transaction()?;
+        sequence::current_value(&txn, db_name, name)
+    }
+
+    pub fn reset_sequence(&self, db_name: Option<&str>, name: &str, value: i64, txn: &TransactionInner) -> Result<()> {
+        sequence::reset_value(txn, db_name, name, value)?;
+        txn.auto_commit()
+    }
+
+    /// Report the counters and the gauges of the storage to the metrics recorder.
+    #[cfg(feature = "metrics")]
+    pub fn report_metrics(&self) -> Result<()> {
+        let recorder = match self.metrics.recorder() {
+            Some(recorder) => recorder,
+            None => return Ok(()),
+        };
+
+        let cache = self.block_cache_stats()?;
+        recorder.absolute_counter("polodb_block_cache_hits_total", &[], cache.hits);
+        recorder.absolute_counter("polodb_block_cache_misses_total", &[], cache.misses);
+        recorder.set_gauge("polodb_block_cache_usage_bytes", &[], cache.usage as f64);
+
+        let tickers = self.rocksdb.tickers()?;
+        recorder.absolute_counter("polodb_wal_syncs_total", &[], tickers.wal_syncs);
+        recorder.absolute_counter("polodb_wal_written_bytes_total", &[], tickers.wal_bytes);
+        recorder.absolute_counter("polodb_compaction_read_bytes_total", &[], tickers.compaction_read_bytes);
+        recorder.absolute_counter("polodb_compaction_written_bytes_total", &[], tickers.compaction_write_bytes);
+        recorder.absolute_counter("polodb_flush_written_bytes_total", &[], tickers.flush_write_bytes);
+
+        let stats = self.storage_stats()?;
+        recorder.set_gauge("polodb_running_compactions", &[], stats.running_compactions as f64);
+        recorder.set_gauge("polodb_pending_compaction_bytes", &[], stats.pending_compaction_bytes as f64);
+        recorder.set_gauge("polodb_memtable_usage_bytes", &[], stats.memtable_usage as f64);
+        recorder.set_gauge("polodb_wal_size_bytes", &[], stats.wal_size as f64);
+        recorder.set_gauge("polodb_disk_size_bytes", &[], stats.disk_size as f64);
+        for level in &stats.levels {
+            let level_label = level.level.to_string();
+            let labels = [("level", level_label.as_str())];
+            recorder.set_gauge("polodb_level_files", &labels, level.file_count as f64);
+            recorder.set_gauge("polodb_level_size_bytes", &labels, level.size as f64);
+        }
+
+        Ok(())
+    }
+
+    pub fn sync_tracker(&self) -> Result<Option<Arc<SyncTracker>>> {
+        self.rocksdb.sync_tracker()
+    }
+
+    pub fn set_replica(&self, replica: bool) -> Result<()> {
+        self.rocksdb.set_replica(replica)
+    }
+
+    /// Write the operations replicated from the primary atomically.
+    pub fn write_replicated(&self, operations: &[WalOperation]) -> Result<()> {
+        self.rocksdb.write_batch(operations, self.wal_sync_policy()?.sync_on_commit())
+    }
+
+    pub fn ship_wal<F>(&self, since: u64, mut f: F) -> Result<u64>
+    where
+        F: FnMut(&WalRecord) -> bool
+    {
+        self.ship_wal_records(since, |record, _| {
+            let next = if f(record) { None } else { Some(record.next_sequence()) };
+            Ok(next)
+        })
+    }
+
+    /// Ship the changes of the documents, `f` returns `false` to stop.
+    ///
+    /// If it stops before the last change of a commit,
+    /// the returned sequence resumes from the commit.
+    pub fn ship_changes<F>(&self, since: u64, mut f: F) -> Result<u64>
+    where
+        F: FnMut(&ChangeEvent) -> bool
+    {
+        self.ship_wal_records(since, |record, pre_images| {
+            let events = change_events(record, pre_images)?;
+            let event_count = events.len();
+            for (index, event) in events.iter().enumerate() {
+                if !f(event) {
+                    let next = if index + 1 == event_count { record.next_sequence() } else { record.sequence };
+                    return Ok(Some(next));
+                }
+            }
+            Ok(None)
+        })
+    }
+
+    /// Pass the records from sequence `since` to `f`, which returns
+    /// the sequence to resume from if the shipping stops.
+    fn ship_wal_records<F>(&self, since: u64, mut f: F) -> Result<u64>
+    where
+        F: FnMut(&WalRecord, Vec<WalPreImage>) -> Result<Option<u64>>
+    {
+        let latest = self.rocksdb.latest_sequence_number()?;
+        if since > latest {
+            return Ok(since);
+        }
+
+        let iter = self.rocksdb.wal_iter(since)?;
+        let mut next_sequence = since;
+        while iter.valid() {
+            let (record, pre_images) = iter.record_with_pre_images()?;
+            // the first batch may start before the requested sequence
+            if record.next_sequence() > since {
+                if let Some(next) = f(&record, pre_images)? {
+                    return Ok(next);
+                }
+                next_sequence = record.next_sequence();
+            }
+            iter.next();
+        }
+        iter.status()?;
+
+        Ok(next_sequence)
     }
 
     fn internal_get_collection_id_by_name(&self, txn: &TransactionInner, name: &str) -> Result<CollectionSpecification> {
@@ -140,10 +987,54 @@ impl DatabaseInner {
     }
 
     pub fn create_collection(&self, name: &str) -> Result<CollectionSpecification> {
-        DatabaseInner::validate_col_name(name)?;
+        self.create_collection_with_options(name, &CreateCollectionOptions::default())
+    }
+
+    pub fn create_collection_with_options(&self, name: &str, options: &CreateCollectionOptions) -> Result<CollectionSpecification> {
+        DatabaseInner::validate_namespaced_col_name(name)?;
+        let capped_info = capped::capped_info_from_options(options)?;
+        timeseries::validate_options(options)?;
+        partition::validate_options(options)?;
+        let view_info = view::view_info_from_options(options)?;
+        if let Some(view_info) = &view_info {
+            DatabaseInner::validate_namespaced_col_name(&view_info.view_on)?;
+        }
+        let validator_info = validator::validator_info_from_options(options)?;
+        if let Some(defaults) = &options.defaults {
+            if view_info.is_some() {
+                return Err(Error::ValidationError("a view can't have default fields".to_string()));
+            }
+            defaults::validate_defaults(defaults)?;
+        }
 
         let txn = self.start_transaction()?;
-        let result = self.create_collection_internal(name, &txn)?;
+        key_ordering::validate_options(options, &txn)?;
+        let mut result = self.create_collection_internal(name, &txn)?;
+        if let Some(IdStrategy::Custom(name)) = &options.id_strategy {
+            if !self.config.id_generators.contains_key(name) {
+                return Err(Error::IdGeneratorNotFound(name.clone()));
+            }
+        }
+        if capped_info.is_some() || options.timeseries.is_some() || view_info.is_some()
+            || validator_info.is_some() || options.defaults.is_some() || options.id_strategy.is_some()
+            || options.partition_key.is_some() || options.key_ordering.is_some() {
+            result.capped = capped_info;
+            result.validator = validator_info;
+            result.defaults = options.defaults.clone();
+            result.id_strategy = options.id_strategy.clone();
+            result.partition_key = options.partition_key.clone();
+            result.key_ordering = options.key_ordering.clone();
+            if let Some(timeseries) = &options.timeseries {
+                result.collection_type = CollectionType::Timeseries;
+                result.timeseries = Some(timeseries.clone());
+            }
+            if view_info.is_some() {
+                result.collection_type = CollectionType::View;
+                result.info.uuid = None;
+                result.view = view_info;
+            }
+            DatabaseInner::update_collection_spec(name, &result, &txn)?;
+        }
         txn.commit()?;
 
         Ok(result)
@@ -173,8 +1064,7 @@ impl DatabaseInner {
             return Err(Error::CollectionAlreadyExits(name.into()));
         }
 
-        let uuid = uuid::Uuid::now_v1(node_id);
-        let spec = CollectionSpecification::new(name.to_string(), uuid);
+        let spec = self.new_collection_spec(txn, name, node_id)?;
 
         let stacked_key = crate::utils::bson::stacked_key(&[
             Bson::String(TABLE_META_PREFIX.to_string()),
@@ -188,6 +1078,180 @@ impl DatabaseInner {
         Ok(spec)
     }
 
+    /// A renamed collection keeps its storage name, so the new collection
+    /// reusing the old name is given a unique one.
+    fn new_collection_spec(&self, txn: &TransactionInner, name: &str, node_id: &[u8; 6]) -> Result<CollectionSpecification> {
+        let uuid = uuid::Uuid::now_v1(node_id);
+        let mut spec = CollectionSpecification::new(name.to_string(), uuid);
+
+        let mut cursor = Cursor::new_with_str_prefix(TABLE_META_PREFIX, txn.rocksdb_txn.new_iterator())?;
+        cursor.reset()?;
+        while cursor.has_next() {
+            let data = cursor.copy_data()?;
+            let other = bson::from_slice::<CollectionSpecification>(data.as_slice())?;
+            if other.storage_name() == name {
+                spec.storage_name = Some(format!("{}${}", name, uuid.simple()));
+                break;
+            }
+            cursor.next()?;
+        }
+
+        Ok(spec)
+    }
+
+    /// Attach the validator to the collection, or remove it if `validator` is `None`.
+    /// The documents in the collection are not checked.
+    pub fn set_validator(&self, name: &str, validator: Option<(Document, ValidationAction)>) -> Result<()> {
+        DatabaseInner::validate_namespaced_col_name(name)?;
+        let validator_info = match validator {
+            Some((validator, action)) => Some(validator::validator_info(validator, action)?),
+            None => None,
+        };
+
+        let txn = self.start_transaction()?;
+        let mut spec = self.internal_get_collection_id_by_name(&txn, name)?;
+        if spec.is_view() {
+            return Err(Error::ViewReadOnly(name.to_string()));
+        }
+        spec.validator = validator_info;
+        DatabaseInner::update_collection_spec(name, &spec, &txn)?;
+        txn.commit()?;
+        self.validators.invalidate(name);
+
+        Ok(())
+    }
+
+    /// Replace the default fields of the collection, or remove them if `defaults` is `None`.
+    pub fn set_defaults(&self, name: &str, defaults: Option<Document>) -> Result<()> {
+        DatabaseInner::validate_namespaced_col_name(name)?;
+        if let Some(defaults) = &defaults {
+            defaults::validate_defaults(defaults)?;
+        }
+
+        let txn = self.start_transaction()?;
+        let mut spec = self.internal_get_collection_id_by_name(&txn, name)?;
+        if spec.is_view() {
+            return Err(Error::ViewReadOnly(name.to_string()));
+        }
+        spec.defaults = defaults;
+        DatabaseInner::update_collection_spec(name, &spec, &txn)?;
+        txn.commit()?;
+
+        Ok(())
+    }
+
+    pub fn rename_collection(&self, old_name: &str, new_name: &str) -> Result<()> {
+        DatabaseInner::validate_namespaced_col_name(old_name)?;
+        DatabaseInner::validate_namespaced_col_name(new_name)?;
+        if new_name.is_empty() {
+            return Err(Error::IllegalCollectionName(new_name.into()));
+        }
+
+        let txn = self.start_transaction()?;
+        let mut spec = self.internal_get_collection_id_by_name(&txn, old_name)?;
+        if old_name == new_name {
+            return Ok(());
+        }
+        if self.check_collection_exist(&txn, new_name)? {
+            return Err(Error::CollectionAlreadyExits(new_name.into()));
+        }
+
+        // Only the metadata is moved, the keys of the documents and the indexes
+        // are still prefixed by the storage name.
+        let storage_name = spec.storage_name().to_string();
+        spec._id = new_name.to_string();
+        spec.storage_name = if storage_name == new_name {
+            None
+        } else {
+            Some(storage_name)
+        };
+
+        DatabaseInner::update_collection_spec(new_name, &spec, &txn)?;
+        self.delete_collection_meta(old_name, &txn)?;
+        txn.commit()?;
+        self.validators.invalidate(old_name);
+
+        Ok(())
+    }
+
+    /// Copy the documents of `src` into the new collection `dst` of `target`
+    /// in a single pass. The indexes are created before the documents are inserted,
+    /// so they are built along the way.
+    pub fn clone_collection_to(
+        &self,
+        src: &str,
+        target: &DatabaseInner,
+        dst: &str,
+        options: &CloneCollectionOptions,
+    ) -> Result<u64> {
+        DatabaseInner::validate_namespaced_col_name(src)?;
+        DatabaseInner::validate_namespaced_col_name(dst)?;
+
+        let src_txn = self.start_transaction()?;
+        let src_spec = self.internal_get_collection_id_by_name(&src_txn, src)?;
+        if src_spec.is_view() {
+            return Err(Error::ValidationError(format!("the view '{}' can't be cloned, create the view on the cloned source instead", src)));
+        }
+
+        let mut dst_txn = target.start_transaction()?;
+        dst_txn.set_auto_commit(false);
+        // the orderings and the id generators are registered in the config of the target
+        if let Some(name) = &src_spec.key_ordering {
+            key_ordering::resolve(&dst_txn, name)?;
+        }
+        if let Some(IdStrategy::Custom(name)) = &src_spec.id_strategy {
+            if !target.config.id_generators.contains_key(name) {
+                return Err(Error::IdGeneratorNotFound(name.clone()));
+            }
+        }
+        let mut dst_spec = target.internal_create_collection(&dst_txn, dst, &target.node_id)?;
+        dst_spec.collection_type = src_spec.collection_type.clone();
+        dst_spec.capped = src_spec.capped;
+        dst_spec.timeseries = src_spec.timeseries.clone();
+        dst_spec.id_strategy = src_spec.id_strategy.clone();
+        dst_spec.partition_key = src_spec.partition_key.clone();
+        dst_spec.key_ordering = src_spec.key_ordering.clone();
+        if options.copy_indexes() {
+            dst_spec.indexes = src_spec.indexes.clone();
+        }
+        DatabaseInner::update_collection_spec(dst, &dst_spec, &dst_txn)?;
+
+        // the oldest documents of a capped collection are removed first, so they are copied first
+        let mut cursor = if src_spec.is_capped() {
+            let pipeline = vec![doc! { "$sort": { "$natural": 1 } }];
+            let mut cursor = self.aggregate_with_owned_session::<Document>(src, pipeline, src_txn, &SpillOptions::default())?;
+            cursor.set_transforms(self.collection_transforms(src));
+            cursor
+        } else {
+            self.find_with_owned_session::<Document>(src, None, src_txn)?
+        };
+        let mut count: u64 = 0;
+        while cursor.advance()? {
+            let doc = cursor.deserialize_current()?;
+            let (_, spec) = target.insert_one_with_meta(&dst_txn, dst_spec, doc)?;
+            dst_spec = spec;
+            count += 1;
+        }
+
+        // the documents are copied as they are, like the ones in the source
+        // inserted before its validator and default fields were set
+        if src_spec.validator.is_some() || src_spec.defaults.is_some() {
+            dst_spec.validator = src_spec.validator.clone();
+            dst_spec.defaults = src_spec.defaults.clone();
+            DatabaseInner::update_collection_spec(dst, &dst_spec, &dst_txn)?;
+        }
+
+        dst_txn.commit()?;
+
+        Ok(count)
+    }
+
+    /// Register the program in the operations in flight and the profiler.
+    fn track_operation(&self, vm: &mut VM, op: &str, col_name: &str, filter_shape: Document) {
+        vm.set_profile(self.profiler.start(op, col_name, &filter_shape));
+        vm.set_operation(self.operations.register(op, col_name, filter_shape));
+    }
+
     pub(crate) fn make_handle<T: DeserializeOwned + Send + Sync>(&self, program: SubProgram, txn: TransactionInner) -> Result<ClientCursor<T>> {
         let vm = VM::new(
             txn,
@@ -198,7 +1262,7 @@ impl DatabaseInner {
     }
 
     pub fn create_index(&self, col_name: &str, index: IndexModel, txn: &TransactionInner) -> Result<()> {
-        DatabaseInner::validate_col_name(col_name)?;
+        DatabaseInner::validate_namespaced_col_name(col_name)?;
 
         self.internal_create_index(txn, col_name, index)?;
 
@@ -228,33 +1292,58 @@ impl DatabaseInner {
         order: &Bson,
         options: Option<&IndexOptions>,
     ) -> Result<()> {
-        if !DatabaseInner::is_num_1(order) {
-            return Err(Error::OnlySupportsAscendingOrder(key.to_string()));
-        }
+        let kind = match order.as_str().and_then(IndexKind::from_spec_name) {
+            Some(kind) => kind,
+            None if DatabaseInner::is_num_1(order) => IndexKind::Ascending,
+            None => return Err(Error::OnlySupportsAscendingOrder(key.to_string())),
+        };
 
-        let index_name = DatabaseInner::make_index_name(key, 1, options)?;
+        DatabaseInner::validate_index_options(kind, options, &self.config)?;
+        let index_name = match kind.spec_name() {
+            Some(spec_name) => DatabaseInner::make_index_name(key, spec_name, options)?,
+            None => DatabaseInner::make_index_name(key, 1, options)?,
+        };
 
         let test_collection_spec = self.internal_get_collection_id_by_name(txn, col_name);
         let mut collection_spec = match test_collection_spec {
             Ok(spec) => spec,
             Err(Error::CollectionNotFound(_)) => {
-                let uuid = uuid::Uuid::now_v1(&self.node_id);
-                CollectionSpecification::new(col_name.to_string(), uuid)
+                self.new_collection_spec(txn, col_name, &self.node_id)?
             }
             Err(err) => {
                 return Err(err);
             }
         };
 
+        if collection_spec.is_timeseries() {
+            return Err(Error::ValidationError("the time-series collections don't support indexes".to_string()));
+        }
+        if collection_spec.is_partitioned() {
+            return Err(Error::ValidationError("the partitioned collections don't support indexes".to_string()));
+        }
+        if collection_spec.is_view() {
+            return Err(Error::ViewReadOnly(col_name.to_string()));
+        }
+
         if collection_spec.indexes.get(&index_name).is_some() {
             return Ok(())
         }
+        if kind == IndexKind::Text && collection_spec.indexes.values().any(IndexInfo::is_text) {
+            return Err(Error::ValidationError(format!("the collection '{}' already has a text index", col_name)));
+        }
 
-        let index_info = IndexInfo::single_index(
-            key.to_string(),
-            1,
-            options.map(|x| x.clone()),
-        );
+        let mut index_info = if kind.is_ascending() {
+            IndexInfo::single_index(
+                key.to_string(),
+                1,
+                options.map(|x| x.clone()),
+            )
+        } else {
+            IndexInfo::with_kind(key.to_string(), kind, options.cloned())
+        };
+        if kind == IndexKind::Vector {
+            index_info.centroids = DatabaseInner::train_vector_index(txn, collection_spec.storage_name(), &index_info)?;
+        }
         collection_spec.indexes.insert(index_name.clone(), index_info.clone());
 
         DatabaseInner::update_collection_spec(
@@ -265,12 +1354,19 @@ impl DatabaseInner {
 
         self.build_index(
             txn,
-            col_name,
+            collection_spec.storage_name(),
             index_name.as_str(),
             &index_info,
         )
     }
 
+    fn train_vector_index(txn: &TransactionInner, col_name: &str, index_info: &IndexInfo) -> Result<Vec<Vec<f32>>> {
+        let (dimensions, similarity) = vector::index_options(index_info.options.as_ref())?;
+        let (field, _) = index_info.keys.iter().next().unwrap();
+        let lists = index_info.options.as_ref().and_then(|options| options.lists);
+        vector::train_centroids(txn, col_name, field, dimensions, similarity, lists)
+    }
+
     fn build_index(
         &self,
         txn: &TransactionInner,
@@ -289,7 +1385,7 @@ impl DatabaseInner {
     }
 
     pub fn drop_index(&self, col_name: &str, index_name: &str, txn: &TransactionInner) -> Result<()> {
-        DatabaseInner::validate_col_name(col_name)?;
+        DatabaseInner::validate_namespaced_col_name(col_name)?;
 
         self.internal_drop_index(col_name, index_name, txn)?;
 
@@ -317,7 +1413,7 @@ impl DatabaseInner {
 
         let mut builder = IndexBuilder::new(
             txn,
-            col_name,
+            collection_spec.storage_name(),
             index_name,
             index_info,
         );
@@ -348,7 +1444,28 @@ impl DatabaseInner {
         Ok(())
     }
 
-    fn make_index_name(key: &str, order: i32, index_options: Option<&IndexOptions>) -> Result<String> {
+    fn validate_index_options(kind: IndexKind, options: Option<&IndexOptions>, config: &Config) -> Result<()> {
+        if let Some(spec_name) = kind.spec_name() {
+            if options.and_then(|options| options.unique).unwrap_or(false) {
+                return Err(Error::ValidationError(format!("a {} index can't be unique", spec_name)));
+            }
+        }
+        if kind == IndexKind::Text {
+            Analyzer::from_options(options, |name| config.tokenizers.get(name).cloned())?;
+        } else if options.is_some_and(|options| options.analyzer.is_some() || options.stop_words.is_some()) {
+            return Err(Error::ValidationError("the analyzer is only for the text indexes".to_string()));
+        }
+        if kind == IndexKind::Vector {
+            vector::index_options(options)?;
+        } else if options.is_some_and(|options| {
+            options.dimensions.is_some() || options.similarity.is_some() || options.lists.is_some()
+        }) {
+            return Err(Error::ValidationError("the dimensions, similarity and lists are only for the vector indexes".to_string()));
+        }
+        Ok(())
+    }
+
+    fn make_index_name(key: &str, order: impl std::fmt::Display, index_options: Option<&IndexOptions>) -> Result<String> {
         if let Some(options) = index_options {
             if let Some(name) = &options.name {
                 DatabaseInner::validate_index_name(name)?;
@@ -375,17 +1492,17 @@ impl DatabaseInner {
     }
 
     #[inline]
-    fn fix_doc(mut doc: Document) -> Document {
+    fn fix_doc(&self, col_spec: &CollectionSpecification, mut doc: Document) -> Result<Document> {
         if let Some(id) = doc.get(meta_doc_key::ID) {
             // If the id type is not null, the document is ok
             if id.as_null().is_none() {
-                return doc;
+                return Ok(doc);
             }
         }
 
-        let new_oid = ObjectId::new();
-        doc.insert::<String, Bson>(meta_doc_key::ID.into(), new_oid.into());
-        doc
+        let new_id = self.id_generators.generate(col_spec.id_strategy.as_ref(), &doc, &self.config)?;
+        doc.insert::<String, Bson>(meta_doc_key::ID.into(), new_id);
+        Ok(doc)
     }
 
     fn validate_col_name(col_name: &str) -> Result<()> {
@@ -398,6 +1515,34 @@ impl DatabaseInner {
         Ok(())
     }
 
+    /// The collections of a named database are qualified by the name of the database,
+    /// such as `tenant_a.users`.
+    fn validate_namespaced_col_name(name: &str) -> Result<()> {
+        match name.split_once(NAMESPACE_SEPARATOR) {
+            Some((db_name, col_name)) => {
+                DatabaseInner::validate_db_name(db_name)?;
+                if col_name.is_empty() || DatabaseInner::validate_col_name(col_name).is_err() {
+                    return Err(Error::IllegalCollectionName(name.to_string()));
+                }
+                Ok(())
+            }
+            None => DatabaseInner::validate_col_name(name),
+        }
+    }
+
+    pub(crate) fn validate_db_name(db_name: &str) -> Result<()> {
+        if db_name.is_empty() {
+            return Err(Error::IllegalDatabaseName(db_name.to_string()));
+        }
+        for ch in db_name.chars() {
+            if ch == '$' || ch == '\n' || ch == '\t' || ch == '\r' || ch == '.' || ch == '/' || ch == '\\' {
+                return Err(Error::IllegalDatabaseName(db_name.to_string()))
+            }
+        }
+
+        Ok(())
+    }
+
     fn validate_index_name(col_name: &str) -> Result<()> {
         for ch in col_name.chars() {
             if ch == '$' || ch == '\n' || ch == '\t' || ch == '\r' || ch == '.' {
@@ -409,11 +1554,11 @@ impl DatabaseInner {
     }
 
     pub fn insert_one(&self, col_name: &str, doc: Document, txn: &TransactionInner) -> Result<InsertOneResult> {
-        DatabaseInner::validate_col_name(col_name)?;
-
-        let changed = self.insert_one_internal(txn, col_name, doc, &self.node_id)?;
-
-        Ok(changed)
+        crate::trace_span!("polodb.insert_one", collection = col_name);
+        self.metrics.observe("insert_one", || {
+            DatabaseInner::validate_namespaced_col_name(col_name)?;
+            self.insert_one_internal(txn, col_name, doc, &self.node_id)
+        })
     }
 
     fn insert_one_internal(&self, txn: &TransactionInner, col_name: &str, doc: Document, node_id: &[u8; 6]) -> Result<InsertOneResult> {
@@ -426,23 +1571,73 @@ impl DatabaseInner {
     /// Insert one item with the collection spec
     /// return the new spec for the outside to do the following operation
     fn insert_one_with_meta(&self, txn: &TransactionInner, col_spec: CollectionSpecification, doc: Document) -> Result<(InsertOneResult, CollectionSpecification)> {
-        let doc  = DatabaseInner::fix_doc(doc);
+        if col_spec.is_view() {
+            return Err(Error::ViewReadOnly(col_spec._id));
+        }
+        let mut doc = doc;
+        if let Some(defaults) = &col_spec.defaults {
+            defaults::apply_defaults(defaults, &mut doc)?;
+        }
+        let mut doc  = self.fix_doc(&col_spec, doc)?;
+        if let Some(transforms) = self.collection_transforms(col_spec.name()) {
+            transforms.stamp(&mut doc);
+        }
+        crate::utils::bson::check_document_size(&doc, self.config.max_document_size)?;
+        if let Some(validator) = self.validators.get(&col_spec)? {
+            validator.validate(&doc)?;
+        }
 
         let pkey = doc.get("_id").unwrap();
 
-        let stacked_key = crate::utils::bson::stacked_key([
-            &Bson::String(col_spec._id.clone()),
-            &pkey,
-        ])?;
+        if let Some(timeseries) = &col_spec.timeseries {
+            timeseries::insert_measurement(txn, col_spec.storage_name(), timeseries, &doc)?;
+            return Ok((
+                InsertOneResult { inserted_id: pkey.clone() },
+                col_spec
+            ));
+        }
+
+        let stored_pkey = match &col_spec.key_ordering {
+            Some(name) => key_ordering::ordered_key(key_ordering::resolve(txn, name)?.as_ref(), pkey)?,
+            None => pkey.clone(),
+        };
+        let stacked_key = match &col_spec.partition_key {
+            Some(partition_key) => {
+                let partition = partition::partition_of(partition_key, &doc)?;
+                partition::document_key(col_spec.storage_name(), &partition, pkey)?
+            }
+            None => crate::utils::bson::stacked_key([
+                &Bson::String(col_spec.storage_name().to_string()),
+                &stored_pkey,
+            ])?,
+        };
 
         let doc_buf = bson::to_vec(&doc)?;
 
+        // replacing a document would leave a stale entry in the insertion order
+        if col_spec.is_capped() && txn.rocksdb_txn.get_for_update(stacked_key.as_ref())?.is_some() {
+            return Err(DuplicateKeyError {
+                name: "_id_".to_string(),
+                key: pkey.to_string(),
+                ns: col_spec._id.clone(),
+            }.into());
+        }
+
         txn.put(
             stacked_key.as_ref(),
             &doc_buf,
         )?;
 
-        self.try_insert_index(txn, &col_spec, &doc, pkey)?;
+        self.try_insert_index(txn, &col_spec, &doc, &stored_pkey)?;
+
+        if let Some(capped_info) = &col_spec.capped {
+            let evicted = capped::record_insert(txn, col_spec.storage_name(), capped_info, pkey, doc_buf.len() as u64)?;
+            let mut txn = txn.clone();
+            txn.set_auto_commit(false);
+            for evicted_pkey in evicted {
+                self.internal_delete_by_query(&txn, col_spec.name(), doc! { "_id": evicted_pkey }, false)?;
+            }
+        }
 
         Ok((
             InsertOneResult { inserted_id: pkey.clone() },
@@ -466,11 +1661,11 @@ impl DatabaseInner {
         docs: impl IntoIterator<Item = impl Borrow<T>>,
         txn: &TransactionInner,
     ) -> Result<InsertManyResult> {
-        DatabaseInner::validate_col_name(col_name)?;
-
-        let result = self.insert_many_internal(txn, col_name, docs, &self.node_id)?;
-
-        Ok(result)
+        crate::trace_span!("polodb.insert_many", collection = col_name);
+        self.metrics.observe("insert_many", || {
+            DatabaseInner::validate_namespaced_col_name(col_name)?;
+            self.insert_many_internal(txn, col_name, docs, &self.node_id)
+        })
     }
 
     fn insert_many_internal<T: Serialize>(
@@ -526,13 +1721,16 @@ impl DatabaseInner {
         options: UpdateOptions,
         txn: &TransactionInner,
     ) -> Result<UpdateResult> {
-        DatabaseInner::validate_col_name(col_name)?;
+        crate::trace_span!("polodb.update_one", collection = col_name);
+        self.metrics.observe("update_one", || {
+            DatabaseInner::validate_namespaced_col_name(col_name)?;
 
-        let mut txn = txn.clone();
-        txn.set_auto_commit(false);
-        let result = self.internal_update(col_name, query, update, false, options, &txn)?;
+            let mut txn = txn.clone();
+            txn.set_auto_commit(false);
+            let result = self.internal_update(col_name, query, update, false, options, &txn)?;
 
-        Ok(result)
+            Ok(result)
+        })
     }
 
     pub(crate) fn update_many(
@@ -543,20 +1741,23 @@ impl DatabaseInner {
         options: UpdateOptions,
         txn: &TransactionInner,
     ) -> Result<UpdateResult> {
-        DatabaseInner::validate_col_name(col_name)?;
+        crate::trace_span!("polodb.update_many", collection = col_name);
+        self.metrics.observe("update_many", || {
+            DatabaseInner::validate_namespaced_col_name(col_name)?;
 
-        let mut txn = txn.clone();
-        txn.set_auto_commit(false);
-        let result = self.internal_update(
-            col_name,
-            query,
-            update,
-            true,
-            options,
-            &txn,
-        )?;
+            let mut txn = txn.clone();
+            txn.set_auto_commit(false);
+            let result = self.internal_update(
+                col_name,
+                query,
+                update,
+                true,
+                options,
+                &txn,
+            )?;
 
-        Ok(result)
+            Ok(result)
+        })
     }
 
     fn internal_update(
@@ -571,7 +1772,19 @@ impl DatabaseInner {
         let meta_opt = self.get_collection_meta_by_name_advanced_auto(col_name, false, txn)?;
 
         let result = match &meta_opt {
+            Some(col_spec) if col_spec.is_capped() => {
+                return Err(Error::CappedCollectionImmutable(col_name.to_string()));
+            }
+            Some(col_spec) if col_spec.is_timeseries() => {
+                return Err(Error::TimeseriesCollectionImmutable(col_name.to_string()));
+            }
+            Some(col_spec) if col_spec.is_view() => {
+                return Err(Error::ViewReadOnly(col_name.to_string()));
+            }
             Some(col_spec) => {
+                if let Some(partition_key) = &col_spec.partition_key {
+                    partition::validate_update(partition_key, &update)?;
+                }
                 let subprogram = SubProgram::compile_update(
                     col_spec,
                     &query,
@@ -585,6 +1798,12 @@ impl DatabaseInner {
                     subprogram,
                     self.metrics.clone(),
                 );
+                vm.set_max_document_size(self.config.max_document_size);
+                vm.set_validator(self.validators.get(col_spec)?);
+                if let Some(max_time) = options.max_time {
+                    vm.set_max_time(max_time);
+                }
+                self.track_operation(&mut vm, "update", col_name, profiler::filter_shape(&query));
                 vm.execute()?;
 
                 // vm.r2 as u64
@@ -632,43 +1851,109 @@ impl DatabaseInner {
 
         Ok(())
     }
-    pub fn drop_collection(&self, col_name: &str, txn: &TransactionInner) -> Result<()> {
-        DatabaseInner::validate_col_name(col_name)?;
-
-        self.drop_collection_internal(col_name, txn)?;
+    pub fn drop_collection(&self, col_name: &str, txn: &TransactionInner) -> Result<DropResult> {
+        DatabaseInner::validate_namespaced_col_name(col_name)?;
+        self.validators.invalidate(col_name);
 
-        Ok(())
+        self.drop_collection_internal(col_name, txn)
     }
 
-    fn drop_collection_internal(&self, col_name: &str, txn: &TransactionInner) -> Result<()> {
+    /// Remove the documents, the index entries and the metadata of the collection
+    /// in the transaction. The keys are removed by prefix instead of following
+    /// the documents, so the index entries without a document are removed too.
+    fn drop_collection_internal(&self, col_name: &str, txn: &TransactionInner) -> Result<DropResult> {
         let test_collection_spec = self.internal_get_collection_id_by_name(txn, col_name);
         let collection_spec = match test_collection_spec {
             Ok(collection_spec) => collection_spec,
-            Err(Error::CollectionNotFound(_)) => return Ok(()),
+            Err(Error::CollectionNotFound(_)) => return Ok(DropResult::default()),
             Err(err) => return Err(err),
         };
+        let storage_name = collection_spec.storage_name();
 
-        // Delete content begin
-        let subprogram = SubProgram::compile_delete_all(
-            &collection_spec,
-            col_name,
-            true,
-        )?;
+        let mut result = DropResult::default();
 
-        {
-            let mut txn = txn.clone();
-            txn.set_auto_commit(false);
-            let mut vm = VM::new(
-                txn,
-                subprogram,
-                self.metrics.clone(),
-            );
-            vm.execute()?;
-        } // Delete content end
+        let documents_prefix = crate::utils::bson::stacked_key([
+            &Bson::String(storage_name.to_string()),
+        ])?;
+        let (count, bytes) = DatabaseInner::delete_by_prefix(txn, documents_prefix)?;
+        result.deleted_count = count;
+        result.reclaimable_bytes += bytes;
+
+        let index_prefix = crate::utils::bson::stacked_key([
+            &Bson::String(INDEX_PREFIX.to_string()),
+            &Bson::String(storage_name.to_string()),
+        ])?;
+        let (count, bytes) = DatabaseInner::delete_by_prefix(txn, index_prefix)?;
+        result.deleted_index_entries = count;
+        result.reclaimable_bytes += bytes;
+
+        if collection_spec.is_capped() {
+            capped::remove_all(txn, storage_name)?;
+        }
+        if collection_spec.is_timeseries() {
+            timeseries::remove_all(txn, storage_name)?;
+        }
+
+        self.delete_collection_meta(col_name, txn)?;
+
+        Ok(result)
+    }
+
+    /// Remove the documents of a partition of the collection by a range deletion,
+    /// which is written at once without scanning the documents.
+    pub fn drop_partition(&self, col_name: &str, partition: &Bson, txn: &TransactionInner) -> Result<DropResult> {
+        DatabaseInner::validate_namespaced_col_name(col_name)?;
+        // the range deletion can't be rolled back with the transaction
+        if !txn.is_auto_commit() {
+            return Err(Error::ValidationError("a partition can't be dropped in a transaction".to_string()));
+        }
+        let spec = self.partitioned_collection_spec(col_name, txn)?;
+
+        let (start, end) = partition::partition_range(spec.storage_name(), partition)?;
+        let reclaimable_bytes = self.rocksdb.approximate_size(&start, &end)?;
+        let sync = self.wal_sync_policy()?.sync_on_commit();
+        self.rocksdb.write_batch(&[WalOperation::DeleteRange { start, end }], sync)?;
+
+        Ok(DropResult {
+            deleted_count: 0,
+            deleted_index_entries: 0,
+            reclaimable_bytes,
+        })
+    }
+
+    pub fn list_partitions(&self, col_name: &str, txn: &TransactionInner) -> Result<Vec<Bson>> {
+        DatabaseInner::validate_namespaced_col_name(col_name)?;
+        let spec = self.partitioned_collection_spec(col_name, txn)?;
+        partition::list_partitions(txn, spec.storage_name())
+    }
 
-        self.delete_collection_meta(col_name, txn)?;
+    fn partitioned_collection_spec(&self, col_name: &str, txn: &TransactionInner) -> Result<CollectionSpecification> {
+        let spec = self.internal_get_collection_id_by_name(txn, col_name)?;
+        if !spec.is_partitioned() {
+            return Err(Error::ValidationError(format!("the collection '{}' isn't partitioned", col_name)));
+        }
+        Ok(spec)
+    }
 
-        Ok(())
+    /// Delete all the keys starting with `prefix`,
+    /// return the number of keys and the bytes of the keys and values.
+    fn delete_by_prefix(txn: &TransactionInner, prefix: Vec<u8>) -> Result<(u64, u64)> {
+        let mut keys = Vec::new();
+        let mut bytes: u64 = 0;
+        let mut cursor = Cursor::new(prefix, txn.rocksdb_txn.new_iterator());
+        cursor.reset()?;
+        while cursor.has_next() {
+            let key = cursor.peek_key().expect("key must exist");
+            bytes += (key.len() + cursor.copy_data()?.len()) as u64;
+            keys.push(key);
+            cursor.next()?;
+        }
+
+        for key in keys.iter() {
+            txn.delete(key.as_ref())?;
+        }
+
+        Ok((keys.len() as u64, bytes))
     }
 
     fn delete_collection_meta(&self, col_name: &str, txn: &TransactionInner) -> Result<()> {
@@ -690,7 +1975,8 @@ impl DatabaseInner {
     }
 
     pub fn delete(&self, col_name: &str, query: Document, is_many: bool, txn: &TransactionInner) -> Result<usize> {
-        DatabaseInner::validate_col_name(col_name)?;
+        DatabaseInner::validate_namespaced_col_name(col_name)?;
+        self.check_mutable(col_name, txn)?;
         let mut txn = txn.clone();
         txn.set_auto_commit(false);
         let result = self.internal_delete_by_query(&txn, col_name, query, is_many)?;
@@ -706,7 +1992,6 @@ impl DatabaseInner {
 
         let subprogram = SubProgram::compile_delete(
             &col_spec,
-            col_name,
             Some(&query),
             true,
             is_many,
@@ -717,6 +2002,7 @@ impl DatabaseInner {
             subprogram,
             self.metrics.clone(),
         );
+        self.track_operation(&mut vm, "delete", col_name, profiler::filter_shape(&query));
         vm.execute()?;
 
         Ok(vm.r2 as usize)
@@ -733,7 +2019,6 @@ impl DatabaseInner {
         // Delete content begin
         let subprogram = SubProgram::compile_delete_all(
             &collection_spec,
-            col_name,
             true,
         )?;
 
@@ -743,6 +2028,7 @@ impl DatabaseInner {
                 subprogram,
                 self.metrics.clone(),
             );
+            self.track_operation(&mut vm, "delete", col_name, Document::new());
             vm.execute()?;
 
             vm.r2 as usize
@@ -752,13 +2038,33 @@ impl DatabaseInner {
     }
 
     pub fn delete_all(&self, col_name: &str, txn: &TransactionInner) -> Result<usize> {
-        DatabaseInner::validate_col_name(col_name)?;
+        DatabaseInner::validate_namespaced_col_name(col_name)?;
+        self.check_mutable(col_name, txn)?;
         let mut txn= txn.clone();
         txn.set_auto_commit(false);
         let result = self.internal_delete_all(&txn, col_name)?;
         Ok(result)
     }
 
+    /// The documents of a capped collection are only removed by the eviction,
+    /// to keep the insertion order and the size of the collection in sync.
+    /// The measurements of a time-series collection are packed in the buckets.
+    fn check_mutable(&self, col_name: &str, txn: &TransactionInner) -> Result<()> {
+        let col_spec = self.get_collection_meta_by_name_advanced_auto(col_name, false, txn)?;
+        match col_spec {
+            Some(col_spec) if col_spec.is_capped() => {
+                Err(Error::CappedCollectionImmutable(col_name.to_string()))
+            }
+            Some(col_spec) if col_spec.is_timeseries() => {
+                Err(Error::TimeseriesCollectionImmutable(col_name.to_string()))
+            }
+            Some(col_spec) if col_spec.is_view() => {
+                Err(Error::ViewReadOnly(col_name.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+
     // fn get_primary_keys_by_query(&mut self, session: &mut SessionInner, col_name: &str, query: Option<Document>, is_many: bool) -> DbResult<Vec<Bson>> {
     //     let col_spec = self.internal_get_collection_id_by_name(session, col_name)?;
     //     let mut handle = self.find_internal(session, &col_spec, query)?;
@@ -784,7 +2090,7 @@ impl DatabaseInner {
     // }
 
     pub fn count(&self, name: &str, txn: &TransactionInner) -> Result<u64> {
-        DatabaseInner::validate_col_name(name)?;
+        DatabaseInner::validate_namespaced_col_name(name)?;
 
         let col = self.get_collection_meta_by_name_advanced_auto(
             name,
@@ -798,7 +2104,12 @@ impl DatabaseInner {
         let col = col.unwrap();
         let mut count = 0;
 
-        let mut handle = self.find_internal::<Document>(&col, None, txn.clone())?;
+        let mut handle = if col.is_view() {
+            let subprogram = self.compile_view_aggregate(txn, col, Vec::new())?;
+            self.make_handle::<Document>(subprogram, txn.clone())?
+        } else {
+            self.find_internal::<Document>(&col, None, txn.clone())?
+        };
 
         while handle.advance()? {
             count += 1;
@@ -812,6 +2123,211 @@ impl DatabaseInner {
         Ok(collection_metas_to_names(docs))
     }
 
+    pub(crate) fn list_collection_specs(&self, txn: &TransactionInner) -> Result<Vec<CollectionSpecification>> {
+        let mut result = Vec::new();
+        let mut cursor = Cursor::new_with_str_prefix(TABLE_META_PREFIX, txn.rocksdb_txn.new_iterator())?;
+        cursor.reset()?;
+        while cursor.has_next() {
+            let data = cursor.copy_data()?;
+            result.push(bson::from_slice::<CollectionSpecification>(data.as_slice())?);
+            cursor.next()?;
+        }
+        Ok(result)
+    }
+
+    /// The info of the collection with the estimated count and size,
+    /// which are cheap to compute for a large collection.
+    pub(crate) fn collection_info(&self, txn: &TransactionInner, spec: &CollectionSpecification) -> Result<CollectionInfo> {
+        let mut info = CollectionInfo {
+            name: spec.name().to_string(),
+            create_at: spec.info.create_at,
+            options: spec.options(),
+            index_names: spec.indexes.keys().cloned().collect(),
+            approximate_count: 0,
+            approximate_size: 0,
+        };
+        if spec.is_view() {
+            return Ok(info);
+        }
+
+        let documents_prefix = crate::utils::bson::stacked_key([
+            &Bson::String(spec.storage_name().to_string()),
+        ])?;
+        let documents_end = prefix_end(&documents_prefix);
+        let documents_size = self.rocksdb.approximate_size(&documents_prefix, &documents_end)?;
+        let index_prefix = crate::utils::bson::stacked_key([
+            &Bson::String(INDEX_PREFIX.to_string()),
+            &Bson::String(spec.storage_name().to_string()),
+        ])?;
+        let index_size = self.rocksdb.approximate_size(&index_prefix, &prefix_end(&index_prefix))?;
+        info.approximate_size = documents_size + index_size;
+
+        // Count the first documents, and extrapolate by the size of them
+        // if the collection is larger.
+        let mut cursor = Cursor::new(documents_prefix.clone(), txn.rocksdb_txn.new_iterator());
+        cursor.reset()?;
+        let mut count: u64 = 0;
+        while cursor.has_next() {
+            if count == COUNT_SAMPLE_SIZE {
+                let sample_end = cursor.peek_key().expect("key must exist");
+                let sample_size = self.rocksdb.approximate_size(&documents_prefix, sample_end.as_ref())?;
+                if sample_size > 0 && documents_size > sample_size {
+                    info.approximate_count = count * documents_size / sample_size;
+                    return Ok(info);
+                }
+            }
+            count += 1;
+            cursor.next()?;
+        }
+        info.approximate_count = count;
+
+        Ok(info)
+    }
+
+    /// Refresh the statistics of the indexes of the collection for the planner.
+    pub fn analyze_collection(&self, col_name: &str, txn: &TransactionInner) -> Result<HashMap<String, IndexStats>> {
+        DatabaseInner::validate_namespaced_col_name(col_name)?;
+        let mut result = HashMap::new();
+        let mut spec = match self.internal_get_collection_id_by_name(txn, col_name) {
+            Ok(spec) => spec,
+            Err(Error::CollectionNotFound(_)) => return Ok(result),
+            Err(err) => return Err(err),
+        };
+
+        let storage_name = spec.storage_name().to_string();
+        for (index_name, index_info) in spec.indexes.iter_mut() {
+            // the vectors are moved to the lists of the retrained centroids
+            if index_info.kind == IndexKind::Vector {
+                IndexBuilder::new(txn, &storage_name, index_name, index_info).execute(IndexHelperOperation::Delete)?;
+                index_info.centroids = DatabaseInner::train_vector_index(txn, &storage_name, index_info)?;
+                self.build_index(txn, &storage_name, index_name, index_info)?;
+            }
+            let stats = analyze_index(txn, &storage_name, index_name)?;
+            index_info.stats = Some(stats.clone());
+            result.insert(index_name.clone(), stats);
+        }
+
+        if !result.is_empty() {
+            DatabaseInner::update_collection_spec(col_name, &spec, txn)?;
+        }
+        Ok(result)
+    }
+
+    pub(super) fn is_replica(&self) -> Result<bool> {
+        self.rocksdb.is_replica()
+    }
+
+    pub(super) fn analyze_all(&self) -> Result<()> {
+        let txn = self.start_transaction()?;
+        for spec in self.list_collection_specs(&txn)? {
+            if !spec.indexes.is_empty() {
+                self.analyze_collection(spec.name(), &txn)?;
+            }
+        }
+        txn.commit()
+    }
+
+    pub fn collection_stats(&self, col_name: &str, txn: &TransactionInner) -> Result<CollectionStats> {
+        DatabaseInner::validate_namespaced_col_name(col_name)?;
+        let mut stats = CollectionStats::default();
+        let spec = match self.get_collection_meta_by_name_advanced(txn, col_name, false, &self.node_id)? {
+            Some(spec) if !spec.is_view() => spec,
+            _ => return Ok(stats),
+        };
+
+        let perf_context = RocksDBPerfContext::start();
+
+        let documents_prefix = crate::utils::bson::stacked_key([
+            &Bson::String(spec.storage_name().to_string()),
+        ])?;
+        let (count, _, size) = DatabaseInner::measure_prefix(txn, documents_prefix)?;
+        stats.count = count;
+        stats.size = size;
+        stats.avg_obj_size = size.checked_div(count).unwrap_or(0);
+
+        stats.index_count = spec.indexes.len() as u64;
+        for index_name in spec.indexes.keys() {
+            let index_prefix = crate::utils::bson::stacked_key([
+                &Bson::String(INDEX_PREFIX.to_string()),
+                &Bson::String(spec.storage_name().to_string()),
+                &Bson::String(index_name.clone()),
+            ])?;
+            let (_, key_size, value_size) = DatabaseInner::measure_prefix(txn, index_prefix)?;
+            stats.index_sizes.insert(index_name.clone(), key_size + value_size);
+            stats.total_index_size += key_size + value_size;
+        }
+
+        stats.tombstones = perf_context.internal_delete_skipped_count();
+
+        Ok(stats)
+    }
+
+    /// Return the number of the keys starting with `prefix`,
+    /// and the total bytes of the keys and of the values.
+    fn measure_prefix(txn: &TransactionInner, prefix: Vec<u8>) -> Result<(u64, u64, u64)> {
+        let mut count: u64 = 0;
+        let mut key_size: u64 = 0;
+        let mut value_size: u64 = 0;
+        let mut cursor = Cursor::new(prefix, txn.rocksdb_txn.new_iterator());
+        cursor.reset()?;
+        while cursor.has_next() {
+            count += 1;
+            key_size += cursor.peek_key().expect("key must exist").len() as u64;
+            value_size += cursor.copy_data()?.len() as u64;
+            cursor.next()?;
+        }
+        Ok((count, key_size, value_size))
+    }
+
+    /// The names of the named databases, which have at least one collection.
+    pub(crate) fn list_database_names_with_session(&self, txn: &TransactionInner) -> Result<Vec<String>> {
+        let mut result: Vec<String> = self.list_collection_names_with_session(txn)?
+            .iter()
+            .filter_map(|name| name.split_once(NAMESPACE_SEPARATOR))
+            .map(|(db_name, _)| db_name.to_string())
+            .collect();
+        result.sort();
+        result.dedup();
+        Ok(result)
+    }
+
+    /// Drop all the collections of the named database,
+    /// or the collections out of the named databases if `db_name` is `None`.
+    pub fn drop_database(&self, db_name: Option<&str>, txn: &TransactionInner) -> Result<DropResult> {
+        if let Some(db_name) = db_name {
+            DatabaseInner::validate_db_name(db_name)?;
+        }
+        let mut txn = txn.clone();
+        txn.set_auto_commit(false);
+
+        let mut result = DropResult::default();
+        for name in self.list_collection_names_with_session(&txn)? {
+            if !DatabaseInner::in_database(&name, db_name) {
+                continue;
+            }
+            let dropped = self.drop_collection_internal(&name, &txn)?;
+            result.deleted_count += dropped.deleted_count;
+            result.deleted_index_entries += dropped.deleted_index_entries;
+            result.reclaimable_bytes += dropped.reclaimable_bytes;
+        }
+        migration::delete_version(&txn, db_name)?;
+        sequence::delete_sequences(&txn, db_name)?;
+        crate::kv::delete_pairs(&txn, db_name)?;
+
+        Ok(result)
+    }
+
+    /// Whether the collection `name` belongs to the named database `db_name`,
+    /// or to the default database if `db_name` is `None`.
+    fn in_database(name: &str, db_name: Option<&str>) -> bool {
+        match db_name {
+            Some(db_name) => name
+                .split_once(NAMESPACE_SEPARATOR)
+                .map(|(prefix, _)| prefix) == Some(db_name),
+            None => !name.contains(NAMESPACE_SEPARATOR),
+        }
+    }
+
     pub(crate) fn query_all_meta(&self, txn: &TransactionInner) -> Result<Vec<Document>> {
         let mut handle: ClientCursor<Document> = {
             let subprogram = SubProgram::compile_query_all_by_name(
@@ -839,48 +2355,369 @@ impl DatabaseInner {
         filter: impl Into<Option<Document>>,
         txn: TransactionInner,
     ) -> Result<ClientCursor<T>> {
-        DatabaseInner::validate_col_name(col_name)?;
-        let filter_query = filter.into();
-        let meta_opt = self.get_collection_meta_by_name_advanced_auto(
-            col_name,
-            false,
-            &txn,
-        )?;
-        let subprogram = match meta_opt {
-            Some(col_spec) => {
-                let subprogram = match filter_query {
-                    Some(query) => SubProgram::compile_query(
-                        &col_spec,
-                        &query,
-                        true
-                    ),
-                    None => SubProgram::compile_query_all(&col_spec, true),
-                }?;
+        crate::trace_span!("polodb.find", collection = col_name);
+        self.metrics.observe_cursor("find", || {
+            DatabaseInner::validate_namespaced_col_name(col_name)?;
+            let filter_query = filter.into();
+            let filter_shape = filter_query
+                .as_ref()
+                .map(profiler::filter_shape)
+                .unwrap_or_default();
+            let meta_opt = self.get_collection_meta_by_name_advanced_auto(
+                col_name,
+                false,
+                &txn,
+            )?;
+            let subprogram = match meta_opt {
+                Some(col_spec) if col_spec.is_view() => {
+                    let pipeline = filter_query
+                        .filter(|query| !query.is_empty())
+                        .map(|query| doc! { "$match": query })
+                        .into_iter()
+                        .collect();
+                    self.compile_view_aggregate(&txn, col_spec, pipeline)?
+                }
+                Some(col_spec) => {
+                    let subprogram = match filter_query {
+                        Some(query) => SubProgram::compile_query(
+                            &col_spec,
+                            &query,
+                            true
+                        ),
+                        None => SubProgram::compile_query_all(&col_spec, true),
+                    }?;
+
+                    subprogram
+                }
+                None => SubProgram::compile_empty_query(),
+            };
+
+            let mut vm = VM::new(
+                txn,
+                subprogram,
+                self.metrics.clone(),
+            );
+            self.track_operation(&mut vm, "find", col_name, filter_shape);
+
+            let mut handle = ClientCursor::new(vm);
+            handle.set_transforms(self.collection_transforms(col_name));
+
+            Ok(handle)
+        })
+    }
+
+    /// Scan the collection by up to `max_parallelism` threads,
+    /// each reads a range of the primary keys of the same snapshot.
+    ///
+    /// The queries which don't scan the whole collection, such as the queries of an index,
+    /// and the special collections are executed by a single thread.
+    pub fn find_parallel_with_owned_session<T: DeserializeOwned + Send + Sync>(
+        &self,
+        col_name: &str,
+        filter: Document,
+        max_parallelism: usize,
+    ) -> Result<ClientCursor<T>> {
+        let snapshot = self.rocksdb.create_read_snapshot()?;
+        let txn = TransactionInner::new(self.rocksdb.begin_transaction_at(&snapshot)?);
+        if max_parallelism <= 1 {
+            return self.find_with_owned_session(col_name, filter, txn);
+        }
+
+        crate::trace_span!("polodb.find", collection = col_name);
+        let col_spec = match self.get_collection_meta_by_name_advanced_auto(col_name, false, &txn)? {
+            Some(col_spec) => col_spec,
+            None => return self.find_with_owned_session(col_name, filter, txn),
+        };
+        if col_spec.is_view() || col_spec.is_capped() || col_spec.is_timeseries() || col_spec.is_partitioned() {
+            return self.find_with_owned_session(col_name, filter, txn);
+        }
+
+        let program = SubProgram::compile_query(&col_spec, &filter, true)?;
+        if program.plan.as_deref() != Some("COLLSCAN") {
+            return self.find_with_owned_session(col_name, filter, txn);
+        }
 
-                subprogram
+        let ranges = DatabaseInner::split_key_space(col_spec.storage_name(), max_parallelism, &txn)?;
+        if ranges.len() <= 1 {
+            return self.find_with_owned_session(col_name, filter, txn);
+        }
+        crate::trace_event!(partitions = ranges.len(), "plan: parallel collection scan");
+
+        self.metrics.observe_cursor("find", || {
+            let partitions = ranges
+                .into_iter()
+                .map(|range| Ok(ScanPartition {
+                    txn: TransactionInner::new(self.rocksdb.begin_transaction_at(&snapshot)?),
+                    range,
+                }))
+                .collect::<Result<Vec<ScanPartition>>>()?;
+            let scan = ParallelScan::new(col_spec, PartitionProgram::Query(filter), self.metrics.clone(), &program, partitions);
+
+            let mut handle = ClientCursor::new_parallel(scan);
+            handle.set_transforms(self.collection_transforms(col_name));
+
+            Ok(handle)
+        })
+    }
+
+    /// Split the primary keys of the collection into up to `parts` ranges.
+    ///
+    /// The split keys are interpolated between the first and the last key,
+    /// and then moved to the stored keys, so the ranges are even
+    /// when the keys are evenly distributed, such as the increasing numbers and object ids.
+    fn split_key_space(storage_name: &str, parts: usize, txn: &TransactionInner) -> Result<Vec<Document>> {
+        let mut prefix = Vec::<u8>::new();
+        crate::utils::bson::stacked_key_bytes(&mut prefix, &Bson::String(storage_name.to_string()))?;
+        // the string of the prefix ends with 0
+        let mut prefix_end = prefix.clone();
+        *prefix_end.last_mut().unwrap() = 1;
+
+        let iter = txn.rocksdb_txn.new_iterator();
+        iter.seek(&prefix);
+        iter.error()?;
+        if !iter.valid() {
+            return Ok(vec![]);
+        }
+        let first = iter.copy_key()?;
+        if !first.starts_with(&prefix) {
+            return Ok(vec![]);
+        }
+        iter.seek_for_prev(&prefix_end);
+        iter.error()?;
+        let mut last = iter.copy_key()?;
+        if !last.starts_with(&prefix) {
+            iter.prev();
+            iter.error()?;
+            last = iter.copy_key()?;
+        }
+
+        let first_suffix = &first[prefix.len()..];
+        let last_suffix = &last[prefix.len()..];
+        let common = first_suffix
+            .iter()
+            .zip(last_suffix)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let read_u64 = |suffix: &[u8]| {
+            let mut bytes = [0u8; 8];
+            for (dst, src) in bytes.iter_mut().zip(suffix.iter().skip(common)) {
+                *dst = *src;
             }
-            None => SubProgram::compile_empty_query(),
+            u64::from_be_bytes(bytes)
         };
+        let low = read_u64(first_suffix);
+        let high = read_u64(last_suffix);
+        let step = (high - low) / parts as u64;
+        if step == 0 {
+            return Ok(vec![]);
+        }
 
-        let vm = VM::new(
-            txn,
-            subprogram,
-            self.metrics.clone(),
-        );
+        let mut split_keys: Vec<Vec<u8>> = vec![];
+        for i in 1..parts as u64 {
+            let mut key = prefix.clone();
+            key.extend_from_slice(&first_suffix[..common]);
+            key.extend_from_slice(&(low + step * i).to_be_bytes());
+            iter.seek(&key);
+            iter.error()?;
+            if !iter.valid() {
+                break;
+            }
+            let key = iter.copy_key()?;
+            if key >= last {
+                break;
+            }
+            let previous = split_keys.last().unwrap_or(&first);
+            if key > *previous {
+                split_keys.push(key);
+            }
+        }
 
-        let handle = ClientCursor::new(vm);
+        let split_ids = split_keys
+            .iter()
+            .map(|key| {
+                let id = crate::utils::bson::split_stacked_keys(&key[prefix.len()..])?;
+                Ok(id.into_iter().next().unwrap_or(Bson::Null))
+            })
+            .collect::<Result<Vec<Bson>>>()?;
+
+        let mut ranges = Vec::with_capacity(split_ids.len() + 1);
+        let mut lower: Option<Bson> = None;
+        for id in split_ids {
+            let mut range = Document::new();
+            if let Some(lower) = lower.take() {
+                range.insert("$gte", lower);
+            }
+            range.insert("$lt", id.clone());
+            ranges.push(range);
+            lower = Some(id);
+        }
+        if let Some(lower) = lower {
+            ranges.push(doc! { "$gte": lower });
+        }
+        Ok(ranges)
+    }
 
-        Ok(handle)
+    pub fn find_range_with_owned_session<T: DeserializeOwned + Send + Sync>(
+        &self,
+        col_name: &str,
+        lower_bound: Bound<&Bson>,
+        upper_bound: Bound<&Bson>,
+        txn: TransactionInner,
+    ) -> Result<ClientCursor<T>> {
+        crate::trace_span!("polodb.find_range", collection = col_name);
+        self.metrics.observe_cursor("find", || {
+            DatabaseInner::validate_namespaced_col_name(col_name)?;
+            let meta_opt = self.get_collection_meta_by_name_advanced_auto(
+                col_name,
+                false,
+                &txn,
+            )?;
+            let subprogram = match meta_opt {
+                Some(col_spec) if col_spec.is_view() => {
+                    return Err(Error::ValidationError("the views don't support range scans".to_string()));
+                }
+                Some(col_spec) => SubProgram::compile_query_range(&col_spec, lower_bound, upper_bound, true)?,
+                None => SubProgram::compile_empty_query(),
+            };
+
+            let mut vm = VM::new(
+                txn,
+                subprogram,
+                self.metrics.clone(),
+            );
+            self.track_operation(&mut vm, "find", col_name, Document::new());
+
+            let mut handle = ClientCursor::new(vm);
+            handle.set_transforms(self.collection_transforms(col_name));
+
+            Ok(handle)
+        })
     }
 
-    pub(crate) fn count_documents(&self, col_name: &str, txn: &TransactionInner) -> Result<u64> {
-        DatabaseInner::validate_col_name(col_name)?;
-        let test_result = self.count(col_name, txn);
-        match test_result {
-            Ok(result) => Ok(result),
-            Err(Error::CollectionNotFound(_)) => Ok(0),
-            Err(err) => Err(err),
+    /// Whether a document matches `filter`, the scan stops at the first one.
+    /// The filters of a single `_id` only seek the key without reading the document.
+    pub fn exists(&self, col_name: &str, filter: Document, txn: &TransactionInner) -> Result<bool> {
+        crate::trace_span!("polodb.exists", collection = col_name);
+        DatabaseInner::validate_namespaced_col_name(col_name)?;
+        let pkey = match filter.get("_id") {
+            Some(id) if filter.len() == 1 => match id.element_type() {
+                ElementType::EmbeddedDocument | ElementType::Array | ElementType::RegularExpression => None,
+                _ => Some(id),
+            },
+            _ => None,
+        };
+        if let Some(pkey) = pkey {
+            let col_spec = self.get_collection_meta_by_name_advanced_auto(col_name, false, txn)?;
+            match col_spec {
+                None => return Ok(false),
+                Some(col_spec) if !col_spec.is_view() && !col_spec.is_timeseries() && !col_spec.is_partitioned() => {
+                    return self.metrics.observe("find", || {
+                        let ordering = col_spec.key_ordering.as_ref()
+                            .map(|name| key_ordering::resolve(txn, name))
+                            .transpose()?;
+                        let pkey = key_ordering::stored_key(ordering.as_deref(), pkey)?;
+                        let mut cursor = Cursor::new_with_str_prefix(
+                            col_spec.storage_name(),
+                            txn.rocksdb_txn.new_iterator(),
+                        )?;
+                        cursor.reset_by_pkey(&pkey)
+                    });
+                }
+                _ => (),
+            }
+        }
+
+        let mut cursor = self.find_with_owned_session::<Document>(col_name, filter, txn.clone())?;
+        cursor.advance()
+    }
+
+    /// Read the documents of `ids` with a batched lookup of the primary keys,
+    /// the result has the order of `ids` and `None` for the missing documents.
+    pub fn find_many_by_ids(&self, col_name: &str, ids: &[Bson], txn: &TransactionInner) -> Result<Vec<Option<Document>>> {
+        crate::trace_span!("polodb.find_many_by_ids", collection = col_name, count = ids.len());
+        self.metrics.observe("find", || {
+            DatabaseInner::validate_namespaced_col_name(col_name)?;
+            let col_spec = match self.get_collection_meta_by_name_advanced_auto(col_name, false, txn)? {
+                Some(col_spec) => col_spec,
+                None => return Ok(vec![None; ids.len()]),
+            };
+            if col_spec.is_view() || col_spec.is_timeseries() || col_spec.is_partitioned() {
+                return Err(Error::ValidationError(format!(
+                    "the collection '{}' doesn't support the lookups by _id",
+                    col_name,
+                )));
+            }
+
+            let col_key = Bson::String(col_spec.storage_name().to_string());
+            let ordering = col_spec.key_ordering.as_ref()
+                .map(|name| key_ordering::resolve(txn, name))
+                .transpose()?;
+            let mut keys = Vec::with_capacity(ids.len());
+            for id in ids {
+                let id = key_ordering::stored_key(ordering.as_deref(), id)?;
+                keys.push(crate::utils::bson::stacked_key([&col_key, &id])?);
+            }
+            // the sorted keys are read in one pass of the levels
+            let mut sorted_keys = keys.clone();
+            sorted_keys.sort();
+            sorted_keys.dedup();
+            let values = txn.rocksdb_txn.multi_get(&sorted_keys)?;
+
+            let transforms = self.collection_transforms(col_name);
+            let mut result = Vec::with_capacity(ids.len());
+            for key in &keys {
+                let index = sorted_keys.binary_search(key).expect("key must be sorted");
+                let doc = match &values[index] {
+                    Some(bytes) => bson::from_slice::<Document>(bytes)?,
+                    None => {
+                        result.push(None);
+                        continue;
+                    }
+                };
+                let doc = match &transforms {
+                    Some(transforms) => transforms.apply(&doc)?.unwrap_or(doc),
+                    None => doc,
+                };
+                result.push(Some(doc));
+            }
+            Ok(result)
+        })
+    }
+
+    /// The qualified name of the collection referenced by `db_ref` from the collection `col_name`.
+    pub(crate) fn ref_col_name(&self, col_name: &str, db_ref: &DbRef) -> String {
+        let namespace = match &db_ref.database {
+            Some(database) => Some(database.as_str()),
+            None => col_name.split_once(NAMESPACE_SEPARATOR).map(|(namespace, _)| namespace),
+        };
+        qualify_col_name(namespace, &db_ref.collection)
+    }
+
+    /// Finds the document referenced by `db_ref` from the collection `col_name`.
+    pub(crate) fn resolve_ref(&self, col_name: &str, db_ref: &DbRef, txn: &TransactionInner) -> Result<Option<Document>> {
+        let target = self.ref_col_name(col_name, db_ref);
+        let mut cursor = self.find_with_owned_session::<Document>(
+            &target,
+            doc! { "_id": db_ref.id.clone() },
+            txn.clone(),
+        )?;
+        if !cursor.advance()? {
+            return Ok(None);
         }
+        Ok(Some(cursor.deserialize_current()?))
+    }
+
+    pub(crate) fn count_documents(&self, col_name: &str, txn: &TransactionInner) -> Result<u64> {
+        crate::trace_span!("polodb.count_documents", collection = col_name);
+        self.metrics.observe("count_documents", || {
+            DatabaseInner::validate_namespaced_col_name(col_name)?;
+            let test_result = self.count(col_name, txn);
+            match test_result {
+                Ok(result) => Ok(result),
+                Err(Error::CollectionNotFound(_)) => Ok(0),
+                Err(err) => Err(err),
+            }
+        })
     }
 
     pub(crate) fn delete_one(
@@ -889,43 +2726,49 @@ impl DatabaseInner {
         query: Document,
         txn: &TransactionInner,
     ) -> Result<DeleteResult> {
-        DatabaseInner::validate_col_name(col_name)?;
-
-        let test_count = self.delete(
-            col_name,
-            query,
-            false,
-            txn,
-        );
+        crate::trace_span!("polodb.delete_one", collection = col_name);
+        self.metrics.observe("delete_one", || {
+            DatabaseInner::validate_namespaced_col_name(col_name)?;
+
+            let test_count = self.delete(
+                col_name,
+                query,
+                false,
+                txn,
+            );
 
-        match test_count {
-            Ok(count) => Ok(DeleteResult {
-                deleted_count: count as u64,
-            }),
-            Err(Error::CollectionNotFound(_)) => Ok(DeleteResult {
-                deleted_count: 0,
-            }),
-            Err(err) => Err(err),
-        }
+            match test_count {
+                Ok(count) => Ok(DeleteResult {
+                    deleted_count: count as u64,
+                }),
+                Err(Error::CollectionNotFound(_)) => Ok(DeleteResult {
+                    deleted_count: 0,
+                }),
+                Err(err) => Err(err),
+            }
+        })
     }
 
     pub(crate) fn delete_many(&self, col_name: &str, query: Document, txn: &TransactionInner) -> Result<DeleteResult> {
-        DatabaseInner::validate_col_name(col_name)?;
-
-        let test_deleted_count = if query.len() == 0 {
-            self.delete_all(col_name, txn)
-        } else {
-            self.delete(col_name, query, true, txn)
-        };
-        match test_deleted_count {
-            Ok(deleted_count) => Ok(DeleteResult {
-                deleted_count: deleted_count as u64,
-            }),
-            Err(Error::CollectionNotFound(_)) => Ok(DeleteResult {
-                deleted_count: 0
-            }),
-            Err(err) => Err(err),
-        }
+        crate::trace_span!("polodb.delete_many", collection = col_name);
+        self.metrics.observe("delete_many", || {
+            DatabaseInner::validate_namespaced_col_name(col_name)?;
+
+            let test_deleted_count = if query.len() == 0 {
+                self.delete_all(col_name, txn)
+            } else {
+                self.delete(col_name, query, true, txn)
+            };
+            match test_deleted_count {
+                Ok(deleted_count) => Ok(DeleteResult {
+                    deleted_count: deleted_count as u64,
+                }),
+                Err(Error::CollectionNotFound(_)) => Ok(DeleteResult {
+                    deleted_count: 0
+                }),
+                Err(err) => Err(err),
+            }
+        })
     }
 
     pub(crate) fn aggregate_with_owned_session<T: DeserializeOwned + Send + Sync>(
@@ -933,35 +2776,209 @@ impl DatabaseInner {
         col_name: &str,
         pipeline: impl IntoIterator<Item = Document>,
         txn: TransactionInner,
+        spill_options: &SpillOptions,
     ) -> Result<ClientCursor<T>> {
-        DatabaseInner::validate_col_name(col_name)?;
-        let meta_opt = self.get_collection_meta_by_name_advanced_auto(col_name, false, &txn)?;
-        let subprogram = match meta_opt {
-            Some(col_spec) => {
-                let subprogram = SubProgram::compile_aggregate(
-                    &col_spec,
-                    pipeline,
-                    true
-                )?;
+        crate::trace_span!("polodb.aggregate", collection = col_name);
+        self.metrics.observe_cursor("aggregate", || {
+            DatabaseInner::validate_namespaced_col_name(col_name)?;
+            let pipeline: Vec<Document> = pipeline.into_iter().collect();
+            let filter_shape = DatabaseInner::pipeline_shape(&pipeline);
+            let meta_opt = self.get_collection_meta_by_name_advanced_auto(col_name, false, &txn)?;
+            let mut subprogram = match meta_opt {
+                Some(col_spec) if col_spec.is_view() => {
+                    self.compile_view_aggregate(&txn, col_spec, pipeline)?
+                }
+                Some(col_spec) => {
+                    let subprogram = SubProgram::compile_aggregate(
+                        &col_spec,
+                        pipeline,
+                        true
+                    )?;
+
+                    subprogram
+                }
+                None => SubProgram::compile_empty_query(),
+            };
+            subprogram.set_spill_options(spill_options);
+
+            let mut vm = VM::new(
+                txn,
+                subprogram,
+                self.metrics.clone(),
+            );
+            self.track_operation(&mut vm, "aggregate", col_name, filter_shape);
+
+            let handle = ClientCursor::new(vm);
+
+            Ok(handle)
+        })
+    }
+
+    /// Aggregate the documents of a snapshot, the leading `$match` of the pipeline
+    /// is done by up to `max_parallelism` threads like [`DatabaseInner::find_parallel_with_owned_session`],
+    /// and the other stages are done by the cursor on the matched documents in the order of the primary keys.
+    ///
+    /// The pipeline is aggregated by one thread if the collection is not scanned.
+    pub(crate) fn aggregate_parallel_with_owned_session<T: DeserializeOwned + Send + Sync>(
+        &self,
+        col_name: &str,
+        pipeline: Vec<Document>,
+        max_parallelism: usize,
+        spill_options: &SpillOptions,
+    ) -> Result<ClientCursor<T>> {
+        let snapshot = self.rocksdb.create_read_snapshot()?;
+        let txn = TransactionInner::new(self.rocksdb.begin_transaction_at(&snapshot)?);
+        if max_parallelism <= 1 {
+            return self.aggregate_with_owned_session(col_name, pipeline, txn, spill_options);
+        }
+
+        crate::trace_span!("polodb.aggregate", collection = col_name);
+        DatabaseInner::validate_namespaced_col_name(col_name)?;
+        let col_spec = match self.get_collection_meta_by_name_advanced_auto(col_name, false, &txn)? {
+            Some(col_spec) => col_spec,
+            None => return self.aggregate_with_owned_session(col_name, pipeline, txn, spill_options),
+        };
+        if col_spec.is_view() || col_spec.is_capped() || col_spec.is_timeseries() || col_spec.is_partitioned() {
+            return self.aggregate_with_owned_session(col_name, pipeline, txn, spill_options);
+        }
+
+        // the whole pipeline is validated, and its plan tells if the indexes are used
+        let program = SubProgram::compile_aggregate(&col_spec, pipeline.clone(), true)?;
+        if program.plan.as_deref() != Some("COLLSCAN") {
+            return self.aggregate_with_owned_session(col_name, pipeline, txn, spill_options);
+        }
+
+        let ranges = DatabaseInner::split_key_space(col_spec.storage_name(), max_parallelism, &txn)?;
+        if ranges.len() <= 1 {
+            return self.aggregate_with_owned_session(col_name, pipeline, txn, spill_options);
+        }
+        crate::trace_event!(partitions = ranges.len(), "plan: parallel collection scan");
+
+        self.metrics.observe_cursor("aggregate", || {
+            let filter_shape = DatabaseInner::pipeline_shape(&pipeline);
+            let (partition_stages, stages) = DatabaseInner::split_parallel_pipeline(pipeline);
+
+            let partitions = ranges
+                .into_iter()
+                .map(|range| Ok(ScanPartition {
+                    txn: TransactionInner::new(self.rocksdb.begin_transaction_at(&snapshot)?),
+                    range,
+                }))
+                .collect::<Result<Vec<ScanPartition>>>()?;
+            let partition_program = SubProgram::compile_aggregate(&col_spec, partition_stages.clone(), true)?;
+            let mut scan = ParallelScan::new(
+                col_spec,
+                PartitionProgram::Aggregate(partition_stages),
+                self.metrics.clone(),
+                &partition_program,
+                partitions,
+            );
+            scan.set_spill_options(spill_options);
+
+            let mut subprogram = SubProgram::compile_aggregate_of_input(stages, true)?;
+            subprogram.set_spill_options(spill_options);
+
+            let mut vm = VM::new(txn, subprogram, self.metrics.clone());
+            vm.set_input(scan);
+            self.track_operation(&mut vm, "aggregate", col_name, filter_shape);
 
-                subprogram
+            Ok(ClientCursor::new(vm))
+        })
+    }
+
+    /// Split the pipeline of a parallel aggregation into the stages run by each partition
+    /// and the stages run on the documents returned by the partitions.
+    ///
+    /// The partitions run the leading stages transforming each document alone.
+    /// A `$group` after them is run by each partition too, if all its accumulators are `$sum`s,
+    /// and the groups of the partitions are merged by summing the sums.
+    fn split_parallel_pipeline(mut pipeline: Vec<Document>) -> (Vec<Document>, Vec<Document>) {
+        let is_stage = |stage: &Document, name: &str| stage.len() == 1 && stage.contains_key(name);
+        let count = pipeline
+            .iter()
+            .take_while(|stage| ["$match", "$addFields", "$unset"].iter().any(|name| is_stage(stage, name)))
+            .count();
+        let mut stages = pipeline.split_off(count);
+
+        let merge_group = match stages.first() {
+            Some(stage) if is_stage(stage, "$group") => {
+                stage.get_document("$group").ok().and_then(DatabaseInner::merge_group_stage)
             }
-            None => SubProgram::compile_empty_query(),
+            _ => None,
         };
+        if let Some(merge_group) = merge_group {
+            pipeline.push(stages.remove(0));
+            stages.insert(0, doc! { "$group": merge_group });
+        }
+        (pipeline, stages)
+    }
 
-        let vm = VM::new(
-            txn,
-            subprogram,
-            self.metrics.clone(),
-        );
+    /// The `$group` merging the groups of the partitions, `None` if the accumulators can't be merged.
+    fn merge_group_stage(group: &Document) -> Option<Document> {
+        let mut merge_group = doc! { "_id": "$_id" };
+        for (key, value) in group.iter() {
+            if key == "_id" {
+                continue;
+            }
+            let accumulator = match value {
+                Bson::Document(accumulator) => accumulator,
+                _ => return None,
+            };
+            if key.contains('.') || key.starts_with('$') || accumulator.len() != 1 || !accumulator.contains_key("$sum") {
+                return None;
+            }
+            merge_group.insert(key.clone(), doc! { "$sum": format!("${}", key) });
+        }
+        Some(merge_group)
+    }
 
-        let handle = ClientCursor::new(vm);
+    /// The shape of the pipeline recorded by the profiler.
+    fn pipeline_shape(pipeline: &[Document]) -> Document {
+        doc! {
+            "pipeline": pipeline
+                .iter()
+                .map(|stage| Bson::Document(profiler::filter_shape(stage)))
+                .collect::<Vec<Bson>>(),
+        }
+    }
 
-        Ok(handle)
+    /// Compile the pipeline on a view into the pipeline on the source collection,
+    /// following the views on the views.
+    fn compile_view_aggregate(
+        &self,
+        txn: &TransactionInner,
+        view_spec: CollectionSpecification,
+        pipeline: Vec<Document>,
+    ) -> Result<SubProgram> {
+        let mut current = view_spec;
+        let mut stages = pipeline;
+
+        for _ in 0..view::MAX_VIEW_DEPTH {
+            let view_info = current.view.take().expect("not a view");
+            stages = view_info.pipeline.into_iter().chain(stages).collect();
+
+            match self.get_collection_meta_by_name_advanced_auto(&view_info.view_on, false, txn)? {
+                Some(source) if source.is_view() => {
+                    current = source;
+                }
+                Some(source) => return SubProgram::compile_aggregate(&source, stages, true),
+                None => return Ok(SubProgram::compile_empty_query()),
+            }
+        }
+
+        Err(Error::ValidationError(format!("the views on '{}' are nested too deep", current._id)))
     }
 
 }
 
+/// The smallest key after all the keys starting with `prefix`.
+/// The stacked keys end with a 0 terminator, so the last byte can be increased.
+fn prefix_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    *end.last_mut().expect("prefix must not be empty") += 1;
+    end
+}
+
 fn collection_metas_to_names(doc_meta: Vec<Document>) -> Vec<String> {
     doc_meta
         .iter()
@@ -985,6 +3002,16 @@ mod tests {
         assert!(DatabaseInner::validate_col_name("test.ok").is_err());
     }
 
+    #[test]
+    fn test_validate_namespaced_col_name() {
+        assert!(DatabaseInner::validate_namespaced_col_name("test").is_ok());
+        assert!(DatabaseInner::validate_namespaced_col_name("tenant.test").is_ok());
+        assert!(DatabaseInner::validate_namespaced_col_name("tenant.").is_err());
+        assert!(DatabaseInner::validate_namespaced_col_name(".test").is_err());
+        assert!(DatabaseInner::validate_namespaced_col_name("tenant.test.ok").is_err());
+        assert!(DatabaseInner::validate_namespaced_col_name("tenant.$test").is_err());
+    }
+
     #[test]
     fn test_validate_index_name() {
         assert!(DatabaseInner::validate_index_name("test").is_ok());
@@ -0,0 +1,459 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The version of the storage format of a database.
+//!
+//! The version is saved in the database when it's created. The databases written before
+//! the format was versioned have no version, they are of the version 1. When a database
+//! of an older version is opened, the upgrades to the newer versions are applied in order,
+//! each one is committed with its version, so an interrupted upgrade resumes from the
+//! last version committed.
+
+use bson::{doc, Bson, DateTime, Document};
+use crate::coll::capped;
+use crate::cursor::Cursor;
+use crate::db::db_inner::DatabaseInner;
+use crate::index::INDEX_PREFIX;
+use crate::results::UpgradeResult;
+use crate::transaction::TransactionInner;
+use crate::utils::bson::{split_legacy_stacked_keys, stacked_key};
+use crate::{Error, Result};
+
+const FORMAT_VERSION_KEY: &str = "$FORMAT_VERSION";
+
+// the prefix of the keys re-encoded by the version 2 before they are moved back
+const STAGING_PREFIX: &str = "$FORMAT_UPGRADE";
+const STAGED_KEY: &str = "$FORMAT_UPGRADE_STAGED";
+
+// the keys written by a transaction of an upgrade
+const UPGRADE_BATCH_SIZE: usize = 1000;
+
+/// The version of the storage format written by this release of PoloDB.
+pub const FORMAT_VERSION: u32 = 3;
+
+// the version of the databases written before the format was versioned
+const UNVERSIONED_FORMAT: u32 = 1;
+
+type UpgradeFn = fn(&DatabaseInner) -> Result<()>;
+
+/// The upgrades to each version after the first one, in order.
+const UPGRADES: [(u32, &str, UpgradeFn); 2] = [
+    // the keys of the Decimal128 values are in the order of the values
+    (2, "re-encode the Decimal128 keys", reencode_decimal_keys),
+    // the statistics of the indexes and the centroids of the vector indexes
+    (3, "analyze the indexes", DatabaseInner::analyze_all),
+];
+
+fn version_key() -> Result<Vec<u8>> {
+    crate::utils::bson::stacked_key(&[Bson::String(FORMAT_VERSION_KEY.to_string())])
+}
+
+/// Rewrite the primary keys and the index entries with Decimal128 values,
+/// which were written as the 16 bytes of the values before the version 2.
+///
+/// The old and the new keys can't be told apart, so the re-encoded keys are moved
+/// to the staging keys first, and then back to the collections. Each step is committed
+/// in batches, and a key moved by a committed batch is not scanned again, so an
+/// interrupted upgrade resumes from the last batch.
+fn reencode_decimal_keys(db: &DatabaseInner) -> Result<()> {
+    let staging_prefix = stacked_key(&[Bson::String(STAGING_PREFIX.to_string())])?;
+    let staged_key = staged_key()?;
+
+    let is_staged = db.start_snapshot_transaction()?.rocksdb_txn.get(&staged_key)?.is_some();
+    if !is_staged {
+        // the moved keys are not read again by the scan of the snapshot
+        let snapshot = db.start_snapshot_transaction()?;
+        let mut batch = UpgradeBatch::new(db)?;
+        for prefix in decimal_key_prefixes(db, &snapshot)? {
+            let mut cursor = Cursor::new(prefix, snapshot.rocksdb_txn.new_iterator());
+            cursor.reset()?;
+            while cursor.has_next() {
+                let key = cursor.peek_key().expect("key must exist");
+                // the keys which are not stacked keys are kept
+                if let Ok(values) = split_legacy_stacked_keys(&key) {
+                    if values.iter().any(|value| matches!(value, Bson::Decimal128(_))) {
+                        let mut staged = staging_prefix.clone();
+                        staged.extend_from_slice(&stacked_key(values.iter())?);
+                        batch.txn.delete(&key)?;
+                        batch.txn.put(&staged, &cursor.copy_data()?)?;
+                        batch.written()?;
+                    }
+                }
+                cursor.next()?;
+            }
+        }
+        batch.txn.put(&staged_key, &[])?;
+        batch.commit()?;
+    }
+
+    let snapshot = db.start_snapshot_transaction()?;
+    let mut batch = UpgradeBatch::new(db)?;
+    let mut cursor = Cursor::new(staging_prefix.clone(), snapshot.rocksdb_txn.new_iterator());
+    cursor.reset()?;
+    while cursor.has_next() {
+        let key = cursor.peek_key().expect("key must exist");
+        batch.txn.delete(&key)?;
+        batch.txn.put(&key[staging_prefix.len()..], &cursor.copy_data()?)?;
+        batch.written()?;
+        cursor.next()?;
+    }
+    // the staged mark is deleted with the version, see `upgrade`
+    batch.commit()
+}
+
+/// Set when all the keys of the version 1 are moved to the staging keys.
+fn staged_key() -> Result<Vec<u8>> {
+    stacked_key(&[Bson::String(STAGED_KEY.to_string())])
+}
+
+/// The prefixes of the keys which may have Decimal128 values: the documents,
+/// the index entries and the insertion order of the capped collections.
+/// The other keys, e.g. the entries of [`crate::kv`], are not re-encoded.
+fn decimal_key_prefixes(db: &DatabaseInner, txn: &TransactionInner) -> Result<Vec<Vec<u8>>> {
+    let mut result = Vec::new();
+    for spec in db.list_collection_specs(txn)? {
+        if spec.is_view() {
+            continue;
+        }
+        let storage_name = Bson::String(spec.storage_name().to_string());
+        result.push(stacked_key([&storage_name])?);
+        result.push(stacked_key([&Bson::String(INDEX_PREFIX.to_string()), &storage_name])?);
+        if spec.is_capped() {
+            result.push(capped::order_prefix(spec.storage_name())?);
+        }
+    }
+    Ok(result)
+}
+
+/// The writes of an upgrade committed every [`UPGRADE_BATCH_SIZE`] keys,
+/// so the upgrade of a large database doesn't keep all the changes in memory.
+struct UpgradeBatch<'a> {
+    db: &'a DatabaseInner,
+    txn: TransactionInner,
+    count: usize,
+}
+
+impl<'a> UpgradeBatch<'a> {
+
+    fn new(db: &'a DatabaseInner) -> Result<UpgradeBatch<'a>> {
+        Ok(UpgradeBatch {
+            db,
+            txn: db.start_transaction()?,
+            count: 0,
+        })
+    }
+
+    /// Count a written key, and commit the batch if it's full.
+    fn written(&mut self) -> Result<()> {
+        self.count += 1;
+        if self.count >= UPGRADE_BATCH_SIZE {
+            let txn = std::mem::replace(&mut self.txn, self.db.start_transaction()?);
+            txn.commit()?;
+            self.count = 0;
+        }
+        Ok(())
+    }
+
+    fn commit(self) -> Result<()> {
+        self.txn.commit()
+    }
+
+}
+
+/// The format version of the database, `None` if it's not saved.
+pub(crate) fn read_version(txn: &TransactionInner) -> Result<Option<u32>> {
+    let bytes = match txn.rocksdb_txn.get(&version_key()?)? {
+        Some(bytes) => bytes,
+        None => return Ok(None),
+    };
+    let doc = bson::from_slice::<Document>(bytes.as_ref())?;
+    Ok(Some(doc.get_i64("version").unwrap_or(0) as u32))
+}
+
+/// The transaction saving the format version, its commit is not counted by the fault injector,
+/// so the commits of a workload are counted from the first one after the database is opened.
+fn start_version_transaction(db: &DatabaseInner) -> Result<TransactionInner> {
+    let txn = db.start_transaction()?;
+    #[cfg(feature = "fault-injection")]
+    txn.rocksdb_txn.skip_faults();
+    Ok(txn)
+}
+
+fn write_version(txn: &TransactionInner, version: u32) -> Result<()> {
+    let doc = doc! {
+        "version": version as i64,
+        "updatedAt": DateTime::now(),
+    };
+    txn.put(&version_key()?, &bson::to_vec(&doc)?)
+}
+
+/// The format version of the database, a database without a saved version is
+/// of the current version if it's empty.
+pub(crate) fn current_version(db: &DatabaseInner) -> Result<(u32, bool)> {
+    let txn = db.start_snapshot_transaction()?;
+    if let Some(version) = read_version(&txn)? {
+        return Ok((version, true));
+    }
+    let iter = txn.rocksdb_txn.new_iterator();
+    iter.seek_to_first();
+    let is_empty = !iter.valid();
+    iter.error()?;
+    let version = if is_empty { FORMAT_VERSION } else { UNVERSIONED_FORMAT };
+    Ok((version, false))
+}
+
+/// Fail with [`Error::FormatVersionTooNew`] if the database is written by a newer release.
+pub(crate) fn check_version(db: &DatabaseInner) -> Result<u32> {
+    let (version, _) = current_version(db)?;
+    if version > FORMAT_VERSION {
+        return Err(Error::FormatVersionTooNew {
+            found: version,
+            supported: FORMAT_VERSION,
+        });
+    }
+    Ok(version)
+}
+
+/// Apply the upgrades newer than the format version of the database,
+/// and save the version of a new database.
+pub(crate) fn upgrade(db: &DatabaseInner) -> Result<UpgradeResult> {
+    let previous_version = check_version(db)?;
+    let (_, is_saved) = current_version(db)?;
+    // the version of a replica is replicated from the primary
+    if db.is_replica()? {
+        return Ok(UpgradeResult {
+            previous_version,
+            version: previous_version,
+            applied: Vec::new(),
+        });
+    }
+
+    let mut applied = Vec::new();
+    for (version, name, upgrade) in UPGRADES.iter() {
+        if *version <= previous_version {
+            continue;
+        }
+        crate::trace_span!("polodb.upgrade", version = *version, name = *name);
+        upgrade(db)?;
+        let txn = start_version_transaction(db)?;
+        write_version(&txn, *version)?;
+        // the progress of an upgrade is dropped with the version which completes it
+        txn.delete(&staged_key()?)?;
+        txn.commit()?;
+        applied.push(name.to_string());
+    }
+    if !is_saved && applied.is_empty() {
+        let txn = start_version_transaction(db)?;
+        write_version(&txn, FORMAT_VERSION)?;
+        txn.commit()?;
+    }
+
+    Ok(UpgradeResult {
+        previous_version,
+        version: FORMAT_VERSION,
+        applied,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use bson::{doc, Bson, Decimal128, Document};
+    use bson::spec::ElementType;
+    use crate::config::Config;
+    use crate::db::db_inner::DatabaseInner;
+    use crate::{CollectionT, Database, Error, IndexModel};
+    use crate::utils::bson::{split_stacked_keys, stacked_key, stacked_key_bytes};
+    use super::{read_version, staged_key, version_key, write_version, FORMAT_VERSION, STAGING_PREFIX};
+
+    #[test]
+    fn test_upgrade_unversioned() {
+        let path = env::temp_dir().join("test-format-upgrade-unversioned-db");
+        let _ = std::fs::remove_dir_all(&path);
+        {
+            let db = Database::open_path(&path).unwrap();
+            let col = db.collection::<bson::Document>("books");
+            col.create_index(IndexModel {
+                keys: doc! { "title": 1 },
+                options: None,
+            }).unwrap();
+            col.insert_one(doc! { "title": "1984" }).unwrap();
+            assert_eq!(db.format_version().unwrap(), FORMAT_VERSION);
+        }
+        // a database written before the format was versioned
+        {
+            let db = DatabaseInner::open_file(&path, Config::default()).unwrap();
+            let txn = db.start_transaction().unwrap();
+            txn.delete(&version_key().unwrap()).unwrap();
+            txn.commit().unwrap();
+        }
+
+        let result = Database::upgrade(&path).unwrap();
+        assert_eq!(result.previous_version, 1);
+        assert_eq!(result.version, FORMAT_VERSION);
+        assert_eq!(result.applied, vec![
+            "re-encode the Decimal128 keys".to_string(),
+            "analyze the indexes".to_string(),
+        ]);
+
+        let db = Database::open_path(&path).unwrap();
+        assert_eq!(db.format_version().unwrap(), FORMAT_VERSION);
+        drop(db);
+
+        let result = Database::upgrade(&path).unwrap();
+        assert_eq!(result.previous_version, FORMAT_VERSION);
+        assert!(result.applied.is_empty());
+    }
+
+    /// The key written before the version 2, the Decimal128 values were their bytes.
+    fn legacy_key(values: &[Bson]) -> Vec<u8> {
+        let mut key = Vec::new();
+        for value in values {
+            match value {
+                Bson::Decimal128(decimal) => {
+                    key.push(ElementType::Decimal128 as u8);
+                    key.extend_from_slice(&decimal.bytes());
+                }
+                _ => stacked_key_bytes(&mut key, value).unwrap(),
+            }
+        }
+        key
+    }
+
+    /// Write the keys with the Decimal128 values and the version like the version 1.
+    fn write_v1_decimal_keys(db: &DatabaseInner) {
+        let txn = db.start_transaction().unwrap();
+        let iter = txn.rocksdb_txn.new_iterator();
+        iter.seek_to_first();
+        let mut keys = Vec::new();
+        while iter.valid() {
+            keys.push((iter.copy_key().unwrap(), iter.copy_data().unwrap()));
+            iter.next();
+        }
+        for (key, value) in keys {
+            let values = match split_stacked_keys(&key) {
+                Ok(values) if values.iter().any(|value| matches!(value, Bson::Decimal128(_))) => values,
+                _ => continue,
+            };
+            txn.delete(&key).unwrap();
+            txn.put(&legacy_key(&values), &value).unwrap();
+        }
+        txn.delete(&version_key().unwrap()).unwrap();
+        txn.commit().unwrap();
+    }
+
+    #[test]
+    fn test_open_v1_decimal_keys() {
+        let path = env::temp_dir().join("test-format-v1-decimal-db");
+        let _ = std::fs::remove_dir_all(&path);
+        let decimal = |s: &str| Bson::Decimal128(s.parse::<Decimal128>().unwrap());
+        {
+            let db = Database::open_path(&path).unwrap();
+            let col = db.collection::<Document>("prices");
+            col.create_index(IndexModel {
+                keys: doc! { "amount": 1 },
+                options: None,
+            }).unwrap();
+            for (id, amount) in [("2.5", "10"), ("-1", "0.5"), ("1.25", "-3")] {
+                col.insert_one(doc! { "_id": decimal(id), "amount": decimal(amount) }).unwrap();
+            }
+        }
+        {
+            let db = DatabaseInner::open_file(&path, Config::default()).unwrap();
+            write_v1_decimal_keys(&db);
+        }
+
+        let db = Database::open_path(&path).unwrap();
+        assert_eq!(db.format_version().unwrap(), FORMAT_VERSION);
+        let col = db.collection::<Document>("prices");
+        let doc = col.find_one(doc! { "_id": decimal("2.5") }).unwrap().unwrap();
+        assert_eq!(doc.get("amount"), Some(&decimal("10")));
+        let doc = col.find_one(doc! { "amount": decimal("-3") }).unwrap().unwrap();
+        assert_eq!(doc.get("_id"), Some(&decimal("1.25")));
+
+        // the documents are in the order of the values
+        let ids: Vec<Bson> = col.find(doc! {}).run().unwrap()
+            .map(|doc| doc.unwrap().get("_id").unwrap().clone())
+            .collect();
+        assert_eq!(ids, vec![decimal("-1"), decimal("1.25"), decimal("2.5")]);
+    }
+
+    #[test]
+    fn test_resume_decimal_keys() {
+        let path = env::temp_dir().join("test-format-resume-decimal-db");
+        let _ = std::fs::remove_dir_all(&path);
+        let decimal = |s: &str| Bson::Decimal128(s.parse::<Decimal128>().unwrap());
+        {
+            let db = Database::open_path(&path).unwrap();
+            let col = db.collection::<Document>("prices");
+            for id in ["2.5", "-1", "1.25"] {
+                col.insert_one(doc! { "_id": decimal(id) }).unwrap();
+            }
+        }
+        let raw_key = legacy_key(&[Bson::String("raw".to_string()), decimal("1")]);
+        {
+            let db = DatabaseInner::open_file(&path, Config::default()).unwrap();
+            write_v1_decimal_keys(&db);
+
+            // an upgrade interrupted after a batch is staged
+            let txn = db.start_transaction().unwrap();
+            let legacy = legacy_key(&[Bson::String("prices".to_string()), decimal("2.5")]);
+            let data = txn.rocksdb_txn.get(&legacy).unwrap().unwrap();
+            let mut staged = stacked_key(&[Bson::String(STAGING_PREFIX.to_string())]).unwrap();
+            staged.extend_from_slice(&stacked_key(&[Bson::String("prices".to_string()), decimal("2.5")]).unwrap());
+            txn.delete(&legacy).unwrap();
+            txn.put(&staged, &data).unwrap();
+
+            // the keys out of the collections are not re-encoded
+            txn.put(&raw_key, b"raw").unwrap();
+            txn.commit().unwrap();
+        }
+
+        let result = Database::upgrade(&path).unwrap();
+        assert_eq!(result.previous_version, 1);
+
+        let db = Database::open_path(&path).unwrap();
+        let col = db.collection::<Document>("prices");
+        let ids: Vec<Bson> = col.find(doc! {}).run().unwrap()
+            .map(|doc| doc.unwrap().get("_id").unwrap().clone())
+            .collect();
+        assert_eq!(ids, vec![decimal("-1"), decimal("1.25"), decimal("2.5")]);
+        drop(db);
+
+        let db = DatabaseInner::open_file(&path, Config::default()).unwrap();
+        let txn = db.start_snapshot_transaction().unwrap();
+        assert_eq!(txn.rocksdb_txn.get(&raw_key).unwrap(), Some(b"raw".to_vec()));
+        assert_eq!(txn.rocksdb_txn.get(&staged_key().unwrap()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_too_new_version() {
+        let path = env::temp_dir().join("test-format-too-new-db");
+        let _ = std::fs::remove_dir_all(&path);
+        {
+            let db = DatabaseInner::open_file(&path, Config::default()).unwrap();
+            let txn = db.start_transaction().unwrap();
+            assert_eq!(read_version(&txn).unwrap(), Some(FORMAT_VERSION));
+            write_version(&txn, FORMAT_VERSION + 1).unwrap();
+            txn.commit().unwrap();
+        }
+
+        let expected = Error::FormatVersionTooNew { found: FORMAT_VERSION + 1, supported: FORMAT_VERSION };
+        let err = Database::open_path(&path).err().unwrap();
+        assert_eq!(err.to_string(), expected.to_string());
+        let err = Database::open_read_only(&path).err().unwrap();
+        assert!(matches!(err, Error::FormatVersionTooNew { .. }));
+        assert!(Database::upgrade(&path).is_err());
+    }
+}
@@ -0,0 +1,220 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Estimate the dead bytes of each level of the LSM tree.
+//!
+//! The table properties record the sizes of all the keys and the values in
+//! the files, including the tombstones and the shadowed versions. The sizes
+//! of the live ones are read by a scan of the database, the difference is
+//! what is released by a full compaction.
+//!
+//! The tombstones are dead in their own levels. The shadowed versions are
+//! spread over the levels by the bytes of the files overlapping the newer files,
+//! which are the only files where an old version of a key can be.
+
+use crate::db::inspect::INTERNAL_KEY_FOOTER_SIZE;
+use crate::db::rocksdb_wrapper::LiveFile;
+use crate::results::{BlockStats, FragmentationReport, LevelFragmentation};
+
+/// The sizes of the live keys and values, read by a scan of the database.
+#[derive(Default)]
+pub(crate) struct LiveData {
+    pub(crate) entries: u64,
+    /// The sizes of the internal keys and the values, as counted by the table properties.
+    pub(crate) raw_size: u64,
+}
+
+impl LiveData {
+
+    pub(crate) fn add(&mut self, key_len: usize, value_len: usize) {
+        self.entries += 1;
+        self.raw_size += (key_len + INTERNAL_KEY_FOOTER_SIZE + value_len) as u64;
+    }
+
+}
+
+struct LevelEstimate {
+    report: LevelFragmentation,
+    raw_size: u64,
+    tombstone_raw_size: u64,
+    overlapped_raw_size: u64,
+}
+
+pub(crate) fn estimate(levels: &[(Vec<LiveFile>, BlockStats)], live: &LiveData) -> FragmentationReport {
+    let mut estimates = Vec::with_capacity(levels.len());
+
+    for (index, (files, blocks)) in levels.iter().enumerate() {
+        let size: u64 = files.iter().map(|file| file.size).sum();
+        let entries: u64 = files.iter().map(|file| file.entries).sum();
+        let deletions: u64 = files.iter().map(|file| file.deletions).sum();
+        let raw_size = blocks.raw_key_size + blocks.raw_value_size;
+
+        let overlapped_size: u64 = files.iter()
+            .enumerate()
+            .filter(|(file_index, file)| {
+                let newer_files = levels[..index].iter().flat_map(|(files, _)| files.iter());
+                let same_level = files.iter()
+                    .enumerate()
+                    .filter(|(other_index, _)| index == 0 && other_index != file_index)
+                    .map(|(_, other)| other);
+                newer_files.chain(same_level).any(|other| overlaps(file, other))
+            })
+            .map(|(_, file)| file.size)
+            .sum();
+        let overlap_ratio = ratio(overlapped_size, size);
+        let tombstone_raw_size = (blocks.raw_key_size * deletions).checked_div(entries).unwrap_or(0);
+
+        estimates.push(LevelEstimate {
+            report: LevelFragmentation {
+                level: index as u32,
+                file_count: files.len() as u64,
+                size,
+                entries,
+                deletions,
+                tombstone_ratio: ratio(deletions, entries),
+                overlap_ratio,
+                live_size: size,
+                dead_size: 0,
+            },
+            raw_size,
+            tombstone_raw_size,
+            overlapped_raw_size: (raw_size as f64 * overlap_ratio) as u64,
+        });
+    }
+
+    let total_raw_size: u64 = estimates.iter().map(|level| level.raw_size).sum();
+    let total_tombstone_size: u64 = estimates.iter().map(|level| level.tombstone_raw_size).sum();
+    let shadowed_size = total_raw_size.saturating_sub(live.raw_size + total_tombstone_size);
+
+    // without the overlaps, e.g. the versions kept for a snapshot in the same file
+    let total_overlapped_size: u64 = estimates.iter().map(|level| level.overlapped_raw_size).sum();
+    let (weights, total_weight): (Vec<u64>, u64) = if total_overlapped_size > 0 {
+        (estimates.iter().map(|level| level.overlapped_raw_size).collect(), total_overlapped_size)
+    } else {
+        (estimates.iter().map(|level| level.raw_size).collect(), total_raw_size)
+    };
+
+    let mut report = FragmentationReport {
+        live_entries: live.entries,
+        ..Default::default()
+    };
+    for (level, weight) in estimates.into_iter().zip(weights) {
+        let mut level_report = level.report;
+        let shadowed = if total_weight == 0 {
+            0
+        } else {
+            (shadowed_size as u128 * weight as u128 / total_weight as u128) as u64
+        };
+        let dead_raw_size = (level.tombstone_raw_size + shadowed).min(level.raw_size);
+        // the table files are compressed, the dead bytes are scaled by the
+        // same ratio as the level
+        level_report.dead_size = (level_report.size as f64 * ratio(dead_raw_size, level.raw_size)) as u64;
+        level_report.live_size = level_report.size - level_report.dead_size;
+
+        report.size += level_report.size;
+        report.live_size += level_report.live_size;
+        report.reclaimable_size += level_report.dead_size;
+        report.levels.push(level_report);
+    }
+    report
+}
+
+fn overlaps(a: &LiveFile, b: &LiveFile) -> bool {
+    a.smallest_key <= b.largest_key && b.smallest_key <= a.largest_key
+}
+
+fn ratio(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::rocksdb_wrapper::LiveFile;
+    use crate::results::BlockStats;
+    use super::{estimate, LiveData};
+
+    fn file(size: u64, entries: u64, deletions: u64, smallest: u8, largest: u8) -> LiveFile {
+        LiveFile {
+            name: String::new(),
+            size,
+            entries,
+            deletions,
+            smallest_key: vec![smallest],
+            largest_key: vec![largest],
+        }
+    }
+
+    fn blocks(raw_key_size: u64, raw_value_size: u64) -> BlockStats {
+        BlockStats {
+            raw_key_size,
+            raw_value_size,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_estimate() {
+        let levels = vec![
+            // 10 keys of 10 bytes, 2 of them tombstones, the values are 90 bytes
+            (vec![file(500, 10, 2, 0, 10)], blocks(100, 720)),
+            (Vec::new(), BlockStats::default()),
+            // 100 keys of 10 bytes, the values are 90 bytes
+            (vec![file(2_000, 50, 0, 0, 49), file(2_000, 50, 0, 50, 99)], blocks(1_000, 9_000)),
+        ];
+        // 8 keys are overwritten, 2 are deleted
+        let live = LiveData {
+            entries: 98,
+            raw_size: 98 * 100,
+        };
+        let report = estimate(&levels, &live);
+        assert_eq!(report.live_entries, 98);
+        assert_eq!(report.size, 4_500);
+
+        let level0 = &report.levels[0];
+        assert_eq!(level0.tombstone_ratio, 0.2);
+        assert_eq!(level0.overlap_ratio, 0.0);
+        assert_eq!(level0.dead_size, 500 * 20 / 820);
+
+        let level2 = &report.levels[2];
+        // the second file doesn't overlap level 0
+        assert_eq!(level2.overlap_ratio, 0.5);
+        // the 10 versions shadowed by level 0
+        assert_eq!(level2.dead_size, 4_000 * 1_000 / 10_000);
+        assert_eq!(level2.live_size, 4_000 - level2.dead_size);
+
+        assert_eq!(report.reclaimable_size, level0.dead_size + level2.dead_size);
+        assert_eq!(report.live_size, report.size - report.reclaimable_size);
+    }
+
+    #[test]
+    fn test_estimate_compacted() {
+        let levels = vec![
+            (Vec::new(), BlockStats::default()),
+            (vec![file(2_000, 100, 0, 0, 99)], blocks(1_000, 9_000)),
+        ];
+        let live = LiveData {
+            entries: 100,
+            raw_size: 10_000,
+        };
+        let report = estimate(&levels, &live);
+        assert_eq!(report.reclaimable_size, 0);
+        assert_eq!(report.live_size, 2_000);
+    }
+
+}
@@ -0,0 +1,533 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Read the manifest and the write-ahead log files of RocksDB,
+//! see `db/log_format.h` and `db/version_edit.h` of RocksDB for the formats.
+//!
+//! The files are decoded without RocksDB, so the records which RocksDB
+//! doesn't replay, e.g. the torn ones at the end of the files, are still reported.
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use bson::Bson;
+use byteorder::{ByteOrder, LittleEndian};
+use crate::db::rocksdb_wal::{decode_write_batch, read_length_prefixed, read_varint32};
+use crate::results::{ManifestEdit, ManifestFile, ManifestInspection, WalFileInspection, WalOperationInspection, WalRecordInspection};
+use crate::utils::bson::{split_stacked_keys, stacked_key};
+use crate::{Error, Result, WalOperation};
+
+const BLOCK_SIZE: usize = 32768;
+const HEADER_SIZE: usize = 4 + 2 + 1;
+const RECYCLABLE_HEADER_SIZE: usize = 4 + 2 + 1 + 4;
+
+// the types of the physical records
+const ZERO_TYPE: u8 = 0;
+const FULL_TYPE: u8 = 1;
+const FIRST_TYPE: u8 = 2;
+const MIDDLE_TYPE: u8 = 3;
+const LAST_TYPE: u8 = 4;
+const RECYCLABLE_FULL_TYPE: u8 = 5;
+const RECYCLABLE_LAST_TYPE: u8 = 8;
+const SET_COMPRESSION_TYPE: u8 = 9;
+const USER_DEFINED_TIMESTAMP_SIZE_TYPE: u8 = 10;
+const RECYCLABLE_USER_DEFINED_TIMESTAMP_SIZE_TYPE: u8 = 11;
+
+// the tags of the fields of a version edit
+const TAG_COMPARATOR: u32 = 1;
+const TAG_LOG_NUMBER: u32 = 2;
+const TAG_NEXT_FILE_NUMBER: u32 = 3;
+const TAG_LAST_SEQUENCE: u32 = 4;
+const TAG_COMPACT_CURSOR: u32 = 5;
+const TAG_DELETED_FILE: u32 = 6;
+const TAG_PREV_LOG_NUMBER: u32 = 9;
+const TAG_MIN_LOG_NUMBER_TO_KEEP: u32 = 10;
+const TAG_NEW_FILE4: u32 = 103;
+const TAG_COLUMN_FAMILY: u32 = 200;
+const TAG_COLUMN_FAMILY_ADD: u32 = 201;
+const TAG_COLUMN_FAMILY_DROP: u32 = 202;
+const TAG_MAX_COLUMN_FAMILY: u32 = 203;
+const TAG_IN_ATOMIC_GROUP: u32 = 300;
+const TAG_SAFE_IGNORE_MASK: u32 = 1 << 13;
+const TAG_DB_ID: u32 = TAG_SAFE_IGNORE_MASK + 1;
+const NEW_FILE_TERMINATE: u32 = 1;
+
+// the size of the sequence number and the type appended to the user key
+pub(crate) const INTERNAL_KEY_FOOTER_SIZE: usize = 8;
+
+const CRC32C_TABLE: [u32; 256] = crc32c_table();
+
+const fn crc32c_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0x82F63B78 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc = CRC32C_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// RocksDB stores the masked checksums, see `util/crc32c.h`.
+fn unmask_crc(masked: u32) -> u32 {
+    let rot = masked.wrapping_sub(0xa282ead8);
+    rot.rotate_left(15)
+}
+
+/// The records read from a log file.
+pub(crate) struct LogRecords {
+    /// The logical records with the offsets of their starts and ends.
+    pub(crate) records: Vec<(u64, u64, Vec<u8>)>,
+    /// The physical records which can not be read, with their offsets.
+    pub(crate) errors: Vec<(u64, String)>,
+    /// The size of the file without the preallocated space, which is zeros.
+    pub(crate) used_size: u64,
+}
+
+impl LogRecords {
+
+    pub(crate) fn error_messages(&self) -> Vec<String> {
+        self.errors.iter()
+            .map(|(offset, message)| format!("offset {}: {}", offset, message))
+            .collect()
+    }
+
+    /// The offset of the first error, where RocksDB stops replaying
+    /// the log in the point-in-time recovery.
+    pub(crate) fn first_error(&self) -> Option<u64> {
+        self.errors.iter().map(|(offset, _)| *offset).min()
+    }
+
+}
+
+/// Read the logical records of a log file block by block.
+///
+/// Without `payloads`, only the offsets of the records are kept
+/// and their payloads are left empty.
+pub(crate) fn read_log_records<R: Read>(mut reader: R, payloads: bool) -> Result<LogRecords> {
+    let mut records = Vec::new();
+    let mut errors = Vec::new();
+    let mut used_size = 0;
+    let mut fragments: Option<(u64, Vec<u8>)> = None;
+    let mut stopped = false;
+    let mut block = Vec::with_capacity(BLOCK_SIZE);
+    let mut block_start = 0u64;
+
+    loop {
+        block.clear();
+        (&mut reader).take(BLOCK_SIZE as u64).read_to_end(&mut block)?;
+        if block.is_empty() {
+            break;
+        }
+        if let Some(index) = block.iter().rposition(|byte| *byte != 0) {
+            used_size = block_start + index as u64 + 1;
+        }
+
+        // the rest of the file is only read for its used size after a stop
+        let mut pos = 0;
+        while !stopped && pos < block.len() {
+            if BLOCK_SIZE - pos < HEADER_SIZE {
+                // the trailer of the block
+                break;
+            }
+            let record_offset = block_start + pos as u64;
+            if pos + HEADER_SIZE > block.len() {
+                errors.push((record_offset, "truncated record header".to_string()));
+                stopped = true;
+                break;
+            }
+
+            let header = &block[pos..];
+            let masked_crc = LittleEndian::read_u32(&header[0..4]);
+            let length = LittleEndian::read_u16(&header[4..6]) as usize;
+            let record_type = header[6];
+            if record_type == ZERO_TYPE && length == 0 {
+                // the preallocated space of the file
+                stopped = true;
+                break;
+            }
+            let is_recyclable = (RECYCLABLE_FULL_TYPE..=RECYCLABLE_LAST_TYPE).contains(&record_type)
+                || record_type == RECYCLABLE_USER_DEFINED_TIMESTAMP_SIZE_TYPE;
+            let header_size = if is_recyclable { RECYCLABLE_HEADER_SIZE } else { HEADER_SIZE };
+            if pos + header_size + length > block.len() {
+                if block.len() < BLOCK_SIZE {
+                    errors.push((record_offset, format!("truncated record of {} bytes", length)));
+                    stopped = true;
+                } else {
+                    // the physical records never cross the blocks
+                    errors.push((record_offset, format!("record of {} bytes crossing the block", length)));
+                    fragments = None;
+                }
+                break;
+            }
+
+            let payload = &block[pos + header_size..pos + header_size + length];
+            let checked = &block[pos + 6..pos + header_size + length];
+            pos += header_size + length;
+            let record_end = block_start + pos as u64;
+
+            if crc32c(checked) != unmask_crc(masked_crc) {
+                errors.push((record_offset, "checksum mismatch".to_string()));
+                fragments = None;
+                continue;
+            }
+
+            let payload = if payloads { payload } else { &[] };
+            let logical_type = if is_recyclable { record_type - (RECYCLABLE_FULL_TYPE - FULL_TYPE) } else { record_type };
+            match logical_type {
+                FULL_TYPE => {
+                    if let Some((start, _)) = fragments.take() {
+                        errors.push((start, "record without the last fragment".to_string()));
+                    }
+                    records.push((record_offset, record_end, payload.to_vec()));
+                }
+                FIRST_TYPE => {
+                    if let Some((start, _)) = fragments.take() {
+                        errors.push((start, "record without the last fragment".to_string()));
+                    }
+                    fragments = Some((record_offset, payload.to_vec()));
+                }
+                MIDDLE_TYPE | LAST_TYPE => match fragments.as_mut() {
+                    Some((_, buffer)) => {
+                        buffer.extend_from_slice(payload);
+                        if logical_type == LAST_TYPE {
+                            let (start, buffer) = fragments.take().unwrap();
+                            records.push((start, record_end, buffer));
+                        }
+                    }
+                    None => errors.push((record_offset, "fragment without the first one".to_string())),
+                },
+                SET_COMPRESSION_TYPE | USER_DEFINED_TIMESTAMP_SIZE_TYPE => (),
+                _ => errors.push((record_offset, format!("unknown record type {}", record_type))),
+            }
+        }
+
+        block_start += block.len() as u64;
+        if block.len() < BLOCK_SIZE {
+            break;
+        }
+    }
+
+    if let Some((start, _)) = fragments {
+        errors.push((start, "record without the last fragment".to_string()));
+    }
+
+    Ok(LogRecords {
+        records,
+        errors,
+        used_size,
+    })
+}
+
+/// Print a key as the stacked keys of PoloDB, e.g. `["users",1]`,
+/// or in hexadecimal if it can not be decoded.
+pub(crate) fn format_key(key: &[u8]) -> String {
+    if let Ok(values) = split_stacked_keys(key) {
+        if !values.is_empty() && stacked_key(values.iter()).is_ok_and(|encoded| encoded == key) {
+            return Bson::Array(values).into_relaxed_extjson().to_string();
+        }
+    }
+    key.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn format_internal_key(key: &[u8]) -> String {
+    format_key(&key[..key.len().saturating_sub(INTERNAL_KEY_FOOTER_SIZE)])
+}
+
+fn read_varint64(input: &mut &[u8]) -> Result<u64> {
+    let mut result: u64 = 0;
+    for (index, byte) in input.iter().enumerate().take(10) {
+        result |= ((byte & 0x7F) as u64) << (7 * index);
+        if byte & 0x80 == 0 {
+            *input = &input[index + 1..];
+            return Ok(result);
+        }
+    }
+    Err(Error::RocksDbErr("malformed varint in manifest".to_string()))
+}
+
+fn decode_version_edit(offset: u64, mut input: &[u8]) -> Result<ManifestEdit> {
+    let mut edit = ManifestEdit {
+        offset,
+        ..Default::default()
+    };
+    let input = &mut input;
+
+    while !input.is_empty() {
+        let tag = read_varint32(input)?;
+        match tag {
+            TAG_COMPARATOR => {
+                edit.comparator = Some(String::from_utf8_lossy(read_length_prefixed(input)?).to_string());
+            }
+            TAG_DB_ID => {
+                edit.db_id = Some(String::from_utf8_lossy(read_length_prefixed(input)?).to_string());
+            }
+            TAG_LOG_NUMBER => edit.log_number = Some(read_varint64(input)?),
+            TAG_NEXT_FILE_NUMBER => edit.next_file_number = Some(read_varint64(input)?),
+            TAG_LAST_SEQUENCE => edit.last_sequence = Some(read_varint64(input)?),
+            TAG_PREV_LOG_NUMBER | TAG_MIN_LOG_NUMBER_TO_KEEP => {
+                read_varint64(input)?;
+            }
+            TAG_COMPACT_CURSOR => {
+                read_varint32(input)?;
+                read_length_prefixed(input)?;
+            }
+            TAG_DELETED_FILE => {
+                let level = read_varint32(input)?;
+                let number = read_varint64(input)?;
+                edit.deleted_files.push(ManifestFile {
+                    level,
+                    number,
+                    ..Default::default()
+                });
+            }
+            TAG_NEW_FILE4 => {
+                let level = read_varint32(input)?;
+                let number = read_varint64(input)?;
+                let size = read_varint64(input)?;
+                let smallest_key = format_internal_key(read_length_prefixed(input)?);
+                let largest_key = format_internal_key(read_length_prefixed(input)?);
+                // the smallest and the largest sequence numbers
+                read_varint64(input)?;
+                read_varint64(input)?;
+                loop {
+                    let field = read_varint32(input)?;
+                    if field == NEW_FILE_TERMINATE {
+                        break;
+                    }
+                    read_length_prefixed(input)?;
+                }
+                edit.new_files.push(ManifestFile {
+                    level,
+                    number,
+                    size: Some(size),
+                    smallest_key: Some(smallest_key),
+                    largest_key: Some(largest_key),
+                });
+            }
+            TAG_COLUMN_FAMILY | TAG_MAX_COLUMN_FAMILY | TAG_IN_ATOMIC_GROUP => {
+                let value = read_varint32(input)?;
+                if tag == TAG_COLUMN_FAMILY {
+                    edit.column_family = Some(value);
+                }
+            }
+            TAG_COLUMN_FAMILY_ADD => {
+                read_length_prefixed(input)?;
+            }
+            TAG_COLUMN_FAMILY_DROP => (),
+            _ if tag & TAG_SAFE_IGNORE_MASK != 0 => {
+                read_length_prefixed(input)?;
+            }
+            _ => {
+                edit.unknown_fields.push(tag);
+                break;
+            }
+        }
+    }
+
+    Ok(edit)
+}
+
+/// Decode the current manifest of the database in `path`.
+pub(crate) fn inspect_manifest(path: &Path) -> Result<ManifestInspection> {
+    let current = fs::read_to_string(path.join("CURRENT"))?;
+    let file = current.trim().to_string();
+    let data = fs::read(path.join(&file))?;
+
+    let log = read_log_records(data.as_slice(), true)?;
+    let mut errors = log.error_messages();
+    let mut edits = Vec::with_capacity(log.records.len());
+    for (offset, _, record) in log.records {
+        match decode_version_edit(offset, &record) {
+            Ok(edit) => edits.push(edit),
+            Err(err) => errors.push(format!("offset {}: {}", offset, err)),
+        }
+    }
+
+    Ok(ManifestInspection {
+        file,
+        size: data.len() as u64,
+        edits,
+        errors,
+    })
+}
+
+/// Decode the write-ahead log files of the database in `path`,
+/// including the archived ones, the oldest first.
+pub(crate) fn inspect_wal_files(path: &Path) -> Result<Vec<WalFileInspection>> {
+    let names = wal_file_names(path, &["", "archive"])?;
+
+    let mut result = Vec::with_capacity(names.len());
+    for (_, name) in names {
+        let data = fs::read(path.join(&name))?;
+        let log = read_log_records(data.as_slice(), true)?;
+        let mut errors = log.error_messages();
+        let mut wal_records = Vec::with_capacity(log.records.len());
+        for (offset, _, record) in log.records {
+            if record.len() < 8 {
+                errors.push(format!("offset {}: malformed write batch header", offset));
+                continue;
+            }
+            match decode_write_batch(&record) {
+                Ok(operations) => wal_records.push(WalRecordInspection {
+                    offset,
+                    sequence: LittleEndian::read_u64(&record[0..8]),
+                    operations: operations.iter().map(inspect_wal_operation).collect(),
+                }),
+                Err(err) => errors.push(format!("offset {}: {}", offset, err)),
+            }
+        }
+        result.push(WalFileInspection {
+            name,
+            size: data.len() as u64,
+            records: wal_records,
+            errors,
+        });
+    }
+
+    Ok(result)
+}
+
+/// The numbers and the names of the log files in the directories of the database, ordered by the numbers.
+pub(crate) fn wal_file_names(path: &Path, dirs: &[&str]) -> Result<Vec<(u64, String)>> {
+    let mut names = Vec::<(u64, String)>::new();
+    for dir in dirs {
+        let entries = match fs::read_dir(path.join(dir)) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries {
+            let entry = entry?;
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let number = match file_name.strip_suffix(".log").and_then(|stem| stem.parse::<u64>().ok()) {
+                Some(number) => number,
+                None => continue,
+            };
+            let name = if dir.is_empty() { file_name } else { format!("{}/{}", dir, file_name) };
+            names.push((number, name));
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+fn inspect_wal_operation(operation: &WalOperation) -> WalOperationInspection {
+    match operation {
+        WalOperation::Put { key, value } => WalOperationInspection {
+            op: "put".to_string(),
+            key: format_key(key),
+            value_size: value.len() as u64,
+        },
+        WalOperation::Delete { key } => WalOperationInspection {
+            op: "delete".to_string(),
+            key: format_key(key),
+            value_size: 0,
+        },
+        WalOperation::DeleteRange { start, end } => WalOperationInspection {
+            op: "deleteRange".to_string(),
+            key: format!("{}..{}", format_key(start), format_key(end)),
+            value_size: 0,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32c, read_log_records, unmask_crc, BLOCK_SIZE, FIRST_TYPE, FULL_TYPE, HEADER_SIZE, LAST_TYPE};
+
+    fn mask_crc(crc: u32) -> u32 {
+        crc.rotate_right(15).wrapping_add(0xa282ead8)
+    }
+
+    fn physical_record(record_type: u8, payload: &[u8]) -> Vec<u8> {
+        let mut checked = vec![record_type];
+        checked.extend_from_slice(payload);
+        let mut result = mask_crc(crc32c(&checked)).to_le_bytes().to_vec();
+        result.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        result.extend_from_slice(&checked);
+        result
+    }
+
+    #[test]
+    fn test_crc32c() {
+        assert_eq!(crc32c(b"123456789"), 0xE3069283);
+        assert_eq!(unmask_crc(mask_crc(0x12345678)), 0x12345678);
+    }
+
+    #[test]
+    fn test_read_log_records() {
+        let mut data = physical_record(FULL_TYPE, b"hello");
+        let second = data.len() as u64;
+
+        // a record spanning two blocks
+        let first_len = BLOCK_SIZE - data.len() - HEADER_SIZE;
+        let payload = vec![7u8; first_len + 10];
+        data.extend(physical_record(FIRST_TYPE, &payload[..first_len]));
+        data.extend(physical_record(LAST_TYPE, &payload[first_len..]));
+
+        // a torn record at the end
+        let mut torn = physical_record(FULL_TYPE, b"world");
+        torn.truncate(torn.len() - 2);
+        data.extend(torn);
+
+        let third = (BLOCK_SIZE + HEADER_SIZE + 10) as u64;
+        let log = read_log_records(data.as_slice(), true).unwrap();
+        assert_eq!(log.records, vec![(0, second, b"hello".to_vec()), (second, third, payload)]);
+        let errors = log.error_messages();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("truncated"));
+        assert_eq!(log.first_error(), Some(third));
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let mut data = physical_record(FULL_TYPE, b"hello");
+        let last = data.len() - 1;
+        data[last] ^= 0xFF;
+        data.extend(physical_record(FULL_TYPE, b"world"));
+
+        let log = read_log_records(data.as_slice(), true).unwrap();
+        assert_eq!(log.records.len(), 1);
+        assert_eq!(log.records[0].2, b"world".to_vec());
+        assert_eq!(log.error_messages(), vec!["offset 0: checksum mismatch".to_string()]);
+        assert_eq!(log.first_error(), Some(0));
+    }
+
+    #[test]
+    fn test_read_log_offsets() {
+        let mut data = physical_record(FULL_TYPE, b"hello");
+        let end = data.len() as u64;
+        // the preallocated space spanning a few blocks
+        data.resize(3 * BLOCK_SIZE + 100, 0);
+
+        let log = read_log_records(std::io::BufReader::new(data.as_slice()), false).unwrap();
+        assert_eq!(log.records, vec![(0, end, Vec::new())]);
+        assert!(log.errors.is_empty());
+        assert_eq!(log.used_size, end);
+    }
+
+}
@@ -18,8 +18,34 @@ pub mod client_cursor;
 mod rocksdb_wrapper;
 mod rocksdb_transaction;
 mod rocksdb_iterator;
+mod rocksdb_snapshot;
 mod rocksdb_options;
+mod rocksdb_wal;
+mod rocksdb_backup;
+mod rocksdb_storage_backend;
+mod bundle;
+mod rocksdb_cache;
+mod rocksdb_perf_context;
+pub(crate) mod profiler;
+pub(crate) mod sequence;
+pub(crate) mod parallel_scan;
+mod current_op;
+mod inspect;
+mod fragmentation;
+mod change_stream;
+mod recovery;
+pub(crate) mod format;
 
 pub use db::{Database, Result};
-pub(crate) use rocksdb_transaction::RocksDBTransaction;
+pub(crate) use db::{qualify_col_name, SHOULD_LOG};
+pub use rocksdb_wal::{WalRecord, WalOperation};
+pub use rocksdb_cache::BlockCache;
+pub use format::FORMAT_VERSION;
+pub(crate) use rocksdb_transaction::{RocksDBTransaction, RocksDBTransactionInner};
 pub(crate) use rocksdb_iterator::RocksDBIterator;
+pub(crate) use rocksdb_snapshot::RocksDBSnapshot;
+pub(crate) use rocksdb_perf_context::RocksDBPerfContext;
+pub(crate) use profiler::{Profiler, ProfileSpan};
+pub use profiler::PROFILE_COLLECTION;
+pub use current_op::CancellationToken;
+pub(crate) use current_op::{OperationGuard, OperationRegistry};
@@ -0,0 +1,238 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use bson::{Bson, Document};
+use crate::coll::collection_info::CollectionSpecification;
+use crate::metrics::Metrics;
+use crate::transaction::TransactionInner;
+use crate::utils::spill::SpillOptions;
+use crate::vm::{SubProgram, VmState, VM};
+use crate::{CancellationToken, Result};
+
+// the documents a partition reads ahead of the cursor
+const PARTITION_BUFFER_SIZE: usize = 1024;
+
+/// A partition of the parallel scan, the query of the primary keys in `range`
+/// read by its own transaction.
+pub(crate) struct ScanPartition {
+    pub(crate) txn: TransactionInner,
+    pub(crate) range: Document,
+}
+
+/// The program run by each partition.
+pub(crate) enum PartitionProgram {
+    /// The documents matching the query
+    Query(Document),
+    /// The leading stages of an aggregation, whose results are passed to the other stages
+    Aggregate(Vec<Document>),
+}
+
+impl PartitionProgram {
+
+    fn compile(&self, col_spec: &CollectionSpecification, range: Document) -> Result<SubProgram> {
+        match self {
+            PartitionProgram::Query(query) => SubProgram::compile_query_partition(col_spec, query, range, true),
+            PartitionProgram::Aggregate(pipeline) => {
+                SubProgram::compile_aggregate_partition(col_spec, pipeline.clone(), range, true)
+            }
+        }
+    }
+
+}
+
+/// Scan the partitions of a collection on their own threads.
+///
+/// The threads are started by the first [`ParallelScan::advance`].
+/// The documents of the partitions are returned one partition after another,
+/// so they are in the order of the primary keys as the single-threaded scan.
+pub(crate) struct ParallelScan {
+    col_spec: CollectionSpecification,
+    partition_program: Arc<PartitionProgram>,
+    metrics: Metrics,
+    /// The program of the whole collection displayed by the cursor
+    program: String,
+    pending: Vec<ScanPartition>,
+    cancellation_token: Option<CancellationToken>,
+    max_time: Option<Duration>,
+    spill_options: SpillOptions,
+    receivers: VecDeque<Receiver<Result<Bson>>>,
+    workers: Vec<JoinHandle<()>>,
+    current: Bson,
+    finished: bool,
+}
+
+impl ParallelScan {
+
+    pub(crate) fn new(
+        col_spec: CollectionSpecification,
+        partition_program: PartitionProgram,
+        metrics: Metrics,
+        program: &SubProgram,
+        partitions: Vec<ScanPartition>,
+    ) -> ParallelScan {
+        ParallelScan {
+            col_spec,
+            partition_program: Arc::new(partition_program),
+            metrics,
+            program: program.to_string(),
+            pending: partitions,
+            cancellation_token: None,
+            max_time: None,
+            spill_options: SpillOptions::default(),
+            receivers: VecDeque::new(),
+            workers: Vec::new(),
+            current: Bson::Null,
+            finished: false,
+        }
+    }
+
+    pub(crate) fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.cancellation_token = Some(token);
+    }
+
+    pub(crate) fn cancellation_token(&self) -> Option<&CancellationToken> {
+        self.cancellation_token.as_ref()
+    }
+
+    /// The time limit of each partition.
+    pub(crate) fn set_max_time(&mut self, max_time: Duration) {
+        self.max_time = Some(max_time);
+    }
+
+    /// The spill options of the stages of each partition.
+    pub(crate) fn set_spill_options(&mut self, spill_options: &SpillOptions) {
+        self.spill_options = spill_options.clone();
+    }
+
+    pub(crate) fn partition_count(&self) -> usize {
+        self.pending.len().max(self.workers.len())
+    }
+
+    fn start(&mut self) -> Result<()> {
+        for partition in std::mem::take(&mut self.pending) {
+            let (sender, receiver) = sync_channel(PARTITION_BUFFER_SIZE);
+            let col_spec = self.col_spec.clone();
+            let partition_program = self.partition_program.clone();
+            let metrics = self.metrics.clone();
+            let token = self.cancellation_token.clone();
+            let max_time = self.max_time;
+            let spill_options = self.spill_options.clone();
+            let worker = std::thread::Builder::new()
+                .name("polodb-scan".to_string())
+                .spawn(move || {
+                    let options = PartitionOptions { token, max_time, spill_options };
+                    if let Err(err) = scan_partition(&col_spec, &partition_program, metrics, options, partition, &sender) {
+                        let _ = sender.send(Err(err));
+                    }
+                })?;
+            self.receivers.push_back(receiver);
+            self.workers.push(worker);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn advance(&mut self) -> Result<bool> {
+        if self.finished {
+            return Ok(false);
+        }
+        if !self.pending.is_empty() {
+            self.start()?;
+        }
+        while let Some(receiver) = self.receivers.front() {
+            match receiver.recv() {
+                Ok(Ok(doc)) => {
+                    self.current = doc;
+                    return Ok(true);
+                }
+                Ok(Err(err)) => {
+                    self.finished = true;
+                    return Err(err);
+                }
+                // the partition is finished
+                Err(_) => {
+                    self.receivers.pop_front();
+                }
+            }
+        }
+        self.finished = true;
+        Ok(false)
+    }
+
+    #[inline]
+    pub(crate) fn current(&self) -> &Bson {
+        &self.current
+    }
+
+}
+
+struct PartitionOptions {
+    token: Option<CancellationToken>,
+    max_time: Option<Duration>,
+    spill_options: SpillOptions,
+}
+
+fn scan_partition(
+    col_spec: &CollectionSpecification,
+    partition_program: &PartitionProgram,
+    metrics: Metrics,
+    options: PartitionOptions,
+    partition: ScanPartition,
+    sender: &SyncSender<Result<Bson>>,
+) -> Result<()> {
+    let mut subprogram = partition_program.compile(col_spec, partition.range)?;
+    subprogram.set_spill_options(&options.spill_options);
+    let mut vm = VM::new(partition.txn, subprogram, metrics);
+    if let Some(token) = options.token {
+        vm.set_cancellation_token(token);
+    }
+    if let Some(max_time) = options.max_time {
+        vm.set_max_time(max_time);
+    }
+    loop {
+        vm.execute()?;
+        if vm.state != VmState::HasRow {
+            return Ok(());
+        }
+        // the cursor is dropped
+        if sender.send(Ok(vm.stack_top().clone())).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+impl fmt::Display for ParallelScan {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Parallel scan of {} partitions:\n\n{}", self.partition_count(), self.program)
+    }
+
+}
+
+impl Drop for ParallelScan {
+
+    fn drop(&mut self) {
+        // the workers stop when their receivers are dropped
+        self.receivers.clear();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+
+}
@@ -0,0 +1,208 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The profiler of the slow queries.
+//!
+//! The queries running longer than [`crate::Config::slow_query_threshold`]
+//! are recorded as [`ProfileEntry`] documents in the capped collection
+//! [`PROFILE_COLLECTION`] of their database, which is queried like the other collections.
+//! Only the shape of the filter is recorded, the values are replaced by `1`.
+//! The oldest entries are removed beyond `PROFILE_MAX_ENTRIES`.
+//! The queries of the profile collection itself are not recorded.
+
+use std::sync::{Arc, RwLock, Weak};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use bson::{Bson, DateTime, Document};
+use crate::db::db_inner::{DatabaseInner, NAMESPACE_SEPARATOR};
+use crate::db::qualify_col_name;
+use crate::options::CreateCollectionOptions;
+use crate::results::ProfileEntry;
+use crate::{Collection, CollectionT, Error, Result};
+
+/// The name of the collection of the slow queries in each database.
+pub const PROFILE_COLLECTION: &str = "__profile";
+const PROFILE_MAX_ENTRIES: u64 = 1000;
+
+/// The threshold of a disabled profiler.
+const DISABLED: u64 = u64::MAX;
+
+#[derive(Clone)]
+pub(crate) struct Profiler {
+    inner: Arc<ProfilerInner>,
+}
+
+struct ProfilerInner {
+    /// The database to record the entries in, set when the database is shared
+    db: RwLock<Weak<DatabaseInner>>,
+    /// The threshold in microseconds, `DISABLED` if the profiler is disabled
+    threshold: AtomicU64,
+}
+
+impl Profiler {
+
+    pub(crate) fn new(threshold: Option<Duration>) -> Profiler {
+        Profiler {
+            inner: Arc::new(ProfilerInner {
+                db: RwLock::new(Weak::new()),
+                threshold: AtomicU64::new(threshold_micros(threshold)),
+            }),
+        }
+    }
+
+    pub(crate) fn set_database(&self, db: Weak<DatabaseInner>) {
+        *self.inner.db.write().unwrap() = db;
+    }
+
+    /// Change the threshold of the slow queries, `None` disables the profiler.
+    /// The running queries keep the threshold when they started.
+    pub(crate) fn set_threshold(&self, threshold: Option<Duration>) {
+        self.inner.threshold.store(threshold_micros(threshold), Ordering::SeqCst);
+    }
+
+    /// Start to profile a query with the shape of its filter,
+    /// `None` if the profiler is disabled.
+    pub(crate) fn start(&self, op: &str, col_name: &str, filter_shape: &Document) -> Option<ProfileSpan> {
+        let threshold = self.inner.threshold.load(Ordering::SeqCst);
+        let (namespace, name) = split_namespace(col_name);
+        if threshold == DISABLED || name == PROFILE_COLLECTION {
+            return None;
+        }
+        Some(ProfileSpan {
+            profiler: self.inner.clone(),
+            threshold: Duration::from_micros(threshold),
+            op: op.to_string(),
+            namespace: namespace.map(str::to_string),
+            collection: name.to_string(),
+            filter: filter_shape.clone(),
+            start: Instant::now(),
+        })
+    }
+
+}
+
+fn threshold_micros(threshold: Option<Duration>) -> u64 {
+    match threshold {
+        Some(threshold) => (threshold.as_micros() as u64).min(DISABLED - 1),
+        None => DISABLED,
+    }
+}
+
+/// The named database and the name of a qualified collection name.
+fn split_namespace(col_name: &str) -> (Option<&str>, &str) {
+    match col_name.split_once(NAMESPACE_SEPARATOR) {
+        Some((namespace, name)) => (Some(namespace), name),
+        None => (None, col_name),
+    }
+}
+
+/// A running query, recorded by [`ProfileSpan::finish`] if it's slow.
+pub(crate) struct ProfileSpan {
+    profiler: Arc<ProfilerInner>,
+    threshold: Duration,
+    op: String,
+    namespace: Option<String>,
+    collection: String,
+    filter: Document,
+    start: Instant,
+}
+
+impl ProfileSpan {
+
+    pub(crate) fn finish(self, plan: &str, docs_examined: u64, docs_returned: u64) {
+        let duration = self.start.elapsed();
+        if duration < self.threshold {
+            return;
+        }
+        let entry = ProfileEntry {
+            op: self.op,
+            collection: self.collection,
+            filter: self.filter,
+            plan: plan.to_string(),
+            duration_micros: duration.as_micros() as u64,
+            docs_examined,
+            docs_returned,
+            ts: DateTime::now(),
+        };
+        // the profiler never fails the query
+        let _ = self.profiler.record(self.namespace.as_deref(), &entry);
+    }
+
+}
+
+impl ProfilerInner {
+
+    /// Insert the entry into the profile collection of the database,
+    /// which is created on the first entry.
+    fn record(&self, namespace: Option<&str>, entry: &ProfileEntry) -> Result<()> {
+        let db = self.db.read()?.clone();
+        let inner = match db.upgrade() {
+            Some(inner) => inner,
+            None => return Ok(()),
+        };
+        let name = qualify_col_name(namespace, PROFILE_COLLECTION);
+        let options = CreateCollectionOptions::builder()
+            .capped(true)
+            .max(PROFILE_MAX_ENTRIES)
+            .build();
+        match inner.create_collection_with_options(&name, &options) {
+            Ok(_) | Err(Error::CollectionAlreadyExits(_)) => (),
+            Err(err) => return Err(err),
+        }
+        Collection::<ProfileEntry>::new(db, &name).insert_one(entry)?;
+        Ok(())
+    }
+
+}
+
+/// Replace the values of the filter by `1`, keeping the fields and the operators.
+pub(crate) fn filter_shape(filter: &Document) -> Document {
+    filter
+        .iter()
+        .map(|(key, value)| (key.clone(), value_shape(value)))
+        .collect()
+}
+
+fn value_shape(value: &Bson) -> Bson {
+    match value {
+        Bson::Document(doc) => Bson::Document(filter_shape(doc)),
+        // the branches of `$and`, `$or` and `$nor`, and the stages of a pipeline
+        Bson::Array(arr) if arr.iter().all(|item| matches!(item, Bson::Document(_))) && !arr.is_empty() => {
+            Bson::Array(arr.iter().map(value_shape).collect())
+        }
+        _ => Bson::Int32(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::doc;
+    use super::filter_shape;
+
+    #[test]
+    fn test_filter_shape() {
+        let filter = doc! {
+            "name": "a",
+            "age": { "$gt": 18, "$in": [1, 2, 3] },
+            "$or": [{ "x": 1 }, { "y": { "$exists": true } }],
+        };
+        assert_eq!(filter_shape(&filter), doc! {
+            "name": 1,
+            "age": { "$gt": 1, "$in": 1 },
+            "$or": [{ "x": 1 }, { "y": { "$exists": 1 } }],
+        });
+    }
+
+}
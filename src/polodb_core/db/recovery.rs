@@ -0,0 +1,108 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Find what RocksDB discards when a database is opened after a crash.
+//!
+//! The write-ahead logs are replayed in the point-in-time mode: the replay stops
+//! at the first record which can't be read, e.g. torn by a power loss,
+//! so the database is at the last commit before it. The table files of an interrupted
+//! flush or compaction are not in the manifest, and they are deleted on opening.
+//! The files are scanned before opening to report them.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+use crate::db::inspect;
+use crate::results::RecoverySummary;
+use crate::Result;
+
+/// Scan the files of the database in `path` before opening it.
+pub(crate) fn scan(path: &Path, cold_path: Option<&Path>) -> Result<RecoverySummary> {
+    let mut summary = RecoverySummary::default();
+    // a new database
+    if !path.join("CURRENT").exists() {
+        return Ok(summary);
+    }
+
+    let manifest = inspect::inspect_manifest(path)?;
+    summary.errors.extend(manifest.errors.iter().map(|err| format!("{}: {}", manifest.file, err)));
+
+    // the logs older than the log number of the manifest are flushed to the table files
+    let min_log_number = manifest.edits.iter()
+        .filter_map(|edit| edit.log_number)
+        .max()
+        .unwrap_or(0);
+    let mut stopped = false;
+    for (number, name) in inspect::wal_file_names(path, &[""])? {
+        if number < min_log_number {
+            continue;
+        }
+        let file = File::open(path.join(&name))?;
+        let log = inspect::read_log_records(BufReader::new(file), false)?;
+
+        let replayed_end = if stopped {
+            0
+        } else {
+            match log.first_error() {
+                Some(first_error) => {
+                    stopped = true;
+                    summary.errors.extend(log.error_messages().into_iter().map(|err| format!("{}: {}", name, err)));
+                    log.records.iter()
+                        .map(|(_, end, _)| *end)
+                        .filter(|end| *end <= first_error)
+                        .max()
+                        .unwrap_or(0)
+                }
+                None => continue,
+            }
+        };
+
+        summary.discarded_commits += log.records.iter()
+            .filter(|(_, end, _)| *end > replayed_end)
+            .count() as u64;
+        summary.discarded_wal_bytes += log.used_size.saturating_sub(replayed_end);
+        summary.torn_wal_files.push(name);
+    }
+
+    let mut live_files = HashSet::new();
+    for edit in &manifest.edits {
+        for file in &edit.deleted_files {
+            live_files.remove(&file.number);
+        }
+        for file in &edit.new_files {
+            live_files.insert(file.number);
+        }
+    }
+    for dir in std::iter::once(path).chain(cold_path) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries {
+            let file_name = entry?.file_name().to_string_lossy().to_string();
+            let number = match file_name.strip_suffix(".sst").and_then(|stem| stem.parse::<u64>().ok()) {
+                Some(number) => number,
+                None => continue,
+            };
+            if !live_files.contains(&number) {
+                summary.orphan_table_files.push(file_name);
+            }
+        }
+    }
+    summary.orphan_table_files.sort();
+
+    Ok(summary)
+}
@@ -0,0 +1,125 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ffi::CString;
+use std::path::Path;
+use std::ptr::null_mut;
+use libc::c_char;
+use polodb_librocksdb_sys as ffi;
+use super::db::Result;
+use crate::results::BackupInfo;
+
+macro_rules! check_err {
+    ($err:expr) => {
+        if !$err.is_null() {
+            let c_str = std::ffi::CStr::from_ptr($err);
+
+            // Convert the &CStr to a &str
+            let str_slice = c_str.to_str().expect("C string is not valid UTF-8");
+
+            // Convert the &str to a String and return
+            return Err(crate::Error::from_rocksdb(str_slice.to_owned()))
+        }
+    };
+}
+
+/// The backups in a directory share the table files,
+/// so a new backup only copies the files created since the previous one.
+pub(crate) struct RocksDBBackupEngine {
+    inner: *mut ffi::rocksdb_backup_engine_t,
+}
+
+impl RocksDBBackupEngine {
+
+    pub(crate) fn open(backup_dir: &Path) -> Result<RocksDBBackupEngine> {
+        let path_c = CString::new(backup_dir.to_str().unwrap()).unwrap();
+        unsafe {
+            // only the env of the options is used
+            let options = ffi::rocksdb_options_create();
+            let mut err: *mut c_char = null_mut();
+            let inner = ffi::rocksdb_backup_engine_open(options, path_c.as_ptr(), &mut err);
+            ffi::rocksdb_options_destroy(options);
+            check_err!(err);
+            Ok(RocksDBBackupEngine {
+                inner,
+            })
+        }
+    }
+
+    pub(crate) fn create_new_backup(&self, txn_db: *mut ffi::rocksdb_transactiondb_t) -> Result<()> {
+        unsafe {
+            let base_db = ffi::rocksdb_transactiondb_get_base_db(txn_db);
+            let mut err: *mut c_char = null_mut();
+            ffi::rocksdb_backup_engine_create_new_backup_flush(self.inner, base_db, 1, &mut err);
+            ffi::rocksdb_transactiondb_close_base_db(base_db);
+            check_err!(err);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn backup_infos(&self) -> Vec<BackupInfo> {
+        unsafe {
+            let info = ffi::rocksdb_backup_engine_get_backup_info(self.inner);
+            let count = ffi::rocksdb_backup_engine_info_count(info);
+            let mut result = Vec::with_capacity(count as usize);
+            for index in 0..count {
+                result.push(BackupInfo {
+                    backup_id: ffi::rocksdb_backup_engine_info_backup_id(info, index),
+                    timestamp: ffi::rocksdb_backup_engine_info_timestamp(info, index),
+                    size: ffi::rocksdb_backup_engine_info_size(info, index),
+                    number_files: ffi::rocksdb_backup_engine_info_number_files(info, index),
+                });
+            }
+            ffi::rocksdb_backup_engine_info_destroy(info);
+            result
+        }
+    }
+
+    pub(crate) fn restore(&self, backup_id: Option<u32>, db_dir: &Path) -> Result<()> {
+        let path_c = CString::new(db_dir.to_str().unwrap()).unwrap();
+        unsafe {
+            let restore_options = ffi::rocksdb_restore_options_create();
+            let mut err: *mut c_char = null_mut();
+            match backup_id {
+                Some(backup_id) => ffi::rocksdb_backup_engine_restore_db_from_backup(
+                    self.inner,
+                    path_c.as_ptr(),
+                    path_c.as_ptr(),
+                    restore_options,
+                    backup_id,
+                    &mut err,
+                ),
+                None => ffi::rocksdb_backup_engine_restore_db_from_latest_backup(
+                    self.inner,
+                    path_c.as_ptr(),
+                    path_c.as_ptr(),
+                    restore_options,
+                    &mut err,
+                ),
+            }
+            ffi::rocksdb_restore_options_destroy(restore_options);
+            check_err!(err);
+        }
+        Ok(())
+    }
+
+}
+
+impl Drop for RocksDBBackupEngine {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::rocksdb_backup_engine_close(self.inner);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use polodb_librocksdb_sys as ffi;
+
+/// The default capacity of the block cache, the same as RocksDB.
+pub(crate) const DEFAULT_BLOCK_CACHE_SIZE: usize = 32 * 1024 * 1024;
+
+/// A size-bounded cache of the uncompressed blocks read from the table files.
+///
+/// A cache can be shared by multiple databases by cloning it,
+/// so they are bounded by the same budget.
+#[derive(Clone)]
+pub struct BlockCache {
+    inner: Arc<BlockCacheInner>,
+}
+
+struct BlockCacheInner {
+    inner: *mut ffi::rocksdb_cache_t,
+}
+
+unsafe impl Send for BlockCacheInner {}
+unsafe impl Sync for BlockCacheInner {}
+
+impl BlockCache {
+
+    /// A cache evicting the least recently used blocks.
+    pub fn lru(capacity: usize) -> BlockCache {
+        let inner = unsafe { ffi::rocksdb_cache_create_lru(capacity) };
+        assert!(!inner.is_null(), "rocksdb_cache_create_lru failed");
+        BlockCache::from_raw(inner)
+    }
+
+    /// A CLOCK cache, which scales better than [`BlockCache::lru`]
+    /// when the cache is read by many threads.
+    pub fn clock(capacity: usize) -> BlockCache {
+        // the size of the entries is estimated automatically
+        let inner = unsafe { ffi::rocksdb_cache_create_hyper_clock(capacity, 0) };
+        assert!(!inner.is_null(), "rocksdb_cache_create_hyper_clock failed");
+        BlockCache::from_raw(inner)
+    }
+
+    fn from_raw(inner: *mut ffi::rocksdb_cache_t) -> BlockCache {
+        BlockCache {
+            inner: Arc::new(BlockCacheInner { inner }),
+        }
+    }
+
+    pub(crate) fn get(&self) -> *mut ffi::rocksdb_cache_t {
+        self.inner.inner
+    }
+
+    /// The budget of the cache in bytes.
+    pub fn capacity(&self) -> usize {
+        unsafe { ffi::rocksdb_cache_get_capacity(self.get()) }
+    }
+
+    /// Change the budget of the cache. The blocks are evicted
+    /// if the cache is larger than the new capacity.
+    pub fn set_capacity(&self, capacity: usize) {
+        unsafe { ffi::rocksdb_cache_set_capacity(self.get(), capacity) }
+    }
+
+    /// The bytes used by the blocks in the cache.
+    pub fn usage(&self) -> usize {
+        unsafe { ffi::rocksdb_cache_get_usage(self.get()) }
+    }
+
+    /// The bytes used by the blocks which are being read.
+    pub fn pinned_usage(&self) -> usize {
+        unsafe { ffi::rocksdb_cache_get_pinned_usage(self.get()) }
+    }
+
+    /// The number of blocks in the cache.
+    pub fn entries(&self) -> usize {
+        unsafe { ffi::rocksdb_cache_get_occupancy_count(self.get()) }
+    }
+
+}
+
+impl Default for BlockCache {
+
+    fn default() -> Self {
+        BlockCache::lru(DEFAULT_BLOCK_CACHE_SIZE)
+    }
+
+}
+
+impl Drop for BlockCacheInner {
+    fn drop(&mut self) {
+        // the opened databases keep their own references
+        unsafe { ffi::rocksdb_cache_destroy(self.inner) }
+    }
+}
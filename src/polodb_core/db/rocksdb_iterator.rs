@@ -37,10 +37,19 @@ impl RocksDBIterator {
         self.inner.seek_to_first()
     }
 
+    pub fn seek_to_last(&self) {
+        self.inner.seek_to_last()
+    }
+
     pub fn seek(&self, key: &[u8]) {
         self.inner.seek(key)
     }
 
+    /// Seek to the last key which is not greater than `key`.
+    pub fn seek_for_prev(&self, key: &[u8]) {
+        self.inner.seek_for_prev(key)
+    }
+
     pub fn valid(&self) -> bool {
         self.inner.valid()
     }
@@ -49,12 +58,10 @@ impl RocksDBIterator {
         self.inner.next()
     }
 
-    #[allow(dead_code)]
     pub fn prev(&self) {
         self.inner.prev()
     }
 
-    #[allow(dead_code)]
     pub fn error(&self) -> Result<()> {
         self.inner.error()
     }
@@ -86,7 +93,11 @@ impl RocksDBIteratorInner {
         unsafe {
             let txn_ptr = (*txn_inner).inner;
             let read_options = &(*txn_inner).read_options;
-            let iter = ffi::rocksdb_transaction_create_iterator(txn_ptr, read_options.get());
+            let iter = if txn_ptr.is_null() {
+                ffi::rocksdb_create_iterator((*(*txn_inner).db_inner).read_only_db, read_options.get())
+            } else {
+                ffi::rocksdb_transaction_create_iterator(txn_ptr, read_options.get())
+            };
             _ = (*txn_inner).iter_count.fetch_add(1, Ordering::SeqCst);
             RocksDBIteratorInner {
                 inner: iter,
@@ -101,12 +112,24 @@ impl RocksDBIteratorInner {
         }
     }
 
+    pub fn seek_to_last(&self) {
+        unsafe {
+            ffi::rocksdb_iter_seek_to_last(self.inner);
+        }
+    }
+
     pub fn seek(&self, key: &[u8]) {
         unsafe {
             ffi::rocksdb_iter_seek(self.inner, key.as_ptr() as *const i8, key.len());
         }
     }
 
+    pub fn seek_for_prev(&self, key: &[u8]) {
+        unsafe {
+            ffi::rocksdb_iter_seek_for_prev(self.inner, key.as_ptr() as *const i8, key.len());
+        }
+    }
+
     pub fn valid(&self) -> bool {
         unsafe {
             ffi::rocksdb_iter_valid(self.inner) != 0
@@ -136,7 +159,7 @@ impl RocksDBIteratorInner {
                 let str_slice = c_str.to_str().expect("C string is not valid UTF-8");
 
                 // Convert the &str to a String and return
-                return Err(crate::Error::RocksDbErr(str_slice.to_owned()))
+                return Err(crate::Error::from_rocksdb(str_slice.to_owned()))
             }
             Ok(())
         }
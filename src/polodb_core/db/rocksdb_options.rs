@@ -74,6 +74,12 @@ impl RocksDBWriteOptions {
         }
     }
 
+    pub(crate) fn set_no_slowdown(&self, no_slowdown: bool) {
+        unsafe {
+            ffi::rocksdb_writeoptions_set_no_slowdown(self.inner, no_slowdown as u8)
+        }
+    }
+
 }
 
 impl Drop for RocksDBWriteOptions {
@@ -98,6 +104,10 @@ impl RocksDBReadOptions {
         self.inner
     }
 
+    pub(crate) fn set_snapshot(&self, snapshot: *const ffi::rocksdb_snapshot_t) {
+        unsafe { ffi::rocksdb_readoptions_set_snapshot(self.inner, snapshot) }
+    }
+
 }
 
 impl Drop for RocksDBReadOptions {
@@ -130,3 +140,75 @@ impl Drop for RocksDBTransactionOptions {
     }
 }
 
+pub(crate) struct RocksDBFlushOptions {
+    inner: *mut ffi::rocksdb_flushoptions_t,
+}
+
+impl RocksDBFlushOptions {
+
+    pub(crate) fn new() -> RocksDBFlushOptions {
+        let inner = unsafe { ffi::rocksdb_flushoptions_create() };
+        assert!(!inner.is_null(), "rocksdb_flushoptions_create failed");
+        RocksDBFlushOptions { inner }
+    }
+
+    pub(crate) fn get(&self) -> *mut ffi::rocksdb_flushoptions_t {
+        self.inner
+    }
+
+    pub(crate) fn set_wait(&self, wait: bool) {
+        unsafe {
+            ffi::rocksdb_flushoptions_set_wait(self.inner, if wait {
+                1
+            } else {
+                0
+            })
+        }
+    }
+
+}
+
+impl Drop for RocksDBFlushOptions {
+    fn drop(&mut self) {
+        unsafe { ffi::rocksdb_flushoptions_destroy(self.inner) }
+    }
+}
+
+pub(crate) struct RocksDBCompactOptions {
+    inner: *mut ffi::rocksdb_compactoptions_t,
+}
+
+impl RocksDBCompactOptions {
+
+    pub(crate) fn new() -> RocksDBCompactOptions {
+        let inner = unsafe { ffi::rocksdb_compactoptions_create() };
+        assert!(!inner.is_null(), "rocksdb_compactoptions_create failed");
+        RocksDBCompactOptions { inner }
+    }
+
+    pub(crate) fn get(&self) -> *mut ffi::rocksdb_compactoptions_t {
+        self.inner
+    }
+
+    /// Rewrite the files of the bottommost level as well,
+    /// which is `BottommostLevelCompaction::kForce` in RocksDB.
+    pub(crate) fn set_force_bottommost_level(&self) {
+        unsafe {
+            ffi::rocksdb_compactoptions_set_bottommost_level_compaction(self.inner, 2)
+        }
+    }
+
+    /// Write the output files into the `path_id`th db path.
+    pub(crate) fn set_target_path_id(&self, path_id: u32) {
+        unsafe {
+            ffi::polodb_compactoptions_set_target_path_id(self.inner, path_id)
+        }
+    }
+
+}
+
+impl Drop for RocksDBCompactOptions {
+    fn drop(&mut self) {
+        unsafe { ffi::rocksdb_compactoptions_destroy(self.inner) }
+    }
+}
@@ -0,0 +1,53 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use polodb_librocksdb_sys as ffi;
+
+/// The counters of the internal work of the reads on the current thread.
+/// Counting is enabled while the context is alive.
+pub(crate) struct RocksDBPerfContext {
+    inner: *mut ffi::rocksdb_perfcontext_t,
+}
+
+impl RocksDBPerfContext {
+
+    pub(crate) fn start() -> RocksDBPerfContext {
+        let inner = unsafe {
+            ffi::rocksdb_set_perf_level(ffi::rocksdb_enable_count as i32);
+            ffi::rocksdb_perfcontext_create()
+        };
+        assert!(!inner.is_null(), "rocksdb_perfcontext_create failed");
+        unsafe { ffi::rocksdb_perfcontext_reset(inner) };
+        RocksDBPerfContext { inner }
+    }
+
+    /// The number of deleted keys skipped by the iterators,
+    /// i.e. the tombstones which are not compacted yet.
+    pub(crate) fn internal_delete_skipped_count(&self) -> u64 {
+        unsafe {
+            ffi::rocksdb_perfcontext_metric(self.inner, ffi::rocksdb_internal_delete_skipped_count as i32)
+        }
+    }
+
+}
+
+impl Drop for RocksDBPerfContext {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::rocksdb_perfcontext_destroy(self.inner);
+            ffi::rocksdb_set_perf_level(ffi::rocksdb_disable as i32);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use polodb_librocksdb_sys as ffi;
+use crate::db::rocksdb_wrapper::{RocksDBWrapper, RocksDBWrapperInner};
+
+/// A consistent view of the database at the time it's created.
+/// The transactions reading with the snapshot don't see the later writes.
+pub(crate) struct RocksDBSnapshot {
+    inner: *const ffi::rocksdb_snapshot_t,
+    db_inner: *mut RocksDBWrapperInner,
+    // keep the database open until the snapshot is released
+    _db: RocksDBWrapper,
+}
+
+unsafe impl Send for RocksDBSnapshot {}
+unsafe impl Sync for RocksDBSnapshot {}
+
+impl RocksDBSnapshot {
+
+    pub(crate) fn new(db: RocksDBWrapper, db_inner: *mut RocksDBWrapperInner) -> RocksDBSnapshot {
+        let inner = unsafe {
+            if (*db_inner).read_only_db.is_null() {
+                ffi::rocksdb_transactiondb_create_snapshot((*db_inner).inner)
+            } else {
+                ffi::rocksdb_create_snapshot((*db_inner).read_only_db)
+            }
+        };
+        RocksDBSnapshot {
+            inner,
+            db_inner,
+            _db: db,
+        }
+    }
+
+    pub(crate) fn get(&self) -> *const ffi::rocksdb_snapshot_t {
+        self.inner
+    }
+
+}
+
+impl Drop for RocksDBSnapshot {
+
+    fn drop(&mut self) {
+        unsafe {
+            if (*self.db_inner).read_only_db.is_null() {
+                ffi::rocksdb_transactiondb_release_snapshot((*self.db_inner).inner, self.inner);
+            } else {
+                ffi::rocksdb_release_snapshot((*self.db_inner).read_only_db, self.inner);
+            }
+        }
+    }
+
+}
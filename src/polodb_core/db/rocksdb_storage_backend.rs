@@ -0,0 +1,243 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ffi::CStr;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::Arc;
+use libc::{c_char, c_int, c_void};
+use polodb_librocksdb_sys as ffi;
+use crate::storage_backend::{OpenMode, StorageBackend, StorageFile};
+
+type Backend = Arc<dyn StorageBackend>;
+type BackendFile = Box<dyn StorageFile>;
+
+/// Create a RocksDB env which forwards the file operations to the backend.
+/// The env must be destroyed by `rocksdb_env_destroy`.
+pub(crate) fn create_storage_backend_env(backend: Backend) -> *mut ffi::rocksdb_env_t {
+    let state = Box::into_raw(Box::new(backend)) as *mut c_void;
+    let callbacks = ffi::polodb_storage_backend_t {
+        state,
+        destructor: Some(destroy_backend),
+        open: Some(open_file),
+        close: Some(close_file),
+        read_at: Some(read_at),
+        write_at: Some(write_at),
+        sync: Some(sync),
+        size: Some(size),
+        truncate: Some(truncate),
+        delete_path: Some(delete_path),
+        rename: Some(rename),
+        exists: Some(exists),
+        list: Some(list),
+        create_dir: Some(create_dir),
+        is_dir: Some(is_dir),
+    };
+    unsafe {
+        ffi::polodb_create_storage_backend_env(&callbacks)
+    }
+}
+
+unsafe fn backend<'a>(state: *mut c_void) -> &'a Backend {
+    &*(state as *const Backend)
+}
+
+unsafe fn file<'a>(file: *mut c_void) -> &'a BackendFile {
+    &*(file as *const BackendFile)
+}
+
+unsafe fn path<'a>(path: *const c_char) -> io::Result<&'a Path> {
+    CStr::from_ptr(path)
+        .to_str()
+        .map(Path::new)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path is not valid UTF-8"))
+}
+
+/// Copy a slice into a buffer allocated by `malloc`,
+/// so it can be freed by the env. It's null if the allocation fails.
+unsafe fn malloc_copy(data: &[u8], nul_terminated: bool) -> *mut c_char {
+    let len = if nul_terminated { data.len() + 1 } else { data.len() };
+    let buf = libc::malloc(len.max(1)) as *mut u8;
+    if buf.is_null() {
+        return std::ptr::null_mut();
+    }
+    std::ptr::copy_nonoverlapping(data.as_ptr(), buf, data.len());
+    if nul_terminated {
+        *buf.add(data.len()) = 0;
+    }
+    buf as *mut c_char
+}
+
+unsafe fn to_status(result: io::Result<()>, errptr: *mut *mut c_char) -> c_int {
+    match result {
+        Ok(()) => ffi::POLODB_STORAGE_OK as c_int,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => ffi::POLODB_STORAGE_NOT_FOUND as c_int,
+        Err(err) => {
+            // the env reports an error without a message if it's null
+            *errptr = malloc_copy(err.to_string().as_bytes(), true);
+            ffi::POLODB_STORAGE_IO_ERROR as c_int
+        }
+    }
+}
+
+/// Run a callback, the panics of the backend can't unwind into the env,
+/// so they are returned as errors.
+unsafe fn guard<F: FnOnce() -> io::Result<()>>(errptr: *mut *mut c_char, f: F) -> c_int {
+    let result = panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::Other, "the storage backend panicked")));
+    to_status(result, errptr)
+}
+
+unsafe extern "C" fn destroy_backend(state: *mut c_void) {
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        drop(Box::from_raw(state as *mut Backend));
+    }));
+}
+
+unsafe extern "C" fn open_file(
+    state: *mut c_void,
+    file_path: *const c_char,
+    mode: c_int,
+    result: *mut *mut c_void,
+    errptr: *mut *mut c_char,
+) -> c_int {
+    let mode = match mode as u32 {
+        ffi::POLODB_STORAGE_OPEN_TRUNCATE => OpenMode::Truncate,
+        ffi::POLODB_STORAGE_OPEN_APPEND => OpenMode::Append,
+        _ => OpenMode::Read,
+    };
+    guard(errptr, || {
+        let file = backend(state).open(path(file_path)?, mode)?;
+        *result = Box::into_raw(Box::new(file)) as *mut c_void;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn close_file(_state: *mut c_void, file: *mut c_void) {
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        drop(Box::from_raw(file as *mut BackendFile));
+    }));
+}
+
+unsafe extern "C" fn read_at(
+    _state: *mut c_void,
+    f: *mut c_void,
+    offset: u64,
+    buf: *mut c_char,
+    len: usize,
+    read: *mut usize,
+    errptr: *mut *mut c_char,
+) -> c_int {
+    let buf = std::slice::from_raw_parts_mut(buf as *mut u8, len);
+    guard(errptr, || {
+        *read = file(f).read_at(offset, buf)?;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn write_at(
+    _state: *mut c_void,
+    f: *mut c_void,
+    offset: u64,
+    data: *const c_char,
+    len: usize,
+    errptr: *mut *mut c_char,
+) -> c_int {
+    let data = std::slice::from_raw_parts(data as *const u8, len);
+    guard(errptr, || file(f).write_at(offset, data))
+}
+
+unsafe extern "C" fn sync(_state: *mut c_void, f: *mut c_void, errptr: *mut *mut c_char) -> c_int {
+    guard(errptr, || file(f).sync())
+}
+
+unsafe extern "C" fn size(
+    _state: *mut c_void,
+    f: *mut c_void,
+    result: *mut u64,
+    errptr: *mut *mut c_char,
+) -> c_int {
+    guard(errptr, || {
+        *result = file(f).size()?;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn truncate(
+    _state: *mut c_void,
+    f: *mut c_void,
+    size: u64,
+    errptr: *mut *mut c_char,
+) -> c_int {
+    guard(errptr, || file(f).truncate(size))
+}
+
+unsafe extern "C" fn delete_path(state: *mut c_void, p: *const c_char, errptr: *mut *mut c_char) -> c_int {
+    guard(errptr, || backend(state).delete(path(p)?))
+}
+
+unsafe extern "C" fn rename(
+    state: *mut c_void,
+    from: *const c_char,
+    to: *const c_char,
+    errptr: *mut *mut c_char,
+) -> c_int {
+    guard(errptr, || backend(state).rename(path(from)?, path(to)?))
+}
+
+unsafe extern "C" fn exists(state: *mut c_void, p: *const c_char, errptr: *mut *mut c_char) -> c_int {
+    guard(errptr, || {
+        if backend(state).exists(path(p)?)? {
+            Ok(())
+        } else {
+            Err(io::ErrorKind::NotFound.into())
+        }
+    })
+}
+
+unsafe extern "C" fn list(
+    state: *mut c_void,
+    dir: *const c_char,
+    names: *mut *mut c_char,
+    names_len: *mut usize,
+    errptr: *mut *mut c_char,
+) -> c_int {
+    guard(errptr, || {
+        let joined = backend(state).list(path(dir)?)?.join("\0");
+        let copied = malloc_copy(joined.as_bytes(), false);
+        if copied.is_null() {
+            return Err(io::ErrorKind::OutOfMemory.into());
+        }
+        *names = copied;
+        *names_len = joined.len();
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn create_dir(state: *mut c_void, dir: *const c_char, errptr: *mut *mut c_char) -> c_int {
+    guard(errptr, || backend(state).create_dir(path(dir)?))
+}
+
+unsafe extern "C" fn is_dir(
+    state: *mut c_void,
+    p: *const c_char,
+    result: *mut c_int,
+    errptr: *mut *mut c_char,
+) -> c_int {
+    guard(errptr, || {
+        *result = backend(state).is_dir(path(p)?)? as c_int;
+        Ok(())
+    })
+}
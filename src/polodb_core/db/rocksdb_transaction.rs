@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashSet;
 use std::ops::DerefMut;
 use std::ptr;
 use std::ptr::null_mut;
@@ -19,9 +20,12 @@ use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicU64, Ordering};
 use libc::c_char;
 use polodb_librocksdb_sys as ffi;
+use crate::db::rocksdb_wal::encode_pre_image;
 use crate::db::rocksdb_options::{RocksDBReadOptions, RocksDBTransactionOptions, RocksDBWriteOptions};
 use crate::db::rocksdb_wrapper::RocksDBWrapperInner;
-use crate::db::RocksDBIterator;
+use crate::db::{RocksDBIterator, RocksDBSnapshot};
+use crate::sync::{self, DocumentVersion, SyncWrites};
+use crate::{KeyOrdering, Tokenizer};
 use super::db::Result;
 
 macro_rules! check_err {
@@ -33,7 +37,7 @@ macro_rules! check_err {
             let str_slice = c_str.to_str().expect("C string is not valid UTF-8");
 
             // Convert the &str to a String and return
-            return Err(crate::Error::RocksDbErr(str_slice.to_owned()))
+            return Err(crate::Error::from_rocksdb(str_slice.to_owned()))
         }
     };
 }
@@ -45,8 +49,8 @@ pub(crate) struct RocksDBTransaction {
 
 impl RocksDBTransaction {
 
-    pub(crate) fn new(db_inner: *mut RocksDBWrapperInner) -> Result<RocksDBTransaction>  {
-        let inner = RocksDBTransactionInner::new(db_inner)?;
+    pub(crate) fn new(db_inner: *mut RocksDBWrapperInner, sync: bool, no_slowdown: bool) -> Result<RocksDBTransaction>  {
+        let inner = RocksDBTransactionInner::new(db_inner, sync, no_slowdown)?;
         Ok(RocksDBTransaction {
             inner: Arc::new(Mutex::new(inner)),
         })
@@ -62,12 +66,23 @@ impl RocksDBTransaction {
         inner.set(key, value)
     }
 
-    #[allow(dead_code)]
     pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
         let inner = self.inner.lock().unwrap();
         inner.get(key)
     }
 
+    pub fn multi_get(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>> {
+        let inner = self.inner.lock().unwrap();
+        inner.multi_get(keys)
+    }
+
+    /// Read the key and lock it until the transaction ends,
+    /// so the read-modify-write of the key is not interleaved with other transactions.
+    pub fn get_for_update(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let inner = self.inner.lock().unwrap();
+        inner.get_for_update(key)
+    }
+
     pub fn delete(&self, key: &[u8]) -> Result<()> {
         let inner = self.inner.lock().unwrap();
         inner.delete(key)
@@ -78,20 +93,65 @@ impl RocksDBTransaction {
         inner.rollback()
     }
 
+    /// Log the change of the document with `version` instead of a new local version.
+    pub fn set_sync_version(&self, key: &[u8], version: DocumentVersion) -> Result<()> {
+        let inner = self.inner.lock().unwrap();
+        inner.set_sync_version(key, version)
+    }
+
     pub fn commit(&self) -> Result<()> {
         let inner = self.inner.lock().unwrap();
         inner.commit()
     }
 
+    /// Read the keys as of the snapshot, which is kept until the transaction is dropped.
+    pub fn set_snapshot(&self, snapshot: Arc<RocksDBSnapshot>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.read_options.set_snapshot(snapshot.get());
+        inner.snapshot = Some(snapshot);
+    }
+
+    /// Commit the transaction without the faults of the fault injector, and don't count it.
+    #[cfg(feature = "fault-injection")]
+    pub fn skip_faults(&self) {
+        let inner = self.inner.lock().unwrap();
+        inner.skip_faults.store(true, Ordering::SeqCst);
+    }
+
+    /// The tokenizer registered by [`crate::ConfigBuilder::set_tokenizer`] with the name.
+    pub fn tokenizer(&self, name: &str) -> Option<Arc<dyn Tokenizer>> {
+        let inner = self.inner.lock().unwrap();
+        unsafe { (*inner.db_inner).tokenizers.get(name).cloned() }
+    }
+
+    /// The ordering registered by [`crate::ConfigBuilder::set_key_ordering`] with the name.
+    pub fn key_ordering(&self, name: &str) -> Option<Arc<dyn KeyOrdering>> {
+        let inner = self.inner.lock().unwrap();
+        unsafe { (*inner.db_inner).key_orderings.get(name).cloned() }
+    }
+
 }
 
 pub(crate) struct RocksDBTransactionInner {
     pub(crate) read_options: RocksDBReadOptions,
     _write_options: RocksDBWriteOptions,
     _txn_options: RocksDBTransactionOptions,
+    /// Null if the database is opened read-only, the reads go to the db directly
     pub(crate) inner: *mut ffi::rocksdb_transaction_t,
-    db_inner: *mut RocksDBWrapperInner,
+    pub(crate) db_inner: *mut RocksDBWrapperInner,
     pub(crate) iter_count: AtomicU64,
+    /// The snapshot of the reads, released after the transaction
+    snapshot: Option<Arc<RocksDBSnapshot>>,
+    /// The documents written by the transaction, if the changes are tracked
+    sync_writes: Option<Mutex<SyncWrites>>,
+    /// The documents whose pre-images are logged, if the pre-images are enabled
+    pre_image_keys: Option<Mutex<HashSet<Vec<u8>>>>,
+    // the writes are recorded only if a fault injector is attached
+    #[cfg(feature = "fault-injection")]
+    recorded_writes: Mutex<Vec<crate::WalOperation>>,
+    // set if the commit is not seen by the fault injector
+    #[cfg(feature = "fault-injection")]
+    skip_faults: std::sync::atomic::AtomicBool,
 }
 
 unsafe impl Send for RocksDBTransactionInner {}
@@ -99,19 +159,24 @@ unsafe impl Sync for RocksDBTransactionInner {}
 
 impl RocksDBTransactionInner {
 
-    pub(crate) fn new(db_inner: *mut RocksDBWrapperInner) -> Result<RocksDBTransactionInner>  {
+    pub(crate) fn new(db_inner: *mut RocksDBWrapperInner, sync: bool, no_slowdown: bool) -> Result<RocksDBTransactionInner>  {
         unsafe {
             let read_options = RocksDBReadOptions::new();
             let write_options = RocksDBWriteOptions::new();
-            write_options.set_sync(true);
+            write_options.set_sync(sync);
+            write_options.set_no_slowdown(no_slowdown);
             let txn_options = RocksDBTransactionOptions::new();
             _ = (*db_inner).txn_count.fetch_add(1, Ordering::SeqCst);
-            let inner = ffi::rocksdb_transaction_begin(
-                (*db_inner).inner,
-                write_options.get(),
-                txn_options.get(),
-                null_mut(),
-            );
+            let inner = if (*db_inner).inner.is_null() {
+                null_mut()
+            } else {
+                ffi::rocksdb_transaction_begin(
+                    (*db_inner).inner,
+                    write_options.get(),
+                    txn_options.get(),
+                    null_mut(),
+                )
+            };
 
             Ok(RocksDBTransactionInner {
                 read_options,
@@ -120,11 +185,21 @@ impl RocksDBTransactionInner {
                 inner,
                 db_inner,
                 iter_count: AtomicU64::new(0),
+                snapshot: None,
+                sync_writes: (*db_inner).sync_tracker.as_ref().map(|_| Mutex::new(SyncWrites::default())),
+                pre_image_keys: if (*db_inner).change_pre_images { Some(Mutex::new(HashSet::new())) } else { None },
+                #[cfg(feature = "fault-injection")]
+                recorded_writes: Mutex::new(Vec::new()),
+                #[cfg(feature = "fault-injection")]
+                skip_faults: std::sync::atomic::AtomicBool::new(false),
             })
         }
     }
 
     pub fn set(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.check_writable()?;
+        self.track_sync_write(key)?;
+        self.log_pre_image(key)?;
         unsafe {
             let mut err: *mut c_char = ptr::null_mut();
 
@@ -138,20 +213,120 @@ impl RocksDBTransactionInner {
             );
 
             check_err!(err);
-            Ok(())
         }
+        #[cfg(feature = "fault-injection")]
+        self.record_write(crate::WalOperation::Put {
+            key: key.to_vec(),
+            value: value.to_vec(),
+        });
+        Ok(())
     }
 
     pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
         unsafe {
             let mut err: *mut c_char = ptr::null_mut();
             let mut value_len: usize = 0;
-            let value = ffi::rocksdb_transaction_get(
+            let value = if self.inner.is_null() {
+                ffi::rocksdb_get(
+                    (*self.db_inner).read_only_db,
+                    self.read_options.get(),
+                    key.as_ptr() as *const i8,
+                    key.len(),
+                    &mut value_len,
+                    &mut err,
+                )
+            } else {
+                ffi::rocksdb_transaction_get(
+                    self.inner,
+                    self.read_options.get(),
+                    key.as_ptr() as *const i8,
+                    key.len(),
+                    &mut value_len,
+                    &mut err,
+                )
+            };
+
+            check_err!(err);
+
+            if value.is_null() {
+                return Ok(None);
+            }
+
+            let result = std::slice::from_raw_parts(value as *const u8, value_len).to_vec();
+            ffi::rocksdb_free(value as *mut libc::c_void);
+            Ok(Some(result))
+        }
+    }
+
+    /// Read the values of `keys` in one batch, the sorted keys are read faster.
+    pub fn multi_get(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>> {
+        let keys_list: Vec<*const c_char> = keys.iter().map(|key| key.as_ptr() as *const c_char).collect();
+        let keys_list_sizes: Vec<usize> = keys.iter().map(Vec::len).collect();
+        let mut values_list: Vec<*mut c_char> = vec![null_mut(); keys.len()];
+        let mut values_list_sizes: Vec<usize> = vec![0; keys.len()];
+        let mut errs: Vec<*mut c_char> = vec![null_mut(); keys.len()];
+        unsafe {
+            if self.inner.is_null() {
+                ffi::rocksdb_multi_get(
+                    (*self.db_inner).read_only_db,
+                    self.read_options.get(),
+                    keys.len(),
+                    keys_list.as_ptr(),
+                    keys_list_sizes.as_ptr(),
+                    values_list.as_mut_ptr(),
+                    values_list_sizes.as_mut_ptr(),
+                    errs.as_mut_ptr(),
+                );
+            } else {
+                ffi::rocksdb_transaction_multi_get(
+                    self.inner,
+                    self.read_options.get(),
+                    keys.len(),
+                    keys_list.as_ptr(),
+                    keys_list_sizes.as_ptr(),
+                    values_list.as_mut_ptr(),
+                    values_list_sizes.as_mut_ptr(),
+                    errs.as_mut_ptr(),
+                );
+            }
+
+            let mut result = Vec::with_capacity(keys.len());
+            let mut first_err = None;
+            for ((value, value_len), err) in values_list.into_iter().zip(values_list_sizes).zip(errs) {
+                if !err.is_null() {
+                    if first_err.is_none() {
+                        let message = std::ffi::CStr::from_ptr(err).to_string_lossy().into_owned();
+                        first_err = Some(crate::Error::from_rocksdb(message));
+                    }
+                    ffi::rocksdb_free(err as *mut libc::c_void);
+                }
+                if value.is_null() {
+                    result.push(None);
+                    continue;
+                }
+                result.push(Some(std::slice::from_raw_parts(value as *const u8, value_len).to_vec()));
+                ffi::rocksdb_free(value as *mut libc::c_void);
+            }
+
+            match first_err {
+                Some(err) => Err(err),
+                None => Ok(result),
+            }
+        }
+    }
+
+    pub fn get_for_update(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.check_writable()?;
+        unsafe {
+            let mut err: *mut c_char = ptr::null_mut();
+            let mut value_len: usize = 0;
+            let value = ffi::rocksdb_transaction_get_for_update(
                 self.inner,
                 self.read_options.get(),
                 key.as_ptr() as *const i8,
                 key.len(),
                 &mut value_len,
+                1,
                 &mut err,
             );
 
@@ -161,12 +336,16 @@ impl RocksDBTransactionInner {
                 return Ok(None);
             }
 
-            let value = std::slice::from_raw_parts(value as *const u8, value_len).to_vec();
-            Ok(Some(value))
+            let result = std::slice::from_raw_parts(value as *const u8, value_len).to_vec();
+            ffi::rocksdb_free(value as *mut libc::c_void);
+            Ok(Some(result))
         }
     }
 
     pub fn delete(&self, key: &[u8]) -> Result<()> {
+        self.check_writable()?;
+        self.track_sync_write(key)?;
+        self.log_pre_image(key)?;
         unsafe {
             let mut err: *mut c_char = ptr::null_mut();
 
@@ -178,21 +357,107 @@ impl RocksDBTransactionInner {
             );
 
             check_err!(err);
-            Ok(())
         }
+        #[cfg(feature = "fault-injection")]
+        self.record_write(crate::WalOperation::Delete {
+            key: key.to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Keep the value of a document before the first write of the transaction.
+    fn track_sync_write(&self, key: &[u8]) -> Result<()> {
+        if let Some(sync_writes) = &self.sync_writes {
+            if sync::is_document_key(key) && !sync_writes.lock()?.contains(key) {
+                let before = self.get(key)?;
+                sync_writes.lock()?.record_before(key, before);
+            }
+        }
+        Ok(())
+    }
+
+    /// Log the value of a document before the first write of the transaction.
+    /// The document is locked until the transaction ends,
+    /// so the pre-image is the value replaced by the commit.
+    fn log_pre_image(&self, key: &[u8]) -> Result<()> {
+        if let Some(pre_image_keys) = &self.pre_image_keys {
+            if sync::is_document_key(key) && !pre_image_keys.lock()?.contains(key) {
+                let before = self.get_for_update(key)?;
+                let blob = encode_pre_image(key, before.as_deref());
+                unsafe {
+                    ffi::polodb_transaction_put_log_data(
+                        self.inner,
+                        blob.as_ptr() as *const i8,
+                        blob.len(),
+                    );
+                }
+                pre_image_keys.lock()?.insert(key.to_vec());
+            }
+        }
+        Ok(())
+    }
+
+    fn set_sync_version(&self, key: &[u8], version: DocumentVersion) -> Result<()> {
+        if let Some(sync_writes) = &self.sync_writes {
+            sync_writes.lock()?.set_version(key, version);
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn check_writable(&self) -> Result<()> {
+        if unsafe { (*self.db_inner).read_only || (*self.db_inner).replica.load(Ordering::Relaxed) } {
+            return Err(crate::Error::ReadOnlyDatabase);
+        }
+        Ok(())
     }
 
     pub fn rollback(&self) -> Result<()> {
+        if self.inner.is_null() {
+            return Ok(());
+        }
+        crate::trace_span!("polodb.rollback");
         unsafe {
             let mut err: *mut c_char = ptr::null_mut();
             ffi::rocksdb_transaction_rollback(self.inner, &mut err);
 
             check_err!(err);
-            Ok(())
         }
+        if let Some(sync_writes) = &self.sync_writes {
+            *sync_writes.lock()? = SyncWrites::default();
+        }
+        if let Some(pre_image_keys) = &self.pre_image_keys {
+            pre_image_keys.lock()?.clear();
+        }
+        #[cfg(feature = "fault-injection")]
+        self.recorded_writes.lock()?.clear();
+        Ok(())
     }
 
     pub(crate) fn commit(&self) -> Result<()> {
+        #[cfg(feature = "fault-injection")]
+        if let Some(injector) = unsafe { (*self.db_inner).fault_injector.clone() } {
+            if !self.skip_faults.load(Ordering::SeqCst) {
+                return self.commit_with_faults(&injector);
+            }
+        }
+        // nothing can be written to a read-only database
+        if self.inner.is_null() {
+            return Ok(());
+        }
+        crate::trace_span!("polodb.commit");
+        // the tracker is locked until the commit ends, so the changes are logged in the commit order
+        let _sync_state = match (&self.sync_writes, unsafe { (*self.db_inner).sync_tracker.as_ref() }) {
+            (Some(sync_writes), Some(tracker)) => {
+                let writes = std::mem::take(&mut *sync_writes.lock()?);
+                if writes.is_empty() {
+                    None
+                } else {
+                    Some(tracker.record(self, writes)?)
+                }
+            }
+            _ => None,
+        };
         unsafe {
             let mut err: *mut c_char = ptr::null_mut();
 
@@ -205,6 +470,53 @@ impl RocksDBTransactionInner {
 
 }
 
+#[cfg(feature = "fault-injection")]
+impl RocksDBTransactionInner {
+
+    fn record_write(&self, op: crate::WalOperation) {
+        if unsafe { (*self.db_inner).fault_injector.is_some() } {
+            self.recorded_writes.lock().unwrap().push(op);
+        }
+    }
+
+    /// Discard the transaction and write the batches decided by the injector instead.
+    fn commit_with_faults(&self, injector: &crate::fault_injection::FaultInjector) -> Result<()> {
+        let writes = std::mem::take(&mut *self.recorded_writes.lock()?);
+        self.rollback()?;
+
+        for batch_writes in injector.on_commit(writes) {
+            unsafe {
+                let batch = ffi::rocksdb_writebatch_create();
+                for op in &batch_writes {
+                    match op {
+                        crate::WalOperation::Put { key, value } => {
+                            ffi::rocksdb_writebatch_put(
+                                batch,
+                                key.as_ptr() as *const i8,
+                                key.len(),
+                                value.as_ptr() as *const i8,
+                                value.len(),
+                            );
+                        }
+                        crate::WalOperation::Delete { key } => {
+                            ffi::rocksdb_writebatch_delete(batch, key.as_ptr() as *const i8, key.len());
+                        }
+                        // the transactions don't write the range deletions
+                        crate::WalOperation::DeleteRange { .. } => (),
+                    }
+                }
+                let mut err: *mut c_char = ptr::null_mut();
+                ffi::rocksdb_transactiondb_write((*self.db_inner).inner, self._write_options.get(), batch, &mut err);
+                ffi::rocksdb_writebatch_destroy(batch);
+                check_err!(err);
+            }
+        }
+
+        Ok(())
+    }
+
+}
+
 impl Drop for RocksDBTransactionInner {
 
     fn drop(&mut self) {
@@ -212,7 +524,9 @@ impl Drop for RocksDBTransactionInner {
             if self.iter_count.load(Ordering::SeqCst) != 0 {
                 panic!("there are still iterators opened")
             }
-            ffi::rocksdb_transaction_destroy(self.inner);
+            if !self.inner.is_null() {
+                ffi::rocksdb_transaction_destroy(self.inner);
+            }
             _ = self.db_inner.as_mut().unwrap().txn_count.fetch_sub(1, Ordering::SeqCst)
         }
     }
@@ -0,0 +1,327 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::convert::TryInto;
+use std::ptr::null_mut;
+use libc::c_char;
+use polodb_librocksdb_sys as ffi;
+use super::db::Result;
+use crate::Error;
+
+macro_rules! check_err {
+    ($err:expr) => {
+        if !$err.is_null() {
+            let c_str = std::ffi::CStr::from_ptr($err);
+
+            // Convert the &CStr to a &str
+            let str_slice = c_str.to_str().expect("C string is not valid UTF-8");
+
+            // Convert the &str to a String and return
+            return Err(crate::Error::from_rocksdb(str_slice.to_owned()))
+        }
+    };
+}
+
+/// A single write recorded in the write-ahead log.
+///
+/// The keys and values are the raw bytes stored by PoloDB,
+/// so they can be replayed to rebuild the database.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WalOperation {
+    Put {
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Delete {
+        key: Vec<u8>,
+    },
+    /// Delete the keys in `[start, end)`, written by
+    /// [`crate::CollectionT::drop_partition`] without scanning the keys.
+    DeleteRange {
+        start: Vec<u8>,
+        end: Vec<u8>,
+    },
+}
+
+/// A logical commit record in the write-ahead log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WalRecord {
+    /// The sequence number of the first operation of the record.
+    pub sequence: u64,
+    pub operations: Vec<WalOperation>,
+}
+
+impl WalRecord {
+
+    /// The sequence number right after this record.
+    /// Use it to resume shipping from the next record.
+    pub fn next_sequence(&self) -> u64 {
+        self.sequence + self.operations.len() as u64
+    }
+
+}
+
+pub(crate) struct RocksDBWalIterator {
+    base_db: *mut ffi::rocksdb_t,
+    inner: *mut ffi::rocksdb_wal_iterator_t,
+}
+
+impl RocksDBWalIterator {
+
+    pub(crate) fn new(txn_db: *mut ffi::rocksdb_transactiondb_t, since: u64) -> Result<RocksDBWalIterator> {
+        unsafe {
+            let base_db = ffi::rocksdb_transactiondb_get_base_db(txn_db);
+            let mut err: *mut c_char = null_mut();
+            let inner = ffi::rocksdb_get_updates_since(base_db, since, std::ptr::null(), &mut err);
+            if !err.is_null() {
+                ffi::rocksdb_transactiondb_close_base_db(base_db);
+            }
+            check_err!(err);
+            Ok(RocksDBWalIterator {
+                base_db,
+                inner,
+            })
+        }
+    }
+
+    pub(crate) fn latest_sequence_number(txn_db: *mut ffi::rocksdb_transactiondb_t) -> u64 {
+        unsafe {
+            let base_db = ffi::rocksdb_transactiondb_get_base_db(txn_db);
+            let seq = ffi::rocksdb_get_latest_sequence_number(base_db);
+            ffi::rocksdb_transactiondb_close_base_db(base_db);
+            seq
+        }
+    }
+
+    pub fn valid(&self) -> bool {
+        unsafe {
+            ffi::rocksdb_wal_iter_valid(self.inner) != 0
+        }
+    }
+
+    pub fn next(&self) {
+        unsafe {
+            ffi::rocksdb_wal_iter_next(self.inner);
+        }
+    }
+
+    pub fn status(&self) -> Result<()> {
+        unsafe {
+            let mut err: *mut c_char = null_mut();
+            ffi::rocksdb_wal_iter_status(self.inner, &mut err);
+            check_err!(err);
+            Ok(())
+        }
+    }
+
+    /// The record and the documents before its writes,
+    /// which are only logged if the pre-images are enabled.
+    pub fn record_with_pre_images(&self) -> Result<(WalRecord, Vec<WalPreImage>)> {
+        unsafe {
+            let mut sequence: u64 = 0;
+            let batch = ffi::rocksdb_wal_iter_get_batch(self.inner, &mut sequence);
+            let mut size: usize = 0;
+            let data = ffi::rocksdb_writebatch_data(batch, &mut size);
+            let data = std::slice::from_raw_parts(data as *const u8, size);
+            let mut pre_images = Vec::<WalPreImage>::new();
+            let operations = decode_write_batch_with_log_data(data, |blob| {
+                if let Some(pre_image) = decode_pre_image(blob)? {
+                    pre_images.push(pre_image);
+                }
+                Ok(())
+            });
+            ffi::rocksdb_writebatch_destroy(batch);
+            Ok((
+                WalRecord {
+                    sequence,
+                    operations: operations?,
+                },
+                pre_images,
+            ))
+        }
+    }
+
+}
+
+// The tags of the records in a write batch, defined in `db/dbformat.h` of RocksDB.
+const TAG_DELETION: u8 = 0x0;
+const TAG_VALUE: u8 = 0x1;
+const TAG_LOG_DATA: u8 = 0x3;
+const TAG_CF_DELETION: u8 = 0x4;
+const TAG_CF_VALUE: u8 = 0x5;
+const TAG_SINGLE_DELETION: u8 = 0x7;
+const TAG_CF_SINGLE_DELETION: u8 = 0x8;
+const TAG_NOOP: u8 = 0xD;
+const TAG_CF_RANGE_DELETION: u8 = 0xE;
+const TAG_RANGE_DELETION: u8 = 0xF;
+
+// The header of a write batch: 8 bytes of sequence and 4 bytes of count.
+const WRITE_BATCH_HEADER_SIZE: usize = 12;
+
+/// The value of a document before the write of a transaction,
+/// logged in the same batch as the write.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct WalPreImage {
+    pub key: Vec<u8>,
+    /// `None` if the document didn't exist.
+    pub value: Option<Vec<u8>>,
+}
+
+// The prefix of the log data blobs of the pre-images,
+// the other blobs in the log are ignored.
+const PRE_IMAGE_MAGIC: &[u8] = b"$PRE";
+
+/// Encode the pre-image as a log data blob:
+/// magic, u32 length of the key, key, 1 if the value exists, value.
+pub(crate) fn encode_pre_image(key: &[u8], value: Option<&[u8]>) -> Vec<u8> {
+    let value_len = value.map(<[u8]>::len).unwrap_or(0);
+    let mut blob = Vec::with_capacity(PRE_IMAGE_MAGIC.len() + 5 + key.len() + value_len);
+    blob.extend_from_slice(PRE_IMAGE_MAGIC);
+    blob.extend_from_slice(&(key.len() as u32).to_be_bytes());
+    blob.extend_from_slice(key);
+    match value {
+        Some(value) => {
+            blob.push(1);
+            blob.extend_from_slice(value);
+        }
+        None => blob.push(0),
+    }
+    blob
+}
+
+pub(crate) fn decode_pre_image(blob: &[u8]) -> Result<Option<WalPreImage>> {
+    let input = match blob.strip_prefix(PRE_IMAGE_MAGIC) {
+        Some(input) => input,
+        None => return Ok(None),
+    };
+    let malformed = || Error::RocksDbErr("malformed pre-image in write batch".to_string());
+    if input.len() < 4 {
+        return Err(malformed());
+    }
+    let (key_len, input) = input.split_at(4);
+    let key_len = u32::from_be_bytes(key_len.try_into().unwrap()) as usize;
+    if input.len() < key_len + 1 {
+        return Err(malformed());
+    }
+    let (key, input) = input.split_at(key_len);
+    let value = match input[0] {
+        0 => None,
+        1 => Some(input[1..].to_vec()),
+        _ => return Err(malformed()),
+    };
+    Ok(Some(WalPreImage {
+        key: key.to_vec(),
+        value,
+    }))
+}
+
+/// Decode the operations of a write batch.
+///
+/// `rocksdb_writebatch_iterate` can't be used because it stops at the
+/// noop marker which the transactions write at the beginning of the batch.
+pub(crate) fn decode_write_batch(data: &[u8]) -> Result<Vec<WalOperation>> {
+    decode_write_batch_with_log_data(data, |_| Ok(()))
+}
+
+/// Decode the operations of a write batch, the log data blobs
+/// are passed to `on_log_data` in the order of the batch.
+pub(crate) fn decode_write_batch_with_log_data<F>(data: &[u8], mut on_log_data: F) -> Result<Vec<WalOperation>>
+where
+    F: FnMut(&[u8]) -> Result<()>
+{
+    if data.len() < WRITE_BATCH_HEADER_SIZE {
+        return Err(Error::RocksDbErr("malformed write batch header".to_string()));
+    }
+    let mut input = &data[WRITE_BATCH_HEADER_SIZE..];
+    let mut operations = Vec::<WalOperation>::new();
+
+    while !input.is_empty() {
+        let tag = input[0];
+        input = &input[1..];
+        match tag {
+            TAG_VALUE | TAG_CF_VALUE => {
+                if tag == TAG_CF_VALUE {
+                    read_varint32(&mut input)?;
+                }
+                let key = read_length_prefixed(&mut input)?;
+                let value = read_length_prefixed(&mut input)?;
+                operations.push(WalOperation::Put {
+                    key: key.to_vec(),
+                    value: value.to_vec(),
+                });
+            }
+            TAG_DELETION | TAG_CF_DELETION | TAG_SINGLE_DELETION | TAG_CF_SINGLE_DELETION => {
+                if tag == TAG_CF_DELETION || tag == TAG_CF_SINGLE_DELETION {
+                    read_varint32(&mut input)?;
+                }
+                let key = read_length_prefixed(&mut input)?;
+                operations.push(WalOperation::Delete {
+                    key: key.to_vec(),
+                });
+            }
+            TAG_RANGE_DELETION | TAG_CF_RANGE_DELETION => {
+                if tag == TAG_CF_RANGE_DELETION {
+                    read_varint32(&mut input)?;
+                }
+                let start = read_length_prefixed(&mut input)?;
+                let end = read_length_prefixed(&mut input)?;
+                operations.push(WalOperation::DeleteRange {
+                    start: start.to_vec(),
+                    end: end.to_vec(),
+                });
+            }
+            TAG_LOG_DATA => {
+                let blob = read_length_prefixed(&mut input)?;
+                on_log_data(blob)?;
+            }
+            TAG_NOOP => (),
+            _ => {
+                return Err(Error::RocksDbErr(format!("unsupported write batch tag: 0x{:02X}", tag)));
+            }
+        }
+    }
+
+    Ok(operations)
+}
+
+pub(crate) fn read_varint32(input: &mut &[u8]) -> Result<u32> {
+    let mut result: u32 = 0;
+    for (index, byte) in input.iter().enumerate().take(5) {
+        result |= ((byte & 0x7F) as u32) << (7 * index);
+        if byte & 0x80 == 0 {
+            *input = &input[index + 1..];
+            return Ok(result);
+        }
+    }
+    Err(Error::RocksDbErr("malformed varint in write batch".to_string()))
+}
+
+pub(crate) fn read_length_prefixed<'a>(input: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = read_varint32(input)? as usize;
+    if input.len() < len {
+        return Err(Error::RocksDbErr("malformed slice in write batch".to_string()));
+    }
+    let (result, rest) = input.split_at(len);
+    *input = rest;
+    Ok(result)
+}
+
+impl Drop for RocksDBWalIterator {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::rocksdb_wal_iter_destroy(self.inner);
+            ffi::rocksdb_transactiondb_close_base_db(self.base_db);
+        }
+    }
+}
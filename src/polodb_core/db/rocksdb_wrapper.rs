@@ -12,17 +12,27 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
 use std::ops::DerefMut;
 use std::path::{Path, PathBuf};
 use std::{env, ptr};
-use std::ffi::CString;
-use libc::c_char;
+use std::ffi::{CStr, CString};
+use libc::{c_char, c_int};
 use polodb_librocksdb_sys as ffi;
 use super::db::Result;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{Arc, Mutex};
-use crate::db::rocksdb_options::RocksDBWaitForCompactOptions;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use crate::db::rocksdb_options::{RocksDBCompactOptions, RocksDBFlushOptions, RocksDBWaitForCompactOptions, RocksDBWriteOptions};
 use crate::db::rocksdb_transaction::RocksDBTransaction;
+use crate::db::RocksDBSnapshot;
+use crate::db::rocksdb_wal::RocksDBWalIterator;
+use crate::db::rocksdb_backup::RocksDBBackupEngine;
+use crate::db::rocksdb_storage_backend::create_storage_backend_env;
+use crate::results::{BackupInfo, BlockCacheStats, BlockStats, LevelStats, StorageStats};
+use crate::sync::SyncTracker;
+use crate::{BlockCache, Config, KeyOrdering, Tokenizer, WalOperation, WalSyncPolicy};
 
 macro_rules! check_err {
     ($err:expr) => {
@@ -33,11 +43,36 @@ macro_rules! check_err {
             let str_slice = c_str.to_str().expect("C string is not valid UTF-8");
 
             // Convert the &str to a String and return
-            return Err(crate::Error::RocksDbErr(str_slice.to_owned()))
+            return Err(crate::Error::from_rocksdb(str_slice.to_owned()))
         }
     };
 }
 
+// the ids of `rocksdb::Tickers`
+const TICKER_BLOCK_CACHE_MISS: u32 = 0;
+const TICKER_BLOCK_CACHE_HIT: u32 = 1;
+const TICKER_BLOCK_CACHE_ADD: u32 = 2;
+#[cfg(feature = "metrics")]
+const TICKER_WAL_FILE_SYNCED: u32 = 62;
+#[cfg(feature = "metrics")]
+const TICKER_WAL_FILE_BYTES: u32 = 63;
+#[cfg(feature = "metrics")]
+const TICKER_COMPACT_READ_BYTES: u32 = 67;
+#[cfg(feature = "metrics")]
+const TICKER_COMPACT_WRITE_BYTES: u32 = 68;
+#[cfg(feature = "metrics")]
+const TICKER_FLUSH_WRITE_BYTES: u32 = 69;
+
+// `rocksdb::StatsLevel::kExceptHistogramOrTimers`, only the tickers are collected
+const STATS_LEVEL_TICKERS: i32 = 1;
+
+// `rocksdb::WALRecoveryMode::kPointInTimeRecovery`, the replay of the logs
+// stops at the first torn record instead of failing to open
+const WAL_RECOVERY_POINT_IN_TIME: i32 = 2;
+
+// the path of the in-memory databases, which only exists in their own env
+const MEMORY_PATH: &str = "/polodb-memory";
+
 #[derive(Clone)]
 pub(crate) struct RocksDBWrapper {
     inner: Arc<Mutex<RocksDBWrapperInner>>,
@@ -45,27 +80,520 @@ pub(crate) struct RocksDBWrapper {
 
 impl RocksDBWrapper {
 
-    pub fn open(path: &Path) -> Result<RocksDBWrapper> {
-        let inner = RocksDBWrapperInner::open(path)?;
+    pub fn open(path: &Path, config: &Config) -> Result<RocksDBWrapper> {
+        let inner = RocksDBWrapperInner::open(path, config)?;
         Ok(RocksDBWrapper {
             inner: Arc::new(Mutex::new(inner)),
         })
     }
 
-    pub fn begin_transaction(&self) -> Result<RocksDBTransaction> {
+    /// Open a database which keeps all the files in memory.
+    pub fn open_memory(config: &Config) -> Result<RocksDBWrapper> {
+        let inner = unsafe {
+            let env = ffi::rocksdb_create_mem_env();
+            RocksDBWrapperInner::open_with_env(Path::new(MEMORY_PATH), config, env)?
+        };
+        Ok(RocksDBWrapper {
+            inner: Arc::new(Mutex::new(inner)),
+        })
+    }
+
+    /// Open an existing database without writing anything to the files.
+    /// The WAL is replayed in memory only.
+    pub fn open_read_only(path: &Path, config: &Config) -> Result<RocksDBWrapper> {
+        let inner = RocksDBWrapperInner::open_read_only(path, config)?;
+        Ok(RocksDBWrapper {
+            inner: Arc::new(Mutex::new(inner)),
+        })
+    }
+
+    pub fn repair(path: &Path, config: &Config) -> Result<()> {
+        RocksDBWrapperInner::repair(path, config)
+    }
+
+    pub fn begin_transaction(&self, sync: bool) -> Result<RocksDBTransaction> {
+        self.begin_transaction_with_options(sync, false)
+    }
+
+    /// With `no_slowdown` the commit fails with [`crate::Error::WouldBlock`]
+    /// instead of waiting when the writes are stalled.
+    pub fn begin_transaction_with_options(&self, sync: bool, no_slowdown: bool) -> Result<RocksDBTransaction> {
+        let mut db_inner = self.inner.lock()?;
+        RocksDBTransaction::new(db_inner.deref_mut() as *mut _, sync, no_slowdown)
+    }
+
+    /// Pin the current state of the database for the reads of [`RocksDBWrapper::begin_transaction_at`].
+    pub fn create_read_snapshot(&self) -> Result<Arc<RocksDBSnapshot>> {
         let mut db_inner = self.inner.lock()?;
-        RocksDBTransaction::new(db_inner.deref_mut() as *mut _)
+        Ok(Arc::new(RocksDBSnapshot::new(self.clone(), db_inner.deref_mut() as *mut _)))
+    }
+
+    /// Begin a transaction which reads the database as of the snapshot.
+    pub fn begin_transaction_at(&self, snapshot: &Arc<RocksDBSnapshot>) -> Result<RocksDBTransaction> {
+        let txn = self.begin_transaction(false)?;
+        txn.set_snapshot(snapshot.clone());
+        Ok(txn)
+    }
+
+    pub fn latest_sequence_number(&self) -> Result<u64> {
+        let db_inner = self.inner.lock()?;
+        if !db_inner.read_only_db.is_null() {
+            return Ok(unsafe {
+                ffi::rocksdb_get_latest_sequence_number(db_inner.read_only_db)
+            });
+        }
+        Ok(RocksDBWalIterator::latest_sequence_number(db_inner.inner))
+    }
+
+    pub fn is_replica(&self) -> Result<bool> {
+        let db_inner = self.inner.lock()?;
+        Ok(db_inner.replica.load(Ordering::SeqCst))
+    }
+
+    pub fn set_replica(&self, replica: bool) -> Result<()> {
+        let db_inner = self.inner.lock()?;
+        db_inner.replica.store(replica, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub fn sync_tracker(&self) -> Result<Option<Arc<SyncTracker>>> {
+        let db_inner = self.inner.lock()?;
+        Ok(db_inner.sync_tracker.clone())
+    }
+
+    /// Write the operations atomically, without a transaction.
+    ///
+    /// The keys of a batch with range deletions are not locked,
+    /// so it must not conflict with the open transactions.
+    pub fn write_batch(&self, operations: &[WalOperation], sync: bool) -> Result<()> {
+        let txn_db = self.txn_db()?;
+        let write_options = RocksDBWriteOptions::new();
+        write_options.set_sync(sync);
+        let has_range = operations.iter().any(|op| matches!(op, WalOperation::DeleteRange { .. }));
+        unsafe {
+            let batch = ffi::rocksdb_writebatch_create();
+            for op in operations {
+                match op {
+                    WalOperation::Put { key, value } => {
+                        ffi::rocksdb_writebatch_put(
+                            batch,
+                            key.as_ptr() as *const c_char,
+                            key.len(),
+                            value.as_ptr() as *const c_char,
+                            value.len(),
+                        );
+                    }
+                    WalOperation::Delete { key } => {
+                        ffi::rocksdb_writebatch_delete(batch, key.as_ptr() as *const c_char, key.len());
+                    }
+                    WalOperation::DeleteRange { start, end } => {
+                        ffi::rocksdb_writebatch_delete_range(
+                            batch,
+                            start.as_ptr() as *const c_char,
+                            start.len(),
+                            end.as_ptr() as *const c_char,
+                            end.len(),
+                        );
+                    }
+                }
+            }
+            let mut err: *mut c_char = ptr::null_mut();
+            if has_range {
+                ffi::polodb_transactiondb_write_unlocked(txn_db, write_options.get(), batch, &mut err);
+            } else {
+                ffi::rocksdb_transactiondb_write(txn_db, write_options.get(), batch, &mut err);
+            }
+            ffi::rocksdb_writebatch_destroy(batch);
+            check_err!(err);
+        }
+        Ok(())
+    }
+
+    /// The transaction db, which is not available if the database is
+    /// opened by [`RocksDBWrapper::open_read_only`].
+    fn txn_db(&self) -> Result<*mut ffi::rocksdb_transactiondb_t> {
+        let db_inner = self.inner.lock()?;
+        if db_inner.inner.is_null() {
+            return Err(crate::Error::ReadOnlyDatabase);
+        }
+        Ok(db_inner.inner)
+    }
+
+    /// Create a consistent copy of the database in `path`, which must not exist.
+    /// The table files are hard-linked if possible, so it's cheap on the same file system.
+    pub fn create_checkpoint(&self, path: &Path) -> Result<()> {
+        crate::trace_span!("polodb.checkpoint", path = %path.display());
+        let db = self.txn_db()?;
+        let path_c = CString::new(path.to_str().unwrap()).unwrap();
+        unsafe {
+            let mut err: *mut c_char = ptr::null_mut();
+            let checkpoint = ffi::rocksdb_transactiondb_checkpoint_object_create(db, &mut err);
+            check_err!(err);
+            // flush the memtable first, so the WAL in the checkpoint is small
+            ffi::rocksdb_checkpoint_create(checkpoint, path_c.as_ptr(), 0, &mut err);
+            ffi::rocksdb_checkpoint_object_destroy(checkpoint);
+            check_err!(err);
+        }
+        Ok(())
+    }
+
+    /// Create a new backup in `backup_dir`. Only the files which
+    /// are not in the previous backups are copied.
+    pub fn create_incremental_backup(&self, backup_dir: &Path) -> Result<BackupInfo> {
+        let db = self.txn_db()?;
+        let engine = RocksDBBackupEngine::open(backup_dir)?;
+        engine.create_new_backup(db)?;
+        let info = engine.backup_infos().pop().unwrap();
+        Ok(info)
+    }
+
+    /// Flush the memtable and rewrite all the live data into new table files.
+    /// The obsolete files are deleted after the compaction.
+    pub fn compact_all(&self) -> Result<()> {
+        let db = self.txn_db()?;
+        let has_cold_path = self.inner.lock()?.has_cold_path;
+        self.flush()?;
+        unsafe {
+            crate::trace_span!("polodb.compaction", cold = has_cold_path);
+
+            let compact_options = RocksDBCompactOptions::new();
+            compact_options.set_force_bottommost_level();
+            if has_cold_path {
+                // everything is rewritten into the bottommost level, which is cold
+                compact_options.set_target_path_id(1);
+            }
+            let base_db = ffi::rocksdb_transactiondb_get_base_db(db);
+            ffi::rocksdb_compact_range_opt(base_db, compact_options.get(), ptr::null(), 0, ptr::null(), 0);
+            ffi::rocksdb_transactiondb_close_base_db(base_db);
+        }
+        Ok(())
+    }
+
+    /// Write the memtables into the table files and wait for it.
+    /// Nothing is written if the database is opened read-only.
+    pub fn flush(&self) -> Result<()> {
+        if self.inner.lock()?.read_only {
+            return Ok(());
+        }
+        let db = self.txn_db()?;
+        crate::trace_span!("polodb.flush");
+        let flush_options = RocksDBFlushOptions::new();
+        flush_options.set_wait(true);
+        let mut err: *mut c_char = ptr::null_mut();
+        unsafe {
+            ffi::rocksdb_transactiondb_flush(db, flush_options.get(), &mut err);
+            check_err!(err);
+        }
+        Ok(())
+    }
+
+    /// Call `f` with the base db of the transaction db,
+    /// or the read-only db if the database is opened read-only.
+    fn with_base_db<R, F>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(*mut ffi::rocksdb_t) -> R
+    {
+        let db_inner = self.inner.lock()?;
+        if !db_inner.read_only_db.is_null() {
+            return Ok(f(db_inner.read_only_db));
+        }
+        unsafe {
+            let base_db = ffi::rocksdb_transactiondb_get_base_db(db_inner.inner);
+            let result = f(base_db);
+            ffi::rocksdb_transactiondb_close_base_db(base_db);
+            Ok(result)
+        }
+    }
+
+    /// The approximate size of the table files used by the keys in `[start, end)`.
+    /// The writes which are still in the memtable are not counted.
+    pub fn approximate_size(&self, start: &[u8], end: &[u8]) -> Result<u64> {
+        let start_ptr = start.as_ptr() as *const c_char;
+        let end_ptr = end.as_ptr() as *const c_char;
+        let mut size: u64 = 0;
+        let mut err: *mut c_char = ptr::null_mut();
+        self.with_base_db(|db| unsafe {
+            ffi::rocksdb_approximate_sizes(
+                db, 1,
+                &start_ptr, &start.len(),
+                &end_ptr, &end.len(),
+                &mut size, &mut err,
+            );
+        })?;
+        unsafe {
+            check_err!(err);
+        }
+        Ok(size)
+    }
+
+    /// The total size of the table files and the memtables, which is cheap to read.
+    pub fn estimated_size(&self) -> Result<u64> {
+        self.with_base_db(|db| unsafe {
+            let property_int = |name: &str| -> u64 {
+                let name_c = CString::new(name).unwrap();
+                let mut value: u64 = 0;
+                ffi::rocksdb_property_int(db, name_c.as_ptr(), &mut value);
+                value
+            };
+            property_int("rocksdb.total-sst-files-size") + property_int("rocksdb.size-all-mem-tables")
+        })
+    }
+
+    /// The table files of each level, the memtables and the compaction debt.
+    /// The sizes of the files on disk are filled by the caller.
+    pub fn storage_stats(&self) -> Result<StorageStats> {
+        let num_levels = unsafe {
+            ffi::rocksdb_options_get_num_levels(self.inner.lock()?.options)
+        };
+        let mut stats = StorageStats {
+            levels: (0..num_levels as u32)
+                .map(|level| LevelStats {
+                    level,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        self.with_base_db(|db| unsafe {
+            let files = ffi::rocksdb_livefiles(db);
+            for index in 0..ffi::rocksdb_livefiles_count(files) {
+                let level = ffi::rocksdb_livefiles_level(files, index) as usize;
+                if let Some(level_stats) = stats.levels.get_mut(level) {
+                    level_stats.file_count += 1;
+                    level_stats.size += ffi::rocksdb_livefiles_size(files, index) as u64;
+                }
+            }
+            ffi::rocksdb_livefiles_destroy(files);
+
+            let property_int = |name: &str| -> u64 {
+                let name_c = CString::new(name).unwrap();
+                let mut value: u64 = 0;
+                ffi::rocksdb_property_int(db, name_c.as_ptr(), &mut value);
+                value
+            };
+            stats.memtable_usage = property_int("rocksdb.cur-size-all-mem-tables");
+            stats.pending_compaction_bytes = property_int("rocksdb.estimate-pending-compaction-bytes");
+            stats.running_compactions = property_int("rocksdb.num-running-compactions");
+            stats.snapshot_count = property_int("rocksdb.num-snapshots");
+            stats.oldest_snapshot_time = property_int("rocksdb.oldest-snapshot-time");
+            stats.pinned_size = property_int("rocksdb.total-sst-files-size")
+                .saturating_sub(property_int("rocksdb.live-sst-files-size"));
+        })?;
+
+        Ok(stats)
+    }
+
+    /// The live table files of each level, from level 0,
+    /// with the properties of the blocks summed by level.
+    /// The keys are the raw bytes stored by PoloDB.
+    pub fn live_files(&self) -> Result<Vec<(Vec<LiveFile>, BlockStats)>> {
+        let num_levels = unsafe {
+            ffi::rocksdb_options_get_num_levels(self.inner.lock()?.options)
+        };
+        let mut levels: Vec<(Vec<LiveFile>, BlockStats)> = (0..num_levels)
+            .map(|_| (Vec::new(), BlockStats::default()))
+            .collect();
+
+        self.with_base_db(|db| unsafe {
+            let files = ffi::rocksdb_livefiles(db);
+            for index in 0..ffi::rocksdb_livefiles_count(files) {
+                let level = ffi::rocksdb_livefiles_level(files, index) as usize;
+                let key = |f: unsafe extern "C" fn(*const ffi::rocksdb_livefiles_t, c_int, *mut usize) -> *const c_char| {
+                    let mut size: usize = 0;
+                    let data = f(files, index, &mut size);
+                    std::slice::from_raw_parts(data as *const u8, size).to_vec()
+                };
+                let file = LiveFile {
+                    name: CStr::from_ptr(ffi::rocksdb_livefiles_name(files, index)).to_string_lossy().to_string(),
+                    size: ffi::rocksdb_livefiles_size(files, index) as u64,
+                    entries: ffi::rocksdb_livefiles_entries(files, index),
+                    deletions: ffi::rocksdb_livefiles_deletions(files, index),
+                    smallest_key: key(ffi::rocksdb_livefiles_smallestkey),
+                    largest_key: key(ffi::rocksdb_livefiles_largestkey),
+                };
+                if let Some((level_files, _)) = levels.get_mut(level) {
+                    level_files.push(file);
+                }
+            }
+            ffi::rocksdb_livefiles_destroy(files);
+
+            for (level, (_, blocks)) in levels.iter_mut().enumerate() {
+                let name = CString::new(format!("rocksdb.aggregated-table-properties-at-level{}", level)).unwrap();
+                let value = ffi::rocksdb_property_value(db, name.as_ptr());
+                if value.is_null() {
+                    continue;
+                }
+                *blocks = parse_table_properties(&CStr::from_ptr(value).to_string_lossy());
+                ffi::rocksdb_free(value as *mut libc::c_void);
+            }
+        })?;
+
+        Ok(levels)
+    }
+
+    pub fn wal_iter(&self, since: u64) -> Result<RocksDBWalIterator> {
+        RocksDBWalIterator::new(self.txn_db()?, since)
+    }
+
+    pub fn block_cache_stats(&self) -> Result<BlockCacheStats> {
+        let db_inner = self.inner.lock()?;
+        let cache = &db_inner.block_cache;
+        let ticker = |ticker_type: u32| unsafe {
+            ffi::rocksdb_options_statistics_get_ticker_count(db_inner.options, ticker_type)
+        };
+        let entries = cache.entries() as u64;
+        let adds = ticker(TICKER_BLOCK_CACHE_ADD);
+        Ok(BlockCacheStats {
+            capacity: cache.capacity() as u64,
+            usage: cache.usage() as u64,
+            pinned_usage: cache.pinned_usage() as u64,
+            entries,
+            hits: ticker(TICKER_BLOCK_CACHE_HIT),
+            misses: ticker(TICKER_BLOCK_CACHE_MISS),
+            adds,
+            evictions: adds.saturating_sub(entries),
+        })
+    }
+
+    /// Change the budget of the block cache, which is shared
+    /// by the databases opened with the same [`BlockCache`].
+    pub fn set_block_cache_capacity(&self, capacity: usize) -> Result<()> {
+        let db_inner = self.inner.lock()?;
+        db_inner.block_cache.set_capacity(capacity);
+        Ok(())
+    }
+
+    /// Change the mutable database-wide options, e.g. `max_background_jobs`.
+    pub fn set_db_options(&self, options: &[(&str, String)]) -> Result<()> {
+        let keys = options.iter()
+            .map(|(key, _)| CString::new(*key).unwrap())
+            .collect::<Vec<_>>();
+        let values = options.iter()
+            .map(|(_, value)| CString::new(value.as_str()).unwrap())
+            .collect::<Vec<_>>();
+        let key_ptrs = keys.iter().map(|key| key.as_ptr()).collect::<Vec<_>>();
+        let value_ptrs = values.iter().map(|value| value.as_ptr()).collect::<Vec<_>>();
+        let mut err: *mut c_char = ptr::null_mut();
+        self.with_base_db(|db| unsafe {
+            ffi::polodb_set_db_options(
+                db,
+                options.len() as libc::c_int,
+                key_ptrs.as_ptr(),
+                value_ptrs.as_ptr(),
+                &mut err,
+            );
+        })?;
+        unsafe {
+            check_err!(err);
+        }
+        Ok(())
+    }
+
+    /// Restart the background syncing of the WAL for the new policy.
+    pub fn set_wal_sync_policy(&self, policy: WalSyncPolicy) -> Result<()> {
+        let mut db_inner = self.inner.lock()?;
+        if let Some(worker) = db_inner.wal_sync_worker.take() {
+            worker.stop();
+        }
+        if let (WalSyncPolicy::Interval(interval), false) = (policy, db_inner.read_only) {
+            db_inner.wal_sync_worker = Some(WalSyncWorker::start(db_inner.inner, interval));
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn tickers(&self) -> Result<StorageTickers> {
+        let db_inner = self.inner.lock()?;
+        let ticker = |ticker_type: u32| unsafe {
+            ffi::rocksdb_options_statistics_get_ticker_count(db_inner.options, ticker_type)
+        };
+        Ok(StorageTickers {
+            wal_syncs: ticker(TICKER_WAL_FILE_SYNCED),
+            wal_bytes: ticker(TICKER_WAL_FILE_BYTES),
+            compaction_read_bytes: ticker(TICKER_COMPACT_READ_BYTES),
+            compaction_write_bytes: ticker(TICKER_COMPACT_WRITE_BYTES),
+            flush_write_bytes: ticker(TICKER_FLUSH_WRITE_BYTES),
+        })
     }
 
 }
 
+/// A live table file of [`RocksDBWrapper::live_files`].
+pub(crate) struct LiveFile {
+    pub name: String,
+    pub size: u64,
+    pub entries: u64,
+    pub deletions: u64,
+    pub smallest_key: Vec<u8>,
+    pub largest_key: Vec<u8>,
+}
+
+/// Parse the output of `TableProperties::ToString`,
+/// e.g. `# data blocks=1; # entries=3; ...; data block size=120; ...`.
+fn parse_table_properties(value: &str) -> BlockStats {
+    let mut stats = BlockStats::default();
+    for (name, value) in value.split("; ").filter_map(|field| field.rsplit_once('=')) {
+        let value = match value.trim().parse::<u64>() {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        let name = name.trim();
+        if name == "# data blocks" {
+            stats.data_blocks = value;
+        } else if name == "data block size" {
+            stats.data_size = value;
+        } else if name.starts_with("index block size") {
+            stats.index_size = value;
+        } else if name == "filter block size" {
+            stats.filter_size = value;
+        } else if name == "raw key size" {
+            stats.raw_key_size = value;
+        } else if name == "raw value size" {
+            stats.raw_value_size = value;
+        }
+    }
+    stats
+}
+
+/// The counters accumulated by the storage since the database is opened.
+#[cfg(feature = "metrics")]
+pub(crate) struct StorageTickers {
+    pub wal_syncs: u64,
+    pub wal_bytes: u64,
+    pub compaction_read_bytes: u64,
+    pub compaction_write_bytes: u64,
+    pub flush_write_bytes: u64,
+}
+
 pub(crate) struct RocksDBWrapperInner {
     #[allow(dead_code)]
     path: String,
     pub(crate) options: *mut ffi::rocksdb_options_t,
     pub(crate) txn_db_options: *mut ffi::rocksdb_transactiondb_options_t,
+    /// Null if the database is opened read-only
     pub(crate) inner: *mut ffi::rocksdb_transactiondb_t,
+    /// Only set if the database is opened read-only
+    pub(crate) read_only_db: *mut ffi::rocksdb_t,
+    env: *mut ffi::rocksdb_env_t,
+    block_cache: BlockCache,
+    has_cold_path: bool,
     pub(crate) txn_count: AtomicU64,
+    wal_sync_worker: Option<WalSyncWorker>,
+    pub(crate) read_only: bool,
+    /// Set on the followers of the replication, the transactions can't write
+    /// but the replicated writes are applied by [`RocksDBWrapper::write_batch`].
+    pub(crate) replica: AtomicBool,
+    /// Set if [`crate::ConfigBuilder::set_sync_tracking`] is enabled.
+    pub(crate) sync_tracker: Option<Arc<SyncTracker>>,
+    /// Set if [`crate::ConfigBuilder::set_change_pre_images`] is enabled.
+    pub(crate) change_pre_images: bool,
+    /// The tokenizers of the text indexes, see [`crate::ConfigBuilder::set_tokenizer`].
+    pub(crate) tokenizers: HashMap<String, Arc<dyn Tokenizer>>,
+    /// The orderings of the primary keys, see [`crate::ConfigBuilder::set_key_ordering`].
+    pub(crate) key_orderings: HashMap<String, Arc<dyn KeyOrdering>>,
+    #[cfg(feature = "fault-injection")]
+    pub(crate) fault_injector: Option<crate::fault_injection::FaultInjector>,
 }
 
 unsafe impl Send for RocksDBWrapperInner {}
@@ -73,26 +601,201 @@ unsafe impl Sync for RocksDBWrapperInner {}
 
 impl RocksDBWrapperInner {
 
-    pub fn open(path: &Path) -> Result<RocksDBWrapperInner> {
-        let path: String = path.to_str().unwrap().into();
+    pub fn open(path: &Path, config: &Config) -> Result<RocksDBWrapperInner> {
         unsafe {
+            let env = match &config.storage_backend {
+                Some(backend) => create_storage_backend_env(backend.clone()),
+                None => ptr::null_mut(),
+            };
+            RocksDBWrapperInner::open_with_env(path, config, env)
+        }
+    }
+
+    /// Open the database with a custom env, which is owned by the database.
+    /// The default env is used if `env` is null.
+    unsafe fn open_with_env(path: &Path, config: &Config, env: *mut ffi::rocksdb_env_t) -> Result<RocksDBWrapperInner> {
+        let path: String = path.to_str().unwrap().into();
+        {
             let txn_db_opts = ffi::rocksdb_transactiondb_options_create();
-            let options = ffi::rocksdb_options_create();
-            ffi::rocksdb_options_set_create_if_missing(options, 1);
+            let block_cache = config.block_cache.clone().unwrap_or_default();
+            let options = RocksDBWrapperInner::create_options(config, &block_cache);
+            RocksDBWrapperInner::apply_paths_config(options, &path, config);
+            if !env.is_null() {
+                ffi::rocksdb_options_set_env(options, env);
+            }
             let mut err: *mut c_char = ptr::null_mut();
             let path_c = CString::new(path.clone()).unwrap();
             let db = ffi::rocksdb_transactiondb_open(options, txn_db_opts, path_c.as_ptr(), &mut err);
+            if !err.is_null() {
+                ffi::rocksdb_options_destroy(options);
+                ffi::rocksdb_transactiondb_options_destroy(txn_db_opts);
+                if !env.is_null() {
+                    ffi::rocksdb_env_destroy(env);
+                }
+            }
             check_err!(err);
+            let wal_sync_worker = match config.wal_sync_policy {
+                WalSyncPolicy::Interval(interval) => Some(WalSyncWorker::start(db, interval)),
+                _ => None,
+            };
             Ok(RocksDBWrapperInner {
                 path,
                 options,
                 txn_db_options: txn_db_opts,
                 inner: db,
+                read_only_db: ptr::null_mut(),
+                env,
+                block_cache,
+                has_cold_path: config.cold_path.is_some(),
+                txn_count: AtomicU64::new(0),
+                wal_sync_worker,
+                read_only: false,
+                replica: AtomicBool::new(false),
+                sync_tracker: if config.sync_tracking { Some(Arc::new(SyncTracker::new())) } else { None },
+                change_pre_images: config.change_pre_images,
+                tokenizers: config.tokenizers.clone(),
+                key_orderings: config.key_orderings.clone(),
+                #[cfg(feature = "fault-injection")]
+                fault_injector: config.fault_injector.clone(),
+            })
+        }
+    }
+
+    pub fn open_read_only(path: &Path, config: &Config) -> Result<RocksDBWrapperInner> {
+        let path: String = path.to_str().unwrap().into();
+        unsafe {
+            let block_cache = config.block_cache.clone().unwrap_or_default();
+            let options = RocksDBWrapperInner::create_options(config, &block_cache);
+            RocksDBWrapperInner::apply_paths_config(options, &path, config);
+            ffi::rocksdb_options_set_create_if_missing(options, 0);
+            let env = match &config.storage_backend {
+                Some(backend) => create_storage_backend_env(backend.clone()),
+                None => ptr::null_mut(),
+            };
+            if !env.is_null() {
+                ffi::rocksdb_options_set_env(options, env);
+            }
+            let mut err: *mut c_char = ptr::null_mut();
+            let path_c = CString::new(path.clone()).unwrap();
+            let db = ffi::rocksdb_open_for_read_only(options, path_c.as_ptr(), 0, &mut err);
+            if !err.is_null() {
+                ffi::rocksdb_options_destroy(options);
+                if !env.is_null() {
+                    ffi::rocksdb_env_destroy(env);
+                }
+            }
+            check_err!(err);
+            Ok(RocksDBWrapperInner {
+                path,
+                options,
+                txn_db_options: ptr::null_mut(),
+                inner: ptr::null_mut(),
+                read_only_db: db,
+                env,
+                block_cache,
+                has_cold_path: config.cold_path.is_some(),
                 txn_count: AtomicU64::new(0),
+                wal_sync_worker: None,
+                read_only: true,
+                replica: AtomicBool::new(false),
+                sync_tracker: None,
+                change_pre_images: false,
+                tokenizers: config.tokenizers.clone(),
+                key_orderings: config.key_orderings.clone(),
+                #[cfg(feature = "fault-injection")]
+                fault_injector: None,
             })
         }
     }
 
+    /// Rebuild the metadata of a damaged database from the readable files.
+    /// The files which can not be read are moved to the `lost` directory.
+    pub fn repair(path: &Path, config: &Config) -> Result<()> {
+        let path_c = CString::new(path.to_str().unwrap()).unwrap();
+        unsafe {
+            let block_cache = config.block_cache.clone().unwrap_or_default();
+            let options = RocksDBWrapperInner::create_options(config, &block_cache);
+            // the files in the cold path are searched as well
+            RocksDBWrapperInner::apply_paths_config(options, path.to_str().unwrap(), config);
+            let mut err: *mut c_char = ptr::null_mut();
+            ffi::rocksdb_repair_db(options, path_c.as_ptr(), &mut err);
+            ffi::rocksdb_options_destroy(options);
+            check_err!(err);
+        }
+        Ok(())
+    }
+
+    unsafe fn create_options(config: &Config, block_cache: &BlockCache) -> *mut ffi::rocksdb_options_t {
+        let options = ffi::rocksdb_options_create();
+        ffi::rocksdb_options_set_create_if_missing(options, 1);
+        RocksDBWrapperInner::apply_memtable_config(options, config);
+        RocksDBWrapperInner::apply_table_config(options, config, block_cache);
+        RocksDBWrapperInner::apply_io_config(options, config);
+        ffi::rocksdb_options_enable_statistics(options);
+        ffi::rocksdb_options_set_statistics_level(options, STATS_LEVEL_TICKERS);
+        ffi::rocksdb_options_set_wal_recovery_mode(options, WAL_RECOVERY_POINT_IN_TIME);
+        options
+    }
+
+    unsafe fn apply_memtable_config(options: *mut ffi::rocksdb_options_t, config: &Config) {
+        ffi::rocksdb_options_set_write_buffer_size(options, config.memtable_size as usize);
+        // the mutable memtable is counted by rocksdb as well
+        ffi::rocksdb_options_set_max_write_buffer_number(options, config.max_immutable_memtables as i32 + 1);
+        ffi::rocksdb_options_set_level0_slowdown_writes_trigger(options, config.level0_slowdown_writes_trigger as i32);
+        ffi::rocksdb_options_set_level0_stop_writes_trigger(options, config.level0_stop_writes_trigger as i32);
+        ffi::rocksdb_options_set_soft_pending_compaction_bytes_limit(options, config.soft_pending_compaction_bytes_limit as usize);
+        ffi::rocksdb_options_set_hard_pending_compaction_bytes_limit(options, config.hard_pending_compaction_bytes_limit as usize);
+        ffi::rocksdb_options_set_WAL_ttl_seconds(options, config.wal_ttl_seconds);
+        ffi::rocksdb_options_set_WAL_size_limit_MB(options, config.wal_size_limit_mb);
+    }
+
+    /// Store the levels which don't fit in the hot size limit in the cold path.
+    unsafe fn apply_paths_config(options: *mut ffi::rocksdb_options_t, path: &str, config: &Config) {
+        let cold_path = match &config.cold_path {
+            Some(cold_path) => cold_path,
+            None => return,
+        };
+        let hot_path_c = CString::new(path).unwrap();
+        let cold_path_c = CString::new(cold_path.to_str().unwrap()).unwrap();
+        let hot_path = ffi::rocksdb_dbpath_create(hot_path_c.as_ptr(), config.hot_size_limit);
+        // the last path takes the rest of the levels
+        let cold_path = ffi::rocksdb_dbpath_create(cold_path_c.as_ptr(), u64::MAX);
+        let mut paths = [hot_path as *const ffi::rocksdb_dbpath_t, cold_path as *const _];
+        // the paths are copied into the options
+        ffi::rocksdb_options_set_db_paths(options, paths.as_mut_ptr(), paths.len());
+        ffi::rocksdb_dbpath_destroy(hot_path);
+        ffi::rocksdb_dbpath_destroy(cold_path);
+    }
+
+    unsafe fn apply_io_config(options: *mut ffi::rocksdb_options_t, config: &Config) {
+        ffi::rocksdb_options_set_allow_mmap_reads(options, if config.mmap_reads {
+            1
+        } else {
+            0
+        });
+        // rocksdb allocates the aligned buffers for the direct writes
+        ffi::rocksdb_options_set_use_direct_io_for_flush_and_compaction(options, if config.direct_io_writes {
+            1
+        } else {
+            0
+        });
+    }
+
+    unsafe fn apply_table_config(options: *mut ffi::rocksdb_options_t, config: &Config, block_cache: &BlockCache) {
+        let table_options = ffi::rocksdb_block_based_options_create();
+        ffi::rocksdb_block_based_options_set_checksum(table_options, config.checksum_type.to_rocksdb());
+        // the table options keep their own reference of the cache
+        ffi::rocksdb_block_based_options_set_block_cache(table_options, block_cache.get());
+        // the table options are copied into the factory
+        ffi::rocksdb_options_set_block_based_table_factory(options, table_options);
+        ffi::rocksdb_block_based_options_destroy(table_options);
+        ffi::rocksdb_options_set_paranoid_checks(options, if config.paranoid_checks {
+            1
+        } else {
+            0
+        });
+    }
+
 }
 
 impl Drop for RocksDBWrapperInner {
@@ -101,6 +804,17 @@ impl Drop for RocksDBWrapperInner {
             if self.txn_count.load(Ordering::SeqCst) != 0 {
                 panic!("there are still transactions opened")
             }
+            if let Some(worker) = self.wal_sync_worker.take() {
+                worker.stop();
+            }
+            if !self.read_only_db.is_null() {
+                ffi::rocksdb_close(self.read_only_db);
+                ffi::rocksdb_options_destroy(self.options);
+                if !self.env.is_null() {
+                    ffi::rocksdb_env_destroy(self.env);
+                }
+                return;
+            }
             let mut err: *mut c_char = ptr::null_mut();
 
             {
@@ -125,8 +839,102 @@ impl Drop for RocksDBWrapperInner {
 
             ffi::rocksdb_options_destroy(self.options);
             ffi::rocksdb_transactiondb_options_destroy(self.txn_db_options);
+            if !self.env.is_null() {
+                ffi::rocksdb_env_destroy(self.env);
+            }
+        }
+    }
+}
+
+struct DbPtr(*mut ffi::rocksdb_transactiondb_t);
+
+unsafe impl Send for DbPtr {}
+
+/// Syncs the WAL periodically for [`WalSyncPolicy::Interval`].
+struct WalSyncWorker {
+    stopped: Arc<(Mutex<bool>, Condvar)>,
+    handle: JoinHandle<()>,
+}
+
+impl WalSyncWorker {
+
+    fn start(db: *mut ffi::rocksdb_transactiondb_t, interval: Duration) -> WalSyncWorker {
+        let stopped = Arc::new((Mutex::new(false), Condvar::new()));
+        let db = DbPtr(db);
+        let handle = {
+            let stopped = stopped.clone();
+            std::thread::spawn(move || {
+                let db = db;
+                let (lock, cvar) = &*stopped;
+                let mut guard = lock.lock().unwrap();
+                loop {
+                    let (next_guard, _) = cvar.wait_timeout(guard, interval).unwrap();
+                    guard = next_guard;
+                    if *guard {
+                        return;
+                    }
+                    crate::trace_span!("polodb.wal_sync");
+                    unsafe {
+                        let mut err: *mut c_char = ptr::null_mut();
+                        ffi::rocksdb_transactiondb_flush_wal(db.0, 1, &mut err);
+                        if !err.is_null() {
+                            let c_str = std::ffi::CStr::from_ptr(err);
+                            let str_slice = c_str.to_str().expect("C string is not valid UTF-8");
+                            eprintln!("sync wal error: {}", str_slice);
+                            libc::free(err as *mut libc::c_void);
+                        }
+                    }
+                }
+            })
+        };
+        WalSyncWorker {
+            stopped,
+            handle,
+        }
+    }
+
+    fn stop(self) {
+        {
+            let (lock, cvar) = &*self.stopped;
+            *lock.lock().unwrap() = true;
+            cvar.notify_all();
+        }
+        let _ = self.handle.join();
+    }
+
+}
+
+/// The LOCK file of a database, held like an opened database holds it,
+/// so the files can be replaced while no one uses them.
+pub(crate) struct RocksDBLock(*mut ffi::polodb_dblock_t);
+
+impl RocksDBLock {
+
+    /// Fail with [`crate::Error::DatabaseOccupied`] if the database
+    /// is opened by this process or by another one.
+    pub fn acquire(path: &Path) -> Result<RocksDBLock> {
+        let path_c = CString::new(path.to_str().unwrap()).unwrap();
+        unsafe {
+            let mut err: *mut c_char = ptr::null_mut();
+            let lock = ffi::polodb_lock_db(path_c.as_ptr(), &mut err);
+            if !err.is_null() {
+                ffi::rocksdb_free(err as *mut libc::c_void);
+                return Err(crate::Error::DatabaseOccupied);
+            }
+            Ok(RocksDBLock(lock))
+        }
+    }
+
+}
+
+impl Drop for RocksDBLock {
+
+    fn drop(&mut self) {
+        unsafe {
+            ffi::polodb_unlock_db(self.0);
         }
     }
+
 }
 
 #[allow(dead_code)]
@@ -143,9 +951,9 @@ fn test_rocks_db() {
 
     let _ = std::fs::remove_dir_all(test_path.as_path());
 
-    let db = RocksDBWrapper::open(test_path.as_path()).unwrap();
+    let db = RocksDBWrapper::open(test_path.as_path(), &Config::default()).unwrap();
 
-    let txn = db.begin_transaction().unwrap();
+    let txn = db.begin_transaction(true).unwrap();
     txn.set(b"key", b"value").unwrap();
     let value = txn.get(b"key").unwrap().unwrap();
     assert_eq!(value, b"value".to_vec());
@@ -165,9 +973,9 @@ fn test_close_with_open_txn() {
 
         let _ = std::fs::remove_dir_all(test_path.as_path());
 
-        let db = RocksDBWrapper::open(test_path.as_path()).unwrap();
+        let db = RocksDBWrapper::open(test_path.as_path(), &Config::default()).unwrap();
 
-        let txn = db.begin_transaction().unwrap();
+        let txn = db.begin_transaction(true).unwrap();
         txn.set(b"key", b"value").unwrap();
         let value = txn.get(b"key").unwrap().unwrap();
         assert_eq!(value, b"value".to_vec());
@@ -190,6 +998,6 @@ fn test_open_on_exist_file() {
         file.write_all(b"hello world").unwrap();
     }
 
-    let open_err = RocksDBWrapper::open(test_path.as_path());
+    let open_err = RocksDBWrapper::open(test_path.as_path(), &Config::default());
     assert!(open_err.is_err());
 }
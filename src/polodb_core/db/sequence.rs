@@ -0,0 +1,94 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The named sequences of the databases.
+//!
+//! A sequence is stored in a keyspace of `[SEQUENCE_PREFIX, namespace, name]`,
+//! the value is the last number returned, encoded in big endian.
+//! The key is read with `get_for_update`, so the concurrent increments
+//! of the same sequence are serialized by the lock of the transaction.
+
+use std::convert::TryInto;
+use bson::Bson;
+use crate::cursor::Cursor;
+use crate::transaction::TransactionInner;
+use crate::utils::bson::stacked_key;
+use crate::{Error, Result};
+
+const SEQUENCE_PREFIX: &str = "$SEQUENCE";
+
+fn sequence_key(namespace: Option<&str>, name: &str) -> Result<Vec<u8>> {
+    stacked_key(&[
+        Bson::String(SEQUENCE_PREFIX.to_string()),
+        Bson::String(namespace.unwrap_or_default().to_string()),
+        Bson::String(name.to_string()),
+    ])
+}
+
+fn validate_sequence_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.contains('\0') {
+        return Err(Error::IllegalSequenceName(name.to_string()));
+    }
+    Ok(())
+}
+
+/// The last number returned by the sequence, `None` if it's never used.
+pub(crate) fn current_value(txn: &TransactionInner, namespace: Option<&str>, name: &str) -> Result<Option<i64>> {
+    validate_sequence_name(name)?;
+    let key = sequence_key(namespace, name)?;
+    let bytes = match txn.rocksdb_txn.get_for_update(&key)? {
+        Some(bytes) => bytes,
+        None => return Ok(None),
+    };
+    let bytes: [u8; 8] = bytes.as_slice().try_into().map_err(|_| Error::DataOverflow)?;
+    Ok(Some(i64::from_be_bytes(bytes)))
+}
+
+/// Increment the sequence and return the new number, starting from 1.
+pub(crate) fn next_value(txn: &TransactionInner, namespace: Option<&str>, name: &str) -> Result<i64> {
+    let value = match current_value(txn, namespace, name)? {
+        Some(value) => value.checked_add(1).ok_or(Error::DataOverflow)?,
+        None => 1,
+    };
+    txn.put(&sequence_key(namespace, name)?, &value.to_be_bytes())?;
+    Ok(value)
+}
+
+/// Set the last number of the sequence, the next one returned is `value + 1`.
+pub(crate) fn reset_value(txn: &TransactionInner, namespace: Option<&str>, name: &str, value: i64) -> Result<()> {
+    validate_sequence_name(name)?;
+    txn.put(&sequence_key(namespace, name)?, &value.to_be_bytes())
+}
+
+/// Remove the sequences of a dropped database.
+pub(crate) fn delete_sequences(txn: &TransactionInner, namespace: Option<&str>) -> Result<()> {
+    let prefix = stacked_key(&[
+        Bson::String(SEQUENCE_PREFIX.to_string()),
+        Bson::String(namespace.unwrap_or_default().to_string()),
+    ])?;
+    let mut keys = Vec::new();
+    let mut cursor = Cursor::new(prefix, txn.rocksdb_txn.new_iterator());
+    cursor.reset()?;
+    while cursor.has_next() {
+        keys.push(cursor.peek_key().expect("key must exist"));
+        cursor.next()?;
+    }
+
+    for key in &keys {
+        txn.delete(key.as_ref())?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,702 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Dump and restore the collections in the format of `mongodump`.
+//!
+//! A dump is a directory of one database, like `dump/<db>` written by `mongodump --db <db>`.
+//! Each collection has two files:
+//!
+//! - `<name>.bson`: the documents as concatenated BSON, a view has no documents.
+//! - `<name>.metadata.json`: the type, the options and the indexes in canonical extended JSON.
+//!
+//! The default fields and the id strategy are only known to PoloDB, they are stored under
+//! the `polodb` field of the metadata which is ignored by `mongorestore`.
+//!
+//! The `system.*` collections and the `prelude.json` of a dump of MongoDB are skipped,
+//! so the time-series collections of MongoDB, whose buckets are in `system.buckets.<name>`,
+//! are restored without the measurements. The dumps compressed by `--gzip` or written by
+//! `--archive` must be extracted by `mongorestore` into a directory first.
+//!
+//! The metadata of all the collections without the documents is exported to one JSON file
+//! by [`export_metadata`], to keep the collections and the indexes of several databases the same.
+//! It's in relaxed extended JSON sorted by the names, so it can be checked in and reviewed:
+//!
+//! ```json
+//! { "version": 1, "collections": [ { "collectionName": "books", "type": "collection", ... } ] }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use bson::{doc, Bson, Document};
+use indexmap::IndexMap;
+use crate::coll::collection_info::{IndexInfo, IndexKind};
+use crate::coll::{defaults, validator};
+use crate::options::{CreateCollectionOptions, IdStrategy, ListCollectionsOptions, TimeseriesOptions, ValidationAction};
+use crate::results::{CollectionInfo, DumpResult, ImportMetadataResult, RestoreResult};
+use crate::errors::MetadataConflictError;
+use crate::{Collection, CollectionT, Database, Error, IndexModel, Result};
+
+const BSON_EXTENSION: &str = ".bson";
+const METADATA_EXTENSION: &str = ".metadata.json";
+const ID_INDEX_NAME: &str = "_id_";
+const INSERT_BATCH_SIZE: usize = 1000;
+const METADATA_VERSION: i32 = 1;
+
+
+pub(crate) fn dump(db: &Database, path: &Path) -> Result<DumpResult> {
+    fs::create_dir_all(path)?;
+
+    // the documents are read in one transaction to dump a consistent snapshot
+    let txn = db.start_transaction()?;
+    let mut result = DumpResult::default();
+    for info in db.list_collections(ListCollectionsOptions::default())? {
+        let indexes = db.index_infos(&txn, &info.name)?;
+        let metadata = Bson::Document(metadata_doc(&info.name, &info.options, &indexes)).into_canonical_extjson();
+        fs::write(
+            path.join(format!("{}{}", info.name, METADATA_EXTENSION)),
+            serde_json::to_vec(&metadata).map_err(|err| Error::InvalidDump(err.to_string()))?,
+        )?;
+        result.collections += 1;
+
+        if info.options.view_on.is_some() {
+            continue;
+        }
+
+        let file = File::create(path.join(format!("{}{}", info.name, BSON_EXTENSION)))?;
+        let mut writer = BufWriter::new(file);
+        for doc in txn.collection::<Document>(&info.name).find(doc! {}).run()? {
+            doc?.to_writer(&mut writer)?;
+            result.documents += 1;
+        }
+        writer.flush()?;
+    }
+    txn.rollback()?;
+
+    Ok(result)
+}
+
+fn collection_type(options: &CreateCollectionOptions) -> &'static str {
+    if options.view_on.is_some() {
+        "view"
+    } else if options.timeseries.is_some() {
+        "timeseries"
+    } else {
+        "collection"
+    }
+}
+
+fn metadata_doc(name: &str, options: &CreateCollectionOptions, indexes: &IndexMap<String, IndexInfo>) -> Document {
+    let mut options_doc = Document::new();
+    if let Some(view_on) = &options.view_on {
+        options_doc.insert("viewOn", view_on.clone());
+        options_doc.insert("pipeline", options.pipeline.clone().unwrap_or_default());
+    }
+    if options.capped.unwrap_or(false) {
+        options_doc.insert("capped", true);
+        if let Some(size) = options.size {
+            options_doc.insert("size", size as i64);
+        }
+        if let Some(max) = options.max {
+            options_doc.insert("max", max as i64);
+        }
+    }
+    if let Some(timeseries) = &options.timeseries {
+        let mut timeseries_doc = doc! {
+            "timeField": timeseries.time_field.clone(),
+        };
+        if let Some(meta_field) = &timeseries.meta_field {
+            timeseries_doc.insert("metaField", meta_field.clone());
+        }
+        if let Some(granularity) = &timeseries.granularity {
+            timeseries_doc.insert("granularity", bson::to_bson(granularity).unwrap());
+        }
+        options_doc.insert("timeseries", timeseries_doc);
+    }
+    if let Some(validator) = &options.validator {
+        options_doc.insert("validator", validator.clone());
+        let action = options.validation_action.unwrap_or_default();
+        options_doc.insert("validationAction", bson::to_bson(&action).unwrap());
+    }
+
+    let mut indexes_array = Vec::new();
+    if options.view_on.is_none() {
+        indexes_array.push(Bson::Document(doc! {
+            "v": 2,
+            "key": { "_id": 1 },
+            "name": ID_INDEX_NAME,
+        }));
+    }
+    for (name, index) in indexes {
+        indexes_array.push(Bson::Document(index_doc(name, index)));
+    }
+
+    let mut result = doc! {
+        "indexes": indexes_array,
+        "collectionName": name,
+        "type": collection_type(options),
+        "options": options_doc,
+    };
+
+    let mut polodb_doc = Document::new();
+    if let Some(defaults) = &options.defaults {
+        polodb_doc.insert("defaults", defaults.clone());
+    }
+    if let Some(id_strategy) = &options.id_strategy {
+        polodb_doc.insert("idStrategy", bson::to_bson(id_strategy).unwrap());
+    }
+    if let Some(partition_key) = &options.partition_key {
+        polodb_doc.insert("partitionKey", partition_key.clone());
+    }
+    if let Some(key_ordering) = &options.key_ordering {
+        polodb_doc.insert("keyOrdering", key_ordering.clone());
+    }
+    if !polodb_doc.is_empty() {
+        result.insert("polodb", polodb_doc);
+    }
+
+    result
+}
+
+fn index_doc(name: &str, index: &IndexInfo) -> Document {
+    let mut key = Document::new();
+    for (field, order) in &index.keys {
+        match index.kind.spec_name() {
+            Some(spec_name) => key.insert(field.clone(), spec_name),
+            None => key.insert(field.clone(), *order as i32),
+        };
+    }
+    let mut index_doc = doc! {
+        "v": 2,
+        "key": key,
+        "name": name,
+    };
+    if index.is_unique() {
+        index_doc.insert("unique", true);
+    }
+    if let Some(options) = index.options.as_ref().filter(|_| index.is_text()) {
+        if let Some(analyzer) = &options.analyzer {
+            index_doc.insert("analyzer", analyzer.clone());
+        }
+        if let Some(stop_words) = &options.stop_words {
+            index_doc.insert("stopWords", stop_words.clone());
+        }
+    }
+    if let Some(options) = index.options.as_ref().filter(|_| index.kind == IndexKind::Vector) {
+        if let Some(dimensions) = options.dimensions {
+            index_doc.insert("dimensions", dimensions as i64);
+        }
+        if let Some(similarity) = &options.similarity {
+            index_doc.insert("similarity", similarity.clone());
+        }
+        if let Some(lists) = options.lists {
+            index_doc.insert("lists", lists as i64);
+        }
+    }
+    index_doc
+}
+
+/// The collection to restore, read from the files of the dump.
+struct DumpedCollection {
+    name: String,
+    options: CreateCollectionOptions,
+    indexes: Vec<Document>,
+}
+
+impl DumpedCollection {
+
+    fn read(path: &Path, name: &str) -> Result<DumpedCollection> {
+        let metadata_path = path.join(format!("{}{}", name, METADATA_EXTENSION));
+        if !metadata_path.exists() {
+            return Ok(DumpedCollection {
+                name: name.to_string(),
+                options: CreateCollectionOptions::default(),
+                indexes: Vec::new(),
+            });
+        }
+
+        let invalid = |message: String| Error::InvalidDump(format!("{}: {}", metadata_path.display(), message));
+        let content = fs::read(&metadata_path)?;
+        let json: serde_json::Value = serde_json::from_slice(&content)
+            .map_err(|err| invalid(err.to_string()))?;
+        let metadata = match Bson::try_from(json).map_err(|err| invalid(err.to_string()))? {
+            Bson::Document(doc) => doc,
+            _ => return Err(invalid("the metadata is not an object".to_string())),
+        };
+        DumpedCollection::from_metadata(name, &metadata).map_err(invalid)
+    }
+
+    /// Parse the metadata of a collection written by [`metadata_doc`].
+    fn from_metadata(name: &str, metadata: &Document) -> std::result::Result<DumpedCollection, String> {
+        let options_doc = match metadata.get("options") {
+            Some(Bson::Document(doc)) => doc.clone(),
+            None => Document::new(),
+            Some(_) => return Err("options is not an object".to_string()),
+        };
+        let mut options = CreateCollectionOptions::default();
+        if let Some(view_on) = options_doc.get("viewOn") {
+            let view_on = view_on.as_str().ok_or_else(|| "viewOn is not a string".to_string())?;
+            options.view_on = Some(view_on.to_string());
+            let pipeline = match options_doc.get("pipeline") {
+                Some(Bson::Array(stages)) => stages.iter()
+                    .map(|stage| stage.as_document().cloned())
+                    .collect::<Option<Vec<Document>>>()
+                    .ok_or_else(|| "a stage of the pipeline is not an object".to_string())?,
+                None => Vec::new(),
+                Some(_) => return Err("pipeline is not an array".to_string()),
+            };
+            options.pipeline = Some(pipeline);
+        }
+        if options_doc.get_bool("capped").unwrap_or(false) {
+            options.capped = Some(true);
+            options.size = get_u64(&options_doc, "size")?;
+            options.max = get_u64(&options_doc, "max")?;
+        }
+        if let Some(timeseries) = options_doc.get("timeseries") {
+            let timeseries = bson::from_bson::<TimeseriesOptions>(timeseries.clone())
+                .map_err(|err| format!("invalid timeseries: {}", err))?;
+            options.timeseries = Some(timeseries);
+        }
+        if let Some(validator) = options_doc.get("validator") {
+            let validator = validator.as_document().ok_or_else(|| "validator is not an object".to_string())?;
+            options.validator = Some(validator.clone());
+            if let Some(action) = options_doc.get("validationAction") {
+                let action = bson::from_bson::<ValidationAction>(action.clone())
+                    .map_err(|err| format!("invalid validationAction: {}", err))?;
+                options.validation_action = Some(action);
+            }
+        }
+
+        if let Ok(polodb_doc) = metadata.get_document("polodb") {
+            if let Some(defaults) = polodb_doc.get("defaults") {
+                let defaults = defaults.as_document().ok_or_else(|| "defaults is not an object".to_string())?;
+                options.defaults = Some(defaults.clone());
+            }
+            if let Some(id_strategy) = polodb_doc.get("idStrategy") {
+                let id_strategy = bson::from_bson::<IdStrategy>(id_strategy.clone())
+                    .map_err(|err| format!("invalid idStrategy: {}", err))?;
+                options.id_strategy = Some(id_strategy);
+            }
+            if let Some(partition_key) = polodb_doc.get("partitionKey") {
+                let partition_key = partition_key.as_str().ok_or_else(|| "partitionKey is not a string".to_string())?;
+                options.partition_key = Some(partition_key.to_string());
+            }
+            if let Some(key_ordering) = polodb_doc.get("keyOrdering") {
+                let key_ordering = key_ordering.as_str().ok_or_else(|| "keyOrdering is not a string".to_string())?;
+                options.key_ordering = Some(key_ordering.to_string());
+            }
+        }
+
+        let indexes = match metadata.get("indexes") {
+            Some(Bson::Array(indexes)) => indexes.iter()
+                .map(|index| index.as_document().cloned())
+                .collect::<Option<Vec<Document>>>()
+                .ok_or_else(|| "an index is not an object".to_string())?,
+            None => Vec::new(),
+            Some(_) => return Err("indexes is not an array".to_string()),
+        };
+
+        Ok(DumpedCollection {
+            name: name.to_string(),
+            options,
+            indexes,
+        })
+    }
+
+    fn is_view(&self) -> bool {
+        self.options.view_on.is_some()
+    }
+
+}
+
+fn get_u64(doc: &Document, key: &str) -> std::result::Result<Option<u64>, String> {
+    let value = match doc.get(key) {
+        Some(Bson::Int32(value)) => *value as i64,
+        Some(Bson::Int64(value)) => *value,
+        Some(Bson::Double(value)) if value.fract() == 0.0 => *value as i64,
+        None => return Ok(None),
+        Some(_) => return Err(format!("{} is not an integer", key)),
+    };
+    if value < 0 {
+        return Err(format!("{} can't be negative", key));
+    }
+    Ok(Some(value as u64))
+}
+
+/// The names of the collections in the dump, sorted.
+fn list_dumped_names(path: &Path) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let file_name = entry?.file_name();
+        let file_name = match file_name.to_str() {
+            Some(file_name) => file_name,
+            None => continue,
+        };
+        if file_name.ends_with(".gz") {
+            return Err(Error::InvalidDump(format!("the compressed file '{}' is not supported", file_name)));
+        }
+        let name = match file_name.strip_suffix(METADATA_EXTENSION) {
+            Some(name) => name,
+            None => match file_name.strip_suffix(BSON_EXTENSION) {
+                Some(name) => name,
+                None => continue,
+            },
+        };
+        if name.starts_with("system.") || names.iter().any(|n| n == name) {
+            continue;
+        }
+        names.push(name.to_string());
+    }
+    names.sort();
+    Ok(names)
+}
+
+pub(crate) fn restore(db: &Database, path: &Path) -> Result<RestoreResult> {
+    let mut collections = Vec::new();
+    for name in list_dumped_names(path)? {
+        collections.push(DumpedCollection::read(path, &name)?);
+    }
+
+    // check the whole dump before restoring anything
+    let existing: HashSet<String> = db.list_collection_names()?.into_iter().collect();
+    for collection in &collections {
+        if existing.contains(&collection.name) {
+            return Err(Error::CollectionAlreadyExits(collection.name.clone()));
+        }
+        validator::validator_info_from_options(&collection.options)?;
+        if let Some(defaults) = &collection.options.defaults {
+            defaults::validate_defaults(defaults)?;
+        }
+    }
+
+    let mut result = RestoreResult::default();
+    let (views, collections): (Vec<DumpedCollection>, Vec<DumpedCollection>) = collections
+        .into_iter()
+        .partition(DumpedCollection::is_view);
+    for collection in collections {
+        restore_collection(db, path, collection, &mut result)?;
+    }
+
+    // a view is created after its source if the source is a view in the dump too
+    let mut pending = views;
+    while !pending.is_empty() {
+        let pending_names: Vec<String> = pending.iter().map(|view| view.name.clone()).collect();
+        let (ready, blocked): (Vec<DumpedCollection>, Vec<DumpedCollection>) = pending
+            .into_iter()
+            .partition(|view| !pending_names.contains(view.options.view_on.as_ref().unwrap()));
+        // the views in a cycle are created in order
+        let (ready, blocked) = if ready.is_empty() {
+            (blocked, Vec::new())
+        } else {
+            (ready, blocked)
+        };
+        for view in ready {
+            db.create_collection_with_options(&view.name, view.options)?;
+            result.collections += 1;
+        }
+        pending = blocked;
+    }
+
+    Ok(result)
+}
+
+fn restore_collection(db: &Database, path: &Path, collection: DumpedCollection, result: &mut RestoreResult) -> Result<()> {
+    let DumpedCollection { name, mut options, indexes } = collection;
+
+    // the dumped documents are restored as they are, neither validated nor filled with the defaults
+    let validator = options.validator.take();
+    let validation_action = options.validation_action.take();
+    let defaults = options.defaults.take();
+    db.create_collection_with_options(&name, options)?;
+
+    let col = db.collection::<Document>(&name);
+    let bson_path = path.join(format!("{}{}", name, BSON_EXTENSION));
+    if bson_path.exists() {
+        if let Err(err) = restore_documents(&col, &bson_path, result) {
+            let _ = col.drop();
+            return Err(err);
+        }
+    }
+
+    for index in indexes {
+        let index_name = index.get_str("name").unwrap_or_default().to_string();
+        if index_name == ID_INDEX_NAME {
+            continue;
+        }
+        match IndexModel::from_spec(&index) {
+            Some(model) => {
+                col.create_index(model)?;
+                result.indexes += 1;
+            }
+            None => result.skipped_indexes.push(format!("{}.{}", name, index_name)),
+        }
+    }
+
+    if let Some(validator) = validator {
+        db.set_validator(&name, validator, validation_action.unwrap_or_default())?;
+    }
+    if let Some(defaults) = defaults {
+        db.set_defaults(&name, defaults)?;
+    }
+
+    result.collections += 1;
+    Ok(())
+}
+
+// each batch is inserted by its own transaction
+fn restore_documents(col: &Collection<Document>, bson_path: &Path, result: &mut RestoreResult) -> Result<()> {
+    let mut reader = BufReader::new(File::open(bson_path)?);
+    let mut batch = Vec::with_capacity(INSERT_BATCH_SIZE);
+    let mut restored = 0;
+    while !reader.fill_buf()?.is_empty() {
+        let doc = Document::from_reader(&mut reader)
+            .map_err(|err| Error::InvalidDump(format!("{}: {}", bson_path.display(), err)))?;
+        batch.push(doc);
+        if batch.len() == INSERT_BATCH_SIZE {
+            restored += batch.len() as u64;
+            col.insert_many(std::mem::take(&mut batch))?;
+        }
+    }
+    if !batch.is_empty() {
+        restored += batch.len() as u64;
+        col.insert_many(batch)?;
+    }
+    result.documents += restored;
+    Ok(())
+}
+
+pub(crate) fn export_metadata<W: Write>(db: &Database, writer: W) -> Result<()> {
+    let txn = db.start_transaction()?;
+    let mut infos = db.list_collections(ListCollectionsOptions::default())?;
+    infos.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut collections = Vec::with_capacity(infos.len());
+    for info in infos {
+        let mut indexes = db.index_infos(&txn, &info.name)?;
+        indexes.sort_keys();
+        collections.push(Bson::Document(metadata_doc(&info.name, &info.options, &indexes)));
+    }
+    txn.rollback()?;
+
+    let metadata = Bson::Document(doc! {
+        "version": METADATA_VERSION,
+        "collections": collections,
+    }).into_relaxed_extjson();
+    serde_json::to_writer_pretty(writer, &metadata).map_err(|err| {
+        if err.is_io() {
+            return Error::from(std::io::Error::from(err));
+        }
+        Error::InvalidMetadata(err.to_string())
+    })
+}
+
+/// The options of a collection which are fixed when it's created.
+fn creation_doc(options: &CreateCollectionOptions) -> Document {
+    let options = CreateCollectionOptions {
+        validator: None,
+        validation_action: None,
+        defaults: None,
+        ..options.clone()
+    };
+    let mut doc = metadata_doc("", &options, &IndexMap::new());
+    doc.remove("indexes");
+    doc.remove("collectionName");
+    doc
+}
+
+/// The index of the metadata normalized to compare it with an existing one.
+fn normalized_index(index: &Document) -> Option<(String, IndexModel, Document)> {
+    let name = index.get_str("name").ok()?.to_string();
+    let model = IndexModel::from_spec(index)?;
+    let normalized = bson::to_document(&model).ok()?;
+    Some((name, model, normalized))
+}
+
+struct ImportedCollection {
+    collection: DumpedCollection,
+    indexes: Vec<(String, IndexModel, Document)>,
+    existing: Option<CollectionInfo>,
+}
+
+pub(crate) fn import_metadata<R: Read>(db: &Database, reader: R) -> Result<ImportMetadataResult> {
+    let json: serde_json::Value = serde_json::from_reader(reader).map_err(|err| {
+        if err.is_io() {
+            return Error::from(std::io::Error::from(err));
+        }
+        Error::InvalidMetadata(err.to_string())
+    })?;
+    let metadata = match Bson::try_from(json).map_err(|err| Error::InvalidMetadata(err.to_string()))? {
+        Bson::Document(doc) => doc,
+        _ => return Err(Error::InvalidMetadata("the metadata is not an object".to_string())),
+    };
+    match metadata.get("version") {
+        Some(Bson::Int32(METADATA_VERSION)) => (),
+        Some(version) => return Err(Error::InvalidMetadata(format!("unsupported version {}", version))),
+        None => return Err(Error::InvalidMetadata("version is missing".to_string())),
+    }
+    let collection_docs = metadata.get_array("collections")
+        .map_err(|_| Error::InvalidMetadata("collections is not an array".to_string()))?;
+
+    // check the whole metadata before changing anything
+    let mut existing: HashMap<String, CollectionInfo> = db.list_collections(ListCollectionsOptions::default())?
+        .into_iter()
+        .map(|info| (info.name.clone(), info))
+        .collect();
+    let mut imported = Vec::with_capacity(collection_docs.len());
+    let mut names = HashSet::new();
+    for collection_doc in collection_docs {
+        let collection_doc = collection_doc.as_document()
+            .ok_or_else(|| Error::InvalidMetadata("a collection is not an object".to_string()))?;
+        let name = collection_doc.get_str("collectionName")
+            .map_err(|_| Error::InvalidMetadata("collectionName is not a string".to_string()))?;
+        let invalid = |message: String| Error::InvalidMetadata(format!("{}: {}", name, message));
+        if !names.insert(name.to_string()) {
+            return Err(invalid("the collection is duplicated".to_string()));
+        }
+        let mut collection = DumpedCollection::from_metadata(name, collection_doc).map_err(invalid)?;
+        validator::validator_info_from_options(&collection.options)?;
+        if let Some(defaults) = &collection.options.defaults {
+            defaults::validate_defaults(defaults)?;
+        }
+
+        let mut indexes = Vec::new();
+        for index in std::mem::take(&mut collection.indexes) {
+            if index.get_str("name") == Ok(ID_INDEX_NAME) {
+                continue;
+            }
+            let index = normalized_index(&index)
+                .ok_or_else(|| invalid(format!("the index {} is not supported", index)))?;
+            indexes.push(index);
+        }
+
+        let existing = existing.remove(name);
+        if let Some(info) = &existing {
+            let is_view = info.options.view_on.is_some();
+            if is_view != collection.is_view()
+                || (!is_view && creation_doc(&info.options) != creation_doc(&collection.options)) {
+                return Err(MetadataConflictError {
+                    collection: name.to_string(),
+                    reason: format!(
+                        "it's created as {} but the metadata is {}",
+                        Bson::Document(creation_doc(&info.options)).into_relaxed_extjson(),
+                        Bson::Document(creation_doc(&collection.options)).into_relaxed_extjson(),
+                    ),
+                }.into());
+            }
+        }
+        imported.push(ImportedCollection { collection, indexes, existing });
+    }
+
+    let mut result = ImportMetadataResult::default();
+    let (views, collections): (Vec<ImportedCollection>, Vec<ImportedCollection>) = imported
+        .into_iter()
+        .partition(|imported| imported.collection.is_view());
+    for imported in collections {
+        import_collection(db, imported, &mut result)?;
+    }
+
+    // the changed views are recreated, a view is created after its source like restore
+    let mut pending = Vec::new();
+    for view in views {
+        match &view.existing {
+            Some(info) if creation_doc(&info.options) == creation_doc(&view.collection.options) => (),
+            Some(_) => {
+                db.collection::<Document>(&view.collection.name).drop()?;
+                result.updated_collections.push(view.collection.name.clone());
+                pending.push(view.collection);
+            }
+            None => {
+                result.created_collections.push(view.collection.name.clone());
+                pending.push(view.collection);
+            }
+        }
+    }
+    while !pending.is_empty() {
+        let pending_names: Vec<String> = pending.iter().map(|view| view.name.clone()).collect();
+        let (ready, blocked): (Vec<DumpedCollection>, Vec<DumpedCollection>) = pending
+            .into_iter()
+            .partition(|view| !pending_names.contains(view.options.view_on.as_ref().unwrap()));
+        let (ready, blocked) = if ready.is_empty() {
+            (blocked, Vec::new())
+        } else {
+            (ready, blocked)
+        };
+        for view in ready {
+            db.create_collection_with_options(&view.name, view.options)?;
+        }
+        pending = blocked;
+    }
+
+    Ok(result)
+}
+
+fn import_collection(db: &Database, imported: ImportedCollection, result: &mut ImportMetadataResult) -> Result<()> {
+    let ImportedCollection { collection: DumpedCollection { name, options, .. }, indexes, existing } = imported;
+    let mut existing_indexes = IndexMap::new();
+    match existing {
+        Some(info) => {
+            let mut updated = false;
+            let action = options.validation_action.unwrap_or_default();
+            if info.options.validator != options.validator || info.options.validation_action.unwrap_or_default() != action {
+                match options.validator {
+                    Some(validator) => db.set_validator(&name, validator, action)?,
+                    None => db.remove_validator(&name)?,
+                }
+                updated = true;
+            }
+            if info.options.defaults != options.defaults {
+                match options.defaults {
+                    Some(defaults) => db.set_defaults(&name, defaults)?,
+                    None => db.remove_defaults(&name)?,
+                }
+                updated = true;
+            }
+            if updated {
+                result.updated_collections.push(name.clone());
+            }
+            let txn = db.start_transaction()?;
+            existing_indexes = db.index_infos(&txn, &name)?;
+            txn.rollback()?;
+        }
+        None => {
+            db.create_collection_with_options(&name, options)?;
+            result.created_collections.push(name.clone());
+        }
+    }
+
+    let col = db.collection::<Document>(&name);
+    let mut kept = HashSet::new();
+    for (index_name, index) in &existing_indexes {
+        let unchanged = normalized_index(&index_doc(index_name, index))
+            .is_some_and(|(_, _, existing)| indexes.iter()
+                .any(|(name, _, normalized)| name == index_name && *normalized == existing));
+        if unchanged {
+            kept.insert(index_name.clone());
+        } else {
+            col.drop_index(index_name)?;
+            result.dropped_indexes.push(format!("{}.{}", name, index_name));
+        }
+    }
+    for (index_name, model, _) in indexes {
+        if kept.contains(&index_name) {
+            continue;
+        }
+        col.create_index(model)?;
+        result.created_indexes.push(format!("{}.{}", name, index_name));
+    }
+
+    Ok(())
+}
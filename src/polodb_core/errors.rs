@@ -18,6 +18,7 @@ use std::fmt;
 use std::io;
 use std::string::FromUtf8Error;
 use std::sync::PoisonError;
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Debug)]
@@ -142,6 +143,18 @@ pub struct DuplicateKeyError {
     pub ns: String,   // collection name
 }
 
+#[derive(Debug)]
+pub struct DocumentValidationError {
+    pub ns: String,     // collection name
+    pub reason: String,
+}
+
+#[derive(Debug)]
+pub struct MetadataConflictError {
+    pub collection: String,
+    pub reason: String,
+}
+
 #[derive(Debug)]
 pub struct RegexError {
     pub error: String,
@@ -149,7 +162,84 @@ pub struct RegexError {
     pub options: String,
 }
 
+#[derive(Debug)]
+pub struct CorruptionError {
+    pub message: String,
+    /// The file in which the corruption is detected, if known.
+    pub file: Option<String>,
+    /// The offset in the file of the corrupted block, if known.
+    pub offset: Option<u64>,
+}
+
+impl CorruptionError {
+
+    /// Parse an error message of RocksDB such as:
+    /// "Corruption: block checksum mismatch: ... in /path/000010.sst offset 0 size 36"
+    fn parse(message: String) -> CorruptionError {
+        let mut file = None;
+        let mut offset = None;
+        if let Some(in_pos) = message.rfind(" in ") {
+            let mut parts = message[in_pos + 4..].split_whitespace();
+            file = parts.next().map(|s| s.to_string());
+            if parts.next() == Some("offset") {
+                offset = parts.next().and_then(|s| s.parse::<u64>().ok());
+            }
+        }
+        CorruptionError {
+            message,
+            file,
+            offset,
+        }
+    }
+
+}
+
+impl fmt::Display for CorruptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(file) = &self.file {
+            write!(f, ", file: {}", file)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, ", offset: {}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+/// The category of an [`Error`], returned by [`Error::category`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorCategory {
+    /// The input is invalid, e.g. a malformed query or a document failing the validation.
+    Validation,
+    /// The collection, snapshot or file doesn't exist.
+    NotFound,
+    /// The data already exists or is changed concurrently, e.g. a duplicate key.
+    Conflict,
+    /// The files of the database are damaged.
+    Corruption,
+    /// An error of the file system, the storage or an external system.
+    Io,
+    /// A size limit or a quota is exceeded.
+    Limit,
+    /// The operation is not allowed in the current state, e.g. writing a read-only database.
+    InvalidState,
+    /// The operation is not supported.
+    Unsupported,
+    /// The operation is cancelled by [`crate::Database::kill_op`] or a timeout.
+    Cancelled,
+    Internal,
+    /// The user is not authenticated or not allowed to run the operation.
+    Unauthorized,
+}
+
+/// The errors of PoloDB.
+///
+/// Match on [`Error::code`] or [`Error::category`] to handle the errors programmatically,
+/// the messages may change between the versions.
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum Error {
     #[error("unexpected id type, expected: {0}, actual: {1}")]
     UnexpectedIdType(u8, u8),
@@ -216,6 +306,8 @@ pub enum Error {
     IllegalCollectionName(String),
     #[error("index name '{0}' is illegal")]
     IllegalIndexName(String),
+    #[error("database name '{0}' is illegal")]
+    IllegalDatabaseName(String),
     #[error("unexpected page header")]
     UnexpectedPageHeader,
     #[error("unexpected page type")]
@@ -274,13 +366,283 @@ pub enum Error {
     InvalidAggregationStage(Box<Document>),
     #[error("rocks db error: {0}")]
     RocksDbErr(String),
+    #[error("data corruption detected: {0}")]
+    Corruption(Box<CorruptionError>),
     #[error("$set value is not a document")]
     SetIsNotADocument,
     #[error("the field '{0}' is not a valid field name")]
     UpsertError(String),
+    #[error("the database is opened in read-only mode")]
+    ReadOnlyDatabase,
+    #[error("snapshot name '{0}' is illegal")]
+    IllegalSnapshotName(String),
+    #[error("snapshot '{0}' already exists")]
+    SnapshotAlreadyExists(String),
+    #[error("snapshot '{0}' not found")]
+    SnapshotNotFound(String),
+    #[error("the operation is not supported by the in-memory database")]
+    InMemoryNotSupported,
+    #[error("the snapshots and the backups are not supported by the database with a cold path")]
+    ColdPathNotSupported,
+    #[error("the size of the document {size} exceeds the limit {limit}")]
+    DocumentTooLarge {
+        size: u64,
+        limit: u64,
+    },
+    #[error("the documents of the capped collection '{0}' can't be updated or deleted")]
+    CappedCollectionImmutable(String),
+    #[error("the measurements of the time-series collection '{0}' can't be updated or deleted")]
+    TimeseriesCollectionImmutable(String),
+    #[error("the view '{0}' is read-only")]
+    ViewReadOnly(String),
+    #[error("file '{0}' not found")]
+    FileNotFound(String),
+    #[error("the chunks of file '{0}' are missing")]
+    FileChunksMissing(String),
+    #[error("the operation is cancelled")]
+    OperationCancelled,
+    #[error("the operation exceeded the time limit of {0:?}")]
+    MaxTimeExceeded(Duration),
+    #[error("the size of the database {size} exceeds the quota {limit}")]
+    QuotaExceeded { size: u64, limit: u64 },
+    #[error("the writes are stalled, try again later")]
+    WouldBlock,
+    #[error("document failed validation of collection '{}': {}", .0.ns, .0.reason)]
+    DocumentValidationFailed(Box<DocumentValidationError>),
+    #[error("the schema version {stored} of the database is newer than the latest migration {latest}")]
+    SchemaVersionMismatch { stored: u32, latest: u32 },
+    #[error("the format version {found} of the database is newer than the supported version {supported}, open it with a newer release")]
+    FormatVersionTooNew { found: u32, supported: u32 },
+    #[error("the id generator '{0}' is not registered")]
+    IdGeneratorNotFound(String),
+    #[error("the tokenizer '{0}' is not registered")]
+    TokenizerNotFound(String),
+    #[error("the key ordering '{0}' is not registered")]
+    KeyOrderingNotFound(String),
+    #[error("sequence name '{0}' is illegal")]
+    IllegalSequenceName(String),
+    #[error("invalid dump: {0}")]
+    InvalidDump(String),
+    #[error("invalid metadata: {0}")]
+    InvalidMetadata(String),
+    #[error("the collection '{}' conflicts with the metadata: {}", .0.collection, .0.reason)]
+    MetadataConflict(Box<MetadataConflictError>),
+    #[error("sqlite error: {0}")]
+    SqliteError(String),
+    #[error("arrow error: {0}")]
+    ArrowError(String),
+    #[error("replication error: {0}")]
+    ReplicationError(String),
+    #[error("sync error: {0}")]
+    SyncError(String),
+    #[error("write conflict: {0}")]
+    WriteConflict(String),
+    #[error("user '{0}' not found")]
+    UserNotFound(String),
+    #[error("user '{0}' already exists")]
+    UserAlreadyExists(String),
+    #[error("authentication failed")]
+    AuthenticationFailed,
+    #[error("not authorized: {0}")]
+    Unauthorized(String),
 }
 
 impl Error {
+    pub(crate) fn from_rocksdb(message: String) -> Error {
+        if message.starts_with("Corruption:") {
+            return Error::Corruption(Box::new(CorruptionError::parse(message)));
+        }
+        // the statuses of the conflicting transactions: Busy, TimedOut and TryAgain
+        if message.starts_with("Resource busy")
+            || message.starts_with("Operation timed out")
+            || message.starts_with("Operation failed. Try again.") {
+            return Error::WriteConflict(message);
+        }
+        // a write with no_slowdown is rejected when the writes are stalled
+        if message.starts_with("Result incomplete: Write stall") {
+            return Error::WouldBlock;
+        }
+        Error::RocksDbErr(message)
+    }
+
+    /// The stable numeric code of the error.
+    ///
+    /// A code is never changed or reused, the new errors get new codes.
+    /// The thousands of the code are the [`ErrorCategory`]: 1xxx validation,
+    /// 2xxx not found, 3xxx conflict, 4xxx corruption, 5xxx IO, 6xxx limit,
+    /// 7xxx invalid state, 8xxx unsupported, 9xxx cancelled and internal,
+    /// 10xxx unauthorized.
+    pub fn code(&self) -> u32 {
+        match self {
+            Error::UnexpectedIdType(_, _) => 1001,
+            Error::NotAValidKeyType(_) => 1002,
+            Error::InvalidField(_) => 1003,
+            Error::ValidationError(_) => 1004,
+            Error::InvalidOrderOfIndex(_) => 1005,
+            Error::FieldTypeUnexpected(_) => 1006,
+            Error::UnexpectedTypeForOp(_) => 1007,
+            Error::ParseError(_) => 1008,
+            Error::UTF8Err { .. } => 1009,
+            Error::BsonErr(_) => 1010,
+            Error::BsonDeErr(_) => 1011,
+            Error::DataHasNoPrimaryKey => 1012,
+            Error::IllegalCollectionName(_) => 1013,
+            Error::IllegalIndexName(_) => 1014,
+            Error::IllegalDatabaseName(_) => 1015,
+            Error::UnknownUpdateOperation(_) => 1016,
+            Error::IncrementNullField => 1017,
+            Error::UnableToUpdatePrimaryKey => 1018,
+            Error::CannotApplyOperation(_) => 1019,
+            Error::FromUtf8Error(_) => 1020,
+            Error::UnknownBsonElementType(_) => 1021,
+            Error::RegexError(_) => 1022,
+            Error::UnknownAggregationOperation(_) => 1023,
+            Error::InvalidAggregationStage(_) => 1024,
+            Error::SetIsNotADocument => 1025,
+            Error::UpsertError(_) => 1026,
+            Error::IllegalSnapshotName(_) => 1027,
+            Error::DocumentValidationFailed(_) => 1028,
+            Error::IllegalSequenceName(_) => 1029,
+            Error::InvalidDump(_) => 1030,
+            Error::SyncError(_) => 1031,
+            Error::InvalidMetadata(_) => 1032,
+
+            Error::CollectionNotFound(_) => 2001,
+            Error::SnapshotNotFound(_) => 2002,
+            Error::FileNotFound(_) => 2003,
+            Error::IdGeneratorNotFound(_) => 2004,
+            Error::UserNotFound(_) => 2005,
+            Error::TokenizerNotFound(_) => 2006,
+            Error::KeyOrderingNotFound(_) => 2007,
+
+            Error::IndexAlreadyExists(_) => 3001,
+            Error::DataExist(_) => 3002,
+            Error::CollectionAlreadyExits(_) => 3003,
+            Error::DuplicateKey(_) => 3004,
+            Error::SnapshotAlreadyExists(_) => 3005,
+            Error::WriteConflict(_) => 3006,
+            Error::Busy => 3007,
+            Error::DatabaseOccupied => 3008,
+            Error::UserAlreadyExists(_) => 3009,
+            Error::MetadataConflict(_) => 3010,
+
+            Error::DecodeEOF => 4001,
+            Error::DataOverflow => 4002,
+            Error::ChecksumMismatch => 4003,
+            Error::JournalPageSizeMismatch(_, _) => 4004,
+            Error::SaltMismatch => 4005,
+            Error::PageMagicMismatch(_) => 4006,
+            Error::ItemSizeGreaterThanExpected => 4007,
+            Error::MetaPageIdError => 4008,
+            Error::UnexpectedPageHeader => 4009,
+            Error::UnexpectedPageType => 4010,
+            Error::NotAValidDatabase => 4011,
+            Error::Corruption(_) => 4012,
+            Error::FileChunksMissing(_) => 4013,
+
+            Error::IOErr(_) => 5001,
+            Error::RocksDbErr(_) => 5002,
+            Error::SqliteError(_) => 5003,
+            Error::ArrowError(_) => 5004,
+            Error::ReplicationError(_) => 5005,
+
+            Error::DataSizeTooLarge(_, _) => 6001,
+            Error::PageSpaceNotEnough => 6002,
+            Error::BufferNotEnough(_) => 6003,
+            Error::DocumentTooLarge { .. } => 6004,
+            Error::QuotaExceeded { .. } => 6005,
+            Error::WouldBlock => 6006,
+
+            Error::CannotWriteDbWithoutTransaction => 7001,
+            Error::StartTransactionInAnotherTransaction => 7002,
+            Error::RollbackNotInTransaction => 7003,
+            Error::VmIsHalt => 7004,
+            Error::NoTransactionStarted => 7005,
+            Error::SessionOutdated => 7006,
+            Error::DbIsClosed => 7007,
+            Error::DbNotReady => 7008,
+            Error::ReadOnlyDatabase => 7009,
+            Error::CappedCollectionImmutable(_) => 7010,
+            Error::TimeseriesCollectionImmutable(_) => 7011,
+            Error::ViewReadOnly(_) => 7012,
+            Error::VersionMismatch(_) => 7013,
+            Error::SchemaVersionMismatch { .. } => 7014,
+            Error::FormatVersionTooNew { .. } => 7015,
+
+            Error::InMemoryNotSupported => 8001,
+            Error::OnlySupportSingleFieldIndexes(_) => 8002,
+            Error::OnlySupportsAscendingOrder(_) => 8003,
+            Error::ColdPathNotSupported => 8004,
+
+            Error::OperationCancelled => 9001,
+            Error::MaxTimeExceeded(_) => 9002,
+            Error::LockError => 9501,
+            Error::UnknownTransactionType => 9502,
+            Error::Multiple(_) => 9503,
+
+            Error::AuthenticationFailed => 10001,
+            Error::Unauthorized(_) => 10002,
+        }
+    }
+
+    /// The category of the error, [`Error::Multiple`] has the category of its first error.
+    pub fn category(&self) -> ErrorCategory {
+        if let Error::Multiple(errors) = self {
+            if let Some(first) = errors.first() {
+                return first.category();
+            }
+        }
+        match self.code() / 1000 {
+            1 => ErrorCategory::Validation,
+            2 => ErrorCategory::NotFound,
+            3 => ErrorCategory::Conflict,
+            4 => ErrorCategory::Corruption,
+            5 => ErrorCategory::Io,
+            6 => ErrorCategory::Limit,
+            7 => ErrorCategory::InvalidState,
+            8 => ErrorCategory::Unsupported,
+            9 if self.code() < 9500 => ErrorCategory::Cancelled,
+            10 => ErrorCategory::Unauthorized,
+            _ => ErrorCategory::Internal,
+        }
+    }
+
+    /// The collection the error is about, if known.
+    pub fn collection(&self) -> Option<&str> {
+        match self {
+            Error::CollectionNotFound(name)
+            | Error::IllegalCollectionName(name)
+            | Error::CollectionAlreadyExits(name)
+            | Error::CappedCollectionImmutable(name)
+            | Error::TimeseriesCollectionImmutable(name)
+            | Error::ViewReadOnly(name) => Some(name),
+            Error::DuplicateKey(err) => Some(&err.ns),
+            Error::DocumentValidationFailed(err) => Some(&err.ns),
+            Error::MetadataConflict(err) => Some(&err.collection),
+            _ => None,
+        }
+    }
+
+    /// The index the error is about, if known.
+    pub fn index(&self) -> Option<&str> {
+        match self {
+            Error::IllegalIndexName(name) | Error::IndexAlreadyExists(name) => Some(name),
+            Error::DuplicateKey(err) => Some(&err.name),
+            _ => None,
+        }
+    }
+
+    /// The field the error is about, if known.
+    pub fn field(&self) -> Option<&str> {
+        match self {
+            Error::InvalidField(err) => Some(&err.field_name),
+            Error::FieldTypeUnexpected(err) => Some(&err.field_name),
+            Error::CannotApplyOperation(err) => Some(&err.field_name),
+            Error::UpsertError(name) => Some(name),
+            _ => None,
+        }
+    }
+
     pub(crate) fn add(self, next: Error) -> Error {
         match self {
             Error::Multiple(mut result) => {
@@ -328,6 +690,18 @@ impl From<DuplicateKeyError> for Error {
     }
 }
 
+impl From<DocumentValidationError> for Error {
+    fn from(value: DocumentValidationError) -> Self {
+        Error::DocumentValidationFailed(Box::new(value))
+    }
+}
+
+impl From<MetadataConflictError> for Error {
+    fn from(value: MetadataConflictError) -> Self {
+        Error::MetadataConflict(Box::new(value))
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(value: io::Error) -> Self {
         Error::IOErr(Box::new(BtWrapper {
@@ -347,6 +721,65 @@ impl From<RegexError> for Error {
 mod tests {
     use crate::Error;
 
+    #[test]
+    fn test_parse_corruption_error() {
+        let err = Error::from_rocksdb(
+            "Corruption: block checksum mismatch: stored = 1, computed = 2, type = 4  in /tmp/db/000010.sst offset 128 size 36".to_string()
+        );
+        match err {
+            Error::Corruption(err) => {
+                assert_eq!(err.file.as_deref(), Some("/tmp/db/000010.sst"));
+                assert_eq!(err.offset, Some(128));
+            }
+            _ => panic!("unexpected error: {:?}", err),
+        }
+
+        let err = Error::from_rocksdb("IO error: No such file".to_string());
+        assert!(matches!(err, Error::RocksDbErr(_)));
+    }
+
+    #[test]
+    fn test_error_codes() {
+        use std::collections::HashSet;
+        use crate::errors::{DuplicateKeyError, ErrorCategory};
+
+        let err = Error::from(DuplicateKeyError {
+            name: "_id_".to_string(),
+            key: "1".to_string(),
+            ns: "users".to_string(),
+        });
+        assert_eq!(err.code(), 3004);
+        assert_eq!(err.category(), ErrorCategory::Conflict);
+        assert_eq!(err.collection(), Some("users"));
+        assert_eq!(err.index(), Some("_id_"));
+
+        let err = Error::from_rocksdb("Resource busy: ".to_string());
+        assert!(matches!(err, Error::WriteConflict(_)));
+        assert_eq!(err.category(), ErrorCategory::Conflict);
+
+        let err = Error::from_rocksdb("Result incomplete: Write stall".to_string());
+        assert!(matches!(err, Error::WouldBlock));
+        assert_eq!(err.category(), ErrorCategory::Limit);
+
+        assert_eq!(Error::ReadOnlyDatabase.category(), ErrorCategory::InvalidState);
+        assert_eq!(Error::OperationCancelled.category(), ErrorCategory::Cancelled);
+        assert_eq!(Error::MaxTimeExceeded(std::time::Duration::from_secs(1)).category(), ErrorCategory::Cancelled);
+        assert_eq!(Error::LockError.category(), ErrorCategory::Internal);
+        let err = Error::CollectionNotFound("a".to_string()).add(Error::LockError);
+        assert_eq!(err.category(), ErrorCategory::NotFound);
+
+        let errors = [
+            Error::CollectionNotFound(String::new()),
+            Error::SnapshotNotFound(String::new()),
+            Error::Busy,
+            Error::DatabaseOccupied,
+            Error::RocksDbErr(String::new()),
+            Error::DecodeEOF,
+        ];
+        let codes: HashSet<u32> = errors.iter().map(Error::code).collect();
+        assert_eq!(codes.len(), errors.len());
+    }
+
     #[test]
     fn print_value_size() {
         let size = std::mem::size_of::<Error>();
@@ -0,0 +1,194 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Export and import the documents as MongoDB Extended JSON,
+//! which keeps the BSON types of the values, e.g. `{ "$oid": "..." }`.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Write};
+use bson::{doc, Bson, Document};
+use serde::de::{Deserializer as _, SeqAccess, Visitor};
+use serde_json::Value;
+use crate::options::{ExportOptions, ExtJsonMode, ImportOptions};
+use crate::{CollectionT, Database, Error, Result, Transaction};
+
+const INSERT_BATCH_SIZE: usize = 1000;
+
+pub(crate) fn export_json<W: Write>(db: &Database, col_name: &str, mut writer: W, options: ExportOptions) -> Result<u64> {
+    let json_array = options.json_array.unwrap_or(false);
+    let mode = options.mode.unwrap_or_default();
+    let cursor = db.collection::<Document>(col_name)
+        .find(options.filter.unwrap_or_default())
+        .run()?;
+
+    if json_array {
+        writer.write_all(b"[")?;
+    }
+    let mut count: u64 = 0;
+    for doc in cursor {
+        let value = match mode {
+            ExtJsonMode::Relaxed => Bson::Document(doc?).into_relaxed_extjson(),
+            ExtJsonMode::Canonical => Bson::Document(doc?).into_canonical_extjson(),
+        };
+        if json_array && count > 0 {
+            writer.write_all(b",")?;
+        }
+        serde_json::to_writer(&mut writer, &value).map_err(json_error)?;
+        if !json_array {
+            writer.write_all(b"\n")?;
+        }
+        count += 1;
+    }
+    if json_array {
+        writer.write_all(b"]\n")?;
+    }
+    writer.flush()?;
+
+    Ok(count)
+}
+
+fn json_error(err: serde_json::Error) -> Error {
+    if err.is_io() {
+        return Error::from(std::io::Error::from(err));
+    }
+    Error::ParseError(err.to_string())
+}
+
+fn value_to_document(value: Value) -> Result<Document> {
+    match Bson::try_from(value).map_err(|err| Error::ParseError(err.to_string()))? {
+        Bson::Document(doc) => Ok(doc),
+        _ => Err(Error::ParseError("expect an object of a document".to_string())),
+    }
+}
+
+/// Inserts the parsed documents in batches into the collection of the transaction.
+struct Importer<'a> {
+    txn: &'a Transaction,
+    col_name: &'a str,
+    batch: Vec<Document>,
+    count: u64,
+}
+
+impl<'a> Importer<'a> {
+
+    fn push(&mut self, value: Value) -> Result<()> {
+        self.batch.push(value_to_document(value)?);
+        if self.batch.len() == INSERT_BATCH_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        self.count += self.batch.len() as u64;
+        self.txn.collection::<Document>(self.col_name).insert_many(std::mem::take(&mut self.batch))?;
+        Ok(())
+    }
+
+}
+
+/// Visits the elements of the top-level array one by one,
+/// so the array is not read into memory at once.
+struct ArrayVisitor<'a, 'b> {
+    importer: &'b mut Importer<'a>,
+    // the error of inserting, which can't be carried by the error of serde_json
+    error: Option<Error>,
+}
+
+impl<'de, 'a, 'b> Visitor<'de> for &mut ArrayVisitor<'a, 'b> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of documents")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<(), A::Error> {
+        while let Some(value) = seq.next_element::<Value>()? {
+            if let Err(err) = self.importer.push(value) {
+                self.error = Some(err);
+                return Err(serde::de::Error::custom("failed to import the document"));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn import_json<R: Read>(db: &Database, col_name: &str, reader: R, options: ImportOptions) -> Result<u64> {
+    let mut reader = BufReader::new(reader);
+    let json_array = match options.json_array {
+        Some(json_array) => json_array,
+        None => first_non_whitespace(&mut reader)? == Some(b'['),
+    };
+
+    // all the documents are imported, or none of them
+    let txn = db.start_transaction()?;
+    if options.drop.unwrap_or(false) {
+        txn.collection::<Document>(col_name).delete_many(doc! {})?;
+    }
+    let mut importer = Importer {
+        txn: &txn,
+        col_name,
+        batch: Vec::with_capacity(INSERT_BATCH_SIZE),
+        count: 0,
+    };
+
+    if json_array {
+        let mut deserializer = serde_json::Deserializer::from_reader(&mut reader);
+        let mut visitor = ArrayVisitor {
+            importer: &mut importer,
+            error: None,
+        };
+        let result = deserializer.deserialize_seq(&mut visitor);
+        if let Some(err) = visitor.error.take() {
+            return Err(err);
+        }
+        result.map_err(json_error)?;
+        deserializer.end().map_err(json_error)?;
+    } else {
+        // one document per line, or any whitespace between the documents
+        for value in serde_json::Deserializer::from_reader(&mut reader).into_iter::<Value>() {
+            importer.push(value.map_err(json_error)?)?;
+        }
+    }
+    importer.flush()?;
+    let count = importer.count;
+    txn.commit()?;
+
+    Ok(count)
+}
+
+fn first_non_whitespace<R: BufRead>(reader: &mut R) -> Result<Option<u8>> {
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(None);
+        }
+        match buf.iter().position(|b| !b.is_ascii_whitespace()) {
+            Some(index) => {
+                let first = buf[index];
+                reader.consume(index);
+                return Ok(Some(first));
+            }
+            None => {
+                let len = buf.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A storage shim to test the crash consistency of the workloads.
+//!
+//! A [`FaultInjector`] is attached to the database with
+//! [`crate::ConfigBuilder::set_fault_injector`]. It counts the commits
+//! and applies a [`Fault`] to the selected ones. The commits of opening the database,
+//! such as saving its format version, are not counted. After running the workload,
+//! close the database to simulate a crash, open it again without the injector,
+//! and check the invariants of the data.
+//!
+//! ```rust
+//! use polodb_core::{ConfigBuilder, Database, CollectionT};
+//! use polodb_core::bson::{doc, Document};
+//! use polodb_core::fault_injection::{Fault, FaultInjector};
+//!
+//! let path = std::env::temp_dir().join("fault-injection-doc-db");
+//! let _ = std::fs::remove_dir_all(&path);
+//!
+//! let injector = FaultInjector::new();
+//! injector.inject_at(1, Fault::DropWrite);
+//!
+//! let mut config = ConfigBuilder::new();
+//! config.set_fault_injector(injector.clone());
+//! {
+//!     let db = Database::open_path_with_config(&path, config.take()).unwrap();
+//!     let col = db.collection::<Document>("test");
+//!     col.insert_one(doc! { "_id": 1 }).unwrap();
+//!     col.insert_one(doc! { "_id": 2 }).unwrap();
+//! }
+//!
+//! let db = Database::open_path(&path).unwrap();
+//! assert_eq!(db.collection::<Document>("test").count_documents().unwrap(), 1);
+//! assert_eq!(injector.injected_count(), 1);
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use crate::WalOperation;
+
+/// The fault applied to a commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The commit reports success but nothing is written.
+    DropWrite,
+    /// Only the first `n` writes of the commit are written, like a torn write.
+    Truncate(usize),
+    /// The commit is held back and written after the next commit.
+    /// It's lost if the database is closed before the next commit.
+    Reorder,
+}
+
+#[derive(Clone, Default)]
+pub struct FaultInjector {
+    inner: Arc<Mutex<FaultInjectorInner>>,
+}
+
+#[derive(Default)]
+struct FaultInjectorInner {
+    commit_count: u64,
+    injected_count: u64,
+    faults: HashMap<u64, Fault>,
+    held_back: Option<Vec<WalOperation>>,
+}
+
+impl FaultInjector {
+
+    pub fn new() -> FaultInjector {
+        FaultInjector::default()
+    }
+
+    /// Apply the fault to the `nth` commit, counted from 0
+    /// since the database is opened.
+    pub fn inject_at(&self, nth_commit: u64, fault: Fault) -> &Self {
+        let mut inner = self.inner.lock().unwrap();
+        inner.faults.insert(nth_commit, fault);
+        self
+    }
+
+    /// Remove all the pending faults.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.faults.clear();
+    }
+
+    /// The number of commits seen by the injector.
+    pub fn commit_count(&self) -> u64 {
+        self.inner.lock().unwrap().commit_count
+    }
+
+    /// The number of faults which have been applied.
+    pub fn injected_count(&self) -> u64 {
+        self.inner.lock().unwrap().injected_count
+    }
+
+    /// Apply the faults to the writes of a commit.
+    /// Return the batches to write in order.
+    pub(crate) fn on_commit(&self, operations: Vec<WalOperation>) -> Vec<Vec<WalOperation>> {
+        let mut inner = self.inner.lock().unwrap();
+        let nth_commit = inner.commit_count;
+        inner.commit_count += 1;
+
+        let held_back = inner.held_back.take();
+        let mut result = Vec::new();
+
+        match inner.faults.remove(&nth_commit) {
+            Some(Fault::DropWrite) => {
+                inner.injected_count += 1;
+            }
+            Some(Fault::Truncate(n)) => {
+                inner.injected_count += 1;
+                result.push(operations.into_iter().take(n).collect());
+            }
+            Some(Fault::Reorder) => {
+                inner.injected_count += 1;
+                inner.held_back = Some(operations);
+            }
+            None => {
+                result.push(operations);
+            }
+        }
+
+        if let Some(held_back) = held_back {
+            result.push(held_back);
+        }
+
+        result
+    }
+
+}
@@ -0,0 +1,750 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! A GraphQL layer over the collections, enabled by the `graphql` feature.
+//!
+//! [`GraphQlSchema`] generates an object type for every collection from its JSON Schema,
+//! e.g. the `$jsonSchema` of the validator, and resolves the queries and the mutations
+//! against the database. For the collection `books`, the schema has:
+//!
+//! ```graphql
+//! type Query {
+//!   books(filter: JSON, sort: JSON, skip: Int, limit: Int): [Books!]!
+//!   booksCount(filter: JSON): Int!
+//! }
+//!
+//! type Mutation {
+//!   insertBooks(document: JSON!): ID!
+//!   updateBooks(filter: JSON!, update: JSON!, many: Boolean, upsert: Boolean): UpdateResult!
+//!   deleteBooks(filter: JSON!, many: Boolean): Int!
+//! }
+//! ```
+//!
+//! The `filter`, `sort`, `update` and `document` arguments take the same documents as
+//! the Rust API. The keys like `$gt` are not valid GraphQL names, pass the documents with
+//! operators as variables. The collections without a JSON Schema only expose `_id`
+//! and the whole `document`. A mutation runs in one transaction, which is rolled back
+//! when a field fails.
+//!
+//! The introspection queries and the subscriptions are not supported,
+//! use [`GraphQlSchema::sdl`] to feed the schema to the tools.
+//!
+//! ```rust
+//! use polodb_core::{Database, CollectionT};
+//! use polodb_core::bson::doc;
+//! use polodb_core::graphql::{GraphQlRequest, GraphQlSchema};
+//!
+//! let db = Database::open_memory().unwrap();
+//! let schema = GraphQlSchema::builder()
+//!     .collection("books", doc! {
+//!         "bsonType": "object",
+//!         "required": ["title"],
+//!         "properties": {
+//!             "title": { "bsonType": "string" },
+//!             "year": { "bsonType": "int" },
+//!         },
+//!     })
+//!     .build()
+//!     .unwrap();
+//!
+//! schema.execute(&db, &GraphQlRequest::new(r#"
+//!     mutation { insertBooks(document: { title: "1984", year: 1949 }) }
+//! "#));
+//!
+//! let response = schema.execute(&db, &GraphQlRequest::new(r#"
+//!     query Classics($filter: JSON) { books(filter: $filter) { title } }
+//! "#).variables(doc! { "filter": { "year": { "$lt": 1950 } } }));
+//! assert_eq!(response, doc! { "data": { "books": [{ "title": "1984" }] } });
+//! ```
+
+mod parser;
+mod schema;
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use bson::{doc, Bson, Document};
+use indexmap::IndexMap;
+use crate::options::UpdateOptions;
+use crate::{CollectionT, Database, Result, Transaction};
+use parser::{ExecutableDocument, Field, Fragment, OperationKind, Selection, TypeRef, Value};
+use schema::{CollectionDef, FieldDef, FieldSource, ObjectType, RootField, SchemaTypes};
+
+/// The GraphQL schema generated from the collections.
+pub struct GraphQlSchema {
+    collections: Vec<CollectionDef>,
+    types: SchemaTypes,
+}
+
+impl GraphQlSchema {
+
+    pub fn builder() -> GraphQlSchemaBuilder {
+        GraphQlSchemaBuilder::default()
+    }
+
+    /// Generate the schema of all the collections in `db`, typed by the `$jsonSchema`
+    /// of their validators. The views are read-only.
+    pub fn from_database(db: &Database) -> Result<GraphQlSchema> {
+        let mut collections = Vec::new();
+        for info in db.list_collections(Default::default())? {
+            let json_schema = info.options.validator
+                .as_ref()
+                .and_then(|validator| validator.get_document("$jsonSchema").ok())
+                .cloned();
+            collections.push(CollectionDef {
+                name: info.name,
+                json_schema,
+                read_only: info.options.view_on.is_some(),
+            });
+        }
+        GraphQlSchema::new(collections)
+    }
+
+    fn new(collections: Vec<CollectionDef>) -> Result<GraphQlSchema> {
+        let types = SchemaTypes::build(&collections)?;
+        Ok(GraphQlSchema {
+            collections,
+            types,
+        })
+    }
+
+    /// The schema in the GraphQL schema definition language.
+    pub fn sdl(&self) -> String {
+        self.types.sdl()
+    }
+
+    /// Execute `request` and return the response, i.e. `{ "data": ..., "errors": [...] }`.
+    pub fn execute(&self, db: &Database, request: &GraphQlRequest) -> Document {
+        let document = match parser::parse_document(&request.query) {
+            Ok(document) => document,
+            Err(err) => return error_response(err.to_string()),
+        };
+        let mut executor = Executor {
+            schema: self,
+            fragments: &document.fragments,
+            variables: Document::new(),
+            errors: Vec::new(),
+        };
+        let data = executor.execute(db, &document, request);
+        let mut response = Document::new();
+        if let Some(data) = data {
+            response.insert("data", data);
+        }
+        if !executor.errors.is_empty() {
+            response.insert("errors", executor.errors);
+        }
+        response
+    }
+
+}
+
+#[derive(Default)]
+pub struct GraphQlSchemaBuilder {
+    collections: Vec<CollectionDef>,
+}
+
+impl GraphQlSchemaBuilder {
+
+    /// Expose the collection `name` with the fields of `json_schema`.
+    /// The properties which are not valid GraphQL names are left out.
+    pub fn collection<T: Into<String>>(mut self, name: T, json_schema: Document) -> Self {
+        self.collections.push(CollectionDef {
+            name: name.into(),
+            json_schema: Some(json_schema),
+            read_only: false,
+        });
+        self
+    }
+
+    /// Expose the collection `name` with the `_id` and the whole `document` as JSON.
+    pub fn untyped_collection<T: Into<String>>(mut self, name: T) -> Self {
+        self.collections.push(CollectionDef {
+            name: name.into(),
+            json_schema: None,
+            read_only: false,
+        });
+        self
+    }
+
+    pub fn build(self) -> Result<GraphQlSchema> {
+        GraphQlSchema::new(self.collections)
+    }
+
+}
+
+/// A GraphQL request, the same as the body of the GraphQL requests over HTTP.
+#[derive(Debug, Clone)]
+pub struct GraphQlRequest {
+    pub query: String,
+    /// The operation to execute when the query has several operations.
+    pub operation_name: Option<String>,
+    pub variables: Document,
+}
+
+impl GraphQlRequest {
+
+    pub fn new<T: Into<String>>(query: T) -> GraphQlRequest {
+        GraphQlRequest {
+            query: query.into(),
+            operation_name: None,
+            variables: Document::new(),
+        }
+    }
+
+    pub fn operation_name<T: Into<String>>(mut self, operation_name: T) -> Self {
+        self.operation_name = Some(operation_name.into());
+        self
+    }
+
+    pub fn variables(mut self, variables: Document) -> Self {
+        self.variables = variables;
+        self
+    }
+
+}
+
+fn error_response(message: String) -> Document {
+    doc! {
+        "errors": [{ "message": message }],
+    }
+}
+
+type FieldGroups<'a> = IndexMap<&'a str, Vec<&'a Field>>;
+
+struct Executor<'a> {
+    schema: &'a GraphQlSchema,
+    fragments: &'a HashMap<String, Fragment>,
+    variables: Document,
+    errors: Vec<Document>,
+}
+
+impl<'a> Executor<'a> {
+
+    fn error(&mut self, message: String, path: &[Bson]) {
+        let mut error = doc! { "message": message };
+        if !path.is_empty() {
+            error.insert("path", path.to_vec());
+        }
+        self.errors.push(error);
+    }
+
+    fn execute(&mut self, db: &Database, document: &'a ExecutableDocument, request: &GraphQlRequest) -> Option<Bson> {
+        let operation = match &request.operation_name {
+            Some(name) => document.operations.iter().find(|op| op.name.as_deref() == Some(name.as_str())),
+            None if document.operations.len() == 1 => document.operations.first(),
+            None => {
+                self.error("Must provide the operation name if the query contains several operations.".to_string(), &[]);
+                return None;
+            }
+        };
+        let operation = match operation {
+            Some(operation) => operation,
+            None => {
+                self.error(format!("Unknown operation named \"{}\".", request.operation_name.as_deref().unwrap_or_default()), &[]);
+                return None;
+            }
+        };
+
+        for definition in &operation.variables {
+            let value = match (request.variables.get(&definition.name), &definition.default) {
+                (Some(value), _) => value.clone(),
+                (None, Some(default)) => self.value_to_bson(default),
+                (None, None) => Bson::Null,
+            };
+            if value == Bson::Null && definition.ty.is_non_null() {
+                self.error(format!(
+                    "Variable \"${}\" of required type \"{}\" was not provided.",
+                    definition.name,
+                    definition.ty,
+                ), &[]);
+                return None;
+            }
+            self.variables.insert(definition.name.clone(), value);
+        }
+
+        let root_name = match operation.kind {
+            OperationKind::Query => schema::QUERY_TYPE,
+            OperationKind::Mutation => schema::MUTATION_TYPE,
+            OperationKind::Subscription => {
+                self.error("The subscriptions are not supported.".to_string(), &[]);
+                return None;
+            }
+        };
+        let root = match self.schema.types.object(root_name) {
+            Some(root) => root,
+            None => {
+                self.error("The schema has no mutation.".to_string(), &[]);
+                return None;
+            }
+        };
+        let mut visited = Vec::new();
+        self.validate_selections(root, &operation.selection_set, &mut visited);
+        if !self.errors.is_empty() {
+            return None;
+        }
+        let groups = self.collect_fields(root, &operation.selection_set);
+
+        let txn = match db.start_transaction() {
+            Ok(txn) => txn,
+            Err(err) => {
+                self.error(err.to_string(), &[]);
+                return None;
+            }
+        };
+        let mut data = Document::new();
+        let mut path = Vec::new();
+        for (key, fields) in groups {
+            path.push(Bson::String(key.to_string()));
+            let value = self.resolve_field(&txn, root, None, &fields, &mut path);
+            path.pop();
+            data.insert(key, value);
+        }
+
+        if operation.kind == OperationKind::Mutation {
+            let result = if self.errors.is_empty() {
+                txn.commit()
+            } else {
+                txn.rollback()
+            };
+            if let Err(err) = result {
+                self.error(err.to_string(), &[]);
+            }
+            if !self.errors.is_empty() {
+                return Some(Bson::Null);
+            }
+        }
+        Some(Bson::Document(data))
+    }
+
+    // the fields, the arguments and the selections are checked against the schema
+    // before running anything, even the fields which are not reached because of the data
+    fn validate_selections(&mut self, object: &ObjectType, selections: &'a [Selection], visited: &mut Vec<&'a str>) {
+        for selection in selections {
+            match selection {
+                Selection::Field(field) => {
+                    if field.name == "__typename" {
+                        continue;
+                    }
+                    let definition = match object.field(&field.name) {
+                        Some(definition) => definition,
+                        None => {
+                            self.error(format!("Cannot query field \"{}\" on type \"{}\".", field.name, object.name), &[]);
+                            continue;
+                        }
+                    };
+                    if let Err(message) = check_field(definition, field) {
+                        self.error(message, &[]);
+                        continue;
+                    }
+                    let schema = self.schema;
+                    if let Some(inner) = schema.types.object(definition.ty.type_name()) {
+                        self.validate_selections(inner, &field.selection_set, visited);
+                    }
+                }
+                Selection::FragmentSpread { name, .. } => {
+                    if visited.contains(&name.as_str()) {
+                        continue;
+                    }
+                    visited.push(name);
+                    let fragments = self.fragments;
+                    match fragments.get(name) {
+                        Some(fragment) if fragment.type_condition == object.name => {
+                            self.validate_selections(object, &fragment.selection_set, visited);
+                        }
+                        Some(_) => (),
+                        None => self.error(format!("Unknown fragment \"{}\".", name), &[]),
+                    }
+                }
+                Selection::InlineFragment { type_condition, selection_set, .. } => {
+                    if let Some(type_condition) = type_condition {
+                        if *type_condition != object.name {
+                            continue;
+                        }
+                    }
+                    self.validate_selections(object, selection_set, visited);
+                }
+            }
+        }
+    }
+
+    fn collect_fields(&mut self, object: &ObjectType, selections: &'a [Selection]) -> FieldGroups<'a> {
+        let mut groups = IndexMap::new();
+        let mut visited = Vec::new();
+        self.collect_fields_into(object, selections, &mut groups, &mut visited);
+        groups
+    }
+
+    fn collect_fields_into(
+        &mut self,
+        object: &ObjectType,
+        selections: &'a [Selection],
+        groups: &mut FieldGroups<'a>,
+        visited: &mut Vec<&'a str>,
+    ) {
+        for selection in selections {
+            match selection {
+                Selection::Field(field) => {
+                    if self.is_skipped(&field.directives) {
+                        continue;
+                    }
+                    groups.entry(field.response_key()).or_default().push(field);
+                }
+                Selection::FragmentSpread { name, directives } => {
+                    if self.is_skipped(directives) || visited.contains(&name.as_str()) {
+                        continue;
+                    }
+                    visited.push(name);
+                    let fragments = self.fragments;
+                    let fragment = match fragments.get(name) {
+                        Some(fragment) => fragment,
+                        None => continue,
+                    };
+                    if fragment.type_condition != object.name || self.is_skipped(&fragment.directives) {
+                        continue;
+                    }
+                    self.collect_fields_into(object, &fragment.selection_set, groups, visited);
+                }
+                Selection::InlineFragment { type_condition, directives, selection_set } => {
+                    if self.is_skipped(directives) {
+                        continue;
+                    }
+                    if let Some(type_condition) = type_condition {
+                        if *type_condition != object.name {
+                            continue;
+                        }
+                    }
+                    self.collect_fields_into(object, selection_set, groups, visited);
+                }
+            }
+        }
+    }
+
+    fn is_skipped(&mut self, directives: &[parser::Directive]) -> bool {
+        for directive in directives {
+            let condition = directive.arguments
+                .iter()
+                .find(|(name, _)| name == "if")
+                .map(|(_, value)| self.value_to_bson(value));
+            match (directive.name.as_str(), condition) {
+                ("skip", Some(Bson::Boolean(true))) => return true,
+                ("include", Some(Bson::Boolean(false))) => return true,
+                _ => (),
+            }
+        }
+        false
+    }
+
+    fn value_to_bson(&self, value: &Value) -> Bson {
+        match value {
+            Value::Variable(name) => self.variables.get(name).cloned().unwrap_or(Bson::Null),
+            Value::Int(value) => match i32::try_from(*value) {
+                Ok(value) => Bson::Int32(value),
+                Err(_) => Bson::Int64(*value),
+            },
+            Value::Float(value) => Bson::Double(*value),
+            Value::String(value) | Value::Enum(value) => Bson::String(value.clone()),
+            Value::Boolean(value) => Bson::Boolean(*value),
+            Value::Null => Bson::Null,
+            Value::List(items) => Bson::Array(items.iter().map(|item| self.value_to_bson(item)).collect()),
+            Value::Object(fields) => {
+                let mut doc = Document::new();
+                for (name, value) in fields {
+                    doc.insert(name.clone(), self.value_to_bson(value));
+                }
+                Bson::Document(doc)
+            }
+        }
+    }
+
+    fn coerce_arguments(&self, definition: &FieldDef, field: &Field) -> std::result::Result<HashMap<String, Bson>, String> {
+        let mut arguments = HashMap::new();
+        for (name, ty) in &definition.arguments {
+            let value = field.arguments
+                .iter()
+                .find(|(arg_name, _)| arg_name == name)
+                .map(|(_, value)| self.value_to_bson(value))
+                .unwrap_or(Bson::Null);
+            if value == Bson::Null {
+                if ty.is_non_null() {
+                    return Err(format!("Argument \"{}\" of required type \"{}\" was not provided.", name, ty));
+                }
+                continue;
+            }
+            arguments.insert(name.clone(), value);
+        }
+        Ok(arguments)
+    }
+
+    fn resolve_field(
+        &mut self,
+        txn: &Transaction,
+        object: &ObjectType,
+        parent: Option<&Document>,
+        fields: &[&'a Field],
+        path: &mut Vec<Bson>,
+    ) -> Bson {
+        let field = fields[0];
+        if field.name == "__typename" {
+            return Bson::String(object.name.clone());
+        }
+        let definition = match object.field(&field.name) {
+            Some(definition) => definition,
+            None => return Bson::Null,
+        };
+        let arguments = match self.coerce_arguments(definition, field) {
+            Ok(arguments) => arguments,
+            Err(message) => {
+                self.error(message, path);
+                return Bson::Null;
+            }
+        };
+        let value = match &definition.source {
+            FieldSource::Key(key) => parent.and_then(|doc| doc.get(key)).cloned().unwrap_or(Bson::Null),
+            FieldSource::Document => parent.cloned().map(Bson::Document).unwrap_or(Bson::Null),
+            FieldSource::Root(root) => match self.resolve_root(txn, *root, &arguments) {
+                Ok(value) => value,
+                Err(message) => {
+                    self.error(message, path);
+                    return Bson::Null;
+                }
+            },
+        };
+        self.complete_value(txn, &definition.ty, value, fields, path)
+    }
+
+    fn complete_value(
+        &mut self,
+        txn: &Transaction,
+        ty: &TypeRef,
+        value: Bson,
+        fields: &[&'a Field],
+        path: &mut Vec<Bson>,
+    ) -> Bson {
+        match ty {
+            TypeRef::NonNull(inner) => {
+                let result = self.complete_value(txn, inner, value, fields, path);
+                if result == Bson::Null {
+                    self.error(format!("Cannot return null for non-nullable field \"{}\".", fields[0].name), path);
+                }
+                result
+            }
+            _ if value == Bson::Null => Bson::Null,
+            TypeRef::List(inner) => match value {
+                Bson::Array(items) => {
+                    let mut result = Vec::with_capacity(items.len());
+                    for (index, item) in items.into_iter().enumerate() {
+                        path.push(Bson::Int64(index as i64));
+                        result.push(self.complete_value(txn, inner, item, fields, path));
+                        path.pop();
+                    }
+                    Bson::Array(result)
+                }
+                value => {
+                    self.error(format!("Expected a list, found {}.", value), path);
+                    Bson::Null
+                }
+            },
+            TypeRef::Named(name) => {
+                if schema::is_scalar(name) {
+                    return match coerce_scalar(name, &value) {
+                        Some(value) => value,
+                        None => {
+                            self.error(format!("{} cannot represent value: {}", name, value), path);
+                            Bson::Null
+                        }
+                    };
+                }
+                let schema = self.schema;
+                let object = match schema.types.object(name) {
+                    Some(object) => object,
+                    None => return Bson::Null,
+                };
+                let doc = match value {
+                    Bson::Document(doc) => doc,
+                    value => {
+                        self.error(format!("{} cannot represent value: {}", name, value), path);
+                        return Bson::Null;
+                    }
+                };
+                let mut groups: FieldGroups<'a> = IndexMap::new();
+                for field in fields {
+                    for (key, sub_fields) in self.collect_fields(object, &field.selection_set) {
+                        groups.entry(key).or_default().extend(sub_fields);
+                    }
+                }
+                let mut result = Document::new();
+                for (key, sub_fields) in groups {
+                    path.push(Bson::String(key.to_string()));
+                    let value = self.resolve_field(txn, object, Some(&doc), &sub_fields, path);
+                    path.pop();
+                    result.insert(key, value);
+                }
+                Bson::Document(result)
+            }
+        }
+    }
+
+    fn resolve_root(&self, txn: &Transaction, root: RootField, arguments: &HashMap<String, Bson>) -> std::result::Result<Bson, String> {
+        let result = match root {
+            RootField::Find(index) => {
+                let collection = txn.collection::<Document>(&self.schema.collections[index].name);
+                let mut find = collection.find(document_argument(arguments, "filter")?.unwrap_or_default());
+                if let Some(sort) = document_argument(arguments, "sort")? {
+                    find = find.sort(sort);
+                }
+                if let Some(skip) = u64_argument(arguments, "skip")? {
+                    find = find.skip(skip);
+                }
+                if let Some(limit) = u64_argument(arguments, "limit")? {
+                    find = find.limit(limit);
+                }
+                find.run()
+                    .and_then(|cursor| cursor.map(|doc| doc.map(Bson::Document)).collect::<Result<Vec<Bson>>>())
+                    .map(Bson::Array)
+            }
+            RootField::Count(index) => {
+                let collection = txn.collection::<Document>(&self.schema.collections[index].name);
+                let filter = document_argument(arguments, "filter")?.unwrap_or_default();
+                collection.find(filter).run().and_then(|cursor| {
+                    let mut count = 0i64;
+                    for doc in cursor {
+                        doc?;
+                        count += 1;
+                    }
+                    Ok(Bson::Int64(count))
+                })
+            }
+            RootField::Insert(index) => {
+                let collection = txn.collection::<Document>(&self.schema.collections[index].name);
+                let document = document_argument(arguments, "document")?.unwrap_or_default();
+                collection.insert_one(document).map(|result| result.inserted_id)
+            }
+            RootField::Update(index) => {
+                let collection = txn.collection::<Document>(&self.schema.collections[index].name);
+                let filter = document_argument(arguments, "filter")?.unwrap_or_default();
+                let update = document_argument(arguments, "update")?.unwrap_or_default();
+                let options = UpdateOptions::builder()
+                    .upsert(bool_argument(arguments, "upsert")?)
+                    .build();
+                let result = if bool_argument(arguments, "many")? {
+                    collection.update_many_with_options(filter, update, options)
+                } else {
+                    collection.update_one_with_options(filter, update, options)
+                };
+                result.map(|result| Bson::Document(doc! {
+                    "matchedCount": result.matched_count as i64,
+                    "modifiedCount": result.modified_count as i64,
+                }))
+            }
+            RootField::Delete(index) => {
+                let collection = txn.collection::<Document>(&self.schema.collections[index].name);
+                let filter = document_argument(arguments, "filter")?.unwrap_or_default();
+                let result = if bool_argument(arguments, "many")? {
+                    collection.delete_many(filter)
+                } else {
+                    collection.delete_one(filter)
+                };
+                result.map(|result| Bson::Int64(result.deleted_count as i64))
+            }
+        };
+        result.map_err(|err| err.to_string())
+    }
+
+}
+
+fn check_field(definition: &FieldDef, field: &Field) -> std::result::Result<(), String> {
+    for (name, _) in &field.arguments {
+        if !definition.arguments.iter().any(|(arg_name, _)| arg_name == name) {
+            return Err(format!("Unknown argument \"{}\" on field \"{}\".", name, definition.name));
+        }
+    }
+    for (name, ty) in &definition.arguments {
+        let provided = field.arguments.iter().any(|(arg_name, value)| arg_name == name && *value != Value::Null);
+        if ty.is_non_null() && !provided {
+            return Err(format!("Argument \"{}\" of required type \"{}\" was not provided.", name, ty));
+        }
+    }
+    let type_name = definition.ty.type_name();
+    if schema::is_scalar(type_name) && !field.selection_set.is_empty() {
+        return Err(format!(
+            "Field \"{}\" must not have a selection since type \"{}\" has no subfields.",
+            definition.name,
+            definition.ty,
+        ));
+    }
+    if !schema::is_scalar(type_name) && field.selection_set.is_empty() {
+        return Err(format!(
+            "Field \"{}\" of type \"{}\" must have a selection of subfields.",
+            definition.name,
+            definition.ty,
+        ));
+    }
+    Ok(())
+}
+
+fn document_argument(arguments: &HashMap<String, Bson>, name: &str) -> std::result::Result<Option<Document>, String> {
+    match arguments.get(name) {
+        Some(Bson::Document(doc)) => Ok(Some(doc.clone())),
+        Some(value) => Err(format!("Argument \"{}\" expects an object, found {}.", name, value)),
+        None => Ok(None),
+    }
+}
+
+fn u64_argument(arguments: &HashMap<String, Bson>, name: &str) -> std::result::Result<Option<u64>, String> {
+    let value = match arguments.get(name) {
+        Some(Bson::Int32(value)) => *value as i64,
+        Some(Bson::Int64(value)) => *value,
+        Some(value) => return Err(format!("Argument \"{}\" expects an Int, found {}.", name, value)),
+        None => return Ok(None),
+    };
+    u64::try_from(value)
+        .map(Some)
+        .map_err(|_| format!("Argument \"{}\" can't be negative.", name))
+}
+
+fn bool_argument(arguments: &HashMap<String, Bson>, name: &str) -> std::result::Result<bool, String> {
+    match arguments.get(name) {
+        Some(Bson::Boolean(value)) => Ok(*value),
+        Some(value) => Err(format!("Argument \"{}\" expects a Boolean, found {}.", name, value)),
+        None => Ok(false),
+    }
+}
+
+fn coerce_scalar(name: &str, value: &Bson) -> Option<Bson> {
+    let result = match (name, value) {
+        ("JSON", value) => value.clone(),
+        ("Int", Bson::Int32(value)) => Bson::Int32(*value),
+        ("Int", Bson::Int64(value)) => Bson::Int32(i32::try_from(*value).ok()?),
+        ("Int", Bson::Double(value)) if value.fract() == 0.0 && value.abs() <= i32::MAX as f64 => Bson::Int32(*value as i32),
+        ("Long", Bson::Int32(value)) => Bson::Int64(*value as i64),
+        ("Long", Bson::Int64(value)) => Bson::Int64(*value),
+        ("Long", Bson::Double(value)) if value.fract() == 0.0 && value.abs() < i64::MAX as f64 => Bson::Int64(*value as i64),
+        ("Float", Bson::Int32(value)) => Bson::Double(*value as f64),
+        ("Float", Bson::Int64(value)) => Bson::Double(*value as f64),
+        ("Float", Bson::Double(value)) => Bson::Double(*value),
+        ("Float", Bson::Decimal128(value)) => Bson::Double(value.to_string().parse().ok()?),
+        ("String", Bson::String(value)) => Bson::String(value.clone()),
+        ("String", Bson::Decimal128(value)) => Bson::String(value.to_string()),
+        ("String", Bson::ObjectId(value)) => Bson::String(value.to_hex()),
+        ("Boolean", Bson::Boolean(value)) => Bson::Boolean(*value),
+        ("ID", Bson::String(value)) => Bson::String(value.clone()),
+        ("ID", Bson::ObjectId(value)) => Bson::String(value.to_hex()),
+        ("ID", Bson::Int32(value)) => Bson::String(value.to_string()),
+        ("ID", Bson::Int64(value)) => Bson::String(value.to_string()),
+        ("DateTime", Bson::DateTime(value)) => Bson::String(value.try_to_rfc3339_string().ok()?),
+        ("DateTime", Bson::String(value)) => Bson::String(value.clone()),
+        _ => return None,
+    };
+    Some(result)
+}
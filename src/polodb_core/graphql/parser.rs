@@ -0,0 +1,781 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The parser of the executable documents of GraphQL: the operations and the fragments.
+
+use std::collections::HashMap;
+use std::fmt;
+use crate::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TypeRef {
+    Named(String),
+    List(Box<TypeRef>),
+    NonNull(Box<TypeRef>),
+}
+
+impl TypeRef {
+
+    pub(crate) fn named(name: &str) -> TypeRef {
+        TypeRef::Named(name.to_string())
+    }
+
+    pub(crate) fn non_null(self) -> TypeRef {
+        match self {
+            TypeRef::NonNull(_) => self,
+            _ => TypeRef::NonNull(Box::new(self)),
+        }
+    }
+
+    pub(crate) fn list(self) -> TypeRef {
+        TypeRef::List(Box::new(self))
+    }
+
+    pub(crate) fn is_non_null(&self) -> bool {
+        matches!(self, TypeRef::NonNull(_))
+    }
+
+    /// The name of the type without the lists and the non-null wrappers.
+    pub(crate) fn type_name(&self) -> &str {
+        match self {
+            TypeRef::Named(name) => name,
+            TypeRef::List(inner) | TypeRef::NonNull(inner) => inner.type_name(),
+        }
+    }
+
+}
+
+impl fmt::Display for TypeRef {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeRef::Named(name) => write!(f, "{}", name),
+            TypeRef::List(inner) => write!(f, "[{}]", inner),
+            TypeRef::NonNull(inner) => write!(f, "{}!", inner),
+        }
+    }
+
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Variable(String),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Boolean(bool),
+    Null,
+    Enum(String),
+    List(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum OperationKind {
+    Query,
+    Mutation,
+    Subscription,
+}
+
+#[derive(Debug)]
+pub(crate) struct VariableDefinition {
+    pub(crate) name: String,
+    pub(crate) ty: TypeRef,
+    pub(crate) default: Option<Value>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Directive {
+    pub(crate) name: String,
+    pub(crate) arguments: Vec<(String, Value)>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Field {
+    pub(crate) alias: Option<String>,
+    pub(crate) name: String,
+    pub(crate) arguments: Vec<(String, Value)>,
+    pub(crate) directives: Vec<Directive>,
+    pub(crate) selection_set: Vec<Selection>,
+}
+
+impl Field {
+
+    pub(crate) fn response_key(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
+
+}
+
+#[derive(Debug)]
+pub(crate) enum Selection {
+    Field(Field),
+    FragmentSpread {
+        name: String,
+        directives: Vec<Directive>,
+    },
+    InlineFragment {
+        type_condition: Option<String>,
+        directives: Vec<Directive>,
+        selection_set: Vec<Selection>,
+    },
+}
+
+#[derive(Debug)]
+pub(crate) struct Operation {
+    pub(crate) kind: OperationKind,
+    pub(crate) name: Option<String>,
+    pub(crate) variables: Vec<VariableDefinition>,
+    pub(crate) selection_set: Vec<Selection>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Fragment {
+    pub(crate) type_condition: String,
+    pub(crate) directives: Vec<Directive>,
+    pub(crate) selection_set: Vec<Selection>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ExecutableDocument {
+    pub(crate) operations: Vec<Operation>,
+    pub(crate) fragments: HashMap<String, Fragment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Punctuator(char),
+    Spread,
+    Name(String),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Eof,
+}
+
+impl fmt::Display for Token {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Punctuator(c) => write!(f, "\"{}\"", c),
+            Token::Spread => write!(f, "\"...\""),
+            Token::Name(name) => write!(f, "Name \"{}\"", name),
+            Token::Int(value) => write!(f, "Int \"{}\"", value),
+            Token::Float(value) => write!(f, "Float \"{}\"", value),
+            Token::String(value) => write!(f, "String \"{}\"", value),
+            Token::Eof => write!(f, "<EOF>"),
+        }
+    }
+
+}
+
+struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Lexer<'a> {
+
+    fn new(source: &'a str) -> Lexer<'a> {
+        Lexer {
+            chars: source.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, message: String) -> Error {
+        syntax_error(message, self.line, self.column)
+    }
+
+    /// Return the token with the line and the column it starts at.
+    fn next_token(&mut self) -> Result<(Token, usize, usize)> {
+        // the commas are insignificant like the white spaces
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                ' ' | '\t' | '\n' | '\r' | ',' | '\u{feff}' => {
+                    self.bump();
+                }
+                '#' => {
+                    while let Some(&c) = self.chars.peek() {
+                        if c == '\n' {
+                            break;
+                        }
+                        self.bump();
+                    }
+                }
+                _ => break,
+            }
+        }
+        let (line, column) = (self.line, self.column);
+        let c = match self.chars.peek() {
+            Some(&c) => c,
+            None => return Ok((Token::Eof, line, column)),
+        };
+        let token = match c {
+            '!' | '$' | '&' | '(' | ')' | ':' | '=' | '@' | '[' | ']' | '{' | '|' | '}' => {
+                self.bump();
+                Token::Punctuator(c)
+            }
+            '.' => {
+                for _ in 0..3 {
+                    if self.bump() != Some('.') {
+                        return Err(syntax_error("Unexpected \".\"".to_string(), line, column));
+                    }
+                }
+                Token::Spread
+            }
+            '"' => self.read_string()?,
+            '-' | '0'..='9' => self.read_number()?,
+            c if c == '_' || c.is_ascii_alphabetic() => {
+                let mut name = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if c == '_' || c.is_ascii_alphanumeric() {
+                        name.push(c);
+                        self.bump();
+                    } else {
+                        break;
+                    }
+                }
+                Token::Name(name)
+            }
+            c => return Err(self.error(format!("Unexpected character \"{}\"", c))),
+        };
+        Ok((token, line, column))
+    }
+
+    fn read_number(&mut self) -> Result<Token> {
+        let mut text = String::new();
+        let mut is_float = false;
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                '0'..='9' | '-' | '+' => (),
+                '.' | 'e' | 'E' => is_float = true,
+                _ => break,
+            }
+            text.push(c);
+            self.bump();
+        }
+        if is_float {
+            text.parse::<f64>()
+                .map(Token::Float)
+                .map_err(|_| self.error(format!("Invalid number \"{}\"", text)))
+        } else {
+            text.parse::<i64>()
+                .map(Token::Int)
+                .map_err(|_| self.error(format!("Invalid number \"{}\"", text)))
+        }
+    }
+
+    fn read_string(&mut self) -> Result<Token> {
+        self.bump();
+        if self.chars.peek() == Some(&'"') {
+            self.bump();
+            if self.chars.peek() != Some(&'"') {
+                return Ok(Token::String(String::new()));
+            }
+            self.bump();
+            return self.read_block_string();
+        }
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                None | Some('\n') => return Err(self.error("Unterminated string".to_string())),
+                Some('"') => return Ok(Token::String(value)),
+                Some('\\') => {
+                    let c = match self.bump() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let mut hex = String::new();
+                            for _ in 0..4 {
+                                hex.extend(self.bump());
+                            }
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error(format!("Invalid unicode escape \"\\u{}\"", hex)))?
+                        }
+                        _ => return Err(self.error("Invalid escape sequence".to_string())),
+                    };
+                    value.push(c);
+                }
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    // the common indentation of the block strings is not removed
+    fn read_block_string(&mut self) -> Result<Token> {
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.error("Unterminated string".to_string())),
+                Some('"') if value.ends_with("\"\"") => {
+                    value.truncate(value.len() - 2);
+                    return Ok(Token::String(value.trim().to_string()));
+                }
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+}
+
+// the selection sets, the values and the types nested deeper fail to parse,
+// so a query can't overflow the stack of the parser
+const MAX_DEPTH: usize = 64;
+
+fn syntax_error(message: String, line: usize, column: usize) -> Error {
+    Error::ParseError(format!("Syntax Error: {} at line {} column {}", message, line, column))
+}
+
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    token: Token,
+    line: usize,
+    column: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+
+    fn new(source: &'a str) -> Result<Parser<'a>> {
+        let mut lexer = Lexer::new(source);
+        let (token, line, column) = lexer.next_token()?;
+        Ok(Parser {
+            lexer,
+            token,
+            line,
+            column,
+            depth: 0,
+        })
+    }
+
+    fn advance(&mut self) -> Result<Token> {
+        let (token, line, column) = self.lexer.next_token()?;
+        self.line = line;
+        self.column = column;
+        Ok(std::mem::replace(&mut self.token, token))
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> Result<T> {
+        Err(syntax_error(format!("Expected {}, found {}", expected, self.token), self.line, self.column))
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            let message = format!("The document is nested deeper than {} levels", MAX_DEPTH);
+            return Err(syntax_error(message, self.line, self.column));
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    fn peek_punctuator(&self, c: char) -> bool {
+        self.token == Token::Punctuator(c)
+    }
+
+    fn peek_name(&self, name: &str) -> bool {
+        matches!(&self.token, Token::Name(n) if n == name)
+    }
+
+    fn skip_punctuator(&mut self, c: char) -> Result<bool> {
+        if self.peek_punctuator(c) {
+            self.advance()?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn expect_punctuator(&mut self, c: char) -> Result<()> {
+        if self.skip_punctuator(c)? {
+            return Ok(());
+        }
+        self.unexpected(&format!("\"{}\"", c))
+    }
+
+    fn expect_name(&mut self) -> Result<String> {
+        if let Token::Name(_) = &self.token {
+            if let Token::Name(name) = self.advance()? {
+                return Ok(name);
+            }
+        }
+        self.unexpected("Name")
+    }
+
+    fn parse_document(&mut self) -> Result<ExecutableDocument> {
+        let mut document = ExecutableDocument::default();
+        loop {
+            match &self.token {
+                Token::Eof => break,
+                Token::Punctuator('{') => {
+                    let selection_set = self.parse_selection_set()?;
+                    document.operations.push(Operation {
+                        kind: OperationKind::Query,
+                        name: None,
+                        variables: Vec::new(),
+                        selection_set,
+                    });
+                }
+                Token::Name(name) if name == "fragment" => {
+                    self.advance()?;
+                    let name = self.expect_name()?;
+                    if name == "on" {
+                        return self.unexpected("fragment name");
+                    }
+                    if !self.peek_name("on") {
+                        return self.unexpected("\"on\"");
+                    }
+                    self.advance()?;
+                    let type_condition = self.expect_name()?;
+                    let directives = self.parse_directives()?;
+                    let selection_set = self.parse_selection_set()?;
+                    if document.fragments.contains_key(&name) {
+                        return Err(Error::ParseError(format!("There can be only one fragment named \"{}\"", name)));
+                    }
+                    document.fragments.insert(name, Fragment {
+                        type_condition,
+                        directives,
+                        selection_set,
+                    });
+                }
+                Token::Name(_) => {
+                    let operation = self.parse_operation()?;
+                    document.operations.push(operation);
+                }
+                _ => return self.unexpected("an operation or a fragment"),
+            }
+        }
+        if document.operations.is_empty() {
+            return Err(Error::ParseError("The document has no operation".to_string()));
+        }
+        Ok(document)
+    }
+
+    fn parse_operation(&mut self) -> Result<Operation> {
+        let kind = match &self.token {
+            Token::Name(name) if name == "query" => OperationKind::Query,
+            Token::Name(name) if name == "mutation" => OperationKind::Mutation,
+            Token::Name(name) if name == "subscription" => OperationKind::Subscription,
+            _ => return self.unexpected("\"query\", \"mutation\" or \"subscription\""),
+        };
+        self.advance()?;
+        let name = match &self.token {
+            Token::Name(_) => Some(self.expect_name()?),
+            _ => None,
+        };
+        let mut variables = Vec::new();
+        if self.skip_punctuator('(')? {
+            while !self.skip_punctuator(')')? {
+                self.expect_punctuator('$')?;
+                let name = self.expect_name()?;
+                self.expect_punctuator(':')?;
+                let ty = self.parse_type()?;
+                let default = if self.skip_punctuator('=')? {
+                    Some(self.parse_value(true)?)
+                } else {
+                    None
+                };
+                self.parse_directives()?;
+                variables.push(VariableDefinition {
+                    name,
+                    ty,
+                    default,
+                });
+            }
+        }
+        self.parse_directives()?;
+        let selection_set = self.parse_selection_set()?;
+        Ok(Operation {
+            kind,
+            name,
+            variables,
+            selection_set,
+        })
+    }
+
+    fn parse_type(&mut self) -> Result<TypeRef> {
+        let ty = if self.skip_punctuator('[')? {
+            self.enter()?;
+            let inner = self.parse_type()?;
+            self.leave();
+            self.expect_punctuator(']')?;
+            inner.list()
+        } else {
+            TypeRef::Named(self.expect_name()?)
+        };
+        if self.skip_punctuator('!')? {
+            return Ok(ty.non_null());
+        }
+        Ok(ty)
+    }
+
+    fn parse_selection_set(&mut self) -> Result<Vec<Selection>> {
+        self.expect_punctuator('{')?;
+        self.enter()?;
+        let mut selections = Vec::new();
+        while !self.skip_punctuator('}')? {
+            selections.push(self.parse_selection()?);
+        }
+        self.leave();
+        if selections.is_empty() {
+            return self.unexpected("Name");
+        }
+        Ok(selections)
+    }
+
+    fn parse_selection(&mut self) -> Result<Selection> {
+        if self.token == Token::Spread {
+            self.advance()?;
+            if let Token::Name(name) = &self.token {
+                if name != "on" {
+                    let name = self.expect_name()?;
+                    let directives = self.parse_directives()?;
+                    return Ok(Selection::FragmentSpread {
+                        name,
+                        directives,
+                    });
+                }
+            }
+            let type_condition = if self.peek_name("on") {
+                self.advance()?;
+                Some(self.expect_name()?)
+            } else {
+                None
+            };
+            let directives = self.parse_directives()?;
+            let selection_set = self.parse_selection_set()?;
+            return Ok(Selection::InlineFragment {
+                type_condition,
+                directives,
+                selection_set,
+            });
+        }
+
+        let mut name = self.expect_name()?;
+        let mut alias = None;
+        if self.skip_punctuator(':')? {
+            alias = Some(name);
+            name = self.expect_name()?;
+        }
+        let arguments = self.parse_arguments(false)?;
+        let directives = self.parse_directives()?;
+        let selection_set = if self.peek_punctuator('{') {
+            self.parse_selection_set()?
+        } else {
+            Vec::new()
+        };
+        Ok(Selection::Field(Field {
+            alias,
+            name,
+            arguments,
+            directives,
+            selection_set,
+        }))
+    }
+
+    fn parse_arguments(&mut self, is_const: bool) -> Result<Vec<(String, Value)>> {
+        let mut arguments = Vec::new();
+        if self.skip_punctuator('(')? {
+            while !self.skip_punctuator(')')? {
+                let name = self.expect_name()?;
+                self.expect_punctuator(':')?;
+                let value = self.parse_value(is_const)?;
+                arguments.push((name, value));
+            }
+        }
+        Ok(arguments)
+    }
+
+    fn parse_directives(&mut self) -> Result<Vec<Directive>> {
+        let mut directives = Vec::new();
+        while self.skip_punctuator('@')? {
+            let name = self.expect_name()?;
+            let arguments = self.parse_arguments(false)?;
+            directives.push(Directive {
+                name,
+                arguments,
+            });
+        }
+        Ok(directives)
+    }
+
+    fn parse_value(&mut self, is_const: bool) -> Result<Value> {
+        let value = match &self.token {
+            Token::Punctuator('$') if !is_const => {
+                self.advance()?;
+                Value::Variable(self.expect_name()?)
+            }
+            Token::Punctuator('[') => {
+                self.advance()?;
+                self.enter()?;
+                let mut items = Vec::new();
+                while !self.skip_punctuator(']')? {
+                    items.push(self.parse_value(is_const)?);
+                }
+                self.leave();
+                Value::List(items)
+            }
+            Token::Punctuator('{') => {
+                self.advance()?;
+                self.enter()?;
+                let mut fields = Vec::new();
+                while !self.skip_punctuator('}')? {
+                    let name = self.expect_name()?;
+                    self.expect_punctuator(':')?;
+                    fields.push((name, self.parse_value(is_const)?));
+                }
+                self.leave();
+                Value::Object(fields)
+            }
+            Token::Int(_) | Token::Float(_) | Token::String(_) | Token::Name(_) => {
+                match self.advance()? {
+                    Token::Int(value) => Value::Int(value),
+                    Token::Float(value) => Value::Float(value),
+                    Token::String(value) => Value::String(value),
+                    Token::Name(name) => match name.as_str() {
+                        "true" => Value::Boolean(true),
+                        "false" => Value::Boolean(false),
+                        "null" => Value::Null,
+                        _ => Value::Enum(name),
+                    },
+                    _ => unreachable!(),
+                }
+            }
+            _ => return self.unexpected("a value"),
+        };
+        Ok(value)
+    }
+
+}
+
+pub(crate) fn parse_document(source: &str) -> Result<ExecutableDocument> {
+    Parser::new(source)?.parse_document()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_document() {
+        let document = parse_document(r#"
+            # find the classics
+            query Classics($year: Int = 1950, $tags: [String!]!) {
+                old: books(filter: { year: $year, tags: $tags }, limit: 10) {
+                    title
+                    ...Author @include(if: true)
+                    ... on Books { year }
+                }
+            }
+
+            fragment Author on Books {
+                author { name }
+            }
+        "#).unwrap();
+
+        assert_eq!(document.operations.len(), 1);
+        let operation = &document.operations[0];
+        assert_eq!(operation.kind, OperationKind::Query);
+        assert_eq!(operation.name.as_deref(), Some("Classics"));
+        assert_eq!(operation.variables[0].default, Some(Value::Int(1950)));
+        assert_eq!(operation.variables[1].ty.to_string(), "[String!]!");
+
+        let field = match &operation.selection_set[0] {
+            Selection::Field(field) => field,
+            _ => panic!("expect a field"),
+        };
+        assert_eq!(field.response_key(), "old");
+        assert_eq!(field.name, "books");
+        assert_eq!(field.arguments[0].1, Value::Object(vec![
+            ("year".to_string(), Value::Variable("year".to_string())),
+            ("tags".to_string(), Value::Variable("tags".to_string())),
+        ]));
+        assert_eq!(field.selection_set.len(), 3);
+        assert!(document.fragments.contains_key("Author"));
+    }
+
+    #[test]
+    fn test_parse_values() {
+        let document = parse_document(r#"{ f(a: -1.5e2, b: "a\"\u0041", c: [null, RED, false], d: """ block "quoted" """) }"#).unwrap();
+        let field = match &document.operations[0].selection_set[0] {
+            Selection::Field(field) => field,
+            _ => panic!("expect a field"),
+        };
+        assert_eq!(field.arguments[0].1, Value::Float(-150.0));
+        assert_eq!(field.arguments[1].1, Value::String("a\"A".to_string()));
+        assert_eq!(field.arguments[2].1, Value::List(vec![Value::Null, Value::Enum("RED".to_string()), Value::Boolean(false)]));
+        assert_eq!(field.arguments[3].1, Value::String("block \"quoted\"".to_string()));
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        assert!(parse_document("").is_err());
+        assert!(parse_document("{ }").is_err());
+        assert!(parse_document("{ books(").is_err());
+        assert!(parse_document("query ($a: ) { a }").is_err());
+        assert!(parse_document("{ a(b: $c) }").is_ok());
+        assert!(parse_document("query ($a: Int = $b) { a }").is_err());
+        let err = parse_document("{\n  books {\n    title\n  }").unwrap_err();
+        assert!(err.to_string().contains("line 4"), "{}", err);
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let nested = |depth: usize, open: &str, inner: &str, close: &str| {
+            format!("{}{}{}", open.repeat(depth), inner, close.repeat(depth))
+        };
+        assert!(parse_document(&nested(MAX_DEPTH, "{ a ", "b", " }")).is_ok());
+        // the selection set of the arguments is a level too
+        assert!(parse_document(&format!("{{ a(b: {}) }}", nested(MAX_DEPTH - 1, "[", "1", "]"))).is_ok());
+
+        let deep_queries = vec![
+            nested(MAX_DEPTH + 1, "{ a ", "b", " }"),
+            format!("{{ a(b: {}) }}", nested(MAX_DEPTH + 1, "[", "1", "]")),
+            format!("{{ a(b: {}) }}", nested(MAX_DEPTH + 1, "{ c: ", "1", " }")),
+            format!("query ($a: {}) {{ a }}", nested(MAX_DEPTH + 1, "[", "Int", "]")),
+            // deep enough to overflow the stack without the limit
+            nested(100_000, "{ a ", "b", " }"),
+            format!("{{ a(b: {}) }}", nested(100_000, "[", "1", "]")),
+        ];
+        for query in deep_queries {
+            let err = parse_document(&query).unwrap_err();
+            assert!(err.to_string().contains("nested deeper"), "{}", err);
+        }
+    }
+}
@@ -0,0 +1,453 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The GraphQL types generated from the JSON Schemas of the collections.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use bson::Document;
+use indexmap::IndexMap;
+use crate::utils::json_schema::JsonSchema;
+use crate::{Error, Result};
+use super::parser::TypeRef;
+
+pub(crate) const QUERY_TYPE: &str = "Query";
+pub(crate) const MUTATION_TYPE: &str = "Mutation";
+pub(crate) const UPDATE_RESULT_TYPE: &str = "UpdateResult";
+
+const CUSTOM_SCALARS: &[(&str, &str)] = &[
+    ("JSON", "Any BSON value, written as relaxed extended JSON by the HTTP server."),
+    ("DateTime", "A BSON date, written as a RFC 3339 string."),
+    ("Long", "A 64-bit integer."),
+];
+
+const BUILTIN_SCALARS: &[&str] = &["Int", "Float", "String", "Boolean", "ID"];
+
+pub(crate) fn is_scalar(name: &str) -> bool {
+    BUILTIN_SCALARS.contains(&name) || CUSTOM_SCALARS.iter().any(|(scalar, _)| *scalar == name)
+}
+
+/// Where the value of a field comes from.
+#[derive(Debug, Clone)]
+pub(crate) enum FieldSource {
+    /// The field of the document with the same name.
+    Key(String),
+    /// The whole document.
+    Document,
+    /// A field of the root types.
+    Root(RootField),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum RootField {
+    Find(usize),
+    Count(usize),
+    Insert(usize),
+    Update(usize),
+    Delete(usize),
+}
+
+#[derive(Debug)]
+pub(crate) struct FieldDef {
+    pub(crate) name: String,
+    pub(crate) arguments: Vec<(String, TypeRef)>,
+    pub(crate) ty: TypeRef,
+    pub(crate) source: FieldSource,
+}
+
+#[derive(Debug)]
+pub(crate) struct ObjectType {
+    pub(crate) name: String,
+    pub(crate) fields: Vec<FieldDef>,
+}
+
+impl ObjectType {
+
+    pub(crate) fn field(&self, name: &str) -> Option<&FieldDef> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+}
+
+/// A collection exposed by the schema.
+pub(crate) struct CollectionDef {
+    pub(crate) name: String,
+    pub(crate) json_schema: Option<Document>,
+    pub(crate) read_only: bool,
+}
+
+pub(crate) struct SchemaTypes {
+    pub(crate) objects: IndexMap<String, ObjectType>,
+}
+
+impl SchemaTypes {
+
+    pub(crate) fn object(&self, name: &str) -> Option<&ObjectType> {
+        self.objects.get(name)
+    }
+
+    pub(crate) fn build(collections: &[CollectionDef]) -> Result<SchemaTypes> {
+        let mut builder = TypesBuilder {
+            objects: IndexMap::new(),
+        };
+        let mut query_fields = Vec::new();
+        let mut mutation_fields = Vec::new();
+
+        for (index, collection) in collections.iter().enumerate() {
+            let type_name = pascal_case(&collection.name)?;
+            match &collection.json_schema {
+                Some(json_schema) => {
+                    let schema = JsonSchema::parse(json_schema)?;
+                    builder.add_document_type(&type_name, &schema)?;
+                }
+                None => {
+                    builder.add_object(ObjectType {
+                        name: type_name.clone(),
+                        fields: vec![
+                            id_field(TypeRef::named("ID").non_null()),
+                            FieldDef {
+                                name: "document".to_string(),
+                                arguments: Vec::new(),
+                                ty: TypeRef::named("JSON").non_null(),
+                                source: FieldSource::Document,
+                            },
+                        ],
+                    })?;
+                }
+            }
+
+            let field_name = camel_case(&type_name);
+            let filter_arg = ("filter".to_string(), TypeRef::named("JSON"));
+            query_fields.push(root_field(
+                &field_name,
+                vec![
+                    filter_arg.clone(),
+                    ("sort".to_string(), TypeRef::named("JSON")),
+                    ("skip".to_string(), TypeRef::named("Int")),
+                    ("limit".to_string(), TypeRef::named("Int")),
+                ],
+                TypeRef::named(&type_name).non_null().list().non_null(),
+                RootField::Find(index),
+            ));
+            query_fields.push(root_field(
+                &format!("{}Count", field_name),
+                vec![filter_arg],
+                TypeRef::named("Int").non_null(),
+                RootField::Count(index),
+            ));
+
+            if collection.read_only {
+                continue;
+            }
+            let required_filter_arg = ("filter".to_string(), TypeRef::named("JSON").non_null());
+            mutation_fields.push(root_field(
+                &format!("insert{}", type_name),
+                vec![("document".to_string(), TypeRef::named("JSON").non_null())],
+                TypeRef::named("ID").non_null(),
+                RootField::Insert(index),
+            ));
+            mutation_fields.push(root_field(
+                &format!("update{}", type_name),
+                vec![
+                    required_filter_arg.clone(),
+                    ("update".to_string(), TypeRef::named("JSON").non_null()),
+                    ("many".to_string(), TypeRef::named("Boolean")),
+                    ("upsert".to_string(), TypeRef::named("Boolean")),
+                ],
+                TypeRef::named(UPDATE_RESULT_TYPE).non_null(),
+                RootField::Update(index),
+            ));
+            mutation_fields.push(root_field(
+                &format!("delete{}", type_name),
+                vec![
+                    required_filter_arg,
+                    ("many".to_string(), TypeRef::named("Boolean")),
+                ],
+                TypeRef::named("Int").non_null(),
+                RootField::Delete(index),
+            ));
+        }
+
+        builder.add_root(QUERY_TYPE, query_fields)?;
+        if !mutation_fields.is_empty() {
+            let count_field = |name: &str| FieldDef {
+                name: name.to_string(),
+                arguments: Vec::new(),
+                ty: TypeRef::named("Int").non_null(),
+                source: FieldSource::Key(name.to_string()),
+            };
+            builder.objects.insert(UPDATE_RESULT_TYPE.to_string(), ObjectType {
+                name: UPDATE_RESULT_TYPE.to_string(),
+                fields: vec![count_field("matchedCount"), count_field("modifiedCount")],
+            });
+            builder.add_root(MUTATION_TYPE, mutation_fields)?;
+        }
+
+        Ok(SchemaTypes {
+            objects: builder.objects,
+        })
+    }
+
+    pub(crate) fn sdl(&self) -> String {
+        let mut result = String::new();
+        for (name, description) in CUSTOM_SCALARS {
+            let _ = writeln!(result, "\"{}\"\nscalar {}\n", description, name);
+        }
+        for object in self.objects.values() {
+            let _ = writeln!(result, "type {} {{", object.name);
+            for field in &object.fields {
+                let _ = write!(result, "  {}", field.name);
+                if !field.arguments.is_empty() {
+                    let arguments: Vec<String> = field.arguments
+                        .iter()
+                        .map(|(name, ty)| format!("{}: {}", name, ty))
+                        .collect();
+                    let _ = write!(result, "({})", arguments.join(", "));
+                }
+                let _ = writeln!(result, ": {}", field.ty);
+            }
+            let _ = writeln!(result, "}}\n");
+        }
+        result.truncate(result.trim_end().len());
+        result.push('\n');
+        result
+    }
+
+}
+
+fn root_field(name: &str, arguments: Vec<(String, TypeRef)>, ty: TypeRef, root: RootField) -> FieldDef {
+    FieldDef {
+        name: name.to_string(),
+        arguments,
+        ty,
+        source: FieldSource::Root(root),
+    }
+}
+
+fn id_field(ty: TypeRef) -> FieldDef {
+    FieldDef {
+        name: "_id".to_string(),
+        arguments: Vec::new(),
+        ty,
+        source: FieldSource::Key("_id".to_string()),
+    }
+}
+
+struct TypesBuilder {
+    objects: IndexMap<String, ObjectType>,
+}
+
+impl TypesBuilder {
+
+    fn add_object(&mut self, object: ObjectType) -> Result<()> {
+        if is_scalar(&object.name)
+            || object.name == QUERY_TYPE
+            || object.name == MUTATION_TYPE
+            || object.name == UPDATE_RESULT_TYPE
+            || self.objects.contains_key(&object.name) {
+            return Err(Error::ValidationError(format!("the GraphQL type name {} is used twice", object.name)));
+        }
+        self.objects.insert(object.name.clone(), object);
+        Ok(())
+    }
+
+    fn add_root(&mut self, name: &str, fields: Vec<FieldDef>) -> Result<()> {
+        let mut names = HashMap::new();
+        for field in &fields {
+            if names.insert(field.name.as_str(), ()).is_some() {
+                return Err(Error::ValidationError(format!("the GraphQL field name {}.{} is used twice", name, field.name)));
+            }
+        }
+        self.objects.insert(name.to_string(), ObjectType {
+            name: name.to_string(),
+            fields,
+        });
+        Ok(())
+    }
+
+    fn add_document_type(&mut self, type_name: &str, schema: &JsonSchema) -> Result<()> {
+        let id_ty = match schema.properties().iter().find(|(name, _)| name == "_id") {
+            Some((_, id_schema)) => self.field_type(type_name, "_id", id_schema, true)?.non_null(),
+            None => TypeRef::named("ID").non_null(),
+        };
+        let mut fields = vec![id_field(id_ty)];
+        fields.extend(self.property_fields(type_name, schema)?);
+        self.add_object(ObjectType {
+            name: type_name.to_string(),
+            fields,
+        })
+    }
+
+    // the properties which are not valid GraphQL names are not exposed
+    fn property_fields(&mut self, type_name: &str, schema: &JsonSchema) -> Result<Vec<FieldDef>> {
+        let mut fields = Vec::new();
+        for (name, property) in schema.properties() {
+            if name == "_id" || !is_valid_name(name) {
+                continue;
+            }
+            let ty = self.field_type(type_name, name, property, schema.is_required(name))?;
+            fields.push(FieldDef {
+                name: name.clone(),
+                arguments: Vec::new(),
+                ty,
+                source: FieldSource::Key(name.clone()),
+            });
+        }
+        Ok(fields)
+    }
+
+    fn field_type(&mut self, parent: &str, field: &str, schema: &JsonSchema, required: bool) -> Result<TypeRef> {
+        let (types, nullable) = match schema.types() {
+            Some(types) => (
+                types.iter().map(String::as_str).filter(|ty| *ty != "null").collect::<Vec<&str>>(),
+                types.iter().any(|ty| ty == "null"),
+            ),
+            None => (Vec::new(), true),
+        };
+        let is_object = match types.as_slice() {
+            ["object"] => true,
+            [] => schema.types().is_none(),
+            _ => false,
+        };
+        let ty = if is_object && !schema.properties().is_empty() {
+            let name = format!("{}{}", parent, pascal_case(field)?);
+            let fields = self.property_fields(&name, schema)?;
+            if fields.is_empty() {
+                TypeRef::named("JSON")
+            } else {
+                self.add_object(ObjectType {
+                    name: name.clone(),
+                    fields,
+                })?;
+                TypeRef::Named(name)
+            }
+        } else {
+            match types.as_slice() {
+                ["string"] | ["decimal"] => TypeRef::named("String"),
+                ["int"] => TypeRef::named("Int"),
+                ["long"] => TypeRef::named("Long"),
+                ["double"] | ["number"] => TypeRef::named("Float"),
+                ["bool"] | ["boolean"] => TypeRef::named("Boolean"),
+                ["objectId"] => TypeRef::named("ID"),
+                ["date"] => TypeRef::named("DateTime"),
+                ["array"] => match schema.items() {
+                    Some(items) => self.field_type(parent, field, items, true)?.list(),
+                    None => TypeRef::named("JSON").list(),
+                },
+                _ => TypeRef::named("JSON"),
+            }
+        };
+        if required && !nullable && !types.is_empty() {
+            return Ok(ty.non_null());
+        }
+        Ok(ty)
+    }
+
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c == '_' || c.is_ascii_alphabetic() => (),
+        _ => return false,
+    }
+    !name.starts_with("__") && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+/// The GraphQL type name of a collection, e.g. `user_profiles` is `UserProfiles`.
+pub(crate) fn pascal_case(name: &str) -> Result<String> {
+    let mut result = String::new();
+    for word in name.split(|c: char| !c.is_ascii_alphanumeric()).filter(|word| !word.is_empty()) {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            result.push(first.to_ascii_uppercase());
+            result.extend(chars);
+        }
+    }
+    match result.chars().next() {
+        None => Err(Error::ValidationError(format!("{} can't be converted to a GraphQL name", name))),
+        Some(c) if c.is_ascii_digit() => Ok(format!("_{}", result)),
+        Some(_) => Ok(result),
+    }
+}
+
+fn camel_case(type_name: &str) -> String {
+    let mut chars = type_name.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::doc;
+    use super::*;
+
+    #[test]
+    fn test_sdl() {
+        let types = SchemaTypes::build(&[
+            CollectionDef {
+                name: "books".to_string(),
+                json_schema: Some(doc! {
+                    "bsonType": "object",
+                    "required": ["title", "author"],
+                    "properties": {
+                        "title": { "bsonType": "string" },
+                        "year": { "bsonType": ["int", "null"] },
+                        "tags": { "bsonType": "array", "items": { "bsonType": "string" } },
+                        "author": {
+                            "bsonType": "object",
+                            "properties": { "name": { "bsonType": "string" } },
+                        },
+                        "published-at": { "bsonType": "date" },
+                    },
+                }),
+                read_only: false,
+            },
+            CollectionDef {
+                name: "audit_logs".to_string(),
+                json_schema: None,
+                read_only: true,
+            },
+        ]).unwrap();
+
+        let sdl = types.sdl();
+        assert!(sdl.contains("type Books {\n  _id: ID!\n  title: String!\n  year: Int\n  tags: [String!]\n  author: BooksAuthor!\n}"), "{}", sdl);
+        assert!(sdl.contains("type BooksAuthor {\n  name: String\n}"), "{}", sdl);
+        assert!(sdl.contains("type AuditLogs {\n  _id: ID!\n  document: JSON!\n}"), "{}", sdl);
+        assert!(sdl.contains("  books(filter: JSON, sort: JSON, skip: Int, limit: Int): [Books!]!\n"), "{}", sdl);
+        assert!(sdl.contains("  auditLogsCount(filter: JSON): Int!\n"), "{}", sdl);
+        assert!(sdl.contains("  updateBooks(filter: JSON!, update: JSON!, many: Boolean, upsert: Boolean): UpdateResult!\n"), "{}", sdl);
+        assert!(!sdl.contains("insertAuditLogs"), "{}", sdl);
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(pascal_case("user_profiles").unwrap(), "UserProfiles");
+        assert_eq!(pascal_case("order-items.2024").unwrap(), "OrderItems2024");
+        assert_eq!(pascal_case("2024").unwrap(), "_2024");
+        assert!(pascal_case("--").is_err());
+        assert_eq!(camel_case("UserProfiles"), "userProfiles");
+        assert!(is_valid_name("_id"));
+        assert!(!is_valid_name("__typename"));
+        assert!(!is_valid_name("first-name"));
+
+        let conflict = SchemaTypes::build(&[
+            CollectionDef { name: "query".to_string(), json_schema: None, read_only: false },
+        ]);
+        assert!(conflict.is_err());
+    }
+}
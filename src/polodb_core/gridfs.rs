@@ -0,0 +1,414 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A store of large binaries, like GridFS of MongoDB.
+//!
+//! A file is split into the chunks stored in the `<bucket>_chunks` collection,
+//! and described by a [`GridFsFile`] in the `<bucket>_files` collection.
+//! The file document is inserted after all the chunks are written,
+//! so the incomplete uploads are never visible.
+//!
+//! ```rust
+//! use std::io::{Read, Seek, SeekFrom, Write};
+//! use polodb_core::Database;
+//!
+//! let db = Database::open_memory().unwrap();
+//! let bucket = db.fs();
+//!
+//! let mut upload = bucket.open_upload_stream("hello.txt", None).unwrap();
+//! upload.write_all(b"hello world").unwrap();
+//! let id = upload.finish().unwrap();
+//!
+//! let mut download = bucket.open_download_stream(id).unwrap();
+//! download.seek(SeekFrom::Start(6)).unwrap();
+//! let mut content = String::new();
+//! download.read_to_string(&mut content).unwrap();
+//! assert_eq!(content, "world");
+//! ```
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::sync::Weak;
+use bson::{doc, Binary, Bson, DateTime, Document};
+use bson::oid::ObjectId;
+use bson::spec::BinarySubtype;
+use serde::{Deserialize, Serialize};
+use crate::db::db_inner::DatabaseInner;
+use crate::{Collection, CollectionT, Error, Result, Transaction};
+
+/// The default size of the chunks, the same as GridFS.
+pub const DEFAULT_CHUNK_SIZE: u32 = 255 * 1024;
+
+/// The name of the default bucket returned by [`crate::Database::fs`].
+pub const DEFAULT_BUCKET_NAME: &str = "fs";
+
+/// The metadata of a file in the bucket.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GridFsFile {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    /// The size of the file in bytes.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub length: u64,
+    pub chunk_size: u32,
+    pub upload_date: DateTime,
+    pub filename: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Document>,
+}
+
+impl GridFsFile {
+
+    /// The number of the chunks of the file.
+    pub fn chunk_count(&self) -> u64 {
+        self.length.div_ceil(self.chunk_size as u64)
+    }
+
+}
+
+/// A bucket of files, returned by [`crate::Database::fs`].
+#[derive(Clone)]
+pub struct GridFsBucket {
+    db: Weak<DatabaseInner>,
+    name: String,
+    chunk_size: u32,
+}
+
+impl GridFsBucket {
+
+    pub(crate) fn new(db: Weak<DatabaseInner>, name: &str) -> GridFsBucket {
+        GridFsBucket {
+            db,
+            name: name.to_string(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Set the size of the chunks of the files uploaded later.
+    /// The files uploaded before keep their own chunk size.
+    pub fn with_chunk_size(mut self, chunk_size: u32) -> GridFsBucket {
+        assert!(chunk_size > 0, "the chunk size must be positive");
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    fn files_collection_name(&self) -> String {
+        format!("{}_files", self.name)
+    }
+
+    fn chunks_collection_name(&self) -> String {
+        format!("{}_chunks", self.name)
+    }
+
+    fn files_collection(&self) -> Collection<GridFsFile> {
+        Collection::new(self.db.clone(), &self.files_collection_name())
+    }
+
+    fn chunks_collection(&self) -> Collection<Document> {
+        Collection::new(self.db.clone(), &self.chunks_collection_name())
+    }
+
+    /// Start uploading a file. The content is written to the returned stream,
+    /// and the file is visible when [`GridFsUploadStream::finish`] is called.
+    pub fn open_upload_stream(&self, filename: &str, metadata: Option<Document>) -> Result<GridFsUploadStream> {
+        if self.db.upgrade().is_none() {
+            return Err(Error::DbIsClosed);
+        }
+        Ok(GridFsUploadStream {
+            bucket: self.clone(),
+            file: GridFsFile {
+                id: ObjectId::new(),
+                length: 0,
+                chunk_size: self.chunk_size,
+                upload_date: DateTime::now(),
+                filename: filename.to_string(),
+                metadata,
+            },
+            buffer: Vec::with_capacity(self.chunk_size as usize),
+            chunk_count: 0,
+            finished: false,
+        })
+    }
+
+    /// Upload the whole content of a file at once.
+    pub fn upload_from_slice(&self, filename: &str, data: &[u8], metadata: Option<Document>) -> Result<ObjectId> {
+        let mut stream = self.open_upload_stream(filename, metadata)?;
+        stream.write_chunks(data)?;
+        stream.finish()
+    }
+
+    /// Find the metadata of a file by its id.
+    pub fn find_file(&self, id: ObjectId) -> Result<Option<GridFsFile>> {
+        self.files_collection().find_one(doc! { "_id": id })
+    }
+
+    /// Find the metadata of the files matching `filter`,
+    /// e.g. `doc! { "filename": "a.txt" }`.
+    pub fn find(&self, filter: Document) -> Result<Vec<GridFsFile>> {
+        self.files_collection().find(filter).run()?.collect()
+    }
+
+    /// Open a file to read. The stream implements [`Seek`] for the range reads,
+    /// and only the chunks being read are loaded.
+    pub fn open_download_stream(&self, id: ObjectId) -> Result<GridFsDownloadStream> {
+        let file = self.find_file(id)?.ok_or_else(|| Error::FileNotFound(id.to_hex()))?;
+        Ok(GridFsDownloadStream {
+            chunks: self.chunks_collection(),
+            file,
+            position: 0,
+            current_chunk: None,
+        })
+    }
+
+    /// Read the whole content of a file.
+    pub fn download(&self, id: ObjectId) -> Result<Vec<u8>> {
+        let mut stream = self.open_download_stream(id)?;
+        let mut result = Vec::with_capacity(stream.file().length as usize);
+        stream.read_to_end(&mut result)?;
+        Ok(result)
+    }
+
+    /// Read the bytes in `range` of a file. The range is truncated
+    /// to the length of the file.
+    pub fn download_range(&self, id: ObjectId, range: Range<u64>) -> Result<Vec<u8>> {
+        let mut stream = self.open_download_stream(id)?;
+        let end = range.end.min(stream.file().length);
+        if range.start >= end {
+            return Ok(Vec::new());
+        }
+        stream.seek(SeekFrom::Start(range.start))?;
+        let mut result = Vec::with_capacity((end - range.start) as usize);
+        stream.by_ref().take(end - range.start).read_to_end(&mut result)?;
+        Ok(result)
+    }
+
+    /// Delete a file and all its chunks in one transaction.
+    pub fn delete(&self, id: ObjectId) -> Result<()> {
+        let file = self.find_file(id)?.ok_or_else(|| Error::FileNotFound(id.to_hex()))?;
+        let txn = self.start_transaction()?;
+        let result = self.delete_with_txn(&txn, &file);
+        match result {
+            Ok(()) => txn.commit(),
+            Err(err) => {
+                txn.rollback()?;
+                Err(err)
+            }
+        }
+    }
+
+    fn delete_with_txn(&self, txn: &Transaction, file: &GridFsFile) -> Result<()> {
+        txn.collection::<GridFsFile>(&self.files_collection_name())
+            .delete_one(doc! { "_id": file.id })?;
+        self.delete_chunks(txn, file.id, file.chunk_count())
+    }
+
+    fn delete_chunks(&self, txn: &Transaction, file_id: ObjectId, chunk_count: u64) -> Result<()> {
+        let chunks = txn.collection::<Document>(&self.chunks_collection_name());
+        for n in 0..chunk_count {
+            chunks.delete_one(doc! { "_id": chunk_key(file_id, n) })?;
+        }
+        Ok(())
+    }
+
+    fn start_transaction(&self) -> Result<Transaction> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let mut inner = db.start_transaction()?;
+        inner.set_auto_commit(false);
+        Ok(Transaction::new(self.db.clone(), None, inner))
+    }
+
+}
+
+/// The chunk `n` of a file. The ids are ordered by the number of the chunks.
+fn chunk_key(file_id: ObjectId, n: u64) -> String {
+    format!("{}-{:012}", file_id.to_hex(), n)
+}
+
+/// A file being uploaded, returned by [`GridFsBucket::open_upload_stream`].
+///
+/// Each full chunk is written by its own transaction, so the memory
+/// used by the upload is bounded by the chunk size.
+/// The written chunks are deleted if the stream is dropped before finishing.
+pub struct GridFsUploadStream {
+    bucket: GridFsBucket,
+    file: GridFsFile,
+    buffer: Vec<u8>,
+    chunk_count: u64,
+    finished: bool,
+}
+
+impl GridFsUploadStream {
+
+    /// The id of the file being uploaded.
+    pub fn id(&self) -> ObjectId {
+        self.file.id
+    }
+
+    fn write_chunks(&mut self, mut data: &[u8]) -> Result<()> {
+        let chunk_size = self.file.chunk_size as usize;
+        while !data.is_empty() {
+            let size = (chunk_size - self.buffer.len()).min(data.len());
+            self.buffer.extend_from_slice(&data[..size]);
+            data = &data[size..];
+            if self.buffer.len() == chunk_size {
+                self.flush_chunk()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush_chunk(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let n = self.chunk_count;
+        let bytes = std::mem::replace(&mut self.buffer, Vec::with_capacity(self.file.chunk_size as usize));
+        let length = bytes.len() as u64;
+        self.bucket.chunks_collection().insert_one(doc! {
+            "_id": chunk_key(self.file.id, n),
+            "files_id": self.file.id,
+            "n": n as i64,
+            "data": Bson::Binary(Binary {
+                subtype: BinarySubtype::Generic,
+                bytes,
+            }),
+        })?;
+        self.chunk_count += 1;
+        self.file.length += length;
+        Ok(())
+    }
+
+    /// Write the last chunk and the metadata of the file.
+    /// Return the id of the file.
+    pub fn finish(mut self) -> Result<ObjectId> {
+        self.flush_chunk()?;
+        self.file.upload_date = DateTime::now();
+        self.bucket.files_collection().insert_one(&self.file)?;
+        self.finished = true;
+        Ok(self.file.id)
+    }
+
+    /// Cancel the upload and delete the written chunks.
+    pub fn abort(mut self) -> Result<()> {
+        self.finished = true;
+        self.delete_written_chunks()
+    }
+
+    fn delete_written_chunks(&self) -> Result<()> {
+        let txn = self.bucket.start_transaction()?;
+        self.bucket.delete_chunks(&txn, self.file.id, self.chunk_count)?;
+        txn.commit()
+    }
+
+}
+
+impl Write for GridFsUploadStream {
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_chunks(buf).map_err(io::Error::other)?;
+        Ok(buf.len())
+    }
+
+    /// The partial chunk is kept in memory until it's full or
+    /// the stream is finished, so nothing is done here.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+}
+
+impl Drop for GridFsUploadStream {
+
+    fn drop(&mut self) {
+        if !self.finished && self.chunk_count > 0 {
+            let _ = self.delete_written_chunks();
+        }
+    }
+
+}
+
+/// A file being read, returned by [`GridFsBucket::open_download_stream`].
+pub struct GridFsDownloadStream {
+    chunks: Collection<Document>,
+    file: GridFsFile,
+    position: u64,
+    current_chunk: Option<(u64, Vec<u8>)>,
+}
+
+impl GridFsDownloadStream {
+
+    pub fn file(&self) -> &GridFsFile {
+        &self.file
+    }
+
+    fn load_chunk(&mut self, n: u64) -> Result<&[u8]> {
+        let loaded = matches!(&self.current_chunk, Some((current, _)) if *current == n);
+        if !loaded {
+            let chunk = self.chunks.find_one(doc! { "_id": chunk_key(self.file.id, n) })?
+                .ok_or_else(|| Error::FileChunksMissing(self.file.id.to_hex()))?;
+            let data = match chunk.get("data") {
+                Some(Bson::Binary(binary)) => binary.bytes.clone(),
+                _ => return Err(Error::FileChunksMissing(self.file.id.to_hex())),
+            };
+            self.current_chunk = Some((n, data));
+        }
+        Ok(self.current_chunk.as_ref().map(|(_, data)| data.as_slice()).unwrap())
+    }
+
+}
+
+impl Read for GridFsDownloadStream {
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.file.length || buf.is_empty() {
+            return Ok(0);
+        }
+        let chunk_size = self.file.chunk_size as u64;
+        let n = self.position / chunk_size;
+        let offset = (self.position % chunk_size) as usize;
+        let chunk = self.load_chunk(n).map_err(io::Error::other)?;
+        if offset >= chunk.len() {
+            return Err(io::Error::other(Error::FileChunksMissing(self.file.id.to_hex())));
+        }
+        let size = (chunk.len() - offset).min(buf.len());
+        buf[..size].copy_from_slice(&chunk[offset..offset + size]);
+        self.position += size as u64;
+        Ok(size)
+    }
+
+}
+
+impl Seek for GridFsDownloadStream {
+
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.file.length.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative position")),
+        }
+    }
+
+}
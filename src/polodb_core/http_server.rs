@@ -0,0 +1,832 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! A small HTTP server exposing the collections of a database as JSON,
+//! for the local admin and debug interfaces. It's enabled by the `http-server` feature.
+//!
+//! The server has no authentication by default, bind it to a local address
+//! or call [`HttpServer::require_auth`]. Then every request authenticates with
+//! `Authorization: Bearer <token>`, a token of [`crate::auth::Users::create_token`],
+//! or `Authorization: Basic` with the name and the password of a user,
+//! and needs a role of the user on the database of the server and the collection, see [`crate::auth`].
+//! The bodies of the requests are parsed as [extended JSON](https://www.mongodb.com/docs/manual/reference/mongodb-extended-json/),
+//! and the documents are returned as relaxed extended JSON,
+//! e.g. an `ObjectId` is written as `{ "$oid": "..." }`.
+//!
+//! | Request                              | Body                                 | Response                     |
+//! |--------------------------------------|--------------------------------------|------------------------------|
+//! | `GET /collections`                   |                                      | the names of the collections |
+//! | `GET /collections/{name}`            |                                      | the documents                |
+//! | `POST /collections/{name}`           | a document or an array of documents  | the inserted ids             |
+//! | `DELETE /collections/{name}`         |                                      | drops the collection         |
+//! | `GET /collections/{name}/count`      |                                      | `{ "count": n }`             |
+//! | `POST /collections/{name}/find`      | `{ filter, sort, skip, limit }`      | the documents                |
+//! | `POST /collections/{name}/update`    | `{ filter, update, many, upsert }`   | the matched and modified counts |
+//! | `POST /collections/{name}/delete`    | `{ filter, many }`                   | the deleted count            |
+//! | `POST /collections/{name}/aggregate` | `{ pipeline }`                       | the documents                |
+//!
+//! `GET /collections/{name}` takes the `filter`, `skip` and `limit` query parameters.
+//! With the `graphql` feature, [`HttpServer::graphql`] also serves the GraphQL requests
+//! `{ query, operationName, variables }` at `POST /graphql`.
+//! An error is returned as `{ "error": "..." }` with a 4xx or 5xx status.
+//! The requests with the missing or wrong credentials get 401, the unauthorized ones get 403.
+//!
+//! Each connection is served by its own thread, up to [`HttpServer::max_connections`],
+//! the other connections get 503 and are closed. A connection is closed with 408
+//! when a request or a response stalls longer than [`HttpServer::timeout`].
+//!
+//! ```rust
+//! use polodb_core::Database;
+//! use polodb_core::http_server::HttpServer;
+//!
+//! let db = Database::open_memory().unwrap();
+//! let server = HttpServer::bind(db, "127.0.0.1:0").unwrap();
+//! let handle = server.spawn().unwrap();
+//! println!("listening on http://{}", handle.local_addr());
+//! handle.shutdown().unwrap();
+//! ```
+
+use std::convert::TryFrom;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use bson::{Bson, Document};
+use serde::Serialize;
+use serde_json::Value;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use crate::auth::{lookup_collections, Action, User};
+use crate::options::UpdateOptions;
+use crate::{CollectionT, Database, Error, Result};
+#[cfg(feature = "graphql")]
+use crate::graphql::{GraphQlRequest, GraphQlSchema};
+
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+const MAX_LINE_SIZE: u64 = 16 * 1024;
+const DEFAULT_MAX_CONNECTIONS: usize = 64;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct HttpServer {
+    context: Context,
+    listener: TcpListener,
+    max_connections: usize,
+    timeout: Duration,
+}
+
+#[derive(Clone)]
+struct Context {
+    db: Arc<Database>,
+    require_auth: bool,
+    #[cfg(feature = "graphql")]
+    graphql: Option<Arc<GraphQlSchema>>,
+}
+
+impl Context {
+
+    /// The authenticated user of the request, `None` if the authentication is not required.
+    fn authenticate(&self, request: &Request) -> HttpResult<Option<User>> {
+        if !self.require_auth {
+            return Ok(None);
+        }
+        let authorization = request
+            .authorization
+            .as_deref()
+            .ok_or_else(|| HttpError::new(401, "authentication required"))?;
+        let users = self.db.users();
+        let result = match authorization.split_once(' ') {
+            Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => {
+                users.authenticate_token(token.trim())
+            }
+            Some((scheme, credentials)) if scheme.eq_ignore_ascii_case("basic") => {
+                let credentials = BASE64
+                    .decode(credentials.trim())
+                    .ok()
+                    .and_then(|credentials| String::from_utf8(credentials).ok())
+                    .ok_or_else(|| HttpError::new(401, "malformed basic credentials"))?;
+                let (name, password) = credentials
+                    .split_once(':')
+                    .ok_or_else(|| HttpError::new(401, "malformed basic credentials"))?;
+                users.authenticate(name, password)
+            }
+            _ => return Err(HttpError::new(401, "unsupported authorization scheme")),
+        };
+        match result {
+            Ok(user) => Ok(Some(user)),
+            Err(Error::AuthenticationFailed) => Err(HttpError::new(401, "authentication failed")),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn authorize(&self, user: Option<&User>, action: Action, collection: Option<&str>) -> HttpResult<()> {
+        let user = match user {
+            Some(user) => user,
+            None => return Ok(()),
+        };
+        if user.is_allowed(action, self.db.name(), collection) {
+            return Ok(());
+        }
+        let message = match collection {
+            Some(collection) => format!("user '{}' is not allowed to {} '{}'", user.name, action, collection),
+            None => format!("user '{}' is not allowed to {} the database", user.name, action),
+        };
+        Err(HttpError::new(403, message))
+    }
+
+}
+
+impl HttpServer {
+
+    pub fn bind<A: ToSocketAddrs>(db: Database, addr: A) -> Result<HttpServer> {
+        let listener = TcpListener::bind(addr)?;
+        Ok(HttpServer {
+            context: Context {
+                db: Arc::new(db),
+                require_auth: false,
+                #[cfg(feature = "graphql")]
+                graphql: None,
+            },
+            listener,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// The connections served at the same time, 64 by default.
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections.max(1);
+        self
+    }
+
+    /// The time a connection waits for reading a request or writing a response,
+    /// including an idle connection waiting for the next request, 30 seconds by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Require the requests to authenticate as one of the users of the database.
+    /// The GraphQL requests need the write permission on the database.
+    pub fn require_auth(mut self) -> Self {
+        self.context.require_auth = true;
+        self
+    }
+
+    /// Serve the GraphQL requests of `schema` at `POST /graphql`.
+    #[cfg(feature = "graphql")]
+    pub fn graphql(mut self, schema: GraphQlSchema) -> Self {
+        self.context.graphql = Some(Arc::new(schema));
+        self
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Serve the requests in the current thread, every connection is served in its own thread.
+    pub fn run(self) -> Result<()> {
+        self.serve(&AtomicBool::new(false))
+    }
+
+    /// Serve the requests in a background thread until [`HttpServerHandle::shutdown`].
+    pub fn spawn(self) -> Result<HttpServerHandle> {
+        let addr = self.local_addr()?;
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stopped = stopped.clone();
+            thread::Builder::new()
+                .name("polodb-http-server".to_string())
+                .spawn(move || self.serve(&stopped))?
+        };
+        Ok(HttpServerHandle {
+            addr,
+            stopped,
+            thread: Some(thread),
+        })
+    }
+
+    fn serve(&self, stopped: &AtomicBool) -> Result<()> {
+        let connections = Arc::new(AtomicUsize::new(0));
+        for stream in self.listener.incoming() {
+            if stopped.load(Ordering::SeqCst) {
+                break;
+            }
+            // the errors of the accepted connections are not fatal
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            if stream.set_read_timeout(Some(self.timeout)).is_err()
+                || stream.set_write_timeout(Some(self.timeout)).is_err() {
+                continue;
+            }
+            if connections.fetch_add(1, Ordering::SeqCst) >= self.max_connections {
+                connections.fetch_sub(1, Ordering::SeqCst);
+                let response = Err(HttpError::new(503, "too many connections"));
+                let _ = write_response(&mut stream, response, false);
+                continue;
+            }
+            let context = self.context.clone();
+            let guard = ConnectionGuard(connections.clone());
+            let _ = thread::Builder::new()
+                .name("polodb-http-connection".to_string())
+                .spawn(move || {
+                    let _guard = guard;
+                    let _ = handle_connection(&context, stream);
+                });
+        }
+        Ok(())
+    }
+
+}
+
+/// A connection being served, counted until its thread ends.
+struct ConnectionGuard(Arc<AtomicUsize>);
+
+impl Drop for ConnectionGuard {
+
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+
+}
+
+/// The handle of a server started by [`HttpServer::spawn`].
+/// The server is shut down when the handle is dropped.
+pub struct HttpServerHandle {
+    addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl HttpServerHandle {
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stop accepting the connections and wait for the server thread.
+    /// The open connections are served until they are closed by the clients.
+    pub fn shutdown(mut self) -> Result<()> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        let thread = match self.thread.take() {
+            Some(thread) => thread,
+            None => return Ok(()),
+        };
+        self.stopped.store(true, Ordering::SeqCst);
+
+        // wake up the blocking accept
+        let mut addr = self.addr;
+        if addr.ip().is_unspecified() {
+            addr.set_ip(match addr.ip() {
+                IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            });
+        }
+        let _ = TcpStream::connect(addr);
+
+        match thread.join() {
+            Ok(result) => result,
+            Err(_) => Ok(()),
+        }
+    }
+
+}
+
+impl Drop for HttpServerHandle {
+
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+
+}
+
+struct HttpError {
+    status: u16,
+    message: String,
+}
+
+impl HttpError {
+
+    fn new<T: Into<String>>(status: u16, message: T) -> HttpError {
+        HttpError {
+            status,
+            message: message.into(),
+        }
+    }
+
+    fn bad_request<T: Into<String>>(message: T) -> HttpError {
+        HttpError::new(400, message)
+    }
+
+    /// The error of reading a request, the stalled requests time out.
+    fn read_failed(err: io::Error) -> HttpError {
+        match err.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => HttpError::new(408, "the request timed out"),
+            _ => HttpError::bad_request(err.to_string()),
+        }
+    }
+
+}
+
+impl From<Error> for HttpError {
+
+    fn from(err: Error) -> Self {
+        let status = match &err {
+            Error::CollectionNotFound(_) | Error::UserNotFound(_) => 404,
+            Error::AuthenticationFailed => 401,
+            Error::Unauthorized(_) => 403,
+            Error::IOErr(_)
+            | Error::RocksDbErr(_)
+            | Error::Corruption(_)
+            | Error::ChecksumMismatch
+            | Error::DbIsClosed
+            | Error::DbNotReady
+            | Error::Busy
+            | Error::LockError => 500,
+            _ => 400,
+        };
+        HttpError::new(status, err.to_string())
+    }
+
+}
+
+type HttpResult<T> = std::result::Result<T, HttpError>;
+
+struct Request {
+    method: String,
+    path: Vec<String>,
+    query: Vec<(String, String)>,
+    body: Vec<u8>,
+    keep_alive: bool,
+    authorization: Option<String>,
+}
+
+impl Request {
+
+    fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn body_json(&self) -> HttpResult<Value> {
+        if self.body.is_empty() {
+            return Ok(Value::Object(Default::default()));
+        }
+        serde_json::from_slice(&self.body)
+            .map_err(|err| HttpError::bad_request(format!("invalid JSON body: {}", err)))
+    }
+
+    fn body_document(&self) -> HttpResult<Document> {
+        json_to_document(self.body_json()?)
+    }
+
+}
+
+fn handle_connection(context: &Context, stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    loop {
+        let (response, keep_alive) = match read_request(&mut reader) {
+            Ok(None) => return Ok(()),
+            Ok(Some(request)) => (route(context, &request), request.keep_alive),
+            Err(err) => (Err(err), false),
+        };
+        write_response(&mut writer, response, keep_alive)?;
+        if !keep_alive {
+            return Ok(());
+        }
+    }
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> HttpResult<Option<String>> {
+    let mut line = String::new();
+    let size = reader
+        .by_ref()
+        .take(MAX_LINE_SIZE)
+        .read_line(&mut line)
+        .map_err(HttpError::read_failed)?;
+    if size == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with('\n') {
+        return Err(HttpError::new(431, "the line of the request is too long"));
+    }
+    Ok(Some(line.trim_end_matches(&['\r', '\n'][..]).to_string()))
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> HttpResult<Option<Request>> {
+    let request_line = match read_line(reader)? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    let mut parts = request_line.split_whitespace();
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) => (method, target, version),
+        _ => return Err(HttpError::bad_request("malformed request line")),
+    };
+
+    let mut keep_alive = version == "HTTP/1.1";
+    let mut content_length = 0usize;
+    let mut authorization = None;
+    loop {
+        let line = read_line(reader)?.ok_or_else(|| HttpError::bad_request("unexpected end of the headers"))?;
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| HttpError::bad_request("malformed header"))?;
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => {
+                content_length = value
+                    .parse()
+                    .map_err(|_| HttpError::bad_request("invalid Content-Length"))?;
+            }
+            "connection" => {
+                keep_alive = !value.eq_ignore_ascii_case("close")
+                    && (keep_alive || value.eq_ignore_ascii_case("keep-alive"));
+            }
+            "authorization" => {
+                authorization = Some(value.to_string());
+            }
+            "transfer-encoding" => {
+                return Err(HttpError::new(501, "the transfer encodings are not supported"));
+            }
+            _ => (),
+        }
+    }
+    if content_length > MAX_BODY_SIZE {
+        return Err(HttpError::new(413, "the body of the request is too large"));
+    }
+
+    let mut body = vec![0u8; content_length];
+    reader
+        .read_exact(&mut body)
+        .map_err(HttpError::read_failed)?;
+
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, query),
+        None => (target, ""),
+    };
+    let path = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| percent_decode(segment, false))
+        .collect::<HttpResult<Vec<String>>>()?;
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Ok((percent_decode(key, true)?, percent_decode(value, true)?))
+        })
+        .collect::<HttpResult<Vec<(String, String)>>>()?;
+
+    Ok(Some(Request {
+        method: method.to_string(),
+        path,
+        query,
+        body,
+        keep_alive,
+        authorization,
+    }))
+}
+
+fn percent_decode(value: &str, plus_as_space: bool) -> HttpResult<String> {
+    let bytes = value.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = bytes
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| HttpError::bad_request("malformed percent-encoding"))?;
+                result.push(hex);
+                i += 3;
+            }
+            b'+' if plus_as_space => {
+                result.push(b' ');
+                i += 1;
+            }
+            byte => {
+                result.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(result).map_err(|_| HttpError::bad_request("the URL is not UTF-8"))
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+fn write_response<W: Write>(writer: &mut W, response: HttpResult<Value>, keep_alive: bool) -> io::Result<()> {
+    let (status, body) = match response {
+        Ok(body) => (200, body),
+        Err(err) => {
+            let mut body = serde_json::Map::new();
+            body.insert("error".to_string(), Value::String(err.message));
+            (err.status, Value::Object(body))
+        }
+    };
+    let body = serde_json::to_vec(&body)?;
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: {}\r\n",
+        status,
+        reason_phrase(status),
+        body.len(),
+        if keep_alive { "keep-alive" } else { "close" },
+    )?;
+    if status == 401 {
+        write!(writer, "WWW-Authenticate: Basic realm=\"polodb\", charset=\"UTF-8\"\r\n")?;
+    }
+    write!(writer, "\r\n")?;
+    writer.write_all(&body)?;
+    writer.flush()
+}
+
+fn route(context: &Context, request: &Request) -> HttpResult<Value> {
+    let user = context.authenticate(request)?;
+    let user = user.as_ref();
+    let db = context.db.as_ref();
+    let path: Vec<&str> = request.path.iter().map(String::as_str).collect();
+    match (request.method.as_str(), path.as_slice()) {
+        ("GET", ["collections"]) => {
+            context.authorize(user, Action::Read, None)?;
+            let mut names = db.list_collection_names()?;
+            names.retain(|name| context.authorize(user, Action::Read, Some(name)).is_ok());
+            Ok(Value::from(names))
+        }
+        ("GET", ["collections", name]) => {
+            context.authorize(user, Action::Read, Some(name))?;
+            let filter = match request.query_param("filter") {
+                Some(filter) => {
+                    let filter = serde_json::from_str(filter)
+                        .map_err(|err| HttpError::bad_request(format!("invalid filter: {}", err)))?;
+                    json_to_document(filter)?
+                }
+                None => Document::new(),
+            };
+            let skip = parse_query_u64(request, "skip")?;
+            let limit = parse_query_u64(request, "limit")?;
+            find_documents(db, name, filter, None, skip, limit)
+        }
+        ("POST", ["collections", name]) => {
+            context.authorize(user, Action::Write, Some(name))?;
+            insert_documents(db, name, request.body_json()?)
+        }
+        ("DELETE", ["collections", name]) => {
+            context.authorize(user, Action::Write, Some(name))?;
+            let result = db.collection::<Document>(name).drop()?;
+            to_json(&result)
+        }
+        ("GET", ["collections", name, "count"]) => {
+            context.authorize(user, Action::Read, Some(name))?;
+            let count = db.collection::<Document>(name).count_documents()?;
+            let mut body = serde_json::Map::new();
+            body.insert("count".to_string(), Value::from(count));
+            Ok(Value::Object(body))
+        }
+        ("POST", ["collections", name, "find"]) => {
+            context.authorize(user, Action::Read, Some(name))?;
+            let body = request.body_document()?;
+            let filter = get_document(&body, "filter")?.unwrap_or_default();
+            let sort = get_document(&body, "sort")?;
+            let skip = get_u64(&body, "skip")?;
+            let limit = get_u64(&body, "limit")?;
+            find_documents(db, name, filter, sort, skip, limit)
+        }
+        ("POST", ["collections", name, "update"]) => {
+            context.authorize(user, Action::Write, Some(name))?;
+            let body = request.body_document()?;
+            let filter = get_document(&body, "filter")?.unwrap_or_default();
+            let update = get_document(&body, "update")?
+                .ok_or_else(|| HttpError::bad_request("update is missing"))?;
+            let options = UpdateOptions::builder()
+                .upsert(get_bool(&body, "upsert")?)
+                .build();
+            let collection = db.collection::<Document>(name);
+            let result = if get_bool(&body, "many")? {
+                collection.update_many_with_options(filter, update, options)?
+            } else {
+                collection.update_one_with_options(filter, update, options)?
+            };
+            to_json(&result)
+        }
+        ("POST", ["collections", name, "delete"]) => {
+            context.authorize(user, Action::Write, Some(name))?;
+            let body = request.body_document()?;
+            let filter = get_document(&body, "filter")?.unwrap_or_default();
+            let collection = db.collection::<Document>(name);
+            let result = if get_bool(&body, "many")? {
+                collection.delete_many(filter)?
+            } else {
+                collection.delete_one(filter)?
+            };
+            to_json(&result)
+        }
+        ("POST", ["collections", name, "aggregate"]) => {
+            let body = request.body_document()?;
+            let pipeline = match body.get("pipeline") {
+                Some(Bson::Array(stages)) => stages
+                    .iter()
+                    .map(|stage| match stage {
+                        Bson::Document(stage) => Ok(stage.clone()),
+                        _ => Err(HttpError::bad_request("the stage of the pipeline is not a document")),
+                    })
+                    .collect::<HttpResult<Vec<Document>>>()?,
+                Some(_) => return Err(HttpError::bad_request("pipeline is not an array")),
+                None => return Err(HttpError::bad_request("pipeline is missing")),
+            };
+            context.authorize(user, Action::Read, Some(name))?;
+            for foreign in lookup_collections(&pipeline) {
+                context.authorize(user, Action::Read, Some(&foreign))?;
+            }
+            let cursor = db.collection::<Document>(name).aggregate(pipeline).run()?;
+            documents_to_json(cursor)
+        }
+        #[cfg(feature = "graphql")]
+        ("POST", ["graphql"]) if context.graphql.is_some() => {
+            context.authorize(user, Action::Write, None)?;
+            let schema = context.graphql.as_ref().unwrap();
+            graphql_request(db, schema, request.body_document()?)
+        }
+        #[cfg(feature = "graphql")]
+        (_, ["graphql"]) if context.graphql.is_some() => {
+            Err(HttpError::new(405, format!("method {} is not allowed", request.method)))
+        }
+        (_, ["collections"])
+        | (_, ["collections", _])
+        | (_, ["collections", _, "count"])
+        | (_, ["collections", _, "find"])
+        | (_, ["collections", _, "update"])
+        | (_, ["collections", _, "delete"])
+        | (_, ["collections", _, "aggregate"]) => {
+            Err(HttpError::new(405, format!("method {} is not allowed", request.method)))
+        }
+        _ => Err(HttpError::new(404, "not found")),
+    }
+}
+
+fn find_documents(
+    db: &Database,
+    name: &str,
+    filter: Document,
+    sort: Option<Document>,
+    skip: Option<u64>,
+    limit: Option<u64>,
+) -> HttpResult<Value> {
+    let collection = db.collection::<Document>(name);
+    let mut find = collection.find(filter);
+    if let Some(sort) = sort {
+        find = find.sort(sort);
+    }
+    if let Some(skip) = skip {
+        find = find.skip(skip);
+    }
+    if let Some(limit) = limit {
+        find = find.limit(limit);
+    }
+    documents_to_json(find.run()?)
+}
+
+fn insert_documents(db: &Database, name: &str, body: Value) -> HttpResult<Value> {
+    let collection = db.collection::<Document>(name);
+    match body {
+        Value::Array(items) => {
+            let docs = items
+                .into_iter()
+                .map(json_to_document)
+                .collect::<HttpResult<Vec<Document>>>()?;
+            let result = collection.insert_many(docs)?;
+            to_json(&result)
+        }
+        body => {
+            let result = collection.insert_one(json_to_document(body)?)?;
+            to_json(&result)
+        }
+    }
+}
+
+#[cfg(feature = "graphql")]
+fn graphql_request(db: &Database, schema: &GraphQlSchema, body: Document) -> HttpResult<Value> {
+    let query = match body.get("query") {
+        Some(Bson::String(query)) => query.clone(),
+        Some(_) => return Err(HttpError::bad_request("query is not a string")),
+        None => return Err(HttpError::bad_request("query is missing")),
+    };
+    let mut request = GraphQlRequest::new(query);
+    match body.get("operationName") {
+        Some(Bson::String(name)) => request = request.operation_name(name.clone()),
+        Some(Bson::Null) | None => (),
+        Some(_) => return Err(HttpError::bad_request("operationName is not a string")),
+    }
+    match body.get("variables") {
+        Some(Bson::Document(variables)) => request = request.variables(variables.clone()),
+        Some(Bson::Null) | None => (),
+        Some(_) => return Err(HttpError::bad_request("variables is not an object")),
+    }
+    let response = schema.execute(db, &request);
+    Ok(Bson::Document(response).into_relaxed_extjson())
+}
+
+fn documents_to_json(cursor: impl Iterator<Item = Result<Document>>) -> HttpResult<Value> {
+    let mut docs = Vec::new();
+    for doc in cursor {
+        docs.push(Bson::Document(doc?).into_relaxed_extjson());
+    }
+    Ok(Value::Array(docs))
+}
+
+fn to_json<T: Serialize>(value: &T) -> HttpResult<Value> {
+    let value = bson::to_bson(value).map_err(|err| HttpError::new(500, err.to_string()))?;
+    Ok(value.into_relaxed_extjson())
+}
+
+fn json_to_document(value: Value) -> HttpResult<Document> {
+    match Bson::try_from(value) {
+        Ok(Bson::Document(doc)) => Ok(doc),
+        Ok(_) => Err(HttpError::bad_request("expect a JSON object")),
+        Err(err) => Err(HttpError::bad_request(format!("invalid extended JSON: {}", err))),
+    }
+}
+
+fn get_document(doc: &Document, key: &str) -> HttpResult<Option<Document>> {
+    match doc.get(key) {
+        Some(Bson::Document(value)) => Ok(Some(value.clone())),
+        Some(_) => Err(HttpError::bad_request(format!("{} is not a document", key))),
+        None => Ok(None),
+    }
+}
+
+fn get_u64(doc: &Document, key: &str) -> HttpResult<Option<u64>> {
+    let value = match doc.get(key) {
+        Some(Bson::Int32(value)) => *value as i64,
+        Some(Bson::Int64(value)) => *value,
+        Some(Bson::Double(value)) if value.fract() == 0.0 => *value as i64,
+        Some(_) => return Err(HttpError::bad_request(format!("{} is not an integer", key))),
+        None => return Ok(None),
+    };
+    u64::try_from(value)
+        .map(Some)
+        .map_err(|_| HttpError::bad_request(format!("{} is negative", key)))
+}
+
+fn get_bool(doc: &Document, key: &str) -> HttpResult<bool> {
+    match doc.get(key) {
+        Some(Bson::Boolean(value)) => Ok(*value),
+        Some(_) => Err(HttpError::bad_request(format!("{} is not a boolean", key))),
+        None => Ok(false),
+    }
+}
+
+fn parse_query_u64(request: &Request, name: &str) -> HttpResult<Option<u64>> {
+    request
+        .query_param(name)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| HttpError::bad_request(format!("{} is not an integer", name)))
+        })
+        .transpose()
+}
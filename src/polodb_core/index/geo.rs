@@ -0,0 +1,224 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The points of the `2dsphere` indexes, and the distances `$geoNear` sorts them by.
+//!
+//! A point is in the cell of its geohash, the cells of the nearby points share the first
+//! characters of their geohashes. A search within a distance only scans the entries of the
+//! coarser cells covering the bounding box of the circle.
+
+use std::convert::TryInto;
+use bson::Bson;
+
+/// The radius of the Earth in meters, as MongoDB measures the spherical distances.
+const EARTH_RADIUS: f64 = 6_378_100.0;
+/// The length of the geohashes of the index entries, cells of about 38 by 19 meters.
+pub(crate) const INDEX_PRECISION: usize = 8;
+/// The largest number of the cells a search scans, the cells are coarser if more are needed.
+const MAX_COVER_CELLS: usize = 64;
+const BASE32: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// The longitude and the latitude of a GeoJSON point, e.g.
+/// `{ "type": "Point", "coordinates": [2.35, 48.85] }`, or of a legacy pair `[2.35, 48.85]`.
+/// `None` for the other values, which are not indexed.
+pub(crate) fn parse_point(value: &Bson) -> Option<(f64, f64)> {
+    let coordinates = match value {
+        Bson::Document(doc) if doc.get_str("type").ok()? == "Point" => doc.get_array("coordinates").ok()?,
+        Bson::Array(coordinates) => coordinates,
+        _ => return None,
+    };
+    if coordinates.len() != 2 {
+        return None;
+    }
+    let number = |value: &Bson| match value {
+        Bson::Double(value) => Some(*value),
+        Bson::Int32(value) => Some(*value as f64),
+        Bson::Int64(value) => Some(*value as f64),
+        _ => None,
+    };
+    let (lng, lat) = (number(&coordinates[0])?, number(&coordinates[1])?);
+    if !(-180.0..=180.0).contains(&lng) || !(-90.0..=90.0).contains(&lat) {
+        return None;
+    }
+    Some((lng, lat))
+}
+
+/// The big-endian bytes of the point, the value of its index entry.
+pub(crate) fn encode(point: (f64, f64)) -> Vec<u8> {
+    let mut result = point.0.to_be_bytes().to_vec();
+    result.extend_from_slice(&point.1.to_be_bytes());
+    result
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> Option<(f64, f64)> {
+    if bytes.len() != 16 {
+        return None;
+    }
+    let lng = f64::from_be_bytes(bytes[..8].try_into().unwrap());
+    let lat = f64::from_be_bytes(bytes[8..].try_into().unwrap());
+    Some((lng, lat))
+}
+
+/// The spherical distance of the points in meters, by the haversine formula.
+pub(crate) fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat_a, lat_b) = (a.1.to_radians(), b.1.to_radians());
+    let d_lat = lat_b - lat_a;
+    let d_lng = (b.0 - a.0).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lng / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * h.sqrt().min(1.0).asin()
+}
+
+/// The geohash of the point with `precision` characters.
+pub(crate) fn geohash(point: (f64, f64), precision: usize) -> String {
+    let mut lng_range = (-180.0, 180.0);
+    let mut lat_range = (-90.0, 90.0);
+    let mut result = String::with_capacity(precision);
+    let mut is_lng = true;
+    let mut bits = 0;
+    let mut index = 0;
+    while result.len() < precision {
+        let (range, value) = if is_lng { (&mut lng_range, point.0) } else { (&mut lat_range, point.1) };
+        let mid = (range.0 + range.1) / 2.0;
+        index <<= 1;
+        if value >= mid {
+            index |= 1;
+            range.0 = mid;
+        } else {
+            range.1 = mid;
+        }
+        is_lng = !is_lng;
+        bits += 1;
+        if bits == 5 {
+            result.push(BASE32[index] as char);
+            bits = 0;
+            index = 0;
+        }
+    }
+    result
+}
+
+// the width and the height in degrees of the cells of the geohashes with `precision` characters
+fn cell_size(precision: usize) -> (f64, f64) {
+    let bits = 5 * precision as i32;
+    let lng_bits = (bits + 1) / 2;
+    let lat_bits = bits / 2;
+    (360.0 / 2f64.powi(lng_bits), 180.0 / 2f64.powi(lat_bits))
+}
+
+/// The geohashes of the cells covering the points within `max_distance` meters of the center,
+/// the coarsest cells of at most [`MAX_COVER_CELLS`]. The entries of a cell are the ones
+/// whose geohashes start with the geohash of the cell.
+pub(crate) fn cover_cells(center: (f64, f64), max_distance: f64) -> Vec<String> {
+    let angle = max_distance / EARTH_RADIUS;
+    let d_lat = angle.to_degrees();
+    let (min_lat, max_lat) = (center.1 - d_lat, center.1 + d_lat);
+
+    // the circle around a pole or wider than the hemisphere covers every longitude
+    let lng_ranges: Vec<(f64, f64)> = if min_lat <= -90.0 || max_lat >= 90.0 || angle >= std::f64::consts::FRAC_PI_2 {
+        vec![(-180.0, 180.0)]
+    } else {
+        let sin = angle.sin() / center.1.to_radians().cos();
+        if sin >= 1.0 {
+            vec![(-180.0, 180.0)]
+        } else {
+            let d_lng = sin.asin().to_degrees();
+            let (min_lng, max_lng) = (center.0 - d_lng, center.0 + d_lng);
+            // split at the antimeridian
+            if min_lng < -180.0 {
+                vec![(min_lng + 360.0, 180.0), (-180.0, max_lng)]
+            } else if max_lng > 180.0 {
+                vec![(min_lng, 180.0), (-180.0, max_lng - 360.0)]
+            } else {
+                vec![(min_lng, max_lng)]
+            }
+        }
+    };
+    let (min_lat, max_lat) = (min_lat.max(-90.0), max_lat.min(90.0));
+
+    let cells_of = |precision: usize| -> Vec<String> {
+        let (width, height) = cell_size(precision);
+        let mut cells = Vec::new();
+        let lat_cells = |lat: f64| (((lat + 90.0) / height).floor() as i64).min((180.0 / height) as i64 - 1);
+        let lng_cells = |lng: f64| (((lng + 180.0) / width).floor() as i64).min((360.0 / width) as i64 - 1);
+        for (min_lng, max_lng) in &lng_ranges {
+            for y in lat_cells(min_lat)..=lat_cells(max_lat) {
+                for x in lng_cells(*min_lng)..=lng_cells(*max_lng) {
+                    let cell_center = (-180.0 + (x as f64 + 0.5) * width, -90.0 + (y as f64 + 0.5) * height);
+                    let cell = geohash(cell_center, precision);
+                    if !cells.contains(&cell) {
+                        cells.push(cell);
+                    }
+                }
+            }
+        }
+        cells
+    };
+    let cell_count = |precision: usize| -> usize {
+        let (width, height) = cell_size(precision);
+        let lat_count = ((max_lat + 90.0) / height).floor() - ((min_lat + 90.0) / height).floor() + 1.0;
+        let lng_count: f64 = lng_ranges.iter()
+            .map(|(min_lng, max_lng)| ((max_lng + 180.0) / width).floor() - ((min_lng + 180.0) / width).floor() + 1.0)
+            .sum();
+        (lat_count * lng_count) as usize
+    };
+
+    let precision = (1..=INDEX_PRECISION)
+        .rev()
+        .find(|precision| cell_count(*precision) <= MAX_COVER_CELLS)
+        .unwrap_or(1);
+    cells_of(precision)
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{bson, Bson};
+    use super::{cover_cells, decode, distance, encode, geohash, parse_point, INDEX_PRECISION};
+
+    const PARIS: (f64, f64) = (2.3522, 48.8566);
+    const LONDON: (f64, f64) = (-0.1276, 51.5072);
+
+    #[test]
+    fn test_points() {
+        assert_eq!(parse_point(&bson!({ "type": "Point", "coordinates": [2.5, 48] })), Some((2.5, 48.0)));
+        assert_eq!(parse_point(&bson!([2.5, 48.0])), Some((2.5, 48.0)));
+        assert_eq!(parse_point(&bson!({ "type": "LineString", "coordinates": [2.5, 48] })), None);
+        assert_eq!(parse_point(&bson!([200, 48])), None);
+        assert_eq!(parse_point(&Bson::Null), None);
+        assert_eq!(decode(&encode(PARIS)), Some(PARIS));
+
+        let meters = distance(PARIS, LONDON);
+        assert!((meters - 344_000.0).abs() < 2_000.0, "{}", meters);
+        assert_eq!(distance(PARIS, PARIS), 0.0);
+        assert_eq!(geohash((-5.6, 42.6), 5), "ezs42");
+    }
+
+    #[test]
+    fn test_cover_cells() {
+        let points = [PARIS, LONDON, (2.4, 48.9), (179.99, 0.0), (-179.99, 0.0), (0.0, 89.9), (120.0, 89.95)];
+        let max_distances = [10.0, 1_000.0, 10_000.0, 400_000.0, 5_000_000.0];
+        for center in points.iter() {
+            for max_distance in max_distances.iter() {
+                let cells = cover_cells(*center, *max_distance);
+                assert!(cells.len() <= 64, "{:?} {}", center, max_distance);
+                // every point within the distance is in a cell of the cover
+                for point in points.iter().filter(|point| distance(*center, **point) <= *max_distance) {
+                    let hash = geohash(*point, INDEX_PRECISION);
+                    assert!(cells.iter().any(|cell| hash.starts_with(cell)), "{:?} {:?} {}", center, point, max_distance);
+                }
+            }
+        }
+        assert!(!cover_cells(PARIS, 1_000.0).iter().any(|cell| geohash(LONDON, INDEX_PRECISION).starts_with(cell)));
+    }
+
+}
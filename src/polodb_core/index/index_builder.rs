@@ -15,6 +15,7 @@
 use bson::Document;
 use crate::Result;
 use crate::coll::collection_info::IndexInfo;
+use crate::coll::key_ordering;
 use crate::cursor::Cursor;
 use crate::index::{IndexHelper, IndexHelperOperation};
 use crate::transaction::TransactionInner;
@@ -55,8 +56,9 @@ impl<'b, 'c, 'd, 'e> IndexBuilder<'b, 'c, 'd, 'e> {
         while cursor.has_next() {
             // get the value and insert index
             let current_data = cursor.copy_data()?;
+            let current_key = cursor.peek_key().expect("key must exist");
 
-            self.execute_index_item(op, current_data.as_ref())?;
+            self.execute_index_item(op, current_key.as_ref(), current_data.as_ref())?;
 
             cursor.next()?;
         }
@@ -64,9 +66,16 @@ impl<'b, 'c, 'd, 'e> IndexBuilder<'b, 'c, 'd, 'e> {
         Ok(())
     }
 
-    fn execute_index_item(&mut self, op: IndexHelperOperation, current_data: &[u8]) -> Result<()> {
+    fn execute_index_item(&mut self, op: IndexHelperOperation, current_key: &[u8], current_data: &[u8]) -> Result<()> {
         let data_doc = bson::from_slice::<Document>(current_data)?;
-        let pkey = data_doc.get("_id").unwrap();
+        // the entries of a collection with a key ordering end with the stored key
+        let stored_pkey = crate::utils::bson::split_stacked_keys(current_key)?
+            .pop()
+            .filter(key_ordering::is_ordered_key);
+        let pkey = match &stored_pkey {
+            Some(stored_pkey) => stored_pkey,
+            None => data_doc.get("_id").unwrap(),
+        };
 
         IndexHelper::try_execute_with_index_info(
             op,
@@ -18,8 +18,10 @@ use crate::Result;
 use crate::coll::collection_info::{
     CollectionSpecification,
     IndexInfo,
+    IndexKind,
 };
 use crate::errors::DuplicateKeyError;
+use crate::index::{geo, trigram, vector, Analyzer};
 use crate::transaction::TransactionInner;
 
 pub(crate) const INDEX_PREFIX: &'static str = "$I";
@@ -62,7 +64,7 @@ impl<'b, 'c, 'd, 'e> IndexHelper<'b, 'c, 'd, 'e> {
             IndexHelper::try_execute_with_index_info(
                 op,
                 &self.doc,
-                self.col_spec._id.as_str(),
+                self.col_spec.storage_name(),
                 self.pkey,
                 index_name.as_str(),
                 index_info,
@@ -88,12 +90,52 @@ impl<'b, 'c, 'd, 'e> IndexHelper<'b, 'c, 'd, 'e> {
         let first_tuple = tuples.first().unwrap();
         let (keys, _order) = first_tuple;
 
-        let value = crate::utils::bson::try_get_document_value(data_doc, keys);
+        // the GeoJSON points are documents
+        let value = if index_info.kind == IndexKind::Geo2dSphere {
+            crate::utils::bson::get_path_value(data_doc, keys).cloned()
+        } else {
+            crate::utils::bson::try_get_document_value(data_doc, keys)
+        };
         if value.is_none() {
             return Ok(())
         }
 
-        if index_info.is_unique() {
+        if index_info.kind == IndexKind::Geo2dSphere {
+            return IndexHelper::execute_geo_index(
+                op,
+                value.as_ref().unwrap(),
+                col_name,
+                pkey,
+                index_name,
+                txn,
+            );
+        }
+
+        if index_info.kind == IndexKind::Vector {
+            return IndexHelper::execute_vector_index(
+                op,
+                value.as_ref().unwrap(),
+                col_name,
+                pkey,
+                index_name,
+                index_info,
+                txn,
+            );
+        }
+
+        if !index_info.kind.is_ascending() {
+            return IndexHelper::execute_term_index(
+                op,
+                value.as_ref().unwrap(),
+                col_name,
+                pkey,
+                index_name,
+                index_info,
+                txn,
+            );
+        }
+
+        if op == IndexHelperOperation::Insert && index_info.is_unique() {
             IndexHelper::check_unique_key(
                 col_name,
                 index_name,
@@ -119,6 +161,113 @@ impl<'b, 'c, 'd, 'e> IndexHelper<'b, 'c, 'd, 'e> {
         Ok(())
     }
 
+    // The key of a text or trigram index has a term instead of the value:
+    // '$I' + '\t' + collection_id + '\t' + index_name + '\t' + term + '\t' + primary_key
+    fn execute_term_index(
+        op: IndexHelperOperation,
+        value: &Bson,
+        col_name: &str,
+        pkey: &Bson,
+        index_name: &str,
+        index_info: &IndexInfo,
+        txn: &TransactionInner,
+    ) -> Result<()> {
+        let terms = if index_info.kind == IndexKind::Trigram {
+            trigram::trigrams_of_value(value)
+        } else {
+            let analyzer = Analyzer::from_options(
+                index_info.options.as_ref(),
+                |name| txn.rocksdb_txn.tokenizer(name),
+            )?;
+            analyzer.analyze_value(value)
+        };
+
+        for term in terms {
+            let index_key = IndexHelper::make_index_key(
+                col_name,
+                index_name,
+                &Bson::String(term),
+                Some(pkey),
+            )?;
+
+            if op == IndexHelperOperation::Insert {
+                let value_buf = [ElementType::Null as u8];
+                txn.put(index_key.as_slice(), &value_buf)?;
+            } else {
+                txn.delete(index_key.as_slice())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // The key of a vector index has the list of the nearest centroid instead of the value,
+    // and the value of the entry is the vector:
+    // '$I' + '\t' + collection_id + '\t' + index_name + '\t' + list + '\t' + primary_key
+    fn execute_vector_index(
+        op: IndexHelperOperation,
+        value: &Bson,
+        col_name: &str,
+        pkey: &Bson,
+        index_name: &str,
+        index_info: &IndexInfo,
+        txn: &TransactionInner,
+    ) -> Result<()> {
+        let (dimensions, similarity) = vector::index_options(index_info.options.as_ref())?;
+        let vector = match vector::parse_vector(value, dimensions) {
+            Some(vector) => vector,
+            None => return Ok(()),
+        };
+        let list = vector::nearest_list(&index_info.centroids, similarity, &vector);
+
+        let index_key = IndexHelper::make_index_key(
+            col_name,
+            index_name,
+            &Bson::Int32(list as i32),
+            Some(pkey),
+        )?;
+
+        if op == IndexHelperOperation::Insert {
+            txn.put(index_key.as_slice(), &vector::encode(&vector))?;
+        } else {
+            txn.delete(index_key.as_slice())?;
+        }
+
+        Ok(())
+    }
+
+    // The key of a 2dsphere index has the geohash of the point instead of the value,
+    // and the value of the entry is the point:
+    // '$I' + '\t' + collection_id + '\t' + index_name + '\t' + geohash + '\t' + primary_key
+    fn execute_geo_index(
+        op: IndexHelperOperation,
+        value: &Bson,
+        col_name: &str,
+        pkey: &Bson,
+        index_name: &str,
+        txn: &TransactionInner,
+    ) -> Result<()> {
+        let point = match geo::parse_point(value) {
+            Some(point) => point,
+            None => return Ok(()),
+        };
+
+        let index_key = IndexHelper::make_index_key(
+            col_name,
+            index_name,
+            &Bson::String(geo::geohash(point, geo::INDEX_PRECISION)),
+            Some(pkey),
+        )?;
+
+        if op == IndexHelperOperation::Insert {
+            txn.put(index_key.as_slice(), &geo::encode(point))?;
+        } else {
+            txn.delete(index_key.as_slice())?;
+        }
+
+        Ok(())
+    }
+
     fn check_unique_key(
         col_name: &str,
         index_name: &str,
@@ -12,9 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bson::Document;
+use bson::{doc, Bson, Document};
+use std::convert::TryFrom;
 use serde::{Deserialize, Serialize};
 
+// the fields of the index specifications supported by PoloDB,
+// the indexes with other fields, e.g. `sparse`, are not supported
+const SUPPORTED_INDEX_FIELDS: [&str; 12] = [
+    "v", "key", "name", "unique", "ns", "background", "analyzer", "stopWords", "dimensions", "similarity", "lists",
+    "2dsphereIndexVersion",
+];
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct IndexModel {
@@ -26,6 +34,88 @@ pub struct IndexModel {
     pub options: Option<IndexOptions>,
 }
 
+impl IndexModel {
+
+    /// Convert an index specification of MongoDB, as returned by `listIndexes`,
+    /// e.g. `{ "key": { "name": 1 }, "name": "name_1", "unique": true }`.
+    /// Return `None` if the index is not supported by PoloDB,
+    /// i.e. it has several keys, a descending or a special key other than `2dsphere`, or other options.
+    /// The text, trigram and vector indexes of PoloDB, e.g. `{ "key": { "body": "text" }, "analyzer": "english" }`,
+    /// are supported, unlike the text indexes of MongoDB.
+    pub fn from_spec(index: &Document) -> Option<IndexModel> {
+        if index.keys().any(|field| !SUPPORTED_INDEX_FIELDS.contains(&field.as_str())) {
+            return None;
+        }
+        let key = index.get_document("key").ok()?;
+        if key.len() != 1 {
+            return None;
+        }
+        let (field, order) = key.iter().next()?;
+        if let Some(kind @ ("trigram" | "2dsphere")) = order.as_str() {
+            return Some(IndexModel {
+                keys: doc! { field.clone(): kind },
+                options: Some(IndexOptions {
+                    name: index.get_str("name").ok().map(String::from),
+                    ..Default::default()
+                }),
+            });
+        }
+        if order.as_str() == Some("vector") {
+            let as_u32 = |field: &str| -> Option<Option<u32>> {
+                match index.get(field) {
+                    None => Some(None),
+                    Some(Bson::Int32(value)) => u32::try_from(*value).ok().map(Some),
+                    Some(Bson::Int64(value)) => u32::try_from(*value).ok().map(Some),
+                    Some(_) => None,
+                }
+            };
+            return Some(IndexModel {
+                keys: doc! { field.clone(): "vector" },
+                options: Some(IndexOptions {
+                    name: index.get_str("name").ok().map(String::from),
+                    dimensions: as_u32("dimensions")?,
+                    similarity: index.get_str("similarity").ok().map(String::from),
+                    lists: as_u32("lists")?,
+                    ..Default::default()
+                }),
+            });
+        }
+        if order.as_str() == Some("text") {
+            let stop_words = match index.get_array("stopWords") {
+                Ok(words) => Some(words.iter().map(|word| word.as_str().map(String::from)).collect::<Option<Vec<String>>>()?),
+                Err(_) => None,
+            };
+            return Some(IndexModel {
+                keys: doc! { field.clone(): "text" },
+                options: Some(IndexOptions {
+                    name: index.get_str("name").ok().map(String::from),
+                    analyzer: index.get_str("analyzer").ok().map(String::from),
+                    stop_words,
+                    ..Default::default()
+                }),
+            });
+        }
+        let ascending = match order {
+            Bson::Int32(order) => *order == 1,
+            Bson::Int64(order) => *order == 1,
+            Bson::Double(order) => *order == 1.0,
+            _ => false,
+        };
+        if !ascending {
+            return None;
+        }
+        Some(IndexModel {
+            keys: doc! { field.clone(): 1 },
+            options: Some(IndexOptions {
+                name: index.get_str("name").ok().map(String::from),
+                unique: Some(index.get_bool("unique").unwrap_or(false)),
+                ..Default::default()
+            }),
+        })
+    }
+
+}
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct IndexOptions {
@@ -37,4 +127,25 @@ pub struct IndexOptions {
     /// key value matches an existing value in the index. The default value is false.
     pub unique: Option<bool>,
 
+    /// The analyzer of a text index, i.e. an index whose key is `"text"`, e.g.
+    /// `{ "body": "text" }`. One of `standard`, the default, `english`, which removes
+    /// the English stop words and stems the words, `whitespace`, or the name of a
+    /// tokenizer registered by [`crate::ConfigBuilder::set_tokenizer`].
+    pub analyzer: Option<String>,
+
+    /// The words not indexed by a text index, instead of the stop words of the analyzer.
+    pub stop_words: Option<Vec<String>>,
+
+    /// The number of the dimensions of the vectors of a vector index, i.e. an index
+    /// whose key is `"vector"`, e.g. `{ "embedding": "vector" }`. It's required, the
+    /// values which aren't arrays of that many numbers are not indexed.
+    pub dimensions: Option<u32>,
+
+    /// The similarity of the vectors of a vector index, `cosine`, the default, or `euclidean`.
+    pub similarity: Option<String>,
+
+    /// The number of the lists of a vector index, the more lists the fewer vectors
+    /// a search compares. The square root of the number of the vectors by default.
+    pub lists: Option<u32>,
+
 }
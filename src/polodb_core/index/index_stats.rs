@@ -0,0 +1,205 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The statistics of the values of an index, for the selectivity estimates of the planner.
+//!
+//! The entries are scanned in the order of the index, so the equal values are adjacent.
+//! The histogram is equi-depth: a bucket is closed at the end of the first value after
+//! it holds `count / MAX_BUCKETS` entries, so a frequent value closes a bucket by itself
+//! and its count is kept exactly in the `upper_count` of the bucket.
+
+use std::cmp::Ordering;
+use bson::Bson;
+use crate::cursor::Cursor;
+use crate::index::INDEX_PREFIX;
+use crate::results::{HistogramBucket, IndexStats};
+use crate::transaction::TransactionInner;
+use crate::utils::bson::{bson_datetime_now, split_stacked_keys, stacked_key, stacked_key_bytes};
+use crate::Result;
+
+const MAX_BUCKETS: u64 = 64;
+
+// the position of the value in the key of an index entry:
+// prefix, collection, index name, value, primary key
+const INDEX_KEY_VALUE_POSITION: usize = 3;
+
+/// Scan the entries of the index to compute its statistics.
+pub(crate) fn analyze_index(txn: &TransactionInner, col_name: &str, index_name: &str) -> Result<IndexStats> {
+    let prefix = stacked_key([
+        &Bson::String(INDEX_PREFIX.to_string()),
+        &Bson::String(col_name.to_string()),
+        &Bson::String(index_name.to_string()),
+    ])?;
+
+    // the first scan only reads the keys to decide the depth of the buckets
+    let mut count: u64 = 0;
+    let mut cursor = Cursor::new(prefix.clone(), txn.rocksdb_txn.new_iterator());
+    cursor.reset()?;
+    while cursor.has_next() {
+        count += 1;
+        cursor.next()?;
+    }
+
+    let mut builder = HistogramBuilder::new(count);
+    let mut cursor = Cursor::new(prefix, txn.rocksdb_txn.new_iterator());
+    cursor.reset()?;
+    while cursor.has_next() {
+        let key = cursor.peek_key().expect("key must exist");
+        let mut slices = split_stacked_keys(key.as_ref())?;
+        if slices.len() > INDEX_KEY_VALUE_POSITION {
+            builder.add(slices.swap_remove(INDEX_KEY_VALUE_POSITION))?;
+        }
+        cursor.next()?;
+    }
+
+    Ok(builder.finish())
+}
+
+fn encode_value(value: &Bson) -> Result<Vec<u8>> {
+    let mut encoded = Vec::new();
+    stacked_key_bytes(&mut encoded, value)?;
+    Ok(encoded)
+}
+
+/// Build the histogram from the values added in the order of the index.
+struct HistogramBuilder {
+    depth: u64,
+    count: u64,
+    distinct: u64,
+    buckets: Vec<HistogramBucket>,
+    bucket_count: u64,
+    bucket_distinct: u64,
+    /// The last value and its encoded bytes, which are compared with the next value
+    value: Option<(Vec<u8>, Bson)>,
+    value_count: u64,
+}
+
+impl HistogramBuilder {
+
+    fn new(expected_count: u64) -> HistogramBuilder {
+        HistogramBuilder {
+            depth: expected_count.div_ceil(MAX_BUCKETS).max(1),
+            count: 0,
+            distinct: 0,
+            buckets: Vec::new(),
+            bucket_count: 0,
+            bucket_distinct: 0,
+            value: None,
+            value_count: 0,
+        }
+    }
+
+    fn add(&mut self, value: Bson) -> Result<()> {
+        let encoded = encode_value(&value)?;
+        let is_same = matches!(&self.value, Some((last, _)) if *last == encoded);
+        if !is_same {
+            self.close_value(false);
+            self.value = Some((encoded, value));
+            self.value_count = 0;
+            self.distinct += 1;
+            self.bucket_distinct += 1;
+        }
+        self.value_count += 1;
+        self.count += 1;
+        self.bucket_count += 1;
+        Ok(())
+    }
+
+    /// End the last value, which closes the bucket if it's deep enough or `force`.
+    fn close_value(&mut self, force: bool) {
+        if !force && self.bucket_count < self.depth {
+            return;
+        }
+        if let Some((_, upper)) = self.value.take() {
+            self.buckets.push(HistogramBucket {
+                upper,
+                count: self.bucket_count,
+                distinct: self.bucket_distinct,
+                upper_count: self.value_count,
+            });
+            self.bucket_count = 0;
+            self.bucket_distinct = 0;
+        }
+    }
+
+    fn finish(mut self) -> IndexStats {
+        self.close_value(true);
+        IndexStats {
+            count: self.count,
+            distinct: self.distinct,
+            histogram: self.buckets,
+            updated_at: bson_datetime_now(),
+        }
+    }
+
+}
+
+impl IndexStats {
+
+    /// The estimated number of the entries equal to `value`.
+    ///
+    /// The value is found exactly if it's the upper bound of a bucket,
+    /// otherwise the other values of its bucket are assumed to be equally frequent.
+    pub(crate) fn estimate_equal(&self, value: &Bson) -> Result<f64> {
+        let encoded = encode_value(value)?;
+        for bucket in &self.histogram {
+            match encoded.cmp(&encode_value(&bucket.upper)?) {
+                Ordering::Equal => return Ok(bucket.upper_count as f64),
+                Ordering::Less => {
+                    let others = bucket.distinct.saturating_sub(1);
+                    if others == 0 {
+                        return Ok(0.0);
+                    }
+                    let others_count = bucket.count.saturating_sub(bucket.upper_count);
+                    return Ok(others_count as f64 / others as f64);
+                }
+                Ordering::Greater => (),
+            }
+        }
+        Ok(0.0)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::Bson;
+    use super::{HistogramBuilder, MAX_BUCKETS};
+
+    #[test]
+    fn test_histogram_skewed_values() {
+        // 1000 entries of "common", then one entry of each rare value
+        let rare_count = 3 * MAX_BUCKETS;
+        let mut builder = HistogramBuilder::new(1000 + rare_count);
+        for _ in 0..1000 {
+            builder.add(Bson::String("common".to_string())).unwrap();
+        }
+        for i in 0..rare_count {
+            builder.add(Bson::String(format!("rare{:04}", i))).unwrap();
+        }
+        let stats = builder.finish();
+
+        assert_eq!(stats.count, 1000 + rare_count);
+        assert_eq!(stats.distinct, 1 + rare_count);
+        assert!(stats.histogram.len() as u64 <= MAX_BUCKETS + 1);
+        assert_eq!(stats.histogram.iter().map(|bucket| bucket.count).sum::<u64>(), stats.count);
+
+        assert_eq!(stats.estimate_equal(&Bson::String("common".to_string())).unwrap(), 1000.0);
+        let rare = stats.estimate_equal(&Bson::String("rare0100".to_string())).unwrap();
+        assert!(rare > 0.0 && rare <= 1.0);
+        assert_eq!(stats.estimate_equal(&Bson::String("zzz".to_string())).unwrap(), 0.0);
+    }
+
+}
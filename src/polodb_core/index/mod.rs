@@ -15,7 +15,16 @@
 mod index_helper;
 mod index_model;
 mod index_builder;
+mod index_stats;
+mod stemmer;
+mod text_analyzer;
+pub(crate) mod geo;
+pub(crate) mod trigram;
+pub(crate) mod vector;
 
 pub(crate) use index_helper::{IndexHelper, IndexHelperOperation, INDEX_PREFIX};
 pub(crate) use index_builder::IndexBuilder;
+pub(crate) use index_stats::analyze_index;
+pub(crate) use text_analyzer::Analyzer;
 pub use index_model::{IndexModel, IndexOptions};
+pub use text_analyzer::{Tokenizer, StandardTokenizer, WhitespaceTokenizer};
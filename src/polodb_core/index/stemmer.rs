@@ -0,0 +1,294 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The Porter stemming algorithm for the English words,
+//! see <https://tartarus.org/martin/PorterStemmer/def.txt>.
+//!
+//! The port follows the reference implementation of the author, including its
+//! departures from the paper: `logi -> log` in step 2 and `bli -> ble` instead of `abli -> able`.
+
+/// The stem of a lower-cased word, the words with other characters than `a-z` are kept.
+pub(crate) fn stem(word: &str) -> String {
+    if word.len() <= 2 || !word.bytes().all(|b| b.is_ascii_lowercase()) {
+        return word.to_string();
+    }
+    let mut stemmer = Stemmer {
+        b: word.as_bytes().to_vec(),
+        k: word.len() as isize - 1,
+        j: 0,
+    };
+    stemmer.step1ab();
+    if stemmer.k > 0 {
+        stemmer.step1c();
+        stemmer.step2();
+        stemmer.step3();
+        stemmer.step4();
+        stemmer.step5();
+    }
+    stemmer.b.truncate((stemmer.k + 1) as usize);
+    String::from_utf8(stemmer.b).unwrap()
+}
+
+/// The word is `b[0..=k]`, `j` is the end of the stem before the suffix found by `ends`.
+struct Stemmer {
+    b: Vec<u8>,
+    k: isize,
+    j: isize,
+}
+
+impl Stemmer {
+
+    fn cons(&self, i: isize) -> bool {
+        match self.b[i as usize] {
+            b'a' | b'e' | b'i' | b'o' | b'u' => false,
+            b'y' => i == 0 || !self.cons(i - 1),
+            _ => true,
+        }
+    }
+
+    /// The number of the vowel-consonant sequences in `b[0..=j]`.
+    fn m(&self) -> usize {
+        let mut n = 0;
+        let mut i = 0;
+        loop {
+            if i > self.j {
+                return n;
+            }
+            if !self.cons(i) {
+                break;
+            }
+            i += 1;
+        }
+        i += 1;
+        loop {
+            loop {
+                if i > self.j {
+                    return n;
+                }
+                if self.cons(i) {
+                    break;
+                }
+                i += 1;
+            }
+            i += 1;
+            n += 1;
+            loop {
+                if i > self.j {
+                    return n;
+                }
+                if !self.cons(i) {
+                    break;
+                }
+                i += 1;
+            }
+            i += 1;
+        }
+    }
+
+    fn vowel_in_stem(&self) -> bool {
+        (0..=self.j).any(|i| !self.cons(i))
+    }
+
+    fn double_cons(&self, j: isize) -> bool {
+        j >= 1 && self.b[j as usize] == self.b[(j - 1) as usize] && self.cons(j)
+    }
+
+    /// `b[i - 2..=i]` is consonant-vowel-consonant and the last one is not `w`, `x` or `y`.
+    fn cvc(&self, i: isize) -> bool {
+        if i < 2 || !self.cons(i) || self.cons(i - 1) || !self.cons(i - 2) {
+            return false;
+        }
+        !matches!(self.b[i as usize], b'w' | b'x' | b'y')
+    }
+
+    fn ends(&mut self, s: &str) -> bool {
+        let len = s.len() as isize;
+        if len > self.k + 1 {
+            return false;
+        }
+        let start = (self.k - len + 1) as usize;
+        if &self.b[start..=self.k as usize] != s.as_bytes() {
+            return false;
+        }
+        self.j = self.k - len;
+        true
+    }
+
+    fn set_to(&mut self, s: &str) {
+        let start = (self.j + 1) as usize;
+        self.b.truncate(start);
+        self.b.extend_from_slice(s.as_bytes());
+        self.k = self.j + s.len() as isize;
+    }
+
+    fn replace(&mut self, s: &str) {
+        if self.m() > 0 {
+            self.set_to(s);
+        }
+    }
+
+    /// Replace the first suffix of `rules` which the word ends with,
+    /// if the stem before it has a vowel-consonant sequence.
+    fn replace_suffix(&mut self, rules: &[(&str, &str)]) {
+        for (suffix, replacement) in rules {
+            if self.ends(suffix) {
+                self.replace(replacement);
+                return;
+            }
+        }
+    }
+
+    /// Remove the plurals and `-ed` or `-ing`.
+    fn step1ab(&mut self) {
+        if self.b[self.k as usize] == b's' {
+            if self.ends("sses") {
+                self.k -= 2;
+            } else if self.ends("ies") {
+                self.set_to("i");
+            } else if self.b[(self.k - 1) as usize] != b's' {
+                self.k -= 1;
+            }
+        }
+        if self.ends("eed") {
+            if self.m() > 0 {
+                self.k -= 1;
+            }
+        } else if (self.ends("ed") || self.ends("ing")) && self.vowel_in_stem() {
+            self.k = self.j;
+            if self.ends("at") {
+                self.set_to("ate");
+            } else if self.ends("bl") {
+                self.set_to("ble");
+            } else if self.ends("iz") {
+                self.set_to("ize");
+            } else if self.double_cons(self.k) {
+                if !matches!(self.b[self.k as usize], b'l' | b's' | b'z') {
+                    self.k -= 1;
+                }
+            } else {
+                self.j = self.k;
+                if self.m() == 1 && self.cvc(self.k) {
+                    self.set_to("e");
+                }
+            }
+        }
+    }
+
+    /// Turn the terminal `y` to `i` if there is another vowel in the stem.
+    fn step1c(&mut self) {
+        if self.ends("y") && self.vowel_in_stem() {
+            self.b[self.k as usize] = b'i';
+        }
+    }
+
+    /// Map the double suffixes to the single ones, e.g. `-ization` to `-ize`.
+    fn step2(&mut self) {
+        let rules: &[(&str, &str)] = match self.b[(self.k - 1) as usize] {
+            b'a' => &[("ational", "ate"), ("tional", "tion")],
+            b'c' => &[("enci", "ence"), ("anci", "ance")],
+            b'e' => &[("izer", "ize")],
+            b'l' => &[("bli", "ble"), ("alli", "al"), ("entli", "ent"), ("eli", "e"), ("ousli", "ous")],
+            b'o' => &[("ization", "ize"), ("ation", "ate"), ("ator", "ate")],
+            b's' => &[("alism", "al"), ("iveness", "ive"), ("fulness", "ful"), ("ousness", "ous")],
+            b't' => &[("aliti", "al"), ("iviti", "ive"), ("biliti", "ble")],
+            b'g' => &[("logi", "log")],
+            _ => return,
+        };
+        self.replace_suffix(rules);
+    }
+
+    /// Handle `-ic-`, `-full`, `-ness` etc.
+    fn step3(&mut self) {
+        let rules: &[(&str, &str)] = match self.b[self.k as usize] {
+            b'e' => &[("icate", "ic"), ("ative", ""), ("alize", "al")],
+            b'i' => &[("iciti", "ic")],
+            b'l' => &[("ical", "ic"), ("ful", "")],
+            b's' => &[("ness", "")],
+            _ => return,
+        };
+        self.replace_suffix(rules);
+    }
+
+    /// Remove `-ant`, `-ence` etc. if the stem has more than one vowel-consonant sequence.
+    fn step4(&mut self) {
+        let suffixes: &[&str] = match self.b[(self.k - 1) as usize] {
+            b'a' => &["al"],
+            b'c' => &["ance", "ence"],
+            b'e' => &["er"],
+            b'i' => &["ic"],
+            b'l' => &["able", "ible"],
+            b'n' => &["ant", "ement", "ment", "ent"],
+            b'o' => {
+                let found = (self.ends("ion") && self.j >= 0 && matches!(self.b[self.j as usize], b's' | b't'))
+                    || self.ends("ou");
+                if found && self.m() > 1 {
+                    self.k = self.j;
+                }
+                return;
+            }
+            b's' => &["ism"],
+            b't' => &["ate", "iti"],
+            b'u' => &["ous"],
+            b'v' => &["ive"],
+            b'z' => &["ize"],
+            _ => return,
+        };
+        for suffix in suffixes {
+            if self.ends(suffix) {
+                if self.m() > 1 {
+                    self.k = self.j;
+                }
+                return;
+            }
+        }
+    }
+
+    /// Remove the final `-e` and reduce `-ll` to `-l` in the long stems.
+    fn step5(&mut self) {
+        self.j = self.k;
+        if self.b[self.k as usize] == b'e' {
+            let m = self.m();
+            if m > 1 || (m == 1 && !self.cvc(self.k - 1)) {
+                self.k -= 1;
+            }
+        }
+        if self.b[self.k as usize] == b'l' && self.double_cons(self.k) && self.m() > 1 {
+            self.k -= 1;
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::stem;
+
+    #[test]
+    fn test_stem() {
+        let cases = [
+            ("caresses", "caress"), ("ponies", "poni"), ("cats", "cat"), ("feed", "feed"),
+            ("agreed", "agre"), ("plastered", "plaster"), ("motoring", "motor"), ("sing", "sing"),
+            ("conflated", "conflat"), ("hopping", "hop"), ("falling", "fall"), ("filing", "file"),
+            ("happy", "happi"), ("relational", "relat"), ("conditional", "condit"),
+            ("digitizer", "digit"), ("hopefulness", "hope"), ("formality", "formal"),
+            ("electrical", "electr"), ("adjustment", "adjust"), ("adoption", "adopt"),
+            ("controlling", "control"), ("generalization", "gener"), ("running", "run"),
+            ("is", "is"), ("c++", "c++"),
+        ];
+        for (word, expected) in cases.iter() {
+            assert_eq!(stem(word), *expected, "the stem of {}", word);
+        }
+    }
+
+}
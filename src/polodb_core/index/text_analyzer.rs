@@ -0,0 +1,287 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The analyzers of the text indexes, which turn a text into the terms of the index.
+//!
+//! A text is split by a [`Tokenizer`], then the tokens are lower-cased, the stop words
+//! are removed, and the `english` analyzer stems the remaining ones. The same analyzer
+//! runs on the documents and on the `$search` string of a `$text` query.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use bson::Bson;
+use crate::{Error, Result};
+use crate::index::IndexOptions;
+use crate::index::stemmer;
+
+/// The analyzer of the text indexes without [`IndexOptions::analyzer`].
+pub(crate) const DEFAULT_ANALYZER: &str = "standard";
+
+// the stop words of the `english` analyzer, the same as Lucene
+const ENGLISH_STOP_WORDS: [&str; 33] = [
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with",
+];
+
+/// Splits a text into the tokens of a text index.
+///
+/// Register a tokenizer with [`crate::ConfigBuilder::set_tokenizer`] and name it
+/// in [`IndexOptions::analyzer`]. The tokens are lower-cased and the stop words are
+/// removed afterwards, and a tokenizer must split a text the same way every time,
+/// or the entries of the updated documents are not deleted from the index.
+pub trait Tokenizer: Send + Sync {
+    fn tokenize(&self, text: &str) -> Vec<String>;
+}
+
+/// Splits a text into the runs of letters and digits.
+///
+/// The Chinese, Japanese and Korean characters are written without spaces, so a run of them
+/// is split into the overlapping bigrams, e.g. "北京大学" into "北京", "京大" and "大学",
+/// and a single character is a token by itself.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StandardTokenizer;
+
+impl StandardTokenizer {
+
+    fn push_cjk_bigrams(run: &mut Vec<char>, tokens: &mut Vec<String>) {
+        if run.len() == 1 {
+            tokens.push(run[0].to_string());
+        }
+        for pair in run.windows(2) {
+            tokens.push(pair.iter().collect());
+        }
+        run.clear();
+    }
+
+    fn is_cjk(c: char) -> bool {
+        matches!(c as u32,
+            0x1100..=0x11FF       // Hangul Jamo
+            | 0x3040..=0x30FF     // Hiragana and Katakana
+            | 0x3130..=0x318F     // Hangul Compatibility Jamo
+            | 0x31F0..=0x31FF     // Katakana Phonetic Extensions
+            | 0x3400..=0x4DBF     // CJK Unified Ideographs Extension A
+            | 0x4E00..=0x9FFF     // CJK Unified Ideographs
+            | 0xAC00..=0xD7AF     // Hangul Syllables
+            | 0xF900..=0xFAFF     // CJK Compatibility Ideographs
+            | 0xFF66..=0xFF9F     // Halfwidth Katakana
+            | 0x20000..=0x2FA1F   // CJK Unified Ideographs Extension B to F
+        )
+    }
+
+}
+
+impl Tokenizer for StandardTokenizer {
+
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        let mut tokens = Vec::new();
+        let mut word = String::new();
+        let mut cjk_run = Vec::<char>::new();
+        for c in text.chars() {
+            if StandardTokenizer::is_cjk(c) {
+                if !word.is_empty() {
+                    tokens.push(std::mem::take(&mut word));
+                }
+                cjk_run.push(c);
+                continue;
+            }
+            if !cjk_run.is_empty() {
+                StandardTokenizer::push_cjk_bigrams(&mut cjk_run, &mut tokens);
+            }
+            if c.is_alphanumeric() {
+                word.push(c);
+            } else if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+        }
+        if !cjk_run.is_empty() {
+            StandardTokenizer::push_cjk_bigrams(&mut cjk_run, &mut tokens);
+        }
+        if !word.is_empty() {
+            tokens.push(word);
+        }
+        tokens
+    }
+
+}
+
+/// Splits a text at the whitespaces, the punctuations are kept in the tokens.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WhitespaceTokenizer;
+
+impl Tokenizer for WhitespaceTokenizer {
+
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+}
+
+pub(crate) struct Analyzer {
+    tokenizer: Arc<dyn Tokenizer>,
+    stop_words: HashSet<String>,
+    stemming: bool,
+}
+
+impl Analyzer {
+
+    /// The analyzer of a text index with `options`.
+    ///
+    /// The built-in analyzers are `standard`, `english` and `whitespace`,
+    /// the other names are looked up by `lookup` in the registered tokenizers.
+    pub(crate) fn from_options<F>(options: Option<&IndexOptions>, lookup: F) -> Result<Analyzer>
+    where
+        F: FnOnce(&str) -> Option<Arc<dyn Tokenizer>>,
+    {
+        let name = options
+            .and_then(|options| options.analyzer.as_deref())
+            .unwrap_or(DEFAULT_ANALYZER);
+        let (tokenizer, default_stop_words, stemming): (Arc<dyn Tokenizer>, &[&str], bool) = match name {
+            "standard" => (Arc::new(StandardTokenizer), &[], false),
+            "english" => (Arc::new(StandardTokenizer), &ENGLISH_STOP_WORDS, true),
+            "whitespace" => (Arc::new(WhitespaceTokenizer), &[], false),
+            _ => {
+                let tokenizer = lookup(name).ok_or_else(|| Error::TokenizerNotFound(name.to_string()))?;
+                (tokenizer, &[], false)
+            }
+        };
+        let stop_words = match options.and_then(|options| options.stop_words.as_ref()) {
+            Some(words) => words.iter().map(|word| word.to_lowercase()).collect(),
+            None => default_stop_words.iter().map(|word| word.to_string()).collect(),
+        };
+        Ok(Analyzer {
+            tokenizer,
+            stop_words,
+            stemming,
+        })
+    }
+
+    /// The distinct terms of `text`, in the order of their first occurrences.
+    pub(crate) fn analyze(&self, text: &str) -> Vec<String> {
+        let mut terms = Vec::new();
+        self.analyze_into(text, &mut terms);
+        terms
+    }
+
+    /// The distinct terms of a field value, a string or an array of strings.
+    /// The other values have no terms.
+    pub(crate) fn analyze_value(&self, value: &Bson) -> Vec<String> {
+        let mut terms = Vec::new();
+        match value {
+            Bson::String(text) => self.analyze_into(text, &mut terms),
+            Bson::Array(values) => {
+                for value in values {
+                    if let Bson::String(text) = value {
+                        self.analyze_into(text, &mut terms);
+                    }
+                }
+            }
+            _ => (),
+        }
+        terms
+    }
+
+    fn analyze_into(&self, text: &str, terms: &mut Vec<String>) {
+        for token in self.tokenizer.tokenize(text) {
+            let token = token.to_lowercase();
+            if token.is_empty() || self.stop_words.contains(&token) {
+                continue;
+            }
+            let term = if self.stemming {
+                stemmer::stem(&token)
+            } else {
+                token
+            };
+            if !terms.contains(&term) {
+                terms.push(term);
+            }
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::index::IndexOptions;
+    use super::{Analyzer, StandardTokenizer, Tokenizer};
+
+    fn analyzer(name: &str) -> Analyzer {
+        let options = IndexOptions {
+            analyzer: Some(name.to_string()),
+            ..Default::default()
+        };
+        Analyzer::from_options(Some(&options), |_| None).unwrap()
+    }
+
+    #[test]
+    fn test_standard_tokenizer() {
+        assert_eq!(
+            StandardTokenizer.tokenize("Hello, world! 北京大学 in 東京 and 한국어 x"),
+            vec!["Hello", "world", "北京", "京大", "大学", "in", "東京", "and", "한국", "국어", "x"],
+        );
+        assert_eq!(StandardTokenizer.tokenize("東ABC"), vec!["東", "ABC"]);
+    }
+
+    #[test]
+    fn test_analyzers() {
+        assert_eq!(
+            analyzer("standard").analyze("The Running dogs, the running DOGS"),
+            vec!["the", "running", "dogs"],
+        );
+        assert_eq!(
+            analyzer("english").analyze("The Running dogs, the running DOGS"),
+            vec!["run", "dog"],
+        );
+        assert_eq!(
+            analyzer("whitespace").analyze("C++ and C#"),
+            vec!["c++", "and", "c#"],
+        );
+
+        let options = IndexOptions {
+            analyzer: Some("english".to_string()),
+            stop_words: Some(vec!["Dogs".to_string()]),
+            ..Default::default()
+        };
+        let with_stop_words = Analyzer::from_options(Some(&options), |_| None).unwrap();
+        assert_eq!(with_stop_words.analyze("the dogs"), vec!["the"]);
+
+        assert!(Analyzer::from_options(Some(&IndexOptions {
+            analyzer: Some("unknown".to_string()),
+            ..Default::default()
+        }), |_| None).is_err());
+    }
+
+    #[test]
+    fn test_custom_tokenizer() {
+        struct CommaTokenizer;
+
+        impl Tokenizer for CommaTokenizer {
+            fn tokenize(&self, text: &str) -> Vec<String> {
+                text.split(',').map(|token| token.trim().to_string()).collect()
+            }
+        }
+
+        let options = IndexOptions {
+            analyzer: Some("comma".to_string()),
+            ..Default::default()
+        };
+        let analyzer = Analyzer::from_options(Some(&options), |name| {
+            assert_eq!(name, "comma");
+            Some(Arc::new(CommaTokenizer) as Arc<dyn Tokenizer>)
+        }).unwrap();
+        assert_eq!(analyzer.analyze("New York, Los Angeles,"), vec!["new york", "los angeles"]);
+    }
+
+}
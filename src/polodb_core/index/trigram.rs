@@ -0,0 +1,154 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The trigrams of the trigram indexes, and the `$contains` and `$fuzzy` matches they speed up.
+//!
+//! The strings are lower-cased, and a string has an entry of each distinct substring
+//! of three characters, the strings shorter than that have none. The queries which may
+//! match such strings are too short to use the index, so they scan the collection.
+
+use bson::Bson;
+
+/// The distinct trigrams of the lower-cased `value`, in the order of their first occurrences.
+pub(crate) fn trigrams(value: &str) -> Vec<String> {
+    let chars: Vec<char> = value.to_lowercase().chars().collect();
+    let mut result: Vec<String> = Vec::new();
+    for window in chars.windows(3) {
+        let gram: String = window.iter().collect();
+        if !result.contains(&gram) {
+            result.push(gram);
+        }
+    }
+    result
+}
+
+/// The distinct trigrams of a field value, a string or an array of strings.
+pub(crate) fn trigrams_of_value(value: &Bson) -> Vec<String> {
+    match value {
+        Bson::String(value) => trigrams(value),
+        Bson::Array(values) => {
+            let mut result: Vec<String> = Vec::new();
+            for value in values {
+                if let Bson::String(value) = value {
+                    for gram in trigrams(value) {
+                        if !result.contains(&gram) {
+                            result.push(gram);
+                        }
+                    }
+                }
+            }
+            result
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// The trigrams of which every string matching `$contains: needle` has all.
+/// Empty if the needle is too short to use the index.
+pub(crate) fn contains_candidate_grams(needle: &str) -> Vec<String> {
+    trigrams(needle).into_iter().take(1).collect()
+}
+
+/// The trigrams of which every string matching `$fuzzy: query` has at least one.
+/// An edit changes at most three trigrams, so one of any `3 * max_edits + 1` trigrams
+/// of the query is kept. Empty if the query is too short to use the index.
+pub(crate) fn fuzzy_candidate_grams(query: &str, max_edits: u32) -> Vec<String> {
+    let grams = trigrams(query);
+    let needed = 3 * max_edits as usize + 1;
+    if grams.len() < needed {
+        return Vec::new();
+    }
+    grams.into_iter().take(needed).collect()
+}
+
+/// Test if the string, or a string of the array, contains the lower-cased `needle`, ignoring the case.
+pub(crate) fn value_contains(value: &Bson, needle: &str) -> bool {
+    match value {
+        Bson::String(value) => value.to_lowercase().contains(needle),
+        Bson::Array(values) => values.iter().any(|value| match value {
+            Bson::String(value) => value.to_lowercase().contains(needle),
+            _ => false,
+        }),
+        _ => false,
+    }
+}
+
+/// Test if the string, or a string of the array, is at most `max_edits` insertions,
+/// deletions or substitutions of a character away from the lower-cased `query`, ignoring the case.
+pub(crate) fn value_within_edits(value: &Bson, query: &str, max_edits: u32) -> bool {
+    let query: Vec<char> = query.chars().collect();
+    let within = |value: &str| {
+        let value: Vec<char> = value.to_lowercase().chars().collect();
+        edit_distance_within(&value, &query, max_edits as usize)
+    };
+    match value {
+        Bson::String(value) => within(value),
+        Bson::Array(values) => values.iter().any(|value| match value {
+            Bson::String(value) => within(value),
+            _ => false,
+        }),
+        _ => false,
+    }
+}
+
+// the Levenshtein distance by rows, stopped once a row exceeds the max distance
+fn edit_distance_within(a: &[char], b: &[char], max: usize) -> bool {
+    if a.len().abs_diff(b.len()) > max {
+        return false;
+    }
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + if ca == cb { 0 } else { 1 };
+            current[j + 1] = substitution.min(prev[j + 1] + 1).min(current[j] + 1);
+        }
+        if current.iter().all(|distance| *distance > max) {
+            return false;
+        }
+        std::mem::swap(&mut prev, &mut current);
+    }
+    prev[b.len()] <= max
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::Bson;
+    use super::{fuzzy_candidate_grams, trigrams, value_contains, value_within_edits};
+
+    #[test]
+    fn test_trigrams() {
+        assert_eq!(trigrams("Banana"), vec!["ban", "ana", "nan"]);
+        assert_eq!(trigrams("ab"), Vec::<String>::new());
+        assert_eq!(trigrams("東京都庁"), vec!["東京都", "京都庁"]);
+        assert_eq!(fuzzy_candidate_grams("johnson", 1), vec!["joh", "ohn", "hns", "nso"]);
+        assert!(fuzzy_candidate_grams("john", 1).is_empty());
+    }
+
+    #[test]
+    fn test_matches() {
+        let value = Bson::String("SKU-10042-BLUE".to_string());
+        assert!(value_contains(&value, "0042-b"));
+        assert!(!value_contains(&value, "red"));
+        assert!(value_within_edits(&Bson::String("Jonathan".to_string()), "jonathon", 1));
+        assert!(value_within_edits(&Bson::String("Jonathan".to_string()), "jonatan", 1));
+        assert!(!value_within_edits(&Bson::String("Jonathan".to_string()), "jonaton", 1));
+        assert!(value_within_edits(&Bson::String("Jonathan".to_string()), "jonaton", 2));
+        let values = Bson::Array(vec![Bson::Int32(1), Bson::String("kitten".to_string())]);
+        assert!(value_within_edits(&values, "sitten", 1));
+        assert!(!value_within_edits(&values, "sitting", 2));
+    }
+
+}
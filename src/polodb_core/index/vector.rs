@@ -0,0 +1,274 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The vectors of the vector indexes, and the similarities `$vectorSearch` ranks them by.
+//!
+//! A vector index is an inverted file of flat lists: the vectors are assigned to the list of
+//! their nearest centroid, and a search only compares the query with the vectors of the lists
+//! nearest to it. The centroids are trained by k-means when the index is created and when
+//! the collection is analyzed, before that all the vectors are in one list.
+
+use bson::{Bson, Document};
+use crate::{Error, Result};
+use crate::cursor::Cursor;
+use crate::index::IndexOptions;
+use crate::transaction::TransactionInner;
+
+const TRAIN_ITERATIONS: usize = 10;
+const MAX_LISTS: usize = 1024;
+// the largest number of vectors sampled for each list to train the centroids
+const TRAIN_SAMPLES_PER_LIST: usize = 256;
+
+/// How the distance of two vectors is measured, from the `similarity` of the index options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Similarity {
+    Cosine,
+    Euclidean,
+}
+
+impl Similarity {
+
+    pub(crate) fn from_options(similarity: Option<&str>) -> Result<Similarity> {
+        match similarity {
+            None | Some("cosine") => Ok(Similarity::Cosine),
+            Some("euclidean") => Ok(Similarity::Euclidean),
+            Some(other) => Err(Error::ValidationError(format!(
+                "the similarity '{}' is not supported, it must be cosine or euclidean",
+                other,
+            ))),
+        }
+    }
+
+    /// The score of the similarity between 0 and 1, the greater the more similar,
+    /// `(1 + cosine) / 2` or `1 / (1 + distance)`.
+    pub(crate) fn score(&self, a: &[f32], b: &[f32]) -> f64 {
+        match self {
+            Similarity::Cosine => (1.0 + cosine(a, b)) / 2.0,
+            Similarity::Euclidean => 1.0 / (1.0 + squared_distance(a, b).sqrt()),
+        }
+    }
+
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f64 {
+    let mut dot = 0.0;
+    let mut norm_a = 0.0;
+    let mut norm_b = 0.0;
+    for (x, y) in a.iter().zip(b) {
+        dot += *x as f64 * *y as f64;
+        norm_a += *x as f64 * *x as f64;
+        norm_b += *y as f64 * *y as f64;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+fn squared_distance(a: &[f32], b: &[f32]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| {
+        let diff = *x as f64 - *y as f64;
+        diff * diff
+    }).sum()
+}
+
+/// The dimensions and the similarity of the options of a vector index,
+/// the dimensions are required.
+pub(crate) fn index_options(options: Option<&IndexOptions>) -> Result<(usize, Similarity)> {
+    let dimensions = match options.and_then(|options| options.dimensions) {
+        Some(dimensions) if dimensions > 0 => dimensions as usize,
+        _ => return Err(Error::ValidationError("a vector index needs the dimensions of the vectors".to_string())),
+    };
+    let similarity = Similarity::from_options(options.and_then(|options| options.similarity.as_deref()))?;
+    Ok((dimensions, similarity))
+}
+
+/// The vector of a field value, an array of `dimensions` numbers.
+/// `None` for the other values, which are not indexed.
+pub(crate) fn parse_vector(value: &Bson, dimensions: usize) -> Option<Vec<f32>> {
+    let values = match value {
+        Bson::Array(values) if values.len() == dimensions => values,
+        _ => return None,
+    };
+    values.iter().map(|value| match value {
+        Bson::Double(value) => Some(*value as f32),
+        Bson::Int32(value) => Some(*value as f32),
+        Bson::Int64(value) => Some(*value as f32),
+        _ => None,
+    }).collect()
+}
+
+/// The little-endian bytes of the vector, the value of its index entry.
+pub(crate) fn encode(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> Vec<f32> {
+    bytes.chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+/// The list of the nearest centroid, 0 if there are no centroids.
+pub(crate) fn nearest_list(centroids: &[Vec<f32>], similarity: Similarity, vector: &[f32]) -> usize {
+    let mut best = 0;
+    let mut best_score = f64::MIN;
+    for (list, centroid) in centroids.iter().enumerate() {
+        let score = similarity.score(centroid, vector);
+        if score > best_score {
+            best = list;
+            best_score = score;
+        }
+    }
+    best
+}
+
+/// The lists from the nearest to the farthest of the query.
+pub(crate) fn probe_order(centroids: &[Vec<f32>], similarity: Similarity, query: &[f32]) -> Vec<usize> {
+    if centroids.is_empty() {
+        return vec![0];
+    }
+    let mut scores: Vec<(usize, f64)> = centroids.iter()
+        .enumerate()
+        .map(|(list, centroid)| (list, similarity.score(centroid, query)))
+        .collect();
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scores.into_iter().map(|(list, _)| list).collect()
+}
+
+/// Train the centroids of the vectors of the field in the collection,
+/// `lists` of them, or the square root of the number of vectors by default.
+pub(crate) fn train_centroids(
+    txn: &TransactionInner,
+    col_name: &str,
+    field: &str,
+    dimensions: usize,
+    similarity: Similarity,
+    lists: Option<u32>,
+) -> Result<Vec<Vec<f32>>> {
+    let mut vectors: Vec<Vec<f32>> = Vec::new();
+    let mut cursor = Cursor::new_with_str_prefix(col_name.to_string(), txn.rocksdb_txn.new_iterator())?;
+    cursor.reset()?;
+    while cursor.has_next() {
+        let doc = bson::from_slice::<Document>(cursor.copy_data()?.as_ref())?;
+        let value = crate::utils::bson::try_get_document_value(&doc, field);
+        if let Some(vector) = value.as_ref().and_then(|value| parse_vector(value, dimensions)) {
+            vectors.push(vector);
+        }
+        cursor.next()?;
+    }
+
+    let lists = match lists {
+        Some(lists) => lists as usize,
+        None => (vectors.len() as f64).sqrt().ceil() as usize,
+    };
+    Ok(kmeans(vectors, lists.min(MAX_LISTS), similarity))
+}
+
+/// The centroids of the k-means of the vectors, fewer than `k` if there are fewer vectors.
+/// The vectors are normalized for the cosine similarity, and the centroids start from the
+/// vectors evenly spaced in the sample, which keeps the training deterministic.
+fn kmeans(mut vectors: Vec<Vec<f32>>, k: usize, similarity: Similarity) -> Vec<Vec<f32>> {
+    let k = k.min(vectors.len());
+    if k == 0 {
+        return Vec::new();
+    }
+    let max_samples = k * TRAIN_SAMPLES_PER_LIST;
+    if vectors.len() > max_samples {
+        let step = vectors.len() as f64 / max_samples as f64;
+        vectors = (0..max_samples).map(|i| vectors[(i as f64 * step) as usize].clone()).collect();
+    }
+    if similarity == Similarity::Cosine {
+        vectors.iter_mut().for_each(|vector| normalize(vector));
+    }
+
+    let step = vectors.len() as f64 / k as f64;
+    let mut centroids: Vec<Vec<f32>> = (0..k).map(|i| vectors[(i as f64 * step) as usize].clone()).collect();
+    let dimensions = centroids[0].len();
+
+    for _ in 0..TRAIN_ITERATIONS {
+        let mut sums = vec![vec![0.0f64; dimensions]; k];
+        let mut counts = vec![0usize; k];
+        for vector in &vectors {
+            let list = nearest_list(&centroids, similarity, vector);
+            counts[list] += 1;
+            for (sum, value) in sums[list].iter_mut().zip(vector) {
+                *sum += *value as f64;
+            }
+        }
+        // an empty list keeps its centroid
+        for (list, centroid) in centroids.iter_mut().enumerate() {
+            if counts[list] == 0 {
+                continue;
+            }
+            for (value, sum) in centroid.iter_mut().zip(&sums[list]) {
+                *value = (*sum / counts[list] as f64) as f32;
+            }
+            if similarity == Similarity::Cosine {
+                normalize(centroid);
+            }
+        }
+    }
+
+    centroids
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|value| *value as f64 * *value as f64).sum::<f64>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|value| *value = (*value as f64 / norm) as f32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{bson, Bson};
+    use super::{decode, encode, kmeans, nearest_list, parse_vector, Similarity};
+
+    #[test]
+    fn test_similarity() {
+        let cosine = Similarity::Cosine;
+        assert!((cosine.score(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-9);
+        assert!((cosine.score(&[1.0, 0.0], &[0.0, 3.0]) - 0.5).abs() < 1e-9);
+        assert!(cosine.score(&[1.0, 0.0], &[-1.0, 0.0]).abs() < 1e-9);
+        let euclidean = Similarity::Euclidean;
+        assert!((euclidean.score(&[0.0, 0.0], &[3.0, 4.0]) - 1.0 / 6.0).abs() < 1e-9);
+        assert!(Similarity::from_options(Some("dot")).is_err());
+
+        assert_eq!(parse_vector(&bson!([1, 2.5, 3_i64]), 3), Some(vec![1.0, 2.5, 3.0]));
+        assert_eq!(parse_vector(&bson!([1, 2]), 3), None);
+        assert_eq!(parse_vector(&Bson::String("1".to_string()), 1), None);
+        assert_eq!(decode(&encode(&[0.25, -1.5])), vec![0.25, -1.5]);
+    }
+
+    #[test]
+    fn test_kmeans() {
+        let mut vectors = Vec::new();
+        for i in 0..50 {
+            let offset = i as f32 / 100.0;
+            vectors.push(vec![offset, 0.0]);
+            vectors.push(vec![10.0 + offset, 10.0]);
+        }
+        let centroids = kmeans(vectors, 2, Similarity::Euclidean);
+        assert_eq!(centroids.len(), 2);
+        let near = nearest_list(&centroids, Similarity::Euclidean, &[0.1, 0.1]);
+        let far = nearest_list(&centroids, Similarity::Euclidean, &[9.0, 9.0]);
+        assert_ne!(near, far);
+        assert!(centroids[near][0] < 1.0 && centroids[far][0] > 9.0);
+
+        assert!(kmeans(Vec::new(), 4, Similarity::Cosine).is_empty());
+        assert_eq!(kmeans(vec![vec![1.0, 0.0]], 4, Similarity::Cosine).len(), 1);
+    }
+
+}
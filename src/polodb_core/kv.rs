@@ -0,0 +1,204 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! A raw key-value store beside the collections.
+//!
+//! The keys and the values are arbitrary bytes, the keys are ordered by their bytes.
+//! The pairs are stored in a keyspace of `[KV_PREFIX, namespace]` followed by the key,
+//! so each database opened by [`crate::Database::database`] has its own store.
+//!
+//! A store returned by [`crate::Database::kv`] commits every write on its own,
+//! the one returned by [`crate::Transaction::kv`] writes in the transaction,
+//! and the reads see its uncommitted writes.
+//!
+//! ```rust
+//! use polodb_core::Database;
+//!
+//! let db = Database::open_memory().unwrap();
+//! let kv = db.kv();
+//! kv.put(b"user:1", b"alice").unwrap();
+//! kv.put(b"user:2", b"bob").unwrap();
+//! kv.put(b"video:1", b"intro").unwrap();
+//!
+//! assert_eq!(kv.get(b"user:1").unwrap(), Some(b"alice".to_vec()));
+//! let users = kv.scan(&b"user:"[..]..&b"user;"[..]).unwrap()
+//!     .collect::<polodb_core::Result<Vec<_>>>()
+//!     .unwrap();
+//! assert_eq!(users.len(), 2);
+//! ```
+
+use std::ops::{Bound, RangeBounds};
+use std::sync::Weak;
+use bson::Bson;
+use crate::cursor::Cursor;
+use crate::db::db_inner::DatabaseInner;
+use crate::transaction::TransactionInner;
+use crate::utils::bson::stacked_key;
+use crate::{Error, Result};
+
+const KV_PREFIX: &str = "$KV";
+
+fn kv_prefix(namespace: Option<&str>) -> Result<Vec<u8>> {
+    stacked_key(&[
+        Bson::String(KV_PREFIX.to_string()),
+        Bson::String(namespace.unwrap_or_default().to_string()),
+    ])
+}
+
+/// Remove the pairs of a dropped database.
+pub(crate) fn delete_pairs(txn: &TransactionInner, namespace: Option<&str>) -> Result<()> {
+    let mut keys = Vec::new();
+    let mut cursor = Cursor::new(kv_prefix(namespace)?, txn.rocksdb_txn.new_iterator());
+    cursor.reset()?;
+    while cursor.has_next() {
+        keys.push(cursor.peek_key().expect("key must exist"));
+        cursor.next()?;
+    }
+
+    for key in &keys {
+        txn.delete(key.as_ref())?;
+    }
+
+    Ok(())
+}
+
+/// The key-value store of a database, returned by [`crate::Database::kv`]
+/// and [`crate::Transaction::kv`].
+#[derive(Clone)]
+pub struct KvStore {
+    db: Weak<DatabaseInner>,
+    namespace: Option<String>,
+    /// The writes are committed one by one without a transaction
+    txn: Option<TransactionInner>,
+}
+
+impl KvStore {
+
+    pub(crate) fn new(db: Weak<DatabaseInner>, namespace: Option<String>, txn: Option<TransactionInner>) -> KvStore {
+        KvStore {
+            db,
+            namespace,
+            txn,
+        }
+    }
+
+    fn key(&self, key: &[u8]) -> Result<Vec<u8>> {
+        let mut buffer = kv_prefix(self.namespace.as_deref())?;
+        buffer.extend_from_slice(key);
+        Ok(buffer)
+    }
+
+    fn transaction(&self, db: &DatabaseInner) -> Result<TransactionInner> {
+        match &self.txn {
+            Some(txn) => Ok(txn.clone()),
+            None => db.start_transaction(),
+        }
+    }
+
+    /// The value of `key`, `None` if it doesn't exist.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let txn = self.transaction(&db)?;
+        txn.rocksdb_txn.get(&self.key(key)?)
+    }
+
+    /// Set the value of `key`, replacing the old one if any.
+    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.check_quota()?;
+        let txn = self.transaction(&db)?;
+        txn.put(&self.key(key)?, value)?;
+        txn.auto_commit()
+    }
+
+    /// Delete `key`, nothing happens if it doesn't exist.
+    pub fn delete(&self, key: &[u8]) -> Result<()> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let txn = self.transaction(&db)?;
+        txn.delete(&self.key(key)?)?;
+        txn.auto_commit()
+    }
+
+    /// Iterate the pairs whose keys are in `range`, in the order of the keys.
+    ///
+    /// The pairs are read from a snapshot taken when the scan starts,
+    /// the writes after that are not visible.
+    pub fn scan<'a, R: RangeBounds<&'a [u8]>>(&self, range: R) -> Result<KvScan> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let txn = self.transaction(&db)?;
+        let prefix = kv_prefix(self.namespace.as_deref())?;
+        let mut cursor = Cursor::new(prefix, txn.rocksdb_txn.new_iterator());
+        match range.start_bound() {
+            Bound::Included(&start) | Bound::Excluded(&start) => {
+                let found = cursor.reset_by_pkey_buf(start)?;
+                if found && matches!(range.start_bound(), Bound::Excluded(_)) {
+                    cursor.next()?;
+                }
+            }
+            Bound::Unbounded => cursor.reset()?,
+        }
+        let end = match range.end_bound() {
+            Bound::Included(&end) => Bound::Included(end.to_vec()),
+            Bound::Excluded(&end) => Bound::Excluded(end.to_vec()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        Ok(KvScan {
+            cursor,
+            end,
+            _txn: txn,
+        })
+    }
+
+}
+
+/// The pairs of a [`KvStore::scan`].
+pub struct KvScan {
+    cursor: Cursor,
+    end: Bound<Vec<u8>>,
+    // the iterator of the cursor points into the transaction,
+    // so it is dropped after the cursor
+    _txn: TransactionInner,
+}
+
+impl KvScan {
+
+    fn next_pair(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        if !self.cursor.has_next() {
+            return Ok(None);
+        }
+        let key = self.cursor.peek_key().expect("key must exist");
+        let key = key[self.cursor.prefix_bytes.len()..].to_vec();
+        let in_range = match &self.end {
+            Bound::Included(end) => key <= *end,
+            Bound::Excluded(end) => key < *end,
+            Bound::Unbounded => true,
+        };
+        if !in_range {
+            return Ok(None);
+        }
+        let value = self.cursor.copy_data()?;
+        self.cursor.next()?;
+        Ok(Some((key, value)))
+    }
+
+}
+
+impl Iterator for KvScan {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_pair().transpose()
+    }
+}
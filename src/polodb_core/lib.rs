@@ -36,6 +36,18 @@
 //! let db = Database::open_path(db_path).unwrap();
 //! ```
 //!
+//! The options of the database are set by [`Database::options`]:
+//!
+//! ```rust
+//! use polodb_core::{Database, WalSyncPolicy};
+//! # let db_path = polodb_core::test_utils::mk_db_path("doc-test-polo-options");
+//! let db = Database::options()
+//!     .cache_size(32 * 1024 * 1024)
+//!     .durability(WalSyncPolicy::OsBuffered)
+//!     .open(db_path)
+//!     .unwrap();
+//! ```
+//!
 //! # Example
 //!
 //!  ```rust
@@ -138,32 +150,83 @@
 
 extern crate core;
 
-mod vm;
-mod errors;
-mod cursor;
+// The engine is built on RocksDB, which is written in C++ and isn't compiled
+// for wasm32, so only the storage backends are built for it.
+macro_rules! cfg_engine {
+    ($($item:item)*) => {
+        $(
+            #[cfg(not(target_arch = "wasm32"))]
+            $item
+        )*
+    };
+}
 
-mod db;
-mod meta_doc_helper;
-mod config;
+// the macros are used by their paths, which can't be declared by another macro
+#[cfg(not(target_arch = "wasm32"))]
 mod macros;
-mod transaction;
-pub mod options;
-pub mod results;
 
-pub mod test_utils;
-mod metrics;
-mod utils;
-mod index;
-mod coll;
-pub mod action;
+cfg_engine! {
+    mod vm;
+    mod errors;
+    mod cursor;
+
+    mod db;
+    mod meta_doc_helper;
+    mod config;
+    mod transaction;
+    pub mod options;
+    pub mod results;
+
+    pub mod test_utils;
+    mod metrics;
+    mod utils;
+    mod index;
+    mod coll;
+    pub mod action;
+    pub mod gridfs;
+    pub mod kv;
+    #[cfg(feature = "auth")]
+    pub mod auth;
+    #[cfg(feature = "fault-injection")]
+    pub mod fault_injection;
+    pub mod migration;
+    mod dump;
+    mod extjson;
+    #[cfg(feature = "csv")]
+    mod csv_io;
+    pub mod date;
+    pub mod replication;
+    pub mod sync;
+    #[cfg(feature = "http-server")]
+    pub mod http_server;
+    #[cfg(feature = "graphql")]
+    pub mod graphql;
+    #[cfg(feature = "sqlite")]
+    pub mod sqlite;
+    #[cfg(feature = "arrow")]
+    pub mod arrow_export;
+    #[cfg(feature = "async")]
+    pub mod stream;
+
+    pub use db::{Database, Result, WalRecord, WalOperation, BlockCache, CancellationToken, FORMAT_VERSION, PROFILE_COLLECTION};
+    pub use coll::{CaseInsensitiveOrdering, Collection, CollectionT, DbRef, KeyOrdering, MapReduceCursor, MapReduceEmitter, SemverOrdering, TransactionalCollection};
+    pub use config::{Config, ConfigBuilder, WalSyncPolicy, ChecksumType, QuotaPolicy, QuotaEvictor, IdGenerator, RuntimeOption};
+    pub use transaction::Transaction;
+    pub use db::client_cursor::ClientCursor;
+    pub use errors::{Error, ErrorCategory};
+    pub use metrics::Metrics;
+    #[cfg(feature = "metrics")]
+    pub use metrics::{MetricsRecorder, MetricLabels, PrometheusExporter};
+    pub use index::{IndexModel, IndexOptions, Tokenizer, StandardTokenizer, WhitespaceTokenizer};
+}
 
-pub use db::{Database, Result};
-pub use coll::{Collection, CollectionT, TransactionalCollection};
-pub use config::{Config, ConfigBuilder};
-pub use transaction::Transaction;
-pub use db::client_cursor::ClientCursor;
-pub use errors::Error;
-pub use metrics::Metrics;
-pub use index::{IndexModel, IndexOptions};
+pub mod storage_backend;
+#[cfg(feature = "opfs")]
+pub mod opfs;
 
 pub extern crate bson;
+pub extern crate uuid;
+#[cfg(feature = "arrow")]
+pub extern crate arrow;
+#[cfg(feature = "arrow")]
+pub extern crate parquet;
@@ -86,3 +86,23 @@ macro_rules! path_hint_3 {
         }
     };
 }
+
+/// Enter a `tracing` span until the end of the current scope.
+/// Nothing is emitted without the `tracing` feature.
+#[macro_export]
+macro_rules! trace_span {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!($($arg)+).entered();
+    };
+}
+
+/// Emit a `tracing` event in the current span.
+/// Nothing is emitted without the `tracing` feature.
+#[macro_export]
+macro_rules! trace_event {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)+);
+    };
+}
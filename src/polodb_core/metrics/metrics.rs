@@ -14,10 +14,18 @@
 
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+use serde::de::DeserializeOwned;
+use crate::{ClientCursor, Result};
+#[cfg(feature = "metrics")]
+use crate::metrics::recorder::SharedMetricsRecorder;
 
 #[derive(Clone)]
 pub struct Metrics {
     inner: Arc<MetricsInner>,
+    #[cfg(feature = "metrics")]
+    recorder: Option<SharedMetricsRecorder>,
 }
 
 #[allow(dead_code)]
@@ -27,6 +35,74 @@ impl Metrics {
         let inner = Arc::new(MetricsInner::new());
         Metrics {
             inner,
+            #[cfg(feature = "metrics")]
+            recorder: None,
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(crate) fn with_recorder(mut self, recorder: Option<SharedMetricsRecorder>) -> Metrics {
+        self.recorder = recorder;
+        self
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(crate) fn recorder(&self) -> Option<&SharedMetricsRecorder> {
+        self.recorder.as_ref()
+    }
+
+    /// Run the operation and report its result and latency to the recorder.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn observe<R, F>(&self, operation: &str, f: F) -> Result<R>
+    where
+        F: FnOnce() -> Result<R>
+    {
+        let recorder = match &self.recorder {
+            Some(recorder) => recorder,
+            None => return f(),
+        };
+        let start = Instant::now();
+        let result = f();
+        let status = if result.is_ok() { "ok" } else { "error" };
+        recorder.increment_counter(
+            "polodb_operations_total",
+            &[("operation", operation), ("status", status)],
+            1,
+        );
+        recorder.record_histogram(
+            "polodb_operation_duration_seconds",
+            &[("operation", operation)],
+            start.elapsed().as_secs_f64(),
+        );
+        result
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    #[inline]
+    pub(crate) fn observe<R, F>(&self, _operation: &str, f: F) -> Result<R>
+    where
+        F: FnOnce() -> Result<R>
+    {
+        f()
+    }
+
+    /// Run the operation creating a cursor, its latency is reported
+    /// when the cursor returns its last document, fails or is dropped.
+    pub(crate) fn observe_cursor<T, F>(&self, operation: &'static str, f: F) -> Result<ClientCursor<T>>
+    where
+        T: DeserializeOwned + Send + Sync,
+        F: FnOnce() -> Result<ClientCursor<T>>
+    {
+        let timer = OperationTimer::start(self, operation);
+        match f() {
+            Ok(mut cursor) => {
+                cursor.set_timer(timer);
+                Ok(cursor)
+            }
+            Err(err) => {
+                timer.finish(false);
+                Err(err)
+            }
         }
     }
 
@@ -45,6 +121,69 @@ impl Metrics {
 
 }
 
+/// The latency of an operation returning a cursor, from the creating of the cursor
+/// to the end of its documents.
+pub(crate) struct OperationTimer {
+    #[cfg(feature = "metrics")]
+    recorder: Option<SharedMetricsRecorder>,
+    #[cfg(feature = "metrics")]
+    operation: &'static str,
+    #[cfg(feature = "metrics")]
+    start: Instant,
+}
+
+impl OperationTimer {
+
+    #[allow(unused_variables)]
+    fn start(metrics: &Metrics, operation: &'static str) -> OperationTimer {
+        OperationTimer {
+            #[cfg(feature = "metrics")]
+            recorder: metrics.recorder.clone(),
+            #[cfg(feature = "metrics")]
+            operation,
+            #[cfg(feature = "metrics")]
+            start: Instant::now(),
+        }
+    }
+
+    /// Report the latency once, `ok` is false if the operation failed.
+    #[allow(unused_mut, unused_variables)]
+    pub(crate) fn finish(mut self, ok: bool) {
+        #[cfg(feature = "metrics")]
+        self.report(ok);
+    }
+
+    #[cfg(feature = "metrics")]
+    fn report(&mut self, ok: bool) {
+        let recorder = match self.recorder.take() {
+            Some(recorder) => recorder,
+            None => return,
+        };
+        let status = if ok { "ok" } else { "error" };
+        recorder.increment_counter(
+            "polodb_operations_total",
+            &[("operation", self.operation), ("status", status)],
+            1,
+        );
+        recorder.record_histogram(
+            "polodb_operation_duration_seconds",
+            &[("operation", self.operation)],
+            self.start.elapsed().as_secs_f64(),
+        );
+    }
+
+}
+
+impl Drop for OperationTimer {
+
+    // the cursor is dropped before its last document
+    fn drop(&mut self) {
+        #[cfg(feature = "metrics")]
+        self.report(true);
+    }
+
+}
+
 struct MetricsInner {
     enable: AtomicBool,
     find_by_index_count: AtomicUsize,
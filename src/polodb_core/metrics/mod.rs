@@ -14,5 +14,14 @@
 
 
 mod metrics;
+#[cfg(feature = "metrics")]
+mod recorder;
+#[cfg(feature = "metrics")]
+mod prometheus;
 
 pub use metrics::{Metrics};
+pub(crate) use metrics::OperationTimer;
+#[cfg(feature = "metrics")]
+pub use recorder::{MetricsRecorder, MetricLabels};
+#[cfg(feature = "metrics")]
+pub use prometheus::PrometheusExporter;
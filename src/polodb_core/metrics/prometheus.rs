@@ -0,0 +1,257 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use crate::metrics::recorder::{MetricLabels, MetricsRecorder};
+
+/// The upper bounds of the histogram buckets, in seconds.
+const DEFAULT_BUCKETS: [f64; 11] = [
+    0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0,
+];
+
+/// A [`MetricsRecorder`] which keeps the metrics in memory
+/// and renders them in the Prometheus text exposition format.
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use polodb_core::{ConfigBuilder, Database, CollectionT, PrometheusExporter};
+/// use polodb_core::bson::{doc, Document};
+///
+/// let exporter = PrometheusExporter::new();
+/// let mut config = ConfigBuilder::new();
+/// config.set_metrics_recorder(Arc::new(exporter.clone()));
+///
+/// let db = Database::open_memory_with_config(config.take()).unwrap();
+/// db.collection::<Document>("test").insert_one(doc! { "name": "a" }).unwrap();
+/// db.report_metrics().unwrap();
+///
+/// let text = exporter.render();
+/// assert!(text.contains("polodb_operations_total{operation=\"insert_one\",status=\"ok\"} 1"));
+/// ```
+#[derive(Clone, Default)]
+pub struct PrometheusExporter {
+    inner: Arc<Mutex<BTreeMap<String, MetricFamily>>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MetricKind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl MetricKind {
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+            MetricKind::Histogram => "histogram",
+        }
+    }
+
+}
+
+struct MetricFamily {
+    kind: MetricKind,
+    /// Indexed by the rendered labels
+    series: BTreeMap<String, Series>,
+}
+
+enum Series {
+    Value(f64),
+    Histogram {
+        /// The count of the observations in each bucket, not accumulated
+        bucket_counts: Vec<u64>,
+        sum: f64,
+        count: u64,
+    },
+}
+
+impl PrometheusExporter {
+
+    pub fn new() -> PrometheusExporter {
+        PrometheusExporter::default()
+    }
+
+    /// Render all the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let families = self.inner.lock().unwrap();
+        let mut result = String::new();
+
+        for (name, family) in families.iter() {
+            writeln!(result, "# TYPE {} {}", name, family.kind.as_str()).unwrap();
+            for (labels, series) in &family.series {
+                match series {
+                    Series::Value(value) => {
+                        writeln!(result, "{}{} {}", name, wrap_labels(labels), value).unwrap();
+                    }
+                    Series::Histogram { bucket_counts, sum, count } => {
+                        let mut accumulated = 0;
+                        for (bound, bucket_count) in DEFAULT_BUCKETS.iter().zip(bucket_counts) {
+                            accumulated += bucket_count;
+                            let bucket_labels = join_labels(labels, &format!("le=\"{}\"", bound));
+                            writeln!(result, "{}_bucket{{{}}} {}", name, bucket_labels, accumulated).unwrap();
+                        }
+                        let bucket_labels = join_labels(labels, "le=\"+Inf\"");
+                        writeln!(result, "{}_bucket{{{}}} {}", name, bucket_labels, count).unwrap();
+                        writeln!(result, "{}_sum{} {}", name, wrap_labels(labels), sum).unwrap();
+                        writeln!(result, "{}_count{} {}", name, wrap_labels(labels), count).unwrap();
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Remove all the metrics.
+    pub fn clear(&self) {
+        self.inner.lock().unwrap().clear();
+    }
+
+    fn update<F>(&self, name: &str, kind: MetricKind, labels: MetricLabels, f: F)
+    where
+        F: FnOnce(&mut Series)
+    {
+        let mut families = self.inner.lock().unwrap();
+        let family = families.entry(name.to_string()).or_insert_with(|| MetricFamily {
+            kind,
+            series: BTreeMap::new(),
+        });
+        // the first kind wins if a name is reported as different kinds
+        if family.kind != kind {
+            return;
+        }
+        let series = family.series.entry(render_labels(labels)).or_insert_with(|| match kind {
+            MetricKind::Counter | MetricKind::Gauge => Series::Value(0.0),
+            MetricKind::Histogram => Series::Histogram {
+                bucket_counts: vec![0; DEFAULT_BUCKETS.len()],
+                sum: 0.0,
+                count: 0,
+            },
+        });
+        f(series);
+    }
+
+}
+
+impl MetricsRecorder for PrometheusExporter {
+
+    fn increment_counter(&self, name: &str, labels: MetricLabels, value: u64) {
+        self.update(name, MetricKind::Counter, labels, |series| {
+            if let Series::Value(current) = series {
+                *current += value as f64;
+            }
+        });
+    }
+
+    fn absolute_counter(&self, name: &str, labels: MetricLabels, value: u64) {
+        self.update(name, MetricKind::Counter, labels, |series| {
+            if let Series::Value(current) = series {
+                *current = value as f64;
+            }
+        });
+    }
+
+    fn set_gauge(&self, name: &str, labels: MetricLabels, value: f64) {
+        self.update(name, MetricKind::Gauge, labels, |series| {
+            if let Series::Value(current) = series {
+                *current = value;
+            }
+        });
+    }
+
+    fn record_histogram(&self, name: &str, labels: MetricLabels, value: f64) {
+        self.update(name, MetricKind::Histogram, labels, |series| {
+            if let Series::Histogram { bucket_counts, sum, count } = series {
+                if let Some(index) = DEFAULT_BUCKETS.iter().position(|bound| value <= *bound) {
+                    bucket_counts[index] += 1;
+                }
+                *sum += value;
+                *count += 1;
+            }
+        });
+    }
+
+}
+
+fn render_labels(labels: MetricLabels) -> String {
+    labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_label_value(value)))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn wrap_labels(labels: &str) -> String {
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels)
+    }
+}
+
+fn join_labels(labels: &str, extra: &str) -> String {
+    if labels.is_empty() {
+        extra.to_string()
+    } else {
+        format!("{},{}", labels, extra)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::recorder::MetricsRecorder;
+    use super::PrometheusExporter;
+
+    #[test]
+    fn test_render_histogram() {
+        let exporter = PrometheusExporter::new();
+        exporter.record_histogram("latency_seconds", &[("operation", "find")], 0.002);
+        exporter.record_histogram("latency_seconds", &[("operation", "find")], 20.0);
+
+        let text = exporter.render();
+        assert!(text.contains("# TYPE latency_seconds histogram\n"));
+        assert!(text.contains("latency_seconds_bucket{operation=\"find\",le=\"0.001\"} 0\n"));
+        assert!(text.contains("latency_seconds_bucket{operation=\"find\",le=\"0.005\"} 1\n"));
+        assert!(text.contains("latency_seconds_bucket{operation=\"find\",le=\"10\"} 1\n"));
+        assert!(text.contains("latency_seconds_bucket{operation=\"find\",le=\"+Inf\"} 2\n"));
+        assert!(text.contains("latency_seconds_count{operation=\"find\"} 2\n"));
+    }
+
+    #[test]
+    fn test_render_counter() {
+        let exporter = PrometheusExporter::new();
+        exporter.increment_counter("ops_total", &[], 2);
+        exporter.increment_counter("ops_total", &[], 3);
+        exporter.absolute_counter("syncs_total", &[("name", "a\"b")], 7);
+        exporter.absolute_counter("syncs_total", &[("name", "a\"b")], 9);
+
+        let text = exporter.render();
+        assert!(text.contains("# TYPE ops_total counter\nops_total 5\n"));
+        assert!(text.contains("syncs_total{name=\"a\\\"b\"} 9\n"));
+    }
+
+}
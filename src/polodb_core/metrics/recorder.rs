@@ -0,0 +1,46 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use std::sync::Arc;
+
+/// The metric labels, pairs of the label name and the value.
+pub type MetricLabels<'a> = &'a [(&'a str, &'a str)];
+
+/// The sink of the metrics reported by the database.
+///
+/// Attach a recorder with [`crate::ConfigBuilder::set_metrics_recorder`].
+/// The operations are reported when they finish. The counters of the storage,
+/// like the block cache hits and the WAL syncs, are reported by
+/// [`crate::Database::report_metrics`], which is usually called before
+/// the metrics are scraped.
+///
+/// Implement it to forward the metrics to another library,
+/// or use the [`crate::PrometheusExporter`].
+pub trait MetricsRecorder: Send + Sync {
+
+    /// Add `value` to a counter.
+    fn increment_counter(&self, name: &str, labels: MetricLabels, value: u64);
+
+    /// Set a counter which is accumulated by the storage to `value`.
+    fn absolute_counter(&self, name: &str, labels: MetricLabels, value: u64);
+
+    fn set_gauge(&self, name: &str, labels: MetricLabels, value: f64);
+
+    /// Record an observation, like the latency of an operation in seconds.
+    fn record_histogram(&self, name: &str, labels: MetricLabels, value: f64);
+
+}
+
+pub(crate) type SharedMetricsRecorder = Arc<dyn MetricsRecorder>;
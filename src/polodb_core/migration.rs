@@ -0,0 +1,303 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Versioned migrations of the schema of the database.
+//!
+//! The migrations are registered in code with increasing versions, and
+//! [`crate::Database::migrate`] applies the ones newer than the version stored
+//! in the database, in a single transaction. A failed migration is rolled back,
+//! the version is unchanged.
+//!
+//! A [`Migrations::step`] runs in the transaction, e.g. to create the indexes or to rewrite the documents.
+//! A [`Migrations::transform`] is lazy, the documents of the collection are transformed
+//! when they are read by [`crate::CollectionT::find`], so the large collections don't have
+//! to be rewritten at once. The documents record their version in the version field,
+//! which is added to the inserted documents, the documents without it are at version 0.
+//! The transforms are not applied to the stored documents, so the filters, the updates
+//! and the aggregations see the documents as they are stored.
+//!
+//! Call [`crate::Database::migrate`] every time the database is opened,
+//! it installs the lazy transforms even if there are no pending migrations.
+//!
+//! ```rust
+//! use polodb_core::{CollectionT, Database, IndexModel};
+//! use polodb_core::bson::{doc, Document};
+//! use polodb_core::migration::Migrations;
+//!
+//! let db = Database::open_memory().unwrap();
+//! let users = db.collection::<Document>("users");
+//! users.insert_one(doc! { "_id": 1, "name": "Vincent Chan" }).unwrap();
+//!
+//! let migrations = Migrations::new()
+//!     .step(1, "index the names", |txn| {
+//!         txn.collection::<Document>("users").create_index(IndexModel {
+//!             keys: doc! { "name": 1 },
+//!             options: None,
+//!         })
+//!     })
+//!     .transform(2, "split the names", "users", |doc| {
+//!         let name = doc.get_str("name").unwrap_or_default().to_string();
+//!         if let Some((first, last)) = name.split_once(' ') {
+//!             doc.insert("first", first);
+//!             doc.insert("last", last);
+//!         }
+//!         Ok(())
+//!     });
+//!
+//! let result = db.migrate(&migrations).unwrap();
+//! assert_eq!(result.version, 2);
+//!
+//! let user = users.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+//! assert_eq!(user.get_str("last").unwrap(), "Chan");
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use bson::{doc, Bson, DateTime, Document};
+use crate::db::qualify_col_name;
+use crate::results::MigrateResult;
+use crate::transaction::TransactionInner;
+use crate::{Error, Result, Transaction};
+
+const SCHEMA_VERSION_PREFIX: &str = "$SCHEMA_VERSION";
+
+/// The default field of the version of the documents with lazy transforms.
+pub const DEFAULT_VERSION_FIELD: &str = "_schemaVersion";
+
+pub type MigrationStep = Box<dyn Fn(&Transaction) -> Result<()> + Send + Sync>;
+
+pub type DocumentTransform = Arc<dyn Fn(&mut Document) -> Result<()> + Send + Sync>;
+
+enum MigrationKind {
+    Step(MigrationStep),
+    Transform {
+        collection: String,
+        transform: DocumentTransform,
+    },
+}
+
+struct Migration {
+    version: u32,
+    name: String,
+    kind: MigrationKind,
+}
+
+/// The migrations of a database, see the [module documentation](self).
+pub struct Migrations {
+    version_field: String,
+    migrations: Vec<Migration>,
+}
+
+impl Default for Migrations {
+    fn default() -> Self {
+        Migrations {
+            version_field: DEFAULT_VERSION_FIELD.to_string(),
+            migrations: Vec::new(),
+        }
+    }
+}
+
+impl Migrations {
+
+    pub fn new() -> Migrations {
+        Migrations::default()
+    }
+
+    /// The field of the version of the documents with lazy transforms,
+    /// [`DEFAULT_VERSION_FIELD`] by default.
+    pub fn version_field<T: Into<String>>(mut self, name: T) -> Self {
+        self.version_field = name.into();
+        self
+    }
+
+    /// Run `f` in the migration transaction.
+    pub fn step<F>(mut self, version: u32, name: &str, f: F) -> Self
+    where
+        F: Fn(&Transaction) -> Result<()> + Send + Sync + 'static
+    {
+        self.migrations.push(Migration {
+            version,
+            name: name.to_string(),
+            kind: MigrationKind::Step(Box::new(f)),
+        });
+        self
+    }
+
+    /// Transform the documents of `collection` older than `version` when they are read.
+    pub fn transform<F>(mut self, version: u32, name: &str, collection: &str, f: F) -> Self
+    where
+        F: Fn(&mut Document) -> Result<()> + Send + Sync + 'static
+    {
+        self.migrations.push(Migration {
+            version,
+            name: name.to_string(),
+            kind: MigrationKind::Transform {
+                collection: collection.to_string(),
+                transform: Arc::new(f),
+            },
+        });
+        self
+    }
+
+    /// The version of the latest migration, 0 if there are no migrations.
+    pub fn latest_version(&self) -> u32 {
+        self.migrations.iter().map(|migration| migration.version).max().unwrap_or(0)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let mut prev = 0;
+        for migration in &self.migrations {
+            if migration.version <= prev {
+                return Err(Error::ValidationError(format!(
+                    "the versions of the migrations must be positive and increasing, found {} after {}",
+                    migration.version, prev,
+                )));
+            }
+            prev = migration.version;
+        }
+        Ok(())
+    }
+
+    /// The lazy transforms of each collection.
+    fn collection_transforms(&self, namespace: Option<&str>) -> HashMap<String, Arc<CollectionTransforms>> {
+        let mut result: HashMap<String, CollectionTransforms> = HashMap::new();
+        for migration in &self.migrations {
+            if let MigrationKind::Transform { collection, transform } = &migration.kind {
+                let transforms = result
+                    .entry(qualify_col_name(namespace, collection))
+                    .or_insert_with(|| CollectionTransforms {
+                        version_field: self.version_field.clone(),
+                        version: self.latest_version(),
+                        transforms: Vec::new(),
+                    });
+                transforms.transforms.push((migration.version, transform.clone()));
+            }
+        }
+        result.into_iter().map(|(name, transforms)| (name, Arc::new(transforms))).collect()
+    }
+
+}
+
+/// The lazy transforms of a collection.
+pub(crate) struct CollectionTransforms {
+    version_field: String,
+    /// The version of the inserted documents
+    version: u32,
+    transforms: Vec<(u32, DocumentTransform)>,
+}
+
+impl CollectionTransforms {
+
+    fn document_version(&self, doc: &Document) -> u32 {
+        match doc.get(&self.version_field) {
+            Some(Bson::Int32(v)) => *v as u32,
+            Some(Bson::Int64(v)) => *v as u32,
+            Some(Bson::Double(v)) => *v as u32,
+            _ => 0,
+        }
+    }
+
+    /// Record the version in the inserted document.
+    pub(crate) fn stamp(&self, doc: &mut Document) {
+        if !doc.contains_key(&self.version_field) {
+            doc.insert(self.version_field.clone(), self.version as i64);
+        }
+    }
+
+    /// Apply the transforms newer than the document.
+    /// Return `None` if the document is up to date.
+    pub(crate) fn apply(&self, doc: &Document) -> Result<Option<Document>> {
+        let doc_version = self.document_version(doc);
+        if doc_version >= self.version {
+            return Ok(None);
+        }
+        let mut doc = doc.clone();
+        for (version, transform) in &self.transforms {
+            if *version > doc_version {
+                transform(&mut doc)?;
+            }
+        }
+        doc.insert(self.version_field.clone(), self.version as i64);
+        Ok(Some(doc))
+    }
+
+}
+
+fn version_key(namespace: Option<&str>) -> Result<Vec<u8>> {
+    crate::utils::bson::stacked_key(&[
+        Bson::String(SCHEMA_VERSION_PREFIX.to_string()),
+        Bson::String(namespace.unwrap_or_default().to_string()),
+    ])
+}
+
+/// The schema version of the database, 0 if it's never migrated.
+pub(crate) fn read_version(txn: &TransactionInner, namespace: Option<&str>) -> Result<u32> {
+    let key = version_key(namespace)?;
+    let bytes = match txn.rocksdb_txn.get_for_update(&key)? {
+        Some(bytes) => bytes,
+        None => return Ok(0),
+    };
+    let doc = bson::from_slice::<Document>(bytes.as_ref())?;
+    Ok(doc.get_i64("version").unwrap_or(0) as u32)
+}
+
+fn write_version(txn: &TransactionInner, namespace: Option<&str>, version: u32) -> Result<()> {
+    let doc = doc! {
+        "version": version as i64,
+        "updatedAt": DateTime::now(),
+    };
+    txn.put(&version_key(namespace)?, &bson::to_vec(&doc)?)
+}
+
+/// Remove the schema version of a dropped database.
+pub(crate) fn delete_version(txn: &TransactionInner, namespace: Option<&str>) -> Result<()> {
+    txn.delete(&version_key(namespace)?)
+}
+
+/// Apply the pending migrations in `txn` and return the lazy transforms to install.
+pub(crate) fn migrate(
+    migrations: &Migrations,
+    txn: &Transaction,
+) -> Result<(MigrateResult, HashMap<String, Arc<CollectionTransforms>>)> {
+    migrations.validate()?;
+    let namespace = txn.namespace();
+    let previous_version = read_version(txn.inner(), namespace)?;
+    let latest_version = migrations.latest_version();
+    if previous_version > latest_version {
+        return Err(Error::SchemaVersionMismatch {
+            stored: previous_version,
+            latest: latest_version,
+        });
+    }
+
+    let mut applied = Vec::new();
+    for migration in migrations.migrations.iter().filter(|migration| migration.version > previous_version) {
+        crate::trace_span!("polodb.migration", version = migration.version, name = migration.name.as_str());
+        if let MigrationKind::Step(step) = &migration.kind {
+            step(txn)?;
+        }
+        applied.push(migration.name.clone());
+    }
+    if latest_version > previous_version {
+        write_version(txn.inner(), namespace, latest_version)?;
+    }
+
+    let result = MigrateResult {
+        previous_version,
+        version: latest_version,
+        applied,
+    };
+    Ok((result, migrations.collection_transforms(namespace)))
+}
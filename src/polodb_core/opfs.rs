@@ -0,0 +1,550 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A [`StorageBackend`] over the Origin Private File System of the browsers.
+//!
+//! The engine reads and writes the files synchronously, but the handles of OPFS
+//! are only returned by promises. [`OpfsBackend::load`] opens a sync access handle
+//! of every file under a directory of OPFS before the database is opened, without
+//! reading them, and the engine reads and writes these files in place. The sync
+//! access handles are only available in the dedicated workers.
+//!
+//! The files created after `load` are kept in memory until [`OpfsBackend::persist`]
+//! writes them to OPFS, with the files deleted and renamed since then. These
+//! changes are lost if the page is closed before `persist`.
+//!
+//! The paths of the database are resolved from the directory passed to `load`,
+//! e.g. the files of the database opened at `/notes.db` are in the directory
+//! `notes.db` of it.
+//!
+//! ```ignore
+//! use std::sync::Arc;
+//! use polodb_core::{ConfigBuilder, Database};
+//! use polodb_core::opfs::OpfsBackend;
+//!
+//! let root = opfs_root().await?; // navigator.storage.getDirectory()
+//! let backend = Arc::new(OpfsBackend::load(&root).await?);
+//! let mut config = ConfigBuilder::new();
+//! config.set_storage_backend(backend.clone());
+//! let db = Database::open_path_with_config("/notes.db", config.take())?;
+//! // ...
+//! backend.persist(&root).await?;
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::storage_backend::{OpenMode, StorageBackend, StorageFile};
+
+/// Where the content of a file is.
+enum Contents {
+    /// Created since the load, written to OPFS by persist
+    Memory(Vec<u8>),
+    /// A file of OPFS, read and written in place
+    #[cfg(target_arch = "wasm32")]
+    Handle(js::SyncHandle),
+}
+
+impl Contents {
+
+    fn is_memory(&self) -> bool {
+        match self {
+            Contents::Memory(_) => true,
+            #[cfg(target_arch = "wasm32")]
+            Contents::Handle(_) => false,
+        }
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Contents::Memory(bytes) => {
+                let offset = (offset as usize).min(bytes.len());
+                let n = buf.len().min(bytes.len() - offset);
+                buf[..n].copy_from_slice(&bytes[offset..offset + n]);
+                Ok(n)
+            }
+            #[cfg(target_arch = "wasm32")]
+            Contents::Handle(handle) => handle.read_at(offset, buf),
+        }
+    }
+
+    fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        match self {
+            Contents::Memory(bytes) => {
+                let end = offset as usize + data.len();
+                if bytes.len() < end {
+                    bytes.resize(end, 0);
+                }
+                bytes[offset as usize..end].copy_from_slice(data);
+                Ok(())
+            }
+            #[cfg(target_arch = "wasm32")]
+            Contents::Handle(handle) => handle.write_at(offset, data),
+        }
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        match self {
+            Contents::Memory(bytes) => Ok(bytes.len() as u64),
+            #[cfg(target_arch = "wasm32")]
+            Contents::Handle(handle) => handle.size(),
+        }
+    }
+
+    fn truncate(&mut self, size: u64) -> io::Result<()> {
+        match self {
+            Contents::Memory(bytes) => {
+                bytes.resize(size as usize, 0);
+                Ok(())
+            }
+            #[cfg(target_arch = "wasm32")]
+            Contents::Handle(handle) => handle.truncate(size),
+        }
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        match self {
+            // written to OPFS by OpfsBackend::persist
+            Contents::Memory(_) => Ok(()),
+            #[cfg(target_arch = "wasm32")]
+            Contents::Handle(handle) => handle.flush(),
+        }
+    }
+
+    /// Read a file of OPFS into memory, to write it to another path.
+    fn move_to_memory(&mut self) -> io::Result<()> {
+        match self {
+            Contents::Memory(_) => Ok(()),
+            #[cfg(target_arch = "wasm32")]
+            Contents::Handle(handle) => {
+                let mut bytes = vec![0; handle.size()? as usize];
+                handle.read_at(0, &mut bytes)?;
+                *self = Contents::Memory(bytes);
+                Ok(())
+            }
+        }
+    }
+
+    /// Close the handle of a deleted file, OPFS doesn't remove the files with open handles.
+    fn release(&mut self) {
+        match self {
+            Contents::Memory(_) => (),
+            #[cfg(target_arch = "wasm32")]
+            Contents::Handle(_) => *self = Contents::Memory(Vec::new()),
+        }
+    }
+
+}
+
+struct FileData {
+    contents: Mutex<Contents>,
+    /// In memory and changed since the last persist
+    dirty: AtomicBool,
+}
+
+impl FileData {
+
+    fn mark_written(&self, contents: &Contents) {
+        if contents.is_memory() {
+            self.dirty.store(true, Ordering::Release);
+        }
+    }
+
+}
+
+/// The files of a database in OPFS, see the [module docs](self).
+#[derive(Default)]
+pub struct OpfsBackend {
+    files: Mutex<BTreeMap<PathBuf, Arc<FileData>>>,
+    dirs: Mutex<BTreeSet<PathBuf>>,
+    /// The files and the directories deleted since the last persist
+    deleted: Mutex<BTreeSet<PathBuf>>,
+}
+
+impl OpfsBackend {
+
+    /// An empty backend, which doesn't read anything from OPFS.
+    pub fn new() -> OpfsBackend {
+        OpfsBackend::default()
+    }
+
+    /// Whether there are changes not written to OPFS yet.
+    pub fn is_dirty(&self) -> bool {
+        !self.deleted.lock().unwrap().is_empty()
+            || self.files.lock().unwrap().values().any(|file| file.dirty.load(Ordering::Acquire))
+    }
+
+    fn insert_file(&self, path: PathBuf, contents: Contents, dirty: bool) -> Arc<FileData> {
+        let data = Arc::new(FileData {
+            contents: Mutex::new(contents),
+            dirty: AtomicBool::new(dirty),
+        });
+        self.deleted.lock().unwrap().remove(&path);
+        self.files.lock().unwrap().insert(path, data.clone());
+        data
+    }
+
+}
+
+impl StorageBackend for OpfsBackend {
+
+    fn open(&self, path: &Path, mode: OpenMode) -> io::Result<Box<dyn StorageFile>> {
+        let existing = self.files.lock().unwrap().get(path).cloned();
+        let data = match (existing, mode) {
+            (Some(data), OpenMode::Truncate) => {
+                let mut contents = data.contents.lock().unwrap();
+                contents.truncate(0)?;
+                data.mark_written(&contents);
+                drop(contents);
+                data
+            }
+            (Some(data), _) => data,
+            (None, OpenMode::Read) => return Err(io::ErrorKind::NotFound.into()),
+            (None, _) => self.insert_file(path.to_path_buf(), Contents::Memory(Vec::new()), true),
+        };
+        Ok(Box::new(OpfsFile { data }))
+    }
+
+    fn delete(&self, path: &Path) -> io::Result<()> {
+        let removed = match self.files.lock().unwrap().remove(path) {
+            Some(data) => {
+                data.contents.lock().unwrap().release();
+                true
+            }
+            None => self.dirs.lock().unwrap().remove(path),
+        };
+        if !removed {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        self.deleted.lock().unwrap().insert(path.to_path_buf());
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let data = self.files.lock().unwrap().get(from).cloned().ok_or(io::ErrorKind::NotFound)?;
+        data.contents.lock().unwrap().move_to_memory()?;
+        data.dirty.store(true, Ordering::Release);
+        self.files.lock().unwrap().remove(from);
+        self.deleted.lock().unwrap().insert(from.to_path_buf());
+        self.deleted.lock().unwrap().remove(to);
+        let replaced = self.files.lock().unwrap().insert(to.to_path_buf(), data);
+        if let Some(replaced) = replaced {
+            replaced.contents.lock().unwrap().release();
+        }
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        Ok(self.files.lock().unwrap().contains_key(path) || self.dirs.lock().unwrap().contains(path))
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<String>> {
+        let files = self.files.lock().unwrap();
+        let dirs = self.dirs.lock().unwrap();
+        if !dirs.contains(dir) {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        Ok(files.keys().chain(dirs.iter())
+            .filter(|path| path.parent() == Some(dir))
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect())
+    }
+
+    fn create_dir(&self, dir: &Path) -> io::Result<()> {
+        let mut dirs = self.dirs.lock().unwrap();
+        let mut deleted = self.deleted.lock().unwrap();
+        for ancestor in dir.ancestors() {
+            dirs.insert(ancestor.to_path_buf());
+            deleted.remove(ancestor);
+        }
+        Ok(())
+    }
+
+    fn is_dir(&self, path: &Path) -> io::Result<bool> {
+        if self.dirs.lock().unwrap().contains(path) {
+            return Ok(true);
+        }
+        if self.files.lock().unwrap().contains_key(path) {
+            return Ok(false);
+        }
+        Err(io::ErrorKind::NotFound.into())
+    }
+
+}
+
+struct OpfsFile {
+    data: Arc<FileData>,
+}
+
+impl StorageFile for OpfsFile {
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.data.contents.lock().unwrap().read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+        let mut contents = self.data.contents.lock().unwrap();
+        contents.write_at(offset, data)?;
+        self.data.mark_written(&contents);
+        Ok(())
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.data.contents.lock().unwrap().sync()
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.data.contents.lock().unwrap().size()
+    }
+
+    fn truncate(&self, size: u64) -> io::Result<()> {
+        let mut contents = self.data.contents.lock().unwrap();
+        contents.truncate(size)?;
+        self.data.mark_written(&contents);
+        Ok(())
+    }
+
+}
+
+#[cfg(target_arch = "wasm32")]
+mod js {
+    use std::io;
+    use std::path::{Component, Path, PathBuf};
+    use std::sync::Arc;
+    use std::sync::atomic::Ordering;
+    use js_sys::{Array, AsyncIterator, IteratorNext};
+    use wasm_bindgen::{JsCast, JsValue};
+    use wasm_bindgen_futures::JsFuture;
+    use web_sys::{
+        FileSystemDirectoryHandle, FileSystemFileHandle, FileSystemGetDirectoryOptions,
+        FileSystemGetFileOptions, FileSystemHandle, FileSystemHandleKind, FileSystemReadWriteOptions,
+        FileSystemSyncAccessHandle,
+    };
+    use super::{Contents, FileData, OpfsBackend};
+
+    /// A sync access handle of a file, it's closed when dropped.
+    pub(super) struct SyncHandle(FileSystemSyncAccessHandle);
+
+    // the JS values can't be sent to other threads,
+    // but the engine only runs on the thread of the worker on wasm32
+    unsafe impl Send for SyncHandle {}
+    unsafe impl Sync for SyncHandle {}
+
+    fn at(offset: u64) -> FileSystemReadWriteOptions {
+        let options = FileSystemReadWriteOptions::new();
+        options.set_at(offset as f64);
+        options
+    }
+
+    impl SyncHandle {
+
+        pub(super) fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+            let mut read = 0;
+            while read < buf.len() {
+                let n = self.0.read_with_u8_array_and_options(&mut buf[read..], &at(offset + read as u64))
+                    .map_err(js_error)? as usize;
+                if n == 0 {
+                    break;
+                }
+                read += n;
+            }
+            Ok(read)
+        }
+
+        pub(super) fn write_at(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+            let mut written = 0;
+            while written < data.len() {
+                let n = self.0.write_with_u8_array_and_options(&data[written..], &at(offset + written as u64))
+                    .map_err(js_error)? as usize;
+                if n == 0 {
+                    return Err(io::ErrorKind::WriteZero.into());
+                }
+                written += n;
+            }
+            Ok(())
+        }
+
+        pub(super) fn size(&self) -> io::Result<u64> {
+            Ok(self.0.get_size().map_err(js_error)? as u64)
+        }
+
+        pub(super) fn truncate(&self, size: u64) -> io::Result<()> {
+            self.0.truncate_with_f64(size as f64).map_err(js_error)
+        }
+
+        pub(super) fn flush(&self) -> io::Result<()> {
+            self.0.flush().map_err(js_error)
+        }
+
+    }
+
+    impl Drop for SyncHandle {
+
+        fn drop(&mut self) {
+            self.0.close();
+        }
+
+    }
+
+    /// The names of the entries from the root of OPFS to the path.
+    fn opfs_components(path: &Path) -> Vec<String> {
+        path.components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn js_error(value: JsValue) -> io::Error {
+        // the handles are missing
+        if value.dyn_ref::<js_sys::Error>().map(|err| err.name() == "NotFoundError").unwrap_or(false) {
+            return io::ErrorKind::NotFound.into();
+        }
+        io::Error::new(io::ErrorKind::Other, format!("OPFS: {:?}", value))
+    }
+
+    async fn wait<T: JsCast>(promise: js_sys::Promise) -> io::Result<T> {
+        let value = JsFuture::from(promise).await.map_err(js_error)?;
+        Ok(value.unchecked_into())
+    }
+
+    async fn dir_handle(root: &FileSystemDirectoryHandle, names: &[String], create: bool) -> io::Result<FileSystemDirectoryHandle> {
+        let options = FileSystemGetDirectoryOptions::new();
+        options.set_create(create);
+        let mut dir = root.clone();
+        for name in names {
+            dir = wait(dir.get_directory_handle_with_options(name, &options)).await?;
+        }
+        Ok(dir)
+    }
+
+    async fn open_handle(handle: &FileSystemFileHandle) -> io::Result<SyncHandle> {
+        Ok(SyncHandle(wait(handle.create_sync_access_handle()).await?))
+    }
+
+    async fn create_handle(root: &FileSystemDirectoryHandle, path: &Path) -> io::Result<SyncHandle> {
+        let names = opfs_components(path);
+        let (name, parents) = names.split_last().ok_or(io::ErrorKind::InvalidInput)?;
+        let dir = dir_handle(root, parents, true).await?;
+        let options = FileSystemGetFileOptions::new();
+        options.set_create(true);
+        let handle: FileSystemFileHandle = wait(dir.get_file_handle_with_options(name, &options)).await?;
+        open_handle(&handle).await
+    }
+
+    async fn remove_entry(root: &FileSystemDirectoryHandle, path: &Path) -> io::Result<()> {
+        let names = opfs_components(path);
+        let (name, parents) = names.split_last().ok_or(io::ErrorKind::InvalidInput)?;
+        let result = match dir_handle(root, parents, false).await {
+            Ok(dir) => wait::<JsValue>(dir.remove_entry(name)).await.map(|_| ()),
+            Err(err) => Err(err),
+        };
+        match result {
+            // deleted before it was persisted
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Write a file in memory through the handle, and read and write it in place since then.
+    fn adopt(file: &FileData, handle: SyncHandle) -> io::Result<()> {
+        let mut contents = file.contents.lock().unwrap();
+        if let Contents::Memory(bytes) = &*contents {
+            handle.write_at(0, bytes)?;
+            handle.truncate(bytes.len() as u64)?;
+            handle.flush()?;
+        }
+        *contents = Contents::Handle(handle);
+        file.dirty.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    impl OpfsBackend {
+
+        /// Open the files under `root`, which is usually the root of OPFS
+        /// returned by `navigator.storage.getDirectory()`.
+        ///
+        /// The content of the files isn't read, it's read by the engine when needed.
+        pub async fn load(root: &FileSystemDirectoryHandle) -> io::Result<OpfsBackend> {
+            let backend = OpfsBackend::new();
+            let mut pending = vec![(PathBuf::from("/"), root.clone())];
+            while let Some((path, dir)) = pending.pop() {
+                backend.dirs.lock().unwrap().insert(path.clone());
+
+                let entries: AsyncIterator = dir.entries();
+                loop {
+                    let next: IteratorNext = wait(entries.next().map_err(js_error)?).await?;
+                    if next.done() {
+                        break;
+                    }
+                    let entry: Array = next.value().unchecked_into();
+                    let name = entry.get(0).as_string().ok_or(io::ErrorKind::InvalidData)?;
+                    let handle: FileSystemHandle = entry.get(1).unchecked_into();
+                    match handle.kind() {
+                        FileSystemHandleKind::Directory => {
+                            pending.push((path.join(&name), handle.unchecked_into()));
+                        }
+                        _ => {
+                            let handle = open_handle(handle.unchecked_ref::<FileSystemFileHandle>()).await?;
+                            backend.insert_file(path.join(&name), Contents::Handle(handle), false);
+                        }
+                    }
+                }
+            }
+            Ok(backend)
+        }
+
+        /// Write the files created since the last persist to `root`,
+        /// and delete the files deleted by the engine.
+        ///
+        /// The changes not written are kept if it fails, so it can be called again.
+        pub async fn persist(&self, root: &FileSystemDirectoryHandle) -> io::Result<()> {
+            let dirs: Vec<PathBuf> = self.dirs.lock().unwrap().iter().cloned().collect();
+            for dir in &dirs {
+                dir_handle(root, &opfs_components(dir), true).await?;
+            }
+
+            let files: Vec<(PathBuf, Arc<FileData>)> = self.files.lock().unwrap()
+                .iter()
+                .filter(|(_, file)| file.dirty.load(Ordering::Acquire))
+                .map(|(path, file)| (path.clone(), file.clone()))
+                .collect();
+            for (path, file) in files {
+                let handle = create_handle(root, &path).await?;
+                // the file may be deleted or renamed while the handle is opened
+                let current = self.files.lock().unwrap()
+                    .get(&path)
+                    .map(|current| Arc::ptr_eq(current, &file))
+                    .unwrap_or(false);
+                if current {
+                    adopt(&file, handle)?;
+                }
+            }
+
+            // the files are written before the old ones are deleted,
+            // so the renamed files are never missing from OPFS
+            let deleted: Vec<PathBuf> = self.deleted.lock().unwrap().iter().cloned().collect();
+            for path in deleted.iter().rev() {
+                remove_entry(root, path).await?;
+                self.deleted.lock().unwrap().remove(path);
+            }
+            Ok(())
+        }
+
+    }
+
+}
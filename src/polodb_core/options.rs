@@ -12,9 +12,237 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+#[cfg(feature = "csv")]
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use bson::Document;
+use serde::{Deserialize, Serialize};
+use crate::storage_backend::StorageBackend;
+use crate::{BlockCache, ChecksumType, Config, Database, IdGenerator, KeyOrdering, QuotaPolicy, Result, Tokenizer, WalSyncPolicy};
+#[cfg(feature = "fault-injection")]
+use crate::fault_injection::FaultInjector;
+#[cfg(feature = "metrics")]
+use crate::MetricsRecorder;
+
+/// The options to open a database, returned by [`Database::options`].
+///
+/// ```rust
+/// use polodb_core::{Database, WalSyncPolicy};
+/// # let db_path = polodb_core::test_utils::mk_db_path("doc-test-open-options");
+/// let db = Database::options()
+///     .cache_size(64 * 1024 * 1024)
+///     .durability(WalSyncPolicy::OsBuffered)
+///     .open(&db_path)
+///     .unwrap();
+/// ```
+///
+/// The options not set keep the defaults of [`Config`].
+#[derive(Default)]
+pub struct OpenOptions {
+    config: Config,
+    read_only: bool,
+}
+
+impl OpenOptions {
+
+    pub fn new() -> OpenOptions {
+        OpenOptions::default()
+    }
+
+    /// Start from a [`Config`] made by [`crate::ConfigBuilder`],
+    /// the options set before are replaced.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Open the database without modifying its files, see [`Database::open_read_only`].
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Use a block cache of `capacity` bytes for this database only.
+    /// See [`OpenOptions::block_cache`] to share a cache between the databases.
+    pub fn cache_size(mut self, capacity: usize) -> Self {
+        self.config.block_cache = Some(BlockCache::lru(capacity));
+        self
+    }
+
+    /// See [`crate::ConfigBuilder::set_block_cache`].
+    pub fn block_cache(mut self, cache: BlockCache) -> Self {
+        self.config.block_cache = Some(cache);
+        self
+    }
+
+    /// When the commits are synced to the disk, see [`WalSyncPolicy`].
+    pub fn durability(mut self, policy: WalSyncPolicy) -> Self {
+        self.config.wal_sync_policy = policy;
+        self
+    }
+
+    /// See [`crate::ConfigBuilder::set_memtable_size`].
+    pub fn memtable_size(mut self, size: u64) -> Self {
+        self.config.memtable_size = size;
+        self
+    }
+
+    /// See [`crate::ConfigBuilder::set_max_immutable_memtables`].
+    pub fn max_immutable_memtables(mut self, count: u32) -> Self {
+        self.config.max_immutable_memtables = count;
+        self
+    }
+
+    /// Slow down and stop the writes when the number of level-0 files reaches
+    /// the triggers, see [`crate::ConfigBuilder::set_level0_slowdown_writes_trigger`].
+    pub fn level0_write_triggers(mut self, slowdown: u32, stop: u32) -> Self {
+        self.config.level0_slowdown_writes_trigger = slowdown;
+        self.config.level0_stop_writes_trigger = stop;
+        self
+    }
+
+    /// Slow down and stop the writes when the estimated bytes waiting for the compaction
+    /// reach the limits, see [`crate::ConfigBuilder::set_soft_pending_compaction_bytes_limit`].
+    pub fn pending_compaction_limits(mut self, soft: u64, hard: u64) -> Self {
+        self.config.soft_pending_compaction_bytes_limit = soft;
+        self.config.hard_pending_compaction_bytes_limit = hard;
+        self
+    }
+
+    /// Keep the obsolete WAL files, see [`crate::ConfigBuilder::set_wal_ttl_seconds`]
+    /// and [`crate::ConfigBuilder::set_wal_size_limit_mb`].
+    pub fn wal_retention(mut self, ttl_seconds: u64, size_limit_mb: u64) -> Self {
+        self.config.wal_ttl_seconds = ttl_seconds;
+        self.config.wal_size_limit_mb = size_limit_mb;
+        self
+    }
+
+    pub fn checksum(mut self, checksum: ChecksumType) -> Self {
+        self.config.checksum_type = checksum;
+        self
+    }
+
+    /// See [`crate::ConfigBuilder::set_paranoid_checks`].
+    pub fn paranoid_checks(mut self, paranoid_checks: bool) -> Self {
+        self.config.paranoid_checks = paranoid_checks;
+        self
+    }
+
+    pub fn max_document_size(mut self, size: u32) -> Self {
+        self.config.max_document_size = size;
+        self
+    }
+
+    /// See [`crate::ConfigBuilder::set_mmap_reads`].
+    pub fn mmap_reads(mut self, mmap_reads: bool) -> Self {
+        self.config.mmap_reads = mmap_reads;
+        self
+    }
+
+    /// See [`crate::ConfigBuilder::set_direct_io_writes`].
+    pub fn direct_io_writes(mut self, direct_io_writes: bool) -> Self {
+        self.config.direct_io_writes = direct_io_writes;
+        self
+    }
+
+    /// See [`crate::ConfigBuilder::set_cold_path`].
+    pub fn cold_path<P: AsRef<Path>>(mut self, path: P, hot_size_limit: u64) -> Self {
+        self.config.cold_path = Some(path.as_ref().to_path_buf());
+        self.config.hot_size_limit = hot_size_limit;
+        self
+    }
+
+    /// Store the files through a [`StorageBackend`], e.g. to encrypt them.
+    pub fn storage_backend(mut self, backend: Arc<dyn StorageBackend>) -> Self {
+        self.config.storage_backend = Some(backend);
+        self
+    }
+
+    /// Limit the size of the database, see [`crate::ConfigBuilder::set_max_db_size`].
+    pub fn max_db_size(mut self, size: u64) -> Self {
+        self.config.max_db_size = Some(size);
+        self
+    }
+
+    pub fn quota_policy(mut self, policy: QuotaPolicy) -> Self {
+        self.config.quota_policy = policy;
+        self
+    }
+
+    /// See [`crate::ConfigBuilder::set_slow_query_threshold`].
+    pub fn slow_query_threshold(mut self, threshold: Duration) -> Self {
+        self.config.slow_query_threshold = Some(threshold);
+        self
+    }
+
+    /// See [`crate::ConfigBuilder::set_id_generator`].
+    pub fn id_generator(mut self, name: &str, generator: IdGenerator) -> Self {
+        self.config.id_generators.insert(name.to_string(), generator);
+        self
+    }
+
+    /// See [`crate::ConfigBuilder::set_tokenizer`].
+    pub fn tokenizer(mut self, name: &str, tokenizer: Arc<dyn Tokenizer>) -> Self {
+        self.config.tokenizers.insert(name.to_string(), tokenizer);
+        self
+    }
+
+    /// See [`crate::ConfigBuilder::set_key_ordering`].
+    pub fn key_ordering(mut self, name: &str, ordering: Arc<dyn KeyOrdering>) -> Self {
+        self.config.key_orderings.insert(name.to_string(), ordering);
+        self
+    }
+
+    /// See [`crate::ConfigBuilder::set_sync_tracking`].
+    pub fn sync_tracking(mut self, sync_tracking: bool) -> Self {
+        self.config.sync_tracking = sync_tracking;
+        self
+    }
+
+    /// See [`crate::ConfigBuilder::set_auto_upgrade`].
+    pub fn auto_upgrade(mut self, auto_upgrade: bool) -> Self {
+        self.config.auto_upgrade = auto_upgrade;
+        self
+    }
+
+    #[cfg(feature = "fault-injection")]
+    pub fn fault_injector(mut self, injector: FaultInjector) -> Self {
+        self.config.fault_injector = Some(injector);
+        self
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn metrics_recorder(mut self, recorder: Arc<dyn MetricsRecorder>) -> Self {
+        self.config.metrics_recorder = Some(recorder);
+        self
+    }
+
+    /// Open the database in `path`, it's created if it doesn't exist
+    /// unless the database is opened read-only.
+    pub fn open<P: AsRef<Path>>(self, path: P) -> Result<Database> {
+        if self.read_only {
+            Database::open_read_only_with_config(path, self.config)
+        } else {
+            Database::open_path_with_config(path, self.config)
+        }
+    }
+
+    /// Open a database which keeps all the data in memory, see [`Database::open_memory`].
+    pub fn open_memory(self) -> Result<Database> {
+        Database::open_memory_with_config(self.config)
+    }
+
+}
+
 #[derive(Debug, Clone)]
 pub struct UpdateOptions {
     pub upsert: Option<bool>,
+
+    /// Fail the update with [`crate::Error::MaxTimeExceeded`] when it takes longer,
+    /// including the time waiting for the stalled writes outside a transaction.
+    pub max_time: Option<Duration>,
 }
 
 impl UpdateOptions {
@@ -29,6 +257,7 @@ impl UpdateOptions {
 
 pub struct UpdateOptionsBuilder {
     upsert: Option<bool>,
+    max_time: Option<Duration>,
 }
 
 impl UpdateOptionsBuilder {
@@ -37,21 +266,695 @@ impl UpdateOptionsBuilder {
         self
     }
 
+    pub fn max_time(mut self, max_time: Duration) -> Self {
+        self.max_time = Some(max_time);
+        self
+    }
+
     pub fn build(self) -> UpdateOptions {
         UpdateOptions {
             upsert: self.upsert,
+            max_time: self.max_time,
         }
     }
 }
 
 impl Default for UpdateOptionsBuilder {
     fn default() -> Self {
-        UpdateOptionsBuilder { upsert: None }
+        UpdateOptionsBuilder { upsert: None, max_time: None }
     }
 }
 
 impl Default for UpdateOptions {
     fn default() -> Self {
-        UpdateOptions { upsert: None }
+        UpdateOptions { upsert: None, max_time: None }
+    }
+}
+
+/// Options of a manually started transaction.
+#[derive(Debug, Clone, Default)]
+pub struct TransactionOptions {
+    /// Override the [`WalSyncPolicy`] of the database for this transaction.
+    ///
+    /// [`WalSyncPolicy::Interval`] is only accepted if it's the policy of the database,
+    /// because the WAL is synced periodically for the whole database. The other intervals
+    /// fail with [`crate::Error::ValidationError`] when the transaction is started.
+    pub sync_policy: Option<WalSyncPolicy>,
+
+    /// Fail the commit with [`crate::Error::WouldBlock`] instead of waiting
+    /// when the writes are stalled by the flushes and the compactions.
+    pub no_slowdown: Option<bool>,
+}
+
+impl TransactionOptions {
+    pub fn builder() -> TransactionOptionsBuilder {
+        TransactionOptionsBuilder::default()
+    }
+}
+
+#[derive(Default)]
+pub struct TransactionOptionsBuilder {
+    sync_policy: Option<WalSyncPolicy>,
+    no_slowdown: Option<bool>,
+}
+
+impl TransactionOptionsBuilder {
+    pub fn sync_policy(mut self, sync_policy: WalSyncPolicy) -> Self {
+        self.sync_policy = Some(sync_policy);
+        self
+    }
+
+    pub fn no_slowdown(mut self, no_slowdown: bool) -> Self {
+        self.no_slowdown = Some(no_slowdown);
+        self
+    }
+
+    pub fn build(self) -> TransactionOptions {
+        TransactionOptions {
+            sync_policy: self.sync_policy,
+            no_slowdown: self.no_slowdown,
+        }
+    }
+}
+
+/// Options of [`crate::Database::create_collection_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct CreateCollectionOptions {
+    /// Create a capped collection. The oldest documents are removed
+    /// when the collection exceeds `size` or `max`.
+    pub capped: Option<bool>,
+
+    /// The max size in bytes of the documents of a capped collection.
+    pub size: Option<u64>,
+
+    /// The max count of the documents of a capped collection.
+    pub max: Option<u64>,
+
+    /// Create a time-series collection.
+    pub timeseries: Option<TimeseriesOptions>,
+
+    /// Create a read-only view on the source collection or view.
+    pub view_on: Option<String>,
+
+    /// The aggregation pipeline of the view.
+    pub pipeline: Option<Vec<Document>>,
+
+    /// Validate the inserted and updated documents, e.g. `{ "$jsonSchema": { ... } }`.
+    pub validator: Option<Document>,
+
+    /// What to do with the invalid documents, [`ValidationAction::Error`] by default.
+    pub validation_action: Option<ValidationAction>,
+
+    /// The default and computed fields of the inserted documents,
+    /// e.g. `{ "createdAt": "$$NOW", "status": "new" }`. See [`crate::Database::set_defaults`].
+    pub defaults: Option<Document>,
+
+    /// Generates the `_id` of the inserted documents without it, [`IdStrategy::ObjectId`] by default.
+    pub id_strategy: Option<IdStrategy>,
+
+    /// Partition the documents by the value of the field, e.g. `"day"`.
+    ///
+    /// The documents of a partition are stored together, so a query with an equality
+    /// on the field only scans its partition, and a partition is dropped by
+    /// [`crate::CollectionT::drop_partition`] without scanning the others.
+    /// The partitions are compared by the BSON value, so `1` and `1.0` are different partitions.
+    ///
+    /// The `_id` is unique in a partition, the field can't be updated,
+    /// and a partitioned collection has no secondary indexes and isn't synced.
+    pub partition_key: Option<String>,
+
+    /// Order the documents by their `_id` with the named [`crate::KeyOrdering`],
+    /// the built-in `"caseInsensitive"` or `"semver"`, or one registered by
+    /// [`crate::ConfigBuilder::set_key_ordering`].
+    ///
+    /// The documents are scanned in the order, and the range of [`crate::CollectionT::find_range`]
+    /// is in the order too, so `"B".."D"` includes `"b"` and `"BAR"` with `"caseInsensitive"`.
+    /// The filters are still on the `_id` itself, so `"abc"` and `"ABC"` are different documents
+    /// and `{ "_id": { "$gte": "B" } }` compares the values, like `sort({ "_id": 1 })`.
+    ///
+    /// Only a normal collection can have an ordering, and it isn't synced.
+    pub key_ordering: Option<String>,
+}
+
+impl CreateCollectionOptions {
+    pub fn builder() -> CreateCollectionOptionsBuilder {
+        CreateCollectionOptionsBuilder::default()
+    }
+
+    pub(crate) fn is_capped(&self) -> bool {
+        self.capped.unwrap_or(false)
+    }
+}
+
+#[derive(Default)]
+pub struct CreateCollectionOptionsBuilder {
+    capped: Option<bool>,
+    size: Option<u64>,
+    max: Option<u64>,
+    timeseries: Option<TimeseriesOptions>,
+    view_on: Option<String>,
+    pipeline: Option<Vec<Document>>,
+    validator: Option<Document>,
+    validation_action: Option<ValidationAction>,
+    defaults: Option<Document>,
+    id_strategy: Option<IdStrategy>,
+    partition_key: Option<String>,
+    key_ordering: Option<String>,
+}
+
+impl CreateCollectionOptionsBuilder {
+    pub fn capped(mut self, capped: bool) -> Self {
+        self.capped = Some(capped);
+        self
+    }
+
+    pub fn size(mut self, size: u64) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    pub fn max(mut self, max: u64) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    pub fn timeseries(mut self, timeseries: TimeseriesOptions) -> Self {
+        self.timeseries = Some(timeseries);
+        self
+    }
+
+    pub fn view_on<T: Into<String>>(mut self, view_on: T) -> Self {
+        self.view_on = Some(view_on.into());
+        self
+    }
+
+    pub fn pipeline(mut self, pipeline: impl IntoIterator<Item = Document>) -> Self {
+        self.pipeline = Some(pipeline.into_iter().collect());
+        self
+    }
+
+    pub fn validator(mut self, validator: Document) -> Self {
+        self.validator = Some(validator);
+        self
+    }
+
+    pub fn validation_action(mut self, validation_action: ValidationAction) -> Self {
+        self.validation_action = Some(validation_action);
+        self
+    }
+
+    pub fn defaults(mut self, defaults: Document) -> Self {
+        self.defaults = Some(defaults);
+        self
+    }
+
+    pub fn id_strategy(mut self, id_strategy: IdStrategy) -> Self {
+        self.id_strategy = Some(id_strategy);
+        self
+    }
+
+    pub fn partition_key<T: Into<String>>(mut self, partition_key: T) -> Self {
+        self.partition_key = Some(partition_key.into());
+        self
+    }
+
+    pub fn key_ordering<T: Into<String>>(mut self, key_ordering: T) -> Self {
+        self.key_ordering = Some(key_ordering.into());
+        self
+    }
+
+    pub fn build(self) -> CreateCollectionOptions {
+        CreateCollectionOptions {
+            capped: self.capped,
+            size: self.size,
+            max: self.max,
+            timeseries: self.timeseries,
+            view_on: self.view_on,
+            pipeline: self.pipeline,
+            validator: self.validator,
+            validation_action: self.validation_action,
+            defaults: self.defaults,
+            id_strategy: self.id_strategy,
+            partition_key: self.partition_key,
+            key_ordering: self.key_ordering,
+        }
+    }
+}
+
+/// How the `_id` of the inserted documents is generated when it's missing or null.
+///
+/// The ids of [`IdStrategy::UuidV7`] and [`IdStrategy::Snowflake`] are ordered by their creation,
+/// so they can be used as the cursors of the pages, e.g. `{ "_id": { "$gt": last_id } }`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IdStrategy {
+    /// A new [`bson::oid::ObjectId`].
+    #[default]
+    ObjectId,
+    /// A UUID of version 7 stored as a binary, ordered by the creation in a process.
+    UuidV7,
+    /// A 64-bit integer of the milliseconds since 2024, the node and a sequence,
+    /// ordered by the creation in a process.
+    Snowflake,
+    /// The generator registered by [`crate::ConfigBuilder::set_id_generator`] with the name.
+    Custom(String),
+}
+
+/// What to do with the documents which fail the validator of the collection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ValidationAction {
+    /// Reject the write with [`crate::Error::DocumentValidationFailed`].
+    #[default]
+    Error,
+    /// Accept the write and log the failure.
+    Warn,
+}
+
+/// Options of a time-series collection.
+///
+/// The measurements with the same metadata in the same time window
+/// are stored together in a bucket document, one column per field.
+/// The buckets are unpacked into the measurements when they are queried.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeseriesOptions {
+    /// The field of the date of the measurements, it's required for every measurement.
+    pub time_field: String,
+
+    /// The field of the metadata of the measurements, e.g. the id of the sensor.
+    /// The metadata is stored once per bucket.
+    pub meta_field: Option<String>,
+
+    /// Decides the time window of a bucket, [`TimeseriesGranularity::Seconds`] by default.
+    pub granularity: Option<TimeseriesGranularity>,
+}
+
+impl TimeseriesOptions {
+    pub fn new<T: Into<String>>(time_field: T) -> TimeseriesOptions {
+        TimeseriesOptions {
+            time_field: time_field.into(),
+            meta_field: None,
+            granularity: None,
+        }
+    }
+
+    pub fn meta_field<T: Into<String>>(mut self, meta_field: T) -> Self {
+        self.meta_field = Some(meta_field.into());
+        self
+    }
+
+    pub fn granularity(mut self, granularity: TimeseriesGranularity) -> Self {
+        self.granularity = Some(granularity);
+        self
+    }
+}
+
+/// The expected interval between the measurements of the same metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TimeseriesGranularity {
+    /// A bucket covers an hour.
+    Seconds,
+    /// A bucket covers a day.
+    Minutes,
+    /// A bucket covers 30 days.
+    Hours,
+}
+
+/// Options of [`crate::Database::clone_collection_to`].
+///
+/// The clone is created with the options of the source, like [`CreateCollectionOptions`]:
+/// the capped limits, the time-series options, the validator, the default fields,
+/// the id strategy, the partition key and the key ordering. The documents are copied
+/// as they are, the validator and the default fields are only applied to the documents
+/// inserted after the clone. A key ordering or a custom id generator must be registered
+/// in the config of the target database.
+///
+/// A view can't be cloned, it fails with [`crate::Error::ValidationError`].
+#[derive(Debug, Clone, Default)]
+pub struct CloneCollectionOptions {
+    /// Copy the index definitions of the source collection, `true` by default.
+    /// The indexes are built while the documents are copied.
+    pub indexes: Option<bool>,
+}
+
+impl CloneCollectionOptions {
+    pub fn builder() -> CloneCollectionOptionsBuilder {
+        CloneCollectionOptionsBuilder::default()
+    }
+
+    pub(crate) fn copy_indexes(&self) -> bool {
+        self.indexes.unwrap_or(true)
+    }
+}
+
+#[derive(Default)]
+pub struct CloneCollectionOptionsBuilder {
+    indexes: Option<bool>,
+}
+
+impl CloneCollectionOptionsBuilder {
+    pub fn indexes(mut self, indexes: bool) -> Self {
+        self.indexes = Some(indexes);
+        self
+    }
+
+    pub fn build(self) -> CloneCollectionOptions {
+        CloneCollectionOptions {
+            indexes: self.indexes,
+        }
+    }
+}
+
+/// Options of [`crate::Database::list_collections`].
+#[derive(Debug, Clone, Default)]
+pub struct ListCollectionsOptions {
+    /// Only list the collections whose names start with the prefix.
+    pub name_prefix: Option<String>,
+}
+
+impl ListCollectionsOptions {
+    pub fn builder() -> ListCollectionsOptionsBuilder {
+        ListCollectionsOptionsBuilder::default()
+    }
+}
+
+#[derive(Default)]
+pub struct ListCollectionsOptionsBuilder {
+    name_prefix: Option<String>,
+}
+
+impl ListCollectionsOptionsBuilder {
+    pub fn name_prefix<T: Into<String>>(mut self, name_prefix: T) -> Self {
+        self.name_prefix = Some(name_prefix.into());
+        self
+    }
+
+    pub fn build(self) -> ListCollectionsOptions {
+        ListCollectionsOptions {
+            name_prefix: self.name_prefix,
+        }
+    }
+}
+
+/// The flavor of the Extended JSON written by [`crate::Database::export_json`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExtJsonMode {
+    /// The numbers and the dates are written as plain JSON where it's lossless,
+    /// e.g. `{ "$date": "2024-01-01T00:00:00Z" }`, like `mongoexport`.
+    #[default]
+    Relaxed,
+    /// Every type is preserved, e.g. `{ "$numberInt": "1" }`.
+    Canonical,
+}
+
+/// Options of [`crate::Database::export_json`].
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// Only export the documents matching the filter.
+    pub filter: Option<Document>,
+
+    /// Write a JSON array instead of one document per line, `false` by default.
+    pub json_array: Option<bool>,
+
+    /// [`ExtJsonMode::Relaxed`] by default.
+    pub mode: Option<ExtJsonMode>,
+}
+
+impl ExportOptions {
+    pub fn builder() -> ExportOptionsBuilder {
+        ExportOptionsBuilder::default()
+    }
+}
+
+#[derive(Default)]
+pub struct ExportOptionsBuilder {
+    filter: Option<Document>,
+    json_array: Option<bool>,
+    mode: Option<ExtJsonMode>,
+}
+
+impl ExportOptionsBuilder {
+    pub fn filter(mut self, filter: Document) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    pub fn json_array(mut self, json_array: bool) -> Self {
+        self.json_array = Some(json_array);
+        self
+    }
+
+    pub fn mode(mut self, mode: ExtJsonMode) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    pub fn build(self) -> ExportOptions {
+        ExportOptions {
+            filter: self.filter,
+            json_array: self.json_array,
+            mode: self.mode,
+        }
+    }
+}
+
+/// Options of [`crate::Database::import_json`].
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    /// Read a JSON array instead of one document per line.
+    /// It's detected from the first character if it's not set.
+    pub json_array: Option<bool>,
+
+    /// Remove the documents of the collection before importing, `false` by default.
+    pub drop: Option<bool>,
+}
+
+impl ImportOptions {
+    pub fn builder() -> ImportOptionsBuilder {
+        ImportOptionsBuilder::default()
+    }
+}
+
+#[derive(Default)]
+pub struct ImportOptionsBuilder {
+    json_array: Option<bool>,
+    drop: Option<bool>,
+}
+
+impl ImportOptionsBuilder {
+    pub fn json_array(mut self, json_array: bool) -> Self {
+        self.json_array = Some(json_array);
+        self
+    }
+
+    pub fn drop(mut self, drop: bool) -> Self {
+        self.drop = Some(drop);
+        self
+    }
+
+    pub fn build(self) -> ImportOptions {
+        ImportOptions {
+            json_array: self.json_array,
+            drop: self.drop,
+        }
+    }
+}
+
+/// The type of a column of [`crate::Database::import_csv`].
+#[cfg(feature = "csv")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CsvType {
+    /// A boolean for `true` and `false`, a number if it's a number,
+    /// a string otherwise. The numbers with leading zeros, e.g. `007`, are strings.
+    #[default]
+    Auto,
+    String,
+    Int32,
+    Int64,
+    Double,
+    /// `true` or `false`, case-insensitive.
+    Boolean,
+    /// An RFC 3339 date, e.g. `2024-01-02T03:04:05Z`, or a day, e.g. `2024-01-02`.
+    Date,
+    /// The hex string of an ObjectId.
+    ObjectId,
+    /// A value in Extended JSON, e.g. an array.
+    Json,
+}
+
+/// Options of [`crate::Database::export_csv`].
+#[cfg(feature = "csv")]
+#[derive(Debug, Clone, Default)]
+pub struct CsvExportOptions {
+    /// The dot paths of the columns, e.g. `address.city` or `tags.0`.
+    /// The paths of the first document are used if it's not set,
+    /// the embedded documents are flattened into the paths of their fields.
+    pub fields: Option<Vec<String>>,
+
+    /// Only export the documents matching the filter.
+    pub filter: Option<Document>,
+
+    /// `b','` by default.
+    pub delimiter: Option<u8>,
+
+    /// Write the paths as the first row, `true` by default.
+    pub header: Option<bool>,
+}
+
+#[cfg(feature = "csv")]
+impl CsvExportOptions {
+    pub fn builder() -> CsvExportOptionsBuilder {
+        CsvExportOptionsBuilder::default()
+    }
+}
+
+#[cfg(feature = "csv")]
+#[derive(Default)]
+pub struct CsvExportOptionsBuilder {
+    fields: Option<Vec<String>>,
+    filter: Option<Document>,
+    delimiter: Option<u8>,
+    header: Option<bool>,
+}
+
+#[cfg(feature = "csv")]
+impl CsvExportOptionsBuilder {
+    pub fn fields<T: Into<String>>(mut self, fields: impl IntoIterator<Item = T>) -> Self {
+        self.fields = Some(fields.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn filter(mut self, filter: Document) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = Some(delimiter);
+        self
+    }
+
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = Some(header);
+        self
+    }
+
+    pub fn build(self) -> CsvExportOptions {
+        CsvExportOptions {
+            fields: self.fields,
+            filter: self.filter,
+            delimiter: self.delimiter,
+            header: self.header,
+        }
+    }
+}
+
+/// Options of [`crate::Database::import_csv`].
+#[cfg(feature = "csv")]
+#[derive(Debug, Clone, Default)]
+pub struct CsvImportOptions {
+    /// The types of the columns by the names in the header,
+    /// the other columns are [`CsvType::Auto`].
+    pub column_types: Option<HashMap<String, CsvType>>,
+
+    /// `b','` by default.
+    pub delimiter: Option<u8>,
+
+    /// Remove the documents of the collection before importing, `false` by default.
+    pub drop: Option<bool>,
+}
+
+#[cfg(feature = "csv")]
+impl CsvImportOptions {
+    pub fn builder() -> CsvImportOptionsBuilder {
+        CsvImportOptionsBuilder::default()
+    }
+}
+
+#[cfg(feature = "csv")]
+#[derive(Default)]
+pub struct CsvImportOptionsBuilder {
+    column_types: Option<HashMap<String, CsvType>>,
+    delimiter: Option<u8>,
+    drop: Option<bool>,
+}
+
+#[cfg(feature = "csv")]
+impl CsvImportOptionsBuilder {
+    pub fn column_type<T: Into<String>>(mut self, column: T, ty: CsvType) -> Self {
+        self.column_types.get_or_insert_with(HashMap::new).insert(column.into(), ty);
+        self
+    }
+
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = Some(delimiter);
+        self
+    }
+
+    pub fn drop(mut self, drop: bool) -> Self {
+        self.drop = Some(drop);
+        self
+    }
+
+    pub fn build(self) -> CsvImportOptions {
+        CsvImportOptions {
+            column_types: self.column_types,
+            delimiter: self.delimiter,
+            drop: self.drop,
+        }
+    }
+}
+
+/// Options of [`crate::CollectionT::map_reduce`].
+#[derive(Debug, Clone, Default)]
+pub struct MapReduceOptions {
+    /// Only map the documents matching the filter.
+    pub filter: Option<Document>,
+
+    /// The bytes of the emitted values kept in memory before they are
+    /// reduced and written to a temporary file, 100MB by default.
+    pub memory_limit: Option<usize>,
+
+    /// The directory of the temporary files, [`std::env::temp_dir`] by default.
+    pub temp_dir: Option<PathBuf>,
+}
+
+impl MapReduceOptions {
+    pub fn builder() -> MapReduceOptionsBuilder {
+        MapReduceOptionsBuilder::default()
+    }
+}
+
+#[derive(Default)]
+pub struct MapReduceOptionsBuilder {
+    filter: Option<Document>,
+    memory_limit: Option<usize>,
+    temp_dir: Option<PathBuf>,
+}
+
+impl MapReduceOptionsBuilder {
+    pub fn filter(mut self, filter: Document) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    pub fn memory_limit(mut self, memory_limit: usize) -> Self {
+        self.memory_limit = Some(memory_limit);
+        self
+    }
+
+    pub fn temp_dir<P: Into<PathBuf>>(mut self, temp_dir: P) -> Self {
+        self.temp_dir = Some(temp_dir.into());
+        self
+    }
+
+    pub fn build(self) -> MapReduceOptions {
+        MapReduceOptions {
+            filter: self.filter,
+            memory_limit: self.memory_limit,
+            temp_dir: self.temp_dir,
+        }
     }
 }
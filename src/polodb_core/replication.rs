@@ -0,0 +1,759 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Leader-follower replication.
+//!
+//! The primary streams its write-ahead log to the followers, which apply the
+//! writes in the same order and serve the read-only queries, e.g. as a warm standby.
+//! A new follower, or a follower too far behind, first receives a snapshot of
+//! the primary. The followers replicate all the named databases of the primary.
+//!
+//! A [`ReplicationServer`] serves the followers over TCP, and a [`Follower`]
+//! connects to it with [`Follower::connect`]. Other transports, e.g. a TLS stream,
+//! implement [`ReplicationTransport`] and are served by [`serve_follower`]
+//! and followed by [`Follower::start`].
+//!
+//! The transactions of a follower are read-only, the writes fail with
+//! [`Error::ReadOnlyDatabase`] until the follower is promoted by [`Follower::promote`].
+//! The sequence numbers of the promoted follower are not the ones of the
+//! former primary, so the other followers restart from an empty database
+//! to follow it.
+//!
+//! Set [`crate::ConfigBuilder::set_wal_ttl_seconds`] on the primary to keep the
+//! WAL files, so the followers catch up after a disconnection without a new snapshot.
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use polodb_core::{Database, CollectionT};
+//! use polodb_core::bson::{doc, Document};
+//! use polodb_core::replication::{Follower, ReplicationServer};
+//!
+//! let primary = std::sync::Arc::new(Database::open_memory().unwrap());
+//! let server = ReplicationServer::bind(primary.clone(), "127.0.0.1:0").unwrap();
+//! let handle = server.spawn().unwrap();
+//!
+//! let follower = Follower::connect(Database::open_memory().unwrap(), handle.local_addr()).unwrap();
+//!
+//! primary.collection::<Document>("test").insert_one(doc! { "name": "a" }).unwrap();
+//! let sequence = primary.latest_sequence_number().unwrap();
+//! assert!(follower.wait_for(sequence, Duration::from_secs(10)));
+//! assert_eq!(follower.database().collection::<Document>("test").count_documents().unwrap(), 1);
+//!
+//! handle.shutdown().unwrap();
+//! ```
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use bson::spec::BinarySubtype;
+use bson::{doc, Binary, Bson, Document};
+use crate::utils::bson::stacked_key;
+use crate::{CancellationToken, Database, Error, Result, WalOperation, WalRecord};
+
+const REPLICATION_PREFIX: &str = "$REPLICATION";
+const SNAPSHOT_CHUNK_SIZE: usize = 1000;
+const MAX_MESSAGE_SIZE: usize = 256 * 1024 * 1024;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A message between the primary and a follower.
+///
+/// The follower sends [`ReplicationMessage::Subscribe`], then the primary
+/// sends an optional snapshot and the records of the WAL.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplicationMessage {
+    /// Follow the primary from sequence `since`, or from a snapshot if it's `None`.
+    Subscribe { since: Option<u64> },
+    /// The follower clears its data to receive a snapshot.
+    SnapshotStart,
+    SnapshotChunk(Vec<WalOperation>),
+    /// The snapshot is complete, the records follow from `next_sequence`.
+    SnapshotEnd { next_sequence: u64 },
+    Record(WalRecord),
+    /// Sent by the primary when there is no new record.
+    Heartbeat { latest_sequence: u64 },
+}
+
+impl ReplicationMessage {
+
+    pub fn to_document(&self) -> Document {
+        match self {
+            ReplicationMessage::Subscribe { since } => doc! {
+                "t": "subscribe",
+                "since": since.map(|since| Bson::Int64(since as i64)).unwrap_or(Bson::Null),
+            },
+            ReplicationMessage::SnapshotStart => doc! {
+                "t": "snapshotStart",
+            },
+            ReplicationMessage::SnapshotChunk(operations) => doc! {
+                "t": "snapshotChunk",
+                "ops": operations_to_bson(operations),
+            },
+            ReplicationMessage::SnapshotEnd { next_sequence } => doc! {
+                "t": "snapshotEnd",
+                "next": *next_sequence as i64,
+            },
+            ReplicationMessage::Record(record) => doc! {
+                "t": "record",
+                "seq": record.sequence as i64,
+                "ops": operations_to_bson(&record.operations),
+            },
+            ReplicationMessage::Heartbeat { latest_sequence } => doc! {
+                "t": "heartbeat",
+                "latest": *latest_sequence as i64,
+            },
+        }
+    }
+
+    pub fn from_document(doc: &Document) -> Result<ReplicationMessage> {
+        let ty = doc.get_str("t").map_err(|_| malformed_message())?;
+        let message = match ty {
+            "subscribe" => {
+                let since = match doc.get("since") {
+                    Some(Bson::Int64(since)) => Some(*since as u64),
+                    Some(Bson::Null) | None => None,
+                    Some(_) => return Err(malformed_message()),
+                };
+                ReplicationMessage::Subscribe { since }
+            }
+            "snapshotStart" => ReplicationMessage::SnapshotStart,
+            "snapshotChunk" => ReplicationMessage::SnapshotChunk(operations_from_bson(doc)?),
+            "snapshotEnd" => ReplicationMessage::SnapshotEnd {
+                next_sequence: get_sequence(doc, "next")?,
+            },
+            "record" => ReplicationMessage::Record(WalRecord {
+                sequence: get_sequence(doc, "seq")?,
+                operations: operations_from_bson(doc)?,
+            }),
+            "heartbeat" => ReplicationMessage::Heartbeat {
+                latest_sequence: get_sequence(doc, "latest")?,
+            },
+            _ => return Err(malformed_message()),
+        };
+        Ok(message)
+    }
+
+}
+
+fn malformed_message() -> Error {
+    Error::ReplicationError("malformed message".to_string())
+}
+
+fn get_sequence(doc: &Document, key: &str) -> Result<u64> {
+    doc.get_i64(key).map(|sequence| sequence as u64).map_err(|_| malformed_message())
+}
+
+fn binary(bytes: &[u8]) -> Bson {
+    Bson::Binary(Binary {
+        subtype: BinarySubtype::Generic,
+        bytes: bytes.to_vec(),
+    })
+}
+
+fn operations_to_bson(operations: &[WalOperation]) -> Bson {
+    let operations = operations.iter().map(|op| {
+        let doc = match op {
+            WalOperation::Put { key, value } => doc! { "k": binary(key), "v": binary(value) },
+            WalOperation::Delete { key } => doc! { "k": binary(key) },
+            WalOperation::DeleteRange { start, end } => doc! { "k": binary(start), "e": binary(end) },
+        };
+        Bson::Document(doc)
+    }).collect();
+    Bson::Array(operations)
+}
+
+fn operations_from_bson(doc: &Document) -> Result<Vec<WalOperation>> {
+    let operations = doc.get_array("ops").map_err(|_| malformed_message())?;
+    operations.iter().map(|op| {
+        let op = op.as_document().ok_or_else(malformed_message)?;
+        let key = op.get_binary_generic("k").map_err(|_| malformed_message())?.clone();
+        let op = match (op.get("v"), op.get("e")) {
+            (Some(Bson::Binary(value)), None) => WalOperation::Put { key, value: value.bytes.clone() },
+            (None, Some(Bson::Binary(end))) => WalOperation::DeleteRange { start: key, end: end.bytes.clone() },
+            (None, None) => WalOperation::Delete { key },
+            _ => return Err(malformed_message()),
+        };
+        Ok(op)
+    }).collect()
+}
+
+/// The connection between the primary and a follower.
+pub trait ReplicationTransport: Send {
+
+    fn send(&mut self, message: &ReplicationMessage) -> Result<()>;
+
+    /// Wait for the next message, `None` if the peer has closed the connection.
+    fn receive(&mut self) -> Result<Option<ReplicationMessage>>;
+
+}
+
+/// Send the messages over a byte stream, e.g. a [`TcpStream`],
+/// as the BSON documents of [`ReplicationMessage::to_document`].
+pub struct StreamTransport<S> {
+    stream: S,
+}
+
+impl<S: Read + Write + Send> StreamTransport<S> {
+
+    pub fn new(stream: S) -> StreamTransport<S> {
+        StreamTransport { stream }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+}
+
+impl<S: Read + Write + Send> ReplicationTransport for StreamTransport<S> {
+
+    fn send(&mut self, message: &ReplicationMessage) -> Result<()> {
+        let bytes = bson::to_vec(&message.to_document())?;
+        self.stream.write_all(&bytes)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<ReplicationMessage>> {
+        let mut len_buf = [0u8; 4];
+        match self.stream.read_exact(&mut len_buf) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
+        let len = i32::from_le_bytes(len_buf);
+        if len < 5 || len as usize > MAX_MESSAGE_SIZE {
+            return Err(Error::ReplicationError(format!("invalid message size: {}", len)));
+        }
+        let mut buf = vec![0u8; len as usize];
+        buf[..4].copy_from_slice(&len_buf);
+        self.stream.read_exact(&mut buf[4..])?;
+        let doc = Document::from_reader(buf.as_slice())?;
+        ReplicationMessage::from_document(&doc).map(Some)
+    }
+
+}
+
+/// The key storing the sequence of the primary to apply next on a follower.
+fn applied_key() -> Result<Vec<u8>> {
+    stacked_key(&[
+        Bson::String(REPLICATION_PREFIX.to_string()),
+        Bson::String("applied".to_string()),
+    ])
+}
+
+/// The keys of the replication state are not replicated.
+fn is_replication_key(prefix: &[u8], op: &WalOperation) -> bool {
+    let key = match op {
+        WalOperation::Put { key, .. } => key,
+        WalOperation::Delete { key } => key,
+        WalOperation::DeleteRange { start, .. } => start,
+    };
+    key.starts_with(prefix)
+}
+
+fn replication_prefix() -> Result<Vec<u8>> {
+    stacked_key(&[Bson::String(REPLICATION_PREFIX.to_string())])
+}
+
+enum Shipped {
+    Records(u64),
+    Nothing,
+    /// The records from the requested sequence are no longer in the WAL.
+    Gap,
+}
+
+/// Serve a follower on the primary until the follower disconnects
+/// or the token is cancelled.
+pub fn serve_follower<T: ReplicationTransport>(db: &Database, mut transport: T, token: &CancellationToken) -> Result<()> {
+    let since = match transport.receive()? {
+        Some(ReplicationMessage::Subscribe { since }) => since,
+        Some(_) => return Err(Error::ReplicationError("expected a subscribe message".to_string())),
+        None => return Ok(()),
+    };
+    let prefix = replication_prefix()?;
+    let (mut next_sequence, mut after_snapshot) = match since {
+        Some(since) => (since, false),
+        None => (send_snapshot(db, &mut transport, &prefix)?, true),
+    };
+
+    while !token.is_cancelled() {
+        match ship_records(db, &mut transport, next_sequence)? {
+            Shipped::Records(next) => {
+                next_sequence = next;
+                after_snapshot = false;
+            }
+            Shipped::Nothing => {
+                let latest_sequence = db.latest_sequence_number()?;
+                transport.send(&ReplicationMessage::Heartbeat { latest_sequence })?;
+                thread::sleep(POLL_INTERVAL);
+            }
+            Shipped::Gap if after_snapshot => {
+                return Err(Error::ReplicationError("the records after the snapshot are not in the WAL".to_string()));
+            }
+            Shipped::Gap => {
+                next_sequence = send_snapshot(db, &mut transport, &prefix)?;
+                after_snapshot = true;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn send_snapshot<T: ReplicationTransport>(db: &Database, transport: &mut T, prefix: &[u8]) -> Result<u64> {
+    // The snapshot may contain the writes after this sequence,
+    // they're applied again by the follower to the same result.
+    let next_sequence = db.latest_sequence_number()? + 1;
+    let txn = db.start_transaction()?;
+    let iter = txn.inner().rocksdb_txn.new_iterator();
+
+    transport.send(&ReplicationMessage::SnapshotStart)?;
+    let mut chunk = Vec::with_capacity(SNAPSHOT_CHUNK_SIZE);
+    iter.seek_to_first();
+    while iter.valid() {
+        let op = WalOperation::Put {
+            key: iter.copy_key()?,
+            value: iter.copy_data()?,
+        };
+        if !is_replication_key(prefix, &op) {
+            chunk.push(op);
+        }
+        if chunk.len() == SNAPSHOT_CHUNK_SIZE {
+            transport.send(&ReplicationMessage::SnapshotChunk(std::mem::take(&mut chunk)))?;
+        }
+        iter.next();
+    }
+    iter.error()?;
+    if !chunk.is_empty() {
+        transport.send(&ReplicationMessage::SnapshotChunk(chunk))?;
+    }
+    transport.send(&ReplicationMessage::SnapshotEnd { next_sequence })?;
+
+    Ok(next_sequence)
+}
+
+fn ship_records<T: ReplicationTransport>(db: &Database, transport: &mut T, since: u64) -> Result<Shipped> {
+    let mut send_error = None;
+    let mut count = 0;
+    let mut gap = false;
+    let result = db.ship_wal(since, |record| {
+        if count == 0 && record.sequence > since {
+            gap = true;
+            return false;
+        }
+        // the follower skips the keys of its replication state, the record is sent
+        // whole so its next sequence is the one of the primary
+        match transport.send(&ReplicationMessage::Record(record.clone())) {
+            Ok(()) => {
+                count += 1;
+                true
+            }
+            Err(err) => {
+                send_error = Some(err);
+                false
+            }
+        }
+    });
+    if let Some(err) = send_error {
+        return Err(err);
+    }
+    if gap {
+        return Ok(Shipped::Gap);
+    }
+    match result {
+        Ok(next_sequence) if count > 0 => Ok(Shipped::Records(next_sequence)),
+        Ok(_) => Ok(Shipped::Nothing),
+        // the WAL can't be read from the sequence, e.g. the files are deleted
+        Err(_) => Ok(Shipped::Gap),
+    }
+}
+
+/// Serve the followers of a primary over TCP.
+pub struct ReplicationServer {
+    db: Arc<Database>,
+    listener: TcpListener,
+}
+
+impl ReplicationServer {
+
+    /// Bind the server to `addr`. Pass an `Arc<Database>` to keep
+    /// writing to the primary while it's served.
+    pub fn bind<D: Into<Arc<Database>>, A: ToSocketAddrs>(db: D, addr: A) -> Result<ReplicationServer> {
+        let listener = TcpListener::bind(addr)?;
+        Ok(ReplicationServer {
+            db: db.into(),
+            listener,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Serve the followers in the current thread, each follower in its own thread.
+    pub fn run(self) -> Result<()> {
+        self.serve(&CancellationToken::new())
+    }
+
+    /// Serve the followers in a background thread.
+    pub fn spawn(self) -> Result<ReplicationServerHandle> {
+        let addr = self.local_addr()?;
+        let token = CancellationToken::new();
+        let thread_token = token.clone();
+        let thread = thread::Builder::new()
+            .name("polodb-replication".to_string())
+            .spawn(move || self.serve(&thread_token))?;
+        Ok(ReplicationServerHandle {
+            addr,
+            token,
+            thread: Some(thread),
+        })
+    }
+
+    fn serve(&self, token: &CancellationToken) -> Result<()> {
+        for stream in self.listener.incoming() {
+            if token.is_cancelled() {
+                break;
+            }
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let db = self.db.clone();
+            let token = token.clone();
+            thread::spawn(move || {
+                let _ = stream.set_nodelay(true);
+                // the follower reconnects if the connection fails
+                let _ = serve_follower(&db, StreamTransport::new(stream), &token);
+            });
+        }
+        Ok(())
+    }
+
+}
+
+pub struct ReplicationServerHandle {
+    addr: SocketAddr,
+    token: CancellationToken,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl ReplicationServerHandle {
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stop accepting the followers and disconnect the connected ones.
+    pub fn shutdown(mut self) -> Result<()> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        let thread = match self.thread.take() {
+            Some(thread) => thread,
+            None => return Ok(()),
+        };
+        self.token.cancel();
+        // wake up the accept
+        let _ = TcpStream::connect(self.addr);
+        thread.join().map_err(|_| Error::ReplicationError("the server thread panicked".to_string()))?
+    }
+
+}
+
+impl Drop for ReplicationServerHandle {
+
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+
+}
+
+/// The state of a [`Follower`].
+#[derive(Debug, Clone, Default)]
+pub struct FollowerStatus {
+    /// The sequence of the primary to apply next, `None` until a snapshot is applied.
+    pub next_sequence: Option<u64>,
+    /// The latest sequence of the primary the follower knows of.
+    pub primary_sequence: Option<u64>,
+    pub connected: bool,
+    /// All the writes of the primary up to `primary_sequence` are applied.
+    pub caught_up: bool,
+    /// The error ending the last connection to the primary.
+    pub last_error: Option<String>,
+}
+
+#[derive(Default)]
+struct FollowerShared {
+    status: Mutex<FollowerStatus>,
+    changed: Condvar,
+}
+
+impl FollowerShared {
+
+    fn update<F: FnOnce(&mut FollowerStatus)>(&self, f: F) {
+        let mut status = self.status.lock().unwrap();
+        f(&mut status);
+        status.caught_up = match (status.next_sequence, status.primary_sequence) {
+            (Some(next_sequence), Some(primary_sequence)) => next_sequence > primary_sequence,
+            _ => false,
+        };
+        self.changed.notify_all();
+    }
+
+}
+
+/// A follower of the primary, applying its writes to a read-only database.
+///
+/// The follower reconnects to the primary when the connection fails, and
+/// resumes from the last applied write, also after the database is reopened.
+/// The readers see an empty database while a snapshot is being applied.
+pub struct Follower {
+    db: Arc<Database>,
+    shared: Arc<FollowerShared>,
+    token: CancellationToken,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Follower {
+
+    /// Follow the primary served by a [`ReplicationServer`] at `addr`.
+    pub fn connect<D: Into<Arc<Database>>, A: ToSocketAddrs>(db: D, addr: A) -> Result<Follower> {
+        let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+        Follower::start(db, move || {
+            let mut last_error = None;
+            for addr in &addrs {
+                match TcpStream::connect_timeout(addr, CONNECT_TIMEOUT) {
+                    Ok(stream) => {
+                        stream.set_nodelay(true)?;
+                        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+                        return Ok(StreamTransport::new(stream));
+                    }
+                    Err(err) => last_error = Some(err),
+                }
+            }
+            Err(match last_error {
+                Some(err) => err.into(),
+                None => Error::ReplicationError("no address to connect to".to_string()),
+            })
+        })
+    }
+
+    /// Follow the primary with the transports returned by `connect`,
+    /// which is called again to reconnect.
+    pub fn start<D, F, T>(db: D, mut connect: F) -> Result<Follower>
+    where
+        D: Into<Arc<Database>>,
+        F: FnMut() -> Result<T> + Send + 'static,
+        T: ReplicationTransport + 'static,
+    {
+        let db = db.into();
+        db.set_replica(true)?;
+        let shared = Arc::new(FollowerShared::default());
+        let next_sequence = read_next_sequence(&db)?;
+        shared.update(|status| status.next_sequence = next_sequence);
+
+        let token = CancellationToken::new();
+        let thread = {
+            let db = db.clone();
+            let shared = shared.clone();
+            let token = token.clone();
+            thread::Builder::new()
+                .name("polodb-follower".to_string())
+                .spawn(move || {
+                    while !token.is_cancelled() {
+                        let result = connect().and_then(|transport| {
+                            shared.update(|status| status.connected = true);
+                            follow(&db, transport, &shared, &token)
+                        });
+                        shared.update(|status| {
+                            status.connected = false;
+                            if let Err(err) = result {
+                                status.last_error = Some(err.to_string());
+                            }
+                        });
+                        sleep_unless_cancelled(&token, RECONNECT_INTERVAL);
+                    }
+                })?
+        };
+
+        Ok(Follower {
+            db,
+            shared,
+            token,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn database(&self) -> &Arc<Database> {
+        &self.db
+    }
+
+    pub fn status(&self) -> FollowerStatus {
+        self.shared.status.lock().unwrap().clone()
+    }
+
+    /// Wait until the writes of the primary up to `sequence` are applied,
+    /// `sequence` is usually [`Database::latest_sequence_number`] of the primary.
+    /// Return `false` on timeout.
+    pub fn wait_for(&self, sequence: u64, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut status = self.shared.status.lock().unwrap();
+        loop {
+            if matches!(status.next_sequence, Some(next_sequence) if next_sequence > sequence) {
+                return true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            status = self.shared.changed.wait_timeout(status, deadline - now).unwrap().0;
+        }
+    }
+
+    /// Stop following the primary and make the database writable.
+    pub fn promote(mut self) -> Result<Arc<Database>> {
+        self.stop();
+        self.db.set_replica(false)?;
+        Ok(self.db.clone())
+    }
+
+    fn stop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.token.cancel();
+            let _ = thread.join();
+        }
+    }
+
+}
+
+impl Drop for Follower {
+
+    fn drop(&mut self) {
+        self.stop();
+    }
+
+}
+
+fn sleep_unless_cancelled(token: &CancellationToken, duration: Duration) {
+    let deadline = Instant::now() + duration;
+    while !token.is_cancelled() && Instant::now() < deadline {
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn read_next_sequence(db: &Database) -> Result<Option<u64>> {
+    let txn = db.start_transaction()?;
+    let value = txn.inner().rocksdb_txn.get(&applied_key()?)?;
+    let next_sequence = match value {
+        Some(value) => {
+            let bytes = <[u8; 8]>::try_from(value.as_slice())
+                .map_err(|_| Error::ReplicationError("invalid replication state".to_string()))?;
+            Some(u64::from_be_bytes(bytes))
+        }
+        None => None,
+    };
+    Ok(next_sequence)
+}
+
+fn follow<T: ReplicationTransport>(
+    db: &Database,
+    mut transport: T,
+    shared: &FollowerShared,
+    token: &CancellationToken,
+) -> Result<()> {
+    let prefix = replication_prefix()?;
+    let applied_key = applied_key()?;
+    let since = read_next_sequence(db)?;
+    transport.send(&ReplicationMessage::Subscribe { since })?;
+
+    while !token.is_cancelled() {
+        let message = match transport.receive()? {
+            Some(message) => message,
+            None => return Ok(()),
+        };
+        match message {
+            ReplicationMessage::SnapshotStart => {
+                clear_database(db)?;
+                shared.update(|status| status.next_sequence = None);
+            }
+            ReplicationMessage::SnapshotChunk(operations) => {
+                let operations: Vec<WalOperation> = operations.into_iter()
+                    .filter(|op| !is_replication_key(&prefix, op))
+                    .collect();
+                db.write_replicated(&operations)?;
+            }
+            ReplicationMessage::SnapshotEnd { next_sequence } => {
+                db.write_replicated(&[applied_operation(&applied_key, next_sequence)])?;
+                shared.update(|status| status.next_sequence = Some(next_sequence));
+            }
+            ReplicationMessage::Record(record) => {
+                let next_sequence = record.next_sequence();
+                let mut operations: Vec<WalOperation> = record.operations.into_iter()
+                    .filter(|op| !is_replication_key(&prefix, op))
+                    .collect();
+                // the record and the replication state are written atomically
+                operations.push(applied_operation(&applied_key, next_sequence));
+                db.write_replicated(&operations)?;
+                shared.update(|status| {
+                    status.next_sequence = Some(next_sequence);
+                    status.primary_sequence = Some(status.primary_sequence.unwrap_or(0).max(next_sequence - 1));
+                });
+            }
+            ReplicationMessage::Heartbeat { latest_sequence } => {
+                shared.update(|status| status.primary_sequence = Some(latest_sequence));
+            }
+            ReplicationMessage::Subscribe { .. } => {
+                return Err(Error::ReplicationError("unexpected subscribe message".to_string()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn applied_operation(applied_key: &[u8], next_sequence: u64) -> WalOperation {
+    WalOperation::Put {
+        key: applied_key.to_vec(),
+        value: next_sequence.to_be_bytes().to_vec(),
+    }
+}
+
+/// Delete all the keys before applying a snapshot, including the replication
+/// state, so a follower interrupted during the snapshot receives a new one.
+fn clear_database(db: &Database) -> Result<()> {
+    let txn = db.start_transaction()?;
+    let iter = txn.inner().rocksdb_txn.new_iterator();
+    let mut chunk = Vec::with_capacity(SNAPSHOT_CHUNK_SIZE);
+    iter.seek_to_first();
+    while iter.valid() {
+        chunk.push(WalOperation::Delete { key: iter.copy_key()? });
+        if chunk.len() == SNAPSHOT_CHUNK_SIZE {
+            db.write_replicated(&std::mem::take(&mut chunk))?;
+        }
+        iter.next();
+    }
+    iter.error()?;
+    if !chunk.is_empty() {
+        db.write_replicated(&chunk)?;
+    }
+    Ok(())
+}
@@ -13,9 +13,10 @@
 // limitations under the License.
 
 use std::collections::{HashMap};
-use crate::bson::Bson;
-use serde::{Serialize, Serializer};
+use crate::bson::{Bson, DateTime, Document};
+use serde::{Deserialize, Serialize, Serializer};
 use serde::ser::SerializeMap;
+use crate::options::CreateCollectionOptions;
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -106,3 +107,602 @@ mod tests {
     }
 
 }
+
+/// What was discarded when the database was opened after a crash,
+/// see [`crate::Database::recovery_summary`].
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoverySummary {
+    /// The sequence number of the last commit which is recovered.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub last_sequence: u64,
+    /// The write-ahead log files which are not replayed to the end.
+    pub torn_wal_files: Vec<String>,
+    /// The commits which are readable but not replayed,
+    /// because they are written after a torn record.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub discarded_commits: u64,
+    /// The bytes of the write-ahead logs after the last recovered commit.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub discarded_wal_bytes: u64,
+    /// The table files which are not in the manifest, e.g. written by an interrupted flush.
+    /// They are deleted on opening.
+    pub orphan_table_files: Vec<String>,
+    /// The errors of the records where the replay stops.
+    pub errors: Vec<String>,
+}
+
+impl RecoverySummary {
+
+    /// Whether nothing was discarded, e.g. the database was closed cleanly.
+    pub fn is_clean(&self) -> bool {
+        self.torn_wal_files.is_empty() && self.orphan_table_files.is_empty() && self.errors.is_empty()
+    }
+
+}
+
+/// The report of [`crate::Database::open_repair`].
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairReport {
+    /// The files which can not be read, they are moved to the `lost` directory
+    /// of the repaired database.
+    pub lost_files: Vec<String>,
+    /// The number of documents recovered into the repaired database.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub recovered_count: u64,
+    /// The documents which can not be decoded, they are not recovered.
+    pub skipped_documents: Vec<SkippedDocument>,
+    /// The errors encountered while scanning the collections.
+    pub errors: Vec<String>,
+}
+
+/// A document skipped by [`crate::Database::open_repair`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedDocument {
+    pub collection: String,
+    /// The key of the document, decoded like the keys of [`crate::Database::inspect`].
+    pub key: String,
+    pub error: String,
+}
+
+/// A backup created by [`crate::Database::backup_incremental`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupInfo {
+    pub backup_id: u32,
+    /// The creation time in seconds since the Unix epoch.
+    pub timestamp: i64,
+    /// The total size of the files of the backup in bytes.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub size: u64,
+    pub number_files: u32,
+}
+
+/// The state of the block cache, returned by [`crate::Database::block_cache_stats`].
+///
+/// The counters are accumulated since the database is opened,
+/// while the usage is of the whole cache, which may be shared by other databases.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockCacheStats {
+    /// The budget of the cache in bytes.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub capacity: u64,
+    /// The bytes used by the blocks in the cache.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub usage: u64,
+    /// The bytes used by the blocks which are being read.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub pinned_usage: u64,
+    /// The number of blocks in the cache.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub entries: u64,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub hits: u64,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub misses: u64,
+    /// The number of blocks added to the cache.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub adds: u64,
+    /// The number of blocks added but no longer in the cache,
+    /// i.e. evicted or dropped with the obsolete table files.
+    /// RocksDB doesn't count the evictions, so it's derived from
+    /// the adds and the entries and is only accurate for an unshared cache.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub evictions: u64,
+}
+
+/// The result of [`crate::Database::vacuum`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VacuumResult {
+    /// The size of the database files in bytes before vacuuming.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub size_before: u64,
+    /// The size of the database files in bytes after vacuuming.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub size_after: u64,
+}
+
+/// The result of dropping a collection or a database.
+///
+/// The deleted keys still take space until they are compacted,
+/// e.g. by [`crate::Database::vacuum`].
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DropResult {
+    /// The number of stored documents removed.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub deleted_count: u64,
+    /// The number of index entries removed.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub deleted_index_entries: u64,
+    /// The bytes of the keys and values removed,
+    /// which are reclaimed by the next compaction.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub reclaimable_bytes: u64,
+}
+
+/// A collection listed by [`crate::Database::list_collections`].
+#[derive(Debug, Clone)]
+pub struct CollectionInfo {
+    pub name: String,
+    pub create_at: bson::DateTime,
+    /// The options the collection is created with.
+    pub options: CreateCollectionOptions,
+    pub index_names: Vec<String>,
+    /// The approximate number of the stored documents.
+    /// The measurements of a time-series collection are stored in buckets,
+    /// so the buckets are counted.
+    pub approximate_count: u64,
+    /// The approximate size in bytes of the table files of the documents and the indexes.
+    /// The recent writes are not counted until they are flushed from the memtable.
+    pub approximate_size: u64,
+}
+
+/// The statistics of a collection, returned by [`crate::CollectionT::stats`].
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionStats {
+    /// The number of stored documents, i.e. the buckets of a time-series collection.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub count: u64,
+    /// The total size in bytes of the BSON documents.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub size: u64,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub avg_obj_size: u64,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub index_count: u64,
+    /// The size in bytes of the entries of each index.
+    pub index_sizes: HashMap<String, u64>,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub total_index_size: u64,
+    /// The estimated number of the deleted keys of the collection which are not compacted yet,
+    /// counted from the tombstones skipped while scanning the collection.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub tombstones: u64,
+}
+
+/// The distribution of the values of an index, refreshed by [`crate::CollectionT::analyze`]
+/// and [`crate::Database::vacuum`]. The planner estimates the entries matching a value from it
+/// to choose between the indexes of a query.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexStats {
+    /// The number of the entries in the index.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub count: u64,
+    /// The number of the distinct values.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub distinct: u64,
+    /// The buckets of about the same number of entries, in the order of the index.
+    pub histogram: Vec<HistogramBucket>,
+    /// The time the statistics were computed.
+    pub updated_at: DateTime,
+}
+
+/// The entries of an [`IndexStats`] from the upper bound of the previous bucket
+/// (exclusive) to `upper` (inclusive).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistogramBucket {
+    pub upper: Bson,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub count: u64,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub distinct: u64,
+    /// The number of the entries equal to `upper`.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub upper_count: u64,
+}
+
+/// The storage statistics of the database, returned by [`crate::Database::stats`].
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageStats {
+    /// The table files of each level, from level 0.
+    pub levels: Vec<LevelStats>,
+    /// The size in bytes of the live write-ahead log files.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub wal_size: u64,
+    /// The memory in bytes used by the memtables which are not flushed yet.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub memtable_usage: u64,
+    /// The estimated bytes to rewrite by the compaction
+    /// to bring all the levels under their target sizes.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub pending_compaction_bytes: u64,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub running_compactions: u64,
+    /// The snapshots read by the open cursors.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub snapshot_count: u64,
+    /// The unix time in seconds when the oldest snapshot was taken, 0 without a snapshot.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub oldest_snapshot_time: u64,
+    /// The size in bytes of the table files replaced by the compaction,
+    /// which are kept until the cursors reading them are dropped.
+    /// The old versions of the keys kept in the live files for the snapshots are not counted.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub pinned_size: u64,
+    /// The total size in bytes of the files of the database, including the cold path,
+    /// excluding the snapshots. It's 0 for the in-memory databases.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub disk_size: u64,
+}
+
+/// The table files of a level of the LSM tree.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LevelStats {
+    pub level: u32,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub file_count: u64,
+    /// The size in bytes of the table files.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub size: u64,
+}
+
+/// The estimated fragmentation of the storage, returned by [`crate::Database::fragmentation`].
+///
+/// The dead bytes are the tombstones and the old versions of the keys which are
+/// shadowed by the newer writes. They're estimated from the sizes of the keys and
+/// the values in the table files against the sizes of the live ones.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FragmentationReport {
+    /// The table files of each level, from level 0.
+    pub levels: Vec<LevelFragmentation>,
+    /// The count of the live keys.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub live_entries: u64,
+    /// The size in bytes of the table files.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub size: u64,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub live_size: u64,
+    /// The bytes released by a full compaction, see [`crate::Database::vacuum`].
+    /// The versions read by the open cursors are still kept by the compaction.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub reclaimable_size: u64,
+}
+
+/// The fragmentation of a level of the LSM tree.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LevelFragmentation {
+    pub level: u32,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub file_count: u64,
+    /// The size in bytes of the table files.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub size: u64,
+    /// The count of the entries, including the tombstones.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub entries: u64,
+    /// The count of the tombstones.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub deletions: u64,
+    /// The ratio of the tombstones to the entries, from 0 to 1.
+    pub tombstone_ratio: f64,
+    /// The ratio of the bytes in the files overlapping the key ranges of the newer files,
+    /// i.e. the files of the upper levels or the other files of level 0, from 0 to 1.
+    pub overlap_ratio: f64,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub live_size: u64,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub dead_size: u64,
+}
+
+/// A slow query recorded by the profiler, returned by [`crate::Database::profile_entries`].
+/// See [`crate::ConfigBuilder::set_slow_query_threshold`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileEntry {
+    /// The operation, such as `find`, `aggregate`, `update` or `delete`.
+    pub op: String,
+    pub collection: String,
+    /// The shape of the filter, the values are replaced by `1`.
+    /// The stages of an aggregation are in the `pipeline` field.
+    pub filter: Document,
+    /// `IDHACK` for the lookups of the primary key, `IXSCAN <index name>` for the index scans,
+    /// `COLLSCAN` for the full scans and `EOF` if the collection doesn't exist.
+    pub plan: String,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub duration_micros: u64,
+    /// The count of the documents read from the storage.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub docs_examined: u64,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub docs_returned: u64,
+    /// The time the query finished.
+    pub ts: DateTime,
+}
+
+/// A change of a document, returned by [`crate::Database::ship_changes`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeEvent {
+    /// The sequence number of the commit record of the change.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub sequence: u64,
+    pub collection: String,
+    pub id: Bson,
+    /// The document before the change, `None` if the document is inserted
+    /// or [`crate::ConfigBuilder::set_change_pre_images`] is not enabled.
+    pub before: Option<Document>,
+    /// The document after the change, `None` if the document is deleted.
+    pub after: Option<Document>,
+}
+
+/// An operation in flight, returned by [`crate::Database::current_ops`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CurrentOp {
+    /// The id to cancel the operation by [`crate::Database::kill_op`].
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub id: u64,
+    /// The operation, such as `find`, `aggregate`, `update` or `delete`.
+    pub op: String,
+    pub collection: String,
+    /// The shape of the filter, the values are replaced by `1`.
+    pub filter: Document,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub elapsed_micros: u64,
+    /// The operation is cancelled, but it hasn't stopped yet.
+    pub cancelled: bool,
+}
+
+/// The result of [`crate::Database::migrate`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrateResult {
+    /// The schema version before the migration.
+    pub previous_version: u32,
+    /// The schema version after the migration.
+    pub version: u32,
+    /// The names of the applied migrations in order.
+    pub applied: Vec<String>,
+}
+
+/// The result of [`crate::Database::upgrade`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpgradeResult {
+    /// The format version before the upgrade.
+    pub previous_version: u32,
+    /// The format version after the upgrade, [`crate::FORMAT_VERSION`] unless the database is a replica.
+    pub version: u32,
+    /// The names of the applied upgrades in order.
+    pub applied: Vec<String>,
+}
+
+/// The result of [`crate::Database::dump`].
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DumpResult {
+    /// The number of the dumped collections and views.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub collections: u64,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub documents: u64,
+}
+
+/// The result of [`crate::Database::restore`].
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreResult {
+    /// The number of the restored collections and views.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub collections: u64,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub documents: u64,
+    /// The number of the restored indexes, without the `_id_` index.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub indexes: u64,
+    /// The indexes not supported by PoloDB, e.g. the compound and the text indexes,
+    /// in the form of `<collection>.<index name>`.
+    pub skipped_indexes: Vec<String>,
+}
+
+/// The result of [`crate::Database::import_metadata`].
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportMetadataResult {
+    /// The collections and views which didn't exist.
+    pub created_collections: Vec<String>,
+    /// The collections whose validator or default fields are changed, and the recreated views.
+    pub updated_collections: Vec<String>,
+    /// The created indexes in the form of `<collection>.<index name>`, including the recreated ones.
+    pub created_indexes: Vec<String>,
+    /// The indexes not in the metadata or recreated with another definition,
+    /// in the form of `<collection>.<index name>`.
+    pub dropped_indexes: Vec<String>,
+}
+
+/// What is physically stored in the files of a database, returned by [`crate::Database::inspect`].
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InspectReport {
+    pub manifest: ManifestInspection,
+    /// The sequence number of the latest write, including the writes only in the WAL.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub latest_sequence: u64,
+    /// The levels of the LSM tree, from level 0.
+    pub levels: Vec<LevelInspection>,
+    /// The write-ahead log files, including the archived ones, the oldest first.
+    pub wal_files: Vec<WalFileInspection>,
+}
+
+/// The manifest file, which records the changes of the table files.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestInspection {
+    /// The name of the current manifest file, read from `CURRENT`.
+    pub file: String,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub size: u64,
+    /// The decoded edits of the manifest, the oldest first.
+    pub edits: Vec<ManifestEdit>,
+    /// The errors of the records which can not be read, e.g. torn at the end of the file.
+    pub errors: Vec<String>,
+}
+
+/// A record of the manifest. Only the fields which are present in the record are set.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestEdit {
+    /// The offset of the record in the file.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub offset: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub db_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comparator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column_family: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_number: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_file_number: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_sequence: Option<u64>,
+    /// The table files added to the levels.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub new_files: Vec<ManifestFile>,
+    /// The table files removed from the levels, e.g. by a compaction.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub deleted_files: Vec<ManifestFile>,
+    /// The fields which are not decoded, the rest of the record is skipped after them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unknown_fields: Vec<u32>,
+}
+
+/// A table file added or removed by a [`ManifestEdit`].
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestFile {
+    pub level: u32,
+    /// The number of the file, the file name is `<number>.sst`.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub number: u64,
+    /// The size in bytes, not set for the deleted files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smallest_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub largest_key: Option<String>,
+}
+
+/// A level of the LSM tree with its live table files.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LevelInspection {
+    pub level: u32,
+    pub files: Vec<TableFileInspection>,
+    /// The sum of the properties of the table files of the level.
+    pub blocks: BlockStats,
+}
+
+/// A live table file.
+///
+/// The keys are printed as the stacked keys of PoloDB, e.g. `["users", 1]`,
+/// or in hexadecimal if they can not be decoded.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TableFileInspection {
+    pub name: String,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub size: u64,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub entries: u64,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub deletions: u64,
+    pub smallest_key: String,
+    pub largest_key: String,
+}
+
+/// The blocks of the table files.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockStats {
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub data_blocks: u64,
+    /// The size in bytes of the data blocks.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub data_size: u64,
+    /// The size in bytes of the index blocks.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub index_size: u64,
+    /// The size in bytes of the filter blocks.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub filter_size: u64,
+    /// The size in bytes of the keys before the compression.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub raw_key_size: u64,
+    /// The size in bytes of the values before the compression.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub raw_value_size: u64,
+}
+
+/// A write-ahead log file.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WalFileInspection {
+    /// The path of the file relative to the database, e.g. `000012.log` or `archive/000008.log`.
+    pub name: String,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub size: u64,
+    pub records: Vec<WalRecordInspection>,
+    /// The errors of the records which can not be read, e.g. torn at the end of the file.
+    pub errors: Vec<String>,
+}
+
+/// A commit recorded in a write-ahead log file.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WalRecordInspection {
+    /// The offset of the record in the file.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub offset: u64,
+    /// The sequence number of the first operation.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub sequence: u64,
+    pub operations: Vec<WalOperationInspection>,
+}
+
+/// A write of a [`WalRecordInspection`].
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WalOperationInspection {
+    /// `put`, `delete` or `deleteRange`, the key of a `deleteRange` is `<start>..<end>`.
+    pub op: String,
+    pub key: String,
+    /// The size in bytes of the value, 0 for the deletes.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub value_size: u64,
+}
@@ -0,0 +1,420 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! Import the tables of a SQLite database into the collections.
+//! It's enabled by the `sqlite` feature.
+//!
+//! [`crate::Database::import_sqlite`] creates a collection per table, named after the table,
+//! and inserts a document per row:
+//!
+//! | SQLite                                           | BSON                        |
+//! |--------------------------------------------------|-----------------------------|
+//! | `INTEGER`                                        | int32, or int64 if it's out of the range |
+//! | `INTEGER` of a column declared as `BOOLEAN`      | boolean for 0 and 1         |
+//! | `REAL`                                           | double                      |
+//! | `TEXT`                                           | string                      |
+//! | `TEXT` of a column declared as `DATE`, `DATETIME` or `TIMESTAMP` | date, if it's in ISO 8601 or the format of `CURRENT_TIMESTAMP` |
+//! | `BLOB`                                           | binary                      |
+//! | `NULL`                                           | the field is omitted        |
+//!
+//! The primary key of a single column is stored as `_id`, so importing the same file again
+//! replaces the documents. The other rows get a new ObjectId.
+//! The single column indexes of the tables are created in the collections.
+//!
+//! With [`SqliteImportOptions::embed_foreign_keys`], the value of a foreign key column is
+//! replaced by the referenced row, converted in the same way. Only one level is embedded,
+//! the foreign keys of the embedded row are kept as they are.
+//!
+//! ```rust
+//! use polodb_core::Database;
+//! use polodb_core::sqlite::SqliteImportOptions;
+//!
+//! # let path = std::env::temp_dir().join("sqlite-import-doc.sqlite");
+//! # let _ = std::fs::remove_file(&path);
+//! # let conn = rusqlite::Connection::open(&path).unwrap();
+//! # conn.execute_batch("
+//! #     CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT);
+//! #     CREATE TABLE books (id INTEGER PRIMARY KEY, title TEXT, author_id INTEGER REFERENCES authors(id));
+//! #     INSERT INTO authors VALUES (1, 'Liu Cixin');
+//! #     INSERT INTO books VALUES (1, 'The Three-Body Problem', 1);
+//! # ").unwrap();
+//! # drop(conn);
+//! let db = Database::open_memory().unwrap();
+//! let options = SqliteImportOptions::builder()
+//!     .embed_foreign_keys(true)
+//!     .build();
+//! let result = db.import_sqlite(&path, options).unwrap();
+//! assert_eq!(result.tables, 2);
+//! ```
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::path::Path;
+use bson::{doc, spec::BinarySubtype, Binary, Bson, DateTime, Document};
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{Connection, OpenFlags, Row};
+use serde::Serialize;
+use crate::{CollectionT, Database, Error, IndexModel, IndexOptions, Result};
+
+const INSERT_BATCH_SIZE: usize = 1000;
+
+/// Options of [`crate::Database::import_sqlite`].
+#[derive(Debug, Clone, Default)]
+pub struct SqliteImportOptions {
+    /// Only import these tables, all the tables by default.
+    pub tables: Option<Vec<String>>,
+
+    /// Replace the values of the foreign keys by the referenced rows, `false` by default.
+    pub embed_foreign_keys: Option<bool>,
+}
+
+impl SqliteImportOptions {
+    pub fn builder() -> SqliteImportOptionsBuilder {
+        SqliteImportOptionsBuilder::default()
+    }
+}
+
+#[derive(Default)]
+pub struct SqliteImportOptionsBuilder {
+    tables: Option<Vec<String>>,
+    embed_foreign_keys: Option<bool>,
+}
+
+impl SqliteImportOptionsBuilder {
+    pub fn tables<T: Into<String>>(mut self, tables: impl IntoIterator<Item = T>) -> Self {
+        self.tables = Some(tables.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn embed_foreign_keys(mut self, embed_foreign_keys: bool) -> Self {
+        self.embed_foreign_keys = Some(embed_foreign_keys);
+        self
+    }
+
+    pub fn build(self) -> SqliteImportOptions {
+        SqliteImportOptions {
+            tables: self.tables,
+            embed_foreign_keys: self.embed_foreign_keys,
+        }
+    }
+}
+
+/// The result of [`crate::Database::import_sqlite`].
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SqliteImportResult {
+    /// The number of the imported tables.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub tables: u64,
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub documents: u64,
+    /// The number of the created indexes.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub indexes: u64,
+}
+
+fn sqlite_error(err: rusqlite::Error) -> Error {
+    Error::SqliteError(err.to_string())
+}
+
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+struct Column {
+    name: String,
+    declared_type: String,
+}
+
+/// The columns of a table, and how they're converted.
+struct TableInfo {
+    name: String,
+    columns: Vec<Column>,
+    /// The index of the column of the primary key, if it's a single column.
+    primary_key: Option<usize>,
+}
+
+impl TableInfo {
+
+    fn read(conn: &Connection, name: &str) -> Result<TableInfo> {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", quote(name))).map_err(sqlite_error)?;
+        let mut columns = Vec::new();
+        let mut primary_keys = Vec::new();
+        let mut rows = stmt.query([]).map_err(sqlite_error)?;
+        while let Some(row) = rows.next().map_err(sqlite_error)? {
+            let column_name: String = row.get("name").map_err(sqlite_error)?;
+            let declared_type: String = row.get::<_, Option<String>>("type").map_err(sqlite_error)?.unwrap_or_default();
+            let pk: i64 = row.get("pk").map_err(sqlite_error)?;
+            if pk > 0 {
+                primary_keys.push(columns.len());
+            }
+            columns.push(Column {
+                name: column_name,
+                declared_type: declared_type.to_ascii_uppercase(),
+            });
+        }
+        let primary_key = if primary_keys.len() == 1 {
+            Some(primary_keys[0])
+        } else {
+            None
+        };
+        Ok(TableInfo {
+            name: name.to_string(),
+            columns,
+            primary_key,
+        })
+    }
+
+    fn field_name(&self, index: usize) -> &str {
+        if self.primary_key == Some(index) {
+            "_id"
+        } else {
+            &self.columns[index].name
+        }
+    }
+
+    fn row_to_document(&self, row: &Row) -> Result<Document> {
+        let mut doc = Document::new();
+        for (index, column) in self.columns.iter().enumerate() {
+            let value = row.get_ref(index).map_err(sqlite_error)?;
+            if let Some(value) = convert_value(value, &column.declared_type) {
+                doc.insert(self.field_name(index), value);
+            }
+        }
+        Ok(doc)
+    }
+
+}
+
+fn convert_value(value: ValueRef, declared_type: &str) -> Option<Bson> {
+    let result = match value {
+        ValueRef::Null => return None,
+        ValueRef::Integer(value) => {
+            if declared_type.contains("BOOL") && (value == 0 || value == 1) {
+                Bson::Boolean(value == 1)
+            } else if let Ok(value) = i32::try_from(value) {
+                Bson::Int32(value)
+            } else {
+                Bson::Int64(value)
+            }
+        }
+        ValueRef::Real(value) => Bson::Double(value),
+        ValueRef::Text(text) => {
+            let text = String::from_utf8_lossy(text).into_owned();
+            if declared_type.contains("DATE") || declared_type.contains("TIME") {
+                if let Some(date) = parse_date(&text) {
+                    return Some(Bson::DateTime(date));
+                }
+            }
+            Bson::String(text)
+        }
+        ValueRef::Blob(bytes) => Bson::Binary(Binary {
+            subtype: BinarySubtype::Generic,
+            bytes: bytes.to_vec(),
+        }),
+    };
+    Some(result)
+}
+
+/// Parse the dates in ISO 8601, e.g. `2024-01-02T03:04:05Z`,
+/// or in the format of `CURRENT_TIMESTAMP` in UTC, e.g. `2024-01-02 03:04:05`.
+fn parse_date(text: &str) -> Option<DateTime> {
+    if let Ok(date) = DateTime::parse_rfc3339_str(text) {
+        return Some(date);
+    }
+    let text = if text.len() == 10 {
+        format!("{}T00:00:00Z", text)
+    } else {
+        format!("{}Z", text.replacen(' ', "T", 1))
+    };
+    DateTime::parse_rfc3339_str(text).ok()
+}
+
+/// A foreign key of a single column, whose value is replaced by the referenced row.
+struct ForeignKey {
+    column: usize,
+    table: TableInfo,
+    sql: String,
+}
+
+fn read_foreign_keys(conn: &Connection, table: &TableInfo) -> Result<Vec<ForeignKey>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA foreign_key_list({})", quote(&table.name))).map_err(sqlite_error)?;
+    // the columns of each foreign key by its id
+    let mut keys: Vec<(i64, String, String, Option<String>)> = Vec::new();
+    let mut column_counts: HashMap<i64, usize> = HashMap::new();
+    let mut rows = stmt.query([]).map_err(sqlite_error)?;
+    while let Some(row) = rows.next().map_err(sqlite_error)? {
+        let id: i64 = row.get("id").map_err(sqlite_error)?;
+        *column_counts.entry(id).or_default() += 1;
+        keys.push((
+            id,
+            row.get("table").map_err(sqlite_error)?,
+            row.get("from").map_err(sqlite_error)?,
+            row.get("to").map_err(sqlite_error)?,
+        ));
+    }
+
+    let mut result = Vec::new();
+    for (id, ref_table, from, to) in keys {
+        if column_counts[&id] != 1 {
+            continue;
+        }
+        let column = match table.columns.iter().position(|column| column.name == from) {
+            Some(column) => column,
+            None => continue,
+        };
+        let ref_info = TableInfo::read(conn, &ref_table)?;
+        // the primary key of the referenced table if the column is not given
+        let to = match to.or_else(|| ref_info.primary_key.map(|index| ref_info.columns[index].name.clone())) {
+            Some(to) => to,
+            None => continue,
+        };
+        let sql = format!("SELECT * FROM {} WHERE {} = ?1 LIMIT 1", quote(&ref_table), quote(&to));
+        result.push(ForeignKey {
+            column,
+            table: ref_info,
+            sql,
+        });
+    }
+    Ok(result)
+}
+
+/// The single column indexes of the table, except the primary key.
+fn read_indexes(conn: &Connection, table: &TableInfo) -> Result<Vec<IndexModel>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA index_list({})", quote(&table.name))).map_err(sqlite_error)?;
+    let mut indexes: Vec<(String, bool)> = Vec::new();
+    let mut rows = stmt.query([]).map_err(sqlite_error)?;
+    while let Some(row) = rows.next().map_err(sqlite_error)? {
+        let origin: String = row.get("origin").map_err(sqlite_error)?;
+        let partial: bool = row.get("partial").map_err(sqlite_error)?;
+        if origin == "pk" || partial {
+            continue;
+        }
+        indexes.push((row.get("name").map_err(sqlite_error)?, row.get("unique").map_err(sqlite_error)?));
+    }
+
+    let mut result = Vec::new();
+    for (name, unique) in indexes {
+        let mut stmt = conn.prepare(&format!("PRAGMA index_info({})", quote(&name))).map_err(sqlite_error)?;
+        let columns = stmt
+            .query_map([], |row| row.get::<_, Option<i64>>("cid"))
+            .map_err(sqlite_error)?
+            .collect::<rusqlite::Result<Vec<Option<i64>>>>()
+            .map_err(sqlite_error)?;
+        // the expressions have no column
+        let column = match columns.as_slice() {
+            [Some(column)] => *column as usize,
+            _ => continue,
+        };
+        if table.primary_key == Some(column) {
+            continue;
+        }
+        let field = table.field_name(column).to_string();
+        // the names of the indexes of the constraints are generated by SQLite
+        let name = if name.starts_with("sqlite_autoindex_") {
+            format!("{}_1", field)
+        } else {
+            name
+        };
+        result.push(IndexModel {
+            keys: doc! { field: 1 },
+            options: Some(IndexOptions {
+                name: Some(name),
+                unique: Some(unique),
+                ..Default::default()
+            }),
+        });
+    }
+    Ok(result)
+}
+
+fn list_tables(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name")
+        .map_err(sqlite_error)?;
+    let names = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(sqlite_error)?
+        .collect::<rusqlite::Result<Vec<String>>>()
+        .map_err(sqlite_error)?;
+    Ok(names)
+}
+
+pub(crate) fn import_sqlite(db: &Database, path: &Path, options: SqliteImportOptions) -> Result<SqliteImportResult> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+        .map_err(sqlite_error)?;
+    let all_tables = list_tables(&conn)?;
+    let tables = match options.tables {
+        Some(tables) => {
+            if let Some(missing) = tables.iter().find(|table| !all_tables.contains(table)) {
+                return Err(Error::SqliteError(format!("table '{}' not found", missing)));
+            }
+            tables
+        }
+        None => all_tables,
+    };
+    let embed_foreign_keys = options.embed_foreign_keys.unwrap_or(false);
+
+    let mut result = SqliteImportResult::default();
+    for name in &tables {
+        let table = TableInfo::read(&conn, name)?;
+        let foreign_keys = if embed_foreign_keys {
+            read_foreign_keys(&conn, &table)?
+        } else {
+            Vec::new()
+        };
+
+        // the rows of a table are imported in one transaction
+        let txn = db.start_transaction()?;
+        let col = txn.collection::<Document>(name);
+        let mut stmt = conn.prepare(&format!("SELECT * FROM {}", quote(name))).map_err(sqlite_error)?;
+        let mut rows = stmt.query([]).map_err(sqlite_error)?;
+        let mut batch = Vec::with_capacity(INSERT_BATCH_SIZE);
+        while let Some(row) = rows.next().map_err(sqlite_error)? {
+            let mut doc = table.row_to_document(row)?;
+            for foreign_key in &foreign_keys {
+                let value: Value = row.get_ref(foreign_key.column).map_err(sqlite_error)?.into();
+                if value == Value::Null {
+                    continue;
+                }
+                let mut ref_stmt = conn.prepare_cached(&foreign_key.sql).map_err(sqlite_error)?;
+                let mut ref_rows = ref_stmt.query([value]).map_err(sqlite_error)?;
+                if let Some(ref_row) = ref_rows.next().map_err(sqlite_error)? {
+                    let embedded = foreign_key.table.row_to_document(ref_row)?;
+                    doc.insert(table.field_name(foreign_key.column), embedded);
+                }
+            }
+            batch.push(doc);
+            if batch.len() == INSERT_BATCH_SIZE {
+                result.documents += batch.len() as u64;
+                col.insert_many(std::mem::take(&mut batch))?;
+            }
+        }
+        if !batch.is_empty() {
+            result.documents += batch.len() as u64;
+            col.insert_many(batch)?;
+        }
+        txn.commit()?;
+
+        let col = db.collection::<Document>(name);
+        for index in read_indexes(&conn, &table)? {
+            col.create_index(index)?;
+            result.indexes += 1;
+        }
+        result.tables += 1;
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,232 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The file layer of the storage engine.
+//!
+//! By default, the database files are accessed by the file system of the OS.
+//! Implement [`StorageBackend`] and attach it with
+//! [`crate::ConfigBuilder::set_storage_backend`] to store the files somewhere
+//! else, like an encrypted container or a cache of an object storage.
+//!
+//! The engine only appends to the files it writes, and it never modifies
+//! a file after it's synced except for truncating, so a backend doesn't need
+//! to support random writes efficiently.
+//!
+//! The paths passed to the backend are the path of the database joined with
+//! the names of the files. Returning an error of [`std::io::ErrorKind::NotFound`]
+//! tells the engine that the file doesn't exist.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+/// How to open a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Open an existing file to read.
+    Read,
+    /// Create the file, or clear it if it exists.
+    Truncate,
+    /// Create the file if it doesn't exist, and keep the content.
+    Append,
+}
+
+/// A file opened by a [`StorageBackend`].
+///
+/// The engine may use a file from multiple threads.
+pub trait StorageFile: Send + Sync {
+
+    /// Read from `offset` until `buf` is full or the end of the file is reached.
+    /// Return the number of bytes read.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// Write all the `data` at `offset`.
+    fn write_at(&self, offset: u64, data: &[u8]) -> io::Result<()>;
+
+    /// Make sure the written data is durable.
+    fn sync(&self) -> io::Result<()>;
+
+    fn size(&self) -> io::Result<u64>;
+
+    fn truncate(&self, size: u64) -> io::Result<()>;
+
+}
+
+/// The file layer used by the storage engine.
+pub trait StorageBackend: Send + Sync {
+
+    fn open(&self, path: &Path, mode: OpenMode) -> io::Result<Box<dyn StorageFile>>;
+
+    /// Delete a file or an empty directory.
+    fn delete(&self, path: &Path) -> io::Result<()>;
+
+    /// Rename a file, replacing the target if it exists.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Whether a file or a directory exists.
+    fn exists(&self, path: &Path) -> io::Result<bool>;
+
+    /// The names of the entries in a directory.
+    fn list(&self, dir: &Path) -> io::Result<Vec<String>>;
+
+    /// Create a directory and its parents. It succeeds if the directory exists.
+    fn create_dir(&self, dir: &Path) -> io::Result<()>;
+
+    /// Whether the path is a directory, it fails with [`std::io::ErrorKind::NotFound`]
+    /// if the path doesn't exist. By default, the directories are the paths which can be listed.
+    fn is_dir(&self, path: &Path) -> io::Result<bool> {
+        if !self.exists(path)? {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        Ok(self.list(path).is_ok())
+    }
+
+}
+
+/// A [`StorageBackend`] over [`std::fs`].
+///
+/// It's a starting point to wrap the file system, e.g. to encrypt the data.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileSystemBackend;
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileSystemBackend {
+
+    pub fn new() -> FileSystemBackend {
+        FileSystemBackend
+    }
+
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl StorageBackend for FileSystemBackend {
+
+    fn open(&self, path: &Path, mode: OpenMode) -> io::Result<Box<dyn StorageFile>> {
+        let mut options = OpenOptions::new();
+        options.read(true);
+        match mode {
+            OpenMode::Read => (),
+            OpenMode::Truncate => {
+                options.write(true).create(true).truncate(true);
+            }
+            OpenMode::Append => {
+                options.write(true).create(true);
+            }
+        }
+        let file = options.open(path)?;
+        Ok(Box::new(FileSystemFile { file }))
+    }
+
+    fn delete(&self, path: &Path) -> io::Result<()> {
+        if path.is_dir() {
+            std::fs::remove_dir(path)
+        } else {
+            std::fs::remove_file(path)
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        std::fs::rename(from, to)
+    }
+
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        path.try_exists()
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<String>> {
+        let mut result = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            result.push(entry?.file_name().to_string_lossy().into_owned());
+        }
+        Ok(result)
+    }
+
+    fn create_dir(&self, dir: &Path) -> io::Result<()> {
+        std::fs::create_dir_all(dir)
+    }
+
+    fn is_dir(&self, path: &Path) -> io::Result<bool> {
+        Ok(std::fs::metadata(path)?.is_dir())
+    }
+
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+struct FileSystemFile {
+    file: File,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl StorageFile for FileSystemFile {
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        while read < buf.len() {
+            let n = read_at(&self.file, offset + read as u64, &mut buf[read..])?;
+            if n == 0 {
+                break;
+            }
+            read += n;
+        }
+        Ok(read)
+    }
+
+    fn write_at(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+        write_all_at(&self.file, offset, data)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    fn truncate(&self, size: u64) -> io::Result<()> {
+        self.file.set_len(size)
+    }
+
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(unix)]
+fn write_all_at(file: &File, offset: u64, data: &[u8]) -> io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, data, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+// there is no `write_all_at` on Windows
+#[cfg(windows)]
+fn write_all_at(file: &File, offset: u64, data: &[u8]) -> io::Result<()> {
+    let mut written = 0;
+    while written < data.len() {
+        let n = std::os::windows::fs::FileExt::seek_write(file, &data[written..], offset + written as u64)?;
+        if n == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        written += n;
+    }
+    Ok(())
+}
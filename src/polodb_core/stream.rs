@@ -0,0 +1,123 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Read the documents of a [`ClientCursor`] as a [`Stream`].
+//!
+//! The cursor is advanced by a thread, which reads ahead a bounded number of documents.
+//! When the consumer is slower than the query, the thread waits instead of
+//! buffering the whole result, so the backpressure reaches the scan of the database.
+
+use std::pin::Pin;
+use std::sync::mpsc::{sync_channel, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use futures_core::Stream;
+use serde::de::DeserializeOwned;
+use crate::{CancellationToken, ClientCursor, Result};
+
+/// A default of the documents read ahead by [`ClientCursor::into_stream`].
+pub const DEFAULT_PREFETCH: usize = 64;
+
+/// A [`Stream`] of the documents of a cursor, created by [`ClientCursor::into_stream`].
+///
+/// The stream doesn't depend on an async runtime, the reads happen on its own thread.
+/// Dropping the stream cancels the cursor without waiting for the thread,
+/// which stops before reading the next document and releases the transaction.
+pub struct CursorStream<T> {
+    receiver: Option<Receiver<Result<T>>>,
+    waker: Arc<Mutex<Option<Waker>>>,
+    cancellation_token: CancellationToken,
+}
+
+impl<T> CursorStream<T>
+where
+    T: DeserializeOwned + Unpin + Send + Sync + 'static,
+{
+
+    pub(crate) fn new(mut cursor: ClientCursor<T>, prefetch: usize) -> Result<CursorStream<T>> {
+        // the token of the caller still cancels the cursor
+        let cancellation_token = cursor.cancellation_token()
+            .map(|token| token.child())
+            .unwrap_or_default();
+        cursor.set_cancellation_token(cancellation_token.clone());
+        // the thread wakes the stream after sending a document,
+        // so it can't wait for the stream to receive the document
+        let (sender, receiver) = sync_channel(prefetch.max(1));
+        let waker: Arc<Mutex<Option<Waker>>> = Arc::new(Mutex::new(None));
+        let producer_waker = waker.clone();
+        std::thread::Builder::new()
+            .name("polodb-stream".to_string())
+            .spawn(move || {
+                let wake = || {
+                    if let Some(waker) = producer_waker.lock().unwrap().take() {
+                        waker.wake();
+                    }
+                };
+                for item in cursor {
+                    let failed = item.is_err();
+                    // the stream is dropped
+                    if sender.send(item).is_err() {
+                        return;
+                    }
+                    wake();
+                    // e.g. the cursor is cancelled
+                    if failed {
+                        break;
+                    }
+                }
+                drop(sender);
+                wake();
+            })?;
+        Ok(CursorStream {
+            receiver: Some(receiver),
+            waker,
+            cancellation_token,
+        })
+    }
+
+}
+
+impl<T> Stream for CursorStream<T> {
+    type Item = Result<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let receiver = match &self.receiver {
+            Some(receiver) => receiver,
+            None => return Poll::Ready(None),
+        };
+        match receiver.try_recv() {
+            Ok(item) => return Poll::Ready(Some(item)),
+            Err(TryRecvError::Disconnected) => return Poll::Ready(None),
+            Err(TryRecvError::Empty) => (),
+        }
+        *self.waker.lock().unwrap() = Some(cx.waker().clone());
+        // the document may be sent before the waker is registered
+        match receiver.try_recv() {
+            Ok(item) => Poll::Ready(Some(item)),
+            Err(TryRecvError::Disconnected) => Poll::Ready(None),
+            Err(TryRecvError::Empty) => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for CursorStream<T> {
+
+    fn drop(&mut self) {
+        // the thread stops at the next document it reads or sends,
+        // and then the transaction of the cursor is released
+        self.cancellation_token.cancel();
+        self.receiver.take();
+    }
+
+}
@@ -0,0 +1,813 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sync the documents of the databases on multiple devices.
+//!
+//! Enable [`crate::ConfigBuilder::set_sync_tracking`] on each database, then
+//! [`crate::Database::sync_with`] exchanges the changes of the documents
+//! since the last sync in both directions. When the databases are not in the same
+//! process, the changes are sent as a [`ChangeSet`]: the replica asks the other
+//! one for [`crate::Database::sync_changes`] since [`crate::Database::sync_checkpoint`],
+//! and applies them by [`crate::Database::apply_changes`].
+//!
+//! Each document has a [`DocumentVersion`] counting its changes by each replica.
+//! A change is applied if it follows the local version of the document.
+//! The concurrent changes are resolved by the [`ConflictStrategy`] of the [`SyncOptions`].
+//!
+//! The inserts, updates and deletes of the documents are synced, but not the
+//! creation, the indexes or the drop of the collections.
+//! The measurements of the time series collections are not synced.
+//!
+//! ```rust
+//! use polodb_core::{ConfigBuilder, Database, CollectionT};
+//! use polodb_core::bson::{doc, Document};
+//! use polodb_core::sync::SyncOptions;
+//!
+//! let open = || {
+//!     let mut config = ConfigBuilder::new();
+//!     config.set_sync_tracking(true);
+//!     Database::open_memory_with_config(config.take()).unwrap()
+//! };
+//! let phone = open();
+//! let laptop = open();
+//!
+//! phone.collection::<Document>("notes").insert_one(doc! { "_id": 1, "text": "milk" }).unwrap();
+//! laptop.collection::<Document>("notes").insert_one(doc! { "_id": 2, "text": "eggs" }).unwrap();
+//!
+//! phone.sync_with(&laptop, &SyncOptions::default()).unwrap();
+//! assert_eq!(phone.collection::<Document>("notes").count_documents().unwrap(), 2);
+//! assert_eq!(laptop.collection::<Document>("notes").count_documents().unwrap(), 2);
+//! ```
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+use bson::oid::ObjectId;
+use bson::spec::ElementType;
+use bson::{doc, Bson, Document};
+use serde::{Deserialize, Serialize};
+use crate::coll::key_ordering;
+use crate::db::{RocksDBTransaction, RocksDBTransactionInner};
+use crate::utils::bson::{split_stacked_keys, stacked_key};
+use crate::{CollectionT, Database, Error, Result, Transaction};
+
+const SYNC_PREFIX: &str = "$SYNC";
+const SYNC_LOG_PREFIX: &str = "$SYNC_LOG";
+const SYNC_VERSION_PREFIX: &str = "$SYNC_VERSION";
+const SYNC_PEER_PREFIX: &str = "$SYNC_PEER";
+
+/// The changes of a document, the replicas are identified by [`crate::Database::sync_replica_id`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocumentVersion {
+    /// The number of changes made by each replica.
+    pub vector: BTreeMap<String, i64>,
+    /// The time of the latest change in milliseconds since the epoch.
+    pub timestamp: i64,
+    /// The replica of the latest change.
+    pub replica: String,
+    /// The time of the latest change of each top-level field.
+    pub fields: BTreeMap<String, i64>,
+    pub deleted: bool,
+}
+
+impl DocumentVersion {
+
+    /// The latest of the two changes by the time, then by the replica.
+    fn is_later_than(&self, other: &DocumentVersion) -> bool {
+        (self.timestamp, &self.replica) > (other.timestamp, &other.replica)
+    }
+
+}
+
+/// The causal order of the versions, `None` if they're concurrent.
+fn compare_vectors(a: &BTreeMap<String, i64>, b: &BTreeMap<String, i64>) -> Option<Ordering> {
+    let mut result = Ordering::Equal;
+    let replicas: HashSet<&String> = a.keys().chain(b.keys()).collect();
+    for replica in replicas {
+        let a_count = a.get(replica).copied().unwrap_or(0);
+        let b_count = b.get(replica).copied().unwrap_or(0);
+        match (result, a_count.cmp(&b_count)) {
+            (_, Ordering::Equal) => (),
+            (Ordering::Equal, ord) => result = ord,
+            (current, ord) if current != ord => return None,
+            _ => (),
+        }
+    }
+    Some(result)
+}
+
+fn merge_vectors(a: &BTreeMap<String, i64>, b: &BTreeMap<String, i64>) -> BTreeMap<String, i64> {
+    let mut result = a.clone();
+    for (replica, count) in b {
+        let entry = result.entry(replica.clone()).or_insert(0);
+        *entry = (*entry).max(*count);
+    }
+    result
+}
+
+/// A change of a document, `document` is `None` if it's deleted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncChange {
+    pub collection: String,
+    pub id: Bson,
+    pub document: Option<Document>,
+    pub version: DocumentVersion,
+}
+
+/// The changes of a replica since a checkpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangeSet {
+    pub replica_id: String,
+    /// Pass it to [`crate::Database::sync_changes`] to get the next changes.
+    pub checkpoint: u64,
+    pub changes: Vec<SyncChange>,
+}
+
+/// Two changes of a document made concurrently by the replicas.
+pub struct SyncConflict<'a> {
+    pub collection: &'a str,
+    pub id: &'a Bson,
+    /// `None` if the document is deleted.
+    pub local: Option<&'a Document>,
+    pub remote: Option<&'a Document>,
+    pub local_version: &'a DocumentVersion,
+    pub remote_version: &'a DocumentVersion,
+}
+
+/// Resolves a conflict into the document to keep, `None` to delete it.
+pub type ConflictResolver = Arc<dyn Fn(&SyncConflict) -> Result<Option<Document>> + Send + Sync>;
+
+#[derive(Clone, Default)]
+pub enum ConflictStrategy {
+    /// Keep the latest change of the document.
+    #[default]
+    LastWriterWins,
+    /// Keep the latest change of each top-level field,
+    /// or the latest change of the document if it's deleted by one of them.
+    MergeFields,
+    Custom(ConflictResolver),
+}
+
+impl fmt::Debug for ConflictStrategy {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConflictStrategy::LastWriterWins => write!(f, "LastWriterWins"),
+            ConflictStrategy::MergeFields => write!(f, "MergeFields"),
+            ConflictStrategy::Custom(_) => write!(f, "Custom"),
+        }
+    }
+
+}
+
+/// Options of [`crate::Database::sync_with`].
+///
+/// Use the same collections on each sync with a replica, the changes
+/// of the other collections are skipped by the checkpoints.
+#[derive(Debug, Clone, Default)]
+pub struct SyncOptions {
+    /// Only sync these collections, all the collections by default.
+    pub collections: Option<Vec<String>>,
+
+    pub strategy: ConflictStrategy,
+}
+
+impl SyncOptions {
+    pub fn builder() -> SyncOptionsBuilder {
+        SyncOptionsBuilder::default()
+    }
+
+    fn includes(&self, collection: &str) -> bool {
+        match &self.collections {
+            Some(collections) => collections.iter().any(|name| name == collection),
+            None => true,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SyncOptionsBuilder {
+    collections: Option<Vec<String>>,
+    strategy: ConflictStrategy,
+}
+
+impl SyncOptionsBuilder {
+    pub fn collections<T: Into<String>>(mut self, collections: impl IntoIterator<Item = T>) -> Self {
+        self.collections = Some(collections.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn strategy(mut self, strategy: ConflictStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Resolve the conflicts by `f`.
+    pub fn resolver<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&SyncConflict) -> Result<Option<Document>> + Send + Sync + 'static
+    {
+        self.strategy = ConflictStrategy::Custom(Arc::new(f));
+        self
+    }
+
+    pub fn build(self) -> SyncOptions {
+        SyncOptions {
+            collections: self.collections,
+            strategy: self.strategy,
+        }
+    }
+}
+
+/// The result of applying a [`ChangeSet`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApplyChangesResult {
+    /// The changes following the local versions.
+    pub applied: u64,
+    /// The concurrent changes, resolved by the strategy.
+    pub conflicts: u64,
+    /// The changes already seen by the replica.
+    pub skipped: u64,
+}
+
+/// The result of [`crate::Database::sync_with`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncResult {
+    /// The changes of the other replica applied to this one.
+    pub pulled: ApplyChangesResult,
+    /// The changes of this replica applied to the other one.
+    pub pushed: ApplyChangesResult,
+}
+
+/// The stored version of a document, with the position of its change in the log.
+#[derive(Serialize, Deserialize)]
+struct StoredVersion {
+    version: DocumentVersion,
+    log: i64,
+}
+
+/// The keys and the values of the documents written by a transaction.
+#[derive(Default)]
+pub(crate) struct SyncWrites {
+    before: HashMap<Vec<u8>, Option<Vec<u8>>>,
+    versions: HashMap<Vec<u8>, DocumentVersion>,
+}
+
+impl SyncWrites {
+
+    pub(crate) fn contains(&self, key: &[u8]) -> bool {
+        self.before.contains_key(key)
+    }
+
+    pub(crate) fn record_before(&mut self, key: &[u8], value: Option<Vec<u8>>) {
+        self.before.insert(key.to_vec(), value);
+    }
+
+    pub(crate) fn set_version(&mut self, key: &[u8], version: DocumentVersion) {
+        self.versions.insert(key.to_vec(), version);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.before.is_empty() && self.versions.is_empty()
+    }
+
+}
+
+/// The reads and writes of the sync state, in the transaction of the database
+/// or in the transaction being committed.
+trait SyncStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+    fn set(&self, key: &[u8], value: &[u8]) -> Result<()>;
+}
+
+impl SyncStore for RocksDBTransaction {
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        RocksDBTransaction::get(self, key)
+    }
+
+    fn set(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        RocksDBTransaction::set(self, key, value)
+    }
+
+}
+
+impl SyncStore for RocksDBTransactionInner {
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        RocksDBTransactionInner::get(self, key)
+    }
+
+    fn set(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        RocksDBTransactionInner::set(self, key, value)
+    }
+
+}
+
+/// The keys of the documents are the names of the collections followed by the ids,
+/// the keys of the other data start with `$`.
+pub(crate) fn is_document_key(key: &[u8]) -> bool {
+    key.len() > 1 && key[0] == ElementType::String as u8 && key[1] != b'$'
+}
+
+pub(crate) struct TrackerState {
+    replica_id: String,
+    counter: u64,
+}
+
+/// Logs the changes of the documents on commit.
+pub(crate) struct SyncTracker {
+    state: Mutex<Option<TrackerState>>,
+}
+
+impl SyncTracker {
+
+    pub(crate) fn new() -> SyncTracker {
+        SyncTracker {
+            state: Mutex::new(None),
+        }
+    }
+
+    fn replica_id(&self, db: &Database) -> Result<String> {
+        let mut state = self.state.lock()?;
+        if let Some(state) = state.as_ref() {
+            return Ok(state.replica_id.clone());
+        }
+        let txn = db.start_transaction()?;
+        let loaded = load_state(&txn.inner().rocksdb_txn)?;
+        txn.commit()?;
+        let replica_id = loaded.replica_id.clone();
+        *state = Some(loaded);
+        Ok(replica_id)
+    }
+
+    /// Write the versions and the log of the changed documents in the transaction.
+    /// Return the lock to hold until the transaction is committed.
+    pub(crate) fn record(&self, txn: &RocksDBTransactionInner, writes: SyncWrites) -> Result<MutexGuard<'_, Option<TrackerState>>> {
+        let mut guard = self.state.lock()?;
+        if guard.is_none() {
+            *guard = Some(load_state(txn)?);
+        }
+        let state = guard.as_mut().unwrap();
+
+        let mut keys: Vec<&Vec<u8>> = writes.before.keys()
+            .chain(writes.versions.keys().filter(|key| !writes.before.contains_key(*key)))
+            .collect();
+        keys.sort();
+
+        for key in keys {
+            let (collection, id) = match split_document_key(key)? {
+                // the collections with a key ordering aren't synced
+                Some((_, id)) if key_ordering::is_ordered_key(&id) => continue,
+                Some(result) => result,
+                None => continue,
+            };
+            let version_key = version_key(&collection, &id)?;
+            let stored = read_version(txn, &version_key)?;
+            let version = match writes.versions.get(key) {
+                Some(version) => version.clone(),
+                None => {
+                    let before = writes.before.get(key).cloned().flatten();
+                    let after = txn.get(key)?;
+                    if before == after {
+                        continue;
+                    }
+                    local_version(stored.as_ref().map(|stored| &stored.version), before, after, &state.replica_id)?
+                }
+            };
+
+            state.counter += 1;
+            if let Some(stored) = &stored {
+                txn.delete(&log_key(stored.log)?)?;
+            }
+            txn.set(&log_key(state.counter as i64)?, &bson::to_vec(&doc! {
+                "c": collection,
+                "id": id,
+            })?)?;
+            txn.set(&version_key, &bson::to_vec(&StoredVersion {
+                version,
+                log: state.counter as i64,
+            })?)?;
+        }
+        txn.set(&state_key("counter")?, &state.counter.to_be_bytes())?;
+
+        Ok(guard)
+    }
+
+}
+
+fn state_key(name: &str) -> Result<Vec<u8>> {
+    stacked_key(&[
+        Bson::String(SYNC_PREFIX.to_string()),
+        Bson::String(name.to_string()),
+    ])
+}
+
+fn log_key(counter: i64) -> Result<Vec<u8>> {
+    stacked_key(&[
+        Bson::String(SYNC_LOG_PREFIX.to_string()),
+        Bson::Int64(counter),
+    ])
+}
+
+fn version_key(storage_name: &str, id: &Bson) -> Result<Vec<u8>> {
+    stacked_key(&[
+        Bson::String(SYNC_VERSION_PREFIX.to_string()),
+        Bson::String(storage_name.to_string()),
+        id.clone(),
+    ])
+}
+
+fn peer_key(db: &Database, replica_id: &str) -> Result<Vec<u8>> {
+    stacked_key(&[
+        Bson::String(SYNC_PEER_PREFIX.to_string()),
+        Bson::String(replica_id.to_string()),
+        db.name().map(|name| Bson::String(name.to_string())).unwrap_or(Bson::Null),
+    ])
+}
+
+/// The collection and the `_id` of a document key.
+pub(crate) fn split_document_key(key: &[u8]) -> Result<Option<(String, Bson)>> {
+    let mut keys = split_stacked_keys(key)?;
+    if keys.len() != 2 {
+        return Ok(None);
+    }
+    let id = keys.pop().unwrap();
+    match keys.pop().unwrap() {
+        Bson::String(collection) => Ok(Some((collection, id))),
+        _ => Ok(None),
+    }
+}
+
+fn load_state<S: SyncStore>(txn: &S) -> Result<TrackerState> {
+    let replica_key = state_key("replica")?;
+    let replica_id = match txn.get(&replica_key)? {
+        Some(value) => String::from_utf8(value)?,
+        None => {
+            let replica_id = ObjectId::new().to_hex();
+            txn.set(&replica_key, replica_id.as_bytes())?;
+            replica_id
+        }
+    };
+    let counter = match txn.get(&state_key("counter")?)? {
+        Some(value) => {
+            let bytes = <[u8; 8]>::try_from(value.as_slice())
+                .map_err(|_| Error::SyncError("invalid sync state".to_string()))?;
+            u64::from_be_bytes(bytes)
+        }
+        None => 0,
+    };
+    Ok(TrackerState {
+        replica_id,
+        counter,
+    })
+}
+
+fn read_version<S: SyncStore>(txn: &S, version_key: &[u8]) -> Result<Option<StoredVersion>> {
+    match txn.get(version_key)? {
+        Some(value) => Ok(Some(bson::from_slice(&value)?)),
+        None => Ok(None),
+    }
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+/// The version of a document changed by this replica.
+fn local_version(
+    previous: Option<&DocumentVersion>,
+    before: Option<Vec<u8>>,
+    after: Option<Vec<u8>>,
+    replica_id: &str,
+) -> Result<DocumentVersion> {
+    let mut version = previous.cloned().unwrap_or_default();
+    // later than the previous change even if the clock goes backward
+    let timestamp = now_millis().max(version.timestamp + 1);
+    *version.vector.entry(replica_id.to_string()).or_insert(0) += 1;
+    version.timestamp = timestamp;
+    version.replica = replica_id.to_string();
+
+    match after {
+        Some(after) => {
+            let after: Document = bson::from_slice(&after)?;
+            let before: Document = match &before {
+                Some(before) => bson::from_slice(before)?,
+                None => {
+                    // all the fields of a new document are changed
+                    for field in version.fields.values_mut() {
+                        *field = timestamp;
+                    }
+                    Document::new()
+                }
+            };
+            for key in before.keys().chain(after.keys()) {
+                if key != "_id" && before.get(key) != after.get(key) {
+                    version.fields.insert(key.clone(), timestamp);
+                }
+            }
+            version.deleted = false;
+        }
+        None => {
+            version.deleted = true;
+        }
+    }
+
+    Ok(version)
+}
+
+fn tracker(db: &Database) -> Result<Arc<SyncTracker>> {
+    db.sync_tracker()?.ok_or_else(|| {
+        Error::SyncError("the changes are not tracked, enable ConfigBuilder::set_sync_tracking".to_string())
+    })
+}
+
+pub(crate) fn sync_replica_id(db: &Database) -> Result<String> {
+    tracker(db)?.replica_id(db)
+}
+
+pub(crate) fn sync_checkpoint(db: &Database, replica_id: &str) -> Result<u64> {
+    tracker(db)?;
+    let txn = db.start_transaction()?;
+    let value = txn.inner().rocksdb_txn.get(&peer_key(db, replica_id)?)?;
+    match value {
+        Some(value) => {
+            let bytes = <[u8; 8]>::try_from(value.as_slice())
+                .map_err(|_| Error::SyncError("invalid sync checkpoint".to_string()))?;
+            Ok(u64::from_be_bytes(bytes))
+        }
+        None => Ok(0),
+    }
+}
+
+pub(crate) fn sync_changes(db: &Database, since: u64, options: &SyncOptions) -> Result<ChangeSet> {
+    let replica_id = sync_replica_id(db)?;
+    let txn = db.start_transaction()?;
+    let names: HashMap<String, String> = db.collection_storage_names(&txn)?
+        .into_iter()
+        .map(|(name, storage_name)| (storage_name, name))
+        .collect();
+
+    let rocksdb_txn = &txn.inner().rocksdb_txn;
+    let prefix = stacked_key(&[Bson::String(SYNC_LOG_PREFIX.to_string())])?;
+    let iter = rocksdb_txn.new_iterator();
+    iter.seek(&log_key(since as i64 + 1)?);
+
+    let mut checkpoint = since;
+    let mut changes = Vec::new();
+    while iter.valid() {
+        let key = iter.copy_key()?;
+        if !key.starts_with(&prefix) {
+            break;
+        }
+        if let Some(Bson::Int64(counter)) = split_stacked_keys(&key)?.get(1) {
+            checkpoint = *counter as u64;
+        }
+        let entry: Document = bson::from_slice(&iter.copy_data()?)?;
+        iter.next();
+
+        let storage_name = entry.get_str("c").map_err(|_| Error::SyncError("invalid sync log".to_string()))?;
+        let id = entry.get("id").ok_or_else(|| Error::SyncError("invalid sync log".to_string()))?;
+        let collection = match names.get(storage_name) {
+            Some(collection) if options.includes(collection) => collection,
+            _ => continue,
+        };
+        let stored = match read_version(rocksdb_txn, &version_key(storage_name, id)?)? {
+            Some(stored) => stored,
+            None => continue,
+        };
+        let document = match rocksdb_txn.get(&stacked_key(&[Bson::String(storage_name.to_string()), id.clone()])?)? {
+            Some(value) => Some(bson::from_slice(&value)?),
+            None => None,
+        };
+        changes.push(SyncChange {
+            collection: collection.clone(),
+            id: id.clone(),
+            document,
+            version: stored.version,
+        });
+    }
+    iter.error()?;
+
+    Ok(ChangeSet {
+        replica_id,
+        checkpoint,
+        changes,
+    })
+}
+
+enum Resolution {
+    Keep,
+    Apply {
+        document: Option<Document>,
+        version: DocumentVersion,
+        conflict: bool,
+    },
+}
+
+pub(crate) fn apply_changes(db: &Database, changes: &ChangeSet, options: &SyncOptions) -> Result<ApplyChangesResult> {
+    let replica_id = sync_replica_id(db)?;
+    if changes.replica_id == replica_id {
+        return Err(Error::SyncError("the changes are made by this replica".to_string()));
+    }
+
+    let txn = db.start_transaction()?;
+    let storage_names = db.collection_storage_names(&txn)?;
+    let mut result = ApplyChangesResult::default();
+
+    for change in &changes.changes {
+        if !options.includes(&change.collection) {
+            continue;
+        }
+        let storage_name = storage_names.get(&change.collection)
+            .cloned()
+            .unwrap_or_else(|| db.qualified_name(&change.collection));
+        let data_key = stacked_key(&[Bson::String(storage_name.clone()), change.id.clone()])?;
+        let rocksdb_txn = &txn.inner().rocksdb_txn;
+        let local: Option<Document> = match rocksdb_txn.get(&data_key)? {
+            Some(value) => Some(bson::from_slice(&value)?),
+            None => None,
+        };
+        let local_version = read_version(rocksdb_txn, &version_key(&storage_name, &change.id)?)?
+            .map(|stored| stored.version)
+            .unwrap_or_default();
+
+        match resolve(change, local.as_ref(), &local_version, &options.strategy, &replica_id)? {
+            Resolution::Keep => result.skipped += 1,
+            Resolution::Apply { document, version, conflict } => {
+                if conflict {
+                    result.conflicts += 1;
+                } else {
+                    result.applied += 1;
+                }
+                if document != local {
+                    write_document(&txn, &change.collection, &change.id, document)?;
+                }
+                txn.inner().rocksdb_txn.set_sync_version(&data_key, version)?;
+            }
+        }
+    }
+
+    txn.inner().rocksdb_txn.set(&peer_key(db, &changes.replica_id)?, &changes.checkpoint.to_be_bytes())?;
+    txn.commit()?;
+
+    Ok(result)
+}
+
+fn write_document(txn: &Transaction, collection: &str, id: &Bson, document: Option<Document>) -> Result<()> {
+    let collection = txn.collection::<Document>(collection);
+    collection.delete_one(doc! { "_id": id.clone() })?;
+    if let Some(mut document) = document {
+        document.insert("_id", id.clone());
+        collection.insert_one(document)?;
+    }
+    Ok(())
+}
+
+fn resolve(
+    change: &SyncChange,
+    local: Option<&Document>,
+    local_version: &DocumentVersion,
+    strategy: &ConflictStrategy,
+    replica_id: &str,
+) -> Result<Resolution> {
+    let remote_version = &change.version;
+    match compare_vectors(&local_version.vector, &remote_version.vector) {
+        Some(Ordering::Less) => {
+            return Ok(Resolution::Apply {
+                document: change.document.clone(),
+                version: remote_version.clone(),
+                conflict: false,
+            });
+        }
+        Some(_) => return Ok(Resolution::Keep),
+        None => (),
+    }
+
+    let vector = merge_vectors(&local_version.vector, &remote_version.vector);
+    let (document, version) = match (strategy, local, &change.document) {
+        (ConflictStrategy::MergeFields, Some(local), Some(remote)) => {
+            merge_fields(local, local_version, remote, remote_version, vector)
+        }
+        (ConflictStrategy::LastWriterWins, _, _) | (ConflictStrategy::MergeFields, _, _) => {
+            let (document, mut version) = if remote_version.is_later_than(local_version) {
+                (change.document.clone(), remote_version.clone())
+            } else {
+                (local.cloned(), local_version.clone())
+            };
+            version.vector = vector;
+            (document, version)
+        }
+        (ConflictStrategy::Custom(resolver), _, _) => {
+            let document = resolver(&SyncConflict {
+                collection: &change.collection,
+                id: &change.id,
+                local,
+                remote: change.document.as_ref(),
+                local_version,
+                remote_version,
+            })?;
+            let version = resolved_version(local_version, remote_version, vector, document.as_ref(), replica_id);
+            (document, version)
+        }
+    };
+
+    Ok(Resolution::Apply {
+        document,
+        version,
+        conflict: true,
+    })
+}
+
+fn merge_fields(
+    local: &Document,
+    local_version: &DocumentVersion,
+    remote: &Document,
+    remote_version: &DocumentVersion,
+    vector: BTreeMap<String, i64>,
+) -> (Option<Document>, DocumentVersion) {
+    let mut keys: Vec<&String> = Vec::new();
+    for key in local.keys().chain(remote.keys()).chain(local_version.fields.keys()).chain(remote_version.fields.keys()) {
+        if key != "_id" && !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    let mut document = Document::new();
+    if let Some(id) = local.get("_id") {
+        document.insert("_id", id.clone());
+    }
+    let mut fields = BTreeMap::new();
+    for key in keys {
+        let local_time = local_version.fields.get(key).copied().unwrap_or(0);
+        let remote_time = remote_version.fields.get(key).copied().unwrap_or(0);
+        let from_remote = (remote_time, &remote_version.replica) > (local_time, &local_version.replica);
+        let value = if from_remote { remote.get(key) } else { local.get(key) };
+        if let Some(value) = value {
+            document.insert(key.clone(), value.clone());
+        }
+        fields.insert(key.clone(), local_time.max(remote_time));
+    }
+
+    let latest = if remote_version.is_later_than(local_version) { remote_version } else { local_version };
+    let version = DocumentVersion {
+        vector,
+        timestamp: latest.timestamp,
+        replica: latest.replica.clone(),
+        fields,
+        deleted: false,
+    };
+    (Some(document), version)
+}
+
+/// The resolved document is a new change of this replica.
+fn resolved_version(
+    local_version: &DocumentVersion,
+    remote_version: &DocumentVersion,
+    mut vector: BTreeMap<String, i64>,
+    document: Option<&Document>,
+    replica_id: &str,
+) -> DocumentVersion {
+    let timestamp = now_millis().max(local_version.timestamp.max(remote_version.timestamp) + 1);
+    *vector.entry(replica_id.to_string()).or_insert(0) += 1;
+    let mut fields: BTreeMap<String, i64> = local_version.fields.keys()
+        .chain(remote_version.fields.keys())
+        .map(|key| (key.clone(), timestamp))
+        .collect();
+    if let Some(document) = document {
+        for key in document.keys().filter(|key| *key != "_id") {
+            fields.insert(key.clone(), timestamp);
+        }
+    }
+    DocumentVersion {
+        vector,
+        timestamp,
+        replica: replica_id.to_string(),
+        fields,
+        deleted: document.is_none(),
+    }
+}
+
+/// Pull the changes of `from` into `to` since the last sync.
+pub(crate) fn pull(to: &Database, from: &Database, options: &SyncOptions) -> Result<ApplyChangesResult> {
+    let since = sync_checkpoint(to, &sync_replica_id(from)?)?;
+    let changes = sync_changes(from, since, options)?;
+    apply_changes(to, &changes, options)
+}
@@ -289,6 +289,38 @@ fn test_aggregate_sort() {
     assert_eq!(result[4].get("name").unwrap().as_str().unwrap(), "apple");
 }
 
+#[test]
+fn test_aggregate_stages_after_sort() {
+    let db = prepare_db("test-aggregate-stages-after-sort").unwrap();
+    let fruits = db.collection::<Document>("fruits");
+
+    let result = fruits
+        .aggregate(vec![
+            doc! {
+                "$sort": {
+                    "weight": -1,
+                },
+            },
+            doc! {
+                "$match": {
+                    "weight": {
+                        "$lt": 200,
+                    },
+                },
+            },
+            doc! {
+                "$limit": 2,
+            },
+        ])
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[0].get("name").unwrap().as_str().unwrap(), "orange");
+    assert_eq!(result[1].get("name").unwrap().as_str().unwrap(), "peach");
+}
+
 #[test]
 fn test_aggregate_unset() {
     let db = prepare_db("test-aggregate-unset").unwrap();
@@ -372,3 +404,238 @@ fn test_aggregate_abs() {
         assert_eq!(abs_weight, weight.abs());
     }
 }
+
+#[test]
+fn test_aggregate_string_and_add_operators() {
+    let db = project_prepare_db("test-aggregate-string-and-add").unwrap();
+    let people = db.collection::<Document>("people");
+    people.insert_many(vec![
+        doc! { "_id": 1, "first": "Ada", "last": "Lovelace", "age": 36 },
+        doc! { "_id": 2, "first": "Alan", "age": 41 },
+    ]).unwrap();
+
+    let result = people
+        .aggregate(vec![
+            doc! {
+                "$addFields": {
+                    "name": { "$concat": ["$first", " ", "$last"] },
+                    "upper": { "$toUpper": "$first" },
+                    "lower": { "$toLower": ["$last"] },
+                    "next_age": { "$add": ["$age", 1] },
+                },
+            }
+        ])
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(result[0].get_str("name").unwrap(), "Ada Lovelace");
+    assert_eq!(result[0].get_str("upper").unwrap(), "ADA");
+    assert_eq!(result[0].get_str("lower").unwrap(), "lovelace");
+    assert_eq!(result[0].get_i32("next_age").unwrap(), 37);
+    // the missing fields make $concat null
+    assert_eq!(result[1].get("name"), Some(&bson::Bson::Null));
+    assert_eq!(result[1].get_str("lower").unwrap(), "");
+}
+
+#[test]
+fn test_aggregate_sort_spill() {
+    let db = project_prepare_db("test-aggregate-sort-spill").unwrap();
+    let collection = db.collection::<Document>("scores");
+    collection.insert_many((0..2000i64).map(|i| doc! {
+        "_id": i,
+        "score": (i * 7919) % 100,
+        "padding": "x".repeat(64),
+    })).unwrap();
+
+    let temp_dir = std::env::temp_dir().join("test-aggregate-sort-spill");
+    let _ = std::fs::remove_dir_all(&temp_dir);
+    std::fs::create_dir_all(&temp_dir).unwrap();
+
+    let mut cursor = collection
+        .aggregate(vec![
+            doc! { "$sort": { "score": -1 } },
+            doc! { "$limit": 1500 },
+        ])
+        .memory_limit(16 * 1024)
+        .temp_dir(&temp_dir)
+        .run()
+        .unwrap();
+    assert!(cursor.advance().unwrap());
+    let mut docs = vec![cursor.deserialize_current().unwrap()];
+    assert!(std::fs::read_dir(&temp_dir).unwrap().count() > 1);
+    for doc in cursor.by_ref() {
+        docs.push(doc.unwrap());
+    }
+    drop(cursor);
+    assert_eq!(std::fs::read_dir(&temp_dir).unwrap().count(), 0);
+
+    assert_eq!(docs.len(), 1500);
+    for pair in docs.windows(2) {
+        let (a, b) = (pair[0].get_i64("score").unwrap(), pair[1].get_i64("score").unwrap());
+        assert!(a >= b);
+        // the ties keep the order of the scan
+        if a == b {
+            assert!(pair[0].get_i64("_id").unwrap() < pair[1].get_i64("_id").unwrap());
+        }
+    }
+
+    let in_memory = collection
+        .aggregate(vec![doc! { "$sort": { "score": -1 } }])
+        .run()
+        .unwrap()
+        .take(1500)
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(docs, in_memory);
+}
+
+#[test]
+fn test_aggregate_parallel() {
+    let db = project_prepare_db("test-aggregate-parallel").unwrap();
+    let collection = db.collection::<Document>("events");
+    collection.insert_many((0..1000i64).map(|i| doc! {
+        "_id": i,
+        "value": i % 7,
+    })).unwrap();
+
+    let pipelines = vec![
+        vec![
+            doc! { "$match": { "value": 3 } },
+            doc! { "$skip": 10 },
+            doc! { "$limit": 5 },
+        ],
+        vec![
+            doc! { "$match": { "value": { "$gt": 4 } } },
+            doc! { "$count": "count" },
+        ],
+        // the whole collection is scanned by the partitions
+        vec![
+            doc! { "$sort": { "value": -1, "_id": 1 } },
+            doc! { "$limit": 3 },
+        ],
+        // the partitions group their documents, and the groups are merged
+        vec![
+            doc! { "$match": { "value": { "$gt": 1 } } },
+            doc! { "$addFields": { "double": { "$add": ["$value", "$value"] } } },
+            doc! {
+                "$group": {
+                    "_id": "$value",
+                    "total": { "$sum": "$double" },
+                    "count": { "$sum": 1 },
+                },
+            },
+            doc! { "$sort": { "_id": -1 } },
+        ],
+        vec![
+            doc! { "$group": { "_id": null, "total": { "$sum": "$value" } } },
+        ],
+    ];
+    for pipeline in pipelines {
+        let expected = collection.aggregate(pipeline.clone()).run().unwrap()
+            .collect::<Result<Vec<Document>>>()
+            .unwrap();
+        assert!(!expected.is_empty());
+
+        let cursor = collection.aggregate(pipeline).max_parallelism(4).run().unwrap();
+        assert!(cursor.to_string().contains("RewindInput"), "{}", cursor);
+
+        // the partitions read the snapshot of the aggregation
+        collection.insert_one(doc! { "_id": -1i64, "value": 6 }).unwrap();
+        collection.delete_one(doc! { "_id": -1i64 }).unwrap();
+
+        let result = cursor.collect::<Result<Vec<Document>>>().unwrap();
+        assert_eq!(result, expected);
+    }
+
+    // the index is used by one thread
+    collection.create_index(polodb_core::IndexModel {
+        keys: doc! { "value": 1 },
+        options: None,
+    }).unwrap();
+    let cursor = collection.aggregate(vec![doc! { "$match": { "value": 3i64 } }]).max_parallelism(4).run().unwrap();
+    assert!(!cursor.to_string().contains("RewindInput"));
+    assert_eq!(cursor.count(), 143);
+}
+
+#[test]
+fn test_aggregate_group_spill() {
+    let db = project_prepare_db("test-aggregate-group-spill").unwrap();
+    let collection = db.collection::<Document>("orders");
+    collection.insert_many((0..3000i64).map(|i| doc! {
+        "_id": i,
+        "customer": format!("customer-{:04}", i % 1000),
+        "amount": i % 10,
+    })).unwrap();
+
+    let temp_dir = std::env::temp_dir().join("test-aggregate-group-spill");
+    let _ = std::fs::remove_dir_all(&temp_dir);
+    std::fs::create_dir_all(&temp_dir).unwrap();
+
+    let pipeline = vec![
+        doc! {
+            "$group": {
+                "_id": "$customer",
+                "total": { "$sum": "$amount" },
+                "count": { "$sum": 1 },
+            },
+        },
+    ];
+    let mut cursor = collection
+        .aggregate(pipeline.clone())
+        .memory_limit(8 * 1024)
+        .temp_dir(&temp_dir)
+        .run()
+        .unwrap();
+    assert!(cursor.advance().unwrap());
+    let mut groups = vec![cursor.deserialize_current().unwrap()];
+    assert!(std::fs::read_dir(&temp_dir).unwrap().count() > 1);
+    for doc in cursor.by_ref() {
+        groups.push(doc.unwrap());
+    }
+    drop(cursor);
+    assert_eq!(std::fs::read_dir(&temp_dir).unwrap().count(), 0);
+
+    assert_eq!(groups.len(), 1000);
+    for (i, group) in groups.iter().enumerate() {
+        let i = i as i64;
+        assert_eq!(group.get_str("_id").unwrap(), format!("customer-{:04}", i));
+        assert_eq!(group.get_i64("total").unwrap(), i % 10 + (i + 1000) % 10 + (i + 2000) % 10);
+        assert_eq!(group.get_i64("count").unwrap(), 3);
+    }
+
+    let in_memory = collection.aggregate(pipeline).run().unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(groups, in_memory);
+}
+
+#[test]
+fn test_distinct() {
+    let db = project_prepare_db("test-distinct").unwrap();
+    let collection = db.collection::<Document>("orders");
+    collection.insert_many((0..2000i64).map(|i| doc! {
+        "_id": i,
+        "customer": format!("customer-{:04}", i % 500),
+        "open": i % 2 == 0,
+    })).unwrap();
+
+    let temp_dir = std::env::temp_dir().join("test-distinct");
+    let _ = std::fs::remove_dir_all(&temp_dir);
+    std::fs::create_dir_all(&temp_dir).unwrap();
+
+    let customers = collection
+        .distinct("customer", doc! { "open": true })
+        .memory_limit(4 * 1024)
+        .temp_dir(&temp_dir)
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<bson::Bson>>>()
+        .unwrap();
+    let expected = (0..500i64)
+        .filter(|i| i % 2 == 0)
+        .map(|i| bson::Bson::String(format!("customer-{:04}", i)))
+        .collect::<Vec<_>>();
+    assert_eq!(customers, expected);
+    assert_eq!(std::fs::read_dir(&temp_dir).unwrap().count(), 0);
+}
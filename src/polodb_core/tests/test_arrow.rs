@@ -0,0 +1,163 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+#![cfg(feature = "arrow")]
+
+use std::sync::Arc;
+use polodb_core::{CollectionT, Error};
+use polodb_core::arrow::array::{Array, BinaryArray, Float64Array, Int32Array, Int64Array, ListArray, StringArray, StructArray, TimestampMillisecondArray};
+use polodb_core::arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use polodb_core::arrow::record_batch::RecordBatchReader;
+use polodb_core::arrow_export::ArrowExportOptions;
+use polodb_core::bson::{doc, oid::ObjectId, spec::BinarySubtype, Binary, DateTime, Document};
+use polodb_core::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+mod common;
+
+use common::prepare_db;
+
+#[test]
+fn test_export_arrow_infer_schema() {
+    let db = prepare_db("test-export-arrow-infer-schema").unwrap();
+    let col = db.collection::<Document>("events");
+    let oid = ObjectId::new();
+    col.insert_many(vec![
+        doc! {
+            "_id": oid,
+            "count": 1,
+            "size": 1,
+            "user": { "name": "Alice", "age": 30 },
+            "tags": ["a", "b"],
+            "at": DateTime::from_millis(1000),
+            "raw": Binary { subtype: BinarySubtype::Generic, bytes: vec![1, 2] },
+        },
+        doc! {
+            "_id": 2,
+            "count": 5_000_000_000_i64,
+            "size": 1.5,
+            "user": { "name": "Bob", "city": "Paris" },
+            "tags": [],
+        },
+    ]).unwrap();
+
+    let reader = db.export_arrow("events", ArrowExportOptions::default()).unwrap();
+    let schema = reader.schema();
+    assert_eq!(schema.field_with_name("_id").unwrap().data_type(), &DataType::Utf8);
+    assert_eq!(schema.field_with_name("count").unwrap().data_type(), &DataType::Int64);
+    assert_eq!(schema.field_with_name("size").unwrap().data_type(), &DataType::Float64);
+    assert_eq!(
+        schema.field_with_name("at").unwrap().data_type(),
+        &DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+    );
+
+    let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(batches.len(), 1);
+    let batch = &batches[0];
+    assert_eq!(batch.num_rows(), 2);
+
+    let ids = batch.column_by_name("_id").unwrap().as_any().downcast_ref::<StringArray>().unwrap();
+    assert_eq!(ids.value(0), oid.to_hex());
+    assert_eq!(ids.value(1), "2");
+
+    let counts = batch.column_by_name("count").unwrap().as_any().downcast_ref::<Int64Array>().unwrap();
+    assert_eq!(counts.values(), &[1, 5_000_000_000]);
+    let sizes = batch.column_by_name("size").unwrap().as_any().downcast_ref::<Float64Array>().unwrap();
+    assert_eq!(sizes.values(), &[1.0, 1.5]);
+
+    let users = batch.column_by_name("user").unwrap().as_any().downcast_ref::<StructArray>().unwrap();
+    let names = users.column_by_name("name").unwrap().as_any().downcast_ref::<StringArray>().unwrap();
+    assert_eq!(names.value(1), "Bob");
+    let ages = users.column_by_name("age").unwrap().as_any().downcast_ref::<Int32Array>().unwrap();
+    assert_eq!(ages.value(0), 30);
+    assert!(ages.is_null(1));
+
+    let tags = batch.column_by_name("tags").unwrap().as_any().downcast_ref::<ListArray>().unwrap();
+    assert_eq!(tags.value_length(0), 2);
+    assert_eq!(tags.value_length(1), 0);
+
+    let at = batch.column_by_name("at").unwrap().as_any().downcast_ref::<TimestampMillisecondArray>().unwrap();
+    assert_eq!(at.value(0), 1000);
+    assert!(at.is_null(1));
+
+    let raw = batch.column_by_name("raw").unwrap().as_any().downcast_ref::<BinaryArray>().unwrap();
+    assert_eq!(raw.value(0), &[1, 2]);
+}
+
+#[test]
+fn test_export_arrow_with_schema_and_pipeline() {
+    let db = prepare_db("test-export-arrow-schema-pipeline").unwrap();
+    let col = db.collection::<Document>("orders");
+    col.insert_many((0..10).map(|i| doc! { "_id": i, "item": if i % 2 == 0 { "apple" } else { "pear" }, "qty": i }))
+        .unwrap();
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("item", DataType::Utf8, false),
+        Field::new("qty", DataType::Int64, true),
+    ]));
+    let options = ArrowExportOptions::builder()
+        .filter(doc! { "qty": { "$lt": 5 } })
+        .pipeline(vec![
+            doc! { "$sort": { "qty": -1 } },
+            doc! { "$limit": 2 },
+        ])
+        .schema(schema.clone())
+        .batch_size(1)
+        .build();
+    let batches = db.export_arrow("orders", options).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(batches.len(), 2);
+    let rows = batches.iter()
+        .map(|batch| {
+            assert_eq!(batch.schema(), schema);
+            let items = batch.column(0).as_any().downcast_ref::<StringArray>().unwrap();
+            let qty = batch.column(1).as_any().downcast_ref::<Int64Array>().unwrap();
+            (items.value(0).to_string(), qty.value(0))
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(rows, vec![("apple".to_string(), 4), ("pear".to_string(), 3)]);
+
+    // the value doesn't fit the schema
+    let schema = Arc::new(Schema::new(vec![Field::new("item", DataType::Int32, true)]));
+    let options = ArrowExportOptions::builder().schema(schema).build();
+    let mut reader = db.export_arrow("orders", options).unwrap();
+    let err = reader.next().unwrap().unwrap_err();
+    assert!(err.to_string().contains("the value of 'item' is not Int32"));
+}
+
+#[test]
+fn test_export_parquet() {
+    let db = prepare_db("test-export-parquet").unwrap();
+    let col = db.collection::<Document>("metrics");
+    col.insert_many((0..100).map(|i| doc! { "_id": i, "value": i as f64 / 2.0, "tags": ["x"], "extra": null }))
+        .unwrap();
+
+    let path = std::env::temp_dir().join("test-export-parquet.parquet");
+    let file = std::fs::File::create(&path).unwrap();
+    let count = db.export_parquet("metrics", file, ArrowExportOptions::default()).unwrap();
+    assert_eq!(count, 100);
+
+    let file = std::fs::File::open(&path).unwrap();
+    let reader = ParquetRecordBatchReaderBuilder::try_new(file).unwrap().build().unwrap();
+    let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    let rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+    assert_eq!(rows, 100);
+    let values = batches[0].column_by_name("value").unwrap().as_any().downcast_ref::<Float64Array>().unwrap();
+    assert_eq!(values.value(3), 1.5);
+
+    let err = db.export_parquet("metrics", std::io::sink(), ArrowExportOptions::builder()
+        .schema(Arc::new(Schema::new(vec![Field::new("value", DataType::Boolean, true)])))
+        .build()
+    ).unwrap_err();
+    assert!(matches!(err, Error::ArrowError(_)));
+}
@@ -0,0 +1,53 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(feature = "auth")]
+
+use polodb_core::{Database, Error, ErrorCategory};
+use polodb_core::auth::{Action, Role, User};
+
+#[test]
+fn test_users() {
+    let db = Database::open_memory().unwrap();
+    let users = db.database("tenant").users();
+    users.create("bob", "pw", vec![Role::read("shop")]).unwrap();
+    users.create("alice", "pw", vec![Role::admin()]).unwrap();
+    assert!(matches!(users.create("bob", "pw", vec![]), Err(Error::UserAlreadyExists(_))));
+
+    // the users are shared by the named databases
+    let names: Vec<String> = db.users().list().unwrap().into_iter().map(|user| user.name).collect();
+    assert_eq!(names, vec!["alice", "bob"]);
+
+    users.set_roles("bob", vec![Role::read_write("shop")]).unwrap();
+    users.set_password("bob", "new").unwrap();
+    let err = users.authenticate("bob", "pw").unwrap_err();
+    assert_eq!(err.category(), ErrorCategory::Unauthorized);
+    let bob = users.authenticate("bob", "new").unwrap();
+    assert_eq!(bob, User {
+        name: "bob".to_string(),
+        roles: vec![Role::read_write("shop")],
+    });
+    assert!(bob.is_allowed(Action::Write, Some("shop"), Some("orders")));
+    assert!(matches!(users.authenticate("nobody", "pw"), Err(Error::AuthenticationFailed)));
+
+    let token = users.create_token("bob").unwrap();
+    assert_eq!(users.authenticate_token(&token).unwrap().name, "bob");
+    assert_eq!(users.revoke_tokens("bob").unwrap(), 1);
+    assert!(users.authenticate_token(&token).is_err());
+
+    assert!(users.drop("bob").unwrap());
+    assert!(!users.drop("bob").unwrap());
+    assert_eq!(users.get("bob").unwrap(), None);
+    assert!(matches!(users.set_roles("bob", vec![]), Err(Error::UserNotFound(_))));
+}
@@ -0,0 +1,156 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use polodb_core::{CollectionT, Database, Error, IndexModel};
+use polodb_core::bson::{doc, Document};
+use polodb_core::options::CreateCollectionOptions;
+
+mod common;
+
+use common::prepare_db;
+
+fn ids(db: &Database, name: &str, filter: Document) -> Vec<i32> {
+    db.collection::<Document>(name)
+        .find(filter)
+        .run()
+        .unwrap()
+        .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+        .collect()
+}
+
+#[test]
+fn test_capped_max() {
+    let db = prepare_db("test-capped-max").unwrap();
+    db.create_collection_with_options(
+        "logs",
+        CreateCollectionOptions::builder().capped(true).max(3).build(),
+    ).unwrap();
+    let col = db.collection::<Document>("logs");
+    col.create_index(IndexModel {
+        keys: doc! { "level": 1 },
+        options: None,
+    }).unwrap();
+
+    // the ids are not in insertion order
+    for id in [5, 1, 4, 2, 3] {
+        col.insert_one(doc! { "_id": id, "level": id % 2 }).unwrap();
+    }
+
+    assert_eq!(col.count_documents().unwrap(), 3);
+    assert_eq!(ids(&db, "logs", doc! {}), vec![4, 2, 3]);
+    assert_eq!(ids(&db, "logs", doc! { "_id": { "$gt": 2 } }), vec![4, 3]);
+    assert!(col.find_one(doc! { "_id": 5 }).unwrap().is_none());
+    // the index entries of the evicted documents are removed too
+    let odd: Vec<Document> = col.find(doc! { "level": 1 }).run().unwrap()
+        .map(|doc| doc.unwrap())
+        .collect();
+    assert_eq!(odd.len(), 1);
+    assert_eq!(odd[0].get_i32("_id").unwrap(), 3);
+
+    let result = col.insert_one(doc! { "_id": 3 });
+    assert!(matches!(result, Err(Error::DuplicateKey(_))));
+}
+
+#[test]
+fn test_capped_size() {
+    let db = prepare_db("test-capped-size").unwrap();
+    db.create_collection_with_options(
+        "logs",
+        CreateCollectionOptions::builder().capped(true).size(1024).build(),
+    ).unwrap();
+    let col = db.collection::<Document>("logs");
+    let payload = "x".repeat(200);
+
+    for id in 0..20 {
+        col.insert_one(doc! { "_id": id, "payload": payload.clone() }).unwrap();
+    }
+
+    // every document is about 220 bytes
+    assert_eq!(ids(&db, "logs", doc! {}), vec![16, 17, 18, 19]);
+
+    let result = col.insert_one(doc! { "_id": 100, "payload": "x".repeat(2000) });
+    assert!(matches!(result, Err(Error::DocumentTooLarge { limit: 1024, .. })));
+    assert_eq!(col.count_documents().unwrap(), 4);
+}
+
+#[test]
+fn test_capped_immutable() {
+    let db = prepare_db("test-capped-immutable").unwrap();
+    db.create_collection_with_options(
+        "logs",
+        CreateCollectionOptions::builder().capped(true).max(10).build(),
+    ).unwrap();
+    let col = db.collection::<Document>("logs");
+    col.insert_one(doc! { "_id": 1, "value": 1 }).unwrap();
+
+    let result = col.update_one(doc! { "_id": 1 }, doc! { "$set": { "value": 2 } });
+    assert!(matches!(result, Err(Error::CappedCollectionImmutable(_))));
+    let result = col.delete_one(doc! { "_id": 1 });
+    assert!(matches!(result, Err(Error::CappedCollectionImmutable(_))));
+    let result = col.delete_many(doc! {});
+    assert!(matches!(result, Err(Error::CappedCollectionImmutable(_))));
+
+    col.drop().unwrap();
+    db.create_collection("logs").unwrap();
+    assert_eq!(col.count_documents().unwrap(), 0);
+    col.insert_one(doc! { "_id": 1, "value": 1 }).unwrap();
+    col.delete_one(doc! { "_id": 1 }).unwrap();
+}
+
+#[test]
+fn test_capped_options() {
+    let db = prepare_db("test-capped-options").unwrap();
+    let result = db.create_collection_with_options(
+        "a",
+        CreateCollectionOptions::builder().capped(true).build(),
+    );
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+    let result = db.create_collection_with_options(
+        "b",
+        CreateCollectionOptions::builder().max(10).build(),
+    );
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+    let result = db.create_collection_with_options(
+        "c",
+        CreateCollectionOptions::builder().capped(true).size(0).build(),
+    );
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+}
+
+#[test]
+fn test_capped_natural() {
+    let db = prepare_db("test-capped-natural").unwrap();
+    db.create_collection_with_options(
+        "logs",
+        CreateCollectionOptions::builder().capped(true).max(4).build(),
+    ).unwrap();
+    let col = db.collection::<Document>("logs");
+    for id in [5, 1, 4, 2, 3] {
+        col.insert_one(doc! { "_id": id }).unwrap();
+    }
+
+    let natural = |order: i32, limit: u64| -> Vec<i32> {
+        col.find(doc! {})
+            .sort(doc! { "$natural": order })
+            .limit(limit)
+            .run()
+            .unwrap()
+            .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+            .collect()
+    };
+    assert_eq!(natural(1, 10), vec![1, 4, 2, 3]);
+    // the last inserted ones
+    assert_eq!(natural(-1, 2), vec![3, 2]);
+}
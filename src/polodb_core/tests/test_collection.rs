@@ -13,7 +13,8 @@
 // limitations under the License.
 
 use polodb_core::bson::{Document, doc};
-use polodb_core::{CollectionT, Result};
+use polodb_core::{CollectionT, Database, Error, IndexModel, IndexOptions, Result};
+use polodb_core::options::{CloneCollectionOptions, CreateCollectionOptions, ListCollectionsOptions};
 mod common;
 
 use common::{
@@ -76,6 +77,311 @@ fn test_create_collection_and_drop() {
     });
 }
 
+#[test]
+fn test_drop_collection_removes_index_entries() {
+    let db = prepare_db("test-drop-collection-index-entries").unwrap();
+    let collection = db.collection::<Document>("test");
+    let unique_index = || IndexModel {
+        keys: doc! {
+            "name": 1,
+        },
+        options: Some(IndexOptions {
+            unique: Some(true),
+            ..Default::default()
+        }),
+    };
+    collection.create_index(unique_index()).unwrap();
+    collection.insert_many(vec![
+        doc! { "name": "Apple" },
+        doc! { "name": "Banana" },
+    ]).unwrap();
+
+    let result = collection.drop().unwrap();
+    assert_eq!(result.deleted_count, 2);
+    assert_eq!(result.deleted_index_entries, 2);
+    assert!(result.reclaimable_bytes > 0);
+
+    // nothing is left for the collection created with the same name
+    collection.create_index(unique_index()).unwrap();
+    collection.insert_one(doc! { "name": "Apple" }).unwrap();
+    assert_eq!(collection.count_documents().unwrap(), 1);
+
+    let result = db.collection::<Document>("missing").drop().unwrap();
+    assert_eq!(result.deleted_count, 0);
+}
+
+#[test]
+fn test_list_collections() {
+    let db = prepare_db("test-list-collections").unwrap();
+    let logs = db.collection::<Document>("logs");
+    logs.create_index(IndexModel {
+        keys: doc! {
+            "level": 1,
+        },
+        options: None,
+    }).unwrap();
+    let docs: Vec<Document> = (0..3000).map(|i| doc! {
+        "level": i % 3,
+        "message": format!("message {}", i),
+    }).collect();
+    logs.insert_many(docs).unwrap();
+    db.create_collection_with_options("log_tail", CreateCollectionOptions::builder()
+        .capped(true)
+        .max(10)
+        .build()
+    ).unwrap();
+    db.create_view("errors", "logs", vec![doc! { "$match": { "level": 2 } }]).unwrap();
+    db.collection::<Document>("users").insert_one(doc! { "name": "Alice" }).unwrap();
+    db.vacuum().unwrap();
+
+    let infos = db.list_collections(ListCollectionsOptions::default()).unwrap();
+    let names: Vec<&str> = infos.iter().map(|info| info.name.as_str()).collect();
+    assert_eq!(names, vec!["errors", "log_tail", "logs", "users"]);
+
+    let logs_info = &infos[2];
+    assert_eq!(logs_info.index_names, vec!["level_1".to_string()]);
+    assert!(logs_info.approximate_size > 0);
+    // the count is extrapolated beyond the sample
+    assert!(logs_info.approximate_count > 2000 && logs_info.approximate_count < 4000);
+    assert_eq!(infos[3].approximate_count, 1);
+
+    assert_eq!(infos[1].options.capped, Some(true));
+    assert_eq!(infos[1].options.max, Some(10));
+    assert_eq!(infos[0].options.view_on.as_deref(), Some("logs"));
+    assert_eq!(infos[0].approximate_count, 0);
+
+    let infos = db.list_collections(ListCollectionsOptions::builder()
+        .name_prefix("log")
+        .build()
+    ).unwrap();
+    let names: Vec<&str> = infos.iter().map(|info| info.name.as_str()).collect();
+    assert_eq!(names, vec!["log_tail", "logs"]);
+}
+
+#[test]
+fn test_collection_stats() {
+    let db = prepare_db("test-collection-stats").unwrap();
+    let collection = db.collection::<Document>("test");
+    collection.create_index(IndexModel {
+        keys: doc! {
+            "num": 1,
+        },
+        options: None,
+    }).unwrap();
+    let docs: Vec<Document> = (0..100).map(|i| doc! {
+        "_id": i,
+        "num": i,
+    }).collect();
+    let doc_size = polodb_core::bson::to_vec(&docs[0]).unwrap().len() as u64;
+    collection.insert_many(docs).unwrap();
+
+    let stats = collection.stats().unwrap();
+    assert_eq!(stats.count, 100);
+    assert_eq!(stats.size, doc_size * 100);
+    assert_eq!(stats.avg_obj_size, doc_size);
+    assert_eq!(stats.index_count, 1);
+    assert!(stats.index_sizes["num_1"] > 0);
+    assert_eq!(stats.total_index_size, stats.index_sizes["num_1"]);
+    assert_eq!(stats.tombstones, 0);
+
+    collection.delete_many(doc! { "_id": { "$lt": 50 } }).unwrap();
+    let stats = collection.stats().unwrap();
+    assert_eq!(stats.count, 50);
+    // the documents and their index entries
+    assert_eq!(stats.tombstones, 100);
+
+    db.vacuum().unwrap();
+    let stats = collection.stats().unwrap();
+    assert_eq!(stats.count, 50);
+    assert_eq!(stats.tombstones, 0);
+
+    let stats = db.collection::<Document>("missing").stats().unwrap();
+    assert_eq!(stats.count, 0);
+}
+
+#[test]
+fn test_rename_collection() {
+    let db = prepare_db("test-rename-collection").unwrap();
+    let collection = db.collection::<Document>("old");
+    collection.create_index(IndexModel {
+        keys: doc! {
+            "name": 1,
+        },
+        options: Some(IndexOptions {
+            unique: Some(true),
+            ..Default::default()
+        }),
+    }).unwrap();
+    collection.insert_many(vec![
+        doc! { "name": "Apple" },
+        doc! { "name": "Banana" },
+    ]).unwrap();
+
+    let mut cursor = collection.find(doc! {}).run().unwrap();
+    assert!(cursor.advance().unwrap());
+    db.rename_collection("old", "new").unwrap();
+
+    // the cursor opened before the rename keeps reading the documents
+    assert!(cursor.advance().unwrap());
+    assert!(!cursor.advance().unwrap());
+
+    let names = db.list_collection_names().unwrap();
+    assert_eq!(names, vec!["new".to_string()]);
+
+    let renamed = db.collection::<Document>("new");
+    assert_eq!(renamed.count_documents().unwrap(), 2);
+    let found = renamed.find_one(doc! { "name": "Banana" }).unwrap();
+    assert!(found.is_some());
+    // the unique index is kept
+    assert!(renamed.insert_one(doc! { "name": "Apple" }).is_err());
+
+    // the old name can be reused by a new collection
+    let reused = db.collection::<Document>("old");
+    assert_eq!(reused.count_documents().unwrap(), 0);
+    reused.insert_one(doc! { "name": "Apple" }).unwrap();
+    assert_eq!(reused.count_documents().unwrap(), 1);
+    assert_eq!(renamed.count_documents().unwrap(), 2);
+
+    reused.drop().unwrap();
+    assert_eq!(renamed.count_documents().unwrap(), 2);
+}
+
+#[test]
+fn test_rename_collection_errors() {
+    let db = prepare_db("test-rename-collection-errors").unwrap();
+    db.collection::<Document>("a").insert_one(doc! { "x": 1 }).unwrap();
+    db.collection::<Document>("b").insert_one(doc! { "x": 2 }).unwrap();
+
+    let result = db.rename_collection("missing", "c");
+    assert!(matches!(result, Err(Error::CollectionNotFound(_))));
+
+    let result = db.rename_collection("a", "b");
+    assert!(matches!(result, Err(Error::CollectionAlreadyExits(_))));
+
+    let result = db.rename_collection("a", "$c");
+    assert!(matches!(result, Err(Error::IllegalCollectionName(_))));
+
+    // renaming back restores the original storage
+    db.rename_collection("a", "c").unwrap();
+    db.rename_collection("c", "a").unwrap();
+    let found = db.collection::<Document>("a").find_one(doc! {}).unwrap().unwrap();
+    assert_eq!(found.get_i32("x").unwrap(), 1);
+}
+
+#[test]
+fn test_clone_collection() {
+    let db = prepare_db("test-clone-collection").unwrap();
+    let collection = db.collection::<Document>("src");
+    collection.create_index(IndexModel {
+        keys: doc! {
+            "name": 1,
+        },
+        options: Some(IndexOptions {
+            unique: Some(true),
+            ..Default::default()
+        }),
+    }).unwrap();
+    collection.insert_many(vec![
+        doc! { "name": "Apple", "price": 100 },
+        doc! { "name": "Banana", "price": 200 },
+    ]).unwrap();
+
+    let count = db.clone_collection("src", "dst").unwrap();
+    assert_eq!(count, 2);
+
+    let cloned = db.collection::<Document>("dst");
+    let found = cloned.find_one(doc! { "name": "Banana" }).unwrap().unwrap();
+    assert_eq!(found.get_i32("price").unwrap(), 200);
+    assert!(cloned.insert_one(doc! { "name": "Apple" }).is_err());
+
+    // the copy is independent of the source
+    cloned.insert_one(doc! { "name": "Orange" }).unwrap();
+    assert_eq!(collection.count_documents().unwrap(), 2);
+    assert_eq!(cloned.count_documents().unwrap(), 3);
+
+    let result = db.clone_collection("src", "dst");
+    assert!(matches!(result, Err(Error::CollectionAlreadyExits(_))));
+    let result = db.clone_collection("missing", "other");
+    assert!(matches!(result, Err(Error::CollectionNotFound(_))));
+}
+
+#[test]
+fn test_clone_collection_to_another_database() {
+    let db = prepare_db("test-clone-collection-source").unwrap();
+    let target = Database::open_memory().unwrap();
+    let collection = db.collection::<Document>("src");
+    collection.create_index(IndexModel {
+        keys: doc! {
+            "name": 1,
+        },
+        options: Some(IndexOptions {
+            unique: Some(true),
+            ..Default::default()
+        }),
+    }).unwrap();
+    collection.insert_many(vec![
+        doc! { "name": "Apple" },
+        doc! { "name": "Banana" },
+    ]).unwrap();
+
+    let options = CloneCollectionOptions::builder()
+        .indexes(false)
+        .build();
+    let count = db.clone_collection_to("src", &target, "fixture", options).unwrap();
+    assert_eq!(count, 2);
+
+    let cloned = target.collection::<Document>("fixture");
+    assert_eq!(cloned.count_documents().unwrap(), 2);
+    // the indexes are not copied
+    cloned.insert_one(doc! { "name": "Apple" }).unwrap();
+    assert_eq!(cloned.count_documents().unwrap(), 3);
+}
+
+#[test]
+fn test_clone_collection_options() {
+    let db = prepare_db("test-clone-collection-options").unwrap();
+    db.create_collection_with_options("events", CreateCollectionOptions::builder()
+        .capped(true)
+        .max(3)
+        .validator(doc! {
+            "$jsonSchema": {
+                "bsonType": "object",
+                "required": ["n"],
+            },
+        })
+        .build()
+    ).unwrap();
+    let events = db.collection::<Document>("events");
+    for i in (1..=5).rev() {
+        events.insert_one(doc! { "_id": i, "n": i }).unwrap();
+    }
+
+    let count = db.clone_collection("events", "cloned").unwrap();
+    assert_eq!(count, 3);
+
+    let infos = db.list_collections(ListCollectionsOptions::builder()
+        .name_prefix("cloned")
+        .build()
+    ).unwrap();
+    assert_eq!(infos[0].options.capped, Some(true));
+    assert_eq!(infos[0].options.max, Some(3));
+    assert!(infos[0].options.validator.is_some());
+
+    let cloned = db.collection::<Document>("cloned");
+    let result = cloned.insert_one(doc! { "_id": 10 });
+    assert!(matches!(result, Err(Error::DocumentValidationFailed(_))));
+    // the documents are copied in the insertion order, so the oldest one is removed
+    cloned.insert_one(doc! { "_id": 0, "n": 0 }).unwrap();
+    let ids: Vec<i32> = cloned.find(doc! {}).run().unwrap()
+        .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+        .collect();
+    assert_eq!(ids, vec![0, 1, 2]);
+
+    db.create_view("recent", "events", vec![doc! { "$match": { "n": { "$gt": 2 } } }]).unwrap();
+    let result = db.clone_collection("recent", "recent_copy");
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+}
+
 #[test]
 fn test_create_collection_with_number_pkey() {
     vec![
@@ -0,0 +1,136 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(feature = "csv")]
+
+use polodb_core::{CollectionT, Error, Result};
+use polodb_core::bson::{doc, oid::ObjectId, DateTime, Document};
+use polodb_core::options::{CsvExportOptions, CsvImportOptions, CsvType};
+
+mod common;
+
+use common::prepare_db;
+
+#[test]
+fn test_export_csv() {
+    let db = prepare_db("test-csv-export").unwrap();
+    let col = db.collection::<Document>("users");
+    let oid = ObjectId::parse_str("65a1b2c3d4e5f6a7b8c9d0e1").unwrap();
+    col.insert_many(vec![
+        doc! {
+            "_id": oid,
+            "name": "Alice, Jr.",
+            "address": { "city": "Paris", "geo": { "lat": 48.85 } },
+            "tags": ["a", "b"],
+            "joined": DateTime::from_millis(1_704_164_645_000),
+        },
+        doc! { "_id": 2, "name": "Bob", "tags": [] },
+    ]).unwrap();
+
+    // the paths of the first document
+    let mut output = Vec::new();
+    assert_eq!(db.export_csv("users", &mut output, CsvExportOptions::default()).unwrap(), 2);
+    assert_eq!(String::from_utf8(output).unwrap(), concat!(
+        "_id,name,address.city,address.geo.lat,tags,joined\n",
+        "65a1b2c3d4e5f6a7b8c9d0e1,\"Alice, Jr.\",Paris,48.85,\"[\"\"a\"\",\"\"b\"\"]\",2024-01-02T03:04:05Z\n",
+        "2,Bob,,,[],\n",
+    ));
+
+    let mut output = Vec::new();
+    let options = CsvExportOptions::builder()
+        .fields(["name", "tags.1", "address.city"])
+        .filter(doc! { "_id": 2 })
+        .delimiter(b';')
+        .header(false)
+        .build();
+    assert_eq!(db.export_csv("users", &mut output, options).unwrap(), 1);
+    assert_eq!(String::from_utf8(output).unwrap(), "Bob;;\n");
+}
+
+#[test]
+fn test_import_csv() {
+    let db = prepare_db("test-csv-import").unwrap();
+    let input = concat!(
+        "name,address.city,zip,age,score,active,joined,tags\n",
+        "alice,Paris,007,30,9.5,true,2024-01-02,\"[1, 2]\"\n",
+        "bob,,12345,99999999999,-1,FALSE,2024-01-02T03:04:05Z,[]\n",
+    );
+    let options = CsvImportOptions::builder()
+        .column_type("joined", CsvType::Date)
+        .column_type("tags", CsvType::Json)
+        .build();
+    assert_eq!(db.import_csv("users", input.as_bytes(), options).unwrap(), 2);
+
+    let col = db.collection::<Document>("users");
+    let alice = col.find_one(doc! { "name": "alice" }).unwrap().unwrap();
+    assert_eq!(alice.get_document("address").unwrap(), &doc! { "city": "Paris" });
+    // the leading zeros are kept
+    assert_eq!(alice.get_str("zip").unwrap(), "007");
+    assert_eq!(alice.get_i32("age").unwrap(), 30);
+    assert_eq!(alice.get_f64("score").unwrap(), 9.5);
+    assert!(alice.get_bool("active").unwrap());
+    assert_eq!(alice.get_datetime("joined").unwrap(), &DateTime::parse_rfc3339_str("2024-01-02T00:00:00Z").unwrap());
+    assert_eq!(alice.get_array("tags").unwrap().len(), 2);
+
+    let bob = col.find_one(doc! { "name": "bob" }).unwrap().unwrap();
+    // the empty cells are skipped
+    assert!(!bob.contains_key("address"));
+    assert_eq!(bob.get_i32("zip").unwrap(), 12345);
+    assert_eq!(bob.get_i64("age").unwrap(), 99999999999);
+    assert_eq!(bob.get_i32("score").unwrap(), -1);
+    assert!(!bob.get_bool("active").unwrap());
+
+    // round trip
+    let mut output = Vec::new();
+    let options = CsvExportOptions::builder()
+        .fields(["name", "zip"])
+        .build();
+    db.export_csv("users", &mut output, options).unwrap();
+    let options = CsvImportOptions::builder()
+        .column_type("zip", CsvType::String)
+        .drop(true)
+        .build();
+    assert_eq!(db.import_csv("users", output.as_slice(), options).unwrap(), 2);
+    let zips: Vec<String> = col.find(doc! {}).run().unwrap()
+        .map(|doc| doc.unwrap().get_str("zip").unwrap().to_string())
+        .collect();
+    assert_eq!(zips, vec!["007".to_string(), "12345".to_string()]);
+}
+
+#[test]
+fn test_import_csv_errors() {
+    let db = prepare_db("test-csv-import-errors").unwrap();
+    let col = db.collection::<Document>("users");
+
+    // nothing is inserted if a row is invalid
+    let input = "name,age\nalice,30\nbob,old\n";
+    let options = CsvImportOptions::builder()
+        .column_type("age", CsvType::Int32)
+        .build();
+    let err = db.import_csv("users", input.as_bytes(), options).unwrap_err();
+    match err {
+        Error::ParseError(message) => assert!(message.contains("line 3, column 'age'"), "{}", message),
+        err => panic!("unexpected error: {:?}", err),
+    }
+    assert_eq!(col.count_documents().unwrap(), 0);
+
+    let input = "name,age\nalice,30,extra\n";
+    let err = db.import_csv("users", input.as_bytes(), CsvImportOptions::default()).unwrap_err();
+    assert!(matches!(err, Error::ParseError(_)));
+
+    let input = "a,a.b\n1,2\n";
+    let err = db.import_csv("users", input.as_bytes(), CsvImportOptions::default()).unwrap_err();
+    assert!(matches!(err, Error::ParseError(_)));
+    assert_eq!(col.find(doc! {}).run().unwrap().collect::<Result<Vec<Document>>>().unwrap().len(), 0);
+}
@@ -0,0 +1,109 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use polodb_core::{CollectionT, Error};
+use polodb_core::bson::{doc, DateTime, Document};
+use polodb_core::date::{DateBucket, DateRange, DateUnit, TimeZone};
+
+mod common;
+
+use common::prepare_db;
+
+fn date(s: &str) -> DateTime {
+    DateTime::parse_rfc3339_str(s).unwrap()
+}
+
+fn ids(docs: impl Iterator<Item = polodb_core::Result<Document>>) -> Vec<i32> {
+    docs.map(|doc| doc.unwrap().get_i32("_id").unwrap()).collect()
+}
+
+fn prepare_orders(name: &str) -> polodb_core::Database {
+    let db = prepare_db(name).unwrap();
+    db.collection::<Document>("orders").insert_many(vec![
+        doc! { "_id": 1, "at": date("2024-03-09T23:30:00Z") },
+        doc! { "_id": 2, "at": date("2024-03-10T08:00:00Z") },
+        doc! { "_id": 3, "at": date("2024-03-10T22:30:00Z") },
+        doc! { "_id": 4, "at": date("2024-04-02T12:00:00Z") },
+    ]).unwrap();
+    db
+}
+
+#[test]
+fn test_date_bucket_filter() {
+    let db = prepare_orders("test-date-bucket-filter");
+    let orders = db.collection::<Document>("orders");
+
+    let found = orders.find(doc! {
+        "at": { "$dateBucket": { "date": date("2024-03-10T12:00:00Z"), "unit": "day" } },
+    }).run().unwrap();
+    assert_eq!(ids(found), vec![2, 3]);
+
+    // 2024-03-10 in UTC+02:00 is from 2024-03-09T22:00:00Z
+    let found = orders.find(doc! {
+        "at": { "$dateBucket": { "date": date("2024-03-10T12:00:00Z"), "unit": "day", "timezone": "+02:00" } },
+    }).run().unwrap();
+    assert_eq!(ids(found), vec![1, 2]);
+
+    let found = orders.find(doc! {
+        "at": { "$dateBucket": { "date": date("2024-03-01T00:00:00Z"), "unit": "month" } },
+        "_id": { "$gt": 1 },
+    }).run().unwrap();
+    assert_eq!(ids(found), vec![2, 3]);
+
+    let result = orders.find(doc! {
+        "at": { "$dateBucket": { "date": date("2024-03-01T00:00:00Z"), "unit": "day", "timezone": "Europe/Paris" } },
+    }).run();
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+}
+
+#[test]
+fn test_date_range_helpers() {
+    let db = prepare_orders("test-date-range-helpers");
+    let orders = db.collection::<Document>("orders");
+
+    let week = DateBucket::new(DateUnit::Week).range(date("2024-03-13T00:00:00Z"));
+    assert_eq!(week.start, date("2024-03-10T00:00:00Z"));
+    let found = orders.find(doc! { "at": week.to_filter() }).run().unwrap();
+    assert_eq!(ids(found), vec![2, 3]);
+
+    let tz = TimeZone::parse("-05:00").unwrap();
+    let quarter = DateBucket::new(DateUnit::Quarter).timezone(tz).range(date("2024-04-01T03:00:00Z"));
+    assert_eq!(quarter.start, date("2024-01-01T05:00:00Z"));
+    let found = orders.find(doc! { "at": quarter.to_filter() }).run().unwrap();
+    assert_eq!(ids(found), vec![1, 2, 3]);
+
+    let range = DateRange::new(std::time::SystemTime::UNIX_EPOCH, date("2024-03-10T00:00:00Z"));
+    let found = orders.find(doc! { "at": range.to_filter() }).run().unwrap();
+    assert_eq!(ids(found), vec![1]);
+}
+
+#[test]
+fn test_date_aggregation_operators() {
+    let db = prepare_orders("test-date-aggregation-operators");
+    let orders = db.collection::<Document>("orders");
+
+    let result = orders.aggregate(vec![
+        doc! {
+            "$addFields": {
+                "day": { "$dateTrunc": { "date": "$at", "unit": "day", "timezone": "+02:00" } },
+                "due": { "$dateAdd": { "startDate": "$at", "unit": "month", "amount": 1 } },
+            },
+        },
+    ]).run().unwrap().collect::<polodb_core::Result<Vec<Document>>>().unwrap();
+
+    assert_eq!(result[0].get_datetime("day").unwrap(), &date("2024-03-09T22:00:00Z"));
+    assert_eq!(result[2].get_datetime("day").unwrap(), &date("2024-03-10T22:00:00Z"));
+    assert_eq!(result[3].get_datetime("due").unwrap(), &date("2024-05-02T12:00:00Z"));
+}
@@ -74,3 +74,1265 @@ fn test_multi_threads() {
     assert_eq!(one.get("content").unwrap().as_str().unwrap(), "Hello");
 }
 
+
+#[test]
+fn test_small_memtable_config() {
+    use polodb_core::ConfigBuilder;
+
+    let db_path = mk_db_path("test-small-memtable");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    let mut config_builder = ConfigBuilder::new();
+    config_builder
+        .set_memtable_size(64 * 1024)
+        .set_max_immutable_memtables(2)
+        .set_level0_slowdown_writes_trigger(4)
+        .set_level0_stop_writes_trigger(8);
+
+    {
+        let db = Database::open_path_with_config(db_path.as_path(), config_builder.take()).unwrap();
+        let collection = db.collection::<Document>("test");
+        let docs = (0..TEST_SIZE).map(|i| doc! {
+            "_id": i as i64,
+            "content": "x".repeat(128),
+        }).collect::<Vec<Document>>();
+        collection.insert_many(&docs).unwrap();
+    }
+
+    {
+        let db = Database::open_path(db_path.as_path()).unwrap();
+        let collection = db.collection::<Document>("test");
+        assert_eq!(collection.count_documents().unwrap(), TEST_SIZE as u64);
+    }
+}
+
+#[test]
+fn test_wal_sync_policy() {
+    use std::time::Duration;
+    use polodb_core::{ConfigBuilder, WalSyncPolicy};
+    use polodb_core::options::TransactionOptions;
+
+    let db_path = mk_db_path("test-wal-sync-policy");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    let mut config_builder = ConfigBuilder::new();
+    config_builder.set_wal_sync_policy(WalSyncPolicy::Interval(Duration::from_millis(10)));
+
+    {
+        let db = Database::open_path_with_config(db_path.as_path(), config_builder.take()).unwrap();
+        let collection = db.collection::<Document>("test");
+        collection.insert_one(doc! { "_id": 1 }).unwrap();
+
+        let txn = db.start_transaction_with_options(
+            TransactionOptions::builder()
+                .sync_policy(WalSyncPolicy::EveryCommit)
+                .build()
+        ).unwrap();
+        txn.collection::<Document>("test").insert_one(doc! { "_id": 2 }).unwrap();
+        txn.commit().unwrap();
+
+        let txn = db.start_transaction_with_options(
+            TransactionOptions::builder()
+                .sync_policy(WalSyncPolicy::OsBuffered)
+                .build()
+        ).unwrap();
+        txn.collection::<Document>("test").insert_one(doc! { "_id": 3 }).unwrap();
+        txn.commit().unwrap();
+
+        // the interval of the database is kept
+        let txn = db.start_transaction_with_options(
+            TransactionOptions::builder()
+                .sync_policy(WalSyncPolicy::Interval(Duration::from_millis(10)))
+                .build()
+        ).unwrap();
+        txn.rollback().unwrap();
+        let result = db.start_transaction_with_options(
+            TransactionOptions::builder()
+                .sync_policy(WalSyncPolicy::Interval(Duration::from_millis(20)))
+                .build()
+        );
+        assert!(matches!(result, Err(polodb_core::Error::ValidationError(_))));
+
+        let synced = db.collection::<Document>("test").with_sync_policy(WalSyncPolicy::EveryCommit);
+        synced.insert_one(doc! { "_id": 4 }).unwrap();
+        let buffered = db.collection::<Document>("test").with_sync_policy(WalSyncPolicy::OsBuffered);
+        buffered.insert_one(doc! { "_id": 5 }).unwrap();
+        buffered.update_one(doc! { "_id": 5 }, doc! { "$set": { "x": 1 } }).unwrap();
+
+        let interval = db.collection::<Document>("test")
+            .with_sync_policy(WalSyncPolicy::Interval(Duration::from_millis(20)));
+        let result = interval.insert_one(doc! { "_id": 6 });
+        assert!(matches!(result, Err(polodb_core::Error::ValidationError(_))));
+        let result = interval.delete_one(doc! { "_id": 5 });
+        assert!(matches!(result, Err(polodb_core::Error::ValidationError(_))));
+
+        std::thread::sleep(Duration::from_millis(30));
+    }
+
+    {
+        let db = Database::open_path(db_path.as_path()).unwrap();
+        let collection = db.collection::<Document>("test");
+        assert_eq!(collection.count_documents().unwrap(), 5);
+        assert_eq!(collection.find_one(doc! { "_id": 5 }).unwrap(), Some(doc! { "_id": 5, "x": 1 }));
+    }
+}
+
+#[test]
+fn test_ship_wal() {
+    use polodb_core::{ConfigBuilder, WalOperation};
+
+    let db_path = mk_db_path("test-ship-wal");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    let mut config_builder = ConfigBuilder::new();
+    config_builder.set_wal_ttl_seconds(3600);
+
+    let db = Database::open_path_with_config(db_path.as_path(), config_builder.take()).unwrap();
+    let collection = db.collection::<Document>("test");
+    collection.insert_one(doc! { "_id": 1 }).unwrap();
+    collection.insert_one(doc! { "_id": 2 }).unwrap();
+
+    let mut put_count = 0;
+    let next = db.ship_wal(0, |record| {
+        put_count += record.operations
+            .iter()
+            .filter(|op| matches!(op, WalOperation::Put { .. }))
+            .count();
+        true
+    }).unwrap();
+    assert!(put_count >= 2);
+    assert_eq!(next, db.latest_sequence_number().unwrap() + 1);
+
+    let mut record_count = 0;
+    let resumed = db.ship_wal(next, |_| {
+        record_count += 1;
+        true
+    }).unwrap();
+    assert_eq!(record_count, 0);
+    assert_eq!(resumed, next);
+
+    collection.insert_one(doc! { "_id": 3 }).unwrap();
+    let mut records = Vec::new();
+    db.ship_wal(next, |record| {
+        records.push(record.clone());
+        true
+    }).unwrap();
+    assert_eq!(records.len(), 1);
+    assert!(records[0].sequence >= next);
+}
+
+#[test]
+fn test_ship_changes() {
+    use polodb_core::ConfigBuilder;
+    use polodb_core::bson::Bson;
+
+    let db_path = mk_db_path("test-ship-changes");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    let mut config_builder = ConfigBuilder::new();
+    config_builder.set_wal_ttl_seconds(3600);
+    config_builder.set_change_pre_images(true);
+
+    let db = Database::open_path_with_config(db_path.as_path(), config_builder.take()).unwrap();
+    let collection = db.collection::<Document>("test");
+    collection.insert_one(doc! { "_id": 0 }).unwrap();
+    let since = db.latest_sequence_number().unwrap() + 1;
+    collection.insert_one(doc! { "_id": 1, "x": 1 }).unwrap();
+    collection.update_one(doc! { "_id": 1 }, doc! { "$set": { "x": 2 } }).unwrap();
+    collection.delete_one(doc! { "_id": 1 }).unwrap();
+
+    let mut events = Vec::new();
+    let next = db.ship_changes(since, |event| {
+        events.push(event.clone());
+        true
+    }).unwrap();
+    assert_eq!(next, db.latest_sequence_number().unwrap() + 1);
+    assert_eq!(events.len(), 3);
+    assert!(events.iter().all(|event| event.collection == "test" && event.id == Bson::Int32(1)));
+
+    assert_eq!(events[0].before, None);
+    assert_eq!(events[0].after, Some(doc! { "_id": 1, "x": 1 }));
+    assert_eq!(events[1].before, Some(doc! { "_id": 1, "x": 1 }));
+    assert_eq!(events[1].after, Some(doc! { "_id": 1, "x": 2 }));
+    assert_eq!(events[2].before, Some(doc! { "_id": 1, "x": 2 }));
+    assert_eq!(events[2].after, None);
+
+    let mut count = 0;
+    let resumed = db.ship_changes(since, |_| {
+        count += 1;
+        false
+    }).unwrap();
+    assert_eq!(count, 1);
+    assert_eq!(resumed, events[1].sequence);
+}
+
+#[test]
+fn test_ship_changes_after_rename() {
+    use polodb_core::ConfigBuilder;
+    use polodb_core::bson::Bson;
+
+    let db_path = mk_db_path("test-ship-changes-after-rename");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    let mut config_builder = ConfigBuilder::new();
+    config_builder.set_wal_ttl_seconds(3600);
+
+    let db = Database::open_path_with_config(db_path.as_path(), config_builder.take()).unwrap();
+    db.collection::<Document>("a").insert_one(doc! { "_id": 1 }).unwrap();
+    let since = db.latest_sequence_number().unwrap() + 1;
+    db.rename_collection("a", "b").unwrap();
+    db.collection::<Document>("b").insert_one(doc! { "_id": 2 }).unwrap();
+    // the new collection has a unique storage name
+    db.collection::<Document>("a").insert_one(doc! { "_id": 3 }).unwrap();
+
+    let mut events = Vec::new();
+    db.ship_changes(since, |event| {
+        events.push((event.collection.clone(), event.id.clone()));
+        true
+    }).unwrap();
+    assert_eq!(events, vec![
+        ("b".to_string(), Bson::Int32(2)),
+        ("a".to_string(), Bson::Int32(3)),
+    ]);
+}
+
+fn insert_docs_and_close(db_path: &std::path::Path, config: polodb_core::Config) {
+    let db = Database::open_path_with_config(db_path, config).unwrap();
+    let collection = db.collection::<Document>("test");
+    let docs = (0..TEST_SIZE).map(|i| doc! {
+        "_id": i as i64,
+        "content": "x".repeat(128),
+    }).collect::<Vec<Document>>();
+    collection.insert_many(&docs).unwrap();
+}
+
+// flip the bytes in the data blocks of the table files
+fn corrupt_table_files(db_path: &std::path::Path) {
+    for entry in std::fs::read_dir(db_path).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().map(|ext| ext == "sst").unwrap_or(false) {
+            let mut content = std::fs::read(path.as_path()).unwrap();
+            let offset = content.len() / 4;
+            for byte in &mut content[offset..offset + 16] {
+                *byte = !*byte;
+            }
+            std::fs::write(path.as_path(), content).unwrap();
+        }
+    }
+}
+
+#[test]
+fn test_detect_corruption() {
+    use polodb_core::{ChecksumType, ConfigBuilder, Error};
+
+    let db_path = mk_db_path("test-detect-corruption");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    let mut config_builder = ConfigBuilder::new();
+    config_builder.set_checksum_type(ChecksumType::Crc32c);
+
+    insert_docs_and_close(db_path.as_path(), config_builder.take());
+    corrupt_table_files(db_path.as_path());
+
+    let result = Database::open_path(db_path.as_path())
+        .and_then(|db| db.collection::<Document>("test").count_documents());
+    match result {
+        Err(Error::Corruption(err)) => {
+            assert!(err.file.is_some());
+        }
+        other => panic!("expected corruption error, got: {:?}", other),
+    }
+}
+
+#[test]
+fn test_open_repair() {
+    let db_path = mk_db_path("test-open-repair");
+    let target_path = mk_db_path("test-open-repair-target");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+    let _ = std::fs::remove_dir_all(target_path.as_path());
+
+    insert_docs_and_close(db_path.as_path(), polodb_core::Config::default());
+    corrupt_table_files(db_path.as_path());
+    let damaged_files = std::fs::read_dir(db_path.as_path()).unwrap().count();
+
+    let (db, report) = Database::open_repair(db_path.as_path(), target_path.as_path()).unwrap();
+    let count = db.collection::<Document>("test").count_documents().unwrap();
+    assert_eq!(report.recovered_count, count);
+    assert!(count < TEST_SIZE as u64);
+    assert!(!report.lost_files.is_empty() || !report.skipped_documents.is_empty() || !report.errors.is_empty());
+    for name in &report.lost_files {
+        assert!(target_path.join("lost").join(name).exists());
+    }
+
+    // the damaged database is kept as it is
+    assert!(!db_path.join("lost").exists());
+    assert_eq!(std::fs::read_dir(db_path.as_path()).unwrap().count(), damaged_files);
+
+    // the target must be a new database
+    drop(db);
+    assert!(Database::open_repair(db_path.as_path(), target_path.as_path()).is_err());
+}
+
+#[test]
+fn test_backup_to() {
+    let db_path = mk_db_path("test-backup-to");
+    let backup_path = mk_db_path("test-backup-to-backup");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+    let _ = std::fs::remove_dir_all(backup_path.as_path());
+
+    let db = Database::open_path(db_path.as_path()).unwrap();
+    let collection = db.collection::<Document>("test");
+    collection.insert_one(doc! { "_id": 1 }).unwrap();
+
+    // an uncommitted transaction is not in the backup
+    let txn = db.start_transaction().unwrap();
+    txn.collection::<Document>("test").insert_one(doc! { "_id": 2 }).unwrap();
+
+    db.backup_to(backup_path.as_path()).unwrap();
+
+    txn.commit().unwrap();
+    collection.insert_one(doc! { "_id": 3 }).unwrap();
+    assert_eq!(collection.count_documents().unwrap(), 3);
+
+    let backup = Database::open_path(backup_path.as_path()).unwrap();
+    assert_eq!(backup.collection::<Document>("test").count_documents().unwrap(), 1);
+}
+
+#[test]
+fn test_incremental_backup() {
+    let db_path = mk_db_path("test-incremental-backup");
+    let backup_dir = mk_db_path("test-incremental-backup-dir");
+    let restore_path = mk_db_path("test-incremental-backup-restore");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+    let _ = std::fs::remove_dir_all(backup_dir.as_path());
+    let _ = std::fs::remove_dir_all(restore_path.as_path());
+
+    let db = Database::open_path(db_path.as_path()).unwrap();
+    let collection = db.collection::<Document>("test");
+    collection.insert_one(doc! { "_id": 1 }).unwrap();
+    let first = db.backup_incremental(backup_dir.as_path()).unwrap();
+
+    collection.insert_one(doc! { "_id": 2 }).unwrap();
+    let second = db.backup_incremental(backup_dir.as_path()).unwrap();
+    assert!(second.backup_id > first.backup_id);
+
+    let backups = Database::list_backups(backup_dir.as_path()).unwrap();
+    assert_eq!(backups.len(), 2);
+
+    Database::restore_backup(backup_dir.as_path(), Some(first.backup_id), restore_path.as_path()).unwrap();
+    {
+        let restored = Database::open_path(restore_path.as_path()).unwrap();
+        assert_eq!(restored.collection::<Document>("test").count_documents().unwrap(), 1);
+    }
+
+    Database::restore_backup(backup_dir.as_path(), None, restore_path.as_path()).unwrap();
+    let restored = Database::open_path(restore_path.as_path()).unwrap();
+    assert_eq!(restored.collection::<Document>("test").count_documents().unwrap(), 2);
+}
+
+#[test]
+fn test_named_snapshot() {
+    use polodb_core::Error;
+
+    let db_path = mk_db_path("test-named-snapshot");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    {
+        let db = Database::open_path(db_path.as_path()).unwrap();
+        let collection = db.collection::<Document>("test");
+        collection.insert_one(doc! { "_id": 1 }).unwrap();
+
+        db.create_snapshot("before_migration").unwrap();
+        assert!(matches!(db.create_snapshot("before_migration"), Err(Error::SnapshotAlreadyExists(_))));
+        assert!(matches!(db.create_snapshot("../escape"), Err(Error::IllegalSnapshotName(_))));
+        assert_eq!(db.list_snapshots().unwrap(), vec!["before_migration".to_string()]);
+
+        collection.insert_one(doc! { "_id": 2 }).unwrap();
+
+        let snapshot = db.open_snapshot("before_migration").unwrap();
+        let snapshot_collection = snapshot.collection::<Document>("test");
+        assert_eq!(snapshot_collection.count_documents().unwrap(), 1);
+        assert!(matches!(snapshot_collection.insert_one(doc! { "_id": 3 }), Err(Error::ReadOnlyDatabase)));
+
+        assert!(matches!(
+            Database::restore_snapshot(db_path.as_path(), "before_migration"),
+            Err(Error::DatabaseOccupied),
+        ));
+    }
+
+    Database::restore_snapshot(db_path.as_path(), "before_migration").unwrap();
+    assert!(db_path.join("LOCK").exists());
+
+    let db = Database::open_path(db_path.as_path()).unwrap();
+    let collection = db.collection::<Document>("test");
+    assert_eq!(collection.count_documents().unwrap(), 1);
+
+    // the restored database doesn't write to the files of the snapshot
+    collection.insert_one(doc! { "_id": 4 }).unwrap();
+    assert_eq!(db.open_snapshot("before_migration").unwrap()
+        .collection::<Document>("test").count_documents().unwrap(), 1);
+    db.drop_snapshot("before_migration").unwrap();
+    assert!(db.list_snapshots().unwrap().is_empty());
+    assert!(matches!(db.drop_snapshot("before_migration"), Err(Error::SnapshotNotFound(_))));
+}
+
+#[test]
+fn test_vacuum() {
+    let db_path = mk_db_path("test-vacuum");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    let db = Database::open_path(db_path.as_path()).unwrap();
+    let collection = db.collection::<Document>("test");
+    let docs = (0..TEST_SIZE).map(|i| doc! {
+        "_id": i as i64,
+        "content": "x".repeat(1024),
+    }).collect::<Vec<Document>>();
+    collection.insert_many(&docs).unwrap();
+    collection.delete_many(doc! {
+        "_id": { "$gte": 10 },
+    }).unwrap();
+
+    let result = db.vacuum().unwrap();
+    assert!(result.size_after < result.size_before / 2);
+    assert_eq!(collection.count_documents().unwrap(), 10);
+}
+
+#[test]
+fn test_open_memory() {
+    use polodb_core::Error;
+
+    let db_path = mk_db_path("test-open-memory-persist");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    {
+        let db = Database::open_memory().unwrap();
+        let collection = db.collection::<Document>("test");
+        let docs = (0..TEST_SIZE).map(|i| doc! {
+            "_id": i as i64,
+        }).collect::<Vec<Document>>();
+        collection.insert_many(&docs).unwrap();
+        assert_eq!(collection.count_documents().unwrap(), TEST_SIZE as u64);
+        assert!(matches!(db.create_snapshot("snapshot"), Err(Error::InMemoryNotSupported)));
+
+        db.persist_to(db_path.as_path()).unwrap();
+    }
+
+    // every in-memory database is independent
+    let db = Database::open_memory().unwrap();
+    assert_eq!(db.collection::<Document>("test").count_documents().unwrap(), 0);
+
+    let db = Database::open_path(db_path.as_path()).unwrap();
+    assert_eq!(db.collection::<Document>("test").count_documents().unwrap(), TEST_SIZE as u64);
+}
+
+#[test]
+fn test_open_read_only() {
+    use polodb_core::Error;
+
+    let db_path = mk_db_path("test-open-read-only");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    let list_files = || {
+        let mut files = std::fs::read_dir(db_path.as_path()).unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                (entry.file_name(), entry.metadata().unwrap().len())
+            })
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    };
+
+    {
+        // the documents are only in the WAL when it's opened read-only
+        let db = Database::open_path(db_path.as_path()).unwrap();
+        let docs = (0..TEST_SIZE).map(|i| doc! {
+            "_id": i as i64,
+        }).collect::<Vec<Document>>();
+        db.collection::<Document>("test").insert_many(&docs).unwrap();
+
+        let read_only = Database::open_read_only(db_path.as_path()).unwrap();
+        assert_eq!(read_only.collection::<Document>("test").count_documents().unwrap(), TEST_SIZE as u64);
+    }
+
+    let files_before = list_files();
+    {
+        let db = Database::open_read_only(db_path.as_path()).unwrap();
+        let collection = db.collection::<Document>("test");
+        assert_eq!(collection.count_documents().unwrap(), TEST_SIZE as u64);
+        assert!(collection.find_one(doc! { "_id": 10_i64 }).unwrap().is_some());
+        assert!(matches!(collection.insert_one(doc! { "_id": -1 }), Err(Error::ReadOnlyDatabase)));
+        assert!(matches!(collection.delete_many(doc! {}), Err(Error::ReadOnlyDatabase)));
+        assert!(matches!(db.vacuum(), Err(Error::ReadOnlyDatabase)));
+    }
+    assert_eq!(list_files(), files_before);
+
+    let missing_path = mk_db_path("test-open-read-only-missing");
+    let _ = std::fs::remove_dir_all(missing_path.as_path());
+    assert!(Database::open_read_only(missing_path.as_path()).is_err());
+    assert!(!missing_path.exists());
+}
+
+#[test]
+fn test_open_bytes() {
+    use polodb_core::Error;
+
+    let db_path = mk_db_path("test-open-bytes");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    let buffer = {
+        let db = Database::open_path(db_path.as_path()).unwrap();
+        let docs = (0..TEST_SIZE).map(|i| doc! {
+            "_id": i as i64,
+            "content": format!("content-{}", i),
+        }).collect::<Vec<Document>>();
+        db.collection::<Document>("test").insert_many(&docs).unwrap();
+        db.to_bytes().unwrap()
+    };
+
+    let db = Database::open_bytes(buffer).unwrap();
+    let collection = db.collection::<Document>("test");
+    assert_eq!(collection.count_documents().unwrap(), TEST_SIZE as u64);
+    let doc = collection.find_one(doc! { "_id": 7_i64 }).unwrap().unwrap();
+    assert_eq!(doc.get_str("content").unwrap(), "content-7");
+    assert!(matches!(collection.insert_one(doc! { "_id": -1 }), Err(Error::ReadOnlyDatabase)));
+
+    // the in-memory databases can be packed as well
+    let memory_db = Database::open_memory().unwrap();
+    memory_db.collection::<Document>("test").insert_one(doc! { "_id": 1 }).unwrap();
+    let buffer: &'static [u8] = Box::leak(memory_db.to_bytes().unwrap().into_boxed_slice());
+    let db = Database::open_bytes(buffer).unwrap();
+    assert_eq!(db.collection::<Document>("test").count_documents().unwrap(), 1);
+
+    assert!(matches!(Database::open_bytes(b"not a database"), Err(Error::NotAValidDatabase)));
+}
+
+#[test]
+fn test_block_cache_stats() {
+    use polodb_core::{BlockCache, ConfigBuilder};
+
+    let cache = BlockCache::lru(1024 * 1024);
+    let open = |name: &str| -> Database {
+        let db_path = mk_db_path(name);
+        let _ = std::fs::remove_dir_all(db_path.as_path());
+        let mut config = ConfigBuilder::new();
+        config.set_block_cache(cache.clone());
+        Database::open_path_with_config(db_path.as_path(), config.take()).unwrap()
+    };
+
+    let db = open("test-block-cache-stats");
+    let docs = (0..TEST_SIZE).map(|i| doc! {
+        "_id": i as i64,
+        "content": format!("content-{}", i),
+    }).collect::<Vec<Document>>();
+    let collection = db.collection::<Document>("test");
+    collection.insert_many(&docs).unwrap();
+    // move the documents from the memtable to the table files
+    db.vacuum().unwrap();
+
+    for _ in 0..10 {
+        assert!(collection.find_one(doc! { "_id": 10_i64 }).unwrap().is_some());
+    }
+
+    let stats = db.block_cache_stats().unwrap();
+    assert_eq!(stats.capacity, 1024 * 1024);
+    assert!(stats.hits > 0);
+    assert!(stats.misses > 0);
+    assert!(stats.adds > 0);
+    assert!(stats.entries > 0);
+    assert!(stats.usage > 0 && stats.usage <= stats.capacity);
+
+    // the cache is shared, but the counters are of each database
+    let other = open("test-block-cache-stats-other");
+    let other_stats = other.block_cache_stats().unwrap();
+    assert_eq!(other_stats.usage, stats.usage);
+    assert_eq!(other_stats.hits, 0);
+
+    cache.set_capacity(0);
+    assert_eq!(db.block_cache_stats().unwrap().capacity, 0);
+    // the blocks pinned by the open table files are kept
+    assert!(cache.entries() < stats.entries as usize || stats.entries == 1);
+
+    let clock_cache = BlockCache::clock(1024 * 1024);
+    assert_eq!(clock_cache.capacity(), 1024 * 1024);
+}
+
+#[test]
+fn test_mmap_reads() {
+    use polodb_core::ConfigBuilder;
+
+    let db_path = mk_db_path("test-mmap-reads");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+    let open = || {
+        let mut config = ConfigBuilder::new();
+        config.set_mmap_reads(true);
+        Database::open_path_with_config(db_path.as_path(), config.take()).unwrap()
+    };
+
+    {
+        let db = open();
+        let docs = (0..TEST_SIZE).map(|i| doc! {
+            "_id": i as i64,
+            "content": format!("content-{}", i),
+        }).collect::<Vec<Document>>();
+        db.collection::<Document>("test").insert_many(&docs).unwrap();
+        db.vacuum().unwrap();
+    }
+
+    let db = open();
+    let collection = db.collection::<Document>("test");
+    assert_eq!(collection.count_documents().unwrap(), TEST_SIZE as u64);
+    let doc = collection.find_one(doc! { "_id": 42_i64 }).unwrap().unwrap();
+    assert_eq!(doc.get_str("content").unwrap(), "content-42");
+}
+
+#[test]
+fn test_direct_io_writes() {
+    use polodb_core::{ConfigBuilder, Error};
+
+    let db_path = mk_db_path("test-direct-io-writes");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+    let mut config = ConfigBuilder::new();
+    config.set_direct_io_writes(true);
+    let db = match Database::open_path_with_config(db_path.as_path(), config.take()) {
+        Ok(db) => db,
+        // the temp dir may be on a file system without O_DIRECT, e.g. tmpfs,
+        // where opening the files fails with EINVAL
+        Err(Error::RocksDbErr(message)) if message.contains("Direct I/O is not supported")
+            || message.contains("Invalid argument") => return,
+        Err(err) => panic!("failed to open the database with direct I/O: {}", err),
+    };
+
+    let docs = (0..TEST_SIZE).map(|i| doc! {
+        "_id": i as i64,
+        "content": format!("content-{}", i),
+    }).collect::<Vec<Document>>();
+    let collection = db.collection::<Document>("test");
+    collection.insert_many(&docs).unwrap();
+    db.vacuum().unwrap();
+
+    assert_eq!(collection.count_documents().unwrap(), TEST_SIZE as u64);
+    let doc = collection.find_one(doc! { "_id": 42_i64 }).unwrap().unwrap();
+    assert_eq!(doc.get_str("content").unwrap(), "content-42");
+}
+
+#[test]
+fn test_cold_path() {
+    use polodb_core::ConfigBuilder;
+
+    let db_path = mk_db_path("test-cold-path");
+    let cold_path = mk_db_path("test-cold-path-cold");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+    let _ = std::fs::remove_dir_all(cold_path.as_path());
+    let open = || {
+        let mut config = ConfigBuilder::new();
+        // nothing fits in the hot path, so the compacted levels are all cold
+        config.set_cold_path(cold_path.as_path(), 0);
+        Database::open_path_with_config(db_path.as_path(), config.take()).unwrap()
+    };
+    let count_table_files = |path: &std::path::Path| {
+        std::fs::read_dir(path).unwrap()
+            .filter(|entry| {
+                entry.as_ref().unwrap().file_name().to_str().unwrap().ends_with(".sst")
+            })
+            .count()
+    };
+
+    {
+        let db = open();
+        let docs = (0..TEST_SIZE).map(|i| doc! {
+            "_id": i as i64,
+            "content": format!("content-{}", i),
+        }).collect::<Vec<Document>>();
+        db.collection::<Document>("test").insert_many(&docs).unwrap();
+        db.vacuum().unwrap();
+    }
+    assert!(count_table_files(cold_path.as_path()) > 0);
+    assert_eq!(count_table_files(db_path.as_path()), 0);
+
+    let db = open();
+    let collection = db.collection::<Document>("test");
+    assert_eq!(collection.count_documents().unwrap(), TEST_SIZE as u64);
+    let doc = collection.find_one(doc! { "_id": 42_i64 }).unwrap().unwrap();
+    assert_eq!(doc.get_str("content").unwrap(), "content-42");
+
+    // the copies would miss the cold files
+    let backup_path = mk_db_path("test-cold-path-backup");
+    let _ = std::fs::remove_dir_all(backup_path.as_path());
+    assert!(matches!(db.create_snapshot("snapshot"), Err(polodb_core::Error::ColdPathNotSupported)));
+    assert!(matches!(db.backup_to(backup_path.as_path()), Err(polodb_core::Error::ColdPathNotSupported)));
+    assert!(matches!(db.backup_incremental(backup_path.as_path()), Err(polodb_core::Error::ColdPathNotSupported)));
+    assert!(db.list_snapshots().unwrap().is_empty());
+    assert!(!backup_path.exists());
+}
+
+#[test]
+fn test_named_databases() {
+    let db_path = mk_db_path("test-named-databases");
+    let _ = std::fs::remove_dir_all(&db_path);
+    let db = Database::open_path(&db_path).unwrap();
+
+    db.collection::<Document>("users").insert_one(doc! { "name": "root" }).unwrap();
+    let tenant_a = db.database("tenant_a");
+    let tenant_b = db.database("tenant_b");
+    tenant_a.collection::<Document>("users").insert_many(vec![
+        doc! { "name": "a1" },
+        doc! { "name": "a2" },
+    ]).unwrap();
+    tenant_b.collection::<Document>("users").insert_one(doc! { "name": "b1" }).unwrap();
+    tenant_b.collection::<Document>("orders").insert_one(doc! { "item": 1 }).unwrap();
+
+    assert_eq!(db.collection::<Document>("users").count_documents().unwrap(), 1);
+    assert_eq!(tenant_a.collection::<Document>("users").count_documents().unwrap(), 2);
+    assert_eq!(tenant_b.collection::<Document>("users").count_documents().unwrap(), 1);
+
+    assert_eq!(db.list_collection_names().unwrap(), vec!["users".to_string()]);
+    assert_eq!(tenant_a.list_collection_names().unwrap(), vec!["users".to_string()]);
+    let mut names = tenant_b.list_collection_names().unwrap();
+    names.sort();
+    assert_eq!(names, vec!["orders".to_string(), "users".to_string()]);
+    assert_eq!(db.list_database_names().unwrap(), vec!["tenant_a".to_string(), "tenant_b".to_string()]);
+
+    // the transactions are scoped to the database too
+    let txn = tenant_a.start_transaction().unwrap();
+    txn.collection::<Document>("users").insert_one(doc! { "name": "a3" }).unwrap();
+    txn.commit().unwrap();
+    assert_eq!(tenant_a.collection::<Document>("users").count_documents().unwrap(), 3);
+
+    db.drop_database("tenant_b").unwrap();
+    assert_eq!(db.list_database_names().unwrap(), vec!["tenant_a".to_string()]);
+    assert!(tenant_b.list_collection_names().unwrap().is_empty());
+    assert_eq!(tenant_a.collection::<Document>("users").count_documents().unwrap(), 3);
+    assert_eq!(db.collection::<Document>("users").count_documents().unwrap(), 1);
+
+    let tenant_c = db.database("tenant_c");
+    tenant_c.collection::<Document>("logs").insert_one(doc! { "x": 1 }).unwrap();
+    let result = db.drop().unwrap();
+    assert_eq!(result.deleted_count, 1);
+    assert!(db.list_collection_names().unwrap().is_empty());
+    assert_eq!(db.list_database_names().unwrap(), vec!["tenant_a".to_string(), "tenant_c".to_string()]);
+    let result = tenant_c.drop().unwrap();
+    assert_eq!(result.deleted_count, 1);
+    assert_eq!(db.list_database_names().unwrap(), vec!["tenant_a".to_string()]);
+
+    let result = db.database("bad/name").collection::<Document>("users").insert_one(doc! {});
+    assert!(matches!(result, Err(polodb_core::Error::IllegalDatabaseName(_))));
+}
+
+#[test]
+fn test_storage_stats() {
+    let db_path = mk_db_path("test-storage-stats");
+    let _ = std::fs::remove_dir_all(&db_path);
+    let db = Database::open_path(&db_path).unwrap();
+    let collection = db.collection::<Document>("test");
+    let docs: Vec<Document> = (0..1000).map(|i| doc! {
+        "_id": i,
+        "content": format!("content {}", i),
+    }).collect();
+    collection.insert_many(docs).unwrap();
+
+    let stats = db.stats().unwrap();
+    assert_eq!(stats.levels.len(), 7);
+    assert!(stats.memtable_usage > 0);
+    assert!(stats.wal_size > 0);
+    assert!(stats.disk_size >= stats.wal_size);
+
+    db.vacuum().unwrap();
+    let stats = db.stats().unwrap();
+    let file_count: u64 = stats.levels.iter().map(|level| level.file_count).sum();
+    let tables_size: u64 = stats.levels.iter().map(|level| level.size).sum();
+    assert!(file_count > 0);
+    assert!(tables_size > 0);
+    assert!(stats.disk_size >= tables_size);
+    assert_eq!(stats.pending_compaction_bytes, 0);
+
+    let memory_db = Database::open_memory().unwrap();
+    assert_eq!(memory_db.stats().unwrap().disk_size, 0);
+}
+
+#[test]
+fn test_fragmentation() {
+    let db_path = mk_db_path("test-fragmentation");
+    let _ = std::fs::remove_dir_all(&db_path);
+    let db = Database::open_path(&db_path).unwrap();
+    let collection = db.collection::<Document>("test");
+    let docs: Vec<Document> = (0..1000).map(|i| doc! {
+        "_id": i,
+        "content": format!("content {}", i),
+    }).collect();
+    collection.insert_many(docs).unwrap();
+    db.vacuum().unwrap();
+
+    let report = db.fragmentation().unwrap();
+    assert_eq!(report.levels.len(), 7);
+    assert!(report.size > 0);
+    assert_eq!(report.reclaimable_size, 0);
+    assert_eq!(report.live_size, report.size);
+
+    collection.update_many(doc! { "_id": { "$lt": 500 } }, doc! {
+        "$set": { "content": "updated" },
+    }).unwrap();
+    collection.delete_many(doc! { "_id": { "$gte": 900 } }).unwrap();
+
+    let report = db.fragmentation().unwrap();
+    let deletions: u64 = report.levels.iter().map(|level| level.deletions).sum();
+    assert_eq!(deletions, 100);
+    assert!(report.levels[0].tombstone_ratio > 0.0);
+    assert!(report.levels.iter().any(|level| level.overlap_ratio > 0.0));
+    assert!(report.reclaimable_size > 0);
+    assert_eq!(report.live_size + report.reclaimable_size, report.size);
+
+    db.vacuum().unwrap();
+    let report = db.fragmentation().unwrap();
+    assert_eq!(report.reclaimable_size, 0);
+    let deletions: u64 = report.levels.iter().map(|level| level.deletions).sum();
+    assert_eq!(deletions, 0);
+}
+
+#[test]
+fn test_pinned_snapshots() {
+    let db_path = mk_db_path("test-pinned-snapshots");
+    let _ = std::fs::remove_dir_all(&db_path);
+    let db = Database::open_path(&db_path).unwrap();
+    let collection = db.collection::<Document>("test");
+    collection.create_index(polodb_core::IndexModel {
+        keys: doc! { "n": 1 },
+        options: None,
+    }).unwrap();
+    collection.insert_many((0..1000).map(|i| doc! { "_id": i, "n": i })).unwrap();
+    db.vacuum().unwrap();
+
+    let mut cursor = collection.find(doc! { "n": { "$gte": 0 } }).run().unwrap();
+    assert!(cursor.advance().unwrap());
+    let stats = db.stats().unwrap();
+    assert_eq!(stats.snapshot_count, 1);
+    assert!(stats.oldest_snapshot_time > 0);
+
+    // the compaction replaces the files read by the cursor
+    collection.update_many(doc! {}, doc! { "$inc": { "n": 1000 } }).unwrap();
+    db.vacuum().unwrap();
+    assert!(db.stats().unwrap().pinned_size > 0);
+
+    // the documents are read as of the snapshot of the cursor
+    let mut count = 1;
+    while cursor.advance().unwrap() {
+        let doc = cursor.deserialize_current().unwrap();
+        assert!(doc.get_i32("n").unwrap() < 1000);
+        count += 1;
+    }
+    assert_eq!(count, 1000);
+
+    drop(cursor);
+    let stats = db.stats().unwrap();
+    assert_eq!(stats.snapshot_count, 0);
+    assert_eq!(stats.pinned_size, 0);
+}
+
+#[test]
+fn test_open_options() {
+    use polodb_core::{Error, WalSyncPolicy};
+
+    let db_path = mk_db_path("test-open-options");
+    let _ = std::fs::remove_dir_all(&db_path);
+    {
+        let db = Database::options()
+            .cache_size(1024 * 1024)
+            .durability(WalSyncPolicy::OsBuffered)
+            .max_document_size(64)
+            .open(&db_path)
+            .unwrap();
+        let collection = db.collection::<Document>("test");
+        collection.insert_one(doc! { "_id": 1 }).unwrap();
+        assert!(collection.insert_one(doc! { "content": "x".repeat(100) }).is_err());
+        assert_eq!(db.block_cache_stats().unwrap().capacity, 1024 * 1024);
+    }
+
+    let db = Database::options().read_only(true).open(&db_path).unwrap();
+    let collection = db.collection::<Document>("test");
+    assert_eq!(collection.count_documents().unwrap(), 1);
+    assert!(matches!(collection.insert_one(doc! {}), Err(Error::ReadOnlyDatabase)));
+
+    let db = Database::options().memtable_size(1024 * 1024).open_memory().unwrap();
+    db.collection::<Document>("test").insert_one(doc! {}).unwrap();
+}
+
+#[test]
+fn test_inspect() {
+    let db_path = mk_db_path("test-inspect");
+    let _ = std::fs::remove_dir_all(&db_path);
+    {
+        let db = Database::open_path(&db_path).unwrap();
+        let collection = db.collection::<Document>("test");
+        let docs: Vec<Document> = (0..100).map(|i| doc! {
+            "_id": i,
+            "content": format!("content {}", i),
+        }).collect();
+        collection.insert_many(docs).unwrap();
+        db.vacuum().unwrap();
+        collection.delete_one(doc! { "_id": 1 }).unwrap();
+
+        // the delete after the vacuum is only in the WAL
+        let report = db.inspect().unwrap();
+        let operations: Vec<_> = report.wal_files.iter()
+            .flat_map(|file| file.records.iter())
+            .flat_map(|record| record.operations.iter())
+            .collect();
+        assert!(report.wal_files.iter().all(|file| file.errors.is_empty()));
+        assert!(operations.iter().any(|op| op.op == "delete" && op.key == "[\"test\",1]"));
+        assert!(report.latest_sequence > 0);
+    }
+
+    let db = Database::open_read_only(&db_path).unwrap();
+    let report = db.inspect().unwrap();
+    assert!(report.manifest.file.starts_with("MANIFEST-"));
+    assert!(report.manifest.errors.is_empty());
+    assert!(report.manifest.edits.iter().any(|edit| edit.comparator.is_some()));
+    assert!(report.manifest.edits.iter().any(|edit| !edit.new_files.is_empty()));
+
+    assert_eq!(report.levels.len(), 7);
+    let files: Vec<_> = report.levels.iter().flat_map(|level| level.files.iter()).collect();
+    assert!(!files.is_empty());
+    assert!(files.iter().all(|file| file.name.ends_with(".sst") && file.entries > 0));
+    assert!(files.iter().any(|file| file.largest_key.starts_with("[\"test\"")));
+    let blocks: u64 = report.levels.iter().map(|level| level.blocks.data_blocks).sum();
+    assert!(blocks > 0);
+    assert!(report.levels.iter().any(|level| level.blocks.raw_value_size > 0));
+
+    let memory_db = Database::open_memory().unwrap();
+    let report = memory_db.inspect().unwrap();
+    assert!(report.wal_files.is_empty());
+}
+
+#[test]
+fn test_slow_query_profiler() {
+    let mut config = polodb_core::ConfigBuilder::new();
+    config.set_slow_query_threshold(std::time::Duration::ZERO);
+    let db = Database::open_memory_with_config(config.take()).unwrap();
+    let collection = db.collection::<Document>("test");
+    collection.create_index(polodb_core::IndexModel {
+        keys: doc! { "name": 1 },
+        options: None,
+    }).unwrap();
+    let docs: Vec<Document> = (0..10).map(|i| doc! {
+        "_id": i,
+        "name": format!("name {}", i),
+        "age": i,
+    }).collect();
+    collection.insert_many(docs).unwrap();
+    assert!(db.profile_entries().unwrap().is_empty());
+
+    collection.find_one(doc! { "_id": 3 }).unwrap().unwrap();
+    collection.find_one(doc! { "name": "name 4" }).unwrap().unwrap();
+    let found = collection.find(doc! { "age": { "$gt": 7 } }).run().unwrap().count();
+    assert_eq!(found, 2);
+    collection.update_many(doc! { "age": { "$lt": 2 } }, doc! { "$set": { "old": false } }).unwrap();
+
+    let entries = db.profile_entries().unwrap();
+    assert_eq!(entries.len(), 4);
+
+    assert_eq!(entries[0].op, "find");
+    assert_eq!(entries[0].collection, "test");
+    assert_eq!(entries[0].plan, "IDHACK");
+    assert_eq!(entries[0].filter, doc! { "_id": 1 });
+    assert_eq!(entries[0].docs_examined, 1);
+
+    assert_eq!(entries[1].plan, "IXSCAN name_1");
+    assert_eq!(entries[1].docs_returned, 1);
+
+    assert_eq!(entries[2].plan, "COLLSCAN");
+    assert_eq!(entries[2].filter, doc! { "age": { "$gt": 1 } });
+    assert_eq!(entries[2].docs_examined, 10);
+    assert_eq!(entries[2].docs_returned, 2);
+
+    assert_eq!(entries[3].op, "update");
+    assert_eq!(entries[3].docs_returned, 0);
+
+    // the entries are queried like the other collections, without being recorded
+    let profile = db.collection::<Document>(polodb_core::PROFILE_COLLECTION);
+    let scans = profile
+        .find(doc! { "plan": "COLLSCAN" })
+        .run()
+        .unwrap()
+        .collect::<polodb_core::Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(scans.len(), 1);
+    assert_eq!(scans[0].get_str("collection").unwrap(), "test");
+    assert_eq!(scans[0].get_document("filter").unwrap(), &doc! { "age": { "$gt": 1 } });
+    assert_eq!(profile.count_documents().unwrap(), 4);
+
+    // the entries of the named databases are not mixed
+    let tenant = db.database("tenant");
+    tenant.collection::<Document>("test").find_one(doc! { "_id": 1 }).unwrap();
+    assert_eq!(tenant.profile_entries().unwrap().len(), 1);
+    assert_eq!(db.profile_entries().unwrap().len(), 4);
+
+    assert_eq!(db.clear_profile().unwrap(), 4);
+    assert!(db.profile_entries().unwrap().is_empty());
+    assert_eq!(tenant.profile_entries().unwrap().len(), 1);
+}
+
+#[test]
+fn test_slow_query_threshold() {
+    let mut config = polodb_core::ConfigBuilder::new();
+    config.set_slow_query_threshold(std::time::Duration::from_secs(60));
+    let db = Database::open_memory_with_config(config.take()).unwrap();
+    let collection = db.collection::<Document>("test");
+    collection.insert_one(doc! { "_id": 1 }).unwrap();
+    collection.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert!(db.profile_entries().unwrap().is_empty());
+}
+
+#[test]
+fn test_set_option() {
+    use std::time::Duration;
+    use polodb_core::{RuntimeOption, WalSyncPolicy};
+
+    let db_path = mk_db_path("test-set-option");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+    let db = Database::open_path(db_path.as_path()).unwrap();
+    let collection = db.collection::<Document>("test");
+    collection.insert_one(doc! { "_id": 1 }).unwrap();
+
+    db.set_option(RuntimeOption::BlockCacheSize(2 * 1024 * 1024)).unwrap();
+    assert_eq!(db.block_cache_stats().unwrap().capacity, 2 * 1024 * 1024);
+
+    db.set_option(RuntimeOption::MaxBackgroundJobs(4)).unwrap();
+    assert!(db.set_option(RuntimeOption::MaxBackgroundJobs(0)).is_err());
+
+    // the profiler is disabled by default
+    collection.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert!(db.profile_entries().unwrap().is_empty());
+    db.set_option(RuntimeOption::SlowQueryThreshold(Some(Duration::ZERO))).unwrap();
+    collection.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert_eq!(db.profile_entries().unwrap().len(), 1);
+    db.set_option(RuntimeOption::SlowQueryThreshold(None)).unwrap();
+    collection.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert_eq!(db.profile_entries().unwrap().len(), 1);
+
+    db.set_option(RuntimeOption::WalSyncPolicy(WalSyncPolicy::Interval(Duration::from_millis(10)))).unwrap();
+    collection.insert_one(doc! { "_id": 2 }).unwrap();
+    std::thread::sleep(Duration::from_millis(30));
+    db.set_option(RuntimeOption::WalSyncPolicy(WalSyncPolicy::OsBuffered)).unwrap();
+    collection.insert_one(doc! { "_id": 3 }).unwrap();
+    db.set_option(RuntimeOption::WalSyncPolicy(WalSyncPolicy::EveryCommit)).unwrap();
+    collection.insert_one(doc! { "_id": 4 }).unwrap();
+    drop(collection);
+    drop(db);
+
+    let db = Database::open_path(db_path.as_path()).unwrap();
+    assert_eq!(db.collection::<Document>("test").count_documents().unwrap(), 4);
+}
+
+#[test]
+fn test_current_ops_and_kill_op() {
+    let db = Database::open_memory().unwrap();
+    let collection = db.collection::<Document>("test");
+    let docs: Vec<Document> = (0..10).map(|i| doc! { "_id": i, "age": i }).collect();
+    collection.insert_many(docs).unwrap();
+    assert!(db.current_ops().is_empty());
+
+    let mut cursor = collection.find(doc! { "age": { "$gte": 0 } }).run().unwrap();
+    assert!(cursor.advance().unwrap());
+
+    let ops = db.current_ops();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].op, "find");
+    assert_eq!(ops[0].collection, "test");
+    assert_eq!(ops[0].filter, doc! { "age": { "$gte": 1 } });
+    assert!(!ops[0].cancelled);
+
+    // the operations of the other databases are not visible
+    assert!(db.database("tenant").current_ops().is_empty());
+    assert!(!db.database("tenant").kill_op(ops[0].id));
+
+    assert!(db.kill_op(ops[0].id));
+    assert!(db.current_ops()[0].cancelled);
+    assert!(matches!(cursor.advance(), Err(polodb_core::Error::OperationCancelled)));
+
+    drop(cursor);
+    assert!(db.current_ops().is_empty());
+    assert!(!db.kill_op(ops[0].id));
+}
+
+#[test]
+fn test_cancellation_token() {
+    let db = Database::open_memory().unwrap();
+    let collection = db.collection::<Document>("test");
+    let docs: Vec<Document> = (0..10).map(|i| doc! { "_id": i }).collect();
+    collection.insert_many(docs).unwrap();
+
+    let token = polodb_core::CancellationToken::new();
+    let mut cursor = collection
+        .aggregate(vec![doc! { "$match": { "_id": { "$gte": 0 } } }])
+        .cancellation_token(token.clone())
+        .run()
+        .unwrap();
+    assert!(cursor.advance().unwrap());
+    assert_eq!(db.current_ops()[0].op, "aggregate");
+
+    token.cancel();
+    assert!(matches!(cursor.advance(), Err(polodb_core::Error::OperationCancelled)));
+
+    let found = collection.find(doc! {})
+        .cancellation_token(polodb_core::CancellationToken::new())
+        .run()
+        .unwrap()
+        .count();
+    assert_eq!(found, 10);
+}
+
+#[test]
+fn test_max_time() {
+    use std::time::Duration;
+    use polodb_core::options::UpdateOptions;
+
+    let db = Database::open_memory().unwrap();
+    let collection = db.collection::<Document>("test");
+    let docs: Vec<Document> = (0..10).map(|i| doc! { "_id": i, "n": i }).collect();
+    collection.insert_many(docs).unwrap();
+
+    let result: polodb_core::Result<Vec<Document>> = collection
+        .find(doc! {})
+        .max_time(Duration::ZERO)
+        .run()
+        .unwrap()
+        .collect();
+    assert!(matches!(result, Err(polodb_core::Error::MaxTimeExceeded(_))));
+
+    let result: polodb_core::Result<Vec<Document>> = collection
+        .aggregate(vec![doc! { "$match": { "n": { "$gte": 0 } } }])
+        .max_time(Duration::ZERO)
+        .run()
+        .unwrap()
+        .collect();
+    assert!(matches!(result, Err(polodb_core::Error::MaxTimeExceeded(_))));
+
+    let options = UpdateOptions::builder().max_time(Duration::ZERO).build();
+    let err = collection.update_many_with_options(doc! {}, doc! { "$inc": { "n": 1 } }, options).unwrap_err();
+    assert!(matches!(err, polodb_core::Error::MaxTimeExceeded(max_time) if max_time == Duration::ZERO));
+    // the failed update is rolled back
+    assert_eq!(collection.find_one(doc! { "_id": 0 }).unwrap().unwrap().get_i32("n").unwrap(), 0);
+
+    let options = UpdateOptions::builder().max_time(Duration::from_secs(60)).build();
+    let result = collection.update_many_with_options(doc! {}, doc! { "$inc": { "n": 1 } }, options).unwrap();
+    assert_eq!(result.modified_count, 10);
+
+    let found = collection.find(doc! {}).max_time(Duration::from_secs(60)).run().unwrap().count();
+    assert_eq!(found, 10);
+}
+
+#[test]
+fn test_quota_reject() {
+    let mut config = polodb_core::ConfigBuilder::new();
+    config.set_max_db_size(1);
+    let db = Database::open_memory_with_config(config.take()).unwrap();
+    let collection = db.collection::<Document>("test");
+    let err = collection.insert_one(doc! { "_id": 1 }).unwrap_err();
+    assert!(matches!(err, polodb_core::Error::QuotaExceeded { limit: 1, .. }));
+    assert!(matches!(
+        collection.create_index(polodb_core::IndexModel {
+            keys: doc! { "age": 1 },
+            options: None,
+        }),
+        Err(polodb_core::Error::QuotaExceeded { .. }),
+    ));
+
+    // the deletes are always allowed
+    collection.delete_many(doc! {}).unwrap();
+}
+
+#[test]
+fn test_quota_evict() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    let calls = Arc::new(AtomicU64::new(0));
+    let evictor_calls = calls.clone();
+    let mut config = polodb_core::ConfigBuilder::new();
+    config.set_max_db_size(1);
+    config.set_quota_policy(polodb_core::QuotaPolicy::Evict(Arc::new(move |db, size, limit| {
+        assert!(size > limit);
+        evictor_calls.fetch_add(1, Ordering::SeqCst);
+        db.collection::<Document>("test").delete_many(doc! {})?;
+        db.vacuum()?;
+        Ok(())
+    })));
+    let db = Database::open_memory_with_config(config.take()).unwrap();
+    let collection = db.collection::<Document>("test");
+
+    // the memtable still takes the space after the eviction
+    let err = collection.insert_one(doc! { "_id": 1 }).unwrap_err();
+    assert!(matches!(err, polodb_core::Error::QuotaExceeded { .. }));
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    let mut config = polodb_core::ConfigBuilder::new();
+    config.set_max_db_size(1 << 40);
+    let evictor_calls = calls.clone();
+    config.set_quota_policy(polodb_core::QuotaPolicy::Evict(Arc::new(move |_, _, _| {
+        evictor_calls.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    })));
+    let db = Database::open_memory_with_config(config.take()).unwrap();
+    db.collection::<Document>("test").insert_one(doc! { "_id": 1 }).unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_quota_evict_oldest() {
+    use std::sync::{Arc, Mutex};
+
+    fn estimated_size(db: &Database) -> u64 {
+        let stats = db.stats().unwrap();
+        stats.levels.iter().map(|level| level.size).sum::<u64>() + stats.memtable_usage
+    }
+
+    let db_path = mk_db_path("test-quota-evict-oldest");
+    let full_size = {
+        let db = Database::open_path(&db_path).unwrap();
+        let collection = db.collection::<Document>("test");
+        // pseudo-random strings which don't compress much
+        let mut seed: u64 = 42;
+        let docs: Vec<Document> = (0..200).map(|i| {
+            let data: String = (0..4096).map(|_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                char::from(b'a' + (seed >> 59) as u8)
+            }).collect();
+            doc! { "_id": i, "data": data }
+        }).collect();
+        collection.insert_many(docs).unwrap();
+        db.vacuum().unwrap();
+        estimated_size(&db)
+    };
+
+    let limit = full_size / 2;
+    let seen_sizes = Arc::new(Mutex::new(Vec::new()));
+    let evictor_sizes = seen_sizes.clone();
+    let mut config = polodb_core::ConfigBuilder::new();
+    config.set_max_db_size(limit);
+    config.set_quota_policy(polodb_core::QuotaPolicy::Evict(Arc::new(move |db, size, limit| {
+        evictor_sizes.lock().unwrap().push((size, limit));
+        // evict the oldest three quarters
+        db.collection::<Document>("test").delete_many(doc! { "_id": { "$lt": 150 } })?;
+        db.vacuum()?;
+        Ok(())
+    })));
+    let db = Database::open_path_with_config(&db_path, config.take()).unwrap();
+    let collection = db.collection::<Document>("test");
+    collection.insert_one(doc! { "_id": 200, "data": "new" }).unwrap();
+
+    let seen_sizes = seen_sizes.lock().unwrap().clone();
+    assert_eq!(seen_sizes.len(), 1);
+    let (size_before, seen_limit) = seen_sizes[0];
+    assert_eq!(seen_limit, limit);
+    assert!(size_before > limit);
+
+    let ids: Vec<i32> = collection.find(doc! {}).run().unwrap()
+        .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+        .collect();
+    assert_eq!(ids, (150..=200).collect::<Vec<i32>>());
+
+    db.vacuum().unwrap();
+    let size_after = estimated_size(&db);
+    assert!(size_after <= limit, "{} > {}", size_after, limit);
+    assert!(size_after < size_before / 2);
+}
@@ -0,0 +1,155 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use polodb_core::{CollectionT, DbRef};
+use polodb_core::bson::{doc, Bson, Document};
+use serde::{Deserialize, Serialize};
+
+mod common;
+
+use common::prepare_db;
+
+#[test]
+fn test_resolve_ref() {
+    let db = prepare_db("test-resolve-ref").unwrap();
+    let users = db.collection::<Document>("users");
+    users.insert_one(doc! { "_id": 1, "name": "Vincent" }).unwrap();
+
+    let tenant = db.database("tenant");
+    tenant.collection::<Document>("users").insert_one(doc! { "_id": 1, "name": "Tenant" }).unwrap();
+
+    let posts = db.collection::<Document>("posts");
+    let user = posts.resolve_ref(&DbRef::new("users", 1)).unwrap().unwrap();
+    assert_eq!(user.get_str("name").unwrap(), "Vincent");
+
+    let user = posts.resolve_ref(&DbRef::new("users", 1).with_database("tenant")).unwrap().unwrap();
+    assert_eq!(user.get_str("name").unwrap(), "Tenant");
+
+    // without $db, the reference is resolved in the database of the collection
+    let user = tenant.collection::<Document>("posts").resolve_ref(&DbRef::new("users", 1)).unwrap().unwrap();
+    assert_eq!(user.get_str("name").unwrap(), "Tenant");
+
+    assert!(posts.resolve_ref(&DbRef::new("users", 2)).unwrap().is_none());
+    assert!(posts.resolve_ref(&DbRef::new("missing", 1)).unwrap().is_none());
+
+    let txn = db.start_transaction().unwrap();
+    txn.collection::<Document>("users").insert_one(doc! { "_id": 2, "name": "Uncommitted" }).unwrap();
+    let user = txn.collection::<Document>("posts").resolve_ref(&DbRef::new("users", 2)).unwrap().unwrap();
+    assert_eq!(user.get_str("name").unwrap(), "Uncommitted");
+    txn.collection::<Document>("posts").insert_one(doc! { "_id": 1, "author": DbRef::new("users", 2) }).unwrap();
+    let post = txn.collection::<Document>("posts").find(doc! {}).resolve_refs(1).run().unwrap().next().unwrap().unwrap();
+    assert_eq!(post.get_document("author").unwrap().get_str("name").unwrap(), "Uncommitted");
+    txn.rollback().unwrap();
+}
+
+#[test]
+fn test_find_resolve_refs() {
+    let db = prepare_db("test-find-resolve-refs").unwrap();
+    db.collection::<Document>("countries").insert_one(doc! { "_id": "cn", "name": "China" }).unwrap();
+    db.collection::<Document>("authors").insert_many(vec![
+        doc! { "_id": 1, "name": "Liu Cixin", "country": DbRef::new("countries", "cn") },
+        doc! { "_id": 2, "name": "Wang Jinkang", "country": DbRef::new("countries", "cn") },
+    ]).unwrap();
+    let books = db.collection::<Document>("books");
+    books.insert_many(vec![
+        doc! {
+            "_id": 1,
+            "title": "The Three-Body Problem",
+            "authors": [DbRef::new("authors", 1), DbRef::new("authors", 3)],
+        },
+        doc! {
+            "_id": 2,
+            "title": "Ant Life",
+            "authors": [DbRef::new("authors", 2)],
+        },
+    ]).unwrap();
+
+    let book = books.find(doc! { "_id": 1 }).run().unwrap().next().unwrap().unwrap();
+    let authors = book.get_array("authors").unwrap();
+    assert_eq!(DbRef::from_document(authors[0].as_document().unwrap()), Some(DbRef::new("authors", 1)));
+
+    let book = books.find(doc! { "_id": 1 }).resolve_refs(1).run().unwrap().next().unwrap().unwrap();
+    let authors = book.get_array("authors").unwrap();
+    let author = authors[0].as_document().unwrap();
+    assert_eq!(author.get_str("name").unwrap(), "Liu Cixin");
+    assert_eq!(DbRef::from_document(author.get_document("country").unwrap()), Some(DbRef::new("countries", "cn")));
+    // the dangling reference is kept
+    assert_eq!(DbRef::from_document(authors[1].as_document().unwrap()), Some(DbRef::new("authors", 3)));
+
+    let result: Vec<Document> = books
+        .find(doc! {})
+        .sort(doc! { "_id": -1 })
+        .resolve_refs(2)
+        .run()
+        .unwrap()
+        .collect::<polodb_core::Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(result.len(), 2);
+    let author = result[0].get_array("authors").unwrap()[0].as_document().unwrap().clone();
+    assert_eq!(author.get_str("name").unwrap(), "Wang Jinkang");
+    assert_eq!(author.get_document("country").unwrap().get_str("name").unwrap(), "China");
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Comment {
+    text: String,
+    author: DbRef,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct User {
+    name: String,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct ResolvedComment {
+    text: String,
+    author: User,
+}
+
+#[test]
+fn test_dbref_serde() {
+    let db = prepare_db("test-dbref-serde").unwrap();
+    db.database("accounts").collection::<Document>("users").insert_one(doc! { "_id": 7, "name": "Vincent" }).unwrap();
+
+    let comments = db.collection::<Comment>("comments");
+    let comment = Comment {
+        text: "hello".to_string(),
+        author: DbRef::new("users", 7).with_database("accounts"),
+    };
+    comments.insert_one(&comment).unwrap();
+
+    let raw = db.collection::<Document>("comments").find_one(doc! {}).unwrap().unwrap();
+    assert_eq!(raw.get_document("author").unwrap(), &doc! {
+        "$ref": "users",
+        "$id": 7,
+        "$db": "accounts",
+    });
+    assert_eq!(comments.find_one(doc! {}).unwrap().unwrap(), comment);
+
+    let resolved = db.collection::<ResolvedComment>("comments")
+        .find(doc! {})
+        .resolve_refs(1)
+        .run()
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_eq!(resolved.author.name, "Vincent");
+
+    assert_eq!(Bson::from(DbRef::new("users", 7)), Bson::Document(doc! { "$ref": "users", "$id": 7 }));
+    assert_eq!(DbRef::from_document(&doc! { "$ref": "users" }), None);
+    assert_eq!(DbRef::from_document(&doc! { "$ref": "users", "$id": 7, "$db": 1 }), None);
+}
@@ -0,0 +1,112 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use std::str::FromStr;
+use polodb_core::{CollectionT, IndexModel};
+use polodb_core::bson::{doc, Bson, Decimal128, Document};
+
+mod common;
+
+use common::prepare_db;
+
+fn dec(s: &str) -> Bson {
+    Bson::Decimal128(Decimal128::from_str(s).unwrap())
+}
+
+fn amounts(docs: Vec<Document>) -> Vec<String> {
+    docs.iter().map(|doc| doc.get("amount").unwrap().to_string()).collect()
+}
+
+#[test]
+fn test_decimal_query_and_sort() {
+    let db = prepare_db("test-decimal-query-and-sort").unwrap();
+    let col = db.collection::<Document>("payments");
+
+    col.insert_many(vec![
+        doc! { "_id": 1, "amount": dec("10.25") },
+        doc! { "_id": 2, "amount": dec("9.99") },
+        doc! { "_id": 3, "amount": dec("-0.50") },
+        doc! { "_id": 4, "amount": dec("10.250") },
+        doc! { "_id": 5, "amount": dec("100") },
+    ]).unwrap();
+
+    let found: Vec<Document> = col.find(doc! { "amount": { "$gt": 10 } })
+        .sort(doc! { "amount": 1 })
+        .run()
+        .unwrap()
+        .collect::<polodb_core::Result<_>>()
+        .unwrap();
+    assert_eq!(amounts(found), vec!["10.25", "10.250", "100"]);
+
+    let count = col.find(doc! { "amount": dec("10.2500") }).run().unwrap().count();
+    assert_eq!(count, 2);
+
+    let count = col.find(doc! { "amount": { "$lt": 9.995 } }).run().unwrap().count();
+    assert_eq!(count, 2);
+
+    let sorted: Vec<Document> = col.find(doc! {})
+        .sort(doc! { "amount": -1 })
+        .run()
+        .unwrap()
+        .collect::<polodb_core::Result<_>>()
+        .unwrap();
+    assert_eq!(amounts(sorted), vec!["100", "10.25", "10.250", "9.99", "-0.50"]);
+}
+
+#[test]
+fn test_decimal_index() {
+    let db = prepare_db("test-decimal-index").unwrap();
+    let col = db.collection::<Document>("prices");
+    col.create_index(IndexModel {
+        keys: doc! { "price": 1 },
+        options: None,
+    }).unwrap();
+
+    col.insert_one(doc! { "_id": 1, "price": dec("1.50") }).unwrap();
+    col.insert_one(doc! { "_id": 2, "price": dec("2.00") }).unwrap();
+
+    let found = col.find_one(doc! { "price": dec("1.5") }).unwrap().unwrap();
+    assert_eq!(found.get_i32("_id").unwrap(), 1);
+    assert_eq!(found.get("price").unwrap(), &dec("1.50"));
+
+    // the equal decimals are the same primary key
+    let ids = db.collection::<Document>("ids");
+    ids.insert_one(doc! { "_id": dec("7.0"), "v": 1 }).unwrap();
+    ids.insert_one(doc! { "_id": dec("7.00"), "v": 2 }).unwrap();
+    assert_eq!(ids.count_documents().unwrap(), 1);
+    let found = ids.find_one(doc! { "_id": dec("7") }).unwrap().unwrap();
+    assert_eq!(found.get_i32("v").unwrap(), 2);
+}
+
+#[test]
+fn test_decimal_inc_and_mul() {
+    let db = prepare_db("test-decimal-inc-and-mul").unwrap();
+    let col = db.collection::<Document>("accounts");
+
+    col.insert_one(doc! { "_id": 1, "balance": dec("0.10") }).unwrap();
+
+    col.update_one(doc! { "_id": 1 }, doc! { "$inc": { "balance": dec("0.20") } }).unwrap();
+    let account = col.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert_eq!(account.get("balance").unwrap(), &dec("0.30"));
+
+    col.update_one(doc! { "_id": 1 }, doc! { "$inc": { "balance": 1 } }).unwrap();
+    col.update_one(doc! { "_id": 1 }, doc! { "$mul": { "balance": dec("1.5") } }).unwrap();
+    let account = col.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert_eq!(account.get("balance").unwrap(), &dec("1.950"));
+
+    col.update_one(doc! { "_id": 1 }, doc! { "$inc": { "balance": -0.95 } }).unwrap();
+    let account = col.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert_eq!(account.get("balance").unwrap(), &dec("1.000"));
+}
@@ -0,0 +1,110 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use polodb_core::{CollectionT, Database, Error};
+use polodb_core::bson::{doc, Bson, Document};
+use polodb_core::options::{CreateCollectionOptions, UpdateOptions};
+
+mod common;
+
+use common::prepare_db;
+
+#[test]
+fn test_defaults_on_insert() {
+    let db = prepare_db("test-defaults-on-insert").unwrap();
+    db.create_collection_with_options(
+        "orders",
+        CreateCollectionOptions::builder()
+            .defaults(doc! {
+                "status": "new",
+                "createdAt": "$$NOW",
+                "total": { "$add": ["$price", "$shipping"] },
+                "label": { "$concat": ["$sku", "-", "$status"] },
+                "tags": [],
+                "raw": { "$literal": "$price" },
+                "meta": { "source": "app", "version": 1 },
+            })
+            .build(),
+    ).unwrap();
+    let col = db.collection::<Document>("orders");
+
+    col.insert_one(doc! { "_id": 1, "sku": "a1", "price": 10, "shipping": 2 }).unwrap();
+    let order = col.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert_eq!(order.get_str("status").unwrap(), "new");
+    assert!(order.get_datetime("createdAt").is_ok());
+    assert_eq!(order.get_i32("total").unwrap(), 12);
+    // the rules use the fields added by the previous rules
+    assert_eq!(order.get_str("label").unwrap(), "a1-new");
+    assert_eq!(order.get_array("tags").unwrap().len(), 0);
+    assert_eq!(order.get_str("raw").unwrap(), "$price");
+    assert_eq!(order.get_document("meta").unwrap(), &doc! { "source": "app", "version": 1 });
+
+    // the existing fields are kept
+    col.insert_one(doc! { "_id": 2, "sku": "b2", "price": 5, "status": "paid", "total": 0 }).unwrap();
+    let order = col.find_one(doc! { "_id": 2 }).unwrap().unwrap();
+    assert_eq!(order.get_str("status").unwrap(), "paid");
+    assert_eq!(order.get_i32("total").unwrap(), 0);
+    assert_eq!(order.get_str("label").unwrap(), "b2-paid");
+
+    // the missing operands give null
+    col.insert_one(doc! { "_id": 3 }).unwrap();
+    let order = col.find_one(doc! { "_id": 3 }).unwrap().unwrap();
+    assert_eq!(order.get("total"), Some(&Bson::Null));
+
+    // the upserted documents are inserted
+    col.update_one_with_options(
+        doc! { "_id": 4 },
+        doc! { "$set": { "sku": "c3" } },
+        UpdateOptions::builder().upsert(true).build(),
+    ).unwrap();
+    let order = col.find_one(doc! { "_id": 4 }).unwrap().unwrap();
+    assert_eq!(order.get_str("status").unwrap(), "new");
+
+    // the updates are not changed
+    col.update_one(doc! { "_id": 1 }, doc! { "$unset": { "status": "" } }).unwrap();
+    let order = col.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert!(order.get("status").is_none());
+}
+
+#[test]
+fn test_defaults_computed_id() {
+    let db = Database::open_memory().unwrap();
+    let col = db.collection::<Document>("users");
+    col.insert_one(doc! { "_id": "before" }).unwrap();
+
+    db.set_defaults("users", doc! { "_id": { "$toLower": "$email" } }).unwrap();
+    let result = col.insert_one(doc! { "email": "Ada@Example.com" }).unwrap();
+    assert_eq!(result.inserted_id, Bson::String("ada@example.com".to_string()));
+
+    db.remove_defaults("users").unwrap();
+    let result = col.insert_one(doc! { "email": "alan@example.com" }).unwrap();
+    assert!(result.inserted_id.as_object_id().is_some());
+}
+
+#[test]
+fn test_defaults_errors() {
+    let db = Database::open_memory().unwrap();
+    let create = |defaults: Document| db.create_collection_with_options(
+        "test",
+        CreateCollectionOptions::builder().defaults(defaults).build(),
+    );
+    assert!(matches!(create(doc! { "a.b": 1 }), Err(Error::ValidationError(_))));
+    assert!(matches!(create(doc! { "a": "$$ROOT" }), Err(Error::ValidationError(_))));
+    assert!(matches!(create(doc! { "a": { "$unknown": 1 } }), Err(Error::InvalidField(_))));
+    assert!(matches!(
+        db.set_defaults("missing", doc! { "a": 1 }),
+        Err(Error::CollectionNotFound(_)),
+    ));
+}
@@ -0,0 +1,283 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use std::fs;
+use polodb_core::{CollectionT, Database, Error, IndexModel, IndexOptions, Result};
+use polodb_core::bson::{doc, DateTime, Document};
+use polodb_core::options::{CreateCollectionOptions, TimeseriesOptions, ValidationAction};
+
+mod common;
+
+use common::{mk_db_path, prepare_db};
+
+fn prepare_dump_dir(name: &str) -> std::path::PathBuf {
+    let path = mk_db_path(name);
+    let _ = fs::remove_dir_all(&path);
+    path
+}
+
+fn find_all(db: &Database, name: &str) -> Vec<Document> {
+    db.collection::<Document>(name)
+        .find(doc! {})
+        .sort(doc! { "_id": 1 })
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap()
+}
+
+#[test]
+fn test_dump_and_restore() {
+    let db = prepare_db("test-dump-source").unwrap();
+    let users = db.collection::<Document>("users");
+    let docs: Vec<Document> = (0..2500).map(|i| doc! {
+        "_id": i,
+        "name": format!("user-{}", i),
+        "email": format!("{}@example.com", i),
+        "age": i % 90,
+    }).collect();
+    users.insert_many(&docs).unwrap();
+    users.create_index(IndexModel {
+        keys: doc! { "email": 1 },
+        options: Some(IndexOptions {
+            name: Some("email_unique".to_string()),
+            unique: Some(true),
+            ..Default::default()
+        }),
+    }).unwrap();
+    db.set_validator("users", doc! {
+        "$jsonSchema": { "required": ["name"] },
+    }, ValidationAction::Error).unwrap();
+    db.set_defaults("users", doc! { "active": true }).unwrap();
+
+    db.create_collection_with_options("logs", CreateCollectionOptions::builder()
+        .capped(true)
+        .max(100)
+        .build()).unwrap();
+    let logs = db.collection::<Document>("logs");
+    logs.insert_one(doc! { "_id": 1, "message": "started" }).unwrap();
+
+    db.create_collection_with_options("weather", CreateCollectionOptions::builder()
+        .timeseries(TimeseriesOptions::new("ts"))
+        .build()).unwrap();
+    db.collection::<Document>("weather").insert_many(vec![
+        doc! { "ts": DateTime::from_millis(1_700_000_000_000), "temp": 20 },
+        doc! { "ts": DateTime::from_millis(1_700_000_010_000), "temp": 21 },
+    ]).unwrap();
+
+    // a view on a view, restored after its source
+    db.create_view("adults", "users", vec![doc! { "$match": { "age": { "$gte": 18 } } }]).unwrap();
+    db.create_view("seniors", "adults", vec![doc! { "$match": { "age": { "$gte": 65 } } }]).unwrap();
+
+    let dump_path = prepare_dump_dir("test-dump-files");
+    let result = db.dump(&dump_path).unwrap();
+    assert_eq!(result.collections, 5);
+    assert_eq!(result.documents, 2503);
+    assert!(dump_path.join("users.bson").exists());
+    assert!(dump_path.join("users.metadata.json").exists());
+    assert!(!dump_path.join("adults.bson").exists());
+
+    let metadata: serde_json::Value = serde_json::from_slice(
+        &fs::read(dump_path.join("users.metadata.json")).unwrap()
+    ).unwrap();
+    assert_eq!(metadata["collectionName"], "users");
+    assert_eq!(metadata["type"], "collection");
+    assert_eq!(metadata["indexes"][0]["name"], "_id_");
+    assert_eq!(metadata["indexes"][1]["name"], "email_unique");
+    assert_eq!(metadata["indexes"][1]["key"]["email"]["$numberInt"], "1");
+    assert_eq!(metadata["polodb"]["defaults"]["active"], true);
+
+    let target = prepare_db("test-dump-target").unwrap();
+    let result = target.restore(&dump_path).unwrap();
+    assert_eq!(result.collections, 5);
+    assert_eq!(result.documents, 2503);
+    assert_eq!(result.indexes, 1);
+    assert!(result.skipped_indexes.is_empty());
+
+    assert_eq!(find_all(&target, "users"), docs);
+    assert_eq!(find_all(&target, "logs"), vec![doc! { "_id": 1, "message": "started" }]);
+    assert_eq!(target.collection::<Document>("weather").count_documents().unwrap(), 2);
+    assert_eq!(target.collection::<Document>("seniors").count_documents().unwrap(),
+               db.collection::<Document>("seniors").count_documents().unwrap());
+
+    let target_users = target.collection::<Document>("users");
+    // the unique index, the validator and the defaults are restored
+    let err = target_users.insert_one(doc! { "_id": -1, "name": "x", "email": "1@example.com" }).unwrap_err();
+    assert!(matches!(err, Error::DuplicateKey(_)));
+    let err = target_users.insert_one(doc! { "_id": -2 }).unwrap_err();
+    assert!(matches!(err, Error::DocumentValidationFailed(_)));
+    target_users.insert_one(doc! { "_id": -3, "name": "new", "email": "new@example.com" }).unwrap();
+    let inserted = target_users.find_one(doc! { "_id": -3 }).unwrap().unwrap();
+    assert!(inserted.get_bool("active").unwrap());
+
+    let info = target.list_collections(Default::default()).unwrap()
+        .into_iter()
+        .find(|info| info.name == "logs")
+        .unwrap();
+    assert_eq!(info.options.capped, Some(true));
+    assert_eq!(info.options.max, Some(100));
+
+    // the collections must not exist
+    let err = target.restore(&dump_path).unwrap_err();
+    assert!(matches!(err, Error::CollectionAlreadyExits(_)));
+}
+
+#[test]
+fn test_restore_mongodump() {
+    // the files written by mongodump, with the indexes PoloDB doesn't support
+    let dump_path = prepare_dump_dir("test-restore-mongodump-files");
+    fs::create_dir_all(&dump_path).unwrap();
+    let mut bson = Vec::new();
+    for doc in [
+        doc! { "_id": 1, "title": "a", "tags": ["x"] },
+        doc! { "_id": 2, "title": "b", "tags": ["y"] },
+    ] {
+        doc.to_writer(&mut bson).unwrap();
+    }
+    fs::write(dump_path.join("posts.bson"), bson).unwrap();
+    fs::write(dump_path.join("posts.metadata.json"), r#"{
+        "indexes": [
+            {"v": {"$numberInt": "2"}, "key": {"_id": {"$numberInt": "1"}}, "name": "_id_"},
+            {"v": {"$numberInt": "2"}, "key": {"title": {"$numberInt": "1"}}, "name": "title_1", "background": true},
+            {"v": {"$numberInt": "2"}, "key": {"title": {"$numberInt": "1"}, "tags": {"$numberInt": "1"}}, "name": "title_1_tags_1"},
+            {"v": {"$numberInt": "2"}, "key": {"_fts": "text", "_ftsx": {"$numberInt": "1"}}, "name": "title_text"},
+            {"v": {"$numberInt": "2"}, "key": {"tags": {"$numberInt": "1"}}, "name": "tags_1", "sparse": true}
+        ],
+        "uuid": "0f9b4a5e3c1d4e2f8a7b6c5d4e3f2a1b",
+        "collectionName": "posts",
+        "type": "collection"
+    }"#).unwrap();
+    // only the documents, like the dumps of the old versions
+    let mut bson = Vec::new();
+    doc! { "_id": "only" }.to_writer(&mut bson).unwrap();
+    fs::write(dump_path.join("plain.bson"), bson).unwrap();
+    fs::write(dump_path.join("system.views.bson"), b"").unwrap();
+    fs::write(dump_path.join("prelude.json"), b"{}").unwrap();
+
+    let db = prepare_db("test-restore-mongodump").unwrap();
+    let result = db.restore(&dump_path).unwrap();
+    assert_eq!(result.collections, 2);
+    assert_eq!(result.documents, 3);
+    assert_eq!(result.indexes, 1);
+    assert_eq!(result.skipped_indexes, vec![
+        "posts.title_1_tags_1".to_string(),
+        "posts.title_text".to_string(),
+        "posts.tags_1".to_string(),
+    ]);
+    assert_eq!(db.list_collection_names().unwrap().len(), 2);
+    assert_eq!(find_all(&db, "posts").len(), 2);
+    assert_eq!(find_all(&db, "plain"), vec![doc! { "_id": "only" }]);
+
+    // the compressed dumps are not supported
+    let gzip_path = prepare_dump_dir("test-restore-mongodump-gzip");
+    fs::create_dir_all(&gzip_path).unwrap();
+    fs::write(gzip_path.join("posts.bson.gz"), b"").unwrap();
+    let err = db.restore(&gzip_path).unwrap_err();
+    assert!(matches!(err, Error::InvalidDump(_)));
+}
+
+#[test]
+fn test_restore_truncated_dump() {
+    let dump_path = prepare_dump_dir("test-restore-truncated-files");
+    fs::create_dir_all(&dump_path).unwrap();
+    let mut bson = Vec::new();
+    for i in 0..1500 {
+        doc! { "_id": i, "name": format!("user-{}", i) }.to_writer(&mut bson).unwrap();
+    }
+    bson.extend_from_slice(&[0x20, 0x00, 0x00]);
+    fs::write(dump_path.join("users.bson"), bson).unwrap();
+
+    // the batches restored before the error are dropped with the collection
+    let db = prepare_db("test-restore-truncated").unwrap();
+    let err = db.restore(&dump_path).unwrap_err();
+    assert!(matches!(err, Error::InvalidDump(_)));
+    assert!(db.list_collection_names().unwrap().is_empty());
+}
+
+#[test]
+fn test_export_and_import_metadata() {
+    let db = prepare_db("test-metadata-source").unwrap();
+    let users = db.collection::<Document>("users");
+    users.insert_one(doc! { "_id": 1, "name": "Alice", "email": "alice@example.com" }).unwrap();
+    users.create_index(IndexModel {
+        keys: doc! { "email": 1 },
+        options: Some(IndexOptions {
+            name: Some("email_unique".to_string()),
+            unique: Some(true),
+            ..Default::default()
+        }),
+    }).unwrap();
+    db.set_validator("users", doc! {
+        "$jsonSchema": { "required": ["name"] },
+    }, ValidationAction::Error).unwrap();
+    db.create_collection_with_options("logs", CreateCollectionOptions::builder()
+        .capped(true)
+        .max(100)
+        .build()).unwrap();
+    db.create_view("named", "users", vec![doc! { "$match": { "name": "Alice" } }]).unwrap();
+
+    let mut metadata = Vec::new();
+    db.export_metadata(&mut metadata).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&metadata).unwrap();
+    assert_eq!(json["version"], 1);
+    let names: Vec<&str> = json["collections"].as_array().unwrap().iter()
+        .map(|collection| collection["collectionName"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["logs", "named", "users"]);
+    assert_eq!(json["collections"][2]["indexes"][1]["key"]["email"], 1);
+
+    // the target has an extra index and no validator
+    let target = prepare_db("test-metadata-target").unwrap();
+    let target_users = target.collection::<Document>("users");
+    target_users.insert_one(doc! { "_id": 1, "email": "bob@example.com" }).unwrap();
+    target_users.create_index(IndexModel {
+        keys: doc! { "age": 1 },
+        options: None,
+    }).unwrap();
+
+    let result = target.import_metadata(metadata.as_slice()).unwrap();
+    assert_eq!(result.created_collections, vec!["logs".to_string(), "named".to_string()]);
+    assert_eq!(result.updated_collections, vec!["users".to_string()]);
+    assert_eq!(result.created_indexes, vec!["users.email_unique".to_string()]);
+    assert_eq!(result.dropped_indexes, vec!["users.age_1".to_string()]);
+
+    let err = target_users.insert_one(doc! { "_id": 2 }).unwrap_err();
+    assert!(matches!(err, Error::DocumentValidationFailed(_)));
+    let err = target_users.insert_one(doc! { "_id": 3, "name": "x", "email": "bob@example.com" }).unwrap_err();
+    assert!(matches!(err, Error::DuplicateKey(_)));
+    assert_eq!(target.collection::<Document>("named").count_documents().unwrap(), 0);
+
+    let mut exported = Vec::new();
+    target.export_metadata(&mut exported).unwrap();
+    assert_eq!(exported, metadata);
+
+    // importing again changes nothing
+    let result = target.import_metadata(metadata.as_slice()).unwrap();
+    assert!(result.created_collections.is_empty());
+    assert!(result.updated_collections.is_empty());
+    assert!(result.created_indexes.is_empty());
+    assert!(result.dropped_indexes.is_empty());
+
+    // a collection created with other options conflicts
+    let other = prepare_db("test-metadata-conflict").unwrap();
+    other.create_collection("logs").unwrap();
+    let err = other.import_metadata(metadata.as_slice()).unwrap_err();
+    assert_eq!(err.collection(), Some("logs"));
+    assert!(matches!(err, Error::MetadataConflict(_)));
+    assert_eq!(other.list_collection_names().unwrap(), vec!["logs".to_string()]);
+
+    let err = other.import_metadata(r#"{ "version": 2, "collections": [] }"#.as_bytes()).unwrap_err();
+    assert!(matches!(err, Error::InvalidMetadata(_)));
+}
@@ -0,0 +1,125 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use polodb_core::{CollectionT, Error, Result};
+use polodb_core::bson::{doc, oid::ObjectId, spec::BinarySubtype, Binary, DateTime, Document};
+use polodb_core::options::{ExportOptions, ExtJsonMode, ImportOptions};
+
+mod common;
+
+use common::prepare_db;
+
+fn find_all(col: &polodb_core::Collection<Document>) -> Vec<Document> {
+    col.find(doc! {})
+        .sort(doc! { "n": 1 })
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap()
+}
+
+#[test]
+fn test_export_import_json_types() {
+    let db = prepare_db("test-extjson-types").unwrap();
+    let col = db.collection::<Document>("items");
+    let docs: Vec<Document> = (0..1500).map(|i| doc! {
+        "_id": ObjectId::new(),
+        "n": i,
+        "big": i as i64 + (1_i64 << 40),
+        "ratio": i as f64 / 4.0,
+        "at": DateTime::from_millis(1_700_000_000_000 + i as i64),
+        "bytes": Binary { subtype: BinarySubtype::Generic, bytes: vec![1, 2, 3] },
+        "tags": ["a", { "nested": true }],
+    }).collect();
+    col.insert_many(&docs).unwrap();
+
+    for mode in [ExtJsonMode::Relaxed, ExtJsonMode::Canonical] {
+        for json_array in [false, true] {
+            let mut output = Vec::new();
+            let options = ExportOptions::builder()
+                .mode(mode)
+                .json_array(json_array)
+                .build();
+            assert_eq!(db.export_json("items", &mut output, options).unwrap(), 1500);
+            let text = String::from_utf8(output.clone()).unwrap();
+            assert_eq!(text.starts_with('['), json_array);
+            if !json_array {
+                assert_eq!(text.lines().count(), 1500);
+            }
+            assert!(text.contains("\"$oid\""));
+
+            // the array is detected
+            let target = db.collection::<Document>("imported");
+            target.drop().unwrap();
+            assert_eq!(db.import_json("imported", output.as_slice(), ImportOptions::default()).unwrap(), 1500);
+            // the relaxed numbers lose their types, only the canonical documents are equal
+            let imported = find_all(&target);
+            assert_eq!(imported.len(), docs.len());
+            for (imported, doc) in imported.iter().zip(&docs) {
+                assert_eq!(imported.get_object_id("_id").unwrap(), doc.get_object_id("_id").unwrap());
+                assert_eq!(imported.get_datetime("at").unwrap(), doc.get_datetime("at").unwrap());
+                assert_eq!(imported.get_binary_generic("bytes").unwrap(), &vec![1, 2, 3]);
+                assert_eq!(imported.get("tags"), doc.get("tags"));
+                if mode == ExtJsonMode::Canonical {
+                    assert_eq!(imported, doc);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_export_json_filter() {
+    let db = prepare_db("test-extjson-filter").unwrap();
+    let col = db.collection::<Document>("users");
+    col.insert_many(vec![
+        doc! { "_id": 1, "age": 10 },
+        doc! { "_id": 2, "age": 20 },
+        doc! { "_id": 3, "age": 30 },
+    ]).unwrap();
+
+    let mut output = Vec::new();
+    let options = ExportOptions::builder()
+        .filter(doc! { "age": { "$gte": 20 } })
+        .build();
+    assert_eq!(db.export_json("users", &mut output, options).unwrap(), 2);
+    assert_eq!(String::from_utf8(output).unwrap(), "{\"_id\":2,\"age\":20}\n{\"_id\":3,\"age\":30}\n");
+}
+
+#[test]
+fn test_import_json_errors() {
+    let db = prepare_db("test-extjson-errors").unwrap();
+    let col = db.collection::<Document>("users");
+    col.insert_one(doc! { "_id": 0 }).unwrap();
+
+    // nothing is inserted if a document is invalid
+    let input = "{\"_id\": 1}\n{\"_id\": 2}\n[1, 2]\n";
+    let err = db.import_json("users", input.as_bytes(), ImportOptions::default()).unwrap_err();
+    assert!(matches!(err, Error::ParseError(_)));
+    let err = db.import_json("users", "[{\"_id\": 1}, ".as_bytes(), ImportOptions::default()).unwrap_err();
+    assert!(matches!(err, Error::ParseError(_)));
+    let err = db.import_json("users", "{\"at\": {\"$date\": \"yesterday\"}}".as_bytes(), ImportOptions::default()).unwrap_err();
+    assert!(matches!(err, Error::ParseError(_)));
+    assert_eq!(col.count_documents().unwrap(), 1);
+
+    // the empty input is fine
+    assert_eq!(db.import_json("users", " \n".as_bytes(), ImportOptions::default()).unwrap(), 0);
+    assert_eq!(db.import_json("users", "[]".as_bytes(), ImportOptions::default()).unwrap(), 0);
+
+    let options = ImportOptions::builder().drop(true).build();
+    assert_eq!(db.import_json("users", "{\"_id\": 5} {\"_id\": 6}".as_bytes(), options).unwrap(), 2);
+    assert_eq!(col.count_documents().unwrap(), 2);
+    assert!(col.find_one(doc! { "_id": 0 }).unwrap().is_none());
+}
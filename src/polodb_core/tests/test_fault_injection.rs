@@ -0,0 +1,84 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(feature = "fault-injection")]
+
+use polodb_core::{ConfigBuilder, Database, CollectionT};
+use polodb_core::bson::{doc, Document};
+use polodb_core::fault_injection::{Fault, FaultInjector};
+
+mod common;
+
+use common::mk_db_path;
+
+fn run_workload(db_name: &str, injector: &FaultInjector) -> Database {
+    let db_path = mk_db_path(db_name);
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    let mut config = ConfigBuilder::new();
+    config.set_fault_injector(injector.clone());
+    {
+        let db = Database::open_path_with_config(db_path.as_path(), config.take()).unwrap();
+        let collection = db.collection::<Document>("test");
+        // commit 0 also creates the collection
+        collection.insert_one(doc! { "_id": 0 }).unwrap();
+        for i in 1..4 {
+            collection.insert_one(doc! { "_id": i }).unwrap();
+        }
+    }
+
+    Database::open_path(db_path.as_path()).unwrap()
+}
+
+fn find_ids(db: &Database) -> Vec<i32> {
+    let collection = db.collection::<Document>("test");
+    collection.find(doc! {})
+        .run()
+        .unwrap()
+        .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+        .collect()
+}
+
+#[test]
+fn test_drop_write() {
+    let injector = FaultInjector::new();
+    injector.inject_at(2, Fault::DropWrite);
+
+    let db = run_workload("test-fault-drop-write", &injector);
+    assert_eq!(find_ids(&db), vec![0, 1, 3]);
+    assert_eq!(injector.commit_count(), 4);
+    assert_eq!(injector.injected_count(), 1);
+}
+
+#[test]
+fn test_truncate_write() {
+    let injector = FaultInjector::new();
+    // only the collection metadata is written
+    injector.inject_at(0, Fault::Truncate(1));
+
+    let db = run_workload("test-fault-truncate-write", &injector);
+    assert_eq!(find_ids(&db), vec![1, 2, 3]);
+}
+
+#[test]
+fn test_reorder_write() {
+    let injector = FaultInjector::new();
+    injector
+        .inject_at(1, Fault::Reorder)
+        .inject_at(3, Fault::Reorder);
+
+    // the last commit is held back when the database is closed
+    let db = run_workload("test-fault-reorder-write", &injector);
+    assert_eq!(find_ids(&db), vec![0, 1, 2]);
+}
@@ -354,3 +354,302 @@ fn test_find_sort() {
     assert_eq!(result[1].get("name").unwrap().as_str().unwrap(), "banana");
     assert_eq!(result[2].get("name").unwrap().as_str().unwrap(), "orange");
 }
+
+#[test]
+fn test_find_sort_priority() {
+    let db = prepare_db("test-find-sort-priority").unwrap();
+
+    let fruits = db.collection::<Document>("fruits");
+    fruits.insert_many(vec![
+        doc! { "name": "apple", "shape": "round", "weight": 1 },
+        doc! { "name": "banana", "shape": "long", "weight": 2 },
+        doc! { "name": "orange", "shape": "round", "weight": 3 },
+        doc! { "name": "cucumber", "shape": "long", "weight": 4 },
+    ]).unwrap();
+
+    let names = |sort: Document| -> Vec<String> {
+        fruits
+            .find(doc! {})
+            .sort(sort)
+            .run()
+            .unwrap()
+            .map(|doc| doc.unwrap().get_str("name").unwrap().to_string())
+            .collect()
+    };
+
+    // the first field of the sort decides first
+    assert_eq!(
+        names(doc! { "shape": 1, "weight": -1 }),
+        vec!["cucumber", "banana", "orange", "apple"],
+    );
+    assert_eq!(
+        names(doc! { "weight": -1, "shape": 1 }),
+        vec!["cucumber", "orange", "banana", "apple"],
+    );
+}
+
+#[test]
+fn test_find_range() {
+    use std::ops::Bound;
+    use polodb_core::bson::Bson;
+    use polodb_core::Error;
+
+    let db = prepare_db("test-find-range").unwrap();
+    let collection = db.collection::<Document>("events");
+    collection.insert_many((0..100i64).map(|i| doc! {
+        "_id": i,
+        "value": i * 10,
+    })).unwrap();
+    collection.insert_one(doc! { "_id": "a string" }).unwrap();
+
+    let ids = |range: (Bound<Bson>, Bound<Bson>)| -> Vec<i64> {
+        collection.find_range(range).unwrap()
+            .map(|doc| doc.unwrap().get_i64("_id").unwrap())
+            .collect()
+    };
+    assert_eq!(
+        ids((Bound::Included(Bson::Int64(10)), Bound::Excluded(Bson::Int64(15)))),
+        vec![10, 11, 12, 13, 14],
+    );
+    assert_eq!(
+        ids((Bound::Excluded(Bson::Int64(10)), Bound::Included(Bson::Int64(15)))),
+        vec![11, 12, 13, 14, 15],
+    );
+    assert_eq!(ids((Bound::Included(Bson::Int64(97)), Bound::Unbounded)), vec![97, 98, 99]);
+    assert_eq!(ids((Bound::Included(Bson::Int64(200)), Bound::Unbounded)), Vec::<i64>::new());
+    assert!(ids((Bound::Included(Bson::Int64(50)), Bound::Excluded(Bson::Int64(50)))).is_empty());
+
+    // the strings are stored before the numbers
+    let docs = collection.find_range(..Bson::Int64(3)).unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(docs.len(), 4);
+    assert_eq!(docs[0].get_str("_id").unwrap(), "a string");
+    assert_eq!(collection.find_range(..).unwrap().count(), 101);
+
+    let err = collection.find_range(Bson::Int64(-5)..Bson::Int64(5)).err().unwrap();
+    assert!(matches!(err, Error::ValidationError(_)));
+    let err = collection.find_range(Bson::Int64(1)..Bson::String("b".to_string())).err().unwrap();
+    assert!(matches!(err, Error::ValidationError(_)));
+
+    // the writes of the transaction are visible to its range scans
+    let txn = db.start_transaction().unwrap();
+    let txn_collection = txn.collection::<Document>("events");
+    txn_collection.delete_one(doc! { "_id": 1i64 }).unwrap();
+    assert_eq!(txn_collection.find_range(Bson::Int64(0)..Bson::Int64(3)).unwrap().count(), 2);
+    txn.rollback().unwrap();
+    assert_eq!(collection.find_range(Bson::Int64(0)..Bson::Int64(3)).unwrap().count(), 3);
+
+    let missing = db.collection::<Document>("missing");
+    assert_eq!(missing.find_range(..).unwrap().count(), 0);
+}
+
+#[test]
+fn test_find_many_by_ids() {
+    use polodb_core::bson::Bson;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Item {
+        _id: i64,
+        name: String,
+    }
+
+    let db = prepare_db("test-find-many-by-ids").unwrap();
+    let collection = db.collection::<Item>("items");
+    db.collection::<Document>("items").insert_many((0..500i64).map(|i| doc! {
+        "_id": i,
+        "name": format!("item-{}", i),
+    })).unwrap();
+
+    let ids = vec![
+        Bson::Int64(42),
+        Bson::Int64(7),
+        Bson::Int64(1000),
+        Bson::Int64(42),
+        Bson::String("missing".to_string()),
+        Bson::Int64(499),
+    ];
+    let items = collection.find_many_by_ids(&ids).unwrap();
+    let names: Vec<Option<String>> = items.into_iter().map(|item| item.map(|item| item.name)).collect();
+    assert_eq!(names, vec![
+        Some("item-42".to_string()),
+        Some("item-7".to_string()),
+        None,
+        Some("item-42".to_string()),
+        None,
+        Some("item-499".to_string()),
+    ]);
+    assert!(collection.find_many_by_ids(&[]).unwrap().is_empty());
+
+    // the lookups see the writes of the transaction
+    let txn = db.start_transaction().unwrap();
+    let txn_collection = txn.collection::<Item>("items");
+    txn_collection.delete_one(doc! { "_id": 7i64 }).unwrap();
+    let items = txn_collection.find_many_by_ids(&[Bson::Int64(7), Bson::Int64(8)]).unwrap();
+    assert_eq!(items, vec![None, Some(Item { _id: 8, name: "item-8".to_string() })]);
+    txn.rollback().unwrap();
+
+    let missing = db.collection::<Item>("missing");
+    assert_eq!(missing.find_many_by_ids(&[Bson::Int64(1)]).unwrap(), vec![None]);
+}
+
+#[test]
+fn test_exists() {
+    let db = prepare_db("test-exists").unwrap();
+    let collection = db.collection::<Document>("items");
+    collection.insert_many((0..100i64).map(|i| doc! {
+        "_id": i,
+        "group": i % 10,
+    })).unwrap();
+
+    assert!(collection.exists(doc! { "_id": 42i64 }).unwrap());
+    assert!(!collection.exists(doc! { "_id": 420i64 }).unwrap());
+    assert!(!collection.exists(doc! { "_id": 42 }).unwrap());
+    assert!(collection.exists(doc! { "_id": { "$gt": 98i64 } }).unwrap());
+    assert!(collection.exists(doc! { "group": 3i64 }).unwrap());
+    assert!(!collection.exists(doc! { "group": 30i64 }).unwrap());
+    assert!(collection.exists(doc! { "_id": 42i64, "group": 2i64 }).unwrap());
+    assert!(!collection.exists(doc! { "_id": 42i64, "group": 3i64 }).unwrap());
+    assert!(collection.exists(doc! {}).unwrap());
+
+    let txn = db.start_transaction().unwrap();
+    let txn_collection = txn.collection::<Document>("items");
+    txn_collection.delete_one(doc! { "_id": 42i64 }).unwrap();
+    assert!(!txn_collection.exists(doc! { "_id": 42i64 }).unwrap());
+    assert!(collection.exists(doc! { "_id": 42i64 }).unwrap());
+    txn.rollback().unwrap();
+
+    let missing = db.collection::<Document>("missing");
+    assert!(!missing.exists(doc! { "_id": 1 }).unwrap());
+    assert!(!missing.exists(doc! {}).unwrap());
+}
+
+#[test]
+fn test_find_parallel() {
+    let db = prepare_db("test-find-parallel").unwrap();
+    let collection = db.collection::<Document>("events");
+    collection.insert_many((0..1000i64).map(|i| doc! {
+        "_id": i,
+        "value": i % 7,
+    })).unwrap();
+
+    let filter = doc! { "value": 3 };
+    let expected: Vec<Document> = collection.find(filter.clone()).run().unwrap()
+        .map(|doc| doc.unwrap())
+        .collect();
+    assert_eq!(expected.len(), 143);
+
+    let cursor = collection.find(filter.clone()).max_parallelism(4).run().unwrap();
+    assert!(cursor.to_string().starts_with("Parallel scan of 4 partitions"));
+
+    // the partitions read the snapshot of the query
+    collection.insert_one(doc! { "_id": 1000i64, "value": 3 }).unwrap();
+
+    let result: Vec<Document> = cursor.map(|doc| doc.unwrap()).collect();
+    assert_eq!(result, expected);
+
+    let all = collection.find(doc! {}).max_parallelism(3).run().unwrap().count();
+    assert_eq!(all, 1001);
+}
+
+#[test]
+fn test_find_natural() {
+    use polodb_core::Error;
+    use polodb_core::options::CreateCollectionOptions;
+
+    let db = prepare_db("test-find-natural").unwrap();
+    db.create_collection_with_options(
+        "logs",
+        CreateCollectionOptions::builder().capped(true).size(1 << 20).build(),
+    ).unwrap();
+    let collection = db.collection::<Document>("logs");
+    collection.create_index(polodb_core::IndexModel {
+        keys: doc! { "level": 1 },
+        options: None,
+    }).unwrap();
+    // the ids are not in insertion order
+    collection.insert_many((0..10).map(|i| doc! {
+        "_id": (i * 7) % 10,
+        "seq": i,
+        "level": i % 2,
+    })).unwrap();
+
+    let seqs = |sort: Document, filter: Document, limit: Option<u64>| -> Vec<i32> {
+        let mut find = collection.find(filter).sort(sort);
+        if let Some(limit) = limit {
+            find = find.limit(limit);
+        }
+        find.run().unwrap()
+            .map(|doc| doc.unwrap().get_i32("seq").unwrap())
+            .collect()
+    };
+
+    assert_eq!(seqs(doc! { "$natural": 1 }, doc! {}, None), (0..10).collect::<Vec<_>>());
+    assert_eq!(seqs(doc! { "$natural": -1 }, doc! {}, Some(3)), vec![9, 8, 7]);
+    // the index is not used
+    assert_eq!(seqs(doc! { "$natural": -1 }, doc! { "level": 1 }, None), vec![9, 7, 5, 3, 1]);
+
+    let result = collection.aggregate(vec![
+        doc! { "$sort": { "$natural": -1 } },
+        doc! { "$limit": 2 },
+    ]).run().unwrap().map(|doc| doc.unwrap().get_i32("seq").unwrap()).collect::<Vec<_>>();
+    assert_eq!(result, vec![9, 8]);
+
+    assert!(collection.find(doc! {}).sort(doc! { "$natural": 2 }).run().is_err());
+    assert!(collection.find(doc! {}).sort(doc! { "$natural": 1, "seq": 1 }).run().is_err());
+    assert!(collection.aggregate(vec![
+        doc! { "$limit": 2 },
+        doc! { "$sort": { "$natural": -1 } },
+    ]).run().is_err());
+
+    // the other collections are in the order of _id
+    let others = db.collection::<Document>("others");
+    others.insert_one(doc! { "seq": 0 }).unwrap();
+    let result = others.find(doc! {}).sort(doc! { "$natural": 1 }).run();
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+    let result = others.aggregate(vec![doc! { "$sort": { "$natural": -1 } }]).run();
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+}
+
+#[test]
+fn test_find_json_schema() {
+    let db = prepare_db("test-find-json-schema").unwrap();
+    let collection = db.collection::<Document>("users");
+    collection.insert_many(vec![
+        doc! { "_id": 1, "name": "alice", "age": 30 },
+        doc! { "_id": 2, "name": 42, "age": 25 },
+        doc! { "_id": 3, "age": 40 },
+        doc! { "_id": 4, "name": "bob", "age": "unknown" },
+    ]).unwrap();
+
+    let schema = doc! {
+        "bsonType": "object",
+        "required": ["name"],
+        "properties": {
+            "name": { "bsonType": "string" },
+            "age": { "bsonType": "int", "minimum": 0 },
+        },
+    };
+    let ids = |filter: Document| -> Vec<i32> {
+        collection.find(filter).run().unwrap()
+            .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+            .collect()
+    };
+
+    assert_eq!(ids(doc! { "$jsonSchema": schema.clone() }), vec![1]);
+    // the documents violating the schema
+    assert_eq!(ids(doc! { "$nor": [{ "$jsonSchema": schema.clone() }] }), vec![2, 3, 4]);
+    assert_eq!(ids(doc! {
+        "$nor": [{ "$jsonSchema": schema.clone() }],
+        "age": { "$gt": 30 },
+    }), vec![3]);
+
+    let count = collection.aggregate(vec![
+        doc! { "$match": { "$nor": [{ "$jsonSchema": schema }] } },
+        doc! { "$count": "count" },
+    ]).run().unwrap().next().unwrap().unwrap();
+    assert_eq!(count.get_i64("count").unwrap(), 3);
+
+    assert!(collection.find(doc! { "$jsonSchema": { "bsonType": "none" } }).run().is_err());
+}
@@ -0,0 +1,159 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+#![cfg(feature = "graphql")]
+
+use polodb_core::{CollectionT, Database};
+use polodb_core::bson::{doc, Bson, Document};
+use polodb_core::graphql::{GraphQlRequest, GraphQlSchema};
+use polodb_core::options::CreateCollectionOptions;
+
+mod common;
+
+use common::prepare_db;
+
+fn prepare_users(db: &Database) {
+    db.create_collection_with_options(
+        "users",
+        CreateCollectionOptions::builder().validator(doc! {
+            "$jsonSchema": {
+                "bsonType": "object",
+                "required": ["name"],
+                "properties": {
+                    "_id": { "bsonType": "int" },
+                    "name": { "bsonType": "string" },
+                    "age": { "bsonType": "int" },
+                    "address": {
+                        "bsonType": "object",
+                        "properties": { "city": { "bsonType": "string" } },
+                    },
+                },
+            },
+        }).build(),
+    ).unwrap();
+    db.collection::<Document>("users").insert_many(vec![
+        doc! { "_id": 1, "name": "Alice", "age": 30, "address": { "city": "Paris" } },
+        doc! { "_id": 2, "name": "Bob", "age": 25 },
+        doc! { "_id": 3, "name": "Carol", "age": 41, "address": { "city": "Oslo" } },
+    ]).unwrap();
+}
+
+#[test]
+fn test_graphql_query() {
+    let db = prepare_db("test-graphql-query").unwrap();
+    prepare_users(&db);
+    db.collection::<Document>("logs").insert_one(doc! { "level": "info" }).unwrap();
+    let schema = GraphQlSchema::from_database(&db).unwrap();
+
+    let sdl = schema.sdl();
+    assert!(sdl.contains("type Users {\n  _id: Int!\n  name: String!\n  age: Int\n  address: UsersAddress\n}"), "{}", sdl);
+    assert!(sdl.contains("type Logs {\n  _id: ID!\n  document: JSON!\n}"), "{}", sdl);
+
+    let response = schema.execute(&db, &GraphQlRequest::new(r#"
+        query Adults($filter: JSON, $limit: Int = 10) {
+            users(filter: $filter, sort: { age: -1 }, limit: $limit) {
+                ...names
+                town: address { city }
+            }
+            total: usersCount
+        }
+        fragment names on Users { _id name }
+    "#).variables(doc! { "filter": { "age": { "$gte": 30 } } }));
+    assert_eq!(response, doc! {
+        "data": {
+            "users": [
+                { "_id": 3, "name": "Carol", "town": { "city": "Oslo" } },
+                { "_id": 1, "name": "Alice", "town": { "city": "Paris" } },
+            ],
+            "total": 3,
+        },
+    });
+
+    let response = schema.execute(&db, &GraphQlRequest::new(r#"
+        { logs { __typename document } }
+    "#));
+    let logs = response.get_document("data").unwrap().get_array("logs").unwrap();
+    assert_eq!(logs.len(), 1);
+    let log = logs[0].as_document().unwrap();
+    assert_eq!(log.get_str("__typename").unwrap(), "Logs");
+    assert_eq!(log.get_document("document").unwrap().get_str("level").unwrap(), "info");
+}
+
+#[test]
+fn test_graphql_mutation() {
+    let db = prepare_db("test-graphql-mutation").unwrap();
+    prepare_users(&db);
+    let schema = GraphQlSchema::from_database(&db).unwrap();
+
+    let response = schema.execute(&db, &GraphQlRequest::new(r#"
+        mutation($update: JSON!) {
+            insertUsers(document: { _id: 4, name: "Dave", age: 19 })
+            updateUsers(filter: {}, update: $update, many: true) { matchedCount modifiedCount }
+            deleteUsers(filter: { name: "Bob" })
+        }
+    "#).variables(doc! { "update": { "$inc": { "age": 1 } } }));
+    assert_eq!(response, doc! {
+        "data": {
+            "insertUsers": "4",
+            "updateUsers": { "matchedCount": 4, "modifiedCount": 4 },
+            "deleteUsers": 1,
+        },
+    });
+    let col = db.collection::<Document>("users");
+    assert_eq!(col.count_documents().unwrap(), 3);
+    assert_eq!(col.find_one(doc! { "_id": 4 }).unwrap().unwrap().get_i32("age").unwrap(), 20);
+
+    // the failing field rolls back the whole mutation
+    let response = schema.execute(&db, &GraphQlRequest::new(r#"
+        mutation {
+            deleteUsers(filter: {}, many: true)
+            insertUsers(document: { _id: 5 })
+        }
+    "#));
+    assert_eq!(response.get("data"), Some(&Bson::Null));
+    let errors = response.get_array("errors").unwrap();
+    assert_eq!(errors.len(), 1);
+    let error = errors[0].as_document().unwrap();
+    assert_eq!(error.get_array("path").unwrap(), &vec![Bson::String("insertUsers".to_string())]);
+    assert_eq!(col.count_documents().unwrap(), 3);
+}
+
+#[test]
+fn test_graphql_errors() {
+    let db = Database::open_memory().unwrap();
+    let schema = GraphQlSchema::builder()
+        .untyped_collection("items")
+        .build()
+        .unwrap();
+
+    let error_message = |query: &str| {
+        let response = schema.execute(&db, &GraphQlRequest::new(query));
+        let errors = response.get_array("errors").expect(query);
+        errors[0].as_document().unwrap().get_str("message").unwrap().to_string()
+    };
+
+    assert!(error_message("{ items { _id ").starts_with("parse error: Syntax Error"));
+    assert_eq!(error_message("{ items { price } }"), "Cannot query field \"price\" on type \"Items\".");
+    assert_eq!(error_message("{ items }"), "Field \"items\" of type \"[Items!]!\" must have a selection of subfields.");
+    assert_eq!(error_message("{ itemsCount(limit: 1) }"), "Unknown argument \"limit\" on field \"itemsCount\".");
+    assert_eq!(error_message("mutation { deleteItems(many: true) }"), "Argument \"filter\" of required type \"JSON!\" was not provided.");
+    assert_eq!(error_message("query A { itemsCount } query B { itemsCount }"), "Must provide the operation name if the query contains several operations.");
+    assert_eq!(error_message("subscription { items { _id } }"), "The subscriptions are not supported.");
+
+    let response = schema.execute(&db, &GraphQlRequest::new(r#"
+        query A { itemsCount } query B { itemsCount @skip(if: true) __typename }
+    "#).operation_name("B"));
+    assert_eq!(response, doc! { "data": { "__typename": "Query" } });
+}
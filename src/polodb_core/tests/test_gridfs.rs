@@ -0,0 +1,108 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{Read, Seek, SeekFrom, Write};
+use polodb_core::{CollectionT, Database, Error};
+use polodb_core::bson::{doc, Document};
+
+mod common;
+
+use common::prepare_db;
+
+fn make_data(size: usize) -> Vec<u8> {
+    (0..size).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn test_upload_and_download() {
+    let db = prepare_db("test-gridfs-upload").unwrap();
+    let bucket = db.fs().with_chunk_size(1000);
+    let data = make_data(10_500);
+
+    let mut upload = bucket.open_upload_stream("data.bin", Some(doc! { "kind": "test" })).unwrap();
+    // write in pieces which don't align with the chunks
+    for piece in data.chunks(777) {
+        upload.write_all(piece).unwrap();
+    }
+    let id = upload.finish().unwrap();
+
+    let file = bucket.find_file(id).unwrap().unwrap();
+    assert_eq!(file.length, data.len() as u64);
+    assert_eq!(file.chunk_size, 1000);
+    assert_eq!(file.chunk_count(), 11);
+    assert_eq!(file.filename, "data.bin");
+    assert_eq!(file.metadata.unwrap().get_str("kind").unwrap(), "test");
+    assert_eq!(db.collection::<Document>("fs_chunks").count_documents().unwrap(), 11);
+
+    assert_eq!(bucket.download(id).unwrap(), data);
+    assert_eq!(bucket.find(doc! { "filename": "data.bin" }).unwrap().len(), 1);
+}
+
+#[test]
+fn test_range_read() {
+    let db = prepare_db("test-gridfs-range").unwrap();
+    let bucket = db.fs().with_chunk_size(1000);
+    let data = make_data(5000);
+    let id = bucket.upload_from_slice("data.bin", &data, None).unwrap();
+
+    assert_eq!(bucket.download_range(id, 990..2010).unwrap(), &data[990..2010]);
+    assert_eq!(bucket.download_range(id, 4990..6000).unwrap(), &data[4990..]);
+    assert!(bucket.download_range(id, 6000..7000).unwrap().is_empty());
+
+    let mut stream = bucket.open_download_stream(id).unwrap();
+    stream.seek(SeekFrom::End(-10)).unwrap();
+    let mut tail = Vec::new();
+    stream.read_to_end(&mut tail).unwrap();
+    assert_eq!(tail, &data[4990..]);
+    assert!(stream.seek(SeekFrom::Current(-6000)).is_err());
+}
+
+#[test]
+fn test_delete_and_abort() {
+    let db = prepare_db("test-gridfs-delete").unwrap();
+    let bucket = db.fs_bucket("attachments").with_chunk_size(100);
+    let id = bucket.upload_from_slice("a.bin", &make_data(1000), None).unwrap();
+    let chunks = db.collection::<Document>("attachments_chunks");
+    assert_eq!(chunks.count_documents().unwrap(), 10);
+
+    bucket.delete(id).unwrap();
+    assert!(bucket.find_file(id).unwrap().is_none());
+    assert_eq!(chunks.count_documents().unwrap(), 0);
+    assert!(matches!(bucket.open_download_stream(id), Err(Error::FileNotFound(_))));
+    assert!(matches!(bucket.delete(id), Err(Error::FileNotFound(_))));
+
+    // the incomplete uploads are invisible and cleaned up
+    let mut upload = bucket.open_upload_stream("b.bin", None).unwrap();
+    upload.write_all(&make_data(550)).unwrap();
+    let id = upload.id();
+    assert_eq!(chunks.count_documents().unwrap(), 5);
+    assert!(bucket.find_file(id).unwrap().is_none());
+    upload.abort().unwrap();
+    assert_eq!(chunks.count_documents().unwrap(), 0);
+
+    let mut upload = bucket.open_upload_stream("c.bin", None).unwrap();
+    upload.write_all(&make_data(550)).unwrap();
+    drop(upload);
+    assert_eq!(chunks.count_documents().unwrap(), 0);
+}
+
+#[test]
+fn test_empty_file() {
+    let db = Database::open_memory().unwrap();
+    let bucket = db.fs();
+    let id = bucket.upload_from_slice("empty", &[], None).unwrap();
+    assert_eq!(bucket.find_file(id).unwrap().unwrap().chunk_count(), 0);
+    assert!(bucket.download(id).unwrap().is_empty());
+    bucket.delete(id).unwrap();
+}
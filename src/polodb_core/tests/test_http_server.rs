@@ -0,0 +1,310 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+#![cfg(feature = "http-server")]
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+use polodb_core::{CollectionT, Database};
+use polodb_core::bson::{doc, oid::ObjectId, Document};
+use polodb_core::http_server::HttpServer;
+use serde_json::{json, Value};
+
+fn read_response<R: BufRead>(reader: &mut R) -> (u16, Value) {
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line).unwrap();
+    let status = status_line.split_whitespace().nth(1).unwrap().parse().unwrap();
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':').unwrap();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.trim().parse().unwrap();
+        }
+    }
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+fn request(addr: SocketAddr, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+    request_with_auth(addr, method, path, None, body)
+}
+
+fn request_with_auth(addr: SocketAddr, method: &str, path: &str, authorization: Option<&str>, body: Option<Value>) -> (u16, Value) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let body = body.map(|body| body.to_string()).unwrap_or_default();
+    let authorization = authorization
+        .map(|authorization| format!("Authorization: {}\r\n", authorization))
+        .unwrap_or_default();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{}Content-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        authorization,
+        body.len(),
+        body,
+    ).unwrap();
+    read_response(&mut BufReader::new(stream))
+}
+
+#[test]
+fn test_http_crud() {
+    let db = Database::open_memory().unwrap();
+    let handle = HttpServer::bind(db, "127.0.0.1:0").unwrap().spawn().unwrap();
+    let addr = handle.local_addr();
+
+    let oid = ObjectId::new();
+    let (status, body) = request(addr, "POST", "/collections/books", Some(json!({
+        "_id": { "$oid": oid.to_hex() },
+        "title": "1984",
+        "year": 1949,
+    })));
+    assert_eq!(status, 200);
+    assert_eq!(body, json!({ "insertedId": { "$oid": oid.to_hex() } }));
+
+    let (status, _) = request(addr, "POST", "/collections/books", Some(json!([
+        { "title": "Animal Farm", "year": 1945 },
+        { "title": "The Great Gatsby", "year": 1925 },
+    ])));
+    assert_eq!(status, 200);
+
+    let (_, body) = request(addr, "GET", "/collections", None);
+    assert_eq!(body, json!(["books"]));
+
+    let (_, body) = request(addr, "GET", "/collections/books/count", None);
+    assert_eq!(body, json!({ "count": 3 }));
+
+    let (status, body) = request(addr, "POST", "/collections/books/find", Some(json!({
+        "filter": { "year": { "$lt": 1950 } },
+        "sort": { "year": 1 },
+        "limit": 2,
+    })));
+    assert_eq!(status, 200);
+    let titles: Vec<&str> = body.as_array().unwrap().iter().map(|doc| doc["title"].as_str().unwrap()).collect();
+    assert_eq!(titles, vec!["The Great Gatsby", "Animal Farm"]);
+
+    let (_, body) = request(addr, "GET", "/collections/books?filter=%7B%22title%22%3A%221984%22%7D", None);
+    assert_eq!(body[0]["_id"], json!({ "$oid": oid.to_hex() }));
+
+    let (_, body) = request(addr, "POST", "/collections/books/update", Some(json!({
+        "filter": { "year": { "$gt": 1930 } },
+        "update": { "$set": { "classic": true } },
+        "many": true,
+    })));
+    assert_eq!(body, json!({ "matchedCount": 2, "modifiedCount": 2 }));
+
+    let (_, body) = request(addr, "POST", "/collections/books/aggregate", Some(json!({
+        "pipeline": [
+            { "$match": { "classic": true } },
+            { "$count": "count" },
+        ],
+    })));
+    assert_eq!(body, json!([{ "count": 2 }]));
+
+    let (_, body) = request(addr, "POST", "/collections/books/delete", Some(json!({
+        "filter": { "title": "1984" },
+    })));
+    assert_eq!(body, json!({ "deletedCount": 1 }));
+
+    let (status, _) = request(addr, "DELETE", "/collections/books", None);
+    assert_eq!(status, 200);
+    let (_, body) = request(addr, "GET", "/collections", None);
+    assert_eq!(body, json!([]));
+
+    handle.shutdown().unwrap();
+}
+
+#[test]
+fn test_http_errors() {
+    let db = Database::open_memory().unwrap();
+    db.collection::<Document>("books").insert_one(doc! { "title": "1984" }).unwrap();
+    let handle = HttpServer::bind(db, "127.0.0.1:0").unwrap().spawn().unwrap();
+    let addr = handle.local_addr();
+
+    let (status, _) = request(addr, "GET", "/unknown", None);
+    assert_eq!(status, 404);
+
+    let (status, _) = request(addr, "PUT", "/collections/books", None);
+    assert_eq!(status, 405);
+
+    let (status, body) = request(addr, "POST", "/collections/books/update", Some(json!({
+        "filter": {},
+    })));
+    assert_eq!(status, 400);
+    assert!(body["error"].as_str().unwrap().contains("update"));
+
+    let (status, _) = request(addr, "POST", "/collections/books/find", Some(json!({
+        "filter": { "title": { "$unknown": 1 } },
+    })));
+    assert_eq!(status, 400);
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"POST /collections/books HTTP/1.1\r\nContent-Length: 9\r\n\r\nnot json!").unwrap();
+    let (status, _) = read_response(&mut BufReader::new(stream));
+    assert_eq!(status, 400);
+
+    handle.shutdown().unwrap();
+}
+
+#[test]
+fn test_http_auth() {
+    use polodb_core::auth::Role;
+
+    let db = Database::open_memory().unwrap();
+    db.collection::<Document>("books").insert_one(doc! { "title": "1984" }).unwrap();
+    let users = db.users();
+    users.create("reader", "secret", vec![Role::read_any_database().collection("books")]).unwrap();
+    users.create("writer", "secret", vec![Role::read_write_any_database()]).unwrap();
+    let token = users.create_token("writer").unwrap();
+    let handle = HttpServer::bind(db, "127.0.0.1:0").unwrap().require_auth().spawn().unwrap();
+    let addr = handle.local_addr();
+
+    let (status, _) = request(addr, "GET", "/collections/books", None);
+    assert_eq!(status, 401);
+    // "reader:wrong"
+    let (status, _) = request_with_auth(addr, "GET", "/collections/books", Some("Basic cmVhZGVyOndyb25n"), None);
+    assert_eq!(status, 401);
+    let (status, _) = request_with_auth(addr, "GET", "/collections/books", Some("Bearer 0123"), None);
+    assert_eq!(status, 401);
+
+    // "reader:secret"
+    let reader = Some("Basic cmVhZGVyOnNlY3JldA==");
+    let (status, body) = request_with_auth(addr, "GET", "/collections/books", reader, None);
+    assert_eq!(status, 200);
+    assert_eq!(body[0]["title"], json!("1984"));
+    let (status, _) = request_with_auth(addr, "POST", "/collections/books", reader, Some(json!({ "title": "Emma" })));
+    assert_eq!(status, 403);
+    let (status, _) = request_with_auth(addr, "GET", "/collections/authors/count", reader, None);
+    assert_eq!(status, 403);
+
+    let writer = format!("Bearer {}", token);
+    let writer = Some(writer.as_str());
+    let (status, _) = request_with_auth(addr, "POST", "/collections/authors", writer, Some(json!({ "name": "Orwell" })));
+    assert_eq!(status, 200);
+    let (_, body) = request_with_auth(addr, "GET", "/collections", writer, None);
+    assert_eq!(body, json!(["authors", "books"]));
+    let (status, _) = request_with_auth(addr, "GET", "/collections/__users", writer, None);
+    assert_eq!(status, 403);
+
+    handle.shutdown().unwrap();
+}
+
+#[test]
+fn test_http_keep_alive() {
+    let db = Database::open_memory().unwrap();
+    let handle = HttpServer::bind(db.database("tenant"), "127.0.0.1:0").unwrap().spawn().unwrap();
+
+    let stream = TcpStream::connect(handle.local_addr()).unwrap();
+    let mut writer = stream.try_clone().unwrap();
+    let mut reader = BufReader::new(stream);
+    for i in 0..3 {
+        let body = json!({ "i": i }).to_string();
+        write!(writer, "POST /collections/items HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        let (status, _) = read_response(&mut reader);
+        assert_eq!(status, 200);
+    }
+    writer.write_all(b"GET /collections/items/count HTTP/1.1\r\n\r\n").unwrap();
+    let (_, body) = read_response(&mut reader);
+    assert_eq!(body, json!({ "count": 3 }));
+    drop(handle);
+}
+
+#[test]
+fn test_http_connection_limits() {
+    let db = Database::open_memory().unwrap();
+    let handle = HttpServer::bind(db, "127.0.0.1:0")
+        .unwrap()
+        .max_connections(1)
+        .timeout(Duration::from_millis(300))
+        .spawn()
+        .unwrap();
+    let addr = handle.local_addr();
+
+    // an idle connection holds the only slot
+    let idle = TcpStream::connect(addr).unwrap();
+    let (status, body) = request(addr, "GET", "/collections", None);
+    assert_eq!(status, 503);
+    assert_eq!(body, json!({ "error": "too many connections" }));
+
+    // until it times out
+    let (status, _) = read_response(&mut BufReader::new(idle));
+    assert_eq!(status, 408);
+    let start = Instant::now();
+    loop {
+        let (status, _) = request(addr, "GET", "/collections", None);
+        if status == 200 {
+            break;
+        }
+        assert_eq!(status, 503);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    // a request stalled in the middle times out too,
+    // after the thread of the last connection has released the slot
+    let (status, body) = loop {
+        let mut stalled = TcpStream::connect(addr).unwrap();
+        stalled.write_all(b"POST /collections/items HTTP/1.1\r\nContent-Length: 10\r\n\r\n{").unwrap();
+        let (status, body) = read_response(&mut BufReader::new(stalled));
+        if status != 503 {
+            break (status, body);
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+    };
+    assert_eq!(status, 408);
+    assert_eq!(body, json!({ "error": "the request timed out" }));
+
+    handle.shutdown().unwrap();
+}
+
+#[cfg(feature = "graphql")]
+#[test]
+fn test_http_graphql() {
+    use polodb_core::graphql::GraphQlSchema;
+
+    let db = Database::open_memory().unwrap();
+    db.collection::<Document>("books").insert_one(doc! { "title": "1984", "year": 1949 }).unwrap();
+    let schema = GraphQlSchema::builder()
+        .collection("books", doc! {
+            "bsonType": "object",
+            "properties": {
+                "title": { "bsonType": "string" },
+                "year": { "bsonType": "int" },
+            },
+        })
+        .build()
+        .unwrap();
+    let handle = HttpServer::bind(db, "127.0.0.1:0").unwrap().graphql(schema).spawn().unwrap();
+    let addr = handle.local_addr();
+
+    let (status, body) = request(addr, "POST", "/graphql", Some(json!({
+        "query": "query Books($filter: JSON) { books(filter: $filter) { title year } }",
+        "operationName": null,
+        "variables": { "filter": { "year": { "$lt": 1950 } } },
+    })));
+    assert_eq!(status, 200);
+    assert_eq!(body, json!({ "data": { "books": [{ "title": "1984", "year": 1949 }] } }));
+
+    let (status, _) = request(addr, "GET", "/graphql", None);
+    assert_eq!(status, 405);
+}
@@ -0,0 +1,110 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use std::sync::Arc;
+use polodb_core::{CollectionT, ConfigBuilder, Database, Error};
+use polodb_core::bson::{doc, Bson, Document};
+use polodb_core::bson::spec::BinarySubtype;
+use polodb_core::options::{CreateCollectionOptions, IdStrategy};
+
+fn create(db: &Database, name: &str, strategy: IdStrategy) {
+    db.create_collection_with_options(
+        name,
+        CreateCollectionOptions::builder().id_strategy(strategy).build(),
+    ).unwrap();
+}
+
+fn insert_ids(db: &Database, name: &str, count: i32) -> Vec<Bson> {
+    let col = db.collection::<Document>(name);
+    (0..count)
+        .map(|i| col.insert_one(doc! { "n": i }).unwrap().inserted_id)
+        .collect()
+}
+
+#[test]
+fn test_id_strategy_uuid_v7() {
+    let db = Database::open_memory().unwrap();
+    create(&db, "events", IdStrategy::UuidV7);
+    let ids = insert_ids(&db, "events", 100);
+    for id in &ids {
+        match id {
+            Bson::Binary(binary) => {
+                assert_eq!(binary.subtype, BinarySubtype::Uuid);
+                assert_eq!(binary.bytes[6] >> 4, 7);
+            }
+            _ => panic!("unexpected id: {:?}", id),
+        }
+    }
+
+    // the ids are the cursors of the pages
+    let col = db.collection::<Document>("events");
+    let page: Vec<i32> = col.find(doc! { "_id": { "$gt": ids[49].clone() } }).run().unwrap()
+        .map(|doc| doc.unwrap().get_i32("n").unwrap())
+        .collect();
+    assert_eq!(page, (50..100).collect::<Vec<i32>>());
+}
+
+#[test]
+fn test_id_strategy_snowflake() {
+    let db = Database::open_memory().unwrap();
+    create(&db, "events", IdStrategy::Snowflake);
+    let ids = insert_ids(&db, "events", 100);
+    let ids: Vec<i64> = ids.iter().map(|id| id.as_i64().unwrap()).collect();
+    let mut sorted = ids.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(ids, sorted);
+
+    let col = db.collection::<Document>("events");
+    let n: Vec<i32> = col.find(doc! {}).run().unwrap()
+        .map(|doc| doc.unwrap().get_i32("n").unwrap())
+        .collect();
+    assert_eq!(n, (0..100).collect::<Vec<i32>>());
+
+    // the given ids are kept, the null ids are generated
+    assert_eq!(col.insert_one(doc! { "_id": 1 }).unwrap().inserted_id, Bson::Int32(1));
+    assert!(col.insert_one(doc! { "_id": Bson::Null }).unwrap().inserted_id.as_i64().is_some());
+}
+
+#[test]
+fn test_id_strategy_custom() {
+    let mut config = ConfigBuilder::new();
+    config.set_id_generator("sku", Arc::new(|doc: &Document| {
+        let category = doc.get_str("category")
+            .map_err(|_| Error::ValidationError("category is required".to_string()))?;
+        Ok(Bson::String(format!("{}-{}", category, doc.get_i32("n").unwrap_or_default())))
+    }));
+    let db = Database::open_memory_with_config(config.take()).unwrap();
+    create(&db, "items", IdStrategy::Custom("sku".to_string()));
+    let col = db.collection::<Document>("items");
+    let result = col.insert_one(doc! { "category": "book", "n": 7 }).unwrap();
+    assert_eq!(result.inserted_id, Bson::String("book-7".to_string()));
+
+    // the errors of the generator fail the insert
+    assert!(col.insert_one(doc! { "n": 8 }).is_err());
+    assert_eq!(col.count_documents().unwrap(), 1);
+
+    assert!(matches!(
+        db.create_collection_with_options(
+            "missing",
+            CreateCollectionOptions::builder().id_strategy(IdStrategy::Custom("missing".to_string())).build(),
+        ),
+        Err(Error::IdGeneratorNotFound(_)),
+    ));
+
+    // the default strategy
+    let id = db.collection::<Document>("other").insert_one(doc! {}).unwrap().inserted_id;
+    assert!(id.as_object_id().is_some());
+}
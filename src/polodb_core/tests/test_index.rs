@@ -290,6 +290,39 @@ fn test_delete_with_index() {
     });
 }
 
+#[test]
+fn test_delete_with_unique_index() {
+    let db = prepare_db("test-delete-with-unique-index").unwrap();
+    let col = db.collection::<Document>("teacher");
+
+    col.create_index(IndexModel {
+        keys: doc! {
+            "name": 1,
+        },
+        options: Some(IndexOptions {
+            unique: Some(true),
+            ..Default::default()
+        }),
+    }).unwrap();
+
+    col.insert_one(doc! {
+        "_id": 1,
+        "name": "David",
+    }).unwrap();
+
+    let result = col.delete_many(doc! {
+        "name": "David",
+    }).unwrap();
+    assert_eq!(result.deleted_count, 1);
+
+    // the key is free after the delete
+    col.insert_one(doc! {
+        "_id": 2,
+        "name": "David",
+    }).unwrap();
+    assert_eq!(col.count_documents().unwrap(), 1);
+}
+
 #[test]
 fn test_drop_index() {
     vec![
@@ -325,3 +358,463 @@ fn test_drop_index() {
         assert_eq!(metrics.find_by_index_count(), 0);
     });
 }
+
+#[test]
+fn test_analyze_chooses_selective_index() {
+    use polodb_core::{ConfigBuilder, Database};
+
+    let mut config = ConfigBuilder::new();
+    config.set_slow_query_threshold(std::time::Duration::ZERO);
+    let db = Database::open_memory_with_config(config.take()).unwrap();
+    let col = db.collection::<Document>("tasks");
+    col.create_index(IndexModel {
+        keys: doc! { "status": 1 },
+        options: None,
+    }).unwrap();
+    col.create_index(IndexModel {
+        keys: doc! { "kind": 1 },
+        options: None,
+    }).unwrap();
+
+    let docs: Vec<Document> = (0..100).map(|i| doc! {
+        "_id": i,
+        "status": if i < 95 { "done" } else { "open" },
+        "kind": format!("k{}", i % 10),
+    }).collect();
+    col.insert_many(docs).unwrap();
+
+    // without the statistics the first index is used
+    assert_eq!(col.find(doc! { "status": "done", "kind": "k1" }).run().unwrap().count(), 10);
+
+    let stats = col.analyze().unwrap();
+    assert_eq!(stats["status_1"].count, 100);
+    assert_eq!(stats["status_1"].distinct, 2);
+    assert_eq!(stats["kind_1"].distinct, 10);
+
+    assert_eq!(col.find(doc! { "status": "done", "kind": "k1" }).run().unwrap().count(), 10);
+    assert_eq!(col.find(doc! { "status": "open", "kind": "k5" }).run().unwrap().count(), 1);
+
+    let plans: Vec<String> = db.profile_entries().unwrap()
+        .into_iter()
+        .map(|entry| entry.plan)
+        .collect();
+    assert_eq!(plans, vec!["IXSCAN status_1", "IXSCAN kind_1", "IXSCAN status_1"]);
+}
+
+#[test]
+fn test_text_index() {
+    use std::sync::Arc;
+    use polodb_core::{ConfigBuilder, Database, Error, Tokenizer};
+
+    struct CommaTokenizer;
+
+    impl Tokenizer for CommaTokenizer {
+        fn tokenize(&self, text: &str) -> Vec<String> {
+            text.split(',').map(|token| token.trim().to_string()).collect()
+        }
+    }
+
+    let mut config = ConfigBuilder::new();
+    config.set_slow_query_threshold(std::time::Duration::ZERO);
+    config.set_tokenizer("comma", Arc::new(CommaTokenizer));
+    let db = Database::open_memory_with_config(config.take()).unwrap();
+
+    let notes = db.collection::<Document>("notes");
+    notes.create_index(IndexModel {
+        keys: doc! { "body": "text" },
+        options: Some(IndexOptions {
+            analyzer: Some("english".to_string()),
+            ..Default::default()
+        }),
+    }).unwrap();
+    notes.insert_many(vec![
+        doc! { "_id": 1, "body": "The dog is running in the park", "pinned": true },
+        doc! { "_id": 2, "body": "Dogs run faster than cats", "pinned": false },
+        doc! { "_id": 3, "body": "A cat sleeps all day" },
+        doc! { "_id": 4, "body": "我们在東京大学见面" },
+        doc! { "_id": 5, "body": ["running shoes", "red dog"] },
+    ]).unwrap();
+
+    let ids = |filter: Document| -> Vec<i32> {
+        notes.find(filter).run().unwrap()
+            .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+            .collect::<Vec<i32>>()
+    };
+    let search = |text: &str| -> Vec<i32> {
+        let mut result = ids(doc! { "$text": { "$search": text } });
+        result.sort();
+        result
+    };
+
+    assert_eq!(search("running dogs"), vec![1, 2, 5]);
+    assert_eq!(search("CATS"), vec![2, 3]);
+    assert_eq!(search("東京"), vec![4]);
+    assert_eq!(search("京都"), Vec::<i32>::new());
+    assert_eq!(search("the"), Vec::<i32>::new());
+    assert_eq!(ids(doc! { "$text": { "$search": "dog" }, "pinned": true }), vec![1]);
+
+    notes.update_one(doc! { "_id": 2 }, doc! { "$set": { "body": "Birds fly" } }).unwrap();
+    assert_eq!(search("dog"), vec![1, 5]);
+    assert_eq!(search("bird"), vec![2]);
+    notes.delete_one(doc! { "_id": 1 }).unwrap();
+    assert_eq!(search("dog"), vec![5]);
+    assert_eq!(notes.delete_many(doc! { "$text": { "$search": "shoe" } }).unwrap().deleted_count, 1);
+    assert_eq!(search("dog"), Vec::<i32>::new());
+
+    let plans: Vec<String> = db.profile_entries().unwrap()
+        .into_iter()
+        .filter(|entry| entry.op == "find")
+        .map(|entry| entry.plan)
+        .collect();
+    assert!(plans.iter().all(|plan| plan == "TEXT body_text"), "{:?}", plans);
+
+    let result = notes.create_index(IndexModel {
+        keys: doc! { "title": "text" },
+        options: None,
+    });
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+
+    let tags = db.collection::<Document>("tags");
+    tags.insert_many(vec![
+        doc! { "_id": 1, "names": "New York, Los Angeles, misc" },
+        doc! { "_id": 2, "names": "York, Boston" },
+    ]).unwrap();
+    let result = tags.create_index(IndexModel {
+        keys: doc! { "names": "text" },
+        options: Some(IndexOptions {
+            analyzer: Some("unknown".to_string()),
+            ..Default::default()
+        }),
+    });
+    assert!(matches!(result, Err(Error::TokenizerNotFound(_))));
+    assert!(tags.find(doc! { "$text": { "$search": "york" } }).run().is_err());
+
+    tags.create_index(IndexModel {
+        keys: doc! { "names": "text" },
+        options: Some(IndexOptions {
+            analyzer: Some("comma".to_string()),
+            stop_words: Some(vec!["misc".to_string()]),
+            ..Default::default()
+        }),
+    }).unwrap();
+    let found: Vec<Document> = tags.find(doc! { "$text": { "$search": "new york" } }).run().unwrap()
+        .collect::<Result<Vec<Document>>>().unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].get_i32("_id").unwrap(), 1);
+    assert_eq!(tags.find(doc! { "$text": { "$search": "misc" } }).run().unwrap().count(), 0);
+}
+
+#[test]
+fn test_trigram_index() {
+    use polodb_core::{ConfigBuilder, Database, Error};
+
+    let mut config = ConfigBuilder::new();
+    config.set_slow_query_threshold(std::time::Duration::ZERO);
+    let db = Database::open_memory_with_config(config.take()).unwrap();
+
+    let products = db.collection::<Document>("products");
+    products.insert_many(vec![
+        doc! { "_id": 1, "name": "Jonathan Smith", "sku": "SKU-10042-BLUE" },
+        doc! { "_id": 2, "name": "Johnson Baker", "sku": "SKU-10042-RED" },
+        doc! { "_id": 3, "name": "Jonathon Smyth", "sku": "SKU-20001-BLUE" },
+        doc! { "_id": 4, "name": ["Al", "Alison Gray"], "sku": "SKU-30000" },
+    ]).unwrap();
+
+    let ids = |filter: Document| -> Vec<i32> {
+        let mut result = products.find(filter).run().unwrap()
+            .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+            .collect::<Vec<i32>>();
+        result.sort();
+        result
+    };
+
+    // the matches are the same without an index
+    assert_eq!(ids(doc! { "sku": { "$contains": "0042-b" } }), vec![1]);
+
+    products.create_index(IndexModel {
+        keys: doc! { "sku": "trigram" },
+        options: None,
+    }).unwrap();
+    products.create_index(IndexModel {
+        keys: doc! { "name": "trigram" },
+        options: None,
+    }).unwrap();
+
+    assert_eq!(ids(doc! { "sku": { "$contains": "0042-b" } }), vec![1]);
+    assert_eq!(ids(doc! { "sku": { "$contains": "10042" } }), vec![1, 2]);
+    assert_eq!(ids(doc! { "sku": { "$contains": "sku" }, "_id": { "$gt": 2 } }), vec![3, 4]);
+    assert_eq!(ids(doc! { "name": { "$contains": "SMITH" } }), vec![1]);
+    assert_eq!(ids(doc! { "name": { "$fuzzy": "jonathan smyth" } }), vec![1, 3]);
+    assert_eq!(ids(doc! { "name": { "$fuzzy": { "query": "jonatan smth", "maxEdits": 2 } } }), vec![1]);
+    assert_eq!(ids(doc! { "name": { "$fuzzy": { "query": "alsion gray", "maxEdits": 2 } } }), vec![4]);
+    assert_eq!(ids(doc! { "name": { "$fuzzy": "ak" } }), vec![4]);
+
+    products.update_one(doc! { "_id": 2 }, doc! { "$set": { "sku": "SKU-99999" } }).unwrap();
+    assert_eq!(ids(doc! { "sku": { "$contains": "10042" } }), vec![1]);
+    products.delete_one(doc! { "_id": 1 }).unwrap();
+    assert_eq!(ids(doc! { "sku": { "$contains": "10042" } }), Vec::<i32>::new());
+    assert_eq!(ids(doc! { "sku": { "$contains": "999" } }), vec![2]);
+
+    let plans: Vec<String> = db.profile_entries().unwrap()
+        .into_iter()
+        .filter(|entry| entry.op == "find")
+        .map(|entry| entry.plan)
+        .collect();
+    assert!(plans.iter().any(|plan| plan == "TRIGRAM sku_trigram"), "{:?}", plans);
+    assert!(plans.iter().any(|plan| plan == "TRIGRAM name_trigram"), "{:?}", plans);
+
+    let result = products.find(doc! { "name": { "$fuzzy": { "query": "john", "maxEdits": 3 } } }).run();
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+    let result = products.create_index(IndexModel {
+        keys: doc! { "code": "trigram" },
+        options: Some(IndexOptions {
+            unique: Some(true),
+            ..Default::default()
+        }),
+    });
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+}
+
+#[test]
+fn test_vector_index() {
+    use polodb_core::{ConfigBuilder, Database, Error};
+
+    let mut config = ConfigBuilder::new();
+    config.set_slow_query_threshold(std::time::Duration::ZERO);
+    let db = Database::open_memory_with_config(config.take()).unwrap();
+
+    let notes = db.collection::<Document>("notes");
+    notes.insert_many(vec![
+        doc! { "_id": 1, "topic": "rust", "embedding": [1.0, 0.0, 0.0] },
+        doc! { "_id": 2, "topic": "rust", "embedding": [0.9, 0.1, 0.0] },
+        doc! { "_id": 3, "topic": "go", "embedding": [0.0, 1.0, 0.0] },
+        doc! { "_id": 4, "topic": "go", "embedding": [0.1, 0.9, 0.1] },
+        doc! { "_id": 5, "topic": "sql", "embedding": [0.0, 0.0, 1.0] },
+        doc! { "_id": 6, "topic": "none", "embedding": [1.0, 0.0] },
+        doc! { "_id": 7, "topic": "none" },
+    ]).unwrap();
+
+    let result = notes.create_index(IndexModel {
+        keys: doc! { "embedding": "vector" },
+        options: None,
+    });
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+    notes.create_index(IndexModel {
+        keys: doc! { "embedding": "vector" },
+        options: Some(IndexOptions {
+            dimensions: Some(3),
+            lists: Some(2),
+            ..Default::default()
+        }),
+    }).unwrap();
+
+    let search = |stage: Document| -> Vec<Document> {
+        notes.aggregate(vec![doc! { "$vectorSearch": stage }]).run().unwrap()
+            .collect::<Result<Vec<Document>>>().unwrap()
+    };
+    let ids = |docs: &[Document]| -> Vec<i32> {
+        docs.iter().map(|doc| doc.get_i32("_id").unwrap()).collect()
+    };
+
+    let found = search(doc! {
+        "path": "embedding",
+        "queryVector": [1.0, 0.05, 0.0],
+        "limit": 2,
+        "numCandidates": 10,
+        "scoreField": "score",
+    });
+    assert_eq!(ids(&found), vec![1, 2]);
+    assert!(found[0].get_f64("score").unwrap() >= found[1].get_f64("score").unwrap());
+    assert!(found[0].get_f64("score").unwrap() > 0.99);
+
+    let found = search(doc! {
+        "path": "embedding",
+        "queryVector": [1.0, 0.0, 0.0],
+        "limit": 3,
+        "numCandidates": 10,
+        "filter": { "topic": "go" },
+    });
+    assert_eq!(ids(&found), vec![4, 3]);
+    assert!(!found[0].contains_key("score"));
+
+    // the documents are moved to the lists of the retrained centroids
+    notes.insert_one(doc! { "_id": 8, "topic": "sql", "embedding": [0.0, 0.1, 0.9] }).unwrap();
+    notes.delete_one(doc! { "_id": 5 }).unwrap();
+    notes.update_one(doc! { "_id": 1 }, doc! { "$set": { "embedding": [0.0, 0.0, 1.0] } }).unwrap();
+    let query = doc! { "path": "embedding", "queryVector": [0.0, 0.0, 1.0], "limit": 2, "numCandidates": 10 };
+    assert_eq!(ids(&search(query.clone())), vec![1, 8]);
+    notes.analyze().unwrap();
+    assert_eq!(ids(&search(query)), vec![1, 8]);
+
+    let found = notes.aggregate(vec![
+        doc! { "$vectorSearch": { "path": "embedding", "queryVector": [0, 1, 0], "limit": 5 } },
+        doc! { "$count": "count" },
+    ]).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+    assert_eq!(found[0].get_i64("count").unwrap(), 5);
+
+    let plans: Vec<String> = db.profile_entries().unwrap()
+        .into_iter()
+        .filter(|entry| entry.op == "aggregate")
+        .map(|entry| entry.plan)
+        .collect();
+    assert!(!plans.is_empty());
+    assert!(plans.iter().all(|plan| plan == "VECTOR embedding_vector"), "{:?}", plans);
+
+    let points = db.collection::<Document>("points");
+    points.create_index(IndexModel {
+        keys: doc! { "location": "vector" },
+        options: Some(IndexOptions {
+            dimensions: Some(2),
+            similarity: Some("euclidean".to_string()),
+            ..Default::default()
+        }),
+    }).unwrap();
+    points.insert_many(vec![
+        doc! { "_id": 1, "location": [0, 0] },
+        doc! { "_id": 2, "location": [3, 4] },
+        doc! { "_id": 3, "location": [30, 40] },
+    ]).unwrap();
+    let found = points.aggregate(vec![
+        doc! { "$vectorSearch": { "path": "location", "queryVector": [2, 2], "limit": 2, "scoreField": "score" } },
+    ]).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+    assert_eq!(ids(&found), vec![2, 1]);
+    assert!((found[0].get_f64("score").unwrap() - 1.0 / (1.0 + 5f64.sqrt())).abs() < 1e-6);
+
+    let invalid = [
+        doc! { "path": "location", "queryVector": [1, 2, 3], "limit": 1 },
+        doc! { "path": "location", "queryVector": [1, 2] },
+        doc! { "path": "other", "queryVector": [1, 2], "limit": 1 },
+        doc! { "path": "location", "queryVector": [1, 2], "limit": 5, "numCandidates": 2 },
+    ];
+    for stage in invalid.iter() {
+        let result = points.aggregate(vec![doc! { "$vectorSearch": stage.clone() }]).run();
+        assert!(matches!(result, Err(Error::ValidationError(_))), "{:?}", stage);
+    }
+    let result = points.aggregate(vec![
+        doc! { "$match": {} },
+        doc! { "$vectorSearch": { "path": "location", "queryVector": [1, 2], "limit": 1 } },
+    ]).run();
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+}
+
+#[test]
+fn test_geo_near() {
+    use polodb_core::{ConfigBuilder, Database, Error};
+
+    let mut config = ConfigBuilder::new();
+    config.set_slow_query_threshold(std::time::Duration::ZERO);
+    let db = Database::open_memory_with_config(config.take()).unwrap();
+
+    let stores = db.collection::<Document>("stores");
+    stores.insert_many(vec![
+        doc! { "_id": 1, "city": "Paris", "open": true, "location": { "type": "Point", "coordinates": [2.3522, 48.8566] } },
+        doc! { "_id": 2, "city": "Versailles", "open": false, "location": { "type": "Point", "coordinates": [2.1301, 48.8049] } },
+        doc! { "_id": 3, "city": "London", "open": true, "location": [-0.1276, 51.5072] },
+        doc! { "_id": 4, "city": "Berlin", "open": true, "location": { "type": "Point", "coordinates": [13.405, 52.52] } },
+        doc! { "_id": 5, "city": "Nowhere", "location": "unknown" },
+    ]).unwrap();
+
+    let near_paris = doc! { "type": "Point", "coordinates": [2.35, 48.85] };
+    let result = stores.aggregate(vec![
+        doc! { "$geoNear": { "near": near_paris.clone(), "distanceField": "distance" } },
+    ]).run();
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+
+    stores.create_index(IndexModel {
+        keys: doc! { "location": "2dsphere" },
+        options: None,
+    }).unwrap();
+
+    let geo_near = |stage: Document, rest: Vec<Document>| -> Vec<Document> {
+        let mut pipeline = vec![doc! { "$geoNear": stage }];
+        pipeline.extend(rest);
+        stores.aggregate(pipeline).run().unwrap()
+            .collect::<Result<Vec<Document>>>().unwrap()
+    };
+    let ids = |docs: &[Document]| -> Vec<i32> {
+        docs.iter().map(|doc| doc.get_i32("_id").unwrap()).collect()
+    };
+
+    let found = geo_near(doc! { "near": near_paris.clone(), "distanceField": "distance" }, vec![]);
+    assert_eq!(ids(&found), vec![1, 2, 3, 4]);
+    let distances: Vec<f64> = found.iter().map(|doc| doc.get_f64("distance").unwrap()).collect();
+    assert!(distances[0] < 1_000.0);
+    assert!((distances[2] - 344_000.0).abs() < 5_000.0, "{:?}", distances);
+    assert!(distances.windows(2).all(|pair| pair[0] <= pair[1]));
+
+    // the nearest ones of a $limit
+    let found = geo_near(doc! { "near": [2.35, 48.85], "distanceField": "meters" }, vec![doc! { "$limit": 2 }]);
+    assert_eq!(ids(&found), vec![1, 2]);
+    assert!(found[1].get_f64("meters").unwrap() > 15_000.0);
+
+    let found = geo_near(doc! {
+        "near": near_paris.clone(),
+        "distanceField": "distance",
+        "maxDistance": 400_000,
+        "minDistance": 1_000.0,
+    }, vec![]);
+    assert_eq!(ids(&found), vec![2, 3]);
+
+    let found = geo_near(doc! {
+        "near": near_paris.clone(),
+        "distanceField": "distance",
+        "query": { "open": true },
+        "spherical": true,
+    }, vec![doc! { "$limit": 2 }]);
+    assert_eq!(ids(&found), vec![1, 3]);
+
+    stores.update_one(doc! { "_id": 4 }, doc! { "$set": { "location": [2.36, 48.86] } }).unwrap();
+    stores.delete_one(doc! { "_id": 1 }).unwrap();
+    let found = geo_near(doc! { "near": near_paris.clone(), "distanceField": "distance", "maxDistance": 5_000 }, vec![]);
+    assert_eq!(ids(&found), vec![4]);
+
+    let plans: Vec<String> = db.profile_entries().unwrap()
+        .into_iter()
+        .filter(|entry| entry.op == "aggregate")
+        .map(|entry| entry.plan)
+        .collect();
+    assert!(plans.iter().filter(|plan| *plan == "GEONEAR location_2dsphere").count() >= 5, "{:?}", plans);
+
+    let invalid = [
+        doc! { "near": [2.35, 48.85] },
+        doc! { "near": [200, 48.85], "distanceField": "distance" },
+        doc! { "near": [2.35, 48.85], "distanceField": "distance", "maxDistance": -1 },
+        doc! { "near": [2.35, 48.85], "distanceField": "distance", "key": "other" },
+        doc! { "near": [2.35, 48.85], "distanceField": "distance", "spherical": false },
+    ];
+    for stage in invalid.iter() {
+        let result = stores.aggregate(vec![doc! { "$geoNear": stage.clone() }]).run();
+        assert!(matches!(result, Err(Error::ValidationError(_))), "{:?}", stage);
+    }
+    let result = stores.aggregate(vec![
+        doc! { "$match": {} },
+        doc! { "$geoNear": { "near": [2.35, 48.85], "distanceField": "distance" } },
+    ]).run();
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+}
+
+#[test]
+fn test_find_by_index_stops_at_value() {
+    let db = prepare_db("test-find-by-index-stops-at-value").unwrap();
+    let col = db.collection::<Document>("teacher");
+    col.create_index(IndexModel {
+        keys: doc! {
+            "age": 1,
+        },
+        options: None,
+    }).unwrap();
+
+    col.insert_many(vec![
+        doc! { "name": "David", "age": 33 },
+        doc! { "name": "John", "age": 33 },
+        doc! { "name": "Mike", "age": 40 },
+    ]).unwrap();
+
+    let names: Vec<String> = col.find(doc! { "age": 33 })
+        .run()
+        .unwrap()
+        .map(|doc| doc.unwrap().get_str("name").unwrap().to_string())
+        .collect();
+    assert_eq!(names.len(), 2);
+    assert!(!names.contains(&"Mike".to_string()));
+}
@@ -23,7 +23,7 @@ use polodb_core::bson::{doc, Bson};
 
 mod common;
 
-use common::prepare_db;
+use common::{prepare_db, prepare_db_with_config};
 use polodb_core::test_utils::mk_db_path;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -287,3 +287,73 @@ fn test_insert_persist() {
         assert_eq!(result.len() as u64, i as u64 + 1);
     }
 }
+
+#[test]
+fn test_max_document_size() {
+    use polodb_core::{ConfigBuilder, Error};
+
+    let mut config = ConfigBuilder::new();
+    config.set_max_document_size(1024);
+    let db = prepare_db_with_config("test-max-document-size", config.take()).unwrap();
+    let collection = db.collection::<Document>("test");
+
+    collection.insert_one(doc! { "_id": 1, "content": "a".repeat(900) }).unwrap();
+    let result = collection.insert_one(doc! { "_id": 2, "content": "a".repeat(2000) });
+    assert!(matches!(result, Err(Error::DocumentTooLarge { limit: 1024, .. })));
+    let result = collection.insert_many(vec![
+        doc! { "_id": 3 },
+        doc! { "_id": 4, "content": "a".repeat(2000) },
+    ]);
+    assert!(matches!(result, Err(Error::DocumentTooLarge { .. })));
+    assert_eq!(collection.count_documents().unwrap(), 1);
+
+    // the updated documents are checked as well
+    let result = collection.update_one(doc! { "_id": 1 }, doc! {
+        "$set": { "content": "a".repeat(2000) },
+    });
+    assert!(matches!(result, Err(Error::DocumentTooLarge { .. })));
+    let doc = collection.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert_eq!(doc.get_str("content").unwrap().len(), 900);
+}
+
+#[test]
+fn test_try_insert_backpressure() {
+    use polodb_core::{ConfigBuilder, Error};
+
+    let mut config_builder = ConfigBuilder::new();
+    config_builder
+        .set_memtable_size(64 * 1024)
+        .set_max_immutable_memtables(1)
+        .set_level0_slowdown_writes_trigger(4)
+        .set_level0_stop_writes_trigger(4)
+        .set_soft_pending_compaction_bytes_limit(64 * 1024)
+        .set_hard_pending_compaction_bytes_limit(256 * 1024);
+    let db = prepare_db_with_config("test-try-insert-backpressure", config_builder.take()).unwrap();
+    let collection = db.collection::<Document>("test");
+
+    for i in 0..2000 {
+        let doc = doc! {
+            "_id": i,
+            "content": "x".repeat(1024),
+        };
+        loop {
+            match collection.try_insert_one(&doc) {
+                Ok(_) => break,
+                Err(Error::WouldBlock) => std::thread::sleep(std::time::Duration::from_millis(1)),
+                Err(err) => panic!("unexpected error: {}", err),
+            }
+        }
+    }
+    let docs = (2000..2010).map(|i| doc! { "_id": i }).collect::<Vec<Document>>();
+    loop {
+        match collection.try_insert_many(&docs) {
+            Ok(result) => {
+                assert_eq!(result.inserted_ids.len(), 10);
+                break;
+            }
+            Err(Error::WouldBlock) => std::thread::sleep(std::time::Duration::from_millis(1)),
+            Err(err) => panic!("unexpected error: {}", err),
+        }
+    }
+    assert_eq!(collection.count_documents().unwrap(), 2010);
+}
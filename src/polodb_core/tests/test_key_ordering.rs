@@ -0,0 +1,182 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use std::sync::Arc;
+use polodb_core::{CollectionT, ConfigBuilder, Database, Error, IndexModel, KeyOrdering, Result};
+use polodb_core::bson::{doc, Bson, Document};
+use polodb_core::options::CreateCollectionOptions;
+
+mod common;
+
+use common::{mk_db_path, prepare_db};
+
+fn ids(docs: &[Document]) -> Vec<String> {
+    docs.iter().map(|doc| doc.get_str("_id").unwrap().to_string()).collect()
+}
+
+fn create_ordered(db: &Database, name: &str, ordering: &str, keys: &[&str]) {
+    db.create_collection_with_options(
+        name,
+        CreateCollectionOptions::builder().key_ordering(ordering).build(),
+    ).unwrap();
+    let col = db.collection::<Document>(name);
+    col.insert_many(keys.iter().enumerate().map(|(i, key)| doc! {
+        "_id": *key,
+        "rank": i as i32,
+    })).unwrap();
+}
+
+#[test]
+fn test_case_insensitive_ordering() {
+    let db = prepare_db("test-key-ordering-case").unwrap();
+    create_ordered(&db, "words", "caseInsensitive", &["banana", "Apple", "cherry", "BANANA", "apricot"]);
+    let col = db.collection::<Document>("words");
+
+    // the documents are scanned in the order of the sort keys
+    let docs: Vec<Document> = col.find(doc! {}).run().unwrap().collect::<Result<_>>().unwrap();
+    assert_eq!(ids(&docs), vec!["Apple", "apricot", "BANANA", "banana", "cherry"]);
+
+    // the range is compared as the ordering, so both of the bananas are included
+    let docs: Vec<Document> = col.find_range(Bson::String("B".to_string())..=Bson::String("Banana".to_string()))
+        .unwrap()
+        .collect::<Result<_>>()
+        .unwrap();
+    assert_eq!(ids(&docs), vec!["BANANA", "banana"]);
+    let docs: Vec<Document> = col.find_range(Bson::String("Apricot".to_string())..Bson::String("CHERRY".to_string()))
+        .unwrap()
+        .collect::<Result<_>>()
+        .unwrap();
+    assert_eq!(ids(&docs), vec!["apricot", "BANANA", "banana"]);
+
+    // the _id itself is still unique and looked up exactly
+    let doc = col.find_one(doc! { "_id": "BANANA" }).unwrap().unwrap();
+    assert_eq!(doc.get_i32("rank").unwrap(), 3);
+    assert!(col.find_one(doc! { "_id": "Banana" }).unwrap().is_none());
+    assert!(!col.exists(doc! { "_id": "apple" }).unwrap());
+    assert!(col.exists(doc! { "_id": "Apple" }).unwrap());
+    let found = col.find_many_by_ids(&[Bson::String("cherry".to_string()), Bson::String("kiwi".to_string())]).unwrap();
+    assert_eq!(found[0].as_ref().unwrap().get_i32("rank").unwrap(), 2);
+    assert!(found[1].is_none());
+
+    col.update_one(doc! { "_id": "banana" }, doc! { "$set": { "rank": 10 } }).unwrap();
+    assert_eq!(col.find_one(doc! { "_id": "banana" }).unwrap().unwrap().get_i32("rank").unwrap(), 10);
+    col.delete_one(doc! { "_id": "Apple" }).unwrap();
+    assert_eq!(col.count_documents().unwrap(), 4);
+    assert!(col.find_one(doc! { "_id": "Apple" }).unwrap().is_none());
+}
+
+#[test]
+fn test_key_ordering_with_index() {
+    let db = prepare_db("test-key-ordering-index").unwrap();
+    create_ordered(&db, "words", "caseInsensitive", &["b", "A", "c"]);
+    let col = db.collection::<Document>("words");
+
+    // the index is built on the existing documents and kept on the writes
+    col.create_index(IndexModel {
+        keys: doc! { "rank": 1 },
+        options: None,
+    }).unwrap();
+    col.insert_one(doc! { "_id": "D", "rank": 3 }).unwrap();
+    col.update_one(doc! { "_id": "b" }, doc! { "$set": { "rank": 5 } }).unwrap();
+
+    let doc = col.find_one(doc! { "rank": 3 }).unwrap().unwrap();
+    assert_eq!(doc.get_str("_id").unwrap(), "D");
+    let doc = col.find_one(doc! { "rank": 5 }).unwrap().unwrap();
+    assert_eq!(doc.get_str("_id").unwrap(), "b");
+    assert!(col.find_one(doc! { "rank": 0 }).unwrap().is_none());
+
+    col.delete_one(doc! { "rank": 5 }).unwrap();
+    assert!(col.find_one(doc! { "_id": "b" }).unwrap().is_none());
+    assert!(col.find_one(doc! { "rank": 5 }).unwrap().is_none());
+}
+
+#[test]
+fn test_semver_ordering() {
+    let db = prepare_db("test-key-ordering-semver").unwrap();
+    create_ordered(&db, "releases", "semver", &["1.10.0", "1.2.0", "1.0.0", "1.0.0-rc.1", "0.9.3"]);
+    let col = db.collection::<Document>("releases");
+
+    let docs: Vec<Document> = col.find(doc! {}).run().unwrap().collect::<Result<_>>().unwrap();
+    assert_eq!(ids(&docs), vec!["0.9.3", "1.0.0-rc.1", "1.0.0", "1.2.0", "1.10.0"]);
+
+    let docs: Vec<Document> = col.find_range(Bson::String("1.0.0".to_string())..)
+        .unwrap()
+        .collect::<Result<_>>()
+        .unwrap();
+    assert_eq!(ids(&docs), vec!["1.0.0", "1.2.0", "1.10.0"]);
+
+    // the keys of the ordering are validated
+    let err = col.insert_one(doc! { "_id": "latest" }).unwrap_err();
+    assert!(matches!(err, Error::ValidationError(_)));
+}
+
+/// Orders the strings by their length, then by their values.
+struct LengthOrdering;
+
+impl KeyOrdering for LengthOrdering {
+    fn sort_key(&self, key: &Bson) -> Result<Vec<u8>> {
+        let key = key.as_str().unwrap_or_default();
+        let mut result = (key.len() as u64).to_be_bytes().to_vec();
+        result.extend_from_slice(key.as_bytes());
+        Ok(result)
+    }
+}
+
+#[test]
+fn test_custom_key_ordering() {
+    let db_path = mk_db_path("test-key-ordering-custom");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    let open = || {
+        let mut config_builder = ConfigBuilder::new();
+        config_builder.set_key_ordering("length", Arc::new(LengthOrdering));
+        Database::open_path_with_config(db_path.as_path(), config_builder.take()).unwrap()
+    };
+
+    {
+        let db = open();
+        create_ordered(&db, "names", "length", &["Alexander", "Bo", "Eve", "Charlotte", "Al"]);
+    }
+
+    let db = open();
+    let col = db.collection::<Document>("names");
+    let docs: Vec<Document> = col.find(doc! {}).run().unwrap().collect::<Result<_>>().unwrap();
+    assert_eq!(ids(&docs), vec!["Al", "Bo", "Eve", "Alexander", "Charlotte"]);
+    assert!(col.find_one(doc! { "_id": "Eve" }).unwrap().is_some());
+    drop(db);
+
+    // the ordering must be registered to read the collection
+    let db = Database::open_path(db_path.as_path()).unwrap();
+    let err = db.collection::<Document>("names").find_one(doc! { "_id": "Eve" }).unwrap_err();
+    assert!(matches!(err, Error::KeyOrderingNotFound(name) if name == "length"));
+}
+
+#[test]
+fn test_invalid_key_ordering() {
+    let db = prepare_db("test-key-ordering-invalid").unwrap();
+
+    let err = db.create_collection_with_options(
+        "unknown",
+        CreateCollectionOptions::builder().key_ordering("reverse").build(),
+    ).unwrap_err();
+    assert!(matches!(err, Error::KeyOrderingNotFound(_)));
+
+    let err = db.create_collection_with_options(
+        "capped",
+        CreateCollectionOptions::builder().key_ordering("semver").capped(true).size(1024).build(),
+    ).unwrap_err();
+    assert!(matches!(err, Error::ValidationError(_)));
+    assert!(db.list_collection_names().unwrap().is_empty());
+}
@@ -0,0 +1,112 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use polodb_core::{CollectionT, Database, Result};
+
+mod common;
+
+use common::prepare_db;
+
+fn collect_keys(scan: polodb_core::kv::KvScan) -> Vec<Vec<u8>> {
+    scan.map(|pair| pair.unwrap().0).collect()
+}
+
+#[test]
+fn test_kv_get_put_delete() {
+    let db = prepare_db("test-kv-get-put-delete").unwrap();
+    let kv = db.kv();
+    assert_eq!(kv.get(b"a").unwrap(), None);
+
+    kv.put(b"a", b"1").unwrap();
+    kv.put(b"a", b"2").unwrap();
+    kv.put(b"", b"empty").unwrap();
+    assert_eq!(kv.get(b"a").unwrap(), Some(b"2".to_vec()));
+    assert_eq!(kv.get(b"").unwrap(), Some(b"empty".to_vec()));
+
+    kv.delete(b"a").unwrap();
+    kv.delete(b"missing").unwrap();
+    assert_eq!(kv.get(b"a").unwrap(), None);
+
+    // the pairs are not visible to the collections
+    assert!(db.list_collection_names().unwrap().is_empty());
+}
+
+#[test]
+fn test_kv_scan() {
+    let db = prepare_db("test-kv-scan").unwrap();
+    let kv = db.kv();
+    for key in [&b"b"[..], b"a", b"c", b"ab", b"\xff"] {
+        kv.put(key, key).unwrap();
+    }
+
+    assert_eq!(collect_keys(kv.scan(..).unwrap()), vec![
+        b"a".to_vec(), b"ab".to_vec(), b"b".to_vec(), b"c".to_vec(), b"\xff".to_vec(),
+    ]);
+    assert_eq!(collect_keys(kv.scan(&b"ab"[..]..&b"c"[..]).unwrap()), vec![
+        b"ab".to_vec(), b"b".to_vec(),
+    ]);
+    assert_eq!(collect_keys(kv.scan(&b"ab"[..]..=&b"c"[..]).unwrap()), vec![
+        b"ab".to_vec(), b"b".to_vec(), b"c".to_vec(),
+    ]);
+    let range = (std::ops::Bound::Excluded(&b"a"[..]), std::ops::Bound::Unbounded);
+    assert_eq!(collect_keys(kv.scan(range).unwrap()).len(), 4);
+
+    let (key, value) = kv.scan(&b"c"[..]..).unwrap().next().unwrap().unwrap();
+    assert_eq!(key, b"c".to_vec());
+    assert_eq!(value, b"c".to_vec());
+}
+
+#[test]
+fn test_kv_transaction() -> Result<()> {
+    let db = prepare_db("test-kv-transaction").unwrap();
+    db.kv().put(b"balance", b"10")?;
+
+    let txn = db.start_transaction()?;
+    txn.kv().put(b"balance", b"20")?;
+    txn.kv().put(b"log", b"+10")?;
+    txn.collection::<bson::Document>("audit").insert_one(bson::doc! { "change": 10 })?;
+    assert_eq!(txn.kv().get(b"balance")?, Some(b"20".to_vec()));
+    assert_eq!(txn.kv().scan(..)?.count(), 2);
+    assert_eq!(db.kv().get(b"balance")?, Some(b"10".to_vec()));
+    txn.rollback()?;
+
+    assert_eq!(db.kv().get(b"balance")?, Some(b"10".to_vec()));
+    assert_eq!(db.kv().get(b"log")?, None);
+
+    let txn = db.start_transaction()?;
+    txn.kv().delete(b"balance")?;
+    txn.kv().put(b"log", b"-10")?;
+    txn.commit()?;
+    assert_eq!(db.kv().get(b"balance")?, None);
+    assert_eq!(db.kv().get(b"log")?, Some(b"-10".to_vec()));
+    Ok(())
+}
+
+#[test]
+fn test_kv_namespaces() -> Result<()> {
+    let db = Database::open_memory()?;
+    db.kv().put(b"k", b"default")?;
+    let shop = db.database("shop");
+    shop.kv().put(b"k", b"shop")?;
+
+    assert_eq!(db.kv().get(b"k")?, Some(b"default".to_vec()));
+    assert_eq!(shop.kv().get(b"k")?, Some(b"shop".to_vec()));
+    assert_eq!(shop.kv().scan(..)?.count(), 1);
+
+    db.drop_database("shop")?;
+    assert_eq!(db.database("shop").kv().get(b"k")?, None);
+    assert_eq!(db.kv().get(b"k")?, Some(b"default".to_vec()));
+    Ok(())
+}
@@ -0,0 +1,123 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use bson::{doc, Bson, Document};
+use polodb_core::{CollectionT, Result};
+use polodb_core::options::MapReduceOptions;
+use polodb_core::test_utils::prepare_db;
+
+fn sum_reduce(_key: &Bson, values: Vec<Bson>) -> Bson {
+    Bson::Int64(values.iter().map(|v| v.as_i64().unwrap()).sum())
+}
+
+#[test]
+fn test_map_reduce_word_count() {
+    let db = prepare_db("test-map-reduce-word-count").unwrap();
+    let col = db.collection::<Document>("lines");
+    col.insert_many(vec![
+        doc! { "text": "the quick brown fox", "skip": false },
+        doc! { "text": "the lazy dog", "skip": false },
+        doc! { "text": "the fox", "skip": true },
+    ]).unwrap();
+
+    let mut reduced_keys = Vec::new();
+    let result = col.map_reduce(
+        |doc: Document, emitter| {
+            for word in doc.get_str("text").unwrap().split(' ') {
+                emitter.emit(word, 1i64);
+            }
+        },
+        |key, values| {
+            reduced_keys.push(key.clone());
+            sum_reduce(key, values)
+        },
+        MapReduceOptions::builder()
+            .filter(doc! { "skip": false })
+            .build(),
+    ).unwrap();
+    assert_eq!(result.spilled_runs(), 0);
+    let result = result.collect::<Result<Vec<Document>>>().unwrap();
+
+    assert_eq!(result, vec![
+        doc! { "_id": "brown", "value": 1i64 },
+        doc! { "_id": "dog", "value": 1i64 },
+        doc! { "_id": "fox", "value": 1i64 },
+        doc! { "_id": "lazy", "value": 1i64 },
+        doc! { "_id": "quick", "value": 1i64 },
+        doc! { "_id": "the", "value": 2i64 },
+    ]);
+    // the keys with a single value are not reduced
+    assert_eq!(reduced_keys, vec![Bson::String("the".into())]);
+}
+
+#[test]
+fn test_map_reduce_spill() {
+    let db = prepare_db("test-map-reduce-spill").unwrap();
+    let col = db.collection::<Document>("events");
+    let docs = (0..2000).map(|i| doc! { "_id": i, "user": i % 50, "bytes": i as i64 });
+    col.insert_many(docs).unwrap();
+
+    let temp_dir = std::env::temp_dir().join("test-map-reduce-spill-runs");
+    let _ = std::fs::remove_dir_all(&temp_dir);
+    std::fs::create_dir_all(&temp_dir).unwrap();
+
+    let cursor = col.map_reduce(
+        |doc: Document, emitter| {
+            emitter.emit(doc.get_i32("user").unwrap(), doc.get_i64("bytes").unwrap());
+        },
+        sum_reduce,
+        MapReduceOptions::builder()
+            .memory_limit(1024)
+            .temp_dir(&temp_dir)
+            .build(),
+    ).unwrap();
+    assert!(cursor.spilled_runs() > 1);
+    assert!(std::fs::read_dir(&temp_dir).unwrap().count() > 1);
+
+    let result = cursor.collect::<Result<Vec<Document>>>().unwrap();
+    assert_eq!(result.len(), 50);
+    for (user, doc) in result.iter().enumerate() {
+        let expected: i64 = (0..2000i64).filter(|i| i % 50 == user as i64).sum();
+        assert_eq!(doc, &doc! { "_id": user as i32, "value": expected });
+    }
+
+    // the temporary files are removed with the cursor
+    assert_eq!(std::fs::read_dir(&temp_dir).unwrap().count(), 0);
+}
+
+#[test]
+fn test_map_reduce_in_transaction() {
+    let db = prepare_db("test-map-reduce-in-transaction").unwrap();
+    let txn = db.start_transaction().unwrap();
+    let col = txn.collection::<Document>("items");
+    col.insert_many(vec![
+        doc! { "kind": "a", "n": 1i64 },
+        doc! { "kind": "b", "n": 2i64 },
+        doc! { "kind": "a", "n": 3i64 },
+    ]).unwrap();
+
+    let result = col.map_reduce(
+        |doc: Document, emitter| {
+            emitter.emit(doc.get_str("kind").unwrap(), doc.get_i64("n").unwrap());
+        },
+        sum_reduce,
+        MapReduceOptions::default(),
+    ).unwrap().collect::<Result<Vec<Document>>>().unwrap();
+
+    assert_eq!(result, vec![
+        doc! { "_id": "a", "value": 4i64 },
+        doc! { "_id": "b", "value": 2i64 },
+    ]);
+}
@@ -0,0 +1,121 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+#![cfg(feature = "metrics")]
+
+use std::sync::Arc;
+use polodb_core::{CancellationToken, ConfigBuilder, Database, CollectionT, PrometheusExporter};
+use polodb_core::bson::{doc, Document};
+
+mod common;
+
+use common::mk_db_path;
+
+#[test]
+fn test_operation_metrics() {
+    let exporter = PrometheusExporter::new();
+    let mut config = ConfigBuilder::new();
+    config.set_metrics_recorder(Arc::new(exporter.clone()));
+    let db = Database::open_memory_with_config(config.take()).unwrap();
+
+    let collection = db.collection::<Document>("test");
+    collection.insert_many(vec![
+        doc! { "_id": 1 },
+        doc! { "_id": 2 },
+    ]).unwrap();
+    collection.insert_one(doc! { "_id": 3 }).unwrap();
+    db.collection::<Document>("$illegal").insert_one(doc! { "_id": 4 }).unwrap_err();
+    let found: Vec<Document> = collection.find(doc! {}).run().unwrap().map(|doc| doc.unwrap()).collect();
+    assert_eq!(found.len(), 3);
+    collection.delete_one(doc! { "_id": 1 }).unwrap();
+
+    let text = exporter.render();
+    assert!(text.contains("# TYPE polodb_operations_total counter\n"));
+    assert!(text.contains("polodb_operations_total{operation=\"insert_many\",status=\"ok\"} 1\n"));
+    assert!(text.contains("polodb_operations_total{operation=\"insert_one\",status=\"ok\"} 1\n"));
+    assert!(text.contains("polodb_operations_total{operation=\"insert_one\",status=\"error\"} 1\n"));
+    assert!(text.contains("polodb_operations_total{operation=\"find\",status=\"ok\"} 1\n"));
+    assert!(text.contains("polodb_operations_total{operation=\"delete_one\",status=\"ok\"} 1\n"));
+    assert!(text.contains("# TYPE polodb_operation_duration_seconds histogram\n"));
+    assert!(text.contains("polodb_operation_duration_seconds_count{operation=\"insert_one\"} 2\n"));
+}
+
+#[test]
+fn test_cursor_metrics() {
+    let exporter = PrometheusExporter::new();
+    let mut config = ConfigBuilder::new();
+    config.set_metrics_recorder(Arc::new(exporter.clone()));
+    let db = Database::open_memory_with_config(config.take()).unwrap();
+    let collection = db.collection::<Document>("test");
+    collection.insert_many((0..10).map(|i| doc! { "_id": i })).unwrap();
+
+    // the latency is reported when the cursor returns the last document
+    let mut cursor = collection.find(doc! {}).run().unwrap();
+    assert!(cursor.advance().unwrap());
+    assert!(!exporter.render().contains("operation=\"find\""));
+    assert_eq!(cursor.count(), 9);
+    let text = exporter.render();
+    assert!(text.contains("polodb_operations_total{operation=\"find\",status=\"ok\"} 1\n"));
+    assert!(text.contains("polodb_operation_duration_seconds_count{operation=\"find\"} 1\n"));
+
+    // or when it fails
+    let token = CancellationToken::new();
+    let mut cursor = collection.aggregate(vec![doc! { "$match": {} }])
+        .cancellation_token(token.clone())
+        .run()
+        .unwrap();
+    token.cancel();
+    cursor.advance().unwrap_err();
+    assert!(exporter.render().contains("polodb_operations_total{operation=\"aggregate\",status=\"error\"} 1\n"));
+
+    // or when it's dropped
+    let mut cursor = collection.find(doc! {}).run().unwrap();
+    assert!(cursor.advance().unwrap());
+    drop(cursor);
+    let text = exporter.render();
+    assert!(text.contains("polodb_operations_total{operation=\"find\",status=\"ok\"} 2\n"));
+    assert!(text.contains("polodb_operation_duration_seconds_count{operation=\"find\"} 2\n"));
+}
+
+#[test]
+fn test_report_storage_metrics() {
+    let db_path = mk_db_path("test-report-storage-metrics");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    let exporter = PrometheusExporter::new();
+    let mut config = ConfigBuilder::new();
+    config.set_metrics_recorder(Arc::new(exporter.clone()));
+    let db = Database::open_path_with_config(db_path.as_path(), config.take()).unwrap();
+
+    let collection = db.collection::<Document>("test");
+    for i in 0..10 {
+        collection.insert_one(doc! { "_id": i }).unwrap();
+    }
+    db.report_metrics().unwrap();
+
+    let text = exporter.render();
+    assert!(text.contains("# TYPE polodb_block_cache_hits_total counter\n"));
+    assert!(text.contains("# TYPE polodb_wal_syncs_total counter\n"));
+    assert!(!text.contains("polodb_wal_written_bytes_total 0\n"));
+    assert!(text.contains("# TYPE polodb_running_compactions gauge\n"));
+    assert!(text.contains("polodb_level_files{level=\"0\"}"));
+}
+
+#[test]
+fn test_report_without_recorder() {
+    let db = Database::open_memory().unwrap();
+    db.collection::<Document>("test").insert_one(doc! { "_id": 1 }).unwrap();
+    db.report_metrics().unwrap();
+}
@@ -0,0 +1,139 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use polodb_core::{CollectionT, Database, Error, IndexModel};
+use polodb_core::bson::{doc, Document};
+use polodb_core::migration::Migrations;
+use serde::{Deserialize, Serialize};
+
+mod common;
+
+use common::prepare_db;
+
+fn migrations() -> Migrations {
+    Migrations::new()
+        .step(1, "seed the settings", |txn| {
+            txn.collection::<Document>("settings").insert_one(doc! { "_id": "theme", "value": "dark" })?;
+            Ok(())
+        })
+        .step(2, "index the emails", |txn| {
+            txn.collection::<Document>("users").create_index(IndexModel {
+                keys: doc! { "email": 1 },
+                options: None,
+            })
+        })
+}
+
+#[test]
+fn test_migrate() {
+    let db = prepare_db("test-migrate").unwrap();
+    assert_eq!(db.schema_version().unwrap(), 0);
+
+    let result = db.migrate(&migrations()).unwrap();
+    assert_eq!(result.previous_version, 0);
+    assert_eq!(result.version, 2);
+    assert_eq!(result.applied, vec!["seed the settings", "index the emails"]);
+    assert_eq!(db.schema_version().unwrap(), 2);
+
+    // the applied migrations are skipped
+    let result = db.migrate(&migrations()).unwrap();
+    assert!(result.applied.is_empty());
+    assert_eq!(db.collection::<Document>("settings").count_documents().unwrap(), 1);
+
+    let migrations = migrations().step(3, "rename the theme", |txn| {
+        txn.collection::<Document>("settings").update_one(
+            doc! { "_id": "theme" },
+            doc! { "$set": { "value": "light" } },
+        )?;
+        Ok(())
+    });
+    let result = db.migrate(&migrations).unwrap();
+    assert_eq!(result.previous_version, 2);
+    assert_eq!(result.applied, vec!["rename the theme"]);
+    let theme = db.collection::<Document>("settings").find_one(doc! { "_id": "theme" }).unwrap().unwrap();
+    assert_eq!(theme.get_str("value").unwrap(), "light");
+
+    // the database is newer than the application
+    assert!(matches!(
+        db.migrate(&Migrations::new()),
+        Err(Error::SchemaVersionMismatch { stored: 3, latest: 0 }),
+    ));
+}
+
+#[test]
+fn test_migrate_rollback() {
+    let db = Database::open_memory().unwrap();
+    db.migrate(&migrations()).unwrap();
+
+    let failing = migrations()
+        .step(3, "insert a setting", |txn| {
+            txn.collection::<Document>("settings").insert_one(doc! { "_id": "lang", "value": "en" })?;
+            Ok(())
+        })
+        .step(4, "fail", |_| Err(Error::ValidationError("failed".to_string())));
+    assert!(matches!(db.migrate(&failing), Err(Error::ValidationError(_))));
+
+    assert_eq!(db.schema_version().unwrap(), 2);
+    assert!(db.collection::<Document>("settings").find_one(doc! { "_id": "lang" }).unwrap().is_none());
+
+    let unordered = Migrations::new()
+        .step(2, "b", |_| Ok(()))
+        .step(1, "a", |_| Ok(()));
+    assert!(matches!(db.migrate(&unordered), Err(Error::ValidationError(_))));
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct User {
+    first: String,
+    last: String,
+}
+
+#[test]
+fn test_migrate_lazy_transform() {
+    let db = Database::open_memory().unwrap();
+    let users = db.collection::<Document>("users");
+    users.insert_one(doc! { "_id": 1, "name": "Vincent Chan" }).unwrap();
+
+    let migrations = Migrations::new()
+        .transform(1, "split the names", "users", |doc| {
+            let name = doc.get_str("name").unwrap_or_default().to_string();
+            let (first, last) = name.split_once(' ').unwrap_or((name.as_str(), ""));
+            doc.insert("first", first);
+            doc.insert("last", last);
+            doc.remove("name");
+            Ok(())
+        });
+    db.migrate(&migrations).unwrap();
+
+    // the documents are transformed when they are read
+    let user = db.collection::<User>("users").find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert_eq!(user.first, "Vincent");
+    assert_eq!(user.last, "Chan");
+
+    // the inserted documents are in the latest version
+    users.insert_one(doc! { "_id": 2, "first": "Ada", "last": "Lovelace" }).unwrap();
+    let docs: Vec<Document> = users.find(doc! {}).run().unwrap().map(|doc| doc.unwrap()).collect();
+    assert_eq!(docs[0], doc! { "_id": 1, "first": "Vincent", "last": "Chan", "_schemaVersion": 1_i64 });
+    assert_eq!(docs[1], doc! { "_id": 2, "first": "Ada", "last": "Lovelace", "_schemaVersion": 1_i64 });
+
+    // the filters see the stored documents
+    assert!(users.find_one(doc! { "first": "Vincent" }).unwrap().is_none());
+
+    // the transforms are installed per database
+    assert!(db.database("tenant").collection::<Document>("users").find_one(doc! {}).unwrap().is_none());
+    db.database("tenant").migrate(&Migrations::new()).unwrap();
+    let user = db.collection::<User>("users").find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert_eq!(user.first, "Vincent");
+}
@@ -0,0 +1,47 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(feature = "opfs")]
+
+use std::path::Path;
+use std::sync::Arc;
+use polodb_core::{ConfigBuilder, Database, CollectionT};
+use polodb_core::bson::{doc, Document};
+use polodb_core::opfs::OpfsBackend;
+use polodb_core::storage_backend::StorageBackend;
+
+// the files are only read from and written to OPFS in the browsers,
+// the files in memory are the same on every target
+#[test]
+fn test_opfs_backend_in_memory() {
+    let db_path = Path::new("/test-opfs-backend.db");
+    let backend = Arc::new(OpfsBackend::new());
+    assert!(!backend.is_dirty());
+    {
+        let mut config = ConfigBuilder::new();
+        config.set_storage_backend(backend.clone());
+        let db = Database::open_path_with_config(db_path, config.take()).unwrap();
+        let collection = db.collection::<Document>("test");
+        collection.insert_many((0..100i64).map(|i| doc! { "_id": i })).unwrap();
+    }
+
+    assert!(backend.is_dirty());
+    assert!(backend.exists(&db_path.join("CURRENT")).unwrap());
+    assert!(!db_path.exists());
+
+    let mut config = ConfigBuilder::new();
+    config.set_storage_backend(backend.clone());
+    let db = Database::open_path_with_config(db_path, config.take()).unwrap();
+    assert_eq!(db.collection::<Document>("test").count_documents().unwrap(), 100);
+}
@@ -0,0 +1,151 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use polodb_core::{CollectionT, ConfigBuilder, Database, Error, IndexModel, WalOperation};
+use polodb_core::bson::{doc, Bson, Document};
+use polodb_core::options::CreateCollectionOptions;
+
+mod common;
+
+use common::prepare_db;
+
+fn create_telemetry(db: &Database) {
+    db.create_collection_with_options(
+        "telemetry",
+        CreateCollectionOptions::builder().partition_key("day").build(),
+    ).unwrap();
+    let col = db.collection::<Document>("telemetry");
+    for day in ["2024-01-01", "2024-01-02", "2024-01-03"] {
+        let docs: Vec<Document> = (0..5).map(|i| doc! {
+            "_id": i,
+            "day": day,
+            "value": i * 10,
+        }).collect();
+        col.insert_many(docs).unwrap();
+    }
+}
+
+#[test]
+fn test_partition_find_and_drop() {
+    let db = prepare_db("test-partition-find").unwrap();
+    create_telemetry(&db);
+    let col = db.collection::<Document>("telemetry");
+
+    assert_eq!(col.count_documents().unwrap(), 15);
+    assert_eq!(col.find(doc! { "day": "2024-01-02" }).run().unwrap().count(), 5);
+    assert_eq!(col.find(doc! { "value": 20 }).run().unwrap().count(), 3);
+
+    let found = col.find_one(doc! { "_id": 3, "day": "2024-01-03" }).unwrap().unwrap();
+    assert_eq!(found, doc! { "_id": 3, "day": "2024-01-03", "value": 30 });
+    // without the partition, the primary key is found in every partition
+    assert_eq!(col.find(doc! { "_id": 3 }).run().unwrap().count(), 3);
+
+    assert_eq!(col.list_partitions().unwrap(), vec![
+        Bson::String("2024-01-01".to_string()),
+        Bson::String("2024-01-02".to_string()),
+        Bson::String("2024-01-03".to_string()),
+    ]);
+
+    // the size of a partition is estimated from the table files
+    db.vacuum().unwrap();
+    let since = db.latest_sequence_number().unwrap() + 1;
+    let result = col.drop_partition("2024-01-02").unwrap();
+    assert_eq!(result.deleted_count, 0);
+    assert!(result.reclaimable_bytes > 0);
+    // the documents are removed by one range deletion
+    let mut operations = Vec::new();
+    db.ship_wal(since, |record| {
+        operations.extend(record.operations.iter().cloned());
+        true
+    }).unwrap();
+    assert!(matches!(operations.as_slice(), [WalOperation::DeleteRange { .. }]));
+    assert_eq!(col.count_documents().unwrap(), 10);
+    assert_eq!(col.find(doc! { "day": "2024-01-02" }).run().unwrap().count(), 0);
+    assert_eq!(col.list_partitions().unwrap(), vec![
+        Bson::String("2024-01-01".to_string()),
+        Bson::String("2024-01-03".to_string()),
+    ]);
+
+    // the documents without the partition key are in the null partition
+    col.insert_one(doc! { "_id": 100 }).unwrap();
+    assert_eq!(col.find(doc! { "_id": 100 }).run().unwrap().count(), 1);
+    assert!(col.list_partitions().unwrap().contains(&Bson::Null));
+    assert!(col.insert_one(doc! { "_id": 101, "day": ["2024-01-01"] }).is_err());
+}
+
+#[test]
+fn test_partition_pruned_scan() {
+    let mut config = ConfigBuilder::new();
+    config.set_slow_query_threshold(std::time::Duration::ZERO);
+    let db = Database::open_memory_with_config(config.take()).unwrap();
+    create_telemetry(&db);
+    let col = db.collection::<Document>("telemetry");
+
+    let found = col.find(doc! { "day": "2024-01-01", "value": { "$gte": 20 } }).run().unwrap().count();
+    assert_eq!(found, 3);
+    col.find_one(doc! { "_id": 1, "day": "2024-01-01" }).unwrap().unwrap();
+
+    let entries = db.profile_entries().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].plan, "PARTSCAN \"2024-01-01\"");
+    assert_eq!(entries[0].docs_examined, 5);
+    assert_eq!(entries[1].plan, "IDHACK");
+    assert_eq!(entries[1].docs_examined, 1);
+}
+
+#[test]
+fn test_partition_write() {
+    let db = prepare_db("test-partition-write").unwrap();
+    create_telemetry(&db);
+    let col = db.collection::<Document>("telemetry");
+
+    let result = col.update_many(doc! { "day": "2024-01-01" }, doc! { "$inc": { "value": 1 } }).unwrap();
+    assert_eq!(result.modified_count, 5);
+    let found = col.find_one(doc! { "_id": 2, "day": "2024-01-01" }).unwrap().unwrap();
+    assert_eq!(found.get_i32("value").unwrap(), 21);
+    let found = col.find_one(doc! { "_id": 2, "day": "2024-01-02" }).unwrap().unwrap();
+    assert_eq!(found.get_i32("value").unwrap(), 20);
+
+    let result = col.update_one(doc! { "_id": 2 }, doc! { "$set": { "day": "2024-01-04" } });
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+
+    let result = col.delete_many(doc! { "day": "2024-01-03", "value": { "$lt": 20 } }).unwrap();
+    assert_eq!(result.deleted_count, 2);
+    assert_eq!(col.count_documents().unwrap(), 13);
+
+    let result = col.create_index(IndexModel {
+        keys: doc! { "value": 1 },
+        options: None,
+    });
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+
+    // the range deletion can't be rolled back
+    let txn = db.start_transaction().unwrap();
+    let result = txn.collection::<Document>("telemetry").drop_partition("2024-01-02");
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+    txn.rollback().unwrap();
+
+    let result = db.collection::<Document>("plain").drop_partition("2024-01-01");
+    assert!(matches!(result, Err(Error::CollectionNotFound(_))));
+    db.create_collection("plain").unwrap();
+    let result = db.collection::<Document>("plain").drop_partition("2024-01-01");
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+
+    let result = db.create_collection_with_options(
+        "capped",
+        CreateCollectionOptions::builder().capped(true).max(10).partition_key("day").build(),
+    );
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+}
@@ -0,0 +1,135 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use polodb_core::{CollectionT, Database};
+use polodb_core::bson::{doc, Document};
+
+mod common;
+
+use common::mk_db_path;
+
+/// Insert the documents one by one, so each of them is a commit in the log,
+/// and copy the files of the open database to `crash_path` like a power loss.
+/// The memtable is flushed when the database is closed, so the copy is replayed from the log.
+fn write_commits(path: &Path, crash_path: &Path, count: i32) {
+    let _ = std::fs::remove_dir_all(path);
+    let _ = std::fs::remove_dir_all(crash_path);
+    let db = Database::open_path(path).unwrap();
+    let collection = db.collection::<Document>("test");
+    for i in 0..count {
+        collection.insert_one(doc! { "_id": i, "content": format!("content {}", i) }).unwrap();
+    }
+
+    std::fs::create_dir_all(crash_path).unwrap();
+    for entry in std::fs::read_dir(path).unwrap() {
+        let entry = entry.unwrap();
+        std::fs::copy(entry.path(), crash_path.join(entry.file_name())).unwrap();
+    }
+}
+
+fn newest_wal_file(path: &Path) -> PathBuf {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(path).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+        .collect();
+    files.sort();
+    files.pop().unwrap()
+}
+
+#[test]
+fn test_recovery_clean() {
+    let db_path = mk_db_path("test-recovery-clean");
+    write_commits(&db_path, &mk_db_path("test-recovery-clean-crash"), 3);
+
+    let db = Database::open_path(&db_path).unwrap();
+    let summary = db.recovery_summary();
+    assert!(summary.is_clean());
+    assert!(summary.last_sequence > 0);
+    assert_eq!(summary.discarded_wal_bytes, 0);
+
+    let memory_db = Database::open_memory().unwrap();
+    assert!(memory_db.recovery_summary().is_clean());
+}
+
+#[test]
+fn test_recovery_torn_wal_tail() {
+    let db_path = mk_db_path("test-recovery-torn-tail-crash");
+    write_commits(&mk_db_path("test-recovery-torn-tail"), &db_path, 10);
+
+    // the power is lost in the middle of the last commit
+    let wal_file = newest_wal_file(&db_path);
+    let file = OpenOptions::new().write(true).open(&wal_file).unwrap();
+    let size = file.metadata().unwrap().len();
+    file.set_len(size - 5).unwrap();
+    drop(file);
+
+    {
+        let db = Database::open_path(&db_path).unwrap();
+        let summary = db.recovery_summary();
+        assert!(!summary.is_clean());
+        assert_eq!(summary.torn_wal_files.len(), 1);
+        assert!(summary.discarded_wal_bytes > 0);
+        assert_eq!(summary.discarded_commits, 0);
+        assert!(summary.errors[0].contains("truncated record"));
+
+        let collection = db.collection::<Document>("test");
+        assert_eq!(collection.count_documents().unwrap(), 9);
+        assert!(collection.find_one(doc! { "_id": 9 }).unwrap().is_none());
+        collection.insert_one(doc! { "_id": 9 }).unwrap();
+    }
+
+    // the torn log is flushed on opening, so it's not replayed again
+    let db = Database::open_path(&db_path).unwrap();
+    assert!(db.recovery_summary().is_clean());
+    assert_eq!(db.collection::<Document>("test").count_documents().unwrap(), 10);
+}
+
+#[test]
+fn test_recovery_corrupt_wal_record() {
+    let db_path = mk_db_path("test-recovery-corrupt-record-crash");
+    write_commits(&mk_db_path("test-recovery-corrupt-record"), &db_path, 10);
+
+    // damage a byte of the sixth commit, the commits after it are not replayed
+    let wal_file = newest_wal_file(&db_path);
+    let mut data = std::fs::read(&wal_file).unwrap();
+    let position = data.windows(9).position(|window| window == b"content 5").unwrap();
+    data[position] ^= 0xFF;
+    std::fs::write(&wal_file, &data).unwrap();
+
+    let db = Database::open_path(&db_path).unwrap();
+    let summary = db.recovery_summary();
+    assert!(summary.errors[0].contains("checksum mismatch"));
+    assert_eq!(summary.discarded_commits, 4);
+    assert_eq!(db.collection::<Document>("test").count_documents().unwrap(), 5);
+}
+
+#[test]
+fn test_recovery_orphan_table_file() {
+    let db_path = mk_db_path("test-recovery-orphan-table-crash");
+    write_commits(&mk_db_path("test-recovery-orphan-table"), &db_path, 3);
+
+    // a table file of an interrupted flush
+    let orphan = db_path.join("009999.sst");
+    std::fs::write(&orphan, b"partially written").unwrap();
+
+    let db = Database::open_path(&db_path).unwrap();
+    let summary = db.recovery_summary();
+    assert_eq!(summary.orphan_table_files, vec!["009999.sst".to_string()]);
+    assert!(!orphan.exists());
+    assert!(summary.torn_wal_files.is_empty());
+    assert_eq!(db.collection::<Document>("test").count_documents().unwrap(), 3);
+}
@@ -0,0 +1,195 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+mod common;
+
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use polodb_core::{CancellationToken, CollectionT, Database, Error, IndexModel, Result, WalOperation, WalRecord};
+use polodb_core::bson::{doc, Document};
+use polodb_core::replication::{serve_follower, Follower, ReplicationMessage, ReplicationServer, ReplicationTransport};
+use common::{mk_db_path, prepare_db};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+fn wait_for_primary(follower: &Follower, primary: &Database) {
+    let sequence = primary.latest_sequence_number().unwrap();
+    assert!(follower.wait_for(sequence, TIMEOUT));
+}
+
+#[test]
+fn test_replication_over_tcp() {
+    let primary = Arc::new(prepare_db("test-replication-primary").unwrap());
+    let col = primary.collection::<Document>("users");
+    col.create_index(IndexModel {
+        keys: doc! { "name": 1 },
+        options: None,
+    }).unwrap();
+    col.insert_many((0..100).map(|i| doc! { "_id": i, "name": format!("user-{}", i) })).unwrap();
+
+    let handle = ReplicationServer::bind(primary.clone(), "127.0.0.1:0").unwrap().spawn().unwrap();
+    let follower = Follower::connect(prepare_db("test-replication-follower").unwrap(), handle.local_addr()).unwrap();
+    wait_for_primary(&follower, &primary);
+
+    let replica = follower.database().collection::<Document>("users");
+    assert_eq!(replica.count_documents().unwrap(), 100);
+    let found = replica.find_one(doc! { "name": "user-42" }).unwrap().unwrap();
+    assert_eq!(found.get_i32("_id").unwrap(), 42);
+
+    // the writes after the snapshot are shipped from the WAL
+    col.insert_one(doc! { "_id": 100, "name": "user-100" }).unwrap();
+    col.update_one(doc! { "_id": 1 }, doc! { "$set": { "name": "renamed" } }).unwrap();
+    col.delete_one(doc! { "_id": 2 }).unwrap();
+    wait_for_primary(&follower, &primary);
+
+    assert_eq!(replica.count_documents().unwrap(), 100);
+    assert!(replica.find_one(doc! { "_id": 2 }).unwrap().is_none());
+    assert!(replica.find_one(doc! { "name": "user-1" }).unwrap().is_none());
+    assert_eq!(replica.find_one(doc! { "name": "renamed" }).unwrap().unwrap().get_i32("_id").unwrap(), 1);
+
+    let status = follower.status();
+    assert!(status.connected);
+    assert_eq!(status.next_sequence, Some(primary.latest_sequence_number().unwrap() + 1));
+
+    let err = replica.insert_one(doc! { "_id": 1000 }).unwrap_err();
+    assert!(matches!(err, Error::ReadOnlyDatabase));
+
+    let promoted = follower.promote().unwrap();
+    let col = promoted.collection::<Document>("users");
+    col.insert_one(doc! { "_id": 1000, "name": "user-1000" }).unwrap();
+    assert_eq!(col.count_documents().unwrap(), 101);
+
+    handle.shutdown().unwrap();
+}
+
+#[test]
+fn test_follower_resume() {
+    let primary = Arc::new(prepare_db("test-replication-resume-primary").unwrap());
+    let col = primary.collection::<Document>("items");
+    col.insert_one(doc! { "_id": 1 }).unwrap();
+    let handle = ReplicationServer::bind(primary.clone(), "127.0.0.1:0").unwrap().spawn().unwrap();
+
+    {
+        let follower = Follower::connect(prepare_db("test-replication-resume-follower").unwrap(), handle.local_addr()).unwrap();
+        wait_for_primary(&follower, &primary);
+    }
+
+    col.insert_one(doc! { "_id": 2 }).unwrap();
+    col.delete_one(doc! { "_id": 1 }).unwrap();
+
+    let db = Database::open_path(mk_db_path("test-replication-resume-follower")).unwrap();
+    let follower = Follower::connect(db, handle.local_addr()).unwrap();
+    assert!(follower.status().next_sequence.is_some());
+    wait_for_primary(&follower, &primary);
+
+    let items: Vec<Document> = follower.database().collection::<Document>("items")
+        .find(doc! {})
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(items, vec![doc! { "_id": 2 }]);
+
+    handle.shutdown().unwrap();
+}
+
+struct ChannelTransport {
+    sender: Sender<ReplicationMessage>,
+    receiver: Receiver<ReplicationMessage>,
+}
+
+impl ChannelTransport {
+
+    fn pair() -> (ChannelTransport, ChannelTransport) {
+        let (primary_sender, follower_receiver) = channel();
+        let (follower_sender, primary_receiver) = channel();
+        (
+            ChannelTransport { sender: primary_sender, receiver: primary_receiver },
+            ChannelTransport { sender: follower_sender, receiver: follower_receiver },
+        )
+    }
+
+}
+
+impl ReplicationTransport for ChannelTransport {
+
+    fn send(&mut self, message: &ReplicationMessage) -> Result<()> {
+        self.sender.send(message.clone())
+            .map_err(|_| Error::ReplicationError("disconnected".to_string()))
+    }
+
+    fn receive(&mut self) -> Result<Option<ReplicationMessage>> {
+        Ok(self.receiver.recv().ok())
+    }
+
+}
+
+#[test]
+fn test_custom_transport() {
+    let primary = Arc::new(Database::open_memory().unwrap());
+    primary.collection::<Document>("a").insert_one(doc! { "_id": 1 }).unwrap();
+    primary.database("tenant").collection::<Document>("b").insert_one(doc! { "_id": 2 }).unwrap();
+
+    let token = CancellationToken::new();
+    let follower = {
+        let primary = primary.clone();
+        let token = token.clone();
+        Follower::start(Database::open_memory().unwrap(), move || {
+            let (primary_side, follower_side) = ChannelTransport::pair();
+            let primary = primary.clone();
+            let token = token.clone();
+            thread::spawn(move || serve_follower(&primary, primary_side, &token));
+            Ok(follower_side)
+        }).unwrap()
+    };
+    wait_for_primary(&follower, &primary);
+
+    primary.collection::<Document>("a").insert_one(doc! { "_id": 3 }).unwrap();
+    wait_for_primary(&follower, &primary);
+
+    let db = follower.database();
+    assert_eq!(db.collection::<Document>("a").count_documents().unwrap(), 2);
+    assert_eq!(db.database("tenant").collection::<Document>("b").count_documents().unwrap(), 1);
+
+    drop(follower);
+    token.cancel();
+}
+
+#[test]
+fn test_message_document() {
+    let messages = vec![
+        ReplicationMessage::Subscribe { since: None },
+        ReplicationMessage::Subscribe { since: Some(42) },
+        ReplicationMessage::SnapshotStart,
+        ReplicationMessage::SnapshotChunk(vec![WalOperation::Put { key: vec![1, 2], value: vec![3] }]),
+        ReplicationMessage::SnapshotEnd { next_sequence: 7 },
+        ReplicationMessage::Record(WalRecord {
+            sequence: 8,
+            operations: vec![
+                WalOperation::Put { key: vec![1], value: vec![] },
+                WalOperation::Delete { key: vec![2] },
+                WalOperation::DeleteRange { start: vec![3], end: vec![3, 0xFF] },
+            ],
+        }),
+        ReplicationMessage::Heartbeat { latest_sequence: 9 },
+    ];
+    for message in messages {
+        let doc = message.to_document();
+        assert_eq!(ReplicationMessage::from_document(&doc).unwrap(), message);
+    }
+    assert!(ReplicationMessage::from_document(&doc! { "t": "unknown" }).is_err());
+}
@@ -0,0 +1,87 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::thread;
+use polodb_core::{CollectionT, Error};
+use polodb_core::bson::{doc, Document};
+
+mod common;
+
+use common::prepare_db;
+
+#[test]
+fn test_next_sequence() {
+    let db = prepare_db("test-next-sequence").unwrap();
+
+    assert_eq!(db.current_sequence("invoices").unwrap(), None);
+    assert_eq!(db.next_sequence("invoices").unwrap(), 1);
+    assert_eq!(db.next_sequence("invoices").unwrap(), 2);
+    assert_eq!(db.next_sequence("orders").unwrap(), 1);
+    assert_eq!(db.current_sequence("invoices").unwrap(), Some(2));
+
+    db.reset_sequence("invoices", 1000).unwrap();
+    assert_eq!(db.next_sequence("invoices").unwrap(), 1001);
+
+    assert!(matches!(db.next_sequence(""), Err(Error::IllegalSequenceName(_))));
+
+    let sales = db.database("sales");
+    assert_eq!(sales.next_sequence("invoices").unwrap(), 1);
+    sales.drop().unwrap();
+    assert_eq!(sales.current_sequence("invoices").unwrap(), None);
+    assert_eq!(db.current_sequence("invoices").unwrap(), Some(1001));
+}
+
+#[test]
+fn test_next_sequence_in_transaction() {
+    let db = prepare_db("test-next-sequence-in-transaction").unwrap();
+
+    let txn = db.start_transaction().unwrap();
+    let number = txn.next_sequence("invoices").unwrap();
+    txn.collection::<Document>("invoices").insert_one(doc! { "number": number }).unwrap();
+    txn.commit().unwrap();
+    assert_eq!(number, 1);
+
+    let txn = db.start_transaction().unwrap();
+    assert_eq!(txn.next_sequence("invoices").unwrap(), 2);
+    txn.rollback().unwrap();
+
+    assert_eq!(db.current_sequence("invoices").unwrap(), Some(1));
+    assert_eq!(db.next_sequence("invoices").unwrap(), 2);
+    assert_eq!(db.collection::<Document>("invoices").count_documents().unwrap(), 1);
+}
+
+#[test]
+fn test_next_sequence_concurrent() {
+    let db = Arc::new(prepare_db("test-next-sequence-concurrent").unwrap());
+
+    let handles: Vec<_> = (0..4).map(|_| {
+        let db = db.clone();
+        thread::spawn(move || {
+            (0..50).map(|_| db.next_sequence("invoices").unwrap()).collect::<Vec<i64>>()
+        })
+    }).collect();
+
+    let mut numbers = HashSet::new();
+    for handle in handles {
+        for number in handle.join().unwrap() {
+            assert!(numbers.insert(number));
+        }
+    }
+
+    assert_eq!(numbers.len(), 200);
+    assert_eq!(db.current_sequence("invoices").unwrap(), Some(200));
+}
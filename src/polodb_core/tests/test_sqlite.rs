@@ -0,0 +1,139 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+#![cfg(feature = "sqlite")]
+
+use polodb_core::{CollectionT, Error};
+use polodb_core::bson::{doc, Bson, Document};
+use polodb_core::sqlite::SqliteImportOptions;
+
+mod common;
+
+use common::prepare_db;
+
+fn prepare_sqlite(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("test-sqlite-{}.sqlite", name));
+    let _ = std::fs::remove_file(&path);
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    // the book 12 references a missing author
+    conn.execute_batch("
+        PRAGMA foreign_keys = OFF;
+        CREATE TABLE authors (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            email TEXT UNIQUE,
+            active BOOLEAN,
+            born DATE
+        );
+        CREATE TABLE books (
+            id INTEGER PRIMARY KEY,
+            title TEXT,
+            price REAL,
+            cover BLOB,
+            author_id INTEGER REFERENCES authors(id)
+        );
+        CREATE INDEX books_title ON books(title);
+        CREATE TABLE tags (book_id INTEGER, tag TEXT, PRIMARY KEY (book_id, tag));
+        INSERT INTO authors VALUES (1, 'Liu Cixin', 'liu@example.com', 1, '1963-06-23');
+        INSERT INTO authors VALUES (2, 'Ted Chiang', NULL, 0, NULL);
+        INSERT INTO books VALUES (10, 'The Three-Body Problem', 9.5, x'0102', 1);
+        INSERT INTO books VALUES (11, 'Exhalation', 12.0, NULL, 2);
+        INSERT INTO books VALUES (12, 'Unknown', NULL, NULL, 3);
+        INSERT INTO tags VALUES (10, 'sci-fi');
+        INSERT INTO tags VALUES (10, 'classic');
+    ").unwrap();
+    path
+}
+
+#[test]
+fn test_import_sqlite() {
+    let db = prepare_db("test-import-sqlite").unwrap();
+    let path = prepare_sqlite("import");
+
+    let result = db.import_sqlite(&path, SqliteImportOptions::default()).unwrap();
+    assert_eq!(result.tables, 3);
+    assert_eq!(result.documents, 7);
+    assert_eq!(result.indexes, 2);
+
+    let authors = db.collection::<Document>("authors");
+    let liu = authors.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert_eq!(liu.get_str("name").unwrap(), "Liu Cixin");
+    assert!(liu.get_bool("active").unwrap());
+    assert!(matches!(liu.get("born"), Some(Bson::DateTime(_))));
+
+    // the NULL values are omitted
+    let ted = authors.find_one(doc! { "_id": 2 }).unwrap().unwrap();
+    assert!(!ted.contains_key("email"));
+    assert!(!ted.contains_key("born"));
+    assert!(!ted.get_bool("active").unwrap());
+
+    let books = db.collection::<Document>("books");
+    let book = books.find_one(doc! { "_id": 10 }).unwrap().unwrap();
+    assert_eq!(book.get_f64("price").unwrap(), 9.5);
+    assert_eq!(book.get_binary_generic("cover").unwrap(), &vec![1u8, 2]);
+    assert_eq!(book.get_i32("author_id").unwrap(), 1);
+
+    // the composite primary key is kept as the columns
+    let tags = db.collection::<Document>("tags");
+    assert_eq!(tags.count_documents().unwrap(), 2);
+    let tag = tags.find_one(doc! { "tag": "classic" }).unwrap().unwrap();
+    assert_eq!(tag.get_i32("book_id").unwrap(), 10);
+    assert!(matches!(tag.get("_id"), Some(Bson::ObjectId(_))));
+
+    // the unique constraint is imported
+    let err = authors.insert_one(doc! { "_id": 3, "name": "Someone", "email": "liu@example.com" }).unwrap_err();
+    assert!(matches!(err, Error::DuplicateKey(_)));
+}
+
+#[test]
+fn test_import_sqlite_embed_foreign_keys() {
+    let db = prepare_db("test-import-sqlite-embed").unwrap();
+    let path = prepare_sqlite("embed");
+
+    let options = SqliteImportOptions::builder()
+        .tables(["books"])
+        .embed_foreign_keys(true)
+        .build();
+    let result = db.import_sqlite(&path, options).unwrap();
+    assert_eq!(result.tables, 1);
+    assert_eq!(result.documents, 3);
+
+    let books = db.collection::<Document>("books");
+    let book = books.find_one(doc! { "_id": 10 }).unwrap().unwrap();
+    let author = book.get_document("author_id").unwrap();
+    assert_eq!(author.get_i32("_id").unwrap(), 1);
+    assert_eq!(author.get_str("name").unwrap(), "Liu Cixin");
+
+    let found = books.find_one(doc! { "author_id.name": "Ted Chiang" }).unwrap().unwrap();
+    assert_eq!(found.get_str("title").unwrap(), "Exhalation");
+
+    // the value is kept if the referenced row doesn't exist
+    let book = books.find_one(doc! { "_id": 12 }).unwrap().unwrap();
+    assert_eq!(book.get_i32("author_id").unwrap(), 3);
+
+    assert!(db.list_collection_names().unwrap().iter().all(|name| name != "authors"));
+}
+
+#[test]
+fn test_import_sqlite_missing_table() {
+    let db = prepare_db("test-import-sqlite-missing").unwrap();
+    let path = prepare_sqlite("missing");
+
+    let options = SqliteImportOptions::builder()
+        .tables(["nothing"])
+        .build();
+    let err = db.import_sqlite(&path, options).unwrap_err();
+    assert!(matches!(err, Error::SqliteError(_)));
+}
@@ -0,0 +1,237 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use polodb_core::{ConfigBuilder, Database, CollectionT};
+use polodb_core::bson::{doc, Document};
+use polodb_core::storage_backend::{FileSystemBackend, OpenMode, StorageBackend, StorageFile};
+
+mod common;
+
+use common::mk_db_path;
+
+type Data = Arc<Mutex<Vec<u8>>>;
+
+/// Keeps the files in memory and counts the writes.
+#[derive(Default)]
+struct MemoryBackend {
+    files: Mutex<BTreeMap<PathBuf, Data>>,
+    dirs: Mutex<BTreeSet<PathBuf>>,
+    write_count: Arc<AtomicU64>,
+}
+
+struct MemoryFile {
+    data: Data,
+    write_count: Arc<AtomicU64>,
+}
+
+impl StorageFile for MemoryFile {
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.data.lock().unwrap();
+        let offset = (offset as usize).min(data.len());
+        let n = buf.len().min(data.len() - offset);
+        buf[..n].copy_from_slice(&data[offset..offset + n]);
+        Ok(n)
+    }
+
+    fn write_at(&self, offset: u64, bytes: &[u8]) -> io::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        let end = offset as usize + bytes.len();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[offset as usize..end].copy_from_slice(bytes);
+        self.write_count.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.data.lock().unwrap().len() as u64)
+    }
+
+    fn truncate(&self, size: u64) -> io::Result<()> {
+        self.data.lock().unwrap().resize(size as usize, 0);
+        Ok(())
+    }
+
+}
+
+impl StorageBackend for MemoryBackend {
+
+    fn open(&self, path: &Path, mode: OpenMode) -> io::Result<Box<dyn StorageFile>> {
+        let mut files = self.files.lock().unwrap();
+        let data = match (files.get(path), mode) {
+            (Some(data), OpenMode::Truncate) => {
+                data.lock().unwrap().clear();
+                data.clone()
+            }
+            (Some(data), _) => data.clone(),
+            (None, OpenMode::Read) => return Err(io::ErrorKind::NotFound.into()),
+            (None, _) => {
+                let data = Data::default();
+                files.insert(path.to_path_buf(), data.clone());
+                data
+            }
+        };
+        Ok(Box::new(MemoryFile {
+            data,
+            write_count: self.write_count.clone(),
+        }))
+    }
+
+    fn delete(&self, path: &Path) -> io::Result<()> {
+        let removed = self.files.lock().unwrap().remove(path).is_some()
+            || self.dirs.lock().unwrap().remove(path);
+        if removed {
+            Ok(())
+        } else {
+            Err(io::ErrorKind::NotFound.into())
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut files = self.files.lock().unwrap();
+        let data = files.remove(from).ok_or(io::Error::from(io::ErrorKind::NotFound))?;
+        files.insert(to.to_path_buf(), data);
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        Ok(self.files.lock().unwrap().contains_key(path) || self.dirs.lock().unwrap().contains(path))
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<String>> {
+        let files = self.files.lock().unwrap();
+        let dirs = self.dirs.lock().unwrap();
+        if !dirs.contains(dir) {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        Ok(files.keys().chain(dirs.iter())
+            .filter(|path| path.parent() == Some(dir))
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect())
+    }
+
+    fn create_dir(&self, dir: &Path) -> io::Result<()> {
+        let mut dirs = self.dirs.lock().unwrap();
+        for ancestor in dir.ancestors() {
+            dirs.insert(ancestor.to_path_buf());
+        }
+        Ok(())
+    }
+
+}
+
+fn insert_and_count(db: &Database, count: usize) -> u64 {
+    let collection = db.collection::<Document>("test");
+    let docs = (0..count).map(|i| doc! {
+        "_id": i as i64,
+        "content": format!("content-{}", i),
+    }).collect::<Vec<Document>>();
+    collection.insert_many(&docs).unwrap();
+    collection.count_documents().unwrap()
+}
+
+#[test]
+fn test_custom_storage_backend() {
+    let db_path = mk_db_path("test-custom-storage-backend");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    let backend = Arc::new(MemoryBackend::default());
+    {
+        let mut config = ConfigBuilder::new();
+        config.set_storage_backend(backend.clone());
+        let db = Database::open_path_with_config(db_path.as_path(), config.take()).unwrap();
+        assert_eq!(insert_and_count(&db, 100), 100);
+    }
+
+    assert!(backend.write_count.load(Ordering::Relaxed) > 0);
+    assert!(backend.exists(&db_path.join("CURRENT")).unwrap());
+    assert!(!db_path.exists());
+
+    let mut config = ConfigBuilder::new();
+    config.set_storage_backend(backend.clone());
+    let db = Database::open_path_with_config(db_path.as_path(), config.take()).unwrap();
+    let collection = db.collection::<Document>("test");
+    assert_eq!(collection.count_documents().unwrap(), 100);
+    let doc = collection.find_one(doc! { "_id": 42_i64 }).unwrap().unwrap();
+    assert_eq!(doc.get_str("content").unwrap(), "content-42");
+}
+
+#[test]
+fn test_file_system_backend() {
+    let db_path = mk_db_path("test-file-system-backend");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    {
+        let mut config = ConfigBuilder::new();
+        config.set_storage_backend(Arc::new(FileSystemBackend::new()));
+        let db = Database::open_path_with_config(db_path.as_path(), config.take()).unwrap();
+        assert_eq!(insert_and_count(&db, 100), 100);
+    }
+
+    // the files are compatible with the default file layer
+    let db = Database::open_path(db_path.as_path()).unwrap();
+    assert_eq!(db.collection::<Document>("test").count_documents().unwrap(), 100);
+}
+
+/// Panics when the engine opens a file.
+struct PanicBackend;
+
+impl StorageBackend for PanicBackend {
+
+    fn open(&self, _path: &Path, _mode: OpenMode) -> io::Result<Box<dyn StorageFile>> {
+        panic!("the backend is broken");
+    }
+
+    fn delete(&self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn rename(&self, _from: &Path, _to: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn exists(&self, _path: &Path) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    fn list(&self, _dir: &Path) -> io::Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    fn create_dir(&self, _dir: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+}
+
+// the panics of the backend don't unwind into RocksDB
+#[test]
+fn test_storage_backend_panic() {
+    let db_path = mk_db_path("test-storage-backend-panic");
+
+    let mut config = ConfigBuilder::new();
+    config.set_storage_backend(Arc::new(PanicBackend));
+    assert!(Database::open_path_with_config(db_path.as_path(), config.take()).is_err());
+}
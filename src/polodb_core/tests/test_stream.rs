@@ -0,0 +1,107 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(feature = "async")]
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake};
+use std::thread::Thread;
+use futures_core::Stream;
+use polodb_core::{CancellationToken, CollectionT, Database, Error};
+use polodb_core::bson::{doc, Document};
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+    let waker = Arc::new(ThreadWaker(std::thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    struct Next<'a, S>(&'a mut S);
+    impl<S: Stream + Unpin> Future for Next<'_, S> {
+        type Output = Option<S::Item>;
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            Pin::new(&mut *self.0).poll_next(cx)
+        }
+    }
+    let mut future = Next(stream);
+    loop {
+        if let Poll::Ready(item) = Pin::new(&mut future).poll(&mut cx) {
+            return item;
+        }
+        std::thread::park();
+    }
+}
+
+#[test]
+fn test_cursor_stream() {
+    let db = Database::open_memory().unwrap();
+    let collection = db.collection::<Document>("test");
+    collection.insert_many((0..100).map(|i| doc! { "_id": i })).unwrap();
+
+    let mut stream = collection.find(doc! {}).run().unwrap().into_stream(4).unwrap();
+    let mut ids = vec![];
+    while let Some(doc) = next(&mut stream) {
+        ids.push(doc.unwrap().get_i32("_id").unwrap());
+    }
+    assert_eq!(ids, (0..100).collect::<Vec<i32>>());
+
+    // the reads stop when the stream is dropped
+    let mut stream = collection.find(doc! {}).run().unwrap().into_stream(0).unwrap();
+    assert_eq!(next(&mut stream).unwrap().unwrap().get_i32("_id").unwrap(), 0);
+    drop(stream);
+    collection.insert_one(doc! { "_id": 100 }).unwrap();
+}
+
+#[test]
+fn test_cursor_stream_cancel() {
+    let db = Database::open_memory().unwrap();
+    let collection = db.collection::<Document>("test");
+    collection.insert_many((0..100).map(|i| doc! { "_id": i })).unwrap();
+
+    // the token passed to the cursor still cancels the stream
+    let token = CancellationToken::new();
+    let mut stream = collection.find(doc! {})
+        .cancellation_token(token.clone())
+        .run()
+        .unwrap()
+        .into_stream(1)
+        .unwrap();
+    assert_eq!(next(&mut stream).unwrap().unwrap().get_i32("_id").unwrap(), 0);
+    token.cancel();
+    let mut items = vec![];
+    while let Some(item) = next(&mut stream) {
+        items.push(item);
+    }
+    assert!(items.len() < 99);
+    assert!(matches!(items.last(), Some(Err(Error::OperationCancelled))));
+
+    // dropping the stream doesn't cancel the token of the caller
+    let token = CancellationToken::new();
+    let mut stream = collection.find(doc! {})
+        .cancellation_token(token.clone())
+        .run()
+        .unwrap()
+        .into_stream(1)
+        .unwrap();
+    assert!(next(&mut stream).unwrap().is_ok());
+    drop(stream);
+    assert!(!token.is_cancelled());
+}
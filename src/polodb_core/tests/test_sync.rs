@@ -0,0 +1,203 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+use polodb_core::{CollectionT, ConfigBuilder, Database, Error, IndexModel, Result};
+use polodb_core::bson::{doc, Document};
+use polodb_core::sync::{ChangeSet, ConflictStrategy, SyncOptions};
+use common::{mk_db_path, prepare_db_with_config};
+
+fn open_tracked() -> Database {
+    let mut config = ConfigBuilder::new();
+    config.set_sync_tracking(true);
+    Database::open_memory_with_config(config.take()).unwrap()
+}
+
+fn all_documents(db: &Database, name: &str) -> Vec<Document> {
+    db.collection::<Document>(name)
+        .find(doc! {})
+        .sort(doc! { "_id": 1 })
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap()
+}
+
+#[test]
+fn test_sync_two_replicas() {
+    let a = open_tracked();
+    let b = open_tracked();
+    assert_ne!(a.sync_replica_id().unwrap(), b.sync_replica_id().unwrap());
+
+    a.collection::<Document>("notes").insert_many(vec![
+        doc! { "_id": 1, "text": "a1" },
+        doc! { "_id": 2, "text": "a2" },
+    ]).unwrap();
+    b.collection::<Document>("notes").insert_one(doc! { "_id": 3, "text": "b3" }).unwrap();
+
+    let result = a.sync_with(&b, &SyncOptions::default()).unwrap();
+    assert_eq!(result.pulled.applied, 1);
+    assert_eq!(result.pushed.applied, 2);
+    assert_eq!(all_documents(&a, "notes"), all_documents(&b, "notes"));
+    assert_eq!(all_documents(&a, "notes").len(), 3);
+
+    // the updates and the deletes follow the synced versions
+    b.collection::<Document>("notes").update_one(doc! { "_id": 1 }, doc! { "$set": { "text": "edited" } }).unwrap();
+    b.collection::<Document>("notes").delete_one(doc! { "_id": 2 }).unwrap();
+    let result = a.sync_with(&b, &SyncOptions::default()).unwrap();
+    assert_eq!(result.pulled.applied, 2);
+    assert_eq!(result.pulled.conflicts, 0);
+    assert_eq!(all_documents(&a, "notes"), vec![
+        doc! { "_id": 1, "text": "edited" },
+        doc! { "_id": 3, "text": "b3" },
+    ]);
+    assert_eq!(all_documents(&a, "notes"), all_documents(&b, "notes"));
+
+    let result = a.sync_with(&b, &SyncOptions::default()).unwrap();
+    assert_eq!(result.pulled.applied + result.pulled.conflicts, 0);
+    assert_eq!(result.pushed.applied + result.pushed.conflicts, 0);
+}
+
+#[test]
+fn test_sync_last_writer_wins() {
+    let a = open_tracked();
+    let b = open_tracked();
+    a.collection::<Document>("items").insert_one(doc! { "_id": 1, "name": "x", "qty": 1 }).unwrap();
+    a.sync_with(&b, &SyncOptions::default()).unwrap();
+
+    a.collection::<Document>("items").update_one(doc! { "_id": 1 }, doc! { "$set": { "name": "from a" } }).unwrap();
+    thread::sleep(Duration::from_millis(5));
+    b.collection::<Document>("items").update_one(doc! { "_id": 1 }, doc! { "$set": { "qty": 2 } }).unwrap();
+
+    let result = a.sync_with(&b, &SyncOptions::default()).unwrap();
+    assert_eq!(result.pulled.conflicts, 1);
+    let expected = vec![doc! { "_id": 1, "name": "x", "qty": 2 }];
+    assert_eq!(all_documents(&a, "items"), expected);
+    assert_eq!(all_documents(&b, "items"), expected);
+}
+
+#[test]
+fn test_sync_merge_fields() {
+    let a = open_tracked();
+    let b = open_tracked();
+    let options = SyncOptions::builder().strategy(ConflictStrategy::MergeFields).build();
+    a.collection::<Document>("items").insert_one(doc! { "_id": 1, "name": "x", "qty": 1 }).unwrap();
+    a.sync_with(&b, &options).unwrap();
+
+    a.collection::<Document>("items").update_one(doc! { "_id": 1 }, doc! { "$set": { "name": "from a" } }).unwrap();
+    thread::sleep(Duration::from_millis(5));
+    b.collection::<Document>("items").update_one(doc! { "_id": 1 }, doc! { "$set": { "qty": 2, "tag": "new" } }).unwrap();
+
+    let result = a.sync_with(&b, &options).unwrap();
+    assert_eq!(result.pulled.conflicts, 1);
+    let expected = vec![doc! { "_id": 1, "name": "from a", "qty": 2, "tag": "new" }];
+    assert_eq!(all_documents(&a, "items"), expected);
+    assert_eq!(all_documents(&b, "items"), expected);
+}
+
+#[test]
+fn test_sync_custom_resolver() {
+    let a = open_tracked();
+    let b = open_tracked();
+    let options = SyncOptions::builder()
+        .resolver(|conflict| {
+            let total = conflict.local.map(|doc| doc.get_i32("count").unwrap()).unwrap_or(0)
+                + conflict.remote.map(|doc| doc.get_i32("count").unwrap()).unwrap_or(0);
+            Ok(Some(doc! { "count": total }))
+        })
+        .build();
+
+    a.collection::<Document>("counters").insert_one(doc! { "_id": "visits", "count": 3 }).unwrap();
+    b.collection::<Document>("counters").insert_one(doc! { "_id": "visits", "count": 4 }).unwrap();
+
+    let result = a.sync_with(&b, &options).unwrap();
+    assert_eq!(result.pulled.conflicts, 1);
+    // the resolved document is a new change of a
+    assert_eq!(result.pushed.applied, 1);
+    let expected = vec![doc! { "_id": "visits", "count": 7 }];
+    assert_eq!(all_documents(&a, "counters"), expected);
+    assert_eq!(all_documents(&b, "counters"), expected);
+}
+
+#[test]
+fn test_sync_change_set() {
+    let a = open_tracked();
+    let b = open_tracked();
+    let col = a.collection::<Document>("users");
+    col.create_index(IndexModel {
+        keys: doc! { "email": 1 },
+        options: None,
+    }).unwrap();
+    col.insert_one(doc! { "_id": 1, "email": "a@example.com" }).unwrap();
+    a.collection::<Document>("other").insert_one(doc! { "_id": 1 }).unwrap();
+
+    let options = SyncOptions::builder().collections(["users"]).build();
+    let a_id = a.sync_replica_id().unwrap();
+    let since = b.sync_checkpoint(&a_id).unwrap();
+    assert_eq!(since, 0);
+
+    // the change set is sent over the network as a BSON document
+    let changes = a.sync_changes(since, &options).unwrap();
+    let sent = polodb_core::bson::to_document(&changes).unwrap();
+    let received: ChangeSet = polodb_core::bson::from_document(sent).unwrap();
+    assert_eq!(received.changes.len(), 1);
+
+    let result = b.apply_changes(&received, &options).unwrap();
+    assert_eq!(result.applied, 1);
+    assert_eq!(b.sync_checkpoint(&a_id).unwrap(), received.checkpoint);
+    assert!(b.list_collection_names().unwrap().iter().all(|name| name != "other"));
+
+    // the indexes of b are kept up to date by the applied changes
+    b.collection::<Document>("users").create_index(IndexModel {
+        keys: doc! { "email": 1 },
+        options: None,
+    }).unwrap();
+    col.update_one(doc! { "_id": 1 }, doc! { "$set": { "email": "b@example.com" } }).unwrap();
+    let changes = a.sync_changes(b.sync_checkpoint(&a_id).unwrap(), &options).unwrap();
+    assert_eq!(changes.changes.len(), 1);
+    b.apply_changes(&changes, &options).unwrap();
+    let users = b.collection::<Document>("users");
+    assert!(users.find_one(doc! { "email": "a@example.com" }).unwrap().is_none());
+    assert!(users.find_one(doc! { "email": "b@example.com" }).unwrap().is_some());
+
+    let err = a.apply_changes(&changes, &options).unwrap_err();
+    assert!(matches!(err, Error::SyncError(_)));
+}
+
+#[test]
+fn test_sync_persistent_state() {
+    let mut config = ConfigBuilder::new();
+    config.set_sync_tracking(true);
+    let db = prepare_db_with_config("test-sync-persistent", config.take()).unwrap();
+    db.collection::<Document>("notes").insert_one(doc! { "_id": 1 }).unwrap();
+    let replica_id = db.sync_replica_id().unwrap();
+    let checkpoint = db.sync_changes(0, &SyncOptions::default()).unwrap().checkpoint;
+    drop(db);
+
+    let mut config = ConfigBuilder::new();
+    config.set_sync_tracking(true);
+    let db = Database::open_path_with_config(mk_db_path("test-sync-persistent"), config.take()).unwrap();
+    assert_eq!(db.sync_replica_id().unwrap(), replica_id);
+    db.collection::<Document>("notes").insert_one(doc! { "_id": 2 }).unwrap();
+    let changes = db.sync_changes(checkpoint, &SyncOptions::default()).unwrap();
+    assert_eq!(changes.changes.len(), 1);
+    assert!(changes.checkpoint > checkpoint);
+
+    let untracked = Database::open_memory().unwrap();
+    assert!(matches!(untracked.sync_replica_id().unwrap_err(), Error::SyncError(_)));
+}
@@ -0,0 +1,116 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use polodb_core::{CollectionT, Database, Error, IndexModel};
+use polodb_core::bson::{doc, DateTime, Document};
+use polodb_core::options::{CreateCollectionOptions, TimeseriesGranularity, TimeseriesOptions};
+
+mod common;
+
+use common::prepare_db;
+
+fn create_weather(db: &Database) {
+    let timeseries = TimeseriesOptions::new("ts")
+        .meta_field("sensor")
+        .granularity(TimeseriesGranularity::Seconds);
+    db.create_collection_with_options(
+        "weather",
+        CreateCollectionOptions::builder().timeseries(timeseries).build(),
+    ).unwrap();
+}
+
+#[test]
+fn test_timeseries_insert_and_find() {
+    let db = prepare_db("test-timeseries-find").unwrap();
+    create_weather(&db);
+    let col = db.collection::<Document>("weather");
+
+    let start = 1_700_000_000_000_i64;
+    let measurements: Vec<Document> = (0..1200_i64).map(|i| doc! {
+        "ts": DateTime::from_millis(start + i * 10_000),
+        "sensor": { "id": i % 2 },
+        "temp": (i % 40) as i32,
+    }).collect();
+    col.insert_many(&measurements).unwrap();
+
+    assert_eq!(col.count_documents().unwrap(), 1200);
+
+    let sensor_1: Vec<Document> = col.find(doc! { "sensor.id": 1 }).run().unwrap()
+        .map(|doc| doc.unwrap())
+        .collect();
+    assert_eq!(sensor_1.len(), 600);
+    assert!(sensor_1.iter().all(|doc| doc.contains_key("_id") && doc.get_i32("temp").is_ok()));
+
+    let hot: Vec<Document> = col.find(doc! { "temp": { "$gte": 38 } }).run().unwrap()
+        .map(|doc| doc.unwrap())
+        .collect();
+    assert_eq!(hot.len(), 1200 / 40 * 2);
+
+    // the measurements are returned with their own _id
+    let first = &sensor_1[0];
+    let found = col.find_one(doc! { "_id": first.get("_id").unwrap().clone() }).unwrap().unwrap();
+    assert_eq!(&found, first);
+
+    let result: Vec<Document> = col.aggregate(vec![
+        doc! { "$match": { "sensor.id": 0 } },
+        doc! { "$count": "count" },
+    ]).run().unwrap().map(|doc| doc.unwrap()).collect();
+    assert_eq!(result[0].get_i64("count").unwrap(), 600);
+}
+
+#[test]
+fn test_timeseries_restrictions() {
+    let db = prepare_db("test-timeseries-restrictions").unwrap();
+    create_weather(&db);
+    let col = db.collection::<Document>("weather");
+
+    let result = col.insert_one(doc! { "sensor": 1, "temp": 10 });
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+
+    col.insert_one(doc! { "ts": DateTime::now(), "sensor": 1, "temp": 10 }).unwrap();
+
+    let result = col.update_many(doc! {}, doc! { "$set": { "temp": 11 } });
+    assert!(matches!(result, Err(Error::TimeseriesCollectionImmutable(_))));
+    let result = col.delete_many(doc! { "sensor": 1 });
+    assert!(matches!(result, Err(Error::TimeseriesCollectionImmutable(_))));
+    let result = col.create_index(IndexModel {
+        keys: doc! { "temp": 1 },
+        options: None,
+    });
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+
+    // only the forward natural order is kept by the buckets
+    assert_eq!(col.find(doc! {}).sort(doc! { "$natural": 1 }).run().unwrap().count(), 1);
+    let result = col.find(doc! {}).sort(doc! { "$natural": -1 }).run();
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+    let result = col.find(doc! { "sensor": 1 }).sort(doc! { "$natural": -1 }).run();
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+    let result = col.aggregate(vec![doc! { "$sort": { "$natural": -1 } }]).run();
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+
+    col.drop().unwrap();
+    create_weather(&db);
+    assert_eq!(col.count_documents().unwrap(), 0);
+    col.insert_one(doc! { "ts": DateTime::now(), "sensor": 1, "temp": 10 }).unwrap();
+    assert_eq!(col.count_documents().unwrap(), 1);
+
+    let result = db.create_collection_with_options(
+        "invalid",
+        CreateCollectionOptions::builder()
+            .timeseries(TimeseriesOptions::new("ts").meta_field("ts"))
+            .build(),
+    );
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+}
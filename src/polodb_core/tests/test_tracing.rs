@@ -0,0 +1,96 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+#![cfg(feature = "tracing")]
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::{Event, Metadata, Subscriber};
+use tracing::span::{Attributes, Id, Record};
+use polodb_core::{Database, CollectionT, IndexModel};
+use polodb_core::bson::{doc, Document};
+
+/// Record the names of the spans and the messages of the events.
+#[derive(Clone, Default)]
+struct RecordingSubscriber {
+    next_id: Arc<AtomicU64>,
+    names: Arc<Mutex<Vec<String>>>,
+}
+
+struct MessageVisitor<'a>(&'a mut String);
+
+impl tracing::field::Visit for MessageVisitor<'_> {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            *self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+impl Subscriber for RecordingSubscriber {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        self.names.lock().unwrap().push(span.metadata().name().to_string());
+        Id::from_u64(self.next_id.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut message = String::new();
+        event.record(&mut MessageVisitor(&mut message));
+        self.names.lock().unwrap().push(message);
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[test]
+fn test_tracing_spans() {
+    let subscriber = RecordingSubscriber::default();
+    let names = subscriber.names.clone();
+
+    tracing::subscriber::with_default(subscriber, || {
+        let db = Database::open_memory().unwrap();
+        let collection = db.collection::<Document>("test");
+        collection.create_index(IndexModel {
+            keys: doc! { "name": 1 },
+            options: None,
+        }).unwrap();
+        collection.insert_one(doc! { "name": "a" }).unwrap();
+        collection.find_one(doc! { "name": "a" }).unwrap().unwrap();
+        collection.find_one(doc! { "age": 1 }).unwrap();
+    });
+
+    let names = names.lock().unwrap();
+    for expected in [
+        "polodb.insert_one",
+        "polodb.commit",
+        "polodb.find",
+        "polodb.plan",
+        "plan: index scan",
+        "polodb.index_scan",
+        "plan: collection scan",
+    ] {
+        assert!(names.iter().any(|name| name == expected), "{} is not traced", expected);
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use polodb_core::{CollectionT, IndexModel};
+use polodb_core::bson::{doc, Bson, Document};
+use polodb_core::uuid::Uuid;
+use serde::{Deserialize, Serialize};
+
+mod common;
+
+use common::prepare_db;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct User {
+    #[serde(rename = "_id", with = "polodb_core::bson::serde_helpers::uuid_1_as_binary")]
+    id: Uuid,
+    name: String,
+}
+
+#[test]
+fn test_uuid_primary_key() {
+    let db = prepare_db("test-uuid-primary-key").unwrap();
+    let col = db.collection::<Document>("items");
+
+    let id = Uuid::new_v4();
+    let result = col.insert_one_with_uuid(id, doc! { "name": "a" }).unwrap();
+    assert_eq!(result.inserted_id, Bson::from(id));
+
+    let found = col.find_by_uuid(id).unwrap().unwrap();
+    assert_eq!(found.get_str("name").unwrap(), "a");
+    assert!(col.find_by_uuid(Uuid::new_v4()).unwrap().is_none());
+
+    assert_eq!(col.delete_by_uuid(id).unwrap().deleted_count, 1);
+    assert!(col.find_by_uuid(id).unwrap().is_none());
+}
+
+#[test]
+fn test_uuid_key_order() {
+    let db = prepare_db("test-uuid-key-order").unwrap();
+    let col = db.collection::<Document>("items");
+
+    let mut ids: Vec<Uuid> = (0..50).map(|_| Uuid::new_v4()).collect();
+    for id in &ids {
+        col.insert_one_with_uuid(*id, doc! {}).unwrap();
+    }
+    ids.sort();
+
+    let stored: Vec<Uuid> = col.find(doc! {})
+        .run()
+        .unwrap()
+        .map(|doc| {
+            match doc.unwrap().get("_id") {
+                Some(Bson::Binary(binary)) => Uuid::from_slice(&binary.bytes).unwrap(),
+                other => panic!("unexpected _id: {:?}", other),
+            }
+        })
+        .collect();
+    assert_eq!(stored, ids);
+
+    let count = col.find(doc! { "_id": { "$gte": Bson::from(ids[10]) } }).run().unwrap().count();
+    assert_eq!(count, 40);
+}
+
+#[test]
+fn test_uuid_struct_and_index() {
+    let db = prepare_db("test-uuid-struct-and-index").unwrap();
+    let users = db.collection::<User>("users");
+
+    let user = User { id: Uuid::new_v4(), name: "alice".to_string() };
+    users.insert_one(&user).unwrap();
+    assert_eq!(users.find_by_uuid(user.id).unwrap(), Some(user));
+
+    let orders = db.collection::<Document>("orders");
+    orders.create_index(IndexModel {
+        keys: doc! { "user": 1 },
+        options: None,
+    }).unwrap();
+    let owner = Uuid::new_v4();
+    orders.insert_one(doc! { "user": Bson::from(owner), "total": 10 }).unwrap();
+    orders.insert_one(doc! { "user": Bson::from(Uuid::new_v4()), "total": 20 }).unwrap();
+
+    let found: Vec<Document> = orders.find(doc! { "user": Bson::from(owner) })
+        .run()
+        .unwrap()
+        .collect::<polodb_core::Result<_>>()
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].get_i32("total").unwrap(), 10);
+
+    let txn = db.start_transaction().unwrap();
+    let id = Uuid::new_v4();
+    txn.collection::<Document>("items").insert_one_with_uuid(id, doc! {}).unwrap();
+    assert!(txn.collection::<Document>("items").find_by_uuid(id).unwrap().is_some());
+    txn.rollback().unwrap();
+    assert!(db.collection::<Document>("items").find_by_uuid(id).unwrap().is_none());
+}
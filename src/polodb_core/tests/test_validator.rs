@@ -0,0 +1,180 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use polodb_core::{CollectionT, Database, Error};
+use polodb_core::bson::{doc, Document};
+use polodb_core::options::{CreateCollectionOptions, UpdateOptions, ValidationAction};
+
+mod common;
+
+use common::prepare_db;
+
+fn user_schema() -> Document {
+    doc! {
+        "$jsonSchema": {
+            "bsonType": "object",
+            "required": ["name"],
+            "properties": {
+                "name": { "bsonType": "string" },
+                "age": { "bsonType": "int", "minimum": 0 },
+            },
+        },
+    }
+}
+
+fn is_validation_error(err: &Error) -> bool {
+    matches!(err, Error::DocumentValidationFailed(_))
+}
+
+#[test]
+fn test_validator_insert() {
+    let db = prepare_db("test-validator-insert").unwrap();
+    db.create_collection_with_options(
+        "users",
+        CreateCollectionOptions::builder().validator(user_schema()).build(),
+    ).unwrap();
+    let col = db.collection::<Document>("users");
+
+    col.insert_one(doc! { "_id": 1, "name": "Vincent", "age": 30 }).unwrap();
+    let err = col.insert_one(doc! { "_id": 2, "age": 30 }).unwrap_err();
+    assert!(is_validation_error(&err));
+    assert_eq!(
+        err.to_string(),
+        "document failed validation of collection 'users': field 'name' is required",
+    );
+
+    // the whole batch is rejected
+    assert!(is_validation_error(&col.insert_many(vec![
+        doc! { "_id": 3, "name": "a" },
+        doc! { "_id": 4, "name": 4 },
+    ]).unwrap_err()));
+    assert_eq!(col.count_documents().unwrap(), 1);
+
+    let spec = db.list_collections(Default::default()).unwrap();
+    assert_eq!(spec[0].options.validator, Some(user_schema()));
+    assert_eq!(spec[0].options.validation_action, Some(ValidationAction::Error));
+}
+
+#[test]
+fn test_validator_update() {
+    let db = prepare_db("test-validator-update").unwrap();
+    db.create_collection_with_options(
+        "users",
+        CreateCollectionOptions::builder().validator(user_schema()).build(),
+    ).unwrap();
+    let col = db.collection::<Document>("users");
+    col.insert_many(vec![
+        doc! { "_id": 1, "name": "a", "age": 1 },
+        doc! { "_id": 2, "name": "b", "age": 2 },
+    ]).unwrap();
+
+    col.update_one(doc! { "_id": 1 }, doc! { "$inc": { "age": 1 } }).unwrap();
+    assert!(is_validation_error(&col.update_many(doc! {}, doc! { "$set": { "age": -1 } }).unwrap_err()));
+    assert!(is_validation_error(&col.update_one(doc! { "_id": 2 }, doc! { "$unset": { "name": "" } }).unwrap_err()));
+
+    // the upserted documents are validated too
+    assert!(is_validation_error(&col.update_one_with_options(
+        doc! { "_id": 3 },
+        doc! { "$set": { "age": 3 } },
+        UpdateOptions::builder().upsert(true).build(),
+    ).unwrap_err()));
+
+    let ages: Vec<i32> = col.find(doc! {}).run().unwrap()
+        .map(|doc| doc.unwrap().get_i32("age").unwrap())
+        .collect();
+    assert_eq!(ages, vec![2, 2]);
+}
+
+#[test]
+fn test_validator_warn() {
+    let db = Database::open_memory().unwrap();
+    db.create_collection_with_options(
+        "users",
+        CreateCollectionOptions::builder()
+            .validator(user_schema())
+            .validation_action(ValidationAction::Warn)
+            .build(),
+    ).unwrap();
+    let col = db.collection::<Document>("users");
+    col.insert_one(doc! { "_id": 1, "age": -1 }).unwrap();
+    col.update_one(doc! { "_id": 1 }, doc! { "$set": { "age": "x" } }).unwrap();
+    assert_eq!(col.count_documents().unwrap(), 1);
+}
+
+#[test]
+fn test_set_validator() {
+    let db = Database::open_memory().unwrap();
+    let col = db.collection::<Document>("users");
+    // the existing documents are not checked
+    col.insert_one(doc! { "_id": 1 }).unwrap();
+
+    db.set_validator("users", user_schema(), ValidationAction::Error).unwrap();
+    assert!(is_validation_error(&col.insert_one(doc! { "_id": 2 }).unwrap_err()));
+    // a document which was invalid must be fixed by the update
+    assert!(is_validation_error(&col.update_one(doc! { "_id": 1 }, doc! { "$set": { "age": 1 } }).unwrap_err()));
+    col.update_one(doc! { "_id": 1 }, doc! { "$set": { "name": "a" } }).unwrap();
+
+    db.remove_validator("users").unwrap();
+    col.insert_one(doc! { "_id": 2 }).unwrap();
+
+    assert!(matches!(
+        db.set_validator("users", doc! { "age": { "$gt": 0 } }, ValidationAction::Error),
+        Err(Error::ValidationError(_)),
+    ));
+    assert!(matches!(
+        db.set_validator("users", doc! { "$jsonSchema": { "bsonType": "integer" } }, ValidationAction::Error),
+        Err(Error::ValidationError(_)),
+    ));
+    assert!(matches!(
+        db.set_validator("missing", user_schema(), ValidationAction::Error),
+        Err(Error::CollectionNotFound(_)),
+    ));
+}
+
+#[test]
+fn test_changed_validator() {
+    let db = Database::open_memory().unwrap();
+    let col = db.collection::<Document>("users");
+    db.create_collection_with_options(
+        "users",
+        CreateCollectionOptions::builder().validator(user_schema()).build(),
+    ).unwrap();
+    // the validator is compiled by the first write
+    col.insert_one(doc! { "_id": 1, "name": "a" }).unwrap();
+    assert!(is_validation_error(&col.insert_one(doc! { "_id": 2 }).unwrap_err()));
+
+    // the writes after the change use the new validator
+    let email_schema = doc! {
+        "$jsonSchema": {
+            "required": ["email"],
+        },
+    };
+    db.set_validator("users", email_schema.clone(), ValidationAction::Error).unwrap();
+    assert!(is_validation_error(&col.insert_one(doc! { "_id": 2, "name": "b" }).unwrap_err()));
+    col.insert_one(doc! { "_id": 2, "email": "b@example.com" }).unwrap();
+    assert!(is_validation_error(&col.update_one(doc! { "_id": 2 }, doc! { "$unset": { "email": "" } }).unwrap_err()));
+
+    db.set_validator("users", email_schema, ValidationAction::Warn).unwrap();
+    col.insert_one(doc! { "_id": 3 }).unwrap();
+
+    // a collection created again with the same name has its own validator
+    col.drop().unwrap();
+    db.create_collection_with_options(
+        "users",
+        CreateCollectionOptions::builder().validator(user_schema()).build(),
+    ).unwrap();
+    assert!(is_validation_error(&col.insert_one(doc! { "_id": 1, "email": "a@example.com" }).unwrap_err()));
+    col.insert_one(doc! { "_id": 1, "name": "a" }).unwrap();
+}
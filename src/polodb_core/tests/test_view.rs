@@ -0,0 +1,101 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use polodb_core::{CollectionT, Error, Result};
+use polodb_core::bson::{doc, Document};
+
+mod common;
+
+use common::prepare_db;
+
+#[test]
+fn test_view_find_and_aggregate() {
+    let db = prepare_db("test-view-find").unwrap();
+    let users = db.collection::<Document>("users");
+    users.insert_many(vec![
+        doc! { "_id": 1, "name": "alice", "age": 30, "password": "a" },
+        doc! { "_id": 2, "name": "bob", "age": 17, "password": "b" },
+        doc! { "_id": 3, "name": "carol", "age": 45, "password": "c" },
+    ]).unwrap();
+
+    db.create_view("adults", "users", vec![
+        doc! { "$match": { "age": { "$gte": 18 } } },
+        doc! { "$unset": "password" },
+    ]).unwrap();
+    // a view on a view
+    db.create_view("seniors", "adults", vec![
+        doc! { "$match": { "age": { "$gte": 40 } } },
+    ]).unwrap();
+
+    assert!(db.list_collection_names().unwrap().contains(&"adults".to_string()));
+
+    let adults = db.collection::<Document>("adults");
+    assert_eq!(adults.count_documents().unwrap(), 2);
+    let all = adults.find(doc! {}).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+    assert_eq!(all.len(), 2);
+    assert!(all.iter().all(|doc| !doc.contains_key("password")));
+
+    let carol = adults.find_one(doc! { "name": "carol" }).unwrap().unwrap();
+    assert_eq!(carol.get_i32("age").unwrap(), 45);
+    assert!(adults.find_one(doc! { "name": "bob" }).unwrap().is_none());
+
+    let sorted = adults.find(doc! {})
+        .sort(doc! { "age": -1 })
+        .limit(1)
+        .run().unwrap()
+        .collect::<Result<Vec<Document>>>().unwrap();
+    assert_eq!(sorted[0].get_str("name").unwrap(), "carol");
+
+    let count = adults.aggregate(vec![doc! { "$count": "count" }])
+        .run().unwrap()
+        .collect::<Result<Vec<Document>>>().unwrap();
+    assert_eq!(count[0].get("count").unwrap().as_i64().unwrap(), 2);
+
+    let seniors = db.collection::<Document>("seniors");
+    assert_eq!(seniors.count_documents().unwrap(), 1);
+
+    // the view reflects the changes of the source
+    users.insert_one(doc! { "_id": 4, "name": "dave", "age": 60, "password": "d" }).unwrap();
+    assert_eq!(seniors.count_documents().unwrap(), 2);
+}
+
+#[test]
+fn test_view_read_only() {
+    let db = prepare_db("test-view-read-only").unwrap();
+    db.collection::<Document>("users").insert_one(doc! { "_id": 1, "age": 30 }).unwrap();
+    db.create_view("view", "users", vec![]).unwrap();
+    let view = db.collection::<Document>("view");
+
+    assert_eq!(view.count_documents().unwrap(), 1);
+    assert!(matches!(view.insert_one(doc! { "_id": 2 }), Err(Error::ViewReadOnly(_))));
+    assert!(matches!(
+        view.update_many(doc! {}, doc! { "$set": { "age": 31 } }),
+        Err(Error::ViewReadOnly(_))
+    ));
+    assert!(matches!(view.delete_many(doc! {}), Err(Error::ViewReadOnly(_))));
+    assert!(matches!(db.create_view("view", "users", vec![]), Err(Error::CollectionAlreadyExits(_))));
+
+    // a view on a missing collection is empty
+    db.create_view("empty", "missing", vec![]).unwrap();
+    assert_eq!(db.collection::<Document>("empty").count_documents().unwrap(), 0);
+
+    // the views on each other never terminate
+    db.create_view("a", "b", vec![]).unwrap();
+    db.create_view("b", "a", vec![]).unwrap();
+    assert!(matches!(db.collection::<Document>("a").count_documents(), Err(Error::ValidationError(_))));
+
+    view.drop().unwrap();
+    assert_eq!(db.collection::<Document>("users").count_documents().unwrap(), 1);
+}
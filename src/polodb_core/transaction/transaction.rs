@@ -16,19 +16,23 @@ use std::sync::{Arc, Weak};
 use serde::Serialize;
 use crate::{TransactionalCollection};
 use crate::db::db_inner::DatabaseInner;
+use crate::db::qualify_col_name;
+use crate::kv::KvStore;
 use super::transaction_inner::TransactionInner;
 
 #[derive(Clone)]
 pub struct Transaction {
     db: Weak<DatabaseInner>,
+    namespace: Option<String>,
     inner: Arc<TransactionInner>,
 }
 
 impl Transaction {
 
-    pub(crate) fn new(db: Weak<DatabaseInner>, inner: TransactionInner) -> Transaction {
+    pub(crate) fn new(db: Weak<DatabaseInner>, namespace: Option<String>, inner: TransactionInner) -> Transaction {
         Transaction {
             db,
+            namespace,
             inner: Arc::new(inner),
         }
     }
@@ -40,7 +44,33 @@ impl Transaction {
     /// a new collection will be created.
     ///
     pub fn collection<T: Serialize>(&self, col_name: &str) -> TransactionalCollection<T> {
-        TransactionalCollection::new(self.db.clone(), col_name, self.inner.as_ref().clone())
+        let col_name = qualify_col_name(self.namespace.as_deref(), col_name);
+        TransactionalCollection::new(self.db.clone(), &col_name, self.inner.as_ref().clone())
+    }
+
+    /// Increments the sequence `name` in the transaction and returns the new number.
+    ///
+    /// The sequence is locked until the transaction is committed or rolled back,
+    /// the increment is discarded by the rollback.
+    pub fn next_sequence(&self, name: &str) -> crate::Result<i64> {
+        let db = self.db.upgrade().ok_or(crate::Error::DbIsClosed)?;
+        db.next_sequence(self.namespace.as_deref(), name, self.inner.as_ref())
+    }
+
+    /// The raw key-value store of the database, the writes are committed with the transaction.
+    /// See [`crate::kv`].
+    pub fn kv(&self) -> KvStore {
+        KvStore::new(self.db.clone(), self.namespace.clone(), Some(self.inner.as_ref().clone()))
+    }
+
+    #[inline]
+    pub(crate) fn inner(&self) -> &TransactionInner {
+        self.inner.as_ref()
+    }
+
+    #[inline]
+    pub(crate) fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
     }
 
     #[inline]
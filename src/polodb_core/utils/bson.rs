@@ -14,14 +14,49 @@
 
 use std::cmp::Ordering;
 use std::io::{BufRead, Read, Write};
-use bson::{Bson, DateTime, Decimal128, Document, Timestamp};
+use bson::{Binary, Bson, DateTime, Decimal128, Document, Timestamp};
 use bson::oid::ObjectId;
-use bson::spec::ElementType;
+use bson::spec::{BinarySubtype, ElementType};
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use bson::ser::Error as BsonErr;
 use bson::ser::Result as BsonResult;
+use crate::utils::decimal::{decimal_operands, Decimal};
 use crate::{Error, Result};
 
+/// The binary subtype of the primary keys of a collection with a key ordering,
+/// see [`crate::coll::key_ordering`].
+pub(crate) const ORDERED_KEY_SUBTYPE: u8 = 0x80;
+
+/// Write `bytes` followed by `[0, 0]`, each zero byte of them is written as `[0, 0xFF]`,
+/// so the written bytes compare in the order of `bytes` and end where they're followed by other keys.
+pub(crate) fn write_escaped_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> Result<()> {
+    for byte in bytes {
+        writer.write_u8(*byte)?;
+        if *byte == 0 {
+            writer.write_u8(0xFF)?;
+        }
+    }
+    writer.write_all(&[0, 0])?;
+    Ok(())
+}
+
+/// Read the bytes written by [`write_escaped_bytes`].
+pub(crate) fn read_escaped_bytes<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    loop {
+        let byte = reader.read_u8()?;
+        if byte != 0 {
+            bytes.push(byte);
+            continue;
+        }
+        match reader.read_u8()? {
+            0 => return Ok(bytes),
+            0xFF => bytes.push(0),
+            other => return Err(Error::UnknownBsonElementType(other)),
+        }
+    }
+}
+
 pub fn stacked_key<'a, T: IntoIterator<Item = &'a Bson>>(keys: T) -> Result<Vec<u8>> {
     let mut result = Vec::<u8>::new();
 
@@ -93,13 +128,27 @@ pub fn stacked_key_bytes<W: Write>(writer: &mut W, key: &Bson) -> Result<()> {
         Bson::Decimal128(dcl) => {
             writer.write_u8(ElementType::Decimal128 as u8)?;
 
-            let bytes = dcl.bytes();
-
-            writer.write_all(&bytes)?;
+            Decimal::from_decimal128(dcl).write_key(writer)?;
         }
         Bson::Undefined => {
             writer.write_u8(ElementType::Undefined as u8)?;
         }
+        // UUIDs have a fixed length, so the bytes keep the order
+        Bson::Binary(bin) if bin.subtype == BinarySubtype::Uuid && bin.bytes.len() == 16 => {
+            writer.write_u8(ElementType::Binary as u8)?;
+
+            writer.write_u8(u8::from(bin.subtype))?;
+
+            writer.write_all(&bin.bytes)?;
+        }
+        // the keys of a key ordering are escaped to keep the order of the bytes
+        Bson::Binary(bin) if bin.subtype == BinarySubtype::UserDefined(ORDERED_KEY_SUBTYPE) => {
+            writer.write_u8(ElementType::Binary as u8)?;
+
+            writer.write_u8(ORDERED_KEY_SUBTYPE)?;
+
+            write_escaped_bytes(writer, &bin.bytes)?;
+        }
 
         _ => {
             let val = format!("{:?}", key);
@@ -111,6 +160,16 @@ pub fn stacked_key_bytes<W: Write>(writer: &mut W, key: &Bson) -> Result<()> {
 }
 
 pub fn split_stacked_keys(buffer: &[u8]) -> Result<Vec<Bson>> {
+    split_stacked_keys_impl(buffer, false)
+}
+
+/// Split the keys written before the format version 2,
+/// the Decimal128 values were their 16 bytes, which are not in the order of the values.
+pub(crate) fn split_legacy_stacked_keys(buffer: &[u8]) -> Result<Vec<Bson>> {
+    split_stacked_keys_impl(buffer, true)
+}
+
+fn split_stacked_keys_impl(buffer: &[u8], legacy_decimal: bool) -> Result<Vec<Bson>> {
     let mut result = Vec::<Bson>::new();
     let mut reader = buffer;
 
@@ -160,12 +219,27 @@ pub fn split_stacked_keys(buffer: &[u8]) -> Result<Vec<Bson>> {
             reader.read_until(0, &mut bytes)?;
             bytes.pop();
             result.push(Bson::Symbol(String::from_utf8(bytes)?));
-        } else if ch == ElementType::Decimal128 as u8 {
+        } else if ch == ElementType::Decimal128 as u8 && legacy_decimal {
             let mut bytes = [0u8; 16];
             reader.read_exact(&mut bytes)?;
             result.push(Bson::Decimal128(Decimal128::from_bytes(bytes)));
+        } else if ch == ElementType::Decimal128 as u8 {
+            let decimal = Decimal::read_key(&mut reader)?;
+            result.push(Bson::Decimal128(decimal.to_decimal128()));
         } else if ch == ElementType::Undefined as u8 {
             result.push(Bson::Undefined);
+        } else if ch == ElementType::Binary as u8 {
+            let subtype = BinarySubtype::from(reader.read_u8()?);
+            let bytes = match subtype {
+                BinarySubtype::Uuid => {
+                    let mut bytes = vec![0u8; 16];
+                    reader.read_exact(&mut bytes)?;
+                    bytes
+                }
+                BinarySubtype::UserDefined(ORDERED_KEY_SUBTYPE) => read_escaped_bytes(&mut reader)?,
+                _ => return Err(Error::UnknownBsonElementType(ch)),
+            };
+            result.push(Bson::Binary(Binary { subtype, bytes }));
         } else {
             return Err(Error::UnknownBsonElementType(ch));
         }
@@ -175,6 +249,9 @@ pub fn split_stacked_keys(buffer: &[u8]) -> Result<Vec<Bson>> {
 }
 
 pub fn value_cmp(a: &Bson, b: &Bson) -> BsonResult<Ordering> {
+    if let Some((d1, d2)) = decimal_operands(a, b) {
+        return Ok(d1.cmp(&d2));
+    }
     match (a, b) {
         (Bson::Null, Bson::Null) => Ok(Ordering::Equal),
         (Bson::Undefined, Bson::Undefined) => Ok(Ordering::Equal),
@@ -223,6 +300,21 @@ pub fn value_cmp(a: &Bson, b: &Bson) -> BsonResult<Ordering> {
     }
 }
 
+/// A total order of the values, used to group and sort the keys.
+/// The values [`value_cmp`] can't compare, e.g. two documents,
+/// are compared by their encoded bytes.
+pub fn key_cmp(a: &Bson, b: &Bson) -> Ordering {
+    if let Ok(ordering) = value_cmp(a, b) {
+        return ordering;
+    }
+    let encode = |value: &Bson| {
+        let mut buffer = Vec::new();
+        let _ = bson::doc! { "k": value.clone() }.to_writer(&mut buffer);
+        buffer
+    };
+    encode(a).cmp(&encode(b))
+}
+
 pub fn try_get_document_value(doc: &Document, key: &str) -> Option<Bson> {
     let keys = key.split('.').collect::<Vec<&str>>();
     let keys_slice = keys.as_slice();
@@ -251,16 +343,116 @@ fn try_get_document_by_slices(doc: &Document, keys: &[&str]) -> Option<Bson> {
         .flatten()
 }
 
+/// The value of the field of the dotted key, which may be a document unlike [`try_get_document_value`].
+pub fn get_path_value<'a>(doc: &'a Document, key: &str) -> Option<&'a Bson> {
+    let mut keys = key.split('.');
+    let mut value = doc.get(keys.next()?)?;
+    for key in keys {
+        value = value.as_document()?.get(key)?;
+    }
+    Some(value)
+}
+
+/// The text of a value in the cells of CSV and the string columns of Arrow.
+#[cfg(any(feature = "csv", feature = "arrow"))]
+pub(crate) fn format_value(value: &Bson) -> String {
+    match value {
+        Bson::Null | Bson::Undefined => String::new(),
+        Bson::String(value) => value.clone(),
+        Bson::Boolean(value) => value.to_string(),
+        Bson::Int32(value) => value.to_string(),
+        Bson::Int64(value) => value.to_string(),
+        Bson::Double(value) => value.to_string(),
+        Bson::ObjectId(oid) => oid.to_hex(),
+        Bson::DateTime(date) => date.try_to_rfc3339_string()
+            .unwrap_or_else(|_| date.timestamp_millis().to_string()),
+        value => value.clone().into_relaxed_extjson().to_string(),
+    }
+}
+
 pub fn bson_datetime_now() -> bson::datetime::DateTime {
     return bson::datetime::DateTime::now()
 }
 
+/// Fail with [`Error::DocumentTooLarge`] if the encoded document is larger than `limit`.
+pub fn check_document_size(doc: &Document, limit: u32) -> Result<()> {
+    let size = document_size(doc, limit as usize);
+    if size > limit as usize {
+        return Err(Error::DocumentTooLarge {
+            size: size as u64,
+            limit: limit as u64,
+        });
+    }
+    Ok(())
+}
+
+/// The size of the encoded document, calculated without serializing it.
+/// The calculation stops as soon as the size exceeds `limit`,
+/// in which case a number greater than `limit` is returned.
+pub fn document_size(doc: &Document, limit: usize) -> usize {
+    // the length and the trailing zero
+    let mut size = 5;
+    for (key, value) in doc {
+        size += element_size(key.len(), value, limit.saturating_sub(size));
+        if size > limit {
+            break;
+        }
+    }
+    size
+}
+
+/// The size of the encoded value, without the type and the key.
+pub fn value_size(value: &Bson) -> usize {
+    element_size(0, value, usize::MAX) - 2
+}
+
+fn array_size(arr: &[Bson], limit: usize) -> usize {
+    let mut size = 5;
+    for (index, value) in arr.iter().enumerate() {
+        let key_len = if index == 0 { 1 } else { index.ilog10() as usize + 1 };
+        size += element_size(key_len, value, limit.saturating_sub(size));
+        if size > limit {
+            break;
+        }
+    }
+    size
+}
+
+fn element_size(key_len: usize, value: &Bson, limit: usize) -> usize {
+    // the type and the key terminated by zero
+    let header = 1 + key_len + 1;
+    let string_size = |s: &str| 4 + s.len() + 1;
+    header + match value {
+        Bson::Double(_) | Bson::DateTime(_) | Bson::Timestamp(_) | Bson::Int64(_) => 8,
+        Bson::String(s) | Bson::Symbol(s) | Bson::JavaScriptCode(s) => string_size(s),
+        Bson::Document(doc) => document_size(doc, limit),
+        Bson::Array(arr) => array_size(arr, limit),
+        Bson::Binary(binary) => {
+            // the old binary subtype stores the length twice
+            let extra = if binary.subtype == bson::spec::BinarySubtype::BinaryOld { 4 } else { 0 };
+            4 + 1 + extra + binary.bytes.len()
+        }
+        Bson::ObjectId(_) => 12,
+        Bson::Boolean(_) => 1,
+        Bson::Int32(_) => 4,
+        Bson::Decimal128(_) => 16,
+        Bson::Null | Bson::Undefined | Bson::MaxKey | Bson::MinKey => 0,
+        Bson::RegularExpression(regex) => regex.pattern.len() + 1 + regex.options.len() + 1,
+        Bson::JavaScriptCodeWithScope(code) => 4 + string_size(&code.code) + document_size(&code.scope, limit),
+        // the fields of the db pointers are private
+        Bson::DbPointer(_) => {
+            let wrapper = bson::doc! { "": value.clone() };
+            bson::to_vec(&wrapper).map(|buf| buf.len() - 7).unwrap_or(0)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::cmp::Ordering;
     use bson::{Bson, doc, Timestamp};
     use bson::oid::ObjectId;
-    use crate::utils::bson::{split_stacked_keys, stacked_key, value_cmp};
+    use crate::utils::bson::{document_size, split_stacked_keys, stacked_key, value_cmp};
 
     #[test]
     fn test_value_cmp() {
@@ -281,6 +473,32 @@ mod tests {
         assert_eq!(super::try_get_document_value(&doc!{"a": { "b": 1 }}, "a.c"), None);
         assert_eq!(super::try_get_document_value(&doc!{"a": { "b": { "c": 1 }}}, "a.b.c"), Some(Bson::Int32(1)));
         assert_eq!(super::try_get_document_value(&doc!{"a": { "b": { "c": 1 }}}, "a.b.d"), None);
+        assert_eq!(super::get_path_value(&doc!{"a": { "b": { "c": 1 }}}, "a.b"), Some(&Bson::Document(doc!{ "c": 1 })));
+        assert_eq!(super::get_path_value(&doc!{"a": { "b": 1 }}, "a.b.c"), None);
+    }
+
+    #[test]
+    fn test_document_size() {
+        use bson::{Binary, Regex};
+        use bson::spec::BinarySubtype;
+
+        let doc = doc! {
+            "_id": ObjectId::new(),
+            "name": "Hello",
+            "int": 42,
+            "long": 42_i64,
+            "double": 2.5,
+            "null": Bson::Null,
+            "array": (0..12).map(Bson::Int32).collect::<Vec<_>>(),
+            "nested": { "a": [{ "b": true }], "date": super::bson_datetime_now() },
+            "binary": Binary { subtype: BinarySubtype::Generic, bytes: vec![1, 2, 3] },
+            "old_binary": Binary { subtype: BinarySubtype::BinaryOld, bytes: vec![1, 2, 3] },
+            "regex": Regex { pattern: "^a".to_string(), options: "i".to_string() },
+        };
+        let size = bson::to_vec(&doc).unwrap().len();
+        assert_eq!(document_size(&doc, usize::MAX), size);
+        assert_eq!(document_size(&doc!{}, usize::MAX), 5);
+        assert!(document_size(&doc, 10) > 10);
     }
 
     #[test]
@@ -296,6 +514,7 @@ mod tests {
             Bson::Boolean(true),
             Bson::Timestamp(Timestamp { time: 42, increment: 42 }),
             Bson::DateTime(super::bson_datetime_now()),
+            Bson::from(bson::Uuid::new()),
         ];
         let stacked = stacked_key(&values).unwrap();
         let slices = split_stacked_keys(&stacked).unwrap();
@@ -307,4 +526,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_uuid_stacked_key_order() {
+        let low = Bson::from(bson::Uuid::from_bytes([0u8; 16]));
+        let high = Bson::from(bson::Uuid::from_bytes([0xff; 16]));
+        assert!(stacked_key([&low]).unwrap() < stacked_key([&high]).unwrap());
+        assert_eq!(value_cmp(&low, &high).unwrap(), Ordering::Less);
+
+        let generic = Bson::Binary(bson::Binary {
+            subtype: bson::spec::BinarySubtype::Generic,
+            bytes: vec![1, 2, 3],
+        });
+        assert!(stacked_key([&generic]).is_err());
+    }
+
 }
@@ -0,0 +1,548 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The comparison and the arithmetic of the BSON Decimal128 values.
+//!
+//! A [`Decimal`] is decoded from the IEEE 754-2008 decimal128 bytes (BID encoding)
+//! as `coefficient * 10^exponent`, the coefficient has at most 34 digits.
+//! The results are rounded half to even like the IEEE operations,
+//! so the amounts are never converted to a binary floating point.
+
+use std::cmp::Ordering;
+use std::io::{Read, Write};
+use bson::{Bson, Decimal128};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use crate::errors::CorruptionError;
+use crate::{Error, Result};
+
+const MAX_DIGITS: u32 = 34;
+const MAX_COEFFICIENT: u128 = 9_999_999_999_999_999_999_999_999_999_999_999;
+const EXPONENT_BIAS: i32 = 6176;
+const MIN_EXPONENT: i32 = -6176;
+const MAX_EXPONENT: i32 = 6111;
+
+/// The exponent difference beyond which the smaller operand of an addition
+/// only matters for the rounding.
+const MAX_ALIGN: i32 = 40;
+
+const KEY_NAN: u8 = 0;
+const KEY_NEGATIVE_INFINITY: u8 = 1;
+const KEY_NEGATIVE: u8 = 2;
+const KEY_ZERO: u8 = 3;
+const KEY_POSITIVE: u8 = 4;
+const KEY_POSITIVE_INFINITY: u8 = 5;
+const KEY_EXPONENT_BIAS: i32 = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Decimal {
+    NaN,
+    Infinity { negative: bool },
+    Finite { negative: bool, coefficient: u128, exponent: i32 },
+}
+
+impl Decimal {
+
+    pub(crate) fn from_decimal128(value: &Decimal128) -> Decimal {
+        let bits = u128::from_le_bytes(value.bytes());
+        let negative = bits >> 127 == 1;
+        if (bits >> 123) & 0xf == 0xf {
+            return if (bits >> 122) & 1 == 1 {
+                Decimal::NaN
+            } else {
+                Decimal::Infinity { negative }
+            };
+        }
+
+        let (exponent, coefficient) = if (bits >> 125) & 0b11 == 0b11 {
+            // the coefficient would exceed 34 digits, it's read as zero
+            (((bits >> 111) & 0x3fff) as i32, 0)
+        } else {
+            (((bits >> 113) & 0x3fff) as i32, bits & ((1u128 << 113) - 1))
+        };
+        let coefficient = if coefficient > MAX_COEFFICIENT { 0 } else { coefficient };
+
+        Decimal::Finite {
+            negative,
+            coefficient,
+            exponent: exponent - EXPONENT_BIAS,
+        }
+    }
+
+    pub(crate) fn to_decimal128(self) -> Decimal128 {
+        let bits = match self {
+            Decimal::NaN => 0x1f << 122,
+            Decimal::Infinity { negative } => ((negative as u128) << 127) | (0x1e << 122),
+            Decimal::Finite { negative, coefficient, exponent } => {
+                ((negative as u128) << 127)
+                    | (((exponent + EXPONENT_BIAS) as u128) << 113)
+                    | coefficient
+            }
+        };
+        Decimal128::from_bytes(bits.to_le_bytes())
+    }
+
+    pub(crate) fn from_i64(value: i64) -> Decimal {
+        Decimal::Finite {
+            negative: value < 0,
+            coefficient: value.unsigned_abs() as u128,
+            exponent: 0,
+        }
+    }
+
+    /// Convert a double by its shortest representation, `0.1` is exactly `0.1`.
+    pub(crate) fn from_f64(value: f64) -> Decimal {
+        if value.is_nan() {
+            return Decimal::NaN;
+        }
+        if value.is_infinite() {
+            return Decimal::Infinity { negative: value < 0.0 };
+        }
+
+        let repr = format!("{:e}", value.abs());
+        let parsed = repr.split_once('e').and_then(|(mantissa, exponent)| {
+            let exponent = exponent.parse::<i32>().ok()?;
+            let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+            let coefficient = format!("{}{}", int, frac).parse::<u128>().ok()?;
+            Some((coefficient, exponent - frac.len() as i32))
+        });
+
+        match parsed {
+            Some((coefficient, exponent)) => Decimal::Finite {
+                negative: value.is_sign_negative(),
+                coefficient,
+                exponent,
+            },
+            None => Decimal::NaN,
+        }
+    }
+
+    /// The numeric values, `None` for the other types.
+    pub(crate) fn from_bson(value: &Bson) -> Option<Decimal> {
+        match value {
+            Bson::Decimal128(value) => Some(Decimal::from_decimal128(value)),
+            Bson::Int32(value) => Some(Decimal::from_i64(*value as i64)),
+            Bson::Int64(value) => Some(Decimal::from_i64(*value)),
+            Bson::Double(value) => Some(Decimal::from_f64(*value)),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn add(&self, other: &Decimal) -> Decimal {
+        match (*self, *other) {
+            (Decimal::NaN, _) | (_, Decimal::NaN) => Decimal::NaN,
+            (Decimal::Infinity { negative: n1 }, Decimal::Infinity { negative: n2 }) => {
+                if n1 == n2 { *self } else { Decimal::NaN }
+            }
+            (Decimal::Infinity { .. }, _) => *self,
+            (_, Decimal::Infinity { .. }) => *other,
+            (
+                Decimal::Finite { negative: n1, coefficient: c1, exponent: e1 },
+                Decimal::Finite { negative: n2, coefficient: c2, exponent: e2 },
+            ) => {
+                // the operand with the larger exponent is scaled to the smaller one
+                let ((nh, ch, eh), (nl, mut cl, mut el)) = if e1 >= e2 {
+                    ((n1, c1, e1), (n2, c2, e2))
+                } else {
+                    ((n2, c2, e2), (n1, c1, e1))
+                };
+                if eh - el > MAX_ALIGN {
+                    if ch == 0 {
+                        return *if e1 >= e2 { other } else { self };
+                    }
+                    el = eh - MAX_ALIGN;
+                    cl = (cl != 0) as u128;
+                }
+
+                let mut high = Wide::from_u128(ch);
+                for _ in 0..(eh - el) {
+                    high = high.mul_10();
+                }
+                let low = Wide::from_u128(cl);
+
+                let (negative, value) = if nh == nl {
+                    (nh, high.add(&low))
+                } else if high >= low {
+                    (nh, high.sub(&low))
+                } else {
+                    (nl, low.sub(&high))
+                };
+                let negative = if value.is_zero() { nh && nl } else { negative };
+                round(negative, value, el)
+            }
+        }
+    }
+
+    pub(crate) fn mul(&self, other: &Decimal) -> Decimal {
+        match (*self, *other) {
+            (Decimal::NaN, _) | (_, Decimal::NaN) => Decimal::NaN,
+            (Decimal::Infinity { negative: n1 }, Decimal::Infinity { negative: n2 }) => {
+                Decimal::Infinity { negative: n1 != n2 }
+            }
+            (Decimal::Infinity { negative: n1 }, Decimal::Finite { negative: n2, coefficient, .. })
+            | (Decimal::Finite { negative: n2, coefficient, .. }, Decimal::Infinity { negative: n1 }) => {
+                if coefficient == 0 {
+                    Decimal::NaN
+                } else {
+                    Decimal::Infinity { negative: n1 != n2 }
+                }
+            }
+            (
+                Decimal::Finite { negative: n1, coefficient: c1, exponent: e1 },
+                Decimal::Finite { negative: n2, coefficient: c2, exponent: e2 },
+            ) => round(n1 != n2, Wide::mul(c1, c2), e1 + e2),
+        }
+    }
+
+    /// The total order of the values, NaN is the smallest and equal to itself.
+    pub(crate) fn cmp(&self, other: &Decimal) -> Ordering {
+        match (self, other) {
+            (
+                Decimal::Finite { negative: n1, coefficient: c1, exponent: e1 },
+                Decimal::Finite { negative: n2, coefficient: c2, exponent: e2 },
+            ) => {
+                let s1 = signum(*n1, *c1);
+                let s2 = signum(*n2, *c2);
+                if s1 != s2 || s1 == 0 {
+                    return s1.cmp(&s2);
+                }
+                let (a1, m1) = normalize(*c1, *e1);
+                let (a2, m2) = normalize(*c2, *e2);
+                let ord = a1.cmp(&a2).then(m1.cmp(&m2));
+                if *n1 { ord.reverse() } else { ord }
+            }
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            Decimal::NaN => 0,
+            Decimal::Infinity { negative: true } => 1,
+            Decimal::Finite { .. } => 2,
+            Decimal::Infinity { negative: false } => 3,
+        }
+    }
+
+    /// Write the order-preserving key of the value,
+    /// the equal values such as `1.0` and `1.00` have the same key.
+    pub(crate) fn write_key<W: Write>(&self, writer: &mut W) -> Result<()> {
+        match *self {
+            Decimal::NaN => writer.write_u8(KEY_NAN)?,
+            Decimal::Infinity { negative: true } => writer.write_u8(KEY_NEGATIVE_INFINITY)?,
+            Decimal::Infinity { negative: false } => writer.write_u8(KEY_POSITIVE_INFINITY)?,
+            Decimal::Finite { coefficient: 0, .. } => writer.write_u8(KEY_ZERO)?,
+            Decimal::Finite { negative, coefficient, exponent } => {
+                let (adjusted, mantissa) = normalize(coefficient, exponent);
+                let adjusted = (adjusted + KEY_EXPONENT_BIAS) as u16;
+                if negative {
+                    writer.write_u8(KEY_NEGATIVE)?;
+                    writer.write_u16::<BigEndian>(!adjusted)?;
+                    writer.write_u128::<BigEndian>(!mantissa)?;
+                } else {
+                    writer.write_u8(KEY_POSITIVE)?;
+                    writer.write_u16::<BigEndian>(adjusted)?;
+                    writer.write_u128::<BigEndian>(mantissa)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read_key<R: Read>(reader: &mut R) -> Result<Decimal> {
+        let class = reader.read_u8()?;
+        let decimal = match class {
+            KEY_NAN => Decimal::NaN,
+            KEY_NEGATIVE_INFINITY => Decimal::Infinity { negative: true },
+            KEY_POSITIVE_INFINITY => Decimal::Infinity { negative: false },
+            KEY_ZERO => Decimal::Finite { negative: false, coefficient: 0, exponent: 0 },
+            KEY_NEGATIVE | KEY_POSITIVE => {
+                let negative = class == KEY_NEGATIVE;
+                let mut adjusted = reader.read_u16::<BigEndian>()?;
+                let mut mantissa = reader.read_u128::<BigEndian>()?;
+                if negative {
+                    adjusted = !adjusted;
+                    mantissa = !mantissa;
+                }
+                // the zeros have their own class, and the mantissa has 34 digits
+                if mantissa == 0 || mantissa > MAX_COEFFICIENT {
+                    return Err(invalid_key());
+                }
+
+                let mut coefficient = mantissa;
+                while coefficient % 10 == 0 {
+                    coefficient /= 10;
+                }
+                let adjusted = adjusted as i32 - KEY_EXPONENT_BIAS;
+                Decimal::Finite {
+                    negative,
+                    coefficient,
+                    exponent: adjusted - digits(coefficient) as i32 + 1,
+                }
+            }
+            _ => return Err(invalid_key()),
+        };
+        Ok(decimal)
+    }
+
+}
+
+/// The decimals of `a` and `b` if one of them is a Decimal128 and both are numeric.
+pub(crate) fn decimal_operands(a: &Bson, b: &Bson) -> Option<(Decimal, Decimal)> {
+    if !matches!(a, Bson::Decimal128(_)) && !matches!(b, Bson::Decimal128(_)) {
+        return None;
+    }
+    Some((Decimal::from_bson(a)?, Decimal::from_bson(b)?))
+}
+
+fn invalid_key() -> Error {
+    Error::Corruption(Box::new(CorruptionError {
+        message: "invalid Decimal128 key".to_string(),
+        file: None,
+        offset: None,
+    }))
+}
+
+fn signum(negative: bool, coefficient: u128) -> i8 {
+    match (coefficient, negative) {
+        (0, _) => 0,
+        (_, true) => -1,
+        (_, false) => 1,
+    }
+}
+
+fn digits(coefficient: u128) -> u32 {
+    coefficient.checked_ilog10().map_or(1, |log| log + 1)
+}
+
+/// The adjusted exponent and the coefficient scaled to 34 digits of a non-zero value.
+fn normalize(coefficient: u128, exponent: i32) -> (i32, u128) {
+    let digits = digits(coefficient);
+    let adjusted = exponent + digits as i32 - 1;
+    (adjusted, coefficient * 10u128.pow(MAX_DIGITS - digits))
+}
+
+/// Round `value * 10^exponent` to 34 digits and the range of the exponent.
+fn round(negative: bool, mut value: Wide, mut exponent: i32) -> Decimal {
+    let mut last = 0u8;
+    let mut sticky = false;
+    let mut coefficient = loop {
+        match value.to_u128() {
+            Some(coefficient) if coefficient <= MAX_COEFFICIENT && exponent >= MIN_EXPONENT => {
+                break coefficient;
+            }
+            _ => {
+                sticky |= last != 0;
+                last = value.div_rem_10();
+                exponent += 1;
+            }
+        }
+    };
+
+    if last > 5 || (last == 5 && (sticky || coefficient % 2 == 1)) {
+        coefficient += 1;
+        if coefficient > MAX_COEFFICIENT {
+            coefficient /= 10;
+            exponent += 1;
+        }
+    }
+
+    while exponent > MAX_EXPONENT && coefficient != 0 && coefficient * 10 <= MAX_COEFFICIENT {
+        coefficient *= 10;
+        exponent -= 1;
+    }
+    if exponent > MAX_EXPONENT {
+        if coefficient != 0 {
+            return Decimal::Infinity { negative };
+        }
+        exponent = MAX_EXPONENT;
+    }
+
+    Decimal::Finite { negative, coefficient, exponent }
+}
+
+/// An unsigned integer of 256 bits, enough for the product of two coefficients.
+/// The limbs are big endian.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Wide([u64; 4]);
+
+impl Wide {
+
+    fn from_u128(value: u128) -> Wide {
+        Wide([0, 0, (value >> 64) as u64, value as u64])
+    }
+
+    fn to_u128(self) -> Option<u128> {
+        if self.0[0] != 0 || self.0[1] != 0 {
+            return None;
+        }
+        Some(((self.0[2] as u128) << 64) | self.0[3] as u128)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0.iter().all(|limb| *limb == 0)
+    }
+
+    fn mul(a: u128, b: u128) -> Wide {
+        let a = [a as u64, (a >> 64) as u64];
+        let b = [b as u64, (b >> 64) as u64];
+        let mut result = [0u64; 4];
+        for i in 0..2 {
+            let mut carry = 0u128;
+            for j in 0..2 {
+                let t = a[i] as u128 * b[j] as u128 + result[i + j] as u128 + carry;
+                result[i + j] = t as u64;
+                carry = t >> 64;
+            }
+            result[i + 2] = carry as u64;
+        }
+        result.reverse();
+        Wide(result)
+    }
+
+    fn mul_10(self) -> Wide {
+        let mut result = self;
+        let mut carry = 0u128;
+        for limb in result.0.iter_mut().rev() {
+            let t = *limb as u128 * 10 + carry;
+            *limb = t as u64;
+            carry = t >> 64;
+        }
+        result
+    }
+
+    fn add(&self, other: &Wide) -> Wide {
+        let mut result = [0u64; 4];
+        let mut carry = false;
+        for i in (0..4).rev() {
+            let (sum, c1) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            result[i] = sum;
+            carry = c1 || c2;
+        }
+        Wide(result)
+    }
+
+    /// `self - other`, `self` must not be smaller.
+    fn sub(&self, other: &Wide) -> Wide {
+        let mut result = [0u64; 4];
+        let mut borrow = false;
+        for i in (0..4).rev() {
+            let (diff, b1) = self.0[i].overflowing_sub(other.0[i]);
+            let (diff, b2) = diff.overflowing_sub(borrow as u64);
+            result[i] = diff;
+            borrow = b1 || b2;
+        }
+        Wide(result)
+    }
+
+    /// Divide by 10 and return the remainder.
+    fn div_rem_10(&mut self) -> u8 {
+        let mut rem = 0u128;
+        for limb in self.0.iter_mut() {
+            let cur = (rem << 64) | *limb as u128;
+            *limb = (cur / 10) as u64;
+            rem = cur % 10;
+        }
+        rem as u8
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use bson::Decimal128;
+    use crate::Error;
+    use super::{Decimal, KEY_POSITIVE};
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_decimal128(&s.parse::<Decimal128>().unwrap())
+    }
+
+    fn text(d: Decimal) -> String {
+        d.to_decimal128().to_string()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for s in ["0", "-0", "1.10", "-12345.6789", "1E+100", "1E-100", "NaN", "Infinity", "-Infinity",
+            "9999999999999999999999999999999999"] {
+            assert_eq!(text(dec(s)), s);
+        }
+    }
+
+    #[test]
+    fn test_add() {
+        assert_eq!(text(dec("1.10").add(&dec("2.205"))), "3.305");
+        assert_eq!(text(dec("0.10").add(&dec("-0.10"))), "0.00");
+        assert_eq!(text(dec("100").add(&Decimal::from_i64(-250))), "-150");
+        assert_eq!(text(dec("0.1").add(&Decimal::from_f64(0.2))), "0.3");
+        assert_eq!(text(dec("9999999999999999999999999999999999").add(&dec("1"))), "1.000000000000000000000000000000000E+34");
+        assert_eq!(text(dec("1E+50").add(&dec("1E-50"))), "1.000000000000000000000000000000000E+50");
+        assert_eq!(text(dec("Infinity").add(&dec("-Infinity"))), "NaN");
+    }
+
+    #[test]
+    fn test_mul() {
+        assert_eq!(text(dec("1.10").mul(&Decimal::from_i64(3))), "3.30");
+        assert_eq!(text(dec("-2.5").mul(&dec("0.2"))), "-0.50");
+        assert_eq!(
+            text(dec("1234567890123456789012345678901234").mul(&dec("1234567890123456789012345678901234"))),
+            "1.524157875323883675049535156256667E+66",
+        );
+        assert_eq!(text(dec("Infinity").mul(&dec("0"))), "NaN");
+    }
+
+    #[test]
+    fn test_cmp() {
+        assert_eq!(dec("1.0").cmp(&dec("1.00")), Ordering::Equal);
+        assert_eq!(dec("0.1").cmp(&Decimal::from_f64(0.1)), Ordering::Equal);
+        assert_eq!(dec("-2").cmp(&dec("-10")), Ordering::Greater);
+        assert_eq!(dec("0.99").cmp(&Decimal::from_i64(1)), Ordering::Less);
+        assert_eq!(dec("NaN").cmp(&dec("-Infinity")), Ordering::Less);
+        assert_eq!(dec("-0").cmp(&dec("0E+10")), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_key_order() {
+        let values = ["-Infinity", "-1E+10", "-2.5", "-0.001", "0", "0.001", "1", "1.5", "10", "1E+10", "Infinity"];
+        let keys: Vec<Vec<u8>> = values.iter().map(|s| {
+            let mut key = Vec::new();
+            dec(s).write_key(&mut key).unwrap();
+            key
+        }).collect();
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+
+        for (key, s) in keys.iter().zip(values.iter()) {
+            let decoded = Decimal::read_key(&mut key.as_slice()).unwrap();
+            assert_eq!(decoded.cmp(&dec(s)), Ordering::Equal);
+        }
+
+        // a zero mantissa is only written by the zero class
+        let mut corrupted = vec![KEY_POSITIVE];
+        corrupted.extend_from_slice(&[0; 18]);
+        assert!(matches!(Decimal::read_key(&mut corrupted.as_slice()), Err(Error::Corruption(_))));
+        assert!(matches!(Decimal::read_key(&mut [9u8].as_slice()), Err(Error::Corruption(_))));
+
+        let mut a = Vec::new();
+        dec("1.50").write_key(&mut a).unwrap();
+        let mut b = Vec::new();
+        dec("1.5").write_key(&mut b).unwrap();
+        assert_eq!(a, b);
+    }
+
+}
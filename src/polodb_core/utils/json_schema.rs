@@ -0,0 +1,632 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The subset of JSON Schema supported by `$jsonSchema`, the same as MongoDB:
+//! the keywords of draft 4 plus `bsonType`, without `$ref`, `default`,
+//! `definitions`, `dependencies` and `format`.
+//!
+//! The keywords which test a type are ignored by the values of the other types,
+//! e.g. `minLength` accepts a number. Use `bsonType` or `type` to restrict the type.
+
+use std::cmp::Ordering;
+use bson::{Bson, Document};
+use regex::Regex;
+use crate::{Error, Result};
+use crate::utils::bson::value_cmp;
+
+pub(crate) struct JsonSchema {
+    bson_types: Option<Vec<String>>,
+    json_types: Option<Vec<String>>,
+    required: Vec<String>,
+    properties: Vec<(String, JsonSchema)>,
+    pattern_properties: Vec<(Regex, JsonSchema)>,
+    additional_properties: Option<Additional>,
+    min_properties: Option<u64>,
+    max_properties: Option<u64>,
+    enum_values: Option<Vec<Bson>>,
+    minimum: Option<(f64, bool)>,
+    maximum: Option<(f64, bool)>,
+    multiple_of: Option<f64>,
+    min_length: Option<u64>,
+    max_length: Option<u64>,
+    pattern: Option<Regex>,
+    items: Option<Items>,
+    additional_items: Option<Additional>,
+    min_items: Option<u64>,
+    max_items: Option<u64>,
+    unique_items: bool,
+    all_of: Vec<JsonSchema>,
+    any_of: Vec<JsonSchema>,
+    one_of: Vec<JsonSchema>,
+    not: Option<Box<JsonSchema>>,
+}
+
+enum Additional {
+    Allowed(bool),
+    Schema(Box<JsonSchema>),
+}
+
+enum Items {
+    Each(Box<JsonSchema>),
+    Tuple(Vec<JsonSchema>),
+}
+
+const BSON_TYPES: &[&str] = &[
+    "double", "string", "object", "array", "binData", "undefined", "objectId", "bool",
+    "date", "null", "regex", "dbPointer", "javascript", "symbol", "javascriptWithScope",
+    "int", "timestamp", "long", "decimal", "minKey", "maxKey", "number",
+];
+
+const JSON_TYPES: &[&str] = &["object", "array", "number", "boolean", "string", "null"];
+
+fn invalid(message: String) -> Error {
+    Error::ValidationError(format!("invalid $jsonSchema: {}", message))
+}
+
+/// The name of the type used by `bsonType`.
+fn bson_type_name(value: &Bson) -> &'static str {
+    match value {
+        Bson::Double(_) => "double",
+        Bson::String(_) => "string",
+        Bson::Document(_) => "object",
+        Bson::Array(_) => "array",
+        Bson::Binary(_) => "binData",
+        Bson::Undefined => "undefined",
+        Bson::ObjectId(_) => "objectId",
+        Bson::Boolean(_) => "bool",
+        Bson::DateTime(_) => "date",
+        Bson::Null => "null",
+        Bson::RegularExpression(_) => "regex",
+        Bson::DbPointer(_) => "dbPointer",
+        Bson::JavaScriptCode(_) => "javascript",
+        Bson::Symbol(_) => "symbol",
+        Bson::JavaScriptCodeWithScope(_) => "javascriptWithScope",
+        Bson::Int32(_) => "int",
+        Bson::Timestamp(_) => "timestamp",
+        Bson::Int64(_) => "long",
+        Bson::Decimal128(_) => "decimal",
+        Bson::MinKey => "minKey",
+        Bson::MaxKey => "maxKey",
+    }
+}
+
+fn is_numeric(value: &Bson) -> bool {
+    matches!(value, Bson::Double(_) | Bson::Int32(_) | Bson::Int64(_) | Bson::Decimal128(_))
+}
+
+fn as_f64(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Double(v) => Some(*v),
+        Bson::Int32(v) => Some(*v as f64),
+        Bson::Int64(v) => Some(*v as f64),
+        _ => None,
+    }
+}
+
+fn matches_bson_type(value: &Bson, ty: &str) -> bool {
+    if ty == "number" {
+        return is_numeric(value);
+    }
+    bson_type_name(value) == ty
+}
+
+fn matches_json_type(value: &Bson, ty: &str) -> bool {
+    match ty {
+        "object" => matches!(value, Bson::Document(_)),
+        "array" => matches!(value, Bson::Array(_)),
+        "number" => is_numeric(value),
+        "boolean" => matches!(value, Bson::Boolean(_)),
+        "string" => matches!(value, Bson::String(_)),
+        "null" => matches!(value, Bson::Null),
+        _ => false,
+    }
+}
+
+fn bson_eq(a: &Bson, b: &Bson) -> bool {
+    match value_cmp(a, b) {
+        Ok(ordering) => ordering == Ordering::Equal,
+        Err(_) => a == b,
+    }
+}
+
+fn display_path(path: &str) -> String {
+    if path.is_empty() {
+        "the document".to_string()
+    } else {
+        format!("field '{}'", path)
+    }
+}
+
+fn child_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn parse_types(keyword: &str, value: &Bson, known: &[&str]) -> Result<Vec<String>> {
+    let names: Vec<&Bson> = match value {
+        Bson::Array(arr) => arr.iter().collect(),
+        other => vec![other],
+    };
+    let mut result = Vec::with_capacity(names.len());
+    for name in names {
+        let name = name.as_str()
+            .ok_or_else(|| invalid(format!("{} must be a string or an array of strings", keyword)))?;
+        if !known.contains(&name) {
+            return Err(invalid(format!("unknown {} '{}'", keyword, name)));
+        }
+        result.push(name.to_string());
+    }
+    Ok(result)
+}
+
+fn parse_count(keyword: &str, value: &Bson) -> Result<u64> {
+    let count = match value {
+        Bson::Int32(v) => *v as i64,
+        Bson::Int64(v) => *v,
+        Bson::Double(v) if v.fract() == 0.0 => *v as i64,
+        _ => return Err(invalid(format!("{} must be an integer", keyword))),
+    };
+    if count < 0 {
+        return Err(invalid(format!("{} must be non-negative", keyword)));
+    }
+    Ok(count as u64)
+}
+
+fn parse_number(keyword: &str, value: &Bson) -> Result<f64> {
+    as_f64(value).ok_or_else(|| invalid(format!("{} must be a number", keyword)))
+}
+
+fn parse_bool(keyword: &str, value: &Bson) -> Result<bool> {
+    value.as_bool().ok_or_else(|| invalid(format!("{} must be a boolean", keyword)))
+}
+
+fn parse_regex(keyword: &str, value: &Bson) -> Result<Regex> {
+    let pattern = value.as_str()
+        .ok_or_else(|| invalid(format!("{} must be a string", keyword)))?;
+    Regex::new(pattern).map_err(|err| invalid(format!("{} '{}': {}", keyword, pattern, err)))
+}
+
+fn parse_schema(keyword: &str, value: &Bson) -> Result<JsonSchema> {
+    let doc = value.as_document()
+        .ok_or_else(|| invalid(format!("{} must be an object", keyword)))?;
+    JsonSchema::parse(doc)
+}
+
+fn parse_schemas(keyword: &str, value: &Bson) -> Result<Vec<JsonSchema>> {
+    let arr = match value {
+        Bson::Array(arr) if !arr.is_empty() => arr,
+        _ => return Err(invalid(format!("{} must be a non-empty array", keyword))),
+    };
+    arr.iter().map(|item| parse_schema(keyword, item)).collect()
+}
+
+fn parse_additional(keyword: &str, value: &Bson) -> Result<Additional> {
+    match value {
+        Bson::Boolean(allowed) => Ok(Additional::Allowed(*allowed)),
+        Bson::Document(_) => Ok(Additional::Schema(Box::new(parse_schema(keyword, value)?))),
+        _ => Err(invalid(format!("{} must be a boolean or an object", keyword))),
+    }
+}
+
+impl JsonSchema {
+
+    pub(crate) fn parse(doc: &Document) -> Result<JsonSchema> {
+        let mut schema = JsonSchema {
+            bson_types: None,
+            json_types: None,
+            required: Vec::new(),
+            properties: Vec::new(),
+            pattern_properties: Vec::new(),
+            additional_properties: None,
+            min_properties: None,
+            max_properties: None,
+            enum_values: None,
+            minimum: None,
+            maximum: None,
+            multiple_of: None,
+            min_length: None,
+            max_length: None,
+            pattern: None,
+            items: None,
+            additional_items: None,
+            min_items: None,
+            max_items: None,
+            unique_items: false,
+            all_of: Vec::new(),
+            any_of: Vec::new(),
+            one_of: Vec::new(),
+            not: None,
+        };
+        let mut exclusive_minimum = false;
+        let mut exclusive_maximum = false;
+
+        for (key, value) in doc {
+            match key.as_str() {
+                "bsonType" => schema.bson_types = Some(parse_types(key, value, BSON_TYPES)?),
+                "type" => schema.json_types = Some(parse_types(key, value, JSON_TYPES)?),
+                "required" => {
+                    let arr = value.as_array()
+                        .ok_or_else(|| invalid("required must be an array of strings".to_string()))?;
+                    for item in arr {
+                        let name = item.as_str()
+                            .ok_or_else(|| invalid("required must be an array of strings".to_string()))?;
+                        schema.required.push(name.to_string());
+                    }
+                }
+                "properties" => {
+                    let props = value.as_document()
+                        .ok_or_else(|| invalid("properties must be an object".to_string()))?;
+                    for (name, prop) in props {
+                        schema.properties.push((name.clone(), parse_schema(name, prop)?));
+                    }
+                }
+                "patternProperties" => {
+                    let props = value.as_document()
+                        .ok_or_else(|| invalid("patternProperties must be an object".to_string()))?;
+                    for (pattern, prop) in props {
+                        let re = parse_regex(key, &Bson::String(pattern.clone()))?;
+                        schema.pattern_properties.push((re, parse_schema(pattern, prop)?));
+                    }
+                }
+                "additionalProperties" => schema.additional_properties = Some(parse_additional(key, value)?),
+                "minProperties" => schema.min_properties = Some(parse_count(key, value)?),
+                "maxProperties" => schema.max_properties = Some(parse_count(key, value)?),
+                "enum" => {
+                    match value {
+                        Bson::Array(arr) if !arr.is_empty() => schema.enum_values = Some(arr.clone()),
+                        _ => return Err(invalid("enum must be a non-empty array".to_string())),
+                    }
+                }
+                "minimum" => schema.minimum = Some((parse_number(key, value)?, false)),
+                "maximum" => schema.maximum = Some((parse_number(key, value)?, false)),
+                "exclusiveMinimum" => exclusive_minimum = parse_bool(key, value)?,
+                "exclusiveMaximum" => exclusive_maximum = parse_bool(key, value)?,
+                "multipleOf" => {
+                    let divisor = parse_number(key, value)?;
+                    if divisor <= 0.0 {
+                        return Err(invalid("multipleOf must be positive".to_string()));
+                    }
+                    schema.multiple_of = Some(divisor);
+                }
+                "minLength" => schema.min_length = Some(parse_count(key, value)?),
+                "maxLength" => schema.max_length = Some(parse_count(key, value)?),
+                "pattern" => schema.pattern = Some(parse_regex(key, value)?),
+                "items" => {
+                    schema.items = Some(match value {
+                        Bson::Array(_) => Items::Tuple(parse_schemas(key, value)?),
+                        _ => Items::Each(Box::new(parse_schema(key, value)?)),
+                    });
+                }
+                "additionalItems" => schema.additional_items = Some(parse_additional(key, value)?),
+                "minItems" => schema.min_items = Some(parse_count(key, value)?),
+                "maxItems" => schema.max_items = Some(parse_count(key, value)?),
+                "uniqueItems" => schema.unique_items = parse_bool(key, value)?,
+                "allOf" => schema.all_of = parse_schemas(key, value)?,
+                "anyOf" => schema.any_of = parse_schemas(key, value)?,
+                "oneOf" => schema.one_of = parse_schemas(key, value)?,
+                "not" => schema.not = Some(Box::new(parse_schema(key, value)?)),
+                "title" | "description" => (),
+                _ => return Err(invalid(format!("unsupported keyword '{}'", key))),
+            }
+        }
+
+        if schema.bson_types.is_some() && schema.json_types.is_some() {
+            return Err(invalid("bsonType and type can't be used together".to_string()));
+        }
+        if exclusive_minimum {
+            let minimum = schema.minimum.as_mut()
+                .ok_or_else(|| invalid("exclusiveMinimum requires minimum".to_string()))?;
+            minimum.1 = true;
+        }
+        if exclusive_maximum {
+            let maximum = schema.maximum.as_mut()
+                .ok_or_else(|| invalid("exclusiveMaximum requires maximum".to_string()))?;
+            maximum.1 = true;
+        }
+
+        Ok(schema)
+    }
+
+    /// The types allowed by `bsonType` or `type`, `None` if any type is allowed.
+    #[cfg(feature = "graphql")]
+    pub(crate) fn types(&self) -> Option<&[String]> {
+        self.bson_types.as_deref().or(self.json_types.as_deref())
+    }
+
+    #[cfg(feature = "graphql")]
+    pub(crate) fn properties(&self) -> &[(String, JsonSchema)] {
+        &self.properties
+    }
+
+    #[cfg(feature = "graphql")]
+    pub(crate) fn is_required(&self, name: &str) -> bool {
+        self.required.iter().any(|required| required == name)
+    }
+
+    /// The schema of the items when all the items of the array have the same schema.
+    #[cfg(feature = "graphql")]
+    pub(crate) fn items(&self) -> Option<&JsonSchema> {
+        match &self.items {
+            Some(Items::Each(schema)) => Some(schema),
+            _ => None,
+        }
+    }
+
+    /// Return the reason if the document doesn't match.
+    pub(crate) fn validate_document(&self, doc: &Document) -> std::result::Result<(), String> {
+        self.validate_document_at(doc, "")
+    }
+
+    fn validate(&self, value: &Bson, path: &str) -> std::result::Result<(), String> {
+        if let Some(types) = &self.bson_types {
+            if !types.iter().any(|ty| matches_bson_type(value, ty)) {
+                return Err(format!(
+                    "{} must have bsonType {}, found {}",
+                    display_path(path), types.join(" or "), bson_type_name(value),
+                ));
+            }
+        }
+        if let Some(types) = &self.json_types {
+            if !types.iter().any(|ty| matches_json_type(value, ty)) {
+                return Err(format!(
+                    "{} must have type {}, found {}",
+                    display_path(path), types.join(" or "), bson_type_name(value),
+                ));
+            }
+        }
+        if let Some(values) = &self.enum_values {
+            if !values.iter().any(|item| bson_eq(item, value)) {
+                return Err(format!("{} must be one of the enum values", display_path(path)));
+            }
+        }
+
+        match value {
+            Bson::Document(doc) => self.validate_document_at(doc, path)?,
+            Bson::Array(arr) => self.validate_array(arr, path)?,
+            Bson::String(s) => self.validate_string(s, path)?,
+            _ => {
+                if let Some(number) = as_f64(value) {
+                    self.validate_number(number, path)?;
+                }
+            }
+        }
+
+        for schema in &self.all_of {
+            schema.validate(value, path)?;
+        }
+        if !self.any_of.is_empty() && !self.any_of.iter().any(|schema| schema.validate(value, path).is_ok()) {
+            return Err(format!("{} must match a schema of anyOf", display_path(path)));
+        }
+        if !self.one_of.is_empty() {
+            let matched = self.one_of.iter()
+                .filter(|schema| schema.validate(value, path).is_ok())
+                .count();
+            if matched != 1 {
+                return Err(format!("{} must match exactly one schema of oneOf, matched {}", display_path(path), matched));
+            }
+        }
+        if let Some(schema) = &self.not {
+            if schema.validate(value, path).is_ok() {
+                return Err(format!("{} must not match the schema of not", display_path(path)));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_document_at(&self, doc: &Document, path: &str) -> std::result::Result<(), String> {
+        for name in &self.required {
+            if !doc.contains_key(name) {
+                return Err(format!("{} is required", display_path(&child_path(path, name))));
+            }
+        }
+        if let Some(min) = self.min_properties {
+            if (doc.len() as u64) < min {
+                return Err(format!("{} must have at least {} properties", display_path(path), min));
+            }
+        }
+        if let Some(max) = self.max_properties {
+            if doc.len() as u64 > max {
+                return Err(format!("{} must have at most {} properties", display_path(path), max));
+            }
+        }
+
+        for (key, value) in doc {
+            let mut matched = false;
+            let key_path = child_path(path, key);
+            if let Some((_, schema)) = self.properties.iter().find(|(name, _)| name == key) {
+                matched = true;
+                schema.validate(value, &key_path)?;
+            }
+            for (re, schema) in &self.pattern_properties {
+                if re.is_match(key) {
+                    matched = true;
+                    schema.validate(value, &key_path)?;
+                }
+            }
+            if matched {
+                continue;
+            }
+            match &self.additional_properties {
+                Some(Additional::Allowed(false)) => {
+                    return Err(format!("{} is not allowed by additionalProperties", display_path(&key_path)));
+                }
+                Some(Additional::Schema(schema)) => schema.validate(value, &key_path)?,
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_array(&self, arr: &[Bson], path: &str) -> std::result::Result<(), String> {
+        if let Some(min) = self.min_items {
+            if (arr.len() as u64) < min {
+                return Err(format!("{} must have at least {} items", display_path(path), min));
+            }
+        }
+        if let Some(max) = self.max_items {
+            if arr.len() as u64 > max {
+                return Err(format!("{} must have at most {} items", display_path(path), max));
+            }
+        }
+        if self.unique_items {
+            for (i, item) in arr.iter().enumerate() {
+                if arr[..i].iter().any(|prev| bson_eq(prev, item)) {
+                    return Err(format!("{} must have unique items", display_path(path)));
+                }
+            }
+        }
+
+        match &self.items {
+            Some(Items::Each(schema)) => {
+                for (i, item) in arr.iter().enumerate() {
+                    schema.validate(item, &child_path(path, &i.to_string()))?;
+                }
+            }
+            Some(Items::Tuple(schemas)) => {
+                for (i, item) in arr.iter().enumerate() {
+                    let item_path = child_path(path, &i.to_string());
+                    match schemas.get(i) {
+                        Some(schema) => schema.validate(item, &item_path)?,
+                        None => match &self.additional_items {
+                            Some(Additional::Allowed(false)) => {
+                                return Err(format!("{} is not allowed by additionalItems", display_path(&item_path)));
+                            }
+                            Some(Additional::Schema(schema)) => schema.validate(item, &item_path)?,
+                            _ => (),
+                        },
+                    }
+                }
+            }
+            None => (),
+        }
+
+        Ok(())
+    }
+
+    fn validate_string(&self, s: &str, path: &str) -> std::result::Result<(), String> {
+        let len = s.chars().count() as u64;
+        if let Some(min) = self.min_length {
+            if len < min {
+                return Err(format!("{} must be at least {} characters long", display_path(path), min));
+            }
+        }
+        if let Some(max) = self.max_length {
+            if len > max {
+                return Err(format!("{} must be at most {} characters long", display_path(path), max));
+            }
+        }
+        if let Some(re) = &self.pattern {
+            if !re.is_match(s) {
+                return Err(format!("{} must match the pattern '{}'", display_path(path), re.as_str()));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_number(&self, number: f64, path: &str) -> std::result::Result<(), String> {
+        if let Some((min, exclusive)) = self.minimum {
+            if number < min || (exclusive && number == min) {
+                let op = if exclusive { "greater than" } else { "at least" };
+                return Err(format!("{} must be {} {}", display_path(path), op, min));
+            }
+        }
+        if let Some((max, exclusive)) = self.maximum {
+            if number > max || (exclusive && number == max) {
+                let op = if exclusive { "less than" } else { "at most" };
+                return Err(format!("{} must be {} {}", display_path(path), op, max));
+            }
+        }
+        if let Some(divisor) = self.multiple_of {
+            if (number / divisor).fract() != 0.0 {
+                return Err(format!("{} must be a multiple of {}", display_path(path), divisor));
+            }
+        }
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::doc;
+    use super::JsonSchema;
+
+    #[test]
+    fn test_validate_document() {
+        let schema = JsonSchema::parse(&doc! {
+            "bsonType": "object",
+            "required": ["name", "age"],
+            "properties": {
+                "name": { "bsonType": "string", "minLength": 1 },
+                "age": { "bsonType": "int", "minimum": 0, "maximum": 150 },
+                "tags": {
+                    "bsonType": "array",
+                    "items": { "enum": ["a", "b"] },
+                    "uniqueItems": true,
+                },
+                "address": {
+                    "bsonType": "object",
+                    "properties": { "zip": { "bsonType": "string", "pattern": "^[0-9]{5}$" } },
+                    "additionalProperties": false,
+                },
+            },
+        }).unwrap();
+
+        assert!(schema.validate_document(&doc! { "name": "Vincent", "age": 30, "tags": ["a", "b"] }).is_ok());
+        assert_eq!(
+            schema.validate_document(&doc! { "name": "Vincent" }).unwrap_err(),
+            "field 'age' is required",
+        );
+        assert_eq!(
+            schema.validate_document(&doc! { "name": "Vincent", "age": "30" }).unwrap_err(),
+            "field 'age' must have bsonType int, found string",
+        );
+        assert_eq!(
+            schema.validate_document(&doc! { "name": "Vincent", "age": 200 }).unwrap_err(),
+            "field 'age' must be at most 150",
+        );
+        assert_eq!(
+            schema.validate_document(&doc! { "name": "Vincent", "age": 30, "tags": ["a", "a"] }).unwrap_err(),
+            "field 'tags' must have unique items",
+        );
+        assert_eq!(
+            schema.validate_document(&doc! { "name": "Vincent", "age": 30, "tags": ["c"] }).unwrap_err(),
+            "field 'tags.0' must be one of the enum values",
+        );
+        assert_eq!(
+            schema.validate_document(&doc! { "name": "Vincent", "age": 30, "address": { "zip": "1234" } }).unwrap_err(),
+            "field 'address.zip' must match the pattern '^[0-9]{5}$'",
+        );
+        assert_eq!(
+            schema.validate_document(&doc! { "name": "Vincent", "age": 30, "address": { "city": "x" } }).unwrap_err(),
+            "field 'address.city' is not allowed by additionalProperties",
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(JsonSchema::parse(&doc! { "bsonType": "integer" }).is_err());
+        assert!(JsonSchema::parse(&doc! { "bsonType": "int", "type": "number" }).is_err());
+        assert!(JsonSchema::parse(&doc! { "exclusiveMinimum": true }).is_err());
+        assert!(JsonSchema::parse(&doc! { "$ref": "#/definitions/a" }).is_err());
+        assert!(JsonSchema::parse(&doc! { "anyOf": [] }).is_err());
+    }
+
+}
@@ -15,4 +15,7 @@
 pub(crate) mod file_lock;
 
 pub(crate) mod bson;
+pub(crate) mod decimal;
+pub(crate) mod json_schema;
+pub(crate) mod spill;
 pub mod str;
@@ -0,0 +1,161 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The temporary files of the operations which don't fit in memory.
+//! The documents are written in runs and read back in the same order.
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use bson::Document;
+use crate::Result;
+
+static SPILL_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// The default bytes kept in memory before spilling.
+pub(crate) const DEFAULT_MEMORY_LIMIT: usize = 100 * 1024 * 1024;
+
+/// When and where the operations spill their data.
+#[derive(Debug, Clone)]
+pub(crate) struct SpillOptions {
+    pub(crate) memory_limit: usize,
+    /// The system temporary directory if `None`
+    pub(crate) temp_dir: Option<PathBuf>,
+}
+
+impl Default for SpillOptions {
+    fn default() -> Self {
+        SpillOptions {
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            temp_dir: None,
+        }
+    }
+}
+
+/// A temporary file which is removed when it's dropped.
+pub(crate) struct SpillFile {
+    path: PathBuf,
+}
+
+impl SpillFile {
+
+    fn new(dir: &Path) -> SpillFile {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        let name = format!(
+            "polodb-spill-{}-{}-{}",
+            std::process::id(),
+            nanos,
+            SPILL_COUNTER.fetch_add(1, Ordering::Relaxed),
+        );
+        SpillFile {
+            path: dir.join(name),
+        }
+    }
+
+    pub(crate) fn reader(&self) -> Result<SpillReader> {
+        let file = File::open(&self.path)?;
+        Ok(SpillReader {
+            reader: BufReader::new(file),
+        })
+    }
+
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub(crate) struct SpillWriter {
+    file: SpillFile,
+    writer: BufWriter<File>,
+}
+
+impl SpillWriter {
+
+    /// Create a new file in `dir`, the system temporary directory by default.
+    pub(crate) fn create(dir: Option<&Path>) -> Result<SpillWriter> {
+        let dir = dir.map(Path::to_path_buf).unwrap_or_else(std::env::temp_dir);
+        let file = SpillFile::new(&dir);
+        let writer = BufWriter::new(File::create(&file.path)?);
+        Ok(SpillWriter {
+            file,
+            writer,
+        })
+    }
+
+    pub(crate) fn write(&mut self, doc: &Document) -> Result<()> {
+        doc.to_writer(&mut self.writer)?;
+        Ok(())
+    }
+
+    /// Flush the documents and return the file to read them back.
+    pub(crate) fn finish(mut self) -> Result<SpillFile> {
+        self.writer.flush()?;
+        Ok(self.file)
+    }
+
+}
+
+pub(crate) struct SpillReader {
+    reader: BufReader<File>,
+}
+
+impl SpillReader {
+
+    pub(crate) fn next_doc(&mut self) -> Result<Option<Document>> {
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        let doc = Document::from_reader(&mut self.reader)?;
+        Ok(Some(doc))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::doc;
+    use super::SpillWriter;
+
+    #[test]
+    fn test_spill_round_trip() {
+        let mut writer = SpillWriter::create(None).unwrap();
+        for i in 0..100 {
+            writer.write(&doc! { "_id": i, "name": format!("name-{}", i) }).unwrap();
+        }
+        let file = writer.finish().unwrap();
+        let path = file.path.clone();
+
+        let mut reader = file.reader().unwrap();
+        let mut count = 0;
+        while let Some(doc) = reader.next_doc().unwrap() {
+            assert_eq!(doc.get_i32("_id").unwrap(), count);
+            count += 1;
+        }
+        assert_eq!(count, 100);
+
+        drop(reader);
+        drop(file);
+        assert!(!path.exists());
+    }
+
+}
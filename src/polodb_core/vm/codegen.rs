@@ -14,15 +14,18 @@
 
 
 use super::label::{JumpTableRecord, Label, LabelSlot};
-use crate::coll::collection_info::CollectionSpecification;
+use crate::coll::collection_info::{CollectionSpecification, IndexInfo, IndexKind};
+use crate::coll::partition;
 use crate::errors::{mk_invalid_query_field};
-use crate::index::INDEX_PREFIX;
+use crate::index::{geo, trigram, vector, INDEX_PREFIX};
+use crate::utils::json_schema::JsonSchema;
+use crate::date::DateBucket;
 use crate::vm::op::DbOp;
-use crate::vm::subprogram::SubProgramIndexItem;
+use crate::vm::subprogram::{GeoSearch, IndexCandidates, SubProgramIndexItem, TextSearch, VectorSearch};
 use crate::vm::SubProgram;
 use crate::{Error, Result};
 use bson::spec::{BinarySubtype, ElementType};
-use bson::{Array, Binary, Bson, Document};
+use bson::{doc, Array, Binary, Bson, Document};
 use crate::vm::aggregation_codegen_context::{AggregationCodeGenContext, PipelineItem};
 use crate::vm::global_variable::{GlobalVariable, GlobalVariableSlot};
 use crate::vm::operators::OpRegistry;
@@ -46,6 +49,19 @@ pub(super) struct Codegen {
     is_write: bool,
     paths: Vec<String>,
     op_registry: OpRegistry,
+    /// The range of the primary keys the collection scan is limited to
+    scan_range: Option<Document>,
+    /// `Some(reverse)` to scan the collection in the natural order
+    /// without the primary key lookups and the indexes
+    natural_order: Option<bool>,
+    /// The id of the `$text` search of the query, see [`Codegen::push_text_search`]
+    text_search: Option<u32>,
+    /// The id of the `$vectorSearch` the next collection scan finds the candidates of,
+    /// see [`Codegen::push_vector_search`]
+    vector_search: Option<u32>,
+    /// The id of the `$geoNear` the next collection scan finds the candidates of,
+    /// see [`Codegen::push_geo_near`]
+    geo_search: Option<u32>,
 }
 
 impl Codegen {
@@ -57,6 +73,29 @@ impl Codegen {
             is_write,
             paths: Vec::with_capacity(PATH_DEFAULT_SIZE),
             op_registry: OpRegistry,
+            scan_range: None,
+            natural_order: None,
+            text_search: None,
+            vector_search: None,
+            geo_search: None,
+        }
+    }
+
+    /// Limit the next collection scan to the primary keys in `range`,
+    /// a document of `$gt`, `$gte`, `$lt` and `$lte`.
+    pub(super) fn set_scan_range(&mut self, range: Document) {
+        self.scan_range = Some(range);
+    }
+
+    /// Scan the collection in the natural order, from the last document if `reverse`,
+    /// see [`Codegen::emit_open_scan`].
+    pub(super) fn set_natural_order(&mut self, reverse: bool) {
+        self.natural_order = Some(reverse);
+    }
+
+    fn emit_natural_order(&mut self) {
+        if self.natural_order == Some(true) {
+            self.emit(DbOp::SetReverse);
         }
     }
 
@@ -70,6 +109,20 @@ impl Codegen {
         }
     }
 
+    /// Keep the plan of the first collection opened by the program.
+    /// The primary keys of the program are encoded with the key ordering of the collection.
+    fn set_key_ordering(&mut self, col_spec: &CollectionSpecification) {
+        if col_spec.key_ordering.is_some() {
+            self.program.key_ordering = col_spec.key_ordering.clone();
+        }
+    }
+
+    fn set_plan<F: FnOnce() -> String>(&mut self, f: F) {
+        if self.program.plan.is_none() {
+            self.program.plan = Some(f());
+        }
+    }
+
     pub(super) fn take(mut self) -> SubProgram {
         self.unify_labels();
         *self.program
@@ -202,21 +255,87 @@ impl Codegen {
     where
         F: FnOnce(&mut Codegen) -> Result<()>,
     {
-        let try_pkey_result = self.try_query_by_pkey(col_spec, query, result_callback)?;
-        if try_pkey_result.is_none() {
-            return Ok(());
-        }
+        self.set_key_ordering(col_spec);
 
-        let result_callback: F = try_pkey_result.unwrap();
+        // a partitioned collection only scans the partition of the query,
+        // the primary key is unique in a partition
+        let partition = match &col_spec.partition_key {
+            Some(partition_key) => partition::partition_of_query(partition_key, query),
+            None => None,
+        };
 
-        let try_index_result = self.try_query_by_index(col_spec, query, result_callback)?;
-        if try_index_result.is_none() {
-            return Ok(());
-        }
+        // the documents of a $text query are found by a term of the search in the text index,
+        // the writes scan the collection because they modify the text index
+        let text_index = match query.get("$text") {
+            Some(text) => Some(self.push_text_search(col_spec, text)?),
+            None => None,
+        };
+        // the candidates of $vectorSearch and $geoNear are found by their indexes
+        let candidates_search = match (self.vector_search.take(), self.geo_search.take()) {
+            (Some(search_id), _) => Some((DbOp::LoadVectorCandidates, search_id)),
+            (None, Some(search_id)) => Some((DbOp::LoadGeoCandidates, search_id)),
+            (None, None) => None,
+        };
+        let text_scan = match text_index {
+            Some(index_name) if !self.is_write && self.natural_order.is_none() && candidates_search.is_none() => Some(index_name),
+            _ => None,
+        };
 
-        self.emit_open(col_spec._id.clone().into());
+        // the measurements of a time-series collection are only found by unpacking the buckets
+        let result_callback: F = if col_spec.is_timeseries() || self.natural_order.is_some() || candidates_search.is_some() {
+            result_callback
+        } else if col_spec.is_partitioned() {
+            match &partition {
+                Some(partition) => {
+                    let try_pkey_result = self.try_query_by_pkey(col_spec, Some(partition), query, result_callback)?;
+                    if try_pkey_result.is_none() {
+                        return Ok(());
+                    }
+                    try_pkey_result.unwrap()
+                }
+                None => result_callback,
+            }
+        } else {
+            let try_pkey_result = self.try_query_by_pkey(col_spec, None, query, result_callback)?;
+            if try_pkey_result.is_none() {
+                return Ok(());
+            }
+
+            let result_callback: F = try_pkey_result.unwrap();
+            if text_scan.is_some() {
+                result_callback
+            } else {
+                let try_index_result = self.try_query_by_index(col_spec, query, result_callback)?;
+                if try_index_result.is_none() {
+                    return Ok(());
+                }
+
+                try_index_result.unwrap()
+            }
+        };
 
-        let result_callback: F = try_index_result.unwrap();
+        // the candidates of $contains and $fuzzy are found by their trigrams
+        let trigram_scan = if text_scan.is_none() && candidates_search.is_none() && !self.is_write && self.natural_order.is_none() {
+            Codegen::find_trigram_scan(col_spec, query)?
+        } else {
+            None
+        };
+
+        let (rewind_op, next_op) = match &partition {
+            Some(partition) => {
+                crate::trace_event!(collection = col_spec.name(), "plan: partition scan");
+                self.set_plan(|| format!("PARTSCAN {}", partition));
+                self.emit_open(Codegen::partition_prefix(col_spec, partition)?);
+                self.emit_natural_order();
+                (DbOp::Rewind, DbOp::Next)
+            }
+            None => match (candidates_search, &text_scan, trigram_scan) {
+                (Some((load_op, search_id)), _, _) => self.emit_open_search_scan(col_spec, load_op, search_id)?,
+                (None, Some(index_name), _) => self.emit_open_text_scan(col_spec, index_name)?,
+                (None, None, Some(candidates)) => self.emit_open_candidates_scan(col_spec, candidates)?,
+                (None, None, None) => self.emit_open_scan(col_spec)?,
+            },
+        };
 
         let compare_fun = self.new_label();
         let compare_fun_clean = self.new_label();
@@ -226,16 +345,21 @@ impl Codegen {
         let not_found_label = self.new_label();
         let close_label = self.new_label();
 
-        self.emit_goto(DbOp::Rewind, close_label);
+        self.emit_goto(rewind_op, close_label);
 
         self.emit_goto(DbOp::Goto, compare_label);
 
         self.emit_label(next_label);
-        self.emit_goto(DbOp::Next, compare_label);
+        self.emit_goto(next_op, compare_label);
 
         // <==== close cursor
         self.emit_label_with_name(close_label, "close");
 
+        if text_scan.is_some() {
+            self.emit(DbOp::Pop); // pop the collection name
+            self.emit(DbOp::Pop); // pop the term
+        }
+
         if let Some(before_close) = before_close {
             before_close(self)?;
         }
@@ -285,6 +409,7 @@ impl Codegen {
     fn try_query_by_pkey<F>(
         &mut self,
         col_spec: &CollectionSpecification,
+        partition: Option<&Bson>,
         query: &Document,
         result_callback: F,
     ) -> Result<Option<F>>
@@ -293,7 +418,13 @@ impl Codegen {
     {
         if let Some(id_value) = query.get("_id") {
             if id_value.element_type() != ElementType::EmbeddedDocument {
-                self.emit_open(col_spec._id.clone().into());
+                crate::trace_event!(collection = col_spec.name(), "plan: primary key lookup");
+                self.set_plan(|| "IDHACK".to_string());
+                let prefix = match partition {
+                    Some(partition) => Codegen::partition_prefix(col_spec, partition)?,
+                    None => col_spec.storage_name().into(),
+                };
+                self.emit_open(prefix);
                 self.emit_query_layout_has_pkey(id_value.clone(), query, result_callback)?;
                 return Ok(None);
             }
@@ -315,8 +446,14 @@ impl Codegen {
             return Ok(Some(result_callback));
         }
 
+        // the index matching the fewest entries is chosen, the first one if none is analyzed
+        let mut best: Option<(&String, &String, &Bson, Option<f64>)> = None;
         let index_meta = &col_spec.indexes;
         for (index_name, index_info) in index_meta {
+            // the entries of the text and trigram indexes are the terms instead of the values
+            if !index_info.kind.is_ascending() {
+                continue;
+            }
             let (key, _order) = index_info.keys.iter().next().unwrap();
             // the key is ellipse representation, such as "a.b.c"
             // the query is supposed to be ellipse too, such as
@@ -324,22 +461,56 @@ impl Codegen {
             let test_result = query.get(key);
             if let Some(query_doc) = test_result {
                 if query_doc.element_type() != ElementType::EmbeddedDocument {
-                    let mut remain_query = query.clone();
-                    remain_query.remove(key);
-
-                    self.indeed_emit_query_by_index(
-                        col_spec._id.as_str(),
-                        index_name.as_str(),
-                        query_doc,
-                        &remain_query,
-                        result_callback,
-                    )?;
-                    return Ok(None);
+                    let estimate = Codegen::estimate_index_entries(index_info, query_doc)?;
+                    let is_better = match &best {
+                        None => true,
+                        Some((_, _, _, best_estimate)) => match (estimate, best_estimate) {
+                            (Some(estimate), Some(best_estimate)) => estimate < *best_estimate,
+                            (Some(_), None) => true,
+                            (None, _) => false,
+                        },
+                    };
+                    if is_better {
+                        best = Some((index_name, key, query_doc, estimate));
+                    }
                 }
             }
         }
 
-        Ok(Some(result_callback))
+        let (index_name, key, query_doc, _) = match best {
+            Some(best) => best,
+            None => return Ok(Some(result_callback)),
+        };
+        crate::trace_event!(
+            collection = col_spec.name(),
+            index = index_name.as_str(),
+            "plan: index scan",
+        );
+        self.set_plan(|| format!("IXSCAN {}", index_name));
+        let mut remain_query = query.clone();
+        remain_query.remove(key);
+
+        self.indeed_emit_query_by_index(
+            col_spec.storage_name(),
+            index_name.as_str(),
+            query_doc,
+            &remain_query,
+            result_callback,
+        )?;
+        Ok(None)
+    }
+
+    /// The estimated number of the entries of the index equal to `value`,
+    /// `None` if the index is not analyzed.
+    fn estimate_index_entries(index_info: &IndexInfo, value: &Bson) -> Result<Option<f64>> {
+        let estimate = match &index_info.stats {
+            Some(stats) => Some(stats.estimate_equal(value)?),
+            None => None,
+        };
+        if index_info.is_unique() {
+            return Ok(Some(estimate.unwrap_or(1.0).min(1.0)));
+        }
+        Ok(estimate)
     }
 
     fn indeed_emit_query_by_index<F>(
@@ -353,19 +524,7 @@ impl Codegen {
     where
         F: FnOnce(&mut Codegen) -> Result<()>,
     {
-        let prefix_bytes = {
-            let b_prefix = Bson::String(INDEX_PREFIX.to_string());
-            let b_col_name = Bson::String(col_name.to_string());
-            let b_index_name = &Bson::String(index_name.to_string());
-
-            let buf: Vec<&Bson> = vec![&b_prefix, &b_col_name, &b_index_name];
-            crate::utils::bson::stacked_key(buf)?
-        };
-
-        self.emit_open(Bson::Binary(Binary {
-            subtype: BinarySubtype::Generic,
-            bytes: prefix_bytes,
-        }));
+        self.emit_open(Codegen::index_prefix(col_name, index_name)?);
 
         let close_label = self.new_label();
         let result_label = self.new_label();
@@ -392,17 +551,20 @@ impl Codegen {
         self.emit(DbOp::Close);
         self.emit(DbOp::Halt);
 
+        let not_equal_label = self.new_label();
+        let not_this_item_label = self.new_label();
+
         self.emit_label(result_label);
         for (key, value) in remain_query.iter() {
             let key_static_id = self.push_static(Bson::String(key.clone()));
             let value_static_id = self.push_static(value.clone());
 
-            self.emit_goto2(DbOp::GetField, key_static_id, close_label); // push a value1
+            self.emit_goto2(DbOp::GetField, key_static_id, not_this_item_label); // push a value1
             self.emit_push_value(value_static_id); // push a value2
 
             self.emit(DbOp::Equal);
             // if not equal，go to next
-            self.emit_goto(DbOp::IfFalse, close_label);
+            self.emit_goto(DbOp::IfFalse, not_equal_label);
 
             self.emit(DbOp::Pop); // pop a value2
             self.emit(DbOp::Pop); // pop a value1
@@ -412,9 +574,151 @@ impl Codegen {
 
         self.emit_goto(DbOp::Goto, next_label);
 
+        if !remain_query.is_empty() {
+            self.emit_label(not_equal_label);
+            self.emit(DbOp::Pop); // pop a value2
+            self.emit(DbOp::Pop); // pop a value1
+
+            self.emit_label(not_this_item_label);
+            self.emit(DbOp::Pop); // pop the document
+            self.emit_goto(DbOp::Goto, next_label);
+        }
+
         Ok(())
     }
 
+    /// The prefix of the keys of the index.
+    fn index_prefix(col_name: &str, index_name: &str) -> Result<Bson> {
+        let b_prefix = Bson::String(INDEX_PREFIX.to_string());
+        let b_col_name = Bson::String(col_name.to_string());
+        let b_index_name = &Bson::String(index_name.to_string());
+
+        let buf: Vec<&Bson> = vec![&b_prefix, &b_col_name, &b_index_name];
+        let prefix_bytes = crate::utils::bson::stacked_key(buf)?;
+
+        Ok(Bson::Binary(Binary {
+            subtype: BinarySubtype::Generic,
+            bytes: prefix_bytes,
+        }))
+    }
+
+    /// Open the cursor on the entries of a term of the `$text` search in the text index,
+    /// the documents are still compared with the whole query, which has all the terms.
+    fn emit_open_text_scan(&mut self, col_spec: &CollectionSpecification, index_name: &str) -> Result<(DbOp, DbOp)> {
+        crate::trace_event!(collection = col_spec.name(), index = index_name, "plan: text index scan");
+        self.set_plan(|| format!("TEXT {}", index_name));
+
+        self.emit_open(Codegen::index_prefix(col_spec.storage_name(), index_name)?);
+
+        self.emit(DbOp::PushTextTerm);
+        self.emit_u32(self.text_search.unwrap());
+
+        let col_name_id = self.push_static(Bson::String(col_spec.storage_name().to_string()));
+        self.emit_push_value(col_name_id);
+
+        Ok((DbOp::FindByIndex, DbOp::NextIndexValue))
+    }
+
+    /// The candidates of the first `$contains` or `$fuzzy` of the query on the field of a trigram index.
+    fn find_trigram_scan(col_spec: &CollectionSpecification, query: &Document) -> Result<Option<IndexCandidates>> {
+        for (index_name, index_info) in &col_spec.indexes {
+            if index_info.kind != IndexKind::Trigram {
+                continue;
+            }
+            let (key, _) = index_info.keys.iter().next().unwrap();
+            let condition = match query.get(key) {
+                Some(Bson::Document(condition)) => condition,
+                _ => continue,
+            };
+            let terms = if let Some(Bson::String(needle)) = condition.get("$contains") {
+                trigram::contains_candidate_grams(needle)
+            } else if let Some(fuzzy) = condition.get("$fuzzy") {
+                let (query, max_edits) = Codegen::parse_fuzzy(fuzzy)?;
+                trigram::fuzzy_candidate_grams(&query, max_edits)
+            } else {
+                continue;
+            };
+            if !terms.is_empty() {
+                return Ok(Some(IndexCandidates {
+                    col_name: col_spec.storage_name().to_string(),
+                    index_name: index_name.clone(),
+                    terms,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Open the cursor on the candidates found by the terms of an index,
+    /// the candidates are still compared with the whole query.
+    fn emit_open_candidates_scan(&mut self, col_spec: &CollectionSpecification, candidates: IndexCandidates) -> Result<(DbOp, DbOp)> {
+        crate::trace_event!(
+            collection = col_spec.name(),
+            index = candidates.index_name.as_str(),
+            "plan: trigram index scan",
+        );
+        self.set_plan(|| format!("TRIGRAM {}", candidates.index_name));
+
+        self.emit_open(col_spec.storage_name().into());
+
+        let pos = self.program.index_candidates.len() as u32;
+        self.program.index_candidates.push(candidates);
+        self.emit(DbOp::LoadIndexCandidates);
+        self.emit_u32(pos);
+
+        Ok((DbOp::RewindCandidates, DbOp::NextCandidate))
+    }
+
+    /// Open the cursor on the documents of the input of the VM, see [`crate::vm::VM::set_input`].
+    pub(crate) fn emit_open_input(&mut self) -> (DbOp, DbOp) {
+        self.set_plan(|| "INPUT".to_string());
+        (DbOp::RewindInput, DbOp::NextInput)
+    }
+
+    /// Open the cursor on the candidates of the `$vectorSearch`, from the most similar,
+    /// or on the candidates of the `$geoNear`, from the nearest.
+    fn emit_open_search_scan(&mut self, col_spec: &CollectionSpecification, load_op: DbOp, search_id: u32) -> Result<(DbOp, DbOp)> {
+        let (plan, index_name) = if load_op == DbOp::LoadVectorCandidates {
+            ("VECTOR", self.program.vector_searches[search_id as usize].index_name.clone())
+        } else {
+            ("GEONEAR", self.program.geo_searches[search_id as usize].index_name.clone())
+        };
+        crate::trace_event!(collection = col_spec.name(), index = index_name.as_str(), "plan: search index scan");
+        self.set_plan(|| format!("{} {}", plan, index_name));
+
+        self.emit_open(col_spec.storage_name().into());
+
+        self.emit(load_op);
+        self.emit_u32(search_id);
+
+        Ok((DbOp::RewindCandidates, DbOp::NextCandidate))
+    }
+
+    /// The lower-cased query and the max edits of `$fuzzy`,
+    /// a string within one edit, or a document of `query` and `maxEdits`.
+    fn parse_fuzzy(fuzzy: &Bson) -> Result<(String, u32)> {
+        let (query, max_edits) = match fuzzy {
+            Bson::String(query) => (query.as_str(), 1),
+            Bson::Document(doc) => {
+                let query = doc.get_str("query").map_err(|_| {
+                    Error::ValidationError("the query of $fuzzy must be a string".to_string())
+                })?;
+                let max_edits = match doc.get("maxEdits") {
+                    None => 1,
+                    Some(Bson::Int32(max_edits)) => *max_edits as i64,
+                    Some(Bson::Int64(max_edits)) => *max_edits,
+                    Some(_) => return Err(Error::ValidationError("the maxEdits of $fuzzy must be an integer".to_string())),
+                };
+                (query, max_edits)
+            }
+            _ => return Err(Error::ValidationError("$fuzzy must be a string or a document of query and maxEdits".to_string())),
+        };
+        if !(0..=2).contains(&max_edits) {
+            return Err(Error::ValidationError("the maxEdits of $fuzzy must be 0, 1 or 2".to_string()));
+        }
+        Ok((query.to_lowercase(), max_edits as u32))
+    }
+
     fn emit_standard_query_doc(
         &mut self,
         query_doc: &Document,
@@ -483,6 +787,47 @@ impl Codegen {
         arr: &Array,
         ret_label: Label,
     ) -> Result<()> {
+        let functions = self.emit_logic_functions("$or", arr)?;
+        for fun in functions {
+            self.emit_goto(DbOp::Call, fun);
+            self.emit_u32(0);
+            self.emit_goto(DbOp::IfTrue, ret_label);
+        }
+
+        Ok(())
+    }
+
+    /// Fail the query if any of the queries in `arr` matches.
+    fn emit_logic_nor(
+        &mut self,
+        arr: &Array,
+        not_found_label: Label,
+    ) -> Result<()> {
+        let functions = self.emit_logic_functions("$nor", arr)?;
+        let matched_label = self.new_label();
+        let end_label = self.new_label();
+        for fun in functions {
+            self.emit_goto(DbOp::Call, fun);
+            self.emit_u32(0);
+            self.emit_goto(DbOp::IfTrue, matched_label);
+        }
+        self.emit(DbOp::StoreR0_2);
+        self.emit_u8(1);
+        self.emit_goto(DbOp::Goto, end_label);
+
+        self.emit_label(matched_label);
+        self.emit(DbOp::StoreR0_2);
+        self.emit_u8(0);
+        self.emit_goto(DbOp::Goto, not_found_label);
+
+        self.emit_label(end_label);
+
+        Ok(())
+    }
+
+    /// Emit each query of `arr` as a function returning if it matches,
+    /// the code after the functions is where they are called.
+    fn emit_logic_functions(&mut self, op: &str, arr: &Array) -> Result<Vec<Label>> {
         let cmp_label = self.new_label();
         self.emit_goto(DbOp::Goto, cmp_label);
 
@@ -490,7 +835,7 @@ impl Codegen {
         for (index, item_doc_value) in arr.iter().enumerate() {
             let path_msg = format!("[{}]", index);
             crate::path_hint!(self, path_msg, {
-                let item_doc = crate::try_unwrap_document!("$or", item_doc_value);
+                let item_doc = crate::try_unwrap_document!(op, item_doc_value);
 
                 let query_label = self.new_label();
                 let ret_label = self.new_label();
@@ -510,16 +855,12 @@ impl Codegen {
         }
 
         self.emit_label(cmp_label);
-        for fun in functions {
-            self.emit_goto(DbOp::Call, fun);
-            self.emit_u32(0);
-            self.emit_goto(DbOp::IfTrue, ret_label);
-        }
 
-        Ok(())
+        Ok(functions)
     }
 
-    // case1: "$and" | "$or" -> [ Document ]
+    // case1: "$and" | "$or" | "$nor" -> [ Document ]
+    // case2: "$jsonSchema" -> Document
     // case3: "_id" -> Document
     fn emit_query_tuple(
         &mut self,
@@ -547,6 +888,31 @@ impl Codegen {
                     )?;
                 }
 
+                "$nor" => {
+                    let sub_arr = crate::try_unwrap_array!("$nor", value);
+                    self.emit_logic_nor(
+                        sub_arr.as_ref(),
+                        not_found_label,
+                    )?;
+                }
+
+                "$text" => {
+                    let search_id = self.text_search.ok_or_else(|| {
+                        Error::ValidationError("the $text query is only supported at the top level of a filter".to_string())
+                    })?;
+                    self.emit(DbOp::MatchText);
+                    self.emit_u32(search_id);
+                    self.emit_goto(DbOp::IfFalse, not_found_label);
+                }
+
+                "$jsonSchema" => {
+                    let schema_doc = crate::try_unwrap_document!("$jsonSchema", value);
+                    let schema_id = self.push_json_schema(JsonSchema::parse(schema_doc)?);
+                    self.emit(DbOp::MatchJsonSchema);
+                    self.emit_u32(schema_id);
+                    self.emit_goto(DbOp::IfFalse, not_found_label);
+                }
+
                 _ => {
                     return Err(Error::InvalidField(mk_invalid_query_field(
                         self.last_key().into(),
@@ -795,6 +1161,73 @@ impl Codegen {
                 self.emit_u32((field_size + 1) as u32);
             }
 
+            "$contains" => {
+                let needle = match sub_value {
+                    Bson::String(needle) => needle.to_lowercase(),
+                    _ => {
+                        return Err(Error::InvalidField(mk_invalid_query_field(
+                            self.last_key().into(),
+                            self.gen_path(),
+                        )))
+                    }
+                };
+
+                let field_size = self.recursively_get_field(key, not_found_label);
+
+                let stat_val_id = self.push_static(Bson::String(needle));
+                self.emit_push_value(stat_val_id);
+
+                self.emit_logical(DbOp::Contains, is_in_not);
+
+                self.emit_goto(DbOp::IfFalse, not_found_label);
+
+                self.emit(DbOp::Pop2);
+                self.emit_u32((field_size + 1) as u32);
+            }
+
+            "$fuzzy" => {
+                let (query, max_edits) = Codegen::parse_fuzzy(sub_value)?;
+
+                let field_size = self.recursively_get_field(key, not_found_label);
+
+                let stat_val_id = self.push_static(Bson::Document(doc! {
+                    "query": query,
+                    "maxEdits": max_edits as i32,
+                }));
+                self.emit_push_value(stat_val_id);
+
+                self.emit_logical(DbOp::Fuzzy, is_in_not);
+
+                self.emit_goto(DbOp::IfFalse, not_found_label);
+
+                self.emit(DbOp::Pop2);
+                self.emit_u32((field_size + 1) as u32);
+            }
+
+            // the bucket of `date` is compiled to a range of `$gte` and `$lt`
+            "$dateBucket" => {
+                let spec = match sub_value {
+                    Bson::Document(doc) if !is_in_not => doc,
+                    _ => {
+                        return Err(Error::InvalidField(mk_invalid_query_field(
+                            self.last_key().into(),
+                            self.gen_path(),
+                        )))
+                    }
+                };
+                let date = match spec.get("date") {
+                    Some(Bson::DateTime(date)) => *date,
+                    _ => {
+                        return Err(Error::InvalidField(mk_invalid_query_field(
+                            self.last_key().into(),
+                            self.gen_path(),
+                        )))
+                    }
+                };
+                let range = DateBucket::from_doc(spec)?.range(date);
+                self.emit_query_tuple_document(key, &range.to_filter(), false, not_found_label)?;
+            }
+
             "$not" => {
                 let doc = match sub_value {
                     Bson::Document(doc) => doc,
@@ -854,10 +1287,10 @@ impl Codegen {
         }
         let next_label = self.new_label();
 
-        for stage_item in &ctx.items {
-            self.emit_goto(DbOp::Call, stage_item.next_label);
-            self.emit_u32(1);
-        }
+        // every stage passes its output to the next one
+        let first_stage = ctx.items[0].next_label;
+        self.emit_goto(DbOp::Call, first_stage);
+        self.emit_u32(1);
 
         // the final pipeline item to emit the final result
         let final_result_label = self.new_label();
@@ -956,11 +1389,19 @@ impl Codegen {
                         )?;
                         self.emit_external_func(external_func, stage_ctx_item, next_fun);
                     }
+                    "$match" => {
+                        let next_fun = ctx.items[index + 1].next_label;
+                        let query = crate::try_unwrap_document!("$match", value);
+                        self.emit_match_stage(query, stage_ctx_item, next_fun)?;
+                    }
                     "$unset" => {
                         let next_fun = ctx.items[index + 1].next_label;
                         let external_func: Box<dyn VmExternalFunc> = VmFuncUnset::compile(&mut self.paths, value)?;
                         self.emit_external_func(external_func, stage_ctx_item, next_fun);
                     }
+                    "$vectorSearch" | "$geoNear" => {
+                        return Err(Error::ValidationError(format!("{} must be the first stage", key)));
+                    }
                     _ => {
                         return Err(Error::UnknownAggregationOperation(key.clone()));
                     }
@@ -1000,6 +1441,38 @@ impl Codegen {
         self.emit_ret(0);
     }
 
+    // The $match after the other stages, the first $match is merged into the query.
+    fn emit_match_stage(&mut self, query: &Document, stage_ctx_item: &PipelineItem, next_fun: Label) -> Result<()> {
+        let skip_label = self.new_label();
+        let match_fun = self.new_label();
+        let match_fun_clean = self.new_label();
+
+        self.emit_label(stage_ctx_item.next_label);
+
+        // the null at the end of the input is passed to every stage before closing
+        self.emit(DbOp::EqualNull);
+        self.emit_goto(DbOp::IfTrue, skip_label);
+
+        self.emit(DbOp::Dup);
+        self.emit_goto(DbOp::Call, match_fun);
+        self.emit_u32(1);
+        self.emit_goto(DbOp::IfFalse, skip_label);
+
+        self.emit(DbOp::Dup);
+        self.emit_goto(DbOp::Call, next_fun);
+        self.emit_u32(1);
+
+        self.emit_label(skip_label);
+        self.emit_ret(0);
+
+        self.emit_label_with_name(match_fun, "match_function");
+        self.emit_standard_query_doc(query, match_fun_clean, match_fun_clean)?;
+        self.emit_label(match_fun_clean);
+        self.emit_ret(0);
+
+        Ok(())
+    }
+
     fn emit_call_external_func_id(&mut self, external_func_id: u32, param_size: usize) {
         self.emit(DbOp::CallExternal);
         self.emit_u32(external_func_id);
@@ -1160,6 +1633,69 @@ impl Codegen {
         self.emit_u32(id);
     }
 
+    fn partition_prefix(col_spec: &CollectionSpecification, partition: &Bson) -> Result<Bson> {
+        Ok(Bson::Binary(Binary {
+            subtype: BinarySubtype::Generic,
+            bytes: partition::partition_prefix(col_spec.storage_name(), partition)?,
+        }))
+    }
+
+    /// Open the cursor to scan all the documents of the collection,
+    /// return the ops to rewind and to advance the cursor.
+    ///
+    /// The capped collections are read in insertion order,
+    /// and the buckets of the time-series collections are unpacked into the measurements.
+    /// The other collections are read in the order of the primary keys,
+    /// which is their natural order.
+    pub(crate) fn emit_open_scan(&mut self, col_spec: &CollectionSpecification) -> Result<(DbOp, DbOp)> {
+        crate::trace_event!(collection = col_spec.name(), "plan: collection scan");
+        self.set_plan(|| "COLLSCAN".to_string());
+
+        // the write ops modify the current key of the cursor,
+        // so they scan the documents directly
+        if col_spec.is_capped() && !self.is_write {
+            let prefix_bytes = crate::coll::capped::order_prefix(col_spec.storage_name())?;
+            self.emit_open(Bson::Binary(Binary {
+                subtype: BinarySubtype::Generic,
+                bytes: prefix_bytes,
+            }));
+            self.emit_natural_order();
+            return Ok((DbOp::RewindIndex, DbOp::NextIndexValue));
+        }
+
+        if let (Some(timeseries), false) = (&col_spec.timeseries, self.is_write) {
+            self.emit_open(col_spec.storage_name().into());
+            let meta_field = timeseries.meta_field.clone().map(Bson::String).unwrap_or(Bson::Null);
+            let meta_field_id = self.push_static(meta_field);
+            self.emit(DbOp::UnpackBuckets);
+            self.emit_u32(meta_field_id);
+            return Ok((DbOp::Rewind, DbOp::Next));
+        }
+
+        self.set_key_ordering(col_spec);
+        self.emit_open(col_spec.storage_name().into());
+        if let Some(range) = self.scan_range.take() {
+            let range_id = self.push_static(Bson::Document(range));
+            self.emit(DbOp::SetRange);
+            self.emit_u32(range_id);
+        }
+        self.emit_natural_order();
+        Ok((DbOp::Rewind, DbOp::Next))
+    }
+
+    /// Open the cursor to scan the documents whose primary keys are in `range`,
+    /// a document of `$gt`, `$gte`, `$lt` and `$lte`.
+    pub(crate) fn emit_open_range(&mut self, col_spec: &CollectionSpecification, range: Document) {
+        crate::trace_event!(collection = col_spec.name(), "plan: range scan");
+        self.set_plan(|| format!("RANGESCAN {}", range));
+
+        self.set_key_ordering(col_spec);
+        self.emit_open(col_spec.storage_name().into());
+        let range_id = self.push_static(Bson::Document(range));
+        self.emit(DbOp::SetRange);
+        self.emit_u32(range_id);
+    }
+
     pub(crate) fn emit_ret(&mut self, return_size: u32) {
         if return_size == 0 {
             self.emit(DbOp::Ret0);
@@ -1191,6 +1727,188 @@ impl Codegen {
         pos
     }
 
+    pub(super) fn push_json_schema(&mut self, schema: JsonSchema) -> u32 {
+        let pos = self.program.json_schemas.len() as u32;
+        self.program.json_schemas.push(schema);
+        pos
+    }
+
+    /// Push the `$text` search of the query, which is analyzed like the text index of the collection.
+    /// Return the name of the index.
+    fn push_text_search(&mut self, col_spec: &CollectionSpecification, text: &Bson) -> Result<String> {
+        let text_doc = crate::try_unwrap_document!("$text", text);
+        let mut search = None;
+        for (key, value) in text_doc.iter() {
+            match (key.as_str(), value) {
+                ("$search", Bson::String(value)) => search = Some(value.clone()),
+                ("$search", _) => return Err(Error::ValidationError("the $search of $text must be a string".to_string())),
+                _ => return Err(Error::ValidationError(format!("the option '{}' of $text is not supported", key))),
+            }
+        }
+        let search = search.ok_or_else(|| Error::ValidationError("$text needs a $search".to_string()))?;
+
+        let (index_name, index_info) = col_spec.indexes
+            .iter()
+            .find(|(_, index_info)| index_info.is_text())
+            .ok_or_else(|| Error::ValidationError(format!("the $text query needs a text index on the collection '{}'", col_spec.name())))?;
+        let (field, _) = index_info.keys.iter().next().unwrap();
+
+        let pos = self.program.text_searches.len() as u32;
+        self.program.text_searches.push(TextSearch {
+            field: field.clone(),
+            options: index_info.options.clone(),
+            search,
+        });
+        self.text_search = Some(pos);
+        Ok(index_name.clone())
+    }
+
+    /// Push the `$vectorSearch` of the collection scan, e.g.
+    /// `{ "path": "embedding", "queryVector": [0.1, 0.2], "limit": 5 }`,
+    /// with the optional `index`, `numCandidates`, `filter` and `scoreField`.
+    /// Return the filter and the limit of the documents.
+    pub(super) fn push_vector_search(&mut self, col_spec: &CollectionSpecification, stage: &Bson) -> Result<(Document, i64)> {
+        let stage = crate::try_unwrap_document!("$vectorSearch", stage);
+        let invalid = |message: &str| Error::ValidationError(format!("the {} of $vectorSearch", message));
+        let as_positive = |key: &str| -> Result<Option<i64>> {
+            match stage.get(key) {
+                None => Ok(None),
+                Some(Bson::Int32(value)) if *value > 0 => Ok(Some(*value as i64)),
+                Some(Bson::Int64(value)) if *value > 0 => Ok(Some(*value)),
+                Some(_) => Err(invalid(&format!("{} must be a positive integer", key))),
+            }
+        };
+        for key in stage.keys() {
+            if !["index", "path", "queryVector", "limit", "numCandidates", "filter", "scoreField"].contains(&key.as_str()) {
+                return Err(Error::ValidationError(format!("the option '{}' of $vectorSearch is not supported", key)));
+            }
+        }
+
+        let path = stage.get_str("path").map_err(|_| invalid("path must be a string"))?;
+        let index_name = match stage.get("index") {
+            None => None,
+            Some(Bson::String(index_name)) => Some(index_name.as_str()),
+            Some(_) => return Err(invalid("index must be a string")),
+        };
+        let limit = as_positive("limit")?.ok_or_else(|| invalid("limit is required"))?;
+        let num_candidates = as_positive("numCandidates")?.unwrap_or(limit.saturating_mul(10));
+        if num_candidates < limit {
+            return Err(invalid("numCandidates can't be less than the limit"));
+        }
+        let filter = match stage.get("filter") {
+            None => Document::new(),
+            Some(Bson::Document(filter)) => filter.clone(),
+            Some(_) => return Err(invalid("filter must be a document")),
+        };
+        let score_field = match stage.get("scoreField") {
+            None => None,
+            Some(Bson::String(score_field)) => Some(score_field.clone()),
+            Some(_) => return Err(invalid("scoreField must be a string")),
+        };
+
+        let (index_name, index_info) = col_spec.indexes
+            .iter()
+            .find(|(name, index_info)| {
+                index_info.kind == IndexKind::Vector
+                    && index_info.keys.contains_key(path)
+                    && (index_name.is_none() || index_name == Some(name.as_str()))
+            })
+            .ok_or_else(|| Error::ValidationError(format!(
+                "the $vectorSearch needs a vector index on the field '{}' of the collection '{}'",
+                path,
+                col_spec.name(),
+            )))?;
+        let (dimensions, similarity) = vector::index_options(index_info.options.as_ref())?;
+        let query_vector = stage.get("queryVector")
+            .and_then(|query_vector| vector::parse_vector(query_vector, dimensions))
+            .ok_or_else(|| invalid(&format!("queryVector must be an array of {} numbers", dimensions)))?;
+
+        let pos = self.program.vector_searches.len() as u32;
+        self.program.vector_searches.push(VectorSearch {
+            col_name: col_spec.storage_name().to_string(),
+            index_name: index_name.clone(),
+            similarity,
+            centroids: index_info.centroids.clone(),
+            query_vector,
+            num_candidates: num_candidates as usize,
+            score_field,
+        });
+        self.vector_search = Some(pos);
+        Ok((filter, limit))
+    }
+
+    /// Push the `$geoNear` of the collection scan, e.g.
+    /// `{ "near": { "type": "Point", "coordinates": [2.35, 48.85] }, "distanceField": "distance" }`,
+    /// with the optional `key`, `maxDistance` and `minDistance` in meters, `query` and `spherical`.
+    /// Return the query of the documents.
+    pub(super) fn push_geo_near(&mut self, col_spec: &CollectionSpecification, stage: &Bson, limit: Option<usize>) -> Result<Document> {
+        let stage = crate::try_unwrap_document!("$geoNear", stage);
+        let invalid = |message: &str| Error::ValidationError(format!("the {} of $geoNear", message));
+        let as_distance = |key: &str| -> Result<Option<f64>> {
+            let distance = match stage.get(key) {
+                None => return Ok(None),
+                Some(Bson::Double(value)) => *value,
+                Some(Bson::Int32(value)) => *value as f64,
+                Some(Bson::Int64(value)) => *value as f64,
+                Some(_) => return Err(invalid(&format!("{} must be a number", key))),
+            };
+            if distance < 0.0 || distance.is_nan() {
+                return Err(invalid(&format!("{} can't be negative", key)));
+            }
+            Ok(Some(distance))
+        };
+        for key in stage.keys() {
+            if !["near", "distanceField", "key", "maxDistance", "minDistance", "query", "spherical"].contains(&key.as_str()) {
+                return Err(Error::ValidationError(format!("the option '{}' of $geoNear is not supported", key)));
+            }
+        }
+
+        let near = stage.get("near")
+            .and_then(geo::parse_point)
+            .ok_or_else(|| invalid("near must be a GeoJSON point or a pair of the longitude and the latitude"))?;
+        let distance_field = stage.get_str("distanceField").map_err(|_| invalid("distanceField is required"))?;
+        let min_distance = as_distance("minDistance")?.unwrap_or(0.0);
+        let max_distance = as_distance("maxDistance")?;
+        let query = match stage.get("query") {
+            None => Document::new(),
+            Some(Bson::Document(query)) => query.clone(),
+            Some(_) => return Err(invalid("query must be a document")),
+        };
+        match stage.get("spherical") {
+            None | Some(Bson::Boolean(true)) => (),
+            Some(_) => return Err(invalid("spherical must be true, the distances are always spherical")),
+        }
+        let key = match stage.get("key") {
+            None => None,
+            Some(Bson::String(key)) => Some(key.as_str()),
+            Some(_) => return Err(invalid("key must be a string")),
+        };
+
+        let mut indexes = col_spec.indexes
+            .iter()
+            .filter(|(_, index_info)| index_info.kind == IndexKind::Geo2dSphere)
+            .filter(|(_, index_info)| key.is_none() || index_info.keys.contains_key(key.unwrap()));
+        let (index_name, _) = indexes.next()
+            .ok_or_else(|| Error::ValidationError(format!("the $geoNear needs a 2dsphere index on the collection '{}'", col_spec.name())))?;
+        if indexes.next().is_some() {
+            return Err(invalid("key is required if the collection has several 2dsphere indexes"));
+        }
+
+        let pos = self.program.geo_searches.len() as u32;
+        self.program.geo_searches.push(GeoSearch {
+            col_name: col_spec.storage_name().to_string(),
+            index_name: index_name.clone(),
+            near,
+            min_distance,
+            max_distance,
+            distance_field: distance_field.to_string(),
+            // the query may not match the nearest ones
+            limit: limit.filter(|_| query.is_empty()),
+        });
+        self.geo_search = Some(pos);
+        Ok(query)
+    }
+
     pub(super) fn push_index_info(&mut self, index_item: SubProgramIndexItem) -> u32 {
         let pos = self.program.index_infos.len() as u32;
         self.program.index_infos.push(index_item);
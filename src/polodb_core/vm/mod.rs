@@ -22,7 +22,7 @@ mod aggregation_codegen_context;
 mod vm_external_func;
 mod vm_count;
 mod vm_group;
-mod operators;
+pub(crate) mod operators;
 mod vm_skip;
 mod vm_sort;
 mod vm_limit;
@@ -94,6 +94,86 @@ pub enum DbOp {
     // op1. location: 4bytes
     NextIndexValue,
 
+    // find the entries of the terms of an index,
+    // the documents of the distinct primary keys are the candidates
+    //
+    // 5 bytes
+    // op1. id of the candidates: 4 bytes
+    LoadIndexCandidates,
+
+    // find the vectors of the lists of a vector index nearest to the query vector,
+    // the documents of the most similar ones are the candidates, from the most similar
+    //
+    // 5 bytes
+    // op1. id of the vector search: 4 bytes
+    LoadVectorCandidates,
+
+    // find the points of a 2dsphere index within the distances of the $geoNear,
+    // the documents of them are the candidates, from the nearest
+    //
+    // 5 bytes
+    // op1. id of the $geoNear: 4 bytes
+    LoadGeoCandidates,
+
+    // push the first candidate to the stack
+    // if no candidates, jump to location
+    //
+    // 5 bytes
+    // op1. location: 4 bytes
+    RewindCandidates,
+
+    // push the next candidate to the stack and jump to location
+    // if no next candidate, pass
+    //
+    // 5 bytes
+    // op1. location: 4 bytes
+    NextCandidate,
+
+    // push the first document of the input of the VM to the stack
+    // if the input is empty, jump to location
+    //
+    // 5 bytes
+    // op1. location: 4 bytes
+    RewindInput,
+
+    // push the next document of the input to the stack and jump to location
+    // if no next document, pass
+    //
+    // 5 bytes
+    // op1. location: 4 bytes
+    NextInput,
+
+    // reset the cursor to the first element of an index
+    // if empty, jump to location
+    //
+    // push the value referenced by the index to the stack
+    //
+    // 5 bytes
+    // op1. location: 4 bytes
+    RewindIndex,
+
+    // unpack the bucket documents read by the cursor
+    // into the measurements of a time-series collection,
+    // the Rewind and Next of the cursor return the measurements
+    //
+    // 5 bytes
+    // op1. static id of the meta field name, null if none: 4 bytes
+    UnpackBuckets,
+
+    // limit the keys of the cursor to the primary keys in a range,
+    // the range is a document of $gt, $gte, $lt and $lte,
+    // the Rewind seeks to the lower bound
+    //
+    // 5 bytes
+    // op1. static id of the range: 4 bytes
+    SetRange,
+
+    // iterate the keys of the cursor from the last one,
+    // the Rewind seeks to the upper bound of the range
+    //
+    // 1 byte
+    SetReverse,
+
     // push value to the stack
     //
     // 5 bytes
@@ -214,6 +294,10 @@ pub enum DbOp {
     Less,
     LessEqual,
     Regex,
+    // if the string of val1 contains the lower-cased string of val2, ignoring the case
+    Contains,
+    // if the string of val1 is within the max edits of the $fuzzy document of val2
+    Fuzzy,
 
     Not,
 
@@ -223,6 +307,27 @@ pub enum DbOp {
 
     EqualNull,
 
+    // check if the top of the stack matches a $jsonSchema
+    // the result is stored in r0
+    //
+    // 5 bytes
+    // op1. id of the schema: 4 bytes
+    MatchJsonSchema,
+
+    // check if the field of the top of the stack has all the terms of a $text search
+    // the result is stored in r0
+    //
+    // 5 bytes
+    // op1. id of the search: 4 bytes
+    MatchText,
+
+    // push the term of a $text search to find in the text index,
+    // null if the search has no terms
+    //
+    // 5 bytes
+    // op1. id of the search: 4 bytes
+    PushTextTerm,
+
     // open a cursor with op0 as root_pid
     //
     // 5 bytes
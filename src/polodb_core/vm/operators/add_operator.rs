@@ -0,0 +1,88 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use bson::{Bson, DateTime};
+use crate::vm::operators::{OpRegistry, OperatorExpr, VmOperator};
+use crate::utils::decimal::decimal_operands;
+use crate::{Error, Result};
+
+/// `$add` sums the numbers, or adds the milliseconds to a date.
+/// The result is null if any of the arguments is null, missing or not a number.
+pub(crate) struct AddOperator {
+    args: Vec<OperatorExpr>,
+}
+
+impl AddOperator {
+
+    pub(crate) fn compile(paths: &mut Vec<String>, registry: &OpRegistry, v: &Bson) -> Result<Box<dyn VmOperator>> {
+        let arr = v.as_array()
+            .ok_or_else(|| Error::UnknownAggregationOperation("$add requires an array".to_string()))?;
+        let args = arr.iter()
+            .map(|item| OperatorExpr::compile_operand(paths, registry, item))
+            .collect::<Result<Vec<OperatorExpr>>>()?;
+        Ok(Box::new(AddOperator {
+            args,
+        }))
+    }
+
+    fn add(a: Bson, b: Bson) -> Bson {
+        if let Some((a, b)) = decimal_operands(&a, &b) {
+            return Bson::Decimal128(a.add(&b).to_decimal128());
+        }
+        match (a, b) {
+            (Bson::Int32(a), Bson::Int32(b)) => match a.checked_add(b) {
+                Some(v) => Bson::Int32(v),
+                None => Bson::Int64(a as i64 + b as i64),
+            },
+            (Bson::Int32(a), Bson::Int64(b)) | (Bson::Int64(b), Bson::Int32(a)) => Bson::Int64(a as i64 + b),
+            (Bson::Int64(a), Bson::Int64(b)) => Bson::Int64(a.wrapping_add(b)),
+            (Bson::Double(a), Bson::Double(b)) => Bson::Double(a + b),
+            (Bson::Double(a), Bson::Int32(b)) | (Bson::Int32(b), Bson::Double(a)) => Bson::Double(a + b as f64),
+            (Bson::Double(a), Bson::Int64(b)) | (Bson::Int64(b), Bson::Double(a)) => Bson::Double(a + b as f64),
+            (Bson::DateTime(d), Bson::Int32(ms)) | (Bson::Int32(ms), Bson::DateTime(d)) => {
+                Bson::DateTime(DateTime::from_millis(d.timestamp_millis() + ms as i64))
+            }
+            (Bson::DateTime(d), Bson::Int64(ms)) | (Bson::Int64(ms), Bson::DateTime(d)) => {
+                Bson::DateTime(DateTime::from_millis(d.timestamp_millis() + ms))
+            }
+            (Bson::DateTime(d), Bson::Double(ms)) | (Bson::Double(ms), Bson::DateTime(d)) => {
+                Bson::DateTime(DateTime::from_millis(d.timestamp_millis() + ms.round() as i64))
+            }
+            _ => Bson::Null,
+        }
+    }
+
+}
+
+impl VmOperator for AddOperator {
+    fn initial_value(&self) -> Bson {
+        self.next(&Bson::Null)
+    }
+
+    fn next(&self, input: &Bson) -> Bson {
+        let mut result = Bson::Int32(0);
+        for arg in &self.args {
+            result = AddOperator::add(result, arg.next(input));
+            if result == Bson::Null {
+                break;
+            }
+        }
+        result
+    }
+
+    fn complete(&self) -> Bson {
+        self.next(&Bson::Null)
+    }
+}
@@ -0,0 +1,59 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use bson::Bson;
+use crate::vm::operators::{OpRegistry, OperatorExpr, VmOperator};
+use crate::Result;
+
+/// `$toUpper` and `$toLower`, the result is an empty string if the argument is null or missing.
+pub(crate) struct CaseOperator {
+    inner: OperatorExpr,
+    upper: bool,
+}
+
+impl CaseOperator {
+
+    pub(crate) fn compile(paths: &mut Vec<String>, registry: &OpRegistry, v: &Bson, upper: bool) -> Result<Box<dyn VmOperator>> {
+        // the argument can be wrapped in an array of one element
+        let v = match v {
+            Bson::Array(arr) if arr.len() == 1 => &arr[0],
+            _ => v,
+        };
+        Ok(Box::new(CaseOperator {
+            inner: OperatorExpr::compile_operand(paths, registry, v)?,
+            upper,
+        }))
+    }
+
+}
+
+impl VmOperator for CaseOperator {
+    fn initial_value(&self) -> Bson {
+        self.next(&Bson::Null)
+    }
+
+    fn next(&self, input: &Bson) -> Bson {
+        let s = match self.inner.next(input) {
+            Bson::String(s) => s,
+            Bson::Null | Bson::Undefined => String::new(),
+            other => other.to_string(),
+        };
+        Bson::String(if self.upper { s.to_uppercase() } else { s.to_lowercase() })
+    }
+
+    fn complete(&self) -> Bson {
+        self.next(&Bson::Null)
+    }
+}
@@ -0,0 +1,59 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+use bson::Bson;
+use crate::vm::operators::{OpRegistry, OperatorExpr, VmOperator};
+use crate::{Error, Result};
+
+/// `$concat` joins the strings, the result is null if any of them is null or missing.
+pub(crate) struct ConcatOperator {
+    args: Vec<OperatorExpr>,
+}
+
+impl ConcatOperator {
+
+    pub(crate) fn compile(paths: &mut Vec<String>, registry: &OpRegistry, v: &Bson) -> Result<Box<dyn VmOperator>> {
+        let arr = v.as_array()
+            .ok_or_else(|| Error::UnknownAggregationOperation("$concat requires an array".to_string()))?;
+        let args = arr.iter()
+            .map(|item| OperatorExpr::compile_operand(paths, registry, item))
+            .collect::<Result<Vec<OperatorExpr>>>()?;
+        Ok(Box::new(ConcatOperator {
+            args,
+        }))
+    }
+
+}
+
+impl VmOperator for ConcatOperator {
+    fn initial_value(&self) -> Bson {
+        self.next(&Bson::Null)
+    }
+
+    fn next(&self, input: &Bson) -> Bson {
+        let mut result = String::new();
+        for arg in &self.args {
+            match arg.next(input) {
+                Bson::String(s) => result.push_str(&s),
+                _ => return Bson::Null,
+            }
+        }
+        Bson::String(result)
+    }
+
+    fn complete(&self) -> Bson {
+        self.next(&Bson::Null)
+    }
+}
@@ -0,0 +1,120 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+
+use bson::{Bson, Document};
+use crate::date::{date_add, parse_timezone, DateBucket, DateUnit, TimeZone};
+use crate::vm::operators::{OpRegistry, OperatorExpr, VmOperator};
+use crate::{Error, Result};
+
+fn operator_doc<'a>(name: &str, v: &'a Bson) -> Result<&'a Document> {
+    v.as_document()
+        .ok_or_else(|| Error::UnknownAggregationOperation(format!("{} requires a document", name)))
+}
+
+fn compile_required(paths: &mut Vec<String>, registry: &OpRegistry, name: &str, doc: &Document, field: &str) -> Result<OperatorExpr> {
+    let value = doc.get(field)
+        .ok_or_else(|| Error::UnknownAggregationOperation(format!("{} requires '{}'", name, field)))?;
+    OperatorExpr::compile_operand(paths, registry, value)
+}
+
+/// `$dateTrunc` truncates `date` to the start of its bucket, see [`DateBucket`].
+/// The result is null if `date` is not a date.
+pub(crate) struct DateTruncOperator {
+    date: OperatorExpr,
+    bucket: DateBucket,
+}
+
+impl DateTruncOperator {
+
+    pub(crate) fn compile(paths: &mut Vec<String>, registry: &OpRegistry, v: &Bson) -> Result<Box<dyn VmOperator>> {
+        let doc = operator_doc("$dateTrunc", v)?;
+        Ok(Box::new(DateTruncOperator {
+            date: compile_required(paths, registry, "$dateTrunc", doc, "date")?,
+            bucket: DateBucket::from_doc(doc)?,
+        }))
+    }
+
+}
+
+impl VmOperator for DateTruncOperator {
+    fn initial_value(&self) -> Bson {
+        self.next(&Bson::Null)
+    }
+
+    fn next(&self, input: &Bson) -> Bson {
+        match self.date.next(input) {
+            Bson::DateTime(date) => Bson::DateTime(self.bucket.trunc(date)),
+            _ => Bson::Null,
+        }
+    }
+
+    fn complete(&self) -> Bson {
+        self.next(&Bson::Null)
+    }
+}
+
+/// `$dateAdd` adds `amount` units to `startDate`, the amount is a constant integer.
+/// The result is null if `startDate` is not a date.
+pub(crate) struct DateAddOperator {
+    start_date: OperatorExpr,
+    unit: DateUnit,
+    amount: i64,
+    timezone: TimeZone,
+}
+
+impl DateAddOperator {
+
+    pub(crate) fn compile(paths: &mut Vec<String>, registry: &OpRegistry, v: &Bson) -> Result<Box<dyn VmOperator>> {
+        let doc = operator_doc("$dateAdd", v)?;
+        let unit = match doc.get("unit") {
+            Some(Bson::String(unit)) => unit.parse::<DateUnit>()?,
+            _ => return Err(Error::UnknownAggregationOperation("$dateAdd requires 'unit'".to_string())),
+        };
+        let amount = match doc.get("amount") {
+            Some(Bson::Int32(n)) => *n as i64,
+            Some(Bson::Int64(n)) => *n,
+            _ => return Err(Error::UnknownAggregationOperation("$dateAdd requires an integer 'amount'".to_string())),
+        };
+        let timezone = match doc.get("timezone") {
+            Some(timezone) => parse_timezone(timezone)?,
+            None => TimeZone::UTC,
+        };
+        Ok(Box::new(DateAddOperator {
+            start_date: compile_required(paths, registry, "$dateAdd", doc, "startDate")?,
+            unit,
+            amount,
+            timezone,
+        }))
+    }
+
+}
+
+impl VmOperator for DateAddOperator {
+    fn initial_value(&self) -> Bson {
+        self.next(&Bson::Null)
+    }
+
+    fn next(&self, input: &Bson) -> Bson {
+        match self.start_date.next(input) {
+            Bson::DateTime(date) => Bson::DateTime(date_add(date, self.unit, self.amount, self.timezone)),
+            _ => Bson::Null,
+        }
+    }
+
+    fn complete(&self) -> Bson {
+        self.next(&Bson::Null)
+    }
+}
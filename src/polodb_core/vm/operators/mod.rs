@@ -1,8 +1,13 @@
 mod sum_operator;
 mod op_registry;
 mod abs_operator;
+mod add_operator;
+mod case_operator;
+mod concat_operator;
+mod date_operator;
 
-use bson::Bson;
+use bson::{Bson, DateTime};
+use crate::{Error, Result};
 
 pub(crate) trait VmOperator {
 
@@ -20,6 +25,42 @@ pub(crate) enum OperatorExpr {
     Alias(String),
 }
 
+impl OperatorExpr {
+
+    /// Compile an operand of an operator: an expression, a field path or a constant.
+    pub(crate) fn compile_operand(paths: &mut Vec<String>, registry: &OpRegistry, v: &Bson) -> Result<OperatorExpr> {
+        let expr = match v {
+            Bson::Document(doc) => OperatorExpr::Expr(registry.compile_doc(paths, doc)?),
+            // the time is fixed when the operator is compiled, like MongoDB
+            Bson::String(name) if name == "$$NOW" => OperatorExpr::Constant(Bson::DateTime(DateTime::now())),
+            Bson::String(name) if name.starts_with("$$") => {
+                return Err(Error::ValidationError(format!("unknown variable: {}", name)));
+            }
+            Bson::String(field_name) if field_name.starts_with('$') => {
+                OperatorExpr::Alias(field_name[1..].to_string())
+            }
+            _ => OperatorExpr::Constant(v.clone()),
+        };
+        Ok(expr)
+    }
+
+    pub(crate) fn next(&self, input: &Bson) -> Bson {
+        match self {
+            OperatorExpr::Constant(v) => v.clone(),
+            OperatorExpr::Expr(op) => op.next(input),
+            OperatorExpr::Alias(field_name) => match input {
+                Bson::Document(doc) => crate::utils::bson::try_get_document_value(doc, field_name),
+                _ => None,
+            }.unwrap_or(Bson::Null),
+        }
+    }
+
+}
+
 pub(crate) use sum_operator::SumOperator;
 pub(crate) use abs_operator::AbsOperator;
+pub(crate) use add_operator::AddOperator;
+pub(crate) use case_operator::CaseOperator;
+pub(crate) use concat_operator::ConcatOperator;
+pub(crate) use date_operator::{DateAddOperator, DateTruncOperator};
 pub(crate) use op_registry::OpRegistry;
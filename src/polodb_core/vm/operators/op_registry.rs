@@ -15,7 +15,9 @@
 use bson::{Bson, Document};
 use crate::{Error, Result};
 use crate::errors::mk_invalid_aggregate_field;
-use crate::vm::operators::{AbsOperator, SumOperator, VmOperator};
+use crate::vm::operators::{
+    AbsOperator, AddOperator, CaseOperator, ConcatOperator, DateAddOperator, DateTruncOperator, SumOperator, VmOperator,
+};
 
 // Reference: https://www.mongodb.com/docs/manual/reference/operator/aggregation/
 #[derive(Clone)]
@@ -41,6 +43,12 @@ impl OpRegistry {
             match op_name.as_str() {
                 "$sum" => SumOperator::compile(op_value),
                 "$abs" => AbsOperator::compile(paths, self.clone(), op_value)?,
+                "$add" => AddOperator::compile(paths, self, op_value)?,
+                "$concat" => ConcatOperator::compile(paths, self, op_value)?,
+                "$toUpper" => CaseOperator::compile(paths, self, op_value, true)?,
+                "$toLower" => CaseOperator::compile(paths, self, op_value, false)?,
+                "$dateTrunc" => DateTruncOperator::compile(paths, self, op_value)?,
+                "$dateAdd" => DateAddOperator::compile(paths, self, op_value)?,
                 _ => {
                     let invalid_err = mk_invalid_aggregate_field(paths);
                     return Err(Error::InvalidField(invalid_err))
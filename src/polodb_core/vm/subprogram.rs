@@ -17,11 +17,16 @@ use super::label::LabelSlot;
 use super::op::DbOp;
 use crate::coll::collection_info::{CollectionSpecification, IndexInfo};
 use crate::utils::str::escape_binary_to_string;
+use crate::utils::spill::SpillOptions;
+use crate::utils::json_schema::JsonSchema;
+use crate::IndexOptions;
+use crate::index::vector::Similarity;
 use crate::vm::codegen::Codegen;
-use crate::{Result};
-use bson::{Bson, Document};
+use crate::{Error, Result};
+use bson::{doc, Bson, Document};
 use indexmap::IndexMap;
 use std::fmt;
+use std::ops::Bound;
 use std::rc::Rc;
 use crate::errors::FieldTypeUnexpectedStruct;
 use crate::vm::aggregation_codegen_context::AggregationCodeGenContext;
@@ -34,14 +39,63 @@ pub(crate) struct SubProgramIndexItem {
     pub indexes: IndexMap<String, IndexInfo>,
 }
 
+/// The `$search` of a `$text` query, analyzed by the analyzer of the text index
+/// when the program runs, because the tokenizers are registered on the database.
+pub(crate) struct TextSearch {
+    pub field: String,
+    pub options: Option<IndexOptions>,
+    pub search: String,
+}
+
+/// The entries of the terms of an index, which find the candidates of a query.
+pub(crate) struct IndexCandidates {
+    pub col_name: String,
+    pub index_name: String,
+    pub terms: Vec<String>,
+}
+
+/// The `$vectorSearch` stage, which finds the candidates in the lists of a vector index.
+pub(crate) struct VectorSearch {
+    pub col_name: String,
+    pub index_name: String,
+    pub similarity: Similarity,
+    pub centroids: Vec<Vec<f32>>,
+    pub query_vector: Vec<f32>,
+    pub num_candidates: usize,
+    /// The field of the documents to set to the score of the similarity
+    pub score_field: Option<String>,
+}
+
+/// The `$geoNear` stage, which finds the candidates in the cells of a 2dsphere index.
+pub(crate) struct GeoSearch {
+    pub col_name: String,
+    pub index_name: String,
+    pub near: (f64, f64),
+    pub min_distance: f64,
+    pub max_distance: Option<f64>,
+    /// The field of the documents to set to the distance in meters
+    pub distance_field: String,
+    /// The number of the nearest candidates kept, if the stage is followed by a `$limit`
+    pub limit: Option<usize>,
+}
+
 pub(crate) struct SubProgram {
     pub(super) static_values: Vec<Bson>,
     pub(super) instructions: Vec<u8>,
     pub(super) global_variables: Vec<GlobalVariableSlot>,
     pub(super) label_slots: Vec<LabelSlot>,
     pub(super) index_infos: Vec<SubProgramIndexItem>,
+    pub(super) json_schemas: Vec<JsonSchema>,
+    pub(super) text_searches: Vec<TextSearch>,
+    pub(super) index_candidates: Vec<IndexCandidates>,
+    pub(super) vector_searches: Vec<VectorSearch>,
+    pub(super) geo_searches: Vec<GeoSearch>,
+    /// The key ordering of the scanned collection, which encodes the primary keys of the program
+    pub(super) key_ordering: Option<String>,
     pub(crate) external_funcs: Vec<Box<dyn VmExternalFunc>>,
     pub(crate) update_operators: Vec<Box<dyn UpdateOperator>>,
+    /// How the documents are found, reported by the profiler
+    pub(crate) plan: Option<String>,
 }
 
 impl SubProgram {
@@ -52,8 +106,21 @@ impl SubProgram {
             global_variables: Vec::with_capacity(16),
             label_slots: Vec::with_capacity(32),
             index_infos: Vec::new(),
+            json_schemas: Vec::new(),
+            text_searches: Vec::new(),
+            index_candidates: Vec::new(),
+            vector_searches: Vec::new(),
+            geo_searches: Vec::new(),
+            key_ordering: None,
             external_funcs: Vec::new(),
             update_operators: Vec::new(),
+            plan: None,
+        }
+    }
+
+    pub(crate) fn set_spill_options(&mut self, options: &SpillOptions) {
+        for func in &mut self.external_funcs {
+            func.set_spill_options(options);
         }
     }
 
@@ -70,6 +137,8 @@ impl SubProgram {
         query: &Document,
         skip_annotation: bool,
     ) -> Result<SubProgram> {
+        crate::trace_span!("polodb.plan", collection = col_spec.name());
+
         if query.is_empty() {
             return SubProgram::compile_query_all(col_spec, skip_annotation);
         }
@@ -91,6 +160,39 @@ impl SubProgram {
         Ok(codegen.take())
     }
 
+    /// Compile the query of one partition of a parallel scan,
+    /// the collection scan only reads the primary keys in `range`.
+    pub(crate) fn compile_query_partition(
+        col_spec: &CollectionSpecification,
+        query: &Document,
+        range: Document,
+        skip_annotation: bool,
+    ) -> Result<SubProgram> {
+        crate::trace_span!("polodb.plan", collection = col_spec.name());
+
+        let mut codegen = Codegen::new(skip_annotation, false);
+        codegen.set_scan_range(range);
+
+        if query.is_empty() {
+            let scan_ops = codegen.emit_open_scan(col_spec)?;
+            return SubProgram::compile_scan_all(codegen, scan_ops);
+        }
+
+        codegen.emit_query_layout(
+            col_spec,
+            query,
+            |codegen| -> Result<()> {
+                codegen.emit(DbOp::ResultRow);
+                codegen.emit(DbOp::Pop);
+                Ok(())
+            },
+            None,
+            true,
+        )?;
+
+        Ok(codegen.take())
+    }
+
     pub(crate) fn compile_update(
         col_spec: &CollectionSpecification,
         query: &Document,
@@ -98,12 +200,14 @@ impl SubProgram {
         skip_annotation: bool,
         is_many: bool,
     ) -> Result<SubProgram> {
+        crate::trace_span!("polodb.plan", collection = col_spec.name());
+
         let mut codegen = Codegen::new(skip_annotation, true);
 
         let has_indexes = !col_spec.indexes.is_empty();
         let index_item_id: u32 = if has_indexes {
             codegen.push_index_info(SubProgramIndexItem {
-                col_name: col_spec._id.to_string(),
+                col_name: col_spec.storage_name().to_string(),
                 indexes: col_spec.indexes.clone(),
             })
         } else {
@@ -138,24 +242,25 @@ impl SubProgram {
 
     pub(crate) fn compile_delete(
         col_spec: &CollectionSpecification,
-        col_name: &str,
         query: Option<&Document>,
         skip_annotation: bool,
         is_many: bool,
     ) -> Result<SubProgram> {
+        crate::trace_span!("polodb.plan", collection = col_spec.name());
+
         let mut codegen = Codegen::new(skip_annotation, true);
 
         let has_indexes = !col_spec.indexes.is_empty();
         let index_item_id: u32 = if has_indexes {
             codegen.push_index_info(SubProgramIndexItem {
-                col_name: col_spec._id.to_string(),
+                col_name: col_spec.storage_name().to_string(),
                 indexes: col_spec.indexes.clone(),
             })
         } else {
             u32::MAX
         };
 
-        codegen.emit_open(col_name.into());
+        codegen.emit_open(col_spec.storage_name().into());
 
         codegen.emit_query_layout(
             col_spec,
@@ -180,15 +285,16 @@ impl SubProgram {
     // TODO: need test
     pub(crate) fn compile_delete_all(
         col_spec: &CollectionSpecification,
-        col_name: &str,
         skip_annotation: bool,
     ) -> Result<SubProgram> {
+        crate::trace_span!("polodb.plan", collection = col_spec.name());
+
         let mut codegen = Codegen::new(skip_annotation, true);
 
         let has_indexes = !col_spec.indexes.is_empty();
         let index_item_id: u32 = if has_indexes {
             codegen.push_index_info(SubProgramIndexItem {
-                col_name: col_spec._id.to_string(),
+                col_name: col_spec.storage_name().to_string(),
                 indexes: col_spec.indexes.clone(),
             })
         } else {
@@ -199,7 +305,7 @@ impl SubProgram {
         let next_label = codegen.new_label();
         let close_label = codegen.new_label();
 
-        codegen.emit_open(col_name.into());
+        codegen.emit_open(col_spec.storage_name().into());
 
         codegen.emit_goto(DbOp::Rewind, close_label);
 
@@ -229,7 +335,67 @@ impl SubProgram {
         col_spec: &CollectionSpecification,
         skip_annotation: bool,
     ) -> Result<SubProgram> {
-        SubProgram::compile_query_all_by_name(col_spec.name(), skip_annotation)
+        let mut codegen = Codegen::new(skip_annotation, false);
+        let scan_ops = codegen.emit_open_scan(col_spec)?;
+        SubProgram::compile_scan_all(codegen, scan_ops)
+    }
+
+    /// Scan the documents whose `_id` is between the bounds, without a filter.
+    ///
+    /// The bounds are compared as the stored keys, so they must have the same type
+    /// and the numbers must not be negative, whose keys are not in the order of the values,
+    /// unless the collection has a key ordering, which compares them by their sort keys.
+    pub(crate) fn compile_query_range(
+        col_spec: &CollectionSpecification,
+        lower_bound: Bound<&Bson>,
+        upper_bound: Bound<&Bson>,
+        skip_annotation: bool,
+    ) -> Result<SubProgram> {
+        crate::trace_span!("polodb.plan", collection = col_spec.name());
+
+        if col_spec.is_timeseries() {
+            return Err(Error::ValidationError("the time-series collections don't support range scans".to_string()));
+        }
+        if col_spec.is_partitioned() {
+            return Err(Error::ValidationError("the partitioned collections don't support range scans".to_string()));
+        }
+
+        let mut range = Document::new();
+        for (bound, inclusive, exclusive) in [(lower_bound, "$gte", "$gt"), (upper_bound, "$lte", "$lt")] {
+            let (key, value) = match bound {
+                Bound::Included(value) => (inclusive, value),
+                Bound::Excluded(value) => (exclusive, value),
+                Bound::Unbounded => continue,
+            };
+            if col_spec.key_ordering.is_some() {
+                range.insert(key, value.clone());
+                continue;
+            }
+            let negative = match value {
+                Bson::Int32(v) => *v < 0,
+                Bson::Int64(v) => *v < 0,
+                Bson::Double(v) => v.is_sign_negative(),
+                Bson::DateTime(v) => v.timestamp_millis() < 0,
+                _ => false,
+            };
+            if negative {
+                return Err(Error::ValidationError(format!("the bound of the range can't be negative: {}", value)));
+            }
+            if let Some(other) = range.values().next() {
+                if other.element_type() != value.element_type() {
+                    return Err(Error::ValidationError(format!(
+                        "the bounds of the range have different types: {} and {}",
+                        other,
+                        value,
+                    )));
+                }
+            }
+            range.insert(key, value.clone());
+        }
+
+        let mut codegen = Codegen::new(skip_annotation, false);
+        codegen.emit_open_range(col_spec, range);
+        SubProgram::compile_scan_all(codegen, (DbOp::Rewind, DbOp::Next))
     }
 
     pub(crate) fn compile_query_all_by_name(
@@ -237,18 +403,22 @@ impl SubProgram {
         skip_annotation: bool,
     ) -> Result<SubProgram> {
         let mut codegen = Codegen::new(skip_annotation, false);
+        codegen.emit_open(col_name.into());
+        SubProgram::compile_scan_all(codegen, (DbOp::Rewind, DbOp::Next))
+    }
+
+    fn compile_scan_all(mut codegen: Codegen, scan_ops: (DbOp, DbOp)) -> Result<SubProgram> {
+        let (rewind_op, next_op) = scan_ops;
         let result_label = codegen.new_label();
         let next_label = codegen.new_label();
         let close_label = codegen.new_label();
 
-        codegen.emit_open(col_name.into());
-
-        codegen.emit_goto(DbOp::Rewind, close_label);
+        codegen.emit_goto(rewind_op, close_label);
 
         codegen.emit_goto(DbOp::Goto, result_label);
 
         codegen.emit_label(next_label);
-        codegen.emit_goto(DbOp::Next, result_label);
+        codegen.emit_goto(next_op, result_label);
 
         codegen.emit_label(close_label);
         codegen.emit(DbOp::Close);
@@ -269,17 +439,79 @@ impl SubProgram {
         pipeline: impl IntoIterator<Item = Document>,
         skip_annotation: bool,
     ) -> Result<SubProgram> {
-        let pipeline_vec: Vec<Document> = pipeline.into_iter().collect();
+        crate::trace_span!("polodb.plan", collection = col_spec.name());
+
+        let codegen = Codegen::new(skip_annotation, false);
+        SubProgram::compile_aggregate_with_codegen(codegen, col_spec, pipeline.into_iter().collect())
+    }
+
+    /// Compile the leading stages of one partition of a parallel aggregation,
+    /// the collection scan only reads the primary keys in `range`.
+    pub(crate) fn compile_aggregate_partition(
+        col_spec: &CollectionSpecification,
+        pipeline: Vec<Document>,
+        range: Document,
+        skip_annotation: bool,
+    ) -> Result<SubProgram> {
+        crate::trace_span!("polodb.plan", collection = col_spec.name());
+
+        let mut codegen = Codegen::new(skip_annotation, false);
+        codegen.set_scan_range(range);
+        SubProgram::compile_aggregate_with_codegen(codegen, col_spec, pipeline)
+    }
+
+    fn compile_aggregate_with_codegen(
+        mut codegen: Codegen,
+        col_spec: &CollectionSpecification,
+        mut pipeline_vec: Vec<Document>,
+    ) -> Result<SubProgram> {
+        if let Some(reverse) = take_natural_order(&mut pipeline_vec)? {
+            // the other collections are in the order of the primary keys
+            if !col_spec.is_capped() && !col_spec.is_timeseries() {
+                return Err(Error::ValidationError("$natural is only supported by the capped and the time-series collections".to_string()));
+            }
+            // the buckets are only kept in the forward order
+            if reverse && col_spec.is_timeseries() {
+                return Err(Error::ValidationError("the time-series collections can't be scanned in the reverse natural order".to_string()));
+            }
+            codegen.set_natural_order(reverse);
+        }
+
         if pipeline_vec.is_empty() {
-            return SubProgram::compile_query_all(col_spec, skip_annotation);
+            let scan_ops = codegen.emit_open_scan(col_spec)?;
+            return SubProgram::compile_scan_all(codegen, scan_ops);
         }
 
         let first = pipeline_vec.first().unwrap();
         if first.len() == 1 && first.contains_key("$match") {
-            return SubProgram::compile_aggregate_with_match(col_spec, pipeline_vec, skip_annotation);
+            return SubProgram::compile_aggregate_with_match(codegen, col_spec, pipeline_vec);
+        }
+        if first.len() == 1 && first.contains_key("$vectorSearch") {
+            return SubProgram::compile_aggregate_with_vector_search(codegen, col_spec, pipeline_vec);
+        }
+        if first.len() == 1 && first.contains_key("$geoNear") {
+            return SubProgram::compile_aggregate_with_geo_near(codegen, col_spec, pipeline_vec);
         }
 
+        let scan_ops = codegen.emit_open_scan(col_spec)?;
+        SubProgram::compile_aggregate_stages(codegen, pipeline_vec, scan_ops)
+    }
+
+    /// Compile the stages after the stages of the partitions of a parallel aggregation,
+    /// which read the documents returned by the partitions from the input of the VM.
+    pub(crate) fn compile_aggregate_of_input(pipeline_vec: Vec<Document>, skip_annotation: bool) -> Result<SubProgram> {
         let mut codegen = Codegen::new(skip_annotation, false);
+        let scan_ops = codegen.emit_open_input();
+        SubProgram::compile_aggregate_stages(codegen, pipeline_vec, scan_ops)
+    }
+
+    // Pass the documents of the opened cursor to the stages of the pipeline.
+    fn compile_aggregate_stages(
+        mut codegen: Codegen,
+        pipeline_vec: Vec<Document>,
+        scan_ops: (DbOp, DbOp),
+    ) -> Result<SubProgram> {
+        let (rewind_op, next_op) = scan_ops;
         let result_label = codegen.new_label();
         let next_label = codegen.new_label();
         let close_label = codegen.new_label();
@@ -289,14 +521,12 @@ impl SubProgram {
         // set up the slots for the aggregation pipeline
         codegen.emit_aggregation_before_query(&mut ctx, &pipeline_vec)?;
 
-        codegen.emit_open(col_spec.name().into());
-
-        codegen.emit_goto(DbOp::Rewind, close_label);
+        codegen.emit_goto(rewind_op, close_label);
 
         codegen.emit_goto(DbOp::Goto, result_label);
 
         codegen.emit_label(next_label);
-        codegen.emit_goto(DbOp::Next, result_label);
+        codegen.emit_goto(next_op, result_label);
 
         // emit the result row
         codegen.emit_label(close_label);
@@ -312,13 +542,44 @@ impl SubProgram {
         Ok(codegen.take())
     }
 
+    // The $vectorSearch scans the candidates of the vector index,
+    // its filter is the query and its limit is the next stage.
+    fn compile_aggregate_with_vector_search(
+        mut codegen: Codegen,
+        col_spec: &CollectionSpecification,
+        mut pipeline_vec: Vec<Document>,
+    ) -> Result<SubProgram> {
+        let stage = pipeline_vec.remove(0);
+        let (filter, limit) = codegen.push_vector_search(col_spec, stage.get("$vectorSearch").unwrap())?;
+        pipeline_vec.insert(0, doc! { "$match": filter });
+        pipeline_vec.insert(1, doc! { "$limit": limit });
+        SubProgram::compile_aggregate_with_match(codegen, col_spec, pipeline_vec)
+    }
+
+    // The $geoNear scans the candidates of the 2dsphere index, its query is the first $match.
+    // Only the nearest candidates are kept if the next stage is a $limit and there is no query.
+    fn compile_aggregate_with_geo_near(
+        mut codegen: Codegen,
+        col_spec: &CollectionSpecification,
+        mut pipeline_vec: Vec<Document>,
+    ) -> Result<SubProgram> {
+        let stage = pipeline_vec.remove(0);
+        let limit = match pipeline_vec.first().and_then(|stage| stage.get("$limit")) {
+            Some(Bson::Int32(limit)) if *limit >= 0 => Some(*limit as usize),
+            Some(Bson::Int64(limit)) if *limit >= 0 => Some(*limit as usize),
+            _ => None,
+        };
+        let query = codegen.push_geo_near(col_spec, stage.get("$geoNear").unwrap(), limit)?;
+        pipeline_vec.insert(0, doc! { "$match": query });
+        SubProgram::compile_aggregate_with_match(codegen, col_spec, pipeline_vec)
+    }
+
     // If the first pipeline is $match, the process will leverage the index.
-    pub(crate) fn compile_aggregate_with_match(
+    fn compile_aggregate_with_match(
+        mut codegen: Codegen,
         col_spec: &CollectionSpecification,
         pipeline_vec: Vec<Document>,
-        skip_annotation: bool,
     ) -> Result<SubProgram> {
-        let mut codegen = Codegen::new(skip_annotation, false);
         let first_doc = pipeline_vec.first().unwrap();
         let query_doc_value = first_doc.get("$match").unwrap();
         let query_doc = match query_doc_value {
@@ -361,6 +622,36 @@ impl SubProgram {
 
 }
 
+/// Remove the `$sort` stage of `$natural` from the pipeline, which is only
+/// allowed as the first stage or after the first `$match`.
+/// Return `Some(reverse)` if it's found.
+fn take_natural_order(pipeline_vec: &mut Vec<Document>) -> Result<Option<bool>> {
+    let natural_order = |stage: &Document| -> Option<Bson> {
+        let sort = stage.get_document("$sort").ok()?;
+        sort.get("$natural").cloned()
+    };
+    let position = pipeline_vec.iter().position(|stage| natural_order(stage).is_some());
+    let position = match position {
+        Some(position) => position,
+        None => return Ok(None),
+    };
+
+    let after_match = position == 1 && pipeline_vec[0].len() == 1 && pipeline_vec[0].contains_key("$match");
+    if position != 0 && !after_match {
+        return Err(Error::ValidationError("$natural must be sorted before the other stages except the first $match".to_string()));
+    }
+    if pipeline_vec[position].get_document("$sort").unwrap().len() != 1 {
+        return Err(Error::ValidationError("$natural can't be sorted with the other fields".to_string()));
+    }
+    let reverse = match natural_order(&pipeline_vec[position]).unwrap() {
+        Bson::Int32(1) | Bson::Int64(1) => false,
+        Bson::Int32(-1) | Bson::Int64(-1) => true,
+        _ => return Err(Error::ValidationError("$natural must be 1 or -1".to_string())),
+    };
+    pipeline_vec.remove(position);
+    Ok(Some(reverse))
+}
+
 fn open_bson_to_str(val: &Bson) -> Result<String> {
     let (str, is_bin) = match val {
         Bson::String(s) => (s.clone(), false),
@@ -469,6 +760,73 @@ impl fmt::Display for SubProgram {
                         pc += 5;
                     }
 
+                    DbOp::LoadIndexCandidates => {
+                        let id = begin.add(pc + 1).cast::<u32>().read();
+                        writeln!(f, "{}: LoadIndexCandidates({})", pc, id)?;
+                        pc += 5;
+                    }
+
+                    DbOp::LoadVectorCandidates => {
+                        let id = begin.add(pc + 1).cast::<u32>().read();
+                        writeln!(f, "{}: LoadVectorCandidates({})", pc, id)?;
+                        pc += 5;
+                    }
+
+                    DbOp::LoadGeoCandidates => {
+                        let id = begin.add(pc + 1).cast::<u32>().read();
+                        writeln!(f, "{}: LoadGeoCandidates({})", pc, id)?;
+                        pc += 5;
+                    }
+
+                    DbOp::RewindCandidates => {
+                        let location = begin.add(pc + 1).cast::<u32>().read();
+                        writeln!(f, "{}: RewindCandidates({})", pc, location)?;
+                        pc += 5;
+                    }
+
+                    DbOp::NextCandidate => {
+                        let location = begin.add(pc + 1).cast::<u32>().read();
+                        writeln!(f, "{}: NextCandidate({})", pc, location)?;
+                        pc += 5;
+                    }
+
+                    DbOp::RewindInput => {
+                        let location = begin.add(pc + 1).cast::<u32>().read();
+                        writeln!(f, "{}: RewindInput({})", pc, location)?;
+                        pc += 5;
+                    }
+
+                    DbOp::NextInput => {
+                        let location = begin.add(pc + 1).cast::<u32>().read();
+                        writeln!(f, "{}: NextInput({})", pc, location)?;
+                        pc += 5;
+                    }
+
+                    DbOp::UnpackBuckets => {
+                        let index = begin.add(pc + 1).cast::<u32>().read();
+                        let val = &self.static_values[index as usize];
+                        writeln!(f, "{}: UnpackBuckets({})", pc, val)?;
+                        pc += 5;
+                    }
+
+                    DbOp::SetRange => {
+                        let index = begin.add(pc + 1).cast::<u32>().read();
+                        let val = &self.static_values[index as usize];
+                        writeln!(f, "{}: SetRange({})", pc, val)?;
+                        pc += 5;
+                    }
+
+                    DbOp::SetReverse => {
+                        writeln!(f, "{}: SetReverse", pc)?;
+                        pc += 1;
+                    }
+
+                    DbOp::RewindIndex => {
+                        let location = begin.add(pc + 1).cast::<u32>().read();
+                        writeln!(f, "{}: RewindIndex({})", pc, location)?;
+                        pc += 5;
+                    }
+
                     DbOp::PushValue => {
                         let index = begin.add(pc + 1).cast::<u32>().read();
                         let val = &self.static_values[index as usize];
@@ -582,6 +940,16 @@ impl fmt::Display for SubProgram {
                         pc += 1;
                     }
 
+                    DbOp::Contains => {
+                        writeln!(f, "{}: Contains", pc)?;
+                        pc += 1;
+                    }
+
+                    DbOp::Fuzzy => {
+                        writeln!(f, "{}: Fuzzy", pc)?;
+                        pc += 1;
+                    }
+
                     DbOp::Not => {
                         writeln!(f, "{}: Not", pc)?;
                         pc += 1;
@@ -597,6 +965,24 @@ impl fmt::Display for SubProgram {
                         pc += 1;
                     }
 
+                    DbOp::MatchJsonSchema => {
+                        let id = begin.add(pc + 1).cast::<u32>().read();
+                        writeln!(f, "{}: MatchJsonSchema({})", pc, id)?;
+                        pc += 5;
+                    }
+
+                    DbOp::MatchText => {
+                        let id = begin.add(pc + 1).cast::<u32>().read();
+                        writeln!(f, "{}: MatchText({})", pc, id)?;
+                        pc += 5;
+                    }
+
+                    DbOp::PushTextTerm => {
+                        let id = begin.add(pc + 1).cast::<u32>().read();
+                        writeln!(f, "{}: PushTextTerm({})", pc, id)?;
+                        pc += 5;
+                    }
+
                     DbOp::OpenRead => {
                         let idx = begin.add(pc + 1).cast::<u32>().read();
                         let value = &self.static_values[idx as usize];
@@ -741,7 +1127,7 @@ impl fmt::Display for SubProgram {
 
 #[cfg(test)]
 mod tests {
-    use crate::coll::collection_info::{CollectionSpecification, IndexInfo};
+    use crate::coll::collection_info::{CollectionSpecification, IndexInfo, IndexKind};
     use crate::vm::SubProgram;
     use bson::{doc, Regex};
     use indexmap::indexmap;
@@ -938,6 +1324,9 @@ mod tests {
                     "age".into() => 1,
                 },
                 options: None,
+                stats: None,
+                kind: IndexKind::Ascending,
+                centroids: Vec::new(),
             },
         );
 
@@ -967,15 +1356,23 @@ mod tests {
 43: Halt
 
 44: Label(1)
-49: GetField("name", 35)
+49: GetField("name", 85)
 58: PushValue("Vincent Chan")
 63: Equal
-64: FalseJump(35)
+64: FalseJump(78)
 69: Pop
 70: Pop
 71: ResultRow
 72: Pop
 73: Goto(25)
+
+78: Label(3)
+83: Pop
+84: Pop
+
+85: Label(4)
+90: Pop
+91: Goto(25)
 "#;
         assert_eq!(expect, actual);
     }
@@ -1388,6 +1785,9 @@ mod tests {
                     "age".into() => 1,
                 },
                 options: None,
+                stats: None,
+                kind: IndexKind::Ascending,
+                centroids: Vec::new(),
             },
         );
 
@@ -1,6 +1,7 @@
 use bson::{Bson, Document};
 use crate::vm::update_operators::{UpdateOperator, UpdateResult};
 use crate::{Error, Result};
+use crate::utils::decimal::decimal_operands;
 use crate::errors::CannotApplyOperationForTypes;
 
 pub(crate) struct IncOperator {
@@ -17,6 +18,9 @@ impl IncOperator {
     }
 
     fn inc_numeric(key: &str, a: &Bson, b: &Bson) -> Result<Bson> {
+        if let Some((a, b)) = decimal_operands(a, b) {
+            return Ok(Bson::Decimal128(a.add(&b).to_decimal128()));
+        }
         let val = match (a, b) {
             (Bson::Int32(a), Bson::Int32(b)) => Bson::Int32(*a + *b),
             (Bson::Int32(a), Bson::Int64(b)) => Bson::Int64(*a as i64 + *b),
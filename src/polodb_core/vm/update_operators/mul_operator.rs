@@ -1,6 +1,7 @@
 use bson::{Bson, Document};
 use crate::errors::CannotApplyOperationForTypes;
 use crate::vm::update_operators::{UpdateOperator, UpdateResult};
+use crate::utils::decimal::decimal_operands;
 use crate::Result;
 
 pub(crate) struct MulOperator {
@@ -17,6 +18,9 @@ impl MulOperator {
     }
 
     fn mul_numeric(key: &str, a: &Bson, b: &Bson) -> Result<Bson> {
+        if let Some((a, b)) = decimal_operands(a, b) {
+            return Ok(Bson::Decimal128(a.mul(&b).to_decimal128()));
+        }
         let val = match (a, b) {
             (Bson::Int32(a), Bson::Int32(b)) => Bson::Int32(*a * *b),
             (Bson::Int32(a), Bson::Int64(b)) => Bson::Int64(*a as i64 * *b),
@@ -12,11 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::coll::key_ordering::{self, KeyOrdering};
+use crate::coll::timeseries::BucketUnpacker;
+use crate::coll::validator::CollectionValidator;
 use crate::cursor::Cursor;
+use crate::db::{CancellationToken, OperationGuard, ProfileSpan};
+use crate::db::parallel_scan::ParallelScan;
 use crate::errors::{
     FieldTypeUnexpectedStruct, RegexError, UnexpectedTypeForOpStruct,
 };
-use crate::index::{IndexHelper, IndexHelperOperation};
+use crate::index::{geo, trigram, vector, Analyzer, IndexHelper, IndexHelperOperation};
 use crate::transaction::TransactionInner;
 use crate::vm::op::{generic_cmp, DbOp};
 use crate::vm::SubProgram;
@@ -25,6 +30,10 @@ use bson::{Bson, Document};
 use regex::RegexBuilder;
 use std::cell::Cell;
 use std::cmp::Ordering;
+use std::collections::{HashSet, VecDeque};
+use std::ops::Bound;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use crate::vm::vm_external_func::VmExternalFuncStatus;
 
 macro_rules! try_vm {
@@ -70,6 +79,8 @@ pub(crate) struct VM {
     pc: *const u8,
     r0: i32, // usually the logic register
     r1: Option<Cursor>,
+    // set if the cursor reads the buckets of a time-series collection
+    bucket_unpacker: Option<BucketUnpacker>,
     pub(crate) r2: i64, // usually the counter
     r3: usize,
     pub(crate) r4: i64,
@@ -78,6 +89,29 @@ pub(crate) struct VM {
     pub(crate) program: SubProgram,
     global_vars: Vec<Bson>,
     metrics: Metrics,
+    max_document_size: u32,
+    validator: Option<Arc<CollectionValidator>>,
+    /// The count of the documents read from the storage
+    docs_examined: u64,
+    docs_returned: u64,
+    profile: Option<ProfileSpan>,
+    operation: Option<OperationGuard>,
+    cancellation_token: Option<CancellationToken>,
+    max_time: Option<Duration>,
+    /// The time spent by the previous calls of [`VM::execute`]
+    execution_time: Duration,
+    execution_start: Option<Instant>,
+    /// The analyzers and the terms of the `$text` searches of the program, analyzed on the first use.
+    text_searches: Vec<Option<(Analyzer, Vec<String>)>>,
+    /// The index keys of the candidates not read yet, and their scores,
+    /// see [`DbOp::LoadIndexCandidates`], [`DbOp::LoadVectorCandidates`] and [`DbOp::LoadGeoCandidates`].
+    candidates: VecDeque<(Vec<u8>, Option<f64>)>,
+    /// The field of the candidates to set to their scores
+    candidate_score_field: Option<String>,
+    /// The key ordering of the program, resolved on the first use
+    key_ordering: Option<Arc<dyn KeyOrdering>>,
+    /// The documents read by [`DbOp::RewindInput`] and [`DbOp::NextInput`]
+    input: Option<Box<ParallelScan>>,
 }
 
 unsafe impl Send for VM {}
@@ -88,6 +122,7 @@ impl VM {
         let stack = Vec::with_capacity(STACK_SIZE);
         let pc = program.instructions.as_ptr();
         let mut global_vars = Vec::<Bson>::new();
+        let text_searches = program.text_searches.iter().map(|_| None).collect();
 
         for item in &program.global_variables {
             global_vars.push(item.init_value.clone());
@@ -99,6 +134,7 @@ impl VM {
             pc,
             r0: 0,
             r1: None,
+            bucket_unpacker: None,
             r2: 0,
             r3: 0,
             r4: 0,
@@ -107,9 +143,94 @@ impl VM {
             program,
             global_vars,
             metrics,
+            max_document_size: u32::MAX,
+            validator: None,
+            docs_examined: 0,
+            docs_returned: 0,
+            profile: None,
+            operation: None,
+            cancellation_token: None,
+            max_time: None,
+            execution_time: Duration::ZERO,
+            execution_start: None,
+            text_searches,
+            candidates: VecDeque::new(),
+            candidate_score_field: None,
+            key_ordering: None,
+            input: None,
         }
     }
 
+    /// Record the query by the profiler when the program halts.
+    pub(crate) fn set_profile(&mut self, profile: Option<ProfileSpan>) {
+        self.profile = profile;
+    }
+
+    /// Register the program in the operations in flight until it's dropped.
+    pub(crate) fn set_operation(&mut self, operation: OperationGuard) {
+        self.operation = Some(operation);
+    }
+
+    pub(crate) fn set_cancellation_token(&mut self, token: CancellationToken) {
+        if let Some(input) = &mut self.input {
+            input.set_cancellation_token(token.clone());
+        }
+        self.cancellation_token = Some(token);
+    }
+
+    pub(crate) fn cancellation_token(&self) -> Option<&CancellationToken> {
+        self.cancellation_token.as_ref()
+    }
+
+    /// Fail the program with [`Error::MaxTimeExceeded`] when it has been executed longer than `max_time`.
+    /// The time between the calls of [`VM::execute`], such as the time the caller spends
+    /// between the documents of a cursor, is not counted.
+    pub(crate) fn set_max_time(&mut self, max_time: Duration) {
+        if let Some(input) = &mut self.input {
+            input.set_max_time(max_time);
+        }
+        self.max_time = Some(max_time);
+    }
+
+    /// Read the documents of the program from the scan of the partitions
+    /// instead of the collection.
+    pub(crate) fn set_input(&mut self, input: ParallelScan) {
+        self.input = Some(Box::new(input));
+    }
+
+    /// Checked before the scans read the next document.
+    #[inline]
+    fn check_cancelled(&self) -> Result<()> {
+        let killed = self.operation.as_ref().is_some_and(|op| op.is_cancelled());
+        let cancelled = self.cancellation_token.as_ref().is_some_and(|token| token.is_cancelled());
+        if killed || cancelled {
+            return Err(Error::OperationCancelled);
+        }
+        if let (Some(max_time), Some(start)) = (self.max_time, self.execution_start) {
+            if self.execution_time + start.elapsed() > max_time {
+                return Err(Error::MaxTimeExceeded(max_time));
+            }
+        }
+        Ok(())
+    }
+
+    fn finish_profile(&mut self) {
+        if let Some(profile) = self.profile.take() {
+            let plan = self.program.plan.as_deref().unwrap_or("EOF");
+            profile.finish(plan, self.docs_examined, self.docs_returned);
+        }
+    }
+
+    /// Reject the updated documents larger than `limit`.
+    pub(crate) fn set_max_document_size(&mut self, limit: u32) {
+        self.max_document_size = limit;
+    }
+
+    /// Validate the updated documents.
+    pub(crate) fn set_validator(&mut self, validator: Option<Arc<CollectionValidator>>) {
+        self.validator = validator;
+    }
+
     fn prefix_bytes_from_bson(val: Bson) -> Result<Vec<u8>> {
         match val {
             Bson::String(_) => {
@@ -144,13 +265,55 @@ impl VM {
         Ok(())
     }
 
+    /// The ordering of the primary keys of the program, registered on the database.
+    fn key_ordering(&mut self) -> Result<Option<Arc<dyn KeyOrdering>>> {
+        if self.key_ordering.is_none() {
+            if let Some(name) = &self.program.key_ordering {
+                self.key_ordering = Some(key_ordering::resolve(&self.txn, name)?);
+            }
+        }
+        Ok(self.key_ordering.clone())
+    }
+
+    fn set_range(&mut self, range: &Bson) -> Result<()> {
+        let range = range.as_document().expect("range must be a document");
+        let ordering = self.key_ordering()?;
+        let key_bound = |inclusive: &str, exclusive: &str, is_lower: bool| -> Result<Bound<Vec<u8>>> {
+            let bound = match (range.get(inclusive), range.get(exclusive)) {
+                (Some(value), _) => Bound::Included(value),
+                (None, Some(value)) => Bound::Excluded(value),
+                (None, None) => Bound::Unbounded,
+            };
+            let bound = match &ordering {
+                Some(ordering) => key_ordering::ordered_bound(ordering.as_ref(), bound, is_lower)?,
+                None => bound.cloned(),
+            };
+            Ok(match bound {
+                Bound::Included(value) => Bound::Included(crate::utils::bson::stacked_key([&value])?),
+                Bound::Excluded(value) => Bound::Excluded(crate::utils::bson::stacked_key([&value])?),
+                Bound::Unbounded => Bound::Unbounded,
+            })
+        };
+        let lower_bound = key_bound("$gte", "$gt", true)?;
+        let upper_bound = key_bound("$lte", "$lt", false)?;
+        self.r1.as_mut().unwrap().set_range(lower_bound, upper_bound);
+        Ok(())
+    }
+
     fn reset_cursor(&mut self, is_empty: &Cell<bool>) -> Result<()> {
         let cursor = self.r1.as_mut().unwrap();
         cursor.reset()?;
+        if let Some(unpacker) = self.bucket_unpacker.as_mut() {
+            unpacker.clear();
+            let found = self.next_measurement()?;
+            is_empty.set(!found);
+            return Ok(());
+        }
         if cursor.has_next() {
             let item = cursor.copy_data()?;
             let doc = bson::from_slice(item.as_ref())?;
             self.stack.push(Bson::Document(doc));
+            self.docs_examined += 1;
             is_empty.set(false);
         } else {
             is_empty.set(true);
@@ -159,12 +322,14 @@ impl VM {
     }
 
     fn find_by_primary_key(&mut self) -> Result<bool> {
+        let ordering = self.key_ordering()?;
         let cursor = self.r1.as_mut().unwrap();
 
         let top_index = self.stack.len() - 1;
         let op = &self.stack[top_index];
 
-        let result = cursor.reset_by_pkey(op)?;
+        let pkey = key_ordering::stored_key(ordering.as_deref(), op)?;
+        let result = cursor.reset_by_pkey(&pkey)?;
         if !result {
             return Ok(false);
         }
@@ -172,10 +337,13 @@ impl VM {
         let buf = cursor.copy_data()?;
         let doc = bson::from_slice(buf.as_ref())?;
         self.stack.push(Bson::Document(doc));
+        self.docs_examined += 1;
         Ok(true)
     }
 
     fn find_by_index(&mut self) -> Result<bool> {
+        crate::trace_span!("polodb.index_scan");
+
         let stack_len = self.stack.len();
         // let col_name = self.stack[stack_len - 1].as_str().expect("col_name must be string").to_string();
         let query_value = &self.stack[stack_len - 2];
@@ -198,6 +366,7 @@ impl VM {
         self.stack.push(index_value.unwrap());
 
         self.metrics.add_find_by_index_count();
+        crate::trace_event!("index entry found");
 
         Ok(true)
     }
@@ -229,11 +398,41 @@ impl VM {
 
         let buf = db_iter.copy_data()?;
         let doc = bson::from_slice(buf.as_ref())?;
+        self.docs_examined += 1;
 
         Ok(Some(Bson::Document(doc)))
     }
 
+    /// Push the next measurement of the buckets read by the cursor.
+    /// The cursor is moved past a bucket once it's unpacked.
+    fn next_measurement(&mut self) -> Result<bool> {
+        let cursor = self.r1.as_mut().unwrap();
+        let unpacker = self.bucket_unpacker.as_mut().unwrap();
+        loop {
+            if let Some(measurement) = unpacker.pop() {
+                self.stack.push(Bson::Document(measurement));
+                self.docs_examined += 1;
+                return Ok(true);
+            }
+            if !cursor.has_next() {
+                return Ok(false);
+            }
+            let bytes = cursor.copy_data()?;
+            let bucket: Document = bson::from_slice(bytes.as_ref())?;
+            unpacker.unpack(&bucket)?;
+            cursor.next()?;
+        }
+    }
+
     fn next(&mut self) -> Result<()> {
+        self.check_cancelled()?;
+
+        if self.bucket_unpacker.is_some() {
+            let found = self.next_measurement()?;
+            self.r0 = found as i32;
+            return Ok(());
+        }
+
         let cursor = self.r1.as_mut().unwrap();
         cursor.next()?;
 
@@ -241,6 +440,7 @@ impl VM {
             let bytes = cursor.copy_data()?;
             let doc = bson::from_slice(bytes.as_ref())?;
             self.stack.push(Bson::Document(doc));
+            self.docs_examined += 1;
 
             debug_assert!(
                 self.stack.len() <= 64,
@@ -256,7 +456,27 @@ impl VM {
         Ok(())
     }
 
+    fn rewind_index(&mut self) -> Result<bool> {
+        let cursor = self.r1.as_mut().unwrap();
+        cursor.reset()?;
+        if !cursor.has_next() {
+            return Ok(false);
+        }
+        let current_key = cursor.peek_key().expect("key must exist");
+
+        let value_opt = self.read_index_value_by_index_key(current_key.as_ref())?;
+        match value_opt {
+            Some(value) => {
+                self.stack.push(value);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
     fn next_index_value(&mut self) -> Result<()> {
+        self.check_cancelled()?;
+
         let cursor = self.r1.as_mut().unwrap();
         cursor.next()?;
         let current_key = cursor.peek_key();
@@ -384,6 +604,10 @@ impl VM {
 
         let txn = &self.txn;
         let doc = top_value.as_document().unwrap();
+        crate::utils::bson::check_document_size(doc, self.max_document_size)?;
+        if let Some(validator) = &self.validator {
+            validator.validate(doc)?;
+        }
         let doc_buf = bson::to_vec(doc)?;
 
         let updated = {
@@ -398,13 +622,204 @@ impl VM {
         Ok(())
     }
 
+    fn analyze_text_search(&mut self, search_id: u32) -> Result<&(Analyzer, Vec<String>)> {
+        let id = search_id as usize;
+        if self.text_searches[id].is_none() {
+            let search = &self.program.text_searches[id];
+            let txn = &self.txn;
+            let analyzer = Analyzer::from_options(
+                search.options.as_ref(),
+                |name| txn.rocksdb_txn.tokenizer(name),
+            )?;
+            let terms = analyzer.analyze(&search.search);
+            self.text_searches[id] = Some((analyzer, terms));
+        }
+        Ok(self.text_searches[id].as_ref().unwrap())
+    }
+
+    fn match_text(&mut self, search_id: u32) -> Result<bool> {
+        self.analyze_text_search(search_id)?;
+        let (analyzer, terms) = self.text_searches[search_id as usize].as_ref().unwrap();
+        // a search of the stop words only matches nothing
+        if terms.is_empty() {
+            return Ok(false);
+        }
+        let field = &self.program.text_searches[search_id as usize].field;
+        let value = match &self.stack[self.stack.len() - 1] {
+            Bson::Document(doc) => crate::utils::bson::try_get_document_value(doc, field),
+            _ => None,
+        };
+        let doc_terms = match value {
+            Some(value) => analyzer.analyze_value(&value),
+            None => return Ok(false),
+        };
+        Ok(terms.iter().all(|term| doc_terms.contains(term)))
+    }
+
+    fn push_text_term(&mut self, search_id: u32) -> Result<()> {
+        let (_, terms) = self.analyze_text_search(search_id)?;
+        // the longest term is likely to have the fewest entries
+        let term = terms.iter().max_by_key(|term| term.chars().count()).cloned();
+        self.stack.push(term.map(Bson::String).unwrap_or(Bson::Null));
+        Ok(())
+    }
+
+    fn load_index_candidates(&mut self, candidates_id: u32) -> Result<()> {
+        let index_candidates = &self.program.index_candidates[candidates_id as usize];
+        let mut pkeys = HashSet::<Vec<u8>>::new();
+        self.candidates.clear();
+        self.candidate_score_field = None;
+
+        for term in &index_candidates.terms {
+            let prefix = IndexHelper::make_index_key(
+                &index_candidates.col_name,
+                &index_candidates.index_name,
+                &Bson::String(term.clone()),
+                None,
+            )?;
+            let db_iter = self.txn.rocksdb_txn.new_iterator();
+            db_iter.seek(prefix.as_slice());
+            while db_iter.valid() {
+                let key = db_iter.copy_key()?;
+                if !key.starts_with(prefix.as_slice()) {
+                    break;
+                }
+                // the rest of the key is the primary key
+                if pkeys.insert(key[prefix.len()..].to_vec()) {
+                    self.candidates.push_back((key, None));
+                }
+                db_iter.next();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn next_candidate(&mut self) -> Result<bool> {
+        self.check_cancelled()?;
+
+        while let Some((key, score)) = self.candidates.pop_front() {
+            if let Some(mut doc) = self.read_index_value_by_index_key(key.as_slice())? {
+                if let (Some(score), Some(field), Bson::Document(doc)) = (score, &self.candidate_score_field, &mut doc) {
+                    doc.insert(field.clone(), score);
+                }
+                self.stack.push(doc);
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn next_input(&mut self) -> Result<bool> {
+        self.check_cancelled()?;
+
+        let input = match &mut self.input {
+            Some(input) => input,
+            None => return Ok(false),
+        };
+        if !input.advance()? {
+            return Ok(false);
+        }
+        self.stack.push(input.current().clone());
+        Ok(true)
+    }
+
+    fn load_vector_candidates(&mut self, search_id: u32) -> Result<()> {
+        let search = &self.program.vector_searches[search_id as usize];
+        let mut scored: Vec<(f64, Vec<u8>)> = Vec::new();
+
+        // the nearest lists are probed until they have enough candidates
+        for list in vector::probe_order(&search.centroids, search.similarity, &search.query_vector) {
+            if scored.len() >= search.num_candidates {
+                break;
+            }
+            let prefix = IndexHelper::make_index_key(
+                &search.col_name,
+                &search.index_name,
+                &Bson::Int32(list as i32),
+                None,
+            )?;
+            let db_iter = self.txn.rocksdb_txn.new_iterator();
+            db_iter.seek(prefix.as_slice());
+            while db_iter.valid() {
+                let key = db_iter.copy_key()?;
+                if !key.starts_with(prefix.as_slice()) {
+                    break;
+                }
+                let vector = vector::decode(db_iter.copy_data()?.as_ref());
+                scored.push((search.similarity.score(&search.query_vector, &vector), key));
+                db_iter.next();
+            }
+            self.check_cancelled()?;
+        }
+
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.truncate(search.num_candidates);
+        self.candidate_score_field = search.score_field.clone();
+        self.candidates = scored.into_iter().map(|(score, key)| (key, Some(score))).collect();
+
+        Ok(())
+    }
+
+    fn load_geo_candidates(&mut self, search_id: u32) -> Result<()> {
+        let search = &self.program.geo_searches[search_id as usize];
+        let mut nearby: Vec<(f64, Vec<u8>)> = Vec::new();
+
+        // the cells covering the max distance, or the whole index
+        let cells = match search.max_distance {
+            Some(max_distance) => geo::cover_cells(search.near, max_distance),
+            None => vec![String::new()],
+        };
+        for cell in cells {
+            let mut prefix = IndexHelper::make_index_key(
+                &search.col_name,
+                &search.index_name,
+                &Bson::String(cell),
+                None,
+            )?;
+            // the geohashes starting with the cell, without the end of the string
+            prefix.pop();
+            let db_iter = self.txn.rocksdb_txn.new_iterator();
+            db_iter.seek(prefix.as_slice());
+            while db_iter.valid() {
+                let key = db_iter.copy_key()?;
+                if !key.starts_with(prefix.as_slice()) {
+                    break;
+                }
+                if let Some(point) = geo::decode(db_iter.copy_data()?.as_ref()) {
+                    let distance = geo::distance(search.near, point);
+                    let within_max = match search.max_distance {
+                        Some(max_distance) => distance <= max_distance,
+                        None => true,
+                    };
+                    if distance >= search.min_distance && within_max {
+                        nearby.push((distance, key));
+                    }
+                }
+                db_iter.next();
+            }
+            self.check_cancelled()?;
+        }
+
+        nearby.sort_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some(limit) = search.limit {
+            nearby.truncate(limit);
+        }
+        self.candidate_score_field = Some(search.distance_field.clone());
+        self.candidates = nearby.into_iter().map(|(distance, key)| (key, Some(distance))).collect();
+
+        Ok(())
+    }
+
     fn insert_index(&mut self, index_info_id: u32) -> Result<()> {
+        let ordering = self.key_ordering()?;
         let info = &self.program.index_infos[index_info_id as usize];
 
         let index_meta = &info.indexes;
 
         let data_doc = self.stack[self.stack.len() - 1].as_document().unwrap();
-        let pkey = data_doc.get("_id").unwrap();
+        let pkey = key_ordering::stored_key(ordering.as_deref(), data_doc.get("_id").unwrap())?;
         let txn = &self.txn;
 
         for (index_name, index_info) in index_meta {
@@ -412,7 +827,7 @@ impl VM {
                 IndexHelperOperation::Insert,
                 data_doc,
                 info.col_name.as_str(),
-                pkey,
+                &pkey,
                 index_name.as_str(),
                 index_info,
                 txn,
@@ -423,12 +838,13 @@ impl VM {
     }
 
     fn delete_index(&mut self, index_info_id: u32) -> Result<()> {
+        let ordering = self.key_ordering()?;
         let info = &self.program.index_infos[index_info_id as usize];
 
         let index_meta = &info.indexes;
 
         let data_doc = self.stack[self.stack.len() - 1].as_document().unwrap();
-        let pkey = data_doc.get("_id").unwrap();
+        let pkey = key_ordering::stored_key(ordering.as_deref(), data_doc.get("_id").unwrap())?;
         let txn = &self.txn;
 
         for (index_name, index_info) in index_meta {
@@ -436,7 +852,7 @@ impl VM {
                 IndexHelperOperation::Delete,
                 data_doc,
                 info.col_name.as_str(),
-                pkey,
+                &pkey,
                 index_name.as_str(),
                 index_info,
                 txn,
@@ -475,6 +891,18 @@ impl VM {
     }
 
     pub(crate) fn execute(&mut self) -> Result<()> {
+        if self.max_time.is_none() {
+            return self.execute_program();
+        }
+        let start = Instant::now();
+        self.execution_start = Some(start);
+        let result = self.execute_program();
+        self.execution_time += start.elapsed();
+        self.execution_start = None;
+        result
+    }
+
+    fn execute_program(&mut self) -> Result<()> {
         if self.state == VmState::Halt {
             return Err(Error::VmIsHalt);
         }
@@ -535,6 +963,42 @@ impl VM {
                         }
                     }
 
+                    DbOp::UnpackBuckets => {
+                        let meta_field_id = self.pc.add(1).cast::<u32>().read();
+                        let meta_field = self.borrow_static(meta_field_id as usize).as_str().map(String::from);
+
+                        self.bucket_unpacker = Some(BucketUnpacker::new(meta_field));
+
+                        self.pc = self.pc.add(5);
+                    }
+
+                    DbOp::SetRange => {
+                        let range_id = self.pc.add(1).cast::<u32>().read();
+                        let range = self.borrow_static(range_id as usize).clone();
+
+                        try_vm!(self, self.set_range(&range));
+
+                        self.pc = self.pc.add(5);
+                    }
+
+                    DbOp::SetReverse => {
+                        self.r1.as_mut().unwrap().set_reverse(true);
+
+                        self.pc = self.pc.add(1);
+                    }
+
+                    DbOp::RewindIndex => {
+                        let location = self.pc.add(1).cast::<u32>().read();
+
+                        let found = try_vm!(self, self.rewind_index());
+
+                        if !found {
+                            self.reset_location(location);
+                        } else {
+                            self.pc = self.pc.add(5);
+                        }
+                    }
+
                     DbOp::FindByPrimaryKey => {
                         let location = self.pc.add(1).cast::<u32>().read();
 
@@ -569,6 +1033,68 @@ impl VM {
                         }
                     }
 
+                    DbOp::LoadIndexCandidates => {
+                        let id = self.pc.add(1).cast::<u32>().read();
+                        try_vm!(self, self.load_index_candidates(id));
+                        self.pc = self.pc.add(5);
+                    }
+
+                    DbOp::LoadVectorCandidates => {
+                        let id = self.pc.add(1).cast::<u32>().read();
+                        try_vm!(self, self.load_vector_candidates(id));
+                        self.pc = self.pc.add(5);
+                    }
+
+                    DbOp::LoadGeoCandidates => {
+                        let id = self.pc.add(1).cast::<u32>().read();
+                        try_vm!(self, self.load_geo_candidates(id));
+                        self.pc = self.pc.add(5);
+                    }
+
+                    DbOp::RewindCandidates => {
+                        let location = self.pc.add(1).cast::<u32>().read();
+
+                        let found = try_vm!(self, self.next_candidate());
+
+                        if !found {
+                            self.reset_location(location);
+                        } else {
+                            self.pc = self.pc.add(5);
+                        }
+                    }
+
+                    DbOp::NextCandidate => {
+                        let found = try_vm!(self, self.next_candidate());
+                        if found {
+                            let location = self.pc.add(1).cast::<u32>().read();
+                            self.reset_location(location);
+                        } else {
+                            self.pc = self.pc.add(5);
+                        }
+                    }
+
+                    DbOp::RewindInput => {
+                        let location = self.pc.add(1).cast::<u32>().read();
+
+                        let found = try_vm!(self, self.next_input());
+
+                        if !found {
+                            self.reset_location(location);
+                        } else {
+                            self.pc = self.pc.add(5);
+                        }
+                    }
+
+                    DbOp::NextInput => {
+                        let found = try_vm!(self, self.next_input());
+                        if found {
+                            let location = self.pc.add(1).cast::<u32>().read();
+                            self.reset_location(location);
+                        } else {
+                            self.pc = self.pc.add(5);
+                        }
+                    }
+
                     DbOp::NextIndexValue => {
                         try_vm!(self, self.next_index_value());
                         if self.r0 != 0 {
@@ -824,6 +1350,56 @@ impl VM {
                         self.pc = self.pc.add(1);
                     }
 
+                    DbOp::MatchJsonSchema => {
+                        let id = self.pc.add(1).cast::<u32>().read();
+                        let schema = &self.program.json_schemas[id as usize];
+                        let matched = match &self.stack[self.stack.len() - 1] {
+                            Bson::Document(doc) => schema.validate_document(doc).is_ok(),
+                            _ => false,
+                        };
+                        self.r0 = if matched { 1 } else { 0 };
+                        self.pc = self.pc.add(5);
+                    }
+
+                    DbOp::MatchText => {
+                        let id = self.pc.add(1).cast::<u32>().read();
+                        let matched = try_vm!(self, self.match_text(id));
+                        self.r0 = if matched { 1 } else { 0 };
+                        self.pc = self.pc.add(5);
+                    }
+
+                    DbOp::PushTextTerm => {
+                        let id = self.pc.add(1).cast::<u32>().read();
+                        try_vm!(self, self.push_text_term(id));
+                        self.pc = self.pc.add(5);
+                    }
+
+                    DbOp::Contains => {
+                        let val1 = &self.stack[self.stack.len() - 2];
+                        let val2 = &self.stack[self.stack.len() - 1];
+                        let matched = match val2 {
+                            Bson::String(needle) => trigram::value_contains(val1, needle),
+                            _ => false,
+                        };
+                        self.r0 = if matched { 1 } else { 0 };
+                        self.pc = self.pc.add(1);
+                    }
+
+                    DbOp::Fuzzy => {
+                        let val1 = &self.stack[self.stack.len() - 2];
+                        let val2 = &self.stack[self.stack.len() - 1];
+                        let matched = match val2 {
+                            Bson::Document(fuzzy) => {
+                                let query = fuzzy.get_str("query").unwrap_or_default();
+                                let max_edits = fuzzy.get_i32("maxEdits").unwrap_or_default();
+                                trigram::value_within_edits(val1, query, max_edits as u32)
+                            }
+                            _ => false,
+                        };
+                        self.r0 = if matched { 1 } else { 0 };
+                        self.pc = self.pc.add(1);
+                    }
+
                     DbOp::Regex => {
                         let val1 = &self.stack[self.stack.len() - 2];
                         let val2 = &self.stack[self.stack.len() - 1];
@@ -907,6 +1483,7 @@ impl VM {
                     }
 
                     DbOp::ResultRow => {
+                        self.docs_returned += 1;
                         self.pc = self.pc.add(1);
                         self.state = VmState::HasRow;
                         return Ok(());
@@ -914,6 +1491,7 @@ impl VM {
 
                     DbOp::Close => {
                         self.r1 = None;
+                        self.bucket_unpacker = None;
                         self.txn.auto_commit()?;
 
                         self.pc = self.pc.add(1);
@@ -1025,6 +1603,7 @@ impl VM {
                     DbOp::_EOF | DbOp::Halt => {
                         self.r1 = None;
                         self.state = VmState::Halt;
+                        self.finish_profile();
                         return Ok(());
                     }
                 }
@@ -1036,5 +1615,7 @@ impl VM {
 impl Drop for VM {
     fn drop(&mut self) {
         self.r1 = None;
+        // the cursors closed before reaching the end are recorded too
+        self.finish_profile();
     }
 }
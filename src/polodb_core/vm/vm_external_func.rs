@@ -14,6 +14,7 @@
 
 use bson::Bson;
 use crate::Result;
+use crate::utils::spill::SpillOptions;
 
 pub(crate) enum VmExternalFuncStatus {
     Continue,
@@ -24,4 +25,8 @@ pub(crate) trait VmExternalFunc {
     fn name(&self) -> &str;
     fn call(&self, args: &[Bson]) -> Result<VmExternalFuncStatus>;
     fn is_completed(&self) -> bool;
+
+    /// The stages buffering their input write it to temporary files
+    /// when it exceeds the memory limit.
+    fn set_spill_options(&mut self, _options: &SpillOptions) {}
 }
@@ -12,43 +12,131 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Mutex;
-use bson::Bson;
+use std::cell::RefCell;
+use std::collections::{btree_map, BTreeMap, BinaryHeap};
+use bson::{Bson, Document};
+use crate::coll::map_reduce::{read_entry, GroupKey, MergeEntry};
 use crate::vm::vm_external_func::{VmExternalFunc, VmExternalFuncStatus};
 use crate::{Result, Error};
-use indexmap::IndexMap;
-use crate::vm::operators::{OpRegistry, VmOperator};
+use crate::errors::mk_invalid_aggregate_field;
+use crate::utils::bson::value_size;
+use crate::utils::spill::{SpillFile, SpillOptions, SpillReader, SpillWriter};
+use crate::vm::operators::{OpRegistry, OperatorExpr};
 
 const NAME: &'static str = "group";
 
+/// An accumulator of the groups. Its state is a value,
+/// so the groups can be written to the temporary files.
+enum Accumulator {
+    /// `$sum` of the operand, `{ "$sum": 1 }` counts the documents
+    Sum(OperatorExpr),
+}
+
+impl Accumulator {
+
+    fn compile(paths: &mut Vec<String>, registry: &OpRegistry, value: &Bson) -> Result<Accumulator> {
+        let doc = match value {
+            Bson::Document(doc) => doc,
+            _ => {
+                let invalid_err = mk_invalid_aggregate_field(paths);
+                return Err(Error::InvalidField(invalid_err));
+            }
+        };
+        let (op_name, op_value) = match doc.iter().next() {
+            Some(item) if doc.len() == 1 => item,
+            _ => return Err(Error::ValidationError("Operator should have exactly one field".to_string())),
+        };
+        let accumulator = crate::path_hint_3!(paths, op_name.clone(), {
+            match op_name.as_str() {
+                "$sum" => Accumulator::Sum(OperatorExpr::compile_operand(paths, registry, op_value)?),
+                _ => {
+                    let invalid_err = mk_invalid_aggregate_field(paths);
+                    return Err(Error::InvalidField(invalid_err));
+                }
+            }
+        });
+        Ok(accumulator)
+    }
+
+    fn initial_value(&self) -> Bson {
+        match self {
+            Accumulator::Sum(_) => Bson::Int64(0),
+        }
+    }
+
+    fn accumulate(&self, state: &Bson, input: &Bson) -> Bson {
+        match self {
+            Accumulator::Sum(operand) => add_numbers(state, &operand.next(input)),
+        }
+    }
+
+    /// Merge the states of the same group written to different runs.
+    fn merge(&self, a: &Bson, b: &Bson) -> Bson {
+        match self {
+            Accumulator::Sum(_) => add_numbers(a, b),
+        }
+    }
+
+}
+
+/// The sum of two numbers, the values which aren't numbers are ignored like MongoDB.
+fn add_numbers(a: &Bson, b: &Bson) -> Bson {
+    let as_i64 = |value: &Bson| match value {
+        Bson::Int32(v) => Some(*v as i64),
+        Bson::Int64(v) => Some(*v),
+        _ => None,
+    };
+    let as_f64 = |value: &Bson| match value {
+        Bson::Int32(v) => Some(*v as f64),
+        Bson::Int64(v) => Some(*v as f64),
+        Bson::Double(v) => Some(*v),
+        _ => None,
+    };
+    if let (Some(a), Some(b)) = (as_i64(a), as_i64(b)) {
+        return match a.checked_add(b) {
+            Some(sum) => Bson::Int64(sum),
+            None => Bson::Double(a as f64 + b as f64),
+        };
+    }
+    match (as_f64(a), as_f64(b)) {
+        (Some(a), Some(b)) => Bson::Double(a + b),
+        (Some(_), None) => a.clone(),
+        (None, Some(_)) => b.clone(),
+        (None, None) => Bson::Int64(0),
+    }
+}
+
 // Reference: https://www.mongodb.com/docs/manual/reference/operator/aggregation/group/
 pub(crate) struct VmFuncGroup {
-    is_completed: AtomicBool,
-    inner: Mutex<VmFuncGroupInner>,
+    id: OperatorExpr,
+    /// The output fields and their accumulators, in the order of the stage
+    accumulators: Vec<(String, Accumulator)>,
+    spill_options: SpillOptions,
+    state: RefCell<GroupState>,
 }
 
-struct VmFuncGroupInner {
-    group_values: IndexMap<String, Bson>,
-    operators: HashMap<String, Box<dyn VmOperator>>,
+#[derive(Default)]
+struct GroupState {
+    /// The states of the accumulators by the keys of the groups
+    groups: BTreeMap<GroupKey, Document>,
+    /// The estimated bytes of the groups
+    memory: usize,
+    /// The sorted runs written when the groups exceed the memory limit
+    runs: Vec<SpillFile>,
+    output: Option<GroupOutput>,
+    completed: bool,
 }
 
-impl VmFuncGroup {
+enum GroupOutput {
+    Memory(btree_map::IntoIter<GroupKey, Document>),
+    /// The heads of the sorted runs, the groups of the same key are merged
+    Merge {
+        readers: Vec<SpillReader>,
+        heap: BinaryHeap<MergeEntry>,
+    },
+}
 
-    fn compile_command(
-        paths: &mut Vec<String>,
-        registry: OpRegistry,
-        key: &str,
-        value: &Bson,
-        group_values: &mut IndexMap<String, Bson>,
-        operators: &mut HashMap<String, Box<dyn VmOperator>>,
-    ) -> Result<()> {
-        let op = registry.compile(paths, value)?;
-        group_values.insert(key.into(), op.initial_value());
-        operators.insert(key.into(), op);
-        Ok(())
-    }
+impl VmFuncGroup {
 
     pub(crate) fn compile(
         paths: &mut Vec<String>,
@@ -56,44 +144,125 @@ impl VmFuncGroup {
         value: &Bson,
     ) -> Result<Box<dyn VmExternalFunc>> {
         let doc = crate::try_unwrap_document!("$group", value);
-        let mut group_values = IndexMap::new();
-        let mut operators = HashMap::new();
+        let mut id = None;
+        let mut accumulators = Vec::new();
 
-        let mut found_id = false;
         for (k, v) in doc.iter() {
             crate::path_hint_2!(paths, k.clone(), {
-                group_values.insert(k.clone(), v.clone());
-                let k_str = k.as_str();
-                if k_str == "_id" {
-                    found_id = true;
-                    paths.pop();
-                    continue;
+                if k == "_id" {
+                    id = Some(OperatorExpr::compile_operand(paths, &registry, v)?);
+                } else {
+                    accumulators.push((k.clone(), Accumulator::compile(paths, &registry, v)?));
                 }
-
-                VmFuncGroup::compile_command(
-                    paths,
-                    registry.clone(),
-                    k_str,
-                    v,
-                    &mut group_values,
-                    &mut operators,
-                )?;
             });
         }
-        if !found_id {
-            let err_msg = "Field '_id' is required for $group".to_string();
-            return Err(Error::ValidationError(err_msg));
-        }
+        let id = match id {
+            Some(id) => id,
+            None => {
+                let err_msg = "Field '_id' is required for $group".to_string();
+                return Err(Error::ValidationError(err_msg));
+            }
+        };
 
         let result = VmFuncGroup {
-            is_completed: AtomicBool::new(false),
-            inner: Mutex::new(VmFuncGroupInner {
-                group_values,
-                operators,
-            }),
+            id,
+            accumulators,
+            spill_options: SpillOptions::default(),
+            state: RefCell::new(GroupState::default()),
         };
         Ok(Box::new(result))
     }
+
+    fn add(&self, state: &mut GroupState, input: &Bson) -> Result<()> {
+        let key = self.id.next(input);
+        let group = match state.groups.entry(GroupKey(key)) {
+            btree_map::Entry::Vacant(vacant) => {
+                let mut group = Document::new();
+                for (field, accumulator) in &self.accumulators {
+                    group.insert(field.clone(), accumulator.initial_value());
+                }
+                state.memory += value_size(&vacant.key().0) + crate::utils::bson::document_size(&group, usize::MAX);
+                vacant.insert(group)
+            }
+            btree_map::Entry::Occupied(occupied) => occupied.into_mut(),
+        };
+        for (field, accumulator) in &self.accumulators {
+            let next = accumulator.accumulate(group.get(field).unwrap_or(&Bson::Null), input);
+            group.insert(field.clone(), next);
+        }
+        if state.memory > self.spill_options.memory_limit {
+            self.spill(state)?;
+        }
+        Ok(())
+    }
+
+    /// Write the groups in memory to a sorted run.
+    fn spill(&self, state: &mut GroupState) -> Result<()> {
+        let mut writer = SpillWriter::create(self.spill_options.temp_dir.as_deref())?;
+        for (key, group) in std::mem::take(&mut state.groups) {
+            writer.write(&bson::doc! { "_id": key.0, "value": group })?;
+        }
+        state.runs.push(writer.finish()?);
+        state.memory = 0;
+        Ok(())
+    }
+
+    fn start_output(&self, state: &mut GroupState) -> Result<GroupOutput> {
+        if state.runs.is_empty() {
+            let groups = std::mem::take(&mut state.groups);
+            return Ok(GroupOutput::Memory(groups.into_iter()));
+        }
+        if !state.groups.is_empty() {
+            self.spill(state)?;
+        }
+        let mut readers = Vec::with_capacity(state.runs.len());
+        let mut heap = BinaryHeap::with_capacity(state.runs.len());
+        for (run, file) in state.runs.iter().enumerate() {
+            let mut reader = file.reader()?;
+            if let Some(entry) = read_entry(&mut reader, run)? {
+                heap.push(entry);
+            }
+            readers.push(reader);
+        }
+        Ok(GroupOutput::Merge { readers, heap })
+    }
+
+    fn next_group(&self, output: &mut GroupOutput) -> Result<Option<(Bson, Document)>> {
+        let (readers, heap) = match output {
+            GroupOutput::Memory(groups) => return Ok(groups.next().map(|(key, group)| (key.0, group))),
+            GroupOutput::Merge { readers, heap } => (readers, heap),
+        };
+        let first = match heap.pop() {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let key = first.key;
+        let mut group = match first.value {
+            Bson::Document(group) => group,
+            _ => Document::new(),
+        };
+        let mut consumed = vec![first.run];
+        while heap.peek().is_some_and(|entry| entry.key == key) {
+            let entry = heap.pop().unwrap();
+            if let Bson::Document(other) = entry.value {
+                for (field, accumulator) in &self.accumulators {
+                    let merged = match (group.get(field), other.get(field)) {
+                        (Some(a), Some(b)) => accumulator.merge(a, b),
+                        (a, b) => a.or(b).cloned().unwrap_or(Bson::Null),
+                    };
+                    group.insert(field.clone(), merged);
+                }
+            }
+            consumed.push(entry.run);
+        }
+        for run in consumed {
+            if let Some(entry) = read_entry(&mut readers[run], run)? {
+                heap.push(entry);
+            }
+        }
+        Ok(Some((key.0, group)))
+    }
+
 }
 
 impl VmExternalFunc for VmFuncGroup {
@@ -103,38 +272,39 @@ impl VmExternalFunc for VmFuncGroup {
 
     fn call(&self, args: &[Bson]) -> Result<VmExternalFuncStatus> {
         let arg0 = &args[0];
-        let mut inner = self.inner.lock().unwrap();
-        if arg0.as_null().is_some() {  // complete
-            self.is_completed.store(true, Ordering::Relaxed);
-            let mut result = bson::Document::new();
-            for (k, value) in inner.group_values.iter() {
-                if k == "_id" {
-                    result.insert(k.clone(), value.clone());
-                    continue;
-                }
-                let op = inner.operators.get(k).expect("Operator not found");
-                let final_value = op.complete();
-                result.insert(k.clone(), final_value);
-            }
-            return Ok(VmExternalFuncStatus::Next(result.into()));
+        let mut state = self.state.borrow_mut();
+        if arg0.as_null().is_none() {
+            self.add(&mut state, arg0)?;
+            return Ok(VmExternalFuncStatus::Continue);
         }
 
-        let next_map = inner.group_values
-            .iter()
-            .map(|(k, v)| {
-                if k == "_id" {
-                    return (k.clone(), v.clone());
-                }
-                let op = inner.operators.get(k).expect("Operator not found");
-                let next = op.next(v);
-                (k.clone(), next)
-            })
-            .collect::<IndexMap<String, Bson>>();
-        inner.group_values = next_map;
-        Ok(VmExternalFuncStatus::Continue)
+        // the groups are returned one by one, then the null at the end of the input
+        let mut output = match state.output.take() {
+            Some(output) => output,
+            None => self.start_output(&mut state)?,
+        };
+        let next = self.next_group(&mut output)?;
+        state.output = Some(output);
+        let next = match next {
+            Some((key, group)) => {
+                let mut result = Document::new();
+                result.insert("_id", key);
+                result.extend(group);
+                Bson::Document(result)
+            }
+            None => {
+                state.completed = true;
+                Bson::Null
+            }
+        };
+        Ok(VmExternalFuncStatus::Next(next))
     }
 
     fn is_completed(&self) -> bool {
-        true
+        self.state.borrow().completed
+    }
+
+    fn set_spill_options(&mut self, options: &SpillOptions) {
+        self.spill_options = options.clone();
     }
 }
@@ -13,31 +13,56 @@
 // limitations under the License.
 
 use std::cell::RefCell;
-use std::collections::HashMap;
-use std::sync::atomic::AtomicUsize;
+use std::cmp::Ordering;
 use bson::{Bson, Document};
 use crate::vm::vm_external_func::{VmExternalFunc, VmExternalFuncStatus};
 use crate::{Result, Error};
 use crate::errors::mk_invalid_aggregate_field;
+use crate::utils::spill::{SpillFile, SpillOptions, SpillReader, SpillWriter};
 
 pub(crate) struct VmFuncSort {
-    order_map: HashMap<String, i8>,
-    buffer: RefCell<Vec<Document>>,
-    idx: AtomicUsize,
+    /// The fields in the order of priority
+    order_map: Vec<(String, i8)>,
+    spill_options: SpillOptions,
+    state: RefCell<SortState>,
+}
+
+#[derive(Default)]
+struct SortState {
+    buffer: Vec<Document>,
+    /// The estimated bytes of the buffer
+    memory: usize,
+    /// The sorted runs written when the buffer exceeds the memory limit
+    runs: Vec<SpillFile>,
+    output: Option<SortOutput>,
+    /// The documents received but not returned yet
+    pending: usize,
+}
+
+enum SortOutput {
+    Memory(std::vec::IntoIter<Document>),
+    /// The heads of the sorted runs, merged by taking the smallest one
+    Merge {
+        readers: Vec<SpillReader>,
+        heads: Vec<Option<Document>>,
+    },
 }
 
 impl VmFuncSort {
     pub(crate) fn compile(paths: &mut Vec<String>, val: &Bson) -> Result<Box<dyn VmExternalFunc>> {
         let order_map = match val {
             Bson::Document(doc) => {
-                let mut result = HashMap::default();
+                let mut result = Vec::with_capacity(doc.len());
                 for (k, v) in doc.iter() {
+                    if k == "$natural" {
+                        return Err(Error::ValidationError("$natural must be sorted before the other stages except the first $match".into()));
+                    }
                     let order = match v {
                         Bson::Int32(val) => *val as i8,
                         Bson::Int64(val) => *val as i8,
                         _ => return Err(Error::ValidationError("Invalid sort value".into()))
                     };
-                    result.insert(k.clone(), order);
+                    result.push((k.clone(), order));
                 }
                 result
             }
@@ -48,42 +73,100 @@ impl VmFuncSort {
         };
         let result = VmFuncSort {
             order_map,
-            buffer: RefCell::new(Vec::default()),
-            idx: AtomicUsize::new(0),
+            spill_options: SpillOptions::default(),
+            state: RefCell::new(SortState::default()),
         };
         Ok(Box::new(result))
     }
 
-    fn i8_to_ordering(i: i8) -> std::cmp::Ordering {
+    fn i8_to_ordering(i: i8) -> Ordering {
         match i {
-            1 => std::cmp::Ordering::Less,
-            -1 => std::cmp::Ordering::Greater,
-            _ => std::cmp::Ordering::Equal,
+            1 => Ordering::Less,
+            -1 => Ordering::Greater,
+            _ => Ordering::Equal,
         }
     }
 
-    fn sort_array(&self) {
-        let mut array = self.buffer.borrow_mut();
-        array.sort_by(|a, b| {
-            for (k, v) in self.order_map.iter() {
-                let a_val = a.get(k);
-                let b_val = b.get(k);
-                match (a_val, b_val) {
-                    (Some(a_val), Some(b_val)) => {
-                        let result =  crate::utils::bson::value_cmp(a_val, b_val).expect("Invalid sort value");
-                        match result {
-                            std::cmp::Ordering::Equal => continue,
-                            std::cmp::Ordering::Less => return Self::i8_to_ordering(v.clone()),
-                            std::cmp::Ordering::Greater => return Self::i8_to_ordering(-v.clone()),
-                        }
+    fn compare(&self, a: &Document, b: &Document) -> Ordering {
+        for (k, v) in self.order_map.iter() {
+            let a_val = a.get(k);
+            let b_val = b.get(k);
+            match (a_val, b_val) {
+                (Some(a_val), Some(b_val)) => {
+                    let result =  crate::utils::bson::value_cmp(a_val, b_val).expect("Invalid sort value");
+                    match result {
+                        Ordering::Equal => continue,
+                        Ordering::Less => return Self::i8_to_ordering(*v),
+                        Ordering::Greater => return Self::i8_to_ordering(-*v),
                     }
-                    (Some(_), None) => return Self::i8_to_ordering(v.clone()),
-                    (None, Some(_)) => return Self::i8_to_ordering(-v.clone()),
-                    (None, None) => continue,
                 }
+                (Some(_), None) => return Self::i8_to_ordering(*v),
+                (None, Some(_)) => return Self::i8_to_ordering(-*v),
+                (None, None) => continue,
             }
-            std::cmp::Ordering::Equal
-        });
+        }
+        Ordering::Equal
+    }
+
+    /// Sort the buffer and write it to a run, the sort is stable.
+    fn spill(&self, state: &mut SortState) -> Result<()> {
+        let mut buffer = std::mem::take(&mut state.buffer);
+        buffer.sort_by(|a, b| self.compare(a, b));
+        let mut writer = SpillWriter::create(self.spill_options.temp_dir.as_deref())?;
+        for doc in &buffer {
+            writer.write(doc)?;
+        }
+        state.runs.push(writer.finish()?);
+        state.memory = 0;
+        Ok(())
+    }
+
+    fn start_output(&self, state: &mut SortState) -> Result<SortOutput> {
+        if state.runs.is_empty() {
+            let mut buffer = std::mem::take(&mut state.buffer);
+            buffer.sort_by(|a, b| self.compare(a, b));
+            return Ok(SortOutput::Memory(buffer.into_iter()));
+        }
+        if !state.buffer.is_empty() {
+            self.spill(state)?;
+        }
+        let mut readers = Vec::with_capacity(state.runs.len());
+        let mut heads = Vec::with_capacity(state.runs.len());
+        for run in &state.runs {
+            let mut reader = run.reader()?;
+            heads.push(reader.next_doc()?);
+            readers.push(reader);
+        }
+        Ok(SortOutput::Merge { readers, heads })
+    }
+
+    fn next_doc(&self, output: &mut SortOutput) -> Result<Option<Document>> {
+        match output {
+            SortOutput::Memory(docs) => Ok(docs.next()),
+            SortOutput::Merge { readers, heads } => {
+                // the earlier run wins the ties to keep the sort stable
+                let mut min: Option<usize> = None;
+                for (index, head) in heads.iter().enumerate() {
+                    let head = match head {
+                        Some(head) => head,
+                        None => continue,
+                    };
+                    let is_smaller = match min {
+                        Some(min) => self.compare(head, heads[min].as_ref().unwrap()) == Ordering::Less,
+                        None => true,
+                    };
+                    if is_smaller {
+                        min = Some(index);
+                    }
+                }
+                let min = match min {
+                    Some(min) => min,
+                    None => return Ok(None),
+                };
+                let next = readers[min].next_doc()?;
+                Ok(std::mem::replace(&mut heads[min], next))
+            }
+        }
     }
 }
 
@@ -94,22 +177,30 @@ impl VmExternalFunc for VmFuncSort {
 
     fn call(&self, args: &[Bson]) -> Result<VmExternalFuncStatus> {
         let arg0 = &args[0];
+        let mut state = self.state.borrow_mut();
         match arg0 {
             Bson::Document(doc) => {
-                let mut buffer = self.buffer.borrow_mut();
-                buffer.push(doc.clone());
+                state.memory += crate::utils::bson::document_size(doc, usize::MAX);
+                state.buffer.push(doc.clone());
+                state.pending += 1;
+                if state.memory > self.spill_options.memory_limit {
+                    self.spill(&mut state)?;
+                }
                 Ok(VmExternalFuncStatus::Continue)
             }
             Bson::Null => {
-                self.sort_array();
-                let next = {
-                    let idx = self.idx.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                    let buffer = self.buffer.borrow();
-                    if idx >= buffer.len() {
-                        Bson::Null
-                    } else {
-                        buffer[idx].clone().into()
+                let mut output = match state.output.take() {
+                    Some(output) => output,
+                    None => self.start_output(&mut state)?,
+                };
+                let next = self.next_doc(&mut output)?;
+                state.output = Some(output);
+                let next = match next {
+                    Some(doc) => {
+                        state.pending -= 1;
+                        Bson::Document(doc)
                     }
+                    None => Bson::Null,
                 };
                 Ok(VmExternalFuncStatus::Next(next))
             }
@@ -120,8 +211,10 @@ impl VmExternalFunc for VmFuncSort {
     }
 
     fn is_completed(&self) -> bool {
-        let idx = self.idx.load(std::sync::atomic::Ordering::Relaxed);
-        let buffer = self.buffer.borrow();
-        idx >= buffer.len()
+        self.state.borrow().pending == 0
+    }
+
+    fn set_spill_options(&mut self, options: &SpillOptions) {
+        self.spill_options = options.clone();
     }
 }
@@ -0,0 +1,191 @@
+/* The C API of PoloDB, generated by cbindgen. Do not edit. */
+
+#ifndef POLODB_H
+#define POLODB_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The result of the functions.
+enum PLDBErrorCode
+#ifdef __cplusplus
+  : int32_t
+#endif // __cplusplus
+ {
+  PLDB_ERROR_CODE_OK = 0,
+  // An error without a more specific code.
+  PLDB_ERROR_CODE_ERROR = 1,
+  // A `NULL` handle, a string which is not UTF-8 or a malformed BSON buffer.
+  PLDB_ERROR_CODE_INVALID_ARGUMENT = 2,
+  PLDB_ERROR_CODE_NOT_FOUND = 3,
+  PLDB_ERROR_CODE_DUPLICATE_KEY = 4,
+  // The document is not valid against the validator of the collection.
+  PLDB_ERROR_CODE_VALIDATION_FAILED = 5,
+  // The database is locked by another process, or the transaction conflicts.
+  PLDB_ERROR_CODE_BUSY = 6,
+  PLDB_ERROR_CODE_READ_ONLY = 7,
+  PLDB_ERROR_CODE_IO = 8,
+  PLDB_ERROR_CODE_CORRUPTION = 9,
+  PLDB_ERROR_CODE_DATABASE_CLOSED = 10,
+  // A bug of PoloDB, the state of the handles is unspecified.
+  PLDB_ERROR_CODE_PANIC = 11,
+};
+#ifndef __cplusplus
+typedef int32_t PLDBErrorCode;
+#endif // __cplusplus
+
+typedef struct PLDBCursor PLDBCursor;
+
+typedef struct PLDBDatabase PLDBDatabase;
+
+typedef struct PLDBTransaction PLDBTransaction;
+
+// A buffer allocated by the library, released by `polodb_buffer_free`.
+// `data` is `NULL` for an empty output, e.g. when no document is found.
+typedef struct PLDBBuffer {
+  uint8_t *data;
+  size_t len;
+} PLDBBuffer;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// The version of PoloDB, the string is static.
+const char *polodb_version(void);
+
+// The message of the last error of the calling thread, or `NULL`.
+// The string is valid until the next error of the thread.
+const char *polodb_last_error_message(void);
+
+// Release a buffer returned by the library, releasing an empty buffer does nothing.
+void polodb_buffer_free(struct PLDBBuffer buffer);
+
+// Open the database at `path`, it's created if it doesn't exist.
+PLDBErrorCode polodb_open(const char *path, struct PLDBDatabase **out_db);
+
+// Open a database in memory, the data is lost when it's closed.
+PLDBErrorCode polodb_open_memory(struct PLDBDatabase **out_db);
+
+// Close the database and release the handle.
+// The transactions and the cursors of the database must be released before.
+void polodb_close(struct PLDBDatabase *db);
+
+// The names of the collections as a BSON array of strings.
+PLDBErrorCode polodb_list_collection_names(const struct PLDBDatabase *db,
+                                           struct PLDBBuffer *out_names);
+
+PLDBErrorCode polodb_create_collection(const struct PLDBDatabase *db, const char *name);
+
+PLDBErrorCode polodb_drop_collection(const struct PLDBDatabase *db, const char *name);
+
+PLDBErrorCode polodb_start_transaction(const struct PLDBDatabase *db,
+                                       struct PLDBTransaction **out_txn);
+
+// Commit the transaction, the handle still has to be released by `polodb_transaction_free`.
+PLDBErrorCode polodb_transaction_commit(const struct PLDBTransaction *txn);
+
+PLDBErrorCode polodb_transaction_rollback(const struct PLDBTransaction *txn);
+
+// Release the transaction, the writes which are not committed are discarded.
+void polodb_transaction_free(struct PLDBTransaction *txn);
+
+// Insert a document, `out_result` (nullable) receives `{ "insertedId": ... }`.
+PLDBErrorCode polodb_insert_one(const struct PLDBDatabase *db,
+                                const struct PLDBTransaction *txn,
+                                const char *col_name,
+                                const uint8_t *doc,
+                                size_t doc_len,
+                                struct PLDBBuffer *out_result);
+
+// Insert the BSON array of documents, `out_result` (nullable) receives
+// `{ "insertedIds": { "0": ..., "1": ... } }`.
+PLDBErrorCode polodb_insert_many(const struct PLDBDatabase *db,
+                                 const struct PLDBTransaction *txn,
+                                 const char *col_name,
+                                 const uint8_t *docs,
+                                 size_t docs_len,
+                                 struct PLDBBuffer *out_result);
+
+// Find the documents matching `filter`. `options` is a nullable document
+// with the optional `sort`, `skip` and `limit` fields.
+PLDBErrorCode polodb_find(const struct PLDBDatabase *db,
+                          const struct PLDBTransaction *txn,
+                          const char *col_name,
+                          const uint8_t *filter,
+                          size_t filter_len,
+                          const uint8_t *options,
+                          size_t options_len,
+                          struct PLDBCursor **out_cursor);
+
+// Find the first document matching `filter`, `out_doc` is empty if there is none.
+PLDBErrorCode polodb_find_one(const struct PLDBDatabase *db,
+                              const struct PLDBTransaction *txn,
+                              const char *col_name,
+                              const uint8_t *filter,
+                              size_t filter_len,
+                              struct PLDBBuffer *out_doc);
+
+// Run the aggregation `pipeline`, a BSON array of stages.
+PLDBErrorCode polodb_aggregate(const struct PLDBDatabase *db,
+                               const struct PLDBTransaction *txn,
+                               const char *col_name,
+                               const uint8_t *pipeline,
+                               size_t pipeline_len,
+                               struct PLDBCursor **out_cursor);
+
+// Move the cursor to the next document, `out_doc` is empty at the end.
+PLDBErrorCode polodb_cursor_next(struct PLDBCursor *cursor, struct PLDBBuffer *out_doc);
+
+void polodb_cursor_free(struct PLDBCursor *cursor);
+
+PLDBErrorCode polodb_count_documents(const struct PLDBDatabase *db,
+                                     const struct PLDBTransaction *txn,
+                                     const char *col_name,
+                                     uint64_t *out_count);
+
+// Update the first document matching `filter`, `out_result` (nullable) receives
+// `{ "matchedCount": ..., "modifiedCount": ... }`.
+PLDBErrorCode polodb_update_one(const struct PLDBDatabase *db,
+                                const struct PLDBTransaction *txn,
+                                const char *col_name,
+                                const uint8_t *filter,
+                                size_t filter_len,
+                                const uint8_t *update_doc,
+                                size_t update_len,
+                                struct PLDBBuffer *out_result);
+
+// Update all the documents matching `filter`, like `polodb_update_one`.
+PLDBErrorCode polodb_update_many(const struct PLDBDatabase *db,
+                                 const struct PLDBTransaction *txn,
+                                 const char *col_name,
+                                 const uint8_t *filter,
+                                 size_t filter_len,
+                                 const uint8_t *update_doc,
+                                 size_t update_len,
+                                 struct PLDBBuffer *out_result);
+
+// Delete the first document matching `filter`, `out_deleted_count` is nullable.
+PLDBErrorCode polodb_delete_one(const struct PLDBDatabase *db,
+                                const struct PLDBTransaction *txn,
+                                const char *col_name,
+                                const uint8_t *filter,
+                                size_t filter_len,
+                                uint64_t *out_deleted_count);
+
+// Delete all the documents matching `filter`, `out_deleted_count` is nullable.
+PLDBErrorCode polodb_delete_many(const struct PLDBDatabase *db,
+                                 const struct PLDBTransaction *txn,
+                                 const char *col_name,
+                                 const uint8_t *filter,
+                                 size_t filter_len,
+                                 uint64_t *out_deleted_count);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* POLODB_H */
@@ -0,0 +1,820 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The C API of PoloDB, the header is `include/polodb.h`.
+//!
+//! The database, the transactions and the cursors are opaque handles,
+//! created by `polodb_open`, `polodb_start_transaction` and `polodb_find`,
+//! and released by the matching `*_free` or `polodb_close` function.
+//! The documents are passed as BSON buffers, the names as NUL-terminated UTF-8 strings.
+//! The lists, e.g. the documents of `polodb_insert_many`, are BSON arrays,
+//! i.e. the documents with the keys `"0"`, `"1"`, ...
+//!
+//! Every function returns a [`PLDBErrorCode`], the outputs are written to the `out_*`
+//! arguments on success. After an error, `polodb_last_error_message` returns the message
+//! of the last error of the calling thread. The codes may be added in the later versions,
+//! the unknown codes should be handled like `PLDB_ERROR_CODE_ERROR`.
+//!
+//! The functions operating on a collection take an optional transaction,
+//! the operation is committed immediately if it's `NULL`.
+//!
+//! # Safety
+//!
+//! The pointers must be valid for their lengths, the handles must be the ones
+//! returned by this library and not been released. A handle can be used by several
+//! threads, but it must not be released while it's used. The database is closed
+//! after releasing its transactions and cursors.
+
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use polodb_core::bson::{self, Bson, Document};
+use polodb_core::{ClientCursor, CollectionT, Database, Error, Transaction};
+
+/// The result of the functions.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PLDBErrorCode {
+    Ok = 0,
+    /// An error without a more specific code.
+    Error = 1,
+    /// A `NULL` handle, a string which is not UTF-8 or a malformed BSON buffer.
+    InvalidArgument = 2,
+    NotFound = 3,
+    DuplicateKey = 4,
+    /// The document is not valid against the validator of the collection.
+    ValidationFailed = 5,
+    /// The database is locked by another process, or the transaction conflicts.
+    Busy = 6,
+    ReadOnly = 7,
+    Io = 8,
+    Corruption = 9,
+    DatabaseClosed = 10,
+    /// A bug of PoloDB, the state of the handles is unspecified.
+    Panic = 11,
+}
+
+/// A buffer allocated by the library, released by `polodb_buffer_free`.
+/// `data` is `NULL` for an empty output, e.g. when no document is found.
+#[repr(C)]
+pub struct PLDBBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+pub struct PLDBDatabase(Database);
+
+pub struct PLDBTransaction(Transaction);
+
+pub struct PLDBCursor(ClientCursor<Document>);
+
+struct FfiError {
+    code: PLDBErrorCode,
+    message: String,
+}
+
+impl FfiError {
+
+    fn invalid_argument<T: Into<String>>(message: T) -> FfiError {
+        FfiError {
+            code: PLDBErrorCode::InvalidArgument,
+            message: message.into(),
+        }
+    }
+
+}
+
+impl From<Error> for FfiError {
+
+    fn from(err: Error) -> Self {
+        let code = match &err {
+            Error::CollectionNotFound(_)
+            | Error::FileNotFound(_)
+            | Error::SnapshotNotFound(_) => PLDBErrorCode::NotFound,
+            Error::DuplicateKey(_) => PLDBErrorCode::DuplicateKey,
+            Error::DocumentValidationFailed(_) => PLDBErrorCode::ValidationFailed,
+            Error::Busy
+            | Error::DatabaseOccupied
+            | Error::LockError => PLDBErrorCode::Busy,
+            Error::ReadOnlyDatabase
+            | Error::ViewReadOnly(_)
+            | Error::CappedCollectionImmutable(_)
+            | Error::TimeseriesCollectionImmutable(_) => PLDBErrorCode::ReadOnly,
+            Error::IOErr(_) => PLDBErrorCode::Io,
+            Error::Corruption(_)
+            | Error::ChecksumMismatch => PLDBErrorCode::Corruption,
+            Error::DbIsClosed => PLDBErrorCode::DatabaseClosed,
+            _ => PLDBErrorCode::Error,
+        };
+        FfiError {
+            code,
+            message: err.to_string(),
+        }
+    }
+
+}
+
+impl From<bson::ser::Error> for FfiError {
+
+    fn from(err: bson::ser::Error) -> Self {
+        FfiError {
+            code: PLDBErrorCode::Error,
+            message: err.to_string(),
+        }
+    }
+
+}
+
+type FfiResult<T> = std::result::Result<T, FfiError>;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    // the message can't contain a NUL in C
+    let message = CString::new(message.replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|last_error| {
+        *last_error.borrow_mut() = Some(message);
+    });
+}
+
+fn ffi_call<F: FnOnce() -> FfiResult<()>>(f: F) -> PLDBErrorCode {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => PLDBErrorCode::Ok,
+        Ok(Err(err)) => {
+            set_last_error(err.message);
+            err.code
+        }
+        Err(payload) => {
+            let message = payload.downcast_ref::<&str>().map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            set_last_error(format!("panic: {}", message));
+            PLDBErrorCode::Panic
+        }
+    }
+}
+
+unsafe fn handle<'a, T>(ptr: *const T, name: &str) -> FfiResult<&'a T> {
+    ptr.as_ref().ok_or_else(|| FfiError::invalid_argument(format!("{} is NULL", name)))
+}
+
+unsafe fn read_str<'a>(ptr: *const c_char, name: &str) -> FfiResult<&'a str> {
+    if ptr.is_null() {
+        return Err(FfiError::invalid_argument(format!("{} is NULL", name)));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| FfiError::invalid_argument(format!("{} is not UTF-8", name)))
+}
+
+unsafe fn read_document(data: *const u8, len: usize, name: &str) -> FfiResult<Document> {
+    if data.is_null() {
+        return Err(FfiError::invalid_argument(format!("{} is NULL", name)));
+    }
+    let bytes = std::slice::from_raw_parts(data, len);
+    // the length in the header must match the buffer
+    if bytes.len() < 4 || i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize != bytes.len() {
+        return Err(FfiError::invalid_argument(format!("{} is not a BSON document of {} bytes", name, len)));
+    }
+    Document::from_reader(bytes)
+        .map_err(|err| FfiError::invalid_argument(format!("{} is not a valid BSON document: {}", name, err)))
+}
+
+unsafe fn read_array(data: *const u8, len: usize, name: &str) -> FfiResult<Vec<Document>> {
+    read_document(data, len, name)?
+        .into_iter()
+        .map(|(_, value)| match value {
+            Bson::Document(doc) => Ok(doc),
+            _ => Err(FfiError::invalid_argument(format!("the items of {} are not documents", name))),
+        })
+        .collect()
+}
+
+unsafe fn write_buffer(out: *mut PLDBBuffer, bytes: Option<Vec<u8>>) {
+    if out.is_null() {
+        return;
+    }
+    let buffer = match bytes {
+        Some(bytes) => {
+            let bytes = bytes.into_boxed_slice();
+            let len = bytes.len();
+            PLDBBuffer {
+                data: Box::into_raw(bytes) as *mut u8,
+                len,
+            }
+        }
+        None => PLDBBuffer {
+            data: ptr::null_mut(),
+            len: 0,
+        },
+    };
+    out.write(buffer);
+}
+
+unsafe fn write_document(out: *mut PLDBBuffer, doc: Option<&Document>) -> FfiResult<()> {
+    let bytes = match doc {
+        Some(doc) => Some(bson::to_vec(doc)?),
+        None => None,
+    };
+    write_buffer(out, bytes);
+    Ok(())
+}
+
+unsafe fn write_value<T>(out: *mut T, value: T) {
+    if !out.is_null() {
+        out.write(value);
+    }
+}
+
+// runs `$body` with `$col` bound to the collection in the transaction if there is one
+macro_rules! with_collection {
+    ($db:expr, $txn:expr, $name:expr, |$col:ident| $body:expr) => {
+        match $txn {
+            Some(txn) => {
+                let $col = txn.0.collection::<Document>($name);
+                $body
+            }
+            None => {
+                let $col = $db.0.collection::<Document>($name);
+                $body
+            }
+        }
+    };
+}
+
+/// The version of PoloDB, the string is static.
+#[no_mangle]
+pub extern "C" fn polodb_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+/// The message of the last error of the calling thread, or `NULL`.
+/// The string is valid until the next error of the thread.
+#[no_mangle]
+pub extern "C" fn polodb_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error.borrow()
+            .as_ref()
+            .map(|message| message.as_ptr())
+            .unwrap_or(ptr::null())
+    })
+}
+
+/// Release a buffer returned by the library, releasing an empty buffer does nothing.
+#[no_mangle]
+pub unsafe extern "C" fn polodb_buffer_free(buffer: PLDBBuffer) {
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buffer.data, buffer.len)));
+    }
+}
+
+/// Open the database at `path`, it's created if it doesn't exist.
+#[no_mangle]
+pub unsafe extern "C" fn polodb_open(path: *const c_char, out_db: *mut *mut PLDBDatabase) -> PLDBErrorCode {
+    ffi_call(|| {
+        let path = read_str(path, "path")?;
+        let db = Database::open_path(path)?;
+        write_value(out_db, Box::into_raw(Box::new(PLDBDatabase(db))));
+        Ok(())
+    })
+}
+
+/// Open a database in memory, the data is lost when it's closed.
+#[no_mangle]
+pub unsafe extern "C" fn polodb_open_memory(out_db: *mut *mut PLDBDatabase) -> PLDBErrorCode {
+    ffi_call(|| {
+        let db = Database::open_memory()?;
+        write_value(out_db, Box::into_raw(Box::new(PLDBDatabase(db))));
+        Ok(())
+    })
+}
+
+/// Close the database and release the handle.
+/// The transactions and the cursors of the database must be released before.
+#[no_mangle]
+pub unsafe extern "C" fn polodb_close(db: *mut PLDBDatabase) {
+    if !db.is_null() {
+        drop(Box::from_raw(db));
+    }
+}
+
+/// The names of the collections as a BSON array of strings.
+#[no_mangle]
+pub unsafe extern "C" fn polodb_list_collection_names(db: *const PLDBDatabase, out_names: *mut PLDBBuffer) -> PLDBErrorCode {
+    ffi_call(|| {
+        let db = handle(db, "db")?;
+        let mut names = Document::new();
+        for (index, name) in db.0.list_collection_names()?.into_iter().enumerate() {
+            names.insert(index.to_string(), name);
+        }
+        write_document(out_names, Some(&names))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn polodb_create_collection(db: *const PLDBDatabase, name: *const c_char) -> PLDBErrorCode {
+    ffi_call(|| {
+        let db = handle(db, "db")?;
+        db.0.create_collection(read_str(name, "name")?)?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn polodb_drop_collection(db: *const PLDBDatabase, name: *const c_char) -> PLDBErrorCode {
+    ffi_call(|| {
+        let db = handle(db, "db")?;
+        db.0.collection::<Document>(read_str(name, "name")?).drop()?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn polodb_start_transaction(db: *const PLDBDatabase, out_txn: *mut *mut PLDBTransaction) -> PLDBErrorCode {
+    ffi_call(|| {
+        let db = handle(db, "db")?;
+        let txn = db.0.start_transaction()?;
+        write_value(out_txn, Box::into_raw(Box::new(PLDBTransaction(txn))));
+        Ok(())
+    })
+}
+
+/// Commit the transaction, the handle still has to be released by `polodb_transaction_free`.
+#[no_mangle]
+pub unsafe extern "C" fn polodb_transaction_commit(txn: *const PLDBTransaction) -> PLDBErrorCode {
+    ffi_call(|| {
+        handle(txn, "txn")?.0.commit()?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn polodb_transaction_rollback(txn: *const PLDBTransaction) -> PLDBErrorCode {
+    ffi_call(|| {
+        handle(txn, "txn")?.0.rollback()?;
+        Ok(())
+    })
+}
+
+/// Release the transaction, the writes which are not committed are discarded.
+#[no_mangle]
+pub unsafe extern "C" fn polodb_transaction_free(txn: *mut PLDBTransaction) {
+    if !txn.is_null() {
+        drop(Box::from_raw(txn));
+    }
+}
+
+/// Insert a document, `out_result` (nullable) receives `{ "insertedId": ... }`.
+#[no_mangle]
+pub unsafe extern "C" fn polodb_insert_one(
+    db: *const PLDBDatabase,
+    txn: *const PLDBTransaction,
+    col_name: *const c_char,
+    doc: *const u8,
+    doc_len: usize,
+    out_result: *mut PLDBBuffer,
+) -> PLDBErrorCode {
+    ffi_call(|| {
+        let db = handle(db, "db")?;
+        let col_name = read_str(col_name, "col_name")?;
+        let doc = read_document(doc, doc_len, "doc")?;
+        let result = with_collection!(db, txn.as_ref(), col_name, |col| col.insert_one(doc))?;
+        write_document(out_result, Some(&bson::to_document(&result)?))
+    })
+}
+
+/// Insert the BSON array of documents, `out_result` (nullable) receives
+/// `{ "insertedIds": { "0": ..., "1": ... } }`.
+#[no_mangle]
+pub unsafe extern "C" fn polodb_insert_many(
+    db: *const PLDBDatabase,
+    txn: *const PLDBTransaction,
+    col_name: *const c_char,
+    docs: *const u8,
+    docs_len: usize,
+    out_result: *mut PLDBBuffer,
+) -> PLDBErrorCode {
+    ffi_call(|| {
+        let db = handle(db, "db")?;
+        let col_name = read_str(col_name, "col_name")?;
+        let docs = read_array(docs, docs_len, "docs")?;
+        let result = with_collection!(db, txn.as_ref(), col_name, |col| col.insert_many(docs))?;
+        write_document(out_result, Some(&bson::to_document(&result)?))
+    })
+}
+
+/// Find the documents matching `filter`. `options` is a nullable document
+/// with the optional `sort`, `skip` and `limit` fields.
+#[no_mangle]
+pub unsafe extern "C" fn polodb_find(
+    db: *const PLDBDatabase,
+    txn: *const PLDBTransaction,
+    col_name: *const c_char,
+    filter: *const u8,
+    filter_len: usize,
+    options: *const u8,
+    options_len: usize,
+    out_cursor: *mut *mut PLDBCursor,
+) -> PLDBErrorCode {
+    ffi_call(|| {
+        let db = handle(db, "db")?;
+        let col_name = read_str(col_name, "col_name")?;
+        let filter = read_document(filter, filter_len, "filter")?;
+        let options = if options.is_null() {
+            Document::new()
+        } else {
+            read_document(options, options_len, "options")?
+        };
+        let sort = match options.get("sort") {
+            Some(Bson::Document(sort)) => Some(sort.clone()),
+            Some(_) => return Err(FfiError::invalid_argument("sort is not a document")),
+            None => None,
+        };
+        let skip = read_u64_option(&options, "skip")?;
+        let limit = read_u64_option(&options, "limit")?;
+        let cursor = with_collection!(db, txn.as_ref(), col_name, |col| {
+            let mut find = col.find(filter);
+            if let Some(sort) = sort {
+                find = find.sort(sort);
+            }
+            if let Some(skip) = skip {
+                find = find.skip(skip);
+            }
+            if let Some(limit) = limit {
+                find = find.limit(limit);
+            }
+            find.run()
+        })?;
+        write_value(out_cursor, Box::into_raw(Box::new(PLDBCursor(cursor))));
+        Ok(())
+    })
+}
+
+fn read_u64_option(options: &Document, key: &str) -> FfiResult<Option<u64>> {
+    let value = match options.get(key) {
+        Some(Bson::Int32(value)) => *value as i64,
+        Some(Bson::Int64(value)) => *value,
+        Some(_) => return Err(FfiError::invalid_argument(format!("{} is not an integer", key))),
+        None => return Ok(None),
+    };
+    if value < 0 {
+        return Err(FfiError::invalid_argument(format!("{} can't be negative", key)));
+    }
+    Ok(Some(value as u64))
+}
+
+/// Find the first document matching `filter`, `out_doc` is empty if there is none.
+#[no_mangle]
+pub unsafe extern "C" fn polodb_find_one(
+    db: *const PLDBDatabase,
+    txn: *const PLDBTransaction,
+    col_name: *const c_char,
+    filter: *const u8,
+    filter_len: usize,
+    out_doc: *mut PLDBBuffer,
+) -> PLDBErrorCode {
+    ffi_call(|| {
+        let db = handle(db, "db")?;
+        let col_name = read_str(col_name, "col_name")?;
+        let filter = read_document(filter, filter_len, "filter")?;
+        let doc = with_collection!(db, txn.as_ref(), col_name, |col| col.find_one(filter))?;
+        write_document(out_doc, doc.as_ref())
+    })
+}
+
+/// Run the aggregation `pipeline`, a BSON array of stages.
+#[no_mangle]
+pub unsafe extern "C" fn polodb_aggregate(
+    db: *const PLDBDatabase,
+    txn: *const PLDBTransaction,
+    col_name: *const c_char,
+    pipeline: *const u8,
+    pipeline_len: usize,
+    out_cursor: *mut *mut PLDBCursor,
+) -> PLDBErrorCode {
+    ffi_call(|| {
+        let db = handle(db, "db")?;
+        let col_name = read_str(col_name, "col_name")?;
+        let pipeline = read_array(pipeline, pipeline_len, "pipeline")?;
+        let cursor = with_collection!(db, txn.as_ref(), col_name, |col| col.aggregate(pipeline).run())?;
+        write_value(out_cursor, Box::into_raw(Box::new(PLDBCursor(cursor))));
+        Ok(())
+    })
+}
+
+/// Move the cursor to the next document, `out_doc` is empty at the end.
+#[no_mangle]
+pub unsafe extern "C" fn polodb_cursor_next(cursor: *mut PLDBCursor, out_doc: *mut PLDBBuffer) -> PLDBErrorCode {
+    ffi_call(|| {
+        let cursor = cursor.as_mut().ok_or_else(|| FfiError::invalid_argument("cursor is NULL"))?;
+        let doc = cursor.0.next().transpose()?;
+        write_document(out_doc, doc.as_ref())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn polodb_cursor_free(cursor: *mut PLDBCursor) {
+    if !cursor.is_null() {
+        drop(Box::from_raw(cursor));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn polodb_count_documents(
+    db: *const PLDBDatabase,
+    txn: *const PLDBTransaction,
+    col_name: *const c_char,
+    out_count: *mut u64,
+) -> PLDBErrorCode {
+    ffi_call(|| {
+        let db = handle(db, "db")?;
+        let col_name = read_str(col_name, "col_name")?;
+        let count = with_collection!(db, txn.as_ref(), col_name, |col| col.count_documents())?;
+        write_value(out_count, count);
+        Ok(())
+    })
+}
+
+unsafe fn update(
+    db: *const PLDBDatabase,
+    txn: *const PLDBTransaction,
+    col_name: *const c_char,
+    filter: (*const u8, usize),
+    update: (*const u8, usize),
+    many: bool,
+    out_result: *mut PLDBBuffer,
+) -> PLDBErrorCode {
+    ffi_call(|| {
+        let db = handle(db, "db")?;
+        let col_name = read_str(col_name, "col_name")?;
+        let filter = read_document(filter.0, filter.1, "filter")?;
+        let update = read_document(update.0, update.1, "update")?;
+        let result = with_collection!(db, txn.as_ref(), col_name, |col| if many {
+            col.update_many(filter, update)
+        } else {
+            col.update_one(filter, update)
+        })?;
+        write_document(out_result, Some(&bson::to_document(&result)?))
+    })
+}
+
+/// Update the first document matching `filter`, `out_result` (nullable) receives
+/// `{ "matchedCount": ..., "modifiedCount": ... }`.
+#[no_mangle]
+pub unsafe extern "C" fn polodb_update_one(
+    db: *const PLDBDatabase,
+    txn: *const PLDBTransaction,
+    col_name: *const c_char,
+    filter: *const u8,
+    filter_len: usize,
+    update_doc: *const u8,
+    update_len: usize,
+    out_result: *mut PLDBBuffer,
+) -> PLDBErrorCode {
+    update(db, txn, col_name, (filter, filter_len), (update_doc, update_len), false, out_result)
+}
+
+/// Update all the documents matching `filter`, like `polodb_update_one`.
+#[no_mangle]
+pub unsafe extern "C" fn polodb_update_many(
+    db: *const PLDBDatabase,
+    txn: *const PLDBTransaction,
+    col_name: *const c_char,
+    filter: *const u8,
+    filter_len: usize,
+    update_doc: *const u8,
+    update_len: usize,
+    out_result: *mut PLDBBuffer,
+) -> PLDBErrorCode {
+    update(db, txn, col_name, (filter, filter_len), (update_doc, update_len), true, out_result)
+}
+
+unsafe fn delete(
+    db: *const PLDBDatabase,
+    txn: *const PLDBTransaction,
+    col_name: *const c_char,
+    filter: (*const u8, usize),
+    many: bool,
+    out_deleted_count: *mut u64,
+) -> PLDBErrorCode {
+    ffi_call(|| {
+        let db = handle(db, "db")?;
+        let col_name = read_str(col_name, "col_name")?;
+        let filter = read_document(filter.0, filter.1, "filter")?;
+        let result = with_collection!(db, txn.as_ref(), col_name, |col| if many {
+            col.delete_many(filter)
+        } else {
+            col.delete_one(filter)
+        })?;
+        write_value(out_deleted_count, result.deleted_count);
+        Ok(())
+    })
+}
+
+/// Delete the first document matching `filter`, `out_deleted_count` is nullable.
+#[no_mangle]
+pub unsafe extern "C" fn polodb_delete_one(
+    db: *const PLDBDatabase,
+    txn: *const PLDBTransaction,
+    col_name: *const c_char,
+    filter: *const u8,
+    filter_len: usize,
+    out_deleted_count: *mut u64,
+) -> PLDBErrorCode {
+    delete(db, txn, col_name, (filter, filter_len), false, out_deleted_count)
+}
+
+/// Delete all the documents matching `filter`, `out_deleted_count` is nullable.
+#[no_mangle]
+pub unsafe extern "C" fn polodb_delete_many(
+    db: *const PLDBDatabase,
+    txn: *const PLDBTransaction,
+    col_name: *const c_char,
+    filter: *const u8,
+    filter_len: usize,
+    out_deleted_count: *mut u64,
+) -> PLDBErrorCode {
+    delete(db, txn, col_name, (filter, filter_len), true, out_deleted_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{CStr, CString};
+    use std::ptr;
+    use polodb_core::bson::{self, doc, Document};
+    use super::*;
+
+    fn to_bytes(doc: Document) -> Vec<u8> {
+        bson::to_vec(&doc).unwrap()
+    }
+
+    unsafe fn take_document(buffer: PLDBBuffer) -> Option<Document> {
+        if buffer.data.is_null() {
+            return None;
+        }
+        let doc = Document::from_reader(std::slice::from_raw_parts(buffer.data, buffer.len)).unwrap();
+        polodb_buffer_free(buffer);
+        Some(doc)
+    }
+
+    fn empty_buffer() -> PLDBBuffer {
+        PLDBBuffer {
+            data: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    unsafe fn collect(cursor: *mut PLDBCursor) -> Vec<Document> {
+        let mut result = Vec::new();
+        loop {
+            let mut buffer = empty_buffer();
+            assert_eq!(polodb_cursor_next(cursor, &mut buffer), PLDBErrorCode::Ok);
+            match take_document(buffer) {
+                Some(doc) => result.push(doc),
+                None => break,
+            }
+        }
+        polodb_cursor_free(cursor);
+        result
+    }
+
+    #[test]
+    fn test_crud() {
+        unsafe {
+            let mut db = ptr::null_mut();
+            assert_eq!(polodb_open_memory(&mut db), PLDBErrorCode::Ok);
+            let col_name = CString::new("books").unwrap();
+
+            let docs = to_bytes(doc! {
+                "0": { "_id": 1, "title": "1984" },
+                "1": { "_id": 2, "title": "Brave New World" },
+            });
+            let mut result = empty_buffer();
+            assert_eq!(polodb_insert_many(db, ptr::null(), col_name.as_ptr(), docs.as_ptr(), docs.len(), &mut result), PLDBErrorCode::Ok);
+            assert_eq!(take_document(result).unwrap(), doc! { "insertedIds": { "0": 1, "1": 2 } });
+
+            let update = to_bytes(doc! { "$set": { "year": 1949 } });
+            let filter = to_bytes(doc! { "_id": 1 });
+            let mut result = empty_buffer();
+            assert_eq!(polodb_update_one(db, ptr::null(), col_name.as_ptr(), filter.as_ptr(), filter.len(), update.as_ptr(), update.len(), &mut result), PLDBErrorCode::Ok);
+            assert_eq!(take_document(result).unwrap(), doc! { "matchedCount": 1i64, "modifiedCount": 1i64 });
+
+            let mut found = empty_buffer();
+            assert_eq!(polodb_find_one(db, ptr::null(), col_name.as_ptr(), filter.as_ptr(), filter.len(), &mut found), PLDBErrorCode::Ok);
+            assert_eq!(take_document(found).unwrap(), doc! { "_id": 1, "title": "1984", "year": 1949 });
+
+            let all = to_bytes(doc! {});
+            let options = to_bytes(doc! { "sort": { "_id": -1 }, "limit": 1 });
+            let mut cursor = ptr::null_mut();
+            assert_eq!(polodb_find(db, ptr::null(), col_name.as_ptr(), all.as_ptr(), all.len(), options.as_ptr(), options.len(), &mut cursor), PLDBErrorCode::Ok);
+            assert_eq!(collect(cursor), vec![doc! { "_id": 2, "title": "Brave New World" }]);
+
+            let pipeline = to_bytes(doc! { "0": { "$match": { "year": 1949 } } });
+            let mut cursor = ptr::null_mut();
+            assert_eq!(polodb_aggregate(db, ptr::null(), col_name.as_ptr(), pipeline.as_ptr(), pipeline.len(), &mut cursor), PLDBErrorCode::Ok);
+            assert_eq!(collect(cursor).len(), 1);
+
+            let mut deleted = 0;
+            assert_eq!(polodb_delete_many(db, ptr::null(), col_name.as_ptr(), all.as_ptr(), all.len(), &mut deleted), PLDBErrorCode::Ok);
+            assert_eq!(deleted, 2);
+
+            polodb_close(db);
+        }
+    }
+
+    #[test]
+    fn test_transaction() {
+        unsafe {
+            let mut db = ptr::null_mut();
+            assert_eq!(polodb_open_memory(&mut db), PLDBErrorCode::Ok);
+            let col_name = CString::new("items").unwrap();
+            let doc = to_bytes(doc! { "name": "a" });
+
+            let mut txn = ptr::null_mut();
+            assert_eq!(polodb_start_transaction(db, &mut txn), PLDBErrorCode::Ok);
+            assert_eq!(polodb_insert_one(db, txn, col_name.as_ptr(), doc.as_ptr(), doc.len(), ptr::null_mut()), PLDBErrorCode::Ok);
+            let mut count = 0;
+            assert_eq!(polodb_count_documents(db, txn, col_name.as_ptr(), &mut count), PLDBErrorCode::Ok);
+            assert_eq!(count, 1);
+            assert_eq!(polodb_transaction_rollback(txn), PLDBErrorCode::Ok);
+            polodb_transaction_free(txn);
+
+            let mut count = 0;
+            assert_eq!(polodb_count_documents(db, ptr::null(), col_name.as_ptr(), &mut count), PLDBErrorCode::Ok);
+            assert_eq!(count, 0);
+
+            let mut txn = ptr::null_mut();
+            assert_eq!(polodb_start_transaction(db, &mut txn), PLDBErrorCode::Ok);
+            assert_eq!(polodb_insert_one(db, txn, col_name.as_ptr(), doc.as_ptr(), doc.len(), ptr::null_mut()), PLDBErrorCode::Ok);
+            assert_eq!(polodb_transaction_commit(txn), PLDBErrorCode::Ok);
+            polodb_transaction_free(txn);
+
+            let mut names = empty_buffer();
+            assert_eq!(polodb_list_collection_names(db, &mut names), PLDBErrorCode::Ok);
+            assert_eq!(take_document(names).unwrap(), doc! { "0": "items" });
+
+            polodb_close(db);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        unsafe {
+            let mut db = ptr::null_mut();
+            assert_eq!(polodb_open_memory(&mut db), PLDBErrorCode::Ok);
+            let col_name = CString::new("users").unwrap();
+            let last_error = || CStr::from_ptr(polodb_last_error_message()).to_str().unwrap().to_string();
+
+            let doc = to_bytes(doc! { "_id": 1 });
+            assert_eq!(polodb_insert_one(db, ptr::null(), col_name.as_ptr(), doc.as_ptr(), doc.len(), ptr::null_mut()), PLDBErrorCode::Ok);
+
+            let pipeline = to_bytes(doc! { "0": { "$unknown": {} } });
+            let mut cursor = ptr::null_mut();
+            assert_eq!(polodb_aggregate(db, ptr::null(), col_name.as_ptr(), pipeline.as_ptr(), pipeline.len(), &mut cursor), PLDBErrorCode::Error);
+            assert!(cursor.is_null());
+
+            assert_eq!(polodb_insert_one(db, ptr::null(), col_name.as_ptr(), doc.as_ptr(), doc.len() - 1, ptr::null_mut()), PLDBErrorCode::InvalidArgument);
+            assert_eq!(last_error(), "doc is not a BSON document of 13 bytes");
+
+            assert_eq!(polodb_insert_one(db, ptr::null(), ptr::null(), doc.as_ptr(), doc.len(), ptr::null_mut()), PLDBErrorCode::InvalidArgument);
+            assert_eq!(last_error(), "col_name is NULL");
+
+            assert_eq!(polodb_count_documents(ptr::null(), ptr::null(), col_name.as_ptr(), ptr::null_mut()), PLDBErrorCode::InvalidArgument);
+            assert_eq!(last_error(), "db is NULL");
+
+            polodb_close(db);
+        }
+    }
+
+    #[test]
+    fn test_header_is_up_to_date() {
+        let header = include_str!("include/polodb.h");
+        let source = include_str!("lib.rs");
+        for line in source.lines() {
+            let name = match line.strip_prefix("pub unsafe extern \"C\" fn ").or_else(|| line.strip_prefix("pub extern \"C\" fn ")) {
+                Some(rest) => rest.split('(').next().unwrap(),
+                None => continue,
+            };
+            assert!(header.contains(&format!("{}(", name)), "{} is not in the header, run cbindgen", name);
+        }
+    }
+
+}
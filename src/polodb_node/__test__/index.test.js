@@ -0,0 +1,164 @@
+'use strict'
+
+const test = require('node:test')
+const assert = require('node:assert')
+const fs = require('fs')
+const os = require('os')
+const path = require('path')
+const { Database, ObjectId } = require('..')
+
+test('insert and find', async () => {
+  const db = new Database()
+  const col = db.collection('books')
+  const { insertedId } = await col.insertOne({ title: 'The Three-Body Problem', year: 2008 })
+  assert.ok(insertedId instanceof ObjectId)
+
+  const { insertedIds } = await col.insertMany([
+    { title: 'The Dark Forest', year: 2008 },
+    { title: "Death's End", year: 2010 },
+  ])
+  assert.strictEqual(insertedIds.length, 2)
+  assert.strictEqual(await col.countDocuments(), 3)
+  assert.strictEqual(await col.countDocuments({ year: 2008 }), 2)
+
+  const doc = await col.findOne({ _id: insertedId })
+  assert.strictEqual(doc.title, 'The Three-Body Problem')
+  assert.ok(doc._id.equals(insertedId))
+  assert.strictEqual(await col.findOne({ year: 1900 }), null)
+
+  const titles = (await col.find({}, { sort: { year: -1 }, limit: 2 }).toArray()).map(doc => doc.year)
+  assert.deepStrictEqual(titles, [2010, 2008])
+  assert.deepStrictEqual(await db.listCollectionNames(), ['books'])
+})
+
+test('update and delete', async () => {
+  const db = new Database()
+  const col = db.collection('users')
+  await col.insertMany([{ name: 'a', age: 1 }, { name: 'b', age: 2 }, { name: 'c', age: 3 }])
+
+  assert.deepStrictEqual(await col.updateMany({ age: { $gt: 1 } }, { $inc: { age: 10 } }), {
+    matchedCount: 2,
+    modifiedCount: 2,
+  })
+  await col.updateOne({ name: 'd' }, { $set: { age: 4 } }, { upsert: true })
+  assert.strictEqual((await col.findOne({ name: 'd' })).age, 4)
+
+  assert.deepStrictEqual(await col.deleteOne({ name: 'a' }), { deletedCount: 1 })
+  assert.deepStrictEqual(await col.deleteMany({}), { deletedCount: 3 })
+})
+
+test('convert values', async () => {
+  const db = new Database()
+  const col = db.collection('values')
+  const date = new Date('2024-01-02T03:04:05.678Z')
+  const id = new ObjectId('65a1b2c3d4e5f6a7b8c9d0e1')
+  await col.insertOne({
+    _id: id,
+    int: 42,
+    double: 1.5,
+    big: 2n ** 60n,
+    string: 'hello',
+    bool: true,
+    nil: null,
+    date,
+    buffer: Buffer.from([1, 2, 3]),
+    array: [1, 'two', { three: 3 }],
+    nested: { a: { b: 'c' } },
+  })
+
+  const doc = await col.findOne({ _id: id })
+  assert.strictEqual(doc._id.toHexString(), '65a1b2c3d4e5f6a7b8c9d0e1')
+  assert.strictEqual(doc.int, 42)
+  assert.strictEqual(doc.double, 1.5)
+  assert.strictEqual(doc.big, 2n ** 60n)
+  assert.strictEqual(doc.string, 'hello')
+  assert.strictEqual(doc.bool, true)
+  assert.strictEqual(doc.nil, null)
+  assert.strictEqual(doc.date.getTime(), date.getTime())
+  assert.deepStrictEqual([...doc.buffer], [1, 2, 3])
+  assert.deepStrictEqual(doc.array, [1, 'two', { three: 3 }])
+  assert.deepStrictEqual(doc.nested, { a: { b: 'c' } })
+  assert.strictEqual(JSON.stringify({ id }), '{"id":"65a1b2c3d4e5f6a7b8c9d0e1"}')
+
+  // the arguments are converted before the operation starts
+  assert.throws(() => col.insertOne([1, 2]), /expect an object/)
+  assert.throws(() => new ObjectId('not an id'))
+})
+
+test('cursor streaming', async () => {
+  const db = new Database()
+  const col = db.collection('numbers')
+  const docs = []
+  for (let i = 0; i < 250; i++) {
+    docs.push({ n: i })
+  }
+  await col.insertMany(docs)
+
+  let sum = 0
+  for await (const doc of col.find({}, { sort: { n: 1 } })) {
+    sum += doc.n
+  }
+  assert.strictEqual(sum, (249 * 250) / 2)
+
+  let count = 0
+  for await (const doc of col.find({ n: { $lt: 10 } }).stream()) {
+    assert.ok(doc.n < 10)
+    count += 1
+  }
+  assert.strictEqual(count, 10)
+
+  // breaking out of the loop closes the cursor
+  for await (const doc of col.find()) {
+    assert.strictEqual(typeof doc.n, 'number')
+    break
+  }
+
+  const result = await col.aggregate([
+    { $match: { n: { $gte: 100 } } },
+    { $count: 'total' },
+  ]).toArray()
+  assert.deepStrictEqual(result, [{ total: 150 }])
+})
+
+test('transaction', async () => {
+  const db = new Database()
+  await db.withTransaction(async txn => {
+    await txn.collection('accounts').insertOne({ name: 'alice', balance: 100 })
+  })
+  assert.strictEqual(await db.collection('accounts').countDocuments(), 1)
+
+  await assert.rejects(db.withTransaction(async txn => {
+    await txn.collection('accounts').insertOne({ name: 'bob', balance: 50 })
+    throw new Error('abort')
+  }), /abort/)
+  assert.strictEqual(await db.collection('accounts').countDocuments(), 1)
+
+  const txn = await db.startTransaction()
+  await txn.collection('accounts').updateOne({ name: 'alice' }, { $set: { balance: 0 } })
+  await txn.rollback()
+  assert.strictEqual((await db.collection('accounts').findOne({ name: 'alice' })).balance, 100)
+})
+
+test('index and errors', async () => {
+  const db = new Database()
+  const col = db.collection('users')
+  assert.strictEqual(await col.createIndex({ email: 1 }, { unique: true }), 'email_1')
+  await col.insertOne({ email: 'a@example.com' })
+  await assert.rejects(col.insertOne({ email: 'a@example.com' }), /duplicate key/i)
+})
+
+test('open a path', async () => {
+  const dir = fs.mkdtempSync(path.join(os.tmpdir(), 'polodb-node-'))
+  const dbPath = path.join(dir, 'test.db')
+  try {
+    const db = new Database(dbPath)
+    await db.collection('test').insertOne({ _id: 1, value: 'persisted' })
+    const other = db.database('other')
+    assert.strictEqual(other.name, 'other')
+    await other.collection('test').insertOne({ _id: 1 })
+    assert.deepStrictEqual(await other.listCollectionNames(), ['test'])
+    assert.strictEqual(db.name, null)
+  } finally {
+    fs.rmSync(dir, { recursive: true, force: true })
+  }
+})
@@ -0,0 +1,105 @@
+/// <reference types="node" />
+
+import { Readable } from 'stream'
+
+export type Document = { [key: string]: any }
+
+export class ObjectId {
+  /** Generate a new ObjectId, or parse the hex string. */
+  constructor(hex?: string)
+  toHexString(): string
+  toString(): string
+  toJSON(): string
+  equals(other: ObjectId): boolean
+}
+
+export interface FindOptions {
+  sort?: Document
+  skip?: number
+  limit?: number
+}
+
+export interface UpdateOptions {
+  upsert?: boolean
+}
+
+export interface IndexOptions {
+  name?: string
+  unique?: boolean
+}
+
+export interface InsertOneResult {
+  insertedId: any
+}
+
+export interface InsertManyResult {
+  insertedIds: any[]
+}
+
+export interface UpdateResult {
+  matchedCount: number
+  modifiedCount: number
+}
+
+export interface DeleteResult {
+  deletedCount: number
+}
+
+export class Database {
+  /** Open the database at `path`, or a database in memory without a path. */
+  constructor(path?: string)
+  /** The name of the named database, `null` for the default one. */
+  readonly name: string | null
+  /** The named database `name` in the same file. */
+  database(name: string): Database
+  collection(name: string): Collection
+  listCollectionNames(): Promise<string[]>
+  createCollection(name: string): Promise<void>
+  dropCollection(name: string): Promise<void>
+  startTransaction(): Promise<Transaction>
+  /**
+   * Run `fn` in a transaction, it's committed if the returned promise resolves,
+   * or rolled back if it rejects.
+   */
+  withTransaction<T>(fn: (txn: Transaction) => Promise<T>): Promise<T>
+}
+
+export class Transaction {
+  collection(name: string): Collection
+  commit(): Promise<void>
+  rollback(): Promise<void>
+}
+
+export class Collection {
+  readonly name: string
+  insertOne(doc: Document): Promise<InsertOneResult>
+  insertMany(docs: Document[]): Promise<InsertManyResult>
+  find(filter?: Document, options?: FindOptions): Cursor
+  findOne(filter?: Document): Promise<Document | null>
+  countDocuments(filter?: Document): Promise<number>
+  updateOne(filter: Document, update: Document, options?: UpdateOptions): Promise<UpdateResult>
+  updateMany(filter: Document, update: Document, options?: UpdateOptions): Promise<UpdateResult>
+  deleteOne(filter: Document): Promise<DeleteResult>
+  deleteMany(filter: Document): Promise<DeleteResult>
+  aggregate(pipeline: Document[]): Cursor
+  /** Create an index of `keys`, e.g. `{ age: 1 }`, and return its name. */
+  createIndex(keys: Document, options?: IndexOptions): Promise<string>
+  dropIndex(name: string): Promise<void>
+  drop(): Promise<void>
+}
+
+export class Cursor implements AsyncIterable<Document> {
+  /** The next document, or `null` at the end. */
+  next(): Promise<Document | null>
+  toArray(): Promise<Document[]>
+  /** A readable stream in object mode. */
+  stream(): Readable
+  close(): void
+  [Symbol.asyncIterator](): AsyncIterator<Document>
+}
+
+export class NativeCursor {
+  /** Read up to `size` documents, the array is empty at the end. */
+  nextBatch(size: number): Promise<Document[]>
+  close(): void
+}
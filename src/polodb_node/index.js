@@ -0,0 +1,105 @@
+'use strict'
+
+const { Readable } = require('stream')
+const native = require('./polodb.node')
+
+const { Database, Transaction, Collection, NativeCursor, ObjectId } = native
+
+const BATCH_SIZE = 100
+
+/**
+ * The cursor of `find()` and `aggregate()`, the query runs when the first document is read.
+ * It's an async iterator:
+ *
+ *     for await (const doc of collection.find({ age: { $gt: 18 } })) { ... }
+ */
+class Cursor {
+  constructor(open) {
+    this._open = open
+    this._cursor = null
+    this._buffer = []
+    this._done = false
+  }
+
+  /** The next document, or `null` at the end. */
+  async next() {
+    if (this._buffer.length === 0 && !this._done) {
+      if (this._cursor === null) {
+        this._cursor = await this._open()
+      }
+      this._buffer = await this._cursor.nextBatch(BATCH_SIZE)
+      if (this._buffer.length === 0) {
+        this._done = true
+      }
+    }
+    return this._buffer.length > 0 ? this._buffer.shift() : null
+  }
+
+  async toArray() {
+    const result = []
+    for await (const doc of this) {
+      result.push(doc)
+    }
+    return result
+  }
+
+  /** A readable stream in object mode. */
+  stream() {
+    return Readable.from(this)
+  }
+
+  close() {
+    this._done = true
+    this._buffer = []
+    if (this._cursor !== null) {
+      this._cursor.close()
+    }
+  }
+
+  [Symbol.asyncIterator]() {
+    return {
+      next: async () => {
+        const value = await this.next()
+        return value === null ? { value: undefined, done: true } : { value, done: false }
+      },
+      return: async () => {
+        this.close()
+        return { value: undefined, done: true }
+      },
+    }
+  }
+}
+
+Collection.prototype.find = function (filter, options) {
+  return new Cursor(() => this._find(filter, options))
+}
+
+Collection.prototype.aggregate = function (pipeline) {
+  return new Cursor(() => this._aggregate(pipeline))
+}
+
+/**
+ * Run `fn` in a transaction, it's committed if the returned promise resolves,
+ * or rolled back if it rejects.
+ */
+Database.prototype.withTransaction = async function (fn) {
+  const txn = await this.startTransaction()
+  let result
+  try {
+    result = await fn(txn)
+  } catch (err) {
+    await txn.rollback()
+    throw err
+  }
+  await txn.commit()
+  return result
+}
+
+module.exports = {
+  Database,
+  Transaction,
+  Collection,
+  Cursor,
+  NativeCursor,
+  ObjectId,
+}
@@ -0,0 +1,705 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The Node.js bindings of PoloDB, built with [napi-rs](https://napi.rs/).
+//! The classes are wrapped by `index.js`, see `index.d.ts` for the API.
+//!
+//! The operations run in the thread pool of libuv and return a `Promise`,
+//! the values are converted on the main thread:
+//!
+//! | BSON         | JavaScript                                          |
+//! |--------------|-----------------------------------------------------|
+//! | document     | `Object`                                            |
+//! | array        | `Array`                                             |
+//! | int32        | `number`, the integers in the range of int32 are stored as int32 |
+//! | int64        | `number`, or `bigint` if it's not a safe integer, `bigint` is stored as int64 |
+//! | double       | `number`                                            |
+//! | string       | `string`                                            |
+//! | boolean      | `boolean`                                           |
+//! | null         | `null`, `undefined` is stored as null               |
+//! | ObjectId     | `ObjectId`                                          |
+//! | date         | `Date`                                              |
+//! | binary       | `Buffer`                                            |
+//!
+//! The other BSON types are returned as their extended JSON, e.g. `{ $numberDecimal: "0.10" }`.
+
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+use napi::bindgen_prelude::*;
+use napi::{Env, NapiRaw, JsBigInt, JsBoolean, JsBuffer, JsDate, JsNumber, JsObject, JsString, JsUnknown, Task, ValueType};
+use napi_derive::napi;
+use polodb_core::bson::{oid, spec::BinarySubtype, Binary, Bson, DateTime, Document};
+use polodb_core::options::UpdateOptions;
+use polodb_core::{ClientCursor, CollectionT, Database as CoreDatabase, IndexModel, IndexOptions, Transaction as CoreTransaction};
+use serde_json::Value;
+
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+fn to_napi_err(err: polodb_core::Error) -> Error {
+    Error::new(Status::GenericFailure, err.to_string())
+}
+
+fn type_error<T: Into<String>>(message: T) -> Error {
+    Error::new(Status::InvalidArg, message.into())
+}
+
+#[napi]
+pub struct ObjectId {
+    inner: oid::ObjectId,
+}
+
+#[napi]
+impl ObjectId {
+
+    /// Generate a new ObjectId, or parse the hex string.
+    #[napi(constructor)]
+    pub fn new(hex: Option<String>) -> Result<ObjectId> {
+        let inner = match hex {
+            Some(hex) => oid::ObjectId::parse_str(&hex).map_err(|err| type_error(err.to_string()))?,
+            None => oid::ObjectId::new(),
+        };
+        Ok(ObjectId {
+            inner,
+        })
+    }
+
+    #[napi]
+    pub fn to_hex_string(&self) -> String {
+        self.inner.to_hex()
+    }
+
+    #[napi(js_name = "toString")]
+    pub fn to_js_string(&self) -> String {
+        self.inner.to_hex()
+    }
+
+    #[napi(js_name = "toJSON")]
+    pub fn to_json(&self) -> String {
+        self.inner.to_hex()
+    }
+
+    #[napi]
+    pub fn equals(&self, other: &ObjectId) -> bool {
+        self.inner == other.inner
+    }
+
+}
+
+fn js_to_bson(env: &Env, value: JsUnknown) -> Result<Bson> {
+    let result = match value.get_type()? {
+        ValueType::Undefined | ValueType::Null => Bson::Null,
+        ValueType::Boolean => Bson::Boolean(unsafe { value.cast::<JsBoolean>() }.get_value()?),
+        ValueType::Number => {
+            let number = unsafe { value.cast::<JsNumber>() }.get_double()?;
+            if number.fract() == 0.0 && number >= i32::MIN as f64 && number <= i32::MAX as f64 {
+                Bson::Int32(number as i32)
+            } else {
+                Bson::Double(number)
+            }
+        }
+        ValueType::String => Bson::String(unsafe { value.cast::<JsString>() }.into_utf8()?.into_owned()?),
+        ValueType::BigInt => {
+            let (number, lossless) = unsafe { value.cast::<JsBigInt>() }.get_i64()?;
+            if !lossless {
+                return Err(type_error("the bigint doesn't fit in 64 bits"));
+            }
+            Bson::Int64(number)
+        }
+        ValueType::Object => {
+            if value.is_array()? {
+                let array = unsafe { value.cast::<JsObject>() };
+                let mut items = Vec::new();
+                for index in 0..array.get_array_length()? {
+                    items.push(js_to_bson(env, array.get_element::<JsUnknown>(index)?)?);
+                }
+                Bson::Array(items)
+            } else if value.is_date()? {
+                let millis = unsafe { value.cast::<JsDate>() }.value_of()?;
+                if !millis.is_finite() {
+                    return Err(type_error("invalid date"));
+                }
+                Bson::DateTime(DateTime::from_millis(millis as i64))
+            } else if value.is_buffer()? {
+                let buffer = unsafe { value.cast::<JsBuffer>() }.into_value()?;
+                Bson::Binary(Binary {
+                    subtype: BinarySubtype::Generic,
+                    bytes: buffer.to_vec(),
+                })
+            } else if ObjectId::instance_of(*env, &value)? {
+                let oid = unsafe { ObjectId::from_napi_ref(env.raw(), value.raw()) }?;
+                Bson::ObjectId(oid.inner)
+            } else {
+                Bson::Document(js_to_document(env, value)?)
+            }
+        }
+        value_type => return Err(type_error(format!("cannot convert a {} to BSON", value_type))),
+    };
+    Ok(result)
+}
+
+fn js_to_document(env: &Env, value: JsUnknown) -> Result<Document> {
+    if value.get_type()? != ValueType::Object || value.is_array()? {
+        return Err(type_error("expect an object"));
+    }
+    let object = unsafe { value.cast::<JsObject>() };
+    let names = object.get_property_names()?;
+    let mut doc = Document::new();
+    for index in 0..names.get_array_length()? {
+        let name = names.get_element::<JsString>(index)?.into_utf8()?.into_owned()?;
+        let value = object.get_named_property::<JsUnknown>(&name)?;
+        // the undefined properties are skipped like JSON.stringify
+        if value.get_type()? == ValueType::Undefined {
+            continue;
+        }
+        doc.insert(name, js_to_bson(env, value)?);
+    }
+    Ok(doc)
+}
+
+fn optional_document(env: &Env, value: Option<JsUnknown>) -> Result<Document> {
+    match value {
+        Some(value) if !matches!(value.get_type()?, ValueType::Undefined | ValueType::Null) => js_to_document(env, value),
+        _ => Ok(Document::new()),
+    }
+}
+
+fn js_to_documents(env: &Env, value: JsUnknown) -> Result<Vec<Document>> {
+    if !value.is_array()? {
+        return Err(type_error("expect an array of objects"));
+    }
+    let array = unsafe { value.cast::<JsObject>() };
+    let mut docs = Vec::new();
+    for index in 0..array.get_array_length()? {
+        docs.push(js_to_document(env, array.get_element::<JsUnknown>(index)?)?);
+    }
+    Ok(docs)
+}
+
+fn bson_to_js(env: &Env, value: &Bson) -> Result<JsUnknown> {
+    let result = match value {
+        Bson::Null | Bson::Undefined => env.get_null()?.into_unknown(),
+        Bson::Boolean(value) => env.get_boolean(*value)?.into_unknown(),
+        Bson::Int32(value) => env.create_int32(*value)?.into_unknown(),
+        Bson::Int64(value) if value.abs() <= MAX_SAFE_INTEGER => env.create_int64(*value)?.into_unknown(),
+        Bson::Int64(value) => env.create_bigint_from_i64(*value)?.into_unknown()?,
+        Bson::Double(value) => env.create_double(*value)?.into_unknown(),
+        Bson::String(value) => env.create_string(value)?.into_unknown(),
+        Bson::Document(doc) => document_to_js(env, doc)?.into_unknown(),
+        Bson::Array(items) => {
+            let mut array = env.create_array_with_length(items.len())?;
+            for (index, item) in items.iter().enumerate() {
+                array.set_element(index as u32, bson_to_js(env, item)?)?;
+            }
+            array.into_unknown()
+        }
+        Bson::ObjectId(oid) => ObjectId { inner: *oid }.into_instance(*env)?.as_object(*env).into_unknown(),
+        Bson::DateTime(date) => env.create_date(date.timestamp_millis() as f64)?.into_unknown(),
+        Bson::Binary(binary) => env.create_buffer_with_data(binary.bytes.clone())?.into_raw().into_unknown(),
+        value => json_to_js(env, &value.clone().into_relaxed_extjson())?,
+    };
+    Ok(result)
+}
+
+fn document_to_js(env: &Env, doc: &Document) -> Result<JsObject> {
+    let mut object = env.create_object()?;
+    for (key, value) in doc {
+        object.set_named_property(key, bson_to_js(env, value)?)?;
+    }
+    Ok(object)
+}
+
+fn json_to_js(env: &Env, value: &Value) -> Result<JsUnknown> {
+    let result = match value {
+        Value::Null => env.get_null()?.into_unknown(),
+        Value::Bool(value) => env.get_boolean(*value)?.into_unknown(),
+        Value::Number(number) => env.create_double(number.as_f64().unwrap_or(f64::NAN))?.into_unknown(),
+        Value::String(value) => env.create_string(value)?.into_unknown(),
+        Value::Array(items) => {
+            let mut array = env.create_array_with_length(items.len())?;
+            for (index, item) in items.iter().enumerate() {
+                array.set_element(index as u32, json_to_js(env, item)?)?;
+            }
+            array.into_unknown()
+        }
+        Value::Object(fields) => {
+            let mut object = env.create_object()?;
+            for (key, value) in fields {
+                object.set_named_property(key, json_to_js(env, value)?)?;
+            }
+            object.into_unknown()
+        }
+    };
+    Ok(result)
+}
+
+/// The result of a [`DbTask`], converted to JavaScript on the main thread.
+pub enum Output {
+    Value(Bson),
+    Documents(Vec<Document>),
+    Cursor(Box<ClientCursor<Document>>),
+    Transaction(CoreTransaction),
+}
+
+type Job = Box<dyn FnOnce() -> polodb_core::Result<Output> + Send>;
+
+/// An operation running in the thread pool of libuv.
+pub struct DbTask {
+    db: Arc<CoreDatabase>,
+    job: Option<Job>,
+}
+
+impl Task for DbTask {
+    type Output = Output;
+    type JsValue = JsUnknown;
+
+    fn compute(&mut self) -> Result<Output> {
+        let job = self.job.take().unwrap();
+        job().map_err(to_napi_err)
+    }
+
+    fn resolve(&mut self, env: Env, output: Output) -> Result<JsUnknown> {
+        let result = match output {
+            Output::Value(value) => bson_to_js(&env, &value)?,
+            Output::Documents(docs) => {
+                let mut array = env.create_array_with_length(docs.len())?;
+                for (index, doc) in docs.iter().enumerate() {
+                    array.set_element(index as u32, document_to_js(&env, doc)?)?;
+                }
+                array.into_unknown()
+            }
+            Output::Cursor(cursor) => {
+                let cursor = NativeCursor {
+                    cursor: Arc::new(Mutex::new(Some(*cursor))),
+                    db: self.db.clone(),
+                };
+                cursor.into_instance(env)?.as_object(env).into_unknown()
+            }
+            Output::Transaction(txn) => {
+                let txn = Transaction {
+                    txn,
+                    db: self.db.clone(),
+                };
+                txn.into_instance(env)?.as_object(env).into_unknown()
+            }
+        };
+        Ok(result)
+    }
+}
+
+fn spawn<F>(db: &Arc<CoreDatabase>, job: F) -> AsyncTask<DbTask>
+where
+    F: FnOnce() -> polodb_core::Result<Output> + Send + 'static,
+{
+    AsyncTask::new(DbTask {
+        db: db.clone(),
+        job: Some(Box::new(job)),
+    })
+}
+
+fn unit() -> Output {
+    Output::Value(Bson::Null)
+}
+
+#[napi]
+pub struct Database {
+    db: Arc<CoreDatabase>,
+}
+
+#[napi]
+impl Database {
+
+    /// Open the database at `path`, or a database in memory without a path.
+    #[napi(constructor)]
+    pub fn new(path: Option<String>) -> Result<Database> {
+        let db = match path {
+            Some(path) => CoreDatabase::open_path(path),
+            None => CoreDatabase::open_memory(),
+        }.map_err(to_napi_err)?;
+        Ok(Database {
+            db: Arc::new(db),
+        })
+    }
+
+    /// The named database `name` in the same file.
+    #[napi]
+    pub fn database(&self, name: String) -> Database {
+        Database {
+            db: Arc::new(self.db.database(&name)),
+        }
+    }
+
+    /// The name of the named database, `null` for the default one.
+    #[napi(getter)]
+    pub fn name(&self) -> Option<String> {
+        self.db.name().map(String::from)
+    }
+
+    #[napi]
+    pub fn collection(&self, name: String) -> Collection {
+        Collection {
+            txn: None,
+            db: self.db.clone(),
+            name,
+        }
+    }
+
+    #[napi(ts_return_type = "Promise<string[]>")]
+    pub fn list_collection_names(&self) -> AsyncTask<DbTask> {
+        let db = self.db.clone();
+        spawn(&self.db, move || {
+            let names = db.list_collection_names()?;
+            Ok(Output::Value(Bson::Array(names.into_iter().map(Bson::String).collect())))
+        })
+    }
+
+    #[napi(ts_return_type = "Promise<void>")]
+    pub fn create_collection(&self, name: String) -> AsyncTask<DbTask> {
+        let db = self.db.clone();
+        spawn(&self.db, move || db.create_collection(&name).map(|_| unit()))
+    }
+
+    #[napi(ts_return_type = "Promise<void>")]
+    pub fn drop_collection(&self, name: String) -> AsyncTask<DbTask> {
+        let db = self.db.clone();
+        spawn(&self.db, move || db.collection::<Document>(&name).drop().map(|_| unit()))
+    }
+
+    #[napi(ts_return_type = "Promise<Transaction>")]
+    pub fn start_transaction(&self) -> AsyncTask<DbTask> {
+        let db = self.db.clone();
+        spawn(&self.db, move || db.start_transaction().map(Output::Transaction))
+    }
+
+}
+
+#[napi]
+pub struct Transaction {
+    // dropped before the database
+    txn: CoreTransaction,
+    db: Arc<CoreDatabase>,
+}
+
+#[napi]
+impl Transaction {
+
+    #[napi]
+    pub fn collection(&self, name: String) -> Collection {
+        Collection {
+            txn: Some(self.txn.clone()),
+            db: self.db.clone(),
+            name,
+        }
+    }
+
+    #[napi(ts_return_type = "Promise<void>")]
+    pub fn commit(&self) -> AsyncTask<DbTask> {
+        let txn = self.txn.clone();
+        spawn(&self.db, move || txn.commit().map(|_| unit()))
+    }
+
+    #[napi(ts_return_type = "Promise<void>")]
+    pub fn rollback(&self) -> AsyncTask<DbTask> {
+        let txn = self.txn.clone();
+        spawn(&self.db, move || txn.rollback().map(|_| unit()))
+    }
+
+}
+
+// runs `$body` with `$col` bound to the collection in the transaction if there is one
+macro_rules! with_collection {
+    ($db:expr, $txn:expr, $name:expr, |$col:ident| $body:expr) => {
+        match &$txn {
+            Some(txn) => {
+                let $col = txn.collection::<Document>(&$name);
+                $body
+            }
+            None => {
+                let $col = $db.collection::<Document>(&$name);
+                $body
+            }
+        }
+    };
+}
+
+#[napi]
+pub struct Collection {
+    txn: Option<CoreTransaction>,
+    db: Arc<CoreDatabase>,
+    name: String,
+}
+
+impl Collection {
+
+    fn spawn<F>(&self, job: F) -> AsyncTask<DbTask>
+    where
+        F: FnOnce(&CoreDatabase, &Option<CoreTransaction>, &str) -> polodb_core::Result<Output> + Send + 'static,
+    {
+        let db = self.db.clone();
+        let txn = self.txn.clone();
+        let name = self.name.clone();
+        spawn(&self.db, move || job(&db, &txn, &name))
+    }
+
+    fn update(&self, env: Env, filter: JsUnknown, update: JsUnknown, options: Option<JsUnknown>, many: bool) -> Result<AsyncTask<DbTask>> {
+        let filter = js_to_document(&env, filter)?;
+        let update = js_to_document(&env, update)?;
+        let options = optional_document(&env, options)?;
+        let options = UpdateOptions::builder()
+            .upsert(options.get_bool("upsert").unwrap_or(false))
+            .build();
+        Ok(self.spawn(move |db, txn, name| {
+            let result = with_collection!(db, txn, name, |col| if many {
+                col.update_many_with_options(filter, update, options)
+            } else {
+                col.update_one_with_options(filter, update, options)
+            })?;
+            Ok(Output::Value(Bson::Document(polodb_core::bson::to_document(&result)?)))
+        }))
+    }
+
+    fn delete(&self, env: Env, filter: JsUnknown, many: bool) -> Result<AsyncTask<DbTask>> {
+        let filter = js_to_document(&env, filter)?;
+        Ok(self.spawn(move |db, txn, name| {
+            let result = with_collection!(db, txn, name, |col| if many {
+                col.delete_many(filter)
+            } else {
+                col.delete_one(filter)
+            })?;
+            Ok(Output::Value(Bson::Document(polodb_core::bson::to_document(&result)?)))
+        }))
+    }
+
+}
+
+fn get_u64(options: &Document, key: &str) -> Result<Option<u64>> {
+    let value = match options.get(key) {
+        Some(Bson::Int32(value)) => *value as i64,
+        Some(Bson::Int64(value)) => *value,
+        Some(Bson::Null) | None => return Ok(None),
+        Some(_) => return Err(type_error(format!("{} is not an integer", key))),
+    };
+    u64::try_from(value)
+        .map(Some)
+        .map_err(|_| type_error(format!("{} can't be negative", key)))
+}
+
+#[napi]
+impl Collection {
+
+    #[napi(getter)]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[napi(ts_return_type = "Promise<InsertOneResult>")]
+    pub fn insert_one(&self, env: Env, doc: JsUnknown) -> Result<AsyncTask<DbTask>> {
+        let doc = js_to_document(&env, doc)?;
+        Ok(self.spawn(move |db, txn, name| {
+            let result = with_collection!(db, txn, name, |col| col.insert_one(doc))?;
+            Ok(Output::Value(Bson::Document(polodb_core::bson::doc! { "insertedId": result.inserted_id })))
+        }))
+    }
+
+    #[napi(ts_return_type = "Promise<InsertManyResult>")]
+    pub fn insert_many(&self, env: Env, docs: JsUnknown) -> Result<AsyncTask<DbTask>> {
+        let docs = js_to_documents(&env, docs)?;
+        Ok(self.spawn(move |db, txn, name| {
+            let result = with_collection!(db, txn, name, |col| col.insert_many(docs))?;
+            let mut ids: Vec<(usize, Bson)> = result.inserted_ids.into_iter().collect();
+            ids.sort_by_key(|(index, _)| *index);
+            let ids: Vec<Bson> = ids.into_iter().map(|(_, id)| id).collect();
+            Ok(Output::Value(Bson::Document(polodb_core::bson::doc! { "insertedIds": ids })))
+        }))
+    }
+
+    // wrapped by the lazy `find` of index.js
+    #[napi(js_name = "_find", ts_return_type = "Promise<NativeCursor>")]
+    pub fn find_native(&self, env: Env, filter: Option<JsUnknown>, options: Option<JsUnknown>) -> Result<AsyncTask<DbTask>> {
+        let filter = optional_document(&env, filter)?;
+        let options = optional_document(&env, options)?;
+        let sort = match options.get("sort") {
+            Some(Bson::Document(sort)) => Some(sort.clone()),
+            Some(Bson::Null) | None => None,
+            Some(_) => return Err(type_error("sort is not an object")),
+        };
+        let skip = get_u64(&options, "skip")?;
+        let limit = get_u64(&options, "limit")?;
+        Ok(self.spawn(move |db, txn, name| {
+            let cursor = with_collection!(db, txn, name, |col| {
+                let mut find = col.find(filter);
+                if let Some(sort) = sort {
+                    find = find.sort(sort);
+                }
+                if let Some(skip) = skip {
+                    find = find.skip(skip);
+                }
+                if let Some(limit) = limit {
+                    find = find.limit(limit);
+                }
+                find.run()
+            })?;
+            Ok(Output::Cursor(Box::new(cursor)))
+        }))
+    }
+
+    #[napi(ts_return_type = "Promise<object | null>")]
+    pub fn find_one(&self, env: Env, filter: Option<JsUnknown>) -> Result<AsyncTask<DbTask>> {
+        let filter = optional_document(&env, filter)?;
+        Ok(self.spawn(move |db, txn, name| {
+            let doc = with_collection!(db, txn, name, |col| col.find_one(filter))?;
+            Ok(Output::Value(doc.map(Bson::Document).unwrap_or(Bson::Null)))
+        }))
+    }
+
+    #[napi(ts_return_type = "Promise<number>")]
+    pub fn count_documents(&self, env: Env, filter: Option<JsUnknown>) -> Result<AsyncTask<DbTask>> {
+        let filter = optional_document(&env, filter)?;
+        Ok(self.spawn(move |db, txn, name| {
+            let count = with_collection!(db, txn, name, |col| {
+                if filter.is_empty() {
+                    col.count_documents()?
+                } else {
+                    let mut count = 0;
+                    for doc in col.find(filter).run()? {
+                        doc?;
+                        count += 1;
+                    }
+                    count
+                }
+            });
+            Ok(Output::Value(Bson::Int64(count as i64)))
+        }))
+    }
+
+    /// `options` has the optional `upsert`.
+    #[napi(ts_return_type = "Promise<UpdateResult>")]
+    pub fn update_one(&self, env: Env, filter: JsUnknown, update: JsUnknown, options: Option<JsUnknown>) -> Result<AsyncTask<DbTask>> {
+        self.update(env, filter, update, options, false)
+    }
+
+    #[napi(ts_return_type = "Promise<UpdateResult>")]
+    pub fn update_many(&self, env: Env, filter: JsUnknown, update: JsUnknown, options: Option<JsUnknown>) -> Result<AsyncTask<DbTask>> {
+        self.update(env, filter, update, options, true)
+    }
+
+    #[napi(ts_return_type = "Promise<DeleteResult>")]
+    pub fn delete_one(&self, env: Env, filter: JsUnknown) -> Result<AsyncTask<DbTask>> {
+        self.delete(env, filter, false)
+    }
+
+    #[napi(ts_return_type = "Promise<DeleteResult>")]
+    pub fn delete_many(&self, env: Env, filter: JsUnknown) -> Result<AsyncTask<DbTask>> {
+        self.delete(env, filter, true)
+    }
+
+    // wrapped by the lazy `aggregate` of index.js
+    #[napi(js_name = "_aggregate", ts_return_type = "Promise<NativeCursor>")]
+    pub fn aggregate_native(&self, env: Env, pipeline: JsUnknown) -> Result<AsyncTask<DbTask>> {
+        let pipeline = js_to_documents(&env, pipeline)?;
+        Ok(self.spawn(move |db, txn, name| {
+            let cursor = with_collection!(db, txn, name, |col| col.aggregate(pipeline).run())?;
+            Ok(Output::Cursor(Box::new(cursor)))
+        }))
+    }
+
+    /// Create an index of `keys`, e.g. `{ age: 1 }`, and return its name.
+    /// `options` has the optional `name` and `unique`.
+    #[napi(ts_return_type = "Promise<string>")]
+    pub fn create_index(&self, env: Env, keys: JsUnknown, options: Option<JsUnknown>) -> Result<AsyncTask<DbTask>> {
+        let keys = js_to_document(&env, keys)?;
+        let options = optional_document(&env, options)?;
+        let index_name = match options.get("name") {
+            Some(Bson::String(name)) => name.clone(),
+            Some(Bson::Null) | None => {
+                let parts: Vec<String> = keys.iter().map(|(key, value)| format!("{}_{}", key, value)).collect();
+                parts.join("_")
+            }
+            Some(_) => return Err(type_error("name is not a string")),
+        };
+        let index = IndexModel {
+            keys,
+            options: Some(IndexOptions {
+                name: Some(index_name.clone()),
+                unique: Some(options.get_bool("unique").unwrap_or(false)),
+                ..Default::default()
+            }),
+        };
+        Ok(self.spawn(move |db, txn, name| {
+            with_collection!(db, txn, name, |col| col.create_index(index))?;
+            Ok(Output::Value(Bson::String(index_name)))
+        }))
+    }
+
+    #[napi(ts_return_type = "Promise<void>")]
+    pub fn drop_index(&self, index_name: String) -> AsyncTask<DbTask> {
+        self.spawn(move |db, txn, name| {
+            with_collection!(db, txn, name, |col| col.drop_index(&index_name))?;
+            Ok(unit())
+        })
+    }
+
+    #[napi(ts_return_type = "Promise<void>")]
+    pub fn drop(&self) -> AsyncTask<DbTask> {
+        self.spawn(move |db, txn, name| {
+            with_collection!(db, txn, name, |col| col.drop())?;
+            Ok(unit())
+        })
+    }
+
+}
+
+/// The cursor of the native module, `index.js` wraps it in an async iterator.
+#[napi]
+pub struct NativeCursor {
+    // dropped before the database, `None` once it's closed
+    cursor: Arc<Mutex<Option<ClientCursor<Document>>>>,
+    db: Arc<CoreDatabase>,
+}
+
+#[napi]
+impl NativeCursor {
+
+    /// Read up to `size` documents, the array is empty at the end.
+    #[napi(ts_return_type = "Promise<object[]>")]
+    pub fn next_batch(&self, size: u32) -> AsyncTask<DbTask> {
+        let cursor = self.cursor.clone();
+        spawn(&self.db, move || {
+            let mut cursor = cursor.lock().unwrap();
+            let mut docs = Vec::new();
+            if let Some(inner) = cursor.as_mut() {
+                while docs.len() < size.max(1) as usize {
+                    match inner.next() {
+                        Some(doc) => docs.push(doc?),
+                        None => {
+                            *cursor = None;
+                            break;
+                        }
+                    }
+                }
+            }
+            Ok(Output::Documents(docs))
+        })
+    }
+
+    /// Release the cursor before reading all the documents.
+    #[napi]
+    pub fn close(&self) {
+        // a batch being read keeps the cursor until it's finished
+        if let Ok(mut cursor) = self.cursor.try_lock() {
+            *cursor = None;
+        }
+    }
+
+}
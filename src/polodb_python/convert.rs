@@ -0,0 +1,251 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The conversion between the BSON values and the Python objects.
+//!
+//! | BSON              | Python                                 |
+//! |-------------------|----------------------------------------|
+//! | document          | `dict`                                 |
+//! | array             | `list`, `tuple` is accepted            |
+//! | int32, int64      | `int`                                  |
+//! | double            | `float`                                |
+//! | decimal128        | `decimal.Decimal`                      |
+//! | string            | `str`                                  |
+//! | boolean           | `bool`                                 |
+//! | null              | `None`                                 |
+//! | ObjectId          | `polodb.ObjectId`, `bson.ObjectId` is accepted |
+//! | date              | `datetime.datetime` in UTC, the naive ones are UTC |
+//! | binary            | `bytes`, or `uuid.UUID` for the subtype 4 |
+//!
+//! The other BSON types are returned as their extended JSON, e.g. `{"$regularExpression": ...}`.
+
+use std::convert::TryFrom;
+use std::str::FromStr;
+use polodb_core::bson::{oid, spec::BinarySubtype, Binary, Bson, DateTime, Decimal128, Document};
+use pyo3::exceptions::{PyOverflowError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyBytes, PyDateTime, PyDelta, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple};
+use serde_json::Value;
+
+const MILLIS_PER_DAY: i64 = 86_400_000;
+
+/// An ObjectId, `ObjectId()` generates a new one and `ObjectId(hex)` parses one.
+#[pyclass(module = "polodb", frozen, eq, hash)]
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ObjectId(oid::ObjectId);
+
+#[pymethods]
+impl ObjectId {
+
+    #[new]
+    #[pyo3(signature = (oid = None))]
+    fn new(oid: Option<&str>) -> PyResult<ObjectId> {
+        match oid {
+            Some(hex) => oid::ObjectId::parse_str(hex)
+                .map(ObjectId)
+                .map_err(|err| PyValueError::new_err(err.to_string())),
+            None => Ok(ObjectId(oid::ObjectId::new())),
+        }
+    }
+
+    /// The 12 bytes of the ObjectId.
+    #[getter]
+    fn binary<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new_bound(py, &self.0.bytes())
+    }
+
+    fn __str__(&self) -> String {
+        self.0.to_hex()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("ObjectId('{}')", self.0.to_hex())
+    }
+
+}
+
+fn epoch<'py>(py: Python<'py>, aware: bool) -> PyResult<Bound<'py, PyAny>> {
+    let datetime = py.import_bound("datetime")?;
+    let utc = datetime.getattr("timezone")?.getattr("utc")?;
+    let tzinfo = if aware { utc } else { py.None().into_bound(py) };
+    datetime.getattr("datetime")?.call1((1970, 1, 1, 0, 0, 0, 0, tzinfo))
+}
+
+pub(crate) fn bson_to_py(py: Python<'_>, value: &Bson) -> PyResult<PyObject> {
+    let result = match value {
+        Bson::Null | Bson::Undefined => py.None(),
+        Bson::Boolean(value) => value.into_py(py),
+        Bson::Int32(value) => value.into_py(py),
+        Bson::Int64(value) => value.into_py(py),
+        Bson::Double(value) => value.into_py(py),
+        Bson::String(value) => value.into_py(py),
+        Bson::Document(doc) => document_to_py(py, doc)?.into_any().unbind(),
+        Bson::Array(items) => {
+            let list = PyList::empty_bound(py);
+            for item in items {
+                list.append(bson_to_py(py, item)?)?;
+            }
+            list.into_any().unbind()
+        }
+        Bson::ObjectId(oid) => ObjectId(*oid).into_py(py),
+        Bson::DateTime(date) => {
+            let millis = date.timestamp_millis();
+            let delta = PyDelta::new_bound(
+                py,
+                i32::try_from(millis.div_euclid(MILLIS_PER_DAY)).map_err(|err| PyOverflowError::new_err(err.to_string()))?,
+                (millis.rem_euclid(MILLIS_PER_DAY) / 1000) as i32,
+                (millis.rem_euclid(1000) * 1000) as i32,
+                false,
+            )?;
+            epoch(py, true)?.call_method1("__add__", (delta,))?.unbind()
+        }
+        Bson::Binary(binary) if binary.subtype == BinarySubtype::Uuid && binary.bytes.len() == 16 => {
+            let kwargs = PyDict::new_bound(py);
+            kwargs.set_item("bytes", PyBytes::new_bound(py, &binary.bytes))?;
+            py.import_bound("uuid")?.getattr("UUID")?.call((), Some(&kwargs))?.unbind()
+        }
+        Bson::Binary(binary) => PyBytes::new_bound(py, &binary.bytes).into_any().unbind(),
+        Bson::Decimal128(value) => {
+            py.import_bound("decimal")?.getattr("Decimal")?.call1((value.to_string(),))?.unbind()
+        }
+        value => json_to_py(py, &value.clone().into_relaxed_extjson())?,
+    };
+    Ok(result)
+}
+
+pub(crate) fn document_to_py<'py>(py: Python<'py>, doc: &Document) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new_bound(py);
+    for (key, value) in doc {
+        dict.set_item(key, bson_to_py(py, value)?)?;
+    }
+    Ok(dict)
+}
+
+fn json_to_py(py: Python<'_>, value: &Value) -> PyResult<PyObject> {
+    let result = match value {
+        Value::Null => py.None(),
+        Value::Bool(value) => value.into_py(py),
+        Value::Number(number) => match number.as_i64() {
+            Some(value) => value.into_py(py),
+            None => number.as_f64().unwrap_or(f64::NAN).into_py(py),
+        },
+        Value::String(value) => value.into_py(py),
+        Value::Array(items) => {
+            let list = PyList::empty_bound(py);
+            for item in items {
+                list.append(json_to_py(py, item)?)?;
+            }
+            list.into_any().unbind()
+        }
+        Value::Object(fields) => {
+            let dict = PyDict::new_bound(py);
+            for (key, value) in fields {
+                dict.set_item(key, json_to_py(py, value)?)?;
+            }
+            dict.into_any().unbind()
+        }
+    };
+    Ok(result)
+}
+
+fn is_instance_of_type(value: &Bound<'_, PyAny>, module: &str, name: &str) -> PyResult<bool> {
+    let ty = value.py().import_bound(module)?.getattr(name)?;
+    value.is_instance(&ty)
+}
+
+pub(crate) fn py_to_bson(value: &Bound<'_, PyAny>) -> PyResult<Bson> {
+    let py = value.py();
+    if value.is_none() {
+        return Ok(Bson::Null);
+    }
+    // a bool is an int in Python
+    if let Ok(value) = value.downcast::<PyBool>() {
+        return Ok(Bson::Boolean(value.is_true()));
+    }
+    if value.is_instance_of::<PyInt>() {
+        let value: i64 = value.extract().map_err(|_| PyOverflowError::new_err("the int doesn't fit in 64 bits"))?;
+        return Ok(match i32::try_from(value) {
+            Ok(value) => Bson::Int32(value),
+            Err(_) => Bson::Int64(value),
+        });
+    }
+    if let Ok(value) = value.downcast::<PyFloat>() {
+        return Ok(Bson::Double(value.value()));
+    }
+    if let Ok(value) = value.downcast::<PyString>() {
+        return Ok(Bson::String(value.to_str()?.to_string()));
+    }
+    if let Ok(value) = value.downcast::<PyDict>() {
+        return py_to_document(value.as_any()).map(Bson::Document);
+    }
+    if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+        let mut items = Vec::new();
+        for item in value.iter()? {
+            items.push(py_to_bson(&item?)?);
+        }
+        return Ok(Bson::Array(items));
+    }
+    if let Ok(oid) = value.downcast::<ObjectId>() {
+        return Ok(Bson::ObjectId(oid.get().0));
+    }
+    if let Ok(value) = value.downcast::<PyBytes>() {
+        return Ok(Bson::Binary(Binary {
+            subtype: BinarySubtype::Generic,
+            bytes: value.as_bytes().to_vec(),
+        }));
+    }
+    if let Ok(value) = value.downcast::<PyDateTime>() {
+        let aware = !value.getattr("tzinfo")?.is_none();
+        let delta = value.call_method1("__sub__", (epoch(py, aware)?,))?;
+        let days: i64 = delta.getattr("days")?.extract()?;
+        let seconds: i64 = delta.getattr("seconds")?.extract()?;
+        let micros: i64 = delta.getattr("microseconds")?.extract()?;
+        let millis = days * MILLIS_PER_DAY + seconds * 1000 + micros / 1000;
+        return Ok(Bson::DateTime(DateTime::from_millis(millis)));
+    }
+    if is_instance_of_type(value, "uuid", "UUID")? {
+        let bytes: Vec<u8> = value.getattr("bytes")?.extract()?;
+        return Ok(Bson::Binary(Binary {
+            subtype: BinarySubtype::Uuid,
+            bytes,
+        }));
+    }
+    if is_instance_of_type(value, "decimal", "Decimal")? {
+        let text: String = value.str()?.extract()?;
+        return Decimal128::from_str(&text)
+            .map(Bson::Decimal128)
+            .map_err(|err| PyValueError::new_err(err.to_string()));
+    }
+    // the ObjectId of pymongo
+    if value.get_type().name()? == "ObjectId" {
+        let bytes: Vec<u8> = value.getattr("binary")?.extract()?;
+        let bytes = <[u8; 12]>::try_from(bytes.as_slice())
+            .map_err(|_| PyValueError::new_err("the ObjectId is not 12 bytes"))?;
+        return Ok(Bson::ObjectId(oid::ObjectId::from_bytes(bytes)));
+    }
+    Err(PyTypeError::new_err(format!("cannot encode object: {}", value.repr()?)))
+}
+
+pub(crate) fn py_to_document(value: &Bound<'_, PyAny>) -> PyResult<Document> {
+    let dict = value.downcast::<PyDict>()
+        .map_err(|_| PyTypeError::new_err(format!("expect a dict, found {}", value.get_type().name().map(|name| name.to_string()).unwrap_or_default())))?;
+    let mut doc = Document::new();
+    for (key, value) in dict.iter() {
+        let key = key.downcast::<PyString>()
+            .map_err(|_| PyTypeError::new_err("the keys of the documents must be str"))?;
+        doc.insert(key.to_str()?, py_to_bson(&value)?);
+    }
+    Ok(doc)
+}
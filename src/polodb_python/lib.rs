@@ -0,0 +1,540 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+
+//! The Python bindings of PoloDB, built with [maturin](https://www.maturin.rs/):
+//!
+//! ```shell
+//! cd src/polodb_python
+//! maturin develop
+//! ```
+//!
+//! The API follows pymongo:
+//!
+//! ```python
+//! import polodb
+//!
+//! client = polodb.Client("app.db")
+//! books = client["library"]["books"]
+//! books.insert_one({"title": "1984", "year": 1949})
+//! for book in books.find({"year": {"$lt": 1950}}, sort=[("year", -1)]):
+//!     print(book["title"])
+//!
+//! with client["library"].start_transaction() as txn:
+//!     txn["books"].delete_many({})
+//!     txn["archive"].insert_one({"deleted": True})
+//! ```
+//!
+//! `Client()` without a path opens a database in memory. The collections of
+//! `client.get_default_database()` are the ones of the Rust `Database`,
+//! `client["name"]` is the named database `name`. A transaction is committed at the end
+//! of the `with` block, or rolled back if it raises. The conversion of the values
+//! is described in the `convert` module. The GIL is released while the database works.
+
+// the `#[pymethods]` of pyo3 0.22 convert the `PyErr` into itself
+#![allow(clippy::useless_conversion)]
+
+mod convert;
+
+use std::sync::Arc;
+use polodb_core::bson::{Bson, Document};
+use polodb_core::options::UpdateOptions;
+use polodb_core::{ClientCursor, CollectionT, Database as CoreDatabase, Error, IndexModel, IndexOptions, Transaction as CoreTransaction};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList, PyTuple};
+use convert::{bson_to_py, document_to_py, py_to_bson, py_to_document, ObjectId};
+
+create_exception!(polodb, PoloDBError, PyException, "The base class of the errors of PoloDB.");
+create_exception!(polodb, DuplicateKeyError, PoloDBError, "A unique index rejects the document.");
+create_exception!(polodb, DocumentValidationError, PoloDBError, "The validator of the collection rejects the document.");
+
+fn to_py_err(err: Error) -> PyErr {
+    let message = err.to_string();
+    match err {
+        Error::DuplicateKey(_) => DuplicateKeyError::new_err(message),
+        Error::DocumentValidationFailed(_) => DocumentValidationError::new_err(message),
+        _ => PoloDBError::new_err(message),
+    }
+}
+
+fn optional_document(value: Option<&Bound<'_, PyAny>>) -> PyResult<Document> {
+    match value {
+        Some(value) if !value.is_none() => py_to_document(value),
+        _ => Ok(Document::new()),
+    }
+}
+
+// a dict, or a list of (key, direction) like pymongo
+fn sort_document(value: &Bound<'_, PyAny>) -> PyResult<Document> {
+    if value.is_instance_of::<PyDict>() {
+        return py_to_document(value);
+    }
+    if !value.is_instance_of::<PyList>() && !value.is_instance_of::<PyTuple>() {
+        return Err(PyTypeError::new_err("sort must be a dict or a list of (key, direction)"));
+    }
+    let mut sort = Document::new();
+    for item in value.iter()? {
+        let (key, direction): (String, Bound<'_, PyAny>) = item?.extract()
+            .map_err(|_| PyTypeError::new_err("sort must be a dict or a list of (key, direction)"))?;
+        sort.insert(key, py_to_bson(&direction)?);
+    }
+    Ok(sort)
+}
+
+/// The connection to a database file, `Client()` opens a database in memory.
+#[pyclass(module = "polodb")]
+struct Client {
+    db: Arc<CoreDatabase>,
+}
+
+#[pymethods]
+impl Client {
+
+    #[new]
+    #[pyo3(signature = (path = None))]
+    fn new(py: Python<'_>, path: Option<std::path::PathBuf>) -> PyResult<Client> {
+        let db = py.allow_threads(|| match path {
+            Some(path) => CoreDatabase::open_path(path),
+            None => CoreDatabase::open_memory(),
+        }).map_err(to_py_err)?;
+        Ok(Client {
+            db: Arc::new(db),
+        })
+    }
+
+    fn __getitem__(&self, name: &str) -> Database {
+        self.get_database(name)
+    }
+
+    fn get_database(&self, name: &str) -> Database {
+        Database {
+            db: Arc::new(self.db.database(name)),
+        }
+    }
+
+    fn get_default_database(&self) -> Database {
+        Database {
+            db: self.db.clone(),
+        }
+    }
+
+    fn list_database_names(&self, py: Python<'_>) -> PyResult<Vec<String>> {
+        py.allow_threads(|| self.db.list_database_names()).map_err(to_py_err)
+    }
+
+    fn drop_database(&self, py: Python<'_>, name: &str) -> PyResult<()> {
+        py.allow_threads(|| self.db.drop_database(name)).map_err(to_py_err)?;
+        Ok(())
+    }
+
+}
+
+#[pyclass(module = "polodb")]
+struct Database {
+    db: Arc<CoreDatabase>,
+}
+
+#[pymethods]
+impl Database {
+
+    /// The name of the named database, `None` for the default one.
+    #[getter]
+    fn name(&self) -> Option<String> {
+        self.db.name().map(String::from)
+    }
+
+    fn __getitem__(&self, name: &str) -> Collection {
+        self.get_collection(name)
+    }
+
+    fn get_collection(&self, name: &str) -> Collection {
+        Collection {
+            txn: None,
+            db: self.db.clone(),
+            name: name.to_string(),
+        }
+    }
+
+    fn list_collection_names(&self, py: Python<'_>) -> PyResult<Vec<String>> {
+        py.allow_threads(|| self.db.list_collection_names()).map_err(to_py_err)
+    }
+
+    fn create_collection(&self, py: Python<'_>, name: &str) -> PyResult<Collection> {
+        py.allow_threads(|| self.db.create_collection(name)).map_err(to_py_err)?;
+        Ok(self.get_collection(name))
+    }
+
+    fn drop_collection(&self, py: Python<'_>, name: &str) -> PyResult<()> {
+        py.allow_threads(|| self.db.collection::<Document>(name).drop()).map_err(to_py_err)?;
+        Ok(())
+    }
+
+    /// Start a transaction, use it in a `with` block to commit it at the end.
+    fn start_transaction(&self, py: Python<'_>) -> PyResult<Transaction> {
+        let txn = py.allow_threads(|| self.db.start_transaction()).map_err(to_py_err)?;
+        Ok(Transaction {
+            txn,
+            finished: false,
+            db: self.db.clone(),
+        })
+    }
+
+}
+
+#[pyclass(module = "polodb")]
+struct Transaction {
+    // dropped before the database
+    txn: CoreTransaction,
+    finished: bool,
+    db: Arc<CoreDatabase>,
+}
+
+#[pymethods]
+impl Transaction {
+
+    fn __getitem__(&self, name: &str) -> Collection {
+        self.get_collection(name)
+    }
+
+    fn get_collection(&self, name: &str) -> Collection {
+        Collection {
+            txn: Some(self.txn.clone()),
+            db: self.db.clone(),
+            name: name.to_string(),
+        }
+    }
+
+    fn commit(&mut self, py: Python<'_>) -> PyResult<()> {
+        self.finished = true;
+        py.allow_threads(|| self.txn.commit()).map_err(to_py_err)
+    }
+
+    fn abort(&mut self, py: Python<'_>) -> PyResult<()> {
+        self.finished = true;
+        py.allow_threads(|| self.txn.rollback()).map_err(to_py_err)
+    }
+
+    fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    #[pyo3(signature = (exc_type, _exc_value, _traceback))]
+    fn __exit__(
+        &mut self,
+        py: Python<'_>,
+        exc_type: Option<&Bound<'_, PyAny>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<bool> {
+        if !self.finished {
+            match exc_type {
+                Some(exc_type) if !exc_type.is_none() => self.abort(py)?,
+                _ => self.commit(py)?,
+            }
+        }
+        // the exception is not suppressed
+        Ok(false)
+    }
+
+}
+
+// runs `$body` with `$col` bound to the collection in the transaction if there is one
+macro_rules! with_collection {
+    ($self:expr, |$col:ident| $body:expr) => {
+        match &$self.txn {
+            Some(txn) => {
+                let $col = txn.collection::<Document>(&$self.name);
+                $body
+            }
+            None => {
+                let $col = $self.db.collection::<Document>(&$self.name);
+                $body
+            }
+        }
+    };
+}
+
+#[pyclass(module = "polodb")]
+struct Collection {
+    txn: Option<CoreTransaction>,
+    db: Arc<CoreDatabase>,
+    name: String,
+}
+
+impl Collection {
+
+    fn cursor(&self, cursor: ClientCursor<Document>) -> Cursor {
+        Cursor {
+            cursor,
+            _db: self.db.clone(),
+        }
+    }
+
+    fn update(&self, py: Python<'_>, filter: &Bound<'_, PyAny>, update: &Bound<'_, PyAny>, upsert: bool, many: bool) -> PyResult<UpdateResult> {
+        let filter = py_to_document(filter)?;
+        let update = py_to_document(update)?;
+        let options = UpdateOptions::builder().upsert(upsert).build();
+        let result = py.allow_threads(|| with_collection!(self, |col| if many {
+            col.update_many_with_options(filter, update, options)
+        } else {
+            col.update_one_with_options(filter, update, options)
+        })).map_err(to_py_err)?;
+        Ok(UpdateResult {
+            matched_count: result.matched_count,
+            modified_count: result.modified_count,
+        })
+    }
+
+    fn delete(&self, py: Python<'_>, filter: &Bound<'_, PyAny>, many: bool) -> PyResult<DeleteResult> {
+        let filter = py_to_document(filter)?;
+        let result = py.allow_threads(|| with_collection!(self, |col| if many {
+            col.delete_many(filter)
+        } else {
+            col.delete_one(filter)
+        })).map_err(to_py_err)?;
+        Ok(DeleteResult {
+            deleted_count: result.deleted_count,
+        })
+    }
+
+}
+
+#[pymethods]
+impl Collection {
+
+    #[getter]
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn insert_one(&self, py: Python<'_>, document: &Bound<'_, PyAny>) -> PyResult<InsertOneResult> {
+        let doc = py_to_document(document)?;
+        let result = py.allow_threads(|| with_collection!(self, |col| col.insert_one(doc))).map_err(to_py_err)?;
+        Ok(InsertOneResult {
+            inserted_id: bson_to_py(py, &result.inserted_id)?,
+        })
+    }
+
+    fn insert_many(&self, py: Python<'_>, documents: &Bound<'_, PyAny>) -> PyResult<InsertManyResult> {
+        let mut docs = Vec::new();
+        for document in documents.iter()? {
+            docs.push(py_to_document(&document?)?);
+        }
+        let result = py.allow_threads(|| with_collection!(self, |col| col.insert_many(docs))).map_err(to_py_err)?;
+        let mut ids: Vec<(usize, Bson)> = result.inserted_ids.into_iter().collect();
+        ids.sort_by_key(|(index, _)| *index);
+        let inserted_ids = PyList::empty_bound(py);
+        for (_, id) in ids {
+            inserted_ids.append(bson_to_py(py, &id)?)?;
+        }
+        Ok(InsertManyResult {
+            inserted_ids: inserted_ids.unbind(),
+        })
+    }
+
+    /// `sort` is a dict or a list of `(key, direction)`.
+    #[pyo3(signature = (filter = None, sort = None, skip = None, limit = None))]
+    fn find(
+        &self,
+        py: Python<'_>,
+        filter: Option<&Bound<'_, PyAny>>,
+        sort: Option<&Bound<'_, PyAny>>,
+        skip: Option<u64>,
+        limit: Option<u64>,
+    ) -> PyResult<Cursor> {
+        let filter = optional_document(filter)?;
+        let sort = match sort {
+            Some(sort) if !sort.is_none() => Some(sort_document(sort)?),
+            _ => None,
+        };
+        let cursor = py.allow_threads(|| with_collection!(self, |col| {
+            let mut find = col.find(filter);
+            if let Some(sort) = sort {
+                find = find.sort(sort);
+            }
+            if let Some(skip) = skip {
+                find = find.skip(skip);
+            }
+            if let Some(limit) = limit {
+                find = find.limit(limit);
+            }
+            find.run()
+        })).map_err(to_py_err)?;
+        Ok(self.cursor(cursor))
+    }
+
+    #[pyo3(signature = (filter = None))]
+    fn find_one(&self, py: Python<'_>, filter: Option<&Bound<'_, PyAny>>) -> PyResult<Option<PyObject>> {
+        let filter = optional_document(filter)?;
+        let doc = py.allow_threads(|| with_collection!(self, |col| col.find_one(filter))).map_err(to_py_err)?;
+        match doc {
+            Some(doc) => Ok(Some(document_to_py(py, &doc)?.into_any().unbind())),
+            None => Ok(None),
+        }
+    }
+
+    #[pyo3(signature = (filter = None))]
+    fn count_documents(&self, py: Python<'_>, filter: Option<&Bound<'_, PyAny>>) -> PyResult<u64> {
+        let filter = optional_document(filter)?;
+        py.allow_threads(|| with_collection!(self, |col| {
+            if filter.is_empty() {
+                return col.count_documents();
+            }
+            let mut count = 0;
+            for doc in col.find(filter).run()? {
+                doc?;
+                count += 1;
+            }
+            Ok(count)
+        })).map_err(to_py_err)
+    }
+
+    #[pyo3(signature = (filter, update, upsert = false))]
+    fn update_one(&self, py: Python<'_>, filter: &Bound<'_, PyAny>, update: &Bound<'_, PyAny>, upsert: bool) -> PyResult<UpdateResult> {
+        self.update(py, filter, update, upsert, false)
+    }
+
+    #[pyo3(signature = (filter, update, upsert = false))]
+    fn update_many(&self, py: Python<'_>, filter: &Bound<'_, PyAny>, update: &Bound<'_, PyAny>, upsert: bool) -> PyResult<UpdateResult> {
+        self.update(py, filter, update, upsert, true)
+    }
+
+    fn delete_one(&self, py: Python<'_>, filter: &Bound<'_, PyAny>) -> PyResult<DeleteResult> {
+        self.delete(py, filter, false)
+    }
+
+    fn delete_many(&self, py: Python<'_>, filter: &Bound<'_, PyAny>) -> PyResult<DeleteResult> {
+        self.delete(py, filter, true)
+    }
+
+    fn aggregate(&self, py: Python<'_>, pipeline: &Bound<'_, PyAny>) -> PyResult<Cursor> {
+        let mut stages = Vec::new();
+        for stage in pipeline.iter()? {
+            stages.push(py_to_document(&stage?)?);
+        }
+        let cursor = py.allow_threads(|| with_collection!(self, |col| col.aggregate(stages).run())).map_err(to_py_err)?;
+        Ok(self.cursor(cursor))
+    }
+
+    /// Create an index of `keys`, e.g. `{"age": 1}`, and return its name.
+    #[pyo3(signature = (keys, name = None, unique = false))]
+    fn create_index(&self, py: Python<'_>, keys: &Bound<'_, PyAny>, name: Option<String>, unique: bool) -> PyResult<String> {
+        let keys = sort_document(keys)?;
+        let name = match name {
+            Some(name) => name,
+            None => {
+                let parts: Vec<String> = keys.iter().map(|(key, value)| format!("{}_{}", key, value)).collect();
+                parts.join("_")
+            }
+        };
+        if name.is_empty() {
+            return Err(PyValueError::new_err("the keys of the index are empty"));
+        }
+        let index = IndexModel {
+            keys,
+            options: Some(IndexOptions {
+                name: Some(name.clone()),
+                unique: Some(unique),
+                ..Default::default()
+            }),
+        };
+        py.allow_threads(|| with_collection!(self, |col| col.create_index(index))).map_err(to_py_err)?;
+        Ok(name)
+    }
+
+    fn drop_index(&self, py: Python<'_>, name: &str) -> PyResult<()> {
+        py.allow_threads(|| with_collection!(self, |col| col.drop_index(name))).map_err(to_py_err)
+    }
+
+    fn drop(&self, py: Python<'_>) -> PyResult<()> {
+        py.allow_threads(|| with_collection!(self, |col| col.drop())).map_err(to_py_err)?;
+        Ok(())
+    }
+
+}
+
+/// The iterator of the documents returned by `find` and `aggregate`.
+#[pyclass(module = "polodb")]
+struct Cursor {
+    // dropped before the database, which is kept open by the cursor
+    cursor: ClientCursor<Document>,
+    _db: Arc<CoreDatabase>,
+}
+
+#[pymethods]
+impl Cursor {
+
+    fn __iter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let cursor = &mut self.cursor;
+        let doc = py.allow_threads(|| cursor.next().transpose()).map_err(to_py_err)?;
+        match doc {
+            Some(doc) => Ok(Some(document_to_py(py, &doc)?.into_any().unbind())),
+            None => Ok(None),
+        }
+    }
+
+}
+
+#[pyclass(module = "polodb", frozen)]
+struct InsertOneResult {
+    #[pyo3(get)]
+    inserted_id: PyObject,
+}
+
+#[pyclass(module = "polodb", frozen)]
+struct InsertManyResult {
+    #[pyo3(get)]
+    inserted_ids: Py<PyList>,
+}
+
+#[pyclass(module = "polodb", frozen)]
+struct UpdateResult {
+    #[pyo3(get)]
+    matched_count: u64,
+    #[pyo3(get)]
+    modified_count: u64,
+}
+
+#[pyclass(module = "polodb", frozen)]
+struct DeleteResult {
+    #[pyo3(get)]
+    deleted_count: u64,
+}
+
+#[pymodule]
+#[pyo3(name = "polodb")]
+fn polodb_python(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add_class::<Client>()?;
+    m.add_class::<Database>()?;
+    m.add_class::<Collection>()?;
+    m.add_class::<Transaction>()?;
+    m.add_class::<Cursor>()?;
+    m.add_class::<ObjectId>()?;
+    m.add_class::<InsertOneResult>()?;
+    m.add_class::<InsertManyResult>()?;
+    m.add_class::<UpdateResult>()?;
+    m.add_class::<DeleteResult>()?;
+    m.add("PoloDBError", py.get_type_bound::<PoloDBError>())?;
+    m.add("DuplicateKeyError", py.get_type_bound::<DuplicateKeyError>())?;
+    m.add("DocumentValidationError", py.get_type_bound::<DocumentValidationError>())?;
+    Ok(())
+}
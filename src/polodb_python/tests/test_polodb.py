@@ -0,0 +1,163 @@
+# Copyright 2024 Vincent Chan
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#	http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+# Run with `maturin develop && python -m unittest discover tests`
+
+import datetime
+import decimal
+import os
+import shutil
+import tempfile
+import unittest
+import uuid
+
+import polodb
+
+
+class TestCollection(unittest.TestCase):
+
+    def setUp(self):
+        self.client = polodb.Client()
+        self.books = self.client["library"]["books"]
+        self.books.insert_many([
+            {"_id": 1, "title": "1984", "year": 1949},
+            {"_id": 2, "title": "Brave New World", "year": 1932},
+            {"_id": 3, "title": "Fahrenheit 451", "year": 1953},
+        ])
+
+    def test_find(self):
+        titles = [book["title"] for book in self.books.find({"year": {"$lt": 1950}}, sort=[("year", -1)])]
+        self.assertEqual(titles, ["1984", "Brave New World"])
+        self.assertEqual(len(list(self.books.find(skip=1, limit=1))), 1)
+        self.assertEqual(self.books.find_one({"_id": 3})["title"], "Fahrenheit 451")
+        self.assertIsNone(self.books.find_one({"_id": 4}))
+        self.assertEqual(self.books.count_documents(), 3)
+        self.assertEqual(self.books.count_documents({"year": {"$gt": 1940}}), 2)
+
+    def test_update_delete(self):
+        result = self.books.update_many({"year": {"$lt": 1950}}, {"$set": {"classic": True}})
+        self.assertEqual((result.matched_count, result.modified_count), (2, 2))
+        result = self.books.update_one({"_id": 4}, {"$set": {"title": "We"}}, upsert=True)
+        self.assertEqual(result.matched_count, 0)
+        self.assertEqual(self.books.count_documents(), 4)
+        self.assertEqual(self.books.delete_many({"classic": True}).deleted_count, 2)
+        self.assertEqual(self.books.delete_one({}).deleted_count, 1)
+
+    def test_aggregate(self):
+        result = list(self.books.aggregate([
+            {"$match": {"year": {"$gt": 1940}}},
+            {"$count": "count"},
+        ]))
+        self.assertEqual(result, [{"count": 2}])
+
+    def test_index(self):
+        self.assertEqual(self.books.create_index({"title": 1}, unique=True), "title_1")
+        with self.assertRaises(polodb.DuplicateKeyError):
+            self.books.insert_one({"title": "1984"})
+        self.assertEqual(self.books.count_documents(), 3)
+        self.assertEqual(self.books.create_index([("year", 1)], name="by_year"), "by_year")
+        self.books.drop_index("by_year")
+
+    def test_databases(self):
+        self.assertEqual(self.client.list_database_names(), ["library"])
+        self.assertEqual(self.client["library"].list_collection_names(), ["books"])
+        self.assertEqual(self.client.get_default_database().list_collection_names(), [])
+        self.client.drop_database("library")
+        self.assertEqual(self.books.count_documents(), 0)
+
+
+class TestTransaction(unittest.TestCase):
+
+    def test_commit_and_rollback(self):
+        db = polodb.Client().get_default_database()
+        with db.start_transaction() as txn:
+            txn["items"].insert_one({"name": "a"})
+            self.assertEqual(txn["items"].count_documents(), 1)
+        self.assertEqual(db["items"].count_documents(), 1)
+
+        with self.assertRaises(ValueError):
+            with db.start_transaction() as txn:
+                txn["items"].insert_one({"name": "b"})
+                raise ValueError("abort")
+        self.assertEqual(db["items"].count_documents(), 1)
+
+        txn = db.start_transaction()
+        txn["items"].delete_many({})
+        txn.abort()
+        self.assertEqual(db["items"].count_documents(), 1)
+
+
+class TestConversion(unittest.TestCase):
+
+    def test_round_trip(self):
+        col = polodb.Client()["test"]["values"]
+        oid = polodb.ObjectId()
+        date = datetime.datetime(2024, 5, 17, 8, 30, 15, 123000, tzinfo=datetime.timezone.utc)
+        document = {
+            "_id": oid,
+            "int": 1,
+            "long": 1 << 40,
+            "float": 1.5,
+            "decimal": decimal.Decimal("0.10"),
+            "str": "text",
+            "bool": True,
+            "none": None,
+            "date": date,
+            "bytes": b"\x00\x01",
+            "uuid": uuid.UUID("b5d0a4e2-4a3b-4f6e-9d1c-7b1d2e3f4a5b"),
+            "nested": {"list": [1, "two", {"three": 3}], "tuple": (1, 2)},
+        }
+        self.assertEqual(col.insert_one(document).inserted_id, oid)
+        stored = col.find_one({"_id": oid})
+        self.assertEqual(stored, dict(document, nested={"list": [1, "two", {"three": 3}], "tuple": [1, 2]}))
+        self.assertIsInstance(stored["bool"], bool)
+
+        naive = datetime.datetime(2024, 5, 17, 8, 30, 15)
+        col.insert_one({"_id": 2, "date": naive})
+        self.assertEqual(col.find_one({"_id": 2})["date"], naive.replace(tzinfo=datetime.timezone.utc))
+
+    def test_object_id(self):
+        oid = polodb.ObjectId("65a1b2c3d4e5f60718293a4b")
+        self.assertEqual(str(oid), "65a1b2c3d4e5f60718293a4b")
+        self.assertEqual(repr(oid), "ObjectId('65a1b2c3d4e5f60718293a4b')")
+        self.assertEqual(len(oid.binary), 12)
+        self.assertEqual(oid, polodb.ObjectId(str(oid)))
+        self.assertEqual(len({oid, polodb.ObjectId(str(oid))}), 1)
+        with self.assertRaises(ValueError):
+            polodb.ObjectId("not an id")
+
+    def test_unsupported(self):
+        col = polodb.Client()["test"]["values"]
+        with self.assertRaises(TypeError):
+            col.insert_one({"set": {1, 2}})
+        with self.assertRaises(TypeError):
+            col.insert_one({1: "key"})
+        with self.assertRaises(OverflowError):
+            col.insert_one({"big": 1 << 70})
+
+
+class TestFile(unittest.TestCase):
+
+    def test_reopen(self):
+        path = tempfile.mkdtemp()
+        try:
+            db_path = os.path.join(path, "app.db")
+            polodb.Client(db_path)["app"]["users"].insert_one({"name": "Alice"})
+            self.assertEqual(polodb.Client(db_path)["app"]["users"].find_one()["name"], "Alice")
+        finally:
+            shutil.rmtree(path)
+
+
+if __name__ == "__main__":
+    unittest.main()